@@ -0,0 +1,24 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use solana_sdk::hash::Hash;
+use sss_shared::{BLOCKHASH_CACHE, RPC_CLIENT};
+
+/// Compares the blockhash step of a mint with and without the cache
+///
+/// Requires network access to the configured `SOLANA_RPC_URL`.
+fn blockhash_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("blockhash");
+    group.sample_size(10);
+
+    group.bench_function("uncached", |b| {
+        b.iter(|| -> Hash { RPC_CLIENT.get_latest_blockhash().unwrap() })
+    });
+
+    group.bench_function("cached", |b| {
+        b.iter(|| -> Hash { BLOCKHASH_CACHE.get(&RPC_CLIENT).unwrap() })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, blockhash_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,70 @@
+use criterion::{Criterion, black_box, criterion_group, criterion_main};
+use solana_sdk::pubkey::Pubkey;
+use sss_shared::{
+    MintOptions, TokenBuilder, base_to_ui_amount, build_mint_ix, clear_mint_cache, metadata_pda,
+    ui_to_base_amount,
+};
+
+/// Measures the instruction builders used by every create and mint
+///
+/// Runs offline; no RPC calls are made.
+fn instruction_benchmark(c: &mut Criterion) {
+    let payer = Pubkey::new_unique();
+    let mint = Pubkey::new_unique();
+    let owner = Pubkey::new_unique();
+    let options = MintOptions::default();
+    let builder = TokenBuilder::new()
+        .name("My Token")
+        .symbol("MTK")
+        .uri("https://example.com/token.json")
+        .decimals(9);
+
+    let mut group = c.benchmark_group("instructions");
+
+    group.bench_function("build_create_token_ix", |b| {
+        b.iter(|| builder.build_create_token_ix(black_box(&payer), black_box(&mint)))
+    });
+
+    group.bench_function("build_mint_ix", |b| {
+        b.iter(|| {
+            build_mint_ix(
+                black_box(&payer),
+                black_box(&mint),
+                black_box(&owner),
+                1_000_000_000,
+                &options,
+            )
+        })
+    });
+
+    group.bench_function("metadata_pda_uncached", |b| {
+        b.iter(|| {
+            clear_mint_cache();
+            metadata_pda(black_box(&mint))
+        })
+    });
+
+    group.bench_function("metadata_pda_cached", |b| {
+        b.iter(|| metadata_pda(black_box(&mint)))
+    });
+
+    group.finish();
+}
+
+/// Measures the UI amount conversions in both directions
+fn amount_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("amount");
+
+    group.bench_function("ui_to_base", |b| {
+        b.iter(|| ui_to_base_amount(black_box("1234.56789"), 9))
+    });
+
+    group.bench_function("base_to_ui", |b| {
+        b.iter(|| base_to_ui_amount(black_box(1_234_567_890_000), 9))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, instruction_benchmark, amount_benchmark);
+criterion_main!(benches);
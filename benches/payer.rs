@@ -0,0 +1,56 @@
+use criterion::{Criterion, black_box, criterion_group, criterion_main};
+use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer};
+use sss_shared::{MintOptions, build_mint_ix, get_payer, get_payer_pubkey, set_payer};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Threads building transactions at the same time, as in a large airdrop
+const THREADS: usize = 32;
+
+/// Mint instructions each thread builds per iteration
+const BATCH: usize = 64;
+
+/// Builds a batch of mint instructions on every thread and returns the wall time
+fn build_batches(iters: u64, payer: fn() -> Pubkey) -> Duration {
+    let mint = Pubkey::new_unique();
+    let options = MintOptions::default();
+    let start = Instant::now();
+    thread::scope(|scope| {
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                for _ in 0..iters {
+                    for _ in 0..BATCH {
+                        let payer = payer();
+                        let owner = Pubkey::new_unique();
+                        black_box(build_mint_ix(&payer, &mint, &owner, 1, &options).unwrap());
+                    }
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+/// Compares reading the payer public key through the keypair lock and through the cache
+/// while 32 threads build mint instructions
+///
+/// Runs offline; the payer is set to a random keypair.
+fn payer_benchmark(c: &mut Criterion) {
+    set_payer(Keypair::new());
+
+    let mut group = c.benchmark_group("payer_pubkey_32_threads");
+    group.sample_size(10);
+
+    group.bench_function("keypair", |b| {
+        b.iter_custom(|iters| build_batches(iters, || get_payer().unwrap().pubkey()))
+    });
+
+    group.bench_function("cached", |b| {
+        b.iter_custom(|iters| build_batches(iters, || get_payer_pubkey().unwrap()))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, payer_benchmark);
+criterion_main!(benches);
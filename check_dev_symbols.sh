@@ -0,0 +1,36 @@
+#!/bin/bash
+
+# Proves a release build without the `dev-tools` feature cannot airdrop or mass-burn:
+# neither the C library nor the Rust library may contain the development helpers.
+#
+# Builds the release library with the default features and inspects its symbols, then
+# builds it with `dev-tools` to make sure the check would have caught them.
+
+cd "$(dirname "$0")"
+
+DEV_FFI_SYMBOLS="sss_request_airdrop sss_burn_all_assets sss_faucet_grant"
+DEV_RUST_PATH="sss_shared::devtools::"
+
+# Prints the dev-only symbols defined in the release libraries
+dev_symbols() {
+    for symbol in $DEV_FFI_SYMBOLS; do
+        nm -D --defined-only target/release/libsss_shared.so | grep -w "$symbol"
+    done
+    nm -C --defined-only target/release/libsss_shared.rlib 2>/dev/null | grep -F "$DEV_RUST_PATH"
+}
+
+echo "Building the release library with the default features..."
+cargo build --release --lib || exit 1
+if dev_symbols; then
+    echo "❌ The release library exports development helpers"
+    exit 1
+fi
+
+echo "Building the release library with dev-tools..."
+cargo build --release --lib --features dev-tools || exit 1
+if ! dev_symbols > /dev/null; then
+    echo "❌ The development helpers were not found with dev-tools, so the check is broken"
+    exit 1
+fi
+
+echo "✅ Release builds without dev-tools contain no airdrop or mass-burn symbols"
@@ -0,0 +1,52 @@
+#!/bin/bash
+
+# Builds every feature combination and checks the core instruction builders still
+# produce the golden messages in each one.
+#
+# Uses `cargo hack --feature-powerset` when cargo-hack is installed, otherwise checks
+# no features, each feature on its own, the defaults and all features.
+
+cd "$(dirname "$0")"
+
+FEATURES="ffi das env-config mnemonic keystore http-metadata metrics dev-tools u64-amounts"
+
+# Hosts discover features through `sss_capabilities`, so each needs a flag
+for feature in $FEATURES; do
+    if ! grep -q "cfg!(feature = \"$feature\")" src/capabilities.rs; then
+        echo "❌ Feature $feature has no capability flag in src/capabilities.rs"
+        exit 1
+    fi
+done
+
+if cargo hack --version > /dev/null 2>&1; then
+    echo "Checking the feature powerset with cargo-hack..."
+    cargo hack clippy --feature-powerset --exclude-features content-cache --all-targets -- -D warnings
+    if [ $? -ne 0 ]; then
+        echo "❌ A feature combination failed to build"
+        exit 1
+    fi
+    echo "✅ Every feature combination builds"
+    exit 0
+fi
+
+COMBINATIONS=("--no-default-features" "" "--all-features")
+for feature in $FEATURES; do
+    COMBINATIONS+=("--no-default-features --features $feature")
+done
+
+for flags in "${COMBINATIONS[@]}"; do
+    echo "Checking ${flags:-default features}..."
+    cargo clippy --all-targets $flags -- -D warnings
+    if [ $? -ne 0 ]; then
+        echo "❌ Failed to build with ${flags:-default features}"
+        exit 1
+    fi
+
+    cargo test --quiet --test golden_messages $flags
+    if [ $? -ne 0 ]; then
+        echo "❌ Golden messages differ with ${flags:-default features}"
+        exit 1
+    fi
+done
+
+echo "✅ Every feature combination builds and matches the golden messages"
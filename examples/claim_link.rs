@@ -0,0 +1,49 @@
+use solana_sdk::pubkey::Pubkey;
+use sss_shared::{
+    DateTime, SssError, Utc, create_claim_link, reclaim_claim_link, redeem_claim_link,
+};
+use std::env;
+use std::error::Error;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    // The mint of the tokens to hand out and the wallet redeeming the link
+    let mut args = env::args().skip(1);
+    let (Some(mint), Some(recipient)) = (args.next(), args.next()) else {
+        println!("Usage: claim_link <mint> <recipient>");
+        return Ok(());
+    };
+    let mint = Pubkey::from_str(&mint)?;
+    let recipient = Pubkey::from_str(&recipient)?;
+
+    // Escrow one token for a week
+    let expiry = DateTime::<Utc>::from(SystemTime::now() + Duration::from_secs(7 * 86_400));
+    let link = create_claim_link(mint, 1, expiry)?;
+    println!("✅ Claim link created, escrow {}", link.escrow);
+    println!("Funding transaction: {}", link.signature);
+    println!(
+        "Encode in the QR code: https://example.com/claim/{}",
+        link.payload
+    );
+
+    // The claim page redeems the scanned payload for the visitor's wallet
+    let signature = redeem_claim_link(&link.payload, recipient)?;
+    println!("✅ Redeemed to {}: {}", recipient, signature);
+
+    // The link is single-use
+    match redeem_claim_link(&link.payload, recipient) {
+        Err(SssError::ClaimLinkRedeemed { .. }) => println!("Second redemption refused"),
+        other => println!("❌ Unexpected second redemption: {:?}", other),
+    }
+
+    // Links nobody redeems are taken back by the payer
+    let unclaimed = create_claim_link(mint, 1, expiry)?;
+    let signature = reclaim_claim_link(unclaimed.escrow)?;
+    println!(
+        "✅ Reclaimed unredeemed link {}: {}",
+        unclaimed.escrow, signature
+    );
+
+    Ok(())
+}
@@ -0,0 +1,24 @@
+use sss_shared::{DEFAULT_DAS_CACHE_DIR, prune_das_cache};
+use std::env;
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::Duration;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    // Usage: prune_das_cache [dir] [days], defaulting to the default cache dir and 30 days
+    let mut args = env::args().skip(1);
+    let dir = PathBuf::from(
+        args.next()
+            .unwrap_or_else(|| DEFAULT_DAS_CACHE_DIR.to_string()),
+    );
+    let days: u64 = args.next().map(|d| d.parse()).transpose()?.unwrap_or(30);
+
+    let removed = prune_das_cache(&dir, Duration::from_secs(days * 24 * 60 * 60))?;
+    println!(
+        "Removed {} DAS responses recorded more than {} days ago from {}",
+        removed,
+        days,
+        dir.display()
+    );
+    Ok(())
+}
@@ -43,6 +43,1416 @@ int mint_token_ffi(
     int signature_len
 );
 
+/**
+ * Fetches an asset image into a local cache and returns the cached file path
+ *
+ * Only available when the library is built with the `content-cache` feature.
+ *
+ * @param uri_ptr The base58 asset id, or the asset or metadata URI (https://, http:// or ipfs://)
+ * @param cache_dir_ptr The directory used to store cached content
+ * @param path_out A pointer to a buffer where the cached file path will be written
+ * @param path_len The length of the path_out buffer
+ * @return 0 on success, non-zero error code on failure
+ */
+int sss_fetch_asset_image(
+    const char* uri_ptr,
+    const char* cache_dir_ptr,
+    char* path_out,
+    int path_len
+);
+
+/**
+ * Signs a message with the payer keypair
+ *
+ * @param message_b64 The base64-encoded message bytes
+ * @param signature_out A pointer to a buffer where the base58 signature will be written
+ * @param signature_len The length of the signature_out buffer
+ * @return 0 on success, non-zero error code on failure
+ */
+int sss_sign_message(
+    const char* message_b64,
+    char* signature_out,
+    int signature_len
+);
+
+/**
+ * Verifies a message signature
+ *
+ * @param pubkey_str The base58 public key expected to have signed the message
+ * @param message_b64 The base64-encoded message bytes
+ * @param signature_b58 The base58 signature to check
+ * @return 1 if the signature is valid, 0 if not, negative error code on failure
+ */
+int sss_verify_message(
+    const char* pubkey_str,
+    const char* message_b64,
+    const char* signature_b58
+);
+
+/**
+ * Writes a JSON snapshot of the library metrics
+ *
+ * "callback_queues" has the "depth", "capacity", "dropped" and "stalls" of the
+ * event and balance callback queues, and "stalled_ms" while a callback runs
+ * past the watchdog timeout.
+ *
+ * @param json_out A pointer to a buffer where the JSON will be written
+ * @param json_len The length of the json_out buffer
+ * @return 0 on success, non-zero error code on failure
+ */
+int sss_metrics_json(char* json_out, int json_len);
+
+/**
+ * Details of the last failed FFI call on the calling thread
+ */
+typedef struct SssFfiError {
+    int code;          /* error code returned by the failed call */
+    int param_index;   /* zero-based index of the offending parameter, -1 if none */
+    int byte_offset;   /* offset of the first invalid byte in the parameter, -1 if unknown */
+    char message[256]; /* null-terminated description naming the parameter */
+} SssFfiError;
+
+/**
+ * Retrieves details of the last failed FFI call made on the calling thread
+ *
+ * @param error_out A pointer to a struct that receives the error details
+ * @return 0 if an error was written, 1 if no error has been recorded, -1 if error_out is NULL
+ */
+int sss_get_last_error(SssFfiError* error_out);
+
+/**
+ * State of a recipient's associated token account
+ */
+typedef struct SssRecipientStatus {
+    int account_exists;         /* 1 if the token account exists, 0 otherwise */
+    int frozen;                 /* 1 if the account is frozen and minting to it would fail */
+    uint64_t balance;           /* current balance of the token account */
+    uint64_t creation_lamports; /* lamports charged to create the account, 0 if it exists */
+    uint64_t platform_fee_lamports; /* platform fee charged for the mint, 0 if none applies */
+} SssRecipientStatus;
+
+/**
+ * Checks a recipient's token account before minting
+ *
+ * Makes at most two RPC calls and caches the mint properties.
+ *
+ * @param mint_str The base58 address of the mint
+ * @param owner_str The base58 address of the receiving wallet
+ * @param status_out A pointer to a struct that receives the account state
+ * @return 0 on success, non-zero error code on failure
+ */
+int sss_check_recipient(
+    const char* mint_str,
+    const char* owner_str,
+    SssRecipientStatus* status_out
+);
+
+/**
+ * Adds a mint to the trusted set
+ *
+ * @param mint_str The base58 address of the mint to trust
+ * @return 0 on success, non-zero error code on failure
+ */
+int sss_add_trusted_mint(const char* mint_str);
+
+/**
+ * Enables or disables strict mode, in which operations on untrusted mints fail
+ *
+ * @param enabled Non-zero to enable strict mode, 0 to disable it
+ * @return 0 on success
+ */
+int sss_set_strict_mode(int enabled);
+
+/**
+ * Mints tokens into an existing token account
+ *
+ * The account must hold tokens of the given mint and must not be frozen; its
+ * owner is read from the account data.
+ *
+ * @param mint_str The base58 address of the mint
+ * @param token_account_str The base58 address of the receiving token account
+ * @param amount The amount of tokens to mint
+ * @param signature_out A buffer to receive the transaction signature
+ * @param signature_len The size of the signature buffer
+ * @return 0 on success, non-zero error code on failure
+ */
+int sss_mint_token_to_account(
+    const char* mint_str,
+    const char* token_account_str,
+    uint64_t amount,
+    char* signature_out,
+    int signature_len
+);
+
+/**
+ * Writes the base58 address of the payer wallet
+ *
+ * @param pubkey_out A buffer to receive the address
+ * @param pubkey_len The size of the pubkey_out buffer
+ * @return 0 on success, non-zero error code on failure (-3 if the payer failed to load)
+ */
+int sss_get_payer_pubkey(char* pubkey_out, int pubkey_len);
+
+/**
+ * Writes the payer address, lamport balance and load state as JSON
+ *
+ * Succeeds even if the payer failed to load; the JSON then has "loaded": false
+ * and the reason in "load_error". In watch-only mode "watch_only" is true and the
+ * watched wallet is reported.
+ *
+ * @param json_out A buffer to receive the JSON
+ * @param json_len The size of the json_out buffer
+ * @return 0 on success, non-zero error code on failure
+ */
+int sss_get_payer_info(char* json_out, int json_len);
+
+/**
+ * Mints several tokens to the same wallet in a single transaction
+ *
+ * The payer must be the mint authority of every mint; the failure message
+ * names the offending mint otherwise.
+ *
+ * @param items_json A JSON array of {"mint": "<address>", "amount": <base units>} objects
+ * @param owner_str The base58 address of the receiving wallet
+ * @param signature_out A buffer to receive the transaction signature
+ * @param signature_len The size of the signature buffer
+ * @return 0 on success, non-zero error code on failure
+ */
+int sss_mint_basket(
+    const char* items_json,
+    const char* owner_str,
+    char* signature_out,
+    int signature_len
+);
+
+/**
+ * Called with the new balance in base units and the user_data given when
+ * subscribing. Runs on a background thread.
+ */
+typedef void (*SssBalanceCallback)(uint64_t amount, void* user_data);
+
+/**
+ * Gets notified whenever a wallet's token balance changes
+ *
+ * Balances of all subscriptions are polled together every SSS_BALANCE_POLL_MS
+ * milliseconds (default 2000). The current balance is delivered once after
+ * subscribing and again after RPC outages, so no change is missed. Callbacks
+ * run one at a time behind the balance_callbacks queue, see
+ * sss_set_callback_queue_options.
+ *
+ * @param mint_str The base58 address of the mint
+ * @param owner_str The base58 address of the watched wallet
+ * @param callback The function receiving balance changes
+ * @param user_data Passed to the callback unchanged; must stay valid until unsubscribed
+ * @return A positive subscription handle on success, negative error code on failure
+ */
+int64_t sss_subscribe_balance(
+    const char* mint_str,
+    const char* owner_str,
+    SssBalanceCallback callback,
+    void* user_data
+);
+
+/**
+ * Stops the notifications of a balance subscription
+ *
+ * @param handle The handle returned by sss_subscribe_balance
+ * @return 0 on success, -1 if the handle is unknown
+ */
+int sss_unsubscribe(int64_t handle);
+
+/**
+ * Called with the JSON of one page of assets and the user_data given when
+ * fetching. The JSON is only valid during the call. Return non-zero to stop.
+ */
+typedef int (*SssAssetPageCallback)(const char* page_json, void* user_data);
+
+/**
+ * Fetches every asset held by a wallet, one page at a time
+ *
+ * Each page is passed to the callback as JSON with "page", "assets",
+ * "next_cursor" and "progress". Failed pages are retried before giving up;
+ * pass the last delivered next_cursor back as cursor to resume.
+ *
+ * Only available when the library is built with the `das` feature.
+ *
+ * @param owner_str The base58 address of the wallet
+ * @param cursor The page to start from, 0 or 1 for the first page
+ * @param callback The function receiving each page
+ * @param user_data Passed to the callback unchanged
+ * @return 0 once every page was delivered, 1 if the callback stopped the
+ *         fetch, negative error code on failure
+ */
+int sss_fetch_all_assets(
+    const char* owner_str,
+    uint32_t cursor,
+    SssAssetPageCallback callback,
+    void* user_data
+);
+
+/**
+ * Called with one chunk of a streamed JSON document and the user_data given
+ * when streaming. The chunk is len bytes, not null-terminated, and only valid
+ * during the call. Return non-zero to stop.
+ */
+typedef int (*SssChunkCallback)(const char* chunk, int len, void* user_data);
+
+/**
+ * Streams every asset held by a wallet as one JSON document, in chunks
+ *
+ * The chunks concatenate to {"v": 2, "data": [...]} with one asset per
+ * element, written as the pages arrive, so no buffer has to hold the whole
+ * list. A chunk may end in the middle of an element. Unless 0 is returned,
+ * the chunks delivered so far are not a complete document.
+ *
+ * Only available when the library is built with the `das` feature.
+ *
+ * @param owner_str The base58 address of the wallet
+ * @param chunk_cb The function receiving each chunk of at most 64 KiB
+ * @param user_data Passed to the callback unchanged
+ * @return 0 once the document is complete, 1 if the callback stopped the
+ *         stream, -2 for an invalid owner, -3 if fetching failed,
+ *         -4 if an asset could not be serialized
+ */
+int sss_fetch_assets_stream(
+    const char* owner_str,
+    SssChunkCallback chunk_cb,
+    void* user_data
+);
+
+/**
+ * Mints tokens to many recipients in batches, resuming from a checkpoint file
+ *
+ * Recipients that fail do not make the call fail. The report JSON lists
+ * "succeeded" ({"key", "signature"} objects), "failed" ({"key", "error":
+ * {"kind", "message"}} objects, one per recipient of a failed transaction),
+ * "skipped" (addresses already confirmed by a previous run),
+ * "supply_cap_violation" and "platform_fee_lamports".
+ *
+ * @param mint_str The base58 address of the mint
+ * @param allocations_json A JSON array of {"recipient": "<address>", "amount": <base units>} objects
+ * @param checkpoint_path The JSON file recording the progress of the airdrop
+ * @param report_out A buffer to receive the report JSON
+ * @param report_len The size of the report buffer
+ * @return 0 on success, non-zero error code on failure
+ */
+int sss_execute_airdrop(
+    const char* mint_str,
+    const char* allocations_json,
+    const char* checkpoint_path,
+    char* report_out,
+    int report_len
+);
+
+/**
+ * How sss_format_token_amount renders and sss_parse_token_amount reads an amount
+ */
+typedef struct SssFormatOptions {
+    uint32_t grouping_separator; /* code point between groups of three digits, 0 for none */
+    uint32_t decimal_separator;  /* code point between whole and fractional digits */
+    uint8_t min_fraction_digits; /* fractional digits always shown, padded with zeros */
+    int max_fraction_digits;     /* fractional digits shown at most, rounded half up; -1 for all */
+    int trim_trailing_zeros;     /* 1 to drop trailing zeros beyond min_fraction_digits */
+} SssFormatOptions;
+
+/**
+ * Formats an amount in base units for display, e.g. 1000000 with 6 decimals as "1"
+ *
+ * @param amount The amount in base units
+ * @param decimals The number of decimal places of the mint
+ * @param options The separators and fraction digits, or NULL for "1234.5" style
+ * @param formatted_out A buffer to receive the formatted amount
+ * @param formatted_len The size of the formatted_out buffer
+ * @return 0 on success, non-zero error code on failure
+ */
+int sss_format_token_amount(
+    uint64_t amount,
+    unsigned char decimals,
+    const SssFormatOptions* options,
+    char* formatted_out,
+    int formatted_len
+);
+
+/**
+ * Parses an amount formatted by sss_format_token_amount into base units
+ *
+ * Grouping separators are optional but must separate groups of three digits.
+ *
+ * @param formatted_str The formatted amount
+ * @param decimals The number of decimal places of the mint
+ * @param options The separators used, or NULL for "1234.5" style
+ * @param amount_out A pointer that receives the amount in base units
+ * @return 0 on success, non-zero error code on failure
+ */
+int sss_parse_token_amount(
+    const char* formatted_str,
+    unsigned char decimals,
+    const SssFormatOptions* options,
+    uint64_t* amount_out
+);
+
+/**
+ * Writes the receipt of a confirmed transaction as JSON
+ *
+ * The receipt holds "signature", "slot", "block_time", "fee", "fee_payer",
+ * "success", "error", the decoded "instructions", "instruction_summaries"
+ * and the pre/post "token_balances" of every token account touched.
+ *
+ * @param signature_str The base58 transaction signature
+ * @param json_out A pointer to a buffer where the JSON receipt will be written
+ * @param json_len The length of the json_out buffer
+ * @return 0 on success, negative error code on failure
+ */
+int sss_fetch_transaction_receipt(
+    const char* signature_str,
+    char* json_out,
+    int json_len
+);
+
+/**
+ * Transfers an asset held by the payer to another wallet
+ *
+ * The token standard is read from the metadata: pNFTs are moved with the
+ * metadata program's TransferV1, everything else with a checked token
+ * transfer. The recipient's associated token account is created if missing.
+ *
+ * @param mint_str The base58 address of the mint
+ * @param to_str The base58 address of the receiving wallet
+ * @param amount The amount in base units, or 0 for the whole balance; NFTs always transfer 1
+ * @param signature_out A buffer to receive the transaction signature
+ * @param signature_len The size of the signature buffer
+ * @return 0 on success, non-zero error code on failure
+ */
+int sss_transfer_asset(
+    const char* mint_str,
+    const char* to_str,
+    uint64_t amount,
+    char* signature_out,
+    int signature_len
+);
+
+/**
+ * Called with the JSON of a library event, wrapped as {"v": 2, "data": ...},
+ * and the user_data given when registering. Runs on the event delivery thread. The JSON is only valid
+ * during the call.
+ */
+typedef void (*SssEventCallback)(const char* json, void* user_data);
+
+/**
+ * Receives library events as JSON objects tagged with a "type"
+ *
+ * A successful mint emits transaction_sent, transaction_confirmed,
+ * payer_low_on_funds (only below SSS_LOW_BALANCE_LAMPORTS) and
+ * mint_confirmed; each retried attempt adds a retry_scheduled event before
+ * transaction_confirmed. Events that do not fit in the queue while the
+ * callback falls behind are dropped, or delay the operation, as set by
+ * sss_set_callback_queue_options; an events_dropped event tells how many
+ * were missed.
+ *
+ * @param callback The function receiving events, or NULL to stop them
+ * @param user_data Passed to the callback unchanged; must stay valid until replaced or shut down
+ * @return 0 on success
+ */
+int sss_set_event_callback(SssEventCallback callback, void* user_data);
+
+/**
+ * Stops the library's background activity before the host exits
+ *
+ * Queued asynchronous calls run and their callbacks return first. No event
+ * callback runs once this returns, except one already stalled past the
+ * watchdog timeout (SSS_CALLBACK_WATCHDOG_MS, default 30000), which is not
+ * waited for. Balance subscriptions are dropped. Must not be called from an
+ * event callback; called from a completion callback, it does not wait for the
+ * queue.
+ */
+void sss_shutdown(void);
+
+/**
+ * Error code returned by every function refused because the library is in read-only mode
+ */
+#define SSS_ERR_READ_ONLY -20
+
+/**
+ * Error code returned by every function refused because the library is in watch-only mode
+ */
+#define SSS_ERR_WATCH_ONLY -21
+
+/**
+ * Largest output buffer length accepted, in bytes (16 MiB)
+ *
+ * Every *_len argument of an output buffer must be positive and at most this; other
+ * values are rejected as corrupted before anything is written.
+ */
+#define SSS_MAX_BUFFER_LEN (16 * 1024 * 1024)
+
+/**
+ * Enables or disables read-only mode, in which every function that would sign or send a
+ * transaction fails with SSS_ERR_READ_ONLY
+ *
+ * @param enabled Non-zero to enable read-only mode, 0 to disable it
+ * @return 0 on success
+ */
+int sss_set_read_only(int enabled);
+
+/**
+ * Generates a new random reference key for a payment request
+ *
+ * @param reference_out A buffer to receive the base58 reference key
+ * @param reference_len The size of the reference buffer
+ * @return 0 on success, negative error code on failure
+ */
+int sss_generate_payment_reference(char* reference_out, int reference_len);
+
+/**
+ * Builds a Solana Pay transfer request paying the payer wallet
+ *
+ * The label and message are percent-encoded into the URL.
+ *
+ * @param amount The amount in base units, lamports for SOL; must be greater than 0
+ * @param spl_mint_str The base58 address of the SPL token to pay in, or NULL for SOL
+ * @param reference_str The base58 reference key, see sss_generate_payment_reference
+ * @param label Who is requesting the payment, or NULL
+ * @param message What the payment is for, or NULL
+ * @param url_out A buffer to receive the solana: URL
+ * @param url_len The size of the URL buffer
+ * @return 0 on success, negative error code on failure
+ */
+int sss_create_payment_request(
+    uint64_t amount,
+    const char* spl_mint_str,
+    const char* reference_str,
+    const char* label,
+    const char* message,
+    char* url_out,
+    int url_len
+);
+
+/**
+ * Waits for a payment to the payer wallet carrying a reference key
+ *
+ * The reference key is polled once per second. The oldest successful transaction
+ * crediting the payer wallet decides the outcome.
+ *
+ * @param reference_str The base58 reference key of the request
+ * @param spl_mint_str The base58 address of the SPL token requested, or NULL for SOL
+ * @param amount The requested amount in base units, lamports for SOL
+ * @param timeout_ms How long to wait for the payment in milliseconds
+ * @param signature_out A buffer to receive the signature of the paying transaction
+ * @param signature_len The size of the signature buffer
+ * @return 0 on success, -5 if the payment was short, -6 on timeout or other failure
+ */
+int sss_await_payment(
+    const char* reference_str,
+    const char* spl_mint_str,
+    uint64_t amount,
+    uint64_t timeout_ms,
+    char* signature_out,
+    int signature_len
+);
+
+/**
+ * Selects the profile used by later calls on the calling thread
+ *
+ * Profiles are read from the JSON file named by SSS_PROFILES_PATH. Each profile has
+ * its own payer, trusted mints, default collection and platform fee, and other threads
+ * keep their own selection.
+ *
+ * @param name The name of the profile, or NULL for the global configuration
+ * @return 0 on success, -2 if the name is not valid UTF-8, -3 if no such profile is registered
+ */
+int sss_select_profile(const char* name);
+
+/**
+ * Calls a library function by name with JSON parameters
+ *
+ * Writes {"v": 2, "data": {"result": ...}} on success and
+ * {"v": 2, "data": {"error": {"kind", "message", "field"}}} on failure, where
+ * field names the rejected parameter. If the response does not fit,
+ * nothing is written; the operation has already run, so do not repeat a mutation.
+ *
+ * @param method The name of the method, e.g. "mint_token"
+ * @param params_json A JSON object of the method's parameters, or NULL for none
+ * @param result_out A buffer to receive the JSON response
+ * @param result_len The size of the response buffer
+ * @param required_len_out Receives the size the response needs, including the null terminator, or NULL
+ * @return 0 on success, -2 for an unknown method, -3 for invalid parameters, -4 if the
+ *         buffer is too small, -5 if the operation failed, -6 if it panicked
+ */
+int sss_call(
+    const char* method,
+    const char* params_json,
+    char* result_out,
+    int result_len,
+    int* required_len_out
+);
+
+/**
+ * Loads the payer from a passphrase-encrypted keystore file
+ *
+ * Unlike PAYER_KEYSTORE_PASSPHRASE, the passphrase never passes through the environment.
+ * A wrong passphrase and a damaged file fail alike.
+ *
+ * Only available when the library is built with the `keystore` feature.
+ *
+ * @param path The path of the keystore file
+ * @param passphrase The passphrase the keystore was saved with
+ * @return 0 on success, -2 or -3 if path or passphrase is not valid UTF-8, -4 if the
+ *         file cannot be read or the passphrase is wrong
+ */
+int sss_unlock_keystore(const char* path, const char* passphrase);
+
+/**
+ * Writes the consistency report of a token as JSON
+ *
+ * Compares the mint account, the metadata account and the off-chain
+ * metadata. The report holds "mint", "decimals", "supply", "name",
+ * "symbol", "uri", "offchain_checked" and "findings", each finding with
+ * "field", "severity" ("info", "warning" or "error"), "on_chain",
+ * "offchain" and "message". The off-chain metadata is only compared when
+ * the library is built with the `http-metadata` feature; a document that
+ * cannot be fetched is reported as a warning.
+ *
+ * @param mint_str The mint address as a base58 string
+ * @param json_out A pointer to a buffer where the JSON report will be written
+ * @param json_len The length of the json_out buffer
+ * @return 0 on success, negative error code on failure
+ */
+int sss_audit_token_consistency(
+    const char* mint_str,
+    char* json_out,
+    int json_len
+);
+
+/**
+ * Requests SOL for the payer from the cluster's faucet and waits for it
+ *
+ * Only available when the library is built with the `dev-tools` feature;
+ * release builds without it do not export this symbol. Mainnet-beta is
+ * refused.
+ *
+ * @param lamports The amount to request
+ * @param signature_out A pointer to a buffer where the signature will be written
+ * @param signature_len The length of the signature_out buffer
+ * @return 0 on success, negative error code on failure
+ */
+int sss_request_airdrop(
+    uint64_t lamports,
+    char* signature_out,
+    int signature_len
+);
+
+/**
+ * Burns the payer's tokens and closes their accounts, writing the report as JSON
+ *
+ * Only mints whose update authority is the payer are burned, and trusted
+ * mints never are. Only available when the library is built with the
+ * `dev-tools` feature; release builds without it do not export this
+ * symbol. Mainnet-beta is refused unless SSS_ALLOW_MAINNET_CLEANUP is set.
+ *
+ * @param dry_run Non-zero to report what would be burned without sending anything
+ * @param json_out A pointer to a buffer where the JSON report will be written
+ * @param json_len The length of the json_out buffer
+ * @return 0 on success, negative error code on failure
+ */
+int sss_burn_all_assets(
+    int dry_run,
+    char* json_out,
+    int json_len
+);
+
+/**
+ * Returns the version of the JSON schema the library writes
+ *
+ * Every JSON payload is wrapped as {"v": <version>, "data": ...}. Within a
+ * version, payloads only gain fields, so ignore unknown fields and compare
+ * this with the version the host was built against before reading any.
+ *
+ * @return The schema version, currently 2
+ */
+int sss_ffi_schema_version(void);
+
+/**
+ * Creates a token from a template
+ *
+ * Templates are read from the JSON file named by SSS_TEMPLATES_PATH. The
+ * overrides give the token name and whatever differs from the template, e.g.
+ * {"name": "Coffee B-12", "attributes": {"batch": "B-12"}}.
+ *
+ * @param template_name The name of the template
+ * @param overrides_json The JSON of the overrides
+ * @param signature_out A pointer to a buffer where the transaction signature will be written
+ * @param signature_len The length of the signature_out buffer
+ * @param mint_address_out A pointer to a buffer where the mint address will be written
+ * @param mint_address_len The length of the mint_address_out buffer
+ * @return 0 on success, -2 for an invalid template name, -3 for invalid overrides JSON,
+ *         -4/-5 if a buffer is too small, -6 if the creation failed
+ */
+int sss_create_token_from_template(
+    const char* template_name,
+    const char* overrides_json,
+    char* signature_out,
+    int signature_len,
+    char* mint_address_out,
+    int mint_address_len
+);
+
+/**
+ * Reloads the trusted mints and supply cap files without a restart
+ *
+ * Reads the files named by SSS_TRUSTED_MINTS_PATH, SSS_SUPPLY_CAPS_PATH and
+ * SSS_ROLE_POLICIES_PATH. If any fails to parse, all policies are kept and the
+ * error is available from sss_get_last_error.
+ *
+ * @param json_out A buffer to receive the JSON of what changed, or NULL
+ * @param json_len The length of the json_out buffer
+ * @return 0 on success, -2 if the buffer is too small, -3 if a file could not be loaded
+ */
+int sss_reload_policies(char* json_out, int json_len);
+
+/**
+ * Builds a claim transaction minting tokens to a wallet that holds no SOL
+ *
+ * The payer covers the fees and has already signed; the claimer's wallet
+ * countersigns the raw bytes if required before they are submitted.
+ *
+ * @param mint_str The mint of the tokens
+ * @param claimer_str The wallet receiving the tokens
+ * @param amount The amount in base units
+ * @param tx_out A pointer to a buffer where the bincode-serialized transaction will be written
+ * @param tx_len The length of the tx_out buffer
+ * @param tx_written_out Receives the number of bytes written to tx_out
+ * @return 0 on success, -2 for an invalid mint, -3 for an invalid claimer,
+ *         -4 if the buffer is too small, -5 if the transaction could not be built
+ */
+int sss_build_claim_transaction(
+    const char* mint_str,
+    const char* claimer_str,
+    uint64_t amount,
+    unsigned char* tx_out,
+    int tx_len,
+    int* tx_written_out
+);
+
+/**
+ * Creates a claim link for tokens held by the payer
+ *
+ * Moves the tokens into an escrow and writes a URL-safe payload to encode in
+ * a QR code. Whoever holds the payload can redeem it until the expiry.
+ *
+ * @param mint_str The mint of the tokens to escrow
+ * @param amount The amount in base units
+ * @param expiry_unix When the link expires, in seconds since the Unix epoch
+ * @param payload_out A pointer to a buffer where the payload will be written
+ * @param payload_len The length of the payload_out buffer
+ * @param signature_out A pointer to a buffer where the transaction signature will be written
+ * @param signature_len The length of the signature_out buffer
+ * @return 0 on success, -2 for an invalid mint, -3 for an invalid expiry,
+ *         -4/-5 if a buffer is too small, -6 if the link could not be created
+ */
+int sss_create_claim_link(
+    const char* mint_str,
+    uint64_t amount,
+    int64_t expiry_unix,
+    char* payload_out,
+    int payload_len,
+    char* signature_out,
+    int signature_len
+);
+
+/**
+ * Redeems a claim link, sending its tokens to a recipient
+ *
+ * @param payload The payload scanned from the QR code
+ * @param recipient_str The wallet receiving the tokens
+ * @param signature_out A pointer to a buffer where the transaction signature will be written
+ * @param signature_len The length of the signature_out buffer
+ * @return 0 on success, -2 for an invalid payload string, -3 for an invalid recipient,
+ *         -4 if the buffer is too small, -5 if the link has expired,
+ *         -6 if the link was already redeemed or reclaimed, -7 if the redemption failed
+ */
+int sss_redeem_claim_link(
+    const char* payload,
+    const char* recipient_str,
+    char* signature_out,
+    int signature_len
+);
+
+/**
+ * Returns the tokens of an unredeemed claim link to the payer
+ *
+ * @param escrow_str The escrow account of the link
+ * @param signature_out A pointer to a buffer where the transaction signature will be written
+ * @param signature_len The length of the signature_out buffer
+ * @return 0 on success, -2 for an invalid escrow, -3 if the buffer is too small,
+ *         -4 if the tokens could not be reclaimed
+ */
+int sss_reclaim_claim_link(
+    const char* escrow_str,
+    char* signature_out,
+    int signature_len
+);
+
+/**
+ * Verifies a command signed by the backend
+ *
+ * The backend signs "SSS signed command\n" followed by the command as compact
+ * JSON with the fields operation, mint, owner, amount, nonce and expiry in
+ * that order. A successful verification uses up the nonce.
+ *
+ * @param payload_json The command as JSON
+ * @param signature_b58 The base58 signature of the command
+ * @param trusted_signer_str The backend's public key
+ * @param command_json_out A pointer to a buffer where the verified command JSON will be written
+ * @param command_json_len The length of the command_json_out buffer
+ * @return 0 on success, -2/-3/-4 for an invalid payload, signature or signer string,
+ *         -5 if the buffer is too small, -6 if the command was rejected
+ */
+int sss_verify_signed_command(
+    const char* payload_json,
+    const char* signature_b58,
+    const char* trusted_signer_str,
+    char* command_json_out,
+    int command_json_len
+);
+
+/**
+ * Verifies a command signed by the backend and carries it out
+ *
+ * @param payload_json The command as JSON
+ * @param signature_b58 The base58 signature of the command
+ * @param trusted_signer_str The backend's public key
+ * @param signature_out A pointer to a buffer where the transaction signature will be written
+ * @param signature_len The length of the signature_out buffer
+ * @return 0 on success, -2/-3/-4 for an invalid payload, signature or signer string,
+ *         -5 if the buffer is too small, -6 if the command was rejected,
+ *         -7 if the token operation failed
+ */
+int sss_execute_signed_command(
+    const char* payload_json,
+    const char* signature_b58,
+    const char* trusted_signer_str,
+    char* signature_out,
+    int signature_len
+);
+
+/**
+ * Reviews a partner transaction and co-signs and submits it as fee payer
+ *
+ * Only the system, token, associated token account, memo and compute budget
+ * programs are allowed. The outcome JSON holds the signature of the submitted
+ * transaction, or every rule the transaction breaks.
+ *
+ * @param tx_b64 The bincode-serialized legacy or v0 transaction, base64-encoded
+ * @param max_payer_outflow_lamports The most lamports the payer may spend, including the fee
+ * @param outcome_json_out A pointer to a buffer where the outcome JSON will be written
+ * @param outcome_json_len The length of the outcome_json_out buffer
+ * @return 0 if the transaction was submitted, 1 if it was rejected, -2 for an
+ *         invalid transaction string, -3 if the buffer is too small, -4 if the
+ *         review or submission failed
+ */
+int sss_review_external_transaction(
+    const char* tx_b64,
+    uint64_t max_payer_outflow_lamports,
+    char* outcome_json_out,
+    int outcome_json_len
+);
+
+/**
+ * Lists the recent movements of a wallet's tokens as JSON
+ *
+ * Each item is received, sent, self_transfer, minted or burned, with the mint,
+ * amount, counterparty, block time and signature. Pass the next_cursor of a
+ * page to get the one after it.
+ *
+ * @param owner_str The wallet
+ * @param mints_json A JSON array of the mints to include, or NULL for the trusted mints
+ * @param limit The number of items wanted
+ * @param cursor The next_cursor of the previous page, or NULL for the newest items
+ * @param json_out A pointer to a buffer where the page JSON will be written
+ * @param json_len The length of the json_out buffer
+ * @return 0 on success, -2 for an invalid owner, -3 for an invalid mint list,
+ *         -4 for a limit below 1, -5 for an invalid cursor string, -6 if the
+ *         buffer is too small, -7 if the history cannot be read
+ */
+int sss_wallet_activity(
+    const char* owner_str,
+    const char* mints_json,
+    int limit,
+    const char* cursor,
+    char* json_out,
+    int json_len
+);
+
+/**
+ * Creates a new token whose royalty is shared between the given creators
+ *
+ * At most 5 creators are allowed and their shares must add up to 100. Only the
+ * payer may be marked verified; other creators verify with sss_verify_creator.
+ *
+ * @param uri_ptr The token URI
+ * @param name_ptr The token name
+ * @param decimals The number of decimal places for the token
+ * @param seller_fee_bps The royalty charged on secondary sales, in basis points
+ * @param creators_json A JSON array of {"address", "share_percent", "verified"}
+ *        objects; an empty array makes the payer the sole creator
+ * @param signature_out A pointer to a buffer where the transaction signature will be written
+ * @param signature_len The length of the signature_out buffer
+ * @param mint_address_out A pointer to a buffer where the mint address will be written
+ * @param mint_address_len The length of the mint_address_out buffer
+ * @return 0 on success, -2/-3 for an invalid URI or name, -4 for invalid creators
+ *         JSON, -5/-6 if a buffer is too small, -7 if the token could not be created
+ */
+int sss_create_token_with_creators(
+    const char* uri_ptr,
+    const char* name_ptr,
+    unsigned char decimals,
+    uint16_t seller_fee_bps,
+    const char* creators_json,
+    char* signature_out,
+    int signature_len,
+    char* mint_address_out,
+    int mint_address_len
+);
+
+/**
+ * Marks the payer as a verified creator of a token
+ *
+ * @param mint_str The mint whose metadata lists the payer as a creator
+ * @param signature_out A pointer to a buffer where the transaction signature will be written
+ * @param signature_len The length of the signature_out buffer
+ * @return 0 on success, -2 for an invalid mint, -3 if the buffer is too small,
+ *         -4 if the creator could not be verified, e.g. because the payer is not one
+ */
+int sss_verify_creator(
+    const char* mint_str,
+    char* signature_out,
+    int signature_len
+);
+
+/**
+ * Prepares the connection for a fast first operation after an idle period
+ *
+ * Resolves the RPC host, opens the connection, fetches a blockhash into the cache
+ * and loads the payer key, writing the milliseconds each step took as JSON.
+ *
+ * @param json_out A pointer to a buffer where the WarmupReport JSON will be written
+ * @param json_len The length of the json_out buffer
+ * @return 0 on success, -2 if the endpoint or the payer could not be warmed up,
+ *         -3 if the buffer is too small
+ */
+int sss_warm_up(char* json_out, int json_len);
+
+/**
+ * Starts or stops pinging the RPC endpoint to keep the connection warm
+ *
+ * While the endpoint is unreachable, the pings back off up to every 5 minutes.
+ * sss_shutdown stops the keep-alive as well.
+ *
+ * @param interval_secs Seconds between pings, or 0 to stop the keep-alive
+ * @return 0 on success, -2 for a negative interval
+ */
+int sss_set_keep_alive(int interval_secs);
+
+/**
+ * Creates the Metaplex metadata of a mint created by another tool, e.g. the plain
+ * spl-token CLI
+ *
+ * @param mint_str The existing mint, whose mint authority must be the payer
+ * @param uri_ptr The URI pointing to the token's metadata
+ * @param name_ptr The name of the token
+ * @param symbol_ptr The ticker symbol of the token
+ * @param signature_out A pointer to a buffer where the transaction signature will be written
+ * @param signature_len The length of the signature_out buffer
+ * @return 0 on success, -2 for an invalid mint, -3/-4/-5 for an invalid URI, name
+ *         or symbol, -6 if the buffer is too small, -7 if the metadata could not be
+ *         created, e.g. because the mint already has metadata
+ */
+int sss_attach_metadata(
+    const char* mint_str,
+    const char* uri_ptr,
+    const char* name_ptr,
+    const char* symbol_ptr,
+    char* signature_out,
+    int signature_len
+);
+
+/**
+ * Mints tokens at most once per idempotency key, even across process restarts
+ *
+ * With SSS_DEDUP_PATH set, repeating the key of a confirmed mint returns the
+ * signature of that mint instead of minting again.
+ *
+ * @param mint_str The mint to mint from
+ * @param token_owner_str The recipient wallet, or NULL for the payer
+ * @param amount The amount to mint, in base units
+ * @param idempotency_key The caller's identifier of the mint, e.g. a job id
+ * @param signature_out A pointer to a buffer where the transaction signature will be written
+ * @param signature_len The length of the signature_out buffer
+ * @return 0 on success, -2/-3 for an invalid mint or owner, -4 for an invalid key,
+ *         -5 if the buffer is too small, -6 if minting failed or an earlier mint with
+ *         the key is still unconfirmed
+ */
+int sss_mint_token_idempotent(
+    const char* mint_str,
+    const char* token_owner_str,
+    uint64_t amount,
+    const char* idempotency_key,
+    char* signature_out,
+    int signature_len
+);
+
+/**
+ * Function pointer type for the completion of an asynchronous call
+ *
+ * Called once per queued call, on a library pool thread and never on the calling
+ * thread, with the request_id given with the call. code is 0 on success, -5 if the
+ * operation failed and -6 if it panicked. result_json is the response of sss_call,
+ * {"v": 2, "data": {"result": ...}} or {"v": 2, "data": {"error": {...}}}, and is only
+ * valid during the call.
+ */
+typedef void (*SssCompletionCallback)(
+    uint64_t request_id,
+    int code,
+    const char* result_json,
+    void* user_data
+);
+
+/**
+ * Mints tokens without blocking the calling thread
+ *
+ * The mint is queued on the library's thread pool (SSS_ASYNC_THREADS threads, default 4)
+ * and this returns at once. Queued calls run in no particular order, and callbacks of
+ * different calls may run at the same time on different threads. sss_shutdown waits for
+ * every queued call and its callback.
+ *
+ * @param mint_str The mint to mint from
+ * @param token_owner_str The recipient wallet, or NULL for the payer
+ * @param amount The amount to mint, in base units
+ * @param request_id Any value identifying the call, passed back to the callback
+ * @param callback Called once with the outcome, unless queuing failed
+ * @param user_data Passed to the callback unchanged; must stay valid until the callback ran
+ * @return 0 if the call was queued, -2/-3 for an invalid mint or owner, -4 if the queue
+ *         is full (SSS_ASYNC_QUEUE_LIMIT, default 1024) or the library is shutting down
+ */
+int sss_mint_token_async(
+    const char* mint_str,
+    const char* token_owner_str,
+    uint64_t amount,
+    uint64_t request_id,
+    SssCompletionCallback callback,
+    void* user_data
+);
+
+/**
+ * Returns the number of asynchronous calls queued or running
+ *
+ * Hosts can hold back new calls while this is high instead of filling the queue.
+ *
+ * @return The number of calls whose callback has not finished yet
+ */
+int sss_pending_count(void);
+
+/**
+ * Allows or forbids .sol domains as recipients
+ *
+ * When enabled, the owner of mint_token_ffi, the recipient of sss_transfer_asset and the
+ * same parameters of sss_call may be .sol domains, resolved to their owner.
+ *
+ * @param enabled Non-zero to resolve domains, 0 to accept public keys only
+ * @return 0 on success
+ */
+int sss_set_ffi_domain_resolution(int enabled);
+
+/**
+ * Resolves a public key or .sol domain to the wallet it names
+ * @param input_str A base58 public key or a domain such as alice.sol
+ * @param pubkey_out Buffer receiving the wallet address
+ * @param pubkey_len Size of pubkey_out
+ * @return 0 on success, -2 for an invalid input, -3 if pubkey_out is too small, -4 if the
+ *         domain is not registered or cannot be resolved
+ */
+int sss_resolve_recipient(const char* input_str, char* pubkey_out, int pubkey_len);
+
+/**
+ * Writes the primary .sol domain of a wallet, for display
+ * @param wallet_str The wallet whose domain is looked up
+ * @param domain_out Buffer receiving the domain, e.g. alice.sol, or an empty string if the
+ *        wallet has none
+ * @param domain_len Size of domain_out
+ * @return 0 on success, -2 for an invalid wallet, -3 if domain_out is too small, -4 if the
+ *         name service accounts cannot be read
+ */
+int sss_primary_domain_for(const char* wallet_str, char* domain_out, int domain_len);
+
+/**
+ * Enters watch-only mode, for auditors
+ *
+ * The payer becomes the given wallet, used for derivations, balances and fee reports, and
+ * no keypair is loaded from then on. Every function that would sign fails with
+ * SSS_ERR_WATCH_ONLY. The mode lasts until the process exits; calling this again only
+ * replaces the watched wallet.
+ *
+ * @param pubkey_str The wallet whose data is viewed
+ * @return 0 on success, -2 for an invalid public key, -3 if the mode cannot be recorded
+ */
+int sss_init_watch_only(const char* pubkey_str);
+
+/**
+ * Gets alerted when a mint's supply strays from an emission schedule
+ *
+ * The supply is polled on a background thread and compared with the schedule at the
+ * cluster's time. Each anomaly is emitted as a supply_anomaly event and, if a callback
+ * is given, passed to it as the JSON of a SupplyAnomaly: the expected and actual supply,
+ * their delta, and the transactions since the previous poll that changed the supply.
+ *
+ * @param mint_str The mint to monitor
+ * @param schedule_json {"interpolation": "step" | "linear", "tolerance": <base units>,
+ *        "points": [{"time": <unix seconds>, "supply": <base units>}, ...]}
+ * @param poll_interval_ms Time between polls while the RPC node answers
+ * @param callback Called with the JSON of each anomaly, or NULL for events only
+ * @param user_data Passed to the callback unchanged; must stay valid, and usable from
+ *        another thread, until sss_stop_supply_monitor or sss_shutdown returns
+ * @return A positive monitor handle on success, -2 for an invalid mint, -3 for an invalid
+ *         schedule, -4 if the schedule is empty, the interval zero or the mint unreadable
+ */
+int64_t sss_monitor_supply(
+    const char* mint_str,
+    const char* schedule_json,
+    uint64_t poll_interval_ms,
+    SssEventCallback callback,
+    void* user_data
+);
+
+/**
+ * Stops a supply monitor
+ *
+ * A callback already running may still complete after this returns.
+ *
+ * @param handle The handle returned by sss_monitor_supply
+ * @return 0 on success, -1 if the handle is unknown
+ */
+int sss_stop_supply_monitor(int64_t handle);
+
+/**
+ * Mints tokens for an existing token, with the amount in whole tokens
+ *
+ * Like mint_token_ffi, but the amount is a decimal string such as "1.5",
+ * converted exactly with the mint's decimals.
+ *
+ * @param mint_str The base58 address of the mint
+ * @param token_owner_str The base58 address of the owner, or NULL for the payer
+ * @param amount_str The amount in whole tokens, at most the mint's decimals after the point
+ * @param signature_out A buffer to receive the transaction signature
+ * @param signature_len The size of the signature buffer
+ * @return 0 on success, -3 if the amount is malformed, -5 if the amount has too many
+ *         decimal places for the mint or the mint fails
+ */
+int sss_mint_token_ui(
+    const char* mint_str,
+    const char* token_owner_str,
+    const char* amount_str,
+    char* signature_out,
+    int signature_len
+);
+
+/**
+ * Mints tokens into an existing token account, with the amount in whole tokens
+ *
+ * @param mint_str The base58 address of the mint
+ * @param token_account_str The base58 address of the receiving token account
+ * @param amount_str The amount in whole tokens, at most the mint's decimals after the point
+ * @param signature_out A buffer to receive the transaction signature
+ * @param signature_len The size of the signature buffer
+ * @return 0 on success, -3 if the amount is malformed, -5 if the amount has too many
+ *         decimal places for the mint or the mint fails
+ */
+int sss_mint_token_to_account_ui(
+    const char* mint_str,
+    const char* token_account_str,
+    const char* amount_str,
+    char* signature_out,
+    int signature_len
+);
+
+/**
+ * Transfers an asset held by the payer to another wallet, with the amount in whole tokens
+ *
+ * @param mint_str The base58 address of the mint
+ * @param to_str The base58 address of the receiving wallet
+ * @param amount_str The amount in whole tokens, or NULL for the whole balance; NFTs
+ *        always transfer 1
+ * @param signature_out A buffer to receive the transaction signature
+ * @param signature_len The size of the signature buffer
+ * @return 0 on success, -3 if the amount is malformed, -5 if the amount has too many
+ *         decimal places for the mint or the transfer fails
+ */
+int sss_transfer_asset_ui(
+    const char* mint_str,
+    const char* to_str,
+    const char* amount_str,
+    char* signature_out,
+    int signature_len
+);
+
+/**
+ * Writes the statistics of every in-memory cache as JSON
+ *
+ * The JSON is an array of objects with name, entries, max_entries, ttl_secs,
+ * approx_bytes, hits, misses and evictions, one per cache.
+ *
+ * @param json_out A buffer to receive the JSON
+ * @param json_len The size of the JSON buffer
+ * @return 0 on success, non-zero error code on failure
+ */
+int sss_cache_stats(char* json_out, int json_len);
+
+/**
+ * Shrinks the in-memory caches to about target_bytes in total
+ *
+ * Call when the host detects memory pressure. Expired entries are dropped
+ * first, then each cache is shrunk by the same fraction, least recently used
+ * entries first. A target of 0 empties every cache.
+ *
+ * @param target_bytes The estimated memory the caches may keep
+ * @param report_out A buffer to receive the JSON report with bytes_before,
+ *        bytes_after and evicted, or NULL
+ * @param report_len The size of the report buffer
+ * @return 0 on success, non-zero error code on failure
+ */
+int sss_trim_caches(uint64_t target_bytes, char* report_out, int report_len);
+
+/**
+ * Mints a test token to a wallet within daily faucet caps
+ *
+ * Only available when the library is built with the `dev-tools` feature;
+ * release builds without it do not export this symbol. Mainnet-beta is
+ * refused, and SSS_JOURNAL_PATH must be set since grants are accounted in
+ * the journal. Caps reset at midnight UTC.
+ *
+ * The outcome JSON has status "granted", with signature, recipient, amount,
+ * recipient_total and global_total, or "throttled", with limit ("recipient"
+ * or "global"), granted_today, cap and resets_at in Unix seconds.
+ *
+ * @param mint_str The base58 address of the test token's mint
+ * @param recipient_str The base58 address of the receiving wallet
+ * @param amount The amount to mint in base units
+ * @param per_recipient_daily The amount one wallet may receive per day
+ * @param global_daily The amount all wallets together may receive per day
+ * @param outcome_out A buffer to receive the outcome JSON
+ * @param outcome_len The size of the outcome buffer
+ * @return 0 when granted or throttled, negative error code on failure
+ */
+int sss_faucet_grant(
+    const char* mint_str,
+    const char* recipient_str,
+    uint64_t amount,
+    uint64_t per_recipient_daily,
+    uint64_t global_daily,
+    char* outcome_out,
+    int outcome_len
+);
+
+/**
+ * Renders a printable receipt of a confirmed transaction
+ *
+ * The header, footer, line width and explorer link are read from
+ * SSS_RECEIPT_HEADER, SSS_RECEIPT_FOOTER, SSS_RECEIPT_WIDTH and
+ * SSS_EXPLORER_URL.
+ *
+ * @param signature_str The base58 transaction signature
+ * @param format 0 for plain text for a receipt printer, 1 for an HTML fragment
+ * @param payload_out A pointer to a buffer where the payload will be written
+ * @param payload_len The length of the payload_out buffer
+ * @return 0 on success, -1 if a parameter is null, -2 if the signature is
+ *         invalid, -3 if the format is unknown, -4 if the buffer is too
+ *         small, -5 if the receipt could not be fetched or rendered
+ */
+int sss_render_receipt(
+    const char* signature_str,
+    int format,
+    char* payload_out,
+    int payload_len
+);
+
+/**
+ * Writes what this build of the library supports as JSON
+ *
+ * The JSON object holds "version", "ffi_schema" and one true/false flag per
+ * capability, such as "burn", "batch", "token2022", "das" or "keystore".
+ * Flag names are never renamed or removed; a flag a host does not find is
+ * not supported by the loaded library. "role" holds the permissions of the
+ * calling thread's signer role: its name, whether a policy restricts it, and
+ * per operation whether it is "allowed" and its "max_amount" per transaction.
+ *
+ * @param json_out A buffer to receive the JSON
+ * @param json_len The size of the JSON buffer
+ * @return 0 on success, -1 if json_out is null, -2 if the JSON could not be
+ *         written, -3 if the buffer is too small
+ */
+int sss_capabilities(char* json_out, int json_len);
+
+/**
+ * Resolves the transactions an earlier run sent but never saw confirmed
+ *
+ * Call at startup with SSS_JOURNAL_PATH set. Writes a JSON array with one
+ * object per unresolved journal entry: "entry", the journal entry with its
+ * new "status" ("confirmed", "failed", "expired", "unknown" for a manual
+ * review, or still "sent"), and "receipt", the decoded transaction if it
+ * landed. Every resolved transaction also emits an in_flight_recovered event.
+ *
+ * @param json_out A buffer to receive the JSON
+ * @param json_len The size of the JSON buffer
+ * @return 0 on success, -1 if json_out is null, -2 if the journal or the
+ *         chain could not be read, -3 if the buffer is too small
+ */
+int sss_recover_in_flight(char* json_out, int json_len);
+
+/**
+ * Shortens an address to its first and last characters around an ellipsis
+ *
+ * Apps show addresses as e.g. "DZ2D…NGRM" (head 4, tail 4). The ellipsis is
+ * the UTF-8 character U+2026. The address is written whole when shortening
+ * would not make it shorter.
+ *
+ * @param pubkey_str The base58 address to shorten
+ * @param head The number of leading characters to keep
+ * @param tail The number of trailing characters to keep
+ * @param short_out A buffer to receive the shortened address
+ * @param short_len The size of the buffer
+ * @return 0 on success, -1 if a pointer is null, -2 if the address is
+ *         invalid, -3 if head or tail is negative, -4 if the buffer is too small
+ */
+int sss_short_pubkey(const char* pubkey_str, int head, int tail, char* short_out, int short_len);
+
+/**
+ * Derives the RGB avatar color of an address
+ *
+ * The first three bytes of the SHA-256 hash of the address bytes, the same
+ * in every app.
+ *
+ * @param pubkey_str The base58 address
+ * @param rgb_out Receives the red, green and blue components (3 bytes)
+ * @return 0 on success, -1 if a pointer is null, -2 if the address is invalid
+ */
+int sss_pubkey_color_hash(const char* pubkey_str, uint8_t* rgb_out);
+
+/**
+ * Builds the Solana Pay URI to show as a QR code for receiving at an address
+ *
+ * Writes e.g. "solana:<address>?amount=1.5&spl-token=<mint>".
+ *
+ * @param pubkey_str The base58 address of the receiving wallet
+ * @param amount_str The amount in whole tokens, e.g. "1.5", or NULL to let the
+ *                   payer choose
+ * @param mint_str The base58 address of the SPL token to receive, or NULL for SOL
+ * @param uri_out A buffer to receive the URI
+ * @param uri_len The size of the URI buffer
+ * @return 0 on success, -1 if a pointer is null, -2 if the address is invalid,
+ *         -3 if the amount is not a non-negative decimal number, -4 if the mint
+ *         is invalid, -5 if the buffer is too small
+ */
+int sss_qr_payload_for_address(const char* pubkey_str, const char* amount_str, const char* mint_str,
+                               char* uri_out, int uri_len);
+
+/**
+ * Reads a Solana Pay transfer request, e.g. decoded from a QR code
+ *
+ * Writes a JSON object with "recipient", "amount" (whole tokens, or null),
+ * "spl_token" (or null for SOL), the "references" array, "label", "message"
+ * and "memo". URIs written by wallets such as Phantom are accepted;
+ * transaction request links are not.
+ *
+ * @param uri_str The "solana:" URI
+ * @param json_out A buffer to receive the JSON
+ * @param json_len The size of the JSON buffer
+ * @return 0 on success, -1 if a pointer is null, -2 if the URI is not a valid
+ *         transfer request, -3 if the buffer is too small, -4 if the JSON
+ *         could not be written
+ */
+int sss_parse_solana_uri(const char* uri_str, char* json_out, int json_len);
+
+/**
+ * Sets the capacity and overflow policy of a callback queue
+ *
+ * Queue "events" feeds the event callback, "balance_callbacks" the balance
+ * subscription callbacks; each is drained by its own thread. A full queue
+ * applies the policy: "drop_oldest", "drop_newest" (the default) or "block",
+ * which makes the operation wait up to block_timeout_ms for room before
+ * dropping. Dropped items are announced with an events_dropped event.
+ * Defaults come from SSS_EVENT_QUEUE_SIZE (1024), SSS_EVENT_OVERFLOW and
+ * SSS_EVENT_BLOCK_MS (100).
+ *
+ * @param queue "events" or "balance_callbacks"
+ * @param capacity The most items queued at once, at least 1
+ * @param overflow "drop_oldest", "drop_newest" or "block"
+ * @param block_timeout_ms How long "block" waits for room, ignored otherwise
+ * @return 0 on success, -1 if a pointer is null, -2 if the queue is unknown,
+ *         -3 if the capacity is below 1, -4 if the policy is unknown
+ */
+int sss_set_callback_queue_options(const char* queue, int capacity, const char* overflow,
+                                   uint64_t block_timeout_ms);
+
+/**
+ * Signs structured data, such as a coupon, with a signer role
+ *
+ * The payload is brought into a canonical encoding (sorted keys, NFC-normalized
+ * strings, explicit type tags; fractional numbers are rejected) and signed
+ * together with the hash of the domain, so any formatting of the same JSON
+ * verifies. Writes {"signer", "role", "signature", "digest"} as JSON.
+ *
+ * @param domain_json The signing domain, {"name": ..., "version": ...} with
+ *                    optional "cluster" and "salt"
+ * @param payload_json The data to sign as JSON
+ * @param role The profile whose payer signs, or NULL for the active one
+ * @param signature_out A buffer to receive the signature JSON
+ * @param signature_len The size of the buffer
+ * @return 0 on success, -1 if a pointer is null, -2 if the domain is invalid,
+ *         -4 if the role is not valid UTF-8, -5 if the buffer is too small,
+ *         -6 if the payload cannot be encoded, the role is not registered or
+ *         the payer cannot sign
+ */
+int sss_sign_structured(const char* domain_json, const char* payload_json, const char* role,
+                        char* signature_out, int signature_len);
+
+/**
+ * Verifies a structured signature made by sss_sign_structured
+ *
+ * @param domain_json The signing domain the signature must be for
+ * @param payload_json The signed data as JSON, in any formatting
+ * @param signature_b58 The base58 signature to check
+ * @param signer_str The base58 key that must have signed
+ * @return 1 if the signature is valid, 0 if it is not, -1 if a pointer is
+ *         null, -2 if the domain is invalid, -3 if the payload cannot be
+ *         encoded, -4 if the signature or -5 if the signer is malformed
+ */
+int sss_verify_structured(const char* domain_json, const char* payload_json,
+                          const char* signature_b58, const char* signer_str);
+
+/**
+ * Gives plain spl-token mints Metaplex metadata, resuming from a checkpoint file
+ *
+ * Each mint gets its metadata, then has its freeze authority and its mint and
+ * update authorities moved if requested, and is checked with the consistency
+ * audit. Mints whose mint authority is not the payer are reported and left
+ * untouched. Skipped or failed mints do not make the call fail. The report
+ * JSON has a "mints" array of {"mint", "status", "metadata_signature",
+ * "rotations", "audit"} objects, "status" being "migrated", "inconsistent",
+ * "already_migrated", "not_mint_authority" (with "current"), "has_metadata"
+ * or "failed" (with "error").
+ *
+ * @param plan_json A JSON array of {"mint", "name", "symbol", "uri",
+ *                  "freeze_authority", "new_authority"} objects, the two
+ *                  authorities being optional
+ * @param checkpoint_path The JSON file recording the progress per mint
+ * @param report_out A buffer to receive the report JSON
+ * @param report_len The size of the report buffer
+ * @return 0 on success, -1 if a pointer is null, -2 if the plan is invalid,
+ *         -3 if the checkpoint path is not valid UTF-8, -4 if the buffer is
+ *         too small, -5 if the migration could not run
+ */
+int sss_migrate_legacy_mints(const char* plan_json, const char* checkpoint_path,
+                             char* report_out, int report_len);
+
 #ifdef __cplusplus
 }
 #endif
@@ -0,0 +1,786 @@
+//! What happened to a wallet's tokens, for customer support lookups
+//!
+//! The history of the wallet's token accounts is fetched with `getSignaturesForAddress` and
+//! every transaction is decoded like a [`Receipt`]. Each token instruction moving the
+//! wallet's tokens becomes an [`ActivityItem`]; balance changes no top-level instruction
+//! explains, e.g. transfers made by another program, become items without a counterparty.
+
+use crate::RPC_CLIENT;
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::inspect::{DecodedInstruction, InspectedInstruction};
+use crate::mint_cache::mint_info;
+use crate::receipt::{Receipt, TokenBalanceChange, fetch_transaction_receipt_with_client};
+use crate::serde_utils::pubkey_string;
+use crate::trusted_mints::trusted_mints;
+use serde::Serialize;
+use serde_json::json;
+use solana_rpc_client::rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient};
+use solana_rpc_client_api::request::RpcRequest;
+use solana_rpc_client_api::response::{Response, RpcKeyedAccount};
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
+use std::thread;
+
+/// Signatures requested per token account and round
+const SIGNATURE_PAGE_SIZE: usize = 1000;
+
+/// Transactions fetched concurrently
+const TRANSACTION_FETCH_BATCH: usize = 16;
+
+/// Position of the token owner in the accounts of a token metadata mint instruction
+const METADATA_MINT_OWNER_INDEX: usize = 1;
+/// Position of the mint in the accounts of a token metadata mint instruction
+const METADATA_MINT_MINT_INDEX: usize = 5;
+/// Position of the authority in the accounts of a token metadata mint instruction
+const METADATA_MINT_AUTHORITY_INDEX: usize = 6;
+/// Position of the source owner in the accounts of a token metadata transfer
+const METADATA_TRANSFER_OWNER_INDEX: usize = 1;
+/// Position of the destination owner in the accounts of a token metadata transfer
+const METADATA_TRANSFER_DESTINATION_OWNER_INDEX: usize = 3;
+/// Position of the mint in the accounts of a token metadata transfer
+const METADATA_TRANSFER_MINT_INDEX: usize = 4;
+
+/// What happened to the wallet's tokens
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActivityKind {
+    /// Tokens arrived from another wallet
+    Received,
+    /// Tokens left for another wallet
+    Sent,
+    /// Tokens moved between two accounts of the wallet
+    SelfTransfer,
+    /// Tokens were minted to the wallet
+    Minted,
+    /// Tokens of the wallet were burned
+    Burned,
+}
+
+/// A movement of a wallet's tokens
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ActivityItem {
+    /// What happened
+    pub kind: ActivityKind,
+    /// The mint of the tokens
+    #[serde(with = "pubkey_string")]
+    pub mint: Pubkey,
+    /// The amount in base units
+    pub amount: u64,
+    /// The number of decimal places of the mint
+    pub decimals: u8,
+    /// The other wallet, or the mint authority for `Minted`; `None` if unknown or for
+    /// `SelfTransfer` and `Burned`
+    pub counterparty: Option<String>,
+    /// Estimated production time of the block as a Unix timestamp, if the node knows it
+    pub block_time: Option<i64>,
+    /// The slot the transaction was processed in
+    pub slot: u64,
+    /// The transaction signature
+    pub signature: String,
+}
+
+/// A page of wallet activity, see [`wallet_activity_page`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ActivityPage {
+    /// The items, newest first
+    pub items: Vec<ActivityItem>,
+    /// Pass to [`wallet_activity_page`] for the next page; `None` once the history is
+    /// exhausted
+    pub next_cursor: Option<String>,
+}
+
+/// Lists the most recent movements of a wallet's tokens
+///
+/// # Arguments
+///
+/// * `owner` - The wallet
+/// * `mints` - The mints to include; `None` for the trusted mints, or every token account
+///   of the wallet if no mint is trusted
+/// * `limit` - The number of items wanted
+///
+/// # Returns
+///
+/// The newest items, see [`wallet_activity_page`] for paging further
+///
+/// # Errors
+///
+/// Returns a `TokenError` if a mint does not exist and an `RpcError` if the history cannot
+/// be read
+pub fn wallet_activity(
+    owner: Pubkey,
+    mints: Option<&[Pubkey]>,
+    limit: usize,
+) -> SssResult<Vec<ActivityItem>> {
+    wallet_activity_page(owner, mints, limit, None).map(|page| page.items)
+}
+
+/// Lists a page of the movements of a wallet's tokens
+///
+/// A page ends with a whole transaction and a whole slot, so it may hold a few more than
+/// `limit` items.
+///
+/// # Arguments
+///
+/// * `owner` - The wallet
+/// * `mints` - The mints to include; `None` for the trusted mints, or every token account
+///   of the wallet if no mint is trusted
+/// * `limit` - The number of items wanted
+/// * `cursor` - The `next_cursor` of the previous page, `None` for the newest items
+///
+/// # Returns
+///
+/// The items, newest first, and the cursor of the next page
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the cursor is malformed or a mint does not exist and an
+/// `RpcError` if the history cannot be read
+pub fn wallet_activity_page(
+    owner: Pubkey,
+    mints: Option<&[Pubkey]>,
+    limit: usize,
+    cursor: Option<&str>,
+) -> SssResult<ActivityPage> {
+    wallet_activity_page_with_client(&RPC_CLIENT, owner, mints, limit, cursor)
+}
+
+/// Lists a page of wallet activity like [`wallet_activity_page`], querying the given RPC
+/// client
+///
+/// # Arguments
+///
+/// * `client` - The RPC client to query, e.g. an archival node for old transactions
+/// * `owner` - The wallet
+/// * `mints` - The mints to include; `None` for the trusted mints, or every token account
+///   of the wallet if no mint is trusted
+/// * `limit` - The number of items wanted
+/// * `cursor` - The `next_cursor` of the previous page, `None` for the newest items
+///
+/// # Returns
+///
+/// The items, newest first, and the cursor of the next page
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the cursor is malformed or a mint does not exist and an
+/// `RpcError` if the history cannot be read
+pub fn wallet_activity_page_with_client(
+    client: &RpcClient,
+    owner: Pubkey,
+    mints: Option<&[Pubkey]>,
+    limit: usize,
+    cursor: Option<&str>,
+) -> SssResult<ActivityPage> {
+    let mut before = cursor.map(parse_cursor).transpose()?;
+    let trusted;
+    let mints = match mints {
+        Some(mints) => Some(mints),
+        None => {
+            trusted = trusted_mints();
+            (!trusted.is_empty()).then_some(trusted.as_slice())
+        }
+    };
+    let accounts = match mints {
+        Some(mints) => mints
+            .iter()
+            .map(|mint| {
+                let token_program = mint_info(mint)?.token_program.id();
+                Ok(get_associated_token_address_with_program_id(
+                    &owner,
+                    mint,
+                    &token_program,
+                ))
+            })
+            .collect::<SssResult<Vec<_>>>()?,
+        None => owner_token_accounts(client, &owner)?,
+    };
+    let mints: Option<HashSet<Pubkey>> = mints.map(|mints| mints.iter().copied().collect());
+
+    let mut items = Vec::new();
+    loop {
+        let (candidates, more) = signature_round(client, &accounts, before.as_ref())?;
+        if candidates.is_empty() {
+            return Ok(ActivityPage {
+                items,
+                next_cursor: None,
+            });
+        }
+        for batch in candidates.chunks(TRANSACTION_FETCH_BATCH) {
+            let receipts = fetch_receipts(client, batch)?;
+            for ((slot, signature), receipt) in batch.iter().zip(receipts) {
+                // Only stop between slots, as a cursor cannot tell transactions of a slot apart
+                if items.len() >= limit && before.as_ref().is_some_and(|(last, _)| last != slot) {
+                    return Ok(ActivityPage {
+                        items,
+                        next_cursor: before.as_ref().map(format_cursor),
+                    });
+                }
+                items.extend(activity_items(&receipt, &owner, mints.as_ref()));
+                before = Some((*slot, parse_signature(signature)?));
+            }
+        }
+        if !more || items.len() >= limit {
+            return Ok(ActivityPage {
+                next_cursor: more.then(|| before.as_ref().map(format_cursor)).flatten(),
+                items,
+            });
+        }
+    }
+}
+
+/// Fetches the next signatures of the accounts, newest first
+///
+/// Only signatures of slots every account was fetched up to are returned, so no
+/// transaction is skipped unless an account has more than a page of them in a single slot.
+/// Slots from `before` onwards were handled completely and are left out, as are failed
+/// transactions.
+///
+/// # Returns
+///
+/// The slots and signatures, and whether any account may have older signatures
+fn signature_round(
+    client: &RpcClient,
+    accounts: &[Pubkey],
+    before: Option<&(u64, Signature)>,
+) -> SssResult<(Vec<(u64, String)>, bool)> {
+    let mut candidates: Vec<(u64, String)> = Vec::new();
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut horizon: Option<u64> = None;
+    for account in accounts {
+        let page = client
+            .get_signatures_for_address_with_config(
+                account,
+                GetConfirmedSignaturesForAddress2Config {
+                    before: before.map(|(_, signature)| *signature),
+                    until: None,
+                    limit: Some(SIGNATURE_PAGE_SIZE),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                },
+            )
+            .into_sss_error("Failed to get account signatures from rpc")?;
+        if page.len() == SIGNATURE_PAGE_SIZE
+            && let Some(last) = page.last()
+        {
+            horizon = Some(horizon.map_or(last.slot, |h| h.max(last.slot)));
+        }
+        for status in page {
+            if status.err.is_some() || before.is_some_and(|(slot, _)| status.slot >= *slot) {
+                continue;
+            }
+            if seen.insert(status.signature.clone()) {
+                candidates.push((status.slot, status.signature));
+            }
+        }
+    }
+
+    // Stable, so transactions of a slot keep the node's newest-first order
+    candidates.sort_by_key(|(slot, _)| Reverse(*slot));
+    let Some(horizon) = horizon else {
+        return Ok((candidates, false));
+    };
+    let mut complete: Vec<(u64, String)> = candidates
+        .into_iter()
+        .filter(|(slot, _)| *slot >= horizon)
+        .collect();
+    // Leave the horizon slot for the next round, unless it is all there is
+    if complete.iter().any(|(slot, _)| *slot > horizon) {
+        complete.retain(|(slot, _)| *slot > horizon);
+    }
+    Ok((complete, true))
+}
+
+/// Fetches a batch of transactions concurrently
+fn fetch_receipts(client: &RpcClient, batch: &[(u64, String)]) -> SssResult<Vec<Receipt>> {
+    thread::scope(|scope| {
+        let handles: Vec<_> = batch
+            .iter()
+            .map(|(_, signature)| {
+                scope.spawn(move || fetch_transaction_receipt_with_client(client, signature))
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle.join().map_err(|_| {
+                    SssError::RpcError("Transaction fetch thread panicked".to_string())
+                })?
+            })
+            .collect()
+    })
+}
+
+/// Lists every token account of a wallet under both token programs
+fn owner_token_accounts(client: &RpcClient, owner: &Pubkey) -> SssResult<Vec<Pubkey>> {
+    let mut accounts = Vec::new();
+    for program in [spl_token::id(), spl_token_2022::id()] {
+        let response: Response<Vec<RpcKeyedAccount>> = client
+            .send(
+                RpcRequest::GetTokenAccountsByOwner,
+                json!([
+                    owner.to_string(),
+                    { "programId": program.to_string() },
+                    { "encoding": "base64", "dataSlice": { "offset": 0, "length": 0 } },
+                ]),
+            )
+            .into_sss_error("Failed to get owner token accounts from rpc")?;
+        for keyed in response.value {
+            accounts.push(
+                Pubkey::from_str(&keyed.pubkey)
+                    .into_sss_error("Failed to parse token account address from rpc")?,
+            );
+        }
+    }
+    Ok(accounts)
+}
+
+/// Turns a transaction into the movements of the wallet's tokens, in instruction order
+///
+/// # Arguments
+///
+/// * `receipt` - The decoded transaction
+/// * `owner` - The wallet
+/// * `mints` - The mints to include, `None` for all
+fn activity_items(
+    receipt: &Receipt,
+    owner: &Pubkey,
+    mints: Option<&HashSet<Pubkey>>,
+) -> Vec<ActivityItem> {
+    if !receipt.success {
+        return Vec::new();
+    }
+    let owner_str = owner.to_string();
+    let balances: HashMap<Pubkey, &TokenBalanceChange> = receipt
+        .token_balances
+        .iter()
+        .map(|balance| (balance.account, balance))
+        .collect();
+    let owner_of = |account: Option<Pubkey>| {
+        account
+            .and_then(|account| balances.get(&account))
+            .and_then(|balance| balance.owner.clone())
+    };
+    let decimals_of = |mint: &Pubkey| {
+        receipt
+            .token_balances
+            .iter()
+            .find(|balance| balance.mint == *mint)
+            .map(|balance| balance.decimals)
+            .unwrap_or(0)
+    };
+
+    // Movements as (kind, mint, amount, counterparty)
+    let mut movements: Vec<(ActivityKind, Pubkey, u64, Option<String>)> = Vec::new();
+    for ix in &receipt.instructions {
+        match ix.decoded {
+            DecodedInstruction::TokenTransfer { amount } => {
+                // `TransferChecked` passes the mint between source and destination
+                let source = account_at(ix, 0);
+                let destination = match account_at(ix, 1) {
+                    Some(account) if balances.contains_key(&account) => Some(account),
+                    _ => account_at(ix, 2),
+                };
+                let Some(mint) = [source, destination]
+                    .into_iter()
+                    .flatten()
+                    .find_map(|account| balances.get(&account).map(|b| b.mint))
+                else {
+                    continue;
+                };
+                movements.extend(transfer_movement(
+                    &owner_str,
+                    mint,
+                    amount,
+                    owner_of(source),
+                    owner_of(destination),
+                ));
+            }
+            DecodedInstruction::MetadataTransfer { amount } => {
+                let Some(mint) = account_at(ix, METADATA_TRANSFER_MINT_INDEX) else {
+                    continue;
+                };
+                movements.extend(transfer_movement(
+                    &owner_str,
+                    mint,
+                    amount,
+                    account_at(ix, METADATA_TRANSFER_OWNER_INDEX).map(|a| a.to_string()),
+                    account_at(ix, METADATA_TRANSFER_DESTINATION_OWNER_INDEX)
+                        .map(|a| a.to_string()),
+                ));
+            }
+            DecodedInstruction::TokenMintTo { amount } => {
+                if let (Some(mint), Some(destination)) = (account_at(ix, 0), account_at(ix, 1))
+                    && owner_of(Some(destination)).as_deref() == Some(owner_str.as_str())
+                {
+                    let authority = account_at(ix, 2).map(|a| a.to_string());
+                    movements.push((ActivityKind::Minted, mint, amount, authority));
+                }
+            }
+            DecodedInstruction::MetadataMint { amount } => {
+                if account_at(ix, METADATA_MINT_OWNER_INDEX) == Some(*owner)
+                    && let Some(mint) = account_at(ix, METADATA_MINT_MINT_INDEX)
+                {
+                    let authority =
+                        account_at(ix, METADATA_MINT_AUTHORITY_INDEX).map(|a| a.to_string());
+                    movements.push((ActivityKind::Minted, mint, amount, authority));
+                }
+            }
+            DecodedInstruction::TokenBurn { amount } => {
+                if let (Some(account), Some(mint)) = (account_at(ix, 0), account_at(ix, 1))
+                    && owner_of(Some(account)).as_deref() == Some(owner_str.as_str())
+                {
+                    movements.push((ActivityKind::Burned, mint, amount, None));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Balance changes the instructions do not explain, e.g. transfers made by a program
+    let mut unexplained: BTreeMap<Pubkey, i128> = BTreeMap::new();
+    for balance in &receipt.token_balances {
+        if balance.owner.as_deref() == Some(owner_str.as_str()) {
+            *unexplained.entry(balance.mint).or_default() +=
+                balance.post as i128 - balance.pre as i128;
+        }
+    }
+    for (kind, mint, amount, _) in &movements {
+        let change = match kind {
+            ActivityKind::Received | ActivityKind::Minted => *amount as i128,
+            ActivityKind::Sent | ActivityKind::Burned => -(*amount as i128),
+            ActivityKind::SelfTransfer => 0,
+        };
+        *unexplained.entry(*mint).or_default() -= change;
+    }
+    for (mint, change) in unexplained {
+        match change {
+            0 => {}
+            change if change > 0 => {
+                movements.push((ActivityKind::Received, mint, change as u64, None))
+            }
+            change => {
+                movements.push((ActivityKind::Sent, mint, change.unsigned_abs() as u64, None))
+            }
+        }
+    }
+
+    movements
+        .into_iter()
+        .filter(|(_, mint, _, _)| mints.is_none_or(|mints| mints.contains(mint)))
+        .map(|(kind, mint, amount, counterparty)| ActivityItem {
+            kind,
+            decimals: decimals_of(&mint),
+            mint,
+            amount,
+            counterparty,
+            block_time: receipt.block_time,
+            slot: receipt.slot,
+            signature: receipt.signature.clone(),
+        })
+        .collect()
+}
+
+/// Classifies a transfer from the wallet's point of view, `None` if it is not involved
+fn transfer_movement(
+    owner: &str,
+    mint: Pubkey,
+    amount: u64,
+    source_owner: Option<String>,
+    destination_owner: Option<String>,
+) -> Option<(ActivityKind, Pubkey, u64, Option<String>)> {
+    let from_owner = source_owner.as_deref() == Some(owner);
+    let to_owner = destination_owner.as_deref() == Some(owner);
+    match (from_owner, to_owner) {
+        (true, true) => Some((ActivityKind::SelfTransfer, mint, amount, None)),
+        (true, false) => Some((ActivityKind::Sent, mint, amount, destination_owner)),
+        (false, true) => Some((ActivityKind::Received, mint, amount, source_owner)),
+        (false, false) => None,
+    }
+}
+
+/// Returns the account at a position of an instruction's accounts
+fn account_at(ix: &InspectedInstruction, index: usize) -> Option<Pubkey> {
+    ix.accounts.get(index).map(|a| a.pubkey)
+}
+
+/// Parses a cursor written by [`wallet_activity_page`]
+fn parse_cursor(cursor: &str) -> SssResult<(u64, Signature)> {
+    let invalid = || SssError::TokenError(format!("Invalid activity cursor '{}'", cursor));
+    let (slot, signature) = cursor.split_once(':').ok_or_else(invalid)?;
+    let slot = slot.parse().map_err(|_| invalid())?;
+    let signature = Signature::from_str(signature).map_err(|_| invalid())?;
+    Ok((slot, signature))
+}
+
+/// Writes the cursor continuing after a slot and signature
+fn format_cursor((slot, signature): &(u64, Signature)) -> String {
+    format!("{}:{}", slot, signature)
+}
+
+/// Parses a signature returned by the RPC node
+fn parse_signature(signature: &str) -> SssResult<Signature> {
+    Signature::from_str(signature).into_sss_error("Failed to parse signature from rpc")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inspect::inspect_message;
+    use solana_sdk::{instruction::Instruction, message::Message};
+
+    /// A wallet, its two token accounts and the mint they hold
+    struct Fixture {
+        owner: Pubkey,
+        other: Pubkey,
+        mint: Pubkey,
+        owner_account: Pubkey,
+        owner_second_account: Pubkey,
+        other_account: Pubkey,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            Self {
+                owner: Pubkey::new_unique(),
+                other: Pubkey::new_unique(),
+                mint: Pubkey::new_unique(),
+                owner_account: Pubkey::new_unique(),
+                owner_second_account: Pubkey::new_unique(),
+                other_account: Pubkey::new_unique(),
+            }
+        }
+
+        /// A balance change of one of the fixture's token accounts
+        fn balance(
+            &self,
+            account: Pubkey,
+            owner: Pubkey,
+            pre: u64,
+            post: u64,
+        ) -> TokenBalanceChange {
+            TokenBalanceChange {
+                account,
+                mint: self.mint,
+                owner: Some(owner.to_string()),
+                decimals: 2,
+                pre,
+                post,
+            }
+        }
+
+        fn transfer(
+            &self,
+            source: Pubkey,
+            destination: Pubkey,
+            authority: Pubkey,
+            amount: u64,
+        ) -> Instruction {
+            spl_token::instruction::transfer_checked(
+                &spl_token::id(),
+                &source,
+                &self.mint,
+                &destination,
+                &authority,
+                &[],
+                amount,
+                2,
+            )
+            .unwrap()
+        }
+    }
+
+    /// Decodes a confirmed transaction built from the instructions, as a receipt would
+    fn receipt(
+        payer: &Pubkey,
+        instructions: &[Instruction],
+        token_balances: Vec<TokenBalanceChange>,
+    ) -> Receipt {
+        let inspection = inspect_message(&Message::new(instructions, Some(payer)));
+        Receipt {
+            signature: "sig".to_string(),
+            slot: 42,
+            block_time: Some(1_700_000_000),
+            fee: 5000,
+            fee_payer: *payer,
+            success: true,
+            error: None,
+            instruction_summaries: inspection
+                .instructions
+                .iter()
+                .map(|ix| ix.summary())
+                .collect(),
+            instructions: inspection.instructions,
+            token_balances,
+            lamport_balances: Vec::new(),
+        }
+    }
+
+    fn summary(items: &[ActivityItem]) -> Vec<(ActivityKind, u64, Option<String>)> {
+        items
+            .iter()
+            .map(|item| (item.kind, item.amount, item.counterparty.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn owner_as_source_is_sent() {
+        let f = Fixture::new();
+        let receipt = receipt(
+            &f.owner,
+            &[f.transfer(f.owner_account, f.other_account, f.owner, 30)],
+            vec![
+                f.balance(f.owner_account, f.owner, 100, 70),
+                f.balance(f.other_account, f.other, 0, 30),
+            ],
+        );
+        let items = activity_items(&receipt, &f.owner, None);
+        assert_eq!(
+            summary(&items),
+            vec![(ActivityKind::Sent, 30, Some(f.other.to_string()))]
+        );
+        assert_eq!(items[0].mint, f.mint);
+        assert_eq!(items[0].decimals, 2);
+        assert_eq!(items[0].slot, 42);
+        assert_eq!(items[0].block_time, Some(1_700_000_000));
+    }
+
+    #[test]
+    fn owner_as_destination_is_received() {
+        let f = Fixture::new();
+        let receipt = receipt(
+            &f.other,
+            &[f.transfer(f.other_account, f.owner_account, f.other, 30)],
+            vec![
+                f.balance(f.other_account, f.other, 30, 0),
+                f.balance(f.owner_account, f.owner, 0, 30),
+            ],
+        );
+        assert_eq!(
+            summary(&activity_items(&receipt, &f.owner, None)),
+            vec![(ActivityKind::Received, 30, Some(f.other.to_string()))]
+        );
+        // The same transaction seen from the sender
+        assert_eq!(
+            summary(&activity_items(&receipt, &f.other, None)),
+            vec![(ActivityKind::Sent, 30, Some(f.owner.to_string()))]
+        );
+    }
+
+    #[test]
+    fn owner_as_both_is_a_self_transfer() {
+        let f = Fixture::new();
+        let receipt = receipt(
+            &f.owner,
+            &[f.transfer(f.owner_account, f.owner_second_account, f.owner, 30)],
+            vec![
+                f.balance(f.owner_account, f.owner, 100, 70),
+                f.balance(f.owner_second_account, f.owner, 0, 30),
+            ],
+        );
+        // The net balance change is zero, so no unexplained movement is added
+        assert_eq!(
+            summary(&activity_items(&receipt, &f.owner, None)),
+            vec![(ActivityKind::SelfTransfer, 30, None)]
+        );
+    }
+
+    #[test]
+    fn uninvolved_wallet_sees_nothing() {
+        let f = Fixture::new();
+        let receipt = receipt(
+            &f.owner,
+            &[f.transfer(f.owner_account, f.other_account, f.owner, 30)],
+            vec![
+                f.balance(f.owner_account, f.owner, 100, 70),
+                f.balance(f.other_account, f.other, 0, 30),
+            ],
+        );
+        assert!(activity_items(&receipt, &Pubkey::new_unique(), None).is_empty());
+    }
+
+    #[test]
+    fn mints_and_burns() {
+        let f = Fixture::new();
+        let authority = Pubkey::new_unique();
+        let receipt = receipt(
+            &authority,
+            &[
+                spl_token::instruction::mint_to(
+                    &spl_token::id(),
+                    &f.mint,
+                    &f.owner_account,
+                    &authority,
+                    &[],
+                    50,
+                )
+                .unwrap(),
+                spl_token::instruction::burn(
+                    &spl_token::id(),
+                    &f.owner_account,
+                    &f.mint,
+                    &f.owner,
+                    &[],
+                    20,
+                )
+                .unwrap(),
+            ],
+            vec![f.balance(f.owner_account, f.owner, 0, 30)],
+        );
+        assert_eq!(
+            summary(&activity_items(&receipt, &f.owner, None)),
+            vec![
+                (ActivityKind::Minted, 50, Some(authority.to_string())),
+                (ActivityKind::Burned, 20, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn unexplained_balance_changes_have_no_counterparty() {
+        let f = Fixture::new();
+        // A program moved the tokens through an inner instruction
+        let receipt = receipt(
+            &f.other,
+            &[],
+            vec![f.balance(f.owner_account, f.owner, 10, 35)],
+        );
+        assert_eq!(
+            summary(&activity_items(&receipt, &f.owner, None)),
+            vec![(ActivityKind::Received, 25, None)]
+        );
+    }
+
+    #[test]
+    fn failed_transactions_and_other_mints_are_left_out() {
+        let f = Fixture::new();
+        let mut failed = receipt(
+            &f.owner,
+            &[f.transfer(f.owner_account, f.other_account, f.owner, 30)],
+            vec![
+                f.balance(f.owner_account, f.owner, 100, 70),
+                f.balance(f.other_account, f.other, 0, 30),
+            ],
+        );
+        let only_other_mint = HashSet::from([Pubkey::new_unique()]);
+        assert!(activity_items(&failed, &f.owner, Some(&only_other_mint)).is_empty());
+        let only_mint = HashSet::from([f.mint]);
+        assert_eq!(activity_items(&failed, &f.owner, Some(&only_mint)).len(), 1);
+
+        failed.success = false;
+        assert!(activity_items(&failed, &f.owner, None).is_empty());
+    }
+
+    #[test]
+    fn cursor_round_trips() {
+        let cursor = (7, Signature::from([3; 64]));
+        assert_eq!(parse_cursor(&format_cursor(&cursor)).unwrap(), cursor);
+        for bad in ["", "7", "x:1", "7:notasignature"] {
+            assert!(
+                matches!(parse_cursor(bad), Err(SssError::TokenError(_))),
+                "{bad}"
+            );
+        }
+    }
+}
@@ -0,0 +1,784 @@
+//! Resumable batched airdrops
+
+use crate::RPC_CLIENT;
+use crate::batch::{BatchOptions, BatchReport};
+use crate::confirm::{
+    CancelToken, ConfirmationOutcome, ConfirmationStrategy, Confirmer, TxStatus, bulk_statuses,
+};
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::journal::{JournalOperation, journaled};
+use crate::metrics::track;
+use crate::payer::signing_payer;
+use crate::serde_utils::pubkey_string;
+use crate::supply_caps::{SupplyCapViolation, check_supply_after_mint, ensure_within_supply_cap};
+use crate::token::{
+    MintOptions, RecipientLock, TokenProgram, ensure_trusted_mint, mint_instructions,
+    mint_metadata, recipient_lock,
+};
+use crate::transaction::{
+    TxOptions, fitting_items, rebroadcast, record_bulk_outcome, send_signed, send_unconfirmed,
+    sign_with_cached_blockhash,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    hash::Hash,
+    message::Message,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+    transaction::{Transaction, TransactionError},
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// How long throughput mode polls a wave before rebroadcasting what was not seen yet
+const REBROADCAST_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Allocations [`execute_airdrop_from_rows`] reads before sending them
+pub const AIRDROP_ROWS_PER_CHUNK: usize = 4096;
+
+/// Outcome of an airdrop run
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct AirdropReport {
+    /// Recipients confirmed during this run, failed during this run, and already confirmed
+    /// by a previous run
+    #[serde(flatten)]
+    pub batch: BatchReport,
+    /// Set if the supply was found above the mint's cap after the run
+    pub supply_cap_violation: Option<SupplyCapViolation>,
+    /// Platform fees paid by the batches confirmed during this run, in lamports
+    pub platform_fee_lamports: u64,
+}
+
+impl AirdropReport {
+    /// Runs the airdrop again for the recipients that failed in this run
+    ///
+    /// # Arguments
+    ///
+    /// * `mint` - The public key of the token's mint account
+    /// * `allocations` - The allocations of the original run
+    /// * `checkpoint_path` - The checkpoint file of the original run
+    ///
+    /// # Returns
+    ///
+    /// The report of the retry, empty if nothing failed
+    pub fn retry_failed(
+        &self,
+        mint: Pubkey,
+        allocations: &[(Pubkey, u64)],
+        checkpoint_path: &Path,
+    ) -> SssResult<AirdropReport> {
+        self.batch.retry_failed(|failed| {
+            let retry: Vec<(Pubkey, u64)> = allocations
+                .iter()
+                .filter(|(recipient, _)| failed.contains(recipient))
+                .copied()
+                .collect();
+            execute_airdrop(mint, &retry, checkpoint_path)
+        })
+    }
+
+    /// Serializes the report to JSON
+    pub fn to_json(&self) -> SssResult<String> {
+        serde_json::to_string_pretty(self).into_sss_error("Failed to serialize airdrop report")
+    }
+}
+
+/// Delivery state of a recipient in the checkpoint file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum DeliveryStatus {
+    /// The transaction was signed and possibly sent, but not confirmed
+    Pending,
+    /// The transaction was confirmed
+    Confirmed,
+}
+
+/// Checkpoint record of a single recipient
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecipientRecord {
+    amount: u64,
+    signature: String,
+    status: DeliveryStatus,
+    /// Blockhash of a pending transaction, used to tell whether it can still land
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    blockhash: Option<String>,
+}
+
+/// Progress of an airdrop, persisted between batches
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AirdropCheckpoint {
+    #[serde(with = "pubkey_string")]
+    mint: Pubkey,
+    /// Records keyed by the base58 recipient address
+    recipients: BTreeMap<String, RecipientRecord>,
+}
+
+impl AirdropCheckpoint {
+    /// Loads the checkpoint at `path`, or starts a new one if the file does not exist
+    fn load_or_new(path: &Path, mint: Pubkey) -> SssResult<Self> {
+        if !path.exists() {
+            return Ok(Self {
+                mint,
+                recipients: BTreeMap::new(),
+            });
+        }
+
+        let json = fs::read_to_string(path).into_sss_error("Failed to read airdrop checkpoint")?;
+        let checkpoint: Self =
+            serde_json::from_str(&json).into_sss_error("Failed to parse airdrop checkpoint")?;
+        if checkpoint.mint != mint {
+            return Err(SssError::TokenError(format!(
+                "Airdrop checkpoint {} belongs to mint {}, not {}",
+                path.display(),
+                checkpoint.mint,
+                mint
+            )));
+        }
+        Ok(checkpoint)
+    }
+
+    /// Writes the checkpoint through a temporary file so a crash never leaves it truncated
+    fn save(&self, path: &Path) -> SssResult<()> {
+        let json = serde_json::to_string_pretty(self)
+            .into_sss_error("Failed to serialize airdrop checkpoint")?;
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, json).into_sss_error("Failed to write airdrop checkpoint")?;
+        fs::rename(&tmp, path).into_sss_error("Failed to write airdrop checkpoint")
+    }
+}
+
+/// What became of a pending transaction
+enum PendingOutcome {
+    /// The transaction landed successfully
+    Confirmed,
+    /// The transaction failed or can no longer land, so it is safe to send again
+    Dropped {
+        /// Why the transaction did not succeed
+        error: SssError,
+        /// Whether it landed and failed, rather than expired without landing
+        landed: bool,
+    },
+    /// The transaction may still land, so it must not be sent again yet
+    Unresolved,
+}
+
+/// Determines whether a pending transaction landed
+fn resolve_pending(record: &RecipientRecord) -> SssResult<PendingOutcome> {
+    let signature = Signature::from_str(&record.signature)
+        .into_sss_error("Failed to parse signature in airdrop checkpoint")?;
+    let mut confirmer = Confirmer::new(&RPC_CLIENT);
+    if let Some(blockhash) = &record.blockhash {
+        let blockhash = Hash::from_str(blockhash)
+            .into_sss_error("Failed to parse blockhash in airdrop checkpoint")?;
+        confirmer = confirmer.with_blockhash(blockhash);
+    }
+
+    // A deadline that has already passed checks the status only once
+    let outcome = confirmer.confirm(
+        &signature,
+        &ConfirmationStrategy::default(),
+        Instant::now(),
+        &CancelToken::default(),
+    )?;
+    Ok(match outcome {
+        ConfirmationOutcome::Confirmed { .. } => PendingOutcome::Confirmed,
+        ConfirmationOutcome::Failed {
+            error: TransactionError::BlockhashNotFound,
+            ..
+        } => PendingOutcome::Dropped {
+            error: SssError::RpcError("Transaction expired before landing".to_string()),
+            landed: false,
+        },
+        ConfirmationOutcome::Failed { error, .. } => PendingOutcome::Dropped {
+            error: SssError::TokenError(format!("Transaction failed: {}", error)),
+            landed: true,
+        },
+        ConfirmationOutcome::TimedOut { .. } if record.blockhash.is_none() => {
+            PendingOutcome::Dropped {
+                error: SssError::RpcError("Transaction was not found".to_string()),
+                landed: false,
+            }
+        }
+        ConfirmationOutcome::TimedOut { .. } => PendingOutcome::Unresolved,
+    })
+}
+
+/// Mints tokens to many recipients in batches, resuming from a checkpoint file
+///
+/// Every transaction is signed and recorded as pending in the checkpoint before it is
+/// sent, and marked confirmed once the cluster confirms it. On the next run, recipients
+/// already confirmed are skipped, and pending transactions are looked up on chain: they
+/// count as delivered if they landed, are sent again only once their blockhash has
+/// expired, and are otherwise reported as failed so a later run can resolve them.
+///
+/// Allocations to the same recipient are combined and zero amounts are ignored. Each
+/// transaction carries as many recipients as fit in a packet.
+///
+/// # Arguments
+///
+/// * `mint` - The public key of the token's mint account
+/// * `allocations` - The recipient wallets and the amounts they receive
+/// * `checkpoint_path` - The JSON file recording the signature per recipient
+///
+/// # Returns
+///
+/// The recipients that succeeded with their signatures, failed with their errors and were
+/// skipped. Every recipient of a failed transaction is reported with its error.
+///
+/// # Errors
+///
+/// Returns an error if the checkpoint cannot be read or written, or belongs to another
+/// mint. Failed batches are reported in the [`BatchReport::failed`] of the report instead.
+pub fn execute_airdrop(
+    mint: Pubkey,
+    allocations: &[(Pubkey, u64)],
+    checkpoint_path: &Path,
+) -> SssResult<AirdropReport> {
+    execute_airdrop_with_options(mint, allocations, checkpoint_path, &BatchOptions::default())
+}
+
+/// Mints tokens to many recipients like [`execute_airdrop`], with batch options
+///
+/// With [`BatchOptions::throughput_mode`], each wave of transactions is recorded as
+/// pending in the checkpoint before any of them is sent. Transactions still unconfirmed
+/// when a wave times out stay pending and are reported as failed, so the next run settles
+/// them before sending their recipients anything again.
+///
+/// # Arguments
+///
+/// * `mint` - The public key of the token's mint account
+/// * `allocations` - The recipient wallets and the amounts they receive
+/// * `checkpoint_path` - The JSON file recording the signature per recipient
+/// * `batch_options` - How the transactions are sent and confirmed
+///
+/// # Returns
+///
+/// The recipients that succeeded, failed and were skipped. Failed recipients whose
+/// transaction was sent are also listed in [`BatchReport::never_landed`] or
+/// [`BatchReport::landed_failed`].
+///
+/// # Errors
+///
+/// Returns an error if the checkpoint cannot be read or written, or belongs to another
+/// mint
+pub fn execute_airdrop_with_options(
+    mint: Pubkey,
+    allocations: &[(Pubkey, u64)],
+    checkpoint_path: &Path,
+    batch_options: &BatchOptions,
+) -> SssResult<AirdropReport> {
+    track(JournalOperation::Airdrop, || {
+        ensure_trusted_mint(&mint)?;
+        let payer = signing_payer()?;
+
+        let mut totals: BTreeMap<Pubkey, u64> = BTreeMap::new();
+        for (recipient, amount) in allocations.iter().filter(|(_, amount)| *amount > 0) {
+            let total = totals.entry(*recipient).or_insert(0);
+            *total = total.saturating_add(*amount);
+        }
+
+        let mut checkpoint = AirdropCheckpoint::load_or_new(checkpoint_path, mint)?;
+        let mut report = AirdropReport::default();
+
+        // Settle transactions left pending by an interrupted run
+        let mut remaining = Vec::new();
+        for (recipient, amount) in totals {
+            let key = recipient.to_string();
+            let Some(record) = checkpoint.recipients.get(&key) else {
+                remaining.push((recipient, amount));
+                continue;
+            };
+
+            if record.status == DeliveryStatus::Confirmed {
+                report.batch.skipped.push(recipient);
+                continue;
+            }
+
+            match resolve_pending(record)? {
+                PendingOutcome::Confirmed => {
+                    if let Some(record) = checkpoint.recipients.get_mut(&key) {
+                        record.status = DeliveryStatus::Confirmed;
+                        record.blockhash = None;
+                    }
+                    report.batch.skipped.push(recipient);
+                }
+                PendingOutcome::Dropped { .. } => {
+                    checkpoint.recipients.remove(&key);
+                    remaining.push((recipient, amount));
+                }
+                PendingOutcome::Unresolved => {
+                    report.batch.failed.push((
+                        recipient,
+                        SssError::RpcError(format!(
+                            "Previous transaction {} is still pending; run again once it expires",
+                            record.signature
+                        )),
+                    ));
+                }
+            }
+        }
+        checkpoint.save(checkpoint_path)?;
+
+        if remaining.is_empty() {
+            return Ok(report);
+        }
+
+        let mint_account = RPC_CLIENT
+            .get_account(&mint)
+            .into_sss_error("Failed to get mint account from rpc")?;
+        let token_program = if mint_account.owner == spl_token_2022::id() {
+            TokenProgram::Token2022
+        } else {
+            TokenProgram::Legacy
+        };
+        let options = MintOptions {
+            token_program,
+            ..MintOptions::default()
+        };
+        let metadata = mint_metadata(&RPC_CLIENT, &mint, &options)?;
+        // Accounts of a legacy soulbound mint are frozen once they exist, so whether each
+        // recipient already holds the token is looked up once for the whole airdrop
+        let owners: Vec<Pubkey> = remaining.iter().map(|(recipient, _)| *recipient).collect();
+        let locks: HashMap<Pubkey, RecipientLock> = owners
+            .iter()
+            .copied()
+            .zip(
+                recipient_lock(&RPC_CLIENT, &mint, token_program)?.for_recipients(
+                    &RPC_CLIENT,
+                    &mint,
+                    &owners,
+                )?,
+            )
+            .collect();
+        let tx_options = TxOptions {
+            idempotency_key: batch_options.idempotency_key.clone(),
+            ..TxOptions::default()
+        };
+        let build = |batch: &[(Pubkey, u64)]| {
+            let mut instructions = Vec::new();
+            for (recipient, amount) in batch {
+                instructions.extend(mint_instructions(
+                    &payer.pubkey(),
+                    &payer.pubkey(),
+                    &mint,
+                    metadata,
+                    recipient,
+                    *amount,
+                    &options,
+                    locks[recipient],
+                )?);
+            }
+            instructions.extend(tx_options.platform_fee_instruction(&payer.pubkey()));
+            Ok(Message::new(&instructions, Some(&payer.pubkey())))
+        };
+
+        // Each transaction takes as many recipients as fit in a packet
+        let mut batches = Vec::new();
+        let mut rest = remaining.as_slice();
+        while !rest.is_empty() {
+            match fitting_items(rest, 1, build) {
+                Ok(count) => {
+                    batches.push(&rest[..count]);
+                    rest = &rest[count..];
+                }
+                Err(e) => {
+                    report.batch.fail_all(recipients(&rest[..1]), &e);
+                    rest = &rest[1..];
+                }
+            }
+        }
+
+        if batch_options.throughput_mode {
+            for wave in batches.chunks(batch_options.wave_size.max(1)) {
+                send_wave(
+                    wave,
+                    &Wave {
+                        mint: &mint,
+                        payer: &payer,
+                        build: &build,
+                        tx_options: &tx_options,
+                        options: batch_options,
+                    },
+                    &mut checkpoint,
+                    checkpoint_path,
+                    &mut report,
+                )?;
+            }
+        } else {
+            for batch in batches {
+                let batch_total = batch
+                    .iter()
+                    .fold(0u64, |total, (_, amount)| total.saturating_add(*amount));
+                if let Err(e) = ensure_within_supply_cap(&RPC_CLIENT, &mint, batch_total) {
+                    report.batch.fail_all(recipients(batch), &e);
+                    continue;
+                }
+
+                let message = build(batch)?;
+                let tx = match journaled(
+                    JournalOperation::Airdrop,
+                    &journal_params(&mint, batch),
+                    &tx_options,
+                    || sign_with_cached_blockhash(&RPC_CLIENT, message, &[&payer], &tx_options),
+                ) {
+                    Ok(tx) => tx,
+                    Err(e) => {
+                        report.batch.fail_all(recipients(batch), &e);
+                        continue;
+                    }
+                };
+                let mut signature = tx.signatures[0].to_string();
+
+                // Record the attempt before sending so a crash cannot lead to a second transfer
+                record_pending(&mut checkpoint, batch, &tx);
+                checkpoint.save(checkpoint_path)?;
+
+                let sent = journaled(
+                    JournalOperation::Airdrop,
+                    &journal_params(&mint, batch),
+                    &tx_options,
+                    || send_signed(&RPC_CLIENT, &tx),
+                );
+                let outcome = match sent {
+                    // A batch confirmed before a restart returns its original signature
+                    Ok(sent) => {
+                        signature = sent;
+                        PendingOutcome::Confirmed
+                    }
+                    Err(e) => {
+                        // The send may have failed after the transaction reached the cluster
+                        let record = &checkpoint.recipients[&batch[0].0.to_string()];
+                        match resolve_pending(record) {
+                            Ok(PendingOutcome::Confirmed) => PendingOutcome::Confirmed,
+                            Ok(PendingOutcome::Dropped { landed, .. }) => {
+                                PendingOutcome::Dropped { error: e, landed }
+                            }
+                            // Keep the batch pending so the next run settles it before resending
+                            Ok(PendingOutcome::Unresolved) | Err(_) => {
+                                report.batch.fail_all(recipients(batch), &e);
+                                continue;
+                            }
+                        }
+                    }
+                };
+
+                match &outcome {
+                    PendingOutcome::Confirmed => mark_confirmed(&mut checkpoint, batch),
+                    _ => forget(&mut checkpoint, batch),
+                }
+                checkpoint.save(checkpoint_path)?;
+
+                match outcome {
+                    PendingOutcome::Confirmed => {
+                        report.batch.succeeded.extend(
+                            recipients(batch).map(|recipient| (recipient, signature.clone())),
+                        );
+                        if let Some((_, lamports)) = tx_options.resolved_platform_fee() {
+                            report.platform_fee_lamports += lamports;
+                        }
+                    }
+                    PendingOutcome::Dropped { error, landed } => {
+                        report.batch.fail_sent(recipients(batch), &error, landed)
+                    }
+                    PendingOutcome::Unresolved => {}
+                }
+            }
+        }
+
+        if !report.batch.succeeded.is_empty() {
+            report.supply_cap_violation = check_supply_after_mint(&RPC_CLIENT, &mint);
+        }
+        Ok(report)
+    })
+}
+
+/// Mints tokens to the recipients of a stream of allocations, a chunk at a time
+///
+/// Reads [`AIRDROP_ROWS_PER_CHUNK`] allocations, airdrops them like
+/// [`execute_airdrop_with_options`] and reads the next ones, so a large allocation file,
+/// e.g. from [`load_allocations_csv`](crate::load_allocations_csv), is never held whole.
+/// All chunks share the checkpoint file. A wallet must appear in one chunk only: its
+/// second appearance would be taken for a recipient the checkpoint already paid, so
+/// duplicates have to be combined or refused by the stream, as
+/// [`load_allocations_csv`](crate::load_allocations_csv) does.
+///
+/// The first malformed row stops the airdrop before the chunk holding it is sent. The
+/// chunks sent before it are recorded in the checkpoint, so running again with the
+/// corrected file carries on where the airdrop stopped. To list every malformed row
+/// before sending anything, read the file once with `filter_map(Result::err)`.
+///
+/// # Arguments
+///
+/// * `mint` - The public key of the token's mint account
+/// * `allocations` - The recipient wallets and the amounts they receive
+/// * `checkpoint_path` - The JSON file recording the signature per recipient
+/// * `batch_options` - How the transactions are sent and confirmed
+///
+/// # Returns
+///
+/// The combined report of every chunk
+///
+/// # Errors
+///
+/// Returns the error of the first malformed row, or an error if the checkpoint cannot be
+/// read or written, or belongs to another mint
+pub fn execute_airdrop_from_rows(
+    mint: Pubkey,
+    allocations: impl IntoIterator<Item = SssResult<(Pubkey, u64)>>,
+    checkpoint_path: &Path,
+    batch_options: &BatchOptions,
+) -> SssResult<AirdropReport> {
+    let mut allocations = allocations.into_iter();
+    let mut report = AirdropReport::default();
+    let mut chunk = Vec::with_capacity(AIRDROP_ROWS_PER_CHUNK);
+    loop {
+        chunk.clear();
+        for allocation in allocations.by_ref().take(AIRDROP_ROWS_PER_CHUNK) {
+            chunk.push(allocation?);
+        }
+        if chunk.is_empty() {
+            return Ok(report);
+        }
+
+        let part = execute_airdrop_with_options(mint, &chunk, checkpoint_path, batch_options)?;
+        report.batch.append(part.batch);
+        report.platform_fee_lamports += part.platform_fee_lamports;
+        if part.supply_cap_violation.is_some() {
+            report.supply_cap_violation = part.supply_cap_violation;
+        }
+    }
+}
+
+/// What the transactions of a throughput-mode wave are built and sent with
+struct Wave<'a, B> {
+    mint: &'a Pubkey,
+    payer: &'a Keypair,
+    build: &'a B,
+    tx_options: &'a TxOptions,
+    options: &'a BatchOptions,
+}
+
+/// Signs, sends and confirms a wave of batches in throughput mode
+///
+/// # Errors
+///
+/// Returns an error if the checkpoint cannot be written
+fn send_wave<B: Fn(&[(Pubkey, u64)]) -> SssResult<Message>>(
+    batches: &[&[(Pubkey, u64)]],
+    wave: &Wave<B>,
+    checkpoint: &mut AirdropCheckpoint,
+    checkpoint_path: &Path,
+    report: &mut AirdropReport,
+) -> SssResult<()> {
+    // Sign the whole wave and record it as pending before sending any of it
+    let mut signed = Vec::new();
+    let mut wave_total = 0u64;
+    for &batch in batches {
+        let batch_total = batch
+            .iter()
+            .fold(0u64, |total, (_, amount)| total.saturating_add(*amount));
+        if let Err(e) = ensure_within_supply_cap(
+            &RPC_CLIENT,
+            wave.mint,
+            wave_total.saturating_add(batch_total),
+        ) {
+            report.batch.fail_all(recipients(batch), &e);
+            continue;
+        }
+        let tx = match (wave.build)(batch).and_then(|message| {
+            journaled(
+                JournalOperation::Airdrop,
+                &journal_params(wave.mint, batch),
+                wave.tx_options,
+                || sign_with_cached_blockhash(&RPC_CLIENT, message, &[wave.payer], wave.tx_options),
+            )
+        }) {
+            Ok(tx) => tx,
+            Err(e) => {
+                report.batch.fail_all(recipients(batch), &e);
+                continue;
+            }
+        };
+        wave_total = wave_total.saturating_add(batch_total);
+        record_pending(checkpoint, batch, &tx);
+        signed.push((batch, tx, None));
+    }
+    checkpoint.save(checkpoint_path)?;
+
+    for (batch, tx, send_error) in &mut signed {
+        let sent = journaled(
+            JournalOperation::Airdrop,
+            &journal_params(wave.mint, batch),
+            wave.tx_options,
+            || send_unconfirmed(&RPC_CLIENT, tx),
+        );
+        *send_error = sent.err();
+    }
+
+    // Poll the whole wave, rebroadcasting what the cluster has not seen yet
+    let deadline = Instant::now() + wave.options.confirmation_timeout;
+    let signatures: Vec<Signature> = signed.iter().map(|(_, tx, _)| tx.signatures[0]).collect();
+    let mut statuses = vec![TxStatus::NotFound; signed.len()];
+    loop {
+        let open: Vec<usize> = (0..signed.len())
+            .filter(|&i| !statuses[i].is_final())
+            .collect();
+        if open.is_empty() || Instant::now() >= deadline {
+            break;
+        }
+        let query: Vec<Signature> = open.iter().map(|&i| signatures[i]).collect();
+        let round = bulk_statuses(
+            &RPC_CLIENT,
+            &query,
+            (Instant::now() + REBROADCAST_INTERVAL).min(deadline),
+        );
+        for (&i, status) in open.iter().zip(round) {
+            statuses[i] = status;
+        }
+        let unseen: Vec<usize> = open
+            .into_iter()
+            .filter(|&i| statuses[i] == TxStatus::NotFound)
+            .collect();
+        if !unseen.is_empty() && unseen.len() == statuses.iter().filter(|s| !s.is_final()).count() {
+            let expired = expired_blockhashes(unseen.iter().map(|&i| &signed[i].1));
+            if unseen
+                .iter()
+                .all(|&i| expired.contains(&signed[i].1.message.recent_blockhash))
+            {
+                break;
+            }
+        }
+        for &i in &unseen {
+            let _ = rebroadcast(&RPC_CLIENT, &signed[i].1);
+        }
+    }
+
+    // A transaction not found once its blockhash expired never landed; the status is
+    // fetched again after the expiry check, since it may have landed just before
+    let unseen: Vec<usize> = (0..signed.len())
+        .filter(|&i| statuses[i] == TxStatus::NotFound)
+        .collect();
+    let expired = expired_blockhashes(unseen.iter().map(|&i| &signed[i].1));
+    let query: Vec<Signature> = unseen.iter().map(|&i| signatures[i]).collect();
+    for (&i, status) in unseen
+        .iter()
+        .zip(bulk_statuses(&RPC_CLIENT, &query, Instant::now()))
+    {
+        statuses[i] = status;
+    }
+
+    for ((batch, tx, send_error), status) in signed.into_iter().zip(statuses) {
+        let _ = journaled(
+            JournalOperation::Airdrop,
+            &journal_params(wave.mint, batch),
+            wave.tx_options,
+            || -> SssResult<()> {
+                record_bulk_outcome(&tx, &status);
+                Ok(())
+            },
+        );
+        let signature = tx.signatures[0].to_string();
+        match status {
+            TxStatus::Confirmed => {
+                mark_confirmed(checkpoint, batch);
+                report
+                    .batch
+                    .succeeded
+                    .extend(recipients(batch).map(|recipient| (recipient, signature.clone())));
+                if let Some((_, lamports)) = wave.tx_options.resolved_platform_fee() {
+                    report.platform_fee_lamports += lamports;
+                }
+            }
+            TxStatus::Failed(error) => {
+                forget(checkpoint, batch);
+                let error = SssError::TokenError(format!("Transaction failed: {}", error));
+                report.batch.fail_sent(recipients(batch), &error, true);
+            }
+            TxStatus::NotFound if expired.contains(&tx.message.recent_blockhash) => {
+                forget(checkpoint, batch);
+                let error = send_error.unwrap_or_else(|| {
+                    SssError::RpcError("Transaction expired before landing".to_string())
+                });
+                report.batch.fail_sent(recipients(batch), &error, false);
+            }
+            TxStatus::Processed | TxStatus::NotFound => report.batch.fail_all(
+                recipients(batch),
+                &SssError::RpcError(format!(
+                    "Transaction {} is still pending; run again once it expires",
+                    signature
+                )),
+            ),
+        }
+    }
+    checkpoint.save(checkpoint_path)
+}
+
+/// Records every recipient of a signed batch as pending in the checkpoint
+fn record_pending(checkpoint: &mut AirdropCheckpoint, batch: &[(Pubkey, u64)], tx: &Transaction) {
+    for (recipient, amount) in batch {
+        checkpoint.recipients.insert(
+            recipient.to_string(),
+            RecipientRecord {
+                amount: *amount,
+                signature: tx.signatures[0].to_string(),
+                status: DeliveryStatus::Pending,
+                blockhash: Some(tx.message.recent_blockhash.to_string()),
+            },
+        );
+    }
+}
+
+/// Marks every recipient of a batch as confirmed in the checkpoint
+fn mark_confirmed(checkpoint: &mut AirdropCheckpoint, batch: &[(Pubkey, u64)]) {
+    for (recipient, _) in batch {
+        if let Some(record) = checkpoint.recipients.get_mut(&recipient.to_string()) {
+            record.status = DeliveryStatus::Confirmed;
+            record.blockhash = None;
+        }
+    }
+}
+
+/// Removes the recipients of a batch that can no longer land from the checkpoint
+fn forget(checkpoint: &mut AirdropCheckpoint, batch: &[(Pubkey, u64)]) {
+    for (recipient, _) in batch {
+        checkpoint.recipients.remove(&recipient.to_string());
+    }
+}
+
+/// The parameters recorded for a batch in the operation journal
+fn journal_params(mint: &Pubkey, batch: &[(Pubkey, u64)]) -> Value {
+    json!({
+        "mint": mint.to_string(),
+        "recipients": batch
+            .iter()
+            .map(|(recipient, amount)| json!({ "recipient": recipient.to_string(), "amount": amount }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// Returns the blockhashes of the given transactions that are no longer valid
+///
+/// A blockhash whose validity cannot be checked counts as valid, so its transactions are
+/// kept pending rather than sent again.
+fn expired_blockhashes<'a>(txs: impl Iterator<Item = &'a Transaction>) -> HashSet<Hash> {
+    let blockhashes: HashSet<Hash> = txs.map(|tx| tx.message.recent_blockhash).collect();
+    blockhashes
+        .into_iter()
+        .filter(|blockhash| {
+            RPC_CLIENT
+                .is_blockhash_valid(blockhash, CommitmentConfig::processed())
+                .is_ok_and(|valid| !valid)
+        })
+        .collect()
+}
+
+/// Returns the recipients of a batch
+fn recipients(batch: &[(Pubkey, u64)]) -> impl Iterator<Item = Pubkey> + '_ {
+    batch.iter().map(|(recipient, _)| *recipient)
+}
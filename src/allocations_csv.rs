@@ -0,0 +1,707 @@
+//! Streaming CSV import of airdrop allocations and export of batch reports
+//!
+//! Allocation files from operations run to hundreds of thousands of rows, more than
+//! kiosk-class hardware should hold in memory. [`load_allocations_csv`] reads the file one
+//! row at a time and [`execute_airdrop_from_rows`](crate::execute_airdrop_from_rows) sends
+//! the rows a chunk at a time, so the file is never loaded whole. What is kept per wallet
+//! is a public key and a number, to find or combine duplicates.
+//!
+//! Fields may be quoted, with `""` standing for a quote, but may not span lines.
+
+use crate::amount::ui_to_base_amount;
+use crate::batch::BatchReport;
+use crate::error::{IntoSssError, SssError, SssResult};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// How the amounts of an allocation file are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CsvAmounts {
+    /// Amounts are in base units; the file must not have a `decimals` column
+    #[default]
+    Base,
+    /// Amounts are UI amounts of a token with these decimals, such as `"1.5"`
+    ///
+    /// A `decimals` column, if present, must hold the same number on every row, which
+    /// catches a file prepared for another token.
+    Ui(u8),
+    /// Amounts are UI amounts converted with the `decimals` column of their row
+    DecimalsColumn,
+}
+
+/// What happens when a wallet appears on more than one row
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateWallets {
+    /// The amounts are added up, as [`execute_airdrop`](crate::execute_airdrop) does
+    ///
+    /// Totals are only known once the whole file has been read, so row errors are
+    /// yielded as they are found and the totals, ordered by wallet, after the last row.
+    #[default]
+    Sum,
+    /// Every row after the first for a wallet is an error naming both rows
+    Error,
+}
+
+/// Options of [`load_allocations_csv_with_options`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CsvImportOptions {
+    /// How the amounts are written
+    pub amounts: CsvAmounts,
+    /// What happens to wallets listed more than once
+    pub duplicates: DuplicateWallets,
+}
+
+/// Positions of the columns read from an allocation file
+#[derive(Debug, Clone, Copy)]
+struct Columns {
+    wallet: usize,
+    amount: usize,
+    decimals: Option<usize>,
+}
+
+/// The allocations of a CSV file, read one row at a time
+///
+/// Yields `(wallet, amount)` pairs in base units. A malformed row yields a `TokenError`
+/// starting with its line number, counting the header as line 1, and reading goes on with
+/// the next row. A file that cannot be opened or has no usable header yields a single
+/// error.
+pub struct AllocationRows {
+    /// The file, `None` once it has been read to the end or failed
+    reader: Option<BufReader<File>>,
+    /// The file name, for error messages
+    source: String,
+    /// An error to yield before anything else
+    pending_error: Option<SssError>,
+    options: CsvImportOptions,
+    columns: Columns,
+    /// Line number of the last line read
+    line_number: u64,
+    /// Buffer reused for every line
+    line: Vec<u8>,
+    /// With [`DuplicateWallets::Error`], the line each wallet was first seen on
+    ///
+    /// A B-tree grows a node at a time, where a hash map would double at once.
+    first_lines: BTreeMap<Pubkey, u64>,
+    /// With [`DuplicateWallets::Sum`], the total of each wallet
+    totals: BTreeMap<Pubkey, u64>,
+    /// With [`DuplicateWallets::Sum`], the totals left to yield once the file is read
+    drain: Option<std::collections::btree_map::IntoIter<Pubkey, u64>>,
+}
+
+impl AllocationRows {
+    /// Opens `path` and reads its header
+    fn open(path: &Path, options: CsvImportOptions) -> Self {
+        let mut rows = Self {
+            reader: None,
+            source: path.display().to_string(),
+            pending_error: None,
+            options,
+            columns: Columns {
+                wallet: 0,
+                amount: 1,
+                decimals: None,
+            },
+            line_number: 0,
+            line: Vec::new(),
+            first_lines: BTreeMap::new(),
+            totals: BTreeMap::new(),
+            drain: None,
+        };
+        match File::open(path) {
+            Ok(file) => rows.reader = Some(BufReader::new(file)),
+            Err(e) => {
+                rows.pending_error = Some(SssError::TokenError(format!(
+                    "Failed to open allocation file {}: {}",
+                    rows.source, e
+                )));
+                return rows;
+            }
+        }
+        if let Err(e) = rows.read_header() {
+            rows.reader = None;
+            rows.pending_error = Some(e);
+        }
+        rows
+    }
+
+    /// Reads the next line into `self.line`, returning `false` at the end of the file
+    fn read_line(&mut self) -> SssResult<bool> {
+        let Some(reader) = self.reader.as_mut() else {
+            return Ok(false);
+        };
+        self.line.clear();
+        let read = reader
+            .read_until(b'\n', &mut self.line)
+            .into_sss_error("Failed to read allocation file")?;
+        if read == 0 {
+            return Ok(false);
+        }
+        self.line_number += 1;
+        while matches!(self.line.last(), Some(b'\n' | b'\r')) {
+            self.line.pop();
+        }
+        Ok(true)
+    }
+
+    /// Locates the wallet, amount and decimals columns in the header
+    fn read_header(&mut self) -> SssResult<()> {
+        if !self.read_line()? {
+            return Err(self.file_error("is empty"));
+        }
+        let header = self
+            .line
+            .strip_prefix("\u{feff}".as_bytes())
+            .unwrap_or(&self.line);
+        let header = std::str::from_utf8(header)
+            .map_err(|_| self.file_error("has a header that is not UTF-8"))?;
+        let names: Vec<String> = split_fields(header)
+            .map_err(|e| self.file_error(&format!("has a malformed header: {}", e)))?
+            .iter()
+            .map(|name| name.trim().to_ascii_lowercase())
+            .collect();
+        let position = |name: &str| names.iter().position(|n| n == name);
+
+        let (Some(wallet), Some(amount)) = (position("wallet"), position("amount")) else {
+            return Err(self.file_error("needs a header with `wallet` and `amount` columns"));
+        };
+        let decimals = position("decimals");
+        match (self.options.amounts, decimals) {
+            (CsvAmounts::Base, Some(_)) => {
+                return Err(self.file_error(
+                    "has a `decimals` column but is read in base units; use \
+                     CsvAmounts::DecimalsColumn",
+                ));
+            }
+            (CsvAmounts::DecimalsColumn, None) => {
+                return Err(self.file_error("needs a `decimals` column"));
+            }
+            _ => {}
+        }
+        self.columns = Columns {
+            wallet,
+            amount,
+            decimals,
+        };
+        Ok(())
+    }
+
+    /// Parses the current line into a wallet and an amount in base units
+    fn parse_row(&self) -> Result<(Pubkey, u64), String> {
+        let line = std::str::from_utf8(&self.line).map_err(|_| "not UTF-8".to_string())?;
+        let fields = split_fields(line)?;
+        let field = |index: usize, name: &str| {
+            fields
+                .get(index)
+                .map(|field| field.trim())
+                .filter(|field| !field.is_empty())
+                .ok_or_else(|| format!("missing {}", name))
+        };
+
+        let wallet = field(self.columns.wallet, "wallet")?;
+        let wallet =
+            Pubkey::from_str(wallet).map_err(|_| format!("invalid wallet '{}'", wallet))?;
+        let amount = field(self.columns.amount, "amount")?;
+        let row_decimals = match self.columns.decimals {
+            Some(index) => {
+                let decimals = field(index, "decimals")?;
+                Some(
+                    decimals
+                        .parse::<u8>()
+                        .map_err(|_| format!("invalid decimals '{}'", decimals))?,
+                )
+            }
+            None => None,
+        };
+
+        let amount = match (self.options.amounts, row_decimals) {
+            (CsvAmounts::Base, _) => amount
+                .parse::<u64>()
+                .map_err(|_| format!("invalid amount '{}' in base units", amount))?,
+            (CsvAmounts::Ui(decimals), Some(row)) if row != decimals => {
+                return Err(format!(
+                    "decimals {} differ from the expected {}",
+                    row, decimals
+                ));
+            }
+            (CsvAmounts::Ui(decimals), _) | (CsvAmounts::DecimalsColumn, Some(decimals)) => {
+                ui_to_base_amount(amount, decimals).map_err(|e| match e {
+                    SssError::TokenError(message) => message,
+                    e => e.to_string(),
+                })?
+            }
+            (CsvAmounts::DecimalsColumn, None) => unreachable!("checked with the header"),
+        };
+        Ok((wallet, amount))
+    }
+
+    /// An error about the file as a whole
+    fn file_error(&self, problem: &str) -> SssError {
+        SssError::TokenError(format!("Allocation file {} {}", self.source, problem))
+    }
+
+    /// An error about the current line
+    fn row_error(&self, problem: &str) -> SssError {
+        SssError::TokenError(format!(
+            "Line {} of {}: {}",
+            self.line_number, self.source, problem
+        ))
+    }
+}
+
+impl Iterator for AllocationRows {
+    type Item = SssResult<(Pubkey, u64)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.pending_error.take() {
+            return Some(Err(e));
+        }
+        loop {
+            if let Some(drain) = self.drain.as_mut() {
+                return drain.next().map(Ok);
+            }
+            match self.read_line() {
+                Ok(true) => {}
+                Ok(false) => {
+                    self.reader = None;
+                    if self.options.duplicates != DuplicateWallets::Sum {
+                        return None;
+                    }
+                    self.drain = Some(std::mem::take(&mut self.totals).into_iter());
+                    continue;
+                }
+                Err(e) => {
+                    self.reader = None;
+                    return Some(Err(e));
+                }
+            }
+            if self.line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+
+            let (wallet, amount) = match self.parse_row() {
+                Ok(row) => row,
+                Err(problem) => return Some(Err(self.row_error(&problem))),
+            };
+            match self.options.duplicates {
+                DuplicateWallets::Error => {
+                    if let Some(first) = self.first_lines.get(&wallet) {
+                        return Some(Err(self.row_error(&format!(
+                            "wallet {} is already listed on line {}",
+                            wallet, first
+                        ))));
+                    }
+                    self.first_lines.insert(wallet, self.line_number);
+                    return Some(Ok((wallet, amount)));
+                }
+                DuplicateWallets::Sum => {
+                    let total = self.totals.entry(wallet).or_insert(0);
+                    match total.checked_add(amount) {
+                        Some(sum) => *total = sum,
+                        None => {
+                            return Some(Err(self.row_error(&format!(
+                                "the total for wallet {} overflows",
+                                wallet
+                            ))));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Splits a CSV line into its fields, unquoting quoted ones
+fn split_fields(line: &str) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.trim().is_empty() => {
+                field.clear();
+                quoted = true;
+            }
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("unterminated quoted field".to_string());
+    }
+    fields.push(field);
+    Ok(fields)
+}
+
+/// Reads the allocations of a CSV file one row at a time
+///
+/// The file needs a header naming a `wallet` and an `amount` column, in any order and
+/// case; other columns are ignored. Amounts are in base units and the amounts of a wallet
+/// listed more than once are added up. A UTF-8 byte order mark, `\r\n` line endings and
+/// blank lines are accepted.
+///
+/// # Arguments
+///
+/// * `path` - The CSV file
+///
+/// # Returns
+///
+/// An iterator of `(wallet, amount)` pairs, with an error for every malformed row
+pub fn load_allocations_csv(path: impl AsRef<Path>) -> AllocationRows {
+    load_allocations_csv_with_options(path, CsvImportOptions::default())
+}
+
+/// Reads the allocations of a CSV file like [`load_allocations_csv`], with options
+///
+/// # Arguments
+///
+/// * `path` - The CSV file
+/// * `options` - How amounts are written and what happens to duplicate wallets
+///
+/// # Returns
+///
+/// An iterator of `(wallet, amount)` pairs in base units, with an error for every
+/// malformed row
+pub fn load_allocations_csv_with_options(
+    path: impl AsRef<Path>,
+    options: CsvImportOptions,
+) -> AllocationRows {
+    AllocationRows::open(path.as_ref(), options)
+}
+
+/// Quotes a CSV field if it needs it
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Writes a batch report as CSV, one row per entry
+///
+/// The columns are `wallet`, `status` (`succeeded`, `failed` or `skipped`), `signature`,
+/// `error_kind` (one of [`SssError::kind`]), `landed` (`true` or `false` for failed
+/// entries whose transaction was sent) and `detail` (the error message or the reason an
+/// entry was skipped). The file is written through a temporary file, so it is either
+/// complete or untouched.
+///
+/// # Arguments
+///
+/// * `report` - The report, e.g. the `batch` of an [`AirdropReport`](crate::AirdropReport)
+/// * `path` - The CSV file to write
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be written
+pub fn write_batch_report_csv(report: &BatchReport, path: impl AsRef<Path>) -> SssResult<()> {
+    let path = path.as_ref();
+    let tmp = path.with_extension("tmp");
+    let landed: HashSet<&Pubkey> = report.landed_failed.iter().collect();
+    let never_landed: HashSet<&Pubkey> = report.never_landed.iter().collect();
+    let reasons: HashMap<&Pubkey, &str> = report
+        .skip_reasons
+        .iter()
+        .map(|(key, reason)| (key, reason.as_str()))
+        .collect();
+
+    let write = || -> std::io::Result<()> {
+        let mut out = BufWriter::new(File::create(&tmp)?);
+        writeln!(out, "wallet,status,signature,error_kind,landed,detail")?;
+        for (key, signature) in &report.succeeded {
+            writeln!(out, "{},succeeded,{},,,", key, csv_field(signature))?;
+        }
+        for (key, error) in &report.failed {
+            let landed = if landed.contains(key) {
+                "true"
+            } else if never_landed.contains(key) {
+                "false"
+            } else {
+                ""
+            };
+            writeln!(
+                out,
+                "{},failed,,{},{},{}",
+                key,
+                error.kind(),
+                landed,
+                csv_field(&error.to_string())
+            )?;
+        }
+        for key in &report.skipped {
+            let reason = reasons.get(key).copied().unwrap_or_default();
+            writeln!(out, "{},skipped,,,,{}", key, csv_field(reason))?;
+        }
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()
+    };
+    write().into_sss_error("Failed to write batch report")?;
+    fs::rename(&tmp, path).into_sss_error("Failed to write batch report")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::path::PathBuf;
+
+    /// Writes a CSV file with the given bytes
+    fn csv_file(contents: &[u8]) -> PathBuf {
+        let path = env::temp_dir().join(format!("sss-allocations-{}.csv", rand::random::<u64>()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn wallet(byte: u8) -> Pubkey {
+        Pubkey::new_from_array([byte; 32])
+    }
+
+    /// Reads a file into its allocations and the messages of its errors
+    fn load(contents: &str, options: CsvImportOptions) -> (Vec<(Pubkey, u64)>, Vec<String>) {
+        let mut rows = Vec::new();
+        let mut errors = Vec::new();
+        for row in load_allocations_csv_with_options(csv_file(contents.as_bytes()), options) {
+            match row {
+                Ok(row) => rows.push(row),
+                Err(e) => errors.push(e.to_string()),
+            }
+        }
+        (rows, errors)
+    }
+
+    fn errors_only() -> CsvImportOptions {
+        CsvImportOptions {
+            duplicates: DuplicateWallets::Error,
+            ..CsvImportOptions::default()
+        }
+    }
+
+    #[test]
+    fn rows_are_read_in_order_whatever_the_column_order() {
+        let contents = format!(
+            "Note,AMOUNT,Wallet\nfirst,5,{}\n\"a, quoted \"\"note\"\"\",7,{}\n",
+            wallet(1),
+            wallet(2)
+        );
+        let (rows, errors) = load(&contents, errors_only());
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(rows, [(wallet(1), 5), (wallet(2), 7)]);
+    }
+
+    #[test]
+    fn malformed_rows_name_their_line_and_reading_goes_on() {
+        let contents = format!(
+            "wallet,amount\n\
+             {w1},10\n\
+             not-a-wallet,1\n\
+             {w2},\n\
+             {w2},-3\n\
+             {w2},1.5\n\
+             {w2}\n\
+             \"{w2},4\n\
+             {w2},18446744073709551616\n\
+             {w3},2\n",
+            w1 = wallet(1),
+            w2 = wallet(2),
+            w3 = wallet(3),
+        );
+        let (rows, errors) = load(&contents, errors_only());
+        assert_eq!(rows, [(wallet(1), 10), (wallet(3), 2)]);
+        let expected = [
+            (3, "invalid wallet 'not-a-wallet'"),
+            (4, "missing amount"),
+            (5, "invalid amount '-3' in base units"),
+            (6, "invalid amount '1.5' in base units"),
+            (7, "missing amount"),
+            (8, "unterminated quoted field"),
+            (9, "invalid amount '18446744073709551616' in base units"),
+        ];
+        assert_eq!(errors.len(), expected.len(), "{:?}", errors);
+        for (error, (line, problem)) in errors.iter().zip(expected) {
+            assert!(
+                error.contains(&format!("Line {} of ", line)) && error.ends_with(problem),
+                "{} is not about line {}: {}",
+                error,
+                line,
+                problem
+            );
+        }
+    }
+
+    #[test]
+    fn a_byte_order_mark_crlf_endings_and_blank_lines_are_accepted() {
+        let mut contents = b"\xef\xbb\xbfwallet,amount\r\n".to_vec();
+        contents
+            .extend_from_slice(format!("{},1\r\n\r\n  \r\n{},2", wallet(1), wallet(2)).as_bytes());
+        let rows: Vec<_> = load_allocations_csv_with_options(csv_file(&contents), errors_only())
+            .collect::<SssResult<_>>()
+            .unwrap();
+        assert_eq!(rows, [(wallet(1), 1), (wallet(2), 2)]);
+
+        // Only at the start of the file; elsewhere it is part of the field
+        let contents = format!("wallet,amount\n\u{feff}{},1\n", wallet(1));
+        let (rows, errors) = load(&contents, errors_only());
+        assert!(rows.is_empty());
+        assert!(errors[0].contains("Line 2 of") && errors[0].contains("invalid wallet"));
+    }
+
+    #[test]
+    fn a_file_without_a_usable_header_yields_a_single_error() {
+        for (contents, problem) in [
+            ("", "is empty"),
+            (
+                "address,amount\n",
+                "needs a header with `wallet` and `amount` columns",
+            ),
+            ("\"wallet,amount\n", "has a malformed header"),
+            ("wallet,amount,decimals\n", "has a `decimals` column"),
+        ] {
+            let (rows, errors) = load(contents, CsvImportOptions::default());
+            assert!(rows.is_empty());
+            assert_eq!(errors.len(), 1, "{:?}", errors);
+            assert!(errors[0].contains(problem), "{}", errors[0]);
+        }
+
+        let missing = env::temp_dir().join(format!("sss-missing-{}.csv", rand::random::<u64>()));
+        let errors: Vec<_> = load_allocations_csv(&missing).collect();
+        assert_eq!(errors.len(), 1);
+        assert!(
+            errors[0]
+                .as_ref()
+                .unwrap_err()
+                .to_string()
+                .contains("Failed to open")
+        );
+    }
+
+    #[test]
+    fn ui_amounts_are_converted_with_the_given_or_listed_decimals() {
+        let contents = format!("wallet,amount\n{},1.5\n{},0.000001\n", wallet(1), wallet(2));
+        let options = CsvImportOptions {
+            amounts: CsvAmounts::Ui(6),
+            duplicates: DuplicateWallets::Error,
+        };
+        let (rows, errors) = load(&contents, options);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(rows, [(wallet(1), 1_500_000), (wallet(2), 1)]);
+
+        let contents = format!(
+            "wallet,amount,decimals\n{},1.5,2\n{},3,0\n{},0.001,2\n{},1,x\n",
+            wallet(1),
+            wallet(2),
+            wallet(3),
+            wallet(4)
+        );
+        let options = CsvImportOptions {
+            amounts: CsvAmounts::DecimalsColumn,
+            duplicates: DuplicateWallets::Error,
+        };
+        let (rows, errors) = load(&contents, options);
+        assert_eq!(rows, [(wallet(1), 150), (wallet(2), 3)]);
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert!(
+            errors[0].contains("Line 4 of") && errors[0].contains("decimal"),
+            "{}",
+            errors[0]
+        );
+        assert!(errors[1].contains("Line 5 of") && errors[1].ends_with("invalid decimals 'x'"));
+
+        // A decimals column must agree with the expected decimals
+        let contents = format!(
+            "wallet,amount,decimals\n{},1,6\n{},1,9\n",
+            wallet(1),
+            wallet(2)
+        );
+        let options = CsvImportOptions {
+            amounts: CsvAmounts::Ui(6),
+            duplicates: DuplicateWallets::Error,
+        };
+        let (rows, errors) = load(&contents, options);
+        assert_eq!(rows, [(wallet(1), 1_000_000)]);
+        assert!(errors[0].ends_with("decimals 9 differ from the expected 6"));
+
+        let (_, errors) = load("wallet,amount\n", options_with(CsvAmounts::DecimalsColumn));
+        assert!(errors[0].contains("needs a `decimals` column"));
+    }
+
+    fn options_with(amounts: CsvAmounts) -> CsvImportOptions {
+        CsvImportOptions {
+            amounts,
+            ..CsvImportOptions::default()
+        }
+    }
+
+    #[test]
+    fn duplicate_wallets_are_summed_or_refused() {
+        let contents = format!(
+            "wallet,amount\n{w2},1\n{w1},2\n{w2},3\nbad,1\n",
+            w1 = wallet(1),
+            w2 = wallet(2)
+        );
+        // Summed totals come after the last row, ordered by wallet
+        let rows: Vec<_> = load_allocations_csv(csv_file(contents.as_bytes()))
+            .map(|row| row.map_err(|e| e.to_string()))
+            .collect();
+        assert_eq!(rows.len(), 3);
+        assert!(rows[0].as_ref().unwrap_err().contains("Line 5 of"));
+        assert_eq!(rows[1..], [Ok((wallet(1), 2)), Ok((wallet(2), 4))]);
+
+        let (rows, errors) = load(&contents, errors_only());
+        assert_eq!(rows, [(wallet(2), 1), (wallet(1), 2)]);
+        assert!(errors[0].contains("Line 4 of"));
+        assert!(errors[0].ends_with(&format!("wallet {} is already listed on line 2", wallet(2))));
+
+        let contents = format!(
+            "wallet,amount\n{w},18446744073709551615\n{w},1\n",
+            w = wallet(1)
+        );
+        let (rows, errors) = load(&contents, CsvImportOptions::default());
+        assert_eq!(rows, [(wallet(1), u64::MAX)]);
+        assert!(errors[0].contains("Line 3 of") && errors[0].ends_with("overflows"));
+    }
+
+    #[test]
+    fn a_batch_report_is_written_with_quoted_details() {
+        let report = BatchReport {
+            succeeded: vec![(wallet(1), "5sig".to_string())],
+            failed: vec![
+                (
+                    wallet(2),
+                    SssError::RpcError("node said \"no\", twice".to_string()),
+                ),
+                (wallet(3), SssError::TokenError("expired".to_string())),
+                (wallet(4), SssError::TokenError("landed".to_string())),
+            ],
+            skipped: vec![wallet(5)],
+            skip_reasons: vec![(wallet(5), "frozen account".to_string())],
+            never_landed: vec![wallet(3)],
+            landed_failed: vec![wallet(4)],
+        };
+        let path = csv_file(b"");
+        write_batch_report_csv(&report, &path).unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = written.lines().collect();
+        assert_eq!(lines[0], "wallet,status,signature,error_kind,landed,detail");
+        assert_eq!(lines[1], format!("{},succeeded,5sig,,,", wallet(1)));
+        assert_eq!(
+            lines[2],
+            format!(
+                "{},failed,,rpc,,\"RPC error: node said \"\"no\"\", twice\"",
+                wallet(2)
+            )
+        );
+        assert!(lines[3].starts_with(&format!("{},failed,,token,false,", wallet(3))));
+        assert!(lines[4].starts_with(&format!("{},failed,,token,true,", wallet(4))));
+        assert_eq!(lines[5], format!("{},skipped,,,,frozen account", wallet(5)));
+        assert_eq!(lines.len(), 6);
+        assert!(!path.with_extension("tmp").exists());
+    }
+}
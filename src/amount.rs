@@ -0,0 +1,673 @@
+//! Conversion between UI amounts and base units
+
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::mint_cache::{MintInfo, cache_mint, cached_mint};
+use serde::Serialize;
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use std::fmt;
+use std::str::FromStr;
+
+/// Converts a decimal UI amount such as `"1.5"` into base units
+///
+/// The conversion is exact: the amount is parsed as a decimal string rather than a float.
+///
+/// # Arguments
+///
+/// * `ui_amount` - The amount in whole tokens, with at most `decimals` fractional digits
+/// * `decimals` - The number of decimal places of the mint
+///
+/// # Returns
+///
+/// The amount in base units
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the amount is malformed, has too many fractional digits or
+/// does not fit in a `u64`
+pub fn ui_to_base_amount(ui_amount: &str, decimals: u8) -> SssResult<u64> {
+    let (whole, fraction) = ui_amount.split_once('.').unwrap_or((ui_amount, ""));
+    base_amount_from_parts(whole, fraction, decimals, ui_amount)
+}
+
+/// Converts the whole and fractional digits of an amount into base units
+///
+/// `input` is the amount as given by the caller, used in error messages.
+fn base_amount_from_parts(
+    whole: &str,
+    fraction: &str,
+    decimals: u8,
+    input: &str,
+) -> SssResult<u64> {
+    let invalid = || SssError::TokenError(format!("Invalid token amount '{}'", input));
+
+    if whole.is_empty() && fraction.is_empty() {
+        return Err(invalid());
+    }
+    if !whole.bytes().all(|b| b.is_ascii_digit()) || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    // A zero-decimal token counts whole items, such as consumables
+    if decimals == 0 && fraction.bytes().any(|b| b != b'0') {
+        return Err(SssError::TokenError(format!(
+            "Token amount '{}' is not a whole number; the token counts whole items",
+            input
+        )));
+    }
+    if fraction.len() > decimals as usize {
+        return Err(SssError::TokenError(format!(
+            "Token amount '{}' has more than {} decimal places",
+            input, decimals
+        )));
+    }
+
+    // Shifting the digits instead of scaling by 10^decimals keeps amounts of mints with 20
+    // or more decimals, whose scale overflows a u64, exact
+    let mut digits = String::with_capacity(whole.len() + decimals as usize);
+    digits.push_str(whole);
+    digits.push_str(fraction);
+    digits.extend(std::iter::repeat_n('0', decimals as usize - fraction.len()));
+    let digits = digits.trim_start_matches('0');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    digits
+        .parse()
+        .map_err(|_| SssError::TokenError(format!("Token amount '{}' is too large", input)))
+}
+
+/// Converts an amount in base units into a decimal UI amount
+///
+/// Trailing fractional zeros are trimmed, so `1_500_000_000` with 9 decimals is `"1.5"`.
+///
+/// # Arguments
+///
+/// * `amount` - The amount in base units
+/// * `decimals` - The number of decimal places of the mint
+///
+/// # Returns
+///
+/// The amount in whole tokens
+pub fn base_to_ui_amount(amount: u64, decimals: u8) -> String {
+    let digits = amount.to_string();
+    let decimals = decimals as usize;
+    if decimals == 0 {
+        return digits;
+    }
+
+    let mut ui = String::with_capacity(digits.len().max(decimals) + 2);
+    if digits.len() > decimals {
+        let (whole, fraction) = digits.split_at(digits.len() - decimals);
+        ui.push_str(whole);
+        ui.push('.');
+        ui.push_str(fraction);
+    } else {
+        ui.push_str("0.");
+        ui.extend(std::iter::repeat_n('0', decimals - digits.len()));
+        ui.push_str(&digits);
+    }
+
+    let trimmed = ui.trim_end_matches('0').trim_end_matches('.').len();
+    ui.truncate(trimmed);
+    ui
+}
+
+/// An amount in base units, the smallest indivisible unit of a mint
+///
+/// Displays as the bare integer; use [`RawAmount::to_ui`] to render it in whole tokens.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(transparent)]
+pub struct RawAmount(pub u64);
+
+impl RawAmount {
+    /// Converts an amount in whole tokens into base units
+    ///
+    /// # Arguments
+    ///
+    /// * `ui` - The amount in whole tokens
+    /// * `decimals` - The number of decimal places of the mint
+    ///
+    /// # Errors
+    ///
+    /// Returns a `TokenError` if the amount has more than `decimals` fractional digits or
+    /// does not fit in a `u64`
+    pub fn from_ui(ui: &UiAmount, decimals: u8) -> SssResult<Self> {
+        ui_to_base_amount(&ui.0, decimals).map(Self)
+    }
+
+    /// Converts the amount into whole tokens of a mint with the given decimals
+    pub fn to_ui(self, decimals: u8) -> UiAmount {
+        UiAmount(base_to_ui_amount(self.0, decimals))
+    }
+}
+
+impl From<u64> for RawAmount {
+    fn from(amount: u64) -> Self {
+        Self(amount)
+    }
+}
+
+impl From<RawAmount> for u64 {
+    fn from(amount: RawAmount) -> Self {
+        amount.0
+    }
+}
+
+impl fmt::Display for RawAmount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// An exact decimal amount in whole tokens, e.g. `1.5`
+///
+/// Held as normalized decimal text rather than a float, so no precision is lost: leading
+/// whole zeros and trailing fractional zeros are dropped, and `.5` reads as `0.5`. Whether
+/// the amount fits a mint is only known once it is converted with [`RawAmount::from_ui`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(transparent)]
+pub struct UiAmount(String);
+
+impl UiAmount {
+    /// Returns the amount as normalized decimal text
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Returns the number of fractional digits of the amount
+    pub fn fraction_digits(&self) -> usize {
+        self.0
+            .split_once('.')
+            .map_or(0, |(_, fraction)| fraction.len())
+    }
+}
+
+impl FromStr for UiAmount {
+    type Err = SssError;
+
+    /// Parses a plain decimal amount such as `"1.5"`, without sign, exponent or separators
+    fn from_str(input: &str) -> SssResult<Self> {
+        let invalid = || SssError::TokenError(format!("Invalid token amount '{}'", input));
+        let (whole, fraction) = input.split_once('.').unwrap_or((input, ""));
+        if whole.is_empty() && fraction.is_empty() {
+            return Err(invalid());
+        }
+        if !whole.bytes().all(|b| b.is_ascii_digit())
+            || !fraction.bytes().all(|b| b.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+        let whole = whole.trim_start_matches('0');
+        let fraction = fraction.trim_end_matches('0');
+        let whole = if whole.is_empty() { "0" } else { whole };
+        Ok(Self(if fraction.is_empty() {
+            whole.to_string()
+        } else {
+            format!("{}.{}", whole, fraction)
+        }))
+    }
+}
+
+impl fmt::Display for UiAmount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// An amount given either in base units or in whole tokens
+///
+/// Accepted by the mint, transfer and burn functions as `impl Into<AmountSpec>`. A
+/// `RawAmount` states base units and a `UiAmount` whole tokens; a UI amount is converted
+/// with the decimals of the mint it applies to. A bare `u64` still means base units, but
+/// only through a deprecated conversion. Serializes as a number for a raw amount and as
+/// decimal text for a UI amount.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(untagged)]
+pub enum AmountSpec {
+    /// An amount in base units
+    Raw(RawAmount),
+    /// An amount in whole tokens
+    Ui(UiAmount),
+}
+
+impl AmountSpec {
+    /// An amount in base units
+    pub fn raw(amount: u64) -> Self {
+        Self::Raw(RawAmount(amount))
+    }
+
+    /// An amount in whole tokens, parsed from decimal text such as `"1.5"`
+    ///
+    /// # Errors
+    ///
+    /// Returns a `TokenError` if the text is not a plain decimal amount
+    pub fn ui(amount: &str) -> SssResult<Self> {
+        amount.parse().map(Self::Ui)
+    }
+
+    /// Resolves the amount into base units for a mint with the given decimals
+    ///
+    /// # Errors
+    ///
+    /// Returns a `TokenError` if a UI amount has too many fractional digits for `decimals`
+    /// or does not fit in a `u64`
+    pub fn to_raw(&self, decimals: u8) -> SssResult<RawAmount> {
+        match self {
+            Self::Raw(amount) => Ok(*amount),
+            Self::Ui(amount) => RawAmount::from_ui(amount, decimals),
+        }
+    }
+
+    /// Resolves the amount into base units for a mint, reading its decimals through
+    /// `client` only for a UI amount of a mint not cached yet
+    pub(crate) fn resolve(&self, client: &RpcClient, mint: &Pubkey) -> SssResult<u64> {
+        let ui = match self {
+            Self::Raw(amount) => return Ok(amount.0),
+            Self::Ui(ui) => ui,
+        };
+        let info = match cached_mint(mint) {
+            Some(info) => info,
+            None => {
+                let account = client
+                    .get_account(mint)
+                    .into_sss_error("Failed to get mint account from rpc")?;
+                let info = MintInfo::from_account(mint, &account)?;
+                cache_mint(*mint, info);
+                info
+            }
+        };
+        RawAmount::from_ui(ui, info.decimals).map(|raw| raw.0)
+    }
+}
+
+/// A bare `u64` is an amount in base units, as the amount parameters always took
+///
+/// Deprecated: a bare number does not say which unit it is in, which is how whole tokens
+/// end up sent as base units. Use [`AmountSpec::raw`] or [`AmountSpec::ui`] instead. The
+/// compiler cannot flag a trait impl as deprecated, so the impl is behind the default
+/// `u64-amounts` feature; turn it off to find the remaining bare amounts at compile time.
+#[cfg(feature = "u64-amounts")]
+impl From<u64> for AmountSpec {
+    fn from(amount: u64) -> Self {
+        Self::Raw(RawAmount(amount))
+    }
+}
+
+impl From<RawAmount> for AmountSpec {
+    fn from(amount: RawAmount) -> Self {
+        Self::Raw(amount)
+    }
+}
+
+impl From<UiAmount> for AmountSpec {
+    fn from(amount: UiAmount) -> Self {
+        Self::Ui(amount)
+    }
+}
+
+impl fmt::Display for AmountSpec {
+    /// Renders a raw amount as its integer and a UI amount with its decimal point
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Raw(amount) => amount.fmt(f),
+            Self::Ui(amount) => amount.fmt(f),
+        }
+    }
+}
+
+/// How [`format_token_amount`] renders and [`parse_token_amount`] reads an amount
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatOptions {
+    /// Separator between groups of three whole digits, `None` for no grouping
+    pub grouping_separator: Option<char>,
+    /// Separator between the whole and the fractional digits
+    pub decimal_separator: char,
+    /// Fractional digits always shown, padded with zeros
+    pub min_fraction_digits: u8,
+    /// Fractional digits shown at most, rounding half up; `None` shows every decimal
+    pub max_fraction_digits: Option<u8>,
+    /// Drop trailing fractional zeros beyond `min_fraction_digits`
+    pub trim_trailing_zeros: bool,
+}
+
+impl Default for FormatOptions {
+    /// The plain format of [`base_to_ui_amount`], e.g. `1234.5`
+    fn default() -> Self {
+        Self {
+            grouping_separator: None,
+            decimal_separator: '.',
+            min_fraction_digits: 0,
+            max_fraction_digits: None,
+            trim_trailing_zeros: true,
+        }
+    }
+}
+
+impl FormatOptions {
+    /// Returns the separators customary for a locale, e.g. `de-DE` gives `1.234,5`
+    ///
+    /// Only the language subtag is considered, except for Swiss locales. Unknown locales
+    /// get the English separators `1,234.5`.
+    ///
+    /// # Arguments
+    ///
+    /// * `locale` - A BCP 47 tag such as `en-US`, `de_DE` or `fr`
+    pub fn for_locale(locale: &str) -> Self {
+        let locale = locale.to_ascii_lowercase().replace('_', "-");
+        let language = locale.split('-').next().unwrap_or_default();
+        let (grouping, decimal) = if locale.ends_with("-ch") {
+            ('\u{2019}', '.')
+        } else {
+            match language {
+                "de" | "es" | "it" | "nl" | "pt" | "id" | "tr" | "da" | "el" => ('.', ','),
+                "fr" | "ru" | "pl" | "cs" | "sk" | "sv" | "fi" | "nb" | "no" | "uk" | "hu" => {
+                    ('\u{a0}', ',')
+                }
+                _ => (',', '.'),
+            }
+        };
+        Self {
+            grouping_separator: Some(grouping),
+            decimal_separator: decimal,
+            ..Self::default()
+        }
+    }
+}
+
+/// Formats an amount in base units for display
+///
+/// Amounts with more decimals than `max_fraction_digits` are rounded half up, so
+/// `1_999_999` with 6 decimals and 2 fraction digits is `2.00`.
+///
+/// # Arguments
+///
+/// * `amount` - The amount in base units
+/// * `decimals` - The number of decimal places of the mint
+/// * `options` - Separators, fraction digits and trimming
+///
+/// # Returns
+///
+/// The amount in whole tokens, e.g. `1,234.5`
+pub fn format_token_amount(amount: u64, decimals: u8, options: &FormatOptions) -> String {
+    let shown = options
+        .max_fraction_digits
+        .unwrap_or(decimals)
+        .min(decimals);
+    let mut value = amount as u128;
+    if shown < decimals {
+        let divisor = 10u128.pow((decimals - shown) as u32);
+        value = (value + divisor / 2) / divisor;
+    }
+    let scale = 10u128.pow(shown as u32);
+    let whole = (value / scale).to_string();
+    let mut fraction = format!("{:0width$}", value % scale, width = shown as usize);
+    if shown == 0 {
+        fraction.clear();
+    }
+
+    // Never show more digits than max_fraction_digits allows, even when padding
+    let min_digits = match options.max_fraction_digits {
+        Some(max) => options.min_fraction_digits.min(max),
+        None => options.min_fraction_digits,
+    } as usize;
+    if options.trim_trailing_zeros {
+        let trimmed = fraction.trim_end_matches('0').len().max(min_digits);
+        fraction.truncate(trimmed);
+    }
+    if fraction.len() < min_digits {
+        fraction.extend(std::iter::repeat_n('0', min_digits - fraction.len()));
+    }
+
+    let mut formatted = String::with_capacity(whole.len() * 2 + fraction.len() + 1);
+    for (i, digit) in whole.chars().enumerate() {
+        if i > 0
+            && (whole.len() - i).is_multiple_of(3)
+            && let Some(separator) = options.grouping_separator
+        {
+            formatted.push(separator);
+        }
+        formatted.push(digit);
+    }
+    if !fraction.is_empty() {
+        formatted.push(options.decimal_separator);
+        formatted.push_str(&fraction);
+    }
+    formatted
+}
+
+/// Parses an amount formatted with [`format_token_amount`] into base units
+///
+/// Grouping separators are optional, but where present they must separate groups of
+/// three digits, so `1,5` is rejected under English separators instead of read as 15.
+/// Surrounding whitespace is ignored and trailing fractional zeros beyond `decimals`
+/// are accepted.
+///
+/// # Arguments
+///
+/// * `formatted` - The amount in whole tokens, e.g. `1.234,5` under German separators
+/// * `decimals` - The number of decimal places of the mint
+/// * `options` - The separators used
+///
+/// # Returns
+///
+/// The amount in base units
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the amount is malformed, has too many non-zero fractional
+/// digits or does not fit in a `u64`
+pub fn parse_token_amount(
+    formatted: &str,
+    decimals: u8,
+    options: &FormatOptions,
+) -> SssResult<u64> {
+    let invalid = || SssError::TokenError(format!("Invalid token amount '{}'", formatted));
+
+    let trimmed = formatted.trim();
+    let (whole, fraction) = match trimmed.split_once(options.decimal_separator) {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (trimmed, None),
+    };
+
+    let whole = match options.grouping_separator {
+        Some(separator) if whole.contains(separator) => {
+            let groups: Vec<&str> = whole.split(separator).collect();
+            let valid = groups.iter().enumerate().all(|(i, group)| {
+                let len = group.len();
+                if i == 0 {
+                    (1..=3).contains(&len)
+                } else {
+                    len == 3
+                }
+            });
+            if !valid {
+                return Err(invalid());
+            }
+            groups.concat()
+        }
+        _ => whole.to_string(),
+    };
+
+    let fraction = match fraction {
+        Some("") => return Err(invalid()),
+        // Padding zeros beyond the mint's decimals carry no value
+        Some(fraction) => {
+            let kept = fraction.trim_end_matches('0').len().max(decimals as usize);
+            &fraction[..kept.min(fraction.len())]
+        }
+        None => "",
+    };
+    base_amount_from_parts(&whole, fraction, decimals, formatted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ui(amount: &str) -> UiAmount {
+        amount.parse().unwrap()
+    }
+
+    fn error(result: SssResult<impl fmt::Debug>) -> String {
+        match result {
+            Err(SssError::TokenError(message)) => message,
+            other => panic!("expected a TokenError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn largest_amount_converts_exactly_at_every_decimals() {
+        for decimals in 0..=30u8 {
+            let text = base_to_ui_amount(u64::MAX, decimals);
+            assert_eq!(
+                ui_to_base_amount(&text, decimals).unwrap(),
+                u64::MAX,
+                "{text}"
+            );
+            assert_eq!(
+                RawAmount::from_ui(&ui(&text), decimals).unwrap(),
+                RawAmount(u64::MAX)
+            );
+            assert_eq!(RawAmount(u64::MAX).to_ui(decimals), ui(&text));
+        }
+        assert_eq!(base_to_ui_amount(u64::MAX, 9), "18446744073.709551615");
+        assert_eq!(base_to_ui_amount(u64::MAX, 20), "0.18446744073709551615");
+    }
+
+    #[test]
+    fn one_base_unit_past_the_largest_amount_overflows() {
+        let cases = [
+            ("18446744073709551616", 0),
+            ("18446744073.709551616", 9),
+            ("18446744074", 9),
+            ("0.18446744073709551616", 20),
+            ("1", 20),
+            ("99999999999999999999999999999999", 0),
+        ];
+        for (amount, decimals) in cases {
+            let message = error(ui_to_base_amount(amount, decimals));
+            assert!(message.contains("too large"), "{amount}: {message}");
+            let message = error(RawAmount::from_ui(&ui(amount), decimals));
+            assert!(message.contains("too large"), "{amount}: {message}");
+        }
+    }
+
+    #[test]
+    fn smallest_unit_of_mints_with_many_decimals_converts() {
+        assert_eq!(ui_to_base_amount("0.00000000000000000001", 20).unwrap(), 1);
+        assert_eq!(ui_to_base_amount("0", 255).unwrap(), 0);
+        let smallest = format!("0.{}1", "0".repeat(254));
+        assert_eq!(ui_to_base_amount(&smallest, 255).unwrap(), 1);
+        assert_eq!(base_to_ui_amount(1, 255), smallest);
+    }
+
+    #[test]
+    fn too_many_fraction_digits_are_refused() {
+        let message = error(ui_to_base_amount("1.0000000001", 9));
+        assert!(message.contains("more than 9 decimal places"), "{message}");
+        assert_eq!(ui_to_base_amount("1.000000001", 9).unwrap(), 1_000_000_001);
+
+        let message = error(RawAmount::from_ui(&ui("0.1234567"), 6));
+        assert!(message.contains("more than 6 decimal places"), "{message}");
+        // A UI amount drops its trailing zeros, so they do not count as decimal places
+        assert_eq!(
+            RawAmount::from_ui(&ui("0.1234560000"), 6).unwrap(),
+            RawAmount(123_456)
+        );
+        assert_eq!(ui("0.1234560000").fraction_digits(), 6);
+    }
+
+    #[test]
+    fn whole_item_tokens_refuse_fractions() {
+        let message = error(ui_to_base_amount("1.5", 0));
+        assert!(message.contains("not a whole number"), "{message}");
+        assert_eq!(ui_to_base_amount("15", 0).unwrap(), 15);
+        assert_eq!(RawAmount::from_ui(&ui("15.000"), 0).unwrap(), RawAmount(15));
+    }
+
+    #[test]
+    fn malformed_amounts_are_refused() {
+        for amount in [
+            "", ".", "-1", "+1", "1e9", "1_000", "1,5", " 1", "1 ", "1.2.3", "0x10", "١", "NaN",
+        ] {
+            let message = error(ui_to_base_amount(amount, 9));
+            assert!(
+                message.contains("Invalid token amount"),
+                "{amount:?}: {message}"
+            );
+            assert!(amount.parse::<UiAmount>().is_err(), "{amount:?}");
+        }
+    }
+
+    #[test]
+    fn ui_amounts_are_normalized() {
+        assert_eq!(ui("1.50").as_str(), "1.5");
+        assert_eq!(ui(".5").as_str(), "0.5");
+        assert_eq!(ui("5.").as_str(), "5");
+        assert_eq!(ui("007.000").as_str(), "7");
+        assert_eq!(ui("0.000").as_str(), "0");
+        assert_eq!(ui("1.50"), ui("01.5"));
+        assert_eq!(ui_to_base_amount(".5", 1).unwrap(), 5);
+        assert_eq!(ui_to_base_amount("5.", 1).unwrap(), 50);
+    }
+
+    #[test]
+    fn amounts_display_with_the_decimal_point() {
+        assert_eq!(RawAmount(1_500_000_000).to_string(), "1500000000");
+        assert_eq!(RawAmount(1_500_000_000).to_ui(9).to_string(), "1.5");
+        assert_eq!(RawAmount(5).to_ui(6).to_string(), "0.000005");
+        assert_eq!(RawAmount(0).to_ui(6).to_string(), "0");
+        assert_eq!(RawAmount(1_000_000).to_ui(6).to_string(), "1");
+        assert_eq!(AmountSpec::raw(42).to_string(), "42");
+        assert_eq!(AmountSpec::from(ui("0.25")).to_string(), "0.25");
+    }
+
+    #[test]
+    fn amount_spec_resolves_per_unit() {
+        assert_eq!(AmountSpec::raw(7).to_raw(9).unwrap(), RawAmount(7));
+        assert_eq!(
+            AmountSpec::from(RawAmount(u64::MAX)).to_raw(0).unwrap(),
+            RawAmount(u64::MAX)
+        );
+        assert_eq!(
+            AmountSpec::from(ui("2.5")).to_raw(6).unwrap(),
+            RawAmount(2_500_000)
+        );
+        assert!(AmountSpec::from(ui("2.5")).to_raw(0).is_err());
+        assert_eq!(
+            serde_json::to_value(AmountSpec::raw(7)).unwrap(),
+            serde_json::json!(7)
+        );
+        assert_eq!(
+            serde_json::to_value(AmountSpec::from(ui("2.50"))).unwrap(),
+            serde_json::json!("2.5")
+        );
+    }
+
+    #[test]
+    fn explicit_constructors_state_the_unit() {
+        assert_eq!(AmountSpec::raw(7), AmountSpec::Raw(RawAmount(7)));
+        assert_eq!(AmountSpec::ui("2.50").unwrap(), AmountSpec::Ui(ui("2.5")));
+        assert!(AmountSpec::ui("2,5").is_err());
+        assert!(AmountSpec::ui("-1").is_err());
+        // The deprecated bare number still means base units
+        #[cfg(feature = "u64-amounts")]
+        assert_eq!(AmountSpec::from(7u64), AmountSpec::raw(7));
+    }
+
+    #[test]
+    fn formatted_amounts_parse_back() {
+        let options = FormatOptions::for_locale("de-DE");
+        for (amount, decimals) in [(0, 0), (1, 9), (1_234_500, 3), (u64::MAX, 9), (u64::MAX, 0)] {
+            let formatted = format_token_amount(amount, decimals, &options);
+            assert_eq!(
+                parse_token_amount(&formatted, decimals, &options).unwrap(),
+                amount,
+                "{formatted}"
+            );
+        }
+        assert_eq!(format_token_amount(1_234_500, 3, &options), "1.234,5");
+        let message = error(parse_token_amount("18.446.744.073,709551616", 9, &options));
+        assert!(message.contains("too large"), "{message}");
+    }
+}
@@ -0,0 +1,184 @@
+//! Per-entry outcome of batch operations
+
+use crate::confirm::DEFAULT_CONFIRMATION_TIMEOUT;
+use crate::error::{IntoSssError, SssError, SssResult};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use solana_sdk::pubkey::Pubkey;
+use std::time::Duration;
+
+/// Transactions signed, sent and confirmed together in throughput mode by default
+pub const DEFAULT_THROUGHPUT_WAVE_SIZE: usize = 256;
+
+/// Options applied to batch operations such as
+/// [`execute_airdrop_with_options`](crate::execute_airdrop_with_options)
+#[derive(Debug, Clone)]
+pub struct BatchOptions {
+    /// Sends transactions without waiting for each to be confirmed
+    ///
+    /// Transactions are signed and sent a wave at a time with preflight disabled and no
+    /// retries by the RPC node, then confirmed together with
+    /// [`confirm_signatures_bulk`](crate::confirm_signatures_bulk). Transactions not found
+    /// yet are rebroadcast until they land, their blockhash expires or the confirmation
+    /// timeout passes. Without preflight, a transaction that would fail is only noticed
+    /// once it landed, and still pays its fee.
+    pub throughput_mode: bool,
+    /// How many transactions a wave holds in throughput mode
+    pub wave_size: usize,
+    /// How long a wave waits for its transactions to be confirmed in throughput mode
+    pub confirmation_timeout: Duration,
+    /// Caller-chosen key recorded with every transaction of the operation
+    ///
+    /// With a dedup store, a batch sent under the key and confirmed is not sent again
+    /// when the operation is replayed, e.g. after a restart. Throughput mode relies on
+    /// the checkpoint alone.
+    pub idempotency_key: Option<String>,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            throughput_mode: false,
+            wave_size: DEFAULT_THROUGHPUT_WAVE_SIZE,
+            confirmation_timeout: DEFAULT_CONFIRMATION_TIMEOUT,
+            idempotency_key: None,
+        }
+    }
+}
+
+/// Outcome of every entry of a batch operation, e.g. every recipient of an airdrop
+///
+/// An entry sent in a transaction together with others shares that transaction's
+/// signature or error.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BatchReport {
+    /// Entries confirmed during this run, with the signature of their transaction
+    pub succeeded: Vec<(Pubkey, String)>,
+    /// Entries that could not be completed during this run, with the reason
+    pub failed: Vec<(Pubkey, SssError)>,
+    /// Entries left alone because there was nothing to do, e.g. completed by a previous run,
+    /// or because they must not be touched, e.g. frozen accounts
+    pub skipped: Vec<Pubkey>,
+    /// Why skipped entries were skipped, for the operations that say so
+    pub skip_reasons: Vec<(Pubkey, String)>,
+    /// Failed entries whose transaction expired without landing, so retrying them cannot
+    /// deliver twice
+    pub never_landed: Vec<Pubkey>,
+    /// Failed entries whose transaction landed but failed on-chain
+    pub landed_failed: Vec<Pubkey>,
+}
+
+impl BatchReport {
+    /// Returns the keys of the failed entries
+    pub fn failed_keys(&self) -> Vec<Pubkey> {
+        self.failed.iter().map(|(key, _)| *key).collect()
+    }
+
+    /// Records a skipped entry and why it was skipped
+    pub(crate) fn skip(&mut self, key: Pubkey, reason: impl Into<String>) {
+        self.skipped.push(key);
+        self.skip_reasons.push((key, reason.into()));
+    }
+
+    /// Records the same error for every entry of a transaction
+    pub(crate) fn fail_all(&mut self, keys: impl IntoIterator<Item = Pubkey>, error: &SssError) {
+        self.failed
+            .extend(keys.into_iter().map(|key| (key, error.clone())));
+    }
+
+    /// Records the same error for every entry of a transaction that was sent
+    ///
+    /// `landed` tells whether the transaction landed and failed, or expired without landing.
+    pub(crate) fn fail_sent(
+        &mut self,
+        keys: impl IntoIterator<Item = Pubkey>,
+        error: &SssError,
+        landed: bool,
+    ) {
+        let keys: Vec<Pubkey> = keys.into_iter().collect();
+        if landed {
+            self.landed_failed.extend(&keys);
+        } else {
+            self.never_landed.extend(&keys);
+        }
+        self.fail_all(keys, error);
+    }
+
+    /// Appends the entries of a report of another part of the same operation
+    pub(crate) fn append(&mut self, other: BatchReport) {
+        self.succeeded.extend(other.succeeded);
+        self.failed.extend(other.failed);
+        self.skipped.extend(other.skipped);
+        self.skip_reasons.extend(other.skip_reasons);
+        self.never_landed.extend(other.never_landed);
+        self.landed_failed.extend(other.landed_failed);
+    }
+
+    /// Re-runs a batch operation for the failed entries only
+    ///
+    /// # Arguments
+    ///
+    /// * `run` - Runs the operation for the given keys, e.g. an airdrop restricted to them
+    ///
+    /// # Returns
+    ///
+    /// The result of `run`, or an empty report without calling it if nothing failed
+    pub fn retry_failed<R: Default>(
+        &self,
+        run: impl FnOnce(&[Pubkey]) -> SssResult<R>,
+    ) -> SssResult<R> {
+        let failed = self.failed_keys();
+        if failed.is_empty() {
+            return Ok(R::default());
+        }
+        run(&failed)
+    }
+
+    /// Serializes the report to JSON
+    ///
+    /// The schema is stable: `succeeded` holds `{"key", "signature"}` objects, `failed`
+    /// holds `{"key", "error": {"kind", "message"}}` objects, where `kind` is one of
+    /// [`SssError::kind`], and `skipped` holds base58 keys. `skip_reasons` holds
+    /// `{"key", "reason"}` objects for the skipped entries with a known reason.
+    /// `never_landed` and `landed_failed` hold the base58 keys of failed entries whose
+    /// transaction was sent.
+    pub fn to_json(&self) -> SssResult<String> {
+        serde_json::to_string_pretty(self).into_sss_error("Failed to serialize batch report")
+    }
+}
+
+impl Serialize for BatchReport {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let succeeded: Vec<_> = self
+            .succeeded
+            .iter()
+            .map(|(key, signature)| {
+                serde_json::json!({ "key": key.to_string(), "signature": signature })
+            })
+            .collect();
+        let failed: Vec<_> = self
+            .failed
+            .iter()
+            .map(|(key, error)| {
+                serde_json::json!({
+                    "key": key.to_string(),
+                    "error": { "kind": error.kind(), "message": error.to_string() },
+                })
+            })
+            .collect();
+        let skip_reasons: Vec<_> = self
+            .skip_reasons
+            .iter()
+            .map(|(key, reason)| serde_json::json!({ "key": key.to_string(), "reason": reason }))
+            .collect();
+        let keys = |keys: &[Pubkey]| keys.iter().map(|key| key.to_string()).collect::<Vec<_>>();
+
+        let mut report = serializer.serialize_struct("BatchReport", 6)?;
+        report.serialize_field("succeeded", &succeeded)?;
+        report.serialize_field("failed", &failed)?;
+        report.serialize_field("skipped", &keys(&self.skipped))?;
+        report.serialize_field("skip_reasons", &skip_reasons)?;
+        report.serialize_field("never_landed", &keys(&self.never_landed))?;
+        report.serialize_field("landed_failed", &keys(&self.landed_failed))?;
+        report.end()
+    }
+}
@@ -0,0 +1,334 @@
+//! Cached access to recent blockhashes
+
+use crate::RPC_CLIENT;
+use crate::cache::{CacheStats, ManagedCache};
+use crate::error::{IntoSssError, SssError, SssResult};
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_sdk::hash::Hash;
+use std::mem::size_of;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Default age after which a cached blockhash is refetched
+///
+/// Blockhashes stay valid for roughly 60 seconds, so half of that leaves ample time
+/// for the transaction to land.
+pub const DEFAULT_BLOCKHASH_MAX_AGE: Duration = Duration::from_secs(30);
+
+/// Thread-safe cache for the latest blockhash
+#[derive(Debug)]
+pub struct BlockhashCache {
+    /// The cached blockhash and the time it was fetched
+    entry: Mutex<Option<(Hash, Instant)>>,
+    /// Age after which the cached blockhash is considered stale
+    max_age: Duration,
+    /// The background refresh thread, if running, and the sender whose drop stops it
+    refresher: Mutex<Option<(Sender<()>, JoinHandle<()>)>>,
+    /// Calls answered from the cache, for [`crate::cache_stats`]
+    hits: AtomicU64,
+    /// Calls that had to fetch a blockhash
+    misses: AtomicU64,
+    /// Blockhashes discarded before reaching their maximum age
+    evictions: AtomicU64,
+}
+
+impl BlockhashCache {
+    /// Creates an empty cache
+    ///
+    /// # Arguments
+    ///
+    /// * `max_age` - Age after which the cached blockhash is refetched
+    pub fn new(max_age: Duration) -> Self {
+        Self {
+            entry: Mutex::new(None),
+            max_age,
+            refresher: Mutex::new(None),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the cached blockhash, fetching a new one if it is missing or stale
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The RPC client used to fetch a new blockhash
+    pub fn get(&self, client: &RpcClient) -> SssResult<Hash> {
+        {
+            let entry = self
+                .entry
+                .lock()
+                .map_err(|e| SssError::RpcError(format!("Blockhash cache poisoned: {}", e)))?;
+            if let Some((blockhash, fetched_at)) = *entry
+                && fetched_at.elapsed() < self.max_age
+            {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(blockhash);
+            }
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        self.refresh(client)
+    }
+
+    /// Fetches a new blockhash and stores it in the cache
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The RPC client used to fetch the blockhash
+    pub fn refresh(&self, client: &RpcClient) -> SssResult<Hash> {
+        let blockhash = client
+            .get_latest_blockhash()
+            .into_sss_error("Failed to get latest blockhash from rpc")?;
+
+        let mut entry = self
+            .entry
+            .lock()
+            .map_err(|e| SssError::RpcError(format!("Blockhash cache poisoned: {}", e)))?;
+        *entry = Some((blockhash, Instant::now()));
+
+        Ok(blockhash)
+    }
+
+    /// Stores a known blockhash as if it had just been fetched
+    ///
+    /// Lets callers inject a blockhash obtained elsewhere, or a fixed one to make signed
+    /// transactions reproducible. It is used until it reaches the maximum age.
+    ///
+    /// # Arguments
+    ///
+    /// * `blockhash` - The blockhash to cache
+    pub fn set(&self, blockhash: Hash) {
+        if let Ok(mut entry) = self.entry.lock() {
+            *entry = Some((blockhash, Instant::now()));
+        }
+    }
+
+    /// Starts a background thread refreshing this cache from a client
+    ///
+    /// Calling this while a refresh thread is already running has no effect.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The RPC client used to fetch blockhashes
+    /// * `interval` - Time between refreshes
+    pub fn start_refresh(&'static self, client: &'static RpcClient, interval: Duration) {
+        let Ok(mut refresher) = self.refresher.lock() else {
+            return;
+        };
+        if refresher.is_some() {
+            return;
+        }
+
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = thread::spawn(move || {
+            loop {
+                // Failures are ignored here; callers fall back to fetching on demand
+                let _ = self.refresh(client);
+                match stopped.recv_timeout(interval) {
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    _ => break,
+                }
+            }
+        });
+        *refresher = Some((stop, thread));
+    }
+
+    /// Stops the background refresh thread, waiting for it to exit
+    ///
+    /// The thread is woken rather than left to finish its sleep, so this returns as soon as
+    /// a refresh in progress completes.
+    pub fn stop_refresh(&self) {
+        let running = self.refresher.lock().ok().and_then(|mut r| r.take());
+        if let Some((stop, thread)) = running {
+            drop(stop);
+            let _ = thread.join();
+        }
+    }
+
+    /// Discards the cached blockhash so the next call fetches a fresh one
+    pub fn invalidate(&self) {
+        if let Ok(mut entry) = self.entry.lock()
+            && entry.take().is_some()
+        {
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl ManagedCache for BlockhashCache {
+    fn stats(&self) -> CacheStats {
+        let entries = self
+            .entry
+            .lock()
+            .map_or(0, |entry| usize::from(entry.is_some()));
+        CacheStats {
+            name: "blockhash",
+            entries,
+            max_entries: 1,
+            ttl_secs: Some(self.max_age.as_secs()),
+            approx_bytes: entries * size_of::<(Hash, Instant)>(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+
+    fn purge_expired(&self) -> u64 {
+        // A stale blockhash is replaced on the next call and holds no memory worth freeing
+        0
+    }
+
+    fn trim_to(&self, target_bytes: usize) -> u64 {
+        if target_bytes > 0 || self.stats().entries == 0 {
+            return 0;
+        }
+        self.invalidate();
+        1
+    }
+}
+
+impl Default for BlockhashCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_BLOCKHASH_MAX_AGE)
+    }
+}
+
+/// Returns the cache holding the blockhashes of a client
+///
+/// Only the global [`RPC_CLIENT`] shares [`crate::BLOCKHASH_CACHE`]. Other clients may
+/// point at another cluster, so their blockhashes are fetched on every call.
+fn cache_for(client: &RpcClient) -> Option<&'static BlockhashCache> {
+    std::ptr::eq(client, &*RPC_CLIENT).then(|| &*crate::BLOCKHASH_CACHE)
+}
+
+/// Returns a recent blockhash for a client, from the cache where it has one
+pub(crate) fn latest_blockhash(client: &RpcClient) -> SssResult<Hash> {
+    match cache_for(client) {
+        Some(cache) => cache.get(client),
+        None => fetch_blockhash(client),
+    }
+}
+
+/// Fetches a new blockhash for a client, replacing any cached one
+pub(crate) fn fresh_blockhash(client: &RpcClient) -> SssResult<Hash> {
+    match cache_for(client) {
+        Some(cache) => {
+            cache.invalidate();
+            cache.refresh(client)
+        }
+        None => fetch_blockhash(client),
+    }
+}
+
+/// Discards the cached blockhash of a client, if it has one
+pub(crate) fn invalidate_blockhash(client: &RpcClient) {
+    if let Some(cache) = cache_for(client) {
+        cache.invalidate();
+    }
+}
+
+/// Fetches the latest blockhash without caching it
+fn fetch_blockhash(client: &RpcClient) -> SssResult<Hash> {
+    client
+        .get_latest_blockhash()
+        .into_sss_error("Failed to get latest blockhash from rpc")
+}
+
+/// Starts a background thread refreshing the global blockhash cache
+///
+/// Calling this while a refresh thread is already running has no effect.
+///
+/// # Arguments
+///
+/// * `interval` - Time between refreshes
+pub fn start_blockhash_refresh(interval: Duration) {
+    crate::BLOCKHASH_CACHE.start_refresh(&RPC_CLIENT, interval);
+}
+
+/// Stops the background refresh thread started by [`start_blockhash_refresh`]
+///
+/// Returns once the thread has exited, so a following start never runs two threads.
+pub fn stop_blockhash_refresh() {
+    crate::BLOCKHASH_CACHE.stop_refresh();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mock_client() -> &'static RpcClient {
+        Box::leak(Box::new(RpcClient::new_mock("succeeds".to_string())))
+    }
+
+    #[test]
+    fn get_caches_until_invalidated() {
+        let client = mock_client();
+        let cache = BlockhashCache::default();
+        let first = cache.get(client).unwrap();
+        assert_eq!(cache.get(client).unwrap(), first);
+        assert_eq!((cache.stats().hits, cache.stats().misses), (1, 1));
+
+        cache.invalidate();
+        cache.get(client).unwrap();
+        assert_eq!(cache.stats().misses, 2);
+        assert_eq!(cache.stats().evictions, 1);
+    }
+
+    #[test]
+    fn stale_entry_is_refetched() {
+        let client = mock_client();
+        let cache = BlockhashCache::new(Duration::ZERO);
+        cache.set(Hash::new_unique());
+        cache.get(client).unwrap();
+        assert_eq!(cache.stats().misses, 1);
+    }
+
+    #[test]
+    fn set_blockhash_is_served() {
+        let client = mock_client();
+        let cache = BlockhashCache::default();
+        let blockhash = Hash::new_unique();
+        cache.set(blockhash);
+        assert_eq!(cache.get(client).unwrap(), blockhash);
+    }
+
+    #[test]
+    fn restart_after_stop_runs_a_single_thread() {
+        let client = mock_client();
+        let cache: &'static BlockhashCache = Box::leak(Box::default());
+        cache.start_refresh(client, Duration::from_secs(3600));
+        let first = cache
+            .refresher
+            .lock()
+            .unwrap()
+            .as_ref()
+            .unwrap()
+            .1
+            .thread()
+            .id();
+
+        // Stopping waits for the thread even though it sleeps for an hour
+        cache.stop_refresh();
+        assert!(cache.refresher.lock().unwrap().is_none());
+
+        cache.start_refresh(client, Duration::from_secs(3600));
+        cache.start_refresh(client, Duration::from_secs(3600));
+        let second = cache
+            .refresher
+            .lock()
+            .unwrap()
+            .as_ref()
+            .unwrap()
+            .1
+            .thread()
+            .id();
+        assert_ne!(first, second);
+        cache.stop_refresh();
+        assert!(cache.stats().entries == 1);
+    }
+}
@@ -0,0 +1,916 @@
+//! Typed builder for token creation
+
+use crate::RPC_CLIENT;
+#[cfg(feature = "http-metadata")]
+use crate::consistency::{ConsistencySeverity, offchain_findings};
+use crate::consumable_policy::ensure_consumable_decimals;
+#[cfg(feature = "http-metadata")]
+use crate::content_cache::fetch_metadata;
+use crate::creators::{CreatorShare, metadata_creators, validate_creators};
+#[cfg(feature = "das")]
+use crate::das::wait_after_write;
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::events::{SssEvent, emit};
+use crate::instructions::{self, CreateV1Params};
+use crate::journal::{JournalOperation, journaled};
+use crate::metrics::track;
+use crate::mint_cache::{cache_has_metadata, metadata_pda};
+use crate::names::{normalize_token_name, normalize_token_symbol};
+use crate::payer::signing_payer;
+use crate::profiles::{profile_setting, with_profile};
+use crate::read_only::ensure_writable;
+use crate::rollback::{CreatedAccount, CreatedAccounts};
+use crate::soulbound::add_soulbound_mint;
+use crate::token::{
+    CreateOptions, MintOptions, RecipientLock, TokenOperationResult, TokenProgram,
+    emit_mint_confirmed, extended_mint_instructions, mint_instructions,
+};
+use crate::transaction::{
+    TxOptions, assert_tx_size, required_signatures, sign_and_send, sign_and_send_reporting_attempt,
+};
+use crate::trusted_mints::add_trusted_mint;
+use mpl_token_metadata::types::{Collection, TokenStandard};
+use mpl_token_metadata::{MAX_NAME_LENGTH, MAX_SYMBOL_LENGTH, MAX_URI_LENGTH};
+use serde_json::{Value, json};
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_sdk::{
+    instruction::Instruction, message::Message, pubkey::Pubkey, signature::Keypair, signer::Signer,
+};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_associated_token_account::instruction::create_associated_token_account_idempotent;
+use spl_token::state::AccountState;
+#[cfg(feature = "das")]
+use std::time::Duration;
+
+/// Maximum seller fee in basis points (100%)
+pub(crate) const MAX_SELLER_FEE_BPS: u16 = 10_000;
+
+/// Placeholder in a metadata URI replaced by the address of the mint it describes
+///
+/// See [`TokenBuilder::uri`].
+pub const MINT_PLACEHOLDER: &str = "{mint}";
+
+/// Builder for creating tokens with named, validated parameters
+#[derive(Debug, Clone)]
+pub struct TokenBuilder {
+    name: String,
+    symbol: String,
+    uri: String,
+    decimals: u8,
+    seller_fee_bps: u16,
+    creators: Vec<CreatorShare>,
+    freeze_authority: Option<Pubkey>,
+    collection: Option<Pubkey>,
+    standard: TokenStandard,
+    options: CreateOptions,
+    profile: Option<String>,
+}
+
+impl Default for TokenBuilder {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            symbol: String::new(),
+            uri: String::new(),
+            decimals: 0,
+            seller_fee_bps: 0,
+            creators: Vec::new(),
+            freeze_authority: None,
+            collection: None,
+            standard: TokenStandard::Fungible,
+            options: CreateOptions::default(),
+            profile: None,
+        }
+    }
+}
+
+impl TokenBuilder {
+    /// Creates a builder for a fungible token with no decimals
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the on-chain name of the token
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Sets the ticker symbol of the token
+    pub fn symbol(mut self, symbol: impl Into<String>) -> Self {
+        self.symbol = symbol.into();
+        self
+    }
+
+    /// Sets the URI pointing to the token's JSON metadata
+    ///
+    /// The URI may be a template containing [`MINT_PLACEHOLDER`], e.g.
+    /// `https://cdn.example.com/tokens/{mint}.json`. Methods generating the mint keypair,
+    /// [`Self::create`] and [`Self::create_and_mint`], replace the placeholder with the
+    /// base58 mint address. Methods taking a caller-supplied mint refuse a template.
+    pub fn uri(mut self, uri: impl Into<String>) -> Self {
+        self.uri = uri.into();
+        self
+    }
+
+    /// Sets the number of decimal places
+    ///
+    /// Creation fails with a `ConfigError` if the decimals break the
+    /// [`ConsumablePolicy`](crate::ConsumablePolicy) in force.
+    pub fn decimals(mut self, decimals: u8) -> Self {
+        self.decimals = decimals;
+        self
+    }
+
+    /// Sets the royalty charged on secondary sales, in basis points
+    pub fn seller_fee_bps(mut self, seller_fee_bps: u16) -> Self {
+        self.seller_fee_bps = seller_fee_bps;
+        self
+    }
+
+    /// Sets the creators sharing the royalty, at most 5 with shares adding up to 100
+    ///
+    /// Without creators, the payer is recorded as the sole, verified creator. Only the
+    /// payer may be marked verified here; other creators confirm their share with
+    /// [`crate::verify_creator`].
+    pub fn creators(mut self, creators: Vec<CreatorShare>) -> Self {
+        self.creators = creators;
+        self
+    }
+
+    /// Hands the freeze authority to another account once the token is created
+    ///
+    /// By default the payer keeps the freeze authority.
+    pub fn freeze_authority(mut self, freeze_authority: Pubkey) -> Self {
+        self.freeze_authority = Some(freeze_authority);
+        self
+    }
+
+    /// Sets the (unverified) collection the token belongs to
+    ///
+    /// Without one, the default collection of the active profile applies, if any.
+    pub fn collection(mut self, collection: Pubkey) -> Self {
+        self.collection = Some(collection);
+        self
+    }
+
+    /// Sets the Metaplex token standard
+    ///
+    /// Only `Fungible` and `FungibleAsset` are currently supported.
+    pub fn standard(mut self, standard: TokenStandard) -> Self {
+        self.standard = standard;
+        self
+    }
+
+    /// Sets the token program owning the new mint
+    pub fn token_program(mut self, token_program: TokenProgram) -> Self {
+        self.options.token_program = token_program;
+        self
+    }
+
+    /// Sets the state new token accounts start in
+    ///
+    /// `Frozen` is only supported for Token-2022 mints.
+    pub fn initial_state(mut self, initial_state: AccountState) -> Self {
+        self.options.initial_state = initial_state;
+        self
+    }
+
+    /// Sets whether holders are prevented from transferring the token
+    ///
+    /// Token-2022 mints get the NonTransferable extension. Legacy mints have every recipient
+    /// account frozen after each mint, so the payer must keep the freeze authority.
+    pub fn soulbound(mut self, soulbound: bool) -> Self {
+        self.options.soulbound = soulbound;
+        self
+    }
+
+    /// Sets whether the name and symbol are normalized before creation
+    ///
+    /// Enabled by default; disable it to write the name and symbol exactly as given.
+    pub fn sanitize_names(mut self, sanitize_names: bool) -> Self {
+        self.options.sanitize_names = sanitize_names;
+        self
+    }
+
+    /// Sets the options for signing and sending the creation transaction
+    pub fn tx_options(mut self, tx: TxOptions) -> Self {
+        self.options.tx = tx;
+        self
+    }
+
+    /// Applies a set of creation options
+    pub fn options(mut self, options: CreateOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Creates the token with a named profile, see [`crate::with_profile`]
+    ///
+    /// The profile's payer signs, and its default collection and platform fee apply.
+    pub fn profile(mut self, name: impl Into<String>) -> Self {
+        self.profile = Some(name.into());
+        self
+    }
+
+    /// Checks the parameters against the limits enforced on-chain
+    ///
+    /// # Errors
+    ///
+    /// Returns a `TokenError` naming the first invalid parameter, including a URI template
+    /// whose [`MINT_PLACEHOLDER`] has not been replaced
+    pub fn validate(&self) -> SssResult<()> {
+        let (name, symbol) = self.display_strings()?;
+        if name.is_empty() {
+            return Err(SssError::TokenError(
+                "Token name must not be empty".to_string(),
+            ));
+        }
+        if name.len() > MAX_NAME_LENGTH {
+            return Err(SssError::TokenError(format!(
+                "Token name is {} bytes, the maximum is {}",
+                name.len(),
+                MAX_NAME_LENGTH
+            )));
+        }
+        if symbol.len() > MAX_SYMBOL_LENGTH {
+            return Err(SssError::TokenError(format!(
+                "Token symbol is {} bytes, the maximum is {}",
+                symbol.len(),
+                MAX_SYMBOL_LENGTH
+            )));
+        }
+        if self.uri.is_empty() {
+            return Err(SssError::TokenError(
+                "Token URI must not be empty".to_string(),
+            ));
+        }
+        if self.uri.contains(MINT_PLACEHOLDER) {
+            return Err(SssError::TokenError(format!(
+                "Token URI {} contains the {} placeholder, which is only replaced when the mint \
+                 keypair is generated, e.g. by create_new_token",
+                self.uri, MINT_PLACEHOLDER
+            )));
+        }
+        if self.uri.len() > MAX_URI_LENGTH {
+            return Err(SssError::TokenError(format!(
+                "Token URI is {} bytes, the maximum is {}",
+                self.uri.len(),
+                MAX_URI_LENGTH
+            )));
+        }
+        if self.seller_fee_bps > MAX_SELLER_FEE_BPS {
+            return Err(SssError::TokenError(format!(
+                "Seller fee of {} basis points exceeds {}",
+                self.seller_fee_bps, MAX_SELLER_FEE_BPS
+            )));
+        }
+        validate_creators(&self.creators)?;
+        if !matches!(
+            self.standard,
+            TokenStandard::Fungible | TokenStandard::FungibleAsset
+        ) {
+            return Err(SssError::TokenError(format!(
+                "Token standard {:?} is not supported",
+                self.standard
+            )));
+        }
+        if self.options.initial_state == AccountState::Frozen
+            && self.options.token_program != TokenProgram::Token2022
+        {
+            return Err(SssError::TokenError(
+                "A frozen default account state requires a Token-2022 mint".to_string(),
+            ));
+        }
+        if self.options.soulbound
+            && self.options.token_program == TokenProgram::Legacy
+            && self.freeze_authority.is_some()
+        {
+            return Err(SssError::TokenError(
+                "A legacy soulbound token needs the payer to keep the freeze authority".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Sets whether the off-chain metadata is checked against the token before creation
+    ///
+    /// When enabled, the document at the URI is fetched and creation fails with a
+    /// `TokenError` if it states decimals other than the token's, see
+    /// [`crate::audit_token_consistency`]. Differing names and symbols are tolerated. A
+    /// document that cannot be fetched, e.g. one not uploaded yet or behind a slow IPFS
+    /// gateway, does not block creation.
+    #[cfg(feature = "http-metadata")]
+    pub fn check_metadata_consistency(mut self, check: bool) -> Self {
+        self.options.check_metadata_consistency = check;
+        self
+    }
+
+    /// Waits up to `timeout` for the new mint to be visible to the DAS indexer
+    ///
+    /// The outcome is reported in [`TokenOperationResult::indexing`].
+    #[cfg(feature = "das")]
+    pub fn wait_for_indexing(mut self, timeout: Duration) -> Self {
+        self.options.wait_for_indexing = Some(timeout);
+        self
+    }
+
+    /// Creates the token with a newly generated mint keypair
+    ///
+    /// # Returns
+    ///
+    /// A tuple containing the transaction signature and the mint public key
+    pub fn create(&self) -> SssResult<(String, Pubkey)> {
+        self.create_detailed()
+            .map(|result| (result.signature, result.mint))
+    }
+
+    /// Creates the token with a newly generated mint keypair and reports the detailed outcome
+    ///
+    /// # Returns
+    ///
+    /// The transaction signature, the mint and the indexing status
+    pub fn create_detailed(&self) -> SssResult<TokenOperationResult> {
+        let mint = Keypair::new();
+        self.with_mint_uri(&mint.pubkey())?
+            .create_with_mint_detailed(&mint)
+    }
+
+    /// Creates the token using the given mint keypair
+    ///
+    /// # Arguments
+    ///
+    /// * `mint` - The keypair for the mint account
+    ///
+    /// # Returns
+    ///
+    /// The transaction signature as a string
+    pub fn create_with_mint(&self, mint: &Keypair) -> SssResult<String> {
+        self.create_with_mint_detailed(mint)
+            .map(|result| result.signature)
+    }
+
+    /// Creates the token using the given mint keypair and reports the detailed outcome
+    ///
+    /// # Arguments
+    ///
+    /// * `mint` - The keypair for the mint account
+    ///
+    /// # Returns
+    ///
+    /// The transaction signature, the mint and the indexing status
+    pub fn create_with_mint_detailed(&self, mint: &Keypair) -> SssResult<TokenOperationResult> {
+        self.create_with_client(&RPC_CLIENT, mint)
+    }
+
+    /// Creates the token like [`Self::create_with_mint_detailed`], sending through the
+    /// given RPC client
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The RPC client used for every read and for sending the transaction
+    /// * `mint` - The keypair for the mint account
+    ///
+    /// # Returns
+    ///
+    /// The transaction signature, the mint and the indexing status
+    pub fn create_with_client(
+        &self,
+        client: &RpcClient,
+        mint: &Keypair,
+    ) -> SssResult<TokenOperationResult> {
+        self.in_profile(|| {
+            ensure_writable()?;
+            self.validate()?;
+            ensure_consumable_decimals(self.decimals)?;
+            #[cfg(feature = "http-metadata")]
+            self.check_offchain_consistency()?;
+            let signature = track(JournalOperation::CreateToken, || {
+                let payer = signing_payer()?;
+                let mut instructions =
+                    self.create_instructions(client, &payer.pubkey(), &mint.pubkey())?;
+                instructions
+                    .extend(self.freeze_authority_instruction(&payer.pubkey(), &mint.pubkey())?);
+                let instructions = self.options.tx.wrap_instructions(instructions);
+                let message = Message::new(&instructions, Some(&payer.pubkey()));
+                assert_tx_size(&message, required_signatures(&message))?;
+                journaled(
+                    JournalOperation::CreateToken,
+                    &self.journal_params(&mint.pubkey(), None),
+                    &self.options.tx,
+                    || {
+                        sign_and_send_reporting_attempt(
+                            client,
+                            message,
+                            &[mint, &payer],
+                            &self.options.tx,
+                        )
+                    },
+                )
+                .map_err(|attempt| partial_create(mint.pubkey(), attempt))
+            })?;
+            emit(SssEvent::TokenCreated {
+                signature: signature.clone(),
+                mint: mint.pubkey(),
+            });
+
+            Ok(self.result(signature, mint.pubkey(), None))
+        })
+    }
+
+    /// Creates the token and mints an initial supply in a single transaction
+    ///
+    /// # Arguments
+    ///
+    /// * `amount` - The amount of tokens to mint
+    /// * `owner` - Optional public key of the token owner. If None, the payer will be used
+    ///
+    /// # Returns
+    ///
+    /// A tuple containing the transaction signature and the mint public key
+    pub fn create_and_mint(
+        &self,
+        amount: u64,
+        owner: Option<Pubkey>,
+    ) -> SssResult<(String, Pubkey)> {
+        self.create_and_mint_detailed(amount, owner)
+            .map(|result| (result.signature, result.mint))
+    }
+
+    /// Creates the token and mints an initial supply, reporting the detailed outcome
+    ///
+    /// # Arguments
+    ///
+    /// * `amount` - The amount of tokens to mint
+    /// * `owner` - Optional public key of the token owner. If None, the payer will be used
+    ///
+    /// # Returns
+    ///
+    /// The transaction signature, the mint and the indexing status
+    pub fn create_and_mint_detailed(
+        &self,
+        amount: u64,
+        owner: Option<Pubkey>,
+    ) -> SssResult<TokenOperationResult> {
+        let mint = Keypair::new();
+        self.with_mint_uri(&mint.pubkey())?
+            .create_and_mint_with_mint(&mint, amount, owner)
+    }
+
+    /// Creates the token with the given mint keypair and mints an initial supply
+    fn create_and_mint_with_mint(
+        &self,
+        mint: &Keypair,
+        amount: u64,
+        owner: Option<Pubkey>,
+    ) -> SssResult<TokenOperationResult> {
+        self.create_and_mint_with_client(&RPC_CLIENT, mint, amount, owner)
+    }
+
+    /// Creates the token and mints an initial supply like [`Self::create_and_mint_detailed`],
+    /// with the given mint keypair and sending through the given RPC client
+    ///
+    /// The creation and the mint share one transaction when they fit. Otherwise the first
+    /// transaction creates the mint, its metadata and the recipient account, and the second
+    /// mints; if the mint fails after the first landed, the error is
+    /// [`SssError::AccountsCreated`] naming the recipient account, whose rent
+    /// [`crate::rollback_created_accounts`] reclaims while it is empty.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The RPC client used for every read and for sending the transactions
+    /// * `mint` - The keypair for the mint account
+    /// * `amount` - The amount of tokens to mint
+    /// * `owner` - Optional public key of the token owner. If None, the payer will be used
+    ///
+    /// # Returns
+    ///
+    /// The signature of the last transaction, the mint and the indexing status
+    pub fn create_and_mint_with_client(
+        &self,
+        client: &RpcClient,
+        mint: &Keypair,
+        amount: u64,
+        owner: Option<Pubkey>,
+    ) -> SssResult<TokenOperationResult> {
+        self.in_profile(|| {
+            ensure_writable()?;
+            self.validate()?;
+            ensure_consumable_decimals(self.decimals)?;
+            #[cfg(feature = "http-metadata")]
+            self.check_offchain_consistency()?;
+            let (signature, owner) = track(JournalOperation::CreateToken, || {
+                let payer = signing_payer()?;
+                let owner = owner.unwrap_or(payer.pubkey());
+
+                let create = self.create_instructions(client, &payer.pubkey(), &mint.pubkey())?;
+                let mint_options = MintOptions {
+                    token_program: self.options.token_program,
+                    ..MintOptions::default()
+                };
+                let mut mint_ixs = mint_instructions(
+                    &payer.pubkey(),
+                    &payer.pubkey(),
+                    &mint.pubkey(),
+                    Some(metadata_pda(&mint.pubkey())),
+                    &owner,
+                    amount,
+                    &mint_options,
+                    self.recipient_lock(),
+                )?;
+                // Hand over the freeze authority last, after the payer froze the new balance
+                mint_ixs
+                    .extend(self.freeze_authority_instruction(&payer.pubkey(), &mint.pubkey())?);
+                mint_ixs.extend(self.options.tx.platform_fee_instruction(&payer.pubkey()));
+
+                let instructions = self
+                    .options
+                    .tx
+                    .wrap_instructions(create.iter().chain(&mint_ixs).cloned().collect());
+                let message = Message::new(&instructions, Some(&payer.pubkey()));
+                let fits = match assert_tx_size(&message, required_signatures(&message)) {
+                    Ok(_) => true,
+                    Err(SssError::TransactionTooLarge { .. }) => false,
+                    Err(e) => return Err(e),
+                };
+                journaled(
+                    JournalOperation::CreateToken,
+                    &self.journal_params(&mint.pubkey(), Some((amount, owner))),
+                    &self.options.tx,
+                    || {
+                        if fits {
+                            sign_and_send_reporting_attempt(
+                                client,
+                                message,
+                                &[mint, &payer],
+                                &self.options.tx,
+                            )
+                            .map_err(|attempt| partial_create(mint.pubkey(), attempt))
+                        } else {
+                            self.send_create_then_mint(
+                                client, &payer, mint, &owner, create, mint_ixs,
+                            )
+                        }
+                    },
+                )
+                .map(|signature| (signature, owner))
+            })?;
+            emit(SssEvent::TokenCreated {
+                signature: signature.clone(),
+                mint: mint.pubkey(),
+            });
+            emit_mint_confirmed(&signature, mint.pubkey(), owner, amount);
+
+            Ok(self.result(
+                signature,
+                mint.pubkey(),
+                self.options.tx.resolved_platform_fee(),
+            ))
+        })
+    }
+
+    /// Sends a creation and its initial mint as two transactions, for when they do not fit
+    /// in one
+    ///
+    /// The recipient account is created with the mint, so the second transaction only
+    /// mints; if it fails, the error carries the account as [`SssError::AccountsCreated`].
+    ///
+    /// # Returns
+    ///
+    /// The signature of the mint transaction
+    fn send_create_then_mint(
+        &self,
+        client: &RpcClient,
+        payer: &Keypair,
+        mint: &Keypair,
+        owner: &Pubkey,
+        mut create: Vec<Instruction>,
+        mint_ixs: Vec<Instruction>,
+    ) -> SssResult<String> {
+        let token_program = self.options.token_program;
+        create.push(create_associated_token_account_idempotent(
+            &payer.pubkey(),
+            owner,
+            &mint.pubkey(),
+            &token_program.id(),
+        ));
+        let create = self.options.tx.wrap_instructions(create);
+        let message = Message::new(&create, Some(&payer.pubkey()));
+        assert_tx_size(&message, required_signatures(&message))?;
+        sign_and_send_reporting_attempt(client, message, &[mint, payer], &self.options.tx)
+            .map_err(|attempt| partial_create(mint.pubkey(), attempt))?;
+
+        let created = CreatedAccounts {
+            funder: payer.pubkey(),
+            accounts: vec![CreatedAccount {
+                address: get_associated_token_address_with_program_id(
+                    owner,
+                    &mint.pubkey(),
+                    &token_program.id(),
+                ),
+                mint: mint.pubkey(),
+                owner: *owner,
+                token_program,
+            }],
+        };
+        let mint_ixs = self.options.tx.wrap_instructions(mint_ixs);
+        let message = Message::new(&mint_ixs, Some(&payer.pubkey()));
+        assert_tx_size(&message, required_signatures(&message))
+            .and_then(|_| sign_and_send(client, message, &[payer], &self.options.tx))
+            .map_err(|e| SssError::AccountsCreated {
+                created,
+                source: Box::new(e),
+            })
+    }
+
+    /// Writes the token's metadata to an existing mint, e.g. one created with the plain
+    /// spl-token program
+    ///
+    /// The decimals and token program must match the mint, and the payer must be its mint
+    /// authority. A [`MINT_PLACEHOLDER`] in the URI is replaced by the mint address.
+    ///
+    /// # Returns
+    ///
+    /// The transaction signature as a string
+    pub(crate) fn attach_to_existing_mint(
+        &self,
+        client: &RpcClient,
+        mint: &Pubkey,
+    ) -> SssResult<String> {
+        let builder = self.with_mint_uri(mint)?;
+        builder.in_profile(|| {
+            builder.validate()?;
+            let payer = signing_payer()?;
+            let ix = builder.metadata_instruction(&payer.pubkey(), mint, false)?;
+            let instructions = builder.options.tx.wrap_instructions(vec![ix]);
+            let message = Message::new(&instructions, Some(&payer.pubkey()));
+            assert_tx_size(&message, required_signatures(&message))?;
+            let signature = journaled(
+                JournalOperation::AttachMetadata,
+                &builder.journal_params(mint, None),
+                &builder.options.tx,
+                || sign_and_send(client, message, &[&payer], &builder.options.tx),
+            )?;
+            cache_has_metadata(*mint);
+            Ok(signature)
+        })
+    }
+
+    /// Builds the instructions creating the token without sending them
+    ///
+    /// # Arguments
+    ///
+    /// * `payer` - The fee payer, which becomes the mint and update authority
+    /// * `mint` - The address of the new mint, which must sign the transaction
+    ///
+    /// # Returns
+    ///
+    /// The instructions, in execution order
+    pub fn build_create_token_ix(
+        &self,
+        payer: &Pubkey,
+        mint: &Pubkey,
+    ) -> SssResult<Vec<Instruction>> {
+        self.validate()?;
+        ensure_consumable_decimals(self.decimals)?;
+        let mut instructions = self.create_instructions(&RPC_CLIENT, payer, mint)?;
+        instructions.extend(self.freeze_authority_instruction(payer, mint)?);
+        Ok(instructions)
+    }
+
+    /// Fails if the off-chain metadata states decimals other than the token's, when enabled
+    ///
+    /// # Errors
+    ///
+    /// Returns a `TokenError` describing the mismatch
+    #[cfg(feature = "http-metadata")]
+    fn check_offchain_consistency(&self) -> SssResult<()> {
+        if !self.options.check_metadata_consistency {
+            return Ok(());
+        }
+        let Ok(offchain) = fetch_metadata(&self.uri) else {
+            return Ok(());
+        };
+        let (name, symbol) = self.display_strings()?;
+        match offchain_findings(&name, &symbol, self.decimals, &offchain)
+            .into_iter()
+            .find(|finding| finding.severity == ConsistencySeverity::Error)
+        {
+            Some(finding) => Err(SssError::TokenError(format!(
+                "Off-chain metadata at {} is inconsistent with the token: {}",
+                self.uri, finding.message
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Runs an operation with the builder's profile selected, if it names one
+    fn in_profile<T>(&self, f: impl FnOnce() -> SssResult<T>) -> SssResult<T> {
+        match &self.profile {
+            Some(name) => with_profile(name, f),
+            None => f(),
+        }
+    }
+
+    /// Returns a copy of the builder with the mint address substituted into a URI template
+    ///
+    /// # Errors
+    ///
+    /// Returns a `TokenError` if the substituted URI is not a URL
+    fn with_mint_uri(&self, mint: &Pubkey) -> SssResult<Self> {
+        if !self.uri.contains(MINT_PLACEHOLDER) {
+            return Ok(self.clone());
+        }
+        let uri = self.uri.replace(MINT_PLACEHOLDER, &mint.to_string());
+        let scheme = uri.split_once("://").map(|(scheme, _)| scheme);
+        let valid_scheme = scheme.is_some_and(|scheme| {
+            scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        });
+        if !valid_scheme || uri.chars().any(|c| c.is_whitespace() || c.is_control()) {
+            return Err(SssError::TokenError(format!(
+                "Token URI template {} does not produce a valid URL: {}",
+                self.uri, uri
+            )));
+        }
+        Ok(Self {
+            uri,
+            ..self.clone()
+        })
+    }
+
+    /// The parameters recorded for a creation in the operation journal
+    fn journal_params(&self, mint: &Pubkey, initial_mint: Option<(u64, Pubkey)>) -> Value {
+        json!({
+            "mint": mint.to_string(),
+            "name": self.name,
+            "symbol": self.symbol,
+            "uri": self.uri,
+            "decimals": self.decimals,
+            "amount": initial_mint.map(|(amount, _)| amount),
+            "owner": initial_mint.map(|(_, owner)| owner.to_string()),
+        })
+    }
+
+    /// Builds the detailed result, waiting for indexing if requested
+    ///
+    /// Mints created by this library are added to the trusted set.
+    fn result(
+        &self,
+        signature: String,
+        mint: Pubkey,
+        platform_fee: Option<(Pubkey, u64)>,
+    ) -> TokenOperationResult {
+        add_trusted_mint(mint);
+        if self.options.soulbound && self.options.token_program == TokenProgram::Legacy {
+            add_soulbound_mint(mint);
+        }
+        TokenOperationResult {
+            signature,
+            mint,
+            #[cfg(feature = "das")]
+            indexing: wait_after_write(mint, self.options.wait_for_indexing),
+            supply_cap_violation: None,
+            platform_fee,
+        }
+    }
+
+    /// Returns the name and symbol as they will be written on-chain
+    fn display_strings(&self) -> SssResult<(String, String)> {
+        if self.options.sanitize_names {
+            Ok((
+                normalize_token_name(&self.name)?,
+                normalize_token_symbol(&self.symbol)?,
+            ))
+        } else {
+            Ok((self.name.clone(), self.symbol.clone()))
+        }
+    }
+
+    /// Builds the instructions creating the mint and its metadata
+    fn create_instructions(
+        &self,
+        client: &RpcClient,
+        payer: &Pubkey,
+        mint: &Pubkey,
+    ) -> SssResult<Vec<Instruction>> {
+        let mut instructions = Vec::new();
+
+        // Frozen-by-default and non-transferable mints need their extensions initialized
+        // before the metadata program sees the mint, so the mint account is created up front
+        if self.options.initial_state == AccountState::Frozen
+            || (self.options.soulbound && self.options.token_program == TokenProgram::Token2022)
+        {
+            instructions.extend(extended_mint_instructions(
+                client,
+                payer,
+                mint,
+                self.decimals,
+                &self.options,
+            )?);
+        }
+
+        instructions.push(self.metadata_instruction(payer, mint, true)?);
+
+        Ok(instructions)
+    }
+
+    /// Builds the `CreateV1` instruction writing the metadata of a mint
+    ///
+    /// With `create_mint` the instruction also creates the mint, which then has to sign;
+    /// otherwise the mint must exist with the payer as its mint authority.
+    fn metadata_instruction(
+        &self,
+        payer: &Pubkey,
+        mint: &Pubkey,
+        create_mint: bool,
+    ) -> SssResult<Instruction> {
+        let (name, symbol) = self.display_strings()?;
+        let collection = match self.collection {
+            Some(collection) => Some(collection),
+            None => profile_setting(|profile| profile.default_collection)?.flatten(),
+        };
+
+        instructions::create_v1(CreateV1Params {
+            metadata: Some(metadata_pda(mint)),
+            master_edition: None,
+            mint: Some(*mint),
+            create_mint,
+            authority: Some(*payer),
+            payer: Some(*payer),
+            update_authority: Some(*payer),
+            name: Some(name),
+            symbol,
+            uri: Some(self.uri.clone()),
+            seller_fee_basis_points: self.seller_fee_bps,
+            token_standard: self.standard,
+            decimals: Some(self.decimals),
+            collection: collection.map(|key| Collection {
+                verified: false,
+                key,
+            }),
+            creators: metadata_creators(&self.creators, payer)?,
+            spl_token_program: self.options.token_program.id(),
+        })
+    }
+
+    /// Returns how the first mint keeps the recipient account frozen
+    ///
+    /// The mint is new, so the recipient account does not exist yet.
+    fn recipient_lock(&self) -> RecipientLock {
+        if self.options.initial_state == AccountState::Frozen {
+            RecipientLock::FrozenByDefault
+        } else if self.options.soulbound && self.options.token_program == TokenProgram::Legacy {
+            RecipientLock::Soulbound {
+                account_exists: false,
+            }
+        } else {
+            RecipientLock::Unlocked
+        }
+    }
+
+    /// Builds the instruction moving the freeze authority away from the payer, if requested
+    fn freeze_authority_instruction(
+        &self,
+        payer: &Pubkey,
+        mint: &Pubkey,
+    ) -> SssResult<Option<Instruction>> {
+        let Some(freeze_authority) = self.freeze_authority else {
+            return Ok(None);
+        };
+        let ix = match self.options.token_program {
+            TokenProgram::Legacy => spl_token::instruction::set_authority(
+                &spl_token::id(),
+                mint,
+                Some(&freeze_authority),
+                spl_token::instruction::AuthorityType::FreezeAccount,
+                payer,
+                &[],
+            ),
+            TokenProgram::Token2022 => spl_token_2022::instruction::set_authority(
+                &spl_token_2022::id(),
+                mint,
+                Some(&freeze_authority),
+                spl_token_2022::instruction::AuthorityType::FreezeAccount,
+                payer,
+                &[],
+            ),
+        }
+        .into_sss_error("Failed to build token set authority instruction")?;
+        Ok(Some(ix))
+    }
+}
+
+/// Wraps a failed creation in `PartialCreate` if the transaction may still have landed
+fn partial_create(mint: Pubkey, (error, signature): (SssError, Option<String>)) -> SssError {
+    match signature {
+        Some(signature) => SssError::PartialCreate {
+            mint,
+            signature,
+            source: Box::new(error),
+        },
+        None => error,
+    }
+}
@@ -0,0 +1,148 @@
+//! Burning tokens held by the payer
+
+use crate::RPC_CLIENT;
+use crate::amount::AmountSpec;
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::in_flight::exclusive;
+use crate::journal::{JournalOperation, journaled};
+use crate::metrics::track;
+use crate::mint_cache::MintInfo;
+use crate::payer::{get_payer_pubkey, signing_payer};
+use crate::read_only::ensure_writable;
+use crate::soulbound::is_soulbound_mint;
+use crate::token::{TokenProgram, ensure_trusted_mint};
+use crate::transaction::{TxOptions, assert_tx_size, required_signatures, sign_and_send};
+use serde_json::json;
+use solana_sdk::{instruction::Instruction, message::Message, pubkey::Pubkey};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_token_2022::extension::StateWithExtensions;
+use spl_token_2022::state::AccountState;
+
+/// Burns tokens of a mint held in the payer's associated token account
+///
+/// Soulbound tokens stay burnable: Token-2022 permits burning NonTransferable tokens, and
+/// the frozen account of a legacy soulbound mint is thawed, burned from and frozen again
+/// in the same transaction, which needs the payer to hold the mint's freeze authority.
+///
+/// # Arguments
+///
+/// * `mint` - The mint of the tokens to burn
+/// * `amount` - The amount to burn, in base units or as a [`UiAmount`](crate::UiAmount)
+///
+/// # Returns
+///
+/// The transaction signature as a string
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the amount is zero, exceeds the payer's balance or has more
+/// decimal places than the mint, or if the payer's account is frozen and cannot be thawed
+/// by the payer
+pub fn burn_tokens(mint: Pubkey, amount: impl Into<AmountSpec>) -> SssResult<String> {
+    ensure_writable()?;
+    ensure_trusted_mint(&mint)?;
+    let amount = amount.into().resolve(&RPC_CLIENT, &mint)?;
+    if amount == 0 {
+        return Err(SssError::TokenError(
+            "Burn amount must be greater than 0".to_string(),
+        ));
+    }
+    let params = json!({
+        "mint": mint.to_string(),
+        "amount": amount,
+    });
+    let tx_options = TxOptions::default();
+    exclusive(JournalOperation::BurnTokens, &params, &tx_options, || {
+        track(JournalOperation::BurnTokens, || {
+            let payer_pubkey = get_payer_pubkey()?;
+            let instructions = burn_instructions(&mint, &payer_pubkey, amount)?;
+            let message = Message::new(&instructions, Some(&payer_pubkey));
+            assert_tx_size(&message, required_signatures(&message))?;
+            let payer = signing_payer()?;
+            journaled(JournalOperation::BurnTokens, &params, &tx_options, || {
+                sign_and_send(&RPC_CLIENT, message, &[&payer], &tx_options)
+            })
+        })
+    })
+}
+
+/// Builds the instruction burning `amount` tokens from the owner's associated token account
+///
+/// Nothing is read from the cluster, so the caller provides the mint's decimals and token
+/// program; [`burn_tokens`] also checks the balance and thaws soulbound accounts.
+///
+/// # Arguments
+///
+/// * `owner` - The wallet holding the tokens, which signs the burn
+/// * `mint` - The public key of the token's mint account
+/// * `amount` - The amount of tokens to burn, in base units
+/// * `decimals` - The number of decimal places of the mint
+/// * `token_program` - The token program owning the mint
+pub fn build_burn_ix(
+    owner: &Pubkey,
+    mint: &Pubkey,
+    amount: u64,
+    decimals: u8,
+    token_program: TokenProgram,
+) -> SssResult<Instruction> {
+    let program_id = token_program.id();
+    let token_account = get_associated_token_address_with_program_id(owner, mint, &program_id);
+    spl_token_2022::instruction::burn_checked(
+        &program_id,
+        &token_account,
+        mint,
+        owner,
+        &[],
+        amount,
+        decimals,
+    )
+    .into_sss_error("Failed to build token burn instruction")
+}
+
+/// Builds the instructions burning from the owner's associated token account, thawing
+/// and refreezing the account of a legacy soulbound mint
+fn burn_instructions(mint: &Pubkey, owner: &Pubkey, amount: u64) -> SssResult<Vec<Instruction>> {
+    let mint_account = RPC_CLIENT
+        .get_account(mint)
+        .into_sss_error("Failed to get mint account from rpc")?;
+    let info = MintInfo::from_account(mint, &mint_account)?;
+    let program_id = info.token_program.id();
+    let token_account = get_associated_token_address_with_program_id(owner, mint, &program_id);
+    let account = RPC_CLIENT
+        .get_account_with_commitment(&token_account, RPC_CLIENT.commitment())
+        .into_sss_error("Failed to get token account from rpc")?
+        .value
+        .ok_or_else(|| {
+            SssError::TokenError(format!("Payer holds no token account for mint {}", mint))
+        })?;
+    let state = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account.data)
+        .into_sss_error("Failed to parse token account")?;
+    if amount > state.base.amount {
+        return Err(SssError::TokenError(format!(
+            "Cannot burn {} of mint {}, the payer holds {}",
+            amount, mint, state.base.amount
+        )));
+    }
+
+    let burn = build_burn_ix(owner, mint, amount, info.decimals, info.token_program)?;
+    if state.base.state != AccountState::Frozen {
+        return Ok(vec![burn]);
+    }
+
+    let mint_state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_account.data)
+        .into_sss_error("Failed to parse token mint account")?;
+    let payer_freezes = Option::<Pubkey>::from(mint_state.base.freeze_authority) == Some(*owner);
+    if !is_soulbound_mint(mint, &mint_account)? || !payer_freezes {
+        return Err(SssError::TokenError(format!(
+            "Token account {} of mint {} is frozen, so its tokens cannot be burned",
+            token_account, mint
+        )));
+    }
+    let thaw =
+        spl_token_2022::instruction::thaw_account(&program_id, &token_account, mint, owner, &[])
+            .into_sss_error("Failed to build token thaw instruction")?;
+    let freeze =
+        spl_token_2022::instruction::freeze_account(&program_id, &token_account, mint, owner, &[])
+            .into_sss_error("Failed to build token freeze instruction")?;
+    Ok(vec![thaw, burn, freeze])
+}
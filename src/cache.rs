@@ -0,0 +1,594 @@
+//! Bounded in-memory caches with shared limits, statistics and trimming
+//!
+//! Every in-memory cache of the library is capped in entries and, where its contents can go
+//! stale, expires them after a time to live. A long-running service can read what the caches
+//! hold with [`cache_stats`] and shrink them with [`trim_caches`] when its host reports
+//! memory pressure. Caches on disk, such as the DAS recordings and the content cache, are
+//! pruned with their own functions.
+//!
+//! Limits are read once per cache from `SSS_CACHE_<NAME>_MAX_ENTRIES` and
+//! `SSS_CACHE_<NAME>_TTL_SECS`, with the name in upper case, e.g.
+//! `SSS_CACHE_MINT_INFO_MAX_ENTRIES`. A TTL of 0 disables expiry.
+
+use crate::load_dotenv;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::hash::Hash;
+use std::mem::size_of;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Entry cap of a cache that does not set its own
+pub const DEFAULT_CACHE_MAX_ENTRIES: usize = 10_000;
+
+/// Bookkeeping bytes counted per entry on top of its key and value: the hash table slot,
+/// the recency index and the timestamps
+const ENTRY_OVERHEAD_BYTES: usize = 64;
+
+/// How many entries a cache keeps and for how long
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CacheLimits {
+    /// Entries kept at most; the least recently used is evicted beyond it
+    pub max_entries: usize,
+    /// Age after which an entry is dropped, `None` to keep it until evicted
+    pub ttl: Option<Duration>,
+}
+
+impl CacheLimits {
+    /// Reads the limits of a cache from the environment, falling back to `default`
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The cache name, e.g. `mint_info`
+    /// * `default` - The limits used where no variable is set or a value is invalid
+    pub fn from_env(name: &str, default: CacheLimits) -> Self {
+        load_dotenv();
+        let prefix = format!("SSS_CACHE_{}", name.to_uppercase());
+        let max_entries = env::var(format!("{}_MAX_ENTRIES", prefix))
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default.max_entries);
+        let ttl = match env::var(format!("{}_TTL_SECS", prefix))
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+        {
+            Some(0) => None,
+            Some(secs) => Some(Duration::from_secs(secs)),
+            None => default.ttl,
+        };
+        Self { max_entries, ttl }
+    }
+}
+
+impl Default for CacheLimits {
+    /// [`DEFAULT_CACHE_MAX_ENTRIES`] entries without expiry
+    fn default() -> Self {
+        Self {
+            max_entries: DEFAULT_CACHE_MAX_ENTRIES,
+            ttl: None,
+        }
+    }
+}
+
+/// What a cache holds and how it has been used, see [`cache_stats`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CacheStats {
+    /// The cache name, as used in its environment variables
+    pub name: &'static str,
+    /// Entries currently held, including expired ones not yet dropped
+    pub entries: usize,
+    /// Entries kept at most
+    pub max_entries: usize,
+    /// Seconds after which an entry is dropped, `None` without expiry
+    pub ttl_secs: Option<u64>,
+    /// Estimated memory held by the entries
+    pub approx_bytes: usize,
+    /// Lookups answered from the cache
+    pub hits: u64,
+    /// Lookups that found no entry or an expired one
+    pub misses: u64,
+    /// Entries dropped for the entry cap, their age or a trim
+    pub evictions: u64,
+}
+
+/// What [`trim_caches`] freed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TrimReport {
+    /// Estimated memory held by every cache before trimming
+    pub bytes_before: usize,
+    /// Estimated memory held by every cache after trimming
+    pub bytes_after: usize,
+    /// Entries dropped, expired ones included
+    pub evicted: u64,
+}
+
+/// A cache known to [`cache_stats`] and [`trim_caches`]
+pub(crate) trait ManagedCache: Sync {
+    /// Returns the current statistics
+    fn stats(&self) -> CacheStats;
+
+    /// Drops expired entries, returning how many were dropped
+    fn purge_expired(&self) -> u64;
+
+    /// Evicts least recently used entries until at most `target_bytes` are held, returning
+    /// how many were evicted
+    fn trim_to(&self, target_bytes: usize) -> u64;
+}
+
+/// A cached value with its age and recency
+struct Slot<V> {
+    value: V,
+    stored_at: Instant,
+    /// Position in the recency index, larger for more recent use
+    tick: u64,
+    bytes: usize,
+}
+
+/// The state of a [`BoundedCache`], guarded by one lock so the statistics always match
+/// the entries
+struct Inner<K, V> {
+    entries: HashMap<K, Slot<V>>,
+    /// Keys by the tick of their last use, oldest first
+    recency: BTreeMap<u64, K>,
+    next_tick: u64,
+    bytes: usize,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+impl<K: Eq + Hash + Clone, V> Inner<K, V> {
+    fn remove(&mut self, key: &K) -> Option<Slot<V>> {
+        let slot = self.entries.remove(key)?;
+        self.recency.remove(&slot.tick);
+        self.bytes -= slot.bytes;
+        Some(slot)
+    }
+
+    fn evict_oldest(&mut self) -> bool {
+        let Some((_, key)) = self.recency.pop_first() else {
+            return false;
+        };
+        if let Some(slot) = self.entries.remove(&key) {
+            self.bytes -= slot.bytes;
+        }
+        self.evictions += 1;
+        true
+    }
+
+    fn touch(&mut self, key: &K) {
+        let tick = self.next_tick;
+        if let Some(slot) = self.entries.get_mut(key) {
+            self.recency.remove(&slot.tick);
+            slot.tick = tick;
+            self.recency.insert(tick, key.clone());
+            self.next_tick += 1;
+        }
+    }
+}
+
+/// A thread-safe map capped in entries, evicting the least recently used, with optional
+/// expiry
+pub(crate) struct BoundedCache<K, V> {
+    name: &'static str,
+    limits: CacheLimits,
+    /// Estimated bytes of an entry beyond [`ENTRY_OVERHEAD_BYTES`]
+    weigh: fn(&K, &V) -> usize,
+    inner: Mutex<Inner<K, V>>,
+}
+
+impl<K: Eq + Hash + Clone, V> BoundedCache<K, V> {
+    /// Creates an empty cache with its limits read by [`CacheLimits::from_env`]
+    pub fn new(name: &'static str, default: CacheLimits, weigh: fn(&K, &V) -> usize) -> Self {
+        Self {
+            name,
+            limits: CacheLimits::from_env(name, default),
+            weigh,
+            inner: Mutex::new(Inner {
+                entries: HashMap::new(),
+                recency: BTreeMap::new(),
+                next_tick: 0,
+                bytes: 0,
+                hits: 0,
+                misses: 0,
+                evictions: 0,
+            }),
+        }
+    }
+
+    fn expired(&self, slot: &Slot<V>) -> bool {
+        self.limits
+            .ttl
+            .is_some_and(|ttl| slot.stored_at.elapsed() >= ttl)
+    }
+
+    /// Returns a copy of the value of a key, counting a hit or a miss
+    pub fn get(&self, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        let mut inner = self.inner.lock().ok()?;
+        let value = match inner.entries.get(key) {
+            Some(slot) if self.expired(slot) => {
+                inner.remove(key);
+                inner.evictions += 1;
+                None
+            }
+            Some(slot) => Some(slot.value.clone()),
+            None => None,
+        };
+        if value.is_some() {
+            inner.hits += 1;
+            inner.touch(key);
+        } else {
+            inner.misses += 1;
+        }
+        value
+    }
+
+    /// Returns whether a key is held, counting a hit or a miss
+    pub fn contains(&self, key: &K) -> bool
+    where
+        V: Clone,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Stores a value, evicting the least recently used entries beyond the cap
+    pub fn insert(&self, key: K, value: V) {
+        if self.limits.max_entries == 0 {
+            return;
+        }
+        let Ok(mut inner) = self.inner.lock() else {
+            return;
+        };
+        inner.remove(&key);
+        let bytes =
+            size_of::<K>() + size_of::<V>() + ENTRY_OVERHEAD_BYTES + (self.weigh)(&key, &value);
+        let tick = inner.next_tick;
+        inner.next_tick += 1;
+        inner.recency.insert(tick, key.clone());
+        inner.entries.insert(
+            key,
+            Slot {
+                value,
+                stored_at: Instant::now(),
+                tick,
+                bytes,
+            },
+        );
+        inner.bytes += bytes;
+        while inner.entries.len() > self.limits.max_entries {
+            inner.evict_oldest();
+        }
+    }
+
+    /// Removes every entry, keeping the statistics
+    pub fn clear(&self) {
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.entries.clear();
+            inner.recency.clear();
+            inner.bytes = 0;
+        }
+    }
+}
+
+impl<K, V> ManagedCache for BoundedCache<K, V>
+where
+    K: Eq + Hash + Clone + Send,
+    V: Send,
+{
+    fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock();
+        let (entries, approx_bytes, hits, misses, evictions) = match &inner {
+            Ok(inner) => (
+                inner.entries.len(),
+                inner.bytes,
+                inner.hits,
+                inner.misses,
+                inner.evictions,
+            ),
+            Err(_) => (0, 0, 0, 0, 0),
+        };
+        CacheStats {
+            name: self.name,
+            entries,
+            max_entries: self.limits.max_entries,
+            ttl_secs: self.limits.ttl.map(|ttl| ttl.as_secs()),
+            approx_bytes,
+            hits,
+            misses,
+            evictions,
+        }
+    }
+
+    fn purge_expired(&self) -> u64 {
+        let Some(ttl) = self.limits.ttl else {
+            return 0;
+        };
+        let Ok(mut inner) = self.inner.lock() else {
+            return 0;
+        };
+        let expired: Vec<K> = inner
+            .entries
+            .iter()
+            .filter(|(_, slot)| slot.stored_at.elapsed() >= ttl)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &expired {
+            inner.remove(key);
+        }
+        inner.evictions += expired.len() as u64;
+        expired.len() as u64
+    }
+
+    fn trim_to(&self, target_bytes: usize) -> u64 {
+        let Ok(mut inner) = self.inner.lock() else {
+            return 0;
+        };
+        let mut evicted = 0;
+        while inner.bytes > target_bytes && inner.evict_oldest() {
+            evicted += 1;
+        }
+        evicted
+    }
+}
+
+/// Returns every managed cache
+fn managed_caches() -> Vec<&'static dyn ManagedCache> {
+    vec![
+        &*crate::BLOCKHASH_CACHE,
+        &*crate::mint_cache::MINT_CACHE,
+        &*crate::mint_cache::METADATA_PDAS,
+        &*crate::mint_cache::WITH_METADATA,
+        &*crate::sns::DOMAIN_CACHE,
+        &*crate::sns::PRIMARY_CACHE,
+        &*crate::receipt_print::TOKEN_LABELS,
+    ]
+}
+
+/// Returns the statistics of every in-memory cache
+///
+/// The counters run from the start of the process; the entry counts and sizes are those at
+/// the time of the call. Sizes are estimates of the keys, values and bookkeeping, not of
+/// the allocator's actual usage.
+///
+/// # Returns
+///
+/// One entry per cache: `blockhash`, `mint_info`, `metadata_pda`, `with_metadata`,
+/// `sns_domain`, `sns_primary` and `token_labels`
+pub fn cache_stats() -> Vec<CacheStats> {
+    managed_caches()
+        .into_iter()
+        .map(|cache| cache.stats())
+        .collect()
+}
+
+/// Shrinks the in-memory caches to about `target_bytes` in total
+///
+/// Meant to be called by the host when it detects memory pressure. Expired entries are
+/// dropped first; if the caches still hold more than the target, each is shrunk by the
+/// same fraction, evicting its least recently used entries. A target of 0 empties every
+/// cache. Evicted entries are fetched again when next needed.
+///
+/// # Arguments
+///
+/// * `target_bytes` - The estimated memory the caches may keep, as reported by
+///   [`cache_stats`]
+pub fn trim_caches(target_bytes: usize) -> TrimReport {
+    let caches = managed_caches();
+    let bytes_before = caches.iter().map(|cache| cache.stats().approx_bytes).sum();
+    let mut evicted: u64 = caches.iter().map(|cache| cache.purge_expired()).sum();
+    let sizes: Vec<usize> = caches
+        .iter()
+        .map(|cache| cache.stats().approx_bytes)
+        .collect();
+    let total: usize = sizes.iter().sum();
+    if total > target_bytes {
+        for (cache, size) in caches.iter().zip(sizes) {
+            let share = (size as u128 * target_bytes as u128 / total as u128) as usize;
+            evicted += cache.trim_to(share);
+        }
+    }
+    TrimReport {
+        bytes_before,
+        bytes_after: caches.iter().map(|cache| cache.stats().approx_bytes).sum(),
+        evicted,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    /// Bytes an entry of a `BoundedCache<u64, u64>` weighing nothing is counted for
+    const ENTRY_BYTES: usize = 2 * size_of::<u64>() + ENTRY_OVERHEAD_BYTES;
+
+    fn cache(
+        name: &'static str,
+        max_entries: usize,
+        ttl: Option<Duration>,
+    ) -> BoundedCache<u64, u64> {
+        BoundedCache::new(name, CacheLimits { max_entries, ttl }, |_, _| 0)
+    }
+
+    #[test]
+    fn soak_keeps_the_cap_and_exact_statistics() {
+        const THREADS: u64 = 8;
+        const PER_THREAD: u64 = 250_000;
+        const CAP: usize = 10_000;
+        let cache = Arc::new(cache("test_soak", CAP, None));
+
+        let workers: Vec<_> = (0..THREADS)
+            .map(|thread| {
+                let cache = Arc::clone(&cache);
+                thread::spawn(move || {
+                    let mut hits = 0;
+                    for i in 0..PER_THREAD {
+                        let key = thread * PER_THREAD + i;
+                        cache.insert(key, key);
+                        // Re-reading a recent key hits unless the other threads evicted it
+                        if cache.get(&(key - i % 8)) == Some(key - i % 8) {
+                            hits += 1;
+                        }
+                        assert!(cache.stats().entries <= CAP);
+                    }
+                    hits
+                })
+            })
+            .collect();
+        let hits: u64 = workers.into_iter().map(|w| w.join().unwrap()).sum();
+
+        let inserted = THREADS * PER_THREAD;
+        let stats = cache.stats();
+        assert_eq!(stats.entries, CAP);
+        assert_eq!(stats.approx_bytes, CAP * ENTRY_BYTES);
+        assert_eq!(stats.evictions, inserted - CAP as u64);
+        assert_eq!(stats.hits, hits);
+        assert_eq!(stats.hits + stats.misses, inserted);
+        let inner = cache.inner.lock().unwrap();
+        assert_eq!(inner.recency.len(), CAP);
+        assert!(
+            inner
+                .recency
+                .values()
+                .all(|key| inner.entries.contains_key(key))
+        );
+    }
+
+    #[test]
+    fn least_recently_used_entry_is_evicted() {
+        let cache = cache("test_lru", 3, None);
+        for key in 0..3 {
+            cache.insert(key, key);
+        }
+        assert_eq!(cache.get(&0), Some(0));
+        cache.insert(3, 3);
+        assert!(cache.contains(&0));
+        assert!(!cache.contains(&1));
+        assert!(cache.contains(&2));
+        assert!(cache.contains(&3));
+        assert_eq!(cache.stats().evictions, 1);
+    }
+
+    #[test]
+    fn replacing_a_value_is_not_an_eviction() {
+        let cache = cache("test_replace", 2, None);
+        cache.insert(1, 1);
+        cache.insert(1, 2);
+        assert_eq!(cache.get(&1), Some(2));
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.evictions), (1, 0));
+        assert_eq!(stats.approx_bytes, ENTRY_BYTES);
+    }
+
+    #[test]
+    fn expired_entries_are_dropped_and_counted() {
+        let cache = cache("test_ttl", 100, Some(Duration::from_millis(50)));
+        for key in 0..10 {
+            cache.insert(key, key);
+        }
+        assert_eq!(cache.get(&0), Some(0));
+        thread::sleep(Duration::from_millis(60));
+        cache.insert(10, 10);
+
+        assert_eq!(cache.get(&0), None);
+        assert_eq!(cache.purge_expired(), 9);
+        let stats = cache.stats();
+        assert_eq!(stats.entries, 1);
+        assert_eq!(stats.evictions, 10);
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+        assert_eq!(stats.approx_bytes, ENTRY_BYTES);
+        assert_eq!(cache.get(&10), Some(10));
+    }
+
+    #[test]
+    fn trimming_evicts_the_oldest_down_to_the_target() {
+        let cache = cache("test_trim", 100, None);
+        for key in 0..10 {
+            cache.insert(key, key);
+        }
+        cache.get(&0);
+        assert_eq!(cache.trim_to(4 * ENTRY_BYTES + 1), 6);
+        let stats = cache.stats();
+        assert_eq!(stats.entries, 4);
+        assert!(stats.approx_bytes <= 4 * ENTRY_BYTES + 1);
+        assert!(cache.contains(&0));
+        assert!(!cache.contains(&1));
+
+        assert_eq!(cache.trim_to(0), 4);
+        assert_eq!(cache.stats().entries, 0);
+        assert_eq!(cache.stats().approx_bytes, 0);
+    }
+
+    #[test]
+    fn weighed_bytes_are_counted() {
+        let cache: BoundedCache<u64, String> =
+            BoundedCache::new("test_weigh", CacheLimits::default(), |_, value| value.len());
+        cache.insert(1, "x".repeat(1000));
+        assert_eq!(
+            cache.stats().approx_bytes,
+            size_of::<u64>() + size_of::<String>() + ENTRY_OVERHEAD_BYTES + 1000
+        );
+        cache.clear();
+        assert_eq!(cache.stats().approx_bytes, 0);
+    }
+
+    #[test]
+    fn zero_cap_stores_nothing() {
+        let cache = cache("test_zero", 0, None);
+        cache.insert(1, 1);
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.stats().entries, 0);
+    }
+
+    #[test]
+    fn limits_are_read_from_the_environment() {
+        // The variables are unique to this test, so no other test reads them
+        unsafe {
+            env::set_var("SSS_CACHE_TEST_ENV_MAX_ENTRIES", "7");
+            env::set_var("SSS_CACHE_TEST_ENV_TTL_SECS", "0");
+            env::set_var("SSS_CACHE_TEST_ENV_INVALID_MAX_ENTRIES", "many");
+            env::set_var("SSS_CACHE_TEST_ENV_INVALID_TTL_SECS", "30");
+        }
+        let default = CacheLimits {
+            max_entries: 5,
+            ttl: Some(Duration::from_secs(60)),
+        };
+        assert_eq!(
+            CacheLimits::from_env("test_env", default),
+            CacheLimits {
+                max_entries: 7,
+                ttl: None,
+            }
+        );
+        assert_eq!(
+            CacheLimits::from_env("test_env_invalid", default),
+            CacheLimits {
+                max_entries: 5,
+                ttl: Some(Duration::from_secs(30)),
+            }
+        );
+        assert_eq!(CacheLimits::from_env("test_env_unset", default), default);
+    }
+
+    #[test]
+    fn every_managed_cache_is_reported() {
+        let names: Vec<_> = cache_stats().into_iter().map(|stats| stats.name).collect();
+        assert_eq!(
+            names,
+            [
+                "blockhash",
+                "mint_info",
+                "metadata_pda",
+                "with_metadata",
+                "sns_domain",
+                "sns_primary",
+                "token_labels",
+            ]
+        );
+    }
+}
@@ -0,0 +1,151 @@
+//! Runtime discovery of what this build of the library can do
+//!
+//! Hosts ship against several versions of the library and with different feature sets, so
+//! [`capabilities`] reports which APIs the loaded binary has. Every capability is listed
+//! once, in [`CAPABILITY_FLAGS`]; a feature-gated module registers its flag there, and
+//! `check_features.sh` fails when a Cargo feature has none. The flag names are API: hosts
+//! branch on them, so a name is never renamed or removed, only added. The report also
+//! carries what the signer role of the calling thread may do.
+
+use crate::roles::{RolePermissions, role_permissions};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Every capability flag and whether this build has it
+///
+/// Names are snake_case and stable; the golden capability list in `fixtures/golden` pins
+/// them.
+const CAPABILITY_FLAGS: &[(&str, bool)] = &[
+    // Always compiled
+    ("allocations_csv", true),
+    ("batch", true),
+    ("burn", true),
+    ("cache_control", true),
+    ("callback_queues", true),
+    ("consumable_policy", true),
+    ("display", true),
+    ("in_flight_recovery", true),
+    ("journal", true),
+    ("legacy_migration", true),
+    ("receipt_print", true),
+    ("role_policies", true),
+    ("signed_commands", true),
+    ("structured_signing", true),
+    ("sns", true),
+    ("supply_monitor", true),
+    ("token2022", true),
+    ("transfer", true),
+    ("ui_amounts", true),
+    ("vault", true),
+    ("watch_only", true),
+    // Cargo features
+    ("das", cfg!(feature = "das")),
+    ("dev_tools", cfg!(feature = "dev-tools")),
+    ("env_config", cfg!(feature = "env-config")),
+    ("ffi", cfg!(feature = "ffi")),
+    ("http_metadata", cfg!(feature = "http-metadata")),
+    ("keystore", cfg!(feature = "keystore")),
+    ("metadata_swap", cfg!(feature = "http-metadata")),
+    ("metrics", cfg!(feature = "metrics")),
+    ("mnemonic", cfg!(feature = "mnemonic")),
+    ("u64_amounts", cfg!(feature = "u64-amounts")),
+];
+
+/// What the loaded build of the library supports
+///
+/// Serializes as one flat object, e.g.
+/// `{"version": "0.1.0", "ffi_schema": 2, "role": {...}, "burn": true, "das": false, ...}`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Capabilities {
+    /// The library version
+    pub version: &'static str,
+    /// The JSON schema version written across the FFI boundary, `None` without the `ffi`
+    /// feature
+    pub ffi_schema: Option<i32>,
+    /// What the signer role of the calling thread may do, so hosts can hide the actions it
+    /// may not run
+    pub role: RolePermissions,
+    /// Every capability flag, keyed by name
+    #[serde(flatten)]
+    pub flags: BTreeMap<&'static str, bool>,
+}
+
+impl Capabilities {
+    /// Returns whether the build has a capability; unknown names, such as those of
+    /// capabilities added by later versions, are `false`
+    pub fn has(&self, name: &str) -> bool {
+        self.flags.get(name).copied().unwrap_or(false)
+    }
+}
+
+/// Returns the capabilities of this build of the library
+///
+/// # Returns
+///
+/// The library version, FFI schema version, the permissions of the active role and every
+/// capability flag
+pub fn capabilities() -> Capabilities {
+    #[cfg(feature = "ffi")]
+    let ffi_schema = Some(crate::ffi_schema::FFI_SCHEMA_VERSION);
+    #[cfg(not(feature = "ffi"))]
+    let ffi_schema = None;
+
+    Capabilities {
+        version: env!("CARGO_PKG_VERSION"),
+        ffi_schema,
+        role: role_permissions(),
+        flags: CAPABILITY_FLAGS.iter().copied().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn flag_names_are_unique_snake_case() {
+        let mut names = BTreeSet::new();
+        for (name, _) in CAPABILITY_FLAGS {
+            assert!(names.insert(*name), "{} is listed twice", name);
+            assert!(
+                !name.is_empty()
+                    && name
+                        .bytes()
+                        .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_'),
+                "{} is not snake_case",
+                name
+            );
+            assert!(
+                !["version", "ffi_schema", "role"].contains(name),
+                "{} collides with a field of the report",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn feature_flags_follow_the_build() {
+        let capabilities = capabilities();
+        assert!(capabilities.has("burn"));
+        assert!(capabilities.has("token2022"));
+        assert_eq!(capabilities.has("das"), cfg!(feature = "das"));
+        assert_eq!(capabilities.has("dev_tools"), cfg!(feature = "dev-tools"));
+        assert_eq!(capabilities.has("ffi"), cfg!(feature = "ffi"));
+        assert_eq!(capabilities.ffi_schema.is_some(), cfg!(feature = "ffi"));
+        assert_eq!(capabilities.version, env!("CARGO_PKG_VERSION"));
+        // Capabilities of later versions are unknown to this one
+        assert!(!capabilities.has("teleport"));
+    }
+
+    #[test]
+    fn report_serializes_as_one_flat_object() {
+        let value = serde_json::to_value(capabilities()).unwrap();
+        let object = value.as_object().unwrap();
+        assert_eq!(object.len(), CAPABILITY_FLAGS.len() + 3);
+        for (name, enabled) in CAPABILITY_FLAGS {
+            assert_eq!(object[*name], *enabled, "{}", name);
+        }
+        assert!(object["role"].is_object());
+    }
+}
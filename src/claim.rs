@@ -0,0 +1,395 @@
+//! Gasless claim transactions paid for by the library payer
+
+use crate::error::{SssError, SssResult};
+#[cfg(feature = "http-metadata")]
+use crate::expiry::ensure_not_expired;
+use crate::inspect::{
+    DecodedInstruction, InspectedInstruction, MEMO_PROGRAM_ID, inspect_transaction,
+};
+use crate::journal::{JournalOperation, journaled};
+use crate::metrics::track;
+use crate::mint_cache::mint_info_with_client;
+use crate::payer::{get_payer_pubkey, signing_payer};
+use crate::read_only::ensure_writable;
+use crate::review::BLOCKHASH_REUSE_WINDOW;
+use crate::supply_caps::ensure_within_supply_cap;
+use crate::token::{
+    MintOptions, ensure_trusted_mint, mint_instructions, mint_metadata, recipient_lock,
+};
+use crate::transaction::{TxOptions, assert_tx_size, send_signed, sign_partial};
+use crate::{RPC_CLIENT, load_dotenv};
+use lazy_static::lazy_static;
+use serde_json::json;
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    message::Message,
+    pubkey::Pubkey,
+    signature::Signature,
+    signer::Signer,
+    transaction::Transaction,
+};
+use std::collections::VecDeque;
+use std::env;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+/// Memo attached to claims the claimer has to sign
+const CLAIM_MEMO: &[u8] = b"sss claim";
+
+/// Position of the token owner in the accounts of a token metadata mint instruction
+const MINT_TOKEN_OWNER_INDEX: usize = 1;
+/// Position of the mint in the accounts of a token metadata mint instruction
+const MINT_MINT_INDEX: usize = 5;
+/// Position of the authority in the accounts of a token metadata mint instruction
+const MINT_AUTHORITY_INDEX: usize = 6;
+
+/// Most submitted claims remembered at once
+const SUBMITTED_CLAIMS_CAPACITY: usize = 4096;
+
+lazy_static! {
+    /// Largest amount a submitted claim may mint, seeded from `SSS_CLAIM_MAX_AMOUNT`
+    static ref CLAIM_AMOUNT_CAP: AtomicU64 = {
+        load_dotenv();
+        let cap = env::var("SSS_CLAIM_MAX_AMOUNT")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(u64::MAX);
+        AtomicU64::new(cap)
+    };
+
+    /// Claims submitted recently, to refuse a replay while its blockhash is still valid
+    static ref SUBMITTED_CLAIMS: Mutex<VecDeque<(Signature, Instant)>> =
+        Mutex::new(VecDeque::new());
+}
+
+/// Options applied when building a claim transaction
+#[derive(Debug, Clone, Default)]
+pub struct ClaimOptions {
+    /// Whether the claimer must countersign before the claim can be submitted
+    ///
+    /// When set, a memo signed by the claimer is added, so the claim cannot be
+    /// submitted on the claimer's behalf without their wallet approving it.
+    pub require_claimer_signature: bool,
+    /// Whether a claim of an expired voucher is refused with `AssetExpired`
+    ///
+    /// See [`is_asset_expired`](crate::is_asset_expired). Costs a download of the
+    /// off-chain metadata per claim.
+    #[cfg(feature = "http-metadata")]
+    pub refuse_expired: bool,
+    /// Options for signing the transaction
+    pub tx: TxOptions,
+}
+
+/// Sets the largest amount a submitted claim may mint
+///
+/// # Arguments
+///
+/// * `cap` - The amount cap in base units
+pub fn set_claim_amount_cap(cap: u64) {
+    CLAIM_AMOUNT_CAP.store(cap, Ordering::Relaxed);
+}
+
+/// Builds a claim transaction minting tokens to a wallet that holds no SOL
+///
+/// # Arguments
+///
+/// * `mint` - The public key of the token's mint account
+/// * `claimer` - The wallet receiving the tokens
+/// * `amount` - The amount of tokens to mint
+///
+/// # Returns
+///
+/// The serialized transaction, already signed by the payer
+pub fn build_claim_transaction(mint: Pubkey, claimer: Pubkey, amount: u64) -> SssResult<Vec<u8>> {
+    build_claim_transaction_with_options(mint, claimer, amount, &ClaimOptions::default())
+}
+
+/// Builds a claim transaction with claim options
+///
+/// See [`build_claim_transaction_with_client`].
+///
+/// # Arguments
+///
+/// * `mint` - The public key of the token's mint account
+/// * `claimer` - The wallet receiving the tokens
+/// * `amount` - The amount of tokens to mint
+/// * `options` - Whether the claimer has to sign and transaction options
+///
+/// # Returns
+///
+/// The serialized, partially signed transaction
+pub fn build_claim_transaction_with_options(
+    mint: Pubkey,
+    claimer: Pubkey,
+    amount: u64,
+    options: &ClaimOptions,
+) -> SssResult<Vec<u8>> {
+    build_claim_transaction_with_client(&RPC_CLIENT, mint, claimer, amount, options)
+}
+
+/// Builds a claim transaction through the given RPC client
+///
+/// The payer covers the fee and the rent of the claimer's token account and signs
+/// as both fee payer and mint authority. Any signature of the claimer is left empty
+/// for their wallet to fill in.
+///
+/// # Arguments
+///
+/// * `client` - The RPC client reading the mint and fetching the blockhash
+/// * `mint` - The public key of the token's mint account
+/// * `claimer` - The wallet receiving the tokens
+/// * `amount` - The amount of tokens to mint
+/// * `options` - Whether the claimer has to sign and transaction options
+///
+/// # Returns
+///
+/// The serialized, partially signed transaction
+pub fn build_claim_transaction_with_client(
+    client: &RpcClient,
+    mint: Pubkey,
+    claimer: Pubkey,
+    amount: u64,
+    options: &ClaimOptions,
+) -> SssResult<Vec<u8>> {
+    ensure_writable()?;
+    ensure_trusted_mint(&mint)?;
+    check_amount(amount)?;
+    #[cfg(feature = "http-metadata")]
+    if options.refuse_expired {
+        ensure_not_expired(&mint)?;
+    }
+    ensure_within_supply_cap(client, &mint, amount)?;
+
+    let payer = signing_payer()?;
+
+    let token_program = mint_info_with_client(client, &mint)?.token_program;
+    let lock =
+        recipient_lock(client, &mint, token_program)?.for_recipients(client, &mint, &[claimer])?[0];
+    let mint_options = MintOptions {
+        token_program,
+        ..MintOptions::default()
+    };
+    let metadata = mint_metadata(client, &mint, &mint_options)?;
+    let mut instructions = mint_instructions(
+        &payer.pubkey(),
+        &payer.pubkey(),
+        &mint,
+        metadata,
+        &claimer,
+        amount,
+        &mint_options,
+        lock,
+    )?;
+    if options.require_claimer_signature {
+        instructions.push(Instruction {
+            program_id: MEMO_PROGRAM_ID,
+            accounts: vec![AccountMeta::new_readonly(claimer, true)],
+            data: CLAIM_MEMO.to_vec(),
+        });
+    }
+    instructions.extend(options.tx.platform_fee_instruction(&payer.pubkey()));
+
+    let message = Message::new(&instructions, Some(&payer.pubkey()));
+    assert_tx_size(&message, message.header.num_required_signatures.into())?;
+    // Signed under the mint operation so the role policy applies to the payer's signature
+    let tx = journaled(
+        JournalOperation::MintToken,
+        &json!({
+            "mint": mint.to_string(),
+            "owner": claimer.to_string(),
+            "amount": amount,
+        }),
+        &options.tx,
+        || sign_partial(client, message, &[&payer], &options.tx),
+    )?;
+    bincode::serialize(&tx)
+        .map_err(|e| SssError::TokenError(format!("Failed to serialize claim: {}", e)))
+}
+
+/// Validates a claim transaction and broadcasts it
+///
+/// The transaction is refused unless it is fully signed, paid for by the payer, and
+/// only mints tokens of a trusted mint to the wallet that signed alongside the payer,
+/// within the claim amount cap and the mint's supply cap. Apart from the single mint, only
+/// creating and thawing the recipient's account, memos, compute budget instructions and the
+/// configured platform fee are allowed. A claim already submitted within
+/// [`BLOCKHASH_REUSE_WINDOW`], after which its blockhash has expired, is refused.
+///
+/// # Arguments
+///
+/// * `serialized` - The transaction returned by [`build_claim_transaction`], countersigned
+///   by the claimer if required
+///
+/// # Returns
+///
+/// The transaction signature as a string
+///
+/// # Errors
+///
+/// Returns a `TokenError` describing the first check the transaction fails
+pub fn submit_claim(serialized: &[u8]) -> SssResult<String> {
+    submit_claim_with_client(&RPC_CLIENT, serialized)
+}
+
+/// Validates a claim transaction like [`submit_claim`] and broadcasts it through the given
+/// RPC client
+///
+/// # Arguments
+///
+/// * `client` - The RPC client checking the supply cap and sending the transaction
+/// * `serialized` - The transaction returned by [`build_claim_transaction`], countersigned
+///   by the claimer if required
+///
+/// # Returns
+///
+/// The transaction signature as a string
+pub fn submit_claim_with_client(client: &RpcClient, serialized: &[u8]) -> SssResult<String> {
+    ensure_writable()?;
+    let tx: Transaction = bincode::deserialize(serialized)
+        .map_err(|e| SssError::TokenError(format!("Failed to decode claim: {}", e)))?;
+    validate_claim(client, &tx)?;
+    let signature = tx.signatures[0];
+    claim_submission(&signature)?;
+    let params = json!({ "signature": signature.to_string() });
+    let tx_options = TxOptions::default();
+    let sent = track(JournalOperation::SubmitClaim, || {
+        journaled(JournalOperation::SubmitClaim, &params, &tx_options, || {
+            send_signed(client, &tx)
+        })
+    });
+    // A claim that did not land may be submitted again
+    if sent.is_err() {
+        release_submission(&signature);
+    }
+    sent
+}
+
+/// Records a claim as submitted, refusing one submitted before
+fn claim_submission(signature: &Signature) -> SssResult<()> {
+    let mut submitted = SUBMITTED_CLAIMS
+        .lock()
+        .map_err(|e| SssError::TokenError(format!("Submitted claim cache poisoned: {}", e)))?;
+    while submitted
+        .front()
+        .is_some_and(|(_, at)| at.elapsed() > BLOCKHASH_REUSE_WINDOW)
+        || submitted.len() >= SUBMITTED_CLAIMS_CAPACITY
+    {
+        submitted.pop_front();
+    }
+    if submitted.iter().any(|(claimed, _)| claimed == signature) {
+        return Err(SssError::TokenError(format!(
+            "Claim {} was already submitted",
+            signature
+        )));
+    }
+    submitted.push_back((*signature, Instant::now()));
+    Ok(())
+}
+
+/// Forgets a submission that failed
+fn release_submission(signature: &Signature) {
+    if let Ok(mut submitted) = SUBMITTED_CLAIMS.lock() {
+        submitted.retain(|(claimed, _)| claimed != signature);
+    }
+}
+
+/// Checks that a claim transaction was not altered after the payer signed it
+fn validate_claim(client: &RpcClient, tx: &Transaction) -> SssResult<()> {
+    let payer = get_payer_pubkey()?;
+    let inspection = inspect_transaction(tx);
+
+    if inspection.fee_payer != payer {
+        return Err(SssError::TokenError(format!(
+            "Claim fee payer {} is not the payer",
+            inspection.fee_payer
+        )));
+    }
+    tx.verify()
+        .map_err(|e| SssError::TokenError(format!("Claim signatures are invalid: {}", e)))?;
+
+    let mut recipient = None;
+    for ix in &inspection.instructions {
+        match ix.decoded {
+            DecodedInstruction::MetadataMint { amount } => {
+                if recipient.is_some() {
+                    return Err(SssError::TokenError(
+                        "Claim contains more than one mint instruction".to_string(),
+                    ));
+                }
+                let account = |index: usize| {
+                    ix.accounts.get(index).map(|a| a.pubkey).ok_or_else(|| {
+                        SssError::TokenError("Claim mint instruction is malformed".to_string())
+                    })
+                };
+                if account(MINT_AUTHORITY_INDEX)? != payer {
+                    return Err(SssError::TokenError(
+                        "Claim mint authority is not the payer".to_string(),
+                    ));
+                }
+                let mint = account(MINT_MINT_INDEX)?;
+                ensure_trusted_mint(&mint)?;
+                check_amount(amount)?;
+                ensure_within_supply_cap(client, &mint, amount)?;
+                recipient = Some(account(MINT_TOKEN_OWNER_INDEX)?);
+            }
+            DecodedInstruction::SystemTransfer { lamports } => {
+                check_platform_fee(ix, &payer, lamports)?;
+            }
+            DecodedInstruction::AssociatedTokenCreate { .. }
+            | DecodedInstruction::TokenThaw
+            | DecodedInstruction::Memo(_)
+            | DecodedInstruction::ComputeUnitLimit(_)
+            | DecodedInstruction::ComputeUnitPrice(_) => {}
+            _ => {
+                return Err(SssError::TokenError(format!(
+                    "Claim contains a disallowed instruction: {}",
+                    ix.summary()
+                )));
+            }
+        }
+    }
+    let recipient = recipient
+        .ok_or_else(|| SssError::TokenError("Claim contains no mint instruction".to_string()))?;
+
+    // Nobody but the payer and the recipient may have signed
+    let signers = &tx.message.account_keys[..tx.message.header.num_required_signatures as usize];
+    if let Some(signer) = signers
+        .iter()
+        .find(|signer| **signer != payer && **signer != recipient)
+    {
+        return Err(SssError::TokenError(format!(
+            "Claim is signed by unexpected account {}",
+            signer
+        )));
+    }
+    Ok(())
+}
+
+/// Refuses transfers other than the configured platform fee, paid by the payer
+fn check_platform_fee(ix: &InspectedInstruction, payer: &Pubkey, lamports: u64) -> SssResult<()> {
+    let from = ix.accounts.first().map(|a| a.pubkey);
+    let to = ix.accounts.get(1).map(|a| a.pubkey);
+    match TxOptions::default().resolved_platform_fee() {
+        Some((account, fee)) if from == Some(*payer) && to == Some(account) && lamports == fee => {
+            Ok(())
+        }
+        _ => Err(SssError::TokenError(format!(
+            "Claim contains a transfer of {} lamports that is not the platform fee",
+            lamports
+        ))),
+    }
+}
+
+/// Refuses claim amounts above the configured cap
+fn check_amount(amount: u64) -> SssResult<()> {
+    let cap = CLAIM_AMOUNT_CAP.load(Ordering::Relaxed);
+    if amount > cap {
+        return Err(SssError::TokenError(format!(
+            "Claim amount {} exceeds the cap of {}",
+            amount, cap
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,517 @@
+//! Printable claim links handing a fixed amount of tokens to whoever redeems them
+//!
+//! Creating a link moves the tokens from the payer into an escrow, the associated token
+//! account of a newly generated claim key, and encodes the key's secret with the mint,
+//! amount and expiry into a compact URL-safe payload for a QR code. The payer is approved as
+//! the escrow's delegate and made its close authority, so it can take back tokens that were
+//! never claimed. Redeeming signs with the claim key, moves the tokens to the recipient and
+//! closes the escrow, which invalidates the link.
+//!
+//! The payload is a bearer secret: anyone holding it can move the escrowed tokens, also
+//! after the expiry, which only this library enforces. Reclaim expired links with
+//! [`reclaim_claim_link`].
+
+use crate::RPC_CLIENT;
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::expiry::{cluster_time, fetch_cluster_time};
+use crate::in_flight::exclusive;
+use crate::journal::{JournalOperation, journaled};
+use crate::metrics::track;
+use crate::mint_cache::{MintInfo, mint_info_with_client};
+use crate::payer::{get_payer_pubkey, signing_payer};
+use crate::read_only::ensure_writable;
+use crate::token::ensure_trusted_mint;
+use crate::transaction::{TxOptions, assert_tx_size, required_signatures, sign_and_send};
+use crate::transfer::{
+    fetch_asset, is_programmable, transfer_amount, transfer_checked_instruction,
+};
+use base64::prelude::{BASE64_URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_sdk::{
+    instruction::Instruction,
+    message::Message,
+    pubkey::Pubkey,
+    signature::{Keypair, keypair_from_seed},
+    signer::Signer,
+};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_associated_token_account::instruction::create_associated_token_account_idempotent;
+use spl_token_2022::extension::StateWithExtensions;
+use spl_token_2022::instruction::AuthorityType;
+use std::ptr;
+
+/// Version byte leading every claim link payload
+const PAYLOAD_VERSION: u8 = 1;
+
+/// Length of a decoded payload: version, claim secret, mint, amount and expiry
+const PAYLOAD_LEN: usize = 1 + 32 + 32 + 8 + 8;
+
+/// A funded claim link, see [`create_claim_link`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClaimLink {
+    /// The URL-safe base64 payload to encode in the QR code, e.g. appended to a claim page URL
+    pub payload: String,
+    /// The escrow token account holding the tokens until they are claimed
+    pub escrow: Pubkey,
+    /// The mint of the claimable tokens
+    pub mint: Pubkey,
+    /// The claimable amount in base units
+    pub amount: u64,
+    /// When the link stops being redeemable
+    pub expiry: DateTime<Utc>,
+    /// The signature of the transaction funding the escrow
+    pub signature: String,
+}
+
+/// The contents of a claim link payload
+struct ClaimPayload {
+    claim: Keypair,
+    mint: Pubkey,
+    amount: u64,
+    expiry: DateTime<Utc>,
+}
+
+/// Moves tokens held by the payer into the escrow of a new claim link
+///
+/// See [`create_claim_link_with_client`].
+///
+/// # Arguments
+///
+/// * `mint` - The mint of the tokens to hand out
+/// * `amount` - The amount in base units, exactly 1 for NFTs
+/// * `expiry` - When the link stops being redeemable, compared with the cluster time
+///
+/// # Returns
+///
+/// The link, with the payload to print and the escrow holding the tokens
+pub fn create_claim_link(mint: Pubkey, amount: u64, expiry: DateTime<Utc>) -> SssResult<ClaimLink> {
+    create_claim_link_with_client(&RPC_CLIENT, mint, amount, expiry)
+}
+
+/// Moves tokens held by the payer into the escrow of a new claim link through the given RPC
+/// client
+///
+/// # Arguments
+///
+/// * `client` - The RPC client reading the payer's tokens and sending the transaction
+/// * `mint` - The mint of the tokens to hand out
+/// * `amount` - The amount in base units, exactly 1 for NFTs
+/// * `expiry` - When the link stops being redeemable, compared with the cluster time
+///
+/// # Returns
+///
+/// The link, with the payload to print and the escrow holding the tokens
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the expiry has passed, the payer does not hold enough unfrozen
+/// tokens, the amount is invalid for the asset, or the asset is a programmable NFT, which
+/// can only be held in associated token accounts of its owner
+pub fn create_claim_link_with_client(
+    client: &RpcClient,
+    mint: Pubkey,
+    amount: u64,
+    expiry: DateTime<Utc>,
+) -> SssResult<ClaimLink> {
+    ensure_writable()?;
+    ensure_trusted_mint(&mint)?;
+    let now = now(client)?;
+    if expiry <= now {
+        return Err(SssError::TokenError(format!(
+            "Claim link expiry {} is not after the cluster time {}",
+            expiry, now
+        )));
+    }
+
+    let claim = Keypair::new();
+    let options = TxOptions::default();
+    track(JournalOperation::CreateClaimLink, || {
+        let payer_pubkey = get_payer_pubkey()?;
+        let asset = fetch_asset(client, &mint, &payer_pubkey)?;
+        if is_programmable(asset.standard) {
+            return Err(SssError::TokenError(format!(
+                "Mint {} is a programmable NFT, which cannot be held in a claim link escrow",
+                mint
+            )));
+        }
+        let amount = transfer_amount(&mint, &asset, Some(amount))?;
+        let program_id = asset.info.token_program.id();
+        let escrow =
+            get_associated_token_address_with_program_id(&claim.pubkey(), &mint, &program_id);
+        let params = json!({
+            "mint": mint.to_string(),
+            "amount": amount,
+            "escrow": escrow.to_string(),
+            "expiry": expiry.timestamp(),
+        });
+
+        exclusive(JournalOperation::CreateClaimLink, &params, &options, || {
+            let instructions = vec![
+                create_associated_token_account_idempotent(
+                    &payer_pubkey,
+                    &claim.pubkey(),
+                    &mint,
+                    &program_id,
+                ),
+                transfer_checked_instruction(
+                    &asset.info,
+                    &asset.source,
+                    &mint,
+                    &escrow,
+                    &payer_pubkey,
+                    amount,
+                )?,
+                spl_token_2022::instruction::approve_checked(
+                    &program_id,
+                    &escrow,
+                    &mint,
+                    &payer_pubkey,
+                    &claim.pubkey(),
+                    &[],
+                    amount,
+                    asset.info.decimals,
+                )
+                .into_sss_error("Failed to build token approve instruction")?,
+                spl_token_2022::instruction::set_authority(
+                    &program_id,
+                    &escrow,
+                    Some(&payer_pubkey),
+                    AuthorityType::CloseAccount,
+                    &claim.pubkey(),
+                    &[],
+                )
+                .into_sss_error("Failed to build token set authority instruction")?,
+            ];
+            let message = Message::new(&instructions, Some(&payer_pubkey));
+            assert_tx_size(&message, required_signatures(&message))?;
+            let payer = signing_payer()?;
+            let signature =
+                journaled(JournalOperation::CreateClaimLink, &params, &options, || {
+                    sign_and_send(client, message, &[&payer, &claim], &options)
+                })?;
+
+            Ok(ClaimLink {
+                payload: encode_payload(&claim, &mint, amount, &expiry),
+                escrow,
+                mint,
+                amount,
+                expiry,
+                signature,
+            })
+        })
+    })
+}
+
+/// Redeems a claim link, moving its tokens to the associated account of a wallet
+///
+/// See [`redeem_claim_link_with_client`].
+///
+/// # Arguments
+///
+/// * `payload` - The payload of the link, as printed in the QR code
+/// * `recipient` - The wallet receiving the tokens
+///
+/// # Returns
+///
+/// The transaction signature as a string
+pub fn redeem_claim_link(payload: &str, recipient: Pubkey) -> SssResult<String> {
+    redeem_claim_link_with_client(&RPC_CLIENT, payload, recipient)
+}
+
+/// Redeems a claim link through the given RPC client
+///
+/// The recipient's associated token account is created if missing, and the escrow is
+/// closed in the same transaction, returning its rent to the payer.
+///
+/// # Arguments
+///
+/// * `client` - The RPC client reading the escrow and sending the transaction
+/// * `payload` - The payload of the link, as printed in the QR code
+/// * `recipient` - The wallet receiving the tokens
+///
+/// # Returns
+///
+/// The transaction signature as a string
+///
+/// # Errors
+///
+/// Returns `ClaimLinkRedeemed` if the link was already redeemed or reclaimed,
+/// `ClaimLinkExpired` if its expiry has passed, and a `TokenError` if the payload is not a
+/// claim link, its amount is not the amount in escrow, or the recipient is the claim key
+/// owning the escrow
+pub fn redeem_claim_link_with_client(
+    client: &RpcClient,
+    payload: &str,
+    recipient: Pubkey,
+) -> SssResult<String> {
+    ensure_writable()?;
+    let ClaimPayload {
+        claim,
+        mint,
+        amount,
+        expiry,
+    } = decode_payload(payload)?;
+    ensure_trusted_mint(&mint)?;
+    if recipient == claim.pubkey() {
+        return Err(SssError::TokenError(format!(
+            "Claim link of mint {} cannot be redeemed to its own claim key",
+            mint
+        )));
+    }
+    let options = TxOptions::default();
+    track(JournalOperation::RedeemClaimLink, || {
+        let payer_pubkey = get_payer_pubkey()?;
+        let info = mint_info_with_client(client, &mint)?;
+        let program_id = info.token_program.id();
+        let escrow =
+            get_associated_token_address_with_program_id(&claim.pubkey(), &mint, &program_id);
+        let held = fetch_escrow(client, &escrow)?
+            .filter(|state| state.amount > 0)
+            .ok_or(SssError::ClaimLinkRedeemed { mint, escrow })?;
+        if held.amount != amount {
+            return Err(SssError::TokenError(format!(
+                "Claim link payload amount {} does not match the {} tokens in escrow {}",
+                amount, held.amount, escrow
+            )));
+        }
+        if now(client)? > expiry {
+            return Err(SssError::ClaimLinkExpired { mint, expiry });
+        }
+        let params = json!({
+            "mint": mint.to_string(),
+            "amount": amount,
+            "escrow": escrow.to_string(),
+            "recipient": recipient.to_string(),
+        });
+
+        exclusive(JournalOperation::RedeemClaimLink, &params, &options, || {
+            let destination =
+                get_associated_token_address_with_program_id(&recipient, &mint, &program_id);
+            let mut instructions = vec![create_associated_token_account_idempotent(
+                &payer_pubkey,
+                &recipient,
+                &mint,
+                &program_id,
+            )];
+            instructions.extend(release_instructions(
+                &info,
+                &mint,
+                &escrow,
+                &destination,
+                &claim.pubkey(),
+                &payer_pubkey,
+                held.amount,
+            )?);
+            let message = Message::new(&instructions, Some(&payer_pubkey));
+            assert_tx_size(&message, required_signatures(&message))?;
+            let payer = signing_payer()?;
+            journaled(JournalOperation::RedeemClaimLink, &params, &options, || {
+                sign_and_send(client, message, &[&payer, &claim], &options)
+            })
+        })
+    })
+}
+
+/// Takes the tokens of an unredeemed claim link back to the payer and closes its escrow
+///
+/// See [`reclaim_claim_link_with_client`].
+///
+/// # Arguments
+///
+/// * `escrow` - The escrow of the link, see [`ClaimLink::escrow`]
+///
+/// # Returns
+///
+/// The transaction signature as a string
+pub fn reclaim_claim_link(escrow: Pubkey) -> SssResult<String> {
+    reclaim_claim_link_with_client(&RPC_CLIENT, escrow)
+}
+
+/// Takes the tokens of an unredeemed claim link back to the payer through the given RPC
+/// client
+///
+/// Only the escrow is needed, so links can be reclaimed without keeping their payloads,
+/// e.g. once they expired.
+///
+/// # Arguments
+///
+/// * `client` - The RPC client reading the escrow and sending the transaction
+/// * `escrow` - The escrow of the link, see [`ClaimLink::escrow`]
+///
+/// # Returns
+///
+/// The transaction signature as a string
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the escrow no longer exists, i.e. the link was redeemed or
+/// reclaimed, or if the account is not an escrow the payer can empty
+pub fn reclaim_claim_link_with_client(client: &RpcClient, escrow: Pubkey) -> SssResult<String> {
+    ensure_writable()?;
+    let options = TxOptions::default();
+    track(JournalOperation::ReclaimClaimLink, || {
+        let payer_pubkey = get_payer_pubkey()?;
+        let Some(state) = fetch_escrow(client, &escrow)? else {
+            return Err(SssError::TokenError(format!(
+                "Claim link escrow {} does not exist; it was redeemed, reclaimed or never funded",
+                escrow
+            )));
+        };
+        let mint = state.mint;
+        if Option::<Pubkey>::from(state.close_authority) != Some(payer_pubkey) {
+            return Err(SssError::TokenError(format!(
+                "Token account {} is not a claim link escrow of the payer",
+                escrow
+            )));
+        }
+        let info = mint_info_with_client(client, &mint)?;
+        let program_id = info.token_program.id();
+        let params = json!({
+            "mint": mint.to_string(),
+            "escrow": escrow.to_string(),
+        });
+
+        exclusive(
+            JournalOperation::ReclaimClaimLink,
+            &params,
+            &options,
+            || {
+                let destination =
+                    get_associated_token_address_with_program_id(&payer_pubkey, &mint, &program_id);
+                let mut instructions = vec![create_associated_token_account_idempotent(
+                    &payer_pubkey,
+                    &payer_pubkey,
+                    &mint,
+                    &program_id,
+                )];
+                instructions.extend(release_instructions(
+                    &info,
+                    &mint,
+                    &escrow,
+                    &destination,
+                    &payer_pubkey,
+                    &payer_pubkey,
+                    state.amount,
+                )?);
+                let message = Message::new(&instructions, Some(&payer_pubkey));
+                assert_tx_size(&message, required_signatures(&message))?;
+                let payer = signing_payer()?;
+                journaled(
+                    JournalOperation::ReclaimClaimLink,
+                    &params,
+                    &options,
+                    || sign_and_send(client, message, &[&payer], &options),
+                )
+            },
+        )
+    })
+}
+
+/// Builds the instructions emptying an escrow into a token account and closing it
+///
+/// The authority is the claim key when redeeming and the payer, as delegate, when
+/// reclaiming; the payer closes the escrow as its close authority.
+fn release_instructions(
+    info: &MintInfo,
+    mint: &Pubkey,
+    escrow: &Pubkey,
+    destination: &Pubkey,
+    authority: &Pubkey,
+    payer: &Pubkey,
+    amount: u64,
+) -> SssResult<Vec<Instruction>> {
+    let mut instructions = Vec::new();
+    if amount > 0 {
+        instructions.push(transfer_checked_instruction(
+            info,
+            escrow,
+            mint,
+            destination,
+            authority,
+            amount,
+        )?);
+    }
+    instructions.push(
+        spl_token_2022::instruction::close_account(
+            &info.token_program.id(),
+            escrow,
+            payer,
+            payer,
+            &[],
+        )
+        .into_sss_error("Failed to build token close account instruction")?,
+    );
+    Ok(instructions)
+}
+
+/// Fetches the token account state of an escrow, `None` if it does not exist
+fn fetch_escrow(
+    client: &RpcClient,
+    escrow: &Pubkey,
+) -> SssResult<Option<spl_token_2022::state::Account>> {
+    let account = client
+        .get_multiple_accounts(&[*escrow])
+        .into_sss_error("Failed to get escrow token account from rpc")?
+        .pop()
+        .flatten();
+    account
+        .map(|account| {
+            StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account.data)
+                .map(|state| state.base)
+                .into_sss_error("Failed to parse escrow token account")
+        })
+        .transpose()
+}
+
+/// Returns the cluster time, cached for the global client
+fn now(client: &RpcClient) -> SssResult<DateTime<Utc>> {
+    if ptr::eq(client, &*RPC_CLIENT) {
+        cluster_time()
+    } else {
+        fetch_cluster_time(client)
+    }
+}
+
+/// Encodes the claim secret, mint, amount and expiry as URL-safe base64
+fn encode_payload(claim: &Keypair, mint: &Pubkey, amount: u64, expiry: &DateTime<Utc>) -> String {
+    let mut bytes = Vec::with_capacity(PAYLOAD_LEN);
+    bytes.push(PAYLOAD_VERSION);
+    bytes.extend_from_slice(claim.secret().as_bytes());
+    bytes.extend_from_slice(mint.as_ref());
+    bytes.extend_from_slice(&amount.to_le_bytes());
+    bytes.extend_from_slice(&expiry.timestamp().to_le_bytes());
+    BASE64_URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Decodes a payload produced by [`encode_payload`]
+fn decode_payload(payload: &str) -> SssResult<ClaimPayload> {
+    let invalid =
+        |reason: &str| SssError::TokenError(format!("Invalid claim link payload: {}", reason));
+    let bytes = BASE64_URL_SAFE_NO_PAD
+        .decode(payload.trim())
+        .map_err(|e| invalid(&e.to_string()))?;
+    if bytes.len() != PAYLOAD_LEN {
+        return Err(invalid(&format!(
+            "{} bytes, expected {}",
+            bytes.len(),
+            PAYLOAD_LEN
+        )));
+    }
+    if bytes[0] != PAYLOAD_VERSION {
+        return Err(invalid(&format!("unsupported version {}", bytes[0])));
+    }
+    let claim = keypair_from_seed(&bytes[1..33]).map_err(|e| invalid(&e.to_string()))?;
+    let mint = Pubkey::try_from(&bytes[33..65]).map_err(|e| invalid(&e.to_string()))?;
+    let amount = u64::from_le_bytes(bytes[65..73].try_into().unwrap_or_default());
+    let timestamp = i64::from_le_bytes(bytes[73..81].try_into().unwrap_or_default());
+    let expiry = DateTime::from_timestamp(timestamp, 0)
+        .ok_or_else(|| invalid(&format!("expiry {} is out of range", timestamp)))?;
+    Ok(ClaimPayload {
+        claim,
+        mint,
+        amount,
+        expiry,
+    })
+}
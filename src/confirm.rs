@@ -0,0 +1,326 @@
+//! Waiting for sent transactions to be confirmed
+//!
+//! Every mutation sends its transaction and then hands the signature to a [`Confirmer`],
+//! which polls until the transaction is confirmed, fails, expires or the deadline passes.
+
+use crate::RPC_CLIENT;
+use crate::error::{IntoSssError, SssResult};
+use crate::inspect::describe_instruction_error;
+use crate::retry::classify_client_error;
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_rpc_client_api::request::MAX_GET_SIGNATURE_STATUSES_QUERY_ITEMS;
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    hash::Hash,
+    message::Message,
+    signature::Signature,
+    transaction::{Transaction, TransactionError},
+};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a mutation waits for its transaction to be confirmed
+///
+/// A blockhash stays valid for about 150 slots, so a transaction that is not confirmed by
+/// then has normally expired.
+pub const DEFAULT_CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// How the confirmation of a transaction is observed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfirmationStrategy {
+    /// Polls the signature status at a fixed interval
+    Polling {
+        /// Delay between two status requests
+        interval: Duration,
+    },
+}
+
+impl Default for ConfirmationStrategy {
+    fn default() -> Self {
+        Self::Polling {
+            interval: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Stops a [`Confirmer`] from waiting any longer, e.g. on shutdown
+///
+/// Clones share the same flag, so one can be kept to cancel a wait running elsewhere.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    /// Cancels every wait observing this token
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns whether the token was cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// What became of a sent transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfirmationOutcome {
+    /// The transaction landed successfully at the client's commitment
+    Confirmed {
+        /// The transaction signature
+        signature: Signature,
+    },
+    /// The deadline passed, or the wait was cancelled, before the transaction was
+    /// confirmed; it may still land, so it must not be re-signed yet
+    TimedOut {
+        /// The transaction signature
+        signature: Signature,
+    },
+    /// The transaction failed on chain or expired without landing, so it never will
+    Failed {
+        /// The transaction signature
+        signature: Signature,
+        /// The error of the transaction, `BlockhashNotFound` if it expired
+        error: TransactionError,
+        /// The decoded program error, if a program failed and the message is known
+        program_error: Option<String>,
+    },
+}
+
+/// Status of a transaction found by [`confirm_signatures_bulk`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxStatus {
+    /// The transaction landed successfully at the client's commitment
+    Confirmed,
+    /// The transaction landed but failed, so it never will succeed
+    Failed(TransactionError),
+    /// The transaction was processed but not yet at the client's commitment
+    Processed,
+    /// The cluster does not know the transaction: it has not landed yet, or never will
+    /// once its blockhash has expired
+    NotFound,
+}
+
+impl TxStatus {
+    /// Returns whether the status can no longer change
+    pub fn is_final(&self) -> bool {
+        matches!(self, Self::Confirmed | Self::Failed(_))
+    }
+}
+
+/// Polls the status of many sent transactions until all landed or the deadline passes
+///
+/// Statuses are fetched in requests of up to 256 signatures, so confirming thousands of
+/// transactions costs a few requests per poll instead of one per transaction. RPC errors
+/// are retried at the next poll until the deadline.
+///
+/// # Arguments
+///
+/// * `signatures` - The signatures of the sent transactions
+/// * `deadline` - When to stop polling; the statuses are fetched at least once
+///
+/// # Returns
+///
+/// The last known status of every signature, in the order given. A transaction whose
+/// status could not be fetched is reported as [`TxStatus::NotFound`].
+pub fn confirm_signatures_bulk(signatures: &[Signature], deadline: Instant) -> Vec<TxStatus> {
+    bulk_statuses(&RPC_CLIENT, signatures, deadline)
+}
+
+/// Polls the statuses of many signatures through the given client, see
+/// [`confirm_signatures_bulk`]
+pub(crate) fn bulk_statuses(
+    client: &RpcClient,
+    signatures: &[Signature],
+    deadline: Instant,
+) -> Vec<TxStatus> {
+    let ConfirmationStrategy::Polling { interval } = ConfirmationStrategy::default();
+    let commitment = client.commitment();
+    let mut statuses = vec![TxStatus::NotFound; signatures.len()];
+    loop {
+        let open: Vec<usize> = (0..signatures.len())
+            .filter(|&i| !statuses[i].is_final())
+            .collect();
+        for chunk in open.chunks(MAX_GET_SIGNATURE_STATUSES_QUERY_ITEMS) {
+            let query: Vec<Signature> = chunk.iter().map(|&i| signatures[i]).collect();
+            let Ok(response) = client.get_signature_statuses(&query) else {
+                continue;
+            };
+            for (&i, status) in chunk.iter().zip(response.value) {
+                statuses[i] = match status {
+                    None => TxStatus::NotFound,
+                    Some(status) if !status.satisfies_commitment(commitment) => TxStatus::Processed,
+                    Some(status) => match status.err {
+                        None => TxStatus::Confirmed,
+                        Some(error) => TxStatus::Failed(error),
+                    },
+                };
+            }
+        }
+        let now = Instant::now();
+        if now >= deadline || statuses.iter().all(TxStatus::is_final) {
+            return statuses;
+        }
+        thread::sleep(interval.min(deadline - now));
+    }
+}
+
+/// Waits for sent transactions to be confirmed
+///
+/// Without a blockhash, a transaction that never landed can only time out; with one, it
+/// fails as soon as the blockhash has expired.
+#[derive(Clone, Copy)]
+pub struct Confirmer<'a> {
+    client: &'a RpcClient,
+    blockhash: Option<Hash>,
+    message: Option<&'a Message>,
+}
+
+impl<'a> Confirmer<'a> {
+    /// Creates a confirmer querying the given RPC client
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The RPC client to poll
+    pub fn new(client: &'a RpcClient) -> Self {
+        Self {
+            client,
+            blockhash: None,
+            message: None,
+        }
+    }
+
+    /// Detects expiry from the transaction's blockhash and decodes its program errors
+    ///
+    /// # Arguments
+    ///
+    /// * `tx` - The signed transaction being confirmed
+    pub fn with_transaction(self, tx: &'a Transaction) -> Self {
+        Self {
+            blockhash: Some(tx.message.recent_blockhash),
+            message: Some(&tx.message),
+            ..self
+        }
+    }
+
+    /// Detects expiry from the blockhash the transaction was signed with
+    ///
+    /// # Arguments
+    ///
+    /// * `blockhash` - The recent blockhash of the transaction
+    pub fn with_blockhash(self, blockhash: Hash) -> Self {
+        Self {
+            blockhash: Some(blockhash),
+            ..self
+        }
+    }
+
+    /// Waits until a transaction is confirmed, fails or expires, or the deadline passes
+    ///
+    /// The status is checked at least once, so a deadline in the past makes a single check.
+    /// Transient RPC errors while polling are retried until the deadline.
+    ///
+    /// # Arguments
+    ///
+    /// * `signature` - The signature of the sent transaction
+    /// * `strategy` - How the status is observed
+    /// * `deadline` - When to stop waiting and report [`ConfirmationOutcome::TimedOut`]
+    /// * `cancel` - Stops the wait early, also reporting `TimedOut`
+    ///
+    /// # Errors
+    ///
+    /// Returns an `RpcError` if the status cannot be fetched for a reason that is not
+    /// transient
+    pub fn confirm(
+        &self,
+        signature: &Signature,
+        strategy: &ConfirmationStrategy,
+        deadline: Instant,
+        cancel: &CancelToken,
+    ) -> SssResult<ConfirmationOutcome> {
+        let ConfirmationStrategy::Polling { interval } = *strategy;
+        loop {
+            if let Some(outcome) = self.check(signature)? {
+                return Ok(outcome);
+            }
+            let now = Instant::now();
+            if cancel.is_cancelled() || now >= deadline {
+                return Ok(ConfirmationOutcome::TimedOut {
+                    signature: *signature,
+                });
+            }
+            thread::sleep(interval.min(deadline - now));
+        }
+    }
+
+    /// Checks the status once, `None` while the transaction may still land
+    fn check(&self, signature: &Signature) -> SssResult<Option<ConfirmationOutcome>> {
+        match self.status(signature, self.client.commitment())? {
+            Status::Landed(Ok(())) => {
+                return Ok(Some(ConfirmationOutcome::Confirmed {
+                    signature: *signature,
+                }));
+            }
+            Status::Landed(Err(error)) => return Ok(Some(self.failed(signature, error))),
+            Status::Unknown => return Ok(None),
+            Status::NotFound => {}
+        }
+
+        let Some(blockhash) = &self.blockhash else {
+            return Ok(None);
+        };
+        let still_valid = match self
+            .client
+            .is_blockhash_valid(blockhash, CommitmentConfig::processed())
+        {
+            Ok(valid) => valid,
+            Err(e) if classify_client_error(&e).is_some() => return Ok(None),
+            Err(e) => return Err(e).into_sss_error("Failed to check blockhash validity with rpc"),
+        };
+        if still_valid {
+            return Ok(None);
+        }
+        // A transaction processed just before its blockhash expired may still be confirmed
+        match self.status(signature, CommitmentConfig::processed())? {
+            Status::NotFound => Ok(Some(
+                self.failed(signature, TransactionError::BlockhashNotFound),
+            )),
+            _ => Ok(None),
+        }
+    }
+
+    /// Fetches the status of a transaction at a commitment
+    fn status(&self, signature: &Signature, commitment: CommitmentConfig) -> SssResult<Status> {
+        match self
+            .client
+            .get_signature_status_with_commitment(signature, commitment)
+        {
+            Ok(Some(result)) => Ok(Status::Landed(result)),
+            Ok(None) => Ok(Status::NotFound),
+            Err(e) if classify_client_error(&e).is_some() => Ok(Status::Unknown),
+            Err(e) => Err(e).into_sss_error("Failed to get signature status from rpc"),
+        }
+    }
+
+    /// Builds a `Failed` outcome, decoding the program error if the message is known
+    fn failed(&self, signature: &Signature, error: TransactionError) -> ConfirmationOutcome {
+        ConfirmationOutcome::Failed {
+            signature: *signature,
+            program_error: self
+                .message
+                .and_then(|message| describe_instruction_error(message, &error)),
+            error,
+        }
+    }
+}
+
+/// The status of a transaction at one commitment
+enum Status {
+    /// The transaction was processed, successfully or not
+    Landed(Result<(), TransactionError>),
+    /// The cluster does not know the transaction
+    NotFound,
+    /// The request failed with a transient error
+    Unknown,
+}
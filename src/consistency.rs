@@ -0,0 +1,322 @@
+//! Consistency audit of a token's mint account, metadata account and off-chain metadata
+//!
+//! Wallets take the decimals from the mint account, while some partners read them from the
+//! off-chain JSON. A document disagreeing with the mint makes them display wrong amounts,
+//! so a decimals mismatch is an error; differing names and symbols are warnings.
+
+use crate::RPC_CLIENT;
+#[cfg(feature = "http-metadata")]
+use crate::content_cache::fetch_metadata;
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::mint_cache::metadata_pda;
+#[cfg(feature = "http-metadata")]
+use crate::offchain_metadata::OffchainMetadata;
+use crate::serde_utils::pubkey_string;
+use mpl_token_metadata::accounts::Metadata;
+use serde::Serialize;
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use spl_token_2022::extension::StateWithExtensions;
+
+/// How serious a [`ConsistencyFinding`] is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConsistencySeverity {
+    /// Worth knowing, but nothing is displayed wrongly
+    Info,
+    /// Likely to confuse users, e.g. a name differing between on-chain and off-chain
+    Warning,
+    /// Makes wallets or partners display wrong amounts, or hides the token's metadata
+    Error,
+}
+
+/// A mismatch or gap found by [`audit_token_consistency`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConsistencyFinding {
+    /// The field concerned, e.g. `decimals`, `name` or `offchain_metadata`
+    pub field: String,
+    /// How serious the finding is
+    pub severity: ConsistencySeverity,
+    /// The value on-chain, if the finding compares values
+    pub on_chain: Option<String>,
+    /// The value in the off-chain metadata, if the finding compares values
+    pub offchain: Option<String>,
+    /// A human-readable description
+    pub message: String,
+}
+
+impl ConsistencyFinding {
+    /// Creates a finding that does not compare values
+    fn new(field: &str, severity: ConsistencySeverity, message: impl Into<String>) -> Self {
+        Self {
+            field: field.to_string(),
+            severity,
+            on_chain: None,
+            offchain: None,
+            message: message.into(),
+        }
+    }
+
+    /// Creates a finding for a value differing between on-chain and off-chain
+    #[cfg(feature = "http-metadata")]
+    fn mismatch(
+        field: &str,
+        severity: ConsistencySeverity,
+        on_chain: String,
+        offchain: String,
+    ) -> Self {
+        Self {
+            message: format!(
+                "The off-chain {} {:?} differs from the on-chain {} {:?}",
+                field, offchain, field, on_chain
+            ),
+            field: field.to_string(),
+            severity,
+            on_chain: Some(on_chain),
+            offchain: Some(offchain),
+        }
+    }
+}
+
+/// The outcome of [`audit_token_consistency`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConsistencyReport {
+    /// The audited mint
+    #[serde(with = "pubkey_string")]
+    pub mint: Pubkey,
+    /// The decimals of the mint account
+    pub decimals: u8,
+    /// The raw supply of the mint account
+    pub supply: u64,
+    /// The name in the metadata account, if there is one
+    pub name: Option<String>,
+    /// The symbol in the metadata account, if there is one
+    pub symbol: Option<String>,
+    /// The URI in the metadata account, if there is one
+    pub uri: Option<String>,
+    /// Whether the off-chain metadata was fetched and compared
+    pub offchain_checked: bool,
+    /// The mismatches and gaps found, most severe first
+    pub findings: Vec<ConsistencyFinding>,
+}
+
+impl ConsistencyReport {
+    /// Returns the most severe finding's severity, `None` if nothing was found
+    pub fn max_severity(&self) -> Option<ConsistencySeverity> {
+        self.findings.iter().map(|finding| finding.severity).max()
+    }
+
+    /// Returns whether no finding is an error
+    pub fn is_consistent(&self) -> bool {
+        self.max_severity() < Some(ConsistencySeverity::Error)
+    }
+
+    /// Serializes the report to JSON
+    pub fn to_json(&self) -> SssResult<String> {
+        serde_json::to_string_pretty(self).into_sss_error("Failed to serialize consistency report")
+    }
+}
+
+/// Compares a token's mint account, metadata account and off-chain metadata
+///
+/// The off-chain metadata is fetched with the timeout, size cap and IPFS gateways of the
+/// content cache, and is only compared when the library is built with the `http-metadata`
+/// feature. A document that cannot be fetched in time, e.g. from a slow IPFS gateway, is
+/// reported as a warning rather than failing the audit.
+///
+/// # Arguments
+///
+/// * `mint` - The mint to audit
+///
+/// # Returns
+///
+/// The report, listing each mismatch with its severity
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the mint account does not exist or is not a token mint, and
+/// an `RpcError` if the accounts cannot be read
+pub fn audit_token_consistency(mint: Pubkey) -> SssResult<ConsistencyReport> {
+    audit_token_consistency_with_client(&RPC_CLIENT, mint)
+}
+
+/// Compares a token's accounts and off-chain metadata like [`audit_token_consistency`],
+/// reading the accounts through the given RPC client
+///
+/// # Arguments
+///
+/// * `client` - The RPC client used to read the mint and metadata accounts
+/// * `mint` - The mint to audit
+///
+/// # Returns
+///
+/// The report, listing each mismatch with its severity
+pub fn audit_token_consistency_with_client(
+    client: &RpcClient,
+    mint: Pubkey,
+) -> SssResult<ConsistencyReport> {
+    let accounts = client
+        .get_multiple_accounts(&[mint, metadata_pda(&mint)])
+        .into_sss_error("Failed to get mint and metadata accounts from rpc")?;
+    let mint_account = accounts[0]
+        .as_ref()
+        .ok_or_else(|| SssError::TokenError(format!("Mint {} does not exist", mint)))?;
+    if mint_account.owner != spl_token::id() && mint_account.owner != spl_token_2022::id() {
+        return Err(SssError::TokenError(format!(
+            "Account {} is not a token mint",
+            mint
+        )));
+    }
+    let state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_account.data)
+        .into_sss_error("Failed to parse token mint account")?;
+
+    let mut report = ConsistencyReport {
+        mint,
+        decimals: state.base.decimals,
+        supply: state.base.supply,
+        name: None,
+        symbol: None,
+        uri: None,
+        offchain_checked: false,
+        findings: Vec::new(),
+    };
+    if report.supply == 0 {
+        report.findings.push(ConsistencyFinding::new(
+            "supply",
+            ConsistencySeverity::Info,
+            "No tokens have been minted",
+        ));
+    }
+
+    let metadata = match accounts[1].as_ref().map(|a| Metadata::from_bytes(&a.data)) {
+        Some(Ok(metadata)) => metadata,
+        Some(Err(e)) => {
+            report.findings.push(ConsistencyFinding::new(
+                "metadata_account",
+                ConsistencySeverity::Error,
+                format!("The metadata account cannot be parsed: {}", e),
+            ));
+            return Ok(sorted(report));
+        }
+        None => {
+            report.findings.push(ConsistencyFinding::new(
+                "metadata_account",
+                ConsistencySeverity::Error,
+                "The mint has no metadata account",
+            ));
+            return Ok(sorted(report));
+        }
+    };
+    let name = metadata.name.trim_end_matches('\0').to_string();
+    let symbol = metadata.symbol.trim_end_matches('\0').to_string();
+    let uri = metadata.uri.trim_end_matches('\0').to_string();
+    if metadata.mint != mint {
+        report.findings.push(ConsistencyFinding::new(
+            "metadata_account",
+            ConsistencySeverity::Error,
+            format!("The metadata account describes mint {}", metadata.mint),
+        ));
+    }
+    if name.trim().is_empty() {
+        report.findings.push(ConsistencyFinding::new(
+            "name",
+            ConsistencySeverity::Warning,
+            "The metadata account has an empty name",
+        ));
+    }
+    if uri.trim().is_empty() {
+        report.findings.push(ConsistencyFinding::new(
+            "uri",
+            ConsistencySeverity::Warning,
+            "The metadata account has no URI, so there is no off-chain metadata to compare",
+        ));
+    }
+
+    #[cfg(feature = "http-metadata")]
+    if !uri.trim().is_empty() {
+        match fetch_metadata(&uri) {
+            Ok(offchain) => {
+                report.offchain_checked = true;
+                report.findings.extend(offchain_findings(
+                    &name,
+                    &symbol,
+                    report.decimals,
+                    &offchain,
+                ));
+            }
+            Err(e) => report.findings.push(ConsistencyFinding::new(
+                "offchain_metadata",
+                ConsistencySeverity::Warning,
+                format!("The off-chain metadata could not be fetched: {}", e),
+            )),
+        }
+    }
+
+    report.name = Some(name);
+    report.symbol = Some(symbol);
+    report.uri = Some(uri);
+    Ok(sorted(report))
+}
+
+/// Compares an off-chain metadata document with the on-chain name, symbol and decimals
+///
+/// Fields the document leaves out are not reported, since the standard only requires the
+/// name and symbol and most documents carry no decimals.
+#[cfg(feature = "http-metadata")]
+pub(crate) fn offchain_findings(
+    name: &str,
+    symbol: &str,
+    decimals: u8,
+    offchain: &OffchainMetadata,
+) -> Vec<ConsistencyFinding> {
+    let mut findings = Vec::new();
+    if !offchain.name.is_empty() && offchain.name.trim() != name.trim() {
+        findings.push(ConsistencyFinding::mismatch(
+            "name",
+            ConsistencySeverity::Warning,
+            name.to_string(),
+            offchain.name.clone(),
+        ));
+    }
+    if !offchain.symbol.is_empty() && offchain.symbol.trim() != symbol.trim() {
+        findings.push(ConsistencyFinding::mismatch(
+            "symbol",
+            ConsistencySeverity::Warning,
+            symbol.to_string(),
+            offchain.symbol.clone(),
+        ));
+    }
+    if let Some(value) = offchain.extra.get("decimals") {
+        // Some generators write the decimals as a string
+        let offchain_decimals = value
+            .as_u64()
+            .or_else(|| value.as_str().and_then(|s| s.trim().parse().ok()));
+        match offchain_decimals {
+            Some(offchain_decimals) if offchain_decimals == u64::from(decimals) => {}
+            Some(_) => findings.push(ConsistencyFinding::mismatch(
+                "decimals",
+                ConsistencySeverity::Error,
+                decimals.to_string(),
+                value.to_string(),
+            )),
+            None => findings.push(ConsistencyFinding {
+                on_chain: Some(decimals.to_string()),
+                offchain: Some(value.to_string()),
+                ..ConsistencyFinding::new(
+                    "decimals",
+                    ConsistencySeverity::Error,
+                    format!("The off-chain decimals {} are not a number", value),
+                )
+            }),
+        }
+    }
+    findings
+}
+
+/// Orders the findings of a report most severe first
+fn sorted(mut report: ConsistencyReport) -> ConsistencyReport {
+    report
+        .findings
+        .sort_by_key(|finding| std::cmp::Reverse(finding.severity));
+    report
+}
@@ -0,0 +1,276 @@
+//! Consolidation of the payer's token balances into a treasury wallet
+//!
+//! Partner vaults and other token accounts of the payer accumulate small balances across
+//! many mints. [`consolidate_balances`] moves them to the associated token accounts of a
+//! single wallet, e.g. once a week on a schedule.
+
+use crate::RPC_CLIENT;
+use crate::batch::BatchReport;
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::journal::{JournalOperation, journaled};
+use crate::metrics::track;
+use crate::mint_cache::{MintInfo, mint_info};
+use crate::payer::{get_payer_pubkey, signing_payer};
+use crate::token::ensure_trusted_mint;
+use crate::transaction::{TxOptions, fitting_items, sign_and_send};
+use crate::transfer::transfer_checked_instruction;
+use serde_json::json;
+use solana_rpc_client_api::request::RpcRequest;
+use solana_rpc_client_api::response::{Response, RpcKeyedAccount};
+use solana_sdk::{account::Account, instruction::Instruction, message::Message, pubkey::Pubkey};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_associated_token_account::instruction::create_associated_token_account_idempotent;
+use spl_token_2022::extension::StateWithExtensions;
+use spl_token_2022::state::AccountState;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// A token account of the source owner
+struct SourceAccount {
+    address: Pubkey,
+    mint: Pubkey,
+    amount: u64,
+    frozen: bool,
+    delegate: Option<Pubkey>,
+}
+
+/// A balance to move, with the mint it is of
+struct Consolidation {
+    address: Pubkey,
+    mint: Pubkey,
+    amount: u64,
+    info: MintInfo,
+}
+
+/// Moves the balances of the payer's token accounts to the associated token accounts of a
+/// destination wallet in batched transactions
+///
+/// Every token account of the source, under both token programs, is considered, including
+/// partner vaults, or only the accounts of the given mints. The destination's associated
+/// token accounts are created as needed, at the payer's expense. Balances are moved in full
+/// and the emptied accounts are left open. A consolidation is resumed by running it again,
+/// since emptied accounts fall below any minimum; if a previous run was interrupted while a
+/// batch was in flight, run [`journal_reconcile`](crate::journal_reconcile) first.
+///
+/// # Arguments
+///
+/// * `source_owner` - The owner of the balances, which must be the payer since it signs
+/// * `destination` - The wallet receiving the balances, e.g. a treasury
+/// * `mints` - The mints to consolidate, or `None` for every mint the source holds
+/// * `min_amount` - The smallest raw balance worth moving
+/// * `dry_run` - Report what would be moved without signing or sending anything
+///
+/// # Returns
+///
+/// A report keyed by token account. Frozen accounts, accounts with a delegate and balances
+/// below `min_amount` are skipped with their reason, as is every account to move in a dry
+/// run. Accounts of mints that are not trusted in strict mode fail with a `TokenError`.
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the source owner is not the payer or is the destination, and
+/// an `RpcError` if the accounts cannot be listed
+pub fn consolidate_balances(
+    source_owner: Pubkey,
+    destination: Pubkey,
+    mints: Option<&[Pubkey]>,
+    min_amount: u64,
+    dry_run: bool,
+) -> SssResult<BatchReport> {
+    track(JournalOperation::ConsolidateBalances, || {
+        let payer_pubkey = get_payer_pubkey()?;
+        if source_owner != payer_pubkey {
+            return Err(SssError::TokenError(format!(
+                "Balances of {} cannot be consolidated, only those of the payer {}",
+                source_owner, payer_pubkey
+            )));
+        }
+        if destination == source_owner {
+            return Err(SssError::TokenError(format!(
+                "Consolidation destination {} is the source owner itself",
+                destination
+            )));
+        }
+        // Fails early in read-only mode, before anything is listed
+        let signer = if dry_run {
+            None
+        } else {
+            Some(signing_payer()?)
+        };
+
+        let mut report = BatchReport::default();
+        let mut infos: HashMap<Pubkey, SssResult<MintInfo>> = HashMap::new();
+        let mut pending = Vec::new();
+        for account in source_token_accounts(&source_owner, mints)? {
+            if let Some(reason) = skip_reason(&account, min_amount) {
+                report.skip(account.address, reason);
+                continue;
+            }
+            let info = infos.entry(account.mint).or_insert_with(|| {
+                ensure_trusted_mint(&account.mint).and_then(|_| mint_info(&account.mint))
+            });
+            match info {
+                Ok(info) => pending.push(Consolidation {
+                    address: account.address,
+                    mint: account.mint,
+                    amount: account.amount,
+                    info: *info,
+                }),
+                Err(e) => report.failed.push((account.address, e.clone())),
+            }
+        }
+        let Some(payer) = signer else {
+            for consolidation in &pending {
+                report.skip(
+                    consolidation.address,
+                    format!("dry run, would move {}", consolidation.amount),
+                );
+            }
+            return Ok(report);
+        };
+        // Accounts of the same mint share the instruction creating the destination account
+        pending.sort_by_key(|consolidation| (consolidation.mint, consolidation.address));
+
+        let build = |batch: &[Consolidation]| {
+            let mut instructions: Vec<Instruction> = Vec::new();
+            let mut previous_mint = None;
+            for consolidation in batch {
+                let program = consolidation.info.token_program.id();
+                let destination_account = get_associated_token_address_with_program_id(
+                    &destination,
+                    &consolidation.mint,
+                    &program,
+                );
+                if previous_mint != Some(consolidation.mint) {
+                    instructions.push(create_associated_token_account_idempotent(
+                        &payer_pubkey,
+                        &destination,
+                        &consolidation.mint,
+                        &program,
+                    ));
+                    previous_mint = Some(consolidation.mint);
+                }
+                instructions.push(transfer_checked_instruction(
+                    &consolidation.info,
+                    &consolidation.address,
+                    &consolidation.mint,
+                    &destination_account,
+                    &payer_pubkey,
+                    consolidation.amount,
+                )?);
+            }
+            Ok(Message::new(&instructions, Some(&payer_pubkey)))
+        };
+
+        let tx_options = TxOptions::default();
+        let mut rest = pending.as_slice();
+        while !rest.is_empty() {
+            let count = match fitting_items(rest, 1, build) {
+                Ok(count) => count,
+                Err(e) => {
+                    // Only a single account too large to send fails; the rest still go out
+                    report.failed.push((rest[0].address, e));
+                    rest = &rest[1..];
+                    continue;
+                }
+            };
+            let (batch, tail) = rest.split_at(count);
+            rest = tail;
+            let message = build(batch)?;
+            let params = json!({
+                "destination": destination.to_string(),
+                "token_accounts": batch
+                    .iter()
+                    .map(|consolidation| consolidation.address.to_string())
+                    .collect::<Vec<_>>(),
+            });
+            let keys = batch.iter().map(|consolidation| consolidation.address);
+            match journaled(
+                JournalOperation::ConsolidateBalances,
+                &params,
+                &tx_options,
+                || sign_and_send(&RPC_CLIENT, message, &[&payer], &tx_options),
+            ) {
+                Ok(signature) => report
+                    .succeeded
+                    .extend(keys.map(|key| (key, signature.clone()))),
+                Err(e) => report.fail_all(keys, &e),
+            }
+        }
+        Ok(report)
+    })
+}
+
+/// Returns why the balance of an account must not be moved, or `None` if it may be
+fn skip_reason(account: &SourceAccount, min_amount: u64) -> Option<String> {
+    if account.frozen {
+        return Some("account is frozen".to_string());
+    }
+    if let Some(delegate) = account.delegate {
+        return Some(format!("delegate {} is approved on the account", delegate));
+    }
+    if account.amount == 0 {
+        return Some("account is empty".to_string());
+    }
+    if account.amount < min_amount {
+        return Some(format!(
+            "balance {} is below the minimum {}",
+            account.amount, min_amount
+        ));
+    }
+    None
+}
+
+/// Lists the token accounts of an owner by address, of the given mints or of every mint
+/// under both token programs
+fn source_token_accounts(
+    owner: &Pubkey,
+    mints: Option<&[Pubkey]>,
+) -> SssResult<Vec<SourceAccount>> {
+    let filters: Vec<_> = match mints {
+        Some(mints) => mints
+            .iter()
+            .map(|mint| json!({ "mint": mint.to_string() }))
+            .collect(),
+        None => [spl_token::id(), spl_token_2022::id()]
+            .iter()
+            .map(|program| json!({ "programId": program.to_string() }))
+            .collect(),
+    };
+    let mut accounts = Vec::new();
+    for filter in filters {
+        let response: Response<Vec<RpcKeyedAccount>> = RPC_CLIENT
+            .send(
+                RpcRequest::GetTokenAccountsByOwner,
+                json!([
+                    owner.to_string(),
+                    filter,
+                    { "encoding": "base64", "commitment": RPC_CLIENT.commitment().commitment },
+                ]),
+            )
+            .into_sss_error("Failed to get owner token accounts from rpc")?;
+        for keyed in response.value {
+            let address = Pubkey::from_str(&keyed.pubkey)
+                .into_sss_error("Failed to parse token account address from rpc")?;
+            let Some(account) = keyed.account.decode::<Account>() else {
+                continue;
+            };
+            let Ok(state) =
+                StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account.data)
+            else {
+                continue;
+            };
+            accounts.push(SourceAccount {
+                address,
+                mint: state.base.mint,
+                amount: state.base.amount,
+                frozen: state.base.state == AccountState::Frozen,
+                delegate: state.base.delegate.into(),
+            });
+        }
+    }
+    accounts.sort_by_key(|account| account.address);
+    // A mint listed twice lists its accounts twice
+    accounts.dedup_by_key(|account| account.address);
+    Ok(accounts)
+}
@@ -0,0 +1,236 @@
+//! Decimal policy for consumable tokens
+//!
+//! A consumable stands for whole items, so a token created with decimals lets fractional
+//! quantities into redemptions and breaks downstream accounting. [`ConsumablePolicy`] caps
+//! the decimals of the tokens the library creates, and [`audit_existing_mints`] reports
+//! mints created before the policy was set, or by other tools, that violate it.
+
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::mint_cache::{MintInfo, cache_mint, cached_mint};
+use crate::serde_utils::pubkey_string;
+use crate::{RPC_CLIENT, load_dotenv};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_rpc_client_api::request::MAX_MULTIPLE_ACCOUNTS;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::env;
+use std::sync::RwLock;
+
+lazy_static! {
+    /// The policy in force, seeded from the environment
+    static ref POLICY: RwLock<ConsumablePolicy> = RwLock::new(ConsumablePolicy::from_env());
+}
+
+/// Limits on the decimals of consumable tokens
+///
+/// The default allows any number of decimals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConsumablePolicy {
+    /// Whether tokens must have no decimals, counting whole items only
+    pub force_zero_decimals: bool,
+    /// The most decimals a token may have
+    pub max_decimals: u8,
+}
+
+impl Default for ConsumablePolicy {
+    fn default() -> Self {
+        Self {
+            force_zero_decimals: false,
+            max_decimals: u8::MAX,
+        }
+    }
+}
+
+impl ConsumablePolicy {
+    /// Reads the policy from the environment
+    ///
+    /// `SSS_CONSUMABLE_FORCE_ZERO_DECIMALS` set to `true` or `1` forces zero decimals, and
+    /// `SSS_CONSUMABLE_MAX_DECIMALS` caps them; unset variables keep the defaults.
+    pub fn from_env() -> Self {
+        load_dotenv();
+        let default = Self::default();
+        Self {
+            force_zero_decimals: env::var("SSS_CONSUMABLE_FORCE_ZERO_DECIMALS")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(default.force_zero_decimals),
+            max_decimals: env::var("SSS_CONSUMABLE_MAX_DECIMALS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default.max_decimals),
+        }
+    }
+
+    /// Returns the rule a token with `decimals` decimals breaks, if any
+    ///
+    /// # Arguments
+    ///
+    /// * `decimals` - The decimals of the token
+    ///
+    /// # Returns
+    ///
+    /// The broken rule and the most decimals it allows, or `None` if the token complies
+    pub fn check(&self, decimals: u8) -> Option<(ConsumableRule, u8)> {
+        if self.force_zero_decimals && decimals > 0 {
+            Some((ConsumableRule::ForceZeroDecimals, 0))
+        } else if decimals > self.max_decimals {
+            Some((ConsumableRule::MaxDecimals, self.max_decimals))
+        } else {
+            None
+        }
+    }
+}
+
+/// A rule of the [`ConsumablePolicy`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConsumableRule {
+    /// [`ConsumablePolicy::force_zero_decimals`]
+    ForceZeroDecimals,
+    /// [`ConsumablePolicy::max_decimals`]
+    MaxDecimals,
+}
+
+impl ConsumableRule {
+    /// Returns the policy field and the variable configuring it
+    fn describe(&self) -> &'static str {
+        match self {
+            ConsumableRule::ForceZeroDecimals => {
+                "force_zero_decimals (SSS_CONSUMABLE_FORCE_ZERO_DECIMALS)"
+            }
+            ConsumableRule::MaxDecimals => "max_decimals (SSS_CONSUMABLE_MAX_DECIMALS)",
+        }
+    }
+}
+
+/// An existing mint violating the consumable policy, see [`audit_existing_mints`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PolicyViolation {
+    /// The mint
+    #[serde(with = "pubkey_string")]
+    pub mint: Pubkey,
+    /// The decimals of the mint
+    pub decimals: u8,
+    /// The rule the mint breaks
+    pub rule: ConsumableRule,
+    /// The most decimals the rule allows
+    pub allowed_decimals: u8,
+    /// A human-readable description
+    pub message: String,
+}
+
+/// Sets the consumable policy enforced when creating tokens
+///
+/// # Arguments
+///
+/// * `policy` - The policy, replacing the one read from the environment
+pub fn set_consumable_policy(policy: ConsumablePolicy) {
+    if let Ok(mut current) = POLICY.write() {
+        *current = policy;
+    }
+}
+
+/// Returns the consumable policy in force
+pub fn consumable_policy() -> ConsumablePolicy {
+    POLICY.read().map(|policy| *policy).unwrap_or_default()
+}
+
+/// Fails if the consumable policy refuses a new token with `decimals` decimals
+///
+/// # Errors
+///
+/// Returns a `ConfigError` naming the rule the decimals break
+pub(crate) fn ensure_consumable_decimals(decimals: u8) -> SssResult<()> {
+    match consumable_policy().check(decimals) {
+        Some((rule, allowed)) => Err(SssError::ConfigError(format!(
+            "Consumable policy {} refuses a token with {} decimals; at most {} allowed",
+            rule.describe(),
+            decimals,
+            allowed
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Reports existing mints whose decimals violate the consumable policy in force
+///
+/// Mint properties are read from the mint cache where possible and otherwise fetched in
+/// batches.
+///
+/// # Arguments
+///
+/// * `mints` - The mints to audit
+///
+/// # Returns
+///
+/// One violation per offending mint, in the order given; empty if all comply
+///
+/// # Errors
+///
+/// Returns a `TokenError` if a mint does not exist or is not a token mint, or an
+/// `RpcError` if the accounts cannot be fetched
+pub fn audit_existing_mints(mints: &[Pubkey]) -> SssResult<Vec<PolicyViolation>> {
+    audit_existing_mints_with_client(&RPC_CLIENT, mints)
+}
+
+/// Reports existing mints violating the consumable policy like [`audit_existing_mints`],
+/// fetching uncached mints through the given RPC client
+///
+/// # Arguments
+///
+/// * `client` - The RPC client used to fetch the mints not cached yet
+/// * `mints` - The mints to audit
+///
+/// # Returns
+///
+/// One violation per offending mint, in the order given; empty if all comply
+pub fn audit_existing_mints_with_client(
+    client: &RpcClient,
+    mints: &[Pubkey],
+) -> SssResult<Vec<PolicyViolation>> {
+    let policy = consumable_policy();
+    let mut decimals: HashMap<Pubkey, u8> = mints
+        .iter()
+        .filter_map(|mint| cached_mint(mint).map(|info| (*mint, info.decimals)))
+        .collect();
+    let missing: Vec<Pubkey> = mints
+        .iter()
+        .filter(|mint| !decimals.contains_key(mint))
+        .copied()
+        .collect();
+    for chunk in missing.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let accounts = client
+            .get_multiple_accounts(chunk)
+            .into_sss_error("Failed to get mint accounts from rpc")?;
+        for (mint, account) in chunk.iter().zip(accounts) {
+            let account = account.ok_or_else(|| {
+                SssError::TokenError(format!("Mint account {} does not exist", mint))
+            })?;
+            let info = MintInfo::from_account(mint, &account)?;
+            cache_mint(*mint, info);
+            decimals.insert(*mint, info.decimals);
+        }
+    }
+
+    let mut violations = Vec::new();
+    for mint in mints {
+        let decimals = decimals[mint];
+        if let Some((rule, allowed)) = policy.check(decimals) {
+            violations.push(PolicyViolation {
+                mint: *mint,
+                decimals,
+                rule,
+                allowed_decimals: allowed,
+                message: format!(
+                    "Mint {} has {} decimals, consumable policy {} allows at most {}",
+                    mint,
+                    decimals,
+                    rule.describe(),
+                    allowed
+                ),
+            });
+        }
+    }
+    Ok(violations)
+}
@@ -0,0 +1,902 @@
+//! Local caching of asset images and other off-chain content
+
+use crate::RPC_CLIENT;
+#[cfg(feature = "das")]
+use crate::das::get_asset;
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::mint_cache::metadata_uri;
+use crate::offchain_metadata::{OffchainMetadata, parse_metadata_json};
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_sdk::hash::hash;
+use solana_sdk::pubkey::Pubkey;
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Default IPFS gateway used when `SSS_IPFS_GATEWAYS` is not set
+const DEFAULT_IPFS_GATEWAY: &str = "https://ipfs.io/ipfs/";
+
+/// Default maximum size of a single downloaded file (10 MiB)
+const DEFAULT_MAX_DOWNLOAD_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Default maximum size of a metadata JSON document (1 MiB)
+const DEFAULT_MAX_METADATA_BYTES: u64 = 1024 * 1024;
+
+/// Default maximum nesting of arrays and objects in a metadata JSON document
+const DEFAULT_MAX_JSON_DEPTH: usize = 32;
+
+/// Default timeout for a single download attempt
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Default time limit for a whole fetch, across gateways and the image a document references
+const DEFAULT_FETCH_TIME_LIMIT: Duration = Duration::from_secs(30);
+
+/// Content types accepted for cached images
+const DEFAULT_ALLOWED_CONTENT_TYPES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "image/svg+xml",
+];
+
+/// Content types accepted for metadata documents, besides any `+json` type
+const DEFAULT_METADATA_CONTENT_TYPES: &[&str] = &["application/json", "text/json", "text/plain"];
+
+/// Subdirectory holding the uri -> content file references
+const REFS_DIR: &str = "refs";
+
+/// Configuration for fetching and caching off-chain content
+#[derive(Debug, Clone)]
+pub struct ContentCacheConfig {
+    /// IPFS gateways tried in order for `ipfs://` URIs, each ending with a `/`
+    pub gateways: Vec<String>,
+    /// Maximum size in bytes of a single downloaded file
+    pub max_download_bytes: u64,
+    /// Content types that may be stored in the cache
+    pub allowed_content_types: Vec<String>,
+    /// Maximum size in bytes of a metadata JSON document
+    pub max_metadata_bytes: u64,
+    /// Maximum nesting of arrays and objects in a metadata JSON document
+    pub max_json_depth: usize,
+    /// Content types accepted for metadata documents, besides any `+json` type
+    pub metadata_content_types: Vec<String>,
+    /// Timeout for a single download attempt
+    pub timeout: Duration,
+    /// Time limit for a whole fetch, across gateways and the image a document references
+    pub fetch_time_limit: Duration,
+}
+
+impl Default for ContentCacheConfig {
+    fn default() -> Self {
+        Self {
+            gateways: vec![DEFAULT_IPFS_GATEWAY.to_string()],
+            max_download_bytes: DEFAULT_MAX_DOWNLOAD_BYTES,
+            allowed_content_types: DEFAULT_ALLOWED_CONTENT_TYPES
+                .iter()
+                .map(|t| t.to_string())
+                .collect(),
+            max_metadata_bytes: DEFAULT_MAX_METADATA_BYTES,
+            max_json_depth: DEFAULT_MAX_JSON_DEPTH,
+            metadata_content_types: DEFAULT_METADATA_CONTENT_TYPES
+                .iter()
+                .map(|t| t.to_string())
+                .collect(),
+            timeout: DEFAULT_TIMEOUT,
+            fetch_time_limit: DEFAULT_FETCH_TIME_LIMIT,
+        }
+    }
+}
+
+impl ContentCacheConfig {
+    /// Builds the configuration from environment variables
+    ///
+    /// `SSS_IPFS_GATEWAYS` is a comma-separated list of gateway base URLs tried in order,
+    /// and `SSS_CONTENT_MAX_BYTES` overrides the per-file size cap. The limits on metadata
+    /// documents come from `SSS_METADATA_MAX_BYTES`, `SSS_METADATA_MAX_DEPTH` and
+    /// `SSS_FETCH_TIME_LIMIT_SECS`.
+    pub fn from_env() -> Self {
+        crate::load_dotenv();
+        let mut config = Self::default();
+
+        if let Ok(gateways) = env::var("SSS_IPFS_GATEWAYS") {
+            let gateways: Vec<String> = gateways
+                .split(',')
+                .map(str::trim)
+                .filter(|g| !g.is_empty())
+                .map(|g| {
+                    if g.ends_with('/') {
+                        g.to_string()
+                    } else {
+                        format!("{}/", g)
+                    }
+                })
+                .collect();
+            if !gateways.is_empty() {
+                config.gateways = gateways;
+            }
+        }
+
+        if let Some(max_bytes) = env::var("SSS_CONTENT_MAX_BYTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+        {
+            config.max_download_bytes = max_bytes;
+        }
+        if let Some(max_bytes) = env::var("SSS_METADATA_MAX_BYTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+        {
+            config.max_metadata_bytes = max_bytes;
+        }
+        if let Some(max_depth) = env::var("SSS_METADATA_MAX_DEPTH")
+            .ok()
+            .and_then(|v| v.parse().ok())
+        {
+            config.max_json_depth = max_depth;
+        }
+        if let Some(secs) = env::var("SSS_FETCH_TIME_LIMIT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+        {
+            config.fetch_time_limit = Duration::from_secs(secs);
+        }
+
+        config
+    }
+}
+
+/// Fetches the image for an asset and stores it in a local cache
+///
+/// If the URI points to a Metaplex metadata JSON document, its `image` field is followed.
+///
+/// # Arguments
+///
+/// * `uri` - The asset URI (`https://`, `http://` or `ipfs://`)
+/// * `cache_dir` - The directory used to store cached content
+///
+/// # Returns
+///
+/// The path of the cached file
+pub fn fetch_asset_image(uri: &str, cache_dir: &Path) -> SssResult<PathBuf> {
+    fetch_asset_image_with_config(uri, cache_dir, &ContentCacheConfig::from_env())
+}
+
+/// Fetches the image for an asset using an explicit configuration
+///
+/// # Arguments
+///
+/// * `uri` - The asset URI (`https://`, `http://` or `ipfs://`)
+/// * `cache_dir` - The directory used to store cached content
+/// * `config` - The gateway, size and content-type settings
+///
+/// # Returns
+///
+/// The path of the cached file
+pub fn fetch_asset_image_with_config(
+    uri: &str,
+    cache_dir: &Path,
+    config: &ContentCacheConfig,
+) -> SssResult<PathBuf> {
+    let refs_dir = cache_dir.join(REFS_DIR);
+    fs::create_dir_all(&refs_dir).into_sss_error("Failed to create cache directory")?;
+
+    // Serve from the cache if this URI was fetched before and the file still exists
+    let ref_path = refs_dir.join(hash(uri.as_bytes()).to_string());
+    if let Ok(file_name) = fs::read_to_string(&ref_path) {
+        let cached = cache_dir.join(file_name.trim());
+        if cached.is_file() {
+            touch(&cached);
+            return Ok(cached);
+        }
+    }
+
+    let client = reqwest::blocking::Client::builder()
+        .timeout(config.timeout)
+        .build()
+        .into_sss_error("Failed to build HTTP client")?;
+
+    let deadline = Instant::now() + config.fetch_time_limit;
+    let (mut content_type, mut bytes) =
+        download(&client, uri, config, Expected::ImageOrMetadata, deadline)?;
+
+    // Metadata documents reference the actual image through their `image` field
+    if is_metadata_content_type(&content_type, config) {
+        let image_uri = std::str::from_utf8(&bytes)
+            .ok()
+            .and_then(|json| parse_metadata_json(json).ok())
+            .and_then(|metadata| metadata.image)
+            .ok_or_else(|| {
+                SssError::TokenError(format!("Metadata at {} has no image field", uri))
+            })?;
+        (content_type, bytes) = download(&client, &image_uri, config, Expected::Image, deadline)?;
+    }
+
+    let file_name = format!("{}.{}", hash(&bytes), extension_for(&content_type));
+    let path = cache_dir.join(&file_name);
+    if !path.is_file() {
+        fs::write(&path, &bytes).into_sss_error("Failed to write cache file")?;
+    }
+    fs::write(&ref_path, &file_name).into_sss_error("Failed to write cache reference")?;
+
+    Ok(path)
+}
+
+/// Fetches the image of an asset by its id and stores it in the local cache
+///
+/// The metadata URI is read from the asset's token metadata account, or asked of the DAS
+/// API for assets without one, such as compressed NFTs. The image is then fetched and
+/// cached as by [`fetch_asset_image`].
+///
+/// # Arguments
+///
+/// * `asset_id` - The asset id, which is the mint address for tokens
+/// * `cache_dir` - The directory used to store cached content
+///
+/// # Returns
+///
+/// The path of the cached file
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the asset has no metadata URI, besides the errors of
+/// [`fetch_asset_image`]
+pub fn fetch_asset_image_by_id(asset_id: &Pubkey, cache_dir: &Path) -> SssResult<PathBuf> {
+    fetch_asset_image_by_id_with_config(asset_id, cache_dir, &ContentCacheConfig::from_env())
+}
+
+/// Fetches the image of an asset by its id using an explicit configuration
+///
+/// # Arguments
+///
+/// * `asset_id` - The asset id, which is the mint address for tokens
+/// * `cache_dir` - The directory used to store cached content
+/// * `config` - The gateway, size and content-type settings
+///
+/// # Returns
+///
+/// The path of the cached file
+pub fn fetch_asset_image_by_id_with_config(
+    asset_id: &Pubkey,
+    cache_dir: &Path,
+    config: &ContentCacheConfig,
+) -> SssResult<PathBuf> {
+    let uri = asset_metadata_uri(&RPC_CLIENT, asset_id)?;
+    fetch_asset_image_with_config(&uri, cache_dir, config)
+}
+
+/// Resolves the metadata URI of an asset, on-chain first and then through DAS
+fn asset_metadata_uri(client: &RpcClient, asset_id: &Pubkey) -> SssResult<String> {
+    if let Some(uri) = metadata_uri(client, asset_id)?.filter(|uri| !uri.is_empty()) {
+        return Ok(uri);
+    }
+    #[cfg(feature = "das")]
+    if let Some(uri) = get_asset(asset_id)?.and_then(|asset| asset.uri) {
+        return Ok(uri);
+    }
+    Err(SssError::TokenError(format!(
+        "Asset {} has no metadata URI",
+        asset_id
+    )))
+}
+
+/// Downloads and parses the off-chain metadata document at a URI
+///
+/// Nothing is cached, so changes to the document are seen immediately. The document is
+/// held to the metadata size, nesting and time limits of [`ContentCacheConfig::from_env`].
+///
+/// # Arguments
+///
+/// * `uri` - The metadata URI (`https://`, `http://` or `ipfs://`)
+pub(crate) fn fetch_metadata(uri: &str) -> SssResult<OffchainMetadata> {
+    fetch_metadata_with_config(uri, &ContentCacheConfig::from_env())
+}
+
+/// Downloads and parses the off-chain metadata document at a URI, held to the limits of
+/// an explicit configuration
+fn fetch_metadata_with_config(
+    uri: &str,
+    config: &ContentCacheConfig,
+) -> SssResult<OffchainMetadata> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(config.timeout)
+        .build()
+        .into_sss_error("Failed to build HTTP client")?;
+    let deadline = Instant::now() + config.fetch_time_limit;
+    let (_, bytes) = download(&client, uri, config, Expected::Metadata, deadline)?;
+    let json = std::str::from_utf8(&bytes)
+        .map_err(|e| SssError::TokenError(format!("Metadata at {} is not UTF-8: {}", uri, e)))?;
+    parse_metadata_json(json)
+}
+
+/// Evicts the least recently used files until the cache fits within the given size
+///
+/// # Arguments
+///
+/// * `cache_dir` - The directory used to store cached content
+/// * `max_total_bytes` - The maximum total size of the cached content
+///
+/// # Returns
+///
+/// The number of bytes removed
+pub fn evict_cache(cache_dir: &Path, max_total_bytes: u64) -> SssResult<u64> {
+    let mut files = Vec::new();
+    let mut total = 0u64;
+
+    for entry in fs::read_dir(cache_dir).into_sss_error("Failed to read cache directory")? {
+        let entry = entry.into_sss_error("Failed to read cache entry")?;
+        let metadata = entry
+            .metadata()
+            .into_sss_error("Failed to read cache entry")?;
+        if !metadata.is_file() {
+            continue;
+        }
+        let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+        total += metadata.len();
+        files.push((modified, metadata.len(), entry.path()));
+    }
+
+    // Oldest first
+    files.sort_by_key(|(modified, _, _)| *modified);
+
+    let mut removed = 0u64;
+    for (_, len, path) in files {
+        if total <= max_total_bytes {
+            break;
+        }
+        fs::remove_file(&path).into_sss_error("Failed to remove cache file")?;
+        total -= len;
+        removed += len;
+    }
+
+    Ok(removed)
+}
+
+/// What a download must contain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Expected {
+    /// A metadata document
+    Metadata,
+    /// An image, or a metadata document referencing one
+    ImageOrMetadata,
+    /// An image
+    Image,
+}
+
+/// Downloads a URI, trying each configured gateway in order for IPFS content
+fn download(
+    client: &reqwest::blocking::Client,
+    uri: &str,
+    config: &ContentCacheConfig,
+    expected: Expected,
+    deadline: Instant,
+) -> SssResult<(String, Vec<u8>)> {
+    let urls: Vec<String> = match uri.strip_prefix("ipfs://") {
+        Some(cid) => {
+            let cid = cid.trim_start_matches("ipfs/");
+            config
+                .gateways
+                .iter()
+                .map(|gateway| format!("{}{}", gateway, cid))
+                .collect()
+        }
+        None => vec![uri.to_string()],
+    };
+
+    let mut last_error = SssError::TokenError(format!("No gateway configured for {}", uri));
+    for url in urls {
+        if Instant::now() >= deadline {
+            return Err(time_limit_exceeded(uri, config));
+        }
+        match download_url(client, &url, config, expected, deadline) {
+            Ok(result) => return Ok(result),
+            Err(e) => last_error = e,
+        }
+    }
+
+    Err(last_error)
+}
+
+/// Downloads a single URL enforcing the content type, size, nesting and time limits
+fn download_url(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    config: &ContentCacheConfig,
+    expected: Expected,
+    deadline: Instant,
+) -> SssResult<(String, Vec<u8>)> {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    let mut response = client
+        .get(url)
+        .timeout(remaining.min(config.timeout))
+        .send()
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| {
+            if e.is_timeout() && Instant::now() >= deadline {
+                time_limit_exceeded(url, config)
+            } else {
+                SssError::RpcError(format!("Failed to download {}: {}", url, e))
+            }
+        })?;
+
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(media_type)
+        .unwrap_or_default();
+
+    // Rejected before any of the body is read
+    let is_metadata = is_metadata_content_type(&content_type, config)
+        || (expected == Expected::Metadata && content_type.is_empty());
+    let is_image = config
+        .allowed_content_types
+        .iter()
+        .any(|t| media_type(t) == content_type);
+    let accepted = match expected {
+        Expected::Metadata => is_metadata,
+        Expected::ImageOrMetadata => is_metadata || is_image,
+        Expected::Image => is_image,
+    };
+    if !accepted {
+        return Err(SssError::TokenError(format!(
+            "Content type {} at {} is not allowed",
+            content_type, url
+        )));
+    }
+
+    let (max_bytes, limit) = if is_metadata {
+        (config.max_metadata_bytes, "metadata size limit")
+    } else {
+        (config.max_download_bytes, "download size limit")
+    };
+    let size_exceeded = || {
+        SssError::TokenError(format!(
+            "Content at {} exceeds the {} of {} bytes",
+            url, limit, max_bytes
+        ))
+    };
+    if response.content_length().is_some_and(|len| len > max_bytes) {
+        return Err(size_exceeded());
+    }
+
+    // Read in chunks so neither an oversized body without a length header nor a server
+    // trickling bytes can hold the fetch beyond its limits
+    let mut bytes = Vec::new();
+    let mut chunk = [0u8; 8192];
+    loop {
+        if Instant::now() >= deadline {
+            return Err(time_limit_exceeded(url, config));
+        }
+        let read = match response.read(&mut chunk) {
+            Ok(read) => read,
+            Err(_) if Instant::now() >= deadline => return Err(time_limit_exceeded(url, config)),
+            Err(e) => return Err(SssError::RpcError(format!("Failed to read {}: {}", url, e))),
+        };
+        if read == 0 {
+            break;
+        }
+        if (bytes.len() + read) as u64 > max_bytes {
+            return Err(size_exceeded());
+        }
+        bytes.extend_from_slice(&chunk[..read]);
+    }
+
+    if is_metadata && json_depth_exceeds(&bytes, config.max_json_depth) {
+        return Err(SssError::TokenError(format!(
+            "Metadata at {} exceeds the nesting depth limit of {}",
+            url, config.max_json_depth
+        )));
+    }
+
+    Ok((content_type, bytes))
+}
+
+/// Returns the media type of a `Content-Type` value, lowercase and without parameters
+///
+/// `application/json; charset=utf-8` becomes `application/json`.
+fn media_type(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+/// Returns whether a content type is one of a metadata document
+///
+/// Parameters such as a charset are ignored on both the content type and the configured
+/// types.
+fn is_metadata_content_type(content_type: &str, config: &ContentCacheConfig) -> bool {
+    let content_type = media_type(content_type);
+    content_type.ends_with("+json")
+        || config
+            .metadata_content_types
+            .iter()
+            .any(|t| media_type(t) == content_type)
+}
+
+/// Returns whether arrays and objects in a JSON document nest deeper than a limit
+///
+/// Brackets inside strings are ignored. The document is not validated, which is left to
+/// the parser once the depth is known to be safe.
+fn json_depth_exceeds(bytes: &[u8], max_depth: usize) -> bool {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for &byte in bytes {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'{' | b'[' => {
+                depth += 1;
+                if depth > max_depth {
+                    return true;
+                }
+            }
+            b'}' | b']' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Error for a fetch that ran past its time limit
+fn time_limit_exceeded(uri: &str, config: &ContentCacheConfig) -> SssError {
+    SssError::TokenError(format!(
+        "Fetching {} exceeds the time limit of {} seconds",
+        uri,
+        config.fetch_time_limit.as_secs()
+    ))
+}
+
+/// Returns the file extension for a content type
+fn extension_for(content_type: &str) -> &'static str {
+    match content_type {
+        "image/png" => "png",
+        "image/jpeg" => "jpg",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "image/svg+xml" => "svg",
+        _ => "bin",
+    }
+}
+
+/// Marks a cached file as recently used
+fn touch(path: &Path) {
+    if let Ok(file) = fs::File::options().append(true).open(path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::prelude::{BASE64_STANDARD, Engine};
+    use serde_json::json;
+    use solana_rpc_client::mock_sender::Mocks;
+    use solana_rpc_client_api::request::RpcRequest;
+    use std::io::Write;
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::Arc;
+    use std::thread;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("sss-{}-{}", name, rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Serializes a token metadata account holding only the fields up to the URI
+    fn metadata_account(mint: &Pubkey, uri: &str) -> Vec<u8> {
+        let mut data = vec![4u8]; // Key::MetadataV1
+        data.extend_from_slice(Pubkey::new_unique().as_ref());
+        data.extend_from_slice(mint.as_ref());
+        for field in ["Coffee", "COF", uri] {
+            data.extend_from_slice(&(field.len() as u32).to_le_bytes());
+            data.extend_from_slice(field.as_bytes());
+        }
+        // Seller fee, no creators, primary sale, mutable, then every option unset
+        data.extend_from_slice(&[0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+        data
+    }
+
+    fn client_with_accounts(accounts: Vec<serde_json::Value>) -> RpcClient {
+        let mut mocks = Mocks::default();
+        mocks.insert(
+            RpcRequest::GetMultipleAccounts,
+            json!({ "context": { "slot": 1 }, "value": accounts }),
+        );
+        RpcClient::new_mock_with_mocks("succeeds".to_string(), mocks)
+    }
+
+    #[test]
+    fn media_type_drops_parameters_and_case() {
+        assert_eq!(
+            media_type("application/json; charset=utf-8"),
+            "application/json"
+        );
+        assert_eq!(media_type(" Application/JSON "), "application/json");
+        assert_eq!(media_type("image/png"), "image/png");
+        assert_eq!(media_type(""), "");
+    }
+
+    #[test]
+    fn metadata_content_types_match_with_parameters() {
+        let config = ContentCacheConfig::default();
+        assert!(is_metadata_content_type(
+            "application/json; charset=utf-8",
+            &config
+        ));
+        assert!(is_metadata_content_type("application/ld+json", &config));
+        assert!(!is_metadata_content_type("image/png", &config));
+
+        let config = ContentCacheConfig {
+            metadata_content_types: vec!["application/json; charset=utf-8".to_string()],
+            ..ContentCacheConfig::default()
+        };
+        assert!(is_metadata_content_type("application/json", &config));
+    }
+
+    /// Serves every connection to a local port with `respond`, once the request has been
+    /// read, returning the URL of a document on it
+    fn serve(respond: impl Fn(TcpStream) + Send + Sync + 'static) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/metadata.json", listener.local_addr().unwrap());
+        let respond = Arc::new(respond);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let respond = Arc::clone(&respond);
+                thread::spawn(move || {
+                    let mut request = Vec::new();
+                    let mut byte = [0u8; 1];
+                    while !request.ends_with(b"\r\n\r\n")
+                        && stream.read(&mut byte).is_ok_and(|read| read == 1)
+                    {
+                        request.push(byte[0]);
+                    }
+                    respond(stream);
+                });
+            }
+        });
+        url
+    }
+
+    /// Writes a response with the given content type and body, announcing its length or
+    /// closing the connection after it
+    fn reply(mut stream: TcpStream, content_type: &str, body: &[u8], with_length: bool) {
+        let length = if with_length {
+            format!("Content-Length: {}\r\n", body.len())
+        } else {
+            "Connection: close\r\n".to_string()
+        };
+        let head = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: {}\r\n{}\r\n",
+            content_type, length
+        );
+        let _ = stream.write_all(head.as_bytes());
+        let _ = stream.write_all(body);
+    }
+
+    fn token_error<T: std::fmt::Debug>(result: SssResult<T>) -> String {
+        match result {
+            Err(SssError::TokenError(message)) => message,
+            other => panic!("expected a TokenError, got {:?}", other),
+        }
+    }
+
+    /// A configuration with small limits, so tests fail fast
+    fn limited() -> ContentCacheConfig {
+        ContentCacheConfig {
+            max_metadata_bytes: 4096,
+            max_json_depth: 8,
+            timeout: Duration::from_secs(10),
+            fetch_time_limit: Duration::from_secs(1),
+            ..ContentCacheConfig::default()
+        }
+    }
+
+    #[test]
+    fn document_within_the_limits_is_parsed() {
+        let url = serve(|stream| {
+            let body = json!({ "name": "Coffee", "symbol": "COF", "attributes": [
+                { "trait_type": "batch", "value": "B-2024-11" }
+            ] });
+            reply(
+                stream,
+                "application/json; charset=utf-8",
+                body.to_string().as_bytes(),
+                false,
+            )
+        });
+        let metadata = fetch_metadata_with_config(&url, &limited()).unwrap();
+        assert_eq!(metadata.name, "Coffee");
+        assert_eq!(metadata.attributes.len(), 1);
+    }
+
+    #[test]
+    fn oversized_body_is_refused_with_or_without_a_length() {
+        for with_length in [true, false] {
+            let url = serve(move |stream| {
+                let body = format!(r#"{{"name":"{}"}}"#, "x".repeat(64 * 1024));
+                reply(stream, "application/json", body.as_bytes(), with_length)
+            });
+            let message = token_error(fetch_metadata_with_config(&url, &limited()));
+            assert_eq!(
+                message,
+                format!(
+                    "Content at {} exceeds the metadata size limit of 4096 bytes",
+                    url
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn endless_body_is_cut_off_at_the_size_limit() {
+        let url = serve(|mut stream| {
+            let _ = stream.write_all(
+                b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nConnection: close\r\n\r\n[",
+            );
+            // Stops once the client hangs up
+            while stream.write_all(&[b' '; 1024]).is_ok() {}
+        });
+        let message = token_error(fetch_metadata_with_config(&url, &limited()));
+        assert!(message.contains("metadata size limit"), "{message}");
+    }
+
+    #[test]
+    fn deeply_nested_document_is_refused_before_parsing() {
+        // Small on the wire, but a recursive parser would nest two thousand levels
+        let url = serve(|stream| {
+            let body = format!("{}{}", "[".repeat(2000), "]".repeat(2000));
+            reply(stream, "application/json", body.as_bytes(), true)
+        });
+        let message = token_error(fetch_metadata_with_config(&url, &limited()));
+        assert_eq!(
+            message,
+            format!("Metadata at {} exceeds the nesting depth limit of 8", url)
+        );
+
+        let url = serve(|stream| {
+            let body = format!(
+                r#"{{"name":"a","symbol":"b","x":{}1{}}}"#,
+                "{\"y\":".repeat(7),
+                "}".repeat(7)
+            );
+            reply(stream, "application/json", body.as_bytes(), true)
+        });
+        // Nesting exactly at the limit is accepted
+        fetch_metadata_with_config(&url, &limited()).unwrap();
+    }
+
+    #[test]
+    fn non_json_content_type_is_refused_before_the_body() {
+        let url = serve(|mut stream| {
+            let _ = stream.write_all(
+                b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 1000000\r\n\r\n",
+            );
+            // The body never comes, so reading it would run into the time limit
+            thread::sleep(Duration::from_secs(5));
+        });
+        let started = Instant::now();
+        let message = token_error(fetch_metadata_with_config(&url, &limited()));
+        assert_eq!(
+            message,
+            format!("Content type text/html at {} is not allowed", url)
+        );
+        assert!(started.elapsed() < Duration::from_millis(900));
+    }
+
+    #[test]
+    fn slow_loris_server_runs_into_the_time_limit() {
+        // Headers arrive, then one byte of a valid document at a time
+        let url = serve(|mut stream| {
+            let head =
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 100\r\n\r\n";
+            let _ = stream.write_all(head.as_bytes());
+            for byte in br#"{"name":"#.iter().chain([b' '; 100].iter()) {
+                if stream.write_all(&[*byte]).is_err() {
+                    return;
+                }
+                thread::sleep(Duration::from_millis(100));
+            }
+        });
+        let started = Instant::now();
+        let message = token_error(fetch_metadata_with_config(&url, &limited()));
+        assert_eq!(
+            message,
+            format!("Fetching {} exceeds the time limit of 1 seconds", url)
+        );
+        assert!(started.elapsed() < Duration::from_secs(3));
+
+        // A server that never answers at all
+        let url = serve(|_stream| thread::sleep(Duration::from_secs(5)));
+        let message = token_error(fetch_metadata_with_config(&url, &limited()));
+        assert!(message.contains("exceeds the time limit"), "{message}");
+    }
+
+    #[test]
+    fn image_prefetch_shares_the_metadata_limits() {
+        let url = serve(|stream| {
+            let body = format!("{}{}", "{\"a\":".repeat(20), "1}".repeat(20));
+            reply(stream, "application/json", body.as_bytes(), true)
+        });
+        let dir = temp_dir("limits");
+        let message = token_error(fetch_asset_image_with_config(&url, &dir, &limited()));
+        assert!(message.contains("nesting depth limit of 8"), "{message}");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn json_depth_ignores_brackets_in_strings() {
+        assert!(!json_depth_exceeds(br#"{"a":[{"b":"[[[[["}]}"#, 3));
+        assert!(json_depth_exceeds(br#"{"a":[{"b":[]}]}"#, 3));
+    }
+
+    #[test]
+    fn asset_id_resolves_to_the_on_chain_uri() {
+        let mint = Pubkey::new_unique();
+        let data = metadata_account(&mint, "https://example.com/coffee.json\0\0\0");
+        let client = client_with_accounts(vec![json!({
+            "data": [BASE64_STANDARD.encode(&data), "base64"],
+            "executable": false,
+            "lamports": 1,
+            "owner": mpl_token_metadata::ID.to_string(),
+            "rentEpoch": 0,
+            "space": data.len(),
+        })]);
+
+        assert_eq!(
+            asset_metadata_uri(&client, &mint).unwrap(),
+            "https://example.com/coffee.json"
+        );
+    }
+
+    #[test]
+    fn cached_uri_is_served_without_downloading() {
+        let dir = temp_dir("content");
+        let uri = "https://example.invalid/image.png";
+        fs::create_dir_all(dir.join(REFS_DIR)).unwrap();
+        fs::write(dir.join("cached.png"), b"png").unwrap();
+        fs::write(
+            dir.join(REFS_DIR).join(hash(uri.as_bytes()).to_string()),
+            "cached.png",
+        )
+        .unwrap();
+
+        let path =
+            fetch_asset_image_with_config(uri, &dir, &ContentCacheConfig::default()).unwrap();
+        assert_eq!(path, dir.join("cached.png"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn eviction_removes_the_oldest_files_first() {
+        let dir = temp_dir("evict");
+        for (name, age) in [("old", 300), ("mid", 200), ("new", 100)] {
+            let path = dir.join(name);
+            fs::write(&path, [0u8; 10]).unwrap();
+            let file = fs::File::options().append(true).open(&path).unwrap();
+            file.set_modified(SystemTime::now() - Duration::from_secs(age))
+                .unwrap();
+        }
+
+        assert_eq!(evict_cache(&dir, 20).unwrap(), 10);
+        assert!(!dir.join("old").exists());
+        assert!(dir.join("mid").exists() && dir.join("new").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
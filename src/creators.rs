@@ -0,0 +1,373 @@
+//! Creator shares written into token metadata and their verification
+//!
+//! Royalties from secondary sales are split between up to five creators by percentage.
+//! A creator is only marked verified once it has signed, either by paying for the creation
+//! or later through [`verify_creator`].
+
+use crate::RPC_CLIENT;
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::in_flight::exclusive;
+use crate::instructions;
+use crate::journal::{JournalOperation, journaled};
+use crate::metrics::track;
+use crate::mint_cache::metadata_pda;
+use crate::payer::{get_payer_pubkey, signing_payer};
+use crate::read_only::ensure_writable;
+use crate::serde_utils::pubkey_string;
+use crate::token::ensure_trusted_mint;
+use crate::transaction::{TxOptions, assert_tx_size, required_signatures, sign_and_send};
+use mpl_token_metadata::MAX_CREATOR_LIMIT;
+use mpl_token_metadata::accounts::Metadata;
+use mpl_token_metadata::types::Creator;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use solana_sdk::{message::Message, pubkey::Pubkey};
+use std::collections::HashMap;
+
+/// The total the shares of the creators must add up to
+const TOTAL_SHARE_PERCENT: u32 = 100;
+
+/// A creator of a token and its share of the royalties
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CreatorShare {
+    /// The creator's wallet
+    #[serde(with = "pubkey_string")]
+    pub address: Pubkey,
+    /// The creator's share of the royalties, in percent
+    pub share_percent: u8,
+    /// Whether the creator is marked verified at creation, which only the payer can be
+    #[serde(default)]
+    pub verified: bool,
+}
+
+impl CreatorShare {
+    /// Creates an unverified creator share
+    pub fn new(address: Pubkey, share_percent: u8) -> Self {
+        Self {
+            address,
+            share_percent,
+            verified: false,
+        }
+    }
+}
+
+/// Checks the number of creators, their addresses and that their shares add up to 100
+///
+/// No creators is valid: the metadata program then records the update authority as the
+/// sole, verified creator.
+///
+/// # Errors
+///
+/// Returns a `TokenError` naming the offending creator by position and address
+pub(crate) fn validate_creators(creators: &[CreatorShare]) -> SssResult<()> {
+    if creators.is_empty() {
+        return Ok(());
+    }
+    if creators.len() > MAX_CREATOR_LIMIT {
+        return Err(SssError::TokenError(format!(
+            "{} creators given, the maximum is {}",
+            creators.len(),
+            MAX_CREATOR_LIMIT
+        )));
+    }
+    let mut seen = HashMap::new();
+    for (index, creator) in creators.iter().enumerate() {
+        if let Some(first) = seen.insert(creator.address, index) {
+            return Err(SssError::TokenError(format!(
+                "Creator {} ({}) duplicates creator {}",
+                index, creator.address, first
+            )));
+        }
+    }
+    let total: u32 = creators
+        .iter()
+        .map(|creator| u32::from(creator.share_percent))
+        .sum();
+    if total != TOTAL_SHARE_PERCENT {
+        let shares = creators
+            .iter()
+            .enumerate()
+            .map(|(index, creator)| {
+                format!(
+                    "creator {} ({}) {}%",
+                    index, creator.address, creator.share_percent
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        return Err(SssError::TokenError(format!(
+            "Creator shares add up to {}%, not {}%: {}",
+            total, TOTAL_SHARE_PERCENT, shares
+        )));
+    }
+    Ok(())
+}
+
+/// Converts validated creator shares to metadata creators, with `None` for no creators
+///
+/// # Errors
+///
+/// Returns a `TokenError` naming a creator marked verified that is not the update
+/// authority, since the metadata program only accepts the signature of the update
+/// authority at creation
+pub(crate) fn metadata_creators(
+    creators: &[CreatorShare],
+    update_authority: &Pubkey,
+) -> SssResult<Option<Vec<Creator>>> {
+    if creators.is_empty() {
+        return Ok(None);
+    }
+    if let Some((index, creator)) = creators
+        .iter()
+        .enumerate()
+        .find(|(_, creator)| creator.verified && creator.address != *update_authority)
+    {
+        return Err(SssError::TokenError(format!(
+            "Creator {} ({}) is marked verified, but only the payer {} can be verified at \
+             creation; the creator has to call verify_creator itself",
+            index, creator.address, update_authority
+        )));
+    }
+    Ok(Some(
+        creators
+            .iter()
+            .map(|creator| Creator {
+                address: creator.address,
+                verified: creator.verified,
+                share: creator.share_percent,
+            })
+            .collect(),
+    ))
+}
+
+/// Marks the payer as a verified creator of a token
+///
+/// # Arguments
+///
+/// * `mint` - The mint whose metadata lists the payer as a creator
+///
+/// # Returns
+///
+/// The transaction signature as a string
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the mint has no metadata, if the payer is not one of its
+/// creators, or if the payer is already verified
+pub fn verify_creator(mint: Pubkey) -> SssResult<String> {
+    ensure_writable()?;
+    ensure_trusted_mint(&mint)?;
+    let params = json!({ "mint": mint.to_string() });
+    let tx_options = TxOptions::default();
+    exclusive(
+        JournalOperation::VerifyCreator,
+        &params,
+        &tx_options,
+        || {
+            track(JournalOperation::VerifyCreator, || {
+                let payer_pubkey = get_payer_pubkey()?;
+                ensure_unverified_creator(&mint, &payer_pubkey)?;
+                let metadata = metadata_pda(&mint);
+                let ix = instructions::verify_creator_v1(Some(metadata), Some(payer_pubkey))?;
+                let message = Message::new(&[ix], Some(&payer_pubkey));
+                assert_tx_size(&message, required_signatures(&message))?;
+                let payer = signing_payer()?;
+                journaled(
+                    JournalOperation::VerifyCreator,
+                    &params,
+                    &tx_options,
+                    || sign_and_send(&RPC_CLIENT, message, &[&payer], &tx_options),
+                )
+            })
+        },
+    )
+}
+
+/// Fails unless the metadata of a mint lists the payer as a creator that is not verified yet
+fn ensure_unverified_creator(mint: &Pubkey, payer: &Pubkey) -> SssResult<()> {
+    let account = RPC_CLIENT
+        .get_multiple_accounts(&[metadata_pda(mint)])
+        .into_sss_error("Failed to get token metadata account from rpc")?
+        .pop()
+        .flatten()
+        .ok_or_else(|| SssError::TokenError(format!("Mint {} has no metadata account", mint)))?;
+    let metadata =
+        Metadata::from_bytes(&account.data).into_sss_error("Failed to parse token metadata")?;
+    check_unverified_creator(&metadata.creators.unwrap_or_default(), mint, payer)
+}
+
+/// Fails unless the creators of a mint list the payer as a creator that is not verified yet
+fn check_unverified_creator(creators: &[Creator], mint: &Pubkey, payer: &Pubkey) -> SssResult<()> {
+    match creators.iter().find(|creator| creator.address == *payer) {
+        Some(creator) if creator.verified => Err(SssError::TokenError(format!(
+            "Payer {} is already a verified creator of mint {}",
+            payer, mint
+        ))),
+        Some(_) => Ok(()),
+        None => {
+            let listed: Vec<_> = creators
+                .iter()
+                .map(|creator| creator.address.to_string())
+                .collect();
+            Err(SssError::TokenError(format!(
+                "Payer {} is not a creator of mint {}, whose creators are [{}]",
+                payer,
+                mint,
+                listed.join(", ")
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shares(percents: &[u8]) -> Vec<CreatorShare> {
+        percents
+            .iter()
+            .map(|percent| CreatorShare::new(Pubkey::new_unique(), *percent))
+            .collect()
+    }
+
+    fn token_error(result: SssResult<impl std::fmt::Debug>) -> String {
+        match result {
+            Err(SssError::TokenError(message)) => message,
+            other => panic!("expected a TokenError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn shares_adding_up_to_100_are_valid() {
+        validate_creators(&[]).unwrap();
+        validate_creators(&shares(&[100])).unwrap();
+        validate_creators(&shares(&[50, 30, 20])).unwrap();
+        validate_creators(&shares(&[20; 5])).unwrap();
+    }
+
+    #[test]
+    fn shares_not_adding_up_to_100_list_every_creator() {
+        for percents in [&[50, 49][..], &[60, 41], &[0], &[255, 255]] {
+            let creators = shares(percents);
+            let message = token_error(validate_creators(&creators));
+            let total: u32 = percents.iter().map(|p| u32::from(*p)).sum();
+            assert!(message.starts_with(&format!("Creator shares add up to {}%, not 100%", total)));
+            for (index, creator) in creators.iter().enumerate() {
+                assert!(
+                    message.contains(&format!(
+                        "creator {} ({}) {}%",
+                        index, creator.address, creator.share_percent
+                    )),
+                    "{message}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn duplicate_addresses_name_both_positions() {
+        let mut creators = shares(&[40, 30, 30]);
+        creators[2].address = creators[0].address;
+        assert_eq!(
+            token_error(validate_creators(&creators)),
+            format!("Creator 2 ({}) duplicates creator 0", creators[0].address)
+        );
+    }
+
+    #[test]
+    fn more_than_five_creators_are_refused() {
+        assert_eq!(
+            token_error(validate_creators(&shares(&[10, 10, 20, 20, 20, 20]))),
+            "6 creators given, the maximum is 5"
+        );
+    }
+
+    #[test]
+    fn only_the_payer_can_be_verified_at_creation() {
+        let payer = Pubkey::new_unique();
+        let mut creators = shares(&[50, 50]);
+        creators[0] = CreatorShare {
+            address: payer,
+            share_percent: 50,
+            verified: true,
+        };
+        let converted = metadata_creators(&creators, &payer).unwrap().unwrap();
+        assert_eq!(
+            converted
+                .iter()
+                .map(|c| (c.address, c.verified, c.share))
+                .collect::<Vec<_>>(),
+            vec![(payer, true, 50), (creators[1].address, false, 50)]
+        );
+
+        creators[1].verified = true;
+        let message = token_error(metadata_creators(&creators, &payer));
+        assert!(
+            message.starts_with(&format!(
+                "Creator 1 ({}) is marked verified",
+                creators[1].address
+            )),
+            "{message}"
+        );
+        assert_eq!(metadata_creators(&[], &payer).unwrap(), None);
+    }
+
+    #[test]
+    fn verifying_requires_the_payer_to_be_an_unverified_creator() {
+        let mint = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+        let creator = |address, verified| Creator {
+            address,
+            verified,
+            share: 50,
+        };
+
+        check_unverified_creator(
+            &[creator(other, true), creator(payer, false)],
+            &mint,
+            &payer,
+        )
+        .unwrap();
+
+        let message = token_error(check_unverified_creator(
+            &[creator(other, false)],
+            &mint,
+            &payer,
+        ));
+        assert_eq!(
+            message,
+            format!(
+                "Payer {} is not a creator of mint {}, whose creators are [{}]",
+                payer, mint, other
+            )
+        );
+        let message = token_error(check_unverified_creator(&[], &mint, &payer));
+        assert!(message.ends_with("whose creators are []"), "{message}");
+
+        let message = token_error(check_unverified_creator(
+            &[creator(payer, true)],
+            &mint,
+            &payer,
+        ));
+        assert!(
+            message.contains("is already a verified creator"),
+            "{message}"
+        );
+    }
+
+    #[test]
+    fn creator_json_defaults_to_unverified_and_rejects_unknown_fields() {
+        let address = Pubkey::new_unique();
+        let parsed: Vec<CreatorShare> = serde_json::from_str(&format!(
+            r#"[{{"address": "{}", "share_percent": 100}}]"#,
+            address
+        ))
+        .unwrap();
+        assert_eq!(parsed, vec![CreatorShare::new(address, 100)]);
+
+        let misspelled = format!(r#"[{{"address": "{}", "share": 100}}]"#, address);
+        assert!(serde_json::from_str::<Vec<CreatorShare>>(&misspelled).is_err());
+    }
+}
@@ -0,0 +1,1367 @@
+//! Digital Asset Standard (DAS) API queries
+
+use crate::das_cache::DasClient;
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::metrics::{record_rate_limit_hit, record_rpc_retry};
+use crate::mint_cache::metadata_pda;
+use crate::retry::{RetryPolicy, classify_client_error, with_backoff};
+use crate::serde_utils::pubkey_string;
+use crate::trusted_mints::is_trusted_mint;
+use crate::{RPC_CLIENT, load_dotenv};
+use lazy_static::lazy_static;
+use mpl_token_metadata::accounts::Metadata;
+use mpl_token_metadata::types::TokenStandard;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use solana_rpc_client::http_sender::HttpSender;
+use solana_rpc_client_api::client_error::{Error as ClientError, ErrorKind, reqwest};
+use solana_rpc_client_api::request::{MAX_MULTIPLE_ACCOUNTS, RpcError, RpcRequest};
+use solana_rpc_client_api::response::{Response, RpcKeyedAccount};
+use solana_sdk::pubkey::Pubkey;
+use spl_token_2022::extension::StateWithExtensions;
+use std::collections::BTreeMap;
+use std::env;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Delay between `getAsset` polls while waiting for an asset to be indexed
+const INDEXING_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Page size for `getAssetsByOwner`, the maximum most providers accept
+const ASSETS_PAGE_LIMIT: usize = 1000;
+
+/// Number of ids sent in one `getAssetBatch` request, the maximum most providers accept
+pub const ASSET_BATCH_LIMIT: usize = 1000;
+
+/// Attempts per page before an [`AssetStream`] gives up, unless configured otherwise
+pub const DEFAULT_PAGE_ATTEMPTS: u32 = 3;
+
+/// Delay before the first retry of a failed page, doubled on every further retry
+const PAGE_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// JSON-RPC error codes providers use to signal rate limiting
+const RATE_LIMIT_CODES: [i64; 2] = [-32429, -32005];
+
+/// How a DAS provider expects the API key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DasAuthStyle {
+    /// No authentication, e.g. a self-hosted node on a private network
+    None,
+    /// The key is sent as a URL query parameter with the given name (Helius uses `api-key`)
+    QueryParam(String),
+    /// The key is sent in an HTTP header with the given name
+    Header(String),
+}
+
+/// Connection settings of a DAS provider
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DasProvider {
+    /// The JSON-RPC endpoint serving the DAS methods
+    pub base_url: String,
+    /// How the API key is passed
+    pub auth: DasAuthStyle,
+    /// Name of the environment variable holding the API key
+    pub api_key_env: Option<String>,
+    /// Whether the provider accepts the `displayOptions` extension to include fungible tokens
+    pub supports_display_options: bool,
+    /// Whether `searchAssets` filters by `jsonUri` on the provider's side
+    pub supports_json_uri_search: bool,
+    /// Whether the provider extends `searchAssets` with an `attributes` filter
+    pub supports_attribute_search: bool,
+}
+
+impl DasProvider {
+    /// A Helius endpoint, authenticated with an `api-key` query parameter
+    ///
+    /// # Arguments
+    ///
+    /// * `base_url` - The Helius RPC URL without the key, e.g. `https://mainnet.helius-rpc.com`
+    /// * `api_key_env` - The environment variable holding the API key
+    pub fn helius(base_url: impl Into<String>, api_key_env: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into(),
+            auth: DasAuthStyle::QueryParam("api-key".to_string()),
+            api_key_env: Some(api_key_env.into()),
+            supports_display_options: true,
+            supports_json_uri_search: true,
+            supports_attribute_search: false,
+        }
+    }
+
+    /// A Triton endpoint, authenticated with an `x-token` header
+    ///
+    /// # Arguments
+    ///
+    /// * `base_url` - The Triton RPC URL
+    /// * `api_key_env` - The environment variable holding the token
+    pub fn triton(base_url: impl Into<String>, api_key_env: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into(),
+            auth: DasAuthStyle::Header("x-token".to_string()),
+            api_key_env: Some(api_key_env.into()),
+            supports_display_options: false,
+            supports_json_uri_search: false,
+            supports_attribute_search: false,
+        }
+    }
+
+    /// A standard RPC endpoint that also serves DAS methods, without authentication
+    ///
+    /// # Arguments
+    ///
+    /// * `base_url` - The RPC URL
+    pub fn standard(base_url: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into(),
+            auth: DasAuthStyle::None,
+            api_key_env: None,
+            supports_display_options: false,
+            supports_json_uri_search: false,
+            supports_attribute_search: false,
+        }
+    }
+
+    /// Loads the provider from environment variables
+    ///
+    /// `SSS_DAS_URL` sets the endpoint and falls back to `SOLANA_RPC_URL`. `SSS_DAS_AUTH`
+    /// selects `none` (default), `query` or `header`, `SSS_DAS_AUTH_NAME` names the query
+    /// parameter or header (default `api-key`), and `SSS_DAS_API_KEY_ENV` names the variable
+    /// holding the key (default `SSS_DAS_API_KEY`). `SSS_DAS_DISPLAY_OPTIONS=true` enables
+    /// the `displayOptions` extension, and `SSS_DAS_JSON_URI_SEARCH=true` and
+    /// `SSS_DAS_ATTRIBUTE_SEARCH=true` the provider-side search filters.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ConfigError` if `SSS_DAS_AUTH` has an unknown value
+    pub fn from_env() -> SssResult<Self> {
+        load_dotenv();
+        let base_url = env::var("SSS_DAS_URL")
+            .or_else(|_| env::var("SOLANA_RPC_URL"))
+            .unwrap_or_else(|_| "https://api.devnet.solana.com".to_string());
+        let auth_name = env::var("SSS_DAS_AUTH_NAME").unwrap_or_else(|_| "api-key".to_string());
+        let auth = match env::var("SSS_DAS_AUTH").as_deref() {
+            Err(_) | Ok("none") => DasAuthStyle::None,
+            Ok("query") => DasAuthStyle::QueryParam(auth_name),
+            Ok("header") => DasAuthStyle::Header(auth_name),
+            Ok(other) => {
+                return Err(SssError::ConfigError(format!(
+                    "Unknown SSS_DAS_AUTH value '{}', expected none, query or header",
+                    other
+                )));
+            }
+        };
+        let api_key_env = match auth {
+            DasAuthStyle::None => None,
+            _ => Some(
+                env::var("SSS_DAS_API_KEY_ENV").unwrap_or_else(|_| "SSS_DAS_API_KEY".to_string()),
+            ),
+        };
+        let flag = |name: &str| env::var(name).is_ok_and(|v| v == "true" || v == "1");
+
+        Ok(Self {
+            base_url,
+            auth,
+            api_key_env,
+            supports_display_options: flag("SSS_DAS_DISPLAY_OPTIONS"),
+            supports_json_uri_search: flag("SSS_DAS_JSON_URI_SEARCH"),
+            supports_attribute_search: flag("SSS_DAS_ATTRIBUTE_SEARCH"),
+        })
+    }
+
+    /// Builds a client sending the provider's authentication with every request
+    ///
+    /// The client records or replays responses as configured by `SSS_DAS_CACHE`.
+    fn client(&self) -> SssResult<DasClient> {
+        let api_key = match &self.api_key_env {
+            Some(var) if self.auth != DasAuthStyle::None => Some(env::var(var).map_err(|_| {
+                SssError::ConfigError(format!("DAS API key variable {} is not set in env", var))
+            })?),
+            _ => None,
+        };
+
+        let mut url =
+            reqwest::Url::parse(&self.base_url).into_sss_error("Invalid DAS url in config")?;
+        let mut headers = HttpSender::default_headers();
+        match (&self.auth, &api_key) {
+            (DasAuthStyle::QueryParam(name), Some(key)) => {
+                url.query_pairs_mut().append_pair(name, key);
+            }
+            (DasAuthStyle::Header(name), Some(key)) => {
+                let name = reqwest::header::HeaderName::from_bytes(name.as_bytes())
+                    .into_sss_error("Invalid DAS auth header name in config")?;
+                let value = reqwest::header::HeaderValue::from_str(key)
+                    .into_sss_error("Invalid DAS API key in config")?;
+                headers.insert(name, value);
+            }
+            _ => {}
+        }
+
+        let http = reqwest::Client::builder()
+            .default_headers(headers)
+            .timeout(Duration::from_secs(30))
+            .build()
+            .into_sss_error("Failed to build DAS http client")?;
+        let sender = HttpSender::new_with_client(url, http);
+
+        let key_param = match &self.auth {
+            DasAuthStyle::QueryParam(name) => Some(name.as_str()),
+            _ => None,
+        };
+        DasClient::new(sender, api_key.as_deref(), key_param)
+    }
+}
+
+lazy_static! {
+    /// The active DAS provider and its client, created from the environment on first use
+    static ref DAS: RwLock<Option<(DasProvider, Arc<DasClient>)>> = RwLock::new(None);
+}
+
+/// Replaces the DAS provider used by all asset queries
+///
+/// # Arguments
+///
+/// * `provider` - The provider to use from now on
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if the provider's API key variable is not set
+pub fn set_das_provider(provider: DasProvider) -> SssResult<()> {
+    let client = Arc::new(provider.client()?);
+    let mut das = DAS
+        .write()
+        .map_err(|e| SssError::ConfigError(format!("Failed to lock DAS config: {}", e)))?;
+    *das = Some((provider, client));
+    Ok(())
+}
+
+/// Returns the active provider and client, loading them from the environment if needed
+fn das() -> SssResult<(DasProvider, Arc<DasClient>)> {
+    if let Some(das) = DAS.read().ok().and_then(|das| das.clone()) {
+        return Ok(das);
+    }
+    let provider = DasProvider::from_env()?;
+    let client = Arc::new(provider.client()?);
+    if let Ok(mut das) = DAS.write() {
+        *das = Some((provider.clone(), client.clone()));
+    }
+    Ok((provider, client))
+}
+
+/// Sends a DAS request through the active provider
+fn das_request<T: DeserializeOwned>(method: &'static str, params: Value) -> SssResult<T> {
+    let (_, client) = das()?;
+    client
+        .send(method, params)
+        .map_err(|e| das_error(method, *e))
+}
+
+/// Sends a DAS request, retrying rate limiting and other transient failures with the
+/// default [`RetryPolicy`]
+fn das_request_with_retry<T: DeserializeOwned>(
+    client: &DasClient,
+    method: &'static str,
+    params: Value,
+) -> SssResult<T> {
+    let (response, _) = with_backoff::<_, Box<ClientError>>(
+        &RetryPolicy::default(),
+        |e| {
+            if is_rate_limited(e) {
+                Some("rate limited")
+            } else {
+                classify_client_error(e).map(|rule| rule.reason)
+            }
+        },
+        |e, _| {
+            if is_rate_limited(e) {
+                record_rate_limit_hit();
+            }
+            record_rpc_retry();
+        },
+        || client.send::<T>(method, params.clone()),
+    );
+    response.map_err(|e| das_error(method, *e))
+}
+
+/// Whether a DAS failure is the provider rate limiting us
+fn is_rate_limited(e: &ClientError) -> bool {
+    match e.kind() {
+        ErrorKind::Reqwest(err) => err.status() == Some(reqwest::StatusCode::TOO_MANY_REQUESTS),
+        ErrorKind::RpcError(RpcError::RpcResponseError { code, message, .. }) => {
+            RATE_LIMIT_CODES.contains(code) || message.to_lowercase().contains("rate limit")
+        }
+        _ => false,
+    }
+}
+
+/// Converts a DAS failure into an `SssError`, marking rate limiting as retryable
+fn das_error(method: &str, e: ClientError) -> SssError {
+    if is_rate_limited(&e) {
+        record_rate_limit_hit();
+        SssError::RpcError(format!(
+            "DAS provider rate limited {}, retry later: {}",
+            method, e
+        ))
+    } else {
+        SssError::RpcError(format!("DAS request {} failed: {}", method, e))
+    }
+}
+
+/// Whether a DAS failure means the asset is unknown to the indexer
+fn is_not_found(e: &ClientError) -> bool {
+    match e.kind() {
+        ErrorKind::RpcError(RpcError::RpcResponseError { code, message, .. }) => {
+            !RATE_LIMIT_CODES.contains(code) && !message.to_lowercase().contains("rate limit")
+        }
+        _ => false,
+    }
+}
+
+/// A digital asset as reported by the DAS API
+///
+/// Only `id` is required; every other field is optional because providers differ in
+/// which fields they return.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DigitalAsset {
+    /// The asset id, which is the mint address for tokens
+    #[serde(with = "pubkey_string")]
+    pub id: Pubkey,
+    /// The asset interface, e.g. `FungibleToken` or `V1_NFT`
+    pub interface: Option<String>,
+    /// The name from the on-chain metadata
+    pub name: Option<String>,
+    /// The symbol from the on-chain metadata
+    pub symbol: Option<String>,
+    /// The URI of the off-chain JSON metadata
+    pub uri: Option<String>,
+    /// The base58 address of the current owner
+    pub owner: Option<String>,
+    /// The owner's token balance, for fungible tokens
+    pub balance: Option<u64>,
+    /// The number of decimal places, for fungible tokens
+    pub decimals: Option<u8>,
+    /// Whether the asset is a compressed NFT
+    pub compressed: bool,
+    /// Where the entry came from
+    pub source: AssetSource,
+    /// Whether the asset is in the trusted mint set, see [`crate::set_trusted_mints`]
+    pub trusted: bool,
+}
+
+/// Origin of a [`DigitalAsset`] entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub enum AssetSource {
+    /// Returned by the DAS API
+    #[default]
+    Das,
+    /// Built from token and metadata accounts over plain RPC because DAS was unavailable
+    ///
+    /// `interface` and `compressed` are not known for these entries.
+    Fallback,
+}
+
+/// Assets held by a wallet
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct AssetList {
+    /// The assets found
+    pub assets: Vec<DigitalAsset>,
+    /// Reasons the list may be incomplete, empty when DAS answered
+    pub warnings: Vec<String>,
+}
+
+/// Options for listing the assets of a wallet
+#[derive(Debug, Clone)]
+pub struct AssetQueryOptions {
+    /// List fungible tokens over plain RPC if the DAS request fails
+    pub fallback_to_rpc: bool,
+}
+
+impl Default for AssetQueryOptions {
+    fn default() -> Self {
+        Self {
+            fallback_to_rpc: true,
+        }
+    }
+}
+
+/// Raw DAS asset item, tolerant of missing optional sections
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct RawAsset {
+    id: String,
+    interface: Option<String>,
+    content: Option<RawContent>,
+    ownership: Option<RawOwnership>,
+    token_info: Option<RawTokenInfo>,
+    compression: Option<RawCompression>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct RawContent {
+    json_uri: Option<String>,
+    metadata: Option<RawMetadata>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct RawMetadata {
+    name: Option<String>,
+    symbol: Option<String>,
+    /// Kept as JSON, since providers differ in the shape of attribute values
+    attributes: Option<Value>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct RawOwnership {
+    owner: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct RawTokenInfo {
+    balance: Option<u64>,
+    decimals: Option<u8>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct RawCompression {
+    compressed: bool,
+}
+
+/// Raw `getAssetsByOwner` page
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct RawAssetPage {
+    items: Vec<RawAsset>,
+    /// Total number of assets, returned when `showGrandTotal` is requested
+    grand_total: Option<u64>,
+}
+
+impl TryFrom<RawAsset> for DigitalAsset {
+    type Error = SssError;
+
+    fn try_from(raw: RawAsset) -> SssResult<Self> {
+        let id = raw
+            .id
+            .parse()
+            .into_sss_error("Failed to parse asset id from rpc")?;
+        let (uri, metadata) = match raw.content {
+            Some(content) => (content.json_uri.filter(|u| !u.is_empty()), content.metadata),
+            None => (None, None),
+        };
+        let (name, symbol) = match metadata {
+            Some(metadata) => (metadata.name, metadata.symbol),
+            None => (None, None),
+        };
+        let (balance, decimals) = match raw.token_info {
+            Some(info) => (info.balance, info.decimals),
+            None => (None, None),
+        };
+
+        Ok(Self {
+            id,
+            interface: raw.interface,
+            name,
+            symbol,
+            uri,
+            owner: raw.ownership.and_then(|o| o.owner),
+            balance,
+            decimals,
+            compressed: raw.compression.is_some_and(|c| c.compressed),
+            source: AssetSource::Das,
+            trusted: is_trusted_mint(&id),
+        })
+    }
+}
+
+/// Fetches an asset from the DAS API
+///
+/// # Arguments
+///
+/// * `id` - The asset id, which is the mint address for fungible tokens
+///
+/// # Returns
+///
+/// The asset, or `None` if the indexer does not know it
+pub fn get_asset(id: &Pubkey) -> SssResult<Option<DigitalAsset>> {
+    let (_, client) = das()?;
+    match client.send::<Option<RawAsset>>("getAsset", json!({ "id": id.to_string() })) {
+        Ok(Some(raw)) => raw.try_into().map(Some),
+        Ok(None) => Ok(None),
+        // Indexers answer unknown assets with a JSON-RPC error rather than null
+        Err(e) if is_not_found(&e) => Ok(None),
+        Err(e) => Err(das_error("getAsset", *e)),
+    }
+}
+
+/// Fetches many assets from the DAS API with `getAssetBatch`
+///
+/// The ids are sent [`ASSET_BATCH_LIMIT`] at a time. Requests the provider rate limits, and
+/// other transient failures listed in [`RETRYABLE_ERRORS`](crate::RETRYABLE_ERRORS), are
+/// retried with the default [`RetryPolicy`]. Compressed assets are returned like any other,
+/// with [`DigitalAsset::compressed`] set.
+///
+/// # Arguments
+///
+/// * `ids` - The asset ids, which are the mint addresses for tokens
+///
+/// # Returns
+///
+/// One entry per id in the order given, `None` for ids the indexer does not know
+///
+/// # Errors
+///
+/// Returns an `RpcError` if a batch still fails once retries are exhausted
+pub fn fetch_digital_assets(ids: &[Pubkey]) -> SssResult<Vec<Option<DigitalAsset>>> {
+    let (_, client) = das()?;
+    let mut assets = Vec::with_capacity(ids.len());
+    for chunk in ids.chunks(ASSET_BATCH_LIMIT) {
+        let params = json!({ "ids": chunk.iter().map(Pubkey::to_string).collect::<Vec<_>>() });
+        let response: Vec<Option<RawAsset>> =
+            das_request_with_retry(&client, "getAssetBatch", params)?;
+
+        // Providers either answer unknown ids with null or leave them out, so match by id
+        let mut found = BTreeMap::new();
+        for raw in response.into_iter().flatten() {
+            let asset = DigitalAsset::try_from(raw)?;
+            found.insert(asset.id, asset);
+        }
+        assets.extend(chunk.iter().map(|id| found.get(id).cloned()));
+    }
+    Ok(assets)
+}
+
+/// Fetches every asset held by a wallet
+///
+/// Uses the DAS API and falls back to plain RPC if it fails, see
+/// [`fetch_digital_assets_by_owner_with_options`].
+///
+/// # Arguments
+///
+/// * `owner` - The wallet whose assets should be listed
+///
+/// # Returns
+///
+/// The assets held by the wallet and any warnings about missing entries
+pub fn fetch_digital_assets_by_owner(owner: &Pubkey) -> SssResult<AssetList> {
+    fetch_digital_assets_by_owner_with_options(owner, &AssetQueryOptions::default())
+}
+
+/// Fetches every asset held by a wallet with query options
+///
+/// Fungible tokens are included from DAS when the provider supports `displayOptions`. If
+/// the DAS request fails and `fallback_to_rpc` is set, fungible holdings are listed from
+/// the wallet's token accounts and their metadata accounts instead. Such entries are marked
+/// [`AssetSource::Fallback`] and the result carries a warning, because compressed and
+/// non-fungible assets cannot be listed this way.
+///
+/// # Arguments
+///
+/// * `owner` - The wallet whose assets should be listed
+/// * `options` - Whether to fall back to plain RPC
+///
+/// # Returns
+///
+/// The assets held by the wallet and any warnings about missing entries
+pub fn fetch_digital_assets_by_owner_with_options(
+    owner: &Pubkey,
+    options: &AssetQueryOptions,
+) -> SssResult<AssetList> {
+    match fetch_das_assets_by_owner(owner) {
+        Ok(assets) => Ok(AssetList {
+            assets,
+            warnings: Vec::new(),
+        }),
+        Err(e) if options.fallback_to_rpc => Ok(AssetList {
+            assets: fetch_fungible_assets_from_rpc(owner)?,
+            warnings: vec![format!(
+                "DAS unavailable ({}); listed fungible tokens from RPC only, compressed and \
+                 non-fungible assets are missing",
+                e
+            )],
+        }),
+        Err(e) => Err(e),
+    }
+}
+
+/// Lists a wallet's assets through `getAssetsByOwner`
+fn fetch_das_assets_by_owner(owner: &Pubkey) -> SssResult<Vec<DigitalAsset>> {
+    let (provider, _) = das()?;
+    let mut assets = Vec::new();
+    let mut page = 1;
+    loop {
+        let response = fetch_assets_page(&provider, owner, page, false)?;
+        let count = response.items.len();
+        for raw in response.items {
+            assets.push(raw.try_into()?);
+        }
+
+        if count < ASSETS_PAGE_LIMIT {
+            return Ok(assets);
+        }
+        page += 1;
+    }
+}
+
+/// Fetches one page of `getAssetsByOwner`
+fn fetch_assets_page(
+    provider: &DasProvider,
+    owner: &Pubkey,
+    page: u32,
+    grand_total: bool,
+) -> SssResult<RawAssetPage> {
+    let mut params = json!({
+        "ownerAddress": owner.to_string(),
+        "page": page,
+        "limit": ASSETS_PAGE_LIMIT,
+    });
+    if provider.supports_display_options {
+        params["displayOptions"] = json!({
+            "showFungible": true,
+            "showGrandTotal": grand_total,
+        });
+    }
+    das_request("getAssetsByOwner", params)
+}
+
+/// Called after every page of an [`AssetStream`]
+pub type AssetProgressCallback = Arc<dyn Fn(AssetProgress) + Send + Sync>;
+
+/// How far an [`AssetStream`] has got
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct AssetProgress {
+    /// Assets delivered so far, including those before a resumed cursor
+    pub fetched: u64,
+    /// Total number of assets, if the provider reports it
+    pub estimated_total: Option<u64>,
+}
+
+/// Options for streaming the assets of a wallet
+#[derive(Clone)]
+pub struct AssetStreamOptions {
+    /// The page to start from, e.g. a [`AssetPage::next_cursor`] saved by an earlier run
+    ///
+    /// Starts from the first page if `None`.
+    pub cursor: Option<u32>,
+    /// Attempts per page before the stream yields the error
+    pub page_attempts: u32,
+    /// Called after every page
+    pub on_progress: Option<AssetProgressCallback>,
+}
+
+impl Default for AssetStreamOptions {
+    fn default() -> Self {
+        Self {
+            cursor: None,
+            page_attempts: DEFAULT_PAGE_ATTEMPTS,
+            on_progress: None,
+        }
+    }
+}
+
+/// One page of a wallet's assets
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AssetPage {
+    /// The page number, starting at 1
+    pub page: u32,
+    /// The assets on the page
+    pub assets: Vec<DigitalAsset>,
+    /// The cursor to resume from after this page, `None` on the last page
+    pub next_cursor: Option<u32>,
+    /// Progress after this page
+    pub progress: AssetProgress,
+}
+
+/// Lazily fetched pages of a wallet's assets, see [`fetch_all_digital_assets`]
+///
+/// Each call to `next` fetches one page, so only the page being processed is held in
+/// memory. A page that still fails after its retries is yielded as an error and ends the
+/// stream; [`AssetStream::cursor`] then tells where to resume.
+pub struct AssetStream {
+    provider: DasProvider,
+    owner: Pubkey,
+    options: AssetStreamOptions,
+    /// The next page to fetch, `None` once every page has been delivered
+    next_page: Option<u32>,
+    /// Whether a page failed, which ends the stream
+    failed: bool,
+    fetched: u64,
+    estimated_total: Option<u64>,
+}
+
+impl AssetStream {
+    /// Returns the page the stream would fetch next, to save for resuming later
+    ///
+    /// After a failed page this is the page that failed. Returns `None` once every page has
+    /// been delivered.
+    pub fn cursor(&self) -> Option<u32> {
+        self.next_page
+    }
+
+    /// Fetches a page, retrying failures with a doubling delay
+    fn fetch_page(&self, page: u32) -> SssResult<RawAssetPage> {
+        let mut delay = PAGE_RETRY_DELAY;
+        let mut attempt = 1;
+        loop {
+            match fetch_assets_page(&self.provider, &self.owner, page, true) {
+                Ok(response) => return Ok(response),
+                Err(e) if attempt >= self.options.page_attempts => {
+                    return Err(SssError::RpcError(format!(
+                        "Failed to fetch asset page {} after {} attempts: {}",
+                        page, attempt, e
+                    )));
+                }
+                Err(_) => {
+                    record_rpc_retry();
+                    thread::sleep(delay);
+                    delay *= 2;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for AssetStream {
+    type Item = SssResult<AssetPage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let page = self.next_page?;
+        let response = match self.fetch_page(page) {
+            Ok(response) => response,
+            Err(e) => {
+                // The cursor stays at the failed page so callers can resume from it
+                self.failed = true;
+                return Some(Err(e));
+            }
+        };
+
+        let count = response.items.len();
+        let assets = match response
+            .items
+            .into_iter()
+            .map(DigitalAsset::try_from)
+            .collect::<SssResult<Vec<_>>>()
+        {
+            Ok(assets) => assets,
+            Err(e) => {
+                self.failed = true;
+                return Some(Err(e));
+            }
+        };
+
+        self.fetched += count as u64;
+        if response.grand_total.is_some() {
+            self.estimated_total = response.grand_total;
+        }
+        self.next_page = (count >= ASSETS_PAGE_LIMIT).then_some(page + 1);
+        let progress = AssetProgress {
+            fetched: self.fetched,
+            estimated_total: self.estimated_total,
+        };
+        if let Some(on_progress) = &self.options.on_progress {
+            on_progress(progress);
+        }
+
+        Some(Ok(AssetPage {
+            page,
+            assets,
+            next_cursor: self.next_page,
+            progress,
+        }))
+    }
+}
+
+/// Streams every asset held by a wallet page by page
+///
+/// Meant for wallets too large to list with [`fetch_digital_assets_by_owner`]: pages are
+/// fetched only as the stream is iterated, and each can be processed and dropped before the
+/// next one is fetched. A failed page is retried on its own instead of restarting from the
+/// first page. Unlike [`fetch_digital_assets_by_owner`] there is no plain RPC fallback.
+///
+/// # Arguments
+///
+/// * `owner` - The wallet whose assets should be listed
+/// * `options` - The cursor to resume from, retries and the progress callback
+///
+/// # Returns
+///
+/// An iterator over the pages of assets
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if no DAS provider is configured
+pub fn fetch_all_digital_assets(
+    owner: &Pubkey,
+    options: AssetStreamOptions,
+) -> SssResult<AssetStream> {
+    let (provider, _) = das()?;
+    let first_page = options.cursor.unwrap_or(1).max(1);
+    let fetched = u64::from(first_page - 1) * ASSETS_PAGE_LIMIT as u64;
+    Ok(AssetStream {
+        provider,
+        owner: *owner,
+        options,
+        next_page: Some(first_page),
+        failed: false,
+        fetched,
+        estimated_total: None,
+    })
+}
+
+/// Criteria of [`search_assets`]; unset fields match everything
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DasSearchFilter {
+    /// Only assets held by this wallet
+    pub owner: Option<Pubkey>,
+    /// Only assets listing this creator
+    pub creator: Option<Pubkey>,
+    /// Only assets in this collection
+    pub collection: Option<Pubkey>,
+    /// Only burnt assets if `true`, only assets not burnt if `false`
+    pub burnt: Option<bool>,
+    /// Only assets whose off-chain metadata URI is exactly this
+    pub json_uri: Option<String>,
+    /// Only assets having every one of these attributes, by trait type and value
+    pub attributes: BTreeMap<String, String>,
+}
+
+/// Assets found by [`search_assets`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct AssetSearch {
+    /// The matching assets
+    pub assets: Vec<DigitalAsset>,
+    /// The filters the provider could not apply, which were applied to the fetched pages
+    /// instead, e.g. `attribute batch`
+    pub client_side_filters: Vec<String>,
+}
+
+/// Finds assets across all owners with the DAS `searchAssets` method
+///
+/// Owner, creator, collection and burnt filters are applied by the provider. The JSON URI
+/// and attribute filters are too if [`DasProvider::supports_json_uri_search`] and
+/// [`DasProvider::supports_attribute_search`] say so; otherwise every page matching the
+/// other filters is fetched and filtered here, which
+/// [`AssetSearch::client_side_filters`] reports. Pages are retried like
+/// [`fetch_digital_assets`].
+///
+/// # Arguments
+///
+/// * `filter` - The criteria the assets must match
+///
+/// # Returns
+///
+/// The matching assets and the filters applied on this side
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if no filter narrows the search on the provider's side, since
+/// that would page through every asset the indexer knows, and an `RpcError` if a page
+/// still fails once retries are exhausted
+pub fn search_assets(filter: &DasSearchFilter) -> SssResult<AssetSearch> {
+    let (provider, client) = das()?;
+    search_pages(&provider, filter, |params| {
+        das_request_with_retry(&client, "searchAssets", params)
+    })
+}
+
+/// Pages through `searchAssets` with the parameters a provider supports, filtering the
+/// fetched assets by the criteria it does not
+fn search_pages(
+    provider: &DasProvider,
+    filter: &DasSearchFilter,
+    mut fetch_page: impl FnMut(Value) -> SssResult<RawAssetPage>,
+) -> SssResult<AssetSearch> {
+    let mut params = json!({ "limit": ASSETS_PAGE_LIMIT });
+    if let Some(owner) = filter.owner {
+        params["ownerAddress"] = json!(owner.to_string());
+    }
+    if let Some(creator) = filter.creator {
+        params["creatorAddress"] = json!(creator.to_string());
+    }
+    if let Some(collection) = filter.collection {
+        params["grouping"] = json!(["collection", collection.to_string()]);
+    }
+    if let Some(burnt) = filter.burnt {
+        params["burnt"] = json!(burnt);
+    }
+
+    let mut client_side_filters = Vec::new();
+    let mut uri = None;
+    if let Some(json_uri) = &filter.json_uri {
+        if provider.supports_json_uri_search {
+            params["jsonUri"] = json!(json_uri);
+        } else {
+            uri = Some(json_uri.as_str());
+            client_side_filters.push("json_uri".to_string());
+        }
+    }
+    let mut attributes = None;
+    if !filter.attributes.is_empty() {
+        if provider.supports_attribute_search {
+            params["attributes"] = filter
+                .attributes
+                .iter()
+                .map(|(trait_type, value)| json!({ "trait_type": trait_type, "value": value }))
+                .collect();
+        } else {
+            attributes = Some(&filter.attributes);
+            client_side_filters.extend(
+                filter
+                    .attributes
+                    .keys()
+                    .map(|trait_type| format!("attribute {}", trait_type)),
+            );
+        }
+    }
+    let narrowed = filter.owner.is_some()
+        || filter.creator.is_some()
+        || filter.collection.is_some()
+        || params.get("jsonUri").is_some()
+        || params.get("attributes").is_some();
+    if !narrowed {
+        return Err(SssError::ConfigError(
+            "Asset search needs an owner, creator or collection filter, or a filter the DAS \
+             provider applies itself"
+                .to_string(),
+        ));
+    }
+
+    let mut assets = Vec::new();
+    let mut page = 1;
+    loop {
+        params["page"] = json!(page);
+        let response = fetch_page(params.clone())?;
+        let count = response.items.len();
+        for raw in response.items {
+            if matches_client_side(&raw, uri, attributes) {
+                assets.push(raw.try_into()?);
+            }
+        }
+
+        if count < ASSETS_PAGE_LIMIT {
+            return Ok(AssetSearch {
+                assets,
+                client_side_filters,
+            });
+        }
+        page += 1;
+    }
+}
+
+/// Whether an asset has the JSON URI and every attribute, where given
+fn matches_client_side(
+    raw: &RawAsset,
+    uri: Option<&str>,
+    attributes: Option<&BTreeMap<String, String>>,
+) -> bool {
+    let content = raw.content.as_ref();
+    if let Some(uri) = uri
+        && content.and_then(|content| content.json_uri.as_deref()) != Some(uri)
+    {
+        return false;
+    }
+    let Some(attributes) = attributes else {
+        return true;
+    };
+    let listed = content
+        .and_then(|content| content.metadata.as_ref())
+        .and_then(|metadata| metadata.attributes.as_ref())
+        .and_then(Value::as_array);
+    let Some(listed) = listed else {
+        return false;
+    };
+    attributes.iter().all(|(trait_type, value)| {
+        listed.iter().any(|attribute| {
+            attribute.get("trait_type").and_then(Value::as_str) == Some(trait_type)
+                && match attribute.get("value") {
+                    Some(Value::String(listed)) => listed == value,
+                    Some(Value::Number(listed)) => value
+                        .parse::<serde_json::Number>()
+                        .is_ok_and(|v| v == *listed),
+                    Some(Value::Bool(listed)) => value.parse::<bool>() == Ok(*listed),
+                    _ => false,
+                }
+        })
+    })
+}
+
+/// Lists a wallet's fungible token balances from its token accounts and metadata accounts
+fn fetch_fungible_assets_from_rpc(owner: &Pubkey) -> SssResult<Vec<DigitalAsset>> {
+    // Sum balances per mint across both token programs
+    let mut balances: BTreeMap<Pubkey, u64> = BTreeMap::new();
+    for program in [spl_token::id(), spl_token_2022::id()] {
+        let response: Response<Vec<RpcKeyedAccount>> = RPC_CLIENT
+            .send(
+                RpcRequest::GetTokenAccountsByOwner,
+                json!([
+                    owner.to_string(),
+                    { "programId": program.to_string() },
+                    { "encoding": "base64" },
+                ]),
+            )
+            .into_sss_error("Failed to get token accounts from rpc")?;
+
+        for keyed in response.value {
+            let Some(data) = keyed.account.data.decode() else {
+                continue;
+            };
+            let Ok(account) = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&data)
+            else {
+                continue;
+            };
+            if account.base.amount > 0 {
+                let total = balances.entry(account.base.mint).or_insert(0);
+                *total = total.saturating_add(account.base.amount);
+            }
+        }
+    }
+
+    let mints: Vec<Pubkey> = balances.keys().copied().collect();
+    let mut assets = Vec::with_capacity(mints.len());
+    for chunk in mints.chunks(MAX_MULTIPLE_ACCOUNTS / 2) {
+        // Fetch each mint (for decimals) and its metadata account in one request
+        let mut keys = Vec::with_capacity(chunk.len() * 2);
+        for mint in chunk {
+            keys.push(*mint);
+            keys.push(metadata_pda(mint));
+        }
+        let accounts = RPC_CLIENT
+            .get_multiple_accounts(&keys)
+            .into_sss_error("Failed to get token metadata accounts from rpc")?;
+
+        for (mint, pair) in chunk.iter().zip(accounts.chunks(2)) {
+            let decimals = pair[0]
+                .as_ref()
+                .and_then(|a| {
+                    StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&a.data).ok()
+                })
+                .map(|m| m.base.decimals);
+            let metadata = pair[1]
+                .as_ref()
+                .and_then(|a| Metadata::from_bytes(&a.data).ok());
+
+            // Only fungible tokens can be identified reliably without DAS
+            let fungible = match metadata.as_ref().and_then(|m| m.token_standard) {
+                Some(standard) => {
+                    matches!(
+                        standard,
+                        TokenStandard::Fungible | TokenStandard::FungibleAsset
+                    )
+                }
+                None => decimals.unwrap_or(0) > 0,
+            };
+            if !fungible {
+                continue;
+            }
+
+            let trim = |s: &str| {
+                let s = s.trim_end_matches('\0').to_string();
+                (!s.is_empty()).then_some(s)
+            };
+            assets.push(DigitalAsset {
+                id: *mint,
+                interface: None,
+                name: metadata.as_ref().and_then(|m| trim(&m.name)),
+                symbol: metadata.as_ref().and_then(|m| trim(&m.symbol)),
+                uri: metadata.as_ref().and_then(|m| trim(&m.uri)),
+                owner: Some(owner.to_string()),
+                balance: balances.get(mint).copied(),
+                decimals,
+                compressed: false,
+                source: AssetSource::Fallback,
+                trusted: is_trusted_mint(mint),
+            });
+        }
+    }
+
+    Ok(assets)
+}
+
+/// Outcome of waiting for the DAS indexer to pick up an asset
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct IndexingStatus {
+    /// Whether the asset became visible before the timeout
+    ///
+    /// `false` is a warning rather than a failure: the on-chain state is final, the
+    /// indexer has only not caught up yet.
+    pub indexed: bool,
+    /// How long the wait took
+    pub elapsed: Duration,
+}
+
+/// Polls the DAS API until an asset is indexed or the timeout elapses
+///
+/// # Arguments
+///
+/// * `mint` - The mint address of the asset
+/// * `timeout` - How long to keep polling
+///
+/// # Returns
+///
+/// Whether the asset was indexed and how long it took. A timeout is reported through
+/// [`IndexingStatus::indexed`], not as an error.
+///
+/// # Errors
+///
+/// Returns an error if the DAS endpoint cannot be reached
+pub fn wait_for_asset_indexed(mint: Pubkey, timeout: Duration) -> SssResult<IndexingStatus> {
+    let start = Instant::now();
+    loop {
+        if get_asset(&mint)?.is_some() {
+            return Ok(IndexingStatus {
+                indexed: true,
+                elapsed: start.elapsed(),
+            });
+        }
+
+        let elapsed = start.elapsed();
+        if elapsed >= timeout {
+            return Ok(IndexingStatus {
+                indexed: false,
+                elapsed,
+            });
+        }
+        thread::sleep(INDEXING_POLL_INTERVAL.min(timeout - elapsed));
+    }
+}
+
+/// Waits for a freshly written asset to be indexed, if the caller asked for it
+///
+/// The transaction has already been confirmed at this point, so RPC failures while polling
+/// are reported as a timeout instead of hiding the successful result.
+pub(crate) fn wait_after_write(mint: Pubkey, timeout: Option<Duration>) -> Option<IndexingStatus> {
+    let timeout = timeout?;
+    let start = Instant::now();
+    Some(
+        wait_for_asset_indexed(mint, timeout).unwrap_or_else(|_| IndexingStatus {
+            indexed: false,
+            elapsed: start.elapsed(),
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::das_cache::{DasCacheEntry, DasCachedResponse};
+    use std::fs;
+    use std::path::Path;
+
+    const CREATOR: &str = "DgX9xEoN7RZGWevFVCy13JuzKsnmAx9B3VLfvoJxwqKn";
+    const COLLECTION: &str = "Hrg38XcS7wGNGKmCwuGfUXdf7RaxJHFPFsZ2ALfgTgMV";
+    const OWNER: &str = "3Wga6r7yBhshmEnNwTScK1GsAyE5ZkoV6TiheQAhEs6D";
+
+    /// Runs a search answered by the `searchAssets` responses recorded in
+    /// `fixtures/das_search`, returning the result and the parameters of every page fetched
+    fn replay(provider: &DasProvider, filter: &DasSearchFilter) -> (AssetSearch, Vec<Value>) {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/das_search");
+        let recorded: Vec<DasCacheEntry> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| serde_json::from_slice(&fs::read(entry.unwrap().path()).unwrap()).unwrap())
+            .collect();
+        let mut requests = Vec::new();
+        let search = search_pages(provider, filter, |params| {
+            requests.push(params.clone());
+            let entry = recorded
+                .iter()
+                .find(|entry| {
+                    entry.request.method == "searchAssets" && entry.request.params == params
+                })
+                .unwrap_or_else(|| panic!("no recorded response for {}", params));
+            match &entry.response {
+                DasCachedResponse::Result(result) => Ok(serde_json::from_value(result.clone())
+                    .into_sss_error("Failed to parse recorded page")?),
+                DasCachedResponse::Error { message, .. } => {
+                    Err(SssError::RpcError(message.clone()))
+                }
+            }
+        })
+        .unwrap();
+        (search, requests)
+    }
+
+    fn ids(search: &AssetSearch) -> Vec<String> {
+        search
+            .assets
+            .iter()
+            .map(|asset| asset.id.to_string())
+            .collect()
+    }
+
+    fn names(search: &AssetSearch) -> Vec<&str> {
+        search
+            .assets
+            .iter()
+            .map(|asset| asset.name.as_deref().unwrap())
+            .collect()
+    }
+
+    fn batch_filter(value: &str) -> DasSearchFilter {
+        DasSearchFilter {
+            creator: Some(CREATOR.parse().unwrap()),
+            attributes: BTreeMap::from([("batch".to_string(), value.to_string())]),
+            ..DasSearchFilter::default()
+        }
+    }
+
+    #[test]
+    fn attributes_are_filtered_client_side_when_the_provider_cannot() {
+        let provider = DasProvider::helius("https://mainnet.helius-rpc.com", "HELIUS_API_KEY");
+        let (search, requests) = replay(&provider, &batch_filter("B-2024-11"));
+
+        assert_eq!(names(&search), vec!["Pallet 0", "Pallet 3"]);
+        assert_eq!(search.client_side_filters, vec!["attribute batch"]);
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].get("attributes").is_none());
+    }
+
+    #[test]
+    fn attributes_are_sent_to_a_provider_supporting_them() {
+        let provider = DasProvider {
+            supports_attribute_search: true,
+            ..DasProvider::standard("https://das.example.com")
+        };
+        let (search, requests) = replay(&provider, &batch_filter("B-2024-11"));
+
+        assert_eq!(names(&search), vec!["Pallet 0", "Pallet 3"]);
+        assert!(search.client_side_filters.is_empty());
+        assert_eq!(
+            requests[0]["attributes"],
+            json!([{ "trait_type": "batch", "value": "B-2024-11" }])
+        );
+
+        // Both ways of filtering agree on the same recorded assets
+        let helius = DasProvider::helius("https://mainnet.helius-rpc.com", "HELIUS_API_KEY");
+        let (client_side, _) = replay(&helius, &batch_filter("B-2024-11"));
+        assert_eq!(ids(&search), ids(&client_side));
+    }
+
+    #[test]
+    fn numeric_and_boolean_attribute_values_match_their_text() {
+        let provider = DasProvider::triton("https://example.rpcpool.com", "TRITON_TOKEN");
+        let (search, _) = replay(&provider, &batch_filter("2024"));
+        assert_eq!(names(&search), vec!["Pallet 4"]);
+
+        let mut filter = batch_filter("2024");
+        filter
+            .attributes
+            .insert("sealed".to_string(), "true".to_string());
+        let (search, _) = replay(&provider, &filter);
+        assert_eq!(names(&search), vec!["Pallet 4"]);
+        assert_eq!(
+            search.client_side_filters,
+            vec!["attribute batch", "attribute sealed"]
+        );
+
+        filter
+            .attributes
+            .insert("sealed".to_string(), "false".to_string());
+        assert!(replay(&provider, &filter).0.assets.is_empty());
+    }
+
+    #[test]
+    fn every_attribute_must_match() {
+        let provider = DasProvider::triton("https://example.rpcpool.com", "TRITON_TOKEN");
+        let mut filter = batch_filter("B-2024-11");
+        filter
+            .attributes
+            .insert("site".to_string(), "Hamburg".to_string());
+        let (search, _) = replay(&provider, &filter);
+        assert_eq!(names(&search), vec!["Pallet 3"]);
+    }
+
+    #[test]
+    fn json_uri_is_filtered_client_side_with_the_other_filters_sent() {
+        let provider = DasProvider::triton("https://example.rpcpool.com", "TRITON_TOKEN");
+        let filter = DasSearchFilter {
+            owner: Some(OWNER.parse().unwrap()),
+            collection: Some(COLLECTION.parse().unwrap()),
+            json_uri: Some("https://meta.example.com/shared.json".to_string()),
+            ..DasSearchFilter::default()
+        };
+        let (search, requests) = replay(&provider, &filter);
+
+        assert_eq!(names(&search), vec!["Pallet 4", "Pallet 5"]);
+        assert_eq!(search.client_side_filters, vec!["json_uri"]);
+        assert_eq!(requests[0]["ownerAddress"], json!(OWNER));
+        assert_eq!(requests[0]["grouping"], json!(["collection", COLLECTION]));
+        assert!(requests[0].get("jsonUri").is_none());
+        for asset in &search.assets {
+            assert_eq!(asset.owner.as_deref(), Some(OWNER));
+            assert_eq!(
+                asset.uri.as_deref(),
+                Some("https://meta.example.com/shared.json")
+            );
+        }
+    }
+
+    #[test]
+    fn full_pages_are_followed_until_a_short_one() {
+        let provider = DasProvider::triton("https://example.rpcpool.com", "TRITON_TOKEN");
+        let page = |count: usize, matching: usize| {
+            let items = (0..count)
+                .map(|i| {
+                    let batch = if i < matching {
+                        "B-2024-11"
+                    } else {
+                        "B-2024-10"
+                    };
+                    json!({
+                        "id": Pubkey::new_unique().to_string(),
+                        "content": { "metadata": { "attributes": [
+                            { "trait_type": "batch", "value": batch }
+                        ] } },
+                    })
+                })
+                .collect::<Vec<_>>();
+            json!({ "items": items })
+        };
+        let mut pages = vec![
+            page(ASSETS_PAGE_LIMIT, 3),
+            page(ASSETS_PAGE_LIMIT, 0),
+            page(10, 2),
+        ]
+        .into_iter();
+        let mut requested = Vec::new();
+        let search = search_pages(&provider, &batch_filter("B-2024-11"), |params| {
+            requested.push(params["page"].clone());
+            Ok(serde_json::from_value(pages.next().unwrap()).unwrap())
+        })
+        .unwrap();
+
+        assert_eq!(search.assets.len(), 5);
+        assert_eq!(requested, vec![json!(1), json!(2), json!(3)]);
+    }
+
+    #[test]
+    fn search_without_a_provider_side_filter_is_refused() {
+        let provider = DasProvider::triton("https://example.rpcpool.com", "TRITON_TOKEN");
+        let filter = DasSearchFilter {
+            burnt: Some(false),
+            json_uri: Some("https://meta.example.com/shared.json".to_string()),
+            attributes: BTreeMap::from([("batch".to_string(), "B-2024-11".to_string())]),
+            ..DasSearchFilter::default()
+        };
+        let result = search_pages(&provider, &filter, |_| panic!("nothing may be fetched"));
+        assert!(matches!(result, Err(SssError::ConfigError(_))));
+
+        // The same filters narrow the search on a provider applying them
+        let provider = DasProvider {
+            supports_attribute_search: true,
+            ..provider
+        };
+        let result = search_pages(&provider, &filter, |params| {
+            assert_eq!(params["burnt"], json!(false));
+            Ok(RawAssetPage::default())
+        });
+        assert_eq!(result.unwrap().client_side_filters, vec!["json_uri"]);
+    }
+}
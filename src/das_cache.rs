@@ -0,0 +1,365 @@
+//! Record/replay cache of DAS responses
+//!
+//! With `SSS_DAS_CACHE=record`, every DAS request is sent to the provider and its response
+//! written to `SSS_DAS_CACHE_DIR` (default `.sss_das_cache`), one JSON file per request named
+//! after the method and a hash of its parameters. With `SSS_DAS_CACHE=replay`, requests are
+//! answered from those files without touching the network, and a request that was never
+//! recorded fails naming the file it looked for. Replaying the same files always gives the
+//! same answers, so a recorded directory also serves as a fixture of a provider's response
+//! shapes.
+//!
+//! Each file holds a [`DasCacheEntry`]: the request (endpoint, method and parameters), the
+//! response (the JSON-RPC `result`, or the `error` code and message) and when it was
+//! recorded. API keys are scrubbed from the stored endpoint; keys sent in headers are never
+//! stored. Only the method and parameters are hashed, so recordings replay against any
+//! endpoint. Use [`prune_das_cache`] to drop stale entries.
+
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::load_dotenv;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use solana_rpc_client::http_sender::HttpSender;
+use solana_rpc_client::rpc_sender::RpcSender;
+use solana_rpc_client_api::client_error::{Error as ClientError, ErrorKind, reqwest};
+use solana_rpc_client_api::request::{RpcError, RpcRequest, RpcResponseErrorData};
+use solana_sdk::hash::hashv;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::runtime::Runtime;
+
+/// Directory recordings are kept in when `SSS_DAS_CACHE_DIR` is not set
+pub const DEFAULT_DAS_CACHE_DIR: &str = ".sss_das_cache";
+
+/// Placeholder written in place of scrubbed secrets
+const REDACTED: &str = "REDACTED";
+
+/// Whether DAS responses are recorded or replayed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DasCacheMode {
+    /// Requests go to the provider and nothing is stored
+    #[default]
+    Off,
+    /// Requests go to the provider and their responses are stored
+    Record,
+    /// Requests are answered from stored responses only
+    Replay,
+}
+
+impl DasCacheMode {
+    /// Reads the mode from `SSS_DAS_CACHE` (`off`, `record` or `replay`, default `off`)
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ConfigError` if `SSS_DAS_CACHE` has an unknown value
+    pub fn from_env() -> SssResult<Self> {
+        load_dotenv();
+        match env::var("SSS_DAS_CACHE").as_deref() {
+            Err(_) | Ok("") | Ok("off") => Ok(Self::Off),
+            Ok("record") => Ok(Self::Record),
+            Ok("replay") => Ok(Self::Replay),
+            Ok(other) => Err(SssError::ConfigError(format!(
+                "Unknown SSS_DAS_CACHE value '{}', expected off, record or replay",
+                other
+            ))),
+        }
+    }
+}
+
+/// A recorded DAS request and its response, the contents of one cache file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DasCacheEntry {
+    /// The request as sent, with secrets scrubbed
+    pub request: DasCachedRequest,
+    /// The provider's answer
+    pub response: DasCachedResponse,
+    /// When the response was recorded, in seconds since the Unix epoch
+    pub recorded_at: u64,
+}
+
+/// A recorded DAS request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DasCachedRequest {
+    /// The provider endpoint, with API keys replaced by `REDACTED`
+    pub endpoint: String,
+    /// The JSON-RPC method, e.g. `getAssetsByOwner`
+    pub method: String,
+    /// The JSON-RPC parameters
+    pub params: Value,
+}
+
+/// A recorded DAS response
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DasCachedResponse {
+    /// The JSON-RPC `result`
+    Result(Value),
+    /// A JSON-RPC error, e.g. an indexer reporting an unknown asset
+    Error {
+        /// The JSON-RPC error code
+        code: i64,
+        /// The error message
+        message: String,
+    },
+}
+
+/// RPC client for DAS requests, recording or replaying responses when the cache is enabled
+///
+/// Requests go through the sender directly since DAS methods take named parameters, which
+/// `RpcClient` refuses.
+pub(crate) struct DasClient {
+    sender: HttpSender,
+    runtime: Runtime,
+    mode: DasCacheMode,
+    dir: PathBuf,
+    /// The endpoint as stored in recordings
+    endpoint: String,
+}
+
+impl DasClient {
+    /// Wraps a provider client, reading the cache settings from the environment
+    ///
+    /// # Arguments
+    ///
+    /// * `sender` - The sender of requests to the provider
+    /// * `api_key` - The provider's API key, scrubbed from recordings
+    /// * `key_param` - The query parameter carrying the key, if any
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ConfigError` if `SSS_DAS_CACHE` has an unknown value, or an `RpcError` if
+    /// the client runtime cannot be started
+    pub(crate) fn new(
+        sender: HttpSender,
+        api_key: Option<&str>,
+        key_param: Option<&str>,
+    ) -> SssResult<Self> {
+        let mode = DasCacheMode::from_env()?;
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .enable_time()
+            .build()
+            .into_sss_error("Failed to start DAS client runtime")?;
+        let dir = env::var("SSS_DAS_CACHE_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| PathBuf::from(DEFAULT_DAS_CACHE_DIR));
+        let endpoint = scrub_endpoint(&sender.url(), api_key, key_param);
+        Ok(Self {
+            sender,
+            runtime,
+            mode,
+            dir,
+            endpoint,
+        })
+    }
+
+    /// Sends a DAS request, or answers it from the cache in replay mode
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - The JSON-RPC method
+    /// * `params` - The JSON-RPC parameters
+    pub(crate) fn send<T: DeserializeOwned>(
+        &self,
+        method: &'static str,
+        params: Value,
+    ) -> Result<T, Box<ClientError>> {
+        let response = match self.mode {
+            DasCacheMode::Off => {
+                let result = self.call(method, params)?;
+                DasCachedResponse::Result(result)
+            }
+            DasCacheMode::Replay => self.replay(method, &params).map_err(custom_error)?,
+            DasCacheMode::Record => {
+                let response = match self.call(method, params.clone()) {
+                    Ok(result) => DasCachedResponse::Result(result),
+                    Err(e) => match e.kind() {
+                        ErrorKind::RpcError(RpcError::RpcResponseError {
+                            code, message, ..
+                        }) => DasCachedResponse::Error {
+                            code: *code,
+                            message: message.clone(),
+                        },
+                        // Transport failures say nothing about the provider, so are not kept
+                        _ => return Err(e),
+                    },
+                };
+                self.record(method, params, &response)
+                    .map_err(custom_error)?;
+                response
+            }
+        };
+
+        match response {
+            DasCachedResponse::Result(result) => {
+                serde_json::from_value(result).map_err(|e| Box::new(e.into()))
+            }
+            DasCachedResponse::Error { code, message } => Err(Box::new(
+                ErrorKind::RpcError(RpcError::RpcResponseError {
+                    code,
+                    message,
+                    data: RpcResponseErrorData::Empty,
+                })
+                .into(),
+            )),
+        }
+    }
+
+    /// Sends a request to the provider
+    fn call(&self, method: &'static str, params: Value) -> Result<Value, Box<ClientError>> {
+        let request = RpcRequest::Custom { method };
+        self.runtime
+            .block_on(self.sender.send(request, params))
+            .map_err(|e| Box::new(e.into_with_request(request)))
+    }
+
+    /// Reads the recorded response to a request
+    fn replay(&self, method: &str, params: &Value) -> Result<DasCachedResponse, String> {
+        let path = self.dir.join(entry_file_name(method, params));
+        let json = fs::read_to_string(&path).map_err(|e| {
+            format!(
+                "No recorded DAS response for {} {} at {} (SSS_DAS_CACHE=replay): {}",
+                method,
+                params,
+                path.display(),
+                e
+            )
+        })?;
+        let entry: DasCacheEntry = serde_json::from_str(&json)
+            .map_err(|e| format!("Invalid DAS cache entry {}: {}", path.display(), e))?;
+        Ok(entry.response)
+    }
+
+    /// Writes a response to the cache
+    fn record(
+        &self,
+        method: &str,
+        params: Value,
+        response: &DasCachedResponse,
+    ) -> Result<(), String> {
+        let path = self.dir.join(entry_file_name(method, &params));
+        let entry = DasCacheEntry {
+            request: DasCachedRequest {
+                endpoint: self.endpoint.clone(),
+                method: method.to_string(),
+                params,
+            },
+            response: response.clone(),
+            recorded_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        };
+        let mut json = serde_json::to_string_pretty(&entry)
+            .map_err(|e| format!("Failed to serialize DAS cache entry: {}", e))?;
+        json.push('\n');
+
+        let tmp = path.with_extension("tmp");
+        fs::create_dir_all(&self.dir)
+            .and_then(|()| fs::write(&tmp, json))
+            .and_then(|()| fs::rename(&tmp, &path))
+            .map_err(|e| format!("Failed to record DAS response to {}: {}", path.display(), e))
+    }
+}
+
+/// Wraps a cache failure in a client error, so it is reported like a failed request
+fn custom_error(message: String) -> Box<ClientError> {
+    Box::new(ErrorKind::Custom(message).into())
+}
+
+/// Names the cache file of a request after its method and a hash of its parameters
+fn entry_file_name(method: &str, params: &Value) -> String {
+    let hash = hashv(&[method.as_bytes(), params.to_string().as_bytes()]);
+    let hex: String = hash.as_ref()[..16]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("{}-{}.json", method, hex)
+}
+
+/// Replaces credentials in an endpoint URL with `REDACTED`
+///
+/// Redacts the password, the values of `key_param` and of query parameters named like keys
+/// or tokens, and any other occurrence of the API key, e.g. in the path.
+fn scrub_endpoint(endpoint: &str, api_key: Option<&str>, key_param: Option<&str>) -> String {
+    let Ok(mut url) = reqwest::Url::parse(endpoint) else {
+        return REDACTED.to_string();
+    };
+    if url.password().is_some() {
+        let _ = url.set_password(Some(REDACTED));
+    }
+
+    let is_secret_name = |name: &str| {
+        let name = name.to_lowercase();
+        key_param.is_some_and(|param| param.eq_ignore_ascii_case(&name))
+            || ["key", "token", "secret", "auth"]
+                .iter()
+                .any(|word| name.contains(word))
+    };
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(name, value)| {
+            let value = if is_secret_name(&name) {
+                REDACTED.to_string()
+            } else {
+                value.into_owned()
+            };
+            (name.into_owned(), value)
+        })
+        .collect();
+    if !pairs.is_empty() {
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+
+    match api_key {
+        Some(key) if !key.is_empty() => url.to_string().replace(key, REDACTED),
+        _ => url.to_string(),
+    }
+}
+
+/// Removes recorded DAS responses older than the given age
+///
+/// Files that are not cache entries are left alone.
+///
+/// # Arguments
+///
+/// * `cache_dir` - The directory holding the recordings
+/// * `max_age` - The age past which an entry is removed
+///
+/// # Returns
+///
+/// The number of entries removed
+///
+/// # Errors
+///
+/// Returns an `FfiError` if the directory cannot be read or an entry cannot be removed
+pub fn prune_das_cache(cache_dir: &Path, max_age: Duration) -> SssResult<usize> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let cutoff = now.saturating_sub(max_age.as_secs());
+
+    let mut removed = 0;
+    for entry in fs::read_dir(cache_dir).into_sss_error("Failed to read DAS cache directory")? {
+        let path = entry
+            .into_sss_error("Failed to read DAS cache directory")?
+            .path();
+        if path.extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+        let Some(entry) = fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str::<DasCacheEntry>(&json).ok())
+        else {
+            continue;
+        };
+        if entry.recorded_at < cutoff {
+            fs::remove_file(&path).into_sss_error("Failed to remove DAS cache entry")?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
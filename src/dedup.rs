@@ -0,0 +1,471 @@
+//! Persistent record of the transactions sent under an idempotency key
+//!
+//! The in-flight registry only spans one process, and a queue replaying its jobs after a
+//! crash would send their transactions again. The dedup store remembers what each
+//! idempotency key sent across restarts, so a replayed job returns the original signature.
+
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::journal::{JournalStatus, ends_mid_line};
+use crate::load_dotenv;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_sdk::{commitment_config::CommitmentConfig, hash::Hash, signature::Signature};
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Default time a record is kept after its last status change
+pub const DEFAULT_DEDUP_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+lazy_static! {
+    /// File of the default store, from `SSS_DEDUP_PATH`
+    static ref DEDUP_PATH: Option<PathBuf> = {
+        load_dotenv();
+        env::var("SSS_DEDUP_PATH").ok().map(PathBuf::from)
+    };
+
+    /// Time records are kept, from `SSS_DEDUP_TTL_SECS`
+    static ref DEDUP_TTL: Duration = {
+        load_dotenv();
+        env::var("SSS_DEDUP_TTL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_DEDUP_TTL)
+    };
+}
+
+/// The store in use, opened from `SSS_DEDUP_PATH` on first use unless one was installed
+static STORE: Mutex<Option<Box<dyn DedupStore>>> = Mutex::new(None);
+
+/// The last transaction sent under an idempotency key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DedupRecord {
+    /// The transaction signature
+    pub signature: String,
+    /// The blockhash the transaction was signed with
+    pub blockhash: String,
+    /// The state of the transaction when last recorded
+    pub status: JournalStatus,
+    /// Unix time in seconds of the last status change
+    pub updated_at: u64,
+}
+
+/// Storage of dedup records that survives process restarts
+///
+/// Implementations must keep a second process from using the same storage at the same
+/// time, or else make every method safe across processes.
+pub trait DedupStore: Send {
+    /// Returns the record stored under a key, if any
+    fn get(&self, key: &str) -> SssResult<Option<DedupRecord>>;
+
+    /// Stores a record under a key, replacing any earlier one
+    ///
+    /// The record must be durable when this returns, since the transaction is sent next.
+    fn put(&mut self, key: &str, record: DedupRecord) -> SssResult<()>;
+
+    /// Removes the records last updated before a Unix time in seconds
+    ///
+    /// Returns the number of records removed.
+    fn compact(&mut self, before: u64) -> SssResult<usize>;
+}
+
+/// A line of a [`FileDedupStore`]
+#[derive(Serialize, Deserialize)]
+struct DedupLine {
+    key: String,
+    #[serde(flatten)]
+    record: DedupRecord,
+}
+
+/// A dedup store appending JSON lines to a file, the last line for a key being current
+///
+/// The store holds an exclusive lock on a `.lock` file next to it while open, so a second
+/// process opening the same path is refused instead of racing the first.
+pub struct FileDedupStore {
+    path: PathBuf,
+    file: File,
+    records: HashMap<String, DedupRecord>,
+    // Released by the OS when the process exits, even after a crash
+    _lock: File,
+}
+
+impl FileDedupStore {
+    /// Opens the store at a path, creating it if needed
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file holding the records
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ConfigError` if another process has the store open or the file cannot be
+    /// read
+    pub fn open(path: impl AsRef<Path>) -> SssResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut lock_path = path.clone().into_os_string();
+        lock_path.push(".lock");
+        let lock = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&lock_path)
+            .map_err(|e| dedup_error("open", e))?;
+        match lock.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                return Err(SssError::ConfigError(format!(
+                    "Dedup store {} is in use by another process",
+                    path.display()
+                )));
+            }
+            Err(TryLockError::Error(e)) => return Err(dedup_error("lock", e)),
+        }
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)
+            .map_err(|e| dedup_error("open", e))?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)
+            .map_err(|e| dedup_error("read", e))?;
+        // Terminate a line left incomplete by a crash so it does not swallow the next one
+        if ends_mid_line(&mut file).map_err(|e| dedup_error("read", e))? {
+            file.write_all(b"\n").map_err(|e| dedup_error("write", e))?;
+        }
+
+        // Lines that cannot be parsed, such as one cut short by a crash, are skipped
+        let records = contents
+            .lines()
+            .filter_map(|line| serde_json::from_str::<DedupLine>(line).ok())
+            .map(|line| (line.key, line.record))
+            .collect();
+        Ok(Self {
+            path,
+            file,
+            records,
+            _lock: lock,
+        })
+    }
+}
+
+impl DedupStore for FileDedupStore {
+    fn get(&self, key: &str) -> SssResult<Option<DedupRecord>> {
+        Ok(self.records.get(key).cloned())
+    }
+
+    fn put(&mut self, key: &str, record: DedupRecord) -> SssResult<()> {
+        let line = DedupLine {
+            key: key.to_string(),
+            record,
+        };
+        let mut json =
+            serde_json::to_string(&line).into_sss_error("Failed to serialize dedup config")?;
+        json.push('\n');
+        self.file
+            .write_all(json.as_bytes())
+            .and_then(|_| self.file.sync_data())
+            .map_err(|e| dedup_error("write", e))?;
+        self.records.insert(line.key, line.record);
+        Ok(())
+    }
+
+    fn compact(&mut self, before: u64) -> SssResult<usize> {
+        let count = self.records.len();
+        self.records.retain(|_, record| record.updated_at >= before);
+        let removed = count - self.records.len();
+        if removed == 0 {
+            return Ok(0);
+        }
+
+        // Write the remaining records to a new file and swap it in, so a crash leaves
+        // either the old or the new file complete
+        let mut contents = String::new();
+        for (key, record) in &self.records {
+            let line = DedupLine {
+                key: key.clone(),
+                record: record.clone(),
+            };
+            contents.push_str(
+                &serde_json::to_string(&line).into_sss_error("Failed to serialize dedup config")?,
+            );
+            contents.push('\n');
+        }
+        let mut temp_path = self.path.clone().into_os_string();
+        temp_path.push(".tmp");
+        let mut temp = File::create(&temp_path).map_err(|e| dedup_error("write", e))?;
+        temp.write_all(contents.as_bytes())
+            .and_then(|_| temp.sync_all())
+            .map_err(|e| dedup_error("write", e))?;
+        fs::rename(&temp_path, &self.path).map_err(|e| dedup_error("replace", e))?;
+        self.file = OpenOptions::new()
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| dedup_error("open", e))?;
+        Ok(removed)
+    }
+}
+
+/// Returns whether a dedup store is installed or `SSS_DEDUP_PATH` is set
+pub fn dedup_enabled() -> bool {
+    DEDUP_PATH.is_some() || STORE.lock().is_ok_and(|store| store.is_some())
+}
+
+/// Replaces the store opened from `SSS_DEDUP_PATH`, e.g. with one backed by a database
+///
+/// Records already in the previous store are not carried over.
+///
+/// # Arguments
+///
+/// * `store` - The store to consult before sending from now on
+pub fn set_dedup_store(store: Box<dyn DedupStore>) {
+    if let Ok(mut current) = STORE.lock() {
+        *current = Some(store);
+    }
+}
+
+/// Removes the records older than `SSS_DEDUP_TTL_SECS` (default 7 days)
+///
+/// Runs on its own when the store is opened from `SSS_DEDUP_PATH`. A job replayed after
+/// its record was removed is sent again, so the TTL must exceed the longest replay delay.
+///
+/// # Returns
+///
+/// The number of records removed
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if no store is configured or the store cannot be written
+pub fn dedup_compact() -> SssResult<usize> {
+    let before = unix_now().saturating_sub(DEDUP_TTL.as_secs());
+    with_store(|store| store.compact(before))
+}
+
+/// Returns the signature of a transaction already sent under a key that must not be sent
+/// again
+///
+/// A transaction that failed or expired is forgotten, so the caller sends a new one. One
+/// whose outcome is unknown is looked up on chain first.
+///
+/// # Errors
+///
+/// Returns a `DuplicateInFlight` error if the earlier transaction is still unconfirmed and
+/// its blockhash valid, since it may yet land, and an `RpcError` if the chain cannot be
+/// queried
+pub(crate) fn previous_send(client: &RpcClient, key: &str) -> SssResult<Option<Signature>> {
+    let Some(mut record) = with_store(|store| store.get(key))? else {
+        return Ok(None);
+    };
+    let signature = Signature::from_str(&record.signature)
+        .into_sss_error("Failed to parse signature in dedup config")?;
+    match record.status {
+        JournalStatus::Confirmed => return Ok(Some(signature)),
+        JournalStatus::Failed | JournalStatus::Expired => return Ok(None),
+        JournalStatus::Unknown => {
+            return Err(SssError::DuplicateInFlight(format!(
+                "Transaction {} sent earlier with the same idempotency key has an unknown \
+                 outcome and needs a manual review",
+                signature
+            )));
+        }
+        JournalStatus::Sent => {}
+    }
+
+    let status = client
+        .get_signature_status_with_commitment_and_history(
+            &signature,
+            CommitmentConfig::confirmed(),
+            true,
+        )
+        .into_sss_error("Failed to get signature status from rpc")?;
+    record.status = match status {
+        Some(Ok(())) => JournalStatus::Confirmed,
+        Some(Err(_)) => JournalStatus::Failed,
+        None => {
+            let blockhash = Hash::from_str(&record.blockhash)
+                .into_sss_error("Failed to parse blockhash in dedup config")?;
+            let still_valid = client
+                .is_blockhash_valid(&blockhash, CommitmentConfig::processed())
+                .into_sss_error("Failed to check blockhash validity with rpc")?;
+            if still_valid {
+                return Err(SssError::DuplicateInFlight(format!(
+                    "Transaction {} sent earlier with the same idempotency key is still \
+                     unconfirmed",
+                    signature
+                )));
+            }
+            JournalStatus::Expired
+        }
+    };
+    record.updated_at = unix_now();
+    let confirmed = record.status == JournalStatus::Confirmed;
+    with_store(|store| store.put(key, record))?;
+    Ok(confirmed.then_some(signature))
+}
+
+/// Records the state of a transaction sent under a key
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if the record cannot be written, in which case a transaction
+/// about to be sent must not be sent
+pub(crate) fn record(
+    key: &str,
+    signature: &Signature,
+    blockhash: &Hash,
+    status: JournalStatus,
+) -> SssResult<()> {
+    let record = DedupRecord {
+        signature: signature.to_string(),
+        blockhash: blockhash.to_string(),
+        status,
+        updated_at: unix_now(),
+    };
+    with_store(|store| store.put(key, record))
+}
+
+/// Runs a function with the store, opening it from `SSS_DEDUP_PATH` on first use
+fn with_store<T>(f: impl FnOnce(&mut dyn DedupStore) -> SssResult<T>) -> SssResult<T> {
+    let mut store = STORE
+        .lock()
+        .map_err(|e| SssError::ConfigError(format!("Dedup store poisoned: {}", e)))?;
+    if let Some(store) = store.as_deref_mut() {
+        return f(store);
+    }
+    let path = DEDUP_PATH
+        .as_ref()
+        .ok_or_else(|| SssError::ConfigError("SSS_DEDUP_PATH is not set".to_string()))?;
+    let mut opened = FileDedupStore::open(path)?;
+    opened.compact(unix_now().saturating_sub(DEDUP_TTL.as_secs()))?;
+    f(store.insert(Box::new(opened)).as_mut())
+}
+
+fn dedup_error(action: &str, e: std::io::Error) -> SssError {
+    SssError::ConfigError(format!("Failed to {} dedup store: {}", action, e))
+}
+
+/// Returns the current Unix time in seconds
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store_path() -> PathBuf {
+        env::temp_dir().join(format!("sss-dedup-{}.jsonl", rand::random::<u64>()))
+    }
+
+    fn record(signature: &str, status: JournalStatus, updated_at: u64) -> DedupRecord {
+        DedupRecord {
+            signature: signature.to_string(),
+            blockhash: Hash::default().to_string(),
+            status,
+            updated_at,
+        }
+    }
+
+    #[test]
+    fn records_survive_reopening_and_the_last_one_wins() {
+        let path = store_path();
+        let mut store = FileDedupStore::open(&path).unwrap();
+        store
+            .put("job-1", record("a", JournalStatus::Sent, 10))
+            .unwrap();
+        store
+            .put("job-1", record("a", JournalStatus::Confirmed, 11))
+            .unwrap();
+        store
+            .put("job-2", record("b", JournalStatus::Failed, 12))
+            .unwrap();
+        drop(store);
+
+        let store = FileDedupStore::open(&path).unwrap();
+        assert_eq!(
+            store.get("job-1").unwrap(),
+            Some(record("a", JournalStatus::Confirmed, 11))
+        );
+        assert_eq!(
+            store.get("job-2").unwrap(),
+            Some(record("b", JournalStatus::Failed, 12))
+        );
+        assert_eq!(store.get("job-3").unwrap(), None);
+    }
+
+    #[test]
+    fn line_cut_short_by_a_crash_is_skipped() {
+        let path = store_path();
+        let mut store = FileDedupStore::open(&path).unwrap();
+        store
+            .put("job-1", record("a", JournalStatus::Confirmed, 10))
+            .unwrap();
+        drop(store);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"key":"job-2","signature":"#).unwrap();
+        drop(file);
+
+        let mut store = FileDedupStore::open(&path).unwrap();
+        assert_eq!(store.get("job-2").unwrap(), None);
+        store
+            .put("job-3", record("c", JournalStatus::Sent, 11))
+            .unwrap();
+        drop(store);
+
+        let store = FileDedupStore::open(&path).unwrap();
+        assert!(store.get("job-1").unwrap().is_some());
+        assert_eq!(
+            store.get("job-3").unwrap(),
+            Some(record("c", JournalStatus::Sent, 11))
+        );
+    }
+
+    #[test]
+    fn store_in_use_is_refused_until_closed() {
+        let path = store_path();
+        let store = FileDedupStore::open(&path).unwrap();
+        match FileDedupStore::open(&path) {
+            Err(SssError::ConfigError(message)) => {
+                assert!(message.contains("in use by another process"), "{message}")
+            }
+            other => panic!("expected a ConfigError, got {:?}", other.map(|_| ())),
+        }
+        drop(store);
+        FileDedupStore::open(&path).unwrap();
+    }
+
+    #[test]
+    fn compaction_removes_expired_records_for_good() {
+        let path = store_path();
+        let mut store = FileDedupStore::open(&path).unwrap();
+        store
+            .put("old", record("a", JournalStatus::Confirmed, 10))
+            .unwrap();
+        store
+            .put("new", record("b", JournalStatus::Confirmed, 20))
+            .unwrap();
+        assert_eq!(store.compact(15).unwrap(), 1);
+        assert_eq!(store.compact(15).unwrap(), 0);
+        store
+            .put("newer", record("c", JournalStatus::Sent, 30))
+            .unwrap();
+        drop(store);
+
+        let store = FileDedupStore::open(&path).unwrap();
+        assert_eq!(store.get("old").unwrap(), None);
+        assert!(store.get("new").unwrap().is_some());
+        assert!(store.get("newer").unwrap().is_some());
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+    }
+}
@@ -0,0 +1,508 @@
+//! Development helpers that must never run against production funds
+//!
+//! Funding the payer from the faucet and burning every test token are only compiled with the
+//! `dev-tools` feature, so a release build without it exports neither the Rust functions nor
+//! their C symbols. They also check the cluster at runtime and refuse mainnet-beta.
+
+use crate::RPC_CLIENT;
+use crate::batch::BatchReport;
+use crate::confirm::{
+    CancelToken, ConfirmationOutcome, ConfirmationStrategy, Confirmer, DEFAULT_CONFIRMATION_TIMEOUT,
+};
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::journal::{JournalOperation, journaled};
+use crate::load_dotenv;
+use crate::metrics::track;
+use crate::mint_cache::metadata_pda;
+use crate::payer::{get_payer_pubkey, signing_payer};
+use crate::serde_utils::pubkey_string;
+use crate::transaction::{TxOptions, fitting_items, sign_and_send};
+use crate::trusted_mints::is_trusted_mint;
+use lazy_static::lazy_static;
+use mpl_token_metadata::accounts::Metadata;
+use serde::Serialize;
+use serde_json::json;
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_rpc_client_api::request::RpcRequest;
+use solana_rpc_client_api::response::RpcKeyedAccount;
+use solana_sdk::{
+    hash::Hash, instruction::Instruction, message::Message, pubkey::Pubkey, signature::Keypair,
+    signer::Signer,
+};
+use spl_token_2022::extension::StateWithExtensions;
+use spl_token_2022::state::AccountState;
+use std::collections::HashMap;
+use std::env;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+/// Genesis hash of mainnet-beta, which identifies the cluster whatever the RPC URL
+const MAINNET_GENESIS_HASH: &str = "5eykt4UsFv8P8NJdTREpY1vzqKqZKvdpKuc147dw2N9d";
+
+/// Number of metadata accounts fetched in one RPC call
+const METADATA_FETCH_SIZE: usize = 100;
+
+lazy_static! {
+    /// Whether cleanups may run on mainnet, seeded from `SSS_ALLOW_MAINNET_CLEANUP`
+    static ref ALLOW_MAINNET_CLEANUP: AtomicBool = {
+        load_dotenv();
+        let enabled = env::var("SSS_ALLOW_MAINNET_CLEANUP")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+        AtomicBool::new(enabled)
+    };
+}
+
+/// Which of the payer's tokens a cleanup may burn
+///
+/// Mints in the trusted set are never burned, whatever the filter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssetFilter {
+    /// Only burn mints whose metadata update authority is the payer, on by default
+    pub payer_is_update_authority: bool,
+    /// Only burn these mints, if set
+    pub only_mints: Option<Vec<Pubkey>>,
+    /// Never burn these mints
+    pub exclude_mints: Vec<Pubkey>,
+}
+
+impl Default for AssetFilter {
+    fn default() -> Self {
+        Self {
+            payer_is_update_authority: true,
+            only_mints: None,
+            exclude_mints: Vec::new(),
+        }
+    }
+}
+
+/// What happened to a single token account of the payer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum CleanupAction {
+    /// The balance was burned and the account closed in the given transaction
+    Burned {
+        /// The transaction signature
+        signature: String,
+    },
+    /// The balance would be burned and the account closed; nothing was sent
+    WouldBurn,
+    /// The account was left untouched
+    Skipped {
+        /// Why the account was left alone
+        reason: String,
+    },
+    /// The balance could not be burned or the account could not be closed
+    Failed {
+        /// Why the cleanup failed
+        error: String,
+    },
+}
+
+/// The cleanup of a single token account of the payer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CleanupEntry {
+    /// The token account
+    #[serde(with = "pubkey_string")]
+    pub token_account: Pubkey,
+    /// The mint of the account
+    #[serde(with = "pubkey_string")]
+    pub mint: Pubkey,
+    /// The balance burned, or to be burned, in base units
+    pub amount: u64,
+    /// The rent held by the account, returned to the payer when it is closed
+    pub lamports: u64,
+    /// What happened to the account
+    #[serde(flatten)]
+    pub action: CleanupAction,
+}
+
+/// Outcome of a wallet cleanup
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CleanupReport {
+    /// Whether this was a dry run, in which nothing was sent
+    pub dry_run: bool,
+    /// One entry per token account of the payer, in address order
+    pub accounts: Vec<CleanupEntry>,
+    /// Number of accounts burned and closed, or to be in a dry run
+    pub assets_burned: usize,
+    /// Rent returned to the payer by the closed accounts, or to be in a dry run
+    pub lamports_reclaimed: u64,
+}
+
+impl CleanupReport {
+    /// Serializes the report to JSON
+    pub fn to_json(&self) -> SssResult<String> {
+        serde_json::to_string_pretty(self).into_sss_error("Failed to serialize token cleanup")
+    }
+
+    /// Summarizes the report per token account
+    ///
+    /// Burned accounts succeeded, failed accounts failed, and skipped accounts and every
+    /// account of a dry run are skipped.
+    pub fn batch_report(&self) -> BatchReport {
+        let mut batch = BatchReport::default();
+        for entry in &self.accounts {
+            match &entry.action {
+                CleanupAction::Burned { signature } => batch
+                    .succeeded
+                    .push((entry.token_account, signature.clone())),
+                CleanupAction::Failed { error } => batch
+                    .failed
+                    .push((entry.token_account, SssError::TokenError(error.clone()))),
+                CleanupAction::WouldBurn | CleanupAction::Skipped { .. } => {
+                    batch.skipped.push(entry.token_account)
+                }
+            }
+        }
+        batch
+    }
+}
+
+/// Allows or forbids cleanups on mainnet
+///
+/// # Arguments
+///
+/// * `allowed` - Whether [`burn_all_assets`] may run against mainnet-beta
+pub fn set_allow_mainnet_cleanup(allowed: bool) {
+    ALLOW_MAINNET_CLEANUP.store(allowed, Ordering::Relaxed);
+}
+
+/// Requests SOL for the payer from the cluster's faucet and waits for it to arrive
+///
+/// Only devnet, testnet and local validators have a faucet; mainnet-beta is refused before
+/// anything is requested.
+///
+/// # Arguments
+///
+/// * `lamports` - The amount to request; faucets cap it, e.g. devnet at a few SOL
+///
+/// # Returns
+///
+/// The signature of the airdrop transaction
+///
+/// # Errors
+///
+/// Returns a `ConfigError` on mainnet-beta, and an `RpcError` if the faucet refuses the
+/// request or the airdrop is not confirmed in time
+pub fn request_airdrop(lamports: u64) -> SssResult<String> {
+    if is_mainnet(&RPC_CLIENT)? {
+        return Err(SssError::ConfigError(
+            "Refusing to request an airdrop on mainnet".to_string(),
+        ));
+    }
+    let payer = get_payer_pubkey()?;
+    let signature = RPC_CLIENT
+        .request_airdrop(&payer, lamports)
+        .into_sss_error("Failed to request airdrop from rpc")?;
+    let outcome = Confirmer::new(&RPC_CLIENT).confirm(
+        &signature,
+        &ConfirmationStrategy::default(),
+        Instant::now() + DEFAULT_CONFIRMATION_TIMEOUT,
+        &CancelToken::default(),
+    )?;
+    match outcome {
+        ConfirmationOutcome::Confirmed { signature } => Ok(signature.to_string()),
+        ConfirmationOutcome::TimedOut { signature } => Err(SssError::RpcError(format!(
+            "Airdrop {} was not confirmed in time",
+            signature
+        ))),
+        ConfirmationOutcome::Failed {
+            signature, error, ..
+        } => Err(SssError::RpcError(format!(
+            "Airdrop {} failed: {}",
+            signature, error
+        ))),
+    }
+}
+
+/// Burns the payer's tokens matching a filter and closes their accounts
+///
+/// Every token account of the payer, under both token programs, is listed. Accounts of
+/// trusted mints, of mints excluded by the filter, frozen accounts and wrapped SOL are
+/// skipped; the rest have their balance burned and are closed, returning their rent to the
+/// payer. Accounts are batched into as few transactions as fit.
+///
+/// # Arguments
+///
+/// * `filter` - Which mints may be burned
+/// * `dry_run` - Report what would be burned without signing or sending anything
+///
+/// # Returns
+///
+/// The outcome of every token account and the rent reclaimed
+///
+/// # Errors
+///
+/// Returns a `ConfigError` on mainnet-beta unless allowed with
+/// [`set_allow_mainnet_cleanup`] or `SSS_ALLOW_MAINNET_CLEANUP`, and an `RpcError` if the
+/// accounts cannot be listed
+pub fn burn_all_assets(filter: &AssetFilter, dry_run: bool) -> SssResult<CleanupReport> {
+    track(JournalOperation::BurnAssets, || {
+        // Fails early in read-only mode, before anything is listed
+        let signer = if dry_run {
+            None
+        } else {
+            Some(signing_payer()?)
+        };
+        ensure_cleanup_allowed()?;
+        let payer = get_payer_pubkey()?;
+        let accounts = payer_token_accounts(&payer)?;
+        let update_authorities = if filter.payer_is_update_authority {
+            update_authorities(&accounts)?
+        } else {
+            HashMap::new()
+        };
+
+        let mut report = CleanupReport {
+            dry_run,
+            ..CleanupReport::default()
+        };
+        let mut pending = Vec::new();
+        for account in accounts {
+            let update_authority = update_authorities.get(&account.mint).copied().flatten();
+            let action = match skip_reason(&account, filter, update_authority, &payer) {
+                Some(reason) => CleanupAction::Skipped { reason },
+                None => match cleanup_instructions(&account, &payer) {
+                    Ok(instructions) => {
+                        pending.push((report.accounts.len(), instructions));
+                        CleanupAction::WouldBurn
+                    }
+                    Err(e) => CleanupAction::Failed {
+                        error: e.to_string(),
+                    },
+                },
+            };
+            report.accounts.push(CleanupEntry {
+                token_account: account.address,
+                mint: account.mint,
+                amount: account.amount,
+                lamports: account.lamports,
+                action,
+            });
+        }
+
+        if let Some(signer) = &signer {
+            send_cleanup(&mut report, &pending, signer)?;
+        }
+        for entry in &report.accounts {
+            if matches!(
+                entry.action,
+                CleanupAction::Burned { .. } | CleanupAction::WouldBurn
+            ) {
+                report.assets_burned += 1;
+                report.lamports_reclaimed += entry.lamports;
+            }
+        }
+        Ok(report)
+    })
+}
+
+/// A token account of the payer
+struct PayerTokenAccount {
+    address: Pubkey,
+    program: Pubkey,
+    mint: Pubkey,
+    amount: u64,
+    lamports: u64,
+    frozen: bool,
+}
+
+/// Fails with a `ConfigError` on mainnet-beta unless cleanups were allowed there
+fn ensure_cleanup_allowed() -> SssResult<()> {
+    if ALLOW_MAINNET_CLEANUP.load(Ordering::Relaxed) || !is_mainnet(&RPC_CLIENT)? {
+        return Ok(());
+    }
+    Err(SssError::ConfigError(
+        "Refusing to burn assets on mainnet; allow it with set_allow_mainnet_cleanup or SSS_ALLOW_MAINNET_CLEANUP".to_string(),
+    ))
+}
+
+/// Returns whether the RPC node of a client serves mainnet-beta, from its genesis hash
+pub(crate) fn is_mainnet(client: &RpcClient) -> SssResult<bool> {
+    let genesis = client
+        .get_genesis_hash()
+        .into_sss_error("Failed to get genesis hash from rpc")?;
+    Ok(Hash::from_str(MAINNET_GENESIS_HASH).ok() == Some(genesis))
+}
+
+/// Lists the token accounts owned by the payer under both token programs, by address
+fn payer_token_accounts(payer: &Pubkey) -> SssResult<Vec<PayerTokenAccount>> {
+    let mut accounts = Vec::new();
+    for program in [spl_token::id(), spl_token_2022::id()] {
+        // The owner follows the 32-byte mint in every token account
+        let config = json!({
+            "encoding": "base64",
+            "filters": [{ "memcmp": { "offset": 32, "bytes": payer.to_string() } }],
+        });
+        let keyed: Vec<RpcKeyedAccount> = RPC_CLIENT
+            .send(
+                RpcRequest::GetProgramAccounts,
+                json!([program.to_string(), config]),
+            )
+            .into_sss_error("Failed to get token accounts from rpc")?;
+        for keyed in keyed {
+            let address = Pubkey::from_str(&keyed.pubkey)
+                .into_sss_error("Failed to parse token account address from rpc")?;
+            let Some(data) = keyed.account.data.decode() else {
+                continue;
+            };
+            // Mints whose authority happens to match the filter are not token accounts
+            let Ok(state) = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&data)
+            else {
+                continue;
+            };
+            if state.base.owner != *payer {
+                continue;
+            }
+            accounts.push(PayerTokenAccount {
+                address,
+                program,
+                mint: state.base.mint,
+                amount: state.base.amount,
+                lamports: keyed.account.lamports,
+                frozen: state.base.state == AccountState::Frozen,
+            });
+        }
+    }
+    accounts.sort_by_key(|account| account.address);
+    Ok(accounts)
+}
+
+/// Fetches the metadata update authority of every mint, `None` for mints without metadata
+fn update_authorities(
+    accounts: &[PayerTokenAccount],
+) -> SssResult<HashMap<Pubkey, Option<Pubkey>>> {
+    let mut mints: Vec<Pubkey> = accounts.iter().map(|account| account.mint).collect();
+    mints.sort();
+    mints.dedup();
+
+    let mut authorities = HashMap::with_capacity(mints.len());
+    for chunk in mints.chunks(METADATA_FETCH_SIZE) {
+        let keys: Vec<Pubkey> = chunk.iter().map(metadata_pda).collect();
+        let metadata = RPC_CLIENT
+            .get_multiple_accounts(&keys)
+            .into_sss_error("Failed to get token metadata accounts from rpc")?;
+        for (mint, account) in chunk.iter().zip(metadata) {
+            let authority = account
+                .and_then(|account| Metadata::from_bytes(&account.data).ok())
+                .map(|metadata| metadata.update_authority);
+            authorities.insert(*mint, authority);
+        }
+    }
+    Ok(authorities)
+}
+
+/// Returns why an account must not be burned, or `None` if it may be
+fn skip_reason(
+    account: &PayerTokenAccount,
+    filter: &AssetFilter,
+    update_authority: Option<Pubkey>,
+    payer: &Pubkey,
+) -> Option<String> {
+    let mint = &account.mint;
+    if is_trusted_mint(mint) {
+        return Some("mint is in the trusted set".to_string());
+    }
+    if *mint == spl_token::native_mint::id() || *mint == spl_token_2022::native_mint::id() {
+        return Some("wrapped SOL is never burned".to_string());
+    }
+    if filter.exclude_mints.contains(mint) {
+        return Some("mint is excluded by the filter".to_string());
+    }
+    if let Some(only) = &filter.only_mints
+        && !only.contains(mint)
+    {
+        return Some("mint is not selected by the filter".to_string());
+    }
+    if filter.payer_is_update_authority {
+        match update_authority {
+            Some(authority) if authority == *payer => {}
+            Some(authority) => {
+                return Some(format!("update authority is {}, not the payer", authority));
+            }
+            None => return Some("mint has no metadata".to_string()),
+        }
+    }
+    if account.frozen {
+        return Some("account is frozen".to_string());
+    }
+    None
+}
+
+/// Builds the instructions burning an account's balance and closing it
+fn cleanup_instructions(
+    account: &PayerTokenAccount,
+    payer: &Pubkey,
+) -> SssResult<Vec<Instruction>> {
+    let mut instructions = Vec::with_capacity(2);
+    if account.amount > 0 {
+        instructions.push(
+            spl_token_2022::instruction::burn(
+                &account.program,
+                &account.address,
+                &account.mint,
+                payer,
+                &[],
+                account.amount,
+            )
+            .into_sss_error("Failed to build token burn instruction")?,
+        );
+    }
+    instructions.push(
+        spl_token_2022::instruction::close_account(
+            &account.program,
+            &account.address,
+            payer,
+            payer,
+            &[],
+        )
+        .into_sss_error("Failed to build token close instruction")?,
+    );
+    Ok(instructions)
+}
+
+/// Sends the pending burns in as few transactions as fit, recording each outcome
+fn send_cleanup(
+    report: &mut CleanupReport,
+    pending: &[(usize, Vec<Instruction>)],
+    payer: &Keypair,
+) -> SssResult<()> {
+    let payer_pubkey = payer.pubkey();
+    let build = |batch: &[(usize, Vec<Instruction>)]| {
+        let instructions: Vec<Instruction> = batch
+            .iter()
+            .flat_map(|(_, instructions)| instructions.iter().cloned())
+            .collect();
+        Ok(Message::new(&instructions, Some(&payer_pubkey)))
+    };
+
+    let mut rest = pending;
+    while !rest.is_empty() {
+        // The burn and close of a single account always fit in a transaction
+        let count = fitting_items(rest, 1, build).unwrap_or(1);
+        let (batch, tail) = rest.split_at(count);
+        rest = tail;
+        let message = build(batch)?;
+        let params = json!({
+            "token_accounts": batch
+                .iter()
+                .map(|(index, _)| report.accounts[*index].token_account.to_string())
+                .collect::<Vec<_>>(),
+        });
+        let tx_options = TxOptions::default();
+        let action = match journaled(JournalOperation::BurnAssets, &params, &tx_options, || {
+            sign_and_send(&RPC_CLIENT, message, &[payer], &tx_options)
+        }) {
+            Ok(signature) => CleanupAction::Burned { signature },
+            Err(e) => CleanupAction::Failed {
+                error: e.to_string(),
+            },
+        };
+        for (index, _) in batch {
+            report.accounts[*index].action = action.clone();
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,847 @@
+//! Bounded queues between the library and host callbacks
+//!
+//! Events and balance notifications reach host code through a [`CallbackQueue`], drained
+//! by a dedicated thread per queue, so a slow callback neither delays an operation nor
+//! grows memory without bound. A queue holds at most [`QueueOptions::capacity`] items and
+//! applies its [`OverflowPolicy`] when full; dropped items are counted and announced to
+//! the event sink with an `events_dropped` event.
+//!
+//! A callback that runs longer than the watchdog timeout, `SSS_CALLBACK_WATCHDOG_MS`
+//! (default [`DEFAULT_CALLBACK_WATCHDOG`]), is considered stalled: it is counted and shown
+//! in [`crate::metrics_snapshot`], a stalled balance callback is announced with a
+//! `callback_stalled` event, blocking producers stop waiting for it, and
+//! [`crate::shutdown`] returns without it.
+
+use crate::error::{SssError, SssResult};
+use crate::events::{EVENT_QUEUE_SIZE, SssEvent, emit};
+use crate::load_dotenv;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::env;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a callback may run before it is reported as stalled, unless
+/// `SSS_CALLBACK_WATCHDOG_MS` is set
+pub const DEFAULT_CALLBACK_WATCHDOG: Duration = Duration::from_secs(30);
+
+/// How long [`OverflowPolicy::Block`] waits when configured by `SSS_EVENT_OVERFLOW=block`
+/// without `SSS_EVENT_BLOCK_MS`
+pub const DEFAULT_BLOCK_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// A queue feeding host callbacks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CallbackQueue {
+    /// Events for the sink of [`crate::set_event_sink`]
+    Events,
+    /// Notifications of [`crate::subscribe_token_balance`] callbacks
+    BalanceCallbacks,
+}
+
+impl CallbackQueue {
+    /// Every queue, in report order
+    pub const ALL: [CallbackQueue; 2] = [CallbackQueue::Events, CallbackQueue::BalanceCallbacks];
+
+    /// Returns the snake_case name of the queue
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CallbackQueue::Events => "events",
+            CallbackQueue::BalanceCallbacks => "balance_callbacks",
+        }
+    }
+
+    /// Looks up a queue by its snake_case name
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ConfigError` naming the known queues if `name` is not one of them
+    pub fn from_name(name: &str) -> SssResult<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|queue| queue.as_str() == name)
+            .ok_or_else(|| {
+                SssError::ConfigError(format!(
+                    "Unknown callback queue '{}', expected 'events' or 'balance_callbacks'",
+                    name
+                ))
+            })
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+/// What a full queue does with a new item
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Discard the oldest queued item to make room, keeping the latest data
+    DropOldest,
+    /// Discard the new item, keeping what is queued
+    DropNewest,
+    /// Make the producer wait up to the timeout for room, then discard the new item
+    ///
+    /// Slows operations down to the pace of the callback; no producer waits while the
+    /// callback is stalled.
+    Block(Duration),
+}
+
+impl OverflowPolicy {
+    /// Looks up a policy by its name: `drop_oldest`, `drop_newest` or `block`
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The policy name
+    /// * `block_timeout` - How long `block` waits for room
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ConfigError` if the name is unknown
+    pub fn from_name(name: &str, block_timeout: Duration) -> SssResult<Self> {
+        match name {
+            "drop_oldest" => Ok(OverflowPolicy::DropOldest),
+            "drop_newest" => Ok(OverflowPolicy::DropNewest),
+            "block" => Ok(OverflowPolicy::Block(block_timeout)),
+            _ => Err(SssError::ConfigError(format!(
+                "Unknown overflow policy '{}', expected 'drop_oldest', 'drop_newest' or 'block'",
+                name
+            ))),
+        }
+    }
+}
+
+/// Size and overflow policy of a callback queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueOptions {
+    /// The most items queued at once, at least 1
+    pub capacity: usize,
+    /// What a full queue does with a new item
+    pub overflow: OverflowPolicy,
+}
+
+impl Default for QueueOptions {
+    fn default() -> Self {
+        Self {
+            capacity: EVENT_QUEUE_SIZE,
+            overflow: OverflowPolicy::DropNewest,
+        }
+    }
+}
+
+/// The state of a callback queue, see [`crate::MetricsReport::callback_queues`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct QueueMetrics {
+    /// The queue
+    pub queue: CallbackQueue,
+    /// Items waiting for the callback
+    pub depth: usize,
+    /// The most items queued at once
+    pub capacity: usize,
+    /// Items dropped because the queue was full
+    pub dropped: u64,
+    /// Callbacks that ran longer than the watchdog timeout
+    pub stalls: u64,
+    /// How long the running callback has been stalled in milliseconds, `None` unless it
+    /// exceeded the watchdog timeout
+    pub stalled_ms: Option<u64>,
+}
+
+lazy_static! {
+    /// Options for queues not configured by [`set_callback_queue_options`], from
+    /// `SSS_EVENT_QUEUE_SIZE`, `SSS_EVENT_OVERFLOW` and `SSS_EVENT_BLOCK_MS`
+    static ref ENV_OPTIONS: QueueOptions = {
+        load_dotenv();
+        let defaults = QueueOptions::default();
+        let block_timeout = env::var("SSS_EVENT_BLOCK_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_BLOCK_TIMEOUT);
+        QueueOptions {
+            capacity: env::var("SSS_EVENT_QUEUE_SIZE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|capacity| *capacity > 0)
+                .unwrap_or(defaults.capacity),
+            overflow: env::var("SSS_EVENT_OVERFLOW")
+                .ok()
+                .and_then(|v| OverflowPolicy::from_name(&v, block_timeout).ok())
+                .unwrap_or(defaults.overflow),
+        }
+    };
+
+    /// Time after which a running callback counts as stalled, from `SSS_CALLBACK_WATCHDOG_MS`
+    static ref WATCHDOG: Duration = {
+        load_dotenv();
+        env::var("SSS_CALLBACK_WATCHDOG_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_CALLBACK_WATCHDOG)
+    };
+
+    /// Options set by [`set_callback_queue_options`], by queue
+    static ref OPTIONS: Mutex<[Option<QueueOptions>; CallbackQueue::ALL.len()]> =
+        Mutex::new([None; CallbackQueue::ALL.len()]);
+}
+
+/// Sets the capacity and overflow policy of a callback queue
+///
+/// Takes effect immediately, also for a queue already in use; items queued beyond a
+/// reduced capacity are still delivered. Without a call, queues use `SSS_EVENT_QUEUE_SIZE`
+/// (default [`EVENT_QUEUE_SIZE`]), `SSS_EVENT_OVERFLOW` (`drop_oldest`, `drop_newest`, the
+/// default, or `block`) and `SSS_EVENT_BLOCK_MS` (default [`DEFAULT_BLOCK_TIMEOUT`]);
+/// malformed values are ignored.
+///
+/// # Arguments
+///
+/// * `queue` - The queue to configure
+/// * `options` - Its capacity and overflow policy
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if the capacity is 0
+pub fn set_callback_queue_options(queue: CallbackQueue, options: QueueOptions) -> SssResult<()> {
+    if options.capacity == 0 {
+        return Err(SssError::ConfigError(format!(
+            "The capacity of the {} queue must be at least 1",
+            queue.as_str()
+        )));
+    }
+    OPTIONS
+        .lock()
+        .map_err(|e| SssError::ConfigError(format!("Callback queue options poisoned: {}", e)))?
+        [queue.index()] = Some(options);
+    match queue {
+        CallbackQueue::Events => crate::events::apply_queue_options(options),
+        CallbackQueue::BalanceCallbacks => crate::subscriptions::apply_queue_options(options),
+    }
+    Ok(())
+}
+
+/// Returns the capacity and overflow policy of a callback queue
+pub fn callback_queue_options(queue: CallbackQueue) -> QueueOptions {
+    OPTIONS
+        .lock()
+        .ok()
+        .and_then(|options| options[queue.index()])
+        .unwrap_or(*ENV_OPTIONS)
+}
+
+/// A queued item or a report of dropped ones, handed to the delivery function
+pub(crate) enum Delivery<T> {
+    Item(T),
+    /// Items dropped since the last report
+    Dropped(u64),
+}
+
+/// A bounded queue drained by its own thread
+pub(crate) struct Dispatcher<T> {
+    queue: CallbackQueue,
+    state: Mutex<QueueState<T>>,
+    /// Signalled when items are queued or taken, a delivery ends or the queue closes
+    changed: Condvar,
+    dropped: AtomicU64,
+    /// Drops not yet reported to the delivery function
+    unreported: AtomicU64,
+    stalls: AtomicU64,
+}
+
+struct QueueState<T> {
+    items: VecDeque<T>,
+    options: QueueOptions,
+    closed: bool,
+    /// When the running delivery started, `None` while idle
+    busy_since: Option<Instant>,
+    /// Whether the running delivery was already counted as stalled
+    stall_counted: bool,
+}
+
+impl<T: Send + 'static> Dispatcher<T> {
+    /// Creates a queue with the configured options and starts its delivery thread
+    pub(crate) fn start(
+        queue: CallbackQueue,
+        deliver: impl FnMut(Delivery<T>) + Send + 'static,
+    ) -> Arc<Self> {
+        Self::spawn(
+            queue,
+            callback_queue_options(queue),
+            VecDeque::new(),
+            deliver,
+        )
+    }
+
+    fn spawn(
+        queue: CallbackQueue,
+        options: QueueOptions,
+        items: VecDeque<T>,
+        mut deliver: impl FnMut(Delivery<T>) + Send + 'static,
+    ) -> Arc<Self> {
+        let dispatcher = Arc::new(Self {
+            queue,
+            state: Mutex::new(QueueState {
+                items,
+                options,
+                closed: false,
+                busy_since: None,
+                stall_counted: false,
+            }),
+            changed: Condvar::new(),
+            dropped: AtomicU64::new(0),
+            unreported: AtomicU64::new(0),
+            stalls: AtomicU64::new(0),
+        });
+        let worker = dispatcher.clone();
+        thread::spawn(move || {
+            while let Some((dropped, item)) = worker.next() {
+                if dropped > 0 {
+                    deliver(Delivery::Dropped(dropped));
+                }
+                if let Some(item) = item {
+                    deliver(Delivery::Item(item));
+                }
+                if let Ok(mut state) = worker.state.lock() {
+                    state.busy_since = None;
+                }
+                worker.changed.notify_all();
+            }
+        });
+        dispatcher
+    }
+
+    /// Waits for the next item and the drops to report before it, and marks the queue
+    /// busy; `None` once it is closed and every drop was reported
+    ///
+    /// Drops are also returned without an item while the queue is idle or closing, so the
+    /// last ones of a burst are not held back until another item arrives.
+    fn next(&self) -> Option<(u64, Option<T>)> {
+        let mut state = self.state.lock().ok()?;
+        loop {
+            let item = if state.closed {
+                None
+            } else {
+                state.items.pop_front()
+            };
+            let dropped = self.unreported.swap(0, Ordering::Relaxed);
+            if item.is_none() && dropped == 0 {
+                if state.closed {
+                    return None;
+                }
+                state = self.changed.wait(state).ok()?;
+                continue;
+            }
+            state.busy_since = Some(Instant::now());
+            state.stall_counted = false;
+            self.changed.notify_all();
+            return Some((dropped, item));
+        }
+    }
+
+    /// Queues an item without waiting, unless the policy is to block
+    ///
+    /// # Returns
+    ///
+    /// The item dropped to respect the capacity, either this one or the oldest queued
+    pub(crate) fn push(&self, item: T) -> Option<T> {
+        let Ok(mut state) = self.state.lock() else {
+            return Some(item);
+        };
+        if state.closed {
+            return Some(item);
+        }
+        if state.items.len() >= state.options.capacity
+            && let OverflowPolicy::Block(timeout) = state.options.overflow
+            && self.stalled_since(&mut state).is_none()
+        {
+            let deadline = Instant::now() + timeout;
+            while state.items.len() >= state.options.capacity && !state.closed {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                // The callback may stall while the producer waits, which ends the wait
+                if remaining.is_zero() || self.stalled_since(&mut state).is_some() {
+                    break;
+                }
+                let until_stalled = state.busy_since.map_or(remaining, |started| {
+                    WATCHDOG.saturating_sub(started.elapsed())
+                });
+                state = match self
+                    .changed
+                    .wait_timeout(state, remaining.min(until_stalled))
+                {
+                    Ok((state, _)) => state,
+                    Err(_) => return Some(item),
+                };
+            }
+        }
+
+        let dropped = if state.closed {
+            return Some(item);
+        } else if state.items.len() < state.options.capacity {
+            state.items.push_back(item);
+            None
+        } else if state.options.overflow == OverflowPolicy::DropOldest {
+            let oldest = state.items.pop_front();
+            state.items.push_back(item);
+            oldest
+        } else {
+            Some(item)
+        };
+        // Counted before unlocking, so an idle delivery thread cannot miss the drop
+        if dropped.is_some() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            self.unreported.fetch_add(1, Ordering::Relaxed);
+        }
+        drop(state);
+        self.changed.notify_all();
+        dropped
+    }
+
+    /// Changes the capacity and overflow policy
+    pub(crate) fn set_options(&self, options: QueueOptions) {
+        if let Ok(mut state) = self.state.lock() {
+            state.options = options;
+        }
+        self.changed.notify_all();
+    }
+
+    /// Returns how long the running callback has been stalled, if it exceeded the
+    /// watchdog timeout
+    pub(crate) fn stalled_for(&self) -> Option<Duration> {
+        let mut state = self.state.lock().ok()?;
+        self.stalled_since(&mut state)
+    }
+
+    /// Checks the running delivery against the watchdog, counting and announcing a stall
+    /// the first time it is seen
+    fn stalled_since(&self, state: &mut MutexGuard<'_, QueueState<T>>) -> Option<Duration> {
+        let elapsed = state.busy_since?.elapsed();
+        if elapsed < *WATCHDOG {
+            return None;
+        }
+        if !state.stall_counted {
+            state.stall_counted = true;
+            self.stalls.fetch_add(1, Ordering::Relaxed);
+            // A stalled event sink cannot announce itself
+            if self.queue != CallbackQueue::Events {
+                emit(SssEvent::CallbackStalled {
+                    queue: self.queue,
+                    stalled_ms: u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX),
+                });
+            }
+        }
+        Some(elapsed)
+    }
+
+    /// Returns the state of the queue
+    pub(crate) fn metrics(&self) -> QueueMetrics {
+        let stalled_for = self.stalled_for();
+        let (depth, capacity) = self
+            .state
+            .lock()
+            .map(|state| (state.items.len(), state.options.capacity))
+            .unwrap_or_default();
+        QueueMetrics {
+            queue: self.queue,
+            depth,
+            capacity,
+            dropped: self.dropped.load(Ordering::Relaxed),
+            stalls: self.stalls.load(Ordering::Relaxed),
+            stalled_ms: stalled_for.map(|d| u64::try_from(d.as_millis()).unwrap_or(u64::MAX)),
+        }
+    }
+
+    /// Moves the queued items to a new queue and delivery thread, leaving a stalled
+    /// callback behind; the old thread ends if the callback ever returns
+    pub(crate) fn restart(&self, deliver: impl FnMut(Delivery<T>) + Send + 'static) -> Arc<Self> {
+        let (items, options) = match self.state.lock() {
+            Ok(mut state) => {
+                state.closed = true;
+                (std::mem::take(&mut state.items), state.options)
+            }
+            Err(_) => (VecDeque::new(), QueueOptions::default()),
+        };
+        self.changed.notify_all();
+        let replacement = Self::spawn(self.queue, options, items, deliver);
+        replacement
+            .dropped
+            .store(self.dropped.load(Ordering::Relaxed), Ordering::Relaxed);
+        // Taken over, so the old thread does not report them too if its callback returns
+        replacement.unreported.store(
+            self.unreported.swap(0, Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
+        replacement
+            .stalls
+            .store(self.stalls.load(Ordering::Relaxed), Ordering::Relaxed);
+        replacement
+    }
+
+    /// Discards the queued items and stops the delivery thread, waiting for a running
+    /// callback to return unless it is stalled, and for drops not reported yet to be
+    pub(crate) fn close(&self) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        state.closed = true;
+        state.items.clear();
+        self.changed.notify_all();
+        let flush_deadline = Instant::now() + *WATCHDOG;
+        loop {
+            let remaining = match state.busy_since {
+                Some(started) => WATCHDOG.saturating_sub(started.elapsed()),
+                // The delivery thread reports them as soon as it wakes up
+                None if self.unreported.load(Ordering::Relaxed) > 0 => {
+                    flush_deadline.saturating_duration_since(Instant::now())
+                }
+                None => break,
+            };
+            if remaining.is_zero() {
+                // Count the stall before giving up on the callback
+                self.stalled_since(&mut state);
+                break;
+            }
+            state = match self.changed.wait_timeout(state, remaining) {
+                Ok((state, _)) => state,
+                Err(_) => return,
+            };
+        }
+    }
+}
+
+/// Returns the metrics of a queue that has not been started yet
+pub(crate) fn idle_queue_metrics(queue: CallbackQueue) -> QueueMetrics {
+    QueueMetrics {
+        queue,
+        depth: 0,
+        capacity: callback_queue_options(queue).capacity,
+        dropped: 0,
+        stalls: 0,
+        stalled_ms: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::{self, Receiver, Sender};
+
+    /// What the delivery function of a test queue saw
+    #[derive(Debug, PartialEq, Eq)]
+    enum Seen {
+        Item(u32),
+        Dropped(u64),
+    }
+
+    /// Starts a queue whose callback waits for a go-ahead before each delivery, returning
+    /// the queue, the go-ahead sender and the deliveries
+    fn gated(options: QueueOptions) -> (Arc<Dispatcher<u32>>, Sender<()>, Receiver<Seen>) {
+        let (go, gate) = mpsc::channel::<()>();
+        let (seen, deliveries) = mpsc::channel();
+        let dispatcher = Dispatcher::spawn(
+            CallbackQueue::BalanceCallbacks,
+            options,
+            VecDeque::new(),
+            move |delivery| {
+                let _ = gate.recv();
+                let _ = seen.send(match delivery {
+                    Delivery::Item(item) => Seen::Item(item),
+                    Delivery::Dropped(count) => Seen::Dropped(count),
+                });
+            },
+        );
+        (dispatcher, go, deliveries)
+    }
+
+    /// Waits until the delivery thread has taken the first item and is held at the gate
+    fn wait_until_busy(dispatcher: &Dispatcher<u32>) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while dispatcher.state.lock().unwrap().busy_since.is_none() {
+            assert!(Instant::now() < deadline, "the first item was not taken");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// Releases the callback `count` times and collects what it delivers
+    fn release(go: &Sender<()>, deliveries: &Receiver<Seen>, count: usize) -> Vec<Seen> {
+        (0..count)
+            .map(|_| {
+                go.send(()).unwrap();
+                deliveries.recv_timeout(Duration::from_secs(5)).unwrap()
+            })
+            .collect()
+    }
+
+    fn options(capacity: usize, overflow: OverflowPolicy) -> QueueOptions {
+        QueueOptions { capacity, overflow }
+    }
+
+    #[test]
+    fn drop_newest_keeps_the_queued_items() {
+        let (dispatcher, go, deliveries) = gated(options(3, OverflowPolicy::DropNewest));
+        assert_eq!(dispatcher.push(0), None);
+        wait_until_busy(&dispatcher);
+        for item in 1..=3 {
+            assert_eq!(dispatcher.push(item), None);
+        }
+        assert_eq!(dispatcher.push(4), Some(4));
+        assert_eq!(dispatcher.push(5), Some(5));
+
+        let metrics = dispatcher.metrics();
+        assert_eq!(
+            (metrics.depth, metrics.capacity, metrics.dropped),
+            (3, 3, 2)
+        );
+        // The drops are reported once, before the next item delivered after them
+        assert_eq!(
+            release(&go, &deliveries, 5),
+            [
+                Seen::Item(0),
+                Seen::Dropped(2),
+                Seen::Item(1),
+                Seen::Item(2),
+                Seen::Item(3)
+            ]
+        );
+        assert_eq!(dispatcher.push(6), None);
+        assert_eq!(release(&go, &deliveries, 1), [Seen::Item(6)]);
+        assert_eq!(dispatcher.metrics().dropped, 2);
+    }
+
+    #[test]
+    fn drop_oldest_keeps_the_latest_items() {
+        let (dispatcher, go, deliveries) = gated(options(2, OverflowPolicy::DropOldest));
+        dispatcher.push(0);
+        wait_until_busy(&dispatcher);
+        assert_eq!(dispatcher.push(1), None);
+        assert_eq!(dispatcher.push(2), None);
+        assert_eq!(dispatcher.push(3), Some(1));
+        assert_eq!(dispatcher.push(4), Some(2));
+        assert_eq!(dispatcher.metrics().depth, 2);
+        assert_eq!(
+            release(&go, &deliveries, 4),
+            [
+                Seen::Item(0),
+                Seen::Dropped(2),
+                Seen::Item(3),
+                Seen::Item(4)
+            ]
+        );
+    }
+
+    #[test]
+    fn block_waits_for_room_up_to_the_timeout() {
+        let (dispatcher, go, deliveries) = gated(options(
+            1,
+            OverflowPolicy::Block(Duration::from_millis(100)),
+        ));
+        dispatcher.push(0);
+        wait_until_busy(&dispatcher);
+        dispatcher.push(1);
+
+        // Nobody makes room: the producer gives up after the timeout and drops its item
+        let started = Instant::now();
+        assert_eq!(dispatcher.push(2), Some(2));
+        let waited = started.elapsed();
+        assert!(waited >= Duration::from_millis(100), "{:?}", waited);
+        assert!(waited < Duration::from_secs(2), "{:?}", waited);
+
+        // Room made while waiting lets the item in
+        let releaser = {
+            let go = go.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(20));
+                go.send(()).unwrap();
+            })
+        };
+        assert_eq!(dispatcher.push(3), None);
+        releaser.join().unwrap();
+        assert_eq!(deliveries.recv().unwrap(), Seen::Item(0));
+        assert_eq!(
+            release(&go, &deliveries, 3),
+            [Seen::Dropped(1), Seen::Item(1), Seen::Item(3)]
+        );
+    }
+
+    #[test]
+    fn new_options_apply_to_a_running_queue() {
+        let (dispatcher, go, deliveries) = gated(options(4, OverflowPolicy::DropNewest));
+        dispatcher.push(0);
+        wait_until_busy(&dispatcher);
+        for item in 1..=4 {
+            dispatcher.push(item);
+        }
+        // Items beyond a reduced capacity are still delivered, but no new one fits
+        dispatcher.set_options(options(2, OverflowPolicy::DropNewest));
+        assert_eq!(dispatcher.push(5), Some(5));
+        assert_eq!(dispatcher.metrics().capacity, 2);
+        assert_eq!(dispatcher.metrics().depth, 4);
+        assert_eq!(
+            release(&go, &deliveries, 6),
+            [
+                Seen::Item(0),
+                Seen::Dropped(1),
+                Seen::Item(1),
+                Seen::Item(2),
+                Seen::Item(3),
+                Seen::Item(4)
+            ]
+        );
+    }
+
+    #[test]
+    fn a_restarted_queue_delivers_what_was_queued_and_keeps_its_counters() {
+        let (dispatcher, go, deliveries) = gated(options(2, OverflowPolicy::DropNewest));
+        dispatcher.push(0);
+        wait_until_busy(&dispatcher);
+        dispatcher.push(1);
+        dispatcher.push(2);
+        dispatcher.push(3);
+
+        let (seen, restarted_deliveries) = mpsc::channel();
+        let restarted = dispatcher.restart(move |delivery| {
+            let _ = seen.send(match delivery {
+                Delivery::Item(item) => Seen::Item(item),
+                Delivery::Dropped(count) => Seen::Dropped(count),
+            });
+        });
+        // The old queue takes nothing more
+        assert_eq!(dispatcher.push(9), Some(9));
+        let delivered: Vec<Seen> = (0..3)
+            .map(|_| {
+                restarted_deliveries
+                    .recv_timeout(Duration::from_secs(5))
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(delivered, [Seen::Dropped(1), Seen::Item(1), Seen::Item(2)]);
+        assert_eq!(restarted.metrics().dropped, 1);
+
+        // The callback left behind finishes its item and its thread ends, without
+        // reporting the drops the new queue took over
+        assert_eq!(release(&go, &deliveries, 1), [Seen::Item(0)]);
+        let _ = go.send(());
+        assert!(deliveries.recv_timeout(Duration::from_millis(50)).is_err());
+    }
+
+    #[test]
+    fn drops_at_the_end_of_a_burst_are_reported_once_the_queue_is_idle() {
+        let (seen, deliveries) = mpsc::channel();
+        let dispatcher = Dispatcher::spawn(
+            CallbackQueue::BalanceCallbacks,
+            options(1, OverflowPolicy::DropNewest),
+            VecDeque::new(),
+            move |delivery| {
+                let _ = seen.send(match delivery {
+                    Delivery::Item(item) => Seen::Item(item),
+                    Delivery::Dropped(count) => Seen::Dropped(count),
+                });
+            },
+        );
+        // Producers racing the delivery thread, the last of them with nothing after it
+        let producers: Vec<_> = (0..8)
+            .map(|producer| {
+                let dispatcher = Arc::clone(&dispatcher);
+                thread::spawn(move || {
+                    for item in 0..500 {
+                        dispatcher.push(producer * 1_000 + item);
+                    }
+                })
+            })
+            .collect();
+        for producer in producers {
+            producer.join().unwrap();
+        }
+
+        let dropped = dispatcher.metrics().dropped;
+        let (mut delivered, mut reported) = (0, 0);
+        while delivered + reported < 8 * 500 {
+            match deliveries.recv_timeout(Duration::from_secs(5)) {
+                Ok(Seen::Item(_)) => delivered += 1,
+                Ok(Seen::Dropped(count)) => reported += count,
+                Err(_) => panic!("{} of {} drops reported", reported, dropped),
+            }
+        }
+        assert_eq!(reported, dropped);
+        assert!(deliveries.recv_timeout(Duration::from_millis(50)).is_err());
+    }
+
+    #[test]
+    fn closing_reports_the_drops_not_reported_yet() {
+        let (dispatcher, go, deliveries) = gated(options(1, OverflowPolicy::DropNewest));
+        dispatcher.push(0);
+        wait_until_busy(&dispatcher);
+        dispatcher.push(1);
+        assert_eq!(dispatcher.push(2), Some(2));
+        assert_eq!(dispatcher.push(3), Some(3));
+
+        let closer = {
+            let dispatcher = Arc::clone(&dispatcher);
+            thread::spawn(move || dispatcher.close())
+        };
+        thread::sleep(Duration::from_millis(20));
+        assert!(!closer.is_finished());
+        // The queued item is discarded, but the drops are still reported before the close
+        // returns
+        assert_eq!(
+            release(&go, &deliveries, 2),
+            [Seen::Item(0), Seen::Dropped(2)]
+        );
+        closer.join().unwrap();
+        let _ = go.send(());
+        assert!(deliveries.recv_timeout(Duration::from_millis(50)).is_err());
+    }
+
+    #[test]
+    fn a_closed_queue_discards_and_refuses_items() {
+        let (dispatcher, go, deliveries) = gated(options(4, OverflowPolicy::DropNewest));
+        dispatcher.push(0);
+        wait_until_busy(&dispatcher);
+        dispatcher.push(1);
+        let closer = {
+            let dispatcher = Arc::clone(&dispatcher);
+            thread::spawn(move || dispatcher.close())
+        };
+        // Closing waits for the running callback
+        thread::sleep(Duration::from_millis(20));
+        assert!(!closer.is_finished());
+        assert_eq!(release(&go, &deliveries, 1), [Seen::Item(0)]);
+        closer.join().unwrap();
+
+        assert_eq!(dispatcher.push(2), Some(2));
+        assert_eq!(dispatcher.metrics().depth, 0);
+        let _ = go.send(());
+        assert!(deliveries.recv_timeout(Duration::from_millis(50)).is_err());
+    }
+
+    #[test]
+    fn names_are_parsed() {
+        for queue in CallbackQueue::ALL {
+            assert_eq!(CallbackQueue::from_name(queue.as_str()).unwrap(), queue);
+        }
+        assert!(CallbackQueue::from_name("Events").is_err());
+        let timeout = Duration::from_millis(7);
+        assert_eq!(
+            OverflowPolicy::from_name("drop_oldest", timeout).unwrap(),
+            OverflowPolicy::DropOldest
+        );
+        assert_eq!(
+            OverflowPolicy::from_name("drop_newest", timeout).unwrap(),
+            OverflowPolicy::DropNewest
+        );
+        assert_eq!(
+            OverflowPolicy::from_name("block", timeout).unwrap(),
+            OverflowPolicy::Block(timeout)
+        );
+        assert!(OverflowPolicy::from_name("drop", timeout).is_err());
+        assert!(
+            set_callback_queue_options(
+                CallbackQueue::Events,
+                options(0, OverflowPolicy::DropNewest)
+            )
+            .is_err()
+        );
+    }
+}
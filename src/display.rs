@@ -0,0 +1,635 @@
+//! Address formatting and `solana:` URIs shared by every frontend
+//!
+//! Apps, receipts and the C API show addresses the same way: [`short_pubkey`] keeps the
+//! first and last characters around an ellipsis, and [`pubkey_color_hash`] derives the
+//! avatar color that lets a user recognise an address at a glance. Addresses handed to a
+//! wallet are Solana Pay transfer requests, built by [`qr_payload_for_address`] or
+//! [`SolanaPayUri`] and read back by [`parse_solana_uri`].
+
+use crate::error::{SssError, SssResult};
+use crate::payment::percent_encode;
+use crate::serde_utils::{option_pubkey_string, pubkey_string};
+use serde::Serialize;
+use solana_sdk::{hash::hash, pubkey::Pubkey};
+use std::fmt::{self, Write};
+use std::str::FromStr;
+
+/// Characters kept at the start of a shortened address unless the caller chooses
+pub const DEFAULT_SHORT_HEAD: usize = 4;
+
+/// Characters kept at the end of a shortened address unless the caller chooses
+pub const DEFAULT_SHORT_TAIL: usize = 4;
+
+/// Scheme of Solana Pay URIs
+const SOLANA_SCHEME: &str = "solana:";
+
+/// A Solana Pay transfer request, see [`parse_solana_uri`]
+///
+/// Displaying it gives the `solana:` URI, with its parameters in the order of the Solana
+/// Pay specification.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
+pub struct SolanaPayUri {
+    /// The address to pay
+    #[serde(with = "pubkey_string")]
+    pub recipient: Pubkey,
+    /// The amount in whole tokens (SOL when `spl_token` is `None`), without superfluous
+    /// zeros, or `None` to let the payer choose
+    pub amount: Option<String>,
+    /// The SPL token to pay in, `None` for SOL
+    #[serde(with = "option_pubkey_string")]
+    pub spl_token: Option<Pubkey>,
+    /// Keys the paying transaction must reference, see
+    /// [`generate_payment_reference`](crate::generate_payment_reference)
+    #[serde(serialize_with = "serialize_pubkeys")]
+    pub references: Vec<Pubkey>,
+    /// Who is requesting the payment
+    pub label: Option<String>,
+    /// What the payment is for
+    pub message: Option<String>,
+    /// Memo the wallet includes in the paying transaction
+    pub memo: Option<String>,
+}
+
+impl fmt::Display for SolanaPayUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut uri = format!("{}{}", SOLANA_SCHEME, self.recipient);
+        let mut params = Vec::new();
+        if let Some(amount) = &self.amount {
+            params.push(format!("amount={}", amount));
+        }
+        if let Some(mint) = &self.spl_token {
+            params.push(format!("spl-token={}", mint));
+        }
+        for reference in &self.references {
+            params.push(format!("reference={}", reference));
+        }
+        for (name, value) in [
+            ("label", &self.label),
+            ("message", &self.message),
+            ("memo", &self.memo),
+        ] {
+            if let Some(value) = value.as_deref().filter(|v| !v.is_empty()) {
+                params.push(format!("{}={}", name, percent_encode(value)));
+            }
+        }
+        if !params.is_empty() {
+            let _ = write!(uri, "?{}", params.join("&"));
+        }
+        f.write_str(&uri)
+    }
+}
+
+fn serialize_pubkeys<S: serde::Serializer>(
+    keys: &[Pubkey],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(keys.iter().map(|key| key.to_string()))
+}
+
+/// Shortens an address to its first `head` and last `tail` characters around `…`
+///
+/// `short_pubkey(&pubkey, 4, 4)` gives e.g. `DZ2D…NGRM`. The address is returned whole when
+/// shortening would not make it shorter.
+pub fn short_pubkey(pubkey: &Pubkey, head: usize, tail: usize) -> String {
+    elide_middle(&pubkey.to_string(), head, tail, "…")
+}
+
+/// Keeps the first `head` and last `tail` characters of `text` around `marker`
+///
+/// Receipts use this with `...`, which every receipt printer can print.
+pub(crate) fn elide_middle(text: &str, head: usize, tail: usize, marker: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    if head + tail + marker.chars().count() >= chars.len() {
+        return text.to_string();
+    }
+    let start: String = chars[..head].iter().collect();
+    let end: String = chars[chars.len() - tail..].iter().collect();
+    format!("{}{}{}", start, marker, end)
+}
+
+/// Derives the RGB avatar color of an address
+///
+/// The color is the first three bytes of the SHA-256 hash of the address bytes, so every
+/// frontend shows the same color for an address, and addresses sharing their first and
+/// last characters still differ in color.
+pub fn pubkey_color_hash(pubkey: &Pubkey) -> [u8; 3] {
+    let digest = hash(pubkey.as_ref()).to_bytes();
+    [digest[0], digest[1], digest[2]]
+}
+
+/// Builds the `solana:` URI to show as a QR code for receiving at an address
+///
+/// # Arguments
+///
+/// * `pubkey` - The receiving wallet
+/// * `amount` - The amount in whole tokens, e.g. `"1.5"`, or `None` to let the payer choose
+/// * `mint` - The SPL token to receive, `None` for SOL
+///
+/// # Returns
+///
+/// The URI, e.g. `solana:<pubkey>?amount=1.5&spl-token=<mint>`
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the amount is not a non-negative decimal number
+pub fn qr_payload_for_address(
+    pubkey: &Pubkey,
+    amount: Option<&str>,
+    mint: Option<&Pubkey>,
+) -> SssResult<String> {
+    let amount = amount.map(normalize_amount).transpose()?;
+    Ok(SolanaPayUri {
+        recipient: *pubkey,
+        amount,
+        spl_token: mint.copied(),
+        ..SolanaPayUri::default()
+    }
+    .to_string())
+}
+
+/// Reads a Solana Pay transfer request
+///
+/// Accepts the URIs of [`qr_payload_for_address`] and [`SolanaPayUri`] as well as those
+/// of wallets such as Phantom: the scheme is matched case-insensitively, parameters may
+/// come in any order, unknown parameters are skipped, and `+` in the label, message and
+/// memo reads as a space. Amounts are returned without superfluous zeros.
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the URI is not a `solana:` transfer request (transaction
+/// request links carry a URL instead of an address), an address or the amount is
+/// malformed, a percent-escape is invalid, or a parameter other than `reference` repeats
+pub fn parse_solana_uri(uri: &str) -> SssResult<SolanaPayUri> {
+    let invalid = |reason: String| {
+        SssError::TokenError(format!("Invalid Solana Pay URI '{}': {}", uri, reason))
+    };
+
+    let uri = uri.trim();
+    let rest = uri
+        .get(..SOLANA_SCHEME.len())
+        .filter(|scheme| scheme.eq_ignore_ascii_case(SOLANA_SCHEME))
+        .map(|_| &uri[SOLANA_SCHEME.len()..])
+        .ok_or_else(|| invalid("it does not start with 'solana:'".to_string()))?;
+    let (recipient, query) = rest.split_once('?').unwrap_or((rest, ""));
+    let recipient = recipient.trim_start_matches("//");
+    if recipient.contains(':') || recipient.contains("%3A") || recipient.contains("%3a") {
+        return Err(invalid(
+            "it is a transaction request, not a transfer request".to_string(),
+        ));
+    }
+    let recipient = Pubkey::from_str(recipient)
+        .map_err(|e| invalid(format!("recipient '{}': {}", recipient, e)))?;
+
+    let mut request = SolanaPayUri {
+        recipient,
+        ..SolanaPayUri::default()
+    };
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let repeated = || invalid(format!("'{}' is given more than once", name));
+        let pubkey = |value: &str| {
+            Pubkey::from_str(value).map_err(|e| invalid(format!("{} '{}': {}", name, value, e)))
+        };
+        match name {
+            "amount" => {
+                if request.amount.is_some() {
+                    return Err(repeated());
+                }
+                let value = percent_decode(value, false).map_err(&invalid)?;
+                request.amount =
+                    Some(normalize_amount(&value).map_err(|_| {
+                        invalid(format!("amount '{}' is not a decimal number", value))
+                    })?);
+            }
+            "spl-token" => {
+                if request.spl_token.is_some() {
+                    return Err(repeated());
+                }
+                request.spl_token = Some(pubkey(&percent_decode(value, false).map_err(&invalid)?)?);
+            }
+            "reference" => {
+                let value = percent_decode(value, false).map_err(&invalid)?;
+                request.references.push(pubkey(&value)?);
+            }
+            "label" | "message" | "memo" => {
+                let field = match name {
+                    "label" => &mut request.label,
+                    "message" => &mut request.message,
+                    _ => &mut request.memo,
+                };
+                if field.is_some() {
+                    return Err(repeated());
+                }
+                *field = Some(percent_decode(value, true).map_err(&invalid)?);
+            }
+            _ => {}
+        }
+    }
+    Ok(request)
+}
+
+/// Checks a Solana Pay amount and strips its superfluous zeros
+pub(crate) fn normalize_amount(amount: &str) -> SssResult<String> {
+    let invalid = || {
+        SssError::TokenError(format!(
+            "Invalid amount '{}': expected a non-negative decimal number such as 1.5",
+            amount
+        ))
+    };
+
+    let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    let digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+    if whole.is_empty() || !digits(whole) || !digits(fraction) {
+        return Err(invalid());
+    }
+    if amount.ends_with('.') {
+        return Err(invalid());
+    }
+    let whole = whole.trim_start_matches('0');
+    let whole = if whole.is_empty() { "0" } else { whole };
+    let fraction = fraction.trim_end_matches('0');
+    Ok(if fraction.is_empty() {
+        whole.to_string()
+    } else {
+        format!("{}.{}", whole, fraction)
+    })
+}
+
+/// Decodes the percent-escapes of a query value
+///
+/// With `plus_is_space`, `+` reads as a space as in form encoding; `encodeURIComponent`
+/// writes a `+` as `%2B`, so no text is lost.
+fn percent_decode(value: &str, plus_is_space: bool) -> Result<String, String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let escape = value
+                .get(i + 1..i + 3)
+                .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| format!("invalid percent-escape in '{}'", value))?;
+            decoded.push(escape);
+            i += 3;
+        } else if plus_is_space && bytes[i] == b'+' {
+            decoded.push(b' ');
+            i += 1;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| format!("'{}' is not UTF-8 text", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WALLET: &str = "DZ2DmmvyeQ6ZzZDSZxUx7stYqV2xjkNpNdWtJnVgNGRM";
+    const USDC: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+    const REFERENCE: &str = "82ZJ7nbGpixjeDCmEhUcmwXYfvurzAgGdtSMuHnUgyny";
+    /// The first three bytes of the SHA-256 hash of the bytes of `WALLET`
+    const WALLET_COLOR: [u8; 3] = [39, 48, 72];
+    /// The same for the system program, whose bytes are all zero
+    const SYSTEM_COLOR: [u8; 3] = [102, 104, 122];
+
+    fn key(address: &str) -> Pubkey {
+        Pubkey::from_str(address).unwrap()
+    }
+
+    #[test]
+    fn addresses_are_shortened_around_an_ellipsis() {
+        let wallet = key(WALLET);
+        assert_eq!(
+            short_pubkey(&wallet, DEFAULT_SHORT_HEAD, DEFAULT_SHORT_TAIL),
+            "DZ2D…NGRM"
+        );
+        assert_eq!(short_pubkey(&wallet, 6, 2), "DZ2Dmm…RM");
+        assert_eq!(short_pubkey(&wallet, 0, 4), "…NGRM");
+        assert_eq!(short_pubkey(&wallet, 4, 0), "DZ2D…");
+        assert_eq!(short_pubkey(&wallet, 0, 0), "…");
+
+        // Kept whole once the ellipsis would not save a character
+        assert_eq!(WALLET.len(), 44);
+        assert_eq!(short_pubkey(&wallet, 20, 23), WALLET);
+        assert_eq!(short_pubkey(&wallet, 40, 40), WALLET);
+        let shortest = short_pubkey(&wallet, 20, 22);
+        assert_eq!(shortest.chars().count(), 43);
+        assert_eq!(shortest, format!("{}…{}", &WALLET[..20], &WALLET[22..]));
+
+        // Shorter addresses, such as the system program's 32 characters, as well
+        let system = Pubkey::default();
+        assert_eq!(short_pubkey(&system, 4, 4), "1111…1111");
+        assert_eq!(short_pubkey(&system, 16, 15), system.to_string());
+    }
+
+    #[test]
+    fn receipts_elide_with_ascii_dots() {
+        assert_eq!(elide_middle(WALLET, 4, 4, "..."), "DZ2D...NGRM");
+        assert_eq!(elide_middle("abcdefghij", 3, 4, "..."), "abcdefghij");
+        assert_eq!(elide_middle("abcdefghijk", 3, 4, "..."), "abc...hijk");
+        assert_eq!(elide_middle("äöüßäöüßäöü", 2, 2, "…"), "äö…öü");
+    }
+
+    #[test]
+    fn avatar_colors_are_stable_and_tell_lookalikes_apart() {
+        // Pinned, since every app derives the same color from these bytes
+        assert_eq!(pubkey_color_hash(&key(WALLET)), WALLET_COLOR);
+        assert_eq!(pubkey_color_hash(&Pubkey::default()), SYSTEM_COLOR);
+        assert_eq!(
+            pubkey_color_hash(&key(WALLET)),
+            pubkey_color_hash(&key(WALLET))
+        );
+
+        // Addresses shortened alike get different colors
+        let mut lookalike = key(WALLET).to_bytes();
+        lookalike[16] ^= 1;
+        let lookalike = Pubkey::new_from_array(lookalike);
+        assert_ne!(
+            pubkey_color_hash(&lookalike),
+            pubkey_color_hash(&key(WALLET))
+        );
+    }
+
+    #[test]
+    fn qr_payloads_follow_solana_pay() {
+        let wallet = key(WALLET);
+        assert_eq!(
+            qr_payload_for_address(&wallet, None, None).unwrap(),
+            format!("solana:{}", WALLET)
+        );
+        assert_eq!(
+            qr_payload_for_address(&wallet, Some("1.50"), None).unwrap(),
+            format!("solana:{}?amount=1.5", WALLET)
+        );
+        assert_eq!(
+            qr_payload_for_address(&wallet, Some("007"), Some(&key(USDC))).unwrap(),
+            format!("solana:{}?amount=7&spl-token={}", WALLET, USDC)
+        );
+        assert_eq!(
+            qr_payload_for_address(&wallet, None, Some(&key(USDC))).unwrap(),
+            format!("solana:{}?spl-token={}", WALLET, USDC)
+        );
+        for amount in ["", "-1", "1.", ".5", "1e3", "1,5", " 1", "0x10", "1.2.3"] {
+            assert!(
+                qr_payload_for_address(&wallet, Some(amount), None).is_err(),
+                "{:?} was accepted",
+                amount
+            );
+        }
+    }
+
+    #[test]
+    fn amounts_lose_only_superfluous_zeros() {
+        for (amount, normalized) in [
+            ("0", "0"),
+            ("000", "0"),
+            ("0.0", "0"),
+            ("0.10", "0.1"),
+            ("10", "10"),
+            ("10.000", "10"),
+            ("0010.0100", "10.01"),
+            ("0.000000001", "0.000000001"),
+            ("18446744073709551616.5", "18446744073709551616.5"),
+        ] {
+            assert_eq!(normalize_amount(amount).unwrap(), normalized, "{}", amount);
+        }
+    }
+
+    #[test]
+    fn requests_round_trip_through_their_uri() {
+        let request = SolanaPayUri {
+            recipient: key(WALLET),
+            amount: Some("0.01".to_string()),
+            spl_token: Some(key(USDC)),
+            references: vec![key(REFERENCE), Pubkey::default()],
+            label: Some("Café Ünter & Söhne".to_string()),
+            message: Some("Order #42 – 2× latte, 100% arabica".to_string()),
+            memo: Some("a+b=c?&d".to_string()),
+        };
+        let uri = request.to_string();
+        assert_eq!(
+            uri,
+            format!(
+                "solana:{}?amount=0.01&spl-token={}&reference={}&reference={}\
+                 &label=Caf%C3%A9%20%C3%9Cnter%20%26%20S%C3%B6hne\
+                 &message=Order%20%2342%20%E2%80%93%202%C3%97%20latte%2C%20100%25%20arabica\
+                 &memo=a%2Bb%3Dc%3F%26d",
+                WALLET,
+                USDC,
+                REFERENCE,
+                Pubkey::default()
+            )
+        );
+        assert_eq!(parse_solana_uri(&uri).unwrap(), request);
+
+        // Empty texts are left out, and read back as absent
+        let bare = SolanaPayUri {
+            recipient: key(WALLET),
+            label: Some(String::new()),
+            ..SolanaPayUri::default()
+        };
+        assert_eq!(bare.to_string(), format!("solana:{}", WALLET));
+        assert_eq!(
+            parse_solana_uri(&bare.to_string()).unwrap(),
+            SolanaPayUri {
+                recipient: key(WALLET),
+                ..SolanaPayUri::default()
+            }
+        );
+
+        let payload = qr_payload_for_address(&key(WALLET), Some("2.5"), Some(&key(USDC))).unwrap();
+        let parsed = parse_solana_uri(&payload).unwrap();
+        assert_eq!(parsed.amount.as_deref(), Some("2.5"));
+        assert_eq!(parsed.spl_token, Some(key(USDC)));
+    }
+
+    #[test]
+    fn uris_written_by_phantom_are_read() {
+        // Receive QR code of a SOL address
+        let parsed = parse_solana_uri(&format!("solana:{}", WALLET)).unwrap();
+        assert_eq!(parsed.recipient, key(WALLET));
+        assert_eq!(parsed.amount, None);
+
+        // Request for USDC, with the label and message form-encoded
+        let parsed = parse_solana_uri(&format!(
+            "solana:{}?amount=25&spl-token={}&label=Coffee+Shop\
+             &message=Thanks+for+your+order%21&memo=OrderId12345",
+            WALLET, USDC
+        ))
+        .unwrap();
+        assert_eq!(parsed.amount.as_deref(), Some("25"));
+        assert_eq!(parsed.spl_token, Some(key(USDC)));
+        assert_eq!(parsed.label.as_deref(), Some("Coffee Shop"));
+        assert_eq!(parsed.message.as_deref(), Some("Thanks for your order!"));
+        assert_eq!(parsed.memo.as_deref(), Some("OrderId12345"));
+
+        // Parameters in another order, an unknown one, and an upper-case scheme as QR
+        // alphanumeric mode writes it
+        let parsed = parse_solana_uri(&format!(
+            "SOLANA:{}?reference={}&utm_source=phantom&spl-token={}&amount=0.500",
+            WALLET, REFERENCE, USDC
+        ))
+        .unwrap();
+        assert_eq!(parsed.references, [key(REFERENCE)]);
+        assert_eq!(parsed.amount.as_deref(), Some("0.5"));
+
+        // Surrounding whitespace from a scanner, and the `solana://` some wallets write
+        let parsed = parse_solana_uri(&format!("  solana://{}?amount=1\n", WALLET)).unwrap();
+        assert_eq!(parsed.recipient, key(WALLET));
+        assert_eq!(parsed.amount.as_deref(), Some("1"));
+
+        // A percent-encoded `+` stays a plus
+        let parsed = parse_solana_uri(&format!("solana:{}?memo=1%2B1+%3D+2", WALLET)).unwrap();
+        assert_eq!(parsed.memo.as_deref(), Some("1+1 = 2"));
+    }
+
+    #[test]
+    fn malformed_uris_are_refused_with_the_reason() {
+        for (uri, reason) in [
+            (
+                format!("bitcoin:{}", WALLET),
+                "does not start with 'solana:'",
+            ),
+            ("solana".to_string(), "does not start with 'solana:'"),
+            (String::new(), "does not start with 'solana:'"),
+            (
+                "solana:https://example.com/pay".to_string(),
+                "transaction request",
+            ),
+            (
+                "solana:https%3A%2F%2Fexample.com%2Fpay".to_string(),
+                "transaction request",
+            ),
+            ("solana:notakey".to_string(), "recipient 'notakey'"),
+            (format!("solana:{}?amount=1.", WALLET), "amount '1.'"),
+            (format!("solana:{}?amount=-1", WALLET), "amount '-1'"),
+            (
+                format!("solana:{}?amount=1&amount=2", WALLET),
+                "'amount' is given more than once",
+            ),
+            (
+                format!("solana:{}?spl-token={}&spl-token={}", WALLET, USDC, USDC),
+                "'spl-token' is given more than once",
+            ),
+            (
+                format!("solana:{}?label=a&label=b", WALLET),
+                "'label' is given more than once",
+            ),
+            (
+                format!("solana:{}?spl-token=usdc", WALLET),
+                "spl-token 'usdc'",
+            ),
+            (format!("solana:{}?reference=x", WALLET), "reference 'x'"),
+            (
+                format!("solana:{}?memo=100%", WALLET),
+                "invalid percent-escape",
+            ),
+            (
+                format!("solana:{}?memo=%zz", WALLET),
+                "invalid percent-escape",
+            ),
+            (format!("solana:{}?memo=%C3", WALLET), "is not UTF-8 text"),
+        ] {
+            let error = parse_solana_uri(&uri).unwrap_err().to_string();
+            assert!(
+                error.contains("Invalid Solana Pay URI") && error.contains(reason),
+                "{:?}: {}",
+                uri,
+                error
+            );
+        }
+    }
+
+    #[cfg(feature = "ffi")]
+    #[test]
+    fn the_c_api_matches_the_rust_helpers() {
+        use crate::ffi::{
+            sss_parse_solana_uri, sss_pubkey_color_hash, sss_qr_payload_for_address,
+            sss_short_pubkey,
+        };
+        use std::ffi::{CStr, CString, c_char};
+
+        fn read(buffer: &[c_char]) -> String {
+            unsafe { CStr::from_ptr(buffer.as_ptr()) }
+                .to_str()
+                .unwrap()
+                .to_string()
+        }
+
+        let wallet = CString::new(WALLET).unwrap();
+        let mut buffer = vec![0 as c_char; 1024];
+        let len = buffer.len() as i32;
+
+        let code = unsafe { sss_short_pubkey(wallet.as_ptr(), 4, 4, buffer.as_mut_ptr(), len) };
+        assert_eq!(code, 0);
+        assert_eq!(read(&buffer), short_pubkey(&key(WALLET), 4, 4));
+        let code = unsafe { sss_short_pubkey(wallet.as_ptr(), -1, 4, buffer.as_mut_ptr(), len) };
+        assert_eq!(code, -3);
+        // "DZ2D…NGRM" is 11 bytes, and the terminator needs one more
+        let code = unsafe { sss_short_pubkey(wallet.as_ptr(), 4, 4, buffer.as_mut_ptr(), 11) };
+        assert_eq!(code, -4);
+
+        let mut rgb = [0u8; 3];
+        assert_eq!(
+            unsafe { sss_pubkey_color_hash(wallet.as_ptr(), rgb.as_mut_ptr()) },
+            0
+        );
+        assert_eq!(rgb, pubkey_color_hash(&key(WALLET)));
+        let bad = CString::new("not a key").unwrap();
+        assert_eq!(
+            unsafe { sss_pubkey_color_hash(bad.as_ptr(), rgb.as_mut_ptr()) },
+            -2
+        );
+
+        let amount = CString::new("1.50").unwrap();
+        let mint = CString::new(USDC).unwrap();
+        let code = unsafe {
+            sss_qr_payload_for_address(
+                wallet.as_ptr(),
+                amount.as_ptr(),
+                mint.as_ptr(),
+                buffer.as_mut_ptr(),
+                len,
+            )
+        };
+        assert_eq!(code, 0);
+        let uri = read(&buffer);
+        assert_eq!(
+            uri,
+            qr_payload_for_address(&key(WALLET), Some("1.50"), Some(&key(USDC))).unwrap()
+        );
+        let code = unsafe {
+            sss_qr_payload_for_address(
+                wallet.as_ptr(),
+                std::ptr::null(),
+                std::ptr::null(),
+                buffer.as_mut_ptr(),
+                len,
+            )
+        };
+        assert_eq!(code, 0);
+        assert_eq!(read(&buffer), format!("solana:{}", WALLET));
+
+        let uri = CString::new(uri).unwrap();
+        let code = unsafe { sss_parse_solana_uri(uri.as_ptr(), buffer.as_mut_ptr(), len) };
+        assert_eq!(code, 0);
+        let json: serde_json::Value = serde_json::from_str(&read(&buffer)).unwrap();
+        assert_eq!(
+            json["data"],
+            serde_json::json!({
+                "recipient": WALLET,
+                "amount": "1.5",
+                "spl_token": USDC,
+                "references": [],
+                "label": null,
+                "message": null,
+                "memo": null,
+            })
+        );
+        let code = unsafe { sss_parse_solana_uri(bad.as_ptr(), buffer.as_mut_ptr(), len) };
+        assert_eq!(code, -2);
+    }
+}
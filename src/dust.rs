@@ -0,0 +1,247 @@
+//! Detection and sweeping of dust balances too small to be meaningful
+
+use crate::RPC_CLIENT;
+use crate::batch::BatchReport;
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::holders::{MintTokenAccount, mint_token_accounts};
+use crate::journal::{JournalOperation, journaled};
+use crate::metrics::track;
+use crate::mint_cache::{MintInfo, cache_mint};
+use crate::payer::{get_payer_pubkey, signing_payer};
+use crate::read_only::ensure_writable;
+use crate::serde_utils::{option_pubkey_string, pubkey_string};
+use crate::token::ensure_trusted_mint;
+use crate::transaction::{TxOptions, fitting_items, sign_and_send};
+use serde::Serialize;
+use serde_json::json;
+use solana_sdk::{instruction::Instruction, message::Message, pubkey::Pubkey};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_associated_token_account::instruction::create_associated_token_account_idempotent;
+use spl_token_2022::extension::permanent_delegate::PermanentDelegate;
+use spl_token_2022::extension::{BaseStateWithExtensions, StateWithExtensions};
+
+/// A token account holding less than the dust threshold
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DustAccount {
+    /// The token account holding the dust
+    #[serde(with = "pubkey_string")]
+    pub token_account: Pubkey,
+    /// The wallet owning the token account
+    #[serde(with = "pubkey_string")]
+    pub owner: Pubkey,
+    /// The raw token amount
+    pub amount: u64,
+    /// Whether the account is frozen, in which case it is never swept
+    pub frozen: bool,
+    /// The delegate approved on the account, in which case it is never swept
+    #[serde(with = "option_pubkey_string")]
+    pub delegate: Option<Pubkey>,
+}
+
+impl From<MintTokenAccount> for DustAccount {
+    fn from(account: MintTokenAccount) -> Self {
+        Self {
+            token_account: account.address,
+            owner: account.owner,
+            amount: account.amount,
+            frozen: account.frozen,
+            delegate: account.delegate,
+        }
+    }
+}
+
+/// Where [`sweep_dust`] moves the dust
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DustDestination {
+    /// Burns the dust, reducing the supply
+    Burn,
+    /// Transfers the dust to the associated token account of a collection wallet
+    Wallet(Pubkey),
+}
+
+/// Finds the token accounts of a mint holding a non-zero balance below a threshold
+///
+/// # Arguments
+///
+/// * `mint` - The mint whose holders should be scanned
+/// * `threshold_raw` - The smallest raw amount that is not dust
+///
+/// # Returns
+///
+/// The dust accounts, sorted by token account
+pub fn find_dust_accounts(mint: Pubkey, threshold_raw: u64) -> SssResult<Vec<DustAccount>> {
+    let (_, accounts) = mint_token_accounts(&RPC_CLIENT, &mint, None)?;
+    let mut dust: Vec<DustAccount> = accounts
+        .into_iter()
+        .filter(|account| account.amount > 0 && account.amount < threshold_raw)
+        .map(DustAccount::from)
+        .collect();
+    dust.sort_by_key(|account| account.token_account);
+    Ok(dust)
+}
+
+/// Burns or collects the dust of a mint in batched transactions
+///
+/// The payer can only move tokens of accounts it owns, or of any account if it is the
+/// permanent delegate of a Token-2022 mint. A sweep is resumed by running it again, since
+/// swept accounts are empty and no longer found; if a previous run was interrupted while a
+/// batch was in flight, run [`journal_reconcile`](crate::journal_reconcile) first.
+///
+/// # Arguments
+///
+/// * `mint` - The mint whose dust should be swept
+/// * `threshold_raw` - The smallest raw amount that is not dust
+/// * `destination` - Whether to burn the dust or transfer it to a collection wallet
+///
+/// # Returns
+///
+/// A report keyed by token account. Frozen accounts, accounts with a delegate and accounts
+/// of the collection wallet itself are skipped; accounts the payer has no authority over
+/// fail with a `TokenError`.
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the mint is not trusted in strict mode or does not exist
+pub fn sweep_dust(
+    mint: Pubkey,
+    threshold_raw: u64,
+    destination: DustDestination,
+) -> SssResult<BatchReport> {
+    ensure_writable()?;
+    track(JournalOperation::SweepDust, || {
+        ensure_trusted_mint(&mint)?;
+        let mint_account = RPC_CLIENT
+            .get_account(&mint)
+            .into_sss_error("Failed to get mint account from rpc")?;
+        let info = MintInfo::from_account(&mint, &mint_account)?;
+        cache_mint(mint, info);
+        let permanent_delegate =
+            StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_account.data)
+                .ok()
+                .and_then(|state| {
+                    state
+                        .get_extension::<PermanentDelegate>()
+                        .ok()
+                        .and_then(|extension| Option::<Pubkey>::from(extension.delegate))
+                });
+
+        let payer_pubkey = get_payer_pubkey()?;
+        let mut report = BatchReport::default();
+        let mut pending = Vec::new();
+        for account in find_dust_accounts(mint, threshold_raw)? {
+            let is_destination =
+                matches!(destination, DustDestination::Wallet(wallet) if wallet == account.owner);
+            if account.frozen {
+                report.skip(account.token_account, "account is frozen");
+            } else if let Some(delegate) = account.delegate {
+                report.skip(
+                    account.token_account,
+                    format!("delegate {} is approved on the account", delegate),
+                );
+            } else if is_destination {
+                report.skip(
+                    account.token_account,
+                    "account belongs to the collection wallet",
+                );
+            } else if account.owner != payer_pubkey && permanent_delegate != Some(payer_pubkey) {
+                report.failed.push((
+                account.token_account,
+                SssError::TokenError(format!(
+                    "Payer is neither the owner of token account {} nor the permanent delegate of mint {}",
+                    account.token_account, mint
+                )),
+            ));
+            } else {
+                pending.push(account);
+            }
+        }
+        if pending.is_empty() {
+            return Ok(report);
+        }
+
+        let program = info.token_program.id();
+        let setup = match destination {
+            DustDestination::Burn => None,
+            DustDestination::Wallet(wallet) => Some(create_associated_token_account_idempotent(
+                &payer_pubkey,
+                &wallet,
+                &mint,
+                &program,
+            )),
+        };
+        let build = |batch: &[DustAccount]| {
+            let mut instructions: Vec<Instruction> = setup.iter().cloned().collect();
+            for account in batch {
+                instructions.push(sweep_instruction(
+                    account,
+                    &mint,
+                    &info,
+                    &payer_pubkey,
+                    destination,
+                )?);
+            }
+            Ok(Message::new(&instructions, Some(&payer_pubkey)))
+        };
+
+        let payer = signing_payer()?;
+        let tx_options = TxOptions::default();
+        let mut rest = pending.as_slice();
+        while !rest.is_empty() {
+            let count = fitting_items(rest, 1, build)?;
+            let (batch, tail) = rest.split_at(count);
+            rest = tail;
+            let message = build(batch)?;
+            let params = json!({
+                "mint": mint.to_string(),
+                "token_accounts": batch
+                    .iter()
+                    .map(|account| account.token_account.to_string())
+                    .collect::<Vec<_>>(),
+            });
+            let keys = batch.iter().map(|account| account.token_account);
+            match journaled(JournalOperation::SweepDust, &params, &tx_options, || {
+                sign_and_send(&RPC_CLIENT, message, &[&payer], &tx_options)
+            }) {
+                Ok(signature) => report
+                    .succeeded
+                    .extend(keys.map(|key| (key, signature.clone()))),
+                Err(e) => report.fail_all(keys, &e),
+            }
+        }
+        Ok(report)
+    })
+}
+
+/// Builds the instruction burning or collecting the dust of one account
+fn sweep_instruction(
+    account: &DustAccount,
+    mint: &Pubkey,
+    info: &MintInfo,
+    authority: &Pubkey,
+    destination: DustDestination,
+) -> SssResult<Instruction> {
+    let program = info.token_program.id();
+    match destination {
+        DustDestination::Burn => spl_token_2022::instruction::burn_checked(
+            &program,
+            &account.token_account,
+            mint,
+            authority,
+            &[],
+            account.amount,
+            info.decimals,
+        )
+        .into_sss_error("Failed to build token burn instruction"),
+        DustDestination::Wallet(wallet) => spl_token_2022::instruction::transfer_checked(
+            &program,
+            &account.token_account,
+            mint,
+            &get_associated_token_address_with_program_id(&wallet, mint, &program),
+            authority,
+            &[],
+            account.amount,
+            info.decimals,
+        )
+        .into_sss_error("Failed to build token transfer instruction"),
+    }
+}
@@ -1,9 +1,12 @@
 //! Error types for the SSS Shared library
 
+use crate::rollback::CreatedAccounts;
+use chrono::{DateTime, Utc};
+use solana_sdk::pubkey::Pubkey;
 use std::fmt;
 
 /// Custom error type for the SSS Shared library
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum SssError {
     /// Error related to environment configuration
     ConfigError(String),
@@ -15,6 +18,63 @@ pub enum SssError {
     TokenError(String),
     /// Error related to FFI operations
     FfiError(String),
+    /// An identical operation is still unconfirmed, see [`DuplicatePolicy`](crate::DuplicatePolicy)
+    DuplicateInFlight(String),
+    /// A token creation that was sent but may not have landed
+    ///
+    /// Pass `mint` and `signature` to [`resume_create`](crate::resume_create) to find out.
+    PartialCreate {
+        /// The mint the transaction creates
+        mint: Pubkey,
+        /// The signature of the last transaction sent
+        signature: String,
+        /// The error that interrupted the creation
+        source: Box<SssError>,
+    },
+    /// A token whose metadata `valid_until` attribute has passed, see
+    /// [`is_metadata_expired`](crate::is_metadata_expired)
+    AssetExpired {
+        /// The mint of the expired token
+        mint: Pubkey,
+        /// The expiry from the metadata
+        valid_until: DateTime<Utc>,
+        /// The cluster time the expiry was compared with
+        cluster_time: DateTime<Utc>,
+    },
+    /// A claim link redeemed after its expiry, see
+    /// [`redeem_claim_link`](crate::redeem_claim_link)
+    ClaimLinkExpired {
+        /// The mint of the claimable tokens
+        mint: Pubkey,
+        /// The expiry encoded in the link
+        expiry: DateTime<Utc>,
+    },
+    /// A claim link whose tokens were already redeemed or reclaimed
+    ClaimLinkRedeemed {
+        /// The mint of the claimable tokens
+        mint: Pubkey,
+        /// The escrow token account the link held its tokens in
+        escrow: Pubkey,
+    },
+    /// A transaction larger than a packet, see [`assert_tx_size`](crate::assert_tx_size)
+    TransactionTooLarge {
+        /// The size of the signed transaction in bytes
+        size: usize,
+        /// The maximum size in bytes
+        limit: usize,
+        /// For a transaction carrying several items, how many to leave out so it fits
+        items_to_drop: Option<usize>,
+    },
+    /// A flow that failed after creating token accounts and paying their rent
+    ///
+    /// Pass `created` to [`rollback_created_accounts`](crate::rollback_created_accounts) to
+    /// close those still empty.
+    AccountsCreated {
+        /// The accounts created before the failure
+        created: CreatedAccounts,
+        /// The error that interrupted the flow
+        source: Box<SssError>,
+    },
 }
 
 impl fmt::Display for SssError {
@@ -25,11 +85,91 @@ impl fmt::Display for SssError {
             SssError::RpcError(msg) => write!(f, "RPC error: {}", msg),
             SssError::TokenError(msg) => write!(f, "Token error: {}", msg),
             SssError::FfiError(msg) => write!(f, "FFI error: {}", msg),
+            SssError::DuplicateInFlight(msg) => write!(f, "Duplicate operation: {}", msg),
+            SssError::PartialCreate {
+                mint,
+                signature,
+                source,
+            } => write!(
+                f,
+                "Creation of mint {} is unconfirmed (signature {}): {}",
+                mint, signature, source
+            ),
+            SssError::AssetExpired {
+                mint,
+                valid_until,
+                cluster_time,
+            } => write!(
+                f,
+                "Token {} expired at {} (cluster time {})",
+                mint, valid_until, cluster_time
+            ),
+            SssError::ClaimLinkExpired { mint, expiry } => {
+                write!(f, "Claim link for token {} expired at {}", mint, expiry)
+            }
+            SssError::ClaimLinkRedeemed { mint, escrow } => write!(
+                f,
+                "Claim link for token {} was already redeemed or reclaimed (escrow {})",
+                mint, escrow
+            ),
+            SssError::TransactionTooLarge {
+                size,
+                limit,
+                items_to_drop,
+            } => {
+                write!(
+                    f,
+                    "Transaction of {} bytes is {} bytes over the {} byte limit",
+                    size,
+                    size.saturating_sub(*limit),
+                    limit
+                )?;
+                match items_to_drop {
+                    Some(count) => write!(f, "; drop {} items to fit", count),
+                    None => Ok(()),
+                }
+            }
+            SssError::AccountsCreated { created, source } => write!(
+                f,
+                "{} (after creating {} token accounts funded by {})",
+                source,
+                created.accounts.len(),
+                created.funder
+            ),
         }
     }
 }
 
-impl std::error::Error for SssError {}
+impl SssError {
+    /// Returns a stable name of the error variant, used in JSON reports
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SssError::ConfigError(_) => "config",
+            SssError::KeypairError(_) => "keypair",
+            SssError::RpcError(_) => "rpc",
+            SssError::TokenError(_) => "token",
+            SssError::FfiError(_) => "ffi",
+            SssError::DuplicateInFlight(_) => "duplicate_in_flight",
+            SssError::PartialCreate { .. } => "partial_create",
+            SssError::AssetExpired { .. } => "asset_expired",
+            SssError::ClaimLinkExpired { .. } => "claim_link_expired",
+            SssError::ClaimLinkRedeemed { .. } => "claim_link_redeemed",
+            SssError::TransactionTooLarge { .. } => "transaction_too_large",
+            SssError::AccountsCreated { .. } => "accounts_created",
+        }
+    }
+}
+
+impl std::error::Error for SssError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SssError::PartialCreate { source, .. } | SssError::AccountsCreated { source, .. } => {
+                Some(source.as_ref())
+            }
+            _ => None,
+        }
+    }
+}
 
 /// Result type for the SSS Shared library
 pub type SssResult<T> = Result<T, SssError>;
@@ -58,3 +198,115 @@ impl<T, E: std::fmt::Display> IntoSssError<T> for Result<T, E> {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::program_error::ProgramError;
+    use solana_rpc_client_api::client_error::Error as ClientError;
+    use solana_rpc_client_api::request::{RpcError, RpcResponseErrorData};
+    use std::io;
+    use std::str::FromStr;
+
+    /// The message of an error carrying only one
+    fn message(error: &SssError) -> &str {
+        match error {
+            SssError::ConfigError(message)
+            | SssError::KeypairError(message)
+            | SssError::RpcError(message)
+            | SssError::TokenError(message)
+            | SssError::FfiError(message) => message,
+            other => panic!("not a plain error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn contexts_used_across_the_crate_pick_their_error_kind() {
+        let node_behind: ClientError = RpcError::RpcResponseError {
+            code: -32005,
+            message: "Node is behind by 153 slots".to_string(),
+            data: RpcResponseErrorData::NodeUnhealthy {
+                num_slots_behind: Some(153),
+            },
+        }
+        .into();
+        let not_found = || io::Error::new(io::ErrorKind::NotFound, "No such file or directory");
+        let truncated = serde_json::from_str::<Vec<String>>("[\"8T7Q").unwrap_err();
+        let bad_pubkey = Pubkey::from_str("not-a-mint").unwrap_err();
+
+        let table: Vec<(&str, SssResult<()>, &str, String)> = vec![
+            (
+                "an RPC failure",
+                Err(node_behind).into_sss_error("Failed to get mint account from rpc"),
+                "rpc",
+                "Failed to get mint account from rpc: RPC response error -32005: Node is \
+                 behind by 153 slots; "
+                    .to_string(),
+            ),
+            (
+                // "rpc" is matched before "token", so RPC failures stay RPC errors
+                "an RPC failure about tokens",
+                Err(io::Error::other("connection reset"))
+                    .into_sss_error("Failed to get token accounts from rpc"),
+                "rpc",
+                "Failed to get token accounts from rpc: connection reset".to_string(),
+            ),
+            (
+                "an account that does not parse",
+                Err(ProgramError::InvalidAccountData)
+                    .into_sss_error("Failed to parse token mint account"),
+                "token",
+                "Failed to parse token mint account: An account's data contents was invalid"
+                    .to_string(),
+            ),
+            (
+                "a missing configuration file",
+                Err(not_found()).into_sss_error("Failed to read profiles config"),
+                "config",
+                "Failed to read profiles config: No such file or directory".to_string(),
+            ),
+            (
+                // "config" is matched first, so a configuration file about mints stays one
+                "a truncated configuration file about mints",
+                Err(truncated).into_sss_error("Failed to parse trusted mints config trusted.json"),
+                "config",
+                "Failed to parse trusted mints config trusted.json: EOF while parsing a \
+                 string at line 1 column 6"
+                    .to_string(),
+            ),
+            (
+                "an invalid address in a configuration file",
+                Err(bad_pubkey)
+                    .into_sss_error("Invalid mint 'not-a-mint' in trusted mints config caps.json"),
+                "config",
+                "Invalid mint 'not-a-mint' in trusted mints config caps.json: Invalid Base58 \
+                 string"
+                    .to_string(),
+            ),
+            (
+                "a payer that cannot be loaded",
+                Err("SOLANA_PRIVATE_KEY is not set").into_sss_error("Failed to get payer keypair"),
+                "keypair",
+                "Failed to get payer keypair: SOLANA_PRIVATE_KEY is not set".to_string(),
+            ),
+            (
+                // "client" counts as RPC, whichever client it is
+                "an HTTP client that cannot be built",
+                Err("no TLS backend").into_sss_error("Failed to build HTTP client"),
+                "rpc",
+                "Failed to build HTTP client: no TLS backend".to_string(),
+            ),
+            (
+                "anything else",
+                Err(not_found()).into_sss_error("Failed to write airdrop checkpoint"),
+                "ffi",
+                "Failed to write airdrop checkpoint: No such file or directory".to_string(),
+            ),
+        ];
+        for (case, result, kind, message) in table {
+            let error = result.unwrap_err();
+            assert_eq!(error.kind(), kind, "{}: {:?}", case, error);
+            assert_eq!(self::message(&error), message, "{}", case);
+        }
+    }
+}
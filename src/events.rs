@@ -0,0 +1,308 @@
+//! Machine-readable lifecycle events delivered to a user-supplied sink
+//!
+//! Operations queue events without waiting: a single background thread delivers them to
+//! the sink in order. Events that do not fit in the queue while the sink is slow are
+//! dropped as configured with [`crate::set_callback_queue_options`], counted in
+//! [`dropped_events`] and announced by an `events_dropped` event.
+//!
+//! A successful mint emits, in order:
+//!
+//! 1. `transaction_sent`
+//! 2. `transaction_confirmed`
+//! 3. `payer_low_on_funds`, only if the payer balance is below `SSS_LOW_BALANCE_LAMPORTS`
+//! 4. `mint_confirmed`
+//!
+//! A mint whose first attempts hit transient RPC errors emits one `retry_scheduled` per
+//! retried attempt between `transaction_sent` and `transaction_confirmed`, and a failed
+//! transaction emits `transaction_failed` instead of `transaction_confirmed`. Token creation
+//! emits `token_created` where a mint emits `mint_confirmed`, followed by `mint_confirmed`
+//! when the creation also mints an initial supply.
+
+use crate::dispatch::{
+    CallbackQueue, Delivery, Dispatcher, QueueMetrics, QueueOptions, idle_queue_metrics,
+};
+use crate::journal::{JournalOperation, JournalStatus};
+use crate::load_dotenv;
+use crate::policies::PolicyReload;
+use crate::serde_utils::pubkey_string;
+use crate::supply_monitor::SupplyAnomaly;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Number of events queued for a slow sink before further events are dropped, unless
+/// configured otherwise
+pub const EVENT_QUEUE_SIZE: usize = 1024;
+
+/// An event in the lifecycle of an operation
+///
+/// Serialized as JSON objects tagged with a snake_case `type`, e.g.
+/// `{"type": "transaction_sent", "signature": "..."}`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SssEvent {
+    /// A signed transaction is about to be submitted
+    TransactionSent {
+        /// The transaction signature
+        signature: String,
+    },
+    /// A submission failed with a transient error and will be repeated
+    RetryScheduled {
+        /// The transaction signature
+        signature: String,
+        /// The number of the failed attempt, starting at 1
+        attempt: usize,
+        /// Why the error is considered transient
+        reason: String,
+        /// The error of the failed attempt
+        error: String,
+        /// The delay before the next attempt in milliseconds
+        delay_ms: u64,
+    },
+    /// A transaction was confirmed
+    TransactionConfirmed {
+        /// The transaction signature
+        signature: String,
+    },
+    /// A transaction was rejected or failed on chain
+    TransactionFailed {
+        /// The transaction signature
+        signature: String,
+        /// Why the transaction failed
+        error: String,
+    },
+    /// The payer balance dropped below `SSS_LOW_BALANCE_LAMPORTS` after a transaction
+    PayerLowOnFunds {
+        /// The payer
+        #[serde(with = "pubkey_string")]
+        payer: Pubkey,
+        /// The payer balance in lamports
+        lamports: u64,
+        /// The configured threshold in lamports
+        threshold: u64,
+    },
+    /// A token was created
+    TokenCreated {
+        /// The transaction signature
+        signature: String,
+        /// The new mint
+        #[serde(with = "pubkey_string")]
+        mint: Pubkey,
+    },
+    /// A token account was resolved in a way the caller should double-check, e.g. the
+    /// associated token account of an off-curve recipient
+    TokenAccountWarning {
+        /// The mint
+        #[serde(with = "pubkey_string")]
+        mint: Pubkey,
+        /// The owner of the token account
+        #[serde(with = "pubkey_string")]
+        owner: Pubkey,
+        /// The token account that was used
+        #[serde(with = "pubkey_string")]
+        token_account: Pubkey,
+        /// What to double-check
+        warning: String,
+    },
+    /// Tokens were minted
+    MintConfirmed {
+        /// The transaction signature
+        signature: String,
+        /// The mint
+        #[serde(with = "pubkey_string")]
+        mint: Pubkey,
+        /// The wallet owning the receiving token account
+        #[serde(with = "pubkey_string")]
+        owner: Pubkey,
+        /// The amount minted in base units
+        amount: u64,
+    },
+    /// The trusted mints and supply cap files were reloaded
+    PoliciesReloaded {
+        /// What changed
+        #[serde(flatten)]
+        changes: PolicyReload,
+    },
+    /// Reloading the policy files failed and the previous policies were kept
+    PolicyReloadFailed {
+        /// Why the files were rejected
+        error: String,
+    },
+    /// A monitored mint supply strayed from its schedule, see [`crate::monitor_supply`]
+    SupplyAnomaly {
+        /// The expected and actual supply, with the transactions that changed it
+        #[serde(flatten)]
+        anomaly: SupplyAnomaly,
+    },
+    /// A transaction left unresolved by an earlier run was resolved, see
+    /// [`crate::recover_in_flight`]
+    InFlightRecovered {
+        /// The transaction signature
+        signature: String,
+        /// The operation that sent the transaction
+        operation: JournalOperation,
+        /// The idempotency key the operation ran under, if any
+        idempotency_key: Option<String>,
+        /// The new state: `confirmed`, `failed`, `expired` or `unknown`
+        status: JournalStatus,
+        /// Why the transaction failed, or why its outcome is unknown
+        error: Option<String>,
+    },
+    /// Items were dropped from a full callback queue since the last such event
+    EventsDropped {
+        /// The queue that overflowed
+        queue: CallbackQueue,
+        /// How many items were dropped
+        count: u64,
+    },
+    /// A callback has been running longer than the watchdog timeout
+    CallbackStalled {
+        /// The queue whose callback is stalled
+        queue: CallbackQueue,
+        /// How long it has been running in milliseconds
+        stalled_ms: u64,
+    },
+}
+
+/// Receives every event, on the delivery thread
+type EventSink = Arc<dyn Fn(SssEvent) + Send + Sync>;
+
+lazy_static! {
+    /// Payer balance below which `PayerLowOnFunds` is emitted, from `SSS_LOW_BALANCE_LAMPORTS`
+    static ref LOW_BALANCE_LAMPORTS: Option<u64> = {
+        load_dotenv();
+        env::var("SSS_LOW_BALANCE_LAMPORTS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+    };
+
+    /// The installed sink
+    static ref SINK: Mutex<Option<EventSink>> = Mutex::new(None);
+
+    /// Queue to the delivery thread, `None` until a sink is first installed
+    static ref QUEUE: Mutex<Option<Arc<Dispatcher<SssEvent>>>> = Mutex::new(None);
+}
+
+/// Whether a sink is installed, checked before building events
+static SINK_SET: AtomicBool = AtomicBool::new(false);
+
+/// Set by [`crate::shutdown`]; no event is delivered afterwards
+static SHUT_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Installs the sink receiving every event, replacing any previous one
+///
+/// The sink runs on a background thread, one event at a time. It may replace or clear
+/// itself but must not call [`crate::shutdown`]. A sink replacing one stalled past
+/// `SSS_CALLBACK_WATCHDOG_MS` gets a new delivery thread and the queued events. Ignored
+/// after shutdown.
+///
+/// # Arguments
+///
+/// * `sink` - Called with each event
+pub fn set_event_sink(sink: impl Fn(SssEvent) + Send + Sync + 'static) {
+    if SHUT_DOWN.load(Ordering::SeqCst) {
+        return;
+    }
+    if let Ok(mut current) = SINK.lock() {
+        *current = Some(Arc::new(sink));
+        SINK_SET.store(true, Ordering::SeqCst);
+    }
+    if let Ok(mut queue) = QUEUE.lock() {
+        match queue.as_ref() {
+            None => *queue = Some(Dispatcher::start(CallbackQueue::Events, deliver)),
+            Some(dispatcher) if dispatcher.stalled_for().is_some() => {
+                *queue = Some(dispatcher.restart(deliver));
+            }
+            Some(_) => {}
+        }
+    }
+}
+
+/// Removes the event sink; events are no longer queued
+pub fn clear_event_sink() {
+    if let Ok(mut current) = SINK.lock() {
+        *current = None;
+        SINK_SET.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Returns the number of events dropped because the sink could not keep up
+pub fn dropped_events() -> u64 {
+    event_queue_metrics().dropped
+}
+
+/// Returns whether events are being delivered, so callers can skip building them
+pub(crate) fn events_enabled() -> bool {
+    SINK_SET.load(Ordering::SeqCst) && !SHUT_DOWN.load(Ordering::SeqCst)
+}
+
+/// Queues an event for the sink, waiting only if the overflow policy is to block
+pub(crate) fn emit(event: SssEvent) {
+    if !events_enabled() {
+        return;
+    }
+    let dispatcher = QUEUE.lock().ok().and_then(|queue| queue.clone());
+    if let Some(dispatcher) = dispatcher {
+        dispatcher.push(event);
+    }
+}
+
+/// Returns the state of the event queue
+pub(crate) fn event_queue_metrics() -> QueueMetrics {
+    let dispatcher = QUEUE.lock().ok().and_then(|queue| queue.clone());
+    match dispatcher {
+        Some(dispatcher) => dispatcher.metrics(),
+        None => idle_queue_metrics(CallbackQueue::Events),
+    }
+}
+
+/// Applies new options to the event queue if it is running
+pub(crate) fn apply_queue_options(options: QueueOptions) {
+    if let Ok(queue) = QUEUE.lock()
+        && let Some(dispatcher) = queue.as_ref()
+    {
+        dispatcher.set_options(options);
+    }
+}
+
+/// Returns the low balance threshold if events are delivered and one is configured
+pub(crate) fn low_balance_threshold() -> Option<u64> {
+    LOW_BALANCE_LAMPORTS.filter(|_| events_enabled())
+}
+
+/// Stops event delivery for good, waiting for an event being delivered to finish unless
+/// the sink is stalled
+///
+/// Queued events are discarded, but drops not reported yet still reach the sink as a last
+/// `events_dropped` event.
+pub(crate) fn shutdown_events() {
+    SHUT_DOWN.store(true, Ordering::SeqCst);
+    // The closed queue stays in place so its counters remain in the metrics
+    let dispatcher = QUEUE.lock().ok().and_then(|queue| queue.clone());
+    if let Some(dispatcher) = dispatcher {
+        dispatcher.close();
+    }
+    clear_event_sink();
+}
+
+/// Delivers a queued event, or the report of dropped ones, to the current sink
+///
+/// Once shutting down, only the report is delivered, until the sink is cleared.
+fn deliver(delivery: Delivery<SssEvent>) {
+    if SHUT_DOWN.load(Ordering::SeqCst) && matches!(delivery, Delivery::Item(_)) {
+        return;
+    }
+    let sink = SINK.lock().ok().and_then(|sink| sink.clone());
+    if let Some(sink) = sink {
+        sink(match delivery {
+            Delivery::Item(event) => event,
+            Delivery::Dropped(count) => SssEvent::EventsDropped {
+                queue: CallbackQueue::Events,
+                count,
+            },
+        });
+    }
+}
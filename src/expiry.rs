@@ -0,0 +1,168 @@
+//! Expiry of time-boxed tokens, checked against the cluster clock
+//!
+//! The expiry is the `valid_until` attribute of the off-chain metadata, see
+//! [`OffchainMetadata::valid_until`]. It is compared with the block time of the cluster
+//! rather than the local clock, which may be skewed, and a grace period absorbs the lag of
+//! block times behind real time.
+
+#[cfg(feature = "http-metadata")]
+use crate::content_cache::fetch_metadata;
+use crate::error::{IntoSssError, SssError, SssResult};
+#[cfg(feature = "http-metadata")]
+use crate::mint_cache::metadata_uri;
+use crate::offchain_metadata::OffchainMetadata;
+use crate::{RPC_CLIENT, load_dotenv};
+use chrono::{DateTime, TimeDelta, Utc};
+use lazy_static::lazy_static;
+use solana_rpc_client::rpc_client::RpcClient;
+#[cfg(feature = "http-metadata")]
+use solana_sdk::pubkey::Pubkey;
+use std::env;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Default time past `valid_until` during which a token still counts as valid
+pub const DEFAULT_EXPIRY_GRACE_PERIOD: Duration = Duration::from_secs(300);
+
+/// How long a fetched cluster time is reused, advanced by the local monotonic clock
+pub const CLUSTER_TIME_CACHE_TTL: Duration = Duration::from_secs(10);
+
+lazy_static! {
+    /// Grace period in seconds, seeded from `SSS_EXPIRY_GRACE_SECS`
+    static ref GRACE_PERIOD_SECS: AtomicU64 = {
+        load_dotenv();
+        let secs = env::var("SSS_EXPIRY_GRACE_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_EXPIRY_GRACE_PERIOD.as_secs());
+        AtomicU64::new(secs)
+    };
+
+    /// The last fetched block time and when it was fetched
+    static ref CLUSTER_TIME: Mutex<Option<(Instant, DateTime<Utc>)>> = Mutex::new(None);
+}
+
+/// Sets the time past `valid_until` during which a token still counts as valid
+///
+/// # Arguments
+///
+/// * `grace` - The grace period, whole seconds
+pub fn set_expiry_grace_period(grace: Duration) {
+    GRACE_PERIOD_SECS.store(grace.as_secs(), Ordering::Relaxed);
+}
+
+/// Returns the current cluster time
+///
+/// The block time of the latest finalized slot is fetched at most once per
+/// [`CLUSTER_TIME_CACHE_TTL`] and advanced by the local monotonic clock in between.
+///
+/// # Errors
+///
+/// Returns an `RpcError` if the slot or its block time cannot be fetched
+pub fn cluster_time() -> SssResult<DateTime<Utc>> {
+    if let Ok(cached) = CLUSTER_TIME.lock()
+        && let Some((fetched_at, time)) = *cached
+        && fetched_at.elapsed() < CLUSTER_TIME_CACHE_TTL
+    {
+        return Ok(time + TimeDelta::from_std(fetched_at.elapsed()).unwrap_or_default());
+    }
+
+    let fetched_at = Instant::now();
+    let time = fetch_cluster_time(&RPC_CLIENT)?;
+    if let Ok(mut cached) = CLUSTER_TIME.lock() {
+        *cached = Some((fetched_at, time));
+    }
+    Ok(time)
+}
+
+/// Fetches the block time of the latest slot of a node, bypassing the cache
+///
+/// # Errors
+///
+/// Returns an `RpcError` if the slot or its block time cannot be fetched
+pub(crate) fn fetch_cluster_time(client: &RpcClient) -> SssResult<DateTime<Utc>> {
+    let slot = client
+        .get_slot()
+        .into_sss_error("Failed to get slot from rpc")?;
+    let block_time = client
+        .get_block_time(slot)
+        .into_sss_error("Failed to get block time from rpc")?;
+    DateTime::from_timestamp(block_time, 0).ok_or_else(|| {
+        SssError::RpcError(format!(
+            "Block time {} of slot {} is invalid",
+            block_time, slot
+        ))
+    })
+}
+
+/// Returns whether the `valid_until` attribute of off-chain metadata has passed
+///
+/// Metadata without the attribute never expires. The token counts as valid until the
+/// grace period after `valid_until` has passed on the cluster clock.
+///
+/// # Arguments
+///
+/// * `metadata` - The off-chain metadata of the token
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the attribute is malformed, or an `RpcError` if the cluster
+/// time cannot be fetched
+pub fn is_metadata_expired(metadata: &OffchainMetadata) -> SssResult<bool> {
+    Ok(expiry(metadata)?.is_some())
+}
+
+/// Returns whether the token of a mint has expired, see [`is_metadata_expired`]
+///
+/// The off-chain metadata is downloaded from the URI in the on-chain metadata account.
+///
+/// # Arguments
+///
+/// * `mint` - The public key of the token's mint account
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the mint has no metadata account or its document cannot be
+/// fetched or parsed, or an `RpcError` if the cluster time cannot be fetched
+#[cfg(feature = "http-metadata")]
+pub fn is_asset_expired(mint: &Pubkey) -> SssResult<bool> {
+    Ok(expiry(&fetch_offchain_metadata(mint)?)?.is_some())
+}
+
+/// Fails with `AssetExpired` if the token of a mint has expired
+#[cfg(feature = "http-metadata")]
+pub(crate) fn ensure_not_expired(mint: &Pubkey) -> SssResult<()> {
+    match expiry(&fetch_offchain_metadata(mint)?)? {
+        Some((valid_until, cluster_time)) => Err(SssError::AssetExpired {
+            mint: *mint,
+            valid_until,
+            cluster_time,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Returns `valid_until` and the cluster time if the grace period after it has passed
+fn expiry(metadata: &OffchainMetadata) -> SssResult<Option<(DateTime<Utc>, DateTime<Utc>)>> {
+    let Some(valid_until) = metadata.get_valid_until()? else {
+        return Ok(None);
+    };
+    let grace = i64::try_from(GRACE_PERIOD_SECS.load(Ordering::Relaxed))
+        .ok()
+        .and_then(TimeDelta::try_seconds)
+        .unwrap_or(TimeDelta::MAX);
+    let now = cluster_time()?;
+    let expired = valid_until
+        .checked_add_signed(grace)
+        .is_some_and(|deadline| now > deadline);
+    Ok(expired.then_some((valid_until, now)))
+}
+
+/// Fetches the off-chain metadata a mint's metadata account points to
+#[cfg(feature = "http-metadata")]
+fn fetch_offchain_metadata(mint: &Pubkey) -> SssResult<OffchainMetadata> {
+    let uri = metadata_uri(&RPC_CLIENT, mint)?
+        .ok_or_else(|| SssError::TokenError(format!("Mint {} has no metadata account", mint)))?;
+    fetch_metadata(&uri)
+}
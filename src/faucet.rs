@@ -0,0 +1,500 @@
+//! A test token faucet for QA tooling
+//!
+//! [`faucet_grant`] mints a test token to any wallet that asks, within daily caps per
+//! recipient and across all recipients. The grants are accounted in the operation journal:
+//! each is minted under an idempotency key naming the mint, the UTC day, the recipient and
+//! the amount, so the caps hold across restarts and a grant is never counted twice. Only
+//! compiled with the `dev-tools` feature, and refused on mainnet-beta.
+
+use crate::RPC_CLIENT;
+use crate::amount::RawAmount;
+use crate::devtools::is_mainnet;
+use crate::error::{SssError, SssResult};
+use crate::journal::{
+    JournalEntry, JournalFilter, JournalOperation, JournalStatus, journal_enabled, journal_query,
+};
+use crate::read_only::ensure_writable;
+use crate::serde_utils::pubkey_string;
+use crate::token::{MintOptions, mint_token_detailed_with_client};
+use crate::transaction::TxOptions;
+use serde::Serialize;
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Seconds in a faucet day, which starts at midnight UTC
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Prefix of the idempotency keys of faucet grants
+const GRANT_KEY_PREFIX: &str = "faucet";
+
+/// Serializes grants in this process, so concurrent requests see each other's grants
+static GRANT_LOCK: Mutex<()> = Mutex::new(());
+
+/// Daily limits of a faucet, in base units of the mint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaucetPolicy {
+    /// Amount a single recipient may receive per UTC day
+    pub per_recipient_daily: u64,
+    /// Amount all recipients together may receive per UTC day
+    pub global_daily: u64,
+}
+
+/// Which daily cap stopped a grant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FaucetLimit {
+    /// [`FaucetPolicy::per_recipient_daily`]
+    Recipient,
+    /// [`FaucetPolicy::global_daily`]
+    Global,
+}
+
+/// Result of a faucet request, see [`faucet_grant`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum FaucetOutcome {
+    /// The tokens were minted to the recipient
+    Granted {
+        /// The mint transaction signature
+        signature: String,
+        /// The recipient
+        #[serde(with = "pubkey_string")]
+        recipient: Pubkey,
+        /// The amount minted
+        amount: u64,
+        /// The amount granted to the recipient today, this grant included
+        recipient_total: u64,
+        /// The amount granted to everyone today, this grant included
+        global_total: u64,
+    },
+    /// A daily cap would be exceeded, so nothing was minted
+    Throttled {
+        /// The cap that would be exceeded
+        limit: FaucetLimit,
+        /// The amount already granted today under that cap
+        granted_today: u64,
+        /// The cap
+        cap: u64,
+        /// Unix time in seconds the cap resets, the next midnight UTC
+        resets_at: u64,
+    },
+}
+
+/// Mints a test token to a wallet within the daily caps of a policy
+///
+/// Grants of the current UTC day are read back from the journal; failed and expired mints
+/// do not count, while mints of unknown outcome do. Requests are serialized within the
+/// process, so concurrent requests for the same recipient never both pass a cap that only
+/// one fits, and each grant is minted under its own idempotency key so a replayed grant
+/// returns the original signature when a dedup store is configured.
+///
+/// # Arguments
+///
+/// * `mint` - The test token, whose mint authority must be the payer
+/// * `recipient` - The wallet receiving the tokens
+/// * `amount` - The amount to mint in base units
+/// * `policy` - The daily caps
+///
+/// # Returns
+///
+/// The grant, or the cap that stopped it and when it resets
+///
+/// # Errors
+///
+/// Returns a `ConfigError` on mainnet-beta or if `SSS_JOURNAL_PATH` is not set, a
+/// `TokenError` if the amount is 0, and any error of the mint itself
+pub fn faucet_grant(
+    mint: Pubkey,
+    recipient: Pubkey,
+    amount: u64,
+    policy: FaucetPolicy,
+) -> SssResult<FaucetOutcome> {
+    faucet_grant_with_client(&RPC_CLIENT, mint, recipient, amount, policy)
+}
+
+/// Mints a test token within the daily caps like [`faucet_grant`], checking the cluster
+/// and minting through the given RPC client
+///
+/// # Arguments
+///
+/// * `client` - The RPC client used to check the cluster and to mint
+/// * `mint` - The test token, whose mint authority must be the payer
+/// * `recipient` - The wallet receiving the tokens
+/// * `amount` - The amount to mint in base units
+/// * `policy` - The daily caps
+///
+/// # Returns
+///
+/// The grant, or the cap that stopped it and when it resets
+pub fn faucet_grant_with_client(
+    client: &RpcClient,
+    mint: Pubkey,
+    recipient: Pubkey,
+    amount: u64,
+    policy: FaucetPolicy,
+) -> SssResult<FaucetOutcome> {
+    ensure_writable()?;
+    if amount == 0 {
+        return Err(SssError::TokenError(
+            "Faucet amount must be greater than 0".to_string(),
+        ));
+    }
+    if !journal_enabled() {
+        return Err(SssError::ConfigError(
+            "The faucet accounts its grants in the journal; set SSS_JOURNAL_PATH".to_string(),
+        ));
+    }
+    if is_mainnet(client)? {
+        return Err(SssError::ConfigError(
+            "Refusing to run the faucet on mainnet".to_string(),
+        ));
+    }
+
+    let _guard = GRANT_LOCK
+        .lock()
+        .map_err(|e| SssError::ConfigError(format!("Faucet lock poisoned: {}", e)))?;
+    let now = unix_now();
+    let day = now / SECONDS_PER_DAY;
+    let entries = journal_query(&JournalFilter {
+        operation: Some(JournalOperation::MintToken),
+        since: Some(day * SECONDS_PER_DAY),
+        ..JournalFilter::default()
+    })?;
+    let grants = grants_of_day(entries, &mint, day);
+    let (recipient_total, attempts) = match check_caps(&grants, recipient, amount, &policy, now) {
+        Ok(totals) => totals,
+        Err(throttled) => return Ok(throttled),
+    };
+    let global_total = counted_total(grants.iter());
+
+    // Every attempt of the day gets the next sequence number, so a failed one is not
+    // mistaken for a replay of the next
+    let key = format!(
+        "{}:{}:{}:{}:{}:{}",
+        GRANT_KEY_PREFIX, mint, day, recipient, attempts, amount
+    );
+    let options = MintOptions {
+        tx: TxOptions {
+            idempotency_key: Some(key),
+            ..TxOptions::default()
+        },
+        ..MintOptions::default()
+    };
+    let signature = mint_token_detailed_with_client(
+        client,
+        mint,
+        Some(recipient),
+        RawAmount(amount),
+        &options,
+    )?
+    .signature;
+    Ok(FaucetOutcome::Granted {
+        signature,
+        recipient,
+        amount,
+        recipient_total: recipient_total + amount,
+        global_total: global_total + amount,
+    })
+}
+
+/// Checks a grant of `amount` at Unix time `now` against the caps of a policy
+///
+/// # Returns
+///
+/// The amount granted to the recipient on the day and the number of its attempts, or the
+/// `Throttled` outcome of the first cap the grant would exceed
+fn check_caps(
+    grants: &[Grant],
+    recipient: Pubkey,
+    amount: u64,
+    policy: &FaucetPolicy,
+    now: u64,
+) -> Result<(u64, usize), FaucetOutcome> {
+    let recipient_grants: Vec<&Grant> = grants
+        .iter()
+        .filter(|grant| grant.recipient == recipient)
+        .collect();
+    let recipient_total = counted_total(recipient_grants.iter().copied());
+    let global_total = counted_total(grants.iter());
+    for (limit, granted_today, cap) in [
+        (
+            FaucetLimit::Recipient,
+            recipient_total,
+            policy.per_recipient_daily,
+        ),
+        (FaucetLimit::Global, global_total, policy.global_daily),
+    ] {
+        if granted_today.saturating_add(amount) > cap {
+            return Err(FaucetOutcome::Throttled {
+                limit,
+                granted_today,
+                cap,
+                resets_at: (now / SECONDS_PER_DAY + 1) * SECONDS_PER_DAY,
+            });
+        }
+    }
+    Ok((recipient_total, recipient_grants.len()))
+}
+
+/// A faucet grant found in the journal
+#[derive(Debug, PartialEq, Eq)]
+struct Grant {
+    recipient: Pubkey,
+    amount: u64,
+    /// Whether a transaction of the grant landed, may still land or may have landed
+    counted: bool,
+}
+
+/// Returns the faucet grants of a token on a UTC day among journal entries, read from
+/// their idempotency keys
+///
+/// A grant resent with a fresh blockhash has several transactions under its key; it is
+/// listed once.
+fn grants_of_day(entries: Vec<JournalEntry>, mint: &Pubkey, day: u64) -> Vec<Grant> {
+    let prefix = format!("{}:{}:{}:", GRANT_KEY_PREFIX, mint, day);
+    let mut grants: BTreeMap<String, Grant> = BTreeMap::new();
+    for entry in entries {
+        let Some(rest) = entry
+            .idempotency_key
+            .as_deref()
+            .and_then(|key| key.strip_prefix(&prefix))
+        else {
+            continue;
+        };
+        let mut parts = rest.split(':');
+        let (Some(Ok(recipient)), Some(Ok(amount))) = (
+            parts.next().map(Pubkey::from_str),
+            parts.nth(1).map(str::parse::<u64>),
+        ) else {
+            continue;
+        };
+        let counted = matches!(
+            entry.status,
+            JournalStatus::Sent | JournalStatus::Confirmed | JournalStatus::Unknown
+        );
+        grants
+            .entry(rest.to_string())
+            .and_modify(|grant| grant.counted |= counted)
+            .or_insert(Grant {
+                recipient,
+                amount,
+                counted,
+            });
+    }
+    grants.into_values().collect()
+}
+
+/// Sums the grants that landed or may still land
+fn counted_total<'a>(grants: impl Iterator<Item = &'a Grant>) -> u64 {
+    grants
+        .filter(|grant| grant.counted)
+        .fold(0, |total, grant| total.saturating_add(grant.amount))
+}
+
+/// Returns the current Unix time in seconds
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Noon UTC of a day, Unix time 1_700_049_600
+    const NOON: u64 = 19_676 * SECONDS_PER_DAY + SECONDS_PER_DAY / 2;
+    const DAY: u64 = NOON / SECONDS_PER_DAY;
+
+    const POLICY: FaucetPolicy = FaucetPolicy {
+        per_recipient_daily: 100,
+        global_daily: 250,
+    };
+
+    fn entry(key: String, status: JournalStatus) -> JournalEntry {
+        JournalEntry {
+            operation: JournalOperation::MintToken,
+            params_hash: String::new(),
+            idempotency_key: Some(key),
+            signature: String::new(),
+            blockhash: String::new(),
+            status,
+            error: None,
+            created_at: NOON,
+            updated_at: NOON,
+        }
+    }
+
+    fn grant(mint: &Pubkey, day: u64, recipient: &Pubkey, attempt: usize, amount: u64) -> String {
+        format!(
+            "{}:{}:{}:{}:{}:{}",
+            GRANT_KEY_PREFIX, mint, day, recipient, attempt, amount
+        )
+    }
+
+    fn throttled(limit: FaucetLimit, granted_today: u64, cap: u64) -> FaucetOutcome {
+        FaucetOutcome::Throttled {
+            limit,
+            granted_today,
+            cap,
+            resets_at: (DAY + 1) * SECONDS_PER_DAY,
+        }
+    }
+
+    #[test]
+    fn grant_reaching_the_cap_exactly_passes_and_one_more_unit_is_throttled() {
+        let mint = Pubkey::new_unique();
+        let recipient = Pubkey::new_unique();
+        let grants = grants_of_day(
+            vec![entry(
+                grant(&mint, DAY, &recipient, 0, 60),
+                JournalStatus::Confirmed,
+            )],
+            &mint,
+            DAY,
+        );
+        assert_eq!(
+            check_caps(&grants, recipient, 40, &POLICY, NOON),
+            Ok((60, 1))
+        );
+        assert_eq!(
+            check_caps(&grants, recipient, 41, &POLICY, NOON),
+            Err(throttled(FaucetLimit::Recipient, 60, 100))
+        );
+        assert_eq!(
+            check_caps(&grants, recipient, u64::MAX, &POLICY, NOON),
+            Err(throttled(FaucetLimit::Recipient, 60, 100))
+        );
+    }
+
+    #[test]
+    fn global_cap_counts_every_recipient() {
+        let mint = Pubkey::new_unique();
+        let entries = (0..5)
+            .map(|_| {
+                entry(
+                    grant(&mint, DAY, &Pubkey::new_unique(), 0, 50),
+                    JournalStatus::Confirmed,
+                )
+            })
+            .collect();
+        let grants = grants_of_day(entries, &mint, DAY);
+        let newcomer = Pubkey::new_unique();
+        assert_eq!(
+            check_caps(&grants, newcomer, 1, &POLICY, NOON),
+            Err(throttled(FaucetLimit::Global, 250, 250))
+        );
+
+        let grants = &grants[1..];
+        assert_eq!(check_caps(grants, newcomer, 50, &POLICY, NOON), Ok((0, 0)));
+        assert_eq!(
+            check_caps(grants, newcomer, 51, &POLICY, NOON),
+            Err(throttled(FaucetLimit::Global, 200, 250))
+        );
+    }
+
+    #[test]
+    fn grants_of_the_previous_day_do_not_count() {
+        let mint = Pubkey::new_unique();
+        let recipient = Pubkey::new_unique();
+        let entries = || {
+            vec![entry(
+                grant(&mint, DAY, &recipient, 0, 100),
+                JournalStatus::Confirmed,
+            )]
+        };
+
+        // The last second of the day is still capped, the first of the next is not
+        let last_second = (DAY + 1) * SECONDS_PER_DAY - 1;
+        let grants = grants_of_day(entries(), &mint, last_second / SECONDS_PER_DAY);
+        assert_eq!(
+            check_caps(&grants, recipient, 1, &POLICY, last_second),
+            Err(throttled(FaucetLimit::Recipient, 100, 100))
+        );
+
+        let midnight = last_second + 1;
+        let grants = grants_of_day(entries(), &mint, midnight / SECONDS_PER_DAY);
+        assert!(grants.is_empty());
+        assert_eq!(
+            check_caps(&grants, recipient, 100, &POLICY, midnight),
+            Ok((0, 0))
+        );
+        let Err(FaucetOutcome::Throttled { resets_at, .. }) =
+            check_caps(&grants, recipient, 101, &POLICY, midnight)
+        else {
+            panic!("expected the grant to be throttled");
+        };
+        assert_eq!(resets_at, midnight + SECONDS_PER_DAY);
+    }
+
+    #[test]
+    fn failed_grants_do_not_count_but_grants_of_unknown_outcome_do() {
+        let mint = Pubkey::new_unique();
+        let recipient = Pubkey::new_unique();
+        let grants = grants_of_day(
+            vec![
+                entry(grant(&mint, DAY, &recipient, 0, 30), JournalStatus::Failed),
+                entry(grant(&mint, DAY, &recipient, 1, 30), JournalStatus::Expired),
+                entry(grant(&mint, DAY, &recipient, 2, 30), JournalStatus::Unknown),
+                entry(grant(&mint, DAY, &recipient, 3, 30), JournalStatus::Sent),
+            ],
+            &mint,
+            DAY,
+        );
+        // Failed attempts still advance the sequence number of the next one
+        assert_eq!(
+            check_caps(&grants, recipient, 40, &POLICY, NOON),
+            Ok((60, 4))
+        );
+    }
+
+    #[test]
+    fn resent_grant_is_counted_once() {
+        let mint = Pubkey::new_unique();
+        let recipient = Pubkey::new_unique();
+        let key = grant(&mint, DAY, &recipient, 0, 70);
+        let grants = grants_of_day(
+            vec![
+                entry(key.clone(), JournalStatus::Expired),
+                entry(key, JournalStatus::Confirmed),
+            ],
+            &mint,
+            DAY,
+        );
+        assert_eq!(
+            grants,
+            vec![Grant {
+                recipient,
+                amount: 70,
+                counted: true,
+            }]
+        );
+    }
+
+    #[test]
+    fn other_mints_and_foreign_keys_are_ignored() {
+        let mint = Pubkey::new_unique();
+        let recipient = Pubkey::new_unique();
+        let grants = grants_of_day(
+            vec![
+                entry(
+                    grant(&Pubkey::new_unique(), DAY, &recipient, 0, 70),
+                    JournalStatus::Confirmed,
+                ),
+                entry("order-4711".to_string(), JournalStatus::Confirmed),
+                entry(
+                    format!("{}:{}:{}:not-a-key:0:70", GRANT_KEY_PREFIX, mint, DAY),
+                    JournalStatus::Confirmed,
+                ),
+            ],
+            &mint,
+            DAY,
+        );
+        assert!(grants.is_empty());
+    }
+}
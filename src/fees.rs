@@ -0,0 +1,472 @@
+//! Accounting of the SOL the payer spends on transaction fees and rent
+//!
+//! The payer's history is read from the cluster, so the report also covers transactions sent
+//! by other processes or tools sharing the payer. Processed transactions are cached in the
+//! file named by `SSS_FEE_CACHE_PATH`, so repeated reports only fetch new transactions.
+
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::inspect::DecodedInstruction;
+use crate::journal::ends_mid_line;
+use crate::payer::get_payer_pubkey;
+use crate::receipt::{Receipt, fetch_transaction_receipt_with_client};
+use crate::serde_utils::pubkey_string;
+use crate::{RPC_CLIENT, load_dotenv};
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use solana_rpc_client::rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::thread;
+
+/// Number of signatures requested per page, the maximum the RPC allows
+const SIGNATURE_PAGE_SIZE: usize = 1000;
+
+/// Number of transactions fetched concurrently
+const TRANSACTION_FETCH_BATCH: usize = 16;
+
+lazy_static! {
+    /// File processed transactions are cached in, from `SSS_FEE_CACHE_PATH`
+    static ref FEE_CACHE_PATH: Option<PathBuf> = {
+        load_dotenv();
+        env::var("SSS_FEE_CACHE_PATH").ok().map(PathBuf::from)
+    };
+}
+
+/// What the lamports of a transaction were spent on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeeCategory {
+    /// Token creation, including the rent of the mint and metadata accounts
+    Create,
+    /// Minting
+    Mint,
+    /// Rent of associated token accounts created for recipients
+    AtaRent,
+    /// Token and SOL transfers
+    Transfer,
+    /// Anything else, e.g. burns, freezes or authority changes
+    Other,
+}
+
+/// Lamports spent in one category or on one day
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct FeeSpend {
+    /// Transaction fees, in lamports
+    pub fees: u64,
+    /// Lamports funding new accounts, in lamports
+    pub rent: u64,
+    /// Number of transactions contributing
+    pub transactions: u64,
+}
+
+impl FeeSpend {
+    /// Returns the fees and rent together
+    pub fn total(&self) -> u64 {
+        self.fees.saturating_add(self.rent)
+    }
+
+    /// Adds fees and rent of one transaction
+    fn add(&mut self, fees: u64, rent: u64) {
+        self.fees = self.fees.saturating_add(fees);
+        self.rent = self.rent.saturating_add(rent);
+        self.transactions += 1;
+    }
+}
+
+/// Spending on one UTC day
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DailyFeeSpend {
+    /// The day, as `YYYY-MM-DD` in UTC
+    pub date: String,
+    /// What was spent that day
+    #[serde(flatten)]
+    pub spend: FeeSpend,
+}
+
+/// The outcome of [`fee_report`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FeeReport {
+    /// The account whose spending is reported
+    #[serde(with = "pubkey_string")]
+    pub payer: Pubkey,
+    /// Start of the window, as a Unix timestamp
+    pub since: i64,
+    /// End of the window, as a Unix timestamp
+    pub until: i64,
+    /// Everything spent in the window
+    pub total: FeeSpend,
+    /// Spending by category; a transaction funding accounts of several categories counts
+    /// in each of them
+    pub by_category: BTreeMap<FeeCategory, FeeSpend>,
+    /// Spending per UTC day, oldest first, leaving out days without transactions
+    pub daily: Vec<DailyFeeSpend>,
+    /// Slot of the oldest transaction of the payer the node returned
+    pub earliest_slot_covered: Option<u64>,
+    /// Whether the node's history reaches back to `since`; when it does not, transactions
+    /// older than `earliest_slot_covered` are missing from the report
+    pub complete: bool,
+    /// Number of transactions fetched from the node rather than read from the cache
+    pub fetched: usize,
+}
+
+impl FeeReport {
+    /// Serializes the report to JSON
+    pub fn to_json(&self) -> SssResult<String> {
+        serde_json::to_string_pretty(self).into_sss_error("Failed to serialize fee report")
+    }
+}
+
+/// What a processed transaction cost its fee payer, as cached between reports
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct FeeRecord {
+    signature: String,
+    slot: u64,
+    block_time: Option<i64>,
+    #[serde(with = "pubkey_string")]
+    fee_payer: Pubkey,
+    fee: u64,
+    category: FeeCategory,
+    /// Lamports funding accounts the transaction created, by category
+    rent: BTreeMap<FeeCategory, u64>,
+}
+
+/// Reports how much SOL the payer spent on fees and rent in a time window
+///
+/// The payer's signatures are paged through from the newest, and the transactions not in the
+/// cache are fetched and decoded. The fee of a transaction is attributed to its main
+/// operation: creation if it creates a mint or metadata, otherwise minting, otherwise a
+/// transfer. Lamports moving into accounts that did not exist, whether through account
+/// creation or a system transfer, count as rent; the rent of associated token accounts is
+/// reported separately. Only transactions whose fee payer is the payer are counted, and
+/// failed transactions count for their fee.
+///
+/// # Arguments
+///
+/// * `since` - Start of the window, inclusive
+/// * `until` - End of the window, exclusive
+///
+/// # Returns
+///
+/// The totals by category and by day, and how far back the node's history reached
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if the window is empty or the cache cannot be read or written,
+/// and an `RpcError` if the history cannot be read
+pub fn fee_report(since: DateTime<Utc>, until: DateTime<Utc>) -> SssResult<FeeReport> {
+    fee_report_with_client(&RPC_CLIENT, &get_payer_pubkey()?, since, until)
+}
+
+/// Reports fee spending like [`fee_report`], for any account and querying the given RPC
+/// client
+///
+/// # Arguments
+///
+/// * `client` - The RPC client to query, e.g. an archival node for old transactions
+/// * `payer` - The account whose spending is reported
+/// * `since` - Start of the window, inclusive
+/// * `until` - End of the window, exclusive
+///
+/// # Returns
+///
+/// The totals by category and by day, and how far back the node's history reached
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if the window is empty or the cache cannot be read or written,
+/// and an `RpcError` if the history cannot be read
+pub fn fee_report_with_client(
+    client: &RpcClient,
+    payer: &Pubkey,
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+) -> SssResult<FeeReport> {
+    let (since, until) = (since.timestamp(), until.timestamp());
+    if since >= until {
+        return Err(SssError::ConfigError(format!(
+            "Fee report window ends at {} before it starts at {}",
+            until, since
+        )));
+    }
+
+    let (signatures, reached_since, earliest_slot) =
+        window_signatures(client, payer, since, until)?;
+    let mut cache = read_cache()?;
+    let missing: Vec<&String> = signatures
+        .iter()
+        .filter(|signature| !cache.contains_key(*signature))
+        .collect();
+    for batch in missing.chunks(TRANSACTION_FETCH_BATCH) {
+        let records = fetch_records(client, batch)?;
+        append_cache(&records)?;
+        cache.extend(
+            records
+                .into_iter()
+                .map(|record| (record.signature.clone(), record)),
+        );
+    }
+
+    // Without `since` in the page history, the node may have pruned older transactions
+    let complete = reached_since || history_reaches(client, since);
+    let mut report = FeeReport {
+        payer: *payer,
+        since,
+        until,
+        total: FeeSpend::default(),
+        by_category: BTreeMap::new(),
+        daily: Vec::new(),
+        earliest_slot_covered: earliest_slot,
+        complete,
+        fetched: missing.len(),
+    };
+    let mut daily: BTreeMap<String, FeeSpend> = BTreeMap::new();
+    for record in signatures
+        .iter()
+        .filter_map(|signature| cache.get(signature))
+    {
+        let Some(time) = record
+            .block_time
+            .filter(|time| (since..until).contains(time))
+        else {
+            continue;
+        };
+        if record.fee_payer != *payer {
+            continue;
+        }
+        let rent: u64 = record.rent.values().sum();
+        report.total.add(record.fee, rent);
+        report.by_category.entry(record.category).or_default().add(
+            record.fee,
+            record.rent.get(&record.category).copied().unwrap_or(0),
+        );
+        for (category, lamports) in &record.rent {
+            if *category != record.category {
+                report
+                    .by_category
+                    .entry(*category)
+                    .or_default()
+                    .add(0, *lamports);
+            }
+        }
+        if let Some(day) = DateTime::from_timestamp(time, 0) {
+            daily
+                .entry(day.format("%Y-%m-%d").to_string())
+                .or_default()
+                .add(record.fee, rent);
+        }
+    }
+    report.daily = daily
+        .into_iter()
+        .map(|(date, spend)| DailyFeeSpend { date, spend })
+        .collect();
+    Ok(report)
+}
+
+/// Pages through the payer's signatures from the newest down to `since`
+///
+/// # Returns
+///
+/// The signatures in the window, whether the history reached `since`, and the slot of the
+/// oldest signature returned
+fn window_signatures(
+    client: &RpcClient,
+    payer: &Pubkey,
+    since: i64,
+    until: i64,
+) -> SssResult<(Vec<String>, bool, Option<u64>)> {
+    let mut signatures = Vec::new();
+    let mut before = None;
+    let mut earliest_slot = None;
+    loop {
+        let page = client.get_signatures_for_address_with_config(
+            payer,
+            GetConfirmedSignaturesForAddress2Config {
+                before,
+                until: None,
+                limit: Some(SIGNATURE_PAGE_SIZE),
+                commitment: Some(client.commitment()),
+            },
+        );
+        let page = match page {
+            Ok(page) => page,
+            // Nodes without long-term storage refuse to page beyond their history
+            Err(_) if before.is_some() => return Ok((signatures, false, earliest_slot)),
+            Err(e) => return Err(e).into_sss_error("Failed to get payer signatures from rpc"),
+        };
+        let Some(last) = page.last() else {
+            return Ok((signatures, false, earliest_slot));
+        };
+        earliest_slot = Some(last.slot);
+        before = Some(
+            Signature::from_str(&last.signature)
+                .into_sss_error("Failed to parse signature from rpc")?,
+        );
+        let full = page.len() == SIGNATURE_PAGE_SIZE;
+        for status in page {
+            match status.block_time {
+                Some(time) if time < since => return Ok((signatures, true, earliest_slot)),
+                Some(time) if time >= until => {}
+                _ => signatures.push(status.signature),
+            }
+        }
+        if !full {
+            return Ok((signatures, false, earliest_slot));
+        }
+    }
+}
+
+/// Returns whether the oldest block the node keeps was produced before `since`
+fn history_reaches(client: &RpcClient, since: i64) -> bool {
+    let Ok(slot) = client.minimum_ledger_slot() else {
+        return false;
+    };
+    client.get_block_time(slot).is_ok_and(|time| time <= since)
+}
+
+/// Fetches a batch of transactions concurrently and works out what each cost
+fn fetch_records(client: &RpcClient, signatures: &[&String]) -> SssResult<Vec<FeeRecord>> {
+    thread::scope(|scope| {
+        let handles: Vec<_> = signatures
+            .iter()
+            .map(|signature| {
+                scope.spawn(move || fetch_transaction_receipt_with_client(client, signature))
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| {
+                let receipt = handle.join().map_err(|_| {
+                    SssError::RpcError("Transaction fetch thread panicked".to_string())
+                })??;
+                Ok(fee_record(&receipt))
+            })
+            .collect()
+    })
+}
+
+/// Attributes the fee and the rent of a transaction to categories
+fn fee_record(receipt: &Receipt) -> FeeRecord {
+    let decoded = || receipt.instructions.iter().map(|ix| &ix.decoded);
+    let category = if decoded().any(|ix| {
+        matches!(
+            ix,
+            DecodedInstruction::MetadataCreate { .. }
+                | DecodedInstruction::SystemCreateAccount { .. }
+        )
+    }) {
+        FeeCategory::Create
+    } else if decoded().any(|ix| {
+        matches!(
+            ix,
+            DecodedInstruction::TokenMintTo { .. } | DecodedInstruction::MetadataMint { .. }
+        )
+    }) {
+        FeeCategory::Mint
+    } else if decoded().any(|ix| {
+        matches!(
+            ix,
+            DecodedInstruction::TokenTransfer { .. }
+                | DecodedInstruction::MetadataTransfer { .. }
+                | DecodedInstruction::SystemTransfer { .. }
+        )
+    }) {
+        FeeCategory::Transfer
+    } else {
+        FeeCategory::Other
+    };
+
+    // The associated token account is the second account of its creation instruction
+    let token_accounts: Vec<Pubkey> = receipt
+        .instructions
+        .iter()
+        .filter(|ix| matches!(ix.decoded, DecodedInstruction::AssociatedTokenCreate { .. }))
+        .filter_map(|ix| ix.accounts.get(1).map(|account| account.pubkey))
+        .collect();
+    let mut rent = BTreeMap::new();
+    if receipt.success {
+        for balance in receipt
+            .lamport_balances
+            .iter()
+            .filter(|b| b.pre == 0 && b.account != receipt.fee_payer)
+        {
+            let category = if token_accounts.contains(&balance.account) {
+                FeeCategory::AtaRent
+            } else {
+                category
+            };
+            *rent.entry(category).or_insert(0) += balance.post;
+        }
+    }
+
+    FeeRecord {
+        signature: receipt.signature.clone(),
+        slot: receipt.slot,
+        block_time: receipt.block_time,
+        fee_payer: receipt.fee_payer,
+        fee: receipt.fee,
+        category,
+        rent,
+    }
+}
+
+/// Reads the cached records, keyed by signature
+fn read_cache() -> SssResult<HashMap<String, FeeRecord>> {
+    let Some(path) = FEE_CACHE_PATH.as_ref() else {
+        return Ok(HashMap::new());
+    };
+    let mut contents = String::new();
+    match File::open(path) {
+        Ok(mut file) => {
+            file.lock_shared().map_err(|e| cache_error("lock", e))?;
+            file.read_to_string(&mut contents)
+                .map_err(|e| cache_error("read", e))?;
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(cache_error("open", e)),
+    }
+    // A line cut short by a crash is skipped, and its transaction fetched again
+    Ok(contents
+        .lines()
+        .filter_map(|line| serde_json::from_str::<FeeRecord>(line).ok())
+        .map(|record| (record.signature.clone(), record))
+        .collect())
+}
+
+/// Appends records to the cache, if one is configured
+fn append_cache(records: &[FeeRecord]) -> SssResult<()> {
+    let Some(path) = FEE_CACHE_PATH.as_ref() else {
+        return Ok(());
+    };
+    let mut lines = String::new();
+    for record in records {
+        lines.push_str(
+            &serde_json::to_string(record).into_sss_error("Failed to serialize fee record")?,
+        );
+        lines.push('\n');
+    }
+    let mut file = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(path)
+        .map_err(|e| cache_error("open", e))?;
+    file.lock().map_err(|e| cache_error("lock", e))?;
+
+    // Terminate a line left incomplete by a crash so it does not swallow these
+    if ends_mid_line(&mut file).map_err(|e| cache_error("read", e))? {
+        lines.insert(0, '\n');
+    }
+    file.write_all(lines.as_bytes())
+        .map_err(|e| cache_error("write", e))
+}
+
+/// Wraps an I/O error on the fee cache
+fn cache_error(action: &str, e: std::io::Error) -> SssError {
+    SssError::ConfigError(format!("Failed to {} fee cache: {}", action, e))
+}
@@ -1,11 +1,87 @@
 //! FFI functions for C interoperability
+//!
+//! Every function returns 0 (or a documented non-negative value) on success and a negative
+//! error code on failure. After a failure, `sss_get_last_error` describes which parameter
+//! was rejected and why.
 
+use crate::activity::wallet_activity_page;
+use crate::airdrop::execute_airdrop;
+use crate::amount::{AmountSpec, RawAmount, format_token_amount, parse_token_amount};
+use crate::builder::TokenBuilder;
+use crate::cache::{cache_stats, trim_caches};
+use crate::capabilities::capabilities;
+use crate::claim::build_claim_transaction;
+use crate::claim_link::{create_claim_link, reclaim_claim_link, redeem_claim_link};
+use crate::consistency::audit_token_consistency;
+#[cfg(feature = "http-metadata")]
+use crate::content_cache::{fetch_asset_image, fetch_asset_image_by_id};
+use crate::creators::verify_creator;
+#[cfg(feature = "das")]
+use crate::das::{AssetStreamOptions, fetch_all_digital_assets};
+#[cfg(feature = "dev-tools")]
+use crate::devtools::{AssetFilter, burn_all_assets, request_airdrop};
+use crate::dispatch::{CallbackQueue, OverflowPolicy, QueueOptions, set_callback_queue_options};
+use crate::display::{parse_solana_uri, pubkey_color_hash, qr_payload_for_address, short_pubkey};
+use crate::error::{SssError, SssResult};
+use crate::events::{clear_event_sink, set_event_sink};
+#[cfg(feature = "dev-tools")]
+use crate::faucet::{FaucetPolicy, faucet_grant};
+use crate::ffi_call::{CallError, call};
+use crate::ffi_pool::{pending_count, submit};
+use crate::ffi_schema::{FFI_SCHEMA_VERSION, envelope, versioned_json};
+#[cfg(feature = "das")]
+use crate::ffi_stream::{JsonArrayWriter, STREAM_CHUNK_BYTES, c_chunk_sink};
 use crate::ffi_utils::{
-    c_str_to_optional_pubkey, c_str_to_pubkey, c_str_to_string, copy_string_to_buffer,
+    InputError, SssFfiError, c_format_options, c_str_to_base64_bytes, c_str_to_creators,
+    c_str_to_migration_plan, c_str_to_mint_amounts, c_str_to_optional_pubkey,
+    c_str_to_optional_pubkeys, c_str_to_optional_recipient, c_str_to_optional_string,
+    c_str_to_pubkey, c_str_to_recipient, c_str_to_recipient_amounts, c_str_to_signature,
+    c_str_to_signing_domain, c_str_to_string, c_str_to_supply_schedule,
+    c_str_to_template_overrides, c_str_to_ui_amount, copy_bytes_to_buffer, copy_string_to_buffer,
+    first_null_param, invalid_param, last_error, null_param, operation_failed,
 };
-use crate::token::{create_new_token, mint_token};
+#[cfg(feature = "keystore")]
+use crate::keystore::unlock_payer_keystore;
+use crate::metrics::metrics_snapshot;
+use crate::migration::migrate_legacy_mints;
+use crate::payer::{get_payer_pubkey, payer_info};
+use crate::payment::{await_payment, create_payment_request, generate_payment_reference};
+use crate::policies::reload_policy_files;
+use crate::profiles::{select_profile, with_profile};
+use crate::read_only::set_read_only;
+use crate::receipt::fetch_transaction_receipt;
+use crate::receipt_print::{ReceiptFormat, ReceiptTemplate, render_receipt_payload};
+use crate::recovery::recover_in_flight;
+use crate::review::{ReviewOutcome, ReviewPolicy, review_external_transaction};
+use crate::shutdown;
+use crate::signed_commands::{execute_signed_command, verify_signed_command};
+use crate::signing::{sign_message, verify_message};
+use crate::sns::{primary_domain_for, resolve_recipient, set_ffi_domain_resolution};
+use crate::structured_signing::{sign_structured_json, verify_structured};
+use crate::subscriptions::{subscribe_token_balance, unsubscribe_token_balance};
+use crate::supply_monitor::{monitor_supply, monitor_supply_with_callback, stop_supply_monitor};
+use crate::templates::create_token_from_template;
+use crate::token::{
+    attach_metadata_to_existing_mint, check_recipient, create_new_token, mint_basket, mint_token,
+    mint_token_idempotent, mint_token_to_account,
+};
+use crate::transfer::{TransferOptions, transfer_asset_with_options};
+use crate::trusted_mints::{add_trusted_mint, set_strict_mode};
+use crate::warmup::{start_keep_alive, stop_keep_alive, warm_up};
+use crate::watch_only::init_watch_only;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::json;
+use solana_sdk::pubkey::Pubkey;
 use std::ffi::CString;
-use std::os::raw::{c_char, c_int, c_uchar};
+#[cfg(feature = "das")]
+use std::ops::ControlFlow;
+use std::os::raw::{c_char, c_int, c_uchar, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::time::Duration;
+#[cfg(feature = "keystore")]
+use zeroize::Zeroizing;
 
 /// Creates a new token and returns the transaction signature and mint address
 ///
@@ -35,42 +111,115 @@ pub unsafe extern "C" fn create_token(
     mint_address_len: c_int,
 ) -> c_int {
     // Check for null pointers
-    if uri_ptr.is_null()
-        || name_ptr.is_null()
-        || signature_out.is_null()
-        || mint_address_out.is_null()
-    {
-        return -1;
+    if let Some((index, name)) = first_null_param(&[
+        (0, "uri_ptr", uri_ptr.is_null()),
+        (1, "name_ptr", name_ptr.is_null()),
+        (3, "signature_out", signature_out.is_null()),
+        (4, "mint_address_out", mint_address_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
     }
 
     // Convert C strings to Rust strings
     let uri = match unsafe { c_str_to_string(uri_ptr) } {
         Ok(s) => s,
-        Err(_) => return -2,
+        Err(e) => return invalid_param(-2, 0, "uri_ptr", e),
     };
 
     let name = match unsafe { c_str_to_string(name_ptr) } {
         Ok(s) => s,
-        Err(_) => return -3,
+        Err(e) => return invalid_param(-3, 1, "name_ptr", e),
     };
 
     // Call the Rust function
-    match create_new_token(uri, name, decimals) {
+    match create_new_token(&uri, &name, decimals) {
         Ok((signature, mint_pubkey)) => {
             // Copy the signature to the output buffer
-            if unsafe { copy_string_to_buffer(&signature, signature_out, signature_len).is_err() } {
-                return -6;
+            if let Err(e) =
+                unsafe { copy_string_to_buffer(&signature, signature_out, signature_len) }
+            {
+                return invalid_param(-6, 5, "signature_len", e);
             }
 
             // Copy the mint address to the output buffer
-            if unsafe { copy_string_to_buffer(&mint_pubkey.to_string(), mint_address_out, mint_address_len) }.is_err()
+            if let Err(e) = unsafe {
+                copy_string_to_buffer(&mint_pubkey.to_string(), mint_address_out, mint_address_len)
+            } {
+                return invalid_param(-7, 6, "mint_address_len", e);
+            }
+
+            0 // Success
+        }
+        Err(e) => operation_failed(-8, &e), // Error creating token
+    }
+}
+
+/// Creates a token from a registered template and returns the transaction signature and
+/// mint address
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - template_name and overrides_json are valid, null-terminated C strings
+/// - signature_out and mint_address_out are valid pointers to buffers of sufficient size
+///
+/// @param template_name The name of the template, as registered or in SSS_TEMPLATES_PATH
+/// @param overrides_json The JSON of the overrides, e.g. {"name": "...", "attributes": {"batch": "B-12"}}
+/// @param signature_out A pointer to a buffer where the transaction signature will be written
+/// @param signature_len The length of the signature_out buffer
+/// @param mint_address_out A pointer to a buffer where the mint address will be written
+/// @param mint_address_len The length of the mint_address_out buffer
+/// @return 0 on success, non-zero error code on failure
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_create_token_from_template(
+    template_name: *const c_char,
+    overrides_json: *const c_char,
+    signature_out: *mut c_char,
+    signature_len: c_int,
+    mint_address_out: *mut c_char,
+    mint_address_len: c_int,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "template_name", template_name.is_null()),
+        (1, "overrides_json", overrides_json.is_null()),
+        (2, "signature_out", signature_out.is_null()),
+        (4, "mint_address_out", mint_address_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let template_name = match unsafe { c_str_to_string(template_name) } {
+        Ok(s) => s,
+        Err(e) => return invalid_param(-2, 0, "template_name", e),
+    };
+
+    let overrides = match unsafe { c_str_to_template_overrides(overrides_json) } {
+        Ok(o) => o,
+        Err(e) => return invalid_param(-3, 1, "overrides_json", e),
+    };
+
+    // Call the Rust function
+    match create_token_from_template(&template_name, overrides) {
+        Ok((signature, mint_pubkey)) => {
+            // Copy the signature to the output buffer
+            if let Err(e) =
+                unsafe { copy_string_to_buffer(&signature, signature_out, signature_len) }
             {
-                return -7;
+                return invalid_param(-4, 3, "signature_len", e);
+            }
+
+            // Copy the mint address to the output buffer
+            if let Err(e) = unsafe {
+                copy_string_to_buffer(&mint_pubkey.to_string(), mint_address_out, mint_address_len)
+            } {
+                return invalid_param(-5, 5, "mint_address_len", e);
             }
 
             0 // Success
         }
-        Err(_) => -8, // Error creating token
+        Err(e) => operation_failed(-6, &e), // Error creating the token
     }
 }
 
@@ -94,7 +243,8 @@ pub unsafe extern "C" fn free_string(ptr: *mut c_char) {
 /// This function is unsafe because it works with raw pointers for C interoperability.
 /// The caller must ensure that:
 /// - mint_str is a valid, null-terminated C string containing a valid Solana public key
-/// - token_owner_str is either null or a valid, null-terminated C string containing a valid Solana public key
+/// - token_owner_str is either null or a valid, null-terminated C string containing a valid Solana public key,
+///   or a `.sol` domain if enabled with `sss_set_ffi_domain_resolution`
 /// - signature_out is a valid pointer to a buffer of sufficient size (signature_len)
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn mint_token_ffi(
@@ -105,32 +255,3592 @@ pub unsafe extern "C" fn mint_token_ffi(
     signature_len: c_int,
 ) -> c_int {
     // Check for null pointers
-    if mint_str.is_null() || signature_out.is_null() {
-        return -1;
+    if let Some((index, name)) = first_null_param(&[
+        (0, "mint_str", mint_str.is_null()),
+        (3, "signature_out", signature_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
     }
 
     // Convert mint address string to Pubkey
     let mint = match unsafe { c_str_to_pubkey(mint_str) } {
         Ok(p) => p,
-        Err(_) => return -2,
+        Err(e) => return invalid_param(-2, 0, "mint_str", e),
     };
 
     // Convert token owner string to Pubkey if provided
-    let token_owner = match unsafe { c_str_to_optional_pubkey(token_owner_str) } {
+    let token_owner = match unsafe { c_str_to_optional_recipient(token_owner_str) } {
         Ok(opt) => opt,
-        Err(_) => return -3,
+        Err(e) => return invalid_param(-3, 1, "token_owner_str", e),
     };
 
     // Call the Rust function
-    match mint_token(mint, token_owner, amount) {
+    match mint_token(mint, token_owner, RawAmount(amount)) {
+        Ok(signature) => {
+            // Copy the signature to the output buffer
+            if let Err(e) =
+                unsafe { copy_string_to_buffer(&signature, signature_out, signature_len) }
+            {
+                return invalid_param(-4, 4, "signature_len", e);
+            }
+
+            0 // Success
+        }
+        Err(e) => operation_failed(-5, &e), // Error minting token
+    }
+}
+
+/// FFI function to mint tokens into an existing token account
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - mint_str and token_account_str are valid, null-terminated C strings containing valid Solana public keys
+/// - signature_out is a valid pointer to a buffer of sufficient size (signature_len)
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_mint_token_to_account(
+    mint_str: *const c_char,
+    token_account_str: *const c_char,
+    amount: u64,
+    signature_out: *mut c_char,
+    signature_len: c_int,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "mint_str", mint_str.is_null()),
+        (1, "token_account_str", token_account_str.is_null()),
+        (3, "signature_out", signature_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let mint = match unsafe { c_str_to_pubkey(mint_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-2, 0, "mint_str", e),
+    };
+
+    let token_account = match unsafe { c_str_to_pubkey(token_account_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-3, 1, "token_account_str", e),
+    };
+
+    // Call the Rust function
+    match mint_token_to_account(mint, token_account, RawAmount(amount)) {
+        Ok(signature) => {
+            // Copy the signature to the output buffer
+            if let Err(e) =
+                unsafe { copy_string_to_buffer(&signature, signature_out, signature_len) }
+            {
+                return invalid_param(-4, 4, "signature_len", e);
+            }
+
+            0 // Success
+        }
+        Err(e) => operation_failed(-5, &e), // Error minting token
+    }
+}
+
+/// FFI function to mint several tokens to the same wallet in a single transaction
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - items_json is a valid, null-terminated C string containing a JSON array of
+///   `{"mint": "<address>", "amount": <base units>}` objects
+/// - owner_str is a valid, null-terminated C string containing a valid Solana public key
+/// - signature_out is a valid pointer to a buffer of sufficient size (signature_len)
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_mint_basket(
+    items_json: *const c_char,
+    owner_str: *const c_char,
+    signature_out: *mut c_char,
+    signature_len: c_int,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "items_json", items_json.is_null()),
+        (1, "owner_str", owner_str.is_null()),
+        (2, "signature_out", signature_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let items = match unsafe { c_str_to_mint_amounts(items_json) } {
+        Ok(items) => items,
+        Err(e) => return invalid_param(-2, 0, "items_json", e),
+    };
+
+    let owner = match unsafe { c_str_to_pubkey(owner_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-3, 1, "owner_str", e),
+    };
+
+    match mint_basket(&items, owner) {
+        Ok(signature) => {
+            if let Err(e) =
+                unsafe { copy_string_to_buffer(&signature, signature_out, signature_len) }
+            {
+                return invalid_param(-4, 3, "signature_len", e);
+            }
+
+            0 // Success
+        }
+        Err(e) => operation_failed(-5, &e), // Error minting basket
+    }
+}
+
+/// FFI function to transfer an asset held by the payer to another wallet
+///
+/// An `amount` of 0 transfers the whole balance; NFTs always transfer exactly 1.
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - mint_str and to_str are valid, null-terminated C strings containing valid Solana public keys;
+///   to_str may be a `.sol` domain if enabled with `sss_set_ffi_domain_resolution`
+/// - signature_out is a valid pointer to a buffer of sufficient size (signature_len)
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_transfer_asset(
+    mint_str: *const c_char,
+    to_str: *const c_char,
+    amount: u64,
+    signature_out: *mut c_char,
+    signature_len: c_int,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "mint_str", mint_str.is_null()),
+        (1, "to_str", to_str.is_null()),
+        (3, "signature_out", signature_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let mint = match unsafe { c_str_to_pubkey(mint_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-2, 0, "mint_str", e),
+    };
+
+    let to = match unsafe { c_str_to_recipient(to_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-3, 1, "to_str", e),
+    };
+
+    let options = TransferOptions {
+        amount: (amount > 0).then_some(AmountSpec::raw(amount)),
+        ..TransferOptions::default()
+    };
+    match transfer_asset_with_options(mint, to, &options) {
+        Ok(signature) => {
+            // Copy the signature to the output buffer
+            if let Err(e) =
+                unsafe { copy_string_to_buffer(&signature, signature_out, signature_len) }
+            {
+                return invalid_param(-4, 4, "signature_len", e);
+            }
+
+            0 // Success
+        }
+        Err(e) => operation_failed(-5, &e), // Error transferring the asset
+    }
+}
+
+/// FFI function to mint tokens to many recipients in batches, resuming from a checkpoint file
+///
+/// The report is written as JSON with `succeeded` (`{"key", "signature"}` objects),
+/// `failed` (`{"key", "error": {"kind", "message"}}` objects), `skipped` (addresses),
+/// `supply_cap_violation` and `platform_fee_lamports`. Failed recipients do not make the
+/// call fail; they are listed in the report.
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - mint_str is a valid, null-terminated C string containing a valid Solana public key
+/// - allocations_json is a valid, null-terminated C string containing a JSON array of
+///   `{"recipient": "<address>", "amount": <base units>}` objects
+/// - checkpoint_path is a valid, null-terminated C string
+/// - report_out is a valid pointer to a buffer of sufficient size (report_len)
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_execute_airdrop(
+    mint_str: *const c_char,
+    allocations_json: *const c_char,
+    checkpoint_path: *const c_char,
+    report_out: *mut c_char,
+    report_len: c_int,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "mint_str", mint_str.is_null()),
+        (1, "allocations_json", allocations_json.is_null()),
+        (2, "checkpoint_path", checkpoint_path.is_null()),
+        (3, "report_out", report_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let mint = match unsafe { c_str_to_pubkey(mint_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-2, 0, "mint_str", e),
+    };
+
+    let allocations = match unsafe { c_str_to_recipient_amounts(allocations_json) } {
+        Ok(allocations) => allocations,
+        Err(e) => return invalid_param(-3, 1, "allocations_json", e),
+    };
+
+    let checkpoint_path = match unsafe { c_str_to_string(checkpoint_path) } {
+        Ok(s) => s,
+        Err(e) => return invalid_param(-4, 2, "checkpoint_path", e),
+    };
+
+    let report = match execute_airdrop(mint, &allocations, Path::new(&checkpoint_path))
+        .and_then(|report| versioned_json(&report))
+    {
+        Ok(report) => report,
+        Err(e) => return operation_failed(-6, &e), // Error running airdrop
+    };
+
+    // Copy the report to the output buffer
+    if let Err(e) = unsafe { copy_string_to_buffer(&report, report_out, report_len) } {
+        return invalid_param(-5, 4, "report_len", e);
+    }
+
+    0 // Success
+}
+
+/// FFI function to fetch an asset image into a local cache
+///
+/// `uri_ptr` holds either an asset id, whose metadata URI is looked up, or a URI.
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - uri_ptr and cache_dir_ptr are valid, null-terminated C strings
+/// - path_out is a valid pointer to a buffer of sufficient size (path_len)
+#[cfg(feature = "http-metadata")]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_fetch_asset_image(
+    uri_ptr: *const c_char,
+    cache_dir_ptr: *const c_char,
+    path_out: *mut c_char,
+    path_len: c_int,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "uri_ptr", uri_ptr.is_null()),
+        (1, "cache_dir_ptr", cache_dir_ptr.is_null()),
+        (2, "path_out", path_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    // Convert C strings to Rust strings
+    let uri = match unsafe { c_str_to_string(uri_ptr) } {
+        Ok(s) => s,
+        Err(e) => return invalid_param(-2, 0, "uri_ptr", e),
+    };
+
+    let cache_dir = match unsafe { c_str_to_string(cache_dir_ptr) } {
+        Ok(s) => s,
+        Err(e) => return invalid_param(-3, 1, "cache_dir_ptr", e),
+    };
+
+    // A bare address is an asset id; anything else is fetched as a URI
+    let fetched = match uri.parse::<Pubkey>() {
+        Ok(asset_id) => fetch_asset_image_by_id(&asset_id, Path::new(&cache_dir)),
+        Err(_) => fetch_asset_image(&uri, Path::new(&cache_dir)),
+    };
+    match fetched {
+        Ok(path) => {
+            // Copy the path to the output buffer
+            if let Err(e) =
+                unsafe { copy_string_to_buffer(&path.to_string_lossy(), path_out, path_len) }
+            {
+                return invalid_param(-4, 3, "path_len", e);
+            }
+
+            0 // Success
+        }
+        Err(e) => operation_failed(-5, &e), // Error fetching content
+    }
+}
+
+/// FFI function to sign a message with the payer keypair
+///
+/// Bytes that read as a transaction message are refused, see [`sign_message`].
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - message_b64 is a valid, null-terminated C string containing base64-encoded bytes
+/// - signature_out is a valid pointer to a buffer of sufficient size (signature_len)
+///
+/// The base58-encoded signature is written to signature_out.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_sign_message(
+    message_b64: *const c_char,
+    signature_out: *mut c_char,
+    signature_len: c_int,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "message_b64", message_b64.is_null()),
+        (1, "signature_out", signature_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    // Decode the message bytes
+    let message = match unsafe { c_str_to_base64_bytes(message_b64) } {
+        Ok(m) => m,
+        Err(e) => return invalid_param(-2, 0, "message_b64", e),
+    };
+
+    // Call the Rust function
+    match sign_message(&message) {
         Ok(signature) => {
             // Copy the signature to the output buffer
-            if unsafe { copy_string_to_buffer(&signature, signature_out, signature_len).is_err() } {
-                return -4;
+            if let Err(e) = unsafe {
+                copy_string_to_buffer(&signature.to_string(), signature_out, signature_len)
+            } {
+                return invalid_param(-3, 2, "signature_len", e);
             }
 
             0 // Success
         }
-        Err(_) => -5, // Error minting token
+        Err(e) => operation_failed(-4, &e), // Error signing message
+    }
+}
+
+/// FFI function to verify a message signature
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - pubkey_str is a valid, null-terminated C string containing a valid Solana public key
+/// - message_b64 is a valid, null-terminated C string containing base64-encoded bytes
+/// - signature_b58 is a valid, null-terminated C string containing a base58 signature
+///
+/// @return 1 if the signature is valid, 0 if it is not, negative error code on failure
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_verify_message(
+    pubkey_str: *const c_char,
+    message_b64: *const c_char,
+    signature_b58: *const c_char,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "pubkey_str", pubkey_str.is_null()),
+        (1, "message_b64", message_b64.is_null()),
+        (2, "signature_b58", signature_b58.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let pubkey = match unsafe { c_str_to_pubkey(pubkey_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-2, 0, "pubkey_str", e),
+    };
+
+    let message = match unsafe { c_str_to_base64_bytes(message_b64) } {
+        Ok(m) => m,
+        Err(e) => return invalid_param(-3, 1, "message_b64", e),
+    };
+
+    let signature = match unsafe { c_str_to_signature(signature_b58) } {
+        Ok(s) => s,
+        Err(e) => return invalid_param(-4, 2, "signature_b58", e),
+    };
+
+    verify_message(&pubkey, &message, &signature) as c_int
+}
+
+/// FFI function to write a JSON snapshot of the library metrics
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that json_out is a valid pointer to a buffer of sufficient size (json_len).
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_metrics_json(json_out: *mut c_char, json_len: c_int) -> c_int {
+    // Check for null pointers
+    if json_out.is_null() {
+        return null_param(-1, 0, "json_out");
+    }
+
+    let json = match versioned_json(&metrics_snapshot()) {
+        Ok(j) => j,
+        Err(e) => return operation_failed(-2, &e), // Error serializing the metrics
+    };
+
+    // Copy the JSON to the output buffer
+    if let Err(e) = unsafe { copy_string_to_buffer(&json, json_out, json_len) } {
+        return invalid_param(-3, 1, "json_len", e);
+    }
+
+    0 // Success
+}
+
+/// FFI function to write the payer address
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that pubkey_out is a valid pointer to a buffer of sufficient size (pubkey_len).
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_get_payer_pubkey(pubkey_out: *mut c_char, pubkey_len: c_int) -> c_int {
+    // Check for null pointers
+    if pubkey_out.is_null() {
+        return null_param(-1, 0, "pubkey_out");
+    }
+
+    match get_payer_pubkey() {
+        Ok(pubkey) => {
+            // Copy the address to the output buffer
+            if let Err(e) =
+                unsafe { copy_string_to_buffer(&pubkey.to_string(), pubkey_out, pubkey_len) }
+            {
+                return invalid_param(-2, 1, "pubkey_len", e);
+            }
+
+            0 // Success
+        }
+        Err(e) => operation_failed(-3, &e), // Payer failed to load
+    }
+}
+
+/// FFI function to write the payer address, balance and load state as JSON
+///
+/// A payer that failed to load is not an error: the JSON has `"loaded": false` and the
+/// reason in `load_error`. In watch-only mode `watch_only` is true and the watched wallet
+/// is reported.
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that json_out is a valid pointer to a buffer of sufficient size (json_len).
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_get_payer_info(json_out: *mut c_char, json_len: c_int) -> c_int {
+    // Check for null pointers
+    if json_out.is_null() {
+        return null_param(-1, 0, "json_out");
+    }
+
+    let json = match payer_info().and_then(|info| versioned_json(&info)) {
+        Ok(j) => j,
+        Err(e) => return operation_failed(-2, &e), // Error fetching the balance
+    };
+
+    // Copy the JSON to the output buffer
+    if let Err(e) = unsafe { copy_string_to_buffer(&json, json_out, json_len) } {
+        return invalid_param(-3, 1, "json_len", e);
+    }
+
+    0 // Success
+}
+
+/// How `sss_format_token_amount` renders and `sss_parse_token_amount` reads an amount
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SssFormatOptions {
+    /// Unicode code point separating groups of three whole digits, 0 for no grouping
+    pub grouping_separator: u32,
+    /// Unicode code point separating whole and fractional digits
+    pub decimal_separator: u32,
+    /// Fractional digits always shown, padded with zeros
+    pub min_fraction_digits: u8,
+    /// Fractional digits shown at most, rounding half up; -1 shows every decimal
+    pub max_fraction_digits: c_int,
+    /// 1 to drop trailing fractional zeros beyond min_fraction_digits, 0 to keep them
+    pub trim_trailing_zeros: c_int,
+}
+
+/// FFI function to format an amount in base units for display
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - options is null or a valid pointer to an `SssFormatOptions`
+/// - formatted_out is a valid pointer to a buffer of sufficient size (formatted_len)
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_format_token_amount(
+    amount: u64,
+    decimals: c_uchar,
+    options: *const SssFormatOptions,
+    formatted_out: *mut c_char,
+    formatted_len: c_int,
+) -> c_int {
+    // Check for null pointers
+    if formatted_out.is_null() {
+        return null_param(-1, 3, "formatted_out");
+    }
+
+    let options = match unsafe { c_format_options(options) } {
+        Ok(options) => options,
+        Err(e) => return invalid_param(-2, 2, "options", e),
+    };
+
+    let formatted = format_token_amount(amount, decimals, &options);
+
+    // Copy the amount to the output buffer
+    if let Err(e) = unsafe { copy_string_to_buffer(&formatted, formatted_out, formatted_len) } {
+        return invalid_param(-3, 4, "formatted_len", e);
+    }
+
+    0 // Success
+}
+
+/// FFI function to parse a formatted amount into base units
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - formatted_str is a valid, null-terminated C string
+/// - options is null or a valid pointer to an `SssFormatOptions`
+/// - amount_out is a valid pointer to a u64
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_parse_token_amount(
+    formatted_str: *const c_char,
+    decimals: c_uchar,
+    options: *const SssFormatOptions,
+    amount_out: *mut u64,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "formatted_str", formatted_str.is_null()),
+        (3, "amount_out", amount_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let formatted = match unsafe { c_str_to_string(formatted_str) } {
+        Ok(s) => s,
+        Err(e) => return invalid_param(-2, 0, "formatted_str", e),
+    };
+
+    let options = match unsafe { c_format_options(options) } {
+        Ok(options) => options,
+        Err(e) => return invalid_param(-3, 2, "options", e),
+    };
+
+    match parse_token_amount(&formatted, decimals, &options) {
+        Ok(amount) => {
+            unsafe { *amount_out = amount };
+            0 // Success
+        }
+        Err(e) => operation_failed(-4, &e), // Error parsing amount
+    }
+}
+
+/// State of a recipient's associated token account, see `sss_check_recipient`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SssRecipientStatus {
+    /// 1 if the token account exists, 0 otherwise
+    pub account_exists: c_int,
+    /// 1 if the token account is frozen and minting to it would fail, 0 otherwise
+    pub frozen: c_int,
+    /// The current balance of the token account
+    pub balance: u64,
+    /// Lamports charged to create the token account, 0 if it exists
+    pub creation_lamports: u64,
+    /// Lamports charged as platform fee for the mint, 0 if none applies
+    pub platform_fee_lamports: u64,
+}
+
+/// FFI function to check a recipient's token account before minting
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - mint_str and owner_str are valid, null-terminated C strings containing valid Solana public keys
+/// - status_out is a valid pointer to an `SssRecipientStatus`
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_check_recipient(
+    mint_str: *const c_char,
+    owner_str: *const c_char,
+    status_out: *mut SssRecipientStatus,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "mint_str", mint_str.is_null()),
+        (1, "owner_str", owner_str.is_null()),
+        (2, "status_out", status_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let mint = match unsafe { c_str_to_pubkey(mint_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-2, 0, "mint_str", e),
+    };
+
+    let owner = match unsafe { c_str_to_pubkey(owner_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-3, 1, "owner_str", e),
+    };
+
+    // Call the Rust function
+    match check_recipient(mint, owner) {
+        Ok(status) => {
+            unsafe {
+                *status_out = SssRecipientStatus {
+                    account_exists: status.account_exists as c_int,
+                    frozen: status.frozen as c_int,
+                    balance: status.balance,
+                    creation_lamports: status.creation_lamports,
+                    platform_fee_lamports: status.platform_fee_lamports,
+                }
+            };
+
+            0 // Success
+        }
+        Err(e) => operation_failed(-4, &e), // Error checking recipient
+    }
+}
+
+/// Called with the new balance in base units and the `user_data` given when subscribing
+pub type SssBalanceCallback = extern "C" fn(amount: u64, user_data: *mut c_void);
+
+/// Caller data handed back to a balance callback on the polling thread
+struct CallbackUserData(*mut c_void);
+
+// The caller guarantees user_data may be used from the polling thread
+unsafe impl Send for CallbackUserData {}
+unsafe impl Sync for CallbackUserData {}
+
+impl CallbackUserData {
+    fn get(&self) -> *mut c_void {
+        self.0
+    }
+}
+
+/// FFI function to get notified whenever a wallet's token balance changes
+///
+/// The callback runs on a background thread. It receives the current balance once after
+/// subscribing, and again after RPC outages, so no change is missed.
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - mint_str and owner_str are valid, null-terminated C strings containing valid Solana public keys
+/// - user_data stays valid, and may be used from another thread, until `sss_unsubscribe` returns
+///
+/// @return A positive subscription handle on success, negative error code on failure
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_subscribe_balance(
+    mint_str: *const c_char,
+    owner_str: *const c_char,
+    callback: Option<SssBalanceCallback>,
+    user_data: *mut c_void,
+) -> i64 {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "mint_str", mint_str.is_null()),
+        (1, "owner_str", owner_str.is_null()),
+        (2, "callback", callback.is_none()),
+    ]) {
+        return null_param(-1, index, name).into();
+    }
+    let Some(callback) = callback else {
+        return -1;
+    };
+
+    let mint = match unsafe { c_str_to_pubkey(mint_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-2, 0, "mint_str", e).into(),
+    };
+
+    let owner = match unsafe { c_str_to_pubkey(owner_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-3, 1, "owner_str", e).into(),
+    };
+
+    let user_data = CallbackUserData(user_data);
+    match subscribe_token_balance(mint, owner, move |amount| callback(amount, user_data.get())) {
+        Ok(id) => i64::try_from(id).unwrap_or(i64::MAX),
+        Err(e) => operation_failed(-4, &e).into(), // Error subscribing
+    }
+}
+
+/// FFI function to stop the notifications of a balance subscription
+///
+/// A callback already running may still complete after this returns.
+///
+/// @param handle The handle returned by `sss_subscribe_balance`
+/// @return 0 on success, -1 if the handle is unknown
+#[unsafe(no_mangle)]
+pub extern "C" fn sss_unsubscribe(handle: i64) -> c_int {
+    let removed = u64::try_from(handle)
+        .map(unsubscribe_token_balance)
+        .unwrap_or(false);
+    if !removed {
+        return operation_failed(
+            -1,
+            &SssError::FfiError(format!("Unknown subscription handle {}", handle)),
+        );
+    }
+    0
+}
+
+/// Called with the JSON of a library event and the `user_data` given when registering
+///
+/// Runs on the event delivery thread. The JSON is only valid during the call.
+pub type SssEventCallback = extern "C" fn(json: *const c_char, user_data: *mut c_void);
+
+/// FFI function to receive library events as JSON
+///
+/// Replaces any previous callback; passing no callback stops the events. Events are
+/// delivered one at a time on a background thread; when the callback falls behind, the
+/// queue's overflow policy decides whether they are dropped or delay the operation, see
+/// `sss_set_callback_queue_options`.
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that user_data stays valid, and may be used from another thread,
+/// until the callback is replaced or `sss_shutdown` returns.
+///
+/// @return 0 on success
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_set_event_callback(
+    callback: Option<SssEventCallback>,
+    user_data: *mut c_void,
+) -> c_int {
+    let Some(callback) = callback else {
+        clear_event_sink();
+        return 0;
+    };
+
+    let user_data = CallbackUserData(user_data);
+    set_event_sink(move |event| {
+        let Ok(json) = versioned_json(&event) else {
+            return;
+        };
+        // Event JSON never contains interior NUL bytes
+        if let Ok(json) = CString::new(json) {
+            callback(json.as_ptr(), user_data.get());
+        }
+    });
+    0
+}
+
+/// FFI function to stop the library's background activity before the host exits
+///
+/// Queued asynchronous calls run and their callbacks return first. No event callback runs
+/// once this returns, except one stalled past the watchdog timeout, which is not waited
+/// for, and balance subscriptions are dropped. Must not be called from an event callback;
+/// called from a completion callback, it does not wait for the queue.
+#[unsafe(no_mangle)]
+pub extern "C" fn sss_shutdown() {
+    shutdown();
+}
+
+/// Called with the JSON of one page of assets and the `user_data` given when fetching
+///
+/// The JSON is only valid during the call. Returning non-zero stops the fetch.
+#[cfg(feature = "das")]
+pub type SssAssetPageCallback =
+    extern "C" fn(page_json: *const c_char, user_data: *mut c_void) -> c_int;
+
+/// FFI function to fetch every asset held by a wallet, one page at a time
+///
+/// Each page is passed to the callback as the JSON of an `AssetPage`, whose `next_cursor`
+/// can be saved and passed back as `cursor` to resume after a failure. Failed pages are
+/// retried before giving up.
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that owner_str is a valid, null-terminated C string containing a valid Solana public key.
+///
+/// @param cursor The page to start from, 0 or 1 for the first page
+/// @return 0 once every page was delivered, 1 if the callback stopped the fetch, negative error code on failure
+#[cfg(feature = "das")]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_fetch_all_assets(
+    owner_str: *const c_char,
+    cursor: u32,
+    callback: Option<SssAssetPageCallback>,
+    user_data: *mut c_void,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "owner_str", owner_str.is_null()),
+        (2, "callback", callback.is_none()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+    let Some(callback) = callback else {
+        return -1;
+    };
+
+    let owner = match unsafe { c_str_to_pubkey(owner_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-2, 0, "owner_str", e),
+    };
+
+    let options = AssetStreamOptions {
+        cursor: (cursor > 0).then_some(cursor),
+        ..AssetStreamOptions::default()
+    };
+    let stream = match fetch_all_digital_assets(&owner, options) {
+        Ok(stream) => stream,
+        Err(e) => return operation_failed(-3, &e), // Error fetching assets
+    };
+
+    for page in stream {
+        let page = match page {
+            Ok(page) => page,
+            Err(e) => return operation_failed(-3, &e), // Error fetching assets
+        };
+        let json = match versioned_json(&page).and_then(|j| {
+            CString::new(j)
+                .map_err(|e| SssError::FfiError(format!("Failed to serialize asset page: {}", e)))
+        }) {
+            Ok(j) => j,
+            Err(e) => return operation_failed(-4, &e), // Error serializing the page
+        };
+        if callback(json.as_ptr(), user_data) != 0 {
+            return 1; // Stopped by the callback
+        }
+    }
+
+    0 // Success
+}
+
+/// Callback receiving one chunk of a streamed JSON document
+///
+/// The chunk is `len` bytes and not null-terminated, and is only valid during the call.
+/// Returning non-zero stops the stream.
+#[cfg(feature = "das")]
+pub type SssChunkCallback =
+    extern "C" fn(chunk: *const c_char, len: c_int, user_data: *mut c_void) -> c_int;
+
+/// FFI function to stream every asset held by a wallet as one JSON document in chunks
+///
+/// The document is `{"v": 2, "data": [...]}` with one `DigitalAsset` per element, written
+/// as the pages arrive; the host concatenates the chunks. If the call does not return 0,
+/// the chunks delivered so far do not form a complete document.
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that owner_str is a valid, null-terminated C string containing a valid Solana public key.
+///
+/// @return 0 once the document is complete, 1 if the callback stopped the stream, negative error code on failure
+#[cfg(feature = "das")]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_fetch_assets_stream(
+    owner_str: *const c_char,
+    chunk_cb: Option<SssChunkCallback>,
+    user_data: *mut c_void,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "owner_str", owner_str.is_null()),
+        (1, "chunk_cb", chunk_cb.is_none()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+    let Some(chunk_cb) = chunk_cb else {
+        return -1;
+    };
+
+    let owner = match unsafe { c_str_to_pubkey(owner_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-2, 0, "owner_str", e),
+    };
+
+    let stream = match fetch_all_digital_assets(&owner, AssetStreamOptions::default()) {
+        Ok(stream) => stream,
+        Err(e) => return operation_failed(-3, &e), // Error fetching assets
+    };
+
+    let mut writer = JsonArrayWriter::new(c_chunk_sink(chunk_cb, user_data), STREAM_CHUNK_BYTES);
+    for page in stream {
+        let page = match page {
+            Ok(page) => page,
+            Err(e) => return operation_failed(-3, &e), // Error fetching assets
+        };
+        for asset in &page.assets {
+            match writer.push(asset) {
+                Ok(ControlFlow::Continue(())) => {}
+                Ok(ControlFlow::Break(())) => return 1, // Stopped by the callback
+                Err(e) => return operation_failed(-4, &e), // Error serializing an asset
+            }
+        }
+    }
+
+    match writer.finish() {
+        ControlFlow::Continue(()) => 0, // Success
+        ControlFlow::Break(()) => 1,    // Stopped by the callback
+    }
+}
+
+/// FFI function to write the JSON receipt of a confirmed transaction
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - signature_str is a valid, null-terminated C string containing a base58 signature
+/// - json_out is a valid pointer to a buffer of sufficient size (json_len)
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_fetch_transaction_receipt(
+    signature_str: *const c_char,
+    json_out: *mut c_char,
+    json_len: c_int,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "signature_str", signature_str.is_null()),
+        (1, "json_out", json_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let signature = match unsafe { c_str_to_signature(signature_str) } {
+        Ok(s) => s,
+        Err(e) => return invalid_param(-2, 0, "signature_str", e),
+    };
+
+    // Call the Rust function
+    let json =
+        match fetch_transaction_receipt(&signature.to_string()).and_then(|r| versioned_json(&r)) {
+            Ok(json) => json,
+            Err(e) => return operation_failed(-4, &e), // Error fetching the transaction
+        };
+
+    // Copy the receipt to the output buffer
+    if let Err(e) = unsafe { copy_string_to_buffer(&json, json_out, json_len) } {
+        return invalid_param(-3, 2, "json_len", e);
+    }
+
+    0 // Success
+}
+
+/// FFI function to write the consistency report of a token as JSON
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - mint_str is a valid, null-terminated C string containing a valid Solana public key
+/// - json_out is a valid pointer to a buffer of sufficient size (json_len)
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_audit_token_consistency(
+    mint_str: *const c_char,
+    json_out: *mut c_char,
+    json_len: c_int,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "mint_str", mint_str.is_null()),
+        (1, "json_out", json_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let mint = match unsafe { c_str_to_pubkey(mint_str) } {
+        Ok(pk) => pk,
+        Err(e) => return invalid_param(-2, 0, "mint_str", e),
+    };
+
+    // Call the Rust function
+    let json = match audit_token_consistency(mint).and_then(|report| versioned_json(&report)) {
+        Ok(json) => json,
+        Err(e) => return operation_failed(-4, &e), // Error reading the token
+    };
+
+    // Copy the report to the output buffer
+    if let Err(e) = unsafe { copy_string_to_buffer(&json, json_out, json_len) } {
+        return invalid_param(-3, 2, "json_len", e);
+    }
+
+    0 // Success
+}
+
+/// FFI function to request SOL for the payer from the cluster's faucet
+///
+/// Only exported when the library is built with the `dev-tools` feature; mainnet-beta is
+/// refused.
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that signature_out is a valid pointer to a buffer of sufficient size (signature_len)
+#[cfg(feature = "dev-tools")]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_request_airdrop(
+    lamports: u64,
+    signature_out: *mut c_char,
+    signature_len: c_int,
+) -> c_int {
+    // Check for null pointers
+    if signature_out.is_null() {
+        return null_param(-1, 1, "signature_out");
+    }
+
+    match request_airdrop(lamports) {
+        Ok(signature) => {
+            // Copy the signature to the output buffer
+            if let Err(e) =
+                unsafe { copy_string_to_buffer(&signature, signature_out, signature_len) }
+            {
+                return invalid_param(-2, 2, "signature_len", e);
+            }
+
+            0 // Success
+        }
+        Err(e) => operation_failed(-3, &e), // Error requesting the airdrop
+    }
+}
+
+/// FFI function to burn the payer's tokens and close their accounts, writing the report as JSON
+///
+/// Uses the default filter, so only mints whose update authority is the payer are burned.
+/// Only exported when the library is built with the `dev-tools` feature; mainnet-beta is
+/// refused unless allowed with `SSS_ALLOW_MAINNET_CLEANUP`.
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that json_out is a valid pointer to a buffer of sufficient size (json_len)
+#[cfg(feature = "dev-tools")]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_burn_all_assets(
+    dry_run: c_int,
+    json_out: *mut c_char,
+    json_len: c_int,
+) -> c_int {
+    // Check for null pointers
+    if json_out.is_null() {
+        return null_param(-1, 1, "json_out");
+    }
+
+    // Call the Rust function
+    let json = match burn_all_assets(&AssetFilter::default(), dry_run != 0)
+        .and_then(|report| versioned_json(&report))
+    {
+        Ok(json) => json,
+        Err(e) => return operation_failed(-3, &e), // Error burning the assets
+    };
+
+    // Copy the report to the output buffer
+    if let Err(e) = unsafe { copy_string_to_buffer(&json, json_out, json_len) } {
+        return invalid_param(-2, 2, "json_len", e);
+    }
+
+    0 // Success
+}
+
+/// FFI function to add a mint to the trusted set
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that mint_str is a valid, null-terminated C string containing a valid Solana public key.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_add_trusted_mint(mint_str: *const c_char) -> c_int {
+    // Check for null pointers
+    if mint_str.is_null() {
+        return null_param(-1, 0, "mint_str");
+    }
+
+    let mint = match unsafe { c_str_to_pubkey(mint_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-2, 0, "mint_str", e),
+    };
+
+    add_trusted_mint(mint);
+    0 // Success
+}
+
+/// FFI function to enable or disable strict mode
+///
+/// In strict mode, operations on mints outside the trusted set fail.
+///
+/// @param enabled Non-zero to enable strict mode, 0 to disable it
+/// @return 0 on success
+#[unsafe(no_mangle)]
+pub extern "C" fn sss_set_strict_mode(enabled: c_int) -> c_int {
+    set_strict_mode(enabled != 0);
+    0 // Success
+}
+
+/// FFI function to enable or disable read-only mode
+///
+/// In read-only mode, every function that would sign or send a transaction fails with
+/// `SSS_ERR_READ_ONLY`.
+///
+/// @param enabled Non-zero to enable read-only mode, 0 to disable it
+/// @return 0 on success
+#[unsafe(no_mangle)]
+pub extern "C" fn sss_set_read_only(enabled: c_int) -> c_int {
+    set_read_only(enabled != 0);
+    0 // Success
+}
+
+/// FFI function to select the profile used by later calls on the calling thread
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that name is null or a valid, null-terminated C string.
+///
+/// @param name The name of a profile from `SSS_PROFILES_PATH`, or NULL for the global configuration
+/// @return 0 on success, -2 if the name is not valid UTF-8, -3 if no such profile is registered
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_select_profile(name: *const c_char) -> c_int {
+    let name = match unsafe { c_str_to_optional_string(name) } {
+        Ok(s) => s,
+        Err(e) => return invalid_param(-2, 0, "name", e),
+    };
+
+    match select_profile(name.as_deref()) {
+        Ok(()) => 0,                        // Success
+        Err(e) => operation_failed(-3, &e), // Profile not registered
+    }
+}
+
+/// FFI function to generate a new reference key for a payment request
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that reference_out is a valid pointer to a buffer of sufficient
+/// size (reference_len).
+///
+/// @param reference_out A buffer to receive the base58 reference key
+/// @param reference_len The size of the reference buffer
+/// @return 0 on success, negative error code on failure
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_generate_payment_reference(
+    reference_out: *mut c_char,
+    reference_len: c_int,
+) -> c_int {
+    if reference_out.is_null() {
+        return null_param(-1, 0, "reference_out");
+    }
+
+    let reference = generate_payment_reference().to_string();
+    if let Err(e) = unsafe { copy_string_to_buffer(&reference, reference_out, reference_len) } {
+        return invalid_param(-2, 1, "reference_len", e);
+    }
+
+    0 // Success
+}
+
+/// FFI function to build a Solana Pay transfer request paying the payer wallet
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - reference_str is a valid, null-terminated C string containing a valid Solana public key
+/// - spl_mint_str is null or a valid, null-terminated C string containing a valid Solana public key
+/// - label and message are null or valid, null-terminated C strings
+/// - url_out is a valid pointer to a buffer of sufficient size (url_len)
+///
+/// @param amount The amount in base units, lamports for SOL
+/// @param spl_mint_str The base58 address of the SPL token to pay in, or NULL for SOL
+/// @param reference_str The base58 reference key, see sss_generate_payment_reference
+/// @param label Who is requesting the payment, or NULL
+/// @param message What the payment is for, or NULL
+/// @param url_out A buffer to receive the `solana:` URL
+/// @param url_len The size of the URL buffer
+/// @return 0 on success, negative error code on failure
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_create_payment_request(
+    amount: u64,
+    spl_mint_str: *const c_char,
+    reference_str: *const c_char,
+    label: *const c_char,
+    message: *const c_char,
+    url_out: *mut c_char,
+    url_len: c_int,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (2, "reference_str", reference_str.is_null()),
+        (5, "url_out", url_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let spl_mint = match unsafe { c_str_to_optional_pubkey(spl_mint_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-2, 1, "spl_mint_str", e),
+    };
+
+    let reference = match unsafe { c_str_to_pubkey(reference_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-3, 2, "reference_str", e),
+    };
+
+    let label = match unsafe { c_str_to_optional_string(label) } {
+        Ok(s) => s,
+        Err(e) => return invalid_param(-4, 3, "label", e),
+    };
+
+    let message = match unsafe { c_str_to_optional_string(message) } {
+        Ok(s) => s,
+        Err(e) => return invalid_param(-5, 4, "message", e),
+    };
+
+    match create_payment_request(
+        amount,
+        spl_mint,
+        reference,
+        label.as_deref(),
+        message.as_deref(),
+    ) {
+        Ok(request) => {
+            if let Err(e) = unsafe { copy_string_to_buffer(&request.url, url_out, url_len) } {
+                return invalid_param(-6, 6, "url_len", e);
+            }
+
+            0 // Success
+        }
+        Err(e) => operation_failed(-7, &e), // Error building the request
+    }
+}
+
+/// FFI function to wait for a payment to the payer wallet carrying a reference key
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - reference_str is a valid, null-terminated C string containing a valid Solana public key
+/// - spl_mint_str is null or a valid, null-terminated C string containing a valid Solana public key
+/// - signature_out is a valid pointer to a buffer of sufficient size (signature_len)
+///
+/// @param reference_str The base58 reference key of the request
+/// @param spl_mint_str The base58 address of the SPL token requested, or NULL for SOL
+/// @param amount The requested amount in base units, lamports for SOL
+/// @param timeout_ms How long to wait for the payment in milliseconds
+/// @param signature_out A buffer to receive the signature of the paying transaction
+/// @param signature_len The size of the signature buffer
+/// @return 0 on success, -5 if the payment was short, -6 on timeout or other failure
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_await_payment(
+    reference_str: *const c_char,
+    spl_mint_str: *const c_char,
+    amount: u64,
+    timeout_ms: u64,
+    signature_out: *mut c_char,
+    signature_len: c_int,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "reference_str", reference_str.is_null()),
+        (4, "signature_out", signature_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let reference = match unsafe { c_str_to_pubkey(reference_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-2, 0, "reference_str", e),
+    };
+
+    let spl_mint = match unsafe { c_str_to_optional_pubkey(spl_mint_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-3, 1, "spl_mint_str", e),
+    };
+
+    match await_payment(
+        reference,
+        spl_mint,
+        amount,
+        Duration::from_millis(timeout_ms),
+    ) {
+        Ok(confirmation) => {
+            if let Err(e) = unsafe {
+                copy_string_to_buffer(&confirmation.signature, signature_out, signature_len)
+            } {
+                return invalid_param(-4, 5, "signature_len", e);
+            }
+
+            0 // Success
+        }
+        Err(e @ SssError::TokenError(_)) => operation_failed(-5, &e), // Payment was short
+        Err(e) => operation_failed(-6, &e), // Timeout or error looking up the payment
+    }
+}
+
+/// FFI function to load the payer from a passphrase-encrypted keystore file
+///
+/// Unlike `PAYER_KEYSTORE_PASSPHRASE`, the passphrase never passes through the environment.
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that path and passphrase are valid, null-terminated C strings.
+///
+/// @param path The path of the keystore file
+/// @param passphrase The passphrase the keystore was saved with
+/// @return 0 on success, -2 or -3 if path or passphrase is not valid UTF-8, -4 if the
+///         file cannot be read or the passphrase is wrong
+#[cfg(feature = "keystore")]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_unlock_keystore(
+    path: *const c_char,
+    passphrase: *const c_char,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "path", path.is_null()),
+        (1, "passphrase", passphrase.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let path = match unsafe { c_str_to_string(path) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-2, 0, "path", e),
+    };
+
+    let passphrase = match unsafe { c_str_to_string(passphrase) } {
+        Ok(p) => Zeroizing::new(p),
+        Err(e) => return invalid_param(-3, 1, "passphrase", e),
+    };
+
+    match unlock_payer_keystore(&path, &passphrase) {
+        Ok(()) => 0,                        // Success
+        Err(e) => operation_failed(-4, &e), // Error unlocking the keystore
+    }
+}
+
+/// FFI function to reload the trusted mints, supply cap and role policy files, writing what
+/// changed as JSON
+///
+/// A file that fails to parse keeps the previous policies; the error is available from
+/// `sss_get_last_error`.
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that json_out is null or a valid pointer to a buffer of sufficient size (json_len).
+///
+/// @param json_out A buffer to receive the `PolicyReload` JSON, or NULL
+/// @param json_len The size of the buffer
+/// @return 0 on success, -2 if the buffer is too small, -3 if a file could not be loaded
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_reload_policies(json_out: *mut c_char, json_len: c_int) -> c_int {
+    let json = match reload_policy_files().and_then(|reload| versioned_json(&reload)) {
+        Ok(json) => json,
+        Err(e) => return operation_failed(-3, &e), // Error loading a policy file
+    };
+
+    // Copy the summary to the output buffer, if requested
+    if !json_out.is_null()
+        && let Err(e) = unsafe { copy_string_to_buffer(&json, json_out, json_len) }
+    {
+        return invalid_param(-2, 1, "json_len", e);
+    }
+
+    0 // Success
+}
+
+/// FFI function to build a claim transaction minting tokens to a wallet that holds no SOL
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - mint_str and claimer_str are valid, null-terminated C strings containing valid Solana
+///   public keys
+/// - tx_out is a valid pointer to a buffer of sufficient size (tx_len)
+/// - tx_written_out is a valid pointer to an int
+///
+/// @param mint_str The mint of the tokens
+/// @param claimer_str The wallet receiving the tokens
+/// @param amount The amount in base units
+/// @param tx_out A buffer to receive the bincode-serialized, payer-signed transaction
+/// @param tx_len The size of the buffer
+/// @param tx_written_out Receives the number of bytes written to tx_out
+/// @return 0 on success, -2 for an invalid mint, -3 for an invalid claimer,
+///         -4 if the buffer is too small, -5 if the transaction could not be built
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_build_claim_transaction(
+    mint_str: *const c_char,
+    claimer_str: *const c_char,
+    amount: u64,
+    tx_out: *mut c_uchar,
+    tx_len: c_int,
+    tx_written_out: *mut c_int,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "mint_str", mint_str.is_null()),
+        (1, "claimer_str", claimer_str.is_null()),
+        (3, "tx_out", tx_out.is_null()),
+        (5, "tx_written_out", tx_written_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let mint = match unsafe { c_str_to_pubkey(mint_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-2, 0, "mint_str", e),
+    };
+
+    let claimer = match unsafe { c_str_to_recipient(claimer_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-3, 1, "claimer_str", e),
+    };
+
+    match build_claim_transaction(mint, claimer, amount) {
+        Ok(tx) => match unsafe { copy_bytes_to_buffer(&tx, tx_out, tx_len) } {
+            Ok(written) => {
+                unsafe { *tx_written_out = written };
+                0 // Success
+            }
+            Err(e) => invalid_param(-4, 4, "tx_len", e),
+        },
+        Err(e) => operation_failed(-5, &e), // Error building the claim
+    }
+}
+
+/// FFI function to create a claim link for tokens held by the payer
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - mint_str is a valid, null-terminated C string containing a valid Solana public key
+/// - payload_out and signature_out are valid pointers to buffers of sufficient size
+///
+/// @param mint_str The mint of the tokens to escrow
+/// @param amount The amount in base units
+/// @param expiry_unix When the link expires, in seconds since the Unix epoch
+/// @param payload_out A buffer to receive the payload to encode in the QR code
+/// @param payload_len The size of the payload buffer
+/// @param signature_out A buffer to receive the signature of the funding transaction
+/// @param signature_len The size of the signature buffer
+/// @return 0 on success, -2 for an invalid mint, -3 for an invalid expiry,
+///         -4/-5 if a buffer is too small, -6 if the link could not be created
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_create_claim_link(
+    mint_str: *const c_char,
+    amount: u64,
+    expiry_unix: i64,
+    payload_out: *mut c_char,
+    payload_len: c_int,
+    signature_out: *mut c_char,
+    signature_len: c_int,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "mint_str", mint_str.is_null()),
+        (3, "payload_out", payload_out.is_null()),
+        (5, "signature_out", signature_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let mint = match unsafe { c_str_to_pubkey(mint_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-2, 0, "mint_str", e),
+    };
+
+    let Some(expiry) = DateTime::<Utc>::from_timestamp(expiry_unix, 0) else {
+        return invalid_param(
+            -3,
+            2,
+            "expiry_unix",
+            SssError::FfiError(format!("Timestamp {} is out of range", expiry_unix)),
+        );
+    };
+
+    match create_claim_link(mint, amount, expiry) {
+        Ok(link) => {
+            if let Err(e) =
+                unsafe { copy_string_to_buffer(&link.payload, payload_out, payload_len) }
+            {
+                return invalid_param(-4, 4, "payload_len", e);
+            }
+
+            if let Err(e) =
+                unsafe { copy_string_to_buffer(&link.signature, signature_out, signature_len) }
+            {
+                return invalid_param(-5, 6, "signature_len", e);
+            }
+
+            0 // Success
+        }
+        Err(e) => operation_failed(-6, &e), // Error funding the escrow
+    }
+}
+
+/// FFI function to redeem a claim link, sending its tokens to a recipient
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - payload and recipient_str are valid, null-terminated C strings
+/// - signature_out is a valid pointer to a buffer of sufficient size (signature_len)
+///
+/// @param payload The payload scanned from the QR code
+/// @param recipient_str The wallet receiving the tokens
+/// @param signature_out A buffer to receive the transaction signature
+/// @param signature_len The size of the signature buffer
+/// @return 0 on success, -2 for an invalid payload string, -3 for an invalid recipient,
+///         -4 if the buffer is too small, -5 if the link has expired,
+///         -6 if the link was already redeemed or reclaimed, -7 if the redemption failed
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_redeem_claim_link(
+    payload: *const c_char,
+    recipient_str: *const c_char,
+    signature_out: *mut c_char,
+    signature_len: c_int,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "payload", payload.is_null()),
+        (1, "recipient_str", recipient_str.is_null()),
+        (2, "signature_out", signature_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let payload = match unsafe { c_str_to_string(payload) } {
+        Ok(s) => s,
+        Err(e) => return invalid_param(-2, 0, "payload", e),
+    };
+
+    let recipient = match unsafe { c_str_to_pubkey(recipient_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-3, 1, "recipient_str", e),
+    };
+
+    match redeem_claim_link(&payload, recipient) {
+        Ok(signature) => {
+            if let Err(e) =
+                unsafe { copy_string_to_buffer(&signature, signature_out, signature_len) }
+            {
+                return invalid_param(-4, 3, "signature_len", e);
+            }
+
+            0 // Success
+        }
+        Err(e @ SssError::ClaimLinkExpired { .. }) => operation_failed(-5, &e),
+        Err(e @ SssError::ClaimLinkRedeemed { .. }) => operation_failed(-6, &e),
+        Err(e) => operation_failed(-7, &e), // Error redeeming the link
+    }
+}
+
+/// FFI function to return the tokens of an unredeemed claim link to the payer
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - escrow_str is a valid, null-terminated C string containing a valid Solana public key
+/// - signature_out is a valid pointer to a buffer of sufficient size (signature_len)
+///
+/// @param escrow_str The escrow account of the link
+/// @param signature_out A buffer to receive the transaction signature
+/// @param signature_len The size of the signature buffer
+/// @return 0 on success, -2 for an invalid escrow, -3 if the buffer is too small,
+///         -4 if the tokens could not be reclaimed
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_reclaim_claim_link(
+    escrow_str: *const c_char,
+    signature_out: *mut c_char,
+    signature_len: c_int,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "escrow_str", escrow_str.is_null()),
+        (1, "signature_out", signature_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let escrow = match unsafe { c_str_to_pubkey(escrow_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-2, 0, "escrow_str", e),
+    };
+
+    match reclaim_claim_link(escrow) {
+        Ok(signature) => {
+            if let Err(e) =
+                unsafe { copy_string_to_buffer(&signature, signature_out, signature_len) }
+            {
+                return invalid_param(-3, 2, "signature_len", e);
+            }
+
+            0 // Success
+        }
+        Err(e) => operation_failed(-4, &e), // Error reclaiming the tokens
+    }
+}
+
+/// FFI function to verify a command signed by the backend, writing it as JSON
+///
+/// A successful verification uses up the command's nonce; to act on the command as well,
+/// call `sss_execute_signed_command` instead.
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - payload_json, signature_b58 and trusted_signer_str are valid, null-terminated C strings
+/// - command_json_out is a valid pointer to a buffer of sufficient size (command_json_len)
+///
+/// @param payload_json The command as JSON
+/// @param signature_b58 The base58 signature of the command
+/// @param trusted_signer_str The backend's public key
+/// @param command_json_out A buffer to receive the verified `SignedCommand` JSON
+/// @param command_json_len The size of the buffer
+/// @return 0 on success, -2/-3/-4 for an invalid payload, signature or signer string,
+///         -5 if the buffer is too small, -6 if the command was rejected
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_verify_signed_command(
+    payload_json: *const c_char,
+    signature_b58: *const c_char,
+    trusted_signer_str: *const c_char,
+    command_json_out: *mut c_char,
+    command_json_len: c_int,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "payload_json", payload_json.is_null()),
+        (1, "signature_b58", signature_b58.is_null()),
+        (2, "trusted_signer_str", trusted_signer_str.is_null()),
+        (3, "command_json_out", command_json_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let (payload, signature, trusted_signer) =
+        match unsafe { c_signed_command_params(payload_json, signature_b58, trusted_signer_str) } {
+            Ok(params) => params,
+            Err(code) => return code,
+        };
+
+    let json = match verify_signed_command(&payload, &signature, trusted_signer)
+        .and_then(|command| versioned_json(&command))
+    {
+        Ok(json) => json,
+        Err(e) => return operation_failed(-6, &e), // Command rejected
+    };
+
+    if let Err(e) = unsafe { copy_string_to_buffer(&json, command_json_out, command_json_len) } {
+        return invalid_param(-5, 4, "command_json_len", e);
+    }
+
+    0 // Success
+}
+
+/// FFI function to verify a command signed by the backend and carry it out
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - payload_json, signature_b58 and trusted_signer_str are valid, null-terminated C strings
+/// - signature_out is a valid pointer to a buffer of sufficient size (signature_len)
+///
+/// @param payload_json The command as JSON
+/// @param signature_b58 The base58 signature of the command
+/// @param trusted_signer_str The backend's public key
+/// @param signature_out A buffer to receive the transaction signature
+/// @param signature_len The size of the buffer
+/// @return 0 on success, -2/-3/-4 for an invalid payload, signature or signer string,
+///         -5 if the buffer is too small, -6 if the command was rejected,
+///         -7 if the token operation failed
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_execute_signed_command(
+    payload_json: *const c_char,
+    signature_b58: *const c_char,
+    trusted_signer_str: *const c_char,
+    signature_out: *mut c_char,
+    signature_len: c_int,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "payload_json", payload_json.is_null()),
+        (1, "signature_b58", signature_b58.is_null()),
+        (2, "trusted_signer_str", trusted_signer_str.is_null()),
+        (3, "signature_out", signature_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let (payload, signature, trusted_signer) =
+        match unsafe { c_signed_command_params(payload_json, signature_b58, trusted_signer_str) } {
+            Ok(params) => params,
+            Err(code) => return code,
+        };
+
+    let command = match verify_signed_command(&payload, &signature, trusted_signer) {
+        Ok(command) => command,
+        Err(e) => return operation_failed(-6, &e), // Command rejected
+    };
+
+    match execute_signed_command(command) {
+        Ok(tx_signature) => {
+            if let Err(e) =
+                unsafe { copy_string_to_buffer(&tx_signature, signature_out, signature_len) }
+            {
+                return invalid_param(-5, 4, "signature_len", e);
+            }
+
+            0 // Success
+        }
+        Err(e) => operation_failed(-7, &e), // Error carrying out the command
+    }
+}
+
+/// Reads the payload, signature and signer of a signed command, returning the error code
+/// of the first invalid one
+///
+/// # Safety
+///
+/// All three pointers must be valid C strings
+unsafe fn c_signed_command_params(
+    payload_json: *const c_char,
+    signature_b58: *const c_char,
+    trusted_signer_str: *const c_char,
+) -> Result<(String, String, Pubkey), c_int> {
+    let payload = unsafe { c_str_to_string(payload_json) }
+        .map_err(|e| invalid_param(-2, 0, "payload_json", e))?;
+    let signature = unsafe { c_str_to_string(signature_b58) }
+        .map_err(|e| invalid_param(-3, 1, "signature_b58", e))?;
+    let trusted_signer = unsafe { c_str_to_pubkey(trusted_signer_str) }
+        .map_err(|e| invalid_param(-4, 2, "trusted_signer_str", e))?;
+    Ok((payload, signature, trusted_signer))
+}
+
+/// FFI function to review a partner transaction and co-sign and submit it as fee payer
+///
+/// The outcome is written as `ReviewOutcome` JSON: the signature of the submitted
+/// transaction, or every rule the transaction breaks. Only the system, token, associated
+/// token account, memo and compute budget programs are allowed.
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - tx_b64 is a valid, null-terminated C string containing a base64-encoded transaction
+/// - outcome_json_out is a valid pointer to a buffer of sufficient size (outcome_json_len)
+///
+/// @param tx_b64 The bincode-serialized legacy or v0 transaction, base64-encoded
+/// @param max_payer_outflow_lamports The most lamports the payer may spend, including the fee
+/// @param outcome_json_out A buffer to receive the `ReviewOutcome` JSON
+/// @param outcome_json_len The size of the buffer
+/// @return 0 if the transaction was submitted, 1 if it was rejected, -2 for an invalid
+///         transaction string, -3 if the buffer is too small, -4 if the review or
+///         submission failed
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_review_external_transaction(
+    tx_b64: *const c_char,
+    max_payer_outflow_lamports: u64,
+    outcome_json_out: *mut c_char,
+    outcome_json_len: c_int,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "tx_b64", tx_b64.is_null()),
+        (2, "outcome_json_out", outcome_json_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let tx_bytes = match unsafe { c_str_to_base64_bytes(tx_b64) } {
+        Ok(bytes) => bytes,
+        Err(e) => return invalid_param(-2, 0, "tx_b64", e),
+    };
+
+    let policy = ReviewPolicy {
+        max_payer_outflow_lamports,
+        ..ReviewPolicy::default()
+    };
+    let outcome = match review_external_transaction(&tx_bytes, policy) {
+        Ok(outcome) => outcome,
+        Err(e) => return operation_failed(-4, &e), // Error reviewing or submitting
+    };
+    let json = match versioned_json(&outcome) {
+        Ok(json) => json,
+        Err(e) => return operation_failed(-4, &e),
+    };
+
+    if let Err(e) = unsafe { copy_string_to_buffer(&json, outcome_json_out, outcome_json_len) } {
+        return invalid_param(-3, 3, "outcome_json_len", e);
+    }
+
+    match outcome {
+        ReviewOutcome::Approved { .. } => 0, // Submitted
+        ReviewOutcome::Rejected { .. } => 1, // Rejected
+    }
+}
+
+/// FFI function to list the recent movements of a wallet's tokens as JSON
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - owner_str is a valid, null-terminated C string
+/// - mints_json and cursor are null or valid, null-terminated C strings
+/// - json_out is a valid pointer to a buffer of sufficient size (json_len)
+///
+/// @param owner_str The wallet
+/// @param mints_json A JSON array of the mints to include, or null for the trusted mints
+/// @param limit The number of items wanted
+/// @param cursor The `next_cursor` of the previous page, or null for the newest items
+/// @param json_out A buffer to receive the `ActivityPage` JSON
+/// @param json_len The size of the buffer
+/// @return 0 on success, -2 for an invalid owner, -3 for an invalid mint list, -4 for a
+///         limit below 1, -5 for an invalid cursor string, -6 if the buffer is too small,
+///         -7 if the history cannot be read
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_wallet_activity(
+    owner_str: *const c_char,
+    mints_json: *const c_char,
+    limit: c_int,
+    cursor: *const c_char,
+    json_out: *mut c_char,
+    json_len: c_int,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "owner_str", owner_str.is_null()),
+        (4, "json_out", json_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let owner = match unsafe { c_str_to_pubkey(owner_str) } {
+        Ok(owner) => owner,
+        Err(e) => return invalid_param(-2, 0, "owner_str", e),
+    };
+    let mints = match unsafe { c_str_to_optional_pubkeys(mints_json) } {
+        Ok(mints) => mints,
+        Err(e) => return invalid_param(-3, 1, "mints_json", e),
+    };
+    let limit = match usize::try_from(limit) {
+        Ok(limit) if limit > 0 => limit,
+        _ => {
+            return invalid_param(
+                -4,
+                2,
+                "limit",
+                SssError::FfiError(format!("Limit must be at least 1, got {}", limit)),
+            );
+        }
+    };
+    let cursor = match unsafe { c_str_to_optional_string(cursor) } {
+        Ok(cursor) => cursor,
+        Err(e) => return invalid_param(-5, 3, "cursor", e),
+    };
+
+    let json = match wallet_activity_page(owner, mints.as_deref(), limit, cursor.as_deref())
+        .and_then(|page| versioned_json(&page))
+    {
+        Ok(json) => json,
+        Err(e) => return operation_failed(-7, &e), // Error reading the history
+    };
+
+    if let Err(e) = unsafe { copy_string_to_buffer(&json, json_out, json_len) } {
+        return invalid_param(-6, 5, "json_len", e);
+    }
+
+    0 // Success
+}
+
+/// Creates a new token whose royalty is shared between the given creators and returns the
+/// transaction signature and mint address
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - uri_ptr, name_ptr and creators_json are valid, null-terminated C strings
+/// - signature_out and mint_address_out are valid pointers to buffers of sufficient size
+///
+/// @param uri_ptr A pointer to a null-terminated C string containing the token URI
+/// @param name_ptr A pointer to a null-terminated C string containing the token name
+/// @param decimals The number of decimal places for the token
+/// @param seller_fee_bps The royalty charged on secondary sales, in basis points
+/// @param creators_json A JSON array of creators, e.g.
+///        [{"address": "...", "share_percent": 70, "verified": true}, {"address": "...", "share_percent": 30}];
+///        an empty array makes the payer the sole creator
+/// @param signature_out A pointer to a buffer where the transaction signature will be written
+/// @param signature_len The length of the signature_out buffer
+/// @param mint_address_out A pointer to a buffer where the mint address will be written
+/// @param mint_address_len The length of the mint_address_out buffer
+/// @return 0 on success, -2/-3 for an invalid URI or name, -4 for invalid creators JSON,
+///         -5/-6 if a buffer is too small, -7 if the token could not be created, including
+///         creators whose shares do not add up to 100
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn sss_create_token_with_creators(
+    uri_ptr: *const c_char,
+    name_ptr: *const c_char,
+    decimals: c_uchar,
+    seller_fee_bps: u16,
+    creators_json: *const c_char,
+    signature_out: *mut c_char,
+    signature_len: c_int,
+    mint_address_out: *mut c_char,
+    mint_address_len: c_int,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "uri_ptr", uri_ptr.is_null()),
+        (1, "name_ptr", name_ptr.is_null()),
+        (4, "creators_json", creators_json.is_null()),
+        (5, "signature_out", signature_out.is_null()),
+        (7, "mint_address_out", mint_address_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let uri = match unsafe { c_str_to_string(uri_ptr) } {
+        Ok(s) => s,
+        Err(e) => return invalid_param(-2, 0, "uri_ptr", e),
+    };
+
+    let name = match unsafe { c_str_to_string(name_ptr) } {
+        Ok(s) => s,
+        Err(e) => return invalid_param(-3, 1, "name_ptr", e),
+    };
+
+    let creators = match unsafe { c_str_to_creators(creators_json) } {
+        Ok(c) => c,
+        Err(e) => return invalid_param(-4, 4, "creators_json", e),
+    };
+
+    let builder = TokenBuilder::new()
+        .name(name)
+        .uri(uri)
+        .decimals(decimals)
+        .seller_fee_bps(seller_fee_bps)
+        .creators(creators);
+    match builder.create() {
+        Ok((signature, mint_pubkey)) => {
+            if let Err(e) =
+                unsafe { copy_string_to_buffer(&signature, signature_out, signature_len) }
+            {
+                return invalid_param(-5, 6, "signature_len", e);
+            }
+
+            if let Err(e) = unsafe {
+                copy_string_to_buffer(&mint_pubkey.to_string(), mint_address_out, mint_address_len)
+            } {
+                return invalid_param(-6, 8, "mint_address_len", e);
+            }
+
+            0 // Success
+        }
+        Err(e) => operation_failed(-7, &e), // Error creating token
+    }
+}
+
+/// FFI function to mark the payer as a verified creator of a token
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - mint_str is a valid, null-terminated C string containing a valid Solana public key
+/// - signature_out is a valid pointer to a buffer of sufficient size (signature_len)
+///
+/// @param mint_str The mint whose metadata lists the payer as a creator
+/// @param signature_out A buffer to receive the transaction signature
+/// @param signature_len The size of the signature buffer
+/// @return 0 on success, -2 for an invalid mint, -3 if the buffer is too small,
+///         -4 if the creator could not be verified, e.g. because the payer is not one
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_verify_creator(
+    mint_str: *const c_char,
+    signature_out: *mut c_char,
+    signature_len: c_int,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "mint_str", mint_str.is_null()),
+        (1, "signature_out", signature_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let mint = match unsafe { c_str_to_pubkey(mint_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-2, 0, "mint_str", e),
+    };
+
+    match verify_creator(mint) {
+        Ok(signature) => {
+            if let Err(e) =
+                unsafe { copy_string_to_buffer(&signature, signature_out, signature_len) }
+            {
+                return invalid_param(-3, 2, "signature_len", e);
+            }
+
+            0 // Success
+        }
+        Err(e) => operation_failed(-4, &e), // Error verifying the creator
+    }
+}
+
+/// FFI function to prepare the connection for a fast first operation, writing the timing
+/// of each step as JSON
+///
+/// Call it when an idle device expects to send soon, e.g. when a kiosk shows its attract
+/// screen.
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that json_out is a valid pointer to a buffer of sufficient size (json_len).
+///
+/// @param json_out A buffer to receive the `WarmupReport` JSON
+/// @param json_len The size of the buffer
+/// @return 0 on success, -2 if the endpoint or the payer could not be warmed up,
+///         -3 if the buffer is too small
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_warm_up(json_out: *mut c_char, json_len: c_int) -> c_int {
+    // Check for null pointers
+    if json_out.is_null() {
+        return null_param(-1, 0, "json_out");
+    }
+
+    let json = match warm_up().and_then(|report| versioned_json(&report)) {
+        Ok(j) => j,
+        Err(e) => return operation_failed(-2, &e), // Error warming up
+    };
+
+    // Copy the JSON to the output buffer
+    if let Err(e) = unsafe { copy_string_to_buffer(&json, json_out, json_len) } {
+        return invalid_param(-3, 1, "json_len", e);
+    }
+
+    0 // Success
+}
+
+/// FFI function to start or stop pinging the RPC endpoint to keep the connection warm
+///
+/// While the endpoint is unreachable, the pings back off up to every 5 minutes.
+/// `sss_shutdown` stops the keep-alive as well.
+///
+/// @param interval_secs Seconds between pings, or 0 to stop the keep-alive
+/// @return 0 on success, -2 for a negative interval
+#[unsafe(no_mangle)]
+pub extern "C" fn sss_set_keep_alive(interval_secs: c_int) -> c_int {
+    match u64::try_from(interval_secs) {
+        Ok(0) => stop_keep_alive(),
+        Ok(secs) => start_keep_alive(Duration::from_secs(secs)),
+        Err(_) => {
+            return invalid_param(
+                -2,
+                0,
+                "interval_secs",
+                SssError::FfiError(format!(
+                    "Interval must not be negative, got {}",
+                    interval_secs
+                )),
+            );
+        }
+    }
+    0 // Success
+}
+
+/// FFI function to create the Metaplex metadata of a mint created by another tool
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - mint_str, uri_ptr, name_ptr and symbol_ptr are valid, null-terminated C strings
+/// - signature_out is a valid pointer to a buffer of sufficient size (signature_len)
+///
+/// @param mint_str The existing mint, whose mint authority must be the payer
+/// @param uri_ptr The URI pointing to the token's metadata
+/// @param name_ptr The name of the token
+/// @param symbol_ptr The ticker symbol of the token
+/// @param signature_out A buffer to receive the transaction signature
+/// @param signature_len The size of the signature buffer
+/// @return 0 on success, -2 for an invalid mint, -3/-4/-5 for an invalid URI, name or
+///         symbol string, -6 if the buffer is too small, -7 if the metadata could not be
+///         created, e.g. because the mint already has metadata
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_attach_metadata(
+    mint_str: *const c_char,
+    uri_ptr: *const c_char,
+    name_ptr: *const c_char,
+    symbol_ptr: *const c_char,
+    signature_out: *mut c_char,
+    signature_len: c_int,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "mint_str", mint_str.is_null()),
+        (1, "uri_ptr", uri_ptr.is_null()),
+        (2, "name_ptr", name_ptr.is_null()),
+        (3, "symbol_ptr", symbol_ptr.is_null()),
+        (4, "signature_out", signature_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let mint = match unsafe { c_str_to_pubkey(mint_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-2, 0, "mint_str", e),
+    };
+
+    let uri = match unsafe { c_str_to_string(uri_ptr) } {
+        Ok(s) => s,
+        Err(e) => return invalid_param(-3, 1, "uri_ptr", e),
+    };
+
+    let name = match unsafe { c_str_to_string(name_ptr) } {
+        Ok(s) => s,
+        Err(e) => return invalid_param(-4, 2, "name_ptr", e),
+    };
+
+    let symbol = match unsafe { c_str_to_string(symbol_ptr) } {
+        Ok(s) => s,
+        Err(e) => return invalid_param(-5, 3, "symbol_ptr", e),
+    };
+
+    match attach_metadata_to_existing_mint(mint, &uri, &name, &symbol) {
+        Ok(signature) => {
+            if let Err(e) =
+                unsafe { copy_string_to_buffer(&signature, signature_out, signature_len) }
+            {
+                return invalid_param(-6, 5, "signature_len", e);
+            }
+
+            0 // Success
+        }
+        Err(e) => operation_failed(-7, &e), // Error creating the metadata
+    }
+}
+
+/// FFI function to mint tokens at most once per idempotency key, even across restarts
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - mint_str and idempotency_key are valid, null-terminated C strings
+/// - token_owner_str is either null or a valid, null-terminated C string
+/// - signature_out is a valid pointer to a buffer of sufficient size (signature_len)
+///
+/// @param mint_str The mint to mint from
+/// @param token_owner_str The recipient wallet, or null for the payer
+/// @param amount The amount to mint, in base units
+/// @param idempotency_key The caller's identifier of the mint, e.g. a job id
+/// @param signature_out A buffer to receive the signature of the new or earlier mint
+/// @param signature_len The size of the signature buffer
+/// @return 0 on success, -2/-3 for an invalid mint or owner, -4 for an invalid key, -5 if
+///         the buffer is too small, -6 if minting failed or an earlier mint with the key
+///         is still unconfirmed
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_mint_token_idempotent(
+    mint_str: *const c_char,
+    token_owner_str: *const c_char,
+    amount: u64,
+    idempotency_key: *const c_char,
+    signature_out: *mut c_char,
+    signature_len: c_int,
+) -> c_int {
+    if let Some((index, name)) = first_null_param(&[
+        (0, "mint_str", mint_str.is_null()),
+        (3, "idempotency_key", idempotency_key.is_null()),
+        (4, "signature_out", signature_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let mint = match unsafe { c_str_to_pubkey(mint_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-2, 0, "mint_str", e),
+    };
+    let token_owner = match unsafe { c_str_to_optional_pubkey(token_owner_str) } {
+        Ok(opt) => opt,
+        Err(e) => return invalid_param(-3, 1, "token_owner_str", e),
+    };
+    let key = match unsafe { c_str_to_string(idempotency_key) } {
+        Ok(key) => key,
+        Err(e) => return invalid_param(-4, 3, "idempotency_key", e),
+    };
+
+    match mint_token_idempotent(mint, token_owner, RawAmount(amount), &key) {
+        Ok(signature) => {
+            if let Err(e) =
+                unsafe { copy_string_to_buffer(&signature, signature_out, signature_len) }
+            {
+                return invalid_param(-5, 5, "signature_len", e);
+            }
+            0
+        }
+        Err(e) => operation_failed(-6, &e),
+    }
+}
+
+/// Called with the outcome of an asynchronous call and the `user_data` given with it
+///
+/// Runs on a pool thread, never on the thread that made the call. `code` is 0 on
+/// success, -5 if the operation failed and -6 if it panicked; `result_json` is the
+/// versioned response of `sss_call` and only valid during the call.
+pub type SssCompletionCallback =
+    extern "C" fn(request_id: u64, code: c_int, result_json: *const c_char, user_data: *mut c_void);
+
+/// Runs an operation on a pool thread and reports its outcome to a completion callback
+fn complete_async<T: Serialize>(
+    request_id: u64,
+    callback: SssCompletionCallback,
+    user_data: CallbackUserData,
+    operation: impl FnOnce() -> SssResult<T> + Send + 'static,
+) -> SssResult<()> {
+    submit(move || {
+        let (code, response) = match panic::catch_unwind(AssertUnwindSafe(operation)) {
+            Ok(Ok(result)) => (0, envelope(json!({ "result": result }))),
+            Ok(Err(e)) => (-5, CallError::from(e).to_json()),
+            Err(_) => {
+                let error = SssError::FfiError("The operation panicked".to_string());
+                let response = CallError {
+                    kind: "panic",
+                    ..CallError::from(error)
+                };
+                (-6, response.to_json())
+            }
+        };
+        // Response JSON never contains interior NUL bytes
+        let response = CString::new(response).unwrap_or_default();
+        callback(request_id, code, response.as_ptr(), user_data.get());
+    })
+}
+
+/// FFI function to mint tokens without blocking the calling thread
+///
+/// The mint is queued on the library's thread pool (`SSS_ASYNC_THREADS` threads, default 4)
+/// and this returns at once. The callback receives `request_id` and the signature, or the
+/// error, on a pool thread, as `sss_call("mint_token", ..)` would return it. Queued calls run in no particular order, and callbacks of
+/// different calls may run at the same time on different threads. `sss_shutdown` waits
+/// for every queued call and its callback.
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - mint_str is a valid, null-terminated C string containing a valid Solana public key
+/// - token_owner_str is either null or a valid, null-terminated C string containing a valid Solana public key
+/// - user_data stays valid, and may be used from another thread, until the callback ran
+///
+/// @param mint_str The mint to mint from
+/// @param token_owner_str The recipient wallet, or null for the payer
+/// @param amount The amount to mint, in base units
+/// @param request_id Any value identifying the call, passed back to the callback
+/// @param callback Called once with the outcome, unless queuing failed
+/// @param user_data Passed back to the callback
+/// @return 0 if the call was queued, -2/-3 for an invalid mint or owner, -4 if the queue
+///         is full (`SSS_ASYNC_QUEUE_LIMIT`, default 1024) or the library is shutting down
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_mint_token_async(
+    mint_str: *const c_char,
+    token_owner_str: *const c_char,
+    amount: u64,
+    request_id: u64,
+    callback: Option<SssCompletionCallback>,
+    user_data: *mut c_void,
+) -> c_int {
+    if let Some((index, name)) = first_null_param(&[
+        (0, "mint_str", mint_str.is_null()),
+        (4, "callback", callback.is_none()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+    let Some(callback) = callback else {
+        return -1;
+    };
+
+    let mint = match unsafe { c_str_to_pubkey(mint_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-2, 0, "mint_str", e),
+    };
+    let token_owner = match unsafe { c_str_to_optional_pubkey(token_owner_str) } {
+        Ok(opt) => opt,
+        Err(e) => return invalid_param(-3, 1, "token_owner_str", e),
+    };
+
+    let user_data = CallbackUserData(user_data);
+    match complete_async(request_id, callback, user_data, move || {
+        mint_token(mint, token_owner, RawAmount(amount))
+            .map(|signature| json!({ "signature": signature }))
+    }) {
+        Ok(()) => 0,
+        Err(e) => operation_failed(-4, &e), // Queue full or shutting down
+    }
+}
+
+/// FFI function to return the number of asynchronous calls queued or running
+///
+/// Hosts can hold back new calls while this is high instead of filling the queue.
+///
+/// @return The number of calls whose callback has not finished yet
+#[unsafe(no_mangle)]
+pub extern "C" fn sss_pending_count() -> c_int {
+    c_int::try_from(pending_count()).unwrap_or(c_int::MAX)
+}
+
+/// FFI function to allow or forbid `.sol` domains as recipients
+///
+/// When enabled, the owner of `mint_token_ffi`, the recipient of `sss_transfer_asset` and
+/// the same parameters of `sss_call` may be `.sol` domains, resolved to their owner.
+///
+/// @param enabled Non-zero to resolve domains, 0 to accept public keys only
+/// @return 0 on success
+#[unsafe(no_mangle)]
+pub extern "C" fn sss_set_ffi_domain_resolution(enabled: c_int) -> c_int {
+    set_ffi_domain_resolution(enabled != 0);
+    0 // Success
+}
+
+/// FFI function to resolve a public key or `.sol` domain to the wallet it names
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - input_str is a valid, null-terminated C string
+/// - pubkey_out is a valid pointer to a buffer of sufficient size (pubkey_len)
+///
+/// @param input_str A base58 public key or a domain such as `alice.sol`
+/// @param pubkey_out Buffer receiving the wallet address
+/// @param pubkey_len Size of pubkey_out
+/// @return 0 on success, -2 for an invalid input, -3 if pubkey_out is too small, -4 if the
+///         domain is not registered or cannot be resolved
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_resolve_recipient(
+    input_str: *const c_char,
+    pubkey_out: *mut c_char,
+    pubkey_len: c_int,
+) -> c_int {
+    if let Some((index, name)) = first_null_param(&[
+        (0, "input_str", input_str.is_null()),
+        (1, "pubkey_out", pubkey_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+    let input = match unsafe { c_str_to_string(input_str) } {
+        Ok(s) => s,
+        Err(e) => return invalid_param(-2, 0, "input_str", e),
+    };
+
+    match resolve_recipient(&input) {
+        Ok(pubkey) => {
+            if let Err(e) =
+                unsafe { copy_string_to_buffer(&pubkey.to_string(), pubkey_out, pubkey_len) }
+            {
+                return invalid_param(-3, 2, "pubkey_len", e);
+            }
+            0 // Success
+        }
+        Err(e) => operation_failed(-4, &e), // Unregistered or unresolvable domain
+    }
+}
+
+/// FFI function to write the primary `.sol` domain of a wallet, for display
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - wallet_str is a valid, null-terminated C string containing a valid Solana public key
+/// - domain_out is a valid pointer to a buffer of sufficient size (domain_len)
+///
+/// @param wallet_str The wallet whose domain is looked up
+/// @param domain_out Buffer receiving the domain, e.g. `alice.sol`, or an empty string if
+///        the wallet has none
+/// @param domain_len Size of domain_out
+/// @return 0 on success, -2 for an invalid wallet, -3 if domain_out is too small, -4 if the
+///         name service accounts cannot be read
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_primary_domain_for(
+    wallet_str: *const c_char,
+    domain_out: *mut c_char,
+    domain_len: c_int,
+) -> c_int {
+    if let Some((index, name)) = first_null_param(&[
+        (0, "wallet_str", wallet_str.is_null()),
+        (1, "domain_out", domain_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+    let wallet = match unsafe { c_str_to_pubkey(wallet_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-2, 0, "wallet_str", e),
+    };
+
+    match primary_domain_for(wallet) {
+        Ok(domain) => {
+            let domain = domain.unwrap_or_default();
+            if let Err(e) = unsafe { copy_string_to_buffer(&domain, domain_out, domain_len) } {
+                return invalid_param(-3, 2, "domain_len", e);
+            }
+            0 // Success
+        }
+        Err(e) => operation_failed(-4, &e), // Error reading the name service
+    }
+}
+
+/// FFI function to enter watch-only mode, for auditors
+///
+/// The payer becomes the given wallet, used for derivations, balances and fee reports, and
+/// no keypair is loaded from then on. Every function that would sign fails with
+/// `SSS_ERR_WATCH_ONLY`. The mode lasts until the process exits; calling this again only
+/// replaces the watched wallet.
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - pubkey_str is a valid, null-terminated C string containing a valid Solana public key
+///
+/// @param pubkey_str The wallet whose data is viewed
+/// @return 0 on success, -2 for an invalid public key, -3 if the mode cannot be recorded
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_init_watch_only(pubkey_str: *const c_char) -> c_int {
+    if pubkey_str.is_null() {
+        return null_param(-1, 0, "pubkey_str");
+    }
+    let pubkey = match unsafe { c_str_to_pubkey(pubkey_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-2, 0, "pubkey_str", e),
+    };
+
+    match init_watch_only(pubkey) {
+        Ok(()) => 0, // Success
+        Err(e) => operation_failed(-3, &e),
+    }
+}
+
+/// FFI function to get alerted when a mint's supply strays from an emission schedule
+///
+/// The supply is polled on a background thread and compared with the schedule at the
+/// cluster's time. Each anomaly is emitted as a `supply_anomaly` event and, if a callback
+/// is given, passed to it as the JSON of a `SupplyAnomaly`: the expected and actual supply,
+/// their delta, and the transactions since the previous poll that changed the supply.
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - mint_str is a valid, null-terminated C string containing a valid Solana public key
+/// - schedule_json is a valid, null-terminated C string
+/// - user_data stays valid, and may be used from another thread, until
+///   `sss_stop_supply_monitor` or `sss_shutdown` returns
+///
+/// @param mint_str The mint to monitor
+/// @param schedule_json `{"interpolation": "step" | "linear", "tolerance": <base units>,
+///        "points": [{"time": <unix seconds>, "supply": <base units>}, ...]}`
+/// @param poll_interval_ms Time between polls while the RPC node answers
+/// @param callback Called with the JSON of each anomaly, or null for events only
+/// @param user_data Passed to the callback unchanged
+/// @return A positive monitor handle on success, -2 for an invalid mint, -3 for an invalid
+///         schedule, -4 if the schedule is empty, the interval zero or the mint unreadable
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_monitor_supply(
+    mint_str: *const c_char,
+    schedule_json: *const c_char,
+    poll_interval_ms: u64,
+    callback: Option<SssEventCallback>,
+    user_data: *mut c_void,
+) -> i64 {
+    if let Some((index, name)) = first_null_param(&[
+        (0, "mint_str", mint_str.is_null()),
+        (1, "schedule_json", schedule_json.is_null()),
+    ]) {
+        return null_param(-1, index, name).into();
+    }
+    let mint = match unsafe { c_str_to_pubkey(mint_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-2, 0, "mint_str", e).into(),
+    };
+    let schedule = match unsafe { c_str_to_supply_schedule(schedule_json) } {
+        Ok(schedule) => schedule,
+        Err(e) => return invalid_param(-3, 1, "schedule_json", e).into(),
+    };
+
+    let poll_interval = Duration::from_millis(poll_interval_ms);
+    let handle = match callback {
+        Some(callback) => {
+            let user_data = CallbackUserData(user_data);
+            monitor_supply_with_callback(mint, schedule, poll_interval, move |anomaly| {
+                let Ok(json) = versioned_json(anomaly) else {
+                    return;
+                };
+                // Anomaly JSON never contains interior NUL bytes
+                if let Ok(json) = CString::new(json) {
+                    callback(json.as_ptr(), user_data.get());
+                }
+            })
+        }
+        None => monitor_supply(mint, schedule, poll_interval),
+    };
+    match handle {
+        Ok(handle) => i64::try_from(handle.id()).unwrap_or(i64::MAX),
+        Err(e) => operation_failed(-4, &e).into(), // Error starting the monitor
+    }
+}
+
+/// FFI function to stop a supply monitor
+///
+/// A callback already running may still complete after this returns.
+///
+/// @param handle The handle returned by `sss_monitor_supply`
+/// @return 0 on success, -1 if the handle is unknown
+#[unsafe(no_mangle)]
+pub extern "C" fn sss_stop_supply_monitor(handle: i64) -> c_int {
+    let stopped = u64::try_from(handle)
+        .map(stop_supply_monitor)
+        .unwrap_or(false);
+    if !stopped {
+        return operation_failed(
+            -1,
+            &SssError::FfiError(format!("Unknown supply monitor handle {}", handle)),
+        );
+    }
+    0
+}
+
+/// FFI function to mint tokens for an existing token, with the amount in whole tokens
+///
+/// Like `mint_token_ffi`, but `amount_str` is a decimal amount such as `"1.5"`, converted
+/// exactly with the mint's decimals. An amount with more decimal places than the mint has
+/// fails the mint with a token error.
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - mint_str and amount_str are valid, null-terminated C strings
+/// - token_owner_str is either null or a valid, null-terminated C string containing a valid Solana public key,
+///   or a `.sol` domain if enabled with `sss_set_ffi_domain_resolution`
+/// - signature_out is a valid pointer to a buffer of sufficient size (signature_len)
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_mint_token_ui(
+    mint_str: *const c_char,
+    token_owner_str: *const c_char,
+    amount_str: *const c_char,
+    signature_out: *mut c_char,
+    signature_len: c_int,
+) -> c_int {
+    if let Some((index, name)) = first_null_param(&[
+        (0, "mint_str", mint_str.is_null()),
+        (2, "amount_str", amount_str.is_null()),
+        (3, "signature_out", signature_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let mint = match unsafe { c_str_to_pubkey(mint_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-2, 0, "mint_str", e),
+    };
+
+    let token_owner = match unsafe { c_str_to_optional_recipient(token_owner_str) } {
+        Ok(opt) => opt,
+        Err(e) => return invalid_param(-3, 1, "token_owner_str", e),
+    };
+
+    let amount = match unsafe { c_str_to_ui_amount(amount_str) } {
+        Ok(amount) => amount,
+        Err(e) => return invalid_param(-3, 2, "amount_str", e),
+    };
+
+    match mint_token(mint, token_owner, amount) {
+        Ok(signature) => {
+            if let Err(e) =
+                unsafe { copy_string_to_buffer(&signature, signature_out, signature_len) }
+            {
+                return invalid_param(-4, 4, "signature_len", e);
+            }
+
+            0 // Success
+        }
+        Err(e) => operation_failed(-5, &e), // Error minting token
+    }
+}
+
+/// FFI function to mint tokens into an existing token account, with the amount in whole
+/// tokens
+///
+/// Like `sss_mint_token_to_account`, but `amount_str` is a decimal amount such as `"1.5"`.
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - mint_str and token_account_str are valid, null-terminated C strings containing valid Solana public keys
+/// - amount_str is a valid, null-terminated C string
+/// - signature_out is a valid pointer to a buffer of sufficient size (signature_len)
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_mint_token_to_account_ui(
+    mint_str: *const c_char,
+    token_account_str: *const c_char,
+    amount_str: *const c_char,
+    signature_out: *mut c_char,
+    signature_len: c_int,
+) -> c_int {
+    if let Some((index, name)) = first_null_param(&[
+        (0, "mint_str", mint_str.is_null()),
+        (1, "token_account_str", token_account_str.is_null()),
+        (2, "amount_str", amount_str.is_null()),
+        (3, "signature_out", signature_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let mint = match unsafe { c_str_to_pubkey(mint_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-2, 0, "mint_str", e),
+    };
+
+    let token_account = match unsafe { c_str_to_pubkey(token_account_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-3, 1, "token_account_str", e),
+    };
+
+    let amount = match unsafe { c_str_to_ui_amount(amount_str) } {
+        Ok(amount) => amount,
+        Err(e) => return invalid_param(-3, 2, "amount_str", e),
+    };
+
+    match mint_token_to_account(mint, token_account, amount) {
+        Ok(signature) => {
+            if let Err(e) =
+                unsafe { copy_string_to_buffer(&signature, signature_out, signature_len) }
+            {
+                return invalid_param(-4, 4, "signature_len", e);
+            }
+
+            0 // Success
+        }
+        Err(e) => operation_failed(-5, &e), // Error minting token
+    }
+}
+
+/// FFI function to transfer an asset held by the payer to another wallet, with the amount
+/// in whole tokens
+///
+/// Like `sss_transfer_asset`, but `amount_str` is a decimal amount such as `"1.5"`, or null
+/// to transfer the whole balance; NFTs always transfer exactly 1.
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - mint_str and to_str are valid, null-terminated C strings containing valid Solana public keys;
+///   to_str may be a `.sol` domain if enabled with `sss_set_ffi_domain_resolution`
+/// - amount_str is either null or a valid, null-terminated C string
+/// - signature_out is a valid pointer to a buffer of sufficient size (signature_len)
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_transfer_asset_ui(
+    mint_str: *const c_char,
+    to_str: *const c_char,
+    amount_str: *const c_char,
+    signature_out: *mut c_char,
+    signature_len: c_int,
+) -> c_int {
+    if let Some((index, name)) = first_null_param(&[
+        (0, "mint_str", mint_str.is_null()),
+        (1, "to_str", to_str.is_null()),
+        (3, "signature_out", signature_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let mint = match unsafe { c_str_to_pubkey(mint_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-2, 0, "mint_str", e),
+    };
+
+    let to = match unsafe { c_str_to_recipient(to_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-3, 1, "to_str", e),
+    };
+
+    let amount = if amount_str.is_null() {
+        None
+    } else {
+        match unsafe { c_str_to_ui_amount(amount_str) } {
+            Ok(amount) => Some(amount.into()),
+            Err(e) => return invalid_param(-3, 2, "amount_str", e),
+        }
+    };
+
+    let options = TransferOptions {
+        amount,
+        ..TransferOptions::default()
+    };
+    match transfer_asset_with_options(mint, to, &options) {
+        Ok(signature) => {
+            if let Err(e) =
+                unsafe { copy_string_to_buffer(&signature, signature_out, signature_len) }
+            {
+                return invalid_param(-4, 4, "signature_len", e);
+            }
+
+            0 // Success
+        }
+        Err(e) => operation_failed(-5, &e), // Error transferring the asset
+    }
+}
+
+/// FFI function to write the statistics of every in-memory cache as JSON
+///
+/// The JSON is an array of objects with `name`, `entries`, `max_entries`, `ttl_secs`,
+/// `approx_bytes`, `hits`, `misses` and `evictions`.
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that json_out is a valid pointer to a buffer of sufficient size (json_len).
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_cache_stats(json_out: *mut c_char, json_len: c_int) -> c_int {
+    if json_out.is_null() {
+        return null_param(-1, 0, "json_out");
+    }
+
+    let json = match versioned_json(&cache_stats()) {
+        Ok(j) => j,
+        Err(e) => return operation_failed(-2, &e), // Error serializing the statistics
+    };
+
+    if let Err(e) = unsafe { copy_string_to_buffer(&json, json_out, json_len) } {
+        return invalid_param(-3, 1, "json_len", e);
+    }
+
+    0 // Success
+}
+
+/// FFI function to shrink the in-memory caches when the host detects memory pressure
+///
+/// The report is written as JSON with `bytes_before`, `bytes_after` and `evicted`.
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that report_out is either null or a valid pointer to a buffer of
+/// sufficient size (report_len).
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_trim_caches(
+    target_bytes: u64,
+    report_out: *mut c_char,
+    report_len: c_int,
+) -> c_int {
+    let report = trim_caches(usize::try_from(target_bytes).unwrap_or(usize::MAX));
+    if report_out.is_null() {
+        return 0;
+    }
+
+    let json = match versioned_json(&report) {
+        Ok(j) => j,
+        Err(e) => return operation_failed(-2, &e), // Error serializing the report
+    };
+
+    if let Err(e) = unsafe { copy_string_to_buffer(&json, report_out, report_len) } {
+        return invalid_param(-3, 2, "report_len", e);
+    }
+
+    0 // Success
+}
+
+/// FFI function to mint a test token to a wallet within daily faucet caps, writing the
+/// outcome as JSON
+///
+/// Only exported when the library is built with the `dev-tools` feature; mainnet-beta is
+/// refused. The outcome has `status` `granted`, with `signature`, `recipient`, `amount`,
+/// `recipient_total` and `global_total`, or `throttled`, with `limit` (`recipient` or
+/// `global`), `granted_today`, `cap` and `resets_at` in Unix seconds. A throttled request
+/// still returns 0.
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - mint_str and recipient_str are valid, null-terminated C strings containing valid Solana public keys
+/// - outcome_out is a valid pointer to a buffer of sufficient size (outcome_len)
+#[cfg(feature = "dev-tools")]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_faucet_grant(
+    mint_str: *const c_char,
+    recipient_str: *const c_char,
+    amount: u64,
+    per_recipient_daily: u64,
+    global_daily: u64,
+    outcome_out: *mut c_char,
+    outcome_len: c_int,
+) -> c_int {
+    if let Some((index, name)) = first_null_param(&[
+        (0, "mint_str", mint_str.is_null()),
+        (1, "recipient_str", recipient_str.is_null()),
+        (5, "outcome_out", outcome_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let mint = match unsafe { c_str_to_pubkey(mint_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-2, 0, "mint_str", e),
+    };
+
+    let recipient = match unsafe { c_str_to_pubkey(recipient_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-3, 1, "recipient_str", e),
+    };
+
+    let policy = FaucetPolicy {
+        per_recipient_daily,
+        global_daily,
+    };
+    let json = match faucet_grant(mint, recipient, amount, policy)
+        .and_then(|outcome| versioned_json(&outcome))
+    {
+        Ok(json) => json,
+        Err(e) => return operation_failed(-5, &e), // Error granting from the faucet
+    };
+
+    if let Err(e) = unsafe { copy_string_to_buffer(&json, outcome_out, outcome_len) } {
+        return invalid_param(-4, 6, "outcome_len", e);
+    }
+
+    0 // Success
+}
+
+/// FFI function to render a printable receipt of a confirmed transaction
+///
+/// `format` is 0 for plain text laid out for a receipt printer and 1 for an HTML fragment.
+/// The header, footer, line width and explorer link come from `SSS_RECEIPT_HEADER`,
+/// `SSS_RECEIPT_FOOTER`, `SSS_RECEIPT_WIDTH` and `SSS_EXPLORER_URL`.
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - signature_str is a valid, null-terminated C string containing a base58 signature
+/// - payload_out is a valid pointer to a buffer of sufficient size (payload_len)
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_render_receipt(
+    signature_str: *const c_char,
+    format: c_int,
+    payload_out: *mut c_char,
+    payload_len: c_int,
+) -> c_int {
+    if let Some((index, name)) = first_null_param(&[
+        (0, "signature_str", signature_str.is_null()),
+        (2, "payload_out", payload_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let signature = match unsafe { c_str_to_signature(signature_str) } {
+        Ok(s) => s,
+        Err(e) => return invalid_param(-2, 0, "signature_str", e),
+    };
+
+    let format = match format {
+        0 => ReceiptFormat::Text,
+        1 => ReceiptFormat::Html,
+        other => {
+            return invalid_param(
+                -3,
+                1,
+                "format",
+                SssError::FfiError(format!("Unknown receipt format {}, expected 0 or 1", other)),
+            );
+        }
+    };
+
+    let payload = match fetch_transaction_receipt(&signature.to_string())
+        .and_then(|receipt| render_receipt_payload(&receipt, ReceiptTemplate::from_env(format)))
+    {
+        Ok(payload) => payload,
+        Err(e) => return operation_failed(-5, &e), // Error fetching or rendering the receipt
+    };
+
+    if let Err(e) = unsafe { copy_string_to_buffer(&payload, payload_out, payload_len) } {
+        return invalid_param(-4, 3, "payload_len", e);
+    }
+
+    0 // Success
+}
+
+/// FFI function to report what this build of the library supports
+///
+/// Writes the `Capabilities` as JSON: the library version, the FFI schema version, the
+/// permissions of the calling thread's role and a `true`/`false` flag per capability, so a
+/// host can branch on what the loaded binary has and hide what the role may not do.
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that json_out is a valid pointer to a buffer of sufficient size
+/// (json_len).
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_capabilities(json_out: *mut c_char, json_len: c_int) -> c_int {
+    if json_out.is_null() {
+        return null_param(-1, 0, "json_out");
+    }
+
+    let json = match versioned_json(&capabilities()) {
+        Ok(json) => json,
+        Err(e) => return operation_failed(-2, &e), // Error serializing the capabilities
+    };
+
+    if let Err(e) = unsafe { copy_string_to_buffer(&json, json_out, json_len) } {
+        return invalid_param(-3, 1, "json_len", e);
+    }
+
+    0 // Success
+}
+
+/// FFI function to resolve the transactions an earlier run sent but never saw confirmed
+///
+/// Call at startup. The outcomes are written as a JSON array of objects with the journal
+/// `entry` in its new state and the decoded `receipt` of transactions that landed; each
+/// resolved transaction also emits an `in_flight_recovered` event.
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that json_out is a valid pointer to a buffer of sufficient size
+/// (json_len).
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_recover_in_flight(json_out: *mut c_char, json_len: c_int) -> c_int {
+    if json_out.is_null() {
+        return null_param(-1, 0, "json_out");
+    }
+
+    let json = match recover_in_flight().and_then(|outcomes| versioned_json(&outcomes)) {
+        Ok(json) => json,
+        Err(e) => return operation_failed(-2, &e), // Error recovering the transactions
+    };
+
+    if let Err(e) = unsafe { copy_string_to_buffer(&json, json_out, json_len) } {
+        return invalid_param(-3, 1, "json_len", e);
+    }
+
+    0 // Success
+}
+
+/// FFI function to shorten an address to its first and last characters around `…`
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - pubkey_str is a valid, null-terminated C string containing a valid Solana public key
+/// - short_out is a valid pointer to a buffer of sufficient size (short_len)
+///
+/// @param pubkey_str The base58 address to shorten
+/// @param head The number of leading characters to keep, 4 in the apps
+/// @param tail The number of trailing characters to keep, 4 in the apps
+/// @param short_out A buffer to receive the UTF-8 shortened address, e.g. "DZ2D…NGRM"
+/// @param short_len The size of the buffer
+/// @return 0 on success, negative error code on failure
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_short_pubkey(
+    pubkey_str: *const c_char,
+    head: c_int,
+    tail: c_int,
+    short_out: *mut c_char,
+    short_len: c_int,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "pubkey_str", pubkey_str.is_null()),
+        (3, "short_out", short_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let pubkey = match unsafe { c_str_to_pubkey(pubkey_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-2, 0, "pubkey_str", e),
+    };
+
+    let (head, tail) = match (usize::try_from(head), usize::try_from(tail)) {
+        (Ok(head), Ok(tail)) => (head, tail),
+        (Err(_), _) => {
+            let e = SssError::FfiError(format!("head must not be negative, got {}", head));
+            return invalid_param(-3, 1, "head", e);
+        }
+        (_, Err(_)) => {
+            let e = SssError::FfiError(format!("tail must not be negative, got {}", tail));
+            return invalid_param(-3, 2, "tail", e);
+        }
+    };
+
+    let short = short_pubkey(&pubkey, head, tail);
+    if let Err(e) = unsafe { copy_string_to_buffer(&short, short_out, short_len) } {
+        return invalid_param(-4, 4, "short_len", e);
+    }
+
+    0 // Success
+}
+
+/// FFI function to derive the RGB avatar color of an address
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - pubkey_str is a valid, null-terminated C string containing a valid Solana public key
+/// - rgb_out points to at least 3 writable bytes
+///
+/// @param pubkey_str The base58 address
+/// @param rgb_out Receives the red, green and blue components
+/// @return 0 on success, negative error code on failure
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_pubkey_color_hash(
+    pubkey_str: *const c_char,
+    rgb_out: *mut u8,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "pubkey_str", pubkey_str.is_null()),
+        (1, "rgb_out", rgb_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let pubkey = match unsafe { c_str_to_pubkey(pubkey_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-2, 0, "pubkey_str", e),
+    };
+
+    let rgb = pubkey_color_hash(&pubkey);
+    unsafe { std::ptr::copy_nonoverlapping(rgb.as_ptr(), rgb_out, rgb.len()) };
+
+    0 // Success
+}
+
+/// FFI function to build the `solana:` URI to show as a QR code for receiving at an address
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - pubkey_str is a valid, null-terminated C string containing a valid Solana public key
+/// - amount_str is null or a valid, null-terminated C string
+/// - mint_str is null or a valid, null-terminated C string containing a valid Solana public key
+/// - uri_out is a valid pointer to a buffer of sufficient size (uri_len)
+///
+/// @param pubkey_str The base58 address of the receiving wallet
+/// @param amount_str The amount in whole tokens, e.g. "1.5", or NULL to let the payer choose
+/// @param mint_str The base58 address of the SPL token to receive, or NULL for SOL
+/// @param uri_out A buffer to receive the URI
+/// @param uri_len The size of the URI buffer
+/// @return 0 on success, negative error code on failure
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_qr_payload_for_address(
+    pubkey_str: *const c_char,
+    amount_str: *const c_char,
+    mint_str: *const c_char,
+    uri_out: *mut c_char,
+    uri_len: c_int,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "pubkey_str", pubkey_str.is_null()),
+        (3, "uri_out", uri_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let pubkey = match unsafe { c_str_to_pubkey(pubkey_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-2, 0, "pubkey_str", e),
+    };
+
+    let amount = match unsafe { c_str_to_optional_string(amount_str) } {
+        Ok(s) => s,
+        Err(e) => return invalid_param(-3, 1, "amount_str", e),
+    };
+
+    let mint = match unsafe { c_str_to_optional_pubkey(mint_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-4, 2, "mint_str", e),
+    };
+
+    let uri = match qr_payload_for_address(&pubkey, amount.as_deref(), mint.as_ref()) {
+        Ok(uri) => uri,
+        Err(e) => return invalid_param(-3, 1, "amount_str", e),
+    };
+
+    if let Err(e) = unsafe { copy_string_to_buffer(&uri, uri_out, uri_len) } {
+        return invalid_param(-5, 4, "uri_len", e);
+    }
+
+    0 // Success
+}
+
+/// FFI function to read a Solana Pay transfer request
+///
+/// Writes a JSON object with `recipient`, `amount` (whole tokens, or null), `spl_token`
+/// (or null for SOL), the `references` array, `label`, `message` and `memo`. URIs written
+/// by wallets such as Phantom are accepted.
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - uri_str is a valid, null-terminated C string
+/// - json_out is a valid pointer to a buffer of sufficient size (json_len)
+///
+/// @param uri_str The `solana:` URI, e.g. decoded from a QR code
+/// @param json_out A buffer to receive the request as JSON
+/// @param json_len The size of the JSON buffer
+/// @return 0 on success, negative error code on failure
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_parse_solana_uri(
+    uri_str: *const c_char,
+    json_out: *mut c_char,
+    json_len: c_int,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "uri_str", uri_str.is_null()),
+        (1, "json_out", json_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let uri = match unsafe { c_str_to_string(uri_str) } {
+        Ok(s) => s,
+        Err(e) => return invalid_param(-2, 0, "uri_str", e),
+    };
+
+    let request = match parse_solana_uri(&uri) {
+        Ok(request) => request,
+        Err(e) => return invalid_param(-2, 0, "uri_str", e),
+    };
+
+    let json = match versioned_json(&request) {
+        Ok(json) => json,
+        Err(e) => return operation_failed(-4, &e), // Error serializing the request
+    };
+
+    if let Err(e) = unsafe { copy_string_to_buffer(&json, json_out, json_len) } {
+        return invalid_param(-3, 2, "json_len", e);
+    }
+
+    0 // Success
+}
+
+/// FFI function to set the capacity and overflow policy of a callback queue
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that queue and overflow are valid, null-terminated C strings.
+///
+/// @param queue "events" or "balance_callbacks"
+/// @param capacity The most items queued at once, at least 1
+/// @param overflow "drop_oldest", "drop_newest" or "block"
+/// @param block_timeout_ms How long "block" waits for room, ignored otherwise
+/// @return 0 on success, negative error code on failure
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_set_callback_queue_options(
+    queue: *const c_char,
+    capacity: c_int,
+    overflow: *const c_char,
+    block_timeout_ms: u64,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "queue", queue.is_null()),
+        (2, "overflow", overflow.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let queue = match unsafe { c_str_to_string(queue) }
+        .and_then(|name| CallbackQueue::from_name(&name).map_err(Into::into))
+    {
+        Ok(queue) => queue,
+        Err(e) => return invalid_param(-2, 0, "queue", e),
+    };
+
+    let capacity = match usize::try_from(capacity) {
+        Ok(capacity) if capacity > 0 => capacity,
+        _ => {
+            let e = SssError::FfiError(format!("capacity must be at least 1, got {}", capacity));
+            return invalid_param(-3, 1, "capacity", e);
+        }
+    };
+
+    let timeout = Duration::from_millis(block_timeout_ms);
+    let overflow = match unsafe { c_str_to_string(overflow) }
+        .and_then(|name| OverflowPolicy::from_name(&name, timeout).map_err(Into::into))
+    {
+        Ok(overflow) => overflow,
+        Err(e) => return invalid_param(-4, 2, "overflow", e),
+    };
+
+    match set_callback_queue_options(queue, QueueOptions { capacity, overflow }) {
+        Ok(()) => 0,                                   // Success
+        Err(e) => invalid_param(-3, 1, "capacity", e), // Rejected capacity
+    }
+}
+
+/// FFI function to sign structured data, such as a coupon, with a signer role
+///
+/// Writes the JSON of a `StructuredSignature`: the `signer`, `role`, base58 `signature`
+/// and hex `digest`.
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - domain_json and payload_json are valid, null-terminated C strings
+/// - role is null or a valid, null-terminated C string
+/// - signature_out is a valid pointer to a buffer of sufficient size (signature_len)
+///
+/// @param domain_json The signing domain, `{"name": ..., "version": ...}` with optional
+///        `cluster` and `salt`
+/// @param payload_json The data to sign as JSON
+/// @param role The profile whose payer signs, or NULL for the active one
+/// @param signature_out A buffer to receive the signature JSON
+/// @param signature_len The size of the buffer
+/// @return 0 on success, negative error code on failure
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_sign_structured(
+    domain_json: *const c_char,
+    payload_json: *const c_char,
+    role: *const c_char,
+    signature_out: *mut c_char,
+    signature_len: c_int,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "domain_json", domain_json.is_null()),
+        (1, "payload_json", payload_json.is_null()),
+        (3, "signature_out", signature_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let domain = match unsafe { c_str_to_signing_domain(domain_json) } {
+        Ok(domain) => domain,
+        Err(e) => return invalid_param(-2, 0, "domain_json", e),
+    };
+
+    let payload = match unsafe { c_str_to_string(payload_json) } {
+        Ok(s) => s,
+        Err(e) => return invalid_param(-3, 1, "payload_json", e),
+    };
+
+    let role = match unsafe { c_str_to_optional_string(role) } {
+        Ok(s) => s,
+        Err(e) => return invalid_param(-4, 2, "role", e),
+    };
+
+    let signed = match role {
+        Some(role) => with_profile(&role, || sign_structured_json(&domain, &payload)),
+        None => sign_structured_json(&domain, &payload),
+    };
+    let json = match signed.and_then(|signature| versioned_json(&signature)) {
+        Ok(json) => json,
+        Err(e) => return operation_failed(-6, &e), // Error encoding or signing the payload
+    };
+
+    if let Err(e) = unsafe { copy_string_to_buffer(&json, signature_out, signature_len) } {
+        return invalid_param(-5, 4, "signature_len", e);
+    }
+
+    0 // Success
+}
+
+/// FFI function to verify a structured signature
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that domain_json, payload_json, signature_b58 and signer_str are
+/// valid, null-terminated C strings.
+///
+/// @param domain_json The signing domain the signature must be for
+/// @param payload_json The signed data as JSON, in any formatting
+/// @param signature_b58 The base58 signature to check
+/// @param signer_str The base58 key that must have signed
+/// @return 1 if the signature is valid, 0 if it is not, negative error code on failure
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_verify_structured(
+    domain_json: *const c_char,
+    payload_json: *const c_char,
+    signature_b58: *const c_char,
+    signer_str: *const c_char,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "domain_json", domain_json.is_null()),
+        (1, "payload_json", payload_json.is_null()),
+        (2, "signature_b58", signature_b58.is_null()),
+        (3, "signer_str", signer_str.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let domain = match unsafe { c_str_to_signing_domain(domain_json) } {
+        Ok(domain) => domain,
+        Err(e) => return invalid_param(-2, 0, "domain_json", e),
+    };
+
+    let payload = match unsafe { c_str_to_string(payload_json) } {
+        Ok(s) => s,
+        Err(e) => return invalid_param(-3, 1, "payload_json", e),
+    };
+
+    let signature = match unsafe { c_str_to_signature(signature_b58) } {
+        Ok(s) => s,
+        Err(e) => return invalid_param(-4, 2, "signature_b58", e),
+    };
+
+    let signer = match unsafe { c_str_to_pubkey(signer_str) } {
+        Ok(p) => p,
+        Err(e) => return invalid_param(-5, 3, "signer_str", e),
+    };
+
+    match verify_structured(&domain, &payload, &signature, &signer) {
+        Ok(valid) => valid as c_int,
+        Err(e) => invalid_param(-3, 1, "payload_json", e), // Payload cannot be encoded
+    }
+}
+
+/// FFI function to give plain spl-token mints Metaplex metadata, resuming from a checkpoint
+/// file
+///
+/// The report is written as JSON with a `mints` array of objects with `mint`, `status`
+/// (`migrated`, `inconsistent`, `already_migrated`, `not_mint_authority` with `current`,
+/// `has_metadata`, or `failed` with `error`), `metadata_signature`, `rotations` and
+/// `audit`. Mints that are skipped or fail do not make the call fail; they are listed in
+/// the report.
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - plan_json is a valid, null-terminated C string containing a JSON array of
+///   `{"mint", "name", "symbol", "uri", "freeze_authority", "new_authority"}` objects,
+///   the two authorities being optional
+/// - checkpoint_path is a valid, null-terminated C string
+/// - report_out is a valid pointer to a buffer of sufficient size (report_len)
+///
+/// @return 0 on success, -2 for an invalid plan, -3 for an invalid checkpoint path, -4 if
+///         the buffer is too small, -5 if the migration could not run, e.g. because the
+///         checkpoint cannot be written
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_migrate_legacy_mints(
+    plan_json: *const c_char,
+    checkpoint_path: *const c_char,
+    report_out: *mut c_char,
+    report_len: c_int,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "plan_json", plan_json.is_null()),
+        (1, "checkpoint_path", checkpoint_path.is_null()),
+        (2, "report_out", report_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let plan = match unsafe { c_str_to_migration_plan(plan_json) } {
+        Ok(plan) => plan,
+        Err(e) => return invalid_param(-2, 0, "plan_json", e),
+    };
+
+    let checkpoint_path = match unsafe { c_str_to_string(checkpoint_path) } {
+        Ok(s) => s,
+        Err(e) => return invalid_param(-3, 1, "checkpoint_path", e),
+    };
+
+    let report = match migrate_legacy_mints(&plan, Path::new(&checkpoint_path))
+        .and_then(|report| versioned_json(&report))
+    {
+        Ok(report) => report,
+        Err(e) => return operation_failed(-5, &e), // Error running the migration
+    };
+
+    // Copy the report to the output buffer
+    if let Err(e) = unsafe { copy_string_to_buffer(&report, report_out, report_len) } {
+        return invalid_param(-4, 3, "report_len", e);
+    }
+
+    0 // Success
+}
+
+/// FFI function to return the version of the JSON schema the library writes
+///
+/// Every JSON payload is wrapped as `{"v": <version>, "data": ...}`. Within a version,
+/// payloads only gain fields, so a host can compare this with the version it was built
+/// against before reading any.
+#[unsafe(no_mangle)]
+pub extern "C" fn sss_ffi_schema_version() -> c_int {
+    FFI_SCHEMA_VERSION
+}
+
+/// Retrieves details of the last failed FFI call made on the calling thread
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that error_out is a valid pointer to an `SssFfiError`.
+///
+/// @param error_out A pointer to a struct that receives the error details
+/// @return 0 if an error was written, 1 if no error has been recorded on this thread, -1 if error_out is null
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_get_last_error(error_out: *mut SssFfiError) -> c_int {
+    if error_out.is_null() {
+        return -1;
+    }
+
+    match last_error() {
+        Some(error) => {
+            unsafe { *error_out = error };
+            0
+        }
+        None => 1,
+    }
+}
+
+/// FFI function to call a library function by name with JSON parameters
+///
+/// Writes `{"result": ...}` on success and `{"error": {"kind", "message", "field"}}` on
+/// failure, where `field` names the rejected parameter of an `invalid_params` error. The
+/// methods and their parameters are listed in the README.
+///
+/// If the response does not fit, nothing is written and -4 is returned; the operation has
+/// already run, so size the buffer generously rather than repeating a mutation.
+///
+/// # Safety
+///
+/// This function is unsafe because it works with raw pointers for C interoperability.
+/// The caller must ensure that:
+/// - method is a valid, null-terminated C string
+/// - params_json is null or a valid, null-terminated C string
+/// - result_out is a valid pointer to a buffer of sufficient size (result_len)
+/// - required_len_out is null or a valid pointer to an int
+///
+/// @param method The name of the method, e.g. "mint_token"
+/// @param params_json A JSON object of the method's parameters, or NULL for none
+/// @param result_out A buffer to receive the JSON response
+/// @param result_len The size of the response buffer
+/// @param required_len_out Receives the size the response needs, including the null terminator, or NULL
+/// @return 0 on success, -2 for an unknown method, -3 for invalid parameters, -4 if the
+///         buffer is too small, -5 if the operation failed, -6 if it panicked
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sss_call(
+    method: *const c_char,
+    params_json: *const c_char,
+    result_out: *mut c_char,
+    result_len: c_int,
+    required_len_out: *mut c_int,
+) -> c_int {
+    // Check for null pointers
+    if let Some((index, name)) = first_null_param(&[
+        (0, "method", method.is_null()),
+        (2, "result_out", result_out.is_null()),
+    ]) {
+        return null_param(-1, index, name);
+    }
+
+    let (code, response) = match panic::catch_unwind(AssertUnwindSafe(|| unsafe {
+        call_response(method, params_json)
+    })) {
+        Ok(outcome) => outcome,
+        Err(_) => {
+            let error = SssError::FfiError("The method panicked".to_string());
+            let response = CallError {
+                kind: "panic",
+                ..CallError::from(error.clone())
+            };
+            (operation_failed(-6, &error), response.to_json())
+        }
+    };
+
+    if !required_len_out.is_null() {
+        let required_len = c_int::try_from(response.len() + 1).unwrap_or(c_int::MAX);
+        unsafe { *required_len_out = required_len };
+    }
+    if let Err(e) = unsafe { copy_string_to_buffer(&response, result_out, result_len) } {
+        return invalid_param(-4, 3, "result_len", e);
+    }
+
+    code
+}
+
+/// Runs a call of `sss_call`, returning its code and JSON response
+///
+/// # Safety
+///
+/// The method must be a valid C string and the parameters null or a valid C string
+unsafe fn call_response(method: *const c_char, params_json: *const c_char) -> (c_int, String) {
+    let method = match unsafe { c_str_to_string(method) } {
+        Ok(m) => m,
+        Err(e) => {
+            let response = CallError::unknown_method(format!("Invalid method name: {}", e));
+            return (invalid_param(-2, 0, "method", e), response.to_json());
+        }
+    };
+
+    let params = match unsafe { c_str_to_optional_string(params_json) } {
+        Ok(p) => p.unwrap_or_else(|| "{}".to_string()),
+        Err(e) => {
+            let response = CallError::invalid_params(format!("Invalid params: {}", e), None);
+            return (invalid_param(-3, 1, "params_json", e), response.to_json());
+        }
+    };
+
+    match call(&method, &params) {
+        Ok(response) => (0, response),
+        Err(error) => {
+            let code = match (&error.source, error.kind) {
+                (Some(source), _) => operation_failed(-5, source),
+                (None, "unknown_method") => invalid_param(
+                    -2,
+                    0,
+                    "method",
+                    InputError {
+                        reason: error.message.clone(),
+                        byte_offset: None,
+                    },
+                ),
+                (None, _) => invalid_param(
+                    -3,
+                    1,
+                    "params_json",
+                    InputError {
+                        reason: error.message.clone(),
+                        byte_offset: error.byte_offset,
+                    },
+                ),
+            };
+            (code, error.to_json())
+        }
     }
 }
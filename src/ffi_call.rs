@@ -0,0 +1,660 @@
+//! Methods callable by name through `sss_call`
+//!
+//! Every method takes a JSON object of named parameters and produces a JSON result, so a
+//! new capability only needs an entry in [`METHODS`] instead of a dedicated FFI function.
+
+use crate::amount::{FormatOptions, RawAmount, format_token_amount, parse_token_amount};
+use crate::error::SssError;
+use crate::ffi_schema::envelope;
+use crate::metrics::metrics_snapshot;
+use crate::payer::{get_payer_pubkey, payer_info};
+use crate::payment::{create_payment_request, generate_payment_reference};
+use crate::receipt::fetch_transaction_receipt;
+use crate::sns::{ffi_domain_resolution, is_sol_domain, resolve_recipient};
+use crate::token::{check_recipient, create_new_token, mint_token};
+use crate::transfer::transfer_asset;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::error::Category;
+use serde_json::{Value, json};
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+
+/// A method callable through `sss_call`, taking its parameters as JSON
+type Method = fn(&str) -> Result<Value, CallError>;
+
+/// The methods callable through `sss_call`, by name
+pub(crate) const METHODS: &[(&str, Method)] = &[
+    ("create_token", call_create_token),
+    ("mint_token", call_mint_token),
+    ("transfer_asset", call_transfer_asset),
+    ("get_balance", call_get_balance),
+    ("get_payer_info", call_get_payer_info),
+    ("fetch_transaction_receipt", call_fetch_transaction_receipt),
+    ("create_payment_request", call_create_payment_request),
+    ("format_token_amount", call_format_token_amount),
+    ("parse_token_amount", call_parse_token_amount),
+    ("metrics", call_metrics),
+];
+
+/// Why a call failed, written as the `error` object of the response
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct CallError {
+    /// `unknown_method`, `invalid_params`, `panic`, or the [`SssError::kind`] of a failed
+    /// operation
+    pub kind: &'static str,
+    /// What went wrong
+    pub message: String,
+    /// The parameter that was rejected, for `invalid_params`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    /// Byte offset within the parameters JSON where parsing stopped, if known
+    #[serde(skip)]
+    pub byte_offset: Option<usize>,
+    /// The error of a failed operation
+    #[serde(skip)]
+    pub source: Option<Box<SssError>>,
+}
+
+impl CallError {
+    /// An error about the parameters, optionally naming the rejected field
+    pub(crate) fn invalid_params(message: impl Into<String>, field: Option<&str>) -> Self {
+        Self {
+            kind: "invalid_params",
+            message: message.into(),
+            field: field.map(str::to_string),
+            byte_offset: None,
+            source: None,
+        }
+    }
+
+    /// An error about the method name
+    pub(crate) fn unknown_method(message: impl Into<String>) -> Self {
+        Self {
+            kind: "unknown_method",
+            ..Self::invalid_params(message, None)
+        }
+    }
+
+    /// Serializes the error as a versioned response object
+    pub(crate) fn to_json(&self) -> String {
+        envelope(json!({ "error": self }))
+    }
+}
+
+impl From<SssError> for CallError {
+    fn from(e: SssError) -> Self {
+        Self {
+            kind: e.kind(),
+            message: e.to_string(),
+            field: None,
+            byte_offset: None,
+            source: Some(Box::new(e)),
+        }
+    }
+}
+
+/// Calls a method by name
+///
+/// # Arguments
+///
+/// * `method` - The name of the method, see [`METHODS`]
+/// * `params` - The JSON parameters of the method
+///
+/// # Returns
+///
+/// The versioned response object, `{"v": 2, "data": {"result": ...}}`
+///
+/// # Errors
+///
+/// Returns an `unknown_method` error if no method has that name, an `invalid_params` error
+/// if the parameters do not match the method, and otherwise the error of the operation
+pub(crate) fn call(method: &str, params: &str) -> Result<String, CallError> {
+    let (_, run) = METHODS
+        .iter()
+        .find(|(name, _)| *name == method)
+        .ok_or_else(|| {
+            let names: Vec<_> = METHODS.iter().map(|(name, _)| *name).collect();
+            CallError::unknown_method(format!(
+                "Unknown method '{}', expected one of: {}",
+                method,
+                names.join(", ")
+            ))
+        })?;
+    let result = run(params)?;
+    Ok(envelope(json!({ "result": result })))
+}
+
+/// Parses the parameters of a method, naming the offending field on failure
+fn parse<P: DeserializeOwned>(params: &str) -> Result<P, CallError> {
+    serde_json::from_str(params).map_err(|e| {
+        let offset = byte_offset(params, e.line(), e.column());
+        let field = match e.classify() {
+            Category::Data => named_field(&e.to_string())
+                .or_else(|| offset.and_then(|offset| field_before(params, offset))),
+            _ => None,
+        };
+        let message = match &field {
+            Some(field) => format!("Invalid parameter '{}': {}", field, e),
+            None => format!("Invalid params: {}", e),
+        };
+        CallError {
+            byte_offset: offset,
+            ..CallError::invalid_params(message, field.as_deref())
+        }
+    })
+}
+
+/// Serializes the result of a method
+fn respond<R: Serialize>(result: R) -> Result<Value, CallError> {
+    serde_json::to_value(result)
+        .map_err(|e| SssError::FfiError(format!("Failed to serialize result: {}", e)).into())
+}
+
+/// Parses a base58 public key parameter
+fn pubkey(field: &str, value: &str) -> Result<Pubkey, CallError> {
+    Pubkey::from_str(value).map_err(|e| {
+        CallError::invalid_params(
+            format!("Invalid parameter '{}': invalid public key: {}", field, e),
+            Some(field),
+        )
+    })
+}
+
+/// Parses an optional base58 public key parameter
+fn optional_pubkey(field: &str, value: Option<&str>) -> Result<Option<Pubkey>, CallError> {
+    value.map(|value| pubkey(field, value)).transpose()
+}
+
+/// Parses a recipient parameter, resolving `.sol` domains if enabled for the FFI
+fn recipient(field: &str, value: &str) -> Result<Pubkey, CallError> {
+    if ffi_domain_resolution() && is_sol_domain(value) {
+        return resolve_recipient(value).map_err(|e| {
+            CallError::invalid_params(format!("Invalid parameter '{}': {}", field, e), Some(field))
+        });
+    }
+    pubkey(field, value)
+}
+
+/// Returns the field named by a serde "missing field" or "unknown field" message
+fn named_field(message: &str) -> Option<String> {
+    let rest = message
+        .strip_prefix("missing field `")
+        .or_else(|| message.strip_prefix("unknown field `"))
+        .or_else(|| message.strip_prefix("duplicate field `"))?;
+    rest.split_once('`').map(|(field, _)| field.to_string())
+}
+
+/// Converts a 1-based line and column of a serde error to a byte offset
+fn byte_offset(json: &str, line: usize, column: usize) -> Option<usize> {
+    if line == 0 {
+        return None;
+    }
+    let line_start: usize = json
+        .split_inclusive('\n')
+        .take(line - 1)
+        .map(str::len)
+        .sum();
+    Some((line_start + column).min(json.len()))
+}
+
+/// Returns the last key of the top-level object before a byte offset
+fn field_before(json: &str, offset: usize) -> Option<String> {
+    let bytes = &json.as_bytes()[..offset.min(json.len())];
+    let mut depth = 0usize;
+    let mut in_object = false;
+    let mut expecting_key = false;
+    let mut string_start = None;
+    let mut escaped = false;
+    let mut field = None;
+    for (i, &byte) in bytes.iter().enumerate() {
+        if let Some(start) = string_start {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => {
+                    if depth == 1 && in_object && expecting_key {
+                        field = json.get(start..i).map(str::to_string);
+                        expecting_key = false;
+                    }
+                    string_start = None;
+                }
+                _ => {}
+            }
+            continue;
+        }
+        match byte {
+            b'"' => string_start = Some(i + 1),
+            b'{' | b'[' => {
+                depth += 1;
+                if depth == 1 {
+                    in_object = byte == b'{';
+                    expecting_key = in_object;
+                }
+            }
+            b'}' | b']' => depth = depth.saturating_sub(1),
+            b',' if depth == 1 => expecting_key = in_object,
+            _ => {}
+        }
+    }
+    field
+}
+
+/// Parameters of a method taking none
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NoParams {}
+
+/// Parameters of `create_token`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CreateTokenParams {
+    uri: String,
+    name: String,
+    decimals: u8,
+}
+
+/// Calls [`create_new_token`]
+fn call_create_token(params: &str) -> Result<Value, CallError> {
+    let params: CreateTokenParams = parse(params)?;
+    let (signature, mint) = create_new_token(&params.uri, &params.name, params.decimals)?;
+    respond(json!({ "signature": signature, "mint": mint.to_string() }))
+}
+
+/// Parameters of `mint_token`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MintTokenParams {
+    mint: String,
+    /// The receiving wallet, the payer if omitted
+    owner: Option<String>,
+    amount: u64,
+}
+
+/// Calls [`mint_token`]
+fn call_mint_token(params: &str) -> Result<Value, CallError> {
+    let params: MintTokenParams = parse(params)?;
+    let mint = pubkey("mint", &params.mint)?;
+    let owner = params
+        .owner
+        .as_deref()
+        .map(|owner| recipient("owner", owner))
+        .transpose()?;
+    let signature = mint_token(mint, owner, RawAmount(params.amount))?;
+    respond(json!({ "signature": signature }))
+}
+
+/// Parameters of `transfer_asset`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TransferAssetParams {
+    mint: String,
+    to: String,
+}
+
+/// Calls [`transfer_asset`]
+fn call_transfer_asset(params: &str) -> Result<Value, CallError> {
+    let params: TransferAssetParams = parse(params)?;
+    let mint = pubkey("mint", &params.mint)?;
+    let to = recipient("to", &params.to)?;
+    let signature = transfer_asset(mint, to)?;
+    respond(json!({ "signature": signature }))
+}
+
+/// Parameters of `get_balance`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GetBalanceParams {
+    mint: String,
+    /// The wallet whose balance is read, the payer if omitted
+    owner: Option<String>,
+}
+
+/// Calls [`check_recipient`] for the owner, the payer by default
+fn call_get_balance(params: &str) -> Result<Value, CallError> {
+    let params: GetBalanceParams = parse(params)?;
+    let mint = pubkey("mint", &params.mint)?;
+    let owner = match optional_pubkey("owner", params.owner.as_deref())? {
+        Some(owner) => owner,
+        None => get_payer_pubkey()?,
+    };
+    let status = check_recipient(mint, owner)?;
+    respond(json!({
+        "token_account": status.token_account.to_string(),
+        "account_exists": status.account_exists,
+        "frozen": status.frozen,
+        "balance": status.balance,
+    }))
+}
+
+/// Calls [`payer_info`]
+fn call_get_payer_info(params: &str) -> Result<Value, CallError> {
+    let NoParams {} = parse(params)?;
+    respond(payer_info()?)
+}
+
+/// Parameters of `fetch_transaction_receipt`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReceiptParams {
+    signature: String,
+}
+
+/// Calls [`fetch_transaction_receipt`]
+fn call_fetch_transaction_receipt(params: &str) -> Result<Value, CallError> {
+    let params: ReceiptParams = parse(params)?;
+    respond(fetch_transaction_receipt(&params.signature)?)
+}
+
+/// Parameters of `create_payment_request`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PaymentRequestParams {
+    amount: u64,
+    spl_mint: Option<String>,
+    /// The reference key, a new one if omitted
+    reference: Option<String>,
+    label: Option<String>,
+    message: Option<String>,
+}
+
+/// Calls [`create_payment_request`], with a new reference key unless one is given
+fn call_create_payment_request(params: &str) -> Result<Value, CallError> {
+    let params: PaymentRequestParams = parse(params)?;
+    let spl_mint = optional_pubkey("spl_mint", params.spl_mint.as_deref())?;
+    let reference = optional_pubkey("reference", params.reference.as_deref())?
+        .unwrap_or_else(generate_payment_reference);
+    respond(create_payment_request(
+        params.amount,
+        spl_mint,
+        reference,
+        params.label.as_deref(),
+        params.message.as_deref(),
+    )?)
+}
+
+/// Parameters of `format_token_amount`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FormatAmountParams {
+    amount: u64,
+    decimals: u8,
+}
+
+/// Calls [`format_token_amount`] with the default format
+fn call_format_token_amount(params: &str) -> Result<Value, CallError> {
+    let params: FormatAmountParams = parse(params)?;
+    let formatted = format_token_amount(params.amount, params.decimals, &FormatOptions::default());
+    respond(json!({ "formatted": formatted }))
+}
+
+/// Parameters of `parse_token_amount`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ParseAmountParams {
+    formatted: String,
+    decimals: u8,
+}
+
+/// Calls [`parse_token_amount`] with the default format
+fn call_parse_token_amount(params: &str) -> Result<Value, CallError> {
+    let params: ParseAmountParams = parse(params)?;
+    let amount = parse_token_amount(
+        &params.formatted,
+        params.decimals,
+        &FormatOptions::default(),
+    )
+    .map_err(|e| {
+        CallError::invalid_params(
+            format!("Invalid parameter 'formatted': {}", e),
+            Some("formatted"),
+        )
+    })?;
+    respond(json!({ "amount": amount }))
+}
+
+/// Calls [`metrics_snapshot`]
+fn call_metrics(params: &str) -> Result<Value, CallError> {
+    let NoParams {} = parse(params)?;
+    respond(metrics_snapshot())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ffi::sss_call;
+    use std::ffi::{CStr, CString};
+    use std::os::raw::{c_char, c_int};
+
+    /// Methods that only compute, so any parameters that parse are safe to run
+    const PURE_METHODS: [&str; 4] = [
+        "format_token_amount",
+        "parse_token_amount",
+        "create_payment_request",
+        "metrics",
+    ];
+
+    fn result(method: &str, params: &str) -> Value {
+        let response: Value = serde_json::from_str(&call(method, params).unwrap()).unwrap();
+        response["data"]["result"].clone()
+    }
+
+    fn error(method: &str, params: &str) -> CallError {
+        call(method, params).unwrap_err()
+    }
+
+    #[test]
+    fn methods_have_unique_names() {
+        assert!(METHODS.len() >= 6);
+        for (i, (name, _)) in METHODS.iter().enumerate() {
+            assert!(
+                METHODS[..i].iter().all(|(other, _)| other != name),
+                "{} is registered twice",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn pure_methods_return_results() {
+        let formatted = result(
+            "format_token_amount",
+            r#"{"amount": 1500000, "decimals": 6}"#,
+        );
+        assert_eq!(formatted["formatted"], "1.5");
+        let parsed = result(
+            "parse_token_amount",
+            r#"{"formatted": "1.5", "decimals": 6}"#,
+        );
+        assert_eq!(parsed["amount"], 1_500_000);
+        assert!(result("metrics", "{}")["operations"].is_array());
+    }
+
+    #[test]
+    fn unknown_method_lists_the_methods() {
+        let e = error("launch_rocket", "{}");
+        assert_eq!(e.kind, "unknown_method");
+        assert!(e.message.contains("mint_token"));
+        assert_eq!(e.field, None);
+    }
+
+    #[test]
+    fn missing_field_is_named() {
+        let e = error("format_token_amount", r#"{"amount": 1}"#);
+        assert_eq!(e.kind, "invalid_params");
+        assert_eq!(e.field.as_deref(), Some("decimals"));
+    }
+
+    #[test]
+    fn unknown_field_is_named() {
+        let e = error("metrics", r#"{"verbose": true}"#);
+        assert_eq!(e.field.as_deref(), Some("verbose"));
+    }
+
+    #[test]
+    fn mistyped_field_is_named() {
+        let e = error("format_token_amount", r#"{"amount": 1, "decimals": "six"}"#);
+        assert_eq!(e.field.as_deref(), Some("decimals"));
+        assert!(e.byte_offset.is_some());
+
+        let e = error("format_token_amount", r#"{"amount": -1, "decimals": 6}"#);
+        assert_eq!(e.field.as_deref(), Some("amount"));
+
+        let e = error("format_token_amount", r#"{"amount": 1, "decimals": 256}"#);
+        assert_eq!(e.field.as_deref(), Some("decimals"));
+    }
+
+    #[test]
+    fn invalid_public_key_is_named() {
+        let e = error("mint_token", r#"{"mint": "not-a-key", "amount": 1}"#);
+        assert_eq!(e.field.as_deref(), Some("mint"));
+        let e = error(
+            "create_payment_request",
+            r#"{"amount": 1, "spl_mint": "0OIl"}"#,
+        );
+        assert_eq!(e.field.as_deref(), Some("spl_mint"));
+    }
+
+    #[test]
+    fn syntax_errors_name_no_field() {
+        let e = error("metrics", "{");
+        assert_eq!(e.kind, "invalid_params");
+        assert_eq!(e.field, None);
+        assert_eq!(e.byte_offset, Some(1));
+    }
+
+    #[test]
+    fn field_before_skips_nested_keys_and_strings() {
+        let json = r#"{"a": {"b": 1}, "c": "x\"y", "d": [1, {"e": 2}], "f": tru"#;
+        assert_eq!(field_before(json, json.len()).as_deref(), Some("f"));
+        assert_eq!(field_before(json, 10).as_deref(), Some("a"));
+        assert_eq!(field_before("[1, 2]", 4), None);
+    }
+
+    #[test]
+    fn byte_offset_counts_previous_lines() {
+        assert_eq!(byte_offset("{\n  \"a\": x\n}", 2, 8), Some(10));
+        assert_eq!(byte_offset("{}", 0, 0), None);
+        assert_eq!(byte_offset("{}", 1, 99), Some(2));
+    }
+
+    /// Malformed parameters: truncations, wrong types, garbage and deep nesting
+    fn malformed_inputs() -> Vec<String> {
+        let valid = r#"{"mint": "So11111111111111111111111111111111111111112", "owner": null, "amount": 18446744073709551615, "decimals": 6, "formatted": "1.5"}"#;
+        let mut inputs: Vec<String> = (0..valid.len())
+            .filter(|&i| valid.is_char_boundary(i))
+            .map(|i| valid[..i].to_string())
+            .collect();
+        inputs.extend(
+            [
+                "",
+                "null",
+                "[]",
+                "true",
+                "\"{}\"",
+                "{}}",
+                "{\"amount\": 1e400}",
+                "{\"amount\": 18446744073709551616}",
+                "{\"amount\": NaN}",
+                "{\"amount\": 1,}",
+                "{\"\\ud800\": 1}",
+                "{\"a\": \"\\u00zz\"}",
+                "\u{feff}{}",
+                "{\"mint\": [], \"amount\": {}}",
+                "{\"decimals\": -0.0}",
+                "\0",
+            ]
+            .map(str::to_string),
+        );
+        inputs.push("[".repeat(10_000));
+        inputs.push(format!("{}{}", "{\"a\":".repeat(5_000), "}".repeat(5_000)));
+        // Deterministic pseudo-random garbage
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        for _ in 0..200 {
+            let len = (state % 64) as usize;
+            let garbage: String = (0..len)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    b"{}[]\":,0123456789.-eE tfnul\\ax"[(state % 30) as usize] as char
+                })
+                .collect();
+            inputs.push(garbage);
+        }
+        inputs
+    }
+
+    #[test]
+    fn malformed_params_never_panic() {
+        for input in malformed_inputs() {
+            for (method, _) in METHODS {
+                // Inputs that parse are only run against methods without side effects
+                let parses = serde_json::from_str::<Value>(&input).is_ok_and(|v| v.is_object());
+                if parses && !PURE_METHODS.contains(method) {
+                    continue;
+                }
+                if let Err(e) = call(method, &input) {
+                    assert!(!e.message.is_empty());
+                    let response: Value = serde_json::from_str(&e.to_json()).unwrap();
+                    assert!(response["data"]["error"]["kind"].is_string());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ffi_call_reports_required_length() {
+        let method = CString::new("format_token_amount").unwrap();
+        let params = CString::new(r#"{"amount": 1, "decimals": 0}"#).unwrap();
+        let mut required: c_int = 0;
+        let mut small = [0 as c_char; 4];
+        let code = unsafe {
+            sss_call(
+                method.as_ptr(),
+                params.as_ptr(),
+                small.as_mut_ptr(),
+                small.len() as c_int,
+                &mut required,
+            )
+        };
+        assert_eq!(code, -4);
+
+        let mut buffer = vec![0 as c_char; required as usize];
+        let code = unsafe {
+            sss_call(
+                method.as_ptr(),
+                params.as_ptr(),
+                buffer.as_mut_ptr(),
+                required,
+                std::ptr::null_mut(),
+            )
+        };
+        assert_eq!(code, 0);
+        let response = unsafe { CStr::from_ptr(buffer.as_ptr()) }.to_str().unwrap();
+        assert_eq!(response.len() + 1, required as usize);
+        assert!(response.contains("\"formatted\""));
+    }
+
+    #[test]
+    fn ffi_call_maps_error_kinds_to_codes() {
+        let mut buffer = vec![0 as c_char; 1 << 16];
+        let mut code = |method: &str, params: Option<&str>| {
+            let method = CString::new(method).unwrap();
+            let params = params.map(|p| CString::new(p).unwrap());
+            unsafe {
+                sss_call(
+                    method.as_ptr(),
+                    params.as_ref().map_or(std::ptr::null(), |p| p.as_ptr()),
+                    buffer.as_mut_ptr(),
+                    buffer.len() as c_int,
+                    std::ptr::null_mut(),
+                )
+            }
+        };
+        assert_eq!(code("launch_rocket", None), -2);
+        assert_eq!(code("format_token_amount", Some("{")), -3);
+        // Null parameters are an empty object
+        assert_eq!(code("metrics", None), 0);
+    }
+}
@@ -0,0 +1,248 @@
+//! Thread pool running the work of asynchronous FFI calls
+//!
+//! A synchronous FFI call holds the host's thread for the whole RPC round trip, which
+//! freezes a game engine's main thread. The `_async` FFI functions queue their work here
+//! and return at once; a pool thread runs it and reports the result through a callback.
+
+use crate::error::{SssError, SssResult};
+use crate::load_dotenv;
+use lazy_static::lazy_static;
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::env;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+
+/// Threads running queued calls, unless configured otherwise
+const DEFAULT_ASYNC_THREADS: usize = 4;
+
+/// Calls waiting for a thread before new ones are refused, unless configured otherwise
+const DEFAULT_ASYNC_QUEUE_LIMIT: usize = 1024;
+
+lazy_static! {
+    /// Threads in the pool, from `SSS_ASYNC_THREADS`
+    static ref POOL_THREADS: usize = {
+        load_dotenv();
+        env::var("SSS_ASYNC_THREADS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|&threads| threads > 0)
+            .unwrap_or(DEFAULT_ASYNC_THREADS)
+    };
+
+    /// Queued calls the pool accepts, from `SSS_ASYNC_QUEUE_LIMIT`
+    static ref QUEUE_LIMIT: usize = {
+        load_dotenv();
+        env::var("SSS_ASYNC_QUEUE_LIMIT")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|&limit| limit > 0)
+            .unwrap_or(DEFAULT_ASYNC_QUEUE_LIMIT)
+    };
+}
+
+thread_local! {
+    /// Whether the current thread is a pool thread
+    static IS_POOL_THREAD: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Work queued by an asynchronous FFI call
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Queued and running calls and the threads running them
+struct PoolState {
+    queue: VecDeque<Job>,
+    running: usize,
+    threads: Vec<JoinHandle<()>>,
+    /// Number of [`drain`] calls in progress; while any is, new calls are refused and idle
+    /// threads exit
+    drains: usize,
+}
+
+static STATE: Mutex<PoolState> = Mutex::new(PoolState {
+    queue: VecDeque::new(),
+    running: 0,
+    threads: Vec::new(),
+    drains: 0,
+});
+
+/// Signalled when a call is queued or the pool starts draining
+static WORK: Condvar = Condvar::new();
+
+/// Signalled when the last of concurrent [`drain`] calls has joined its threads
+static DRAINED: Condvar = Condvar::new();
+
+/// Queues a call, starting another pool thread if fewer than configured are running
+///
+/// # Errors
+///
+/// Returns an `FfiError` if the queue is full or the pool is draining
+pub(crate) fn submit(job: impl FnOnce() + Send + 'static) -> SssResult<()> {
+    let mut state = lock_state()?;
+    if state.drains > 0 {
+        return Err(SssError::FfiError(
+            "The async call pool is shutting down".to_string(),
+        ));
+    }
+    if state.queue.len() >= *QUEUE_LIMIT {
+        return Err(SssError::FfiError(format!(
+            "The async call queue is full with {} calls, retry once sss_pending_count drops",
+            state.queue.len()
+        )));
+    }
+    if state.threads.len() < *POOL_THREADS {
+        let thread = thread::Builder::new()
+            .name("sss-async".to_string())
+            .spawn(run_pool_thread)
+            .map_err(|e| SssError::FfiError(format!("Failed to start async thread: {}", e)))?;
+        state.threads.push(thread);
+    }
+    state.queue.push_back(Box::new(job));
+    WORK.notify_one();
+    Ok(())
+}
+
+/// Returns the number of calls queued or running
+pub(crate) fn pending_count() -> usize {
+    lock_state().map_or(0, |state| state.queue.len() + state.running)
+}
+
+/// Runs every queued call to completion and stops the pool threads
+///
+/// Calls submitted meanwhile are refused. Concurrent drains all return once the last
+/// of them has joined its threads, and the pool only accepts calls again after that; it
+/// starts again on the next call. Does nothing on a pool thread, which cannot wait for
+/// itself.
+pub(crate) fn drain() {
+    if IS_POOL_THREAD.with(Cell::get) {
+        return;
+    }
+    let threads = match lock_state() {
+        Ok(mut state) => {
+            state.drains += 1;
+            WORK.notify_all();
+            std::mem::take(&mut state.threads)
+        }
+        Err(_) => return,
+    };
+    for thread in threads {
+        let _ = thread.join();
+    }
+    if let Ok(mut state) = lock_state() {
+        state.drains -= 1;
+        if state.drains == 0 {
+            DRAINED.notify_all();
+        }
+        while state.drains > 0 {
+            state = match DRAINED.wait(state) {
+                Ok(state) => state,
+                Err(_) => return,
+            };
+        }
+    }
+}
+
+/// Takes calls off the queue until the pool drains with nothing left to run
+fn run_pool_thread() {
+    IS_POOL_THREAD.with(|is_pool_thread| is_pool_thread.set(true));
+    loop {
+        let job = {
+            let Ok(mut state) = lock_state() else {
+                return;
+            };
+            loop {
+                if let Some(job) = state.queue.pop_front() {
+                    state.running += 1;
+                    break job;
+                }
+                if state.drains > 0 {
+                    return;
+                }
+                state = match WORK.wait(state) {
+                    Ok(state) => state,
+                    Err(_) => return,
+                };
+            }
+        };
+        // A panicking call must not take the thread or the running count with it
+        let _ = panic::catch_unwind(AssertUnwindSafe(job));
+        if let Ok(mut state) = lock_state() {
+            state.running -= 1;
+        }
+    }
+}
+
+fn lock_state() -> SssResult<MutexGuard<'static, PoolState>> {
+    STATE
+        .lock()
+        .map_err(|e| SssError::FfiError(format!("Async call pool poisoned: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, mpsc};
+    use std::time::Duration;
+
+    /// The pool is global, so its tests run one at a time
+    static POOL_TEST: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn drain_runs_every_queued_call() {
+        let _guard = POOL_TEST.lock().unwrap_or_else(|e| e.into_inner());
+        let done = Arc::new(AtomicUsize::new(0));
+        for _ in 0..20 {
+            let done = done.clone();
+            submit(move || {
+                done.fetch_add(1, Ordering::SeqCst);
+            })
+            .unwrap();
+        }
+        drain();
+        assert_eq!(done.load(Ordering::SeqCst), 20);
+        assert_eq!(pending_count(), 0);
+        assert!(lock_state().unwrap().threads.is_empty());
+    }
+
+    #[test]
+    fn concurrent_drains_keep_the_pool_closed_until_both_finish() {
+        let _guard = POOL_TEST.lock().unwrap_or_else(|e| e.into_inner());
+        let (started_tx, started) = mpsc::channel();
+        let (release, release_rx) = mpsc::channel::<()>();
+        submit(move || {
+            started_tx.send(()).unwrap();
+            let _ = release_rx.recv();
+        })
+        .unwrap();
+        started.recv().unwrap();
+
+        let first = thread::spawn(drain);
+        while lock_state().unwrap().drains == 0 {
+            thread::yield_now();
+        }
+        // The second drain finds no threads left but must not reopen the pool
+        let second = thread::spawn(drain);
+        thread::sleep(Duration::from_millis(50));
+        assert!(!second.is_finished());
+        assert!(submit(|| {}).is_err());
+
+        release.send(()).unwrap();
+        first.join().unwrap();
+        second.join().unwrap();
+        assert_eq!(lock_state().unwrap().drains, 0);
+        assert!(lock_state().unwrap().threads.is_empty());
+
+        submit(|| {}).unwrap();
+        drain();
+    }
+
+    #[test]
+    fn panicking_call_does_not_leak_the_running_count() {
+        let _guard = POOL_TEST.lock().unwrap_or_else(|e| e.into_inner());
+        submit(|| panic!("call failed")).unwrap();
+        drain();
+        assert_eq!(pending_count(), 0);
+    }
+}
@@ -0,0 +1,413 @@
+//! Versioning of the JSON written across the FFI boundary
+//!
+//! Every JSON payload the FFI hands to C is wrapped in an envelope naming the schema it
+//! follows, `{"v": 2, "data": ...}`, so hosts built against an older library can tell
+//! whether they understand what they receive. Payloads written before the envelope existed
+//! are schema 1.
+//!
+//! # Compatibility contract
+//!
+//! Within a schema version, payloads only change additively:
+//!
+//! - fields may be added to objects, and new variants to tagged enums such as events
+//! - fields are never removed, renamed, or given another type or unit
+//! - optional fields stay optional, and a field that was always present stays present
+//!
+//! Hosts must therefore ignore fields they do not know. Any other change bumps
+//! [`FFI_SCHEMA_VERSION`], which hosts can compare with the version they were built against
+//! through `sss_ffi_schema_version` before reading a payload.
+//!
+//! Schema 2 covers the payloads of `sss_execute_airdrop` (the `AirdropReport`),
+//! `sss_metrics_json`, `sss_get_payer_info`, `sss_set_event_callback` (one `SssEvent` per
+//! call), `sss_fetch_all_assets` (one `AssetPage` per call), `sss_fetch_assets_stream`
+//! (the concatenated chunks, an array of `DigitalAsset`), `sss_fetch_transaction_receipt`
+//! (the `TransactionReceipt`), `sss_audit_token_consistency`, `sss_burn_all_assets`,
+//! `sss_reload_policies` (the `PolicyReload`), `sss_verify_signed_command` (the
+//! `SignedCommand`), `sss_review_external_transaction` (the `ReviewOutcome`),
+//! `sss_wallet_activity` (the `ActivityPage`), `sss_warm_up` (the `WarmupReport`), and
+//! `sss_call` and the completion callbacks of asynchronous calls, whose `data` is the
+//! `{"result": ...}` or `{"error": ...}` response object.
+
+use crate::error::{IntoSssError, SssResult};
+use serde::Serialize;
+use serde_json::Value;
+use std::os::raw::c_int;
+
+/// Version of the JSON schema written across the FFI boundary
+pub const FFI_SCHEMA_VERSION: c_int = 2;
+
+/// A payload tagged with the schema it follows
+#[derive(Serialize)]
+struct Envelope<'a, T: ?Sized> {
+    v: c_int,
+    data: &'a T,
+}
+
+/// Wraps a JSON value in the versioned envelope
+pub(crate) fn envelope(data: Value) -> String {
+    // A `Value` always serializes
+    versioned_json(&data).unwrap_or_default()
+}
+
+/// Serializes a payload wrapped in the versioned envelope
+///
+/// # Arguments
+///
+/// * `data` - The payload
+///
+/// # Returns
+///
+/// The JSON `{"v": FFI_SCHEMA_VERSION, "data": ...}`
+///
+/// # Errors
+///
+/// Returns an `FfiError` if the payload cannot be serialized
+pub(crate) fn versioned_json<T: Serialize + ?Sized>(data: &T) -> SssResult<String> {
+    serde_json::to_string(&Envelope {
+        v: FFI_SCHEMA_VERSION,
+        data,
+    })
+    .into_sss_error("Failed to serialize FFI payload")
+}
+
+/// Schema snapshots of the payloads hosts deserialize strictly
+///
+/// Each snapshot lists every field path of a payload with its JSON type. A test fails when
+/// a path disappears or changes type; new paths pass, since additions are compatible. When a
+/// test fails, either restore the field or bump [`FFI_SCHEMA_VERSION`] and the snapshots.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::airdrop::AirdropReport;
+    use crate::batch::BatchReport;
+    #[cfg(feature = "das")]
+    use crate::das::{AssetPage, AssetProgress, AssetSource, DigitalAsset};
+    use crate::error::SssError;
+    use crate::ffi_call::CallError;
+    use crate::inspect::{DecodedInstruction, InspectedAccount, InspectedInstruction};
+    use crate::receipt::{LamportBalanceChange, Receipt, TokenBalanceChange};
+    use crate::supply_caps::SupplyCapViolation;
+    use crate::warmup::WarmupReport;
+    use solana_sdk::pubkey::Pubkey;
+    use std::collections::BTreeSet;
+
+    /// Collects `path: type` for every value in a payload, with `[]` for array items
+    fn schema(value: &Value) -> BTreeSet<String> {
+        fn walk(path: &str, value: &Value, out: &mut BTreeSet<String>) {
+            let kind = match value {
+                Value::Null => "null",
+                Value::Bool(_) => "bool",
+                Value::Number(_) => "number",
+                Value::String(_) => "string",
+                Value::Array(items) => {
+                    for item in items {
+                        walk(&format!("{}[]", path), item, out);
+                    }
+                    "array"
+                }
+                Value::Object(fields) => {
+                    for (key, field) in fields {
+                        walk(&format!("{}.{}", path, key), field, out);
+                    }
+                    "object"
+                }
+            };
+            out.insert(format!("{}: {}", path, kind));
+        }
+        let mut out = BTreeSet::new();
+        walk("", value, &mut out);
+        out
+    }
+
+    /// Asserts that a payload still has every field of its snapshot, with the same type
+    fn assert_compatible(json: &str, snapshot: &[&str]) {
+        let value: Value = serde_json::from_str(json).unwrap();
+        assert_eq!(value["v"], FFI_SCHEMA_VERSION);
+        let current = schema(&value);
+        let missing: Vec<&&str> = snapshot
+            .iter()
+            .filter(|path| !current.contains(**path))
+            .collect();
+        assert!(
+            missing.is_empty(),
+            "fields removed, renamed or retyped within schema {}: {:?}\ncurrent schema: {:#?}",
+            FFI_SCHEMA_VERSION,
+            missing,
+            current
+        );
+    }
+
+    fn key(byte: u8) -> Pubkey {
+        Pubkey::new_from_array([byte; 32])
+    }
+
+    #[test]
+    fn envelope_wraps_the_payload() {
+        assert_eq!(
+            envelope(serde_json::json!({ "a": 1 })),
+            format!(r#"{{"v":{},"data":{{"a":1}}}}"#, FFI_SCHEMA_VERSION)
+        );
+        assert_eq!(
+            versioned_json(&[1, 2]).unwrap(),
+            format!(r#"{{"v":{},"data":[1,2]}}"#, FFI_SCHEMA_VERSION)
+        );
+    }
+
+    #[cfg(feature = "das")]
+    #[test]
+    fn asset_page_schema() {
+        let page = AssetPage {
+            page: 2,
+            assets: vec![DigitalAsset {
+                id: key(1),
+                interface: Some("FungibleToken".to_string()),
+                name: Some("Token".to_string()),
+                symbol: Some("TKN".to_string()),
+                uri: Some("https://example.com/token.json".to_string()),
+                owner: Some(key(2).to_string()),
+                balance: Some(5),
+                decimals: Some(6),
+                compressed: false,
+                source: AssetSource::Das,
+                trusted: true,
+            }],
+            next_cursor: Some(3),
+            progress: AssetProgress {
+                fetched: 2,
+                estimated_total: Some(10),
+            },
+        };
+        assert_compatible(
+            &versioned_json(&page).unwrap(),
+            &[
+                ": object",
+                ".v: number",
+                ".data: object",
+                ".data.page: number",
+                ".data.next_cursor: number",
+                ".data.progress: object",
+                ".data.progress.fetched: number",
+                ".data.progress.estimated_total: number",
+                ".data.assets: array",
+                ".data.assets[]: object",
+                ".data.assets[].id: string",
+                ".data.assets[].interface: string",
+                ".data.assets[].name: string",
+                ".data.assets[].symbol: string",
+                ".data.assets[].uri: string",
+                ".data.assets[].owner: string",
+                ".data.assets[].balance: number",
+                ".data.assets[].decimals: number",
+                ".data.assets[].compressed: bool",
+                ".data.assets[].source: string",
+                ".data.assets[].trusted: bool",
+            ],
+        );
+    }
+
+    #[test]
+    fn receipt_schema() {
+        let receipt = Receipt {
+            signature: "sig".to_string(),
+            slot: 7,
+            block_time: Some(1_700_000_000),
+            fee: 5000,
+            fee_payer: key(1),
+            success: false,
+            error: Some("InstructionError".to_string()),
+            instructions: vec![InspectedInstruction {
+                program_id: key(9),
+                program_name: Some("Token"),
+                accounts: vec![InspectedAccount {
+                    pubkey: key(2),
+                    is_signer: true,
+                    is_writable: false,
+                }],
+                decoded: DecodedInstruction::TokenMintTo { amount: 3 },
+            }],
+            instruction_summaries: vec!["Token: mint 3".to_string()],
+            token_balances: vec![TokenBalanceChange {
+                account: key(3),
+                mint: key(4),
+                owner: Some(key(1).to_string()),
+                decimals: 6,
+                pre: 0,
+                post: 3,
+            }],
+            lamport_balances: vec![LamportBalanceChange {
+                account: key(1),
+                pre: 10_000,
+                post: 5_000,
+            }],
+        };
+        assert_compatible(
+            &versioned_json(&receipt).unwrap(),
+            &[
+                ".data: object",
+                ".data.signature: string",
+                ".data.slot: number",
+                ".data.block_time: number",
+                ".data.fee: number",
+                ".data.fee_payer: string",
+                ".data.success: bool",
+                ".data.error: string",
+                ".data.instructions: array",
+                ".data.instructions[].program_id: string",
+                ".data.instructions[].program_name: string",
+                ".data.instructions[].accounts[].pubkey: string",
+                ".data.instructions[].accounts[].is_signer: bool",
+                ".data.instructions[].accounts[].is_writable: bool",
+                ".data.instructions[].decoded: object",
+                ".data.instructions[].decoded.TokenMintTo.amount: number",
+                ".data.instruction_summaries[]: string",
+                ".data.token_balances[].account: string",
+                ".data.token_balances[].mint: string",
+                ".data.token_balances[].owner: string",
+                ".data.token_balances[].decimals: number",
+                ".data.token_balances[].pre: number",
+                ".data.token_balances[].post: number",
+                ".data.lamport_balances[].account: string",
+                ".data.lamport_balances[].pre: number",
+                ".data.lamport_balances[].post: number",
+            ],
+        );
+    }
+
+    #[test]
+    fn batch_report_schema() {
+        let report = AirdropReport {
+            batch: BatchReport {
+                succeeded: vec![(key(1), "sig".to_string())],
+                failed: vec![(key(2), SssError::RpcError("timeout".to_string()))],
+                skipped: vec![key(3)],
+                skip_reasons: vec![(key(3), "frozen".to_string())],
+                never_landed: vec![key(2)],
+                landed_failed: vec![key(4)],
+            },
+            supply_cap_violation: Some(SupplyCapViolation {
+                mint: key(5),
+                supply: 11,
+                cap: 10,
+            }),
+            platform_fee_lamports: 100,
+        };
+        assert_compatible(
+            &versioned_json(&report).unwrap(),
+            &[
+                ".data: object",
+                ".data.succeeded[].key: string",
+                ".data.succeeded[].signature: string",
+                ".data.failed[].key: string",
+                ".data.failed[].error.kind: string",
+                ".data.failed[].error.message: string",
+                ".data.skipped[]: string",
+                ".data.skip_reasons[].key: string",
+                ".data.skip_reasons[].reason: string",
+                ".data.never_landed[]: string",
+                ".data.landed_failed[]: string",
+                ".data.supply_cap_violation.mint: string",
+                ".data.supply_cap_violation.supply: number",
+                ".data.supply_cap_violation.cap: number",
+                ".data.platform_fee_lamports: number",
+            ],
+        );
+    }
+
+    #[test]
+    fn health_check_schema() {
+        let report = WarmupReport {
+            rpc_url: "http://localhost:8899".to_string(),
+            node_version: "2.2.0".to_string(),
+            payer: key(1),
+            dns_ms: 1,
+            connect_ms: 2,
+            blockhash_ms: 3,
+            payer_ms: 4,
+            total_ms: 10,
+        };
+        assert_compatible(
+            &versioned_json(&report).unwrap(),
+            &[
+                ".data: object",
+                ".data.rpc_url: string",
+                ".data.node_version: string",
+                ".data.payer: string",
+                ".data.dns_ms: number",
+                ".data.connect_ms: number",
+                ".data.blockhash_ms: number",
+                ".data.payer_ms: number",
+                ".data.total_ms: number",
+            ],
+        );
+    }
+
+    #[test]
+    fn error_object_schema() {
+        let snapshot = [
+            ".data: object",
+            ".data.error: object",
+            ".data.error.kind: string",
+            ".data.error.message: string",
+        ];
+        assert_compatible(
+            &CallError::from(SssError::TokenError("no supply".to_string())).to_json(),
+            &snapshot,
+        );
+
+        let invalid = CallError::invalid_params("expected a number", Some("amount")).to_json();
+        assert_compatible(&invalid, &snapshot);
+        assert_compatible(&invalid, &[".data.error.field: string"]);
+    }
+
+    #[test]
+    fn result_object_schema() {
+        assert_compatible(
+            &envelope(serde_json::json!({ "result": { "signature": "sig" } })),
+            &[".data.result: object", ".data.result.signature: string"],
+        );
+    }
+
+    #[test]
+    fn capabilities_schema() {
+        let mut buffer = vec![0 as std::ffi::c_char; 64 * 1024];
+        let code = unsafe {
+            crate::ffi::sss_capabilities(buffer.as_mut_ptr(), buffer.len() as std::ffi::c_int)
+        };
+        assert_eq!(code, 0);
+        let json = unsafe { std::ffi::CStr::from_ptr(buffer.as_ptr()) }
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let mut snapshot = vec![
+            ": object".to_string(),
+            ".v: number".to_string(),
+            ".data: object".to_string(),
+            ".data.version: string".to_string(),
+            ".data.ffi_schema: number".to_string(),
+            ".data.role: object".to_string(),
+            ".data.role.role: string".to_string(),
+            ".data.role.restricted: bool".to_string(),
+            ".data.role.operations: object".to_string(),
+            ".data.role.operations.mint_token: object".to_string(),
+            ".data.role.operations.mint_token.allowed: bool".to_string(),
+        ];
+        // Every pinned flag name is a boolean key of the payload itself
+        let names = include_str!("../fixtures/golden/capabilities.txt");
+        snapshot.extend(names.lines().map(|name| format!(".data.{}: bool", name)));
+        let snapshot: Vec<&str> = snapshot.iter().map(String::as_str).collect();
+        assert_compatible(&json, &snapshot);
+    }
+
+    #[test]
+    fn removed_field_is_detected() {
+        let json = envelope(serde_json::json!({ "kept": 1 }));
+        let result = std::panic::catch_unwind(|| {
+            assert_compatible(&json, &[".data.kept: number", ".data.removed: string"])
+        });
+        assert!(result.is_err());
+        let retyped =
+            std::panic::catch_unwind(|| assert_compatible(&json, &[".data.kept: string"]));
+        assert!(retyped.is_err());
+    }
+}
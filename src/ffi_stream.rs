@@ -0,0 +1,241 @@
+//! Incremental JSON output for FFI results too large for one buffer
+//!
+//! A [`JsonArrayWriter`] serializes a versioned `{"v": .., "data": [..]}` document one
+//! element at a time and hands it to a sink in chunks of at most [`STREAM_CHUNK_BYTES`],
+//! so neither side ever holds the whole document. Concatenating the chunks gives the
+//! document; a chunk may end in the middle of an element or of a UTF-8 character.
+
+use crate::error::{IntoSssError, SssResult};
+use crate::ffi_schema::FFI_SCHEMA_VERSION;
+use serde::Serialize;
+use std::ffi::c_void;
+use std::io::Write;
+use std::ops::ControlFlow;
+use std::os::raw::{c_char, c_int};
+
+/// Largest chunk handed to the sink
+pub(crate) const STREAM_CHUNK_BYTES: usize = 64 * 1024;
+
+/// Returns a sink handing each chunk to a C callback, which returns non-zero to stop
+///
+/// # Arguments
+///
+/// * `chunk_cb` - Called with each chunk, its length and `user_data`
+/// * `user_data` - Passed through to every call
+pub(crate) fn c_chunk_sink(
+    chunk_cb: extern "C" fn(*const c_char, c_int, *mut c_void) -> c_int,
+    user_data: *mut c_void,
+) -> impl FnMut(&[u8]) -> ControlFlow<()> {
+    move |chunk: &[u8]| {
+        // Chunks never exceed STREAM_CHUNK_BYTES, which fits a c_int
+        let len = chunk.len() as c_int;
+        if chunk_cb(chunk.as_ptr().cast(), len, user_data) == 0 {
+            ControlFlow::Continue(())
+        } else {
+            ControlFlow::Break(())
+        }
+    }
+}
+
+/// Writes a versioned JSON array to a sink in chunks as its elements arrive
+pub(crate) struct JsonArrayWriter<S: FnMut(&[u8]) -> ControlFlow<()>> {
+    sink: S,
+    buf: Vec<u8>,
+    chunk_size: usize,
+    elements: usize,
+}
+
+impl<S: FnMut(&[u8]) -> ControlFlow<()>> JsonArrayWriter<S> {
+    /// Starts a document whose chunks go to `sink`, which breaks to stop the stream
+    ///
+    /// # Arguments
+    ///
+    /// * `sink` - Receives each chunk; returning `Break` stops the stream
+    /// * `chunk_size` - The largest chunk handed to the sink
+    pub(crate) fn new(sink: S, chunk_size: usize) -> Self {
+        let mut buf = Vec::with_capacity(chunk_size * 2);
+        let _ = write!(buf, "{{\"v\":{},\"data\":[", FFI_SCHEMA_VERSION);
+        Self {
+            sink,
+            buf,
+            chunk_size: chunk_size.max(1),
+            elements: 0,
+        }
+    }
+
+    /// Appends an element, handing every full chunk to the sink
+    ///
+    /// # Errors
+    ///
+    /// Returns an `FfiError` if the element cannot be serialized
+    pub(crate) fn push<T: Serialize + ?Sized>(
+        &mut self,
+        element: &T,
+    ) -> SssResult<ControlFlow<()>> {
+        if self.elements > 0 {
+            self.buf.push(b',');
+        }
+        serde_json::to_writer(&mut self.buf, element)
+            .into_sss_error("Failed to serialize streamed element")?;
+        self.elements += 1;
+
+        while self.buf.len() >= self.chunk_size {
+            let rest = self.buf.split_off(self.chunk_size);
+            let chunk = std::mem::replace(&mut self.buf, rest);
+            if (self.sink)(&chunk).is_break() {
+                return Ok(ControlFlow::Break(()));
+            }
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    /// Closes the document and hands the remaining chunks to the sink
+    pub(crate) fn finish(mut self) -> ControlFlow<()> {
+        self.buf.extend_from_slice(b"]}");
+        for chunk in self.buf.chunks(self.chunk_size) {
+            (self.sink)(chunk)?;
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::das::{AssetSource, DigitalAsset};
+    use serde_json::Value;
+    use solana_sdk::pubkey::Pubkey;
+
+    /// Builds a synthetic asset with a multi-byte name, so chunks split UTF-8 characters
+    fn asset(i: u64) -> DigitalAsset {
+        DigitalAsset {
+            id: Pubkey::new_unique(),
+            interface: Some("FungibleToken".to_string()),
+            name: Some(format!("Jeton n°{} 🎟", i)),
+            symbol: Some("JTN".to_string()),
+            uri: Some(format!("https://example.com/{}.json", i)),
+            owner: None,
+            balance: Some(i),
+            decimals: Some(6),
+            compressed: false,
+            source: AssetSource::Das,
+            trusted: false,
+        }
+    }
+
+    /// Streams `assets` with the given chunk size, returning the chunks
+    fn stream(assets: &[DigitalAsset], chunk_size: usize) -> Vec<Vec<u8>> {
+        let mut chunks = Vec::new();
+        let mut writer = JsonArrayWriter::new(
+            |chunk: &[u8]| {
+                chunks.push(chunk.to_vec());
+                ControlFlow::Continue(())
+            },
+            chunk_size,
+        );
+        for asset in assets {
+            assert!(writer.push(asset).unwrap().is_continue());
+        }
+        assert!(writer.finish().is_continue());
+        chunks
+    }
+
+    #[test]
+    fn large_asset_list_reassembles_into_valid_json() {
+        let assets: Vec<DigitalAsset> = (0..50_000).map(asset).collect();
+        let chunks = stream(&assets, STREAM_CHUNK_BYTES);
+
+        let (last, full) = chunks.split_last().unwrap();
+        assert!(full.iter().all(|chunk| chunk.len() == STREAM_CHUNK_BYTES));
+        assert!(!last.is_empty() && last.len() <= STREAM_CHUNK_BYTES);
+
+        let document: Value = serde_json::from_slice(&chunks.concat()).unwrap();
+        assert_eq!(document["v"], FFI_SCHEMA_VERSION);
+        let data = document["data"].as_array().unwrap();
+        assert_eq!(data.len(), assets.len());
+        for (parsed, asset) in data.iter().zip(&assets) {
+            assert_eq!(parsed, &serde_json::to_value(asset).unwrap());
+        }
+    }
+
+    #[test]
+    fn tiny_chunks_split_characters_but_reassemble_exactly() {
+        let assets: Vec<DigitalAsset> = (0..20).map(asset).collect();
+        let chunks = stream(&assets, 7);
+        assert!(chunks.iter().all(|chunk| chunk.len() <= 7));
+        assert!(
+            chunks
+                .iter()
+                .any(|chunk| std::str::from_utf8(chunk).is_err())
+        );
+        assert_eq!(
+            chunks.concat(),
+            crate::ffi_schema::versioned_json(&assets)
+                .unwrap()
+                .into_bytes()
+        );
+    }
+
+    #[test]
+    fn empty_list_is_an_empty_array() {
+        assert_eq!(
+            stream(&[], STREAM_CHUNK_BYTES).concat(),
+            format!(r#"{{"v":{},"data":[]}}"#, FFI_SCHEMA_VERSION).into_bytes()
+        );
+    }
+
+    /// What the C callback of the stop tests has seen
+    struct Host {
+        calls: usize,
+        stop_at: usize,
+        received: Vec<u8>,
+    }
+
+    extern "C" fn host_callback(chunk: *const c_char, len: c_int, user_data: *mut c_void) -> c_int {
+        let host = unsafe { &mut *user_data.cast::<Host>() };
+        let chunk = unsafe { std::slice::from_raw_parts(chunk.cast::<u8>(), len as usize) };
+        host.calls += 1;
+        host.received.extend_from_slice(chunk);
+        c_int::from(host.calls == host.stop_at)
+    }
+
+    #[test]
+    fn host_stop_ends_the_stream() {
+        let mut host = Host {
+            calls: 0,
+            stop_at: 3,
+            received: Vec::new(),
+        };
+        let user_data: *mut c_void = (&mut host as *mut Host).cast();
+        let mut writer = JsonArrayWriter::new(c_chunk_sink(host_callback, user_data), 1024);
+
+        let mut pushed = 0;
+        for i in 0..10_000 {
+            pushed += 1;
+            if writer.push(&asset(i)).unwrap().is_break() {
+                break;
+            }
+        }
+        drop(writer);
+
+        assert_eq!(host.calls, 3);
+        assert_eq!(host.received.len(), 3 * 1024);
+        assert!(pushed < 10_000);
+    }
+
+    #[test]
+    fn host_stop_during_finish_is_reported() {
+        let mut host = Host {
+            calls: 0,
+            stop_at: 1,
+            received: Vec::new(),
+        };
+        let user_data: *mut c_void = (&mut host as *mut Host).cast();
+        let mut writer = JsonArrayWriter::new(c_chunk_sink(host_callback, user_data), 32);
+        // Stays below one chunk, so everything is handed over by `finish`
+        assert!(writer.push(&1).unwrap().is_continue());
+        assert!(writer.finish().is_break());
+        assert_eq!(host.calls, 1);
+        assert_eq!(host.received, br#"{"v":2,"data":[1]}"#);
+    }
+}
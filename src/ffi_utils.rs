@@ -1,26 +1,233 @@
 //! Utility functions for FFI operations
 
+use crate::amount::{FormatOptions, UiAmount};
+use crate::creators::CreatorShare;
 use crate::error::{SssError, SssResult};
-use solana_sdk::pubkey::Pubkey;
+use crate::ffi::SssFormatOptions;
+use crate::migration::LegacyMigration;
+use crate::read_only::is_read_only_error;
+use crate::sns::{ffi_domain_resolution, is_sol_domain, resolve_recipient};
+use crate::structured_signing::SigningDomain;
+use crate::supply_monitor::{ScheduleInterpolation, SupplySchedule};
+use crate::templates::TemplateOverrides;
+use crate::watch_only::is_watch_only_error;
+use base64::prelude::{BASE64_STANDARD, Engine};
+use chrono::DateTime;
+use serde::Deserialize;
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use std::cell::RefCell;
 use std::ffi::{CStr, CString};
-use std::os::raw::{c_char, c_int};
+use std::fmt;
+use std::os::raw::{c_char, c_int, c_uchar};
 use std::ptr;
 use std::str::FromStr;
 
+/// Size of the message buffer in [`SssFfiError`], including the null terminator
+pub const FFI_ERROR_MESSAGE_LEN: usize = 256;
+
+/// Largest output buffer length accepted, in bytes
+///
+/// No output of the library comes close; a larger `*_len` argument is taken to be an
+/// uninitialized or corrupted value rather than the size of a real buffer.
+pub const MAX_FFI_BUFFER_LEN: usize = 16 * 1024 * 1024;
+
+/// Code returned by every FFI function refused because the library is in read-only mode
+pub const SSS_ERR_READ_ONLY: c_int = -20;
+
+/// Code returned by every FFI function refused because the library is in watch-only mode
+pub const SSS_ERR_WATCH_ONLY: c_int = -21;
+
+/// Details of the last failed FFI call on the current thread
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SssFfiError {
+    /// The error code returned by the failed call, 0 if no error was recorded
+    pub code: c_int,
+    /// Zero-based index of the offending parameter, -1 if the failure was not caused by a parameter
+    pub param_index: c_int,
+    /// Byte offset of the first invalid byte within the parameter, -1 if unknown
+    pub byte_offset: c_int,
+    /// Null-terminated description of the failure, naming the parameter where applicable
+    pub message: [c_char; FFI_ERROR_MESSAGE_LEN],
+}
+
+impl SssFfiError {
+    /// Builds an error record, truncating the message to fit the buffer
+    fn new(code: c_int, param_index: c_int, byte_offset: c_int, message: &str) -> Self {
+        let mut buffer = [0 as c_char; FFI_ERROR_MESSAGE_LEN];
+        let mut len = message.len().min(FFI_ERROR_MESSAGE_LEN.saturating_sub(1));
+        while !message.is_char_boundary(len) {
+            len = len.saturating_sub(1);
+        }
+        for (dst, src) in buffer.iter_mut().zip(&message.as_bytes()[..len]) {
+            *dst = *src as c_char;
+        }
+
+        Self {
+            code,
+            param_index,
+            byte_offset,
+            message: buffer,
+        }
+    }
+}
+
+thread_local! {
+    /// The last error recorded by an FFI call on this thread
+    static LAST_ERROR: RefCell<Option<SssFfiError>> = const { RefCell::new(None) };
+}
+
+/// An FFI input that failed validation
+#[derive(Debug, Clone)]
+pub struct InputError {
+    /// Why the input was rejected
+    pub reason: String,
+    /// Byte offset of the first invalid byte, if known
+    pub byte_offset: Option<usize>,
+}
+
+impl InputError {
+    fn new(reason: impl Into<String>) -> Self {
+        Self {
+            reason: reason.into(),
+            byte_offset: None,
+        }
+    }
+
+    fn at(reason: impl Into<String>, byte_offset: usize) -> Self {
+        Self {
+            reason: reason.into(),
+            byte_offset: Some(byte_offset),
+        }
+    }
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.byte_offset {
+            Some(offset) => write!(f, "{} at byte {}", self.reason, offset),
+            None => write!(f, "{}", self.reason),
+        }
+    }
+}
+
+impl From<InputError> for SssError {
+    fn from(e: InputError) -> Self {
+        SssError::FfiError(e.to_string())
+    }
+}
+
+impl From<SssError> for InputError {
+    fn from(e: SssError) -> Self {
+        InputError::new(e.to_string())
+    }
+}
+
+/// Result type for FFI input conversions
+pub type InputResult<T> = Result<T, InputError>;
+
+/// Records a parameter validation failure as the last error and returns its code
+///
+/// # Arguments
+///
+/// * `code` - The error code the FFI function returns
+/// * `param_index` - Zero-based index of the offending parameter
+/// * `param_name` - Name of the offending parameter
+/// * `error` - What was wrong with the parameter
+pub fn invalid_param(
+    code: c_int,
+    param_index: c_int,
+    param_name: &str,
+    error: impl Into<InputError>,
+) -> c_int {
+    let error = error.into();
+    let byte_offset = error
+        .byte_offset
+        .and_then(|o| c_int::try_from(o).ok())
+        .unwrap_or(-1);
+    let message = format!("{}: {}", param_name, error);
+    set_last_error(SssFfiError::new(code, param_index, byte_offset, &message));
+    code
+}
+
+/// Records a failure not attributable to a parameter as the last error and returns its code
+///
+/// A refusal in read-only mode returns [`SSS_ERR_READ_ONLY`] instead of `code`, and one in
+/// watch-only mode [`SSS_ERR_WATCH_ONLY`].
+///
+/// # Arguments
+///
+/// * `code` - The error code the FFI function returns
+/// * `error` - The error that caused the failure
+pub fn operation_failed(code: c_int, error: &SssError) -> c_int {
+    let code = if is_read_only_error(error) {
+        SSS_ERR_READ_ONLY
+    } else if is_watch_only_error(error) {
+        SSS_ERR_WATCH_ONLY
+    } else {
+        code
+    };
+    set_last_error(SssFfiError::new(code, -1, -1, &error.to_string()));
+    code
+}
+
+/// Returns the index and name of the first null pointer among the given parameters
+///
+/// # Arguments
+///
+/// * `params` - Tuples of parameter index, parameter name and whether the pointer is null
+pub fn first_null_param(params: &[(c_int, &'static str, bool)]) -> Option<(c_int, &'static str)> {
+    params
+        .iter()
+        .find(|(_, _, is_null)| *is_null)
+        .map(|(index, name, _)| (*index, *name))
+}
+
+/// Records a null pointer parameter as the last error and returns its code
+pub fn null_param(code: c_int, param_index: c_int, param_name: &str) -> c_int {
+    invalid_param(
+        code,
+        param_index,
+        param_name,
+        InputError::new("Null pointer provided"),
+    )
+}
+
+/// Returns the last error recorded on the current thread
+pub fn last_error() -> Option<SssFfiError> {
+    LAST_ERROR.with(|e| *e.borrow())
+}
+
+fn set_last_error(error: SssFfiError) {
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(error));
+}
+
 /// Safely converts a C string pointer to a Rust String
 ///
 /// # Safety
 ///
 /// The pointer must be a valid, null-terminated C string
-pub unsafe fn c_str_to_string(ptr: *const c_char) -> SssResult<String> {
+pub unsafe fn c_str_to_string(ptr: *const c_char) -> InputResult<String> {
     if ptr.is_null() {
-        return Err(SssError::FfiError("Null pointer provided".to_string()));
+        return Err(InputError::new("Null pointer provided"));
     }
 
-    unsafe { CStr::from_ptr(ptr)
+    unsafe { CStr::from_ptr(ptr) }
         .to_str()
         .map(|s| s.to_string())
-        .map_err(|e| SssError::FfiError(format!("Invalid UTF-8 string: {}", e))) }
+        .map_err(|e| InputError::at("Invalid UTF-8 string", e.valid_up_to()))
+}
+
+/// Safely converts a C string pointer to an optional Rust String
+///
+/// # Safety
+///
+/// If not null, the pointer must be a valid, null-terminated C string
+pub unsafe fn c_str_to_optional_string(ptr: *const c_char) -> InputResult<Option<String>> {
+    if ptr.is_null() {
+        return Ok(None);
+    }
+    unsafe { c_str_to_string(ptr).map(Some) }
 }
 
 /// Safely converts a C string pointer to a Solana Pubkey
@@ -28,9 +235,27 @@ pub unsafe fn c_str_to_string(ptr: *const c_char) -> SssResult<String> {
 /// # Safety
 ///
 /// The pointer must be a valid, null-terminated C string containing a valid Solana public key
-pub unsafe fn c_str_to_pubkey(ptr: *const c_char) -> SssResult<Pubkey> {
+pub unsafe fn c_str_to_pubkey(ptr: *const c_char) -> InputResult<Pubkey> {
     let key_str = unsafe { c_str_to_string(ptr) }?;
-    Pubkey::from_str(&key_str).map_err(|e| SssError::FfiError(format!("Invalid public key: {}", e)))
+
+    // Decode first so an invalid character can be reported with its position
+    let bytes = bs58::decode(&key_str).into_vec().map_err(|e| match e {
+        bs58::decode::Error::InvalidCharacter { character, index } => InputError::at(
+            format!(
+                "Invalid public key: invalid base58 character '{}'",
+                character
+            ),
+            index,
+        ),
+        e => InputError::new(format!("Invalid public key: {}", e)),
+    })?;
+
+    Pubkey::try_from(bytes.as_slice()).map_err(|_| {
+        InputError::new(format!(
+            "Invalid public key: expected 32 bytes, got {}",
+            bytes.len()
+        ))
+    })
 }
 
 /// Safely converts a C string pointer to an optional Solana Pubkey
@@ -38,18 +263,300 @@ pub unsafe fn c_str_to_pubkey(ptr: *const c_char) -> SssResult<Pubkey> {
 /// # Safety
 ///
 /// If not null, the pointer must be a valid, null-terminated C string containing a valid Solana public key
-pub unsafe fn c_str_to_optional_pubkey(ptr: *const c_char) -> SssResult<Option<Pubkey>> {
+pub unsafe fn c_str_to_optional_pubkey(ptr: *const c_char) -> InputResult<Option<Pubkey>> {
     if ptr.is_null() {
         return Ok(None);
     }
     unsafe { c_str_to_pubkey(ptr).map(Some) }
 }
 
+/// Converts a C string pointer holding a recipient to a Solana Pubkey
+///
+/// When enabled with [`set_ffi_domain_resolution`](crate::set_ffi_domain_resolution),
+/// `.sol` domains are resolved to their owner; anything else is parsed like
+/// [`c_str_to_pubkey`].
+///
+/// # Safety
+///
+/// The pointer must be a valid, null-terminated C string
+pub unsafe fn c_str_to_recipient(ptr: *const c_char) -> InputResult<Pubkey> {
+    let recipient = unsafe { c_str_to_string(ptr) }?;
+    if ffi_domain_resolution() && is_sol_domain(&recipient) {
+        return Ok(resolve_recipient(&recipient)?);
+    }
+    unsafe { c_str_to_pubkey(ptr) }
+}
+
+/// Converts a C string pointer holding an optional recipient to a Solana Pubkey, like
+/// [`c_str_to_recipient`]
+///
+/// # Safety
+///
+/// If not null, the pointer must be a valid, null-terminated C string
+pub unsafe fn c_str_to_optional_recipient(ptr: *const c_char) -> InputResult<Option<Pubkey>> {
+    if ptr.is_null() {
+        return Ok(None);
+    }
+    unsafe { c_str_to_recipient(ptr).map(Some) }
+}
+
+/// Safely converts a C string pointer containing base58 to a Solana Signature
+///
+/// # Safety
+///
+/// The pointer must be a valid, null-terminated C string containing a base58 signature
+pub unsafe fn c_str_to_signature(ptr: *const c_char) -> InputResult<Signature> {
+    let sig_str = unsafe { c_str_to_string(ptr) }?;
+    Signature::from_str(&sig_str).map_err(|e| InputError::new(format!("Invalid signature: {}", e)))
+}
+
+/// Safely converts a C string pointer containing base64 to raw bytes
+///
+/// # Safety
+///
+/// The pointer must be a valid, null-terminated C string containing base64 data
+pub unsafe fn c_str_to_base64_bytes(ptr: *const c_char) -> InputResult<Vec<u8>> {
+    let encoded = unsafe { c_str_to_string(ptr) }?;
+    BASE64_STANDARD.decode(encoded).map_err(|e| match e {
+        base64::DecodeError::InvalidByte(offset, _) => {
+            InputError::at("Invalid base64 data: invalid byte", offset)
+        }
+        e => InputError::new(format!("Invalid base64 data: {}", e)),
+    })
+}
+
+/// Converts a C string pointer holding a decimal amount such as `"1.5"` to a UI amount
+///
+/// # Safety
+///
+/// The pointer must be a valid, null-terminated C string
+pub unsafe fn c_str_to_ui_amount(ptr: *const c_char) -> InputResult<UiAmount> {
+    let amount = unsafe { c_str_to_string(ptr) }?;
+    Ok(UiAmount::from_str(&amount)?)
+}
+
+/// Safely converts an optional C string containing a JSON array of base58 public keys
+///
+/// # Safety
+///
+/// If not null, the pointer must be a valid, null-terminated C string
+pub unsafe fn c_str_to_optional_pubkeys(ptr: *const c_char) -> InputResult<Option<Vec<Pubkey>>> {
+    let Some(json) = unsafe { c_str_to_optional_string(ptr) }? else {
+        return Ok(None);
+    };
+    let keys: Vec<String> = serde_json::from_str(&json)
+        .map_err(|e| InputError::new(format!("Invalid public key list: {}", e)))?;
+    keys.iter()
+        .enumerate()
+        .map(|(i, key)| {
+            Pubkey::from_str(key)
+                .map_err(|e| InputError::new(format!("Invalid public key {}: {}", i, e)))
+        })
+        .collect::<InputResult<_>>()
+        .map(Some)
+}
+
+/// A mint and amount in a JSON basket passed over FFI
+#[derive(Deserialize)]
+struct BasketItem {
+    mint: String,
+    amount: u64,
+}
+
+/// Safely converts a C string pointer containing a JSON array of
+/// `{"mint": "<address>", "amount": <base units>}` objects to mints and amounts
+///
+/// # Safety
+///
+/// The pointer must be a valid, null-terminated C string
+pub unsafe fn c_str_to_mint_amounts(ptr: *const c_char) -> InputResult<Vec<(Pubkey, u64)>> {
+    let json = unsafe { c_str_to_string(ptr) }?;
+    let items: Vec<BasketItem> = serde_json::from_str(&json)
+        .map_err(|e| InputError::new(format!("Invalid token basket: {}", e)))?;
+    items
+        .into_iter()
+        .enumerate()
+        .map(|(i, item)| {
+            Pubkey::from_str(&item.mint)
+                .map(|mint| (mint, item.amount))
+                .map_err(|e| InputError::new(format!("Invalid mint in item {}: {}", i, e)))
+        })
+        .collect()
+}
+
+/// A recipient and amount in a JSON allocation list passed over FFI
+#[derive(Deserialize)]
+struct AllocationItem {
+    recipient: String,
+    amount: u64,
+}
+
+/// Safely converts a C string pointer containing the JSON of [`TemplateOverrides`]
+///
+/// # Safety
+///
+/// The pointer must be a valid, null-terminated C string
+pub unsafe fn c_str_to_template_overrides(ptr: *const c_char) -> InputResult<TemplateOverrides> {
+    let json = unsafe { c_str_to_string(ptr) }?;
+    serde_json::from_str(&json)
+        .map_err(|e| InputError::new(format!("Invalid template overrides: {}", e)))
+}
+
+/// Safely converts a C string pointer containing the JSON of a [`SigningDomain`],
+/// `{"name": ..., "version": ..., "cluster": ..., "salt": ...}` with `cluster` and `salt`
+/// optional
+///
+/// # Safety
+///
+/// The pointer must be a valid, null-terminated C string
+pub unsafe fn c_str_to_signing_domain(ptr: *const c_char) -> InputResult<SigningDomain> {
+    let json = unsafe { c_str_to_string(ptr) }?;
+    serde_json::from_str(&json)
+        .map_err(|e| InputError::new(format!("Invalid signing domain: {}", e)))
+}
+
+/// Safely converts a C string pointer containing a JSON array of [`LegacyMigration`]s,
+/// `{"mint", "name", "symbol", "uri", "freeze_authority", "new_authority"}` objects with
+/// the two authorities optional
+///
+/// # Safety
+///
+/// The pointer must be a valid, null-terminated C string
+pub unsafe fn c_str_to_migration_plan(ptr: *const c_char) -> InputResult<Vec<LegacyMigration>> {
+    let json = unsafe { c_str_to_string(ptr) }?;
+    serde_json::from_str(&json)
+        .map_err(|e| InputError::new(format!("Invalid migration plan: {}", e)))
+}
+
+/// A supply schedule as passed over FFI
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FfiSupplySchedule {
+    interpolation: ScheduleInterpolation,
+    #[serde(default)]
+    tolerance: u64,
+    points: Vec<FfiSchedulePoint>,
+}
+
+/// A point of a supply schedule, at a Unix timestamp in seconds
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FfiSchedulePoint {
+    time: i64,
+    supply: u64,
+}
+
+/// Safely converts a C string pointer containing the JSON of a supply schedule,
+/// `{"interpolation": "step" | "linear", "tolerance": <base units>, "points": [{"time":
+/// <unix seconds>, "supply": <base units>}, ...]}`, to a [`SupplySchedule`]
+///
+/// # Safety
+///
+/// The pointer must be a valid, null-terminated C string
+pub unsafe fn c_str_to_supply_schedule(ptr: *const c_char) -> InputResult<SupplySchedule> {
+    let json = unsafe { c_str_to_string(ptr) }?;
+    let schedule: FfiSupplySchedule = serde_json::from_str(&json)
+        .map_err(|e| InputError::new(format!("Invalid supply schedule: {}", e)))?;
+    let points = schedule
+        .points
+        .into_iter()
+        .map(|point| {
+            DateTime::from_timestamp(point.time, 0)
+                .map(|at| (at, point.supply))
+                .ok_or_else(|| InputError::new(format!("Invalid schedule time {}", point.time)))
+        })
+        .collect::<InputResult<Vec<_>>>()?;
+    Ok(SupplySchedule::new(
+        points,
+        schedule.interpolation,
+        schedule.tolerance,
+    ))
+}
+
+/// Safely converts a C string pointer containing a JSON array of
+/// `{"address": "<address>", "share_percent": <percent>, "verified": <bool>}` objects to
+/// creator shares
+///
+/// # Safety
+///
+/// The pointer must be a valid, null-terminated C string
+pub unsafe fn c_str_to_creators(ptr: *const c_char) -> InputResult<Vec<CreatorShare>> {
+    let json = unsafe { c_str_to_string(ptr) }?;
+    serde_json::from_str(&json).map_err(|e| InputError::new(format!("Invalid creators: {}", e)))
+}
+
+/// Safely converts a C string pointer containing a JSON array of
+/// `{"recipient": "<address>", "amount": <base units>}` objects to recipients and amounts
+///
+/// # Safety
+///
+/// The pointer must be a valid, null-terminated C string
+pub unsafe fn c_str_to_recipient_amounts(ptr: *const c_char) -> InputResult<Vec<(Pubkey, u64)>> {
+    let json = unsafe { c_str_to_string(ptr) }?;
+    let items: Vec<AllocationItem> = serde_json::from_str(&json)
+        .map_err(|e| InputError::new(format!("Invalid allocations: {}", e)))?;
+    items
+        .into_iter()
+        .enumerate()
+        .map(|(i, item)| {
+            Pubkey::from_str(&item.recipient)
+                .map(|recipient| (recipient, item.amount))
+                .map_err(|e| InputError::new(format!("Invalid recipient in item {}: {}", i, e)))
+        })
+        .collect()
+}
+
+impl TryFrom<&SssFormatOptions> for FormatOptions {
+    type Error = InputError;
+
+    fn try_from(options: &SssFormatOptions) -> InputResult<Self> {
+        let separator = |code: u32, name: &str| {
+            char::from_u32(code)
+                .ok_or_else(|| InputError::new(format!("Invalid {}: {:#x}", name, code)))
+        };
+        let grouping_separator = match options.grouping_separator {
+            0 => None,
+            code => Some(separator(code, "grouping separator")?),
+        };
+        let max_fraction_digits = match options.max_fraction_digits {
+            -1 => None,
+            digits => Some(u8::try_from(digits).map_err(|_| {
+                InputError::new(format!("Invalid max_fraction_digits: {}", digits))
+            })?),
+        };
+        Ok(Self {
+            grouping_separator,
+            decimal_separator: separator(options.decimal_separator, "decimal separator")?,
+            min_fraction_digits: options.min_fraction_digits,
+            max_fraction_digits,
+            trim_trailing_zeros: options.trim_trailing_zeros != 0,
+        })
+    }
+}
+
+/// Safely converts an optional pointer to format options, using the defaults for null
+///
+/// # Safety
+///
+/// The pointer must be null or point to a valid `SssFormatOptions`
+pub unsafe fn c_format_options(options: *const SssFormatOptions) -> InputResult<FormatOptions> {
+    match unsafe { options.as_ref() } {
+        Some(options) => options.try_into(),
+        None => Ok(FormatOptions::default()),
+    }
+}
+
 /// Copies a Rust string to a C buffer
 ///
+/// # Errors
+///
+/// Returns an `FfiError` if the string contains a null byte, if `buffer_len` is not
+/// positive or exceeds [`MAX_FFI_BUFFER_LEN`], or if the string and its null terminator do
+/// not fit
+///
 /// # Safety
 ///
-/// The buffer must be large enough to hold the string plus null terminator
+/// The buffer must point to at least `buffer_len` writable bytes
 pub unsafe fn copy_string_to_buffer(
     string: &str,
     buffer: *mut c_char,
@@ -59,17 +566,154 @@ pub unsafe fn copy_string_to_buffer(
         .map_err(|e| SssError::FfiError(format!("Failed to create C string: {}", e)))?;
 
     let bytes = c_string.as_bytes_with_nul();
-    if bytes.len() > buffer_len as usize {
+    ensure_fits(bytes.len(), buffer_len)?;
+
+    unsafe {
+        ptr::copy_nonoverlapping(bytes.as_ptr(), buffer as *mut u8, bytes.len());
+    }
+
+    Ok(())
+}
+
+/// Copies binary data to a C buffer, without a terminator
+///
+/// # Returns
+///
+/// The number of bytes written
+///
+/// # Errors
+///
+/// Returns an `FfiError` if `buffer_len` is not positive or exceeds
+/// [`MAX_FFI_BUFFER_LEN`], or if the data does not fit
+///
+/// # Safety
+///
+/// The buffer must point to at least `buffer_len` writable bytes
+pub unsafe fn copy_bytes_to_buffer(
+    bytes: &[u8],
+    buffer: *mut c_uchar,
+    buffer_len: c_int,
+) -> SssResult<c_int> {
+    ensure_fits(bytes.len(), buffer_len)?;
+    // Fits in buffer_len, so it fits in a c_int
+    let written = c_int::try_from(bytes.len())
+        .map_err(|e| SssError::FfiError(format!("Invalid output length: {}", e)))?;
+
+    unsafe {
+        ptr::copy_nonoverlapping(bytes.as_ptr(), buffer, bytes.len());
+    }
+
+    Ok(written)
+}
+
+/// Checks that `needed` bytes fit a caller's buffer of `buffer_len` bytes
+///
+/// A negative length would wrap to a huge `usize`, so lengths are converted with a check
+/// rather than a cast.
+fn ensure_fits(needed: usize, buffer_len: c_int) -> SssResult<()> {
+    let capacity = match usize::try_from(buffer_len) {
+        Ok(0) | Err(_) => {
+            return Err(SssError::FfiError(format!(
+                "Invalid buffer length {}: must be positive",
+                buffer_len
+            )));
+        }
+        Ok(capacity) if capacity > MAX_FFI_BUFFER_LEN => {
+            return Err(SssError::FfiError(format!(
+                "Invalid buffer length {}: exceeds the limit of {} bytes",
+                buffer_len, MAX_FFI_BUFFER_LEN
+            )));
+        }
+        Ok(capacity) => capacity,
+    };
+    if needed > capacity {
         return Err(SssError::FfiError(format!(
             "Buffer too small: need {} bytes, have {}",
-            bytes.len(),
-            buffer_len
+            needed, buffer_len
         )));
     }
+    Ok(())
+}
 
-    unsafe {
-        ptr::copy_nonoverlapping(bytes.as_ptr(), buffer as *mut u8, bytes.len());
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OVERSIZED: c_int = MAX_FFI_BUFFER_LEN as c_int + 1;
+
+    fn copy_string(string: &str, buffer_len: c_int) -> (SssResult<()>, Vec<u8>) {
+        let mut buffer = vec![0xffu8; 8];
+        let result = unsafe {
+            copy_string_to_buffer(string, buffer.as_mut_ptr() as *mut c_char, buffer_len)
+        };
+        (result, buffer)
     }
 
-    Ok(())
+    fn copy_bytes(bytes: &[u8], buffer_len: c_int) -> (SssResult<c_int>, Vec<u8>) {
+        let mut buffer = vec![0xffu8; 8];
+        let result = unsafe { copy_bytes_to_buffer(bytes, buffer.as_mut_ptr(), buffer_len) };
+        (result, buffer)
+    }
+
+    #[test]
+    fn string_copy_rejects_invalid_lengths_without_writing() {
+        for buffer_len in [-1, c_int::MIN, 0, OVERSIZED, c_int::MAX] {
+            let (result, buffer) = copy_string("abc", buffer_len);
+            assert!(result.is_err(), "buffer_len {}", buffer_len);
+            assert_eq!(buffer, [0xff; 8]);
+        }
+    }
+
+    #[test]
+    fn string_copy_needs_room_for_the_terminator() {
+        let (result, buffer) = copy_string("abc", 4);
+        assert!(result.is_ok());
+        assert_eq!(&buffer[..5], b"abc\0\xff");
+
+        let (result, buffer) = copy_string("abc", 3);
+        assert!(result.is_err());
+        assert_eq!(buffer, [0xff; 8]);
+    }
+
+    #[test]
+    fn string_copy_rejects_interior_nul() {
+        assert!(copy_string("a\0b", 8).0.is_err());
+    }
+
+    #[test]
+    fn bytes_copy_rejects_invalid_lengths_without_writing() {
+        for buffer_len in [-1, c_int::MIN, 0, OVERSIZED, c_int::MAX] {
+            let (result, buffer) = copy_bytes(&[1, 2, 3], buffer_len);
+            assert!(result.is_err(), "buffer_len {}", buffer_len);
+            assert_eq!(buffer, [0xff; 8]);
+        }
+    }
+
+    #[test]
+    fn bytes_copy_fills_an_exact_buffer() {
+        let (result, buffer) = copy_bytes(&[1, 0, 3], 3);
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(&buffer[..4], &[1, 0, 3, 0xff]);
+
+        let (result, buffer) = copy_bytes(&[1, 0, 3], 2);
+        assert!(result.is_err());
+        assert_eq!(buffer, [0xff; 8]);
+    }
+
+    #[test]
+    fn largest_buffer_is_accepted() {
+        assert!(ensure_fits(MAX_FFI_BUFFER_LEN, MAX_FFI_BUFFER_LEN as c_int).is_ok());
+        assert!(ensure_fits(1, OVERSIZED).is_err());
+    }
+
+    #[test]
+    fn ui_amount_strings_are_parsed_exactly() {
+        let amount = std::ffi::CString::new("0018446744073.7095516150").unwrap();
+        let amount = unsafe { c_str_to_ui_amount(amount.as_ptr()) }.unwrap();
+        assert_eq!(amount.as_str(), "18446744073.709551615");
+        for invalid in ["1e3", "-1", "1,5", ""] {
+            let invalid = std::ffi::CString::new(invalid).unwrap();
+            assert!(unsafe { c_str_to_ui_amount(invalid.as_ptr()) }.is_err());
+        }
+    }
 }
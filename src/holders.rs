@@ -0,0 +1,263 @@
+//! Token holder snapshots
+
+use crate::RPC_CLIENT;
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::serde_utils::pubkey_string;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_rpc_client_api::request::RpcRequest;
+use solana_rpc_client_api::response::{OptionalContext, RpcKeyedAccount};
+use solana_sdk::hash::hashv;
+use solana_sdk::pubkey::Pubkey;
+use spl_token_2022::extension::StateWithExtensions;
+use spl_token_2022::state::AccountState;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// Size of a token account without extensions
+const TOKEN_ACCOUNT_LEN: u64 = 165;
+
+/// Version tag mixed into the snapshot hash so the format can evolve
+const SNAPSHOT_HASH_VERSION: &[u8] = b"sss-holder-snapshot-v1";
+
+/// Balance of a single token account at the snapshot slot
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HolderBalance {
+    /// The wallet owning the token account
+    #[serde(with = "pubkey_string")]
+    pub owner: Pubkey,
+    /// The token account holding the balance
+    #[serde(with = "pubkey_string")]
+    pub token_account: Pubkey,
+    /// The raw token amount
+    pub amount: u64,
+}
+
+/// Holder balances of a mint captured at a known slot
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HolderSnapshot {
+    /// The mint whose holders were captured
+    #[serde(with = "pubkey_string")]
+    pub mint: Pubkey,
+    /// The slot at which the RPC node evaluated the accounts
+    pub slot: u64,
+    /// Non-zero balances, sorted by owner and token account
+    pub holders: Vec<HolderBalance>,
+    /// Sum of all captured balances
+    pub total_amount: u128,
+    /// Base58 content hash of the snapshot, see [`HolderSnapshot::compute_hash`]
+    pub hash: String,
+}
+
+impl HolderSnapshot {
+    /// Computes the content hash over the mint, slot and every balance
+    ///
+    /// The hash covers the canonical sorted holder list, so two parties capturing the
+    /// same state always obtain the same value.
+    pub fn compute_hash(&self) -> String {
+        let mut lines = Vec::with_capacity(self.holders.len() + 1);
+        lines.push(format!("{}:{}\n", self.mint, self.slot));
+        for holder in &self.holders {
+            lines.push(format!(
+                "{}:{}:{}\n",
+                holder.owner, holder.token_account, holder.amount
+            ));
+        }
+
+        let mut parts: Vec<&[u8]> = vec![SNAPSHOT_HASH_VERSION];
+        parts.extend(lines.iter().map(|l| l.as_bytes()));
+        hashv(&parts).to_string()
+    }
+
+    /// Returns the total balance per owner, suitable as airdrop allocations
+    pub fn allocations(&self) -> Vec<(Pubkey, u64)> {
+        let mut totals: BTreeMap<Pubkey, u64> = BTreeMap::new();
+        for holder in &self.holders {
+            let total = totals.entry(holder.owner).or_insert(0);
+            *total = total.saturating_add(holder.amount);
+        }
+        totals.into_iter().collect()
+    }
+
+    /// Serializes the snapshot to JSON
+    pub fn to_json(&self) -> SssResult<String> {
+        serde_json::to_string_pretty(self).into_sss_error("Failed to serialize token snapshot")
+    }
+
+    /// Parses a snapshot from JSON
+    pub fn from_json(json: &str) -> SssResult<Self> {
+        serde_json::from_str(json).into_sss_error("Failed to parse token snapshot")
+    }
+}
+
+/// Captures the balances of every holder of a mint
+///
+/// The accounts are read at `finalized` commitment, and the slot the node actually used is
+/// recorded in the snapshot. RPC nodes only serve current state, so `slot` is a lower bound:
+/// the request fails if the node has not yet reached it, and the recorded slot tells
+/// verifiers exactly which state was captured.
+///
+/// # Arguments
+///
+/// * `mint` - The mint whose holders should be captured
+/// * `slot` - Optional minimum slot the state must be evaluated at
+///
+/// # Returns
+///
+/// The snapshot with its content hash
+pub fn snapshot_holders_at_slot(mint: Pubkey, slot: Option<u64>) -> SssResult<HolderSnapshot> {
+    snapshot_holders_at_slot_with_client(&RPC_CLIENT, mint, slot)
+}
+
+/// Captures the balances of every holder of a mint like [`snapshot_holders_at_slot`],
+/// querying the given RPC client
+///
+/// # Arguments
+///
+/// * `client` - The RPC client to query
+/// * `mint` - The mint whose holders should be captured
+/// * `slot` - Optional minimum slot the state must be evaluated at
+///
+/// # Returns
+///
+/// The snapshot with its content hash
+pub fn snapshot_holders_at_slot_with_client(
+    client: &RpcClient,
+    mint: Pubkey,
+    slot: Option<u64>,
+) -> SssResult<HolderSnapshot> {
+    let (context_slot, accounts) = mint_token_accounts(client, &mint, slot)?;
+    let mut holders: Vec<HolderBalance> = accounts
+        .into_iter()
+        .filter(|account| account.amount > 0)
+        .map(|account| HolderBalance {
+            owner: account.owner,
+            token_account: account.address,
+            amount: account.amount,
+        })
+        .collect();
+
+    holders.sort_by_key(|h| (h.owner, h.token_account));
+    let total_amount = holders.iter().map(|h| h.amount as u128).sum();
+
+    let mut snapshot = HolderSnapshot {
+        mint,
+        slot: context_slot,
+        holders,
+        total_amount,
+        hash: String::new(),
+    };
+    snapshot.hash = snapshot.compute_hash();
+
+    Ok(snapshot)
+}
+
+/// Verifies a snapshot against a published hash
+///
+/// The hash is recomputed from the snapshot contents, so any modification to the
+/// holder list is detected even if the stored `hash` field was updated.
+///
+/// # Arguments
+///
+/// * `snapshot` - The snapshot to verify
+/// * `expected_hash` - The hash published when the snapshot was taken
+///
+/// # Returns
+///
+/// `true` if the snapshot contents match the expected hash
+pub fn verify_snapshot(snapshot: &HolderSnapshot, expected_hash: &str) -> bool {
+    snapshot.compute_hash() == expected_hash
+}
+
+/// A token account of a mint as enumerated by [`mint_token_accounts`]
+pub(crate) struct MintTokenAccount {
+    pub address: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub frozen: bool,
+    pub delegate: Option<Pubkey>,
+}
+
+/// Lists every token account of a mint, including empty ones, at `finalized` commitment
+///
+/// # Arguments
+///
+/// * `client` - The RPC client to query
+/// * `mint` - The mint whose token accounts should be listed
+/// * `slot` - Optional minimum slot the state must be evaluated at
+///
+/// # Returns
+///
+/// The slot the node evaluated the accounts at, and the accounts
+pub(crate) fn mint_token_accounts(
+    client: &RpcClient,
+    mint: &Pubkey,
+    slot: Option<u64>,
+) -> SssResult<(u64, Vec<MintTokenAccount>)> {
+    let mint_account = client
+        .get_account(mint)
+        .into_sss_error("Failed to get mint account from rpc")?;
+    let token_program = mint_account.owner;
+    if token_program != spl_token::id() && token_program != spl_token_2022::id() {
+        return Err(SssError::TokenError(format!(
+            "Account {} is not a token mint",
+            mint
+        )));
+    }
+
+    // Token-2022 accounts may carry extensions, so only filter on size for legacy accounts
+    let mut filters = vec![json!({ "memcmp": { "offset": 0, "bytes": mint.to_string() } })];
+    if token_program == spl_token::id() {
+        filters.push(json!({ "dataSize": TOKEN_ACCOUNT_LEN }));
+    }
+
+    let mut config = json!({
+        "encoding": "base64",
+        "commitment": "finalized",
+        "withContext": true,
+        "filters": filters,
+    });
+    if let Some(slot) = slot {
+        config["minContextSlot"] = json!(slot);
+    }
+
+    let response: OptionalContext<Vec<RpcKeyedAccount>> = client
+        .send(
+            RpcRequest::GetProgramAccounts,
+            json!([token_program.to_string(), config]),
+        )
+        .into_sss_error("Failed to get token accounts from rpc")?;
+
+    let (context_slot, keyed_accounts) = match response {
+        OptionalContext::Context(response) => (response.context.slot, response.value),
+        OptionalContext::NoContext(_) => {
+            return Err(SssError::RpcError(
+                "RPC node did not return the context slot of the token accounts".to_string(),
+            ));
+        }
+    };
+
+    let mut accounts = Vec::with_capacity(keyed_accounts.len());
+    for keyed in keyed_accounts {
+        let address = Pubkey::from_str(&keyed.pubkey)
+            .into_sss_error("Failed to parse token account address from rpc")?;
+        let data = keyed.account.data.decode().ok_or_else(|| {
+            SssError::RpcError(format!("Failed to decode token account {}", address))
+        })?;
+        let account = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&data)
+            .into_sss_error("Failed to parse token account")?;
+
+        if account.base.mint == *mint {
+            accounts.push(MintTokenAccount {
+                address,
+                owner: account.base.owner,
+                amount: account.base.amount,
+                frozen: account.base.state == AccountState::Frozen,
+                delegate: account.base.delegate.into(),
+            });
+        }
+    }
+
+    Ok((context_slot, accounts))
+}
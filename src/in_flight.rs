@@ -0,0 +1,189 @@
+//! Detection of identical operations running at the same time
+//!
+//! A double-clicked mint button issues two identical calls. Each would get its own
+//! blockhash, so the cluster would accept both. Identical calls are matched on the
+//! operation, a hash of its inputs and the idempotency key, and only the first one runs.
+
+use crate::error::{SssError, SssResult};
+use crate::journal::JournalOperation;
+use crate::load_dotenv;
+use crate::transaction::TxOptions;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use solana_sdk::hash::hash;
+use std::any::Any;
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// Default time a duplicate call waits for the first call to finish
+pub const DEFAULT_IN_FLIGHT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// What a call does while an identical call is still running
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    /// Wait for the running call and return its result instead of sending again
+    #[default]
+    Wait,
+    /// Fail at once with [`SssError::DuplicateInFlight`]
+    Reject,
+}
+
+/// Result of a finished call, shared with the calls that waited for it
+type SharedResult = Result<Arc<dyn Any + Send + Sync>, SssError>;
+
+/// A running call
+#[derive(Default)]
+struct InFlight {
+    result: Mutex<Option<SharedResult>>,
+    finished: Condvar,
+}
+
+lazy_static! {
+    /// Time a duplicate call waits, from `SSS_IN_FLIGHT_TIMEOUT_SECS`
+    static ref IN_FLIGHT_TIMEOUT: Duration = {
+        load_dotenv();
+        env::var("SSS_IN_FLIGHT_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_IN_FLIGHT_TIMEOUT)
+    };
+
+    /// Running calls by key
+    static ref REGISTRY: Mutex<HashMap<String, Arc<InFlight>>> = Mutex::new(HashMap::new());
+}
+
+/// Removes a call from the registry and wakes its waiters, even if the call panics
+struct Registration {
+    key: String,
+    in_flight: Arc<InFlight>,
+}
+
+impl Registration {
+    fn finish(&self, result: SharedResult) {
+        if let Ok(mut slot) = self.in_flight.result.lock() {
+            *slot = Some(result);
+        }
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        if let Ok(mut registry) = REGISTRY.lock() {
+            registry.remove(&self.key);
+        }
+        // Holding the lock ensures no waiter is between checking and sleeping
+        let _slot = self.in_flight.result.lock();
+        self.in_flight.finished.notify_all();
+    }
+}
+
+/// Runs an operation unless an identical one is already running
+///
+/// A duplicate call either waits for the running call and returns a copy of its result,
+/// or fails, depending on [`TxOptions::on_duplicate`].
+///
+/// # Arguments
+///
+/// * `operation` - The kind of operation
+/// * `params` - The operation inputs, e.g. mint, owner and amount
+/// * `options` - The idempotency key and the duplicate policy
+/// * `f` - The operation
+///
+/// # Errors
+///
+/// Returns a `DuplicateInFlight` error if an identical call is running and the policy is
+/// [`DuplicatePolicy::Reject`], if waiting for it timed out, or if it panicked
+pub(crate) fn exclusive<T: Clone + Send + Sync + 'static>(
+    operation: JournalOperation,
+    params: &impl Serialize,
+    options: &TxOptions,
+    f: impl FnOnce() -> SssResult<T>,
+) -> SssResult<T> {
+    let params = serde_json::to_vec(params).unwrap_or_default();
+    let key = format!(
+        "{:?}:{}:{}",
+        operation,
+        hash(&params),
+        options.idempotency_key.as_deref().unwrap_or_default()
+    );
+
+    let (in_flight, running) = {
+        let mut registry = REGISTRY
+            .lock()
+            .map_err(|e| SssError::DuplicateInFlight(format!("Registry poisoned: {}", e)))?;
+        match registry.get(&key) {
+            Some(in_flight) => (in_flight.clone(), true),
+            None => {
+                let in_flight = Arc::new(InFlight::default());
+                registry.insert(key.clone(), in_flight.clone());
+                (in_flight, false)
+            }
+        }
+    };
+
+    if running {
+        return match options.on_duplicate {
+            DuplicatePolicy::Reject => Err(SssError::DuplicateInFlight(format!(
+                "An identical {:?} operation is still unconfirmed",
+                operation
+            ))),
+            DuplicatePolicy::Wait => wait_for(&in_flight, operation),
+        };
+    }
+
+    let registration = Registration { key, in_flight };
+    let result = f();
+    registration.finish(match &result {
+        Ok(value) => Ok(Arc::new(value.clone())),
+        Err(e) => Err(e.clone()),
+    });
+    result
+}
+
+/// Waits for a running call and returns a copy of its result
+fn wait_for<T: Clone + 'static>(in_flight: &InFlight, operation: JournalOperation) -> SssResult<T> {
+    let poisoned = |e: String| SssError::DuplicateInFlight(format!("Registry poisoned: {}", e));
+    let slot = in_flight
+        .result
+        .lock()
+        .map_err(|e| poisoned(e.to_string()))?;
+    // The registration is dropped, waking us, only after the result is stored
+    let (slot, timeout) = in_flight
+        .finished
+        .wait_timeout_while(slot, *IN_FLIGHT_TIMEOUT, |slot| {
+            slot.is_none() && !finished_without_result(in_flight)
+        })
+        .map_err(|e| poisoned(e.to_string()))?;
+
+    match slot.as_ref() {
+        Some(Ok(value)) => value.downcast_ref::<T>().cloned().ok_or_else(|| {
+            SssError::DuplicateInFlight(format!(
+                "An identical {:?} operation returned an unexpected result",
+                operation
+            ))
+        }),
+        Some(Err(e)) => Err(e.clone()),
+        None if timeout.timed_out() => Err(SssError::DuplicateInFlight(format!(
+            "An identical {:?} operation is still unconfirmed after {} seconds",
+            operation,
+            IN_FLIGHT_TIMEOUT.as_secs()
+        ))),
+        None => Err(SssError::DuplicateInFlight(format!(
+            "An identical {:?} operation failed without a result",
+            operation
+        ))),
+    }
+}
+
+/// Whether the running call was removed from the registry without storing a result,
+/// which happens when it panics
+fn finished_without_result(in_flight: &InFlight) -> bool {
+    REGISTRY.lock().is_ok_and(|registry| {
+        !registry
+            .values()
+            .any(|running| std::ptr::eq(running.as_ref(), in_flight))
+    })
+}
@@ -0,0 +1,705 @@
+//! Instruction-level inspection of built transactions
+
+use mpl_token_metadata::errors::MplTokenMetadataError;
+use num_traits::FromPrimitive;
+use serde::Serialize;
+use solana_sdk::{
+    compute_budget,
+    instruction::{CompiledInstruction, InstructionError},
+    message::{Message, VersionedMessage},
+    program_utils::limited_deserialize,
+    pubkey,
+    pubkey::Pubkey,
+    system_instruction::SystemInstruction,
+    system_program,
+    transaction::{Transaction, TransactionError},
+};
+use spl_token_2022::instruction::{AuthorityType, TokenInstruction};
+use std::fmt;
+
+/// The SPL memo program (v2)
+pub const MEMO_PROGRAM_ID: Pubkey = pubkey!("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr");
+
+/// The legacy SPL memo program (v1)
+const MEMO_V1_PROGRAM_ID: Pubkey = pubkey!("Memo1UhkJRfHyvLMcVucJwxXeuD728EqVDDwQDxFMNo");
+
+/// Token metadata instruction discriminators
+const METADATA_CREATE: u8 = 42;
+const METADATA_MINT: u8 = 43;
+const METADATA_TRANSFER: u8 = 49;
+
+/// An account referenced by an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct InspectedAccount {
+    /// The account address
+    #[serde(with = "crate::serde_utils::pubkey_string")]
+    pub pubkey: Pubkey,
+    /// Whether the transaction must be signed by this account
+    pub is_signer: bool,
+    /// Whether the instruction may modify this account
+    pub is_writable: bool,
+}
+
+/// What an instruction does, for the programs this library knows
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum DecodedInstruction {
+    /// System program transfer
+    SystemTransfer { lamports: u64 },
+    /// System program account creation
+    SystemCreateAccount { lamports: u64, space: u64 },
+    /// Any other system program instruction
+    SystemOther,
+    /// Token program mint
+    TokenMintTo { amount: u64 },
+    /// Token program transfer
+    TokenTransfer { amount: u64 },
+    /// Token program burn
+    TokenBurn { amount: u64 },
+    /// Token program account freeze
+    TokenFreeze,
+    /// Token program account thaw
+    TokenThaw,
+    /// Token program authority change
+    TokenSetAuthority { authority_type: String },
+    /// Token program account close
+    TokenCloseAccount,
+    /// Any other token program instruction, by name
+    TokenOther(String),
+    /// Associated token account creation
+    AssociatedTokenCreate { idempotent: bool },
+    /// Token metadata creation
+    MetadataCreate { name: String },
+    /// Token metadata mint
+    MetadataMint { amount: u64 },
+    /// Token metadata transfer, used for programmable NFTs
+    MetadataTransfer { amount: u64 },
+    /// Any other token metadata instruction, by discriminator
+    MetadataOther(u8),
+    /// Memo text
+    Memo(String),
+    /// Compute unit limit request
+    ComputeUnitLimit(u32),
+    /// Compute unit price in micro-lamports
+    ComputeUnitPrice(u64),
+    /// Any other compute budget instruction
+    ComputeBudgetOther,
+    /// An instruction of a program this library does not decode, or malformed data
+    Unknown,
+}
+
+impl fmt::Display for DecodedInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SystemTransfer { lamports } => write!(f, "Transfer {} lamports", lamports),
+            Self::SystemCreateAccount { lamports, space } => write!(
+                f,
+                "Create account with {} lamports and {} bytes",
+                lamports, space
+            ),
+            Self::SystemOther => write!(f, "System instruction"),
+            Self::TokenMintTo { amount } => write!(f, "Mint {} tokens", amount),
+            Self::TokenTransfer { amount } => write!(f, "Transfer {} tokens", amount),
+            Self::TokenBurn { amount } => write!(f, "Burn {} tokens", amount),
+            Self::TokenFreeze => write!(f, "Freeze token account"),
+            Self::TokenThaw => write!(f, "Thaw token account"),
+            Self::TokenSetAuthority { authority_type } => {
+                write!(f, "Set {} authority", authority_type)
+            }
+            Self::TokenCloseAccount => write!(f, "Close token account"),
+            Self::TokenOther(name) => write!(f, "Token instruction {}", name),
+            Self::AssociatedTokenCreate { idempotent: true } => {
+                write!(f, "Create associated token account if missing")
+            }
+            Self::AssociatedTokenCreate { idempotent: false } => {
+                write!(f, "Create associated token account")
+            }
+            Self::MetadataCreate { name } => write!(f, "Create token metadata '{}'", name),
+            Self::MetadataMint { amount } => write!(f, "Mint {} tokens via metadata", amount),
+            Self::MetadataTransfer { amount } => {
+                write!(f, "Transfer {} tokens via metadata", amount)
+            }
+            Self::MetadataOther(discriminator) => {
+                write!(f, "Token metadata instruction {}", discriminator)
+            }
+            Self::Memo(text) => write!(f, "Memo '{}'", text),
+            Self::ComputeUnitLimit(units) => write!(f, "Set compute unit limit to {}", units),
+            Self::ComputeUnitPrice(price) => {
+                write!(f, "Set compute unit price to {} micro-lamports", price)
+            }
+            Self::ComputeBudgetOther => write!(f, "Compute budget instruction"),
+            Self::Unknown => write!(f, "Unknown instruction"),
+        }
+    }
+}
+
+/// A single inspected instruction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InspectedInstruction {
+    /// The program executing the instruction
+    #[serde(with = "crate::serde_utils::pubkey_string")]
+    pub program_id: Pubkey,
+    /// Human-readable program name, if known
+    pub program_name: Option<&'static str>,
+    /// The accounts passed to the instruction, in order
+    pub accounts: Vec<InspectedAccount>,
+    /// What the instruction does
+    pub decoded: DecodedInstruction,
+}
+
+impl InspectedInstruction {
+    /// Returns a one-line description of the instruction
+    pub fn summary(&self) -> String {
+        match self.program_name {
+            Some(name) => format!("{}: {}", name, self.decoded),
+            None => format!("{}: {}", self.program_id, self.decoded),
+        }
+    }
+}
+
+/// Programs, accounts and decoded instructions of a transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TxInspection {
+    /// The account paying the transaction fee
+    #[serde(with = "crate::serde_utils::pubkey_string")]
+    pub fee_payer: Pubkey,
+    /// The instructions, in execution order
+    pub instructions: Vec<InspectedInstruction>,
+}
+
+impl TxInspection {
+    /// Returns the distinct programs the transaction invokes
+    pub fn program_ids(&self) -> Vec<Pubkey> {
+        let mut ids: Vec<Pubkey> = Vec::new();
+        for ix in &self.instructions {
+            if !ids.contains(&ix.program_id) {
+                ids.push(ix.program_id);
+            }
+        }
+        ids
+    }
+
+    /// Returns the accounts any instruction may modify
+    pub fn writable_accounts(&self) -> Vec<Pubkey> {
+        let mut accounts: Vec<Pubkey> = Vec::new();
+        for account in self.instructions.iter().flat_map(|ix| &ix.accounts) {
+            if account.is_writable && !accounts.contains(&account.pubkey) {
+                accounts.push(account.pubkey);
+            }
+        }
+        accounts
+    }
+}
+
+/// Inspects the instructions of a signed or unsigned transaction
+///
+/// # Arguments
+///
+/// * `tx` - The transaction to inspect
+pub fn inspect_transaction(tx: &Transaction) -> TxInspection {
+    inspect_message(&tx.message)
+}
+
+/// Inspects the instructions of a transaction message
+///
+/// # Arguments
+///
+/// * `message` - The message to inspect
+pub fn inspect_message(message: &Message) -> TxInspection {
+    inspect_compiled(
+        &message.account_keys,
+        &message.instructions,
+        |index| message.is_signer(index),
+        |index| message.is_maybe_writable(index, None),
+    )
+}
+
+/// Inspects the instructions of a legacy or versioned message as loaded on chain
+///
+/// # Arguments
+///
+/// * `message` - The message to inspect
+/// * `loaded_addresses` - The writable and then the readonly addresses loaded from lookup
+///   tables, empty for legacy messages
+pub(crate) fn inspect_versioned_message(
+    message: &VersionedMessage,
+    loaded_addresses: &[Pubkey],
+) -> TxInspection {
+    let keys: Vec<Pubkey> = message
+        .static_account_keys()
+        .iter()
+        .chain(loaded_addresses)
+        .copied()
+        .collect();
+    inspect_compiled(
+        &keys,
+        message.instructions(),
+        |index| message.is_signer(index),
+        |index| message.is_maybe_writable(index, None),
+    )
+}
+
+/// Inspects compiled instructions referencing accounts by index into `keys`
+fn inspect_compiled(
+    keys: &[Pubkey],
+    instructions: &[CompiledInstruction],
+    is_signer: impl Fn(usize) -> bool,
+    is_writable: impl Fn(usize) -> bool,
+) -> TxInspection {
+    let key = |index: usize| keys.get(index).copied().unwrap_or_default();
+    let instructions = instructions
+        .iter()
+        .map(|ix| {
+            let program_id = key(ix.program_id_index as usize);
+            let accounts = ix
+                .accounts
+                .iter()
+                .map(|&index| {
+                    let index = index as usize;
+                    InspectedAccount {
+                        pubkey: key(index),
+                        is_signer: is_signer(index),
+                        is_writable: is_writable(index),
+                    }
+                })
+                .collect();
+
+            InspectedInstruction {
+                program_id,
+                program_name: program_name(&program_id),
+                accounts,
+                decoded: decode_instruction(&program_id, &ix.data),
+            }
+        })
+        .collect();
+
+    TxInspection {
+        fee_payer: keys.first().copied().unwrap_or_default(),
+        instructions,
+    }
+}
+
+/// Returns the name of a program this library knows
+pub fn program_name(program_id: &Pubkey) -> Option<&'static str> {
+    if *program_id == system_program::id() {
+        Some("System")
+    } else if *program_id == spl_token::id() {
+        Some("Token")
+    } else if *program_id == spl_token_2022::id() {
+        Some("Token-2022")
+    } else if *program_id == spl_associated_token_account::id() {
+        Some("Associated Token Account")
+    } else if *program_id == mpl_token_metadata::ID {
+        Some("Token Metadata")
+    } else if *program_id == MEMO_PROGRAM_ID || *program_id == MEMO_V1_PROGRAM_ID {
+        Some("Memo")
+    } else if *program_id == compute_budget::id() {
+        Some("Compute Budget")
+    } else {
+        None
+    }
+}
+
+/// Decodes the data of an instruction for the programs this library knows
+///
+/// # Arguments
+///
+/// * `program_id` - The program executing the instruction
+/// * `data` - The instruction data
+pub fn decode_instruction(program_id: &Pubkey, data: &[u8]) -> DecodedInstruction {
+    if *program_id == system_program::id() {
+        decode_system(data)
+    } else if *program_id == spl_token::id() || *program_id == spl_token_2022::id() {
+        decode_token(data)
+    } else if *program_id == spl_associated_token_account::id() {
+        match data.first() {
+            None | Some(0) => DecodedInstruction::AssociatedTokenCreate { idempotent: false },
+            Some(1) => DecodedInstruction::AssociatedTokenCreate { idempotent: true },
+            Some(_) => DecodedInstruction::Unknown,
+        }
+    } else if *program_id == mpl_token_metadata::ID {
+        decode_metadata(data)
+    } else if *program_id == MEMO_PROGRAM_ID || *program_id == MEMO_V1_PROGRAM_ID {
+        DecodedInstruction::Memo(String::from_utf8_lossy(data).into_owned())
+    } else if *program_id == compute_budget::id() {
+        decode_compute_budget(data)
+    } else {
+        DecodedInstruction::Unknown
+    }
+}
+
+/// Describes a custom error code of a program this library knows
+///
+/// # Arguments
+///
+/// * `program_id` - The program that returned the error
+/// * `code` - The custom error code
+///
+/// # Returns
+///
+/// The error name and description, or `None` if the program or code is unknown
+pub fn decode_program_error(program_id: &Pubkey, code: u32) -> Option<String> {
+    if *program_id == spl_token::id() || *program_id == spl_token_2022::id() {
+        spl_token_2022::error::TokenError::from_u32(code).map(|e| format!("{:?}: {}", e, e))
+    } else if *program_id == mpl_token_metadata::ID {
+        MplTokenMetadataError::from_u32(code).map(|e| {
+            let description = e.to_string();
+            if description.trim().is_empty() {
+                format!("{:?}", e)
+            } else {
+                format!("{:?}: {}", e, description.trim())
+            }
+        })
+    } else {
+        None
+    }
+}
+
+/// Describes why a transaction failed in an instruction, naming the program and its error
+///
+/// # Arguments
+///
+/// * `message` - The message of the failed transaction
+/// * `error` - The transaction error reported by the cluster
+///
+/// # Returns
+///
+/// A description if the error is a custom error of a program this library knows
+pub(crate) fn describe_instruction_error(
+    message: &Message,
+    error: &TransactionError,
+) -> Option<String> {
+    let TransactionError::InstructionError(index, InstructionError::Custom(code)) = error else {
+        return None;
+    };
+    let program_id = message.program_id(*index as usize)?;
+    let decoded = decode_program_error(program_id, *code)?;
+    Some(format!(
+        "{} program error in instruction {}: {}",
+        program_name(program_id).unwrap_or("Unknown"),
+        index,
+        decoded
+    ))
+}
+
+fn decode_system(data: &[u8]) -> DecodedInstruction {
+    match limited_deserialize::<SystemInstruction>(data) {
+        Ok(SystemInstruction::Transfer { lamports }) => {
+            DecodedInstruction::SystemTransfer { lamports }
+        }
+        Ok(SystemInstruction::CreateAccount {
+            lamports, space, ..
+        }) => DecodedInstruction::SystemCreateAccount { lamports, space },
+        Ok(_) => DecodedInstruction::SystemOther,
+        Err(_) => DecodedInstruction::Unknown,
+    }
+}
+
+fn decode_token(data: &[u8]) -> DecodedInstruction {
+    // Token-2022 instructions are a superset of the legacy layout, and the deprecated
+    // unchecked transfer still appears in transactions built elsewhere
+    #[allow(deprecated)]
+    match TokenInstruction::unpack(data) {
+        Ok(TokenInstruction::MintTo { amount })
+        | Ok(TokenInstruction::MintToChecked { amount, .. }) => {
+            DecodedInstruction::TokenMintTo { amount }
+        }
+        Ok(TokenInstruction::Transfer { amount })
+        | Ok(TokenInstruction::TransferChecked { amount, .. }) => {
+            DecodedInstruction::TokenTransfer { amount }
+        }
+        Ok(TokenInstruction::Burn { amount })
+        | Ok(TokenInstruction::BurnChecked { amount, .. }) => {
+            DecodedInstruction::TokenBurn { amount }
+        }
+        Ok(TokenInstruction::FreezeAccount) => DecodedInstruction::TokenFreeze,
+        Ok(TokenInstruction::ThawAccount) => DecodedInstruction::TokenThaw,
+        Ok(TokenInstruction::SetAuthority { authority_type, .. }) => {
+            DecodedInstruction::TokenSetAuthority {
+                authority_type: authority_name(&authority_type).to_string(),
+            }
+        }
+        Ok(TokenInstruction::CloseAccount) => DecodedInstruction::TokenCloseAccount,
+        Ok(other) => {
+            let debug = format!("{:?}", other);
+            let name = debug
+                .split(|c: char| !c.is_alphanumeric())
+                .next()
+                .unwrap_or_default();
+            DecodedInstruction::TokenOther(name.to_string())
+        }
+        Err(_) => DecodedInstruction::Unknown,
+    }
+}
+
+fn authority_name(authority_type: &AuthorityType) -> &'static str {
+    match authority_type {
+        AuthorityType::MintTokens => "mint",
+        AuthorityType::FreezeAccount => "freeze",
+        AuthorityType::AccountOwner => "owner",
+        AuthorityType::CloseAccount => "close",
+        _ => "extension",
+    }
+}
+
+fn decode_metadata(data: &[u8]) -> DecodedInstruction {
+    match data.first() {
+        Some(&METADATA_CREATE) => {
+            // discriminator, create_v1 discriminator, then the borsh-encoded asset name
+            let name = data
+                .get(2..6)
+                .and_then(|len| len.try_into().ok())
+                .map(|len| u32::from_le_bytes(len) as usize)
+                .and_then(|len| data.get(6..6 + len))
+                .map(|name| String::from_utf8_lossy(name).into_owned());
+            match name {
+                Some(name) => DecodedInstruction::MetadataCreate { name },
+                None => DecodedInstruction::Unknown,
+            }
+        }
+        Some(&METADATA_MINT) => {
+            // discriminator, mint_v1 discriminator, then the amount
+            match data.get(2..10).and_then(|amount| amount.try_into().ok()) {
+                Some(amount) => DecodedInstruction::MetadataMint {
+                    amount: u64::from_le_bytes(amount),
+                },
+                None => DecodedInstruction::Unknown,
+            }
+        }
+        Some(&METADATA_TRANSFER) => {
+            // discriminator, transfer_v1 discriminator, then the amount
+            match data.get(2..10).and_then(|amount| amount.try_into().ok()) {
+                Some(amount) => DecodedInstruction::MetadataTransfer {
+                    amount: u64::from_le_bytes(amount),
+                },
+                None => DecodedInstruction::Unknown,
+            }
+        }
+        Some(discriminator) => DecodedInstruction::MetadataOther(*discriminator),
+        None => DecodedInstruction::Unknown,
+    }
+}
+
+fn decode_compute_budget(data: &[u8]) -> DecodedInstruction {
+    match data.split_first() {
+        Some((2, units)) => match units.try_into() {
+            Ok(units) => DecodedInstruction::ComputeUnitLimit(u32::from_le_bytes(units)),
+            Err(_) => DecodedInstruction::Unknown,
+        },
+        Some((3, price)) => match price.try_into() {
+            Ok(price) => DecodedInstruction::ComputeUnitPrice(u64::from_le_bytes(price)),
+            Err(_) => DecodedInstruction::Unknown,
+        },
+        Some(_) => DecodedInstruction::ComputeBudgetOther,
+        None => DecodedInstruction::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::{message_from_base64, message_to_base64};
+    use solana_sdk::{
+        compute_budget::ComputeBudgetInstruction, instruction::Instruction, system_instruction,
+    };
+    use std::path::Path;
+
+    /// The payer, mint and owner the golden fixtures are built from
+    const PAYER: Pubkey = pubkey!("AKnL4NNf3DGWZJS6cPknBuEGnVsV4A4m5tgebLHaRSZ9");
+    const MINT: Pubkey = pubkey!("9hSR6S7WPtxmTojgo6GG3k4yDPecgJY292j7xrsUGWBu");
+    const OWNER: Pubkey = pubkey!("GyGKxMyg1p9SsHfm15MkNUu1u9TN2JtTspcdmrtGUdse");
+
+    /// Decodes a message of `fixtures/golden`
+    fn fixture(name: &str) -> Message {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures/golden")
+            .join(format!("{}.b64", name));
+        message_from_base64(std::fs::read_to_string(path).unwrap().trim()).unwrap()
+    }
+
+    fn summaries(inspection: &TxInspection) -> Vec<String> {
+        inspection
+            .instructions
+            .iter()
+            .map(InspectedInstruction::summary)
+            .collect()
+    }
+
+    #[test]
+    fn golden_messages_decode_into_summaries() {
+        let expected: &[(&str, &[&str])] = &[
+            (
+                "create",
+                &["Token Metadata: Create token metadata 'Golden Token'"],
+            ),
+            (
+                "create_token_2022",
+                &["Token Metadata: Create token metadata 'Golden Token'"],
+            ),
+            (
+                "create_freeze_authority",
+                &[
+                    "Token Metadata: Create token metadata 'Golden Token'",
+                    "Token: Set freeze authority",
+                ],
+            ),
+            (
+                "mint",
+                &["Token Metadata: Mint 1000000 tokens via metadata"],
+            ),
+            (
+                "mint_token_2022",
+                &["Token Metadata: Mint 1000000 tokens via metadata"],
+            ),
+            (
+                "mint_frozen",
+                &[
+                    "Token Metadata: Mint 1000000 tokens via metadata",
+                    "Token: Freeze token account",
+                ],
+            ),
+            (
+                "transfer",
+                &[
+                    "Associated Token Account: Create associated token account if missing",
+                    "Token: Transfer 250000 tokens",
+                ],
+            ),
+            (
+                "transfer_token_2022",
+                &[
+                    "Associated Token Account: Create associated token account if missing",
+                    "Token-2022: Transfer 250000 tokens",
+                ],
+            ),
+            ("burn", &["Token: Burn 100000 tokens"]),
+            ("burn_token_2022", &["Token-2022: Burn 100000 tokens"]),
+            (
+                "vault_create",
+                &[
+                    "System: System instruction",
+                    "Token: Token instruction InitializeAccount3",
+                ],
+            ),
+            (
+                "vault_create_token_2022",
+                &[
+                    "System: System instruction",
+                    "Token-2022: Token instruction InitializeImmutableOwner",
+                    "Token-2022: Token instruction InitializeAccount3",
+                ],
+            ),
+        ];
+        for (name, expected) in expected {
+            let inspection = inspect_message(&fixture(name));
+            assert_eq!(inspection.fee_payer, PAYER, "{}", name);
+            assert_eq!(summaries(&inspection), *expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn a_transfer_lists_who_signs_and_what_changes() {
+        let message = fixture("transfer");
+        let inspection = inspect_message(&message);
+        let source = spl_associated_token_account::get_associated_token_address(&PAYER, &MINT);
+        let destination = spl_associated_token_account::get_associated_token_address(&OWNER, &MINT);
+
+        assert_eq!(
+            inspection.program_ids(),
+            vec![spl_associated_token_account::id(), spl_token::id()]
+        );
+        assert_eq!(
+            inspection.writable_accounts(),
+            vec![PAYER, destination, source]
+        );
+        let transfer = &inspection.instructions[1];
+        assert_eq!(
+            transfer.decoded,
+            DecodedInstruction::TokenTransfer { amount: 250_000 }
+        );
+        assert_eq!(
+            transfer.accounts,
+            vec![
+                InspectedAccount {
+                    pubkey: source,
+                    is_signer: false,
+                    is_writable: true,
+                },
+                InspectedAccount {
+                    pubkey: MINT,
+                    is_signer: false,
+                    is_writable: false,
+                },
+                InspectedAccount {
+                    pubkey: destination,
+                    is_signer: false,
+                    is_writable: true,
+                },
+                InspectedAccount {
+                    pubkey: PAYER,
+                    is_signer: true,
+                    is_writable: true,
+                },
+            ]
+        );
+        // A signed transaction is inspected through its message
+        let tx = Transaction::new_unsigned(message);
+        assert_eq!(inspect_transaction(&tx), inspection);
+    }
+
+    #[test]
+    fn a_mint_through_metadata_only_lets_the_payer_sign() {
+        let inspection = inspect_message(&fixture("mint"));
+        let token_account =
+            spl_associated_token_account::get_associated_token_address(&OWNER, &MINT);
+        assert_eq!(inspection.program_ids(), vec![mpl_token_metadata::ID]);
+        assert_eq!(
+            inspection.writable_accounts(),
+            vec![token_account, MINT, PAYER]
+        );
+        let signers: Vec<Pubkey> = inspection.instructions[0]
+            .accounts
+            .iter()
+            .filter(|account| account.is_signer)
+            .map(|account| account.pubkey)
+            .collect();
+        assert_eq!(signers, vec![PAYER, PAYER]);
+        assert_eq!(
+            inspection.instructions[0].decoded,
+            DecodedInstruction::MetadataMint { amount: 1_000_000 }
+        );
+    }
+
+    #[test]
+    fn memo_compute_budget_and_unknown_programs_decode() {
+        let unknown = Pubkey::new_from_array([7; 32]);
+        let instructions = [
+            ComputeBudgetInstruction::set_compute_unit_limit(200_000),
+            ComputeBudgetInstruction::set_compute_unit_price(5_000),
+            Instruction::new_with_bytes(MEMO_PROGRAM_ID, b"invoice 42", vec![]),
+            Instruction::new_with_bytes(MEMO_V1_PROGRAM_ID, b"legacy", vec![]),
+            system_instruction::transfer(&PAYER, &OWNER, 1_000),
+            Instruction::new_with_bytes(unknown, &[1, 2, 3], vec![]),
+            // Truncated data is reported as unknown rather than misread
+            Instruction::new_with_bytes(spl_token::id(), &[3, 1], vec![]),
+            Instruction::new_with_bytes(compute_budget::id(), &[2, 1], vec![]),
+            Instruction::new_with_bytes(mpl_token_metadata::ID, &[METADATA_MINT, 0], vec![]),
+        ];
+        let message = Message::new(&instructions, Some(&PAYER));
+        // The message survives the same encoding as the fixtures
+        let decoded = message_from_base64(&message_to_base64(&message)).unwrap();
+        assert_eq!(
+            summaries(&inspect_message(&decoded)),
+            vec![
+                "Compute Budget: Set compute unit limit to 200000".to_string(),
+                "Compute Budget: Set compute unit price to 5000 micro-lamports".to_string(),
+                "Memo: Memo 'invoice 42'".to_string(),
+                "Memo: Memo 'legacy'".to_string(),
+                "System: Transfer 1000 lamports".to_string(),
+                format!("{}: Unknown instruction", unknown),
+                "Token: Unknown instruction".to_string(),
+                "Compute Budget: Unknown instruction".to_string(),
+                "Token Metadata: Unknown instruction".to_string(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,654 @@
+//! Checked construction of token metadata instructions
+//!
+//! The metadata program builders panic when a required account is missing. The helpers
+//! here check every required account and the combinations each token standard needs,
+//! and report problems as a `TokenError` naming the missing account or field instead.
+
+use crate::error::{SssError, SssResult};
+use mpl_token_metadata::instructions::{
+    CreateV1Builder, MintV1Builder, TransferV1Builder, UpdateMetadataAccountV2Builder,
+    VerifyCreatorV1Builder,
+};
+use mpl_token_metadata::types::{Collection, Creator, TokenStandard};
+#[cfg(feature = "http-metadata")]
+use mpl_token_metadata::{instructions::UpdateV1Builder, types::Data};
+use solana_sdk::{instruction::Instruction, pubkey, pubkey::Pubkey};
+
+/// The Metaplex token authorization rules program enforcing pNFT rule sets
+pub(crate) const AUTH_RULES_PROGRAM_ID: Pubkey =
+    pubkey!("auth9SigNpDKz4sJJ1DfCTuZrZNSAgh9sFD3rboVmgg");
+
+/// Accounts and arguments of a `CreateV1` instruction
+#[derive(Debug, Clone)]
+pub(crate) struct CreateV1Params {
+    pub metadata: Option<Pubkey>,
+    /// Required for non-fungible standards, not allowed for fungible ones
+    pub master_edition: Option<Pubkey>,
+    pub mint: Option<Pubkey>,
+    /// Whether the instruction creates the mint account, which then has to sign; false
+    /// for a mint that already exists, whose mint authority must be `authority`
+    pub create_mint: bool,
+    pub authority: Option<Pubkey>,
+    pub payer: Option<Pubkey>,
+    pub update_authority: Option<Pubkey>,
+    pub name: Option<String>,
+    pub symbol: String,
+    pub uri: Option<String>,
+    pub seller_fee_basis_points: u16,
+    pub token_standard: TokenStandard,
+    /// Must be unset or 0 for non-fungible standards
+    pub decimals: Option<u8>,
+    pub collection: Option<Collection>,
+    /// The metadata program records the update authority as sole creator when unset
+    pub creators: Option<Vec<Creator>>,
+    pub spl_token_program: Pubkey,
+}
+
+/// Accounts and arguments of a `MintV1` instruction
+#[derive(Debug, Clone)]
+pub(crate) struct MintV1Params {
+    pub token: Option<Pubkey>,
+    pub token_owner: Option<Pubkey>,
+    pub metadata: Option<Pubkey>,
+    /// Required for non-fungible standards
+    pub master_edition: Option<Pubkey>,
+    /// Required for programmable non-fungible standards
+    pub token_record: Option<Pubkey>,
+    pub mint: Option<Pubkey>,
+    pub authority: Option<Pubkey>,
+    pub payer: Option<Pubkey>,
+    /// Must be 1 for non-fungible standards
+    pub amount: u64,
+    pub token_standard: TokenStandard,
+    pub spl_token_program: Pubkey,
+}
+
+/// Accounts and arguments of a `TransferV1` instruction
+#[derive(Debug, Clone)]
+pub(crate) struct TransferV1Params {
+    pub token: Option<Pubkey>,
+    pub token_owner: Option<Pubkey>,
+    pub destination_token: Option<Pubkey>,
+    pub destination_owner: Option<Pubkey>,
+    pub metadata: Option<Pubkey>,
+    /// Required for non-fungible standards
+    pub edition: Option<Pubkey>,
+    /// Required for programmable non-fungible standards
+    pub token_record: Option<Pubkey>,
+    /// Required for programmable non-fungible standards
+    pub destination_token_record: Option<Pubkey>,
+    pub mint: Option<Pubkey>,
+    pub authority: Option<Pubkey>,
+    pub payer: Option<Pubkey>,
+    /// The rule set of a programmable non-fungible, if it has one
+    pub authorization_rules: Option<Pubkey>,
+    /// Must be 1 for non-fungible standards
+    pub amount: u64,
+    pub token_standard: TokenStandard,
+    pub spl_token_program: Pubkey,
+}
+
+/// Builds a `CreateV1` instruction
+///
+/// # Errors
+///
+/// Returns a `TokenError` naming the missing account or field, or the invalid combination
+/// for the token standard
+pub(crate) fn create_v1(params: CreateV1Params) -> SssResult<Instruction> {
+    const IX: &str = "CreateV1";
+    let metadata = required(params.metadata, IX, "metadata")?;
+    let mint = required(params.mint, IX, "mint")?;
+    let authority = required(params.authority, IX, "authority")?;
+    let payer = required(params.payer, IX, "payer")?;
+    let update_authority = required(params.update_authority, IX, "update_authority")?;
+    let name = required(params.name, IX, "name")?;
+    let uri = required(params.uri, IX, "uri")?;
+
+    if is_non_fungible(params.token_standard) {
+        required_for(
+            params.master_edition,
+            IX,
+            "master_edition",
+            params.token_standard,
+        )?;
+        if params.decimals.is_some_and(|d| d != 0) {
+            return Err(SssError::TokenError(format!(
+                "{} for {:?} tokens requires 0 decimals",
+                IX, params.token_standard
+            )));
+        }
+    } else if params.master_edition.is_some() {
+        return Err(SssError::TokenError(format!(
+            "{} for {:?} tokens does not take a master_edition account",
+            IX, params.token_standard
+        )));
+    }
+
+    let mut create = CreateV1Builder::new();
+    create
+        .metadata(metadata)
+        .master_edition(params.master_edition)
+        .mint(mint, params.create_mint)
+        .authority(authority)
+        .payer(payer)
+        .update_authority(update_authority, false)
+        .name(name)
+        .uri(uri)
+        .seller_fee_basis_points(params.seller_fee_basis_points)
+        .symbol(params.symbol)
+        .token_standard(params.token_standard)
+        .spl_token_program(Some(params.spl_token_program));
+    if let Some(decimals) = params.decimals {
+        create.decimals(decimals);
+    }
+    if let Some(collection) = params.collection {
+        create.collection(collection);
+    }
+    if let Some(creators) = params.creators {
+        create.creators(creators);
+    }
+    Ok(create.instruction())
+}
+
+/// Builds a `MintV1` instruction
+///
+/// # Errors
+///
+/// Returns a `TokenError` naming the missing account, or the invalid amount for the
+/// token standard
+pub(crate) fn mint_v1(params: MintV1Params) -> SssResult<Instruction> {
+    const IX: &str = "MintV1";
+    let token = required(params.token, IX, "token")?;
+    let metadata = required(params.metadata, IX, "metadata")?;
+    let mint = required(params.mint, IX, "mint")?;
+    let authority = required(params.authority, IX, "authority")?;
+    let payer = required(params.payer, IX, "payer")?;
+
+    if is_non_fungible(params.token_standard) {
+        required_for(
+            params.master_edition,
+            IX,
+            "master_edition",
+            params.token_standard,
+        )?;
+        if params.amount != 1 {
+            return Err(SssError::TokenError(format!(
+                "{} for {:?} tokens must mint exactly 1, not {}",
+                IX, params.token_standard, params.amount
+            )));
+        }
+    }
+    if matches!(
+        params.token_standard,
+        TokenStandard::ProgrammableNonFungible | TokenStandard::ProgrammableNonFungibleEdition
+    ) {
+        required_for(
+            params.token_record,
+            IX,
+            "token_record",
+            params.token_standard,
+        )?;
+    }
+
+    Ok(MintV1Builder::new()
+        .token(token)
+        .token_owner(params.token_owner)
+        .metadata(metadata)
+        .master_edition(params.master_edition)
+        .token_record(params.token_record)
+        .mint(mint)
+        .authority(authority)
+        .payer(payer)
+        .amount(params.amount)
+        .spl_token_program(params.spl_token_program)
+        .instruction())
+}
+
+/// Builds a `TransferV1` instruction
+///
+/// The destination token account is created by the metadata program if it does not exist.
+///
+/// # Errors
+///
+/// Returns a `TokenError` naming the missing account, or the invalid amount for the
+/// token standard
+pub(crate) fn transfer_v1(params: TransferV1Params) -> SssResult<Instruction> {
+    const IX: &str = "TransferV1";
+    let token = required(params.token, IX, "token")?;
+    let token_owner = required(params.token_owner, IX, "token_owner")?;
+    let destination_token = required(params.destination_token, IX, "destination_token")?;
+    let destination_owner = required(params.destination_owner, IX, "destination_owner")?;
+    let metadata = required(params.metadata, IX, "metadata")?;
+    let mint = required(params.mint, IX, "mint")?;
+    let authority = required(params.authority, IX, "authority")?;
+    let payer = required(params.payer, IX, "payer")?;
+
+    if is_non_fungible(params.token_standard) {
+        required_for(params.edition, IX, "edition", params.token_standard)?;
+        if params.amount != 1 {
+            return Err(SssError::TokenError(format!(
+                "{} for {:?} tokens must transfer exactly 1, not {}",
+                IX, params.token_standard, params.amount
+            )));
+        }
+    }
+    if matches!(
+        params.token_standard,
+        TokenStandard::ProgrammableNonFungible | TokenStandard::ProgrammableNonFungibleEdition
+    ) {
+        required_for(
+            params.token_record,
+            IX,
+            "token_record",
+            params.token_standard,
+        )?;
+        required_for(
+            params.destination_token_record,
+            IX,
+            "destination_token_record",
+            params.token_standard,
+        )?;
+    }
+
+    Ok(TransferV1Builder::new()
+        .token(token)
+        .token_owner(token_owner)
+        .destination_token(destination_token)
+        .destination_owner(destination_owner)
+        .mint(mint)
+        .metadata(metadata)
+        .edition(params.edition)
+        .token_record(params.token_record)
+        .destination_token_record(params.destination_token_record)
+        .authority(authority)
+        .payer(payer)
+        .spl_token_program(params.spl_token_program)
+        .spl_ata_program(spl_associated_token_account::id())
+        .authorization_rules_program(params.authorization_rules.map(|_| AUTH_RULES_PROGRAM_ID))
+        .authorization_rules(params.authorization_rules)
+        .amount(params.amount)
+        .instruction())
+}
+
+/// Builds an `UpdateMetadataAccountV2` instruction changing only the update authority
+///
+/// # Errors
+///
+/// Returns a `TokenError` naming the missing account
+pub(crate) fn update_authority_v2(
+    metadata: Option<Pubkey>,
+    update_authority: Option<Pubkey>,
+    new_update_authority: Pubkey,
+) -> SssResult<Instruction> {
+    const IX: &str = "UpdateMetadataAccountV2";
+    let metadata = required(metadata, IX, "metadata")?;
+    let update_authority = required(update_authority, IX, "update_authority")?;
+
+    Ok(UpdateMetadataAccountV2Builder::new()
+        .metadata(metadata)
+        .update_authority(update_authority)
+        .new_update_authority(new_update_authority)
+        .instruction())
+}
+
+/// Builds an `UpdateV1` instruction replacing the name, symbol, URI, royalty and creators
+/// of a fungible token
+///
+/// # Errors
+///
+/// Returns a `TokenError` naming the missing account
+#[cfg(feature = "http-metadata")]
+pub(crate) fn update_data_v1(
+    metadata: Option<Pubkey>,
+    mint: Option<Pubkey>,
+    authority: Option<Pubkey>,
+    payer: Option<Pubkey>,
+    data: Data,
+) -> SssResult<Instruction> {
+    const IX: &str = "UpdateV1";
+    let metadata = required(metadata, IX, "metadata")?;
+    let mint = required(mint, IX, "mint")?;
+    let authority = required(authority, IX, "authority")?;
+    let payer = required(payer, IX, "payer")?;
+
+    Ok(UpdateV1Builder::new()
+        .authority(authority)
+        .mint(mint)
+        .metadata(metadata)
+        .payer(payer)
+        .data(data)
+        .instruction())
+}
+
+/// Builds a `VerifyCreatorV1` instruction marking the authority as a verified creator
+///
+/// # Errors
+///
+/// Returns a `TokenError` naming the missing account
+pub(crate) fn verify_creator_v1(
+    metadata: Option<Pubkey>,
+    authority: Option<Pubkey>,
+) -> SssResult<Instruction> {
+    const IX: &str = "VerifyCreatorV1";
+    let metadata = required(metadata, IX, "metadata")?;
+    let authority = required(authority, IX, "authority")?;
+
+    Ok(VerifyCreatorV1Builder::new()
+        .metadata(metadata)
+        .authority(authority)
+        .instruction())
+}
+
+/// Returns whether a standard describes a non-fungible token with a master edition
+fn is_non_fungible(standard: TokenStandard) -> bool {
+    matches!(
+        standard,
+        TokenStandard::NonFungible
+            | TokenStandard::NonFungibleEdition
+            | TokenStandard::ProgrammableNonFungible
+            | TokenStandard::ProgrammableNonFungibleEdition
+    )
+}
+
+/// Unwraps a required account or field of an instruction
+fn required<T>(value: Option<T>, instruction: &str, field: &str) -> SssResult<T> {
+    value.ok_or_else(|| {
+        SssError::TokenError(format!(
+            "{} is missing the required {} account or field",
+            instruction, field
+        ))
+    })
+}
+
+/// Unwraps an account that the token standard makes required
+fn required_for<T>(
+    value: Option<T>,
+    instruction: &str,
+    field: &str,
+    standard: TokenStandard,
+) -> SssResult<T> {
+    value.ok_or_else(|| {
+        SssError::TokenError(format!(
+            "{} for {:?} tokens requires the {} account",
+            instruction, standard, field
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STANDARDS: [TokenStandard; 3] = [
+        TokenStandard::Fungible,
+        TokenStandard::NonFungible,
+        TokenStandard::ProgrammableNonFungible,
+    ];
+
+    /// A field of some params and how to leave it out
+    type Removal<P> = (&'static str, fn(&mut P));
+
+    fn key(byte: u8) -> Option<Pubkey> {
+        Some(Pubkey::new_from_array([byte; 32]))
+    }
+
+    fn non_fungible(standard: TokenStandard) -> bool {
+        standard != TokenStandard::Fungible
+    }
+
+    fn create(standard: TokenStandard) -> CreateV1Params {
+        CreateV1Params {
+            metadata: key(1),
+            master_edition: key(2).filter(|_| non_fungible(standard)),
+            mint: key(3),
+            create_mint: true,
+            authority: key(4),
+            payer: key(4),
+            update_authority: key(4),
+            name: Some("Pallet".to_string()),
+            symbol: "PLT".to_string(),
+            uri: Some("https://example.com/pallet.json".to_string()),
+            seller_fee_basis_points: 0,
+            token_standard: standard,
+            decimals: Some(if non_fungible(standard) { 0 } else { 6 }),
+            collection: None,
+            creators: None,
+            spl_token_program: spl_token::id(),
+        }
+    }
+
+    fn mint(standard: TokenStandard) -> MintV1Params {
+        MintV1Params {
+            token: key(5),
+            token_owner: key(6),
+            metadata: key(1),
+            master_edition: key(2).filter(|_| non_fungible(standard)),
+            token_record: key(7).filter(|_| standard == TokenStandard::ProgrammableNonFungible),
+            mint: key(3),
+            authority: key(4),
+            payer: key(4),
+            amount: 1,
+            token_standard: standard,
+            spl_token_program: spl_token::id(),
+        }
+    }
+
+    fn transfer(standard: TokenStandard) -> TransferV1Params {
+        let programmable = standard == TokenStandard::ProgrammableNonFungible;
+        TransferV1Params {
+            token: key(5),
+            token_owner: key(4),
+            destination_token: key(8),
+            destination_owner: key(6),
+            metadata: key(1),
+            edition: key(2).filter(|_| non_fungible(standard)),
+            token_record: key(7).filter(|_| programmable),
+            destination_token_record: key(9).filter(|_| programmable),
+            mint: key(3),
+            authority: key(4),
+            payer: key(4),
+            authorization_rules: None,
+            amount: 1,
+            token_standard: standard,
+            spl_token_program: spl_token::id(),
+        }
+    }
+
+    fn assert_refused(result: SssResult<Instruction>, expected: &str) {
+        match result {
+            Err(SssError::TokenError(message)) => assert_eq!(message, expected),
+            other => panic!("built without a required account: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn complete_params_build_for_every_standard() {
+        for standard in STANDARDS {
+            let create = create_v1(create(standard)).unwrap();
+            assert_eq!(create.program_id, mpl_token_metadata::ID);
+            let accounts: Vec<Pubkey> = create.accounts.iter().map(|a| a.pubkey).collect();
+            assert_eq!(
+                accounts.contains(&key(2).unwrap()),
+                non_fungible(standard),
+                "{:?}",
+                standard
+            );
+            mint_v1(mint(standard)).unwrap();
+            transfer_v1(transfer(standard)).unwrap();
+        }
+    }
+
+    #[test]
+    fn every_missing_account_of_create_is_named() {
+        for standard in STANDARDS {
+            let cases: [Removal<CreateV1Params>; 7] = [
+                ("metadata", |p| p.metadata = None),
+                ("mint", |p| p.mint = None),
+                ("authority", |p| p.authority = None),
+                ("payer", |p| p.payer = None),
+                ("update_authority", |p| p.update_authority = None),
+                ("name", |p| p.name = None),
+                ("uri", |p| p.uri = None),
+            ];
+            for (field, remove) in cases {
+                let mut params = create(standard);
+                remove(&mut params);
+                assert_refused(
+                    create_v1(params),
+                    &format!(
+                        "CreateV1 is missing the required {} account or field",
+                        field
+                    ),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn create_checks_the_master_edition_against_the_standard() {
+        for standard in [
+            TokenStandard::NonFungible,
+            TokenStandard::ProgrammableNonFungible,
+        ] {
+            let mut params = create(standard);
+            params.master_edition = None;
+            assert_refused(
+                create_v1(params),
+                &format!(
+                    "CreateV1 for {:?} tokens requires the master_edition account",
+                    standard
+                ),
+            );
+            let mut params = create(standard);
+            params.decimals = Some(2);
+            assert_refused(
+                create_v1(params),
+                &format!("CreateV1 for {:?} tokens requires 0 decimals", standard),
+            );
+        }
+        let mut params = create(TokenStandard::Fungible);
+        params.master_edition = key(2);
+        assert_refused(
+            create_v1(params),
+            "CreateV1 for Fungible tokens does not take a master_edition account",
+        );
+    }
+
+    #[test]
+    fn every_missing_account_of_mint_is_named() {
+        for standard in STANDARDS {
+            let cases: [Removal<MintV1Params>; 5] = [
+                ("token", |p| p.token = None),
+                ("metadata", |p| p.metadata = None),
+                ("mint", |p| p.mint = None),
+                ("authority", |p| p.authority = None),
+                ("payer", |p| p.payer = None),
+            ];
+            for (field, remove) in cases {
+                let mut params = mint(standard);
+                remove(&mut params);
+                assert_refused(
+                    mint_v1(params),
+                    &format!("MintV1 is missing the required {} account or field", field),
+                );
+            }
+        }
+
+        // Only what the standard needs is required
+        let mut params = mint(TokenStandard::NonFungible);
+        params.master_edition = None;
+        assert_refused(
+            mint_v1(params),
+            "MintV1 for NonFungible tokens requires the master_edition account",
+        );
+        let mut params = mint(TokenStandard::ProgrammableNonFungible);
+        params.token_record = None;
+        assert_refused(
+            mint_v1(params),
+            "MintV1 for ProgrammableNonFungible tokens requires the token_record account",
+        );
+        let mut params = mint(TokenStandard::NonFungible);
+        params.amount = 2;
+        assert_refused(
+            mint_v1(params),
+            "MintV1 for NonFungible tokens must mint exactly 1, not 2",
+        );
+        let mut params = mint(TokenStandard::Fungible);
+        params.amount = 1_000;
+        mint_v1(params).unwrap();
+    }
+
+    #[test]
+    fn every_missing_account_of_transfer_is_named() {
+        for standard in STANDARDS {
+            let cases: [Removal<TransferV1Params>; 8] = [
+                ("token", |p| p.token = None),
+                ("token_owner", |p| p.token_owner = None),
+                ("destination_token", |p| p.destination_token = None),
+                ("destination_owner", |p| p.destination_owner = None),
+                ("metadata", |p| p.metadata = None),
+                ("mint", |p| p.mint = None),
+                ("authority", |p| p.authority = None),
+                ("payer", |p| p.payer = None),
+            ];
+            for (field, remove) in cases {
+                let mut params = transfer(standard);
+                remove(&mut params);
+                assert_refused(
+                    transfer_v1(params),
+                    &format!(
+                        "TransferV1 is missing the required {} account or field",
+                        field
+                    ),
+                );
+            }
+        }
+
+        let mut params = transfer(TokenStandard::NonFungible);
+        params.edition = None;
+        assert_refused(
+            transfer_v1(params),
+            "TransferV1 for NonFungible tokens requires the edition account",
+        );
+        let cases: [Removal<TransferV1Params>; 2] = [
+            ("token_record", |p| p.token_record = None),
+            ("destination_token_record", |p| {
+                p.destination_token_record = None
+            }),
+        ];
+        for (field, remove) in cases {
+            let mut params = transfer(TokenStandard::ProgrammableNonFungible);
+            remove(&mut params);
+            assert_refused(
+                transfer_v1(params),
+                &format!(
+                    "TransferV1 for ProgrammableNonFungible tokens requires the {} account",
+                    field
+                ),
+            );
+        }
+        let mut params = transfer(TokenStandard::ProgrammableNonFungible);
+        params.amount = 0;
+        assert_refused(
+            transfer_v1(params),
+            "TransferV1 for ProgrammableNonFungible tokens must transfer exactly 1, not 0",
+        );
+    }
+
+    #[test]
+    fn other_instructions_name_their_missing_accounts() {
+        assert_refused(
+            update_authority_v2(None, key(4), Pubkey::new_unique()),
+            "UpdateMetadataAccountV2 is missing the required metadata account or field",
+        );
+        assert_refused(
+            update_authority_v2(key(1), None, Pubkey::new_unique()),
+            "UpdateMetadataAccountV2 is missing the required update_authority account or field",
+        );
+        assert_refused(
+            verify_creator_v1(key(1), None),
+            "VerifyCreatorV1 is missing the required authority account or field",
+        );
+        verify_creator_v1(key(1), key(4)).unwrap();
+    }
+}
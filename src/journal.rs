@@ -0,0 +1,694 @@
+//! Write-ahead journal of the transactions sent by mutation operations
+
+use crate::dedup::{self, dedup_enabled};
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::journal_audit::{chained_line, last_line_hash};
+use crate::roles::role_policies_enabled;
+use crate::transaction::TxOptions;
+use crate::{RPC_CLIENT, load_dotenv};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_rpc_client_api::client_error::Error as ClientError;
+use solana_sdk::{
+    commitment_config::CommitmentConfig, hash::Hash, hash::hash, signature::Signature,
+};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+lazy_static! {
+    /// File the journal is appended to, from `SSS_JOURNAL_PATH`
+    static ref JOURNAL_PATH: Option<PathBuf> = {
+        load_dotenv();
+        env::var("SSS_JOURNAL_PATH").ok().map(PathBuf::from)
+    };
+}
+
+thread_local! {
+    /// The operation whose transactions are being sent on this thread
+    static CURRENT_OPERATION: RefCell<Option<OperationContext>> = const { RefCell::new(None) };
+}
+
+/// Mutation operations recorded in the journal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JournalOperation {
+    /// Token creation, with or without an initial mint
+    CreateToken,
+    /// Minting to the associated account of a wallet
+    MintToken,
+    /// Minting into an existing token account
+    MintToAccount,
+    /// Minting several tokens in one transaction
+    MintBasket,
+    /// A batch of an airdrop
+    Airdrop,
+    /// Submission of a countersigned claim
+    SubmitClaim,
+    /// A batch of an authority rotation
+    RotateAuthorities,
+    /// Transfer of an asset held by the payer
+    TransferAsset,
+    /// Deposit into a partner vault
+    FundVault,
+    /// Withdrawal from a partner vault
+    WithdrawFromVault,
+    /// A batch of a wallet cleanup burning tokens and closing their accounts
+    BurnAssets,
+    /// A batch of a dust sweep burning or collecting small balances
+    SweepDust,
+    /// Burning tokens held by the payer
+    BurnTokens,
+    /// Funding the escrow of a claim link
+    CreateClaimLink,
+    /// Redemption of a claim link
+    RedeemClaimLink,
+    /// Return of the tokens of an unredeemed claim link to the payer
+    ReclaimClaimLink,
+    /// Submission of a partner transaction co-signed by the payer
+    CosignExternal,
+    /// Verification of the payer as a creator of a token
+    VerifyCreator,
+    /// Creation of the metadata of a mint created by another tool
+    AttachMetadata,
+    /// A batch of a consolidation of the payer's balances into a treasury wallet
+    ConsolidateBalances,
+    /// The on-chain update of a token's metadata URI during a metadata swap
+    SwapMetadata,
+    /// Closing of empty token accounts created by a flow that failed later on
+    RollbackCreatedAccounts,
+    /// Signing of an off-chain message with the payer, which sends no transaction
+    SignMessage,
+    /// Signing of structured data such as a coupon, which sends no transaction
+    SignStructured,
+}
+
+impl JournalOperation {
+    /// Every operation, in declaration order
+    pub const ALL: [JournalOperation; 24] = [
+        JournalOperation::CreateToken,
+        JournalOperation::MintToken,
+        JournalOperation::MintToAccount,
+        JournalOperation::MintBasket,
+        JournalOperation::Airdrop,
+        JournalOperation::SubmitClaim,
+        JournalOperation::RotateAuthorities,
+        JournalOperation::TransferAsset,
+        JournalOperation::FundVault,
+        JournalOperation::WithdrawFromVault,
+        JournalOperation::BurnAssets,
+        JournalOperation::SweepDust,
+        JournalOperation::BurnTokens,
+        JournalOperation::CreateClaimLink,
+        JournalOperation::RedeemClaimLink,
+        JournalOperation::ReclaimClaimLink,
+        JournalOperation::CosignExternal,
+        JournalOperation::VerifyCreator,
+        JournalOperation::AttachMetadata,
+        JournalOperation::ConsolidateBalances,
+        JournalOperation::SwapMetadata,
+        JournalOperation::RollbackCreatedAccounts,
+        JournalOperation::SignMessage,
+        JournalOperation::SignStructured,
+    ];
+
+    /// Returns the snake_case name the operation is written as, e.g. `mint_token`
+    pub fn name(&self) -> String {
+        match serde_json::to_value(self) {
+            Ok(Value::String(name)) => name,
+            _ => format!("{:?}", self),
+        }
+    }
+}
+
+/// State of a journaled transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JournalStatus {
+    /// The transaction was about to be sent and its outcome is not known yet
+    Sent,
+    /// The transaction was confirmed
+    Confirmed,
+    /// The cluster rejected the transaction or it failed on-chain
+    Failed,
+    /// The blockhash expired before the transaction landed, so it never will
+    Expired,
+    /// The transaction was not found, but it may have landed before the history of the RPC
+    /// node starts; see [`recover_in_flight`](crate::recover_in_flight). Needs a manual
+    /// review.
+    Unknown,
+}
+
+/// A transaction sent by a mutation operation
+///
+/// The journal is append-only: every change of status appends the whole entry again, and
+/// the last line for a signature is the current state. Each line also carries the hash
+/// chain and audit signature checked by [`verify_journal`](crate::verify_journal).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// The operation that sent the transaction
+    pub operation: JournalOperation,
+    /// Hash of the operation parameters, equal for repeated calls with the same input
+    pub params_hash: String,
+    /// The idempotency key from the transaction options, if any
+    pub idempotency_key: Option<String>,
+    /// The transaction signature
+    pub signature: String,
+    /// The blockhash the transaction was signed with
+    pub blockhash: String,
+    /// The current state of the transaction
+    pub status: JournalStatus,
+    /// Why the transaction failed, or the last send error while it is unconfirmed
+    pub error: Option<String>,
+    /// Unix time in seconds the entry was first written
+    pub created_at: u64,
+    /// Unix time in seconds of the last status change
+    pub updated_at: u64,
+}
+
+/// Criteria selecting journal entries; unset fields match everything
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JournalFilter {
+    /// Only entries of this operation
+    pub operation: Option<JournalOperation>,
+    /// Only entries in this state
+    pub status: Option<JournalStatus>,
+    /// Only entries carrying this idempotency key
+    pub idempotency_key: Option<String>,
+    /// Only entries with this parameter hash
+    pub params_hash: Option<String>,
+    /// Only entries created at or after this Unix time in seconds
+    pub since: Option<u64>,
+}
+
+impl JournalFilter {
+    fn matches(&self, entry: &JournalEntry) -> bool {
+        self.operation.is_none_or(|op| entry.operation == op)
+            && self.status.is_none_or(|status| entry.status == status)
+            && self
+                .idempotency_key
+                .as_ref()
+                .is_none_or(|key| entry.idempotency_key.as_ref() == Some(key))
+            && self
+                .params_hash
+                .as_ref()
+                .is_none_or(|hash| entry.params_hash == *hash)
+            && self.since.is_none_or(|since| entry.created_at >= since)
+    }
+}
+
+/// The operation in progress on the current thread
+#[derive(Debug, Clone)]
+struct OperationContext {
+    operation: JournalOperation,
+    params_hash: String,
+    idempotency_key: Option<String>,
+    /// The operation inputs, kept only while role policies are in force
+    params: Option<Value>,
+}
+
+/// Returns whether `SSS_JOURNAL_PATH` is set
+pub fn journal_enabled() -> bool {
+    JOURNAL_PATH.is_some()
+}
+
+/// Returns the current state of every journaled transaction matching a filter
+///
+/// # Arguments
+///
+/// * `filter` - The criteria entries must match
+///
+/// # Returns
+///
+/// The matching entries, in the order they were first written
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if `SSS_JOURNAL_PATH` is not set or the journal cannot be read
+pub fn journal_query(filter: &JournalFilter) -> SssResult<Vec<JournalEntry>> {
+    let mut entries = read_journal()?;
+    entries.retain(|entry| filter.matches(entry));
+    Ok(entries)
+}
+
+/// Looks up every transaction still marked as sent on chain and records its outcome
+///
+/// Transactions that landed become `Confirmed` or `Failed`. Transactions that are not
+/// found and whose blockhash has expired become `Expired`, since they can no longer land.
+/// The others stay `Sent` and can be reconciled again later.
+///
+/// # Returns
+///
+/// The entries whose status changed, in their new state
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if `SSS_JOURNAL_PATH` is not set or the journal cannot be read
+/// or written, and an `RpcError` if the chain cannot be queried
+pub fn journal_reconcile() -> SssResult<Vec<JournalEntry>> {
+    journal_reconcile_with_client(&RPC_CLIENT)
+}
+
+/// Reconciles `Sent` journal entries like [`journal_reconcile`], querying the given RPC
+/// client
+///
+/// # Arguments
+///
+/// * `client` - The RPC client to query, e.g. an archival node for old signatures
+///
+/// # Returns
+///
+/// The entries whose status changed, in their new state
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if `SSS_JOURNAL_PATH` is not set or the journal cannot be read
+/// or written, and an `RpcError` if the chain cannot be queried
+pub fn journal_reconcile_with_client(client: &RpcClient) -> SssResult<Vec<JournalEntry>> {
+    let mut updated = Vec::new();
+    for mut entry in read_journal()? {
+        if entry.status != JournalStatus::Sent {
+            continue;
+        }
+        let signature = Signature::from_str(&entry.signature)
+            .into_sss_error("Failed to parse signature in journal config")?;
+        let status = client
+            .get_signature_status_with_commitment_and_history(
+                &signature,
+                CommitmentConfig::confirmed(),
+                true,
+            )
+            .into_sss_error("Failed to get signature status from rpc")?;
+
+        match status {
+            Some(Ok(())) => {
+                entry.status = JournalStatus::Confirmed;
+                entry.error = None;
+            }
+            Some(Err(e)) => {
+                entry.status = JournalStatus::Failed;
+                entry.error = Some(format!("Transaction failed: {}", e));
+            }
+            None => {
+                let blockhash = Hash::from_str(&entry.blockhash)
+                    .into_sss_error("Failed to parse blockhash in journal config")?;
+                let still_valid = client
+                    .is_blockhash_valid(&blockhash, CommitmentConfig::processed())
+                    .into_sss_error("Failed to check blockhash validity with rpc")?;
+                if still_valid {
+                    continue;
+                }
+                entry.status = JournalStatus::Expired;
+                entry.error = Some("Transaction expired before landing".to_string());
+            }
+        }
+        entry.updated_at = unix_now();
+        append_entry(&entry)?;
+        updated.push(entry);
+    }
+    Ok(updated)
+}
+
+/// Runs a mutation operation, journaling every transaction it sends
+///
+/// Transactions sent under an idempotency key are also recorded in the dedup store, if
+/// one is configured, and every transaction is checked against the role policy of the
+/// signer when role policies are in force. Does nothing beyond calling `f` when none of
+/// these is enabled.
+///
+/// # Arguments
+///
+/// * `operation` - The kind of operation
+/// * `params` - The operation inputs, hashed into [`JournalEntry::params_hash`]
+/// * `options` - The transaction options carrying the idempotency key
+/// * `f` - The operation
+pub(crate) fn journaled<T, E>(
+    operation: JournalOperation,
+    params: &impl Serialize,
+    options: &TxOptions,
+    f: impl FnOnce() -> Result<T, E>,
+) -> Result<T, E> {
+    let roles = role_policies_enabled();
+    if !journal_enabled() && !dedup_enabled() && !roles {
+        return f();
+    }
+    let context = OperationContext {
+        operation,
+        params_hash: hash(&serde_json::to_vec(params).unwrap_or_default()).to_string(),
+        idempotency_key: options.idempotency_key.clone(),
+        params: roles.then(|| serde_json::to_value(params).unwrap_or_default()),
+    };
+
+    let previous = CURRENT_OPERATION.with(|current| current.replace(Some(context)));
+    let result = f();
+    CURRENT_OPERATION.with(|current| *current.borrow_mut() = previous);
+    result
+}
+
+/// Returns the operation running on the current thread and its inputs, if any
+///
+/// The inputs are only kept while role policies are in force.
+pub(crate) fn current_operation() -> Option<(JournalOperation, Option<Value>)> {
+    CURRENT_OPERATION.with(|current| {
+        current
+            .borrow()
+            .as_ref()
+            .map(|context| (context.operation, context.params.clone()))
+    })
+}
+
+/// Records a transaction as sent before it is submitted
+///
+/// Nothing is recorded outside a journaled operation.
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if the entry cannot be written, in which case the transaction
+/// must not be sent
+pub(crate) fn record_sent(signature: &Signature, blockhash: &Hash) -> SssResult<()> {
+    let Some(context) = CURRENT_OPERATION.with(|current| current.borrow().clone()) else {
+        return Ok(());
+    };
+    if let Some(key) = dedup_key(&context) {
+        dedup::record(&key, signature, blockhash, JournalStatus::Sent)?;
+    }
+    if !journal_enabled() {
+        return Ok(());
+    }
+    let now = unix_now();
+    append_entry(&JournalEntry {
+        operation: context.operation,
+        params_hash: context.params_hash,
+        idempotency_key: context.idempotency_key,
+        signature: signature.to_string(),
+        blockhash: blockhash.to_string(),
+        status: JournalStatus::Sent,
+        error: None,
+        created_at: now,
+        updated_at: now,
+    })
+}
+
+/// Records the outcome of a transaction recorded by [`record_sent`]
+///
+/// A send error without a transaction error, such as a confirmation timeout, leaves the
+/// entry `Sent` for [`journal_reconcile`] to resolve. Failures to write are ignored for
+/// the same reason.
+pub(crate) fn record_outcome<T>(
+    signature: &Signature,
+    blockhash: &Hash,
+    outcome: &Result<T, ClientError>,
+) {
+    let Some(context) = CURRENT_OPERATION.with(|current| current.borrow().clone()) else {
+        return;
+    };
+    let (status, error) = match outcome {
+        Ok(_) => (JournalStatus::Confirmed, None),
+        Err(e) if e.get_transaction_error().is_some() => {
+            (JournalStatus::Failed, Some(e.to_string()))
+        }
+        Err(e) => (JournalStatus::Sent, Some(e.to_string())),
+    };
+    if let Some(key) = dedup_key(&context) {
+        let _ = dedup::record(&key, signature, blockhash, status);
+    }
+    if !journal_enabled() {
+        return;
+    }
+    let now = unix_now();
+    let _ = append_entry(&JournalEntry {
+        operation: context.operation,
+        params_hash: context.params_hash,
+        idempotency_key: context.idempotency_key,
+        signature: signature.to_string(),
+        blockhash: blockhash.to_string(),
+        status,
+        error,
+        // Replaced by the time of the first line when the journal is read
+        created_at: now,
+        updated_at: now,
+    });
+}
+
+/// Returns the signature of a transaction the current operation already sent under its
+/// idempotency key, possibly before a restart, instead of which nothing is sent
+///
+/// # Errors
+///
+/// Returns a `DuplicateInFlight` error if that transaction may still land, and a
+/// `ConfigError` or `RpcError` if the dedup store or the chain cannot be read
+pub(crate) fn replayed_signature(client: &RpcClient) -> SssResult<Option<Signature>> {
+    let Some(context) = CURRENT_OPERATION.with(|current| current.borrow().clone()) else {
+        return Ok(None);
+    };
+    match dedup_key(&context) {
+        Some(key) => dedup::previous_send(client, &key),
+        None => Ok(None),
+    }
+}
+
+/// Returns the dedup store key of an operation with an idempotency key, if the store is
+/// enabled
+///
+/// The key also holds the operation and its parameter hash, so each batch of a batch
+/// operation sharing one idempotency key is recorded separately.
+fn dedup_key(context: &OperationContext) -> Option<String> {
+    let idempotency_key = context.idempotency_key.as_ref()?;
+    dedup_enabled().then(|| {
+        format!(
+            "{}:{:?}:{}",
+            idempotency_key, context.operation, context.params_hash
+        )
+    })
+}
+
+/// Appends an entry as a JSON line under an exclusive lock
+///
+/// The lock keeps lines from two processes sharing the path from interleaving, and from
+/// chaining to the same previous line.
+pub(crate) fn append_entry(entry: &JournalEntry) -> SssResult<()> {
+    append_entry_to(journal_path()?, entry)
+}
+
+fn append_entry_to(path: &Path, entry: &JournalEntry) -> SssResult<()> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(path)
+        .map_err(|e| journal_error("open", e))?;
+    file.lock().map_err(|e| journal_error("lock", e))?;
+
+    let previous = last_line_hash(&mut file).map_err(|e| journal_error("read", e))?;
+    let mut line = chained_line(entry, previous)?;
+    line.push('\n');
+    // Terminate a line left incomplete by a crash so it does not swallow this one
+    if ends_mid_line(&mut file).map_err(|e| journal_error("read", e))? {
+        line.insert(0, '\n');
+    }
+    file.write_all(line.as_bytes())
+        .and_then(|_| file.sync_data())
+        .map_err(|e| journal_error("write", e))
+}
+
+/// Reads the current state of every entry under a shared lock
+///
+/// Lines that cannot be parsed, such as one cut short by a crash, are skipped.
+pub(crate) fn read_journal() -> SssResult<Vec<JournalEntry>> {
+    read_journal_from(journal_path()?)
+}
+
+fn read_journal_from(path: &Path) -> SssResult<Vec<JournalEntry>> {
+    let mut contents = String::new();
+    match File::open(path) {
+        Ok(mut file) => {
+            file.lock_shared().map_err(|e| journal_error("lock", e))?;
+            file.read_to_string(&mut contents)
+                .map_err(|e| journal_error("read", e))?;
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(journal_error("open", e)),
+    }
+
+    let mut entries: Vec<JournalEntry> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for line in contents.lines() {
+        let Ok(entry) = serde_json::from_str::<JournalEntry>(line) else {
+            continue;
+        };
+        match index.get(&entry.signature) {
+            Some(&i) => {
+                let created_at = entries[i].created_at;
+                entries[i] = JournalEntry {
+                    created_at,
+                    ..entry
+                };
+            }
+            None => {
+                index.insert(entry.signature.clone(), entries.len());
+                entries.push(entry);
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Returns whether the file is non-empty and does not end with a newline
+pub(crate) fn ends_mid_line(file: &mut File) -> std::io::Result<bool> {
+    if file.metadata()?.len() == 0 {
+        return Ok(false);
+    }
+    let mut last = [0u8; 1];
+    file.seek(SeekFrom::End(-1))?;
+    file.read_exact(&mut last)?;
+    Ok(last[0] != b'\n')
+}
+
+fn journal_path() -> SssResult<&'static PathBuf> {
+    JOURNAL_PATH
+        .as_ref()
+        .ok_or_else(|| SssError::ConfigError("SSS_JOURNAL_PATH is not set".to_string()))
+}
+
+fn journal_error(action: &str, e: std::io::Error) -> SssError {
+    SssError::ConfigError(format!("Failed to {} operation journal: {}", action, e))
+}
+
+/// Returns the current Unix time in seconds
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn temp_journal() -> PathBuf {
+        let dir = env::temp_dir().join(format!("sss-journal-{}", rand::random::<u64>()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("journal.jsonl")
+    }
+
+    fn entry(signature: &str, status: JournalStatus, at: u64) -> JournalEntry {
+        JournalEntry {
+            operation: JournalOperation::MintToken,
+            params_hash: "params".to_string(),
+            idempotency_key: None,
+            signature: signature.to_string(),
+            blockhash: Hash::default().to_string(),
+            status,
+            error: None,
+            created_at: at,
+            updated_at: at,
+        }
+    }
+
+    /// Every line of the file as JSON, `None` for a line that does not parse
+    fn lines(path: &Path) -> Vec<Option<Value>> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).ok())
+            .collect()
+    }
+
+    #[test]
+    fn appended_entries_reload_with_their_latest_status() {
+        let path = temp_journal();
+        assert!(read_journal_from(&path).unwrap().is_empty());
+
+        append_entry_to(&path, &entry("a", JournalStatus::Sent, 100)).unwrap();
+        append_entry_to(&path, &entry("b", JournalStatus::Sent, 110)).unwrap();
+        let mut failed = entry("a", JournalStatus::Failed, 120);
+        failed.error = Some("custom program error: 0x1".to_string());
+        append_entry_to(&path, &failed).unwrap();
+
+        let entries = read_journal_from(&path).unwrap();
+        assert_eq!(entries.len(), 2);
+        // The first entry keeps its place and creation time and takes the latest state
+        assert_eq!(
+            entries[0],
+            JournalEntry {
+                created_at: 100,
+                ..failed
+            }
+        );
+        assert_eq!(entries[1], entry("b", JournalStatus::Sent, 110));
+        assert_eq!(lines(&path).len(), 3);
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn concurrent_appends_keep_every_line_whole_and_chained() {
+        let path = Arc::new(temp_journal());
+        let writers: Vec<_> = (0..8)
+            .map(|writer| {
+                let path = Arc::clone(&path);
+                thread::spawn(move || {
+                    for n in 0..25 {
+                        let signature = format!("{}-{}", writer, n);
+                        append_entry_to(&path, &entry(&signature, JournalStatus::Sent, n)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let entries = read_journal_from(&path).unwrap();
+        assert_eq!(entries.len(), 200);
+        let lines = lines(&path);
+        assert_eq!(lines.len(), 200);
+        // Each line chains to the one before it, so no two appends raced for the same tail
+        let mut previous = Hash::default().to_string();
+        for line in &lines {
+            let line = line.as_ref().expect("a line was torn");
+            assert_eq!(line["prev_hash"], previous.as_str());
+            previous = line["hash"].as_str().unwrap().to_string();
+        }
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn a_truncated_last_line_is_skipped_and_terminated() {
+        let path = temp_journal();
+        append_entry_to(&path, &entry("a", JournalStatus::Sent, 100)).unwrap();
+        append_entry_to(&path, &entry("b", JournalStatus::Sent, 110)).unwrap();
+        let complete = lines(&path);
+        // A crash in the middle of the next write
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"operation":"mint_token","params_hash":"par"#)
+            .unwrap();
+        drop(file);
+
+        assert_eq!(read_journal_from(&path).unwrap().len(), 2);
+
+        append_entry_to(&path, &entry("a", JournalStatus::Confirmed, 120)).unwrap();
+        let entries = read_journal_from(&path).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].status, JournalStatus::Confirmed);
+        let lines = lines(&path);
+        assert_eq!(lines.len(), 4);
+        assert!(lines[2].is_none());
+        // The new line starts on a line of its own and chains past the torn one
+        let last = lines[3].as_ref().unwrap();
+        assert_eq!(last["prev_hash"], complete[1].as_ref().unwrap()["hash"]);
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
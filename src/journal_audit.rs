@@ -0,0 +1,715 @@
+//! Tamper evidence for the operation journal
+//!
+//! Every journal line carries the hash of its content chained to the hash of the line
+//! before, and a signature of that hash by a dedicated audit key. Editing, inserting or
+//! removing a line breaks the chain from there on, and the signatures show that the chain
+//! was not rebuilt by someone without the key. Daily digests signed by the same key anchor
+//! the chain off-site, which also catches lines cut from the end.
+
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::journal::JournalEntry;
+use crate::load_dotenv;
+use crate::payer::get_payer_pubkey;
+use crate::serde_utils::pubkey_string;
+use crate::watch_only::{ensure_not_watch_only, is_watch_only};
+use chrono::{DateTime, NaiveDate};
+use lazy_static::lazy_static;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use solana_sdk::{
+    hash::{Hash, hashv},
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, read_keypair_file},
+    signer::Signer,
+};
+use std::env;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// Bytes read at a time when looking for the last line of the journal
+const TAIL_CHUNK: u64 = 64 * 1024;
+
+/// Prefix of the message signed for a daily digest, versioning its layout
+const DIGEST_DOMAIN: &str = "sss-journal-digest-v1";
+
+lazy_static! {
+    /// The key signing journal lines, from the keypair file at `SSS_JOURNAL_AUDIT_KEYPAIR`
+    static ref AUDIT_KEY: Mutex<SssResult<Option<Keypair>>> = Mutex::new(load_audit_key());
+
+    /// The key journal signatures are checked against, from `SSS_JOURNAL_AUDIT_PUBKEY`
+    static ref AUDIT_PUBKEY: Option<String> = {
+        load_dotenv();
+        env::var("SSS_JOURNAL_AUDIT_PUBKEY").ok()
+    };
+}
+
+/// Outcome of checking the hash chain and signatures of a journal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JournalChainStatus {
+    /// Every line is chained and verified
+    Intact,
+    /// The lines written before the chain was introduced cannot be checked; every line
+    /// after them is chained and verified
+    Legacy,
+    /// A line does not match the chain, see [`JournalVerification::broken_link`]
+    Broken,
+}
+
+/// The first line of a journal that does not match its chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BrokenLink {
+    /// The line number, starting at 1
+    pub line: usize,
+    /// What does not match
+    pub reason: String,
+}
+
+/// Result of [`verify_journal`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct JournalVerification {
+    pub status: JournalChainStatus,
+    /// Lines written before the chain was introduced
+    pub legacy_lines: usize,
+    /// Chained lines verified before the end or the broken link
+    pub chained_lines: usize,
+    /// Verified lines written before an audit key was configured, protected by the chain
+    /// alone
+    pub unsigned_lines: usize,
+    /// Lines cut short by a crash, which the chain skips
+    pub torn_lines: usize,
+    /// The hash of the last verified line, to compare with the last daily digest
+    pub head_hash: Option<String>,
+    /// The first line that does not match, if any; later lines are not checked
+    pub broken_link: Option<BrokenLink>,
+    /// The key the signatures were checked against
+    #[serde(with = "pubkey_string")]
+    pub audit_key: Pubkey,
+}
+
+/// The journal lines of one day, signed by the audit key for off-site archival
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct JournalDigest {
+    /// The UTC day, as `YYYY-MM-DD`
+    pub date: String,
+    /// Lines last updated on the day
+    pub lines: usize,
+    /// The hash of the last line before the day, which the previous digest ends with
+    pub previous_hash: String,
+    /// The hash of the last line of the day, or `previous_hash` if there is none
+    pub last_hash: String,
+    /// The key that signed the digest
+    #[serde(with = "pubkey_string")]
+    pub audit_key: Pubkey,
+    /// The signature of the digest by the audit key
+    pub signature: String,
+}
+
+impl JournalDigest {
+    /// Checks the signature of the digest
+    ///
+    /// # Arguments
+    ///
+    /// * `audit_key` - The key the digest must be signed by
+    pub fn verify(&self, audit_key: &Pubkey) -> bool {
+        self.audit_key == *audit_key
+            && Signature::from_str(&self.signature)
+                .is_ok_and(|signature| signature.verify(audit_key.as_ref(), &self.message()))
+    }
+
+    /// Serializes the digest to JSON
+    pub fn to_json(&self) -> SssResult<String> {
+        serde_json::to_string_pretty(self).into_sss_error("Failed to serialize journal digest")
+    }
+
+    /// The bytes signed by the audit key
+    fn message(&self) -> Vec<u8> {
+        format!(
+            "{}\n{}\n{}\n{}\n{}\n",
+            DIGEST_DOMAIN, self.date, self.lines, self.previous_hash, self.last_hash
+        )
+        .into_bytes()
+    }
+}
+
+/// A verified line of the chain
+struct ChainedLine {
+    hash: Hash,
+    updated_at: u64,
+}
+
+/// Replaces the key signing journal lines from now on
+///
+/// # Arguments
+///
+/// * `keypair` - The audit key, which must not be the payer
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if the key is the payer's, since whoever holds the payer key
+/// could then rewrite the journal of its own transactions, and a `KeypairError` in
+/// watch-only mode
+pub fn set_journal_audit_key(keypair: Keypair) -> SssResult<()> {
+    ensure_not_watch_only()?;
+    ensure_not_payer(&keypair.pubkey())?;
+    let mut key = lock_audit_key()?;
+    *key = Ok(Some(keypair));
+    Ok(())
+}
+
+/// Checks the hash chain and audit signatures of a journal
+///
+/// Signatures are checked against `SSS_JOURNAL_AUDIT_PUBKEY`, or else the public key of
+/// the configured audit key. Lines written before the chain was introduced are reported as
+/// legacy rather than broken, as long as no chained line precedes them.
+///
+/// # Arguments
+///
+/// * `path` - The journal file, e.g. a copy handed to an auditor
+///
+/// # Returns
+///
+/// The verification status, with the first broken link if any
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if no audit key is configured or the journal cannot be read
+pub fn verify_journal(path: impl AsRef<Path>) -> SssResult<JournalVerification> {
+    verify_journal_with_key(path, &expected_audit_key()?)
+}
+
+/// Checks a journal like [`verify_journal`], against the given audit key
+///
+/// # Arguments
+///
+/// * `path` - The journal file
+/// * `audit_key` - The public key the lines must be signed by
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if the journal cannot be read
+pub fn verify_journal_with_key(
+    path: impl AsRef<Path>,
+    audit_key: &Pubkey,
+) -> SssResult<JournalVerification> {
+    let contents = read_file(path.as_ref())?;
+    Ok(walk_chain(&contents, audit_key).0)
+}
+
+/// Writes the signed digest of one day of a journal to a file
+///
+/// The journal is verified first, so a digest is never signed over a broken chain. Each
+/// digest starts with the hash the previous day's digest ends with, so archived digests
+/// chain too, and a journal whose head no longer matches the last digest lost lines.
+///
+/// # Arguments
+///
+/// * `path` - The journal file
+/// * `date` - The UTC day to digest, by the time each line was written
+/// * `out` - The file to write the digest to as JSON
+///
+/// # Returns
+///
+/// The digest written
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if no audit key is configured, the chain is broken, or a file
+/// cannot be read or written
+pub fn export_journal_digest(
+    path: impl AsRef<Path>,
+    date: NaiveDate,
+    out: impl AsRef<Path>,
+) -> SssResult<JournalDigest> {
+    let key = lock_audit_key()?;
+    let keypair = match &*key {
+        Ok(Some(keypair)) => keypair,
+        Ok(None) => return Err(no_audit_key()),
+        Err(e) => return Err(e.clone()),
+    };
+    let contents = read_file(path.as_ref())?;
+    let (verification, lines) = walk_chain(&contents, &keypair.pubkey());
+    if let Some(broken) = verification.broken_link {
+        return Err(SssError::ConfigError(format!(
+            "Refusing to sign a digest of a broken journal, line {}: {}",
+            broken.line, broken.reason
+        )));
+    }
+
+    let day = |line: &ChainedLine| {
+        DateTime::from_timestamp(line.updated_at as i64, 0).map(|time| time.date_naive())
+    };
+    let mut previous_hash = Hash::default();
+    let mut last_hash = None;
+    let mut count = 0;
+    for line in &lines {
+        match day(line) {
+            Some(line_day) if line_day < date => previous_hash = line.hash,
+            Some(line_day) if line_day == date => {
+                last_hash = Some(line.hash);
+                count += 1;
+            }
+            _ => {}
+        }
+    }
+    let mut digest = JournalDigest {
+        date: date.format("%Y-%m-%d").to_string(),
+        lines: count,
+        previous_hash: previous_hash.to_string(),
+        last_hash: last_hash.unwrap_or(previous_hash).to_string(),
+        audit_key: keypair.pubkey(),
+        signature: String::new(),
+    };
+    digest.signature = keypair.sign_message(&digest.message()).to_string();
+    fs::write(out.as_ref(), digest.to_json()?).map_err(|e| {
+        SssError::ConfigError(format!(
+            "Failed to write journal digest to {}: {}",
+            out.as_ref().display(),
+            e
+        ))
+    })?;
+    Ok(digest)
+}
+
+/// Serializes a journal entry as a line chained to the previous one and signed by the
+/// audit key, if one is configured
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if the audit key configured cannot be loaded, in which case
+/// nothing may be journaled
+pub(crate) fn chained_line(entry: &JournalEntry, previous: Option<Hash>) -> SssResult<String> {
+    match &*lock_audit_key()? {
+        Ok(audit_key) => signed_line(entry, previous, audit_key.as_ref()),
+        Err(e) => Err(e.clone()),
+    }
+}
+
+/// Serializes a journal entry as a chained line, signed by `audit_key` if given
+fn signed_line(
+    entry: &JournalEntry,
+    previous: Option<Hash>,
+    audit_key: Option<&Keypair>,
+) -> SssResult<String> {
+    let Value::Object(mut line) =
+        serde_json::to_value(entry).into_sss_error("Failed to serialize journal config")?
+    else {
+        return Err(SssError::ConfigError(
+            "Journal entry is not a JSON object".to_string(),
+        ));
+    };
+    let previous = previous.unwrap_or_default();
+    let hash = line_hash(&previous, &line)?;
+    line.insert("prev_hash".to_string(), previous.to_string().into());
+    line.insert("hash".to_string(), hash.to_string().into());
+    if let Some(keypair) = audit_key {
+        let signature = keypair.sign_message(hash.as_ref());
+        line.insert("audit_signature".to_string(), signature.to_string().into());
+    }
+    serde_json::to_string(&line).into_sss_error("Failed to serialize journal config")
+}
+
+/// Returns the hash of the last complete line of a journal, `None` if there is none or it
+/// predates the chain
+///
+/// Lines cut short by a crash are passed over, like when the journal is verified.
+pub(crate) fn last_line_hash(file: &mut File) -> std::io::Result<Option<Hash>> {
+    let mut end = file.metadata()?.len();
+    let mut tail: Vec<u8> = Vec::new();
+    while end > 0 {
+        let start = end.saturating_sub(TAIL_CHUNK);
+        let mut chunk = vec![0; (end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut chunk)?;
+        chunk.extend_from_slice(&tail);
+        tail = chunk;
+        end = start;
+
+        // Until the start of the file is read, the first line may be incomplete
+        let mut lines: Vec<&[u8]> = tail.split(|&byte| byte == b'\n').collect();
+        let first = if end > 0 { lines.remove(0) } else { &[] };
+        for line in lines.iter().rev() {
+            if let Ok(Value::Object(line)) = serde_json::from_slice::<Value>(line) {
+                return Ok(line
+                    .get("hash")
+                    .and_then(Value::as_str)
+                    .and_then(|hash| Hash::from_str(hash).ok()));
+            }
+        }
+        tail = first.to_vec();
+    }
+    Ok(None)
+}
+
+/// Checks every line of a journal, returning the verification and the verified lines
+fn walk_chain(contents: &str, audit_key: &Pubkey) -> (JournalVerification, Vec<ChainedLine>) {
+    let mut verification = JournalVerification {
+        status: JournalChainStatus::Intact,
+        legacy_lines: 0,
+        chained_lines: 0,
+        unsigned_lines: 0,
+        torn_lines: 0,
+        head_hash: None,
+        broken_link: None,
+        audit_key: *audit_key,
+    };
+    let mut lines = Vec::new();
+    let mut previous: Option<Hash> = None;
+    let mut signed_before = false;
+    for (index, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Ok(Value::Object(mut fields)) = serde_json::from_str::<Value>(line) else {
+            verification.torn_lines += 1;
+            continue;
+        };
+        match check_line(&mut fields, previous, signed_before, audit_key) {
+            Ok(LineCheck::Legacy) => verification.legacy_lines += 1,
+            Ok(LineCheck::Chained { hash, signed }) => {
+                verification.chained_lines += 1;
+                if !signed {
+                    verification.unsigned_lines += 1;
+                }
+                signed_before |= signed;
+                previous = Some(hash);
+                lines.push(ChainedLine {
+                    hash,
+                    updated_at: fields
+                        .get("updated_at")
+                        .and_then(Value::as_u64)
+                        .unwrap_or_default(),
+                });
+            }
+            Err(reason) => {
+                verification.broken_link = Some(BrokenLink {
+                    line: index + 1,
+                    reason,
+                });
+                break;
+            }
+        }
+    }
+    verification.head_hash = previous.map(|hash| hash.to_string());
+    verification.status = if verification.broken_link.is_some() {
+        JournalChainStatus::Broken
+    } else if verification.legacy_lines > 0 {
+        JournalChainStatus::Legacy
+    } else {
+        JournalChainStatus::Intact
+    };
+    (verification, lines)
+}
+
+/// What a journal line turned out to be
+enum LineCheck {
+    /// Written before the chain was introduced
+    Legacy,
+    /// Chained to the previous line, with its hash and whether it carries a signature
+    Chained { hash: Hash, signed: bool },
+}
+
+/// Checks one line against the hash of the previous one, removing the chain fields
+///
+/// Once a line is signed, every later one must be, or else the lines after a signed one
+/// could be rewritten and chained again without the audit key.
+fn check_line(
+    fields: &mut Map<String, Value>,
+    previous: Option<Hash>,
+    signed_before: bool,
+    audit_key: &Pubkey,
+) -> Result<LineCheck, String> {
+    let signature = fields.remove("audit_signature");
+    let hash = fields.remove("hash");
+    let prev_hash = fields.remove("prev_hash");
+    let Some(hash) = hash else {
+        return match previous {
+            None => Ok(LineCheck::Legacy),
+            Some(_) => Err("line has no hash, but earlier lines are chained".to_string()),
+        };
+    };
+    let parse = |value: Option<&Value>, name: &str| {
+        value
+            .and_then(Value::as_str)
+            .and_then(|hash| Hash::from_str(hash).ok())
+            .ok_or_else(|| format!("{} is not a hash", name))
+    };
+    let hash = parse(Some(&hash), "hash")?;
+    let prev_hash = parse(prev_hash.as_ref(), "prev_hash")?;
+
+    let expected_previous = previous.unwrap_or_default();
+    if prev_hash != expected_previous {
+        return Err(format!(
+            "prev_hash {} does not match the hash {} of the previous line",
+            prev_hash, expected_previous
+        ));
+    }
+    let computed = line_hash(&prev_hash, fields).map_err(|e| e.to_string())?;
+    if computed != hash {
+        return Err(format!(
+            "content hashes to {}, not to the recorded {}",
+            computed, hash
+        ));
+    }
+    let Some(signature) = signature else {
+        if signed_before {
+            return Err("line is unsigned, but earlier lines are signed".to_string());
+        }
+        return Ok(LineCheck::Chained {
+            hash,
+            signed: false,
+        });
+    };
+    let signed = signature
+        .as_str()
+        .and_then(|signature| Signature::from_str(signature).ok())
+        .is_some_and(|signature| signature.verify(audit_key.as_ref(), hash.as_ref()));
+    if !signed {
+        return Err(format!(
+            "audit_signature does not verify against audit key {}",
+            audit_key
+        ));
+    }
+    Ok(LineCheck::Chained { hash, signed })
+}
+
+/// Hashes the content of a line together with the hash of the previous line
+fn line_hash(previous: &Hash, fields: &Map<String, Value>) -> SssResult<Hash> {
+    let content =
+        serde_json::to_vec(fields).into_sss_error("Failed to serialize journal config")?;
+    Ok(hashv(&[previous.as_ref(), &content]))
+}
+
+/// Returns the key signatures are checked against
+fn expected_audit_key() -> SssResult<Pubkey> {
+    if let Some(pubkey) = AUDIT_PUBKEY.as_deref() {
+        return Pubkey::from_str(pubkey).map_err(|e| {
+            SssError::ConfigError(format!("Invalid SSS_JOURNAL_AUDIT_PUBKEY: {}", e))
+        });
+    }
+    match &*lock_audit_key()? {
+        Ok(Some(keypair)) => Ok(keypair.pubkey()),
+        Ok(None) => Err(no_audit_key()),
+        Err(e) => Err(e.clone()),
+    }
+}
+
+/// Loads the audit key from `SSS_JOURNAL_AUDIT_KEYPAIR`, `None` if it is not set
+fn load_audit_key() -> SssResult<Option<Keypair>> {
+    load_dotenv();
+    if is_watch_only() {
+        return Ok(None);
+    }
+    let Ok(path) = env::var("SSS_JOURNAL_AUDIT_KEYPAIR") else {
+        return Ok(None);
+    };
+    let keypair = read_keypair_file(&path).map_err(|e| {
+        SssError::ConfigError(format!(
+            "Failed to read journal audit keypair from {}: {}",
+            path, e
+        ))
+    })?;
+    ensure_not_payer(&keypair.pubkey())?;
+    Ok(Some(keypair))
+}
+
+fn ensure_not_payer(audit_key: &Pubkey) -> SssResult<()> {
+    if get_payer_pubkey().ok() == Some(*audit_key) {
+        return Err(SssError::ConfigError(
+            "The journal audit key must not be the payer key".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn no_audit_key() -> SssError {
+    SssError::ConfigError(
+        "No journal audit key configured; set SSS_JOURNAL_AUDIT_KEYPAIR or call \
+         set_journal_audit_key"
+            .to_string(),
+    )
+}
+
+/// Drops the audit key, for watch-only mode
+pub(crate) fn clear_audit_key() {
+    if let Ok(mut key) = AUDIT_KEY.lock() {
+        *key = Ok(None);
+    }
+}
+
+fn lock_audit_key() -> SssResult<std::sync::MutexGuard<'static, SssResult<Option<Keypair>>>> {
+    AUDIT_KEY
+        .lock()
+        .map_err(|e| SssError::ConfigError(format!("Journal audit key poisoned: {}", e)))
+}
+
+fn read_file(path: &Path) -> SssResult<String> {
+    fs::read_to_string(path).map_err(|e| {
+        SssError::ConfigError(format!(
+            "Failed to read operation journal {}: {}",
+            path.display(),
+            e
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::journal::{JournalOperation, JournalStatus};
+
+    fn entry(signature: &str, status: JournalStatus) -> JournalEntry {
+        JournalEntry {
+            operation: JournalOperation::TransferAsset,
+            params_hash: "params".to_string(),
+            idempotency_key: None,
+            signature: signature.to_string(),
+            blockhash: Hash::default().to_string(),
+            status,
+            error: None,
+            created_at: 1_767_225_600,
+            updated_at: 1_767_225_600,
+        }
+    }
+
+    /// Chains and signs the entries as the journal would write them
+    fn chain(entries: &[JournalEntry], audit_key: Option<&Keypair>) -> Vec<String> {
+        let mut previous = None;
+        entries
+            .iter()
+            .map(|entry| {
+                let line = signed_line(entry, previous, audit_key).unwrap();
+                let fields: Value = serde_json::from_str(&line).unwrap();
+                previous = Some(Hash::from_str(fields["hash"].as_str().unwrap()).unwrap());
+                line
+            })
+            .collect()
+    }
+
+    fn four_entries() -> Vec<JournalEntry> {
+        vec![
+            entry("a", JournalStatus::Sent),
+            entry("b", JournalStatus::Sent),
+            entry("a", JournalStatus::Confirmed),
+            entry("b", JournalStatus::Failed),
+        ]
+    }
+
+    fn verify(lines: &[String], audit_key: &Pubkey) -> JournalVerification {
+        let path = env::temp_dir().join(format!("sss-audit-{}.jsonl", rand::random::<u64>()));
+        fs::write(&path, lines.join("\n") + "\n").unwrap();
+        let verification = verify_journal_with_key(&path, audit_key).unwrap();
+        fs::remove_file(path).unwrap();
+        verification
+    }
+
+    /// The line and reason of the first broken link
+    fn broken_at(verification: &JournalVerification) -> (usize, String) {
+        assert_eq!(verification.status, JournalChainStatus::Broken);
+        let link = verification.broken_link.clone().unwrap();
+        (link.line, link.reason)
+    }
+
+    fn hash_of(line: &str) -> String {
+        let fields: Value = serde_json::from_str(line).unwrap();
+        fields["hash"].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn an_intact_chain_verifies() {
+        let key = Keypair::new();
+        let lines = chain(&four_entries(), Some(&key));
+        let verification = verify(&lines, &key.pubkey());
+        assert_eq!(verification.status, JournalChainStatus::Intact);
+        assert_eq!(verification.chained_lines, 4);
+        assert_eq!(verification.unsigned_lines, 0);
+        assert_eq!(verification.head_hash, Some(hash_of(&lines[3])));
+        assert_eq!(verification.broken_link, None);
+
+        // Against another key every signature fails, starting with the first line
+        let (line, reason) = broken_at(&verify(&lines, &Keypair::new().pubkey()));
+        assert_eq!(line, 1);
+        assert!(reason.contains("audit_signature"), "{}", reason);
+    }
+
+    #[test]
+    fn an_edited_entry_breaks_the_chain_where_it_was_edited() {
+        let key = Keypair::new();
+        let mut lines = chain(&four_entries(), Some(&key));
+        lines[1] = lines[1].replace("\"sent\"", "\"confirmed\"");
+        let verification = verify(&lines, &key.pubkey());
+        let (line, reason) = broken_at(&verification);
+        assert_eq!(line, 2);
+        assert!(reason.starts_with("content hashes to"), "{}", reason);
+        assert_eq!(verification.chained_lines, 1);
+        assert_eq!(verification.head_hash, Some(hash_of(&lines[0])));
+
+        // Rebuilding the chain from the edit on needs the audit key
+        let mut edited = four_entries();
+        edited[1].status = JournalStatus::Confirmed;
+        let forged = chain(&edited, Some(&Keypair::new()));
+        let mut lines = chain(&four_entries(), Some(&key));
+        lines.splice(1.., forged[1..].iter().cloned());
+        let (line, reason) = broken_at(&verify(&lines, &key.pubkey()));
+        assert_eq!(line, 2);
+        assert!(reason.contains("audit_signature"), "{}", reason);
+
+        // Nor can the rebuilt lines go unsigned once the journal is signed
+        let unsigned = chain(&edited, None);
+        let mut lines = chain(&four_entries(), Some(&key));
+        lines.splice(1.., unsigned[1..].iter().cloned());
+        let (line, _) = broken_at(&verify(&lines, &key.pubkey()));
+        assert_eq!(line, 2);
+    }
+
+    #[test]
+    fn a_deleted_entry_breaks_the_chain_after_it() {
+        let key = Keypair::new();
+        let lines = chain(&four_entries(), Some(&key));
+        let mut deleted = lines.clone();
+        deleted.remove(1);
+        let (line, reason) = broken_at(&verify(&deleted, &key.pubkey()));
+        assert_eq!(line, 2);
+        assert!(reason.starts_with("prev_hash"), "{}", reason);
+
+        // Cutting lines from the end leaves a valid chain with an earlier head, which the
+        // last daily digest catches
+        let verification = verify(&lines[..3], &key.pubkey());
+        assert_eq!(verification.status, JournalChainStatus::Intact);
+        assert_eq!(verification.head_hash, Some(hash_of(&lines[2])));
+        assert_ne!(verification.head_hash, Some(hash_of(&lines[3])));
+    }
+
+    #[test]
+    fn reordered_entries_break_the_chain() {
+        let key = Keypair::new();
+        let mut lines = chain(&four_entries(), Some(&key));
+        lines.swap(1, 2);
+        let (line, reason) = broken_at(&verify(&lines, &key.pubkey()));
+        assert_eq!(line, 2);
+        assert!(reason.starts_with("prev_hash"), "{}", reason);
+    }
+
+    #[test]
+    fn legacy_and_torn_lines_are_reported_but_not_broken() {
+        let key = Keypair::new();
+        let legacy = serde_json::to_string(&entry("old", JournalStatus::Confirmed)).unwrap();
+        let mut lines = vec![legacy];
+        lines.extend(chain(&four_entries(), Some(&key)));
+        lines.insert(3, r#"{"operation":"transfer_asset","par"#.to_string());
+        let verification = verify(&lines, &key.pubkey());
+        assert_eq!(verification.status, JournalChainStatus::Legacy);
+        assert_eq!(verification.legacy_lines, 1);
+        assert_eq!(verification.torn_lines, 1);
+        assert_eq!(verification.chained_lines, 4);
+
+        // A line without a chain after chained ones was not written by the journal
+        lines.push(serde_json::to_string(&entry("c", JournalStatus::Sent)).unwrap());
+        let (line, reason) = broken_at(&verify(&lines, &key.pubkey()));
+        assert_eq!(line, 7);
+        assert!(reason.contains("no hash"), "{}", reason);
+    }
+}
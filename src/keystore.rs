@@ -0,0 +1,530 @@
+//! Passphrase-encrypted keypair files, so the payer key is never stored in plaintext
+//!
+//! A keystore file starts with a header naming the format version and the key derivation
+//! parameters, followed by the encrypted keypair. The keypair is encrypted with AES-256-GCM-SIV,
+//! authenticating the header along with it. Version 2, written by [`save_encrypted_keypair`],
+//! derives the 256-bit key with Argon2id; version 1 files, derived with PBKDF2-HMAC-SHA256,
+//! remain readable.
+
+use crate::error::{SssError, SssResult};
+use crate::load_dotenv;
+use crate::watch_only::ensure_not_watch_only;
+use aes_gcm_siv::aead::{Aead, KeyInit, Payload};
+use aes_gcm_siv::{Aes256GcmSiv, Nonce};
+use argon2::{Algorithm, Argon2, Params, Version};
+use rand::RngCore;
+use sha2::Sha256;
+use solana_sdk::signature::Keypair;
+use std::env;
+use std::fs;
+use std::path::Path;
+use zeroize::Zeroizing;
+
+/// Bytes every keystore file starts with
+const MAGIC: &[u8; 4] = b"SSSK";
+
+/// Format version of keystores derived with PBKDF2-HMAC-SHA256
+const VERSION_PBKDF2: u8 = 1;
+
+/// Format version of keystores derived with Argon2id, written by [`save_encrypted_keypair`]
+const VERSION_ARGON2ID: u8 = 2;
+
+/// Most PBKDF2 iterations accepted, so a crafted file cannot stall the loader
+const MAX_KDF_ITERATIONS: u32 = 10_000_000;
+
+/// Argon2id memory cost of new keystores in KiB, as recommended by OWASP
+const ARGON2_MEMORY_KIB: u32 = 19 * 1024;
+
+/// Argon2id passes over memory of new keystores, as recommended by OWASP
+const ARGON2_ITERATIONS: u32 = 2;
+
+/// Argon2id lanes of new keystores
+const ARGON2_PARALLELISM: u32 = 1;
+
+/// Most Argon2id memory accepted in KiB, so a crafted file cannot exhaust the loader's memory
+const MAX_ARGON2_MEMORY_KIB: u32 = 1024 * 1024;
+
+/// Most Argon2id passes accepted, so a crafted file cannot stall the loader
+const MAX_ARGON2_ITERATIONS: u32 = 64;
+
+/// Most Argon2id lanes accepted
+const MAX_ARGON2_PARALLELISM: u32 = 16;
+
+/// Size of the random KDF salt
+const SALT_LEN: usize = 16;
+
+/// Size of the random AES-GCM-SIV nonce
+const NONCE_LEN: usize = 12;
+
+/// Size of a keypair: the secret key followed by the public key
+const KEYPAIR_LEN: usize = 64;
+
+/// Size of the authentication tag appended to the ciphertext
+const TAG_LEN: usize = 16;
+
+/// Key derivation recorded in a keystore header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kdf {
+    /// PBKDF2-HMAC-SHA256 with the given iterations (version 1)
+    Pbkdf2 { iterations: u32 },
+    /// Argon2id with the given memory in KiB, passes and lanes (version 2)
+    Argon2id {
+        memory_kib: u32,
+        iterations: u32,
+        parallelism: u32,
+    },
+}
+
+impl Kdf {
+    /// The derivation of new keystores
+    const DEFAULT: Kdf = Kdf::Argon2id {
+        memory_kib: ARGON2_MEMORY_KIB,
+        iterations: ARGON2_ITERATIONS,
+        parallelism: ARGON2_PARALLELISM,
+    };
+
+    /// Format version naming this derivation in the header
+    fn version(self) -> u8 {
+        match self {
+            Kdf::Pbkdf2 { .. } => VERSION_PBKDF2,
+            Kdf::Argon2id { .. } => VERSION_ARGON2ID,
+        }
+    }
+
+    /// Size of the parameters following the version byte
+    fn params_len(version: u8) -> Option<usize> {
+        match version {
+            VERSION_PBKDF2 => Some(4),
+            VERSION_ARGON2ID => Some(12),
+            _ => None,
+        }
+    }
+
+    /// Serializes the parameters following the version byte
+    fn encode_params(self, out: &mut Vec<u8>) {
+        match self {
+            Kdf::Pbkdf2 { iterations } => out.extend_from_slice(&iterations.to_le_bytes()),
+            Kdf::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => {
+                out.extend_from_slice(&memory_kib.to_le_bytes());
+                out.extend_from_slice(&iterations.to_le_bytes());
+                out.extend_from_slice(&parallelism.to_le_bytes());
+            }
+        }
+    }
+
+    /// Parses and bounds-checks the parameters of a header, `None` if they are out of range
+    fn decode_params(version: u8, params: &[u8]) -> Option<Kdf> {
+        let word = |i: usize| {
+            params
+                .get(i * 4..i * 4 + 4)
+                .and_then(|b| b.try_into().ok())
+                .map(u32::from_le_bytes)
+        };
+        let kdf = match version {
+            VERSION_PBKDF2 => Kdf::Pbkdf2 {
+                iterations: word(0)?,
+            },
+            VERSION_ARGON2ID => Kdf::Argon2id {
+                memory_kib: word(0)?,
+                iterations: word(1)?,
+                parallelism: word(2)?,
+            },
+            _ => return None,
+        };
+        let in_range = match kdf {
+            Kdf::Pbkdf2 { iterations } => (1..=MAX_KDF_ITERATIONS).contains(&iterations),
+            Kdf::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => {
+                memory_kib <= MAX_ARGON2_MEMORY_KIB
+                    && (1..=MAX_ARGON2_ITERATIONS).contains(&iterations)
+                    && (1..=MAX_ARGON2_PARALLELISM).contains(&parallelism)
+            }
+        };
+        in_range.then_some(kdf)
+    }
+
+    /// Derives the encryption key from a passphrase, `None` if the parameters are rejected
+    fn derive_key(self, passphrase: &str, salt: &[u8]) -> Option<Zeroizing<[u8; 32]>> {
+        let mut key = Zeroizing::new([0u8; 32]);
+        match self {
+            Kdf::Pbkdf2 { iterations } => {
+                pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, iterations, key.as_mut())
+            }
+            Kdf::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => {
+                let params = Params::new(memory_kib, iterations, parallelism, Some(32)).ok()?;
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(passphrase.as_bytes(), salt, key.as_mut())
+                    .ok()?;
+            }
+        }
+        Some(key)
+    }
+}
+
+/// Encrypts a keypair with a passphrase and writes it to a keystore file
+///
+/// The file is replaced atomically and, on Unix, readable by its owner only.
+///
+/// # Arguments
+///
+/// * `path` - The keystore file to write
+/// * `keypair` - The keypair to store
+/// * `passphrase` - The passphrase needed to load the keypair again
+///
+/// # Errors
+///
+/// Returns a `KeypairError` if the passphrase is empty or the file cannot be written
+pub fn save_encrypted_keypair(
+    path: impl AsRef<Path>,
+    keypair: &Keypair,
+    passphrase: &str,
+) -> SssResult<()> {
+    let path = path.as_ref();
+    if passphrase.is_empty() {
+        return Err(SssError::KeypairError(
+            "Keystore passphrase must not be empty".to_string(),
+        ));
+    }
+
+    let contents = encrypt(keypair, passphrase, Kdf::DEFAULT)?;
+    write_private(path, &contents).map_err(|e| {
+        SssError::KeypairError(format!(
+            "Failed to write keystore {}: {}",
+            path.display(),
+            e
+        ))
+    })
+}
+
+/// Loads a keypair from a keystore file written by [`save_encrypted_keypair`]
+///
+/// # Arguments
+///
+/// * `path` - The keystore file to read
+/// * `passphrase` - The passphrase the keystore was saved with
+///
+/// # Errors
+///
+/// Returns a `KeypairError` if the file cannot be read, and the same `KeypairError` for a
+/// wrong passphrase and for a damaged file, so the error does not reveal which it was.
+/// Returns a `KeypairError` in watch-only mode, without reading the file.
+pub fn load_encrypted_keypair(path: impl AsRef<Path>, passphrase: &str) -> SssResult<Keypair> {
+    ensure_not_watch_only()?;
+    let path = path.as_ref();
+    let contents = fs::read(path).map_err(|e| {
+        SssError::KeypairError(format!("Failed to read keystore {}: {}", path.display(), e))
+    })?;
+    decrypt(&contents, passphrase).ok_or_else(|| {
+        SssError::KeypairError(format!(
+            "Failed to unlock keystore {}: wrong passphrase or damaged file",
+            path.display()
+        ))
+    })
+}
+
+/// Loads the payer from a keystore file, replacing the payer from the environment
+///
+/// Lets a host pass the passphrase directly instead of through `PAYER_KEYSTORE_PASSPHRASE`.
+///
+/// # Arguments
+///
+/// * `path` - The keystore file to read
+/// * `passphrase` - The passphrase the keystore was saved with
+///
+/// # Errors
+///
+/// Returns a `KeypairError` as [`load_encrypted_keypair`] does; the payer is left unchanged
+pub fn unlock_payer_keystore(path: impl AsRef<Path>, passphrase: &str) -> SssResult<()> {
+    crate::set_payer(load_encrypted_keypair(path, passphrase)?);
+    Ok(())
+}
+
+/// Loads the payer from `PAYER_KEYSTORE_PATH` and `PAYER_KEYSTORE_PASSPHRASE`
+///
+/// # Returns
+///
+/// `None` if `PAYER_KEYSTORE_PATH` is not set, so the payer comes from elsewhere
+pub(crate) fn payer_from_env() -> Option<Result<Keypair, String>> {
+    load_dotenv();
+    let path = env::var("PAYER_KEYSTORE_PATH").ok()?;
+    let Ok(passphrase) = env::var("PAYER_KEYSTORE_PASSPHRASE") else {
+        return Some(Err(format!(
+            "Keystore {} is locked: set PAYER_KEYSTORE_PASSPHRASE or call unlock_payer_keystore",
+            path
+        )));
+    };
+    let passphrase = Zeroizing::new(passphrase);
+    Some(load_encrypted_keypair(&path, &passphrase).map_err(|e| e.to_string()))
+}
+
+/// Encrypts a keypair into the contents of a keystore file, deriving the key with `kdf`
+fn encrypt(keypair: &Keypair, passphrase: &str, kdf: Kdf) -> SssResult<Vec<u8>> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let mut header = Vec::new();
+    header.extend_from_slice(MAGIC);
+    header.push(kdf.version());
+    kdf.encode_params(&mut header);
+    header.extend_from_slice(&salt);
+    header.extend_from_slice(&nonce);
+
+    let key = kdf
+        .derive_key(passphrase, &salt)
+        .ok_or_else(|| SssError::KeypairError("Failed to derive keystore key".to_string()))?;
+    let secret = Zeroizing::new(keypair.to_bytes());
+    let ciphertext = Aes256GcmSiv::new(&(*key).into())
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: secret.as_slice(),
+                aad: &header,
+            },
+        )
+        .map_err(|_| SssError::KeypairError("Failed to encrypt keypair".to_string()))?;
+
+    let mut contents = header;
+    contents.extend_from_slice(&ciphertext);
+    Ok(contents)
+}
+
+/// Decrypts the contents of a keystore file, `None` if the passphrase or file is wrong
+fn decrypt(contents: &[u8], passphrase: &str) -> Option<Keypair> {
+    let rest = contents.strip_prefix(MAGIC)?;
+    let version = *rest.first()?;
+    let params_len = Kdf::params_len(version)?;
+    let header_len = MAGIC.len() + 1 + params_len + SALT_LEN + NONCE_LEN;
+    if contents.len() != header_len + KEYPAIR_LEN + TAG_LEN {
+        return None;
+    }
+    let (header, ciphertext) = contents.split_at(header_len);
+    let (params, rest) = header[MAGIC.len() + 1..].split_at(params_len);
+    let (salt, nonce) = rest.split_at(SALT_LEN);
+    let kdf = Kdf::decode_params(version, params)?;
+
+    let key = kdf.derive_key(passphrase, salt)?;
+    let secret = Zeroizing::new(
+        Aes256GcmSiv::new(&(*key).into())
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: header,
+                },
+            )
+            .ok()?,
+    );
+    Keypair::from_bytes(&secret).ok()
+}
+
+/// Writes a file readable by its owner only, replacing it atomically
+///
+/// A temporary file left over by an interrupted write is removed first: opening it again would
+/// keep its old permissions, since the mode only applies to files the open creates.
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    match fs::remove_file(&temp) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let result = std::io::Write::write_all(&mut options.open(&temp)?, contents)
+        .and_then(|()| fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signer::Signer;
+    use std::path::PathBuf;
+
+    /// Cheap Argon2id parameters, so the tests do not spend seconds deriving keys
+    const FAST: Kdf = Kdf::Argon2id {
+        memory_kib: 64,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("sss-keystore-{}-{}", name, rand::random::<u64>()))
+    }
+
+    fn assert_locked(result: SssResult<Keypair>) {
+        match result {
+            Err(SssError::KeypairError(message)) => {
+                assert!(
+                    message.contains("wrong passphrase or damaged file"),
+                    "{message}"
+                )
+            }
+            Err(e) => panic!("expected a KeypairError, got {e:?}"),
+            Ok(_) => panic!("keystore unlocked"),
+        }
+    }
+
+    #[test]
+    fn round_trip_with_default_parameters() {
+        let path = temp_path("round-trip");
+        let keypair = Keypair::new();
+        save_encrypted_keypair(&path, &keypair, "correct horse").unwrap();
+
+        let contents = fs::read(&path).unwrap();
+        assert_eq!(&contents[..4], MAGIC);
+        assert_eq!(contents[4], VERSION_ARGON2ID);
+        assert_eq!(
+            Kdf::decode_params(VERSION_ARGON2ID, &contents[5..17]),
+            Some(Kdf::DEFAULT)
+        );
+        let loaded = load_encrypted_keypair(&path, "correct horse").unwrap();
+        assert_eq!(loaded.pubkey(), keypair.pubkey());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn header_records_the_parameters_used() {
+        let keypair = Keypair::new();
+        let contents = encrypt(&keypair, "pass", FAST).unwrap();
+        assert_eq!(
+            Kdf::decode_params(contents[4], &contents[5..17]),
+            Some(FAST)
+        );
+        assert_eq!(
+            decrypt(&contents, "pass").map(|k| k.pubkey()),
+            Some(keypair.pubkey())
+        );
+    }
+
+    #[test]
+    fn pbkdf2_keystores_stay_readable() {
+        let keypair = Keypair::new();
+        let contents = encrypt(&keypair, "pass", Kdf::Pbkdf2 { iterations: 1_000 }).unwrap();
+        assert_eq!(contents[4], VERSION_PBKDF2);
+        assert_eq!(
+            decrypt(&contents, "pass").map(|k| k.pubkey()),
+            Some(keypair.pubkey())
+        );
+        assert!(decrypt(&contents, "wrong").is_none());
+    }
+
+    #[test]
+    fn wrong_passphrase_is_a_keypair_error() {
+        let path = temp_path("wrong");
+        fs::write(&path, encrypt(&Keypair::new(), "right", FAST).unwrap()).unwrap();
+        assert_locked(load_encrypted_keypair(&path, "wrong"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn corrupt_files_fail_like_a_wrong_passphrase() {
+        let contents = encrypt(&Keypair::new(), "pass", FAST).unwrap();
+        for i in 0..contents.len() {
+            let mut corrupt = contents.clone();
+            corrupt[i] ^= 0x01;
+            assert!(decrypt(&corrupt, "pass").is_none(), "flipped byte {i}");
+        }
+
+        let path = temp_path("corrupt");
+        let mut corrupt = contents;
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 0xff;
+        fs::write(&path, corrupt).unwrap();
+        assert_locked(load_encrypted_keypair(&path, "pass"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn truncated_and_extended_files_are_rejected() {
+        let contents = encrypt(&Keypair::new(), "pass", FAST).unwrap();
+        for len in 0..contents.len() {
+            assert!(decrypt(&contents[..len], "pass").is_none(), "length {len}");
+        }
+        let mut extended = contents;
+        extended.push(0);
+        assert!(decrypt(&extended, "pass").is_none());
+    }
+
+    #[test]
+    fn unknown_versions_and_oversized_parameters_are_rejected() {
+        let mut contents = encrypt(&Keypair::new(), "pass", FAST).unwrap();
+        contents[4] = 3;
+        assert!(decrypt(&contents, "pass").is_none());
+
+        for kdf in [
+            Kdf::Pbkdf2 { iterations: 0 },
+            Kdf::Pbkdf2 {
+                iterations: MAX_KDF_ITERATIONS + 1,
+            },
+            Kdf::Argon2id {
+                memory_kib: MAX_ARGON2_MEMORY_KIB + 1,
+                iterations: 1,
+                parallelism: 1,
+            },
+            Kdf::Argon2id {
+                memory_kib: 64,
+                iterations: 0,
+                parallelism: 1,
+            },
+            Kdf::Argon2id {
+                memory_kib: 64,
+                iterations: 1,
+                parallelism: MAX_ARGON2_PARALLELISM + 1,
+            },
+        ] {
+            let mut params = Vec::new();
+            kdf.encode_params(&mut params);
+            assert_eq!(Kdf::decode_params(kdf.version(), &params), None, "{kdf:?}");
+        }
+    }
+
+    #[test]
+    fn empty_passphrase_is_refused() {
+        let path = temp_path("empty");
+        assert!(matches!(
+            save_encrypted_keypair(&path, &Keypair::new(), ""),
+            Err(SssError::KeypairError(_))
+        ));
+        assert!(!path.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn leftover_temporary_file_does_not_widen_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = temp_path("leftover");
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        fs::write(&temp, b"stale").unwrap();
+        fs::set_permissions(&temp, fs::Permissions::from_mode(0o644)).unwrap();
+
+        write_private(&path, b"fresh").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"fresh");
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+        assert!(!Path::new(&temp).exists());
+        fs::remove_file(&path).unwrap();
+    }
+}
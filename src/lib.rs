@@ -3,67 +3,518 @@
 //! This library provides functionality for creating and managing tokens on the Solana blockchain.
 //! It includes both Rust functions for direct use and FFI functions for C interoperability.
 
+mod activity;
+mod airdrop;
+mod allocations_csv;
+mod amount;
+mod batch;
+mod blockhash;
+mod builder;
+mod burn;
+mod cache;
+mod capabilities;
+mod claim;
+mod claim_link;
+mod confirm;
+mod consistency;
+mod consolidate;
+mod consumable_policy;
+#[cfg(feature = "http-metadata")]
+mod content_cache;
+mod creators;
+#[cfg(feature = "das")]
+mod das;
+#[cfg(feature = "das")]
+mod das_cache;
+mod dedup;
+#[cfg(feature = "dev-tools")]
+mod devtools;
+mod dispatch;
+mod display;
+mod dust;
 mod error;
+mod events;
+mod expiry;
+#[cfg(feature = "dev-tools")]
+mod faucet;
+mod fees;
+#[cfg(feature = "ffi")]
 mod ffi;
+#[cfg(feature = "ffi")]
+mod ffi_call;
+#[cfg(feature = "ffi")]
+mod ffi_pool;
+#[cfg(feature = "ffi")]
+mod ffi_schema;
+#[cfg(all(feature = "ffi", feature = "das"))]
+mod ffi_stream;
+#[cfg(feature = "ffi")]
 mod ffi_utils;
+mod holders;
+mod in_flight;
+mod inspect;
+mod instructions;
+mod journal;
+mod journal_audit;
+#[cfg(feature = "keystore")]
+mod keystore;
+#[cfg(feature = "http-metadata")]
+mod metadata_swap;
+mod metrics;
+mod migration;
+mod mint_cache;
+mod names;
+mod offchain_metadata;
+mod payer;
+mod payment;
+mod policies;
+mod profiles;
+mod read_only;
+mod receipt;
+mod receipt_print;
+mod recovery;
+mod retry;
+mod review;
+mod roles;
+mod rollback;
+mod rotation;
+mod serde_utils;
+mod signed_commands;
+mod signing;
+mod sns;
+mod soulbound;
+mod structured_signing;
+mod subscriptions;
+mod supply_caps;
+mod supply_monitor;
+mod templates;
 mod token;
+mod token_accounts;
+mod transaction;
+mod transfer;
+mod trusted_mints;
+mod vault;
+mod warmup;
+mod watch_only;
 
+pub use activity::{
+    ActivityItem, ActivityKind, ActivityPage, wallet_activity, wallet_activity_page,
+    wallet_activity_page_with_client,
+};
+pub use airdrop::{
+    AIRDROP_ROWS_PER_CHUNK, AirdropReport, execute_airdrop, execute_airdrop_from_rows,
+    execute_airdrop_with_options,
+};
+pub use allocations_csv::{
+    AllocationRows, CsvAmounts, CsvImportOptions, DuplicateWallets, load_allocations_csv,
+    load_allocations_csv_with_options, write_batch_report_csv,
+};
+pub use amount::{
+    AmountSpec, FormatOptions, RawAmount, UiAmount, base_to_ui_amount, format_token_amount,
+    parse_token_amount, ui_to_base_amount,
+};
+pub use batch::{BatchOptions, BatchReport, DEFAULT_THROUGHPUT_WAVE_SIZE};
+pub use blockhash::{
+    BlockhashCache, DEFAULT_BLOCKHASH_MAX_AGE, start_blockhash_refresh, stop_blockhash_refresh,
+};
+pub use builder::{MINT_PLACEHOLDER, TokenBuilder};
+pub use burn::{build_burn_ix, burn_tokens};
+pub use cache::{CacheStats, DEFAULT_CACHE_MAX_ENTRIES, TrimReport, cache_stats, trim_caches};
+pub use capabilities::{Capabilities, capabilities};
+pub use chrono::{DateTime, Utc};
+pub use claim::{
+    ClaimOptions, build_claim_transaction, build_claim_transaction_with_client,
+    build_claim_transaction_with_options, set_claim_amount_cap, submit_claim,
+    submit_claim_with_client,
+};
+pub use claim_link::{
+    ClaimLink, create_claim_link, create_claim_link_with_client, reclaim_claim_link,
+    reclaim_claim_link_with_client, redeem_claim_link, redeem_claim_link_with_client,
+};
+pub use confirm::{
+    CancelToken, ConfirmationOutcome, ConfirmationStrategy, Confirmer,
+    DEFAULT_CONFIRMATION_TIMEOUT, TxStatus, confirm_signatures_bulk,
+};
+pub use consistency::{
+    ConsistencyFinding, ConsistencyReport, ConsistencySeverity, audit_token_consistency,
+    audit_token_consistency_with_client,
+};
+pub use consolidate::consolidate_balances;
+pub use consumable_policy::{
+    ConsumablePolicy, ConsumableRule, PolicyViolation, audit_existing_mints,
+    audit_existing_mints_with_client, consumable_policy, set_consumable_policy,
+};
+#[cfg(feature = "http-metadata")]
+pub use content_cache::{
+    ContentCacheConfig, evict_cache, fetch_asset_image, fetch_asset_image_by_id,
+    fetch_asset_image_by_id_with_config, fetch_asset_image_with_config,
+};
+pub use creators::{CreatorShare, verify_creator};
+#[cfg(feature = "das")]
+pub use das::{
+    ASSET_BATCH_LIMIT, AssetList, AssetPage, AssetProgress, AssetProgressCallback,
+    AssetQueryOptions, AssetSearch, AssetSource, AssetStream, AssetStreamOptions,
+    DEFAULT_PAGE_ATTEMPTS, DasAuthStyle, DasProvider, DasSearchFilter, DigitalAsset,
+    IndexingStatus, fetch_all_digital_assets, fetch_digital_assets, fetch_digital_assets_by_owner,
+    fetch_digital_assets_by_owner_with_options, get_asset, search_assets, set_das_provider,
+    wait_for_asset_indexed,
+};
+#[cfg(feature = "das")]
+pub use das_cache::{
+    DEFAULT_DAS_CACHE_DIR, DasCacheEntry, DasCacheMode, DasCachedRequest, DasCachedResponse,
+    prune_das_cache,
+};
+pub use dedup::{
+    DEFAULT_DEDUP_TTL, DedupRecord, DedupStore, FileDedupStore, dedup_compact, dedup_enabled,
+    set_dedup_store,
+};
+#[cfg(feature = "dev-tools")]
+pub use devtools::{
+    AssetFilter, CleanupAction, CleanupEntry, CleanupReport, burn_all_assets, request_airdrop,
+    set_allow_mainnet_cleanup,
+};
+pub use dispatch::{
+    CallbackQueue, DEFAULT_BLOCK_TIMEOUT, DEFAULT_CALLBACK_WATCHDOG, OverflowPolicy, QueueMetrics,
+    QueueOptions, callback_queue_options, set_callback_queue_options,
+};
+pub use display::{
+    DEFAULT_SHORT_HEAD, DEFAULT_SHORT_TAIL, SolanaPayUri, parse_solana_uri, pubkey_color_hash,
+    qr_payload_for_address, short_pubkey,
+};
+pub use dust::{DustAccount, DustDestination, find_dust_accounts, sweep_dust};
 pub use error::{SssError, SssResult};
-pub use ffi::{create_token, free_string, mint_token_ffi};
-pub use token::{create_consumable_token, create_new_token, mint_token};
+pub use events::{EVENT_QUEUE_SIZE, SssEvent, clear_event_sink, dropped_events, set_event_sink};
+#[cfg(feature = "http-metadata")]
+pub use expiry::is_asset_expired;
+pub use expiry::{
+    CLUSTER_TIME_CACHE_TTL, DEFAULT_EXPIRY_GRACE_PERIOD, cluster_time, is_metadata_expired,
+    set_expiry_grace_period,
+};
+#[cfg(feature = "dev-tools")]
+pub use faucet::{
+    FaucetLimit, FaucetOutcome, FaucetPolicy, faucet_grant, faucet_grant_with_client,
+};
+pub use fees::{
+    DailyFeeSpend, FeeCategory, FeeReport, FeeSpend, fee_report, fee_report_with_client,
+};
+#[cfg(all(feature = "ffi", feature = "http-metadata"))]
+pub use ffi::sss_fetch_asset_image;
+#[cfg(all(feature = "ffi", feature = "keystore"))]
+pub use ffi::sss_unlock_keystore;
+#[cfg(all(feature = "ffi", feature = "das"))]
+pub use ffi::{
+    SssAssetPageCallback, SssChunkCallback, sss_fetch_all_assets, sss_fetch_assets_stream,
+};
+#[cfg(feature = "ffi")]
+pub use ffi::{
+    SssBalanceCallback, SssCompletionCallback, SssEventCallback, SssFormatOptions,
+    SssRecipientStatus, create_token, free_string, mint_token_ffi, sss_add_trusted_mint,
+    sss_attach_metadata, sss_audit_token_consistency, sss_await_payment,
+    sss_build_claim_transaction, sss_cache_stats, sss_call, sss_capabilities, sss_check_recipient,
+    sss_create_claim_link, sss_create_payment_request, sss_create_token_from_template,
+    sss_create_token_with_creators, sss_execute_airdrop, sss_execute_signed_command,
+    sss_ffi_schema_version, sss_format_token_amount, sss_generate_payment_reference,
+    sss_get_last_error, sss_get_payer_info, sss_get_payer_pubkey, sss_init_watch_only,
+    sss_metrics_json, sss_migrate_legacy_mints, sss_mint_basket, sss_mint_token_async,
+    sss_mint_token_idempotent, sss_mint_token_to_account, sss_mint_token_to_account_ui,
+    sss_mint_token_ui, sss_monitor_supply, sss_parse_solana_uri, sss_parse_token_amount,
+    sss_pending_count, sss_primary_domain_for, sss_pubkey_color_hash, sss_qr_payload_for_address,
+    sss_reclaim_claim_link, sss_recover_in_flight, sss_redeem_claim_link, sss_reload_policies,
+    sss_render_receipt, sss_resolve_recipient, sss_review_external_transaction, sss_select_profile,
+    sss_set_callback_queue_options, sss_set_event_callback, sss_set_ffi_domain_resolution,
+    sss_set_keep_alive, sss_set_read_only, sss_set_strict_mode, sss_short_pubkey, sss_shutdown,
+    sss_sign_message, sss_sign_structured, sss_stop_supply_monitor, sss_subscribe_balance,
+    sss_transfer_asset, sss_transfer_asset_ui, sss_trim_caches, sss_unsubscribe,
+    sss_verify_creator, sss_verify_message, sss_verify_signed_command, sss_verify_structured,
+    sss_wallet_activity, sss_warm_up,
+};
+#[cfg(feature = "ffi")]
+pub use ffi_schema::FFI_SCHEMA_VERSION;
+#[cfg(feature = "ffi")]
+pub use ffi_utils::{
+    FFI_ERROR_MESSAGE_LEN, MAX_FFI_BUFFER_LEN, SSS_ERR_READ_ONLY, SSS_ERR_WATCH_ONLY, SssFfiError,
+};
+pub use holders::{
+    HolderBalance, HolderSnapshot, snapshot_holders_at_slot, snapshot_holders_at_slot_with_client,
+    verify_snapshot,
+};
+pub use in_flight::{DEFAULT_IN_FLIGHT_TIMEOUT, DuplicatePolicy};
+pub use inspect::{
+    DecodedInstruction, InspectedAccount, InspectedInstruction, MEMO_PROGRAM_ID, TxInspection,
+    decode_instruction, decode_program_error, inspect_message, inspect_transaction, program_name,
+};
+pub use journal::{
+    JournalEntry, JournalFilter, JournalOperation, JournalStatus, journal_enabled, journal_query,
+    journal_reconcile, journal_reconcile_with_client,
+};
+pub use journal_audit::{
+    BrokenLink, JournalChainStatus, JournalDigest, JournalVerification, export_journal_digest,
+    set_journal_audit_key, verify_journal, verify_journal_with_key,
+};
+#[cfg(feature = "keystore")]
+pub use keystore::{load_encrypted_keypair, save_encrypted_keypair, unlock_payer_keystore};
+#[cfg(feature = "http-metadata")]
+pub use metadata_swap::{
+    DirectoryUploadTarget, MetadataUploadTarget, StagedUpload, SwapOutcome, SwapStep,
+    SwapStepRecord, set_upload_target, swap_metadata, swap_metadata_with_client,
+};
+pub use metrics::{
+    LATENCY_BUCKETS_MS, MetricsReport, OperationMetrics, metrics_snapshot, reset_metrics,
+};
+pub use migration::{
+    LegacyMigration, MigrationReport, MigrationStatus, MintMigration, migrate_legacy_mints,
+    migrate_legacy_mints_with_client,
+};
+pub use mint_cache::{clear_mint_cache, metadata_pda};
+pub use mpl_token_metadata::types::TokenStandard;
+pub use names::{
+    NameChange, NameRejection, NameValidationReport, normalize_token_name, normalize_token_symbol,
+    validate_token_name, validate_token_symbol,
+};
+pub use offchain_metadata::{
+    Attribute, LocalizationWarning, LocalizedFields, LocalizedMetadata, MetadataFile,
+    MetadataProperties, OffchainMetadata, VALID_UNTIL_TRAIT, parse_metadata_json, render_localized,
+    render_metadata_json, validate_localizations,
+};
+pub use payer::{PayerInfo, get_payer_pubkey, payer_info};
+pub use payment::{
+    PAYMENT_POLL_INTERVAL, PaymentConfirmation, PaymentRequest, await_payment,
+    create_payment_request, generate_payment_reference,
+};
+pub use policies::{PolicyReload, reload_policy_files};
+pub use profiles::{ProfileConfig, active_profile, register_profile, select_profile, with_profile};
+pub use read_only::{READ_ONLY_MESSAGE, is_read_only, set_read_only};
+pub use receipt::{
+    LamportBalanceChange, MintExpectation, Receipt, TokenBalanceChange, fetch_transaction_receipt,
+    fetch_transaction_receipt_with_client,
+};
+pub use receipt_print::{
+    DEFAULT_RECEIPT_WIDTH, ReceiptFormat, ReceiptTemplate, render_receipt_payload,
+};
+pub use recovery::{RecoveryOutcome, recover_in_flight, recover_in_flight_with_client};
+pub use retry::{RETRYABLE_ERRORS, RetryPolicy, RetryRule, classify_rpc_error};
+pub use review::{
+    BLOCKHASH_REUSE_WINDOW, DEFAULT_MAX_PAYER_OUTFLOW_LAMPORTS, ReviewOutcome, ReviewPolicy,
+    ReviewViolation, review_external_transaction,
+};
+pub use roles::{
+    DEFAULT_ROLE, OperationPermission, Permission, RolePermissions, RolePolicy, active_role,
+    role_permissions, role_policy, set_role_policy,
+};
+pub use rollback::{
+    AccountRollback, CreatedAccount, CreatedAccounts, RollbackOutcome, RollbackSkip,
+    rollback_created_accounts, rollback_created_accounts_with_client,
+};
+pub use rotation::{
+    AuthorityKinds, MintRotation, RotationOutcome, RotationReport, rotate_authorities,
+    rotate_authorities_with_client,
+};
+pub use signed_commands::{
+    CommandOperation, SignedCommand, execute_signed_command, verify_signed_command,
+};
+pub use signing::{
+    MAX_OUTSTANDING_CHALLENGES, OwnershipChallenge, create_ownership_challenge, sign_message,
+    verify_message, verify_ownership_proof,
+};
+pub use sns::{
+    DEFAULT_SNS_CACHE_TTL, NAME_SERVICE_PROGRAM_ID, SOL_ROOT_DOMAIN, primary_domain_for,
+    resolve_recipient, set_ffi_domain_resolution,
+};
+pub use soulbound::{add_soulbound_mint, is_soulbound_mint};
+pub use spl_token::state::AccountState;
+pub use structured_signing::{
+    SigningDomain, StructuredSignature, canonical_encoding, sign_structured, verify_structured,
+};
+pub use subscriptions::{
+    DEFAULT_BALANCE_POLL_INTERVAL, subscribe_token_balance, unsubscribe_token_balance,
+};
+pub use supply_caps::{
+    SupplyCapChange, SupplyCapViolation, export_supply_caps, import_supply_caps, remove_supply_cap,
+    set_supply_cap, supply_cap,
+};
+pub use supply_monitor::{
+    MonitorHandle, SUPPLY_MONITOR_MAX_BACKOFF, ScheduleInterpolation, SupplyAnomaly,
+    SupplySchedule, monitor_supply, monitor_supply_with_callback, monitor_supply_with_client,
+    stop_supply_monitor,
+};
+pub use templates::{
+    TemplateKind, TemplateOverrides, TokenTemplate, create_token_from_template,
+    register_token_template, token_template,
+};
+pub use token::{
+    CreateOptions, CreateRecovery, MintOptions, RecipientStatus, TokenOperationResult,
+    TokenProgram, attach_metadata_to_existing_mint, attach_metadata_to_existing_mint_with_client,
+    build_mint_ix, check_recipient, check_recipient_with_options, check_recipient_with_resolution,
+    create_consumable_token, create_consumable_token_with_client,
+    create_consumable_token_with_options, create_new_token, create_new_token_with_options,
+    mint_basket, mint_token, mint_token_detailed, mint_token_detailed_with_client,
+    mint_token_idempotent, mint_token_to_account, mint_token_to_account_with_options,
+    mint_token_with_client, mint_token_with_options, resume_create, resume_create_with_client,
+};
+pub use token_accounts::{
+    ResolutionWarning, TokenAccountResolution, TokenBalance, get_token_balance,
+};
+pub use transaction::{
+    TxOptions, TxSizeInfo, assert_tx_size, message_from_base64, message_to_base64,
+};
+pub use transfer::{
+    TransferOptions, build_transfer_ix, transfer_asset, transfer_asset_with_client,
+    transfer_asset_with_options,
+};
+pub use trusted_mints::{
+    add_trusted_mint, is_trusted_mint, set_strict_mode, set_trusted_mints, strict_mode_enabled,
+};
+pub use vault::{
+    VAULT_NAMESPACE, build_create_vault_ix, derive_vault_address, derive_vault_address_with_base,
+    fund_vault, vault_balance, vault_seed, withdraw_from_vault,
+};
+pub use warmup::{
+    KEEP_ALIVE_MAX_BACKOFF, WarmupReport, start_keep_alive, stop_keep_alive, warm_up,
+};
+pub use watch_only::{WATCH_ONLY_MESSAGE, init_watch_only, is_watch_only};
 
+#[cfg(feature = "mnemonic")]
 use bip39::{Language, Mnemonic, Seed};
-use dotenv::dotenv;
 use lazy_static::lazy_static;
 use solana_rpc_client::rpc_client::RpcClient;
-use solana_sdk::signature::{Keypair, keypair_from_seed};
+use solana_sdk::signature::Keypair;
+#[cfg(feature = "mnemonic")]
+use solana_sdk::signature::keypair_from_seed;
+use solana_sdk::signer::Signer;
 use std::{
     env,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
 // Initialize the RPC client using environment variables
 lazy_static! {
     /// Global RPC client initialized from environment variables
     pub static ref RPC_CLIENT: RpcClient = {
-        dotenv().ok();
+        load_dotenv();
         let rpc_url = env::var("SOLANA_RPC_URL")
             .unwrap_or_else(|_| "https://api.devnet.solana.com".to_string());
         RpcClient::new(rpc_url)
     };
 
+    /// Global blockhash cache shared by the transaction builders
+    pub static ref BLOCKHASH_CACHE: BlockhashCache = {
+        load_dotenv();
+        let max_age = env::var("SSS_BLOCKHASH_MAX_AGE_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_BLOCKHASH_MAX_AGE);
+        BlockhashCache::new(max_age)
+    };
+
     /// Global payer keypair result initialized from environment variables
     pub static ref PAYER_RESULT: Arc<Mutex<Result<Keypair, String>>> = {
-        dotenv().ok();
-        let result = match env::var("PAYER_MNEMONIC") {
-            Ok(mnemonic_phrase) => {
-                match Mnemonic::from_phrase(&mnemonic_phrase, Language::English) {
-                    Ok(mnemonic) => {
-                        let seed = Seed::new(&mnemonic, "");
-                        match keypair_from_seed(seed.as_bytes()) {
-                            Ok(keypair) => Ok(keypair),
-                            Err(e) => Err(format!("Failed to derive keypair from seed: {}", e)),
-                        }
-                    }
-                    Err(e) => Err(format!("Invalid mnemonic phrase: {}", e)),
+        load_dotenv();
+        // Nothing is read, not even to fail, in watch-only mode
+        if watch_only::is_watch_only() {
+            return Arc::new(Mutex::new(Err(watch_only::WATCH_ONLY_MESSAGE.to_string())));
+        }
+        #[cfg(feature = "keystore")]
+        let payer = keystore::payer_from_env().unwrap_or_else(load_payer);
+        #[cfg(not(feature = "keystore"))]
+        let payer = load_payer();
+        Arc::new(Mutex::new(payer))
+    };
+}
+
+/// Loads variables from a `.env` file into the process environment
+///
+/// Without the `env-config` feature, configuration is read from the process
+/// environment only.
+pub(crate) fn load_dotenv() {
+    #[cfg(feature = "env-config")]
+    dotenv::dotenv().ok();
+}
+
+/// Derives the payer keypair from the `PAYER_MNEMONIC` variable
+#[cfg(feature = "mnemonic")]
+fn load_payer() -> Result<Keypair, String> {
+    match env::var("PAYER_MNEMONIC") {
+        Ok(mnemonic_phrase) => match Mnemonic::from_phrase(&mnemonic_phrase, Language::English) {
+            Ok(mnemonic) => {
+                let seed = Seed::new(&mnemonic, "");
+                match keypair_from_seed(seed.as_bytes()) {
+                    Ok(keypair) => Ok(keypair),
+                    Err(e) => Err(format!("Failed to derive keypair from seed: {}", e)),
                 }
             }
-            Err(e) => Err(format!("Payer mnemonic not found in .env file: {}", e)),
-        };
-        Arc::new(Mutex::new(result))
-    };
+            Err(e) => Err(format!("Invalid mnemonic phrase: {}", e)),
+        },
+        Err(e) => Err(format!("Payer mnemonic not found in .env file: {}", e)),
+    }
+}
+
+/// Without the `mnemonic` feature the payer must be provided with [`set_payer`]
+#[cfg(not(feature = "mnemonic"))]
+fn load_payer() -> Result<Keypair, String> {
+    Err("Payer not set: call set_payer or enable the mnemonic feature".to_string())
+}
+
+/// Replaces the payer keypair loaded from `PAYER_KEYSTORE_PATH` or `PAYER_MNEMONIC`
+///
+/// Useful when the key comes from elsewhere, or to build reproducible transactions
+/// with a fixed keypair. Ignored in watch-only mode.
+///
+/// # Arguments
+///
+/// * `keypair` - The keypair paying for and signing every transaction
+pub fn set_payer(keypair: Keypair) {
+    if watch_only::is_watch_only() {
+        return;
+    }
+    if let Ok(mut payer) = PAYER_RESULT.lock() {
+        payer::cache_payer_pubkey(Some(keypair.pubkey()));
+        *payer = Ok(keypair);
+    }
+}
+
+/// Drops the payer keypair, so loading it fails with the given reason
+pub(crate) fn drop_payer(reason: &str) {
+    if let Ok(mut payer) = PAYER_RESULT.lock() {
+        payer::cache_payer_pubkey(None);
+        *payer = Err(reason.to_string());
+    }
+}
+
+/// Stops the library's background activity before the host exits
+///
+/// No event reaches the sink once this returns: an event being delivered is waited for,
+/// unless the sink is stalled past `SSS_CALLBACK_WATCHDOG_MS`, queued ones are discarded
+/// and later ones are never queued. Drops not reported yet are still announced with a
+/// last `events_dropped` event. Balance subscriptions are dropped as well, supply
+/// monitors stop, and the connection keep-alive stops. Asynchronous FFI calls already
+/// queued run first, callbacks included. Operations still work afterwards, silently.
+pub fn shutdown() {
+    #[cfg(feature = "ffi")]
+    ffi_pool::drain();
+    events::shutdown_events();
+    subscriptions::clear_subscriptions();
+    supply_monitor::stop_supply_monitors();
+    warmup::stop_keep_alive();
 }
 
 /// Helper function to get the payer keypair
 ///
 /// # Returns
 ///
-/// A new keypair cloned from the global payer keypair
+/// A new keypair cloned from the payer of the profile selected on the current thread, or
+/// else from the global payer keypair
 ///
 /// # Errors
 ///
-/// Returns an error if the payer keypair is not initialized or if there's an error cloning it
+/// Returns an error if the payer keypair is not initialized or if there's an error cloning it,
+/// and a `KeypairError` in watch-only mode
 pub fn get_payer() -> Result<Keypair, Box<dyn std::error::Error>> {
+    watch_only::ensure_not_watch_only()?;
+    if let Some(payer) = profiles::profile_setting(|profile| profile.payer.insecure_clone())? {
+        return Ok(payer);
+    }
     let lock_result = PAYER_RESULT
         .lock()
         .map_err(|e| format!("Failed to acquire lock: {}", e))?;
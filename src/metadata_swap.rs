@@ -0,0 +1,414 @@
+//! Swapping the off-chain metadata of a token without orphaned or missing documents
+//!
+//! Pointing a token at a new document takes an upload and an on-chain `UpdateV1`. If the
+//! upload lands but the update fails, the new document is orphaned; if the old document is
+//! removed before the update confirms, the chain points at nothing. [`swap_metadata`] runs
+//! the steps in an order where each failure can be undone: stage the upload, check it can
+//! be fetched, update the chain and wait for confirmation, and only then promote the new
+//! document and remove the old one. A failure before confirmation removes the staged
+//! upload again. Uploads go through the [`MetadataUploadTarget`] set with
+//! [`set_upload_target`].
+
+use crate::RPC_CLIENT;
+use crate::content_cache::fetch_metadata;
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::in_flight::exclusive;
+use crate::instructions;
+use crate::journal::{JournalOperation, journaled};
+use crate::metrics::track;
+use crate::mint_cache::metadata_pda;
+use crate::offchain_metadata::{OffchainMetadata, parse_metadata_json, render_metadata_json};
+use crate::payer::signing_payer;
+use crate::read_only::ensure_writable;
+use crate::serde_utils::pubkey_string;
+use crate::token::ensure_trusted_mint;
+use crate::transaction::{
+    TxOptions, assert_tx_size, required_signatures, sign_and_send_reporting_attempt,
+};
+use mpl_token_metadata::accounts::Metadata;
+use mpl_token_metadata::types::Data;
+use serde::Serialize;
+use serde_json::json;
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_sdk::{message::Message, pubkey::Pubkey, signer::Signer};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The target metadata documents are uploaded to
+static UPLOAD_TARGET: Mutex<Option<Arc<dyn MetadataUploadTarget>>> = Mutex::new(None);
+
+/// Storage serving off-chain metadata documents, such as a bucket behind a CDN
+pub trait MetadataUploadTarget: Send + Sync {
+    /// Uploads a document as a new version for a mint, under a key no other version uses
+    ///
+    /// The document must be servable at the returned URI once this returns.
+    fn stage(&self, mint: &Pubkey, document: &[u8]) -> SssResult<StagedUpload>;
+
+    /// Makes a staged upload permanent once the chain points at it, e.g. by pinning it or
+    /// lifting a short retention; does nothing by default
+    fn promote(&self, upload: &StagedUpload) -> SssResult<()> {
+        let _ = upload;
+        Ok(())
+    }
+
+    /// Removes a document by URI: a staged upload after a failed swap, or the document the
+    /// chain pointed at before a swap
+    ///
+    /// A URI the target does not serve, such as a document uploaded by another tool, must
+    /// be left alone without an error.
+    fn remove(&self, uri: &str) -> SssResult<()>;
+}
+
+/// A document uploaded by a [`MetadataUploadTarget`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StagedUpload {
+    /// The storage key, e.g. an object name
+    pub key: String,
+    /// The URI the document is served at, written on chain
+    pub uri: String,
+}
+
+/// An upload target writing documents into a directory served by a web server
+///
+/// Each version is written to `<dir>/<mint>/<unix millis>.json` and served at
+/// `<base_url>/<mint>/<unix millis>.json`.
+#[derive(Debug, Clone)]
+pub struct DirectoryUploadTarget {
+    dir: PathBuf,
+    base_url: String,
+}
+
+impl DirectoryUploadTarget {
+    /// Creates a target writing into a directory
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory the web server serves
+    /// * `base_url` - The URL the directory is served at
+    pub fn new(dir: impl Into<PathBuf>, base_url: impl Into<String>) -> Self {
+        Self {
+            dir: dir.into(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+impl MetadataUploadTarget for DirectoryUploadTarget {
+    fn stage(&self, mint: &Pubkey, document: &[u8]) -> SssResult<StagedUpload> {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0);
+        let key = format!("{}/{}.json", mint, millis);
+        let path = self.dir.join(&key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).into_sss_error("Failed to create metadata directory")?;
+        }
+        // Written aside and renamed, so the web server never serves a partial document
+        let partial = path.with_extension("json.partial");
+        fs::write(&partial, document).into_sss_error("Failed to write metadata document")?;
+        fs::rename(&partial, &path).into_sss_error("Failed to write metadata document")?;
+        Ok(StagedUpload {
+            uri: format!("{}/{}", self.base_url, key),
+            key,
+        })
+    }
+
+    fn remove(&self, uri: &str) -> SssResult<()> {
+        let Some(key) = uri
+            .strip_prefix(&self.base_url)
+            .and_then(|rest| rest.strip_prefix('/'))
+        else {
+            return Ok(());
+        };
+        if key.split('/').any(|part| part.is_empty() || part == "..") {
+            return Ok(());
+        }
+        match fs::remove_file(self.dir.join(key)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).into_sss_error("Failed to remove metadata document")
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Sets the target [`swap_metadata`] uploads documents to
+///
+/// # Arguments
+///
+/// * `target` - The upload target, replacing any previous one
+pub fn set_upload_target(target: Box<dyn MetadataUploadTarget>) {
+    if let Ok(mut current) = UPLOAD_TARGET.lock() {
+        *current = Some(Arc::from(target));
+    }
+}
+
+/// A step of a metadata swap, in the order they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SwapStep {
+    /// Uploading the new document
+    Stage,
+    /// Fetching the staged document back and comparing it with the one uploaded
+    Verify,
+    /// Sending the on-chain update of the URI
+    Send,
+    /// Waiting for the update to confirm
+    Confirm,
+    /// Making the new document permanent
+    Promote,
+    /// Removing the document the chain pointed at before
+    Cleanup,
+    /// Removing the staged document after a failure before confirmation
+    Rollback,
+}
+
+/// How a step of a metadata swap went
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SwapStepRecord {
+    /// The step
+    pub step: SwapStep,
+    /// Whether the step succeeded
+    pub ok: bool,
+    /// The error of a failed step, or what was done
+    pub detail: Option<String>,
+}
+
+/// Every step of a [`swap_metadata`] call, for the journal or an audit log
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SwapOutcome {
+    /// The token
+    #[serde(with = "pubkey_string")]
+    pub mint: Pubkey,
+    /// The URI on chain before the swap
+    pub previous_uri: String,
+    /// The URI of the staged document, if the upload succeeded
+    pub new_uri: Option<String>,
+    /// The signature of the on-chain update, if it was sent
+    pub signature: Option<String>,
+    /// The steps run, in order
+    pub steps: Vec<SwapStepRecord>,
+}
+
+impl SwapOutcome {
+    /// Returns whether the chain was confirmed to point at the new document
+    ///
+    /// A failed promotion or cleanup does not undo the swap; see [`Self::steps`].
+    pub fn swapped(&self) -> bool {
+        self.steps
+            .iter()
+            .any(|record| record.step == SwapStep::Confirm && record.ok)
+    }
+
+    /// Returns whether the update was sent but not confirmed, so the chain may point at
+    /// either document and the staged one was kept
+    ///
+    /// Resolve it with [`recover_in_flight`](crate::recover_in_flight) or by looking up
+    /// [`Self::signature`].
+    pub fn outcome_unknown(&self) -> bool {
+        self.signature.is_some()
+            && self
+                .steps
+                .iter()
+                .any(|record| record.step == SwapStep::Confirm && !record.ok)
+    }
+
+    fn record(&mut self, step: SwapStep, result: Result<Option<String>, &SssError>) {
+        let (ok, detail) = match result {
+            Ok(detail) => (true, detail),
+            Err(e) => (false, Some(e.to_string())),
+        };
+        self.steps.push(SwapStepRecord { step, ok, detail });
+    }
+}
+
+/// Points a token at a new off-chain metadata document
+///
+/// The document is uploaded to a fresh key of the upload target and fetched back to check
+/// it is served intact. The on-chain URI is then updated, keeping the name, symbol, royalty
+/// and creators, and the update is awaited. Only once it confirms is the new document
+/// promoted and the previous one removed. If staging, verification or sending fails, or
+/// the cluster rejects the update, the staged document is removed again and the chain
+/// still points at the previous one. If the update was sent but its confirmation timed
+/// out, the staged document is kept, since the chain may already point at it.
+///
+/// # Arguments
+///
+/// * `mint` - The token, whose update authority must be the payer
+/// * `new_metadata` - The new document
+///
+/// # Returns
+///
+/// Every step run and how it went; a failed step is recorded there rather than returned as
+/// an error, see [`SwapOutcome::swapped`]
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if no upload target is set, and a `TokenError` if the mint has
+/// no metadata, its metadata is immutable or the payer is not its update authority
+pub fn swap_metadata(mint: Pubkey, new_metadata: OffchainMetadata) -> SssResult<SwapOutcome> {
+    swap_metadata_with_client(&RPC_CLIENT, mint, new_metadata)
+}
+
+/// Points a token at a new off-chain metadata document like [`swap_metadata`], through an
+/// explicit RPC client
+///
+/// # Arguments
+///
+/// * `client` - The RPC client reading the metadata and sending the update
+/// * `mint` - The token, whose update authority must be the payer
+/// * `new_metadata` - The new document
+///
+/// # Errors
+///
+/// The same as [`swap_metadata`]
+pub fn swap_metadata_with_client(
+    client: &RpcClient,
+    mint: Pubkey,
+    new_metadata: OffchainMetadata,
+) -> SssResult<SwapOutcome> {
+    ensure_writable()?;
+    ensure_trusted_mint(&mint)?;
+    let target = UPLOAD_TARGET
+        .lock()
+        .ok()
+        .and_then(|target| target.clone())
+        .ok_or_else(|| {
+            SssError::ConfigError(
+                "No metadata upload target set; call set_upload_target".to_string(),
+            )
+        })?;
+    let document = render_metadata_json(&new_metadata);
+    let params = json!({ "mint": mint.to_string(), "document": document });
+    let tx_options = TxOptions::default();
+    track(JournalOperation::SwapMetadata, || {
+        exclusive(JournalOperation::SwapMetadata, &params, &tx_options, || {
+            let payer = signing_payer()?;
+            let metadata = current_metadata(client, &mint, &payer.pubkey())?;
+            let mut outcome = SwapOutcome {
+                mint,
+                previous_uri: metadata.uri.trim_end_matches('\0').to_string(),
+                new_uri: None,
+                signature: None,
+                steps: Vec::new(),
+            };
+
+            let staged = match target.stage(&mint, document.as_bytes()) {
+                Ok(staged) => staged,
+                Err(e) => {
+                    outcome.record(SwapStep::Stage, Err(&e));
+                    return Ok(outcome);
+                }
+            };
+            outcome.new_uri = Some(staged.uri.clone());
+            outcome.record(SwapStep::Stage, Ok(Some(staged.key.clone())));
+
+            let verified = verify_staged(&staged.uri, &document);
+            outcome.record(SwapStep::Verify, verified.as_ref().map(|_| None));
+            if verified.is_err() {
+                rollback(target.as_ref(), &staged, &mut outcome);
+                return Ok(outcome);
+            }
+
+            let data = Data {
+                name: metadata.name.trim_end_matches('\0').to_string(),
+                symbol: metadata.symbol.trim_end_matches('\0').to_string(),
+                uri: staged.uri.clone(),
+                seller_fee_basis_points: metadata.seller_fee_basis_points,
+                creators: metadata.creators.clone(),
+            };
+            let sent = instructions::update_data_v1(
+                Some(metadata_pda(&mint)),
+                Some(mint),
+                Some(payer.pubkey()),
+                Some(payer.pubkey()),
+                data,
+            )
+            .and_then(|ix| {
+                let message = Message::new(&[ix], Some(&payer.pubkey()));
+                assert_tx_size(&message, required_signatures(&message))?;
+                Ok(message)
+            })
+            .map_err(|e| (e, None))
+            .and_then(|message| {
+                journaled(JournalOperation::SwapMetadata, &params, &tx_options, || {
+                    sign_and_send_reporting_attempt(client, message, &[&payer], &tx_options)
+                })
+            });
+            match sent {
+                Ok(signature) => {
+                    outcome.signature = Some(signature.clone());
+                    outcome.record(SwapStep::Send, Ok(Some(signature)));
+                    outcome.record(SwapStep::Confirm, Ok(None));
+                }
+                Err((e, Some(signature))) => {
+                    // The update may still land, so the staged document has to stay
+                    outcome.signature = Some(signature.clone());
+                    outcome.record(SwapStep::Send, Ok(Some(signature)));
+                    outcome.record(SwapStep::Confirm, Err(&e));
+                    return Ok(outcome);
+                }
+                Err((e, None)) => {
+                    outcome.record(SwapStep::Send, Err(&e));
+                    rollback(target.as_ref(), &staged, &mut outcome);
+                    return Ok(outcome);
+                }
+            }
+
+            let promoted = target.promote(&staged);
+            outcome.record(SwapStep::Promote, promoted.as_ref().map(|_| None));
+            if outcome.previous_uri != staged.uri {
+                let removed = target.remove(&outcome.previous_uri);
+                let detail = Some(outcome.previous_uri.clone());
+                outcome.record(SwapStep::Cleanup, removed.as_ref().map(|_| detail));
+            }
+            Ok(outcome)
+        })
+    })
+}
+
+/// Reads the metadata of a mint, failing unless the payer may update it
+fn current_metadata(client: &RpcClient, mint: &Pubkey, payer: &Pubkey) -> SssResult<Metadata> {
+    let account = client
+        .get_multiple_accounts(&[metadata_pda(mint)])
+        .into_sss_error("Failed to get token metadata account from rpc")?
+        .pop()
+        .flatten()
+        .ok_or_else(|| SssError::TokenError(format!("Mint {} has no metadata account", mint)))?;
+    let metadata =
+        Metadata::from_bytes(&account.data).into_sss_error("Failed to parse token metadata")?;
+    if !metadata.is_mutable {
+        return Err(SssError::TokenError(format!(
+            "The metadata of mint {} is immutable",
+            mint
+        )));
+    }
+    if metadata.update_authority != *payer {
+        return Err(SssError::TokenError(format!(
+            "Payer {} is not the update authority {} of mint {}",
+            payer, metadata.update_authority, mint
+        )));
+    }
+    Ok(metadata)
+}
+
+/// Fetches a staged document and checks it matches the one uploaded
+fn verify_staged(uri: &str, document: &str) -> SssResult<()> {
+    let fetched = fetch_metadata(uri)?;
+    if fetched != parse_metadata_json(document)? {
+        return Err(SssError::TokenError(format!(
+            "The document served at {} differs from the one uploaded",
+            uri
+        )));
+    }
+    Ok(())
+}
+
+/// Removes a staged upload after a failure before the update confirmed
+fn rollback(target: &dyn MetadataUploadTarget, staged: &StagedUpload, outcome: &mut SwapOutcome) {
+    let removed = target.remove(&staged.uri);
+    outcome.record(SwapStep::Rollback, removed.as_ref().map(|_| None));
+}
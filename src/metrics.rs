@@ -0,0 +1,304 @@
+//! Operation counters and latency histograms
+//!
+//! Counters are kept per [`JournalOperation`], so every mutation operation the journal
+//! knows is reported, whether or not it has been called.
+
+use crate::dispatch::QueueMetrics;
+use crate::error::SssResult;
+use crate::journal::JournalOperation;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+/// Upper bounds (in milliseconds) of the latency histogram buckets
+///
+/// Durations above the last bound are counted in a final overflow bucket.
+pub const LATENCY_BUCKETS_MS: [u64; 9] = [50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 30_000];
+
+/// Number of histogram buckets including the overflow bucket
+const BUCKET_COUNT: usize = LATENCY_BUCKETS_MS.len() + 1;
+
+/// Counters for a single operation
+struct OperationCounters {
+    successes: AtomicU64,
+    failures: AtomicU64,
+    latency_sum_ms: AtomicU64,
+    latency_buckets: [AtomicU64; BUCKET_COUNT],
+}
+
+impl OperationCounters {
+    const fn new() -> Self {
+        Self {
+            successes: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+            latency_sum_ms: AtomicU64::new(0),
+            latency_buckets: [const { AtomicU64::new(0) }; BUCKET_COUNT],
+        }
+    }
+
+    fn reset(&self) {
+        self.successes.store(0, Ordering::Relaxed);
+        self.failures.store(0, Ordering::Relaxed);
+        self.latency_sum_ms.store(0, Ordering::Relaxed);
+        for bucket in &self.latency_buckets {
+            bucket.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Global metrics registry
+struct Registry {
+    operations: [OperationCounters; JournalOperation::ALL.len()],
+    rpc_retries: AtomicU64,
+    rate_limit_hits: AtomicU64,
+}
+
+static REGISTRY: Registry = Registry {
+    operations: [const { OperationCounters::new() }; JournalOperation::ALL.len()],
+    rpc_retries: AtomicU64::new(0),
+    rate_limit_hits: AtomicU64::new(0),
+};
+
+/// Snapshot of the counters for a single operation
+#[derive(Debug, Clone, Serialize)]
+pub struct OperationMetrics {
+    /// The operation name, as written in the journal, e.g. `mint_token`
+    pub operation: String,
+    /// Number of successful calls
+    pub successes: u64,
+    /// Number of failed calls
+    pub failures: u64,
+    /// Sum of the end-to-end latencies in milliseconds
+    pub latency_sum_ms: u64,
+    /// Calls per latency bucket, aligned with `latency_bucket_bounds_ms` plus an overflow bucket
+    pub latency_buckets: Vec<u64>,
+}
+
+/// Snapshot of all library metrics
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricsReport {
+    /// Upper bounds of the latency buckets in milliseconds
+    pub latency_bucket_bounds_ms: Vec<u64>,
+    /// Per-operation counters
+    pub operations: Vec<OperationMetrics>,
+    /// Number of RPC calls retried
+    pub rpc_retries: u64,
+    /// Number of RPC calls rejected by rate limiting
+    pub rate_limit_hits: u64,
+    /// Depth, drops and stalls of the queues feeding host callbacks
+    pub callback_queues: Vec<QueueMetrics>,
+}
+
+/// Returns a snapshot of the current metrics
+pub fn metrics_snapshot() -> MetricsReport {
+    let operations = JournalOperation::ALL
+        .iter()
+        .map(|op| {
+            let counters = &REGISTRY.operations[index(*op)];
+            OperationMetrics {
+                operation: op.name(),
+                successes: counters.successes.load(Ordering::Relaxed),
+                failures: counters.failures.load(Ordering::Relaxed),
+                latency_sum_ms: counters.latency_sum_ms.load(Ordering::Relaxed),
+                latency_buckets: counters
+                    .latency_buckets
+                    .iter()
+                    .map(|b| b.load(Ordering::Relaxed))
+                    .collect(),
+            }
+        })
+        .collect();
+
+    MetricsReport {
+        latency_bucket_bounds_ms: LATENCY_BUCKETS_MS.to_vec(),
+        operations,
+        rpc_retries: REGISTRY.rpc_retries.load(Ordering::Relaxed),
+        rate_limit_hits: REGISTRY.rate_limit_hits.load(Ordering::Relaxed),
+        callback_queues: vec![
+            crate::events::event_queue_metrics(),
+            crate::subscriptions::balance_queue_metrics(),
+        ],
+    }
+}
+
+/// Resets all counters to zero
+pub fn reset_metrics() {
+    for counters in &REGISTRY.operations {
+        counters.reset();
+    }
+    REGISTRY.rpc_retries.store(0, Ordering::Relaxed);
+    REGISTRY.rate_limit_hits.store(0, Ordering::Relaxed);
+}
+
+/// Runs an operation and records its outcome and latency
+///
+/// Called once at the entry of each public mutation operation, around everything it does,
+/// so batched operations count once per call rather than once per transaction.
+pub(crate) fn track<T>(
+    operation: JournalOperation,
+    f: impl FnOnce() -> SssResult<T>,
+) -> SssResult<T> {
+    let started = Instant::now();
+    let result = f();
+    record_operation(operation, started, &result);
+    result
+}
+
+/// Returns the position of an operation's counters in the registry
+fn index(operation: JournalOperation) -> usize {
+    // `ALL` lists the operations in declaration order
+    operation as usize
+}
+
+/// Records the outcome and latency of an operation started at `started`
+fn record_operation<T>(operation: JournalOperation, started: Instant, result: &SssResult<T>) {
+    let counters = &REGISTRY.operations[index(operation)];
+    let elapsed = started.elapsed();
+    let elapsed_ms = elapsed.as_millis() as u64;
+
+    if result.is_ok() {
+        counters.successes.fetch_add(1, Ordering::Relaxed);
+    } else {
+        counters.failures.fetch_add(1, Ordering::Relaxed);
+    }
+    counters
+        .latency_sum_ms
+        .fetch_add(elapsed_ms, Ordering::Relaxed);
+
+    let bucket = LATENCY_BUCKETS_MS
+        .iter()
+        .position(|bound| elapsed_ms <= *bound)
+        .unwrap_or(LATENCY_BUCKETS_MS.len());
+    counters.latency_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+
+    #[cfg(feature = "metrics")]
+    {
+        let outcome = if result.is_ok() { "success" } else { "failure" };
+        ::metrics::counter!(
+            "sss_operations_total",
+            "operation" => operation.name(),
+            "result" => outcome
+        )
+        .increment(1);
+        ::metrics::histogram!(
+            "sss_operation_duration_seconds",
+            "operation" => operation.name()
+        )
+        .record(elapsed.as_secs_f64());
+    }
+}
+
+/// Records an RPC call being retried
+pub(crate) fn record_rpc_retry() {
+    REGISTRY.rpc_retries.fetch_add(1, Ordering::Relaxed);
+
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!("sss_rpc_retries_total").increment(1);
+}
+
+/// Records an RPC call rejected by rate limiting
+pub(crate) fn record_rate_limit_hit() {
+    REGISTRY.rate_limit_hits.fetch_add(1, Ordering::Relaxed);
+
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!("sss_rate_limit_hits_total").increment(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::SssError;
+
+    fn counters(operation: JournalOperation) -> OperationMetrics {
+        let name = operation.name();
+        metrics_snapshot()
+            .operations
+            .into_iter()
+            .find(|m| m.operation == name)
+            .expect("operation missing from the snapshot")
+    }
+
+    #[test]
+    fn snapshot_lists_every_journal_operation() {
+        let report = metrics_snapshot();
+        let names: Vec<String> = report
+            .operations
+            .iter()
+            .map(|m| m.operation.clone())
+            .collect();
+        let expected: Vec<String> = JournalOperation::ALL.iter().map(|op| op.name()).collect();
+        assert_eq!(names, expected);
+        for metrics in &report.operations {
+            assert_eq!(metrics.latency_buckets.len(), BUCKET_COUNT);
+        }
+    }
+
+    #[test]
+    fn registry_index_matches_position_in_all() {
+        for (position, op) in JournalOperation::ALL.iter().enumerate() {
+            assert_eq!(index(*op), position);
+        }
+    }
+
+    #[test]
+    fn counts_mocked_operations() {
+        // Only this test tracks `VerifyCreator`, so the deltas are not raced
+        let operation = JournalOperation::VerifyCreator;
+        let before = counters(operation);
+
+        const SUCCESSES: u64 = 7;
+        const FAILURES: u64 = 3;
+        for _ in 0..SUCCESSES {
+            assert!(track(operation, || Ok(())).is_ok());
+        }
+        for _ in 0..FAILURES {
+            let result: SssResult<()> =
+                track(operation, || Err(SssError::RpcError("mocked".to_string())));
+            assert!(result.is_err());
+        }
+
+        let after = counters(operation);
+        assert_eq!(after.successes - before.successes, SUCCESSES);
+        assert_eq!(after.failures - before.failures, FAILURES);
+        let calls = |m: &OperationMetrics| m.latency_buckets.iter().sum::<u64>();
+        assert_eq!(calls(&after) - calls(&before), SUCCESSES + FAILURES);
+        // Instant closures land in the first bucket
+        assert_eq!(
+            after.latency_buckets[0] - before.latency_buckets[0],
+            SUCCESSES + FAILURES
+        );
+    }
+
+    #[test]
+    fn slow_operation_lands_in_a_later_bucket() {
+        let operation = JournalOperation::SwapMetadata;
+        let before = counters(operation);
+        track(operation, || {
+            std::thread::sleep(std::time::Duration::from_millis(LATENCY_BUCKETS_MS[0] + 20));
+            Ok(())
+        })
+        .unwrap();
+        let after = counters(operation);
+        assert_eq!(after.latency_buckets[1] - before.latency_buckets[1], 1);
+        assert!(after.latency_sum_ms - before.latency_sum_ms > LATENCY_BUCKETS_MS[0]);
+    }
+
+    #[test]
+    fn counters_reset() {
+        let counters = OperationCounters::new();
+        counters.successes.fetch_add(2, Ordering::Relaxed);
+        counters.failures.fetch_add(1, Ordering::Relaxed);
+        counters.latency_sum_ms.fetch_add(40, Ordering::Relaxed);
+        counters.latency_buckets[3].fetch_add(1, Ordering::Relaxed);
+        counters.reset();
+        assert_eq!(counters.successes.load(Ordering::Relaxed), 0);
+        assert_eq!(counters.failures.load(Ordering::Relaxed), 0);
+        assert_eq!(counters.latency_sum_ms.load(Ordering::Relaxed), 0);
+        assert!(
+            counters
+                .latency_buckets
+                .iter()
+                .all(|b| b.load(Ordering::Relaxed) == 0)
+        );
+    }
+}
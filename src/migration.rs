@@ -0,0 +1,370 @@
+//! Migration of plain spl-token mints to tokens with Metaplex metadata
+
+use crate::RPC_CLIENT;
+use crate::batch::BatchReport;
+use crate::consistency::{ConsistencyReport, audit_token_consistency_with_client};
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::mint_cache::metadata_pda;
+use crate::payer::signing_payer;
+use crate::rotation::{AuthorityKinds, MintRotation, rotate_authorities_with_client};
+use crate::serde_utils::{option_pubkey_string, pubkey_string};
+use crate::token::attach_metadata_to_existing_mint_with_client;
+use serde::{Deserialize, Serialize};
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_sdk::{pubkey::Pubkey, signer::Signer};
+use spl_token_2022::extension::StateWithExtensions;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// A mint to migrate and the metadata it receives
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LegacyMigration {
+    /// The existing mint, whose mint authority must be the payer
+    #[serde(with = "pubkey_string")]
+    pub mint: Pubkey,
+    /// The name of the token
+    pub name: String,
+    /// The ticker symbol of the token
+    pub symbol: String,
+    /// The URI pointing to the token's metadata
+    pub uri: String,
+    /// The key to move the freeze authority to, if the payer holds it
+    #[serde(default, with = "option_pubkey_string")]
+    pub freeze_authority: Option<Pubkey>,
+    /// The key to move the mint and metadata update authorities to once the metadata
+    /// exists, `None` to leave them with the payer
+    #[serde(default, with = "option_pubkey_string")]
+    pub new_authority: Option<Pubkey>,
+}
+
+/// What became of a mint in [`migrate_legacy_mints`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum MigrationStatus {
+    /// Every step completed and the consistency audit found no error
+    Migrated,
+    /// Every step completed, but the consistency audit found an error, e.g. off-chain
+    /// metadata disagreeing with the mint's decimals
+    Inconsistent,
+    /// A previous run with the same checkpoint completed the migration
+    AlreadyMigrated,
+    /// The payer is not the mint authority, so the mint was left untouched
+    NotMintAuthority {
+        /// The current mint authority, or `None` if minting is disabled
+        current: Option<String>,
+    },
+    /// The mint already has a metadata account that no previous run recorded in the
+    /// checkpoint
+    HasMetadata,
+    /// A step failed; running again with the same checkpoint resumes after the last
+    /// completed step
+    Failed {
+        /// Why the step failed
+        error: String,
+    },
+}
+
+/// Outcome of the migration of a single mint
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MintMigration {
+    /// The mint
+    #[serde(with = "pubkey_string")]
+    pub mint: Pubkey,
+    /// What became of the mint
+    #[serde(flatten)]
+    pub status: MigrationStatus,
+    /// The transaction that created the metadata, in this run or a previous one
+    pub metadata_signature: Option<String>,
+    /// The outcome of the freeze and mint authority moves, empty if none was requested or
+    /// the migration stopped before them
+    pub rotations: Vec<MintRotation>,
+    /// The consistency audit of the migrated mint
+    pub audit: Option<ConsistencyReport>,
+}
+
+impl MintMigration {
+    fn new(mint: Pubkey, status: MigrationStatus) -> Self {
+        Self {
+            mint,
+            status,
+            metadata_signature: None,
+            rotations: Vec::new(),
+            audit: None,
+        }
+    }
+}
+
+/// Per-mint outcome of a migration run
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MigrationReport {
+    /// One entry per planned mint, in plan order
+    pub mints: Vec<MintMigration>,
+}
+
+impl MigrationReport {
+    /// Serializes the report to JSON
+    pub fn to_json(&self) -> SssResult<String> {
+        serde_json::to_string_pretty(self).into_sss_error("Failed to serialize migration report")
+    }
+
+    /// Summarizes the report per mint
+    ///
+    /// Migrated and inconsistent mints succeeded with their metadata signature, failed
+    /// mints failed, and every other mint is skipped.
+    pub fn batch_report(&self) -> BatchReport {
+        let mut batch = BatchReport::default();
+        for migration in &self.mints {
+            match (&migration.status, &migration.metadata_signature) {
+                (MigrationStatus::Failed { error }, _) => batch
+                    .failed
+                    .push((migration.mint, SssError::TokenError(error.clone()))),
+                (MigrationStatus::Migrated | MigrationStatus::Inconsistent, Some(signature)) => {
+                    batch.succeeded.push((migration.mint, signature.clone()))
+                }
+                _ => batch.skipped.push(migration.mint),
+            }
+        }
+        batch
+    }
+}
+
+/// Checkpoint record of a single mint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct MigrationRecord {
+    /// The transaction that created the metadata
+    metadata_signature: String,
+    /// Whether the authority moves and the audit completed
+    completed: bool,
+}
+
+/// Progress of a migration, persisted after every step
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct MigrationCheckpoint {
+    /// Records keyed by the base58 mint address
+    mints: BTreeMap<String, MigrationRecord>,
+}
+
+impl MigrationCheckpoint {
+    /// Loads the checkpoint at `path`, or starts a new one if the file does not exist
+    fn load_or_new(path: &Path) -> SssResult<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let json =
+            fs::read_to_string(path).into_sss_error("Failed to read migration checkpoint")?;
+        serde_json::from_str(&json).into_sss_error("Failed to parse migration checkpoint")
+    }
+
+    /// Writes the checkpoint through a temporary file so a crash never leaves it truncated
+    fn save(&self, path: &Path) -> SssResult<()> {
+        let json = serde_json::to_string_pretty(self)
+            .into_sss_error("Failed to serialize migration checkpoint")?;
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, json).into_sss_error("Failed to write migration checkpoint")?;
+        fs::rename(&tmp, path).into_sss_error("Failed to write migration checkpoint")
+    }
+}
+
+/// Gives plain spl-token mints Metaplex metadata, resuming from a checkpoint file
+///
+/// Each mint in turn gets its metadata created over the existing mint (see
+/// [`attach_metadata_to_existing_mint`]), then has its freeze authority and its mint and
+/// update authorities moved if the plan asks for it (see [`rotate_authorities`]), and is
+/// finally checked with [`audit_token_consistency`]. The checkpoint is written after every
+/// step, so a run interrupted part-way can be repeated with the same plan and checkpoint:
+/// completed mints are reported as already migrated and the others resume after their
+/// last completed step.
+///
+/// Mints whose mint authority is not the payer, which the metadata program requires, are
+/// reported and left untouched, as are mints that already have metadata.
+///
+/// # Arguments
+///
+/// * `plan` - The mints to migrate and their metadata
+/// * `checkpoint_path` - The JSON file recording the progress per mint
+///
+/// # Returns
+///
+/// The outcome of every planned mint
+///
+/// # Errors
+///
+/// Returns an error if the payer cannot be loaded or the checkpoint cannot be read or
+/// written; failures of a single mint are listed in the report
+pub fn migrate_legacy_mints(
+    plan: &[LegacyMigration],
+    checkpoint_path: &Path,
+) -> SssResult<MigrationReport> {
+    migrate_legacy_mints_with_client(&RPC_CLIENT, plan, checkpoint_path)
+}
+
+/// Gives plain spl-token mints Metaplex metadata like [`migrate_legacy_mints`], reading and
+/// sending through the given RPC client
+///
+/// # Arguments
+///
+/// * `client` - The RPC client used for every read and for sending the transactions
+/// * `plan` - The mints to migrate and their metadata
+/// * `checkpoint_path` - The JSON file recording the progress per mint
+///
+/// # Returns
+///
+/// The outcome of every planned mint
+pub fn migrate_legacy_mints_with_client(
+    client: &RpcClient,
+    plan: &[LegacyMigration],
+    checkpoint_path: &Path,
+) -> SssResult<MigrationReport> {
+    let payer = signing_payer()?.pubkey();
+    let mut checkpoint = MigrationCheckpoint::load_or_new(checkpoint_path)?;
+    let mut report = MigrationReport {
+        mints: Vec::with_capacity(plan.len()),
+    };
+
+    for entry in plan {
+        let key = entry.mint.to_string();
+        let record = checkpoint.mints.get(&key).cloned();
+        let metadata_signature = match record {
+            Some(record) if record.completed => {
+                let mut migration =
+                    MintMigration::new(entry.mint, MigrationStatus::AlreadyMigrated);
+                migration.metadata_signature = Some(record.metadata_signature);
+                report.mints.push(migration);
+                continue;
+            }
+            Some(record) => record.metadata_signature,
+            None => match legacy_status(client, &entry.mint, &payer) {
+                Ok(Some(status)) => {
+                    report.mints.push(MintMigration::new(entry.mint, status));
+                    continue;
+                }
+                Ok(None) => match attach_metadata_to_existing_mint_with_client(
+                    client,
+                    entry.mint,
+                    &entry.uri,
+                    &entry.name,
+                    &entry.symbol,
+                ) {
+                    Ok(signature) => {
+                        checkpoint.mints.insert(
+                            key.clone(),
+                            MigrationRecord {
+                                metadata_signature: signature.clone(),
+                                completed: false,
+                            },
+                        );
+                        checkpoint.save(checkpoint_path)?;
+                        signature
+                    }
+                    Err(e) => {
+                        report.mints.push(failed_migration(entry.mint, e));
+                        continue;
+                    }
+                },
+                Err(e) => {
+                    report.mints.push(failed_migration(entry.mint, e));
+                    continue;
+                }
+            },
+        };
+
+        let mut migration = MintMigration::new(entry.mint, MigrationStatus::Migrated);
+        migration.metadata_signature = Some(metadata_signature);
+        match finish_migration(client, entry, &mut migration) {
+            Ok(()) => {
+                if let Some(record) = checkpoint.mints.get_mut(&key) {
+                    record.completed = true;
+                }
+                checkpoint.save(checkpoint_path)?;
+            }
+            Err(error) => migration.status = MigrationStatus::Failed { error },
+        }
+        report.mints.push(migration);
+    }
+    Ok(report)
+}
+
+/// Returns why a mint cannot be migrated, `None` if it can
+fn legacy_status(
+    client: &RpcClient,
+    mint: &Pubkey,
+    payer: &Pubkey,
+) -> SssResult<Option<MigrationStatus>> {
+    let accounts = client
+        .get_multiple_accounts(&[*mint, metadata_pda(mint)])
+        .into_sss_error("Failed to get mint and metadata accounts from rpc")?;
+    let mint_account = accounts[0]
+        .as_ref()
+        .ok_or_else(|| SssError::TokenError(format!("Mint account {} does not exist", mint)))?;
+    if accounts[1].is_some() {
+        return Ok(Some(MigrationStatus::HasMetadata));
+    }
+    let state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_account.data)
+        .into_sss_error("Failed to parse token mint account")?;
+    let current = Option::<Pubkey>::from(state.base.mint_authority);
+    Ok(
+        (current != Some(*payer)).then(|| MigrationStatus::NotMintAuthority {
+            current: current.map(|authority| authority.to_string()),
+        }),
+    )
+}
+
+/// Moves the requested authorities of a mint with metadata and audits it
+///
+/// The freeze authority moves first, since the mint authority may move away from the
+/// payer; moves completed by an earlier run are reported as already rotated.
+fn finish_migration(
+    client: &RpcClient,
+    entry: &LegacyMigration,
+    migration: &mut MintMigration,
+) -> Result<(), String> {
+    let moves = [
+        (
+            entry.freeze_authority,
+            AuthorityKinds {
+                freeze: true,
+                ..AuthorityKinds::default()
+            },
+        ),
+        (
+            entry.new_authority,
+            AuthorityKinds {
+                mint: true,
+                metadata_update: true,
+                ..AuthorityKinds::default()
+            },
+        ),
+    ];
+    for (new_authority, kinds) in moves {
+        let Some(new_authority) = new_authority else {
+            continue;
+        };
+        let mut rotation =
+            rotate_authorities_with_client(client, &[entry.mint], new_authority, kinds)
+                .map_err(|e| e.to_string())?;
+        let failed = rotation.batch_report().failed.into_iter().next();
+        migration.rotations.append(&mut rotation.mints);
+        if let Some((_, error)) = failed {
+            return Err(error.to_string());
+        }
+    }
+
+    let audit =
+        audit_token_consistency_with_client(client, entry.mint).map_err(|e| e.to_string())?;
+    if !audit.is_consistent() {
+        migration.status = MigrationStatus::Inconsistent;
+    }
+    migration.audit = Some(audit);
+    Ok(())
+}
+
+fn failed_migration(mint: Pubkey, error: SssError) -> MintMigration {
+    MintMigration::new(
+        mint,
+        MigrationStatus::Failed {
+            error: error.to_string(),
+        },
+    )
+}
@@ -0,0 +1,170 @@
+//! Cache of immutable mint properties
+
+use crate::RPC_CLIENT;
+use crate::cache::{BoundedCache, CacheLimits};
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::token::TokenProgram;
+use lazy_static::lazy_static;
+use mpl_token_metadata::accounts::Metadata;
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_sdk::{account::Account, program_pack::Pack, pubkey::Pubkey};
+use spl_token_2022::extension::{BaseStateWithExtensions, ExtensionType, StateWithExtensions};
+
+/// Properties of a mint that cannot change after creation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct MintInfo {
+    /// The token program owning the mint
+    pub token_program: TokenProgram,
+    /// The number of decimal places
+    pub decimals: u8,
+    /// Size of a new associated token account for the mint
+    pub token_account_len: usize,
+}
+
+impl MintInfo {
+    /// Parses the mint properties from its account
+    pub fn from_account(mint: &Pubkey, account: &Account) -> SssResult<Self> {
+        let token_program = if account.owner == spl_token::id() {
+            TokenProgram::Legacy
+        } else if account.owner == spl_token_2022::id() {
+            TokenProgram::Token2022
+        } else {
+            return Err(SssError::TokenError(format!(
+                "Account {} is not a token mint",
+                mint
+            )));
+        };
+
+        let state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&account.data)
+            .into_sss_error("Failed to parse token mint account")?;
+
+        // Associated token accounts always carry ImmutableOwner, plus whatever the mint requires
+        let token_account_len = match token_program {
+            TokenProgram::Legacy => spl_token::state::Account::LEN,
+            TokenProgram::Token2022 => {
+                let mint_extensions = state
+                    .get_extension_types()
+                    .into_sss_error("Failed to parse token mint extensions")?;
+                let mut extensions =
+                    ExtensionType::get_required_init_account_extensions(&mint_extensions);
+                extensions.push(ExtensionType::ImmutableOwner);
+                ExtensionType::try_calculate_account_len::<spl_token_2022::state::Account>(
+                    &extensions,
+                )
+                .into_sss_error("Failed to calculate token account size")?
+            }
+        };
+
+        Ok(Self {
+            token_program,
+            decimals: state.base.decimals,
+            token_account_len,
+        })
+    }
+}
+
+lazy_static! {
+    /// Properties of mints, which never change, so entries only leave under the cap
+    pub(crate) static ref MINT_CACHE: BoundedCache<Pubkey, MintInfo> =
+        BoundedCache::new("mint_info", CacheLimits::default(), |_, _| 0);
+    pub(crate) static ref METADATA_PDAS: BoundedCache<Pubkey, Pubkey> =
+        BoundedCache::new("metadata_pda", CacheLimits::default(), |_, _| 0);
+    /// Mints known to have a metadata account; a missing one may still be attached later
+    pub(crate) static ref WITH_METADATA: BoundedCache<Pubkey, ()> =
+        BoundedCache::new("with_metadata", CacheLimits::default(), |_, _| 0);
+}
+
+/// Returns the cached properties of a mint, if known
+pub(crate) fn cached_mint(mint: &Pubkey) -> Option<MintInfo> {
+    MINT_CACHE.get(mint)
+}
+
+/// Stores the properties of a mint
+pub(crate) fn cache_mint(mint: Pubkey, info: MintInfo) {
+    MINT_CACHE.insert(mint, info);
+}
+
+/// Returns the properties of a mint, fetching and caching them if unknown
+pub(crate) fn mint_info(mint: &Pubkey) -> SssResult<MintInfo> {
+    mint_info_with_client(&RPC_CLIENT, mint)
+}
+
+/// Returns the properties of a mint like [`mint_info`], fetching them through `client`
+pub(crate) fn mint_info_with_client(client: &RpcClient, mint: &Pubkey) -> SssResult<MintInfo> {
+    if let Some(info) = cached_mint(mint) {
+        return Ok(info);
+    }
+    let account = client
+        .get_account(mint)
+        .into_sss_error("Failed to get mint account from rpc")?;
+    let info = MintInfo::from_account(mint, &account)?;
+    cache_mint(*mint, info);
+    Ok(info)
+}
+
+/// Returns the token metadata account of a mint
+///
+/// The address is derived once per mint and cached, since batch operations build many
+/// instructions for the same mint.
+///
+/// # Arguments
+///
+/// * `mint` - The public key of the token's mint account
+pub fn metadata_pda(mint: &Pubkey) -> Pubkey {
+    if let Some(pda) = METADATA_PDAS.get(mint) {
+        return pda;
+    }
+    let (pda, _) = Metadata::find_pda(mint);
+    METADATA_PDAS.insert(*mint, pda);
+    pda
+}
+
+/// Returns whether a mint has a token metadata account
+///
+/// Only a present account is cached, so a mint that gains one later is seen at once.
+pub(crate) fn has_metadata(client: &RpcClient, mint: &Pubkey) -> SssResult<bool> {
+    if WITH_METADATA.contains(mint) {
+        return Ok(true);
+    }
+    let exists = client
+        .get_account_with_commitment(&metadata_pda(mint), client.commitment())
+        .into_sss_error("Failed to get token metadata account from rpc")?
+        .value
+        .is_some();
+    if exists {
+        cache_has_metadata(*mint);
+    }
+    Ok(exists)
+}
+
+/// Returns the off-chain metadata URI stored in a mint's token metadata account
+///
+/// # Returns
+///
+/// `None` if the mint has no metadata account
+#[cfg(feature = "http-metadata")]
+pub(crate) fn metadata_uri(client: &RpcClient, mint: &Pubkey) -> SssResult<Option<String>> {
+    let Some(account) = client
+        .get_multiple_accounts(&[metadata_pda(mint)])
+        .into_sss_error("Failed to get token metadata account from rpc")?
+        .pop()
+        .flatten()
+    else {
+        return Ok(None);
+    };
+    let metadata =
+        Metadata::from_bytes(&account.data).into_sss_error("Failed to parse token metadata")?;
+    Ok(Some(metadata.uri.trim_end_matches('\0').to_string()))
+}
+
+/// Records that a mint has a token metadata account
+pub(crate) fn cache_has_metadata(mint: Pubkey) {
+    WITH_METADATA.insert(mint, ());
+}
+
+/// Removes every entry from the mint cache
+pub fn clear_mint_cache() {
+    MINT_CACHE.clear();
+    METADATA_PDAS.clear();
+    WITH_METADATA.clear();
+}
@@ -0,0 +1,455 @@
+//! Normalization of token names and symbols shown by wallets
+
+use crate::error::{SssError, SssResult};
+use mpl_token_metadata::{MAX_NAME_LENGTH, MAX_SYMBOL_LENGTH};
+use serde::Serialize;
+use std::fmt;
+use unicode_normalization::UnicodeNormalization;
+
+/// Characters that reorder surrounding text and can disguise a name
+const BIDI_OVERRIDES: &[char] = &[
+    '\u{202A}', '\u{202B}', '\u{202C}', '\u{202D}', '\u{202E}', '\u{2066}', '\u{2067}', '\u{2068}',
+    '\u{2069}',
+];
+
+/// Invisible characters that can make two names look identical
+const ZERO_WIDTH: &[char] = &[
+    '\u{200B}', '\u{200C}', '\u{200D}', '\u{200E}', '\u{200F}', '\u{061C}', '\u{2060}', '\u{180E}',
+    '\u{FEFF}',
+];
+
+/// A change made while normalizing a name
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum NameChange {
+    /// The text was rewritten to Unicode normalization form C
+    Normalized,
+    /// A control character was removed
+    RemovedControl {
+        /// The removed code point
+        codepoint: u32,
+    },
+    /// An invisible zero-width or direction mark was removed
+    RemovedZeroWidth {
+        /// The removed code point
+        codepoint: u32,
+    },
+    /// Runs of whitespace were collapsed to single spaces and the ends trimmed
+    CollapsedWhitespace,
+}
+
+impl fmt::Display for NameChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Normalized => write!(f, "normalized to NFC"),
+            Self::RemovedControl { codepoint } => {
+                write!(f, "removed control character U+{:04X}", codepoint)
+            }
+            Self::RemovedZeroWidth { codepoint } => {
+                write!(f, "removed invisible character U+{:04X}", codepoint)
+            }
+            Self::CollapsedWhitespace => write!(f, "collapsed whitespace"),
+        }
+    }
+}
+
+/// A reason a name cannot be used
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum NameRejection {
+    /// The name contains a bidirectional override or isolate
+    BidiOverride {
+        /// The offending code point
+        codepoint: u32,
+    },
+    /// Nothing is left after normalization
+    Empty,
+    /// The normalized name exceeds the on-chain limit
+    TooLong {
+        /// The normalized length in bytes
+        len: usize,
+        /// The maximum length in bytes
+        max: usize,
+    },
+}
+
+impl fmt::Display for NameRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BidiOverride { codepoint } => {
+                write!(f, "contains bidirectional override U+{:04X}", codepoint)
+            }
+            Self::Empty => write!(f, "is empty after normalization"),
+            Self::TooLong { len, max } => {
+                write!(
+                    f,
+                    "is {} bytes after normalization, the maximum is {}",
+                    len, max
+                )
+            }
+        }
+    }
+}
+
+/// What normalizing a name changed, and why it was rejected if it was
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NameValidationReport {
+    /// The name as submitted
+    pub original: String,
+    /// The normalized name
+    pub normalized: String,
+    /// Changes made during normalization, in the order they were applied
+    pub changes: Vec<NameChange>,
+    /// Reasons the name cannot be used; empty if it is valid
+    pub rejections: Vec<NameRejection>,
+}
+
+impl NameValidationReport {
+    /// Returns whether the normalized name can be used
+    pub fn is_valid(&self) -> bool {
+        self.rejections.is_empty()
+    }
+}
+
+/// Normalizes a token name and reports every change and problem
+///
+/// # Arguments
+///
+/// * `name` - The submitted token name
+pub fn validate_token_name(name: &str) -> NameValidationReport {
+    validate(name, MAX_NAME_LENGTH)
+}
+
+/// Normalizes a token symbol and reports every change and problem
+///
+/// # Arguments
+///
+/// * `symbol` - The submitted token symbol
+pub fn validate_token_symbol(symbol: &str) -> NameValidationReport {
+    validate(symbol, MAX_SYMBOL_LENGTH)
+}
+
+/// Normalizes a token name for display in wallets
+///
+/// Applies NFC normalization, strips control and zero-width characters and collapses
+/// whitespace. The result always fits the on-chain name limit.
+///
+/// # Arguments
+///
+/// * `name` - The submitted token name
+///
+/// # Returns
+///
+/// The normalized name
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the name contains a bidirectional override, is empty after
+/// normalization or is too long
+pub fn normalize_token_name(name: &str) -> SssResult<String> {
+    into_result("name", validate_token_name(name))
+}
+
+/// Normalizes a token symbol the same way as [`normalize_token_name`]
+///
+/// # Arguments
+///
+/// * `symbol` - The submitted token symbol
+///
+/// # Returns
+///
+/// The normalized symbol
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the symbol contains a bidirectional override or is too long
+pub fn normalize_token_symbol(symbol: &str) -> SssResult<String> {
+    let mut report = validate_token_symbol(symbol);
+    // Symbols are optional on-chain, so an empty one is fine
+    report.rejections.retain(|r| *r != NameRejection::Empty);
+    into_result("symbol", report)
+}
+
+/// Applies the normalization steps and checks the result against `max_len` bytes
+fn validate(value: &str, max_len: usize) -> NameValidationReport {
+    let mut changes = Vec::new();
+    let mut rejections = Vec::new();
+
+    let composed: String = value.nfc().collect();
+    if composed != value {
+        changes.push(NameChange::Normalized);
+    }
+
+    let mut normalized = String::with_capacity(composed.len());
+    let mut pending_space = false;
+    let mut collapsed = false;
+    for c in composed.chars() {
+        if BIDI_OVERRIDES.contains(&c) {
+            rejections.push(NameRejection::BidiOverride {
+                codepoint: c as u32,
+            });
+        } else if ZERO_WIDTH.contains(&c) {
+            changes.push(NameChange::RemovedZeroWidth {
+                codepoint: c as u32,
+            });
+        } else if c.is_whitespace() {
+            // A single plain space between words is kept as is
+            collapsed |= c != ' ' || pending_space || normalized.is_empty();
+            pending_space = true;
+        } else if c.is_control() {
+            changes.push(NameChange::RemovedControl {
+                codepoint: c as u32,
+            });
+        } else {
+            if pending_space && !normalized.is_empty() {
+                normalized.push(' ');
+            }
+            pending_space = false;
+            normalized.push(c);
+        }
+    }
+    if collapsed || pending_space {
+        changes.push(NameChange::CollapsedWhitespace);
+    }
+    // Removing a character can leave a base and its combining mark next to each other
+    let recomposed: String = normalized.nfc().collect();
+    if recomposed != normalized {
+        if !changes.contains(&NameChange::Normalized) {
+            changes.push(NameChange::Normalized);
+        }
+        normalized = recomposed;
+    }
+
+    if normalized.is_empty() {
+        rejections.push(NameRejection::Empty);
+    }
+    if normalized.len() > max_len {
+        rejections.push(NameRejection::TooLong {
+            len: normalized.len(),
+            max: max_len,
+        });
+    }
+
+    NameValidationReport {
+        original: value.to_string(),
+        normalized,
+        changes,
+        rejections,
+    }
+}
+
+/// Turns a report into the normalized value or an error listing every rejection
+fn into_result(field: &str, report: NameValidationReport) -> SssResult<String> {
+    if report.is_valid() {
+        return Ok(report.normalized);
+    }
+    let reasons: Vec<String> = report.rejections.iter().map(|r| r.to_string()).collect();
+    // Debug formatting escapes the characters that made the value unusable
+    Err(SssError::TokenError(format!(
+        "Token {} {:?} {}",
+        field,
+        report.original,
+        reasons.join(", ")
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::{Rng, SeedableRng};
+
+    /// Pieces partners actually submit, and the ones used to disguise names
+    const PIECES: &[&str] = &[
+        "a",
+        "Z",
+        "7",
+        "Coin",
+        "Café",
+        "e\u{0301}",
+        "\u{0301}",
+        "\u{0323}\u{0307}",
+        "Å",
+        "ﬁ",
+        "Ω",
+        "東京",
+        "العربية",
+        "עברית",
+        "🚀",
+        "👨\u{200D}👩\u{200D}👧",
+        "🇺🇸",
+        "❤\u{FE0F}",
+        "а",
+        " ",
+        "  ",
+        "\t",
+        "\n",
+        "\r\n",
+        "\u{00A0}",
+        "\u{3000}",
+        "\u{2028}",
+        "\u{0000}",
+        "\u{0007}",
+        "\u{001B}",
+        "\u{007F}",
+        "\u{0085}",
+        "\u{009F}",
+        "\u{200B}",
+        "\u{200C}",
+        "\u{200D}",
+        "\u{200E}",
+        "\u{200F}",
+        "\u{061C}",
+        "\u{2060}",
+        "\u{180E}",
+        "\u{FEFF}",
+    ];
+
+    /// Adversarial names, from empty to several times the on-chain limit
+    fn adversarial(rng: &mut StdRng) -> String {
+        let pieces = rng.gen_range(0..=3 * MAX_NAME_LENGTH);
+        (0..pieces).map(|_| *PIECES.choose(rng).unwrap()).collect()
+    }
+
+    fn cases() -> impl Iterator<Item = String> {
+        let mut rng = StdRng::seed_from_u64(123);
+        (0..5_000).map(move |_| adversarial(&mut rng))
+    }
+
+    fn assert_clean(original: &str, normalized: &str, max_len: usize) {
+        assert!(
+            normalized.len() <= max_len,
+            "{:?} became {:?}",
+            original,
+            normalized
+        );
+        assert_eq!(
+            normalized.nfc().collect::<String>(),
+            normalized,
+            "{:?}",
+            original
+        );
+        assert!(
+            !normalized
+                .chars()
+                .any(|c| c.is_control() || ZERO_WIDTH.contains(&c) || BIDI_OVERRIDES.contains(&c)),
+            "{:?} became {:?}",
+            original,
+            normalized
+        );
+        assert!(
+            normalized.chars().all(|c| c == ' ' || !c.is_whitespace()),
+            "{:?} became {:?}",
+            original,
+            normalized
+        );
+        assert!(
+            !normalized.contains("  "),
+            "{:?} became {:?}",
+            original,
+            normalized
+        );
+        assert_eq!(normalized.trim(), normalized, "{:?}", original);
+    }
+
+    #[test]
+    fn normalized_names_fit_on_chain_and_are_clean() {
+        let mut accepted = 0;
+        for name in cases() {
+            match normalize_token_name(&name) {
+                Ok(normalized) => {
+                    assert_clean(&name, &normalized, MAX_NAME_LENGTH);
+                    assert!(!normalized.is_empty());
+                    accepted += 1;
+                }
+                Err(SssError::TokenError(message)) => {
+                    let report = validate_token_name(&name);
+                    assert!(!report.is_valid());
+                    assert!(message.starts_with("Token name "), "{}", message);
+                    // Only the length is checked against the limit, so a rejected name is
+                    // rejected for being long or empty
+                    assert!(report.rejections.iter().all(|rejection| match rejection {
+                        NameRejection::TooLong { len, max } => {
+                            *len == report.normalized.len() && *max == MAX_NAME_LENGTH
+                        }
+                        NameRejection::Empty => report.normalized.is_empty(),
+                        NameRejection::BidiOverride { .. } => false,
+                    }));
+                }
+                Err(other) => panic!("{:?} failed with {:?}", name, other),
+            }
+        }
+        // The generator reaches both sides of the limit
+        assert!(accepted > 500 && accepted < 4_500, "accepted {}", accepted);
+    }
+
+    #[test]
+    fn normalized_symbols_fit_on_chain_and_are_clean() {
+        for symbol in cases() {
+            let symbol: String = symbol.chars().take(2 * MAX_SYMBOL_LENGTH).collect();
+            if let Ok(normalized) = normalize_token_symbol(&symbol) {
+                assert_clean(&symbol, &normalized, MAX_SYMBOL_LENGTH);
+            }
+        }
+    }
+
+    #[test]
+    fn normalizing_twice_changes_nothing() {
+        for name in cases() {
+            let report = validate_token_name(&name);
+            let again = validate_token_name(&report.normalized);
+            assert_eq!(again.normalized, report.normalized, "{:?}", name);
+            assert!(again.changes.is_empty(), "{:?}: {:?}", name, again.changes);
+            // The report is consistent with the result
+            assert_eq!(
+                report.changes.is_empty(),
+                report.normalized == name,
+                "{:?}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn a_bidi_override_anywhere_rejects_the_name() {
+        let mut rng = StdRng::seed_from_u64(202);
+        for name in cases().take(1_000) {
+            let override_char = *BIDI_OVERRIDES.choose(&mut rng).unwrap();
+            let mut chars: Vec<char> = name.chars().collect();
+            chars.insert(rng.gen_range(0..=chars.len()), override_char);
+            let name: String = chars.into_iter().collect();
+
+            let report = validate_token_name(&name);
+            assert!(report.rejections.contains(&NameRejection::BidiOverride {
+                codepoint: override_char as u32
+            }));
+            assert!(!report.normalized.contains(override_char));
+            assert!(normalize_token_name(&name).is_err());
+            assert!(normalize_token_symbol(&name).is_err());
+        }
+    }
+
+    #[test]
+    fn a_spoofed_name_normalizes_to_the_genuine_one() {
+        let report = validate_token_name("\u{FEFF} U\u{200B}SD\u{00A0}\u{00A0}Coin\t");
+        assert_eq!(report.normalized, "USD Coin");
+        assert_eq!(
+            report.changes,
+            vec![
+                NameChange::RemovedZeroWidth { codepoint: 0xFEFF },
+                NameChange::RemovedZeroWidth { codepoint: 0x200B },
+                NameChange::CollapsedWhitespace,
+            ]
+        );
+        assert_eq!(normalize_token_name("Cafe\u{0301}").unwrap(), "Café");
+        // Decoration is kept; only what cannot be seen is removed
+        assert_eq!(normalize_token_name("🚀 Moon").unwrap(), "🚀 Moon");
+        assert_eq!(
+            normalize_token_name("\u{202E}nioC")
+                .unwrap_err()
+                .to_string(),
+            "Token error: Token name \"\\u{202e}nioC\" contains bidirectional override U+202E"
+        );
+    }
+}
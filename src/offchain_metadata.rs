@@ -0,0 +1,666 @@
+//! Typed off-chain metadata JSON following the Metaplex token metadata standard
+//!
+//! Wallets and marketplaces add their own keys to these documents, so every struct keeps
+//! unknown fields in `extra` and writes them back unchanged.
+//!
+//! The standard has no notion of languages. Translated names, descriptions and images are
+//! written as an extension under `properties.localizations`, keyed by locale, where
+//! wallets that do not know it ignore them.
+
+use crate::error::{SssError, SssResult};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Trait type of the attribute holding a token's expiry
+///
+/// The value is a Unix timestamp in seconds with `display_type` `date`, which wallets and
+/// marketplaces show as a date.
+pub const VALID_UNTIL_TRAIT: &str = "valid_until";
+
+/// The JSON document a token's metadata URI points to
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OffchainMetadata {
+    /// The token name, normally the same as on-chain
+    #[serde(default)]
+    pub name: String,
+    /// The token symbol, normally the same as on-chain
+    #[serde(default)]
+    pub symbol: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// URI of the token image
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub animation_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub external_url: Option<String>,
+    /// Traits such as provenance data, e.g. `batch`, `origin` or `expiry`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attributes: Vec<Attribute>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub properties: Option<MetadataProperties>,
+    /// Keys not defined by the standard
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// A trait of a token
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Attribute {
+    pub trait_type: String,
+    /// A string or a number
+    pub value: Value,
+    /// Keys not defined by the standard, e.g. `display_type`
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// The `properties` section of off-chain metadata
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MetadataProperties {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<MetadataFile>,
+    /// e.g. `image` or `video`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    /// Overrides of the name, description and image by locale, e.g. `fr` or `ja-JP`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub localizations: BTreeMap<String, LocalizedFields>,
+    /// Keys not defined by the standard, e.g. the deprecated `creators`
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// A file listed in the `properties` section
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetadataFile {
+    pub uri: String,
+    /// The MIME type, e.g. `image/png`
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    /// Whether the file is served from a CDN
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cdn: Option<bool>,
+    /// Keys not defined by the standard
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// The fields of off-chain metadata translated for one locale
+///
+/// Unset fields fall back to those of the metadata itself.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LocalizedFields {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// URI of an image for the locale, e.g. with translated text on it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// Keys not defined by the extension
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl LocalizedFields {
+    /// Creates a translation of the name and nothing else
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: Some(name.into()),
+            ..Self::default()
+        }
+    }
+
+    /// Sets the translated description
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Sets the image URI for the locale
+    pub fn image(mut self, image: impl Into<String>) -> Self {
+        self.image = Some(image.into());
+        self
+    }
+}
+
+/// The name, description and image of off-chain metadata resolved for a locale
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LocalizedMetadata {
+    /// The locale of the translation used, `None` if none matched
+    pub locale: Option<String>,
+    pub name: String,
+    pub description: Option<String>,
+    pub image: Option<String>,
+}
+
+/// A problem with the translations of off-chain metadata
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LocalizationWarning {
+    /// The locale is not a language code with optional subtags, such as `fr` or `ja-JP`
+    InvalidLocale {
+        /// The locale as written
+        locale: String,
+    },
+    /// The locale lacks a field the metadata has, so wallets show the untranslated one
+    MissingField {
+        /// The locale as written
+        locale: String,
+        /// `name` or `description`
+        field: &'static str,
+    },
+}
+
+impl fmt::Display for LocalizationWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidLocale { locale } => write!(
+                f,
+                "locale '{}' is not a language code such as fr or ja-JP",
+                locale
+            ),
+            Self::MissingField { locale, field } => {
+                write!(f, "locale '{}' has no {}", locale, field)
+            }
+        }
+    }
+}
+
+impl OffchainMetadata {
+    /// Creates metadata with a name and symbol and nothing else
+    pub fn new(name: impl Into<String>, symbol: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            symbol: symbol.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Sets the description
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Sets the image URI
+    pub fn image(mut self, image: impl Into<String>) -> Self {
+        self.image = Some(image.into());
+        self
+    }
+
+    /// Sets the external URL, e.g. a product page
+    pub fn external_url(mut self, external_url: impl Into<String>) -> Self {
+        self.external_url = Some(external_url.into());
+        self
+    }
+
+    /// Sets an attribute, replacing any existing attribute with the same trait type
+    ///
+    /// # Arguments
+    ///
+    /// * `trait_type` - The trait name, e.g. `batch`
+    /// * `value` - A string or number, e.g. `"B-2024-11"`
+    pub fn attribute(mut self, trait_type: impl Into<String>, value: impl Into<Value>) -> Self {
+        let trait_type = trait_type.into();
+        let value = value.into();
+        match self
+            .attributes
+            .iter_mut()
+            .find(|a| a.trait_type == trait_type)
+        {
+            Some(attribute) => attribute.value = value,
+            None => self.attributes.push(Attribute {
+                trait_type,
+                value,
+                extra: Map::new(),
+            }),
+        }
+        self
+    }
+
+    /// Sets the time after which the token is no longer valid
+    ///
+    /// Written as the [`VALID_UNTIL_TRAIT`] attribute. See
+    /// [`is_metadata_expired`](crate::is_metadata_expired) for enforcing it.
+    ///
+    /// # Arguments
+    ///
+    /// * `valid_until` - The expiry, e.g. 90 days after issuance
+    pub fn valid_until(self, valid_until: DateTime<Utc>) -> Self {
+        let mut metadata = self.attribute(VALID_UNTIL_TRAIT, valid_until.timestamp());
+        if let Some(attribute) = metadata
+            .attributes
+            .iter_mut()
+            .find(|a| a.trait_type == VALID_UNTIL_TRAIT)
+        {
+            attribute
+                .extra
+                .insert("display_type".to_string(), Value::from("date"));
+        }
+        metadata
+    }
+
+    /// Adds a file to the `properties` section
+    ///
+    /// # Arguments
+    ///
+    /// * `uri` - The file URI
+    /// * `mime_type` - The MIME type, e.g. `image/png`
+    pub fn file(mut self, uri: impl Into<String>, mime_type: impl Into<String>) -> Self {
+        self.properties
+            .get_or_insert_with(MetadataProperties::default)
+            .files
+            .push(MetadataFile {
+                uri: uri.into(),
+                mime_type: Some(mime_type.into()),
+                cdn: None,
+                extra: Map::new(),
+            });
+        self
+    }
+
+    /// Sets the category in the `properties` section, e.g. `image`
+    pub fn category(mut self, category: impl Into<String>) -> Self {
+        self.properties
+            .get_or_insert_with(MetadataProperties::default)
+            .category = Some(category.into());
+        self
+    }
+
+    /// Sets the translation for a locale, replacing any earlier one
+    ///
+    /// # Arguments
+    ///
+    /// * `locale` - The language code with optional subtags, e.g. `fr` or `ja-JP`
+    /// * `fields` - The translated fields
+    pub fn localization(mut self, locale: impl Into<String>, fields: LocalizedFields) -> Self {
+        self.properties
+            .get_or_insert_with(MetadataProperties::default)
+            .localizations
+            .insert(locale.into(), fields);
+        self
+    }
+
+    /// Returns the value of an attribute
+    ///
+    /// # Arguments
+    ///
+    /// * `trait_type` - The trait name, e.g. `batch`
+    pub fn get_attribute(&self, trait_type: &str) -> Option<&Value> {
+        self.attributes
+            .iter()
+            .find(|a| a.trait_type == trait_type)
+            .map(|a| &a.value)
+    }
+
+    /// Returns the expiry set with [`Self::valid_until`], if any
+    ///
+    /// Besides Unix timestamps, RFC 3339 strings such as `2025-06-30T00:00:00Z` are
+    /// accepted, as written by tools predating the attribute.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `TokenError` if the attribute is neither a timestamp nor an RFC 3339 string
+    pub fn get_valid_until(&self) -> SssResult<Option<DateTime<Utc>>> {
+        let Some(value) = self.get_attribute(VALID_UNTIL_TRAIT) else {
+            return Ok(None);
+        };
+        let valid_until = match value {
+            Value::Number(n) => n
+                .as_i64()
+                .and_then(|secs| DateTime::from_timestamp(secs, 0)),
+            Value::String(s) => DateTime::parse_from_rfc3339(s)
+                .ok()
+                .map(|time| time.with_timezone(&Utc)),
+            _ => None,
+        };
+        valid_until.map(Some).ok_or_else(|| {
+            SssError::TokenError(format!(
+                "Metadata attribute {} is not a timestamp or RFC 3339 date: {}",
+                VALID_UNTIL_TRAIT, value
+            ))
+        })
+    }
+}
+
+/// Renders off-chain metadata as pretty-printed JSON, ready to upload
+///
+/// # Arguments
+///
+/// * `metadata` - The metadata to render
+///
+/// # Returns
+///
+/// The JSON document, including any unknown keys the metadata was parsed with
+pub fn render_metadata_json(metadata: &OffchainMetadata) -> String {
+    // Only strings, numbers and JSON values with string keys are serialized, which cannot fail
+    serde_json::to_string_pretty(metadata).unwrap_or_default()
+}
+
+/// Resolves the name, description and image of off-chain metadata for a locale
+///
+/// The translation of the exact locale is used if there is one, then that of the locale
+/// without its last subtag, so `fr-CA` falls back to `fr`. Locales are compared ignoring
+/// case and treating `_` as `-`. Each field missing from the translation falls back to the
+/// untranslated one.
+///
+/// # Arguments
+///
+/// * `metadata` - The metadata, possibly with translations
+/// * `locale` - The locale of the reader, e.g. `ja-JP`
+///
+/// # Returns
+///
+/// The fields to show and the locale whose translation they came from
+pub fn render_localized(metadata: &OffchainMetadata, locale: &str) -> LocalizedMetadata {
+    let localizations = metadata
+        .properties
+        .as_ref()
+        .map(|properties| &properties.localizations);
+    let mut wanted = normalize_locale(locale);
+    let found = localizations.and_then(|localizations| {
+        loop {
+            if let Some(found) = localizations
+                .iter()
+                .find(|(key, _)| normalize_locale(key) == wanted)
+            {
+                break Some(found);
+            }
+            match wanted.rsplit_once('-') {
+                Some((parent, _)) => wanted = parent.to_string(),
+                None => break None,
+            }
+        }
+    });
+
+    match found {
+        Some((key, fields)) => LocalizedMetadata {
+            locale: Some(key.clone()),
+            name: fields.name.clone().unwrap_or_else(|| metadata.name.clone()),
+            description: fields
+                .description
+                .clone()
+                .or_else(|| metadata.description.clone()),
+            image: fields.image.clone().or_else(|| metadata.image.clone()),
+        },
+        None => LocalizedMetadata {
+            locale: None,
+            name: metadata.name.clone(),
+            description: metadata.description.clone(),
+            image: metadata.image.clone(),
+        },
+    }
+}
+
+/// Checks the translations of off-chain metadata
+///
+/// Every locale must be a language code with optional subtags and translate the name, and
+/// the description if the metadata has one. Missing fields still fall back when rendered,
+/// so these are warnings rather than errors.
+///
+/// # Arguments
+///
+/// * `metadata` - The metadata to check
+///
+/// # Returns
+///
+/// One warning per problem, by locale; empty if every translation is complete
+pub fn validate_localizations(metadata: &OffchainMetadata) -> Vec<LocalizationWarning> {
+    let Some(properties) = &metadata.properties else {
+        return Vec::new();
+    };
+    let mut warnings = Vec::new();
+    for (locale, fields) in &properties.localizations {
+        if !is_locale(locale) {
+            warnings.push(LocalizationWarning::InvalidLocale {
+                locale: locale.clone(),
+            });
+        }
+        if fields.name.as_deref().is_none_or(str::is_empty) {
+            warnings.push(LocalizationWarning::MissingField {
+                locale: locale.clone(),
+                field: "name",
+            });
+        }
+        if metadata.description.is_some() && fields.description.is_none() {
+            warnings.push(LocalizationWarning::MissingField {
+                locale: locale.clone(),
+                field: "description",
+            });
+        }
+    }
+    warnings
+}
+
+/// Lowercases a locale and replaces `_` with `-`, so `ja_JP` matches `ja-JP`
+fn normalize_locale(locale: &str) -> String {
+    locale.trim().replace('_', "-").to_ascii_lowercase()
+}
+
+/// Whether a locale is a two or three letter language code followed by alphanumeric
+/// subtags, e.g. `fr`, `ja-JP` or `zh-Hant-TW`
+fn is_locale(locale: &str) -> bool {
+    let mut subtags = locale.split(['-', '_']);
+    let language = subtags.next().unwrap_or_default();
+    (2..=3).contains(&language.len())
+        && language.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|subtag| {
+            (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+/// Parses an off-chain metadata JSON document
+///
+/// Missing optional sections are left empty and unknown keys are kept in `extra`.
+/// Translations under `properties.localizations` are parsed into
+/// [`MetadataProperties::localizations`].
+///
+/// # Arguments
+///
+/// * `json` - The JSON document
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the document is not a JSON object of the expected shape
+pub fn parse_metadata_json(json: &str) -> SssResult<OffchainMetadata> {
+    serde_json::from_str(json)
+        .map_err(|e| SssError::TokenError(format!("Failed to parse token metadata JSON: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const JA_NAME: &str = "有機コーヒー豆 𠮷野";
+    const JA_DESCRIPTION: &str = "静岡県産の焙煎豆。ロット番号：B-2024-11";
+    const AR_NAME: &str = "حبوب البنّ العضوية";
+    const AR_DESCRIPTION: &str = "تحميص متوسط، الدفعة ١١-٢٠٢٤";
+    const ZH_NAME: &str = "有機咖啡豆";
+
+    fn localized() -> OffchainMetadata {
+        OffchainMetadata {
+            description: Some("Medium roast, batch B-2024-11".to_string()),
+            image: Some("https://example.com/coffee.png".to_string()),
+            ..OffchainMetadata::new("Organic coffee beans", "BEAN")
+        }
+        .localization(
+            "ja-JP",
+            LocalizedFields::new(JA_NAME)
+                .description(JA_DESCRIPTION)
+                .image("https://example.com/coffee-ja.png"),
+        )
+        .localization(
+            "ar",
+            LocalizedFields::new(AR_NAME).description(AR_DESCRIPTION),
+        )
+        .localization("zh-Hant", LocalizedFields::new(ZH_NAME))
+    }
+
+    #[test]
+    fn multi_byte_translations_survive_a_round_trip() {
+        let metadata = localized();
+        let json = render_metadata_json(&metadata);
+        // Written as UTF-8 rather than escaped, so documents stay readable
+        assert!(json.contains(JA_NAME) && json.contains(AR_NAME), "{json}");
+        assert_eq!(parse_metadata_json(&json).unwrap(), metadata);
+
+        // Through raw bytes, as uploaded and downloaded
+        let bytes = render_metadata_json(&metadata).into_bytes();
+        let parsed = parse_metadata_json(std::str::from_utf8(&bytes).unwrap()).unwrap();
+        assert_eq!(render_metadata_json(&parsed).into_bytes(), bytes);
+
+        let localizations = &parsed.properties.unwrap().localizations;
+        assert_eq!(localizations["ja-JP"].name.as_deref(), Some(JA_NAME));
+        assert_eq!(
+            localizations["ar"].description.as_deref(),
+            Some(AR_DESCRIPTION)
+        );
+        assert_eq!(localizations["zh-Hant"].name.as_deref(), Some(ZH_NAME));
+    }
+
+    #[test]
+    fn escaped_translations_parse_to_the_same_strings() {
+        // Tools that escape every non-ASCII character, including surrogate pairs
+        let escaped = serde_json::to_string(&json!({
+            "name": "Organic coffee beans",
+            "symbol": "BEAN",
+            "properties": { "localizations": {
+                "ja-JP": { "name": JA_NAME },
+                "ar": { "name": AR_NAME },
+            } },
+        }))
+        .unwrap()
+        .chars()
+        .map(|c| match c {
+            c if c.is_ascii() => c.to_string(),
+            c => c
+                .encode_utf16(&mut [0; 2])
+                .iter()
+                .map(|unit| format!("\\u{:04x}", unit))
+                .collect(),
+        })
+        .collect::<String>();
+        assert!(escaped.is_ascii());
+        assert!(escaped.contains("\\ud842\\udfb7"), "{escaped}");
+
+        let parsed = parse_metadata_json(&escaped).unwrap();
+        assert_eq!(render_localized(&parsed, "ja-JP").name, JA_NAME);
+        assert_eq!(render_localized(&parsed, "ar").name, AR_NAME);
+    }
+
+    #[test]
+    fn localizations_are_written_under_properties() {
+        let value: Value = serde_json::from_str(&render_metadata_json(&localized())).unwrap();
+        assert_eq!(
+            value["properties"]["localizations"]["ar"],
+            json!({ "name": AR_NAME, "description": AR_DESCRIPTION })
+        );
+        assert!(value.get("localizations").is_none());
+
+        let plain = render_metadata_json(&OffchainMetadata::new("Beans", "BEAN"));
+        assert!(!plain.contains("localizations"), "{plain}");
+    }
+
+    #[test]
+    fn unknown_keys_of_a_translation_are_kept() {
+        let json = json!({
+            "name": "Beans",
+            "symbol": "BEAN",
+            "properties": { "localizations": {
+                "ar": { "name": AR_NAME, "direction": "rtl" },
+            } },
+        })
+        .to_string();
+        let parsed = parse_metadata_json(&json).unwrap();
+        let rendered: Value = serde_json::from_str(&render_metadata_json(&parsed)).unwrap();
+        assert_eq!(
+            rendered["properties"]["localizations"]["ar"]["direction"],
+            "rtl"
+        );
+    }
+
+    #[test]
+    fn render_falls_back_from_locale_to_language_to_default() {
+        let metadata = localized();
+
+        let ja = render_localized(&metadata, "ja_jp");
+        assert_eq!(ja.locale.as_deref(), Some("ja-JP"));
+        assert_eq!(ja.name, JA_NAME);
+        assert_eq!(
+            ja.image.as_deref(),
+            Some("https://example.com/coffee-ja.png")
+        );
+
+        let ar = render_localized(&metadata, "ar-EG");
+        assert_eq!(ar.locale.as_deref(), Some("ar"));
+        assert_eq!(ar.description.as_deref(), Some(AR_DESCRIPTION));
+        assert_eq!(ar.image, metadata.image);
+
+        let zh = render_localized(&metadata, "zh-Hant-TW");
+        assert_eq!(zh.name, ZH_NAME);
+        assert_eq!(zh.description, metadata.description);
+
+        // A region-only translation is not used for the bare language
+        let ja = render_localized(&metadata, "ja");
+        assert_eq!(ja.locale, None);
+        assert_eq!(ja.name, "Organic coffee beans");
+
+        let fr = render_localized(&metadata, "fr-FR");
+        assert_eq!(
+            fr,
+            LocalizedMetadata {
+                locale: None,
+                name: metadata.name.clone(),
+                description: metadata.description.clone(),
+                image: metadata.image.clone(),
+            }
+        );
+    }
+
+    #[test]
+    fn incomplete_and_malformed_locales_are_warned_about() {
+        let metadata = localized()
+            .localization("fr", LocalizedFields::default().description("Torréfaction"))
+            .localization(
+                "日本語",
+                LocalizedFields::new(JA_NAME).description(JA_DESCRIPTION),
+            );
+
+        assert_eq!(
+            validate_localizations(&metadata),
+            vec![
+                LocalizationWarning::MissingField {
+                    locale: "fr".to_string(),
+                    field: "name",
+                },
+                LocalizationWarning::MissingField {
+                    locale: "zh-Hant".to_string(),
+                    field: "description",
+                },
+                LocalizationWarning::InvalidLocale {
+                    locale: "日本語".to_string(),
+                },
+            ]
+        );
+        assert_eq!(
+            LocalizationWarning::InvalidLocale {
+                locale: "日本語".to_string()
+            }
+            .to_string(),
+            "locale '日本語' is not a language code such as fr or ja-JP"
+        );
+
+        // Without a description, translations need only a name
+        let metadata = OffchainMetadata::new("Beans", "BEAN")
+            .localization("ar", LocalizedFields::new(AR_NAME))
+            .localization("ja-JP", LocalizedFields::new(JA_NAME));
+        assert!(validate_localizations(&metadata).is_empty());
+    }
+}
@@ -0,0 +1,181 @@
+//! Information about the configured payer wallet
+
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::journal::{JournalOperation, journaled};
+use crate::profiles::profile_setting;
+use crate::read_only::ensure_writable;
+use crate::roles::ensure_role_permits;
+use crate::serde_utils::option_pubkey_string;
+use crate::transaction::TxOptions;
+use crate::watch_only::{ensure_not_watch_only, is_watch_only, watch_only_payer};
+use crate::{PAYER_RESULT, RPC_CLIENT, get_payer};
+use serde::Serialize;
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+};
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+/// Public key of the payer, null until first read and after a failed load
+///
+/// Only written while holding the [`PAYER_RESULT`] lock. Replaced keys are leaked rather
+/// than freed, since a reader may still hold them; that is 32 bytes per [`crate::set_payer`].
+static PAYER_PUBKEY: AtomicPtr<Pubkey> = AtomicPtr::new(ptr::null_mut());
+
+/// State of the payer wallet, for display before operations that spend from it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PayerInfo {
+    /// Whether the payer keypair loaded successfully
+    pub loaded: bool,
+    /// The payer address, if it loaded
+    #[serde(with = "option_pubkey_string")]
+    pub pubkey: Option<Pubkey>,
+    /// The payer balance in lamports, if it loaded
+    pub lamports: Option<u64>,
+    /// Why the payer failed to load, e.g. a missing `PAYER_MNEMONIC`
+    pub load_error: Option<String>,
+    /// Whether the library is in watch-only mode, where the payer is a public key only
+    pub watch_only: bool,
+}
+
+/// Returns the public key of the payer
+///
+/// In watch-only mode this is the watched wallet. Otherwise the payer of the profile
+/// selected on the current thread takes precedence. The global key is cached on first use,
+/// so later calls neither lock nor copy the keypair. Prefer this over [`crate::get_payer`]
+/// when the keypair is not needed for signing.
+///
+/// # Errors
+///
+/// Returns a `KeypairError` if the payer keypair failed to load, and a `ConfigError` if
+/// the selected profile is no longer registered
+pub fn get_payer_pubkey() -> SssResult<Pubkey> {
+    if let Some(payer) = watch_only_payer() {
+        return payer;
+    }
+    if let Some(pubkey) = profile_setting(|profile| profile.payer.pubkey())? {
+        return Ok(pubkey);
+    }
+    let cached = PAYER_PUBKEY.load(Ordering::Acquire);
+    if !cached.is_null() {
+        // SAFETY: non-null values come from Box::into_raw and are never freed
+        return Ok(unsafe { *cached });
+    }
+
+    let payer = PAYER_RESULT
+        .lock()
+        .map_err(|e| SssError::KeypairError(format!("Failed to acquire lock: {}", e)))?;
+    match &*payer {
+        Ok(keypair) => {
+            let pubkey = keypair.pubkey();
+            // Another caller may have filled the cache while we waited for the lock
+            if PAYER_PUBKEY.load(Ordering::Acquire).is_null() {
+                cache_payer_pubkey(Some(pubkey));
+            }
+            Ok(pubkey)
+        }
+        Err(e) => Err(SssError::KeypairError(format!(
+            "Failed to get payer keypair: {}",
+            e
+        ))),
+    }
+}
+
+/// Returns the payer keypair for signing a transaction
+///
+/// # Errors
+///
+/// Returns a `KeypairError` in watch-only mode and a `ConfigError` in read-only mode, both
+/// without loading the keypair, and a `KeypairError` if the payer keypair failed to load
+pub(crate) fn signing_payer() -> SssResult<Keypair> {
+    ensure_not_watch_only()?;
+    ensure_writable()?;
+    if let Some(payer) = profile_setting(|profile| profile.payer.insecure_clone())? {
+        return Ok(payer);
+    }
+    get_payer().into_sss_error("Failed to get payer keypair")
+}
+
+/// Refuses to use a signature of the payer in read-only mode or when the active role may
+/// not run the current operation
+///
+/// Every signature of the payer passes this gate: transactions when they are signed and
+/// again before they are sent, and messages and structured data in [`sign_off_chain`].
+///
+/// # Errors
+///
+/// Returns a `ConfigError` in read-only mode or if the role policy refuses the operation
+pub(crate) fn ensure_signing_permitted() -> SssResult<()> {
+    ensure_writable()?;
+    ensure_role_permits()
+}
+
+/// Signs bytes that are not a transaction with the payer, as `operation`
+///
+/// The role policy is checked with `params` as the inputs of the operation, as for a
+/// transaction.
+///
+/// # Returns
+///
+/// The key that signed and its signature
+pub(crate) fn sign_off_chain(
+    operation: JournalOperation,
+    params: &impl Serialize,
+    message: &[u8],
+) -> SssResult<(Pubkey, Signature)> {
+    journaled(operation, params, &TxOptions::default(), || {
+        let payer = signing_payer()?;
+        ensure_signing_permitted()?;
+        Ok((payer.pubkey(), payer.sign_message(message)))
+    })
+}
+
+/// Replaces the cached payer public key
+///
+/// Must be called while holding the [`PAYER_RESULT`] lock, whenever the payer changes.
+pub(crate) fn cache_payer_pubkey(pubkey: Option<Pubkey>) {
+    let new = pubkey.map_or(ptr::null_mut(), |pubkey| Box::into_raw(Box::new(pubkey)));
+    PAYER_PUBKEY.store(new, Ordering::Release);
+}
+
+/// Returns the payer address and balance, or why the payer failed to load
+///
+/// A payer that failed to load is reported in [`PayerInfo::load_error`] rather than as
+/// an error, so setup problems can be shown to the user. In watch-only mode the watched
+/// wallet is reported, with its balance, as a payer that is not loaded.
+///
+/// # Errors
+///
+/// Returns an `RpcError` if the balance of a loaded payer cannot be fetched
+pub fn payer_info() -> SssResult<PayerInfo> {
+    let watch_only = is_watch_only();
+    let pubkey = if watch_only {
+        get_payer_pubkey()?
+    } else {
+        match get_payer() {
+            Ok(payer) => payer.pubkey(),
+            Err(e) => {
+                return Ok(PayerInfo {
+                    loaded: false,
+                    pubkey: None,
+                    lamports: None,
+                    load_error: Some(e.to_string()),
+                    watch_only,
+                });
+            }
+        }
+    };
+    let lamports = RPC_CLIENT
+        .get_balance(&pubkey)
+        .into_sss_error("Failed to get payer balance from rpc")?;
+
+    Ok(PayerInfo {
+        loaded: !watch_only,
+        pubkey: Some(pubkey),
+        lamports: Some(lamports),
+        load_error: None,
+        watch_only,
+    })
+}
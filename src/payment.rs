@@ -0,0 +1,237 @@
+//! Solana Pay transfer requests and confirmation of the payments made for them
+//!
+//! A request is a `solana:` URL, shown to the customer as a QR code, asking their wallet to
+//! transfer SOL or an SPL token to the payer. The wallet adds the request's reference key to
+//! the transfer, which is how [`await_payment`] finds it on chain.
+
+use crate::RPC_CLIENT;
+use crate::amount::base_to_ui_amount;
+use crate::display::{SolanaPayUri, normalize_amount};
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::mint_cache::mint_info;
+use crate::payer::get_payer_pubkey;
+use crate::receipt::{Receipt, fetch_transaction_receipt};
+use crate::serde_utils::{option_pubkey_string, pubkey_string};
+use serde::Serialize;
+use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer};
+use std::fmt::Write;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Delay between lookups of the reference key while waiting for a payment
+pub const PAYMENT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Number of decimal places of SOL, so amounts in lamports convert to whole SOL
+const SOL_DECIMALS: u8 = 9;
+
+/// A Solana Pay transfer request paying the payer wallet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PaymentRequest {
+    /// The `solana:` URL to encode in the QR code
+    pub url: String,
+    /// The wallet receiving the payment, the payer
+    #[serde(with = "pubkey_string")]
+    pub recipient: Pubkey,
+    /// The amount in base units, lamports for SOL
+    pub amount: u64,
+    /// The SPL token to pay in, `None` for SOL
+    #[serde(with = "option_pubkey_string")]
+    pub spl_mint: Option<Pubkey>,
+    /// The key identifying the payment on chain
+    #[serde(with = "pubkey_string")]
+    pub reference: Pubkey,
+}
+
+/// A payment found for a reference key, see [`await_payment`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PaymentConfirmation {
+    /// The signature of the paying transaction
+    pub signature: String,
+    /// The fee payer of the paying transaction, normally the customer
+    #[serde(with = "pubkey_string")]
+    pub payer: Pubkey,
+    /// The amount received in base units
+    pub received: u64,
+    /// How much more than expected was received, 0 for an exact payment
+    pub overpaid: u64,
+}
+
+/// Returns a new random reference key for a payment request
+///
+/// Use a new key for every request, so a payment cannot be mistaken for another.
+pub fn generate_payment_reference() -> Pubkey {
+    Keypair::new().pubkey()
+}
+
+/// Builds a Solana Pay transfer request paying the payer wallet
+///
+/// The label and message are percent-encoded, so spaces and non-ASCII text are safe.
+///
+/// # Arguments
+///
+/// * `amount` - The amount in base units, lamports for SOL
+/// * `spl_mint` - The SPL token to pay in, `None` for SOL
+/// * `reference` - The key identifying the payment, see [`generate_payment_reference`]
+/// * `label` - Who is requesting the payment, e.g. the shop name, shown by the wallet
+/// * `message` - What the payment is for, shown by the wallet
+///
+/// # Returns
+///
+/// The request and its `solana:` URL
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the amount is 0 or the SPL mint does not exist, and a
+/// `KeypairError` if the payer cannot be loaded
+pub fn create_payment_request(
+    amount: u64,
+    spl_mint: Option<Pubkey>,
+    reference: Pubkey,
+    label: Option<&str>,
+    message: Option<&str>,
+) -> SssResult<PaymentRequest> {
+    if amount == 0 {
+        return Err(SssError::TokenError(
+            "Payment amount must be greater than 0".to_string(),
+        ));
+    }
+    let recipient = get_payer_pubkey()?;
+    let decimals = match &spl_mint {
+        Some(mint) => mint_info(mint)?.decimals,
+        None => SOL_DECIMALS,
+    };
+
+    let url = SolanaPayUri {
+        recipient,
+        amount: Some(normalize_amount(&base_to_ui_amount(amount, decimals))?),
+        spl_token: spl_mint,
+        references: vec![reference],
+        label: label.map(str::to_string),
+        message: message.map(str::to_string),
+        memo: None,
+    }
+    .to_string();
+
+    Ok(PaymentRequest {
+        url,
+        recipient,
+        amount,
+        spl_mint,
+        reference,
+    })
+}
+
+/// Waits for a payment to the payer wallet carrying a reference key
+///
+/// The reference key is polled every [`PAYMENT_POLL_INTERVAL`]. Successful transactions
+/// referencing it are checked oldest first, and the first one that credits the payer
+/// wallet decides the outcome: its amount is the growth of the payer's SOL balance, or of
+/// its balance of `spl_mint` over all of its token accounts. Mint the purchased tokens
+/// only once this returns `Ok`.
+///
+/// # Arguments
+///
+/// * `reference` - The reference key of the request
+/// * `spl_mint` - The SPL token requested, `None` for SOL
+/// * `expected_amount` - The requested amount in base units, lamports for SOL
+/// * `timeout` - How long to wait for the payment
+///
+/// # Returns
+///
+/// The paying transaction, the amount received and any overpayment
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the payment is smaller than `expected_amount`, and an
+/// `RpcError` if no payment arrives within `timeout`
+pub fn await_payment(
+    reference: Pubkey,
+    spl_mint: Option<Pubkey>,
+    expected_amount: u64,
+    timeout: Duration,
+) -> SssResult<PaymentConfirmation> {
+    let recipient = get_payer_pubkey()?;
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(receipt) = find_payment(&reference, &recipient, spl_mint.as_ref())? {
+            let received = received_amount(&receipt, &recipient, spl_mint.as_ref());
+            if received < expected_amount {
+                return Err(SssError::TokenError(format!(
+                    "Payment {} for reference {} is short: received {}, expected {}",
+                    receipt.signature, reference, received, expected_amount
+                )));
+            }
+            return Ok(PaymentConfirmation {
+                signature: receipt.signature,
+                payer: receipt.fee_payer,
+                received,
+                overpaid: received - expected_amount,
+            });
+        }
+        if Instant::now() + PAYMENT_POLL_INTERVAL > deadline {
+            return Err(SssError::RpcError(format!(
+                "No payment for reference {} arrived within {:?}",
+                reference, timeout
+            )));
+        }
+        thread::sleep(PAYMENT_POLL_INTERVAL);
+    }
+}
+
+/// Returns the receipt of the oldest successful transaction crediting the recipient
+fn find_payment(
+    reference: &Pubkey,
+    recipient: &Pubkey,
+    spl_mint: Option<&Pubkey>,
+) -> SssResult<Option<Receipt>> {
+    let signatures = RPC_CLIENT
+        .get_signatures_for_address(reference)
+        .into_sss_error("Failed to get reference signatures from rpc")?;
+    // Signatures come newest first
+    for status in signatures
+        .iter()
+        .rev()
+        .filter(|status| status.err.is_none())
+    {
+        let receipt = fetch_transaction_receipt(&status.signature)?;
+        if receipt.success && received_amount(&receipt, recipient, spl_mint) > 0 {
+            return Ok(Some(receipt));
+        }
+    }
+    Ok(None)
+}
+
+/// Returns how much a transaction credited the recipient, in base units
+fn received_amount(receipt: &Receipt, recipient: &Pubkey, spl_mint: Option<&Pubkey>) -> u64 {
+    match spl_mint {
+        Some(mint) => {
+            let owner = recipient.to_string();
+            let (pre, post) = receipt
+                .token_balances
+                .iter()
+                .filter(|b| b.mint == *mint && b.owner.as_deref() == Some(owner.as_str()))
+                .fold((0u64, 0u64), |(pre, post), b| {
+                    (pre.saturating_add(b.pre), post.saturating_add(b.post))
+                });
+            post.saturating_sub(pre)
+        }
+        None => receipt
+            .lamport_balances
+            .iter()
+            .find(|b| b.account == *recipient)
+            .map_or(0, |b| b.post.saturating_sub(b.pre)),
+    }
+}
+
+/// Percent-encodes a query value, keeping only the characters `encodeURIComponent` keeps
+pub(crate) fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.!~*'()".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            let _ = write!(encoded, "%{:02X}", byte);
+        }
+    }
+    encoded
+}
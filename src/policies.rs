@@ -0,0 +1,92 @@
+//! Reloading the trusted-mint, supply-cap and role policy files of a running service
+//!
+//! All files are read and parsed before anything is applied, and each registry is swapped
+//! as a whole, so operations in flight see either the old or the new policy and never an
+//! empty one.
+
+use crate::error::SssResult;
+use crate::events::{SssEvent, emit};
+use crate::roles::{read_role_policies_file, replace_role_policies};
+use crate::serde_utils::pubkey_strings;
+use crate::supply_caps::{SupplyCapChange, reload_supply_caps};
+use crate::trusted_mints::{read_trusted_mints_file, replace_file_trusted_mints};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+
+/// What reloading the policy files changed, see [`reload_policy_files`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyReload {
+    /// Mints newly listed in the trusted mints file
+    #[serde(with = "pubkey_strings")]
+    pub trusted_added: Vec<Pubkey>,
+    /// Mints no longer listed in the trusted mints file; they stay trusted if they were
+    /// also trusted at runtime, e.g. because the library created them
+    #[serde(with = "pubkey_strings")]
+    pub trusted_removed: Vec<Pubkey>,
+    /// Supply caps added, changed or removed
+    pub caps_changed: Vec<SupplyCapChange>,
+    /// Roles whose policy was added, changed or removed
+    pub roles_changed: Vec<String>,
+}
+
+impl PolicyReload {
+    /// Returns whether the reload changed nothing
+    pub fn is_empty(&self) -> bool {
+        self.trusted_added.is_empty()
+            && self.trusted_removed.is_empty()
+            && self.caps_changed.is_empty()
+            && self.roles_changed.is_empty()
+    }
+}
+
+/// Reloads the trusted mints, supply caps and role policy files without a restart
+///
+/// The files are those named by `SSS_TRUSTED_MINTS_PATH`, a JSON array of mint addresses,
+/// `SSS_SUPPLY_CAPS_PATH` and `SSS_ROLE_POLICIES_PATH`. A file that is not configured or
+/// does not exist is left alone rather than clearing its policy. The outcome is emitted as
+/// a `policies_reloaded` or `policy_reload_failed` event.
+///
+/// # Returns
+///
+/// The mints and caps that changed
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if any file cannot be read or parsed; no policy is changed
+/// then
+pub fn reload_policy_files() -> SssResult<PolicyReload> {
+    match reload() {
+        Ok(reload) => {
+            emit(SssEvent::PoliciesReloaded {
+                changes: reload.clone(),
+            });
+            Ok(reload)
+        }
+        Err(e) => {
+            emit(SssEvent::PolicyReloadFailed {
+                error: e.to_string(),
+            });
+            Err(e)
+        }
+    }
+}
+
+/// Reads all files, then applies them
+fn reload() -> SssResult<PolicyReload> {
+    let trusted = read_trusted_mints_file()?;
+    let roles = read_role_policies_file()?;
+    // The caps are swapped first, so a caps file that fails to parse leaves the other
+    // policies unchanged as well
+    let caps_changed = reload_supply_caps()?.unwrap_or_default();
+    let (trusted_added, trusted_removed) = match trusted {
+        Some(trusted) => replace_file_trusted_mints(trusted),
+        None => (Vec::new(), Vec::new()),
+    };
+    let roles_changed = roles.map(replace_role_policies).unwrap_or_default();
+    Ok(PolicyReload {
+        trusted_added,
+        trusted_removed,
+        caps_changed,
+        roles_changed,
+    })
+}
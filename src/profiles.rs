@@ -0,0 +1,289 @@
+//! Named configuration profiles for serving several brands from one process
+//!
+//! A profile carries its own payer, trusted mints, default collection and platform fee.
+//! Profiles are selected per thread, so threads serving different brands never share a
+//! payer; a thread without a selected profile uses the global configuration.
+
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::load_dotenv;
+use crate::watch_only::is_watch_only;
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, read_keypair_file},
+};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+/// The configuration a profile replaces
+#[derive(Debug)]
+pub struct ProfileConfig {
+    /// The keypair paying for and signing every transaction
+    pub payer: Keypair,
+    /// The mints trusted in strict mode, instead of the global trusted set
+    pub trusted_mints: HashSet<Pubkey>,
+    /// The collection of tokens created without one
+    pub default_collection: Option<Pubkey>,
+    /// The platform fee charged instead of the configured default, see
+    /// [`crate::TxOptions::platform_fee`]
+    pub platform_fee: Option<(Pubkey, u64)>,
+}
+
+impl ProfileConfig {
+    /// Creates a profile paying with the given keypair and no other settings
+    ///
+    /// # Arguments
+    ///
+    /// * `payer` - The keypair paying for and signing every transaction
+    pub fn new(payer: Keypair) -> Self {
+        Self {
+            payer,
+            trusted_mints: HashSet::new(),
+            default_collection: None,
+            platform_fee: None,
+        }
+    }
+}
+
+/// A profile as written in the `SSS_PROFILES_PATH` file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfileFile {
+    payer_keypair_path: String,
+    #[serde(default)]
+    trusted_mints: Vec<String>,
+    default_collection: Option<String>,
+    platform_fee_account: Option<String>,
+    platform_fee_lamports: Option<u64>,
+}
+
+/// The registered profiles and why the profiles file failed to load, if it did
+#[derive(Default)]
+struct Registry {
+    profiles: HashMap<String, ProfileConfig>,
+    load_error: Option<String>,
+}
+
+lazy_static! {
+    /// Registered profiles, seeded from the JSON file named by `SSS_PROFILES_PATH`
+    static ref PROFILES: RwLock<Registry> = {
+        load_dotenv();
+        let registry = match env::var("SSS_PROFILES_PATH") {
+            // Profiles carry payer keypairs, which are never loaded in watch-only mode
+            Ok(_) if is_watch_only() => Registry::default(),
+            Ok(path) => match load_profiles(Path::new(&path)) {
+                Ok(profiles) => Registry {
+                    profiles,
+                    load_error: None,
+                },
+                Err(SssError::ConfigError(message)) => Registry {
+                    profiles: HashMap::new(),
+                    load_error: Some(message),
+                },
+                Err(e) => Registry {
+                    profiles: HashMap::new(),
+                    load_error: Some(e.to_string()),
+                },
+            },
+            Err(_) => Registry::default(),
+        };
+        RwLock::new(registry)
+    };
+}
+
+thread_local! {
+    /// The profile selected on this thread
+    static ACTIVE_PROFILE: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Registers a profile, replacing any profile of the same name
+///
+/// Ignored in watch-only mode, since the profile carries a payer keypair.
+///
+/// # Arguments
+///
+/// * `name` - The name the profile is selected by
+/// * `config` - The configuration of the profile
+pub fn register_profile(name: impl Into<String>, config: ProfileConfig) {
+    if is_watch_only() {
+        return;
+    }
+    if let Ok(mut registry) = PROFILES.write() {
+        registry.profiles.insert(name.into(), config);
+    }
+}
+
+/// Drops every registered profile with its payer keypair
+pub(crate) fn clear_profiles() {
+    if let Ok(mut registry) = PROFILES.write() {
+        registry.profiles.clear();
+    }
+}
+
+/// Selects the profile used by later calls on the current thread
+///
+/// # Arguments
+///
+/// * `name` - The profile to select, or `None` for the global configuration
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if no profile of that name is registered; the selection is
+/// left unchanged
+pub fn select_profile(name: Option<&str>) -> SssResult<()> {
+    if let Some(name) = name {
+        ensure_registered(name)?;
+    }
+    ACTIVE_PROFILE.with(|active| *active.borrow_mut() = name.map(str::to_string));
+    Ok(())
+}
+
+/// Returns the profile selected on the current thread, if any
+pub fn active_profile() -> Option<String> {
+    ACTIVE_PROFILE.with(|active| active.borrow().clone())
+}
+
+/// Runs an operation with a profile selected on the current thread
+///
+/// The previous selection is restored afterwards, also if the operation panics.
+///
+/// # Arguments
+///
+/// * `name` - The profile to select
+/// * `f` - The operation
+///
+/// # Errors
+///
+/// Returns a `ConfigError` without running the operation if no profile of that name is
+/// registered, and otherwise the error of the operation
+pub fn with_profile<T>(name: &str, f: impl FnOnce() -> SssResult<T>) -> SssResult<T> {
+    /// Restores the previous selection when dropped
+    struct Restore(Option<String>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            ACTIVE_PROFILE.with(|active| *active.borrow_mut() = previous);
+        }
+    }
+
+    ensure_registered(name)?;
+    let _restore = Restore(ACTIVE_PROFILE.with(|active| active.replace(Some(name.to_string()))));
+    f()
+}
+
+/// Reads a setting of the active profile
+///
+/// # Returns
+///
+/// `None` if no profile is selected on the current thread
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if the selected profile is no longer registered
+pub(crate) fn profile_setting<T>(f: impl FnOnce(&ProfileConfig) -> T) -> SssResult<Option<T>> {
+    let Some(name) = active_profile() else {
+        return Ok(None);
+    };
+    let registry = PROFILES
+        .read()
+        .map_err(|e| SssError::ConfigError(format!("Failed to acquire lock: {}", e)))?;
+    let profile = registry
+        .profiles
+        .get(&name)
+        .ok_or_else(|| not_registered(&name, &registry))?;
+    Ok(Some(f(profile)))
+}
+
+/// Changes a setting of the active profile, returning `false` if none is selected
+pub(crate) fn update_profile(f: impl FnOnce(&mut ProfileConfig)) -> bool {
+    let Some(name) = active_profile() else {
+        return false;
+    };
+    if let Ok(mut registry) = PROFILES.write()
+        && let Some(profile) = registry.profiles.get_mut(&name)
+    {
+        f(profile);
+    }
+    true
+}
+
+/// Fails with a `ConfigError` if no profile of that name is registered
+fn ensure_registered(name: &str) -> SssResult<()> {
+    let registry = PROFILES
+        .read()
+        .map_err(|e| SssError::ConfigError(format!("Failed to acquire lock: {}", e)))?;
+    if registry.profiles.contains_key(name) {
+        return Ok(());
+    }
+    Err(not_registered(name, &registry))
+}
+
+/// Describes a missing profile, with the load error of the profiles file if there was one
+fn not_registered(name: &str, registry: &Registry) -> SssError {
+    match &registry.load_error {
+        Some(load_error) => SssError::ConfigError(format!(
+            "Profile '{}' is not registered; SSS_PROFILES_PATH failed to load: {}",
+            name, load_error
+        )),
+        None => SssError::ConfigError(format!("Profile '{}' is not registered", name)),
+    }
+}
+
+/// Loads the profiles file, a JSON object of profiles by name
+fn load_profiles(path: &Path) -> SssResult<HashMap<String, ProfileConfig>> {
+    let json = fs::read_to_string(path).into_sss_error("Failed to read profiles config")?;
+    let files: HashMap<String, ProfileFile> =
+        serde_json::from_str(&json).into_sss_error("Failed to parse profiles config")?;
+    files
+        .into_iter()
+        .map(|(name, file)| {
+            let config = profile_from_file(&name, file)?;
+            Ok((name, config))
+        })
+        .collect()
+}
+
+/// Converts a profile of the profiles file, loading its payer keypair
+fn profile_from_file(name: &str, file: ProfileFile) -> SssResult<ProfileConfig> {
+    let pubkey = |value: &str| {
+        Pubkey::from_str(value).map_err(|e| {
+            SssError::ConfigError(format!(
+                "Invalid public key '{}' in profile '{}': {}",
+                value, name, e
+            ))
+        })
+    };
+    let payer = read_keypair_file(&file.payer_keypair_path).map_err(|e| {
+        SssError::ConfigError(format!(
+            "Failed to read payer keypair of profile '{}' from {}: {}",
+            name, file.payer_keypair_path, e
+        ))
+    })?;
+    let platform_fee = match (file.platform_fee_account, file.platform_fee_lamports) {
+        (Some(account), Some(lamports)) => Some((pubkey(&account)?, lamports)),
+        (None, None) => None,
+        _ => {
+            return Err(SssError::ConfigError(format!(
+                "Profile '{}' must set both platform_fee_account and platform_fee_lamports",
+                name
+            )));
+        }
+    };
+    Ok(ProfileConfig {
+        payer,
+        trusted_mints: file
+            .trusted_mints
+            .iter()
+            .map(|mint| pubkey(mint))
+            .collect::<SssResult<_>>()?,
+        default_collection: file.default_collection.as_deref().map(pubkey).transpose()?,
+        platform_fee,
+    })
+}
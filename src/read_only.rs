@@ -0,0 +1,52 @@
+//! Read-only mode, in which nothing is signed or sent
+
+use crate::error::{SssError, SssResult};
+use crate::load_dotenv;
+use lazy_static::lazy_static;
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Message of the `ConfigError` returned by operations refused in read-only mode
+pub const READ_ONLY_MESSAGE: &str = "library is in read-only mode";
+
+lazy_static! {
+    /// Whether signing and sending are refused, seeded from `SSS_READ_ONLY`
+    static ref READ_ONLY: AtomicBool = {
+        load_dotenv();
+        let enabled = env::var("SSS_READ_ONLY")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+        AtomicBool::new(enabled)
+    };
+}
+
+/// Enables or disables read-only mode
+///
+/// In read-only mode, every operation that would sign or send a transaction fails before
+/// loading the payer keypair. Fetching, deriving and estimating keep working.
+///
+/// # Arguments
+///
+/// * `enabled` - Whether read-only mode is on
+pub fn set_read_only(enabled: bool) {
+    READ_ONLY.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns whether read-only mode is on
+pub fn is_read_only() -> bool {
+    READ_ONLY.load(Ordering::Relaxed)
+}
+
+/// Fails with a `ConfigError` in read-only mode
+pub(crate) fn ensure_writable() -> SssResult<()> {
+    if is_read_only() {
+        return Err(SssError::ConfigError(READ_ONLY_MESSAGE.to_string()));
+    }
+    Ok(())
+}
+
+/// Returns whether an error is the refusal of [`ensure_writable`]
+#[cfg(feature = "ffi")]
+pub(crate) fn is_read_only_error(error: &SssError) -> bool {
+    matches!(error, SssError::ConfigError(message) if message == READ_ONLY_MESSAGE)
+}
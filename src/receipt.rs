@@ -0,0 +1,350 @@
+//! Structured receipts of confirmed transactions, for archiving as evidence
+
+use crate::RPC_CLIENT;
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::inspect::{DecodedInstruction, InspectedInstruction, inspect_versioned_message};
+use crate::serde_utils::pubkey_string;
+use base64::prelude::{BASE64_STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_rpc_client_api::request::RpcRequest;
+use solana_sdk::{pubkey::Pubkey, signature::Signature, transaction::VersionedTransaction};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// A confirmed transaction as recorded on chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Receipt {
+    /// The transaction signature
+    pub signature: String,
+    /// The slot the transaction was processed in
+    pub slot: u64,
+    /// Estimated production time of the block as a Unix timestamp, if the node knows it
+    pub block_time: Option<i64>,
+    /// The fee charged in lamports
+    pub fee: u64,
+    /// The account that paid the fee
+    #[serde(with = "pubkey_string")]
+    pub fee_payer: Pubkey,
+    /// Whether the transaction succeeded
+    pub success: bool,
+    /// Why the transaction failed, if it did
+    pub error: Option<String>,
+    /// The top-level instructions, decoded where the program is known
+    pub instructions: Vec<InspectedInstruction>,
+    /// One-line descriptions of the instructions, in order
+    pub instruction_summaries: Vec<String>,
+    /// Balances of every token account the transaction touched
+    pub token_balances: Vec<TokenBalanceChange>,
+    /// Lamport balances of every account whose balance changed, including the fee payer
+    pub lamport_balances: Vec<LamportBalanceChange>,
+}
+
+/// Lamport balance of an account before and after a transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct LamportBalanceChange {
+    /// The account
+    #[serde(with = "pubkey_string")]
+    pub account: Pubkey,
+    /// The balance in lamports before the transaction
+    pub pre: u64,
+    /// The balance in lamports after the transaction
+    pub post: u64,
+}
+
+/// Balance of a token account before and after a transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TokenBalanceChange {
+    /// The token account
+    #[serde(with = "pubkey_string")]
+    pub account: Pubkey,
+    /// The mint of the token account
+    #[serde(with = "pubkey_string")]
+    pub mint: Pubkey,
+    /// The wallet owning the token account, if the node reports it
+    pub owner: Option<String>,
+    /// The number of decimal places of the mint
+    pub decimals: u8,
+    /// The balance in base units before the transaction, 0 if the account did not exist
+    pub pre: u64,
+    /// The balance in base units after the transaction
+    pub post: u64,
+}
+
+/// A mint a receipt is expected to show, see [`Receipt::verify_expectation`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MintExpectation {
+    /// The mint of the tokens
+    pub mint: Pubkey,
+    /// The wallet that should have received the tokens
+    pub owner: Pubkey,
+    /// The amount in base units
+    pub amount: u64,
+}
+
+impl Receipt {
+    /// Checks that the transaction succeeded and minted the expected amount to the owner
+    ///
+    /// The owner's balance of the mint, summed over all of its token accounts, must have
+    /// grown by exactly `amount`, and the transaction must contain a mint instruction.
+    ///
+    /// # Arguments
+    ///
+    /// * `expectation` - The mint, receiving wallet and amount
+    ///
+    /// # Errors
+    ///
+    /// Returns a `TokenError` describing the first mismatch
+    pub fn verify_expectation(&self, expectation: &MintExpectation) -> SssResult<()> {
+        if !self.success {
+            return Err(SssError::TokenError(format!(
+                "Transaction {} failed: {}",
+                self.signature,
+                self.error.as_deref().unwrap_or("unknown error")
+            )));
+        }
+
+        let mints = self.instructions.iter().any(|ix| {
+            matches!(
+                ix.decoded,
+                DecodedInstruction::TokenMintTo { .. } | DecodedInstruction::MetadataMint { .. }
+            )
+        });
+        if !mints {
+            return Err(SssError::TokenError(format!(
+                "Transaction {} contains no mint instruction",
+                self.signature
+            )));
+        }
+
+        let owner = expectation.owner.to_string();
+        let (pre, post) = self
+            .token_balances
+            .iter()
+            .filter(|b| b.mint == expectation.mint && b.owner.as_deref() == Some(owner.as_str()))
+            .fold((0u128, 0u128), |(pre, post), b| {
+                (pre + b.pre as u128, post + b.post as u128)
+            });
+        let received = post.saturating_sub(pre);
+        if post < pre || received != expectation.amount as u128 {
+            return Err(SssError::TokenError(format!(
+                "Transaction {} changed the balance of {} in mint {} from {} to {}, expected an \
+                 increase of {}",
+                self.signature, owner, expectation.mint, pre, post, expectation.amount
+            )));
+        }
+        Ok(())
+    }
+
+    /// Serializes the receipt to JSON
+    pub fn to_json(&self) -> SssResult<String> {
+        serde_json::to_string_pretty(self).into_sss_error("Failed to serialize transaction receipt")
+    }
+}
+
+/// Raw `getTransaction` response with a base64 encoded transaction
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawTransaction {
+    slot: u64,
+    block_time: Option<i64>,
+    /// The encoded transaction and the encoding name
+    transaction: (String, String),
+    meta: Option<RawMeta>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct RawMeta {
+    err: Option<Value>,
+    fee: u64,
+    pre_balances: Vec<u64>,
+    post_balances: Vec<u64>,
+    pre_token_balances: Vec<RawTokenBalance>,
+    post_token_balances: Vec<RawTokenBalance>,
+    loaded_addresses: Option<RawLoadedAddresses>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawTokenBalance {
+    account_index: usize,
+    mint: String,
+    owner: Option<String>,
+    ui_token_amount: RawTokenAmount,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawTokenAmount {
+    amount: String,
+    decimals: u8,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct RawLoadedAddresses {
+    writable: Vec<String>,
+    readonly: Vec<String>,
+}
+
+/// Fetches a confirmed transaction and builds its receipt
+///
+/// The transaction is fetched in binary form rather than `jsonParsed`, so its instructions
+/// are decoded by the same decoder as [`crate::inspect_message`].
+///
+/// # Arguments
+///
+/// * `signature` - The base58 transaction signature
+///
+/// # Returns
+///
+/// The receipt of the transaction, including failed transactions
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the signature is malformed, or an `RpcError` if the
+/// transaction is not found at `confirmed` commitment or cannot be decoded
+pub fn fetch_transaction_receipt(signature: &str) -> SssResult<Receipt> {
+    fetch_transaction_receipt_with_client(&RPC_CLIENT, signature)
+}
+
+/// Fetches a confirmed transaction like [`fetch_transaction_receipt`], querying the given
+/// RPC client
+///
+/// # Arguments
+///
+/// * `client` - The RPC client to query, e.g. an archival node for old transactions
+/// * `signature` - The base58 transaction signature
+///
+/// # Returns
+///
+/// The receipt of the transaction, including failed transactions
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the signature is malformed, or an `RpcError` if the
+/// transaction is not found at `confirmed` commitment or cannot be decoded
+pub fn fetch_transaction_receipt_with_client(
+    client: &RpcClient,
+    signature: &str,
+) -> SssResult<Receipt> {
+    let signature = Signature::from_str(signature)
+        .map_err(|e| SssError::TokenError(format!("Invalid transaction signature: {}", e)))?;
+    let raw: Option<RawTransaction> = client
+        .send(
+            RpcRequest::GetTransaction,
+            json!([
+                signature.to_string(),
+                {
+                    "encoding": "base64",
+                    "commitment": "confirmed",
+                    "maxSupportedTransactionVersion": 0,
+                }
+            ]),
+        )
+        .into_sss_error("Failed to get transaction from rpc")?;
+    let raw =
+        raw.ok_or_else(|| SssError::RpcError(format!("Transaction {} was not found", signature)))?;
+
+    let bytes = BASE64_STANDARD
+        .decode(&raw.transaction.0)
+        .into_sss_error("Failed to decode transaction from rpc")?;
+    let tx: VersionedTransaction =
+        bincode::deserialize(&bytes).into_sss_error("Failed to decode transaction from rpc")?;
+    let meta = raw.meta.unwrap_or_default();
+
+    let loaded = meta.loaded_addresses.unwrap_or_default();
+    let loaded: Vec<Pubkey> = loaded
+        .writable
+        .iter()
+        .chain(&loaded.readonly)
+        .map(|key| Pubkey::from_str(key))
+        .collect::<Result<_, _>>()
+        .into_sss_error("Failed to parse loaded address from rpc")?;
+    let inspection = inspect_versioned_message(&tx.message, &loaded);
+    let keys: Vec<Pubkey> = tx
+        .message
+        .static_account_keys()
+        .iter()
+        .chain(&loaded)
+        .copied()
+        .collect();
+
+    Ok(Receipt {
+        signature: signature.to_string(),
+        slot: raw.slot,
+        block_time: raw.block_time,
+        fee: meta.fee,
+        fee_payer: inspection.fee_payer,
+        success: meta.err.is_none(),
+        error: meta.err.map(|e| e.to_string()),
+        instruction_summaries: inspection
+            .instructions
+            .iter()
+            .map(|ix| ix.summary())
+            .collect(),
+        instructions: inspection.instructions,
+        token_balances: token_balance_changes(
+            &keys,
+            &meta.pre_token_balances,
+            &meta.post_token_balances,
+        )?,
+        lamport_balances: keys
+            .iter()
+            .zip(meta.pre_balances.iter().zip(&meta.post_balances))
+            .filter(|(_, (pre, post))| pre != post)
+            .map(|(account, (pre, post))| LamportBalanceChange {
+                account: *account,
+                pre: *pre,
+                post: *post,
+            })
+            .collect(),
+    })
+}
+
+/// Pairs the pre- and post-transaction balances of each token account
+fn token_balance_changes(
+    keys: &[Pubkey],
+    pre: &[RawTokenBalance],
+    post: &[RawTokenBalance],
+) -> SssResult<Vec<TokenBalanceChange>> {
+    let mut changes: BTreeMap<usize, TokenBalanceChange> = BTreeMap::new();
+    for (is_post, balance) in pre
+        .iter()
+        .map(|b| (false, b))
+        .chain(post.iter().map(|b| (true, b)))
+    {
+        let account = keys.get(balance.account_index).copied().ok_or_else(|| {
+            SssError::RpcError(format!(
+                "Token balance references unknown account index {}",
+                balance.account_index
+            ))
+        })?;
+        let amount: u64 = balance
+            .ui_token_amount
+            .amount
+            .parse()
+            .into_sss_error("Failed to parse token balance from rpc")?;
+        let change = match changes.get_mut(&balance.account_index) {
+            Some(change) => change,
+            None => changes
+                .entry(balance.account_index)
+                .or_insert(TokenBalanceChange {
+                    account,
+                    mint: Pubkey::from_str(&balance.mint)
+                        .into_sss_error("Failed to parse token mint from rpc")?,
+                    owner: balance.owner.clone(),
+                    decimals: balance.ui_token_amount.decimals,
+                    pre: 0,
+                    post: 0,
+                }),
+        };
+        if is_post {
+            change.post = amount;
+        } else {
+            change.pre = amount;
+        }
+    }
+    Ok(changes.into_values().collect())
+}
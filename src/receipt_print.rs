@@ -0,0 +1,640 @@
+//! Printable proofs of token issuance rendered from transaction receipts
+//!
+//! Retail partners hand customers a printed receipt. [`render_receipt_payload`] turns a
+//! [`Receipt`] into either plain text laid out for a receipt printer's fixed character width,
+//! ready to be sent with ESC/POS text commands, or an HTML fragment for printing from a
+//! browser. Both carry the token name and symbol, the amounts with their decimal point, the
+//! signature, the time, an explorer link and a QR payload from which the issuance can be
+//! verified.
+
+use crate::RPC_CLIENT;
+use crate::amount::base_to_ui_amount;
+use crate::cache::{BoundedCache, CacheLimits};
+use crate::display::elide_middle;
+use crate::error::{IntoSssError, SssResult};
+use crate::load_dotenv;
+use crate::mint_cache::metadata_pda;
+use crate::receipt::Receipt;
+use chrono::DateTime;
+use lazy_static::lazy_static;
+use mpl_token_metadata::accounts::Metadata;
+use solana_sdk::pubkey::Pubkey;
+use std::env;
+
+/// Characters per line of the text format when `SSS_RECEIPT_WIDTH` is not set, that of
+/// 80 mm printers with their condensed font
+pub const DEFAULT_RECEIPT_WIDTH: usize = 42;
+
+/// Explorer link when `SSS_EXPLORER_URL` is not set; `{signature}` is replaced
+const DEFAULT_EXPLORER_URL: &str = "https://explorer.solana.com/tx/{signature}";
+
+/// Prefix of the QR payload
+const QR_SCHEME: &str = "sss-receipt:v1";
+
+lazy_static! {
+    /// Names and symbols of mints from their token metadata, `None` for mints without one
+    pub(crate) static ref TOKEN_LABELS: BoundedCache<Pubkey, Option<TokenLabel>> =
+        BoundedCache::new("token_labels", CacheLimits::default(), |_, label| {
+            label
+                .as_ref()
+                .map_or(0, |label| label.name.capacity() + label.symbol.capacity())
+        });
+}
+
+/// Layout a receipt is rendered in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReceiptFormat {
+    /// Plain text lines of [`ReceiptTemplate::line_width`] characters, for receipt printers
+    #[default]
+    Text,
+    /// An HTML fragment with `sss-receipt` classes, for printing from a browser
+    Html,
+}
+
+/// How [`render_receipt_payload`] lays out a receipt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceiptTemplate {
+    /// The layout
+    pub format: ReceiptFormat,
+    /// Lines printed above the receipt, e.g. the store name and address
+    pub header_lines: Vec<String>,
+    /// Lines printed below the receipt, e.g. a thank-you note
+    pub footer_lines: Vec<String>,
+    /// Characters per line of the text format
+    pub line_width: usize,
+    /// The explorer link, with `{signature}` replaced by the transaction signature
+    pub explorer_url: String,
+}
+
+impl Default for ReceiptTemplate {
+    /// Text without header or footer, linking to the public Solana explorer
+    fn default() -> Self {
+        Self {
+            format: ReceiptFormat::Text,
+            header_lines: Vec::new(),
+            footer_lines: Vec::new(),
+            line_width: DEFAULT_RECEIPT_WIDTH,
+            explorer_url: DEFAULT_EXPLORER_URL.to_string(),
+        }
+    }
+}
+
+impl ReceiptTemplate {
+    /// Builds a template for a format from environment variables
+    ///
+    /// `SSS_RECEIPT_HEADER` and `SSS_RECEIPT_FOOTER` hold the header and footer lines,
+    /// separated by `|`, and `SSS_RECEIPT_WIDTH` the characters per line. `SSS_EXPLORER_URL`
+    /// overrides the explorer link; without it, the link names the cluster when
+    /// `SOLANA_RPC_URL` points at devnet or testnet.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The layout
+    pub fn from_env(format: ReceiptFormat) -> Self {
+        load_dotenv();
+        let lines = |name: &str| {
+            env::var(name)
+                .ok()
+                .filter(|value| !value.is_empty())
+                .map(|value| {
+                    value
+                        .split('|')
+                        .map(|line| line.trim().to_string())
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let explorer_url = env::var("SSS_EXPLORER_URL").unwrap_or_else(|_| {
+            let rpc_url = env::var("SOLANA_RPC_URL").unwrap_or_default();
+            match ["devnet", "testnet"]
+                .into_iter()
+                .find(|cluster| rpc_url.contains(cluster))
+            {
+                Some(cluster) => format!("{}?cluster={}", DEFAULT_EXPLORER_URL, cluster),
+                None => DEFAULT_EXPLORER_URL.to_string(),
+            }
+        });
+        Self {
+            format,
+            header_lines: lines("SSS_RECEIPT_HEADER"),
+            footer_lines: lines("SSS_RECEIPT_FOOTER"),
+            line_width: env::var("SSS_RECEIPT_WIDTH")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|width| *width > 0)
+                .unwrap_or(DEFAULT_RECEIPT_WIDTH),
+            explorer_url,
+        }
+    }
+}
+
+/// The on-chain name and symbol of a mint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TokenLabel {
+    name: String,
+    symbol: String,
+}
+
+/// A token balance change printed on a receipt
+struct LineItem {
+    /// The token name, with its symbol if it has one
+    label: String,
+    symbol: String,
+    /// The signed change in whole tokens
+    amount: String,
+    owner: Option<String>,
+}
+
+/// Renders a printable proof of a transaction from its receipt
+///
+/// Every token balance the transaction changed is a line item, increases first being
+/// issuance, with the token's name and symbol from its metadata and the change in whole
+/// tokens. The QR payload is `sss-receipt:v1?signature=<sig>`, followed for a transaction
+/// that increased a balance by `&mint=<mint>&owner=<wallet>&amount=<base units>` of the
+/// first increase, exactly what [`Receipt::verify_expectation`] checks; the text format
+/// prints it on a final line starting with `QR:` for the printer driver to encode, and the
+/// HTML format puts it in the `data-qr` attribute of an empty `sss-receipt-qr` element.
+///
+/// # Arguments
+///
+/// * `receipt` - The receipt, e.g. from [`crate::fetch_transaction_receipt`]
+/// * `template` - The layout, header and footer
+///
+/// # Returns
+///
+/// The text or HTML to print
+///
+/// # Errors
+///
+/// Returns an `RpcError` if the token metadata cannot be read
+pub fn render_receipt_payload(receipt: &Receipt, template: ReceiptTemplate) -> SssResult<String> {
+    let items = line_items(receipt)?;
+    let timestamp = receipt
+        .block_time
+        .and_then(|time| DateTime::from_timestamp(time, 0))
+        .map_or_else(
+            || "unknown".to_string(),
+            |time| time.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+        );
+    let explorer_url = template
+        .explorer_url
+        .replace("{signature}", &receipt.signature);
+    let qr = qr_payload(receipt);
+    Ok(match template.format {
+        ReceiptFormat::Text => {
+            render_text(receipt, &template, &items, &timestamp, &explorer_url, &qr)
+        }
+        ReceiptFormat::Html => {
+            render_html(receipt, &template, &items, &timestamp, &explorer_url, &qr)
+        }
+    })
+}
+
+/// Lists the token balances the transaction changed, increases first
+fn line_items(receipt: &Receipt) -> SssResult<Vec<LineItem>> {
+    let mut changes: Vec<_> = receipt
+        .token_balances
+        .iter()
+        .filter(|balance| balance.pre != balance.post)
+        .collect();
+    changes.sort_by_key(|balance| balance.post < balance.pre);
+    let mints: Vec<Pubkey> = changes.iter().map(|balance| balance.mint).collect();
+    let labels = token_labels(&mints)?;
+
+    Ok(changes
+        .into_iter()
+        .map(|balance| {
+            let label = labels
+                .iter()
+                .find(|(mint, _)| *mint == balance.mint)
+                .and_then(|(_, label)| label.as_ref());
+            let (label, symbol) = match label {
+                Some(label) if !label.symbol.is_empty() => (
+                    format!("{} ({})", label.name, label.symbol),
+                    label.symbol.clone(),
+                ),
+                Some(label) if !label.name.is_empty() => (label.name.clone(), String::new()),
+                _ => (balance.mint.to_string(), String::new()),
+            };
+            let amount = if balance.post >= balance.pre {
+                format!(
+                    "+{}",
+                    base_to_ui_amount(balance.post - balance.pre, balance.decimals)
+                )
+            } else {
+                format!(
+                    "-{}",
+                    base_to_ui_amount(balance.pre - balance.post, balance.decimals)
+                )
+            };
+            LineItem {
+                label,
+                symbol,
+                amount,
+                owner: balance.owner.clone(),
+            }
+        })
+        .collect())
+}
+
+/// Returns the names and symbols of mints, reading the metadata of those not cached yet
+fn token_labels(mints: &[Pubkey]) -> SssResult<Vec<(Pubkey, Option<TokenLabel>)>> {
+    let mut labels = Vec::with_capacity(mints.len());
+    let mut missing = Vec::new();
+    for mint in mints {
+        if labels.iter().any(|(known, _)| known == mint) || missing.contains(mint) {
+            continue;
+        }
+        match TOKEN_LABELS.get(mint) {
+            Some(label) => labels.push((*mint, label)),
+            None => missing.push(*mint),
+        }
+    }
+    if missing.is_empty() {
+        return Ok(labels);
+    }
+
+    let pdas: Vec<Pubkey> = missing.iter().map(metadata_pda).collect();
+    let accounts = RPC_CLIENT
+        .get_multiple_accounts(&pdas)
+        .into_sss_error("Failed to get token metadata accounts from rpc")?;
+    for (mint, account) in missing.into_iter().zip(accounts) {
+        let label = account
+            .and_then(|account| Metadata::from_bytes(&account.data).ok())
+            .map(|metadata| TokenLabel {
+                name: metadata.name.trim_end_matches('\0').trim().to_string(),
+                symbol: metadata.symbol.trim_end_matches('\0').trim().to_string(),
+            });
+        TOKEN_LABELS.insert(mint, label.clone());
+        labels.push((mint, label));
+    }
+    Ok(labels)
+}
+
+/// Builds the QR payload from which the issuance can be verified
+fn qr_payload(receipt: &Receipt) -> String {
+    let mut payload = format!("{}?signature={}", QR_SCHEME, receipt.signature);
+    if let Some(balance) = receipt
+        .token_balances
+        .iter()
+        .find(|balance| balance.post > balance.pre)
+    {
+        payload.push_str(&format!("&mint={}", balance.mint));
+        if let Some(owner) = &balance.owner {
+            payload.push_str(&format!("&owner={}", owner));
+        }
+        payload.push_str(&format!("&amount={}", balance.post - balance.pre));
+    }
+    payload
+}
+
+fn render_text(
+    receipt: &Receipt,
+    template: &ReceiptTemplate,
+    items: &[LineItem],
+    timestamp: &str,
+    explorer_url: &str,
+    qr: &str,
+) -> String {
+    let width = template.line_width;
+    let mut lines: Vec<String> = Vec::new();
+    let center = |text: &str| {
+        let len = text.chars().count();
+        format!("{}{}", " ".repeat(width.saturating_sub(len) / 2), text)
+    };
+    for line in &template.header_lines {
+        lines.extend(wrap(line, width).iter().map(|part| center(part)));
+    }
+    lines.push("=".repeat(width));
+    lines.push(center("TOKEN RECEIPT"));
+    lines.push("-".repeat(width));
+    if items.is_empty() {
+        lines.push("No token balance changed".to_string());
+    }
+    for item in items {
+        let amount = if item.symbol.is_empty() {
+            item.amount.clone()
+        } else {
+            format!("{} {}", item.amount, item.symbol)
+        };
+        let label_width = width.saturating_sub(amount.chars().count() + 1).max(1);
+        // A mint without metadata is labelled by its address, shortened rather than cut
+        let label = if item.label.contains(' ') {
+            item.label.clone()
+        } else {
+            shorten(&item.label, label_width)
+        };
+        let mut label_lines = wrap(&label, label_width);
+        let first = label_lines.remove(0);
+        let padding = width.saturating_sub(first.chars().count() + amount.chars().count());
+        lines.push(format!("{}{}{}", first, " ".repeat(padding.max(1)), amount));
+        lines.extend(label_lines);
+        if let Some(owner) = &item.owner {
+            lines.push(format!("  to {}", shorten(owner, width.saturating_sub(5))));
+        }
+    }
+    lines.push("-".repeat(width));
+    if !receipt.success {
+        let error = receipt.error.as_deref().unwrap_or("unknown error");
+        lines.extend(wrap(&format!("FAILED: {}", error), width));
+    }
+    lines.extend(wrap(&format!("Date: {}", timestamp), width));
+    lines.push("Signature:".to_string());
+    lines.extend(wrap(&receipt.signature, width));
+    lines.push("Verify at:".to_string());
+    lines.extend(wrap(explorer_url, width));
+    lines.push("=".repeat(width));
+    for line in &template.footer_lines {
+        lines.extend(wrap(line, width).iter().map(|part| center(part)));
+    }
+    lines.push(format!("QR:{}", qr));
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
+
+fn render_html(
+    receipt: &Receipt,
+    template: &ReceiptTemplate,
+    items: &[LineItem],
+    timestamp: &str,
+    explorer_url: &str,
+    qr: &str,
+) -> String {
+    let mut html = String::from("<div class=\"sss-receipt\">\n");
+    for line in &template.header_lines {
+        html.push_str(&format!(
+            "<p class=\"sss-receipt-header\">{}</p>\n",
+            escape_html(line)
+        ));
+    }
+    html.push_str("<h1>Token receipt</h1>\n<table class=\"sss-receipt-items\">\n");
+    for item in items {
+        let amount = if item.symbol.is_empty() {
+            item.amount.clone()
+        } else {
+            format!("{} {}", item.amount, item.symbol)
+        };
+        let owner = item.owner.as_ref().map_or_else(String::new, |owner| {
+            format!("<br><small>to {}</small>", escape_html(owner))
+        });
+        html.push_str(&format!(
+            "<tr><td>{}{}</td><td class=\"sss-receipt-amount\">{}</td></tr>\n",
+            escape_html(&item.label),
+            owner,
+            escape_html(&amount)
+        ));
+    }
+    html.push_str("</table>\n<dl>\n");
+    if !receipt.success {
+        html.push_str(&format!(
+            "<dt>Failed</dt><dd>{}</dd>\n",
+            escape_html(receipt.error.as_deref().unwrap_or("unknown error"))
+        ));
+    }
+    html.push_str(&format!(
+        "<dt>Date</dt><dd>{}</dd>\n<dt>Signature</dt><dd><code>{}</code></dd>\n\
+         <dt>Verify at</dt><dd><a href=\"{url}\">{url}</a></dd>\n</dl>\n",
+        escape_html(timestamp),
+        escape_html(&receipt.signature),
+        url = escape_html(explorer_url)
+    ));
+    html.push_str(&format!(
+        "<div class=\"sss-receipt-qr\" data-qr=\"{}\"></div>\n",
+        escape_html(qr)
+    ));
+    for line in &template.footer_lines {
+        html.push_str(&format!(
+            "<p class=\"sss-receipt-footer\">{}</p>\n",
+            escape_html(line)
+        ));
+    }
+    html.push_str("</div>\n");
+    html
+}
+
+/// Splits text into lines of at most `width` characters, at spaces where possible
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split(' ') {
+        let mut word: Vec<char> = word.chars().collect();
+        let line_len = line.chars().count();
+        if line_len > 0 && line_len + 1 + word.len() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        // Words longer than a line, such as signatures and URLs, are cut
+        while line.chars().count() + word.len() > width {
+            let take = width - line.chars().count();
+            line.extend(word.drain(..take));
+            lines.push(std::mem::take(&mut line));
+        }
+        line.extend(word);
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Shortens text longer than `width` characters by eliding its middle
+fn shorten(text: &str, width: usize) -> String {
+    if width < 5 {
+        return text.to_string();
+    }
+    let keep = width - 3;
+    elide_middle(text, keep.div_ceil(2), keep / 2, "...")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::receipt::TokenBalanceChange;
+    use solana_sdk::signature::keypair_from_seed;
+    use solana_sdk::signer::Signer;
+    use std::fs;
+    use std::path::Path;
+
+    const FIXTURE_DIR: &str = "fixtures/receipts";
+
+    fn fixed_pubkey(byte: u8) -> Pubkey {
+        keypair_from_seed(&[byte; 32]).unwrap().pubkey()
+    }
+
+    /// A receipt issuing 1.5 tokens of a labelled mint and moving 0.25 of another, whose
+    /// labels are cached so no metadata is fetched
+    fn issuance() -> Receipt {
+        let gold = fixed_pubkey(41);
+        let voucher = fixed_pubkey(42);
+        let unlabelled = fixed_pubkey(43);
+        TOKEN_LABELS.insert(
+            gold,
+            Some(TokenLabel {
+                name: "Golden Token".to_string(),
+                symbol: "GLD".to_string(),
+            }),
+        );
+        TOKEN_LABELS.insert(
+            voucher,
+            Some(TokenLabel {
+                name: "Café <Voucher> & Co".to_string(),
+                symbol: String::new(),
+            }),
+        );
+        TOKEN_LABELS.insert(unlabelled, None);
+        let change = |mint, owner: u8, decimals, pre, post| TokenBalanceChange {
+            account: fixed_pubkey(owner + 100),
+            mint,
+            owner: Some(fixed_pubkey(owner).to_string()),
+            decimals,
+            pre,
+            post,
+        };
+        Receipt {
+            signature: "5VERv8NMvzbJMEkV8xnrLkEaWRtSz9CosKDYjCJjBRnbJLgp8uirBgmQpjKhoR4tjF3ZpRzrFmBV6UjKdiSZkQUW".to_string(),
+            slot: 312_457_981,
+            block_time: Some(1_760_535_025),
+            fee: 5000,
+            fee_payer: fixed_pubkey(1),
+            success: true,
+            error: None,
+            instructions: Vec::new(),
+            instruction_summaries: Vec::new(),
+            token_balances: vec![
+                change(voucher, 3, 2, 100, 75),
+                change(gold, 3, 6, 0, 1_500_000),
+                change(unlabelled, 4, 0, 7, 9),
+                // Unchanged balances are not printed
+                change(gold, 5, 6, 10, 10),
+            ],
+            lamport_balances: Vec::new(),
+        }
+    }
+
+    fn store_template(format: ReceiptFormat) -> ReceiptTemplate {
+        ReceiptTemplate {
+            format,
+            header_lines: vec![
+                "Corner Store".to_string(),
+                "12 Long Street, Springfield & Shelbyville".to_string(),
+            ],
+            footer_lines: vec!["Thank you!".to_string()],
+            line_width: 32,
+            explorer_url: "https://explorer.solana.com/tx/{signature}?cluster=devnet".to_string(),
+        }
+    }
+
+    /// Compares each rendering with its fixture, or rewrites the fixtures with
+    /// `SSS_UPDATE_GOLDEN=1`
+    fn check_snapshots(snapshots: Vec<(&str, String)>) {
+        let update = env::var("SSS_UPDATE_GOLDEN").is_ok_and(|v| v == "1" || v == "true");
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(FIXTURE_DIR);
+        let mut mismatches = Vec::new();
+        for (file, actual) in snapshots {
+            let path = dir.join(file);
+            if update {
+                fs::create_dir_all(&dir).unwrap();
+                fs::write(&path, &actual).unwrap();
+                continue;
+            }
+            match fs::read_to_string(&path) {
+                Ok(expected) if expected == actual => {}
+                Ok(expected) => mismatches.push(format!(
+                    "CHANGED {}\n--- expected\n{}--- actual\n{}",
+                    file, expected, actual
+                )),
+                Err(_) => mismatches.push(format!("MISSING {}", file)),
+            }
+        }
+        assert!(
+            mismatches.is_empty(),
+            "{}\nIf the change is intended, rerun with SSS_UPDATE_GOLDEN=1 and review the diff",
+            mismatches.join("\n")
+        );
+    }
+
+    #[test]
+    fn each_template_renders_its_snapshot() {
+        let receipt = issuance();
+        let mut failed = issuance();
+        failed.success = false;
+        failed.error = Some("custom program error: 0x1".to_string());
+        failed.block_time = None;
+        failed.token_balances.clear();
+
+        let render =
+            |receipt: &Receipt, template| render_receipt_payload(receipt, template).unwrap();
+        check_snapshots(vec![
+            ("text.txt", render(&receipt, ReceiptTemplate::default())),
+            (
+                "text_store.txt",
+                render(&receipt, store_template(ReceiptFormat::Text)),
+            ),
+            (
+                "html.html",
+                render(
+                    &receipt,
+                    ReceiptTemplate {
+                        format: ReceiptFormat::Html,
+                        ..ReceiptTemplate::default()
+                    },
+                ),
+            ),
+            (
+                "html_store.html",
+                render(&receipt, store_template(ReceiptFormat::Html)),
+            ),
+            ("failed.txt", render(&failed, ReceiptTemplate::default())),
+            (
+                "failed.html",
+                render(&failed, store_template(ReceiptFormat::Html)),
+            ),
+        ]);
+    }
+
+    #[test]
+    fn text_lines_fit_the_width() {
+        let receipt = issuance();
+        for width in [16, 32, 42, 48] {
+            let template = ReceiptTemplate {
+                line_width: width,
+                ..store_template(ReceiptFormat::Text)
+            };
+            let text = render_receipt_payload(&receipt, template).unwrap();
+            for line in text.lines().filter(|line| !line.starts_with("QR:")) {
+                assert!(line.chars().count() <= width, "{width}: {line:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn qr_payload_names_the_first_issuance() {
+        let receipt = issuance();
+        assert_eq!(
+            qr_payload(&receipt),
+            format!(
+                "sss-receipt:v1?signature={}&mint={}&owner={}&amount=1500000",
+                receipt.signature,
+                fixed_pubkey(41),
+                fixed_pubkey(3)
+            )
+        );
+        let text = render_receipt_payload(&receipt, ReceiptTemplate::default()).unwrap();
+        assert_eq!(
+            text.lines().last(),
+            Some(format!("QR:{}", qr_payload(&receipt)).as_str())
+        );
+    }
+}
@@ -0,0 +1,150 @@
+//! Recovery of transactions left unresolved by a restart
+//!
+//! Every journaled transaction is recorded as `Sent` before it is submitted, so a process
+//! that dies between sending and confirmation leaves the signature in the journal.
+//! [`recover_in_flight`], called at startup, looks each of them up, records the outcome and
+//! emits an event per resolved transaction, so the host can reconcile its orders.
+
+use crate::RPC_CLIENT;
+use crate::error::{IntoSssError, SssResult};
+use crate::events::{SssEvent, emit};
+use crate::journal::{JournalEntry, JournalStatus, append_entry, read_journal, unix_now};
+use crate::receipt::{Receipt, fetch_transaction_receipt_with_client};
+use serde::Serialize;
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_sdk::{commitment_config::CommitmentConfig, hash::Hash, signature::Signature};
+use std::str::FromStr;
+
+/// What recovery found out about a journaled transaction, see [`recover_in_flight`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RecoveryOutcome {
+    /// The journal entry in its current state
+    ///
+    /// `Confirmed` or `Failed` if the transaction landed, `Expired` if it never did,
+    /// `Unknown` if it was not found but may have landed before the history of the RPC
+    /// node starts, and still `Sent` while its blockhash is valid.
+    pub entry: JournalEntry,
+    /// The decoded transaction, if it landed and could be fetched
+    pub receipt: Option<Receipt>,
+}
+
+/// Resolves the transactions an earlier run sent but never saw confirmed
+///
+/// Every journal entry still `Sent`, or `Unknown` from an earlier recovery, is looked up
+/// in the transaction history. A transaction found is recorded as `Confirmed` or `Failed`
+/// and decoded into a receipt. One not found whose blockhash expired is recorded as
+/// `Expired` if the node's history reaches back to when it was sent, since it can no longer
+/// land; otherwise it may have landed before the history starts and is recorded as
+/// `Unknown` for a manual review rather than guessed. Each entry changing state emits an
+/// [`SssEvent::InFlightRecovered`].
+///
+/// # Returns
+///
+/// One outcome per entry looked up, in the order the transactions were sent
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if `SSS_JOURNAL_PATH` is not set or the journal cannot be read
+/// or written, and an `RpcError` if the chain cannot be queried
+pub fn recover_in_flight() -> SssResult<Vec<RecoveryOutcome>> {
+    recover_in_flight_with_client(&RPC_CLIENT)
+}
+
+/// Resolves unresolved journal entries like [`recover_in_flight`], querying the given RPC
+/// client
+///
+/// # Arguments
+///
+/// * `client` - The RPC client to query, e.g. an archival node to resolve `Unknown`
+///   entries
+///
+/// # Returns
+///
+/// One outcome per entry looked up, in the order the transactions were sent
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if `SSS_JOURNAL_PATH` is not set or the journal cannot be read
+/// or written, and an `RpcError` if the chain cannot be queried
+pub fn recover_in_flight_with_client(client: &RpcClient) -> SssResult<Vec<RecoveryOutcome>> {
+    // Unix time of the oldest block the node holds, looked up once when first needed
+    let mut history_start: Option<Option<i64>> = None;
+    let mut outcomes = Vec::new();
+    for mut entry in read_journal()? {
+        if !matches!(entry.status, JournalStatus::Sent | JournalStatus::Unknown) {
+            continue;
+        }
+        let signature = Signature::from_str(&entry.signature)
+            .into_sss_error("Failed to parse signature in journal config")?;
+        let status = client
+            .get_signature_status_with_commitment_and_history(
+                &signature,
+                CommitmentConfig::confirmed(),
+                true,
+            )
+            .into_sss_error("Failed to get signature status from rpc")?;
+
+        let (new_status, error) = match status {
+            Some(Ok(())) => (JournalStatus::Confirmed, None),
+            Some(Err(e)) => (
+                JournalStatus::Failed,
+                Some(format!("Transaction failed: {}", e)),
+            ),
+            None => {
+                let blockhash = Hash::from_str(&entry.blockhash)
+                    .into_sss_error("Failed to parse blockhash in journal config")?;
+                let still_valid = client
+                    .is_blockhash_valid(&blockhash, CommitmentConfig::processed())
+                    .into_sss_error("Failed to check blockhash validity with rpc")?;
+                if still_valid {
+                    (JournalStatus::Sent, entry.error.clone())
+                } else if history_start
+                    .get_or_insert_with(|| oldest_block_time(client))
+                    .is_some_and(|start| entry.created_at as i64 >= start)
+                {
+                    (
+                        JournalStatus::Expired,
+                        Some("Transaction expired before landing".to_string()),
+                    )
+                } else {
+                    (
+                        JournalStatus::Unknown,
+                        Some(
+                            "Transaction not found and sent before the history of the RPC \
+                             node starts; needs a manual review"
+                                .to_string(),
+                        ),
+                    )
+                }
+            }
+        };
+
+        let receipt = match new_status {
+            JournalStatus::Confirmed | JournalStatus::Failed => {
+                fetch_transaction_receipt_with_client(client, &entry.signature).ok()
+            }
+            _ => None,
+        };
+        if new_status != entry.status {
+            entry.status = new_status;
+            entry.error = error;
+            entry.updated_at = unix_now();
+            append_entry(&entry)?;
+            emit(SssEvent::InFlightRecovered {
+                signature: entry.signature.clone(),
+                operation: entry.operation,
+                idempotency_key: entry.idempotency_key.clone(),
+                status: entry.status,
+                error: entry.error.clone(),
+            });
+        }
+        outcomes.push(RecoveryOutcome { entry, receipt });
+    }
+    Ok(outcomes)
+}
+
+/// Returns the Unix time of the oldest block the node still holds, if it can tell
+fn oldest_block_time(client: &RpcClient) -> Option<i64> {
+    let slot = client.get_first_available_block().ok()?;
+    client.get_block_time(slot).ok()
+}
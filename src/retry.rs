@@ -0,0 +1,460 @@
+//! Classification of transient RPC errors and backoff between retries
+
+use rand::Rng;
+use solana_rpc_client_api::client_error::{Error as ClientError, ErrorKind as ClientErrorKind};
+use solana_rpc_client_api::request::RpcError;
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A kind of RPC error that is expected to go away if the request is repeated
+///
+/// A rule matches when the JSON-RPC error code, if given, is equal and the error text, if
+/// given, is contained in the error message. Add rules for newly captured payloads to
+/// [`RETRYABLE_ERRORS`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryRule {
+    /// JSON-RPC error code the error must carry
+    pub code: Option<i64>,
+    /// Text the error message must contain
+    pub text: Option<&'static str>,
+    /// Why the error is transient, shown in the retry trail
+    pub reason: &'static str,
+}
+
+/// Errors retried when sending transactions
+pub const RETRYABLE_ERRORS: &[RetryRule] = &[
+    // "Node is behind by 153 slots" and "Node is unhealthy"
+    RetryRule {
+        code: Some(-32005),
+        text: None,
+        reason: "node is unhealthy",
+    },
+    // Some providers wrap the health check in another code or in the preflight failure
+    RetryRule {
+        code: None,
+        text: Some("Node is behind by"),
+        reason: "node is behind",
+    },
+    RetryRule {
+        code: Some(-32016),
+        text: None,
+        reason: "minimum context slot not reached",
+    },
+    // Preflight ran on a node that has not yet seen the fee payer being funded
+    RetryRule {
+        code: Some(-32002),
+        text: Some("found no record of a prior credit"),
+        reason: "preflight account not found",
+    },
+    RetryRule {
+        code: None,
+        text: Some("429 Too Many Requests"),
+        reason: "rate limited",
+    },
+    RetryRule {
+        code: None,
+        text: Some("502 Bad Gateway"),
+        reason: "gateway error",
+    },
+    RetryRule {
+        code: None,
+        text: Some("503 Service Unavailable"),
+        reason: "service unavailable",
+    },
+    RetryRule {
+        code: None,
+        text: Some("504 Gateway Timeout"),
+        reason: "gateway timeout",
+    },
+];
+
+/// Returns the rule under which an RPC error is retried, if it is transient
+///
+/// # Arguments
+///
+/// * `code` - The JSON-RPC error code, if the node answered with one
+/// * `message` - The error message
+pub fn classify_rpc_error(code: Option<i64>, message: &str) -> Option<&'static RetryRule> {
+    RETRYABLE_ERRORS.iter().find(|rule| {
+        rule.code.is_none_or(|expected| code == Some(expected))
+            && rule.text.is_none_or(|text| message.contains(text))
+    })
+}
+
+/// Classifies a client error, see [`classify_rpc_error`]
+pub(crate) fn classify_client_error(e: &ClientError) -> Option<&'static RetryRule> {
+    let code = match e.kind() {
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. }) => Some(*code),
+        _ => None,
+    };
+    classify_rpc_error(code, &e.to_string())
+}
+
+/// How long transient RPC errors are retried
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Delay before the first retry
+    pub initial_delay: Duration,
+    /// Longest delay between two attempts; the delay doubles until it reaches this
+    pub max_delay: Duration,
+    /// Time after the first attempt past which no retry is started
+    pub max_elapsed: Duration,
+}
+
+impl RetryPolicy {
+    /// A policy that never retries
+    pub const NONE: Self = Self {
+        initial_delay: Duration::ZERO,
+        max_delay: Duration::ZERO,
+        max_elapsed: Duration::ZERO,
+    };
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(250),
+            max_delay: Duration::from_secs(4),
+            max_elapsed: Duration::from_secs(30),
+        }
+    }
+}
+
+/// A failed attempt of a retried request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct FailedAttempt {
+    /// The error of the attempt
+    pub error: String,
+    /// Why the error was retried
+    pub reason: &'static str,
+    /// The delay before the next attempt
+    pub delay: Duration,
+}
+
+/// The failed attempts of a request, in order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct RetryTrail(pub Vec<FailedAttempt>);
+
+impl fmt::Display for RetryTrail {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, attempt) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(
+                f,
+                "attempt {} failed ({}): {}, retried after {}ms",
+                i + 1,
+                attempt.reason,
+                attempt.error,
+                attempt.delay.as_millis()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Repeats a request while it fails with a retryable error
+///
+/// Each delay is drawn at random between half and all of the current backoff, so clients
+/// rejected together do not retry together.
+///
+/// # Arguments
+///
+/// * `policy` - The backoff and the time limit
+/// * `retryable` - Why an error is worth another attempt, or `None` if it is not
+/// * `on_retry` - Called with the error and its trail entry before sleeping for each retry
+/// * `f` - The request
+///
+/// # Returns
+///
+/// The result of the last attempt and the trail of the attempts before it
+pub(crate) fn with_backoff<T, E: fmt::Display>(
+    policy: &RetryPolicy,
+    retryable: impl Fn(&E) -> Option<&'static str>,
+    mut on_retry: impl FnMut(&E, &FailedAttempt),
+    mut f: impl FnMut() -> Result<T, E>,
+) -> (Result<T, E>, RetryTrail) {
+    let start = Instant::now();
+    let mut backoff = policy.initial_delay;
+    let mut trail = RetryTrail::default();
+    loop {
+        let e = match f() {
+            Ok(value) => return (Ok(value), trail),
+            Err(e) => e,
+        };
+        let delay = jittered(backoff);
+        let Some(reason) = retryable(&e) else {
+            return (Err(e), trail);
+        };
+        if start.elapsed() + delay > policy.max_elapsed {
+            return (Err(e), trail);
+        }
+
+        let attempt = FailedAttempt {
+            error: e.to_string(),
+            reason,
+            delay,
+        };
+        on_retry(&e, &attempt);
+        trail.0.push(attempt);
+        thread::sleep(delay);
+        backoff = (backoff * 2).min(policy.max_delay);
+    }
+}
+
+/// Picks a delay between half and all of the backoff
+fn jittered(backoff: Duration) -> Duration {
+    if backoff.is_zero() {
+        return backoff;
+    }
+    let half = backoff / 2;
+    half + half.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+    use solana_rpc_client_api::custom_error::{
+        JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY,
+        JSON_RPC_SERVER_ERROR_SEND_TRANSACTION_PREFLIGHT_FAILURE, NodeUnhealthyErrorData,
+    };
+    use solana_rpc_client_api::error_object::RpcErrorObject;
+    use solana_rpc_client_api::request::RpcResponseErrorData;
+
+    /// Decodes a JSON-RPC error response into the error the HTTP client returns for it
+    fn client_error(response: &Value) -> ClientError {
+        let error = &response["error"];
+        let object: RpcErrorObject = serde_json::from_value(error.clone()).unwrap();
+        let data = match object.code {
+            JSON_RPC_SERVER_ERROR_SEND_TRANSACTION_PREFLIGHT_FAILURE => {
+                serde_json::from_value(error["data"].clone())
+                    .map(RpcResponseErrorData::SendTransactionPreflightFailure)
+                    .unwrap_or(RpcResponseErrorData::Empty)
+            }
+            JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY => {
+                serde_json::from_value::<NodeUnhealthyErrorData>(error["data"].clone())
+                    .map(|data| RpcResponseErrorData::NodeUnhealthy {
+                        num_slots_behind: data.num_slots_behind,
+                    })
+                    .unwrap_or(RpcResponseErrorData::Empty)
+            }
+            _ => RpcResponseErrorData::Empty,
+        };
+        RpcError::RpcResponseError {
+            code: object.code,
+            message: object.message,
+            data,
+        }
+        .into()
+    }
+
+    fn preflight_failure(message: &str, err: Value, logs: &[&str]) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "error": {
+                "code": -32002,
+                "message": message,
+                "data": {
+                    "accounts": null,
+                    "err": err,
+                    "innerInstructions": null,
+                    "logs": logs,
+                    "replacementBlockhash": null,
+                    "returnData": null,
+                    "unitsConsumed": 0,
+                },
+            },
+        })
+    }
+
+    #[test]
+    fn json_rpc_error_payloads_are_classified() {
+        let table: Vec<(&str, Value, Option<&str>)> = vec![
+            (
+                "node behind, with the number of slots",
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": 1,
+                    "error": {
+                        "code": -32005,
+                        "message": "Node is behind by 153 slots",
+                        "data": { "numSlotsBehind": 153 },
+                    },
+                }),
+                Some("node is unhealthy"),
+            ),
+            (
+                "node unhealthy, without data",
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": 1,
+                    "error": { "code": -32005, "message": "Node is unhealthy", "data": {} },
+                }),
+                Some("node is unhealthy"),
+            ),
+            (
+                "node behind, wrapped by a provider as an internal error",
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": 1,
+                    "error": {
+                        "code": -32603,
+                        "message": "Internal error: Node is behind by 42 slots",
+                    },
+                }),
+                Some("node is behind"),
+            ),
+            (
+                "minimum context slot",
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": 1,
+                    "error": {
+                        "code": -32016,
+                        "message": "Minimum context slot has not been reached",
+                        "data": { "contextSlot": 311_442_867 },
+                    },
+                }),
+                Some("minimum context slot not reached"),
+            ),
+            (
+                "preflight on a node that has not seen the payer funded",
+                preflight_failure(
+                    "Transaction simulation failed: Attempt to debit an account but found no \
+                     record of a prior credit.",
+                    json!("AccountNotFound"),
+                    &[],
+                ),
+                Some("preflight account not found"),
+            ),
+            (
+                "preflight rejected by the program",
+                preflight_failure(
+                    "Transaction simulation failed: Error processing Instruction 0: custom \
+                     program error: 0x1",
+                    json!({ "InstructionError": [0, { "Custom": 1 }] }),
+                    &[
+                        "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA invoke [1]",
+                        "Program log: Instruction: MintTo",
+                        "Program log: Error: insufficient funds",
+                        "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA failed: custom \
+                         program error: 0x1",
+                    ],
+                ),
+                None,
+            ),
+            (
+                "preflight with an expired blockhash, which is re-signed instead",
+                preflight_failure(
+                    "Transaction simulation failed: Blockhash not found",
+                    json!("BlockhashNotFound"),
+                    &[],
+                ),
+                None,
+            ),
+            (
+                "payer without funds",
+                preflight_failure(
+                    "Transaction simulation failed: Insufficient funds for fee",
+                    json!("InsufficientFundsForFee"),
+                    &[],
+                ),
+                None,
+            ),
+            (
+                "bad signature",
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": 1,
+                    "error": {
+                        "code": -32003,
+                        "message": "Transaction signature verification failure",
+                    },
+                }),
+                None,
+            ),
+            (
+                "malformed request",
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": 1,
+                    "error": { "code": -32602, "message": "Invalid param: WrongSize" },
+                }),
+                None,
+            ),
+        ];
+        for (case, response, expected) in table {
+            let error = client_error(&response);
+            assert_eq!(
+                classify_client_error(&error).map(|rule| rule.reason),
+                expected,
+                "{}: {}",
+                case,
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn http_status_errors_are_classified() {
+        let table = [
+            (
+                "HTTP status client error (429 Too Many Requests) for url \
+                 (https://api.devnet.solana.com/)",
+                Some("rate limited"),
+            ),
+            (
+                "HTTP status server error (502 Bad Gateway) for url \
+                 (https://api.devnet.solana.com/)",
+                Some("gateway error"),
+            ),
+            (
+                "HTTP status server error (503 Service Unavailable) for url \
+                 (https://devnet.helius-rpc.com/?api-key=recorded)",
+                Some("service unavailable"),
+            ),
+            (
+                "HTTP status server error (504 Gateway Timeout) for url \
+                 (https://api.devnet.solana.com/)",
+                Some("gateway timeout"),
+            ),
+            (
+                "HTTP status client error (401 Unauthorized) for url \
+                 (https://devnet.helius-rpc.com/?api-key=recorded)",
+                None,
+            ),
+            (
+                "HTTP status client error (413 Payload Too Large) for url \
+                 (https://api.devnet.solana.com/)",
+                None,
+            ),
+            (
+                "error sending request for url (https://api.devnet.solana.com/): operation timed \
+                 out",
+                None,
+            ),
+        ];
+        for (message, expected) in table {
+            assert_eq!(
+                classify_rpc_error(None, message).map(|rule| rule.reason),
+                expected,
+                "{}",
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn a_rule_with_a_code_and_a_text_needs_both() {
+        let message = "Transaction simulation failed: Attempt to debit an account but found no \
+                       record of a prior credit.";
+        assert!(classify_rpc_error(Some(-32002), message).is_some());
+        // The same text from a simulation the caller asked for is the caller's answer
+        assert!(classify_rpc_error(None, message).is_none());
+        assert!(classify_rpc_error(Some(-32002), "Transaction simulation failed").is_none());
+    }
+}
@@ -0,0 +1,509 @@
+//! Review of transactions built by partners before the payer co-signs them as fee payer
+//!
+//! A partner hands over a transaction signed by everyone but the payer.
+//! [`review_external_transaction`] checks it against a [`ReviewPolicy`] and lists every
+//! rule it breaks. A transaction that breaks none is signed by the payer and submitted.
+//!
+//! Only the first transaction co-signed with a given recent blockhash is accepted. The
+//! blockhashes of co-signed transactions are kept in memory for
+//! [`BLOCKHASH_REUSE_WINDOW`], after which the cluster refuses them anyway.
+
+use crate::RPC_CLIENT;
+use crate::error::{SssError, SssResult};
+use crate::inspect::{
+    DecodedInstruction, InspectedInstruction, MEMO_PROGRAM_ID, TxInspection,
+    inspect_versioned_message,
+};
+use crate::journal::{JournalOperation, journaled};
+use crate::metrics::track;
+use crate::mint_cache::mint_info;
+use crate::payer::signing_payer;
+use crate::read_only::ensure_writable;
+use crate::serde_utils::pubkey_string;
+use crate::transaction::{TxOptions, send_signed, send_versioned};
+use crate::trusted_mints::is_trusted_mint;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use serde_json::json;
+use solana_sdk::{
+    address_lookup_table::state::AddressLookupTable,
+    compute_budget,
+    hash::Hash,
+    message::VersionedMessage,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+    system_program,
+    transaction::VersionedTransaction,
+};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// How long the blockhash of a co-signed transaction is remembered
+///
+/// A blockhash is valid for at most 150 slots, roughly a minute, so a transaction reusing
+/// it cannot land after this.
+pub const BLOCKHASH_REUSE_WINDOW: Duration = Duration::from_secs(120);
+
+/// Most co-signed transactions remembered at once
+const RECENT_COSIGNED_CAPACITY: usize = 512;
+
+/// Default limit of [`ReviewPolicy::max_payer_outflow_lamports`], 0.01 SOL
+pub const DEFAULT_MAX_PAYER_OUTFLOW_LAMPORTS: u64 = 10_000_000;
+
+/// Position of the funding account in the accounts of system transfers and account creation
+const SYSTEM_FUNDER_INDEX: usize = 0;
+/// Position of the funding account in the accounts of associated token account creation
+const ATA_FUNDER_INDEX: usize = 0;
+/// Position of the mint in the accounts of associated token account creation
+const ATA_MINT_INDEX: usize = 3;
+/// Position of the changed account in the accounts of a token authority change
+const SET_AUTHORITY_ACCOUNT_INDEX: usize = 0;
+/// Position of the current authority in the accounts of a token authority change
+const SET_AUTHORITY_AUTHORITY_INDEX: usize = 1;
+
+lazy_static! {
+    /// Blockhashes and signatures of recently co-signed transactions, oldest first
+    static ref RECENT_COSIGNED: Mutex<VecDeque<(Hash, Signature, Instant)>> =
+        Mutex::new(VecDeque::new());
+}
+
+/// Rules a partner transaction must follow to be co-signed
+#[derive(Debug, Clone)]
+pub struct ReviewPolicy {
+    /// Programs the transaction may invoke
+    pub allowed_programs: Vec<Pubkey>,
+    /// Most lamports the payer may spend on the transaction, including the fee and the
+    /// rent of accounts it funds
+    pub max_payer_outflow_lamports: u64,
+}
+
+impl Default for ReviewPolicy {
+    /// Allows the system, token, associated token account, memo and compute budget
+    /// programs and an outflow of [`DEFAULT_MAX_PAYER_OUTFLOW_LAMPORTS`]
+    fn default() -> Self {
+        Self {
+            allowed_programs: vec![
+                system_program::id(),
+                spl_token::id(),
+                spl_token_2022::id(),
+                spl_associated_token_account::id(),
+                MEMO_PROGRAM_ID,
+                compute_budget::id(),
+            ],
+            max_payer_outflow_lamports: DEFAULT_MAX_PAYER_OUTFLOW_LAMPORTS,
+        }
+    }
+}
+
+/// A rule a partner transaction breaks
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "rule", rename_all = "snake_case")]
+pub enum ReviewViolation {
+    /// A required signature other than the payer's is missing
+    MissingSignature {
+        #[serde(with = "pubkey_string")]
+        signer: Pubkey,
+    },
+    /// A signature does not match the message
+    InvalidSignature {
+        #[serde(with = "pubkey_string")]
+        signer: Pubkey,
+    },
+    /// An instruction invokes a program the policy does not allow
+    ProgramNotAllowed {
+        index: usize,
+        #[serde(with = "pubkey_string")]
+        program_id: Pubkey,
+    },
+    /// The payer would spend more than the policy allows
+    PayerOutflow { lamports: u64, max: u64 },
+    /// An instruction uses the payer's signature for something other than paying
+    PayerSignature { index: usize, instruction: String },
+    /// An instruction changes the authority of a trusted mint or of an account the payer
+    /// controls
+    AuthorityChange {
+        index: usize,
+        #[serde(with = "pubkey_string")]
+        account: Pubkey,
+    },
+    /// A transaction with the same recent blockhash was already co-signed
+    BlockhashReused {
+        blockhash: String,
+        signature: String,
+    },
+}
+
+impl fmt::Display for ReviewViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingSignature { signer } => write!(f, "Signature of {} is missing", signer),
+            Self::InvalidSignature { signer } => write!(f, "Signature of {} is invalid", signer),
+            Self::ProgramNotAllowed { index, program_id } => write!(
+                f,
+                "Instruction {} invokes program {}, which is not allowed",
+                index, program_id
+            ),
+            Self::PayerOutflow { lamports, max } => write!(
+                f,
+                "Payer would spend {} lamports, more than the allowed {}",
+                lamports, max
+            ),
+            Self::PayerSignature { index, instruction } => write!(
+                f,
+                "Instruction {} ({}) requires the payer's signature",
+                index, instruction
+            ),
+            Self::AuthorityChange { index, account } => write!(
+                f,
+                "Instruction {} changes the authority of {}",
+                index, account
+            ),
+            Self::BlockhashReused {
+                blockhash,
+                signature,
+            } => write!(
+                f,
+                "Blockhash {} was already used by co-signed transaction {}",
+                blockhash, signature
+            ),
+        }
+    }
+}
+
+/// The result of reviewing a partner transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum ReviewOutcome {
+    /// The transaction broke no rule and was co-signed and submitted
+    Approved {
+        /// The transaction signature
+        signature: String,
+    },
+    /// The transaction was not signed
+    Rejected {
+        /// Every rule the transaction breaks
+        violations: Vec<ReviewViolation>,
+    },
+}
+
+/// Reviews a partner transaction and co-signs and submits it if it follows the policy
+///
+/// The transaction must be well formed, paid for by the payer and signed by every other
+/// required signer. Apart from paying the fee, the payer's signature may only fund system
+/// transfers, account creation and associated token account creation, whose lamports
+/// count toward the outflow limit together with the fee. Authority changes of trusted
+/// mints are refused whoever signs them.
+///
+/// # Arguments
+///
+/// * `tx_bytes` - The bincode-serialized legacy or v0 transaction
+/// * `policy` - The rules the transaction must follow
+///
+/// # Returns
+///
+/// The signature of the submitted transaction, or every rule the transaction breaks
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the transaction cannot be decoded, is malformed, requires no
+/// signature or is not paid for by the payer, an `RpcError` if its fee, lookup tables or
+/// rent cannot be determined or it fails to land, and the error of the payer if it cannot
+/// sign
+pub fn review_external_transaction(
+    tx_bytes: &[u8],
+    policy: ReviewPolicy,
+) -> SssResult<ReviewOutcome> {
+    ensure_writable()?;
+    let tx: VersionedTransaction = bincode::deserialize(tx_bytes)
+        .map_err(|e| SssError::TokenError(format!("Failed to decode transaction: {}", e)))?;
+    let payer = signing_payer()?;
+    ensure_well_formed(&tx, &payer.pubkey())?;
+
+    let violations = review(&tx, &policy, &payer.pubkey())?;
+    if !violations.is_empty() {
+        return Ok(ReviewOutcome::Rejected { violations });
+    }
+    cosign_and_submit(tx, &payer)
+}
+
+/// Refuses a transaction the payer cannot be asked to co-sign at all
+///
+/// Runs before the review, so every account index it inspects exists, every required
+/// signer has a signature slot and the payer signs first, as fee payer.
+fn ensure_well_formed(tx: &VersionedTransaction, payer: &Pubkey) -> SssResult<()> {
+    if tx.message.header().num_required_signatures == 0 {
+        return Err(SssError::TokenError(
+            "Transaction requires no signature, so it has no fee payer".to_string(),
+        ));
+    }
+    tx.sanitize()
+        .map_err(|e| SssError::TokenError(format!("Malformed transaction: {}", e)))?;
+    match tx.message.static_account_keys().first() {
+        Some(fee_payer) if fee_payer == payer => Ok(()),
+        fee_payer => Err(SssError::TokenError(format!(
+            "Transaction is paid for by {}, not by the payer {}",
+            fee_payer.copied().unwrap_or_default(),
+            payer
+        ))),
+    }
+}
+
+/// Collects every rule a transaction breaks
+fn review(
+    tx: &VersionedTransaction,
+    policy: &ReviewPolicy,
+    payer: &Pubkey,
+) -> SssResult<Vec<ReviewViolation>> {
+    let mut violations = Vec::new();
+    let inspection = inspect_versioned_message(&tx.message, &loaded_addresses(&tx.message)?);
+    check_signatures(tx, payer, &mut violations);
+
+    for (index, ix) in inspection.instructions.iter().enumerate() {
+        if !policy.allowed_programs.contains(&ix.program_id) {
+            violations.push(ReviewViolation::ProgramNotAllowed {
+                index,
+                program_id: ix.program_id,
+            });
+        }
+        if let DecodedInstruction::TokenSetAuthority { .. } = ix.decoded {
+            let account = account_at(ix, SET_AUTHORITY_ACCOUNT_INDEX);
+            let authority = account_at(ix, SET_AUTHORITY_AUTHORITY_INDEX);
+            if account.is_some_and(|account| is_trusted_mint(&account)) || authority == Some(*payer)
+            {
+                violations.push(ReviewViolation::AuthorityChange {
+                    index,
+                    account: account.unwrap_or_default(),
+                });
+            }
+        } else if ix.accounts.iter().any(|a| a.pubkey == *payer) && !payer_may_sign(ix) {
+            violations.push(ReviewViolation::PayerSignature {
+                index,
+                instruction: ix.summary(),
+            });
+        }
+    }
+
+    let blockhash = tx.message.recent_blockhash();
+    if let Some((_, previous, _)) = recent_cosigned()?
+        .iter()
+        .find(|(hash, _, _)| hash == blockhash)
+    {
+        violations.push(ReviewViolation::BlockhashReused {
+            blockhash: blockhash.to_string(),
+            signature: previous.to_string(),
+        });
+    }
+
+    let lamports = payer_outflow(tx, &inspection, payer)?;
+    if lamports > policy.max_payer_outflow_lamports {
+        violations.push(ReviewViolation::PayerOutflow {
+            lamports,
+            max: policy.max_payer_outflow_lamports,
+        });
+    }
+    Ok(violations)
+}
+
+/// Checks that every required signature but the payer's is present and valid
+fn check_signatures(
+    tx: &VersionedTransaction,
+    payer: &Pubkey,
+    violations: &mut Vec<ReviewViolation>,
+) {
+    let message = tx.message.serialize();
+    let required = tx.message.header().num_required_signatures as usize;
+    for (index, signer) in tx
+        .message
+        .static_account_keys()
+        .iter()
+        .take(required)
+        .enumerate()
+    {
+        if signer == payer {
+            continue;
+        }
+        match tx.signatures.get(index) {
+            None => violations.push(ReviewViolation::MissingSignature { signer: *signer }),
+            Some(signature) if *signature == Signature::default() => {
+                violations.push(ReviewViolation::MissingSignature { signer: *signer })
+            }
+            Some(signature) if !signature.verify(signer.as_ref(), &message) => {
+                violations.push(ReviewViolation::InvalidSignature { signer: *signer })
+            }
+            Some(_) => {}
+        }
+    }
+}
+
+/// Returns whether an instruction may involve the payer
+///
+/// Transfers, account creation and associated token account creation may be funded by
+/// the payer, which [`payer_outflow`] accounts for, and memos may name it as a signer.
+fn payer_may_sign(ix: &InspectedInstruction) -> bool {
+    matches!(
+        ix.decoded,
+        DecodedInstruction::SystemTransfer { .. }
+            | DecodedInstruction::SystemCreateAccount { .. }
+            | DecodedInstruction::AssociatedTokenCreate { .. }
+            | DecodedInstruction::Memo(_)
+    )
+}
+
+/// Sums the lamports the payer spends on a transaction: the fee, transfers and the rent of
+/// the accounts it funds
+fn payer_outflow(
+    tx: &VersionedTransaction,
+    inspection: &TxInspection,
+    payer: &Pubkey,
+) -> SssResult<u64> {
+    let fee = match &tx.message {
+        VersionedMessage::Legacy(message) => RPC_CLIENT.get_fee_for_message(message),
+        VersionedMessage::V0(message) => RPC_CLIENT.get_fee_for_message(message),
+    }
+    .map_err(|e| SssError::RpcError(format!("Failed to get transaction fee: {}", e)))?;
+
+    let mut rents: HashMap<usize, u64> = HashMap::new();
+    let mut lamports = if inspection.fee_payer == *payer {
+        fee
+    } else {
+        0
+    };
+    for ix in &inspection.instructions {
+        let spent = match ix.decoded {
+            DecodedInstruction::SystemTransfer { lamports }
+            | DecodedInstruction::SystemCreateAccount { lamports, .. }
+                if account_at(ix, SYSTEM_FUNDER_INDEX) == Some(*payer) =>
+            {
+                lamports
+            }
+            DecodedInstruction::AssociatedTokenCreate { .. }
+                if account_at(ix, ATA_FUNDER_INDEX) == Some(*payer) =>
+            {
+                // Idempotent creation of an existing account costs nothing, but is
+                // counted, as the account may be closed before the transaction lands
+                let mint = account_at(ix, ATA_MINT_INDEX).unwrap_or_default();
+                let len = mint_info(&mint)?.token_account_len;
+                match rents.get(&len) {
+                    Some(rent) => *rent,
+                    None => {
+                        let rent = RPC_CLIENT
+                            .get_minimum_balance_for_rent_exemption(len)
+                            .map_err(|e| {
+                                SssError::RpcError(format!("Failed to get rent: {}", e))
+                            })?;
+                        rents.insert(len, rent);
+                        rent
+                    }
+                }
+            }
+            _ => 0,
+        };
+        lamports = lamports.saturating_add(spent);
+    }
+    Ok(lamports)
+}
+
+/// Resolves the addresses a v0 message loads from lookup tables, writable ones first
+fn loaded_addresses(message: &VersionedMessage) -> SssResult<Vec<Pubkey>> {
+    let Some(lookups) = message.address_table_lookups() else {
+        return Ok(Vec::new());
+    };
+    if lookups.is_empty() {
+        return Ok(Vec::new());
+    }
+    let keys: Vec<Pubkey> = lookups.iter().map(|lookup| lookup.account_key).collect();
+    let accounts = RPC_CLIENT
+        .get_multiple_accounts(&keys)
+        .map_err(|e| SssError::RpcError(format!("Failed to get lookup tables: {}", e)))?;
+
+    let mut tables = Vec::with_capacity(keys.len());
+    for (key, account) in keys.iter().zip(&accounts) {
+        let account = account
+            .as_ref()
+            .ok_or_else(|| SssError::RpcError(format!("Lookup table {} not found", key)))?;
+        let table = AddressLookupTable::deserialize(&account.data)
+            .map_err(|e| SssError::RpcError(format!("Invalid lookup table {}: {}", key, e)))?;
+        tables.push(table.addresses.into_owned());
+    }
+
+    let resolve = |table: &[Pubkey], index: u8, key: &Pubkey| {
+        table.get(index as usize).copied().ok_or_else(|| {
+            SssError::RpcError(format!("Lookup table {} has no index {}", key, index))
+        })
+    };
+    let mut writable = Vec::new();
+    let mut readonly = Vec::new();
+    for (lookup, table) in lookups.iter().zip(&tables) {
+        for index in &lookup.writable_indexes {
+            writable.push(resolve(table, *index, &lookup.account_key)?);
+        }
+        for index in &lookup.readonly_indexes {
+            readonly.push(resolve(table, *index, &lookup.account_key)?);
+        }
+    }
+    writable.extend(readonly);
+    Ok(writable)
+}
+
+/// Signs a reviewed transaction as fee payer and submits it
+///
+/// The blockhash is claimed before submission, so two transactions reviewed at the same
+/// time with the same blockhash are never both co-signed.
+fn cosign_and_submit(mut tx: VersionedTransaction, payer: &Keypair) -> SssResult<ReviewOutcome> {
+    track(JournalOperation::CosignExternal, || {
+        let blockhash = *tx.message.recent_blockhash();
+        let message = tx.message.serialize();
+        let signature = payer.sign_message(&message);
+        let required = tx.message.header().num_required_signatures as usize;
+        tx.signatures.resize(required, Signature::default());
+        tx.signatures[0] = signature;
+
+        {
+            let mut recent = recent_cosigned()?;
+            if let Some((_, previous, _)) = recent.iter().find(|(hash, _, _)| *hash == blockhash) {
+                return Ok(ReviewOutcome::Rejected {
+                    violations: vec![ReviewViolation::BlockhashReused {
+                        blockhash: blockhash.to_string(),
+                        signature: previous.to_string(),
+                    }],
+                });
+            }
+            recent.push_back((blockhash, signature, Instant::now()));
+        }
+
+        let params = json!({ "signature": signature.to_string() });
+        let signature = journaled(
+            JournalOperation::CosignExternal,
+            &params,
+            &TxOptions::default(),
+            || match tx.clone().into_legacy_transaction() {
+                Some(legacy) => send_signed(&RPC_CLIENT, &legacy),
+                None => send_versioned(&RPC_CLIENT, &tx),
+            },
+        )?;
+        Ok(ReviewOutcome::Approved { signature })
+    })
+}
+
+/// Locks the recently co-signed transactions, dropping those outside the reuse window
+fn recent_cosigned() -> SssResult<MutexGuard<'static, VecDeque<(Hash, Signature, Instant)>>> {
+    let mut recent = RECENT_COSIGNED.lock().map_err(|e| {
+        SssError::TokenError(format!("Co-signed transaction cache poisoned: {}", e))
+    })?;
+    while recent
+        .front()
+        .is_some_and(|(_, _, at)| at.elapsed() > BLOCKHASH_REUSE_WINDOW)
+        || recent.len() >= RECENT_COSIGNED_CAPACITY
+    {
+        recent.pop_front();
+    }
+    Ok(recent)
+}
+
+/// Returns the account at a position of an instruction's accounts
+fn account_at(ix: &InspectedInstruction, index: usize) -> Option<Pubkey> {
+    ix.accounts.get(index).map(|a| a.pubkey)
+}
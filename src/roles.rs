@@ -0,0 +1,531 @@
+//! Per-role restrictions on what a signer may do
+//!
+//! Every profile signs with its own key, so a profile is a signer role; calls without a
+//! selected profile run as the role [`DEFAULT_ROLE`]. The file named by
+//! `SSS_ROLE_POLICIES_PATH` lists the operations each role may run, optionally up to an
+//! amount per transaction:
+//!
+//! ```json
+//! {
+//!   "kiosk": ["mint_token", { "operation": "transfer_asset", "max_amount": "1000" }],
+//!   "treasury": ["all"]
+//! }
+//! ```
+//!
+//! Operations are named as in the journal, e.g. `mint_token` or `rotate_authorities`, and
+//! `all` stands for every operation. Every transaction is checked against the policy of
+//! the active role when the payer signs it and again before it is sent, and messages and
+//! structured data when they are signed, as `sign_message` and `sign_structured`. A
+//! compromised host holding the kiosk key can thus neither rotate authorities with it nor
+//! sign anything else. Roles the file does not list are unrestricted.
+
+use crate::amount::ui_to_base_amount;
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::journal::{JournalOperation, current_operation};
+use crate::load_dotenv;
+use crate::mint_cache::mint_info;
+use crate::profiles::active_profile;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+/// The role of calls made without a selected profile
+pub const DEFAULT_ROLE: &str = "default";
+
+/// The name standing for every operation in a policy
+const ALL_OPERATIONS: &str = "all";
+
+lazy_static! {
+    /// File the role policies are read from, from `SSS_ROLE_POLICIES_PATH`
+    static ref ROLE_POLICIES_PATH: Option<PathBuf> = {
+        load_dotenv();
+        env::var("SSS_ROLE_POLICIES_PATH").ok().map(PathBuf::from)
+    };
+
+    /// Policy per role, loaded from the policy file if it exists
+    static ref ROLE_POLICIES: RwLock<Registry> = {
+        let registry = match read_role_policies_file() {
+            Ok(policies) => Registry {
+                policies: policies.unwrap_or_default(),
+                load_error: None,
+            },
+            Err(SssError::ConfigError(message)) => Registry {
+                policies: BTreeMap::new(),
+                load_error: Some(message),
+            },
+            Err(e) => Registry {
+                policies: BTreeMap::new(),
+                load_error: Some(e.to_string()),
+            },
+        };
+        RwLock::new(registry)
+    };
+}
+
+/// The policies in force and why the policy file failed to load, if it did
+///
+/// A file that fails to load refuses every transaction rather than leaving every role
+/// unrestricted.
+struct Registry {
+    policies: BTreeMap<String, RolePolicy>,
+    load_error: Option<String>,
+}
+
+/// An operation a role may run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Permission {
+    /// The operation, or `None` for every operation
+    pub operation: Option<JournalOperation>,
+    /// The most tokens a transaction may move, in UI units such as `"1000"` or `"2.5"`,
+    /// or `None` for no limit
+    ///
+    /// Compared with the amounts of the transaction using the decimals of each mint.
+    pub max_amount: Option<String>,
+}
+
+impl Permission {
+    /// Allows an operation without limit
+    pub fn operation(operation: JournalOperation) -> Self {
+        Self {
+            operation: Some(operation),
+            max_amount: None,
+        }
+    }
+
+    /// Allows every operation without limit
+    pub fn all() -> Self {
+        Self {
+            operation: None,
+            max_amount: None,
+        }
+    }
+
+    /// Limits the tokens a transaction may move, in UI units
+    pub fn max_amount(mut self, max_amount: impl Into<String>) -> Self {
+        self.max_amount = Some(max_amount.into());
+        self
+    }
+
+    fn covers(&self, operation: JournalOperation) -> bool {
+        self.operation.is_none_or(|allowed| allowed == operation)
+    }
+}
+
+/// A permission with a limit as written in the policy file; a permission without one may
+/// also be written as the operation name alone
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LimitedPermissionFile {
+    operation: String,
+    max_amount: Option<String>,
+}
+
+/// The operations a role may run
+///
+/// The first permission covering an operation applies.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RolePolicy {
+    /// The permissions of the role
+    pub permissions: Vec<Permission>,
+}
+
+impl RolePolicy {
+    /// Returns the permission covering an operation, if any
+    fn permission(&self, operation: JournalOperation) -> Option<&Permission> {
+        self.permissions
+            .iter()
+            .find(|permission| permission.covers(operation))
+    }
+}
+
+/// Whether the active role may run an operation, see [`role_permissions`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OperationPermission {
+    /// Whether the role may run the operation at all
+    pub allowed: bool,
+    /// The most tokens a transaction of the operation may move, in UI units
+    pub max_amount: Option<String>,
+}
+
+/// The effective permissions of a role
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RolePermissions {
+    /// The role, the active profile or [`DEFAULT_ROLE`]
+    pub role: String,
+    /// Whether a policy restricts the role
+    pub restricted: bool,
+    /// Every operation by name, e.g. `mint_token`, with whether the role may run it
+    pub operations: BTreeMap<String, OperationPermission>,
+}
+
+/// Returns the role of the current thread: the selected profile, or [`DEFAULT_ROLE`]
+pub fn active_role() -> String {
+    active_profile().unwrap_or_else(|| DEFAULT_ROLE.to_string())
+}
+
+/// Sets the policy of a role until the policy file is reloaded
+///
+/// # Arguments
+///
+/// * `role` - The profile name, or [`DEFAULT_ROLE`]
+/// * `policy` - The operations the role may run
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if a limit is not a decimal amount
+pub fn set_role_policy(role: impl Into<String>, policy: RolePolicy) -> SssResult<()> {
+    let role = role.into();
+    for permission in &policy.permissions {
+        if let Some(max_amount) = &permission.max_amount {
+            parse_limit(&role, max_amount)?;
+        }
+    }
+    let mut registry = ROLE_POLICIES
+        .write()
+        .map_err(|e| SssError::ConfigError(format!("Role policies lock poisoned: {}", e)))?;
+    registry.policies.insert(role, policy);
+    Ok(())
+}
+
+/// Returns the policy of a role, `None` if the role is unrestricted
+pub fn role_policy(role: &str) -> Option<RolePolicy> {
+    ROLE_POLICIES.read().ok()?.policies.get(role).cloned()
+}
+
+/// Returns what the active role may do, e.g. to hide the actions it may not run
+///
+/// Nothing is allowed while the policy file fails to load.
+pub fn role_permissions() -> RolePermissions {
+    let role = active_role();
+    let (policy, load_failed) = match ROLE_POLICIES.read() {
+        Ok(registry) => (
+            registry.policies.get(&role).cloned(),
+            registry.load_error.is_some(),
+        ),
+        Err(_) => (None, true),
+    };
+    let unrestricted = Permission::all();
+    let operations = JournalOperation::ALL
+        .iter()
+        .map(|operation| {
+            let permission = match &policy {
+                _ if load_failed => None,
+                Some(policy) => policy.permission(*operation),
+                None => Some(&unrestricted),
+            };
+            let permission = OperationPermission {
+                allowed: permission.is_some(),
+                max_amount: permission.and_then(|permission| permission.max_amount.clone()),
+            };
+            (operation.name(), permission)
+        })
+        .collect();
+    RolePermissions {
+        restricted: policy.is_some() || load_failed,
+        role,
+        operations,
+    }
+}
+
+/// Returns whether any role is restricted, or the policy file failed to load
+pub(crate) fn role_policies_enabled() -> bool {
+    ROLE_POLICIES
+        .read()
+        .map(|registry| !registry.policies.is_empty() || registry.load_error.is_some())
+        .unwrap_or(true)
+}
+
+/// Refuses a signature the active role may not make
+///
+/// Called through [`crate::payer::ensure_signing_permitted`], inside the operation making
+/// the signature.
+///
+/// # Errors
+///
+/// Returns a `ConfigError` naming the role and the capability it lacks, if the policy
+/// does not allow the operation or the amounts it moves, or if the policy file failed to
+/// load
+pub(crate) fn ensure_role_permits() -> SssResult<()> {
+    let role = active_role();
+    let permission = {
+        let registry = ROLE_POLICIES
+            .read()
+            .map_err(|e| SssError::ConfigError(format!("Role policies lock poisoned: {}", e)))?;
+        if let Some(load_error) = &registry.load_error {
+            return Err(SssError::ConfigError(format!(
+                "Role policies failed to load, so nothing is signed: {}",
+                load_error
+            )));
+        }
+        let Some(policy) = registry.policies.get(&role) else {
+            return Ok(());
+        };
+        let Some((operation, params)) = current_operation() else {
+            return Err(SssError::ConfigError(format!(
+                "Role '{}' may not sign outside a known operation",
+                role
+            )));
+        };
+        match policy.permission(operation) {
+            Some(permission) => (operation, params, permission.max_amount.clone()),
+            None => {
+                return Err(SssError::ConfigError(format!(
+                    "Role '{}' lacks the '{}' capability",
+                    role,
+                    operation.name()
+                )));
+            }
+        }
+    };
+
+    let (operation, params, Some(max_amount)) = permission else {
+        return Ok(());
+    };
+    let refuse = |detail: String| {
+        SssError::ConfigError(format!(
+            "Role '{}' lacks the '{}' capability above {}: {}",
+            role,
+            operation.name(),
+            max_amount,
+            detail
+        ))
+    };
+    let limit = parse_limit(&role, &max_amount)?;
+    let amounts = params
+        .as_ref()
+        .and_then(requested_amounts)
+        .ok_or_else(|| refuse("the amount of the transaction is not known".to_string()))?;
+    for (mint, amount, decimals) in amounts {
+        let decimals = match decimals {
+            Some(decimals) => decimals,
+            None => mint_info(&mint)?.decimals,
+        };
+        if u128::from(amount) > limit.in_base_units(decimals) {
+            return Err(refuse(format!(
+                "the transaction moves {} base units of mint {}",
+                amount, mint
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the amounts an operation moves per mint, with the decimals if the inputs state
+/// them, or `None` if they cannot be told
+///
+/// Reads the inputs the operations record in the journal: a `mint` with an `amount` or
+/// with `recipients` holding amounts, or `items` each holding a `mint` and an `amount`.
+/// An operation with none of these moves no amount.
+fn requested_amounts(params: &Value) -> Option<Vec<(Pubkey, u64, Option<u8>)>> {
+    let mint = |value: &Value| Pubkey::from_str(value.as_str()?).ok();
+    let decimals = params
+        .get("decimals")
+        .and_then(Value::as_u64)
+        .and_then(|decimals| u8::try_from(decimals).ok());
+
+    if let Some(items) = params.get("items") {
+        return items
+            .as_array()?
+            .iter()
+            .map(|item| {
+                Some((
+                    mint(item.get("mint")?)?,
+                    item.get("amount")?.as_u64()?,
+                    None,
+                ))
+            })
+            .collect();
+    }
+    let Some(mint_value) = params.get("mint") else {
+        return Some(Vec::new());
+    };
+    if let Some(recipients) = params.get("recipients") {
+        let total = recipients
+            .as_array()?
+            .iter()
+            .try_fold(0u64, |total, recipient| {
+                total.checked_add(recipient.get("amount")?.as_u64()?)
+            })?;
+        return Some(vec![(mint(mint_value)?, total, decimals)]);
+    }
+    match params.get("amount") {
+        None => Some(Vec::new()),
+        // Created tokens without an initial mint move nothing
+        Some(Value::Null) if decimals.is_some() => Some(Vec::new()),
+        Some(amount) => {
+            let mint = mint(mint_value)?;
+            match amount {
+                Value::Number(number) => Some(vec![(mint, number.as_u64()?, decimals)]),
+                // A UI amount; converted once the decimals are known
+                Value::String(ui_amount) => {
+                    let decimals = match decimals {
+                        Some(decimals) => decimals,
+                        None => mint_info(&mint).ok()?.decimals,
+                    };
+                    let amount = ui_to_base_amount(ui_amount, decimals).ok()?;
+                    Some(vec![(mint, amount, Some(decimals))])
+                }
+                _ => None,
+            }
+        }
+    }
+}
+
+/// A `max_amount` split into its whole and fractional digits
+struct Limit {
+    whole: u128,
+    fraction: String,
+}
+
+impl Limit {
+    /// Returns the limit in base units of a token with `decimals` decimals, rounded down
+    fn in_base_units(&self, decimals: u8) -> u128 {
+        let Some(scale) = 10u128.checked_pow(u32::from(decimals)) else {
+            return u128::MAX;
+        };
+        let fraction: String = self
+            .fraction
+            .chars()
+            .chain(std::iter::repeat('0'))
+            .take(decimals as usize)
+            .collect();
+        let fraction = fraction.parse::<u128>().unwrap_or(0);
+        self.whole
+            .checked_mul(scale)
+            .and_then(|whole| whole.checked_add(fraction))
+            .unwrap_or(u128::MAX)
+    }
+}
+
+/// Parses a `max_amount`, a decimal amount such as `"1000"` or `"2.5"`
+fn parse_limit(role: &str, max_amount: &str) -> SssResult<Limit> {
+    let invalid = || {
+        SssError::ConfigError(format!(
+            "Invalid max_amount '{}' in the policy of role '{}'",
+            max_amount, role
+        ))
+    };
+    let (whole, fraction) = max_amount.split_once('.').unwrap_or((max_amount, ""));
+    if whole.is_empty()
+        || !format!("{}{}", whole, fraction)
+            .bytes()
+            .all(|b| b.is_ascii_digit())
+    {
+        return Err(invalid());
+    }
+    Ok(Limit {
+        whole: whole.parse().map_err(|_| invalid())?,
+        fraction: fraction.to_string(),
+    })
+}
+
+/// Parses the role policy file, a JSON object of permission lists by role
+fn parse_role_policies(json: &str) -> SssResult<BTreeMap<String, RolePolicy>> {
+    let files: BTreeMap<String, Vec<Value>> =
+        serde_json::from_str(json).into_sss_error("Failed to parse role policy config")?;
+    let operation = |role: &str, name: &str| -> SssResult<Option<JournalOperation>> {
+        if name == ALL_OPERATIONS {
+            return Ok(None);
+        }
+        serde_json::from_value(Value::String(name.to_string()))
+            .map(Some)
+            .map_err(|_| {
+                SssError::ConfigError(format!(
+                    "Unknown operation '{}' in the policy of role '{}'",
+                    name, role
+                ))
+            })
+    };
+    files
+        .into_iter()
+        .map(|(role, entries)| {
+            let permissions = entries
+                .into_iter()
+                .map(|entry| {
+                    let (name, max_amount) = match entry {
+                        Value::String(name) => (name, None),
+                        entry => {
+                            let limited: LimitedPermissionFile = serde_json::from_value(entry)
+                                .map_err(|e| {
+                                    SssError::ConfigError(format!(
+                                        "Invalid permission in the policy of role '{}': {}",
+                                        role, e
+                                    ))
+                                })?;
+                            (limited.operation, limited.max_amount)
+                        }
+                    };
+                    if let Some(max_amount) = &max_amount {
+                        parse_limit(&role, max_amount)?;
+                    }
+                    Ok(Permission {
+                        operation: operation(&role, &name)?,
+                        max_amount,
+                    })
+                })
+                .collect::<SssResult<_>>()?;
+            Ok((role, RolePolicy { permissions }))
+        })
+        .collect()
+}
+
+/// Reads the role policy file
+///
+/// # Returns
+///
+/// `None` if no file is configured or it does not exist
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if the file cannot be read or parsed
+pub(crate) fn read_role_policies_file() -> SssResult<Option<BTreeMap<String, RolePolicy>>> {
+    let Some(path) = ROLE_POLICIES_PATH.as_ref() else {
+        return Ok(None);
+    };
+    let json = match fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(e).into_sss_error(&format!(
+                "Failed to read role policy config {}",
+                path.display()
+            ));
+        }
+    };
+    parse_role_policies(&json).map(Some)
+}
+
+/// Replaces every role policy with those read from the policy file
+///
+/// # Returns
+///
+/// The roles whose policy was added, changed or removed
+pub(crate) fn replace_role_policies(policies: BTreeMap<String, RolePolicy>) -> Vec<String> {
+    let Ok(mut registry) = ROLE_POLICIES.write() else {
+        return Vec::new();
+    };
+    let mut changed: Vec<String> = policies
+        .iter()
+        .filter(|(role, policy)| registry.policies.get(*role) != Some(policy))
+        .map(|(role, _)| role.clone())
+        .collect();
+    changed.extend(
+        registry
+            .policies
+            .keys()
+            .filter(|role| !policies.contains_key(*role))
+            .cloned(),
+    );
+    changed.sort_unstable();
+    registry.policies = policies;
+    registry.load_error = None;
+    changed
+}
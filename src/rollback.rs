@@ -0,0 +1,260 @@
+//! Reclaiming the rent of token accounts created by a flow that failed later on
+//!
+//! Flows that create a recipient account in one transaction and mint in the next report the
+//! accounts they created in [`SssError::AccountsCreated`] when the mint fails.
+//! [`rollback_created_accounts`] closes those still empty, returning their rent to the payer
+//! that funded them.
+
+use crate::RPC_CLIENT;
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::journal::{JournalOperation, journaled};
+use crate::metrics::track;
+use crate::payer::{get_payer_pubkey, signing_payer};
+use crate::read_only::ensure_writable;
+use crate::token::TokenProgram;
+use crate::transaction::{TxOptions, assert_tx_size, required_signatures, sign_and_send};
+use serde_json::json;
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_sdk::{account::Account, message::Message, pubkey::Pubkey};
+use spl_token_2022::extension::StateWithExtensions;
+use spl_token_2022::state::AccountState;
+use std::fmt;
+
+/// A token account created by a flow, see [`CreatedAccounts`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CreatedAccount {
+    /// The token account
+    pub address: Pubkey,
+    /// The mint of the token account
+    pub mint: Pubkey,
+    /// The wallet owning the token account
+    pub owner: Pubkey,
+    /// The token program the account belongs to
+    pub token_program: TokenProgram,
+}
+
+/// Token accounts a flow created and paid the rent of before a later step failed
+///
+/// Returned inside [`SssError::AccountsCreated`], so the caller can decide whether to
+/// reclaim the rent with [`rollback_created_accounts`] or retry the failed step.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CreatedAccounts {
+    /// The payer that funded the accounts
+    pub funder: Pubkey,
+    /// The accounts created, in order
+    pub accounts: Vec<CreatedAccount>,
+}
+
+/// Why [`rollback_created_accounts`] left a created account open
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RollbackSkip {
+    /// The account no longer exists, e.g. it was closed already
+    Missing,
+    /// The account is not a token account of its recorded token program
+    NotTokenAccount {
+        /// The program owning the account
+        owner: Pubkey,
+    },
+    /// The account holds another mint than the one the flow created it for
+    OtherMint {
+        /// The mint the account holds
+        mint: Pubkey,
+    },
+    /// The account received tokens after the flow created it
+    HoldsTokens {
+        /// The tokens it holds, in base units
+        amount: u64,
+    },
+    /// The payer neither owns the account nor is its close authority
+    NotClosable {
+        /// The key allowed to close the account
+        close_authority: Pubkey,
+    },
+}
+
+impl fmt::Display for RollbackSkip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing => write!(f, "the account no longer exists"),
+            Self::NotTokenAccount { owner } => {
+                write!(
+                    f,
+                    "the account is not a token account, it belongs to {}",
+                    owner
+                )
+            }
+            Self::OtherMint { mint } => write!(f, "the account holds mint {}", mint),
+            Self::HoldsTokens { amount } => write!(f, "the account holds {} tokens", amount),
+            Self::NotClosable { close_authority } => {
+                write!(f, "only {} may close the account", close_authority)
+            }
+        }
+    }
+}
+
+/// What [`rollback_created_accounts`] did with a created account
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RollbackOutcome {
+    /// The account was closed by the transaction with this signature
+    Closed(String),
+    /// The account was left open
+    Skipped(RollbackSkip),
+}
+
+/// The outcome of the rollback of a single created account
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountRollback {
+    /// The token account
+    pub address: Pubkey,
+    /// Whether it was closed, or why not
+    pub outcome: RollbackOutcome,
+}
+
+/// Closes the empty accounts a failed flow created, returning their rent to the payer
+///
+/// An account is only closed if it still exists, holds no tokens and the payer is allowed to
+/// close it, i.e. owns it or is its close authority; every other account is left alone and
+/// reported as skipped with the reason. An
+/// account that received tokens in the meantime is never closed, and the token program would
+/// refuse to close it anyway if the tokens arrive between the check and the close. A frozen
+/// account is thawed first, which needs the payer to be the mint's freeze authority.
+///
+/// # Arguments
+///
+/// * `ops` - The accounts reported by [`SssError::AccountsCreated`]
+///
+/// # Returns
+///
+/// One outcome per account of `ops`, in order: the signature of the transaction closing it,
+/// or why it was left open
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the accounts were funded by another payer, an `RpcError` if
+/// the accounts cannot be read or a close fails, and a `ConfigError` in read-only mode
+pub fn rollback_created_accounts(ops: &CreatedAccounts) -> SssResult<Vec<AccountRollback>> {
+    rollback_created_accounts_with_client(&RPC_CLIENT, ops)
+}
+
+/// Closes the empty accounts a failed flow created like [`rollback_created_accounts`],
+/// reading and sending through the given RPC client
+///
+/// # Arguments
+///
+/// * `client` - The RPC client used for every read and for sending the transactions
+/// * `ops` - The accounts reported by [`SssError::AccountsCreated`]
+///
+/// # Returns
+///
+/// One outcome per account of `ops`, in order
+pub fn rollback_created_accounts_with_client(
+    client: &RpcClient,
+    ops: &CreatedAccounts,
+) -> SssResult<Vec<AccountRollback>> {
+    ensure_writable()?;
+    track(JournalOperation::RollbackCreatedAccounts, || {
+        let payer_pubkey = get_payer_pubkey()?;
+        if ops.funder != payer_pubkey {
+            return Err(SssError::TokenError(format!(
+                "Accounts were funded by {}, not by the payer {}",
+                ops.funder, payer_pubkey
+            )));
+        }
+        if ops.accounts.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let addresses: Vec<Pubkey> = ops.accounts.iter().map(|a| a.address).collect();
+        let accounts = client
+            .get_multiple_accounts(&addresses)
+            .into_sss_error("Failed to get created token accounts from rpc")?;
+
+        let options = TxOptions::default();
+        let mut outcomes = Vec::with_capacity(ops.accounts.len());
+        for (created, account) in ops.accounts.iter().zip(accounts) {
+            let program_id = created.token_program.id();
+            let state = match closable_state(created, account.as_ref(), &payer_pubkey) {
+                Ok(state) => state,
+                Err(reason) => {
+                    outcomes.push(AccountRollback {
+                        address: created.address,
+                        outcome: RollbackOutcome::Skipped(reason),
+                    });
+                    continue;
+                }
+            };
+
+            let mut instructions = Vec::with_capacity(2);
+            if state.state == AccountState::Frozen {
+                instructions.push(
+                    spl_token_2022::instruction::thaw_account(
+                        &program_id,
+                        &created.address,
+                        &created.mint,
+                        &payer_pubkey,
+                        &[],
+                    )
+                    .into_sss_error("Failed to build token thaw instruction")?,
+                );
+            }
+            instructions.push(
+                spl_token_2022::instruction::close_account(
+                    &program_id,
+                    &created.address,
+                    &ops.funder,
+                    &payer_pubkey,
+                    &[],
+                )
+                .into_sss_error("Failed to build token close account instruction")?,
+            );
+            let message = Message::new(&instructions, Some(&payer_pubkey));
+            assert_tx_size(&message, required_signatures(&message))?;
+            let payer = signing_payer()?;
+            let params = json!({
+                "account": created.address.to_string(),
+                "mint": created.mint.to_string(),
+            });
+            let signature = journaled(
+                JournalOperation::RollbackCreatedAccounts,
+                &params,
+                &options,
+                || sign_and_send(client, message, &[&payer], &options),
+            )?;
+            outcomes.push(AccountRollback {
+                address: created.address,
+                outcome: RollbackOutcome::Closed(signature),
+            });
+        }
+        Ok(outcomes)
+    })
+}
+
+/// Returns the state of a created account the payer may close, or why it may not
+fn closable_state(
+    created: &CreatedAccount,
+    account: Option<&Account>,
+    payer: &Pubkey,
+) -> Result<spl_token_2022::state::Account, RollbackSkip> {
+    let account = account.ok_or(RollbackSkip::Missing)?;
+    let not_token_account = RollbackSkip::NotTokenAccount {
+        owner: account.owner,
+    };
+    if account.owner != created.token_program.id() {
+        return Err(not_token_account);
+    }
+    let state = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account.data)
+        .map_err(|_| not_token_account)?
+        .base;
+    let close_authority = Option::<Pubkey>::from(state.close_authority).unwrap_or(state.owner);
+    if state.mint != created.mint {
+        Err(RollbackSkip::OtherMint { mint: state.mint })
+    } else if state.amount > 0 {
+        Err(RollbackSkip::HoldsTokens {
+            amount: state.amount,
+        })
+    } else if close_authority != *payer {
+        Err(RollbackSkip::NotClosable { close_authority })
+    } else {
+        Ok(state)
+    }
+}
@@ -0,0 +1,410 @@
+//! Moving mint, freeze and metadata update authorities to a new key
+
+use crate::RPC_CLIENT;
+use crate::batch::BatchReport;
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::instructions;
+use crate::journal::{JournalOperation, journaled};
+use crate::metrics::track;
+use crate::mint_cache::metadata_pda;
+use crate::payer::signing_payer;
+use crate::serde_utils::pubkey_string;
+use crate::token::ensure_trusted_mint;
+use crate::transaction::{TxOptions, fitting_items, sign_and_send};
+use mpl_token_metadata::accounts::Metadata;
+
+use serde::Serialize;
+use serde_json::json;
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_sdk::{
+    account::Account, instruction::Instruction, message::Message, pubkey::Pubkey, signer::Signer,
+};
+use spl_token_2022::extension::StateWithExtensions;
+use spl_token_2022::instruction::AuthorityType;
+
+/// Number of mints whose accounts are fetched in one RPC call (two accounts per mint)
+const ROTATION_FETCH_SIZE: usize = 50;
+
+/// Which authorities to move
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AuthorityKinds {
+    /// The mint authority of the token
+    pub mint: bool,
+    /// The freeze authority of the token
+    pub freeze: bool,
+    /// The update authority of the token metadata
+    pub metadata_update: bool,
+}
+
+impl AuthorityKinds {
+    /// Selects every authority
+    pub fn all() -> Self {
+        Self {
+            mint: true,
+            freeze: true,
+            metadata_update: true,
+        }
+    }
+}
+
+/// What happened to a single authority of a mint
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum RotationOutcome {
+    /// The authority was moved in the given transaction
+    Rotated {
+        /// The transaction signature
+        signature: String,
+    },
+    /// The authority already belonged to the new key, e.g. from an earlier run
+    AlreadyRotated,
+    /// The payer is not the current authority, so it was left untouched
+    NotAuthority {
+        /// The current authority, or `None` if the authority is disabled
+        current: Option<String>,
+    },
+    /// The authority could not be moved
+    Failed {
+        /// Why the rotation failed
+        error: String,
+    },
+}
+
+/// Outcome of every requested authority of a mint
+///
+/// Authorities that were not requested are `None`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MintRotation {
+    /// The mint
+    #[serde(with = "pubkey_string")]
+    pub mint: Pubkey,
+    /// The mint authority outcome
+    pub mint_authority: Option<RotationOutcome>,
+    /// The freeze authority outcome
+    pub freeze_authority: Option<RotationOutcome>,
+    /// The metadata update authority outcome
+    pub update_authority: Option<RotationOutcome>,
+}
+
+/// A single authority of a mint
+#[derive(Debug, Clone, Copy)]
+enum Authority {
+    Mint,
+    Freeze,
+    Update,
+}
+
+impl MintRotation {
+    fn outcome_mut(&mut self, authority: Authority) -> &mut Option<RotationOutcome> {
+        match authority {
+            Authority::Mint => &mut self.mint_authority,
+            Authority::Freeze => &mut self.freeze_authority,
+            Authority::Update => &mut self.update_authority,
+        }
+    }
+}
+
+/// Per-mint, per-authority outcome of a rotation run
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RotationReport {
+    /// The key the authorities were moved to
+    #[serde(with = "pubkey_string")]
+    pub new_authority: Pubkey,
+    /// One entry per requested mint, in request order
+    pub mints: Vec<MintRotation>,
+}
+
+impl RotationReport {
+    /// Serializes the report to JSON
+    pub fn to_json(&self) -> SssResult<String> {
+        serde_json::to_string_pretty(self).into_sss_error("Failed to serialize token rotation")
+    }
+
+    /// Summarizes the report per mint
+    ///
+    /// A mint failed if any of its authorities failed, succeeded with the first signature
+    /// if any authority was rotated, and is skipped if every authority was already rotated
+    /// or not held by the payer. Pass [`BatchReport::retry_failed`] a closure calling
+    /// [`rotate_authorities`] to rotate the failed mints again.
+    pub fn batch_report(&self) -> BatchReport {
+        let mut batch = BatchReport::default();
+        for rotation in &self.mints {
+            let outcomes = [
+                &rotation.mint_authority,
+                &rotation.freeze_authority,
+                &rotation.update_authority,
+            ];
+            let failed = outcomes.iter().find_map(|outcome| match outcome {
+                Some(RotationOutcome::Failed { error }) => Some(error),
+                _ => None,
+            });
+            let signature = outcomes.iter().find_map(|outcome| match outcome {
+                Some(RotationOutcome::Rotated { signature }) => Some(signature),
+                _ => None,
+            });
+            match (failed, signature) {
+                (Some(error), _) => batch
+                    .failed
+                    .push((rotation.mint, SssError::TokenError(error.clone()))),
+                (None, Some(signature)) => batch.succeeded.push((rotation.mint, signature.clone())),
+                (None, None) => batch.skipped.push(rotation.mint),
+            }
+        }
+        batch
+    }
+}
+
+/// Moves the selected authorities of each mint from the payer to a new key
+///
+/// Authorities already held by `new_authority` are reported as `AlreadyRotated`, so a
+/// run interrupted part-way can be repeated with the same mints and only the remaining
+/// authorities are moved. Authorities the payer does not hold are skipped and reported.
+///
+/// # Arguments
+///
+/// * `mints` - The mints to rotate
+/// * `new_authority` - The key receiving the authorities
+/// * `kinds` - Which authorities to move
+///
+/// # Returns
+///
+/// The outcome of every requested authority of every mint
+pub fn rotate_authorities(
+    mints: &[Pubkey],
+    new_authority: Pubkey,
+    kinds: AuthorityKinds,
+) -> SssResult<RotationReport> {
+    rotate_authorities_with_client(&RPC_CLIENT, mints, new_authority, kinds)
+}
+
+/// Moves the selected authorities of each mint like [`rotate_authorities`], reading and
+/// sending through the given RPC client
+///
+/// # Arguments
+///
+/// * `client` - The RPC client used for every read and for sending the transactions
+/// * `mints` - The mints to rotate
+/// * `new_authority` - The key receiving the authorities
+/// * `kinds` - Which authorities to move
+///
+/// # Returns
+///
+/// The outcome of every requested authority of every mint
+pub fn rotate_authorities_with_client(
+    client: &RpcClient,
+    mints: &[Pubkey],
+    new_authority: Pubkey,
+    kinds: AuthorityKinds,
+) -> SssResult<RotationReport> {
+    track(JournalOperation::RotateAuthorities, || {
+        let payer = signing_payer()?;
+        let mut report = RotationReport {
+            new_authority,
+            mints: Vec::with_capacity(mints.len()),
+        };
+
+        for chunk in mints.chunks(ROTATION_FETCH_SIZE) {
+            let keys: Vec<Pubkey> = chunk
+                .iter()
+                .flat_map(|mint| [*mint, metadata_pda(mint)])
+                .collect();
+            let accounts = client
+                .get_multiple_accounts(&keys)
+                .into_sss_error("Failed to get mint accounts from rpc");
+
+            let mut pending = Vec::new();
+            for (i, mint) in chunk.iter().enumerate() {
+                let planned = match &accounts {
+                    Ok(accounts) => plan_rotation(
+                        mint,
+                        accounts[2 * i].as_ref(),
+                        accounts[2 * i + 1].as_ref(),
+                        &payer.pubkey(),
+                        &new_authority,
+                        kinds,
+                    ),
+                    Err(e) => Err(e.to_string()),
+                };
+                match planned {
+                    Ok((rotation, moves)) => {
+                        let index = report.mints.len();
+                        report.mints.push(rotation);
+                        if !moves.is_empty() {
+                            pending.push((index, moves));
+                        }
+                    }
+                    Err(error) => report.mints.push(failed_rotation(*mint, kinds, error)),
+                }
+            }
+
+            let build = |batch: &[(usize, Vec<(Authority, Instruction)>)]| {
+                let instructions: Vec<Instruction> = batch
+                    .iter()
+                    .flat_map(|(_, moves)| moves.iter().map(|(_, ix)| ix.clone()))
+                    .collect();
+                Ok(Message::new(&instructions, Some(&payer.pubkey())))
+            };
+            // Each transaction takes as many mints as fit in a packet
+            let mut rest = pending.as_slice();
+            while !rest.is_empty() {
+                // A mint too large on its own is sent alone and reported with the send error
+                let count = fitting_items(rest, 1, build).unwrap_or(1);
+                let (batch, tail) = rest.split_at(count);
+                rest = tail;
+                let message = build(batch)?;
+                let params = json!({
+                    "new_authority": new_authority.to_string(),
+                    "mints": batch
+                        .iter()
+                        .map(|(index, _)| report.mints[*index].mint.to_string())
+                        .collect::<Vec<_>>(),
+                });
+                let tx_options = TxOptions::default();
+                let outcome = match journaled(
+                    JournalOperation::RotateAuthorities,
+                    &params,
+                    &tx_options,
+                    || sign_and_send(client, message, &[&payer], &tx_options),
+                ) {
+                    Ok(signature) => RotationOutcome::Rotated { signature },
+                    Err(e) => RotationOutcome::Failed {
+                        error: e.to_string(),
+                    },
+                };
+
+                for (index, moves) in batch {
+                    for (authority, _) in moves {
+                        *report.mints[*index].outcome_mut(*authority) = Some(outcome.clone());
+                    }
+                }
+            }
+        }
+
+        Ok(report)
+    })
+}
+
+/// Decides the outcome of each requested authority and builds the instructions moving them
+///
+/// Authorities that need moving are left `None` in the returned rotation until their
+/// transaction is sent.
+fn plan_rotation(
+    mint: &Pubkey,
+    mint_account: Option<&Account>,
+    metadata_account: Option<&Account>,
+    payer: &Pubkey,
+    new_authority: &Pubkey,
+    kinds: AuthorityKinds,
+) -> Result<(MintRotation, Vec<(Authority, Instruction)>), String> {
+    ensure_trusted_mint(mint).map_err(|e| e.to_string())?;
+    let mint_account =
+        mint_account.ok_or_else(|| format!("Mint account {} does not exist", mint))?;
+    if mint_account.owner != spl_token::id() && mint_account.owner != spl_token_2022::id() {
+        return Err(format!("Account {} is not a token mint", mint));
+    }
+    let state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_account.data)
+        .map_err(|e| format!("Failed to parse token mint account: {}", e))?;
+
+    let mut rotation = MintRotation {
+        mint: *mint,
+        mint_authority: None,
+        freeze_authority: None,
+        update_authority: None,
+    };
+    let mut moves = Vec::new();
+
+    let token_authorities = [
+        (
+            kinds.mint,
+            Authority::Mint,
+            AuthorityType::MintTokens,
+            state.base.mint_authority,
+        ),
+        (
+            kinds.freeze,
+            Authority::Freeze,
+            AuthorityType::FreezeAccount,
+            state.base.freeze_authority,
+        ),
+    ];
+    for (requested, authority, authority_type, current) in token_authorities {
+        if !requested {
+            continue;
+        }
+        match settled_outcome(current.into(), payer, new_authority) {
+            Some(outcome) => *rotation.outcome_mut(authority) = Some(outcome),
+            None => {
+                let ix = spl_token_2022::instruction::set_authority(
+                    &mint_account.owner,
+                    mint,
+                    Some(new_authority),
+                    authority_type,
+                    payer,
+                    &[],
+                )
+                .map_err(|e| format!("Failed to build token set authority instruction: {}", e))?;
+                moves.push((authority, ix));
+            }
+        }
+    }
+
+    if kinds.metadata_update {
+        match metadata_account.map(|a| Metadata::from_bytes(&a.data)) {
+            None => {
+                rotation.update_authority = Some(RotationOutcome::Failed {
+                    error: format!("Mint {} has no metadata account", mint),
+                });
+            }
+            Some(Err(e)) => {
+                rotation.update_authority = Some(RotationOutcome::Failed {
+                    error: format!("Failed to parse token metadata: {}", e),
+                });
+            }
+            Some(Ok(metadata)) => {
+                match settled_outcome(Some(metadata.update_authority), payer, new_authority) {
+                    Some(outcome) => rotation.update_authority = Some(outcome),
+                    None => moves.push((
+                        Authority::Update,
+                        instructions::update_authority_v2(
+                            Some(metadata_pda(mint)),
+                            Some(*payer),
+                            *new_authority,
+                        )
+                        .map_err(|e| e.to_string())?,
+                    )),
+                }
+            }
+        }
+    }
+
+    Ok((rotation, moves))
+}
+
+/// Returns the outcome of an authority that needs no transaction, or `None` if the payer
+/// holds it and it has to be moved
+fn settled_outcome(
+    current: Option<Pubkey>,
+    payer: &Pubkey,
+    new_authority: &Pubkey,
+) -> Option<RotationOutcome> {
+    match current {
+        Some(current) if current == *new_authority => Some(RotationOutcome::AlreadyRotated),
+        Some(current) if current == *payer => None,
+        current => Some(RotationOutcome::NotAuthority {
+            current: current.map(|c| c.to_string()),
+        }),
+    }
+}
+
+/// Marks every requested authority of a mint as failed
+fn failed_rotation(mint: Pubkey, kinds: AuthorityKinds, error: String) -> MintRotation {
+    let failed = || RotationOutcome::Failed {
+        error: error.clone(),
+    };
+    MintRotation {
+        mint,
+        mint_authority: kinds.mint.then(failed),
+        freeze_authority: kinds.freeze.then(failed),
+        update_authority: kinds.metadata_update.then(failed),
+    }
+}
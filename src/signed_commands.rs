@@ -0,0 +1,448 @@
+//! Commands signed by a trusted backend key
+//!
+//! A kiosk that receives instructions such as "mint approved" over an untrusted channel
+//! checks them with [`verify_signed_command`] before acting. The backend signs the
+//! command's canonical message with ed25519: the line `SSS signed command`, a newline,
+//! and the command as compact JSON with its fields in the order `operation`, `mint`,
+//! `owner`, `amount`, `nonce`, `expiry`, e.g.
+//!
+//! ```text
+//! SSS signed command
+//! {"operation":"mint","mint":"<base58>","owner":"<base58>","amount":1,"nonce":"n-1","expiry":1767225600}
+//! ```
+//!
+//! The payload the kiosk receives may be formatted differently; it is parsed and brought
+//! into canonical form before the signature is checked. Every nonce is accepted once.
+//! Used nonces are kept until their command expires, in the JSON file named by
+//! `SSS_COMMAND_NONCES_PATH` so a restart does not make them valid again, or in memory only
+//! without it. The file is updated under an exclusive lock on a `.lock` file next to it and
+//! synced to disk before it replaces the previous version, so several processes sharing it
+//! see each other's nonces.
+//!
+//! [`execute_signed_command`] takes the command by value and `SignedCommand` is not
+//! `Clone`, so each verification allows exactly one execution.
+
+use crate::amount::{AmountSpec, RawAmount};
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::load_dotenv;
+use crate::serde_utils::pubkey_string;
+use crate::signing::verify_message;
+use crate::token::{mint_token, mint_token_to_account};
+use crate::transfer::{TransferOptions, transfer_asset_with_options};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// First line of the message a command signature covers
+const COMMAND_HEADER: &str = "SSS signed command";
+
+/// Longest nonce accepted, in bytes
+const MAX_NONCE_LEN: usize = 128;
+
+lazy_static! {
+    /// File the used nonces are persisted to, from `SSS_COMMAND_NONCES_PATH`
+    static ref COMMAND_NONCES_PATH: Option<PathBuf> = {
+        load_dotenv();
+        env::var("SSS_COMMAND_NONCES_PATH").ok().map(PathBuf::from)
+    };
+
+    /// Nonces of accepted commands, mapped to their expiry
+    ///
+    /// The nonce file, if any, is merged in on every verification.
+    static ref USED_COMMAND_NONCES: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+}
+
+/// The token operation a signed command asks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandOperation {
+    /// Mint `amount` tokens to the associated account of the wallet `owner`
+    Mint,
+    /// Mint `amount` tokens into the token account `owner`
+    MintToAccount,
+    /// Transfer `amount` tokens held by the payer to the wallet `owner`
+    Transfer,
+}
+
+/// A command whose signature, expiry and nonce were checked by [`verify_signed_command`]
+///
+/// Commands cannot be built or cloned outside the library, so holding one means it was
+/// verified, and executing it consumes it.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct SignedCommand {
+    /// What to do
+    pub operation: CommandOperation,
+    /// The mint of the tokens
+    #[serde(with = "pubkey_string")]
+    pub mint: Pubkey,
+    /// The receiving wallet, or token account for `MintToAccount`
+    #[serde(with = "pubkey_string")]
+    pub owner: Pubkey,
+    /// The amount in base units
+    pub amount: u64,
+    /// A value the backend never reuses, making every command unique
+    pub nonce: String,
+    /// Unix timestamp (seconds) after which the command is no longer accepted
+    pub expiry: u64,
+}
+
+/// A command as received, before verification
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CommandPayload {
+    operation: CommandOperation,
+    #[serde(with = "pubkey_string")]
+    mint: Pubkey,
+    #[serde(with = "pubkey_string")]
+    owner: Pubkey,
+    amount: u64,
+    nonce: String,
+    expiry: u64,
+}
+
+impl From<CommandPayload> for SignedCommand {
+    fn from(payload: CommandPayload) -> Self {
+        Self {
+            operation: payload.operation,
+            mint: payload.mint,
+            owner: payload.owner,
+            amount: payload.amount,
+            nonce: payload.nonce,
+            expiry: payload.expiry,
+        }
+    }
+}
+
+impl SignedCommand {
+    /// Returns the exact bytes the backend signs for this command
+    fn canonical_message(&self) -> SssResult<Vec<u8>> {
+        let json = serde_json::to_string(self).into_sss_error("Failed to serialize command")?;
+        Ok(format!("{}\n{}", COMMAND_HEADER, json).into_bytes())
+    }
+}
+
+/// Verifies a command signed by the backend
+///
+/// The nonce is used up by a successful verification, so a command is accepted once.
+///
+/// # Arguments
+///
+/// * `payload_json` - The command as JSON, see [`SignedCommand`] for the fields
+/// * `signature_b58` - The base58 ed25519 signature of the command's canonical message
+/// * `trusted_signer` - The backend's public key
+///
+/// # Returns
+///
+/// The verified command
+///
+/// # Errors
+///
+/// Returns a `KeypairError` if the payload or signature is malformed, the signature is not
+/// the trusted signer's, the command expired, or its nonce was already used, and a
+/// `ConfigError` if the nonce file cannot be locked, read, parsed or written. A nonce file
+/// that cannot be read refuses every command rather than forgetting the used nonces.
+pub fn verify_signed_command(
+    payload_json: &str,
+    signature_b58: &str,
+    trusted_signer: Pubkey,
+) -> SssResult<SignedCommand> {
+    let command: SignedCommand = serde_json::from_str::<CommandPayload>(payload_json)
+        .map_err(|e| SssError::KeypairError(format!("Invalid signed command: {}", e)))?
+        .into();
+    if command.nonce.is_empty() || command.nonce.len() > MAX_NONCE_LEN {
+        return Err(SssError::KeypairError(format!(
+            "Signed command nonce must be 1 to {} bytes long",
+            MAX_NONCE_LEN
+        )));
+    }
+    let signature = Signature::from_str(signature_b58)
+        .map_err(|e| SssError::KeypairError(format!("Invalid signed command signature: {}", e)))?;
+
+    if !verify_message(&trusted_signer, &command.canonical_message()?, &signature) {
+        return Err(SssError::KeypairError(
+            "Signed command was not signed by the trusted signer".to_string(),
+        ));
+    }
+
+    let now = unix_now();
+    if command.expiry <= now {
+        return Err(SssError::KeypairError(format!(
+            "Signed command {} expired at {}",
+            command.nonce, command.expiry
+        )));
+    }
+
+    let mut used = USED_COMMAND_NONCES
+        .lock()
+        .map_err(|e| SssError::KeypairError(format!("Command nonce store poisoned: {}", e)))?;
+
+    // Hold the file lock from reading the other processes' nonces until ours is written
+    let _file_lock = match COMMAND_NONCES_PATH.as_deref() {
+        Some(path) => {
+            let lock = lock_nonce_file(path)?;
+            used.extend(read_nonce_file(path)?);
+            Some(lock)
+        }
+        None => None,
+    };
+
+    used.retain(|_, expiry| *expiry > now);
+    if used.contains_key(&command.nonce) {
+        return Err(SssError::KeypairError(format!(
+            "Signed command nonce {} has already been used",
+            command.nonce
+        )));
+    }
+    used.insert(command.nonce.clone(), command.expiry);
+
+    if let Some(path) = COMMAND_NONCES_PATH.as_deref() {
+        // Persist before acting, so a crash cannot make the command valid again
+        if let Err(e) = write_nonce_file(path, &used) {
+            used.remove(&command.nonce);
+            return Err(e);
+        }
+    }
+
+    Ok(command)
+}
+
+/// Carries out a verified command
+///
+/// # Arguments
+///
+/// * `command` - The command returned by [`verify_signed_command`], consumed so it runs once
+///
+/// # Returns
+///
+/// The transaction signature
+///
+/// # Errors
+///
+/// Returns the error of the token operation, see [`mint_token`], [`mint_token_to_account`]
+/// and [`transfer_asset_with_options`]
+pub fn execute_signed_command(command: SignedCommand) -> SssResult<String> {
+    match command.operation {
+        CommandOperation::Mint => {
+            mint_token(command.mint, Some(command.owner), RawAmount(command.amount))
+        }
+        CommandOperation::MintToAccount => {
+            mint_token_to_account(command.mint, command.owner, RawAmount(command.amount))
+        }
+        CommandOperation::Transfer => transfer_asset_with_options(
+            command.mint,
+            command.owner,
+            &TransferOptions {
+                amount: Some(AmountSpec::raw(command.amount)),
+                ..TransferOptions::default()
+            },
+        ),
+    }
+}
+
+/// Takes an exclusive lock on the `.lock` file next to the nonce file
+///
+/// The lock is released when the returned file is dropped.
+fn lock_nonce_file(path: &Path) -> SssResult<File> {
+    let lock = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path.with_extension("lock"))
+        .map_err(|e| nonce_file_error("open the lock of", path, e))?;
+    lock.lock().map_err(|e| nonce_file_error("lock", path, e))?;
+    Ok(lock)
+}
+
+/// Reads the persisted nonces
+///
+/// Only a file that does not exist yet holds no nonces. Any other failure to read or parse
+/// it is returned, since treating it as empty would accept used nonces again.
+fn read_nonce_file(path: &Path) -> SssResult<BTreeMap<String, u64>> {
+    let json = match fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(nonce_file_error("read", path, e)),
+    };
+    serde_json::from_str(&json).map_err(|e| nonce_file_error("parse", path, e))
+}
+
+/// Replaces the nonce file with `nonces`, syncing the new file before the rename
+fn write_nonce_file(path: &Path, nonces: &BTreeMap<String, u64>) -> SssResult<()> {
+    let json = serde_json::to_string(nonces).map_err(|e| nonce_file_error("encode", path, e))?;
+    let tmp = path.with_extension("tmp");
+    File::create(&tmp)
+        .and_then(|mut file| {
+            file.write_all(json.as_bytes())?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&tmp, path))
+        .map_err(|e| nonce_file_error("write", path, e))
+}
+
+/// A failure to use the nonce file named by `SSS_COMMAND_NONCES_PATH`
+fn nonce_file_error(action: &str, path: &Path, error: impl std::fmt::Display) -> SssError {
+    SssError::ConfigError(format!(
+        "Failed to {} the command nonce file {}: {}",
+        action,
+        path.display(),
+        error
+    ))
+}
+
+/// Returns the current Unix time in seconds
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::{Keypair, Signer};
+
+    fn signed_payload(backend: &Keypair, nonce: &str, expiry: u64) -> (String, String) {
+        let command = SignedCommand {
+            operation: CommandOperation::Mint,
+            mint: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            amount: 5,
+            nonce: nonce.to_string(),
+            expiry,
+        };
+        let signature = backend.sign_message(&command.canonical_message().unwrap());
+        (
+            serde_json::to_string(&command).unwrap(),
+            signature.to_string(),
+        )
+    }
+
+    #[test]
+    fn canonical_message_orders_fields() {
+        let command = SignedCommand {
+            operation: CommandOperation::MintToAccount,
+            mint: Pubkey::default(),
+            owner: Pubkey::default(),
+            amount: 1,
+            nonce: "n-1".to_string(),
+            expiry: 7,
+        };
+        let message = String::from_utf8(command.canonical_message().unwrap()).unwrap();
+        assert_eq!(
+            message,
+            format!(
+                "SSS signed command\n{{\"operation\":\"mint_to_account\",\"mint\":\"{0}\",\"owner\":\"{0}\",\"amount\":1,\"nonce\":\"n-1\",\"expiry\":7}}",
+                Pubkey::default()
+            )
+        );
+    }
+
+    #[test]
+    fn reformatted_payload_is_accepted_once() {
+        let backend = Keypair::new();
+        let (payload, signature) = signed_payload(&backend, "reformatted", unix_now() + 60);
+        let reformatted = serde_json::to_string_pretty(
+            &serde_json::from_str::<serde_json::Value>(&payload).unwrap(),
+        )
+        .unwrap();
+
+        let command = verify_signed_command(&reformatted, &signature, backend.pubkey()).unwrap();
+        assert_eq!(command.amount, 5);
+        assert!(verify_signed_command(&payload, &signature, backend.pubkey()).is_err());
+    }
+
+    #[test]
+    fn other_signer_is_rejected() {
+        let (payload, signature) = signed_payload(&Keypair::new(), "other", unix_now() + 60);
+        assert!(verify_signed_command(&payload, &signature, Keypair::new().pubkey()).is_err());
+    }
+
+    #[test]
+    fn expired_command_is_rejected() {
+        let backend = Keypair::new();
+        let (payload, signature) = signed_payload(&backend, "expired", unix_now());
+        assert!(verify_signed_command(&payload, &signature, backend.pubkey()).is_err());
+    }
+
+    #[test]
+    fn malformed_payloads_are_rejected() {
+        let backend = Keypair::new();
+        let (payload, signature) = signed_payload(&backend, "", unix_now() + 60);
+        assert!(verify_signed_command(&payload, &signature, backend.pubkey()).is_err());
+
+        let long_nonce = "n".repeat(MAX_NONCE_LEN + 1);
+        let (payload, signature) = signed_payload(&backend, &long_nonce, unix_now() + 60);
+        assert!(verify_signed_command(&payload, &signature, backend.pubkey()).is_err());
+
+        let (payload, signature) = signed_payload(&backend, "extra", unix_now() + 60);
+        let extra = payload.replacen('{', "{\"memo\":\"x\",", 1);
+        assert!(verify_signed_command(&extra, &signature, backend.pubkey()).is_err());
+    }
+
+    #[test]
+    fn nonce_file_round_trips_and_leaves_a_lock_file() {
+        let dir = env::temp_dir().join(format!("sss-nonces-{}", rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("nonces.json");
+        assert!(read_nonce_file(&path).unwrap().is_empty());
+
+        let nonces = BTreeMap::from([("a".to_string(), 10), ("b".to_string(), 20)]);
+        {
+            let _lock = lock_nonce_file(&path).unwrap();
+            write_nonce_file(&path, &nonces).unwrap();
+        }
+        assert_eq!(read_nonce_file(&path).unwrap(), nonces);
+        assert!(path.with_extension("lock").exists());
+        assert!(!path.with_extension("tmp").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn unreadable_or_corrupt_nonce_files_are_errors() {
+        let dir = env::temp_dir().join(format!("sss-nonces-{}", rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+
+        // A directory where the file should be cannot be read
+        let unreadable = dir.join("nonces.json");
+        fs::create_dir_all(&unreadable).unwrap();
+        let error = read_nonce_file(&unreadable).unwrap_err();
+        assert!(
+            matches!(&error, SssError::ConfigError(m) if m.starts_with("Failed to read")),
+            "{:?}",
+            error
+        );
+
+        let corrupt = dir.join("corrupt.json");
+        for contents in ["", "{\"a\": 10", "[\"a\"]", "{\"a\": -1}"] {
+            fs::write(&corrupt, contents).unwrap();
+            let error = read_nonce_file(&corrupt).unwrap_err();
+            assert!(
+                matches!(&error, SssError::ConfigError(m) if m.starts_with("Failed to parse")),
+                "{:?}",
+                error
+            );
+        }
+
+        // Nor can a nonce file be written inside a missing directory
+        let orphan = dir.join("missing").join("nonces.json");
+        let error = write_nonce_file(&orphan, &BTreeMap::new()).unwrap_err();
+        assert!(matches!(error, SssError::ConfigError(_)));
+        assert!(matches!(
+            lock_nonce_file(&orphan),
+            Err(SssError::ConfigError(_))
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
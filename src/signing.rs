@@ -0,0 +1,359 @@
+//! Message signing and wallet ownership proofs
+
+use crate::error::{SssError, SssResult};
+use crate::journal::JournalOperation;
+use crate::metrics::track;
+use crate::payer::sign_off_chain;
+use lazy_static::lazy_static;
+use rand::RngCore;
+use serde_json::json;
+use solana_sdk::hash::hash;
+use solana_sdk::message::VersionedMessage;
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// First line of every serialized ownership challenge
+const CHALLENGE_HEADER: &str = "SSS ownership proof";
+
+/// Maximum number of outstanding challenges kept before the oldest are evicted
+pub const MAX_OUTSTANDING_CHALLENGES: usize = 10_000;
+
+lazy_static! {
+    /// Challenges issued by this process and not yet used, keyed by nonce
+    static ref ISSUED_CHALLENGES: Mutex<HashMap<String, IssuedChallenge>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Server-side record of an issued challenge
+#[derive(Debug, Clone, Copy)]
+struct IssuedChallenge {
+    owner: Pubkey,
+    expires_at: u64,
+}
+
+/// Signs an arbitrary message with the payer keypair
+///
+/// Runs as the operation `sign_message`, so a role policy must allow it. Bytes that read
+/// as a transaction message are refused: their signature would be a valid payer signature
+/// of the transaction, skipping every check made before a transaction is signed.
+///
+/// # Arguments
+///
+/// * `message` - The bytes to sign
+///
+/// # Returns
+///
+/// The ed25519 signature of the message
+///
+/// # Errors
+///
+/// Returns a `KeypairError` if the bytes read as a transaction message or the payer cannot
+/// be loaded, and a `ConfigError` in read-only mode or if the active role may not sign
+/// messages
+pub fn sign_message(message: &[u8]) -> SssResult<Signature> {
+    track(JournalOperation::SignMessage, || {
+        if is_transaction_message(message) {
+            return Err(SssError::KeypairError(
+                "Refusing to sign a transaction message as an off-chain message".to_string(),
+            ));
+        }
+        let params = json!({ "message_hash": hash(message).to_string() });
+        let (_, signature) = sign_off_chain(JournalOperation::SignMessage, &params, message)?;
+        Ok(signature)
+    })
+}
+
+/// Returns whether bytes start with a legacy or versioned transaction message
+///
+/// Trailing bytes are ignored, as a node would only need the message at the start.
+fn is_transaction_message(bytes: &[u8]) -> bool {
+    bincode::deserialize::<VersionedMessage>(bytes).is_ok()
+}
+
+/// Verifies that a message was signed by the given public key
+///
+/// # Arguments
+///
+/// * `pubkey` - The public key expected to have signed the message
+/// * `message` - The signed bytes
+/// * `signature` - The signature to check
+///
+/// # Returns
+///
+/// `true` if the signature is valid for the public key and message
+pub fn verify_message(pubkey: &Pubkey, message: &[u8], signature: &Signature) -> bool {
+    signature.verify(pubkey.as_ref(), message)
+}
+
+/// A challenge a wallet signs to prove it controls an address
+///
+/// The challenge is sent to the wallet in its string form, which is also the exact
+/// message the wallet must sign.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnershipChallenge {
+    /// The address whose ownership is being proven
+    pub owner: Pubkey,
+    /// A random base58 nonce making every challenge unique
+    pub nonce: String,
+    /// Unix timestamp (seconds) after which the challenge is no longer accepted
+    pub expires_at: u64,
+}
+
+impl fmt::Display for OwnershipChallenge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\nowner: {}\nnonce: {}\nexpires: {}",
+            CHALLENGE_HEADER, self.owner, self.nonce, self.expires_at
+        )
+    }
+}
+
+impl FromStr for OwnershipChallenge {
+    type Err = SssError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || SssError::KeypairError("Invalid ownership challenge format".to_string());
+
+        let mut lines = s.lines();
+        if lines.next() != Some(CHALLENGE_HEADER) {
+            return Err(invalid());
+        }
+
+        let mut field = |name: &str| {
+            lines
+                .next()
+                .and_then(|line| line.strip_prefix(name))
+                .and_then(|rest| rest.strip_prefix(": "))
+                .map(str::to_string)
+                .ok_or_else(invalid)
+        };
+
+        let owner = Pubkey::from_str(&field("owner")?).map_err(|_| invalid())?;
+        let nonce = field("nonce")?;
+        let expires_at = field("expires")?.parse().map_err(|_| invalid())?;
+
+        Ok(Self {
+            owner,
+            nonce,
+            expires_at,
+        })
+    }
+}
+
+/// Creates a new ownership challenge for an address
+///
+/// The challenge is recorded with its expiry so that only challenges issued by this
+/// process are accepted by `verify_ownership_proof`. Expired records are pruned on
+/// every call, and the oldest are evicted once `MAX_OUTSTANDING_CHALLENGES` is reached.
+///
+/// # Arguments
+///
+/// * `owner` - The address whose ownership should be proven
+/// * `ttl` - How long the challenge remains valid
+///
+/// # Returns
+///
+/// The challenge to be signed by the owner's wallet
+pub fn create_ownership_challenge(owner: Pubkey, ttl: Duration) -> OwnershipChallenge {
+    let mut nonce = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let now = unix_now();
+    let challenge = OwnershipChallenge {
+        owner,
+        nonce: bs58::encode(nonce).into_string(),
+        expires_at: now.saturating_add(ttl.as_secs()),
+    };
+
+    let mut issued = ISSUED_CHALLENGES
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    record_challenge(&mut issued, &challenge, now, MAX_OUTSTANDING_CHALLENGES);
+
+    challenge
+}
+
+/// Records an issued challenge, pruning expired records and evicting the soonest to
+/// expire while the store is full
+fn record_challenge(
+    issued: &mut HashMap<String, IssuedChallenge>,
+    challenge: &OwnershipChallenge,
+    now: u64,
+    capacity: usize,
+) {
+    issued.retain(|_, record| record.expires_at > now);
+    while issued.len() >= capacity {
+        let oldest = issued
+            .iter()
+            .min_by_key(|(_, record)| record.expires_at)
+            .map(|(nonce, _)| nonce.clone());
+        match oldest {
+            Some(nonce) => issued.remove(&nonce),
+            None => break,
+        };
+    }
+    issued.insert(
+        challenge.nonce.clone(),
+        IssuedChallenge {
+            owner: challenge.owner,
+            expires_at: challenge.expires_at,
+        },
+    );
+}
+
+/// Verifies a signed ownership challenge
+///
+/// Only challenges issued by `create_ownership_challenge` in this process are accepted,
+/// and each one only once: its record is removed when a valid proof is presented. The
+/// expiry is taken from the server-side record, not from the submitted challenge.
+///
+/// # Arguments
+///
+/// * `challenge` - The challenge that was signed
+/// * `signature` - The owner's signature over the challenge string
+///
+/// # Errors
+///
+/// Returns a `KeypairError` if the challenge was not issued here, expired, was already
+/// used, or the signature does not match the owner
+pub fn verify_ownership_proof(
+    challenge: &OwnershipChallenge,
+    signature: &Signature,
+) -> SssResult<()> {
+    let now = unix_now();
+    let mut issued = ISSUED_CHALLENGES
+        .lock()
+        .map_err(|e| SssError::KeypairError(format!("Challenge store poisoned: {}", e)))?;
+    issued.retain(|_, record| record.expires_at > now);
+
+    let record = issued.get(&challenge.nonce).copied().ok_or_else(|| {
+        SssError::KeypairError(
+            "Ownership challenge is unknown, expired or already used".to_string(),
+        )
+    })?;
+    if record.owner != challenge.owner || record.expires_at != challenge.expires_at {
+        return Err(SssError::KeypairError(
+            "Ownership challenge does not match the issued challenge".to_string(),
+        ));
+    }
+
+    if !verify_message(
+        &challenge.owner,
+        challenge.to_string().as_bytes(),
+        signature,
+    ) {
+        return Err(SssError::KeypairError(
+            "Signature does not match the challenge owner".to_string(),
+        ));
+    }
+
+    issued.remove(&challenge.nonce);
+    Ok(())
+}
+
+/// Returns the current Unix time in seconds
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::Keypair;
+    use solana_sdk::signer::Signer;
+
+    fn sign(keypair: &Keypair, challenge: &OwnershipChallenge) -> Signature {
+        keypair.sign_message(challenge.to_string().as_bytes())
+    }
+
+    #[test]
+    fn issued_challenge_is_accepted_once() {
+        let wallet = Keypair::new();
+        let challenge = create_ownership_challenge(wallet.pubkey(), Duration::from_secs(60));
+        let signature = sign(&wallet, &challenge);
+
+        assert!(verify_ownership_proof(&challenge, &signature).is_ok());
+        assert!(verify_ownership_proof(&challenge, &signature).is_err());
+    }
+
+    #[test]
+    fn challenge_round_trips_through_its_string_form() {
+        let challenge = create_ownership_challenge(Pubkey::new_unique(), Duration::from_secs(60));
+        let parsed: OwnershipChallenge = challenge.to_string().parse().unwrap();
+        assert_eq!(parsed, challenge);
+    }
+
+    #[test]
+    fn self_made_challenge_is_rejected() {
+        let wallet = Keypair::new();
+        let challenge = OwnershipChallenge {
+            owner: wallet.pubkey(),
+            nonce: "made-up".to_string(),
+            expires_at: u64::MAX,
+        };
+        let signature = sign(&wallet, &challenge);
+
+        assert!(verify_ownership_proof(&challenge, &signature).is_err());
+    }
+
+    #[test]
+    fn extended_expiry_is_rejected() {
+        let wallet = Keypair::new();
+        let mut challenge = create_ownership_challenge(wallet.pubkey(), Duration::from_secs(60));
+        challenge.expires_at = u64::MAX;
+        let signature = sign(&wallet, &challenge);
+
+        assert!(verify_ownership_proof(&challenge, &signature).is_err());
+    }
+
+    #[test]
+    fn expired_challenge_is_rejected() {
+        let wallet = Keypair::new();
+        let challenge = create_ownership_challenge(wallet.pubkey(), Duration::ZERO);
+        let signature = sign(&wallet, &challenge);
+
+        assert!(verify_ownership_proof(&challenge, &signature).is_err());
+    }
+
+    #[test]
+    fn wrong_signer_does_not_consume_the_challenge() {
+        let wallet = Keypair::new();
+        let challenge = create_ownership_challenge(wallet.pubkey(), Duration::from_secs(60));
+
+        let forged = sign(&Keypair::new(), &challenge);
+        assert!(verify_ownership_proof(&challenge, &forged).is_err());
+        assert!(verify_ownership_proof(&challenge, &sign(&wallet, &challenge)).is_ok());
+    }
+
+    #[test]
+    fn store_is_capped_and_pruned() {
+        let challenge = |nonce: &str, expires_at| OwnershipChallenge {
+            owner: Pubkey::new_unique(),
+            nonce: nonce.to_string(),
+            expires_at,
+        };
+        let mut issued = HashMap::new();
+        record_challenge(&mut issued, &challenge("expired", 5), 0, 3);
+        record_challenge(&mut issued, &challenge("soon", 20), 0, 3);
+        record_challenge(&mut issued, &challenge("late", 30), 0, 3);
+        assert_eq!(issued.len(), 3);
+
+        // At capacity the record expiring soonest makes room
+        record_challenge(&mut issued, &challenge("new", 40), 0, 3);
+        assert!(!issued.contains_key("expired"));
+        assert_eq!(issued.len(), 3);
+
+        // Expired records are dropped before the cap is considered
+        record_challenge(&mut issued, &challenge("newer", 50), 25, 3);
+        assert!(!issued.contains_key("soon"));
+        assert!(issued.contains_key("late") && issued.contains_key("newer"));
+    }
+}
@@ -0,0 +1,330 @@
+//! Resolution of Solana Name Service `.sol` domains
+//!
+//! A domain is an account of the name service program at an address derived from the
+//! hashed name and its parent, the `.sol` root. Its first 96 bytes hold the parent, the
+//! owner and the class; the owner is the wallet the domain resolves to. The reverse
+//! direction goes through the owner's favourite domain, kept by the name offers program,
+//! and the reverse lookup record holding the name of that domain account.
+
+use crate::RPC_CLIENT;
+use crate::cache::{BoundedCache, CacheLimits};
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::load_dotenv;
+use lazy_static::lazy_static;
+use solana_sdk::{account::Account, hash::hashv, pubkey, pubkey::Pubkey};
+use std::env;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// The name service program owning every domain account
+pub const NAME_SERVICE_PROGRAM_ID: Pubkey = pubkey!("namesLPneVptA9Z5rqUDD9tMTWEJwofgaYwp8cawRkX");
+
+/// The parent of every `.sol` domain
+pub const SOL_ROOT_DOMAIN: Pubkey = pubkey!("58PwtjSDuFHuUkYjH9BYnnQKHfwo9reZhC2zMJv9JPkx");
+
+/// The class of reverse lookup records, which map a domain account back to its name
+const REVERSE_LOOKUP_CLASS: Pubkey = pubkey!("33m47vH6Eav6jr5Ry86XjhRft2jRBLDnDgPSHoquXi2Z");
+
+/// The program keeping the favourite domain of each wallet
+const NAME_OFFERS_PROGRAM_ID: Pubkey = pubkey!("85iDfUvr3HJyLM2zcq5BXSiDvUWfw6cSE1FfNBo8Ap29");
+
+/// Prefix hashed together with every name
+const HASH_PREFIX: &str = "SPL Name Service";
+
+/// Size of the header of a name account: parent, owner and class
+const NAME_HEADER_LEN: usize = 96;
+
+/// Time a resolved domain is reused, unless configured otherwise
+pub const DEFAULT_SNS_CACHE_TTL: Duration = Duration::from_secs(300);
+
+lazy_static! {
+    /// Whether FFI recipient parameters accept domains, seeded from `SSS_FFI_RESOLVE_DOMAINS`
+    static ref FFI_RESOLVE_DOMAINS: AtomicBool = {
+        load_dotenv();
+        let enabled = env::var("SSS_FFI_RESOLVE_DOMAINS")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+        AtomicBool::new(enabled)
+    };
+
+    /// Time resolutions are cached, from `SSS_SNS_CACHE_TTL_SECS`
+    static ref CACHE_TTL: Duration = {
+        load_dotenv();
+        env::var("SSS_SNS_CACHE_TTL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_SNS_CACHE_TTL)
+    };
+
+    /// Owners of resolved domains by normalized domain
+    pub(crate) static ref DOMAIN_CACHE: BoundedCache<String, Pubkey> = BoundedCache::new(
+        "sns_domain",
+        CacheLimits {
+            ttl: Some(*CACHE_TTL),
+            ..CacheLimits::default()
+        },
+        |domain, _| domain.capacity(),
+    );
+
+    /// Primary domains by wallet, `None` for wallets without one
+    pub(crate) static ref PRIMARY_CACHE: BoundedCache<Pubkey, Option<String>> = BoundedCache::new(
+        "sns_primary",
+        CacheLimits {
+            ttl: Some(*CACHE_TTL),
+            ..CacheLimits::default()
+        },
+        |_, domain| domain.as_ref().map_or(0, String::capacity),
+    );
+}
+
+/// Resolves a recipient given as a base58 public key or a `.sol` domain
+///
+/// Domains resolve to the owner of their name account, e.g. `alice.sol` or the subdomain
+/// `shop.alice.sol`, and are cached for `SSS_SNS_CACHE_TTL_SECS` (default 5 minutes).
+///
+/// # Arguments
+///
+/// * `input` - The public key or domain, as pasted by a user
+///
+/// # Returns
+///
+/// The wallet to send to
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the input is neither, if the domain is not registered or has
+/// no owner, or if it is owned by a program account such as the escrow of a domain
+/// wrapped as an NFT, and an `RpcError` if the name account cannot be read
+pub fn resolve_recipient(input: &str) -> SssResult<Pubkey> {
+    let input = input.trim();
+    if let Ok(pubkey) = Pubkey::from_str(input) {
+        return Ok(pubkey);
+    }
+    if !is_sol_domain(input) {
+        return Err(SssError::TokenError(format!(
+            "Recipient '{}' is neither a public key nor a .sol domain",
+            input
+        )));
+    }
+    let domain = input.to_lowercase();
+    if let Some(owner) = DOMAIN_CACHE.get(&domain) {
+        return Ok(owner);
+    }
+
+    let key = domain_key(&domain)?;
+    let account = fetch_accounts(&[key])?
+        .pop()
+        .flatten()
+        .ok_or_else(|| SssError::TokenError(format!("Domain {} is not registered", domain)))?;
+    let owner = name_owner(&key, &account)?;
+    if owner == Pubkey::default() {
+        return Err(SssError::TokenError(format!(
+            "Domain {} has no owner; it expired or was released",
+            domain
+        )));
+    }
+    if !owner.is_on_curve() {
+        return Err(SssError::TokenError(format!(
+            "Domain {} is owned by the program account {}, e.g. because it is wrapped as an \
+             NFT; resolve it to the holder's wallet instead",
+            domain, owner
+        )));
+    }
+    DOMAIN_CACHE.insert(domain, owner);
+    Ok(owner)
+}
+
+/// Returns the primary `.sol` domain of a wallet, for display
+///
+/// The primary domain is the one the wallet marked as its favourite. A favourite the
+/// wallet no longer owns is ignored. Results are cached like [`resolve_recipient`].
+///
+/// # Arguments
+///
+/// * `wallet` - The wallet whose domain is looked up
+///
+/// # Returns
+///
+/// The domain including the `.sol` suffix, `None` if the wallet has none
+///
+/// # Errors
+///
+/// Returns an `RpcError` if the accounts cannot be read, and a `TokenError` if a name
+/// record is malformed
+pub fn primary_domain_for(wallet: Pubkey) -> SssResult<Option<String>> {
+    if let Some(domain) = PRIMARY_CACHE.get(&wallet) {
+        return Ok(domain);
+    }
+    let domain = lookup_primary_domain(&wallet)?;
+    PRIMARY_CACHE.insert(wallet, domain.clone());
+    Ok(domain)
+}
+
+/// Allows or forbids `.sol` domains in the recipient parameters of FFI functions
+///
+/// Applies to the owner of `mint_token_ffi` and the recipient of `sss_transfer_asset`, and
+/// to the same parameters of `sss_call`. Rust callers resolve with [`resolve_recipient`]
+/// themselves.
+///
+/// # Arguments
+///
+/// * `enabled` - Whether domains are resolved, overriding `SSS_FFI_RESOLVE_DOMAINS`
+pub fn set_ffi_domain_resolution(enabled: bool) {
+    FFI_RESOLVE_DOMAINS.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns whether FFI recipient parameters accept `.sol` domains
+#[cfg(feature = "ffi")]
+pub(crate) fn ffi_domain_resolution() -> bool {
+    FFI_RESOLVE_DOMAINS.load(Ordering::Relaxed)
+}
+
+/// Returns whether an input names a `.sol` domain rather than a public key
+pub(crate) fn is_sol_domain(input: &str) -> bool {
+    input.trim().to_lowercase().ends_with(".sol")
+}
+
+/// Derives the name account of a lowercase domain, with or without one subdomain
+fn domain_key(domain: &str) -> SssResult<Pubkey> {
+    let name = domain.strip_suffix(".sol").unwrap_or(domain);
+    let labels: Vec<&str> = name.split('.').collect();
+    if labels.iter().any(|label| label.is_empty()) || labels.len() > 2 {
+        return Err(SssError::TokenError(format!(
+            "Domain {} is not of the form name.sol or sub.name.sol",
+            domain
+        )));
+    }
+    let parent = name_account_key(labels[labels.len() - 1], None, Some(&SOL_ROOT_DOMAIN));
+    Ok(match labels.as_slice() {
+        // Subdomain names are prefixed with a zero byte, which sets them apart from records
+        [sub, _] => name_account_key(&format!("\0{}", sub), None, Some(&parent)),
+        _ => parent,
+    })
+}
+
+/// Derives a name account from its name, class and parent
+fn name_account_key(name: &str, class: Option<&Pubkey>, parent: Option<&Pubkey>) -> Pubkey {
+    let hashed = hashv(&[HASH_PREFIX.as_bytes(), name.as_bytes()]);
+    let none = Pubkey::default();
+    Pubkey::find_program_address(
+        &[
+            hashed.as_ref(),
+            class.unwrap_or(&none).as_ref(),
+            parent.unwrap_or(&none).as_ref(),
+        ],
+        &NAME_SERVICE_PROGRAM_ID,
+    )
+    .0
+}
+
+/// Finds the favourite domain of a wallet and the name its reverse lookup record holds
+fn lookup_primary_domain(wallet: &Pubkey) -> SssResult<Option<String>> {
+    let favourite = Pubkey::find_program_address(
+        &[b"favourite_domain", wallet.as_ref()],
+        &NAME_OFFERS_PROGRAM_ID,
+    )
+    .0;
+    let Some(account) = fetch_accounts(&[favourite])?.pop().flatten() else {
+        return Ok(None);
+    };
+    // A tag byte precedes the domain account
+    let Some(domain) = account
+        .data
+        .get(1..33)
+        .and_then(|bytes| Pubkey::try_from(bytes).ok())
+    else {
+        return Err(SssError::TokenError(format!(
+            "Favourite domain record {} is malformed",
+            favourite
+        )));
+    };
+
+    let mut accounts = fetch_accounts(&[domain, reverse_key(&domain, None)])?.into_iter();
+    let (Some(Some(domain_account)), Some(reverse)) = (accounts.next(), accounts.next()) else {
+        return Ok(None);
+    };
+    if name_owner(&domain, &domain_account)? != *wallet {
+        return Ok(None);
+    }
+    let parent = name_parent(&domain, &domain_account)?;
+    if parent == SOL_ROOT_DOMAIN {
+        return reverse
+            .map(|reverse| reverse_name(&domain, &reverse).map(|name| format!("{}.sol", name)))
+            .transpose();
+    }
+
+    // A subdomain's reverse record is a child of its parent, whose own record names it
+    let mut accounts = fetch_accounts(&[
+        reverse_key(&domain, Some(&parent)),
+        reverse_key(&parent, None),
+    ])?
+    .into_iter();
+    let (Some(Some(sub)), Some(Some(parent_reverse))) = (accounts.next(), accounts.next()) else {
+        return Ok(None);
+    };
+    let sub = reverse_name(&domain, &sub)?;
+    let parent_name = reverse_name(&parent, &parent_reverse)?;
+    Ok(Some(format!(
+        "{}.{}.sol",
+        sub.trim_start_matches('\0'),
+        parent_name
+    )))
+}
+
+/// Derives the reverse lookup record of a domain account
+fn reverse_key(domain: &Pubkey, parent: Option<&Pubkey>) -> Pubkey {
+    name_account_key(&domain.to_string(), Some(&REVERSE_LOOKUP_CLASS), parent)
+}
+
+/// Reads the owner from the header of a name account
+fn name_owner(key: &Pubkey, account: &Account) -> SssResult<Pubkey> {
+    header_key(key, account, 32)
+}
+
+/// Reads the parent from the header of a name account
+fn name_parent(key: &Pubkey, account: &Account) -> SssResult<Pubkey> {
+    header_key(key, account, 0)
+}
+
+fn header_key(key: &Pubkey, account: &Account, offset: usize) -> SssResult<Pubkey> {
+    if account.owner != NAME_SERVICE_PROGRAM_ID || account.data.len() < NAME_HEADER_LEN {
+        return Err(SssError::TokenError(format!(
+            "Account {} is not a name service record",
+            key
+        )));
+    }
+    Pubkey::try_from(&account.data[offset..offset + 32]).map_err(|e| {
+        SssError::TokenError(format!("Name service record {} is malformed: {}", key, e))
+    })
+}
+
+/// Reads the name held by a reverse lookup record, a length-prefixed string after the
+/// header
+fn reverse_name(domain: &Pubkey, account: &Account) -> SssResult<String> {
+    let malformed = || {
+        SssError::TokenError(format!(
+            "Reverse lookup record of domain account {} is malformed",
+            domain
+        ))
+    };
+    let data = account
+        .data
+        .get(NAME_HEADER_LEN..)
+        .filter(|_| account.owner == NAME_SERVICE_PROGRAM_ID)
+        .ok_or_else(malformed)?;
+    let len = data
+        .get(..4)
+        .and_then(|len| len.try_into().ok())
+        .map(u32::from_le_bytes)
+        .ok_or_else(malformed)? as usize;
+    let name = data.get(4..4 + len).ok_or_else(malformed)?;
+    String::from_utf8(name.to_vec()).map_err(|_| malformed())
+}
+
+fn fetch_accounts(keys: &[Pubkey]) -> SssResult<Vec<Option<Account>>> {
+    RPC_CLIENT
+        .get_multiple_accounts(keys)
+        .into_sss_error("Failed to get name service accounts from rpc")
+}
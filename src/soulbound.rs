@@ -0,0 +1,90 @@
+//! Soulbound tokens, which their holders cannot transfer to another wallet
+//!
+//! Token-2022 mints enforce this on-chain with the NonTransferable extension. The legacy
+//! token program has no equivalent, so this library keeps every recipient account of a
+//! legacy soulbound mint frozen, with the payer retaining the freeze authority. Nothing
+//! on-chain marks a legacy mint as soulbound, so such mints are tracked in a set seeded
+//! from `SSS_SOULBOUND_MINTS`; mints created by this process are added automatically.
+
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::load_dotenv;
+use lazy_static::lazy_static;
+use solana_sdk::{account::Account, pubkey::Pubkey};
+use spl_token_2022::extension::non_transferable::NonTransferable;
+use spl_token_2022::extension::{BaseStateWithExtensions, StateWithExtensions};
+use std::collections::HashSet;
+use std::env;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+lazy_static! {
+    /// Legacy soulbound mints, seeded from the comma-separated `SSS_SOULBOUND_MINTS` variable
+    static ref SOULBOUND_MINTS: RwLock<HashSet<Pubkey>> = {
+        load_dotenv();
+        let mints = env::var("SSS_SOULBOUND_MINTS")
+            .map(|list| {
+                list.split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .filter_map(|s| Pubkey::from_str(s).ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        RwLock::new(mints)
+    };
+}
+
+/// Marks a legacy mint as soulbound
+///
+/// Later mints freeze the recipient account, and transfers are refused. Token-2022 mints
+/// need no marking, since the NonTransferable extension is read from the mint.
+///
+/// # Arguments
+///
+/// * `mint` - The legacy mint whose tokens must not be transferred
+pub fn add_soulbound_mint(mint: Pubkey) {
+    if let Ok(mut soulbound) = SOULBOUND_MINTS.write() {
+        soulbound.insert(mint);
+    }
+}
+
+/// Returns whether a mint is soulbound, given its account
+///
+/// # Arguments
+///
+/// * `mint` - The mint to look up
+/// * `account` - The mint account
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the account is not a token mint
+pub fn is_soulbound_mint(mint: &Pubkey, account: &Account) -> SssResult<bool> {
+    if account.owner == spl_token::id() {
+        return Ok(is_registered(mint));
+    }
+    if account.owner != spl_token_2022::id() {
+        return Err(SssError::TokenError(format!(
+            "Account {} is not a token mint",
+            mint
+        )));
+    }
+    let state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&account.data)
+        .into_sss_error("Failed to parse token mint account")?;
+    Ok(state.get_extension::<NonTransferable>().is_ok())
+}
+
+/// Returns whether a legacy mint was marked soulbound
+pub(crate) fn is_registered(mint: &Pubkey) -> bool {
+    SOULBOUND_MINTS
+        .read()
+        .map(|soulbound| soulbound.contains(mint))
+        .unwrap_or(false)
+}
+
+/// The error returned when a soulbound token would be transferred
+pub(crate) fn soulbound_transfer_error(mint: &Pubkey) -> SssError {
+    SssError::TokenError(format!(
+        "Mint {} is soulbound, so its tokens cannot be transferred",
+        mint
+    ))
+}
@@ -0,0 +1,566 @@
+//! Structured data signed off-chain, such as coupons authorizing a discount
+//!
+//! The backend signs a payload with [`sign_structured`]; the kiosk attaches it to a
+//! redemption and checks it with [`verify_structured`]. The signature covers a
+//! deterministic encoding of the payload, so any platform that follows its rules
+//! computes the same bytes from the same JSON, however it is formatted: see
+//! [`canonical_encoding`] for the encoding and [`sign_structured`] for what is signed.
+
+use crate::error::{SssError, SssResult};
+use crate::journal::JournalOperation;
+use crate::metrics::track;
+use crate::payer::sign_off_chain;
+use crate::roles::active_role;
+use crate::serde_utils::pubkey_string;
+use serde::de::{self, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use solana_sdk::hash::{hash, hashv};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use std::collections::BTreeMap;
+use std::fmt;
+use unicode_normalization::UnicodeNormalization;
+
+/// Prefix of every signed digest, separating structured data from other signed messages
+const DIGEST_PREFIX: &[u8] = b"SSS structured data v1\0";
+
+/// What a structured signature is for, hashed into the digest of [`sign_structured`]
+///
+/// Fields left `None` are not part of the encoded domain.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SigningDomain {
+    /// The application or kind of authorization, e.g. `"LD coupons"`
+    pub name: String,
+    /// The payload schema version, e.g. `"1"`
+    pub version: String,
+    /// The cluster the authorization is valid on, e.g. `"mainnet-beta"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cluster: Option<String>,
+    /// Any further value telling deployments apart
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub salt: Option<String>,
+}
+
+/// A signature over structured data, see [`sign_structured`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StructuredSignature {
+    /// The key that signed, the payer of `role`
+    #[serde(with = "pubkey_string")]
+    pub signer: Pubkey,
+    /// The signer role that signed
+    pub role: String,
+    /// The base58 ed25519 signature of the digest
+    pub signature: String,
+    /// The signed digest, hex-encoded, for comparing implementations
+    pub digest: String,
+}
+
+/// Signs structured data with the payer of the active signer role
+///
+/// Select the role with [`crate::with_profile`]; without a profile the default payer
+/// signs. Signing runs as the operation `sign_structured`, so a role policy must allow it.
+///
+/// The ed25519 signature covers the 32-byte digest
+/// `SHA-256("SSS structured data v1" || 0x00 || SHA-256(domain) || SHA-256(payload))`, where
+/// `domain` and `payload` are canonical encodings and the domain is the JSON object of its
+/// fields. The domain separates applications and versions, so a coupon can never be
+/// replayed as another kind of authorization.
+///
+/// # Arguments
+///
+/// * `domain` - What the signature is for
+/// * `payload` - The data to sign, e.g. a coupon
+///
+/// # Returns
+///
+/// The signature with the signer, role and digest
+///
+/// # Errors
+///
+/// Returns a `KeypairError` if the payload cannot be encoded (see
+/// [`canonical_encoding`]), the payer cannot be loaded or the library is watch-only, and a
+/// `ConfigError` in read-only mode or if the active role may not sign structured data
+pub fn sign_structured(
+    domain: &SigningDomain,
+    payload: &impl Serialize,
+) -> SssResult<StructuredSignature> {
+    let json = serde_json::to_string(payload)
+        .map_err(|e| SssError::KeypairError(format!("Invalid structured payload: {}", e)))?;
+    sign_structured_json(domain, &json)
+}
+
+/// Signs structured data given as JSON, see [`sign_structured`]
+pub(crate) fn sign_structured_json(
+    domain: &SigningDomain,
+    payload_json: &str,
+) -> SssResult<StructuredSignature> {
+    track(JournalOperation::SignStructured, || {
+        let digest = structured_digest(domain, payload_json)?;
+        let digest_hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+        let params = json!({ "domain": domain, "digest": digest_hex });
+        let (signer, signature) =
+            sign_off_chain(JournalOperation::SignStructured, &params, &digest)?;
+        Ok(StructuredSignature {
+            signer,
+            role: active_role(),
+            signature: signature.to_string(),
+            digest: digest_hex,
+        })
+    })
+}
+
+/// Verifies a structured signature
+///
+/// # Arguments
+///
+/// * `domain` - What the signature must be for
+/// * `payload_json` - The signed data as JSON, in any formatting
+/// * `signature` - The signature to check
+/// * `expected_signer` - The key that must have signed
+///
+/// # Returns
+///
+/// `true` if `expected_signer` signed this payload for this domain
+///
+/// # Errors
+///
+/// Returns a `KeypairError` if the payload is not JSON or cannot be encoded
+pub fn verify_structured(
+    domain: &SigningDomain,
+    payload_json: &str,
+    signature: &Signature,
+    expected_signer: &Pubkey,
+) -> SssResult<bool> {
+    let digest = structured_digest(domain, payload_json)?;
+    Ok(signature.verify(expected_signer.as_ref(), &digest))
+}
+
+/// Returns the canonical encoding of a JSON document
+///
+/// A JSON value is encoded with a one-byte type tag followed by its content:
+///
+/// | Tag | Type | Content |
+/// |-----|------|---------|
+/// | `0x00` | null | nothing |
+/// | `0x01` | boolean | `0x00` for false, `0x01` for true |
+/// | `0x02` | integer | 16 bytes, big-endian two's complement |
+/// | `0x03` | string | byte length as 4 bytes big-endian, then the NFC-normalized UTF-8 bytes |
+/// | `0x04` | array | element count as 4 bytes big-endian, then each element |
+/// | `0x05` | object | entry count as 4 bytes big-endian, then each entry |
+///
+/// An object entry is its key, encoded like a string without the tag, followed by its
+/// value; entries are sorted by the bytes of their NFC-normalized keys. Numbers with a
+/// fraction or exponent, and integers outside the 64-bit range, are rejected: encode them
+/// as strings. Keys that are equal after normalization are rejected too.
+///
+/// # Errors
+///
+/// Returns a `KeypairError` if the document is not JSON, has a non-integer number or has
+/// keys that are equal after normalization
+pub fn canonical_encoding(json: &str) -> SssResult<Vec<u8>> {
+    let value: CanonicalValue = serde_json::from_str(json)
+        .map_err(|e| SssError::KeypairError(format!("Invalid structured payload: {}", e)))?;
+    let mut encoded = Vec::new();
+    value.encode(&mut encoded);
+    Ok(encoded)
+}
+
+/// Computes the digest a structured signature covers
+fn structured_digest(domain: &SigningDomain, payload_json: &str) -> SssResult<[u8; 32]> {
+    let domain_json = serde_json::to_string(domain)
+        .map_err(|e| SssError::KeypairError(format!("Invalid signing domain: {}", e)))?;
+    let domain_hash = hash(&canonical_encoding(&domain_json)?);
+    let payload_hash = hash(&canonical_encoding(payload_json)?);
+    Ok(hashv(&[DIGEST_PREFIX, domain_hash.as_ref(), payload_hash.as_ref()]).to_bytes())
+}
+
+/// A JSON value as it is encoded, with normalized strings and sorted keys
+enum CanonicalValue {
+    Null,
+    Bool(bool),
+    Integer(i128),
+    String(String),
+    Array(Vec<CanonicalValue>),
+    Object(BTreeMap<String, CanonicalValue>),
+}
+
+impl CanonicalValue {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            CanonicalValue::Null => out.push(0x00),
+            CanonicalValue::Bool(value) => out.extend([0x01, u8::from(*value)]),
+            CanonicalValue::Integer(value) => {
+                out.push(0x02);
+                out.extend(value.to_be_bytes());
+            }
+            CanonicalValue::String(value) => {
+                out.push(0x03);
+                encode_str(value, out);
+            }
+            CanonicalValue::Array(items) => {
+                out.push(0x04);
+                out.extend(encoded_len(items.len()));
+                for item in items {
+                    item.encode(out);
+                }
+            }
+            // BTreeMap iterates in byte order of the keys
+            CanonicalValue::Object(entries) => {
+                out.push(0x05);
+                out.extend(encoded_len(entries.len()));
+                for (key, value) in entries {
+                    encode_str(key, out);
+                    value.encode(out);
+                }
+            }
+        }
+    }
+}
+
+/// Appends a normalized string's length and bytes
+fn encode_str(value: &str, out: &mut Vec<u8>) {
+    out.extend(encoded_len(value.len()));
+    out.extend(value.as_bytes());
+}
+
+/// Encodes a length as 4 bytes big-endian; JSON documents never reach 4 GiB
+fn encoded_len(len: usize) -> [u8; 4] {
+    u32::try_from(len).unwrap_or(u32::MAX).to_be_bytes()
+}
+
+impl<'de> Deserialize<'de> for CanonicalValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(CanonicalVisitor)
+    }
+}
+
+struct CanonicalVisitor;
+
+impl<'de> Visitor<'de> for CanonicalVisitor {
+    type Value = CanonicalValue;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a JSON value without fractional numbers")
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(CanonicalValue::Null)
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<Self::Value, E> {
+        Ok(CanonicalValue::Bool(value))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+        Ok(CanonicalValue::Integer(value.into()))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+        Ok(CanonicalValue::Integer(value.into()))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Self::Value, E> {
+        Err(E::custom(format!(
+            "number {} has a fraction or exponent or is outside the 64-bit integer range; \
+             encode it as a string",
+            value
+        )))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        Ok(CanonicalValue::String(value.nfc().collect()))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut items = Vec::new();
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(CanonicalValue::Array(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut entries = BTreeMap::new();
+        while let Some((key, value)) = map.next_entry::<String, CanonicalValue>()? {
+            let key: String = key.nfc().collect();
+            if entries.contains_key(&key) {
+                return Err(de::Error::custom(format!(
+                    "key '{}' appears more than once (after Unicode normalization)",
+                    key
+                )));
+            }
+            entries.insert(key, value);
+        }
+        Ok(CanonicalValue::Object(entries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn encoded(json: &str) -> String {
+        hex(&canonical_encoding(json).unwrap())
+    }
+
+    fn refused(json: &str) -> String {
+        canonical_encoding(json).unwrap_err().to_string()
+    }
+
+    const PAYLOAD: &str = r#"{"coupon":"SPRING-10","discount_bps":1000,"expires_at":1767225600,
+        "kiosk":{"id":7,"tags":["café",null,true]}}"#;
+
+    fn domain() -> SigningDomain {
+        SigningDomain {
+            name: "LD coupons".to_string(),
+            version: "1".to_string(),
+            cluster: Some("devnet".to_string()),
+            salt: None,
+        }
+    }
+
+    // The vectors below were computed by an independent implementation of the documented
+    // encoding; a change to them breaks every signature already issued
+
+    #[test]
+    fn every_type_has_its_tag() {
+        assert_eq!(encoded("null"), "00");
+        assert_eq!(encoded("false"), "0100");
+        assert_eq!(encoded("true"), "0101");
+        assert_eq!(encoded("1"), format!("02{}01", "00".repeat(15)));
+        assert_eq!(encoded("-1"), format!("02{}", "ff".repeat(16)));
+        assert_eq!(
+            encoded("18446744073709551615"),
+            format!("02{}{}", "00".repeat(8), "ff".repeat(8))
+        );
+        assert_eq!(
+            encoded("-9223372036854775808"),
+            format!("02{}80{}", "ff".repeat(8), "00".repeat(7))
+        );
+        assert_eq!(encoded(r#""""#), "0300000000");
+        assert_eq!(encoded(r#""ab""#), "03000000026162");
+        assert_eq!(encoded("[]"), "0400000000");
+        assert_eq!(encoded("{}"), "0500000000");
+        assert_eq!(
+            encoded(r#"{"b":[1,-1],"a":null}"#),
+            "05000000020000000161000000000162040000000202000000000000000000000000000000\
+             0102ffffffffffffffffffffffffffffffff"
+        );
+    }
+
+    #[test]
+    fn a_coupon_encodes_and_hashes_to_its_vector() {
+        assert_eq!(
+            encoded(PAYLOAD),
+            "050000000400000006636f75706f6e0300000009535052494e472d31300000000c646973636f756e\
+             745f62707302000000000000000000000000000003e80000000a657870697265735f617402000000\
+             0000000000000000006955b900000000056b696f736b050000000200000002696402000000000000\
+             00000000000000000007000000047461677304000000030300000005636166c3a9000101"
+        );
+        assert_eq!(
+            hex(&structured_digest(&domain(), PAYLOAD).unwrap()),
+            "a7371c97265d1e8b9baebb73ae1404be472b9ffd6cf24f8f7755d2bfa619067b"
+        );
+    }
+
+    #[test]
+    fn formatting_and_key_order_do_not_matter_at_any_depth() {
+        let reordered = r#"
+            {
+              "kiosk" : { "tags" : [ "café" , null , true ] , "id" : 7 },
+              "expires_at" : 1767225600 ,
+              "discount_bps" : 1000,
+              "coupon" : "SPRING-10"
+            }
+        "#;
+        assert_eq!(encoded(reordered), encoded(PAYLOAD));
+
+        let deep = r#"{"z":{"y":{"x":{"b":1,"a":2}}},"a":[{"d":1,"c":2},{"f":[{"h":1,"g":2}]}]}"#;
+        let shuffled =
+            r#"{"a":[{"c":2,"d":1},{"f":[{"g":2,"h":1}]}],"z":{"y":{"x":{"a":2,"b":1}}}}"#;
+        assert_eq!(encoded(deep), encoded(shuffled));
+
+        // Keys sort by their bytes, so upper case comes first and a prefix before its
+        // extensions
+        assert_eq!(
+            canonical_encoding(r#"{"ab":0,"a":0,"B":0,"é":0,"z":0}"#).unwrap(),
+            canonical_encoding(r#"{"B":0,"a":0,"ab":0,"z":0,"é":0}"#).unwrap()
+        );
+        let keys = canonical_encoding(r#"{"ab":0,"a":0,"B":0,"é":0,"z":0}"#).unwrap();
+        let order: Vec<usize> = ["\"B\"", "\"a\"", "\"ab\"", "\"z\"", "\"é\""]
+            .iter()
+            .map(|key| {
+                let key = key.trim_matches('"').as_bytes();
+                keys.windows(key.len() + 4)
+                    .position(|w| w[..4] == (key.len() as u32).to_be_bytes() && &w[4..] == key)
+                    .unwrap()
+            })
+            .collect();
+        assert!(
+            order.windows(2).all(|pair| pair[0] < pair[1]),
+            "{:?}",
+            order
+        );
+    }
+
+    #[test]
+    fn array_order_and_nesting_matter() {
+        assert_ne!(encoded("[1,2]"), encoded("[2,1]"));
+        assert_ne!(encoded("[[1],2]"), encoded("[1,[2]]"));
+        assert_ne!(
+            encoded(r#"{"a":{"b":1}}"#),
+            encoded(r#"{"a":{"b":{}},"b":1}"#)
+        );
+        assert_ne!(encoded(r#"{"a":[]}"#), encoded(r#"{"a":{}}"#));
+        assert_ne!(encoded(r#"{"a":null}"#), encoded("{}"));
+        // Types are told apart by their tag, not their text
+        assert_ne!(encoded("1"), encoded(r#""1""#));
+        assert_ne!(encoded("true"), encoded(r#""true""#));
+        assert_ne!(encoded("0"), encoded("false"));
+        assert_ne!(encoded("null"), encoded(r#""""#));
+        // Length prefixes keep neighbouring strings apart
+        assert_ne!(encoded(r#"["ab","c"]"#), encoded(r#"["a","bc"]"#));
+    }
+
+    #[test]
+    fn numbers_that_are_not_64_bit_integers_are_refused() {
+        for number in [
+            "1.0",
+            "1.5",
+            "-0",
+            "-0.0",
+            "0.1",
+            "1e3",
+            "1E-3",
+            "-2.5e10",
+            "18446744073709551616",
+            "-9223372036854775809",
+        ] {
+            let error = refused(number);
+            assert!(
+                error.contains("encode it as a string"),
+                "{}: {}",
+                number,
+                error
+            );
+            let nested = format!(r#"{{"a":[{{"b":{}}}]}}"#, number);
+            assert!(
+                refused(&nested).contains("encode it as a string"),
+                "{}",
+                nested
+            );
+        }
+        // The bounds of i64 and u64 are accepted
+        assert_eq!(encoded("9223372036854775807"), {
+            let mut bytes = vec![0x02];
+            bytes.extend(i128::from(i64::MAX).to_be_bytes());
+            hex(&bytes)
+        });
+    }
+
+    #[test]
+    fn strings_and_keys_are_nfc_normalized() {
+        // "é" precomposed and as "e" with a combining acute accent
+        let composed = "\u{e9}";
+        let decomposed = "e\u{301}";
+        assert_eq!(
+            encoded(&format!(r#""caf{}""#, composed)),
+            encoded(&format!(r#""caf{}""#, decomposed))
+        );
+        assert_eq!(encoded(&format!(r#""{}""#, decomposed)), "0300000002c3a9");
+        assert_eq!(
+            encoded(&format!(r#"{{"{}":1}}"#, composed)),
+            encoded(&format!(r#"{{"{}":1}}"#, decomposed))
+        );
+        // Escapes are read before normalizing
+        assert_eq!(encoded(r#""\u00e9""#), encoded(r#""e\u0301""#));
+        // Hangul syllables compose, and compatibility characters are left alone
+        assert_eq!(encoded("\"\u{1100}\u{1161}\""), encoded("\"\u{ac00}\""));
+        assert_ne!(encoded("\"\u{fb01}\""), encoded("\"fi\""));
+        // Combining marks are put in canonical order
+        assert_eq!(
+            encoded("\"a\u{323}\u{307}\""),
+            encoded("\"a\u{307}\u{323}\"")
+        );
+        // Characters outside the basic plane pass through, written out or as surrogates
+        assert_eq!(encoded("\"\u{1f600}\""), "0300000004f09f9880");
+        assert_eq!(encoded(r#""\ud83d\ude00""#), "0300000004f09f9880");
+    }
+
+    #[test]
+    fn keys_repeated_before_or_after_normalization_are_refused() {
+        assert!(refused(r#"{"a":1,"a":2}"#).contains("key 'a' appears more than once"));
+        let error = refused(&format!(r#"{{"{}":1,"{}":1}}"#, "\u{e9}", "e\u{301}"));
+        assert!(error.contains("appears more than once"), "{}", error);
+        assert!(refused(r#"{"x":{"a":1,"a":1}}"#).contains("appears more than once"));
+        // Different keys in different objects are fine
+        assert!(canonical_encoding(r#"{"a":{"a":1},"b":{"a":1}}"#).is_ok());
+    }
+
+    #[test]
+    fn malformed_json_is_refused() {
+        for json in [
+            "",
+            "{",
+            r#"{"a":}"#,
+            "[1,]",
+            "NaN",
+            "Infinity",
+            "'a'",
+            "{} {}",
+            "1e400",
+        ] {
+            let error = refused(json);
+            assert!(
+                error.contains("Invalid structured payload"),
+                "{:?}: {}",
+                json,
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn the_domain_separates_signatures() {
+        let digest = |domain: &SigningDomain| structured_digest(domain, PAYLOAD).unwrap();
+        let base = digest(&domain());
+        for other in [
+            SigningDomain {
+                name: "LD refunds".to_string(),
+                ..domain()
+            },
+            SigningDomain {
+                version: "2".to_string(),
+                ..domain()
+            },
+            SigningDomain {
+                cluster: Some("mainnet-beta".to_string()),
+                ..domain()
+            },
+            SigningDomain {
+                cluster: None,
+                ..domain()
+            },
+            SigningDomain {
+                salt: Some("kiosk fleet A".to_string()),
+                ..domain()
+            },
+        ] {
+            assert_ne!(digest(&other), base, "{:?}", other);
+        }
+        // An unset field is left out rather than encoded as null
+        let empty: SigningDomain = serde_json::from_str(r#"{"name":"a","version":"1"}"#).unwrap();
+        assert_eq!(
+            serde_json::to_string(&empty).unwrap(),
+            r#"{"name":"a","version":"1"}"#
+        );
+        assert!(
+            serde_json::from_str::<SigningDomain>(r#"{"name":"a","version":"1","x":1}"#).is_err()
+        );
+    }
+}
@@ -0,0 +1,279 @@
+//! Token balance change notifications
+//!
+//! Balances are polled by a single background thread that fetches the accounts of every
+//! subscription in one RPC call per interval, so many watchers cost no more than one.
+//! Callbacks run on their own thread behind a bounded queue, see
+//! [`crate::set_callback_queue_options`], so a slow callback does not hold up polling.
+
+use crate::dispatch::{
+    CallbackQueue, Delivery, Dispatcher, QueueMetrics, QueueOptions, idle_queue_metrics,
+};
+use crate::error::{SssError, SssResult};
+use crate::events::{SssEvent, emit};
+use crate::mint_cache::mint_info;
+use crate::{RPC_CLIENT, load_dotenv};
+use lazy_static::lazy_static;
+use solana_rpc_client_api::request::MAX_MULTIPLE_ACCOUNTS;
+use solana_sdk::pubkey::Pubkey;
+use spl_token_2022::extension::StateWithExtensions;
+use std::collections::HashMap;
+use std::env;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Default time between balance polls
+pub const DEFAULT_BALANCE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Called with the new balance in base units
+type BalanceCallback = Arc<dyn Fn(u64) + Send + Sync>;
+
+lazy_static! {
+    /// Time between balance polls, from `SSS_BALANCE_POLL_MS`
+    static ref POLL_INTERVAL: Duration = {
+        load_dotenv();
+        env::var("SSS_BALANCE_POLL_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_BALANCE_POLL_INTERVAL)
+    };
+
+    /// Active subscriptions by id
+    static ref SUBSCRIPTIONS: Mutex<HashMap<u64, Subscription>> = Mutex::new(HashMap::new());
+
+    /// Queue to the callback thread, `None` until the first notification
+    static ref QUEUE: Mutex<Option<Arc<Dispatcher<Notification>>>> = Mutex::new(None);
+}
+
+/// A new balance for the callback of a subscription
+type Notification = (u64, BalanceCallback, u64);
+
+/// Id handed out to the next subscription; ids start at 1
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Whether the polling thread is running
+static POLLING: AtomicBool = AtomicBool::new(false);
+
+/// A watched token account
+struct Subscription {
+    token_account: Pubkey,
+    callback: BalanceCallback,
+    /// The balance last delivered, `None` until the first successful poll
+    last: Option<u64>,
+}
+
+/// Calls `callback` whenever the balance of a wallet's associated token account changes
+///
+/// The current balance is delivered on the first poll. If polling fails, e.g. because the
+/// RPC node is unreachable, the current balance is delivered again once it recovers, so a
+/// change made during the outage is never missed. A missing account counts as a balance
+/// of 0. Callbacks run on a background thread, one at a time; a balance dropped from a
+/// full queue is delivered again after the next poll.
+///
+/// # Arguments
+///
+/// * `mint` - The public key of the token's mint account
+/// * `owner` - The wallet whose balance is watched
+/// * `callback` - Called with the new balance in base units
+///
+/// # Returns
+///
+/// The subscription id to pass to [`unsubscribe_token_balance`]
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the mint does not exist or is not a token mint
+pub fn subscribe_token_balance(
+    mint: Pubkey,
+    owner: Pubkey,
+    callback: impl Fn(u64) + Send + Sync + 'static,
+) -> SssResult<u64> {
+    let token_program = mint_info(&mint)?.token_program;
+    let token_account = spl_associated_token_account::get_associated_token_address_with_program_id(
+        &owner,
+        &mint,
+        &token_program.id(),
+    );
+
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    subscriptions()?.insert(
+        id,
+        Subscription {
+            token_account,
+            callback: Arc::new(callback),
+            last: None,
+        },
+    );
+    start_polling();
+    Ok(id)
+}
+
+/// Stops the notifications of a subscription
+///
+/// # Arguments
+///
+/// * `id` - The id returned by [`subscribe_token_balance`]
+///
+/// # Returns
+///
+/// Whether the subscription existed
+pub fn unsubscribe_token_balance(id: u64) -> bool {
+    subscriptions()
+        .map(|mut subs| subs.remove(&id).is_some())
+        .unwrap_or(false)
+}
+
+/// Stops the notifications of every subscription
+pub(crate) fn clear_subscriptions() {
+    if let Ok(mut subs) = subscriptions() {
+        subs.clear();
+    }
+}
+
+fn subscriptions() -> SssResult<std::sync::MutexGuard<'static, HashMap<u64, Subscription>>> {
+    SUBSCRIPTIONS
+        .lock()
+        .map_err(|e| SssError::RpcError(format!("Balance subscriptions poisoned: {}", e)))
+}
+
+/// Starts the polling thread unless it is running
+///
+/// The thread stops once the last subscription is removed.
+fn start_polling() {
+    if POLLING
+        .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+        .is_err()
+    {
+        return;
+    }
+
+    thread::spawn(|| {
+        while let Ok(accounts) = subscriptions().map(|subs| {
+            subs.iter()
+                .map(|(id, sub)| (*id, sub.token_account))
+                .collect::<Vec<_>>()
+        }) {
+            if accounts.is_empty() {
+                POLLING.store(false, Ordering::SeqCst);
+                // A subscription added meanwhile would otherwise go unpolled
+                let resumed = subscriptions().is_ok_and(|subs| !subs.is_empty())
+                    && POLLING
+                        .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+                        .is_ok();
+                if resumed {
+                    continue;
+                }
+                break;
+            }
+
+            for notification in poll(&accounts) {
+                notify(notification);
+            }
+            thread::sleep(*POLL_INTERVAL);
+        }
+    });
+}
+
+/// Fetches the watched balances and returns the callbacks to notify
+///
+/// Subscriptions whose accounts could not be fetched are reset so their balance is
+/// delivered again on the next successful poll.
+fn poll(accounts: &[(u64, Pubkey)]) -> Vec<Notification> {
+    let mut balances = HashMap::new();
+    for chunk in accounts.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let keys: Vec<Pubkey> = chunk.iter().map(|(_, account)| *account).collect();
+        let Ok(fetched) = RPC_CLIENT.get_multiple_accounts(&keys) else {
+            continue;
+        };
+        for ((id, _), account) in chunk.iter().zip(fetched) {
+            let balance = match account {
+                Some(account) => {
+                    match StateWithExtensions::<spl_token_2022::state::Account>::unpack(
+                        &account.data,
+                    ) {
+                        Ok(state) => state.base.amount,
+                        Err(_) => continue,
+                    }
+                }
+                None => 0,
+            };
+            balances.insert(*id, balance);
+        }
+    }
+
+    let Ok(mut subs) = subscriptions() else {
+        return Vec::new();
+    };
+    let mut notifications = Vec::new();
+    for (id, _) in accounts {
+        // Skip subscriptions removed while polling
+        let Some(sub) = subs.get_mut(id) else {
+            continue;
+        };
+        match balances.get(id) {
+            Some(&balance) if sub.last != Some(balance) => {
+                sub.last = Some(balance);
+                notifications.push((*id, sub.callback.clone(), balance));
+            }
+            Some(_) => {}
+            None => sub.last = None,
+        }
+    }
+    notifications
+}
+
+/// Queues a notification for the callback thread
+///
+/// A dropped notification resets its subscription, so the balance is delivered again after
+/// the next poll.
+fn notify(notification: Notification) {
+    let Ok(mut queue) = QUEUE.lock() else {
+        return;
+    };
+    let dispatcher = queue
+        .get_or_insert_with(|| Dispatcher::start(CallbackQueue::BalanceCallbacks, deliver))
+        .clone();
+    drop(queue);
+    if let Some((id, _, _)) = dispatcher.push(notification)
+        && let Ok(mut subs) = subscriptions()
+        && let Some(sub) = subs.get_mut(&id)
+    {
+        sub.last = None;
+    }
+}
+
+/// Calls the callback of a subscription that is still active, or reports dropped
+/// notifications to the event sink
+fn deliver(delivery: Delivery<Notification>) {
+    match delivery {
+        Delivery::Item((id, callback, balance)) => {
+            if subscriptions().is_ok_and(|subs| subs.contains_key(&id)) {
+                callback(balance);
+            }
+        }
+        Delivery::Dropped(count) => emit(SssEvent::EventsDropped {
+            queue: CallbackQueue::BalanceCallbacks,
+            count,
+        }),
+    }
+}
+
+/// Returns the state of the balance callback queue
+pub(crate) fn balance_queue_metrics() -> QueueMetrics {
+    let dispatcher = QUEUE.lock().ok().and_then(|queue| queue.clone());
+    match dispatcher {
+        Some(dispatcher) => dispatcher.metrics(),
+        None => idle_queue_metrics(CallbackQueue::BalanceCallbacks),
+    }
+}
+
+/// Applies new options to the balance callback queue if it is running
+pub(crate) fn apply_queue_options(options: QueueOptions) {
+    if let Ok(queue) = QUEUE.lock()
+        && let Some(dispatcher) = queue.as_ref()
+    {
+        dispatcher.set_options(options);
+    }
+}
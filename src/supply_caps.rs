@@ -0,0 +1,284 @@
+//! Client-side per-mint supply caps
+
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::load_dotenv;
+use crate::serde_utils::pubkey_string;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use spl_token_2022::extension::StateWithExtensions;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+lazy_static! {
+    /// File the caps are persisted to, from `SSS_SUPPLY_CAPS_PATH`
+    static ref SUPPLY_CAPS_PATH: Option<PathBuf> = {
+        load_dotenv();
+        env::var("SSS_SUPPLY_CAPS_PATH").ok().map(PathBuf::from)
+    };
+
+    /// Supply cap per mint, loaded from the caps file if it exists
+    static ref SUPPLY_CAPS: RwLock<BTreeMap<Pubkey, u64>> = {
+        let caps = SUPPLY_CAPS_PATH
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|json| parse_caps(&json).ok())
+            .unwrap_or_default();
+        RwLock::new(caps)
+    };
+}
+
+/// A mint whose supply ended up above its cap
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SupplyCapViolation {
+    /// The mint that exceeded its cap
+    #[serde(with = "pubkey_string")]
+    pub mint: Pubkey,
+    /// The supply read after the mint confirmed
+    pub supply: u64,
+    /// The configured cap
+    pub cap: u64,
+}
+
+/// A supply cap changed by reloading the caps file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SupplyCapChange {
+    /// The mint whose cap changed
+    #[serde(with = "pubkey_string")]
+    pub mint: Pubkey,
+    /// The cap before the reload, `None` if the mint had none
+    pub previous: Option<u64>,
+    /// The cap after the reload, `None` if it was removed
+    pub cap: Option<u64>,
+}
+
+/// Sets the maximum supply of a mint and persists the registry
+///
+/// # Arguments
+///
+/// * `mint` - The public key of the token's mint account
+/// * `cap` - The maximum supply in base units
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if the caps file cannot be written
+pub fn set_supply_cap(mint: Pubkey, cap: u64) -> SssResult<()> {
+    update_caps(|caps| {
+        caps.insert(mint, cap);
+    })
+}
+
+/// Removes the supply cap of a mint and persists the registry
+///
+/// # Arguments
+///
+/// * `mint` - The public key of the token's mint account
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if the caps file cannot be written
+pub fn remove_supply_cap(mint: Pubkey) -> SssResult<()> {
+    update_caps(|caps| {
+        caps.remove(&mint);
+    })
+}
+
+/// Returns the supply cap of a mint, if one is set
+///
+/// # Arguments
+///
+/// * `mint` - The public key of the token's mint account
+pub fn supply_cap(mint: &Pubkey) -> Option<u64> {
+    SUPPLY_CAPS.read().ok()?.get(mint).copied()
+}
+
+/// Exports every supply cap as a JSON object mapping mint addresses to caps
+///
+/// # Returns
+///
+/// The JSON document, in the same format as the caps file
+pub fn export_supply_caps() -> SssResult<String> {
+    let caps = SUPPLY_CAPS
+        .read()
+        .map_err(|e| SssError::ConfigError(format!("Supply caps lock poisoned: {}", e)))?;
+    caps_to_json(&caps)
+}
+
+/// Imports supply caps exported by another instance and persists the registry
+///
+/// Imported caps replace existing caps of the same mints; other caps are kept.
+///
+/// # Arguments
+///
+/// * `json` - A document produced by [`export_supply_caps`]
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if the document is invalid or the caps file cannot be written
+pub fn import_supply_caps(json: &str) -> SssResult<()> {
+    let imported = parse_caps(json)?;
+    update_caps(|caps| caps.extend(imported))
+}
+
+/// Refuses a mint that would take the supply above its cap
+///
+/// Only reads the supply when the mint has a cap.
+///
+/// # Arguments
+///
+/// * `client` - The RPC client reading the supply
+/// * `mint` - The public key of the token's mint account
+/// * `amount` - The amount about to be minted
+///
+/// # Errors
+///
+/// Returns a `TokenError` with the current supply and the cap if the cap would be exceeded
+pub(crate) fn ensure_within_supply_cap(
+    client: &RpcClient,
+    mint: &Pubkey,
+    amount: u64,
+) -> SssResult<()> {
+    let Some(cap) = supply_cap(mint) else {
+        return Ok(());
+    };
+    let supply = current_supply(client, mint)?;
+    if supply.saturating_add(amount) > cap {
+        return Err(SssError::TokenError(format!(
+            "Minting {} would exceed the supply cap of mint {}: current supply {}, cap {}",
+            amount, mint, supply, cap
+        )));
+    }
+    Ok(())
+}
+
+/// Replaces the registry with the contents of the caps file
+///
+/// The file is read while holding the registry lock, so caps set concurrently are either
+/// in the file already or applied afterwards.
+///
+/// # Returns
+///
+/// The caps that changed, sorted by mint, or `None` if no caps file is configured or it
+/// does not exist
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if the file cannot be read or parsed; the registry is unchanged
+pub(crate) fn reload_supply_caps() -> SssResult<Option<Vec<SupplyCapChange>>> {
+    let Some(path) = SUPPLY_CAPS_PATH.as_ref() else {
+        return Ok(None);
+    };
+    let mut caps = SUPPLY_CAPS
+        .write()
+        .map_err(|e| SssError::ConfigError(format!("Supply caps lock poisoned: {}", e)))?;
+    let json = match fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(e).into_sss_error(&format!(
+                "Failed to read supply cap config {}",
+                path.display()
+            ));
+        }
+    };
+    let reloaded = parse_caps(&json)?;
+
+    let mut changes: Vec<_> = reloaded
+        .iter()
+        .filter(|(mint, cap)| caps.get(mint) != Some(cap))
+        .map(|(mint, cap)| SupplyCapChange {
+            mint: *mint,
+            previous: caps.get(mint).copied(),
+            cap: Some(*cap),
+        })
+        .collect();
+    changes.extend(
+        caps.iter()
+            .filter(|(mint, _)| !reloaded.contains_key(mint))
+            .map(|(mint, cap)| SupplyCapChange {
+                mint: *mint,
+                previous: Some(*cap),
+                cap: None,
+            }),
+    );
+    changes.sort_unstable_by_key(|change| change.mint);
+    *caps = reloaded;
+    Ok(Some(changes))
+}
+
+/// Re-reads the supply after a confirmed mint to catch concurrent mints past the cap
+///
+/// # Arguments
+///
+/// * `client` - The RPC client reading the supply
+/// * `mint` - The public key of the token's mint account
+///
+/// # Returns
+///
+/// The violation, if the mint has a cap and its supply is above it
+pub(crate) fn check_supply_after_mint(
+    client: &RpcClient,
+    mint: &Pubkey,
+) -> Option<SupplyCapViolation> {
+    let cap = supply_cap(mint)?;
+    let supply = current_supply(client, mint).ok()?;
+    (supply > cap).then_some(SupplyCapViolation {
+        mint: *mint,
+        supply,
+        cap,
+    })
+}
+
+/// Reads the current supply from the mint account
+fn current_supply(client: &RpcClient, mint: &Pubkey) -> SssResult<u64> {
+    let data = client
+        .get_account_data(mint)
+        .into_sss_error("Failed to get mint account from rpc")?;
+    let state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)
+        .into_sss_error("Failed to parse token mint account")?;
+    Ok(state.base.supply)
+}
+
+/// Applies a change to the registry and writes it to the caps file, if configured
+fn update_caps(change: impl FnOnce(&mut BTreeMap<Pubkey, u64>)) -> SssResult<()> {
+    let mut caps = SUPPLY_CAPS
+        .write()
+        .map_err(|e| SssError::ConfigError(format!("Supply caps lock poisoned: {}", e)))?;
+    change(&mut caps);
+
+    let Some(path) = SUPPLY_CAPS_PATH.as_ref() else {
+        return Ok(());
+    };
+    let json = caps_to_json(&caps)?;
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, json).into_sss_error("Failed to write supply cap config")?;
+    fs::rename(&tmp, path).into_sss_error("Failed to write supply cap config")
+}
+
+/// Serializes the caps as a JSON object mapping mint addresses to caps
+fn caps_to_json(caps: &BTreeMap<Pubkey, u64>) -> SssResult<String> {
+    let json: BTreeMap<String, u64> = caps
+        .iter()
+        .map(|(mint, cap)| (mint.to_string(), *cap))
+        .collect();
+    serde_json::to_string_pretty(&json).into_sss_error("Failed to serialize supply cap config")
+}
+
+/// Parses a JSON object mapping mint addresses to caps
+fn parse_caps(json: &str) -> SssResult<BTreeMap<Pubkey, u64>> {
+    let raw: BTreeMap<String, u64> =
+        serde_json::from_str(json).into_sss_error("Failed to parse supply cap config")?;
+    raw.into_iter()
+        .map(|(mint, cap)| {
+            Pubkey::from_str(&mint)
+                .map(|mint| (mint, cap))
+                .into_sss_error("Invalid mint in supply cap config")
+        })
+        .collect()
+}
@@ -0,0 +1,531 @@
+//! Alerts on mint supply changes that an emission schedule does not explain
+//!
+//! Each monitor polls the supply of one mint on its own thread and compares it with the
+//! schedule at the cluster's current time. A compromised mint authority minting extra
+//! tokens shows up as supply above the schedule; the alert lists the transactions that
+//! changed the supply since the previous poll, decoded like a [`crate::Receipt`].
+
+use crate::RPC_CLIENT;
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::events::{SssEvent, emit};
+use crate::expiry::{cluster_time, fetch_cluster_time};
+use crate::mint_cache::{MintInfo, mint_info};
+use crate::receipt::fetch_transaction_receipt_with_client;
+use crate::serde_utils::pubkey_string;
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use solana_rpc_client::rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender, channel};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Longest wait between supply polls while the RPC node fails
+pub const SUPPLY_MONITOR_MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Signatures requested per page when listing the mint's transactions
+const SIGNATURE_PAGE_SIZE: usize = 1000;
+
+/// Pages of signatures listed per poll, bounding the work after a long outage
+const MAX_SIGNATURE_PAGES: usize = 10;
+
+/// Called with each anomaly on the monitor's thread
+type AnomalyCallback = Arc<dyn Fn(&SupplyAnomaly) + Send + Sync>;
+
+lazy_static! {
+    /// Running monitors by id; dropping a sender stops its monitor
+    static ref MONITORS: Mutex<HashMap<u64, Sender<()>>> = Mutex::new(HashMap::new());
+}
+
+/// Id handed out to the next monitor; ids start at 1
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// How the expected supply moves between the points of a schedule
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduleInterpolation {
+    /// The supply jumps to each point's amount at its time
+    Step,
+    /// The supply grows or shrinks linearly from one point to the next
+    Linear,
+}
+
+/// The expected supply of a mint over time
+///
+/// The supply is expected to be 0 before the first point and to stay at the last point's
+/// amount after it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SupplySchedule {
+    points: Vec<(DateTime<Utc>, u64)>,
+    interpolation: ScheduleInterpolation,
+    tolerance: u64,
+}
+
+impl SupplySchedule {
+    /// Creates a schedule from its points, in any order
+    ///
+    /// # Arguments
+    ///
+    /// * `points` - The expected supply in base units at each time
+    /// * `interpolation` - How the supply moves between points
+    /// * `tolerance` - The largest deviation in base units that is not an anomaly
+    pub fn new(
+        points: impl IntoIterator<Item = (DateTime<Utc>, u64)>,
+        interpolation: ScheduleInterpolation,
+        tolerance: u64,
+    ) -> Self {
+        let mut points: Vec<_> = points.into_iter().collect();
+        points.sort_by_key(|(at, _)| *at);
+        Self {
+            points,
+            interpolation,
+            tolerance,
+        }
+    }
+
+    /// Creates a step schedule, see [`ScheduleInterpolation::Step`]
+    pub fn step(points: impl IntoIterator<Item = (DateTime<Utc>, u64)>, tolerance: u64) -> Self {
+        Self::new(points, ScheduleInterpolation::Step, tolerance)
+    }
+
+    /// Creates a piecewise-linear schedule, see [`ScheduleInterpolation::Linear`]
+    pub fn linear(points: impl IntoIterator<Item = (DateTime<Utc>, u64)>, tolerance: u64) -> Self {
+        Self::new(points, ScheduleInterpolation::Linear, tolerance)
+    }
+
+    /// The largest deviation in base units that is not an anomaly
+    pub fn tolerance(&self) -> u64 {
+        self.tolerance
+    }
+
+    /// Returns the supply in base units the schedule expects at a time
+    ///
+    /// # Arguments
+    ///
+    /// * `at` - The time, e.g. from [`crate::cluster_time`]
+    pub fn expected_at(&self, at: DateTime<Utc>) -> u64 {
+        let reached = self.points.partition_point(|(time, _)| *time <= at);
+        let Some(&(start_time, start_supply)) = reached.checked_sub(1).map(|i| &self.points[i])
+        else {
+            return 0;
+        };
+        match (self.interpolation, self.points.get(reached)) {
+            (ScheduleInterpolation::Linear, Some(&(end_time, end_supply))) => {
+                // end_time is after at, which is at or after start_time, so span is positive
+                let span = i128::from((end_time - start_time).num_milliseconds());
+                let elapsed = i128::from((at - start_time).num_milliseconds());
+                let start = i128::from(start_supply);
+                let end = i128::from(end_supply);
+                u64::try_from(start + (end - start) * elapsed / span.max(1)).unwrap_or(start_supply)
+            }
+            _ => start_supply,
+        }
+    }
+}
+
+/// A supply outside the tolerance of the schedule
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SupplyAnomaly {
+    /// The mint
+    #[serde(with = "pubkey_string")]
+    pub mint: Pubkey,
+    /// The supply in base units the schedule expects
+    pub expected: u64,
+    /// The supply in base units on chain
+    pub actual: u64,
+    /// `actual - expected`, saturated at the range of an `i64`
+    pub delta: i64,
+    /// The tolerance of the schedule
+    pub tolerance: u64,
+    /// Successful transactions since the previous poll that changed the supply, oldest
+    /// first, including any whose effect could not be read
+    pub signatures: Vec<String>,
+}
+
+/// A running supply monitor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MonitorHandle {
+    id: u64,
+}
+
+impl MonitorHandle {
+    /// The id to pass to [`stop_supply_monitor`]
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Stops the monitor, returning whether it was running
+    pub fn stop(&self) -> bool {
+        stop_supply_monitor(self.id)
+    }
+
+    /// Returns whether the monitor is running
+    pub fn is_running(&self) -> bool {
+        monitors().is_ok_and(|monitors| monitors.contains_key(&self.id))
+    }
+}
+
+/// Polls the supply of a mint and emits `SssEvent::SupplyAnomaly` when it strays from a
+/// schedule by more than its tolerance
+///
+/// The first poll runs at once. An anomaly is reported once per supply value: again when
+/// the supply changes while still off schedule, and not again while it stays unchanged.
+/// While the RPC node fails, the wait between polls doubles up to
+/// [`SUPPLY_MONITOR_MAX_BACKOFF`] and returns to `poll_interval` after the next successful
+/// poll. Dropping the handle does not stop the monitor; [`crate::shutdown`] stops every one.
+///
+/// # Arguments
+///
+/// * `mint` - The public key of the token's mint account
+/// * `expected` - The emission schedule
+/// * `poll_interval` - Time between polls while the RPC node answers
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if the schedule has no points or the interval is zero, and a
+/// `TokenError` if the mint does not exist or is not a token mint
+pub fn monitor_supply(
+    mint: Pubkey,
+    expected: SupplySchedule,
+    poll_interval: Duration,
+) -> SssResult<MonitorHandle> {
+    start_monitor(None, mint, expected, poll_interval, None)
+}
+
+/// Monitors the supply of a mint like [`monitor_supply`], polling the given RPC client
+///
+/// The cluster time is read from the client on every poll rather than from the cache of
+/// [`crate::cluster_time`].
+///
+/// # Arguments
+///
+/// * `client` - The RPC client used for every read of the monitor
+/// * `mint` - The public key of the token's mint account
+/// * `expected` - The emission schedule
+/// * `poll_interval` - Time between polls while the RPC node answers
+///
+/// # Errors
+///
+/// Returns the errors of [`monitor_supply`]
+pub fn monitor_supply_with_client(
+    client: Arc<RpcClient>,
+    mint: Pubkey,
+    expected: SupplySchedule,
+    poll_interval: Duration,
+) -> SssResult<MonitorHandle> {
+    start_monitor(Some(client), mint, expected, poll_interval, None)
+}
+
+/// Monitors the supply of a mint like [`monitor_supply`], also calling `callback` with
+/// each anomaly
+///
+/// # Arguments
+///
+/// * `mint` - The public key of the token's mint account
+/// * `expected` - The emission schedule
+/// * `poll_interval` - Time between polls while the RPC node answers
+/// * `callback` - Called with each anomaly on the monitor's thread
+///
+/// # Errors
+///
+/// Returns the errors of [`monitor_supply`]
+pub fn monitor_supply_with_callback(
+    mint: Pubkey,
+    expected: SupplySchedule,
+    poll_interval: Duration,
+    callback: impl Fn(&SupplyAnomaly) + Send + Sync + 'static,
+) -> SssResult<MonitorHandle> {
+    start_monitor(
+        None,
+        mint,
+        expected,
+        poll_interval,
+        Some(Arc::new(callback)),
+    )
+}
+
+/// Stops a supply monitor
+///
+/// A poll already running completes, but reports nothing after this returns.
+///
+/// # Arguments
+///
+/// * `id` - The id of the monitor's handle
+///
+/// # Returns
+///
+/// Whether the monitor was running
+pub fn stop_supply_monitor(id: u64) -> bool {
+    monitors()
+        .map(|mut monitors| monitors.remove(&id).is_some())
+        .unwrap_or(false)
+}
+
+/// Stops every supply monitor
+pub(crate) fn stop_supply_monitors() {
+    if let Ok(mut monitors) = monitors() {
+        monitors.clear();
+    }
+}
+
+fn monitors() -> SssResult<std::sync::MutexGuard<'static, HashMap<u64, Sender<()>>>> {
+    MONITORS
+        .lock()
+        .map_err(|e| SssError::RpcError(format!("Supply monitors poisoned: {}", e)))
+}
+
+/// Starts a monitor polling `client`, or the global client if `None`
+fn start_monitor(
+    client: Option<Arc<RpcClient>>,
+    mint: Pubkey,
+    expected: SupplySchedule,
+    poll_interval: Duration,
+    callback: Option<AnomalyCallback>,
+) -> SssResult<MonitorHandle> {
+    if expected.points.is_empty() {
+        return Err(SssError::ConfigError(
+            "Supply schedule has no points".to_string(),
+        ));
+    }
+    if poll_interval.is_zero() {
+        return Err(SssError::ConfigError(
+            "Supply poll interval must be positive".to_string(),
+        ));
+    }
+    match &client {
+        Some(client) => {
+            let account = client
+                .get_account(&mint)
+                .into_sss_error("Failed to get mint account from rpc")?;
+            MintInfo::from_account(&mint, &account)?;
+        }
+        None => {
+            mint_info(&mint)?;
+        }
+    }
+
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let (stop, stopped) = channel::<()>();
+    monitors()?.insert(id, stop);
+    let monitor = Monitor {
+        client,
+        mint,
+        schedule: expected,
+        started: false,
+        until: None,
+        reported: None,
+    };
+    thread::spawn(move || run(id, monitor, poll_interval, callback, stopped));
+    Ok(MonitorHandle { id })
+}
+
+/// Polls until the monitor is stopped
+fn run(
+    id: u64,
+    mut monitor: Monitor,
+    poll_interval: Duration,
+    callback: Option<AnomalyCallback>,
+    stopped: Receiver<()>,
+) {
+    let mut wait = Duration::ZERO;
+    // Dropping the sender disconnects the channel, which ends the thread at once
+    while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(wait) {
+        wait = match monitor.poll() {
+            Ok(anomaly) => {
+                // Nothing is reported by a monitor stopped while it was polling
+                if let Some(anomaly) = anomaly
+                    && monitors().is_ok_and(|monitors| monitors.contains_key(&id))
+                {
+                    if let Some(callback) = &callback {
+                        callback(&anomaly);
+                    }
+                    emit(SssEvent::SupplyAnomaly { anomaly });
+                }
+                poll_interval
+            }
+            Err(_) => wait
+                .max(poll_interval)
+                .saturating_mul(2)
+                .min(SUPPLY_MONITOR_MAX_BACKOFF.max(poll_interval)),
+        };
+    }
+}
+
+/// The state of a monitor between polls
+struct Monitor {
+    /// The client polled, `None` for the global one
+    client: Option<Arc<RpcClient>>,
+    mint: Pubkey,
+    schedule: SupplySchedule,
+    /// Whether a poll listed the mint's transactions yet
+    started: bool,
+    /// The newest transaction of the mint seen, `None` if it had none
+    until: Option<Signature>,
+    /// The supply last reported as an anomaly, `None` while on schedule
+    reported: Option<u64>,
+}
+
+impl Monitor {
+    fn client(&self) -> &RpcClient {
+        self.client.as_deref().unwrap_or(&RPC_CLIENT)
+    }
+
+    /// Reads the supply and returns the anomaly to report, if any
+    fn poll(&mut self) -> SssResult<Option<SupplyAnomaly>> {
+        let supply = self
+            .client()
+            .get_token_supply(&self.mint)
+            .into_sss_error("Failed to get token supply from rpc")?;
+        let actual = supply.amount.parse::<u64>().map_err(|e| {
+            SssError::RpcError(format!("Invalid token supply {}: {}", supply.amount, e))
+        })?;
+        let now = match &self.client {
+            Some(client) => fetch_cluster_time(client)?,
+            None => cluster_time()?,
+        };
+        let signatures = self.new_signatures()?;
+
+        let expected = self.schedule.expected_at(now);
+        let delta = i128::from(actual) - i128::from(expected);
+        if delta.unsigned_abs() <= u128::from(self.schedule.tolerance) {
+            self.reported = None;
+            return Ok(None);
+        }
+        if self.reported == Some(actual) {
+            return Ok(None);
+        }
+        self.reported = Some(actual);
+        Ok(Some(SupplyAnomaly {
+            mint: self.mint,
+            expected,
+            actual,
+            delta: i64::try_from(delta).unwrap_or(if delta > 0 { i64::MAX } else { i64::MIN }),
+            tolerance: self.schedule.tolerance,
+            signatures: supply_changes(self.client(), &self.mint, signatures),
+        }))
+    }
+
+    /// Lists the transactions of the mint since the previous poll, oldest first
+    ///
+    /// The first poll only records the newest transaction, so older history is never
+    /// attributed to an anomaly.
+    fn new_signatures(&mut self) -> SssResult<Vec<Signature>> {
+        let limit = if self.started { SIGNATURE_PAGE_SIZE } else { 1 };
+        let mut signatures = Vec::new();
+        let mut before = None;
+        for _ in 0..MAX_SIGNATURE_PAGES {
+            let page = self
+                .client()
+                .get_signatures_for_address_with_config(
+                    &self.mint,
+                    GetConfirmedSignaturesForAddress2Config {
+                        before,
+                        until: self.until,
+                        limit: Some(limit),
+                        commitment: None,
+                    },
+                )
+                .into_sss_error("Failed to get mint signatures from rpc")?;
+            let count = page.len();
+            for status in page {
+                signatures.push(
+                    Signature::from_str(&status.signature)
+                        .into_sss_error("Failed to parse signature from rpc")?,
+                );
+            }
+            before = signatures.last().copied();
+            if count < limit {
+                break;
+            }
+        }
+
+        if let Some(newest) = signatures.first() {
+            self.until = Some(*newest);
+        }
+        if !self.started {
+            self.started = true;
+            return Ok(Vec::new());
+        }
+        signatures.reverse();
+        Ok(signatures)
+    }
+}
+
+/// Keeps the successful transactions that changed the supply of a mint
+///
+/// Transactions whose receipt cannot be fetched are kept, since they cannot be ruled out.
+fn supply_changes(client: &RpcClient, mint: &Pubkey, signatures: Vec<Signature>) -> Vec<String> {
+    signatures
+        .into_iter()
+        .map(|signature| signature.to_string())
+        .filter(
+            |signature| match fetch_transaction_receipt_with_client(client, signature) {
+                Ok(receipt) => {
+                    let (pre, post) = receipt
+                        .token_balances
+                        .iter()
+                        .filter(|change| change.mint == *mint)
+                        .fold((0u128, 0u128), |(pre, post), change| {
+                            (pre + u128::from(change.pre), post + u128::from(change.post))
+                        });
+                    receipt.success && pre != post
+                }
+                Err(_) => true,
+            },
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap()
+    }
+
+    #[test]
+    fn step_schedule_holds_each_amount_until_the_next_point() {
+        let schedule = SupplySchedule::step([(at(100), 3_000), (at(0), 1_000)], 5);
+        assert_eq!(schedule.expected_at(at(-1)), 0);
+        assert_eq!(schedule.expected_at(at(0)), 1_000);
+        assert_eq!(schedule.expected_at(at(99)), 1_000);
+        assert_eq!(schedule.expected_at(at(100)), 3_000);
+        assert_eq!(schedule.expected_at(at(1_000_000)), 3_000);
+        assert_eq!(schedule.tolerance(), 5);
+    }
+
+    #[test]
+    fn linear_schedule_interpolates_between_points() {
+        let schedule =
+            SupplySchedule::linear([(at(0), 1_000), (at(100), 2_000), (at(200), 500)], 0);
+        assert_eq!(schedule.expected_at(at(-1)), 0);
+        assert_eq!(schedule.expected_at(at(0)), 1_000);
+        assert_eq!(schedule.expected_at(at(25)), 1_250);
+        assert_eq!(schedule.expected_at(at(100)), 2_000);
+        // Burns show up as a falling schedule
+        assert_eq!(schedule.expected_at(at(150)), 1_250);
+        assert_eq!(schedule.expected_at(at(300)), 500);
+    }
+
+    #[test]
+    fn linear_schedule_does_not_overflow_near_the_maximum_supply() {
+        let schedule = SupplySchedule::linear([(at(0), 0), (at(1_000_000), u64::MAX)], 0);
+        assert_eq!(schedule.expected_at(at(500_000)), u64::MAX / 2);
+        assert_eq!(schedule.expected_at(at(1_000_000)), u64::MAX);
+    }
+
+    #[test]
+    fn monitor_refuses_an_empty_schedule_or_interval() {
+        let mint = Pubkey::new_unique();
+        let result = monitor_supply(mint, SupplySchedule::step([], 0), Duration::from_secs(1));
+        assert!(matches!(result, Err(SssError::ConfigError(_))));
+        let result = monitor_supply(mint, SupplySchedule::step([(at(0), 1)], 0), Duration::ZERO);
+        assert!(matches!(result, Err(SssError::ConfigError(_))));
+    }
+}
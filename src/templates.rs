@@ -0,0 +1,363 @@
+//! Token creation templates for families of near-identical tokens
+//!
+//! A template holds everything the tokens of a family share, e.g. the symbol, royalty and a
+//! URI template, so creating one only needs its name and the attributes that differ, such
+//! as a batch number.
+
+use crate::builder::{MAX_SELLER_FEE_BPS, MINT_PLACEHOLDER, TokenBuilder};
+use crate::creators::{CreatorShare, validate_creators};
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::load_dotenv;
+use crate::payment::percent_encode;
+use crate::serde_utils::option_pubkey_string;
+use lazy_static::lazy_static;
+use mpl_token_metadata::types::TokenStandard;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::sync::RwLock;
+
+/// Placeholder in a URI template replaced by the percent-encoded token name
+const NAME_PLACEHOLDER: &str = "{name}";
+
+/// Placeholder in a URI template replaced by the percent-encoded token symbol
+const SYMBOL_PLACEHOLDER: &str = "{symbol}";
+
+/// What a template creates
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TemplateKind {
+    /// Fungible tokens, e.g. loyalty points
+    #[default]
+    Fungible,
+    /// Individual items such as certificates, created as Metaplex `FungibleAsset` tokens;
+    /// their decimals are fixed at 0
+    Asset,
+}
+
+/// Defaults shared by the tokens created from a template
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TokenTemplate {
+    /// What the template creates
+    #[serde(default)]
+    pub kind: TemplateKind,
+    /// The ticker symbol
+    #[serde(default)]
+    pub symbol: String,
+    /// The number of decimal places
+    #[serde(default)]
+    pub decimals: u8,
+    /// The royalty charged on secondary sales, in basis points
+    #[serde(default)]
+    pub seller_fee_bps: u16,
+    /// The creators sharing the royalty; the payer is the sole creator if empty
+    #[serde(default)]
+    pub creators: Vec<CreatorShare>,
+    /// The (unverified) collection the tokens belong to
+    #[serde(default, with = "option_pubkey_string")]
+    pub collection: Option<Pubkey>,
+    /// The account the freeze authority is handed to; the payer keeps it if unset
+    #[serde(default, with = "option_pubkey_string")]
+    pub freeze_authority: Option<Pubkey>,
+    /// The metadata URI, in which `{name}`, `{symbol}`, `{mint}` and `{<attribute>}` are
+    /// replaced, e.g. `https://cdn.example.com/{batch}/{mint}.json`
+    pub uri_template: String,
+    /// Attribute defaults by name; an empty value must be given by every creation
+    #[serde(default)]
+    pub attributes: BTreeMap<String, String>,
+}
+
+/// What differs between the tokens created from a template
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateOverrides {
+    /// The on-chain name of the token
+    pub name: String,
+    /// Replaces the template's symbol
+    #[serde(default)]
+    pub symbol: Option<String>,
+    /// Replaces the template's decimals; refused for asset templates
+    #[serde(default)]
+    pub decimals: Option<u8>,
+    /// Replaces the template's royalty, in basis points
+    #[serde(default)]
+    pub seller_fee_bps: Option<u16>,
+    /// Replaces the template's creators
+    #[serde(default)]
+    pub creators: Option<Vec<CreatorShare>>,
+    /// Replaces the template's collection
+    #[serde(default, with = "option_pubkey_string")]
+    pub collection: Option<Pubkey>,
+    /// Replaces the template's freeze authority
+    #[serde(default, with = "option_pubkey_string")]
+    pub freeze_authority: Option<Pubkey>,
+    /// Attribute values by name, each of which the template must define, e.g. `batch`
+    #[serde(default)]
+    pub attributes: BTreeMap<String, String>,
+}
+
+/// The registered templates and why the templates file failed to load, if it did
+#[derive(Default)]
+struct Registry {
+    templates: HashMap<String, TokenTemplate>,
+    load_error: Option<String>,
+}
+
+lazy_static! {
+    /// Registered templates, seeded from the JSON file named by `SSS_TEMPLATES_PATH`
+    static ref TEMPLATES: RwLock<Registry> = {
+        load_dotenv();
+        let registry = match env::var("SSS_TEMPLATES_PATH") {
+            Ok(path) => match load_templates(Path::new(&path)) {
+                Ok(templates) => Registry {
+                    templates,
+                    load_error: None,
+                },
+                Err(SssError::ConfigError(message)) => Registry {
+                    templates: HashMap::new(),
+                    load_error: Some(message),
+                },
+                Err(e) => Registry {
+                    templates: HashMap::new(),
+                    load_error: Some(e.to_string()),
+                },
+            },
+            Err(_) => Registry::default(),
+        };
+        RwLock::new(registry)
+    };
+}
+
+/// Registers a template, replacing any template of the same name
+///
+/// # Arguments
+///
+/// * `name` - The name the template is selected by
+/// * `template` - The defaults of the template
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if the template is inconsistent, e.g. its URI template
+/// references an attribute it does not define
+pub fn register_token_template(name: impl Into<String>, template: TokenTemplate) -> SssResult<()> {
+    let name = name.into();
+    validate_template(&name, &template)?;
+    let mut registry = TEMPLATES
+        .write()
+        .map_err(|e| SssError::ConfigError(format!("Failed to acquire lock: {}", e)))?;
+    registry.templates.insert(name, template);
+    Ok(())
+}
+
+/// Returns a registered template
+///
+/// # Arguments
+///
+/// * `name` - The name of the template
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if no template of that name is registered
+pub fn token_template(name: &str) -> SssResult<TokenTemplate> {
+    let registry = TEMPLATES
+        .read()
+        .map_err(|e| SssError::ConfigError(format!("Failed to acquire lock: {}", e)))?;
+    match (registry.templates.get(name), &registry.load_error) {
+        (Some(template), _) => Ok(template.clone()),
+        (None, Some(load_error)) => Err(SssError::ConfigError(format!(
+            "Token template '{}' is not registered; SSS_TEMPLATES_PATH failed to load: {}",
+            name, load_error
+        ))),
+        (None, None) => {
+            let mut names: Vec<_> = registry.templates.keys().map(String::as_str).collect();
+            names.sort_unstable();
+            Err(SssError::ConfigError(format!(
+                "Token template '{}' is not registered, expected one of: {}",
+                name,
+                names.join(", ")
+            )))
+        }
+    }
+}
+
+/// Creates a token from a registered template
+///
+/// # Arguments
+///
+/// * `template_name` - The name of the template
+/// * `overrides` - The name of the token and the template defaults it replaces
+///
+/// # Returns
+///
+/// A tuple containing the transaction signature and the mint public key
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if the template is not registered or the overrides do not fit
+/// it, e.g. a decimals override for an asset template or an attribute the template does not
+/// define, and otherwise the error of [`TokenBuilder::create`]
+pub fn create_token_from_template(
+    template_name: &str,
+    overrides: TemplateOverrides,
+) -> SssResult<(String, Pubkey)> {
+    let template = token_template(template_name)?;
+    template_builder(template_name, &template, overrides)?.create()
+}
+
+/// Builds the token builder of a creation from a template
+fn template_builder(
+    template_name: &str,
+    template: &TokenTemplate,
+    overrides: TemplateOverrides,
+) -> SssResult<TokenBuilder> {
+    if overrides.name.is_empty() {
+        return Err(SssError::ConfigError(format!(
+            "Creating a token from template '{}' needs a name",
+            template_name
+        )));
+    }
+    if template.kind == TemplateKind::Asset
+        && let Some(decimals) = overrides.decimals
+    {
+        return Err(SssError::ConfigError(format!(
+            "Template '{}' creates assets, whose decimals are fixed at 0; cannot override them \
+             with {}",
+            template_name, decimals
+        )));
+    }
+    if let Some(seller_fee_bps) = overrides.seller_fee_bps
+        && seller_fee_bps > MAX_SELLER_FEE_BPS
+    {
+        return Err(SssError::ConfigError(format!(
+            "Seller fee override of {} basis points for template '{}' exceeds {}",
+            seller_fee_bps, template_name, MAX_SELLER_FEE_BPS
+        )));
+    }
+
+    let mut attributes = template.attributes.clone();
+    for (attribute, value) in overrides.attributes {
+        let Some(slot) = attributes.get_mut(&attribute) else {
+            let defined: Vec<_> = template.attributes.keys().map(String::as_str).collect();
+            return Err(SssError::ConfigError(format!(
+                "Template '{}' has no attribute '{}'; it defines: {}",
+                template_name,
+                attribute,
+                defined.join(", ")
+            )));
+        };
+        *slot = value;
+    }
+    if let Some((attribute, _)) = attributes.iter().find(|(_, value)| value.is_empty()) {
+        return Err(SssError::ConfigError(format!(
+            "Template '{}' needs a value for attribute '{}'",
+            template_name, attribute
+        )));
+    }
+
+    let symbol = overrides.symbol.unwrap_or_else(|| template.symbol.clone());
+    let mut uri = template
+        .uri_template
+        .replace(NAME_PLACEHOLDER, &percent_encode(&overrides.name))
+        .replace(SYMBOL_PLACEHOLDER, &percent_encode(&symbol));
+    for (attribute, value) in &attributes {
+        uri = uri.replace(&placeholder(attribute), &percent_encode(value));
+    }
+
+    let mut builder = TokenBuilder::new()
+        .name(overrides.name)
+        .symbol(symbol)
+        .uri(uri)
+        .decimals(overrides.decimals.unwrap_or(template.decimals))
+        .seller_fee_bps(overrides.seller_fee_bps.unwrap_or(template.seller_fee_bps))
+        .creators(
+            overrides
+                .creators
+                .unwrap_or_else(|| template.creators.clone()),
+        )
+        .standard(match template.kind {
+            TemplateKind::Fungible => TokenStandard::Fungible,
+            TemplateKind::Asset => TokenStandard::FungibleAsset,
+        });
+    if let Some(collection) = overrides.collection.or(template.collection) {
+        builder = builder.collection(collection);
+    }
+    if let Some(freeze_authority) = overrides.freeze_authority.or(template.freeze_authority) {
+        builder = builder.freeze_authority(freeze_authority);
+    }
+    Ok(builder)
+}
+
+/// Fails with a `ConfigError` describing the first inconsistency of a template
+fn validate_template(name: &str, template: &TokenTemplate) -> SssResult<()> {
+    if template.uri_template.is_empty() {
+        return Err(SssError::ConfigError(format!(
+            "Template '{}' must set uri_template",
+            name
+        )));
+    }
+    if template.kind == TemplateKind::Asset && template.decimals != 0 {
+        return Err(SssError::ConfigError(format!(
+            "Template '{}' creates assets, whose decimals must be 0, not {}",
+            name, template.decimals
+        )));
+    }
+    if template.seller_fee_bps > MAX_SELLER_FEE_BPS {
+        return Err(SssError::ConfigError(format!(
+            "Template '{}' has a seller fee of {} basis points, which exceeds {}",
+            name, template.seller_fee_bps, MAX_SELLER_FEE_BPS
+        )));
+    }
+    if let Err(SssError::TokenError(message)) = validate_creators(&template.creators) {
+        return Err(SssError::ConfigError(format!(
+            "Template '{}' has invalid creators: {}",
+            name, message
+        )));
+    }
+    let reserved = [MINT_PLACEHOLDER, NAME_PLACEHOLDER, SYMBOL_PLACEHOLDER];
+    if let Some(attribute) = template
+        .attributes
+        .keys()
+        .find(|attribute| reserved.contains(&placeholder(attribute).as_str()))
+    {
+        return Err(SssError::ConfigError(format!(
+            "Template '{}' defines attribute '{}', whose placeholder is reserved",
+            name, attribute
+        )));
+    }
+    let mut rest = template.uri_template.as_str();
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        let token = &rest[start..=start + end];
+        if !reserved.contains(&token) && !template.attributes.contains_key(&token[1..end]) {
+            return Err(SssError::ConfigError(format!(
+                "Template '{}' references {} in its uri_template, which is neither {}, {}, {} \
+                 nor one of its attributes",
+                name, token, MINT_PLACEHOLDER, NAME_PLACEHOLDER, SYMBOL_PLACEHOLDER
+            )));
+        }
+        rest = &rest[start + end + 1..];
+    }
+    Ok(())
+}
+
+/// Returns the placeholder of a key in a URI template, e.g. `{batch}`
+fn placeholder(key: &str) -> String {
+    format!("{{{}}}", key)
+}
+
+/// Loads the templates file, a JSON object of templates by name
+fn load_templates(path: &Path) -> SssResult<HashMap<String, TokenTemplate>> {
+    let json = fs::read_to_string(path).into_sss_error("Failed to read templates config")?;
+    let templates: HashMap<String, TokenTemplate> =
+        serde_json::from_str(&json).into_sss_error("Failed to parse templates config")?;
+    for (name, template) in &templates {
+        validate_template(name, template)?;
+    }
+    Ok(templates)
+}
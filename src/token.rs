@@ -1,17 +1,173 @@
 //! Token creation and management functionality
 
-use crate::error::{IntoSssError, SssResult};
-use mpl_token_metadata::instructions::{CreateV1Builder, MintV1Builder};
+use crate::error::{IntoSssError, SssError, SssResult};
 use mpl_token_metadata::types::TokenStandard;
+use serde_json::json;
 use solana_sdk::{
-    message::Message, pubkey::Pubkey, signature::Keypair, signer::Signer, transaction::Transaction,
+    instruction::Instruction, message::Message, pubkey::Pubkey, signature::Keypair,
+    signature::Signature, system_instruction,
 };
+use spl_token::state::AccountState;
+use spl_token_2022::extension::{
+    BaseStateWithExtensions, ExtensionType, StateWithExtensions,
+    default_account_state::DefaultAccountState,
+};
+use std::str::FromStr;
+#[cfg(feature = "das")]
+use std::time::Duration;
 
 use crate::RPC_CLIENT;
-use crate::get_payer;
+use crate::amount::AmountSpec;
+use crate::builder::TokenBuilder;
+#[cfg(feature = "das")]
+use crate::das::{IndexingStatus, wait_after_write};
+use crate::events::{SssEvent, emit};
+use crate::in_flight::exclusive;
+use crate::instructions::{self, MintV1Params};
+use crate::journal::{JournalOperation, journaled};
+use crate::metrics::track;
+use crate::mint_cache::{
+    MintInfo, cache_has_metadata, cache_mint, cached_mint, has_metadata, metadata_pda, mint_info,
+};
+use crate::payer::{get_payer_pubkey, signing_payer};
+use crate::read_only::ensure_writable;
+use crate::soulbound::is_registered;
+use crate::supply_caps::{SupplyCapViolation, check_supply_after_mint, ensure_within_supply_cap};
+use crate::token_accounts::{
+    ResolutionWarning, TokenAccountResolution, off_curve_warning, resolve_token_account,
+};
+use crate::transaction::{
+    TxOptions, assert_items_fit, assert_tx_size, required_signatures, sign_and_send,
+};
+use crate::trusted_mints::{add_trusted_mint, is_trusted_mint, strict_mode_enabled};
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_rpc_client_api::request::MAX_MULTIPLE_ACCOUNTS;
+
+/// The SPL token program that owns a mint
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TokenProgram {
+    /// The original SPL token program
+    #[default]
+    Legacy,
+    /// The Token-2022 program with extension support
+    Token2022,
+}
+
+impl TokenProgram {
+    /// Returns the program id
+    pub fn id(&self) -> Pubkey {
+        match self {
+            TokenProgram::Legacy => spl_token::id(),
+            TokenProgram::Token2022 => spl_token_2022::id(),
+        }
+    }
+}
+
+/// Options applied when creating a token
+#[derive(Debug, Clone)]
+pub struct CreateOptions {
+    /// The token program owning the new mint
+    pub token_program: TokenProgram,
+    /// The state new token accounts start in
+    ///
+    /// `Frozen` is only supported for Token-2022 mints, where it enables the
+    /// DefaultAccountState extension.
+    pub initial_state: AccountState,
+    /// How long to wait for the new mint to be visible to the DAS indexer, if at all
+    #[cfg(feature = "das")]
+    pub wait_for_indexing: Option<Duration>,
+    /// Whether the name and symbol are normalized before creation
+    ///
+    /// See [`normalize_token_name`](crate::normalize_token_name). Enabled by default.
+    pub sanitize_names: bool,
+    /// Whether holders are prevented from transferring the token
+    ///
+    /// Token-2022 mints get the NonTransferable extension. Legacy mints have no such
+    /// extension, so every recipient account is frozen right after each mint, and the payer
+    /// must keep the freeze authority; see [`add_soulbound_mint`](crate::add_soulbound_mint).
+    pub soulbound: bool,
+    /// Whether the off-chain metadata is compared with the token before it is created
+    ///
+    /// See [`TokenBuilder::check_metadata_consistency`](crate::TokenBuilder::check_metadata_consistency).
+    #[cfg(feature = "http-metadata")]
+    pub check_metadata_consistency: bool,
+    /// Options for signing and sending the transaction
+    pub tx: TxOptions,
+}
+
+impl Default for CreateOptions {
+    fn default() -> Self {
+        Self {
+            token_program: TokenProgram::Legacy,
+            initial_state: AccountState::Initialized,
+            #[cfg(feature = "das")]
+            wait_for_indexing: None,
+            sanitize_names: true,
+            soulbound: false,
+            #[cfg(feature = "http-metadata")]
+            check_metadata_consistency: false,
+            tx: TxOptions::default(),
+        }
+    }
+}
+
+/// Options applied when minting tokens
+#[derive(Debug, Clone)]
+pub struct MintOptions {
+    /// The token program owning the mint
+    pub token_program: TokenProgram,
+    /// The state the recipient token account is left in after minting
+    ///
+    /// `Frozen` freezes the recipient account in the same transaction as the mint,
+    /// so the tokens are never spendable before an explicit thaw.
+    pub initial_state: AccountState,
+    /// Whether minting fails for a mint without Metaplex metadata
+    ///
+    /// By default, such mints, e.g. ones created with the plain spl-token CLI, are minted
+    /// with a plain token program `MintTo`. When set, minting fails with a `TokenError`
+    /// pointing at [`attach_metadata_to_existing_mint`] instead.
+    pub require_metadata: bool,
+    /// How long to wait for the mint to be visible to the DAS indexer, if at all
+    #[cfg(feature = "das")]
+    pub wait_for_indexing: Option<Duration>,
+    /// Options for signing and sending the transaction
+    pub tx: TxOptions,
+}
+
+impl Default for MintOptions {
+    fn default() -> Self {
+        Self {
+            token_program: TokenProgram::Legacy,
+            initial_state: AccountState::Initialized,
+            require_metadata: false,
+            #[cfg(feature = "das")]
+            wait_for_indexing: None,
+            tx: TxOptions::default(),
+        }
+    }
+}
+
+/// Detailed outcome of a token creation or mint
+#[derive(Debug, Clone)]
+pub struct TokenOperationResult {
+    /// The transaction signature
+    pub signature: String,
+    /// The mint the operation applied to
+    pub mint: Pubkey,
+    /// How indexing went, if `wait_for_indexing` was requested
+    #[cfg(feature = "das")]
+    pub indexing: Option<IndexingStatus>,
+    /// Set if the supply was found above the mint's cap after the transaction confirmed
+    pub supply_cap_violation: Option<SupplyCapViolation>,
+    /// The platform fee account and lamports paid in the transaction, if any
+    pub platform_fee: Option<(Pubkey, u64)>,
+}
 
 /// Creates a fungible token with the specified parameters
 ///
+/// The mint is supplied by the caller, so `uri` must not be a template containing
+/// [`MINT_PLACEHOLDER`](crate::MINT_PLACEHOLDER); see [`create_new_token`].
+///
 /// # Arguments
 ///
 /// * `mint` - The keypair for the mint account
@@ -22,60 +178,83 @@ use crate::get_payer;
 /// # Returns
 ///
 /// The transaction signature as a string
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if `decimals` breaks the
+/// [`ConsumablePolicy`](crate::ConsumablePolicy) in force
 pub fn create_consumable_token(
     mint: &Keypair,
-    uri: String,
-    name: String,
+    uri: &str,
+    name: &str,
     decimals: u8,
 ) -> SssResult<String> {
-    // Get the payer keypair
-    let payer = get_payer().into_sss_error("Failed to get payer keypair")?;
-
-    // Derive the metadata account PDA
-    let seeds = &[
-        "metadata".as_bytes(),
-        &mpl_token_metadata::ID.to_bytes(),
-        &mint.pubkey().to_bytes(),
-    ];
-    let (metadata_account, _) = Pubkey::find_program_address(seeds, &mpl_token_metadata::ID);
-
-    // Create the instruction to create a consumable token
-    let create_ix = CreateV1Builder::new()
-        .metadata(metadata_account)
-        .mint(mint.pubkey(), true)
-        .authority(payer.pubkey())
-        .payer(payer.pubkey())
-        .update_authority(payer.pubkey(), false)
-        .name(name)
+    create_consumable_token_with_options(mint, uri, name, decimals, &CreateOptions::default())
+}
+
+/// Creates a fungible token with the specified parameters and creation options
+///
+/// # Arguments
+///
+/// * `mint` - The keypair for the mint account
+/// * `uri` - The URI pointing to the token's metadata
+/// * `name` - The name of the token
+/// * `decimals` - The number of decimal places for the token
+/// * `options` - The token program and default account state of the mint
+///
+/// # Returns
+///
+/// The transaction signature as a string
+pub fn create_consumable_token_with_options(
+    mint: &Keypair,
+    uri: &str,
+    name: &str,
+    decimals: u8,
+    options: &CreateOptions,
+) -> SssResult<String> {
+    TokenBuilder::new()
         .uri(uri)
-        .seller_fee_basis_points(0)
-        .symbol("".to_string())
-        .token_standard(TokenStandard::Fungible)
+        .name(name)
         .decimals(decimals)
-        .spl_token_program(Some(spl_token::id()))
-        .instruction();
-
-    // Create the message
-    let message = Message::new(&[create_ix], Some(&payer.pubkey()));
-
-    // Get the latest blockhash
-    let blockhash = RPC_CLIENT
-        .get_latest_blockhash()
-        .into_sss_error("Failed to get latest blockhash")?;
-
-    // Create and sign the transaction
-    let tx = Transaction::new(&[mint, &payer], message, blockhash);
-
-    // Send and confirm the transaction
-    let signature = RPC_CLIENT
-        .send_and_confirm_transaction(&tx)
-        .into_sss_error("Failed to send and confirm transaction")?;
+        .options(options.clone())
+        .create_with_mint(mint)
+}
 
-    Ok(signature.to_string())
+/// Creates a fungible token like [`create_consumable_token`], sending through the given
+/// RPC client
+///
+/// # Arguments
+///
+/// * `client` - The RPC client used for every read and for sending the transaction
+/// * `mint` - The keypair for the mint account
+/// * `uri` - The URI pointing to the token's metadata
+/// * `name` - The name of the token
+/// * `decimals` - The number of decimal places for the token
+///
+/// # Returns
+///
+/// The transaction signature as a string
+pub fn create_consumable_token_with_client(
+    client: &RpcClient,
+    mint: &Keypair,
+    uri: &str,
+    name: &str,
+    decimals: u8,
+) -> SssResult<String> {
+    TokenBuilder::new()
+        .uri(uri)
+        .name(name)
+        .decimals(decimals)
+        .create_with_client(client, mint)
+        .map(|result| result.signature)
 }
 
 /// Creates a new token with a newly generated mint keypair
 ///
+/// If `uri` contains [`MINT_PLACEHOLDER`](crate::MINT_PLACEHOLDER), e.g.
+/// `https://cdn.example.com/tokens/{mint}.json`, it is replaced by the address of the
+/// generated mint before the token is created.
+///
 /// # Arguments
 ///
 /// * `uri` - The URI pointing to the token's metadata
@@ -85,10 +264,204 @@ pub fn create_consumable_token(
 /// # Returns
 ///
 /// A tuple containing the transaction signature and the mint public key
-pub fn create_new_token(uri: String, name: String, decimals: u8) -> SssResult<(String, Pubkey)> {
-    let mint = Keypair::new();
-    let signature = create_consumable_token(&mint, uri, name, decimals)?;
-    Ok((signature, mint.pubkey()))
+pub fn create_new_token(uri: &str, name: &str, decimals: u8) -> SssResult<(String, Pubkey)> {
+    create_new_token_with_options(uri, name, decimals, &CreateOptions::default())
+}
+
+/// Creates a new token with a newly generated mint keypair and creation options
+///
+/// # Arguments
+///
+/// * `uri` - The URI pointing to the token's metadata
+/// * `name` - The name of the token
+/// * `decimals` - The number of decimal places for the token
+/// * `options` - The token program and default account state of the mint
+///
+/// # Returns
+///
+/// A tuple containing the transaction signature and the mint public key
+pub fn create_new_token_with_options(
+    uri: &str,
+    name: &str,
+    decimals: u8,
+    options: &CreateOptions,
+) -> SssResult<(String, Pubkey)> {
+    TokenBuilder::new()
+        .uri(uri)
+        .name(name)
+        .decimals(decimals)
+        .options(options.clone())
+        .create()
+}
+
+/// Creates the Metaplex metadata of a mint created by another tool
+///
+/// Mints created with the plain spl-token program have no metadata account, so wallets
+/// show them without a name and [`MintOptions::require_metadata`] refuses to mint them.
+/// The metadata is created over the existing mint, keeping its decimals and token program.
+///
+/// # Arguments
+///
+/// * `mint` - The existing mint, whose mint authority must be the payer
+/// * `uri` - The URI pointing to the token's metadata
+/// * `name` - The name of the token
+/// * `symbol` - The ticker symbol of the token
+///
+/// # Returns
+///
+/// The transaction signature as a string
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the mint does not exist, already has metadata, or its mint
+/// authority is not the payer
+pub fn attach_metadata_to_existing_mint(
+    mint: Pubkey,
+    uri: &str,
+    name: &str,
+    symbol: &str,
+) -> SssResult<String> {
+    attach_metadata_to_existing_mint_with_client(&RPC_CLIENT, mint, uri, name, symbol)
+}
+
+/// Creates the Metaplex metadata of a mint like [`attach_metadata_to_existing_mint`],
+/// reading and sending through the given RPC client
+///
+/// # Arguments
+///
+/// * `client` - The RPC client used for every read and for sending the transaction
+/// * `mint` - The existing mint, whose mint authority must be the payer
+/// * `uri` - The URI pointing to the token's metadata
+/// * `name` - The name of the token
+/// * `symbol` - The ticker symbol of the token
+///
+/// # Returns
+///
+/// The transaction signature as a string
+pub fn attach_metadata_to_existing_mint_with_client(
+    client: &RpcClient,
+    mint: Pubkey,
+    uri: &str,
+    name: &str,
+    symbol: &str,
+) -> SssResult<String> {
+    ensure_writable()?;
+    track(JournalOperation::AttachMetadata, || {
+        ensure_trusted_mint(&mint)?;
+        let accounts = client
+            .get_multiple_accounts(&[mint, metadata_pda(&mint)])
+            .into_sss_error("Failed to get mint and metadata accounts from rpc")?;
+        let mint_account = accounts[0]
+            .as_ref()
+            .ok_or_else(|| SssError::TokenError(format!("Mint account {} does not exist", mint)))?;
+        if accounts[1].is_some() {
+            cache_has_metadata(mint);
+            return Err(SssError::TokenError(format!(
+                "Mint {} already has a metadata account",
+                mint
+            )));
+        }
+        let info = MintInfo::from_account(&mint, mint_account)?;
+        let state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_account.data)
+            .into_sss_error("Failed to parse token mint account")?;
+        let payer = get_payer_pubkey()?;
+        if Option::<Pubkey>::from(state.base.mint_authority) != Some(payer) {
+            return Err(SssError::TokenError(format!(
+                "Payer {} is not the mint authority of mint {}, which the metadata program requires",
+                payer, mint
+            )));
+        }
+
+        TokenBuilder::new()
+            .uri(uri)
+            .name(name)
+            .symbol(symbol)
+            .decimals(info.decimals)
+            .token_program(info.token_program)
+            .attach_to_existing_mint(client, &mint)
+    })
+}
+
+/// What became of a token creation that ended in [`SssError::PartialCreate`]
+#[derive(Debug, Clone)]
+pub enum CreateRecovery {
+    /// The transaction landed and the remaining steps have been completed
+    Completed(TokenOperationResult),
+    /// The transaction failed, so no token was created and it is safe to create it again
+    NotCreated(String),
+    /// The transaction has not been seen yet and may still land; check again later
+    ///
+    /// A transaction can no longer land once its blockhash expires, about two minutes
+    /// after it was sent.
+    Pending,
+}
+
+/// Checks whether an unconfirmed token creation landed and finishes it if it did
+///
+/// # Arguments
+///
+/// * `mint` - The mint reported by [`SssError::PartialCreate`]
+/// * `last_signature` - The signature reported by [`SssError::PartialCreate`]
+///
+/// # Returns
+///
+/// Whether the token was created, was not created, or may still be
+pub fn resume_create(mint: Pubkey, last_signature: &str) -> SssResult<CreateRecovery> {
+    resume_create_with_client(&RPC_CLIENT, mint, last_signature)
+}
+
+/// Checks an unconfirmed token creation like [`resume_create`], querying the given RPC
+/// client
+///
+/// # Arguments
+///
+/// * `client` - The RPC client to query, e.g. an archival node
+/// * `mint` - The mint reported by [`SssError::PartialCreate`]
+/// * `last_signature` - The signature reported by [`SssError::PartialCreate`]
+///
+/// # Returns
+///
+/// Whether the token was created, was not created, or may still be
+pub fn resume_create_with_client(
+    client: &RpcClient,
+    mint: Pubkey,
+    last_signature: &str,
+) -> SssResult<CreateRecovery> {
+    let signature = Signature::from_str(last_signature)
+        .into_sss_error("Failed to parse token creation signature")?;
+
+    let landed = match client
+        .get_signature_status(&signature)
+        .into_sss_error("Failed to get signature status from rpc")?
+    {
+        Some(Ok(())) => true,
+        Some(Err(e)) => {
+            return Ok(CreateRecovery::NotCreated(format!(
+                "Transaction failed: {}",
+                e
+            )));
+        }
+        // The status may have been pruned, so look for the mint itself
+        None => client
+            .get_multiple_accounts(&[mint])
+            .into_sss_error("Failed to get mint account from rpc")?
+            .pop()
+            .flatten()
+            .is_some(),
+    };
+    if !landed {
+        return Ok(CreateRecovery::Pending);
+    }
+
+    add_trusted_mint(mint);
+    Ok(CreateRecovery::Completed(TokenOperationResult {
+        signature: last_signature.to_string(),
+        mint,
+        #[cfg(feature = "das")]
+        indexing: None,
+        supply_cap_violation: None,
+        platform_fee: None,
+    }))
 }
 
 /// Mints tokens for an existing token
@@ -97,55 +470,1060 @@ pub fn create_new_token(uri: String, name: String, decimals: u8) -> SssResult<(S
 ///
 /// * `mint` - The public key of the token's mint account
 /// * `token_owner` - Optional public key of the token owner. If None, the payer will be used
-/// * `amount` - The amount of tokens to mint
+/// * `amount` - The amount of tokens to mint, in base units or as a [`UiAmount`](crate::UiAmount)
+///
+/// # Returns
+///
+/// The transaction signature as a string
+pub fn mint_token(
+    mint: Pubkey,
+    token_owner: Option<Pubkey>,
+    amount: impl Into<AmountSpec>,
+) -> SssResult<String> {
+    mint_token_with_options(mint, token_owner, amount, &MintOptions::default())
+}
+
+/// Mints tokens for an existing token with minting options
+///
+/// # Arguments
+///
+/// * `mint` - The public key of the token's mint account
+/// * `token_owner` - Optional public key of the token owner. If None, the payer will be used
+/// * `amount` - The amount of tokens to mint, in base units or as a [`UiAmount`](crate::UiAmount)
+/// * `options` - The token program and the state the recipient account is left in
+///
+/// # Returns
+///
+/// The transaction signature as a string
+pub fn mint_token_with_options(
+    mint: Pubkey,
+    token_owner: Option<Pubkey>,
+    amount: impl Into<AmountSpec>,
+    options: &MintOptions,
+) -> SssResult<String> {
+    mint_token_detailed(mint, token_owner, amount, options).map(|result| result.signature)
+}
+
+/// Mints tokens at most once per idempotency key, even across process restarts
+///
+/// With `SSS_DEDUP_PATH` set, a call repeating the key of a confirmed mint, e.g. a job
+/// replayed by a queue after a crash, returns the signature of that mint instead of
+/// minting again. A mint that failed or expired is sent again. Without a dedup store,
+/// only an identical mint still running in this process is caught.
+///
+/// # Arguments
+///
+/// * `mint` - The public key of the token's mint account
+/// * `token_owner` - Optional public key of the token owner. If None, the payer will be used
+/// * `amount` - The amount of tokens to mint, in base units or as a [`UiAmount`](crate::UiAmount)
+/// * `idempotency_key` - The caller's identifier of the mint, e.g. a job or order id
+///
+/// # Returns
+///
+/// The transaction signature as a string
+///
+/// # Errors
+///
+/// Returns a `DuplicateInFlight` error if an earlier mint with the key is still
+/// unconfirmed and may yet land
+pub fn mint_token_idempotent(
+    mint: Pubkey,
+    token_owner: Option<Pubkey>,
+    amount: impl Into<AmountSpec>,
+    idempotency_key: &str,
+) -> SssResult<String> {
+    let options = MintOptions {
+        tx: TxOptions {
+            idempotency_key: Some(idempotency_key.to_string()),
+            ..TxOptions::default()
+        },
+        ..MintOptions::default()
+    };
+    mint_token_with_options(mint, token_owner, amount, &options)
+}
+
+/// Mints tokens like [`mint_token`], sending through the given RPC client
+///
+/// # Arguments
+///
+/// * `client` - The RPC client used for every read and for sending the transaction
+/// * `mint` - The public key of the token's mint account
+/// * `token_owner` - Optional public key of the token owner. If None, the payer will be used
+/// * `amount` - The amount of tokens to mint, in base units or as a [`UiAmount`](crate::UiAmount)
+///
+/// # Returns
+///
+/// The transaction signature as a string
+pub fn mint_token_with_client(
+    client: &RpcClient,
+    mint: Pubkey,
+    token_owner: Option<Pubkey>,
+    amount: impl Into<AmountSpec>,
+) -> SssResult<String> {
+    mint_token_detailed_with_client(client, mint, token_owner, amount, &MintOptions::default())
+        .map(|result| result.signature)
+}
+
+/// Mints tokens for an existing token and reports the detailed outcome
+///
+/// # Arguments
+///
+/// * `mint` - The public key of the token's mint account
+/// * `token_owner` - Optional public key of the token owner. If None, the payer will be used
+/// * `amount` - The amount of tokens to mint, in base units or as a [`UiAmount`](crate::UiAmount)
+/// * `options` - The token program, the state the recipient account is left in and
+///   whether to wait for indexing
+///
+/// # Returns
+///
+/// The transaction signature, the mint and the indexing status
+pub fn mint_token_detailed(
+    mint: Pubkey,
+    token_owner: Option<Pubkey>,
+    amount: impl Into<AmountSpec>,
+    options: &MintOptions,
+) -> SssResult<TokenOperationResult> {
+    mint_token_detailed_with_client(&RPC_CLIENT, mint, token_owner, amount, options)
+}
+
+/// Mints tokens like [`mint_token_detailed`], sending through the given RPC client
+///
+/// Blockhashes are only cached for the global client, so other clients fetch one per
+/// transaction. Waiting for indexing still uses the configured DAS provider.
+///
+/// # Arguments
+///
+/// * `client` - The RPC client used for every read and for sending the transaction
+/// * `mint` - The public key of the token's mint account
+/// * `token_owner` - Optional public key of the token owner. If None, the payer will be used
+/// * `amount` - The amount of tokens to mint, in base units or as a [`UiAmount`](crate::UiAmount)
+/// * `options` - The token program, the state the recipient account is left in and
+///   whether to wait for indexing
+///
+/// # Returns
+///
+/// The transaction signature, the mint and the indexing status
+pub fn mint_token_detailed_with_client(
+    client: &RpcClient,
+    mint: Pubkey,
+    token_owner: Option<Pubkey>,
+    amount: impl Into<AmountSpec>,
+    options: &MintOptions,
+) -> SssResult<TokenOperationResult> {
+    ensure_writable()?;
+    ensure_trusted_mint(&mint)?;
+    let amount = amount.into().resolve(client, &mint)?;
+    ensure_within_supply_cap(client, &mint, amount)?;
+    let params = json!({
+        "mint": mint.to_string(),
+        "owner": token_owner.map(|owner| owner.to_string()),
+        "amount": amount,
+    });
+    let signature = exclusive(JournalOperation::MintToken, &params, &options.tx, || {
+        track(JournalOperation::MintToken, || {
+            // The payer will also be the mint authority
+            let payer_pubkey = get_payer_pubkey()?;
+
+            // Get token account - if token_owner is provided, use it, otherwise use payer
+            let owner = token_owner.unwrap_or(payer_pubkey);
+
+            // Frozen recipient accounts must be thawed to receive tokens
+            let lock = recipient_lock(client, &mint, options.token_program)?.for_recipients(
+                client,
+                &mint,
+                &[owner],
+            )?[0];
+            let metadata = mint_metadata(client, &mint, options)?;
+            let mut instructions = mint_instructions(
+                &payer_pubkey,
+                &payer_pubkey,
+                &mint,
+                metadata,
+                &owner,
+                amount,
+                options,
+                lock,
+            )?;
+            instructions.extend(options.tx.platform_fee_instruction(&payer_pubkey));
+            let instructions = options.tx.wrap_instructions(instructions);
+
+            // Create the message and make sure it fits before fetching a blockhash
+            let message = Message::new(&instructions, Some(&payer_pubkey));
+            assert_tx_size(&message, required_signatures(&message))?;
+
+            // Only signing needs the keypair itself
+            let payer = signing_payer()?;
+
+            // Sign with a cached blockhash, send and confirm the transaction
+            let signature = journaled(
+                JournalOperation::MintToken,
+                &json!({
+                    "mint": mint.to_string(),
+                    "owner": owner.to_string(),
+                    "amount": amount,
+                }),
+                &options.tx,
+                || sign_and_send(client, message, &[&payer], &options.tx),
+            )?;
+            emit_mint_confirmed(&signature, mint, owner, amount);
+            Ok(signature)
+        })
+    })?;
+
+    Ok(TokenOperationResult {
+        signature,
+        mint,
+        #[cfg(feature = "das")]
+        indexing: wait_after_write(mint, options.wait_for_indexing),
+        // Concurrent mints may have passed the pre-check at the same time
+        supply_cap_violation: check_supply_after_mint(client, &mint),
+        platform_fee: options.tx.resolved_platform_fee(),
+    })
+}
+
+/// Mints tokens into an existing token account that need not be an associated token account
+///
+/// # Arguments
+///
+/// * `mint` - The public key of the token's mint account
+/// * `token_account` - The token account receiving the tokens
+/// * `amount` - The amount of tokens to mint, in base units or as a [`UiAmount`](crate::UiAmount)
+///
+/// # Returns
+///
+/// The transaction signature as a string
+pub fn mint_token_to_account(
+    mint: Pubkey,
+    token_account: Pubkey,
+    amount: impl Into<AmountSpec>,
+) -> SssResult<String> {
+    mint_token_to_account_with_options(mint, token_account, amount, &MintOptions::default())
+}
+
+/// Mints tokens into an existing token account with minting options
+///
+/// The account is validated client-side: it must be owned by a token program, hold tokens
+/// of `mint` and not be frozen. Its owner is read from the account data, and the token
+/// program is taken from the account rather than from `options`.
+///
+/// # Arguments
+///
+/// * `mint` - The public key of the token's mint account
+/// * `token_account` - The token account receiving the tokens
+/// * `amount` - The amount of tokens to mint, in base units or as a [`UiAmount`](crate::UiAmount)
+/// * `options` - The state the account is left in and transaction options
 ///
 /// # Returns
 ///
 /// The transaction signature as a string
-pub fn mint_token(mint: Pubkey, token_owner: Option<Pubkey>, amount: u64) -> SssResult<String> {
-    // Get the payer keypair which will also be the mint authority
-    let payer = get_payer().into_sss_error("Failed to get payer keypair")?;
-    let authority = Keypair::from_bytes(&payer.to_bytes())
-        .into_sss_error("Failed to create authority keypair")?;
-
-    // Derive the metadata PDA
-    let seeds = &[
-        "metadata".as_bytes(),
-        &mpl_token_metadata::ID.to_bytes(),
-        &mint.to_bytes(),
-    ];
-    let (metadata, _) = Pubkey::find_program_address(seeds, &mpl_token_metadata::ID);
-
-    // Get token account - if token_owner is provided, use it, otherwise use payer
-    let owner = token_owner.unwrap_or(payer.pubkey());
-    let token = spl_associated_token_account::get_associated_token_address(&owner, &mint);
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the account is not a token account of `mint`, is frozen
+/// while the mint is not a legacy soulbound mint, or the mint would exceed its supply cap
+pub fn mint_token_to_account_with_options(
+    mint: Pubkey,
+    token_account: Pubkey,
+    amount: impl Into<AmountSpec>,
+    options: &MintOptions,
+) -> SssResult<String> {
+    ensure_writable()?;
+    ensure_trusted_mint(&mint)?;
+    let amount = amount.into().resolve(&RPC_CLIENT, &mint)?;
+    ensure_within_supply_cap(&RPC_CLIENT, &mint, amount)?;
+    let params = json!({
+        "mint": mint.to_string(),
+        "token_account": token_account.to_string(),
+        "amount": amount,
+    });
+    exclusive(
+        JournalOperation::MintToAccount,
+        &params,
+        &options.tx,
+        || {
+            track(JournalOperation::MintToAccount, || {
+                let account = RPC_CLIENT
+                    .get_account(&token_account)
+                    .into_sss_error("Failed to get token account from rpc")?;
+                let token_program = if account.owner == spl_token::id() {
+                    TokenProgram::Legacy
+                } else if account.owner == spl_token_2022::id() {
+                    TokenProgram::Token2022
+                } else {
+                    return Err(SssError::TokenError(format!(
+                        "Account {} is not owned by a token program",
+                        token_account
+                    )));
+                };
+
+                let state =
+                    StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account.data)
+                        .into_sss_error("Failed to parse token account")?;
+                if state.base.mint != mint {
+                    return Err(SssError::TokenError(format!(
+                        "Token account {} holds mint {}, not {}",
+                        token_account, state.base.mint, mint
+                    )));
+                }
+                // Accounts of legacy soulbound mints are kept frozen and thawed for each mint
+                let soulbound = token_program == TokenProgram::Legacy && is_registered(&mint);
+                let frozen = state.base.state == spl_token_2022::state::AccountState::Frozen;
+                if frozen && !soulbound {
+                    return Err(SssError::TokenError(format!(
+                        "Token account {} is frozen",
+                        token_account
+                    )));
+                }
+
+                // The payer will also be the mint authority
+                let payer_pubkey = get_payer_pubkey()?;
+
+                let metadata = mint_metadata(&RPC_CLIENT, &mint, options)?;
+
+                let mut instructions = Vec::with_capacity(3);
+                if frozen {
+                    instructions.push(
+                        spl_token_2022::instruction::thaw_account(
+                            &token_program.id(),
+                            &token_account,
+                            &mint,
+                            &payer_pubkey,
+                            &[],
+                        )
+                        .into_sss_error("Failed to build token thaw instruction")?,
+                    );
+                }
+                instructions.push(match metadata {
+                    Some(metadata) => instructions::mint_v1(MintV1Params {
+                        token: Some(token_account),
+                        token_owner: Some(state.base.owner),
+                        metadata: Some(metadata),
+                        master_edition: None,
+                        token_record: None,
+                        mint: Some(mint),
+                        authority: Some(payer_pubkey),
+                        payer: Some(payer_pubkey),
+                        amount,
+                        token_standard: TokenStandard::Fungible,
+                        spl_token_program: token_program.id(),
+                    })?,
+                    None => spl_token_2022::instruction::mint_to(
+                        &token_program.id(),
+                        &mint,
+                        &token_account,
+                        &payer_pubkey,
+                        &[],
+                        amount,
+                    )
+                    .into_sss_error("Failed to build token mint instruction")?,
+                });
+                if options.initial_state == AccountState::Frozen || soulbound {
+                    instructions.push(freeze_instruction(
+                        token_program,
+                        &token_account,
+                        &mint,
+                        &payer_pubkey,
+                    )?);
+                }
+                instructions.extend(options.tx.platform_fee_instruction(&payer_pubkey));
+                let instructions = options.tx.wrap_instructions(instructions);
+
+                let message = Message::new(&instructions, Some(&payer_pubkey));
+                assert_tx_size(&message, required_signatures(&message))?;
+                let payer = signing_payer()?;
+                let signature = journaled(
+                    JournalOperation::MintToAccount,
+                    &params,
+                    &options.tx,
+                    || sign_and_send(&RPC_CLIENT, message, &[&payer], &options.tx),
+                )?;
+                emit_mint_confirmed(&signature, mint, state.base.owner, amount);
+                Ok(signature)
+            })
+        },
+    )
+}
+
+/// Mints several tokens to the same wallet in a single transaction
+///
+/// Every mint is checked before anything is sent: the payer must be its mint authority and
+/// the amount must stay within its supply cap. The recipient accounts are created by the
+/// mint instructions where missing, so the basket either arrives whole or not at all.
+///
+/// # Arguments
+///
+/// * `items` - The mints and the amount of each to mint
+/// * `owner` - The wallet receiving the tokens
+///
+/// # Returns
+///
+/// The transaction signature as a string
+///
+/// # Errors
+///
+/// Returns a `TokenError` naming the offending mint if a mint appears twice, does not
+/// exist, is not controlled by the payer or would exceed its supply cap, and
+/// `TransactionTooLarge` with the number of mints to leave out if the basket does not fit
+/// in a single transaction
+pub fn mint_basket(items: &[(Pubkey, u64)], owner: Pubkey) -> SssResult<String> {
+    ensure_writable()?;
+    if items.is_empty() {
+        return Err(SssError::TokenError("Token basket is empty".to_string()));
+    }
+    for (i, (mint, _)) in items.iter().enumerate() {
+        if items[..i].iter().any(|(other, _)| other == mint) {
+            return Err(SssError::TokenError(format!(
+                "Mint {} appears more than once in the token basket",
+                mint
+            )));
+        }
+        ensure_trusted_mint(mint)?;
+    }
+
+    let params: Vec<_> = items
+        .iter()
+        .map(|(mint, amount)| json!({ "mint": mint.to_string(), "amount": amount }))
+        .collect();
+    let params = json!({ "owner": owner.to_string(), "items": params });
+    exclusive(
+        JournalOperation::MintBasket,
+        &params,
+        &TxOptions::default(),
+        || {
+            track(JournalOperation::MintBasket, || {
+                let payer_pubkey = get_payer_pubkey()?;
+
+                let mints: Vec<Pubkey> = items.iter().map(|(mint, _)| *mint).collect();
+                let accounts = RPC_CLIENT
+                    .get_multiple_accounts(&mints)
+                    .into_sss_error("Failed to get mint accounts from rpc")?;
+
+                let mut item_instructions = Vec::with_capacity(items.len());
+                for ((mint, amount), account) in items.iter().zip(&accounts) {
+                    let account = account.as_ref().ok_or_else(|| {
+                        SssError::TokenError(format!("Mint account {} does not exist", mint))
+                    })?;
+                    let info = MintInfo::from_account(mint, account)?;
+                    cache_mint(*mint, info);
+
+                    let state =
+                        StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&account.data)
+                            .into_sss_error("Failed to parse token mint account")?;
+                    if Option::<Pubkey>::from(state.base.mint_authority) != Some(payer_pubkey) {
+                        return Err(SssError::TokenError(format!(
+                            "Payer {} is not the mint authority of mint {}",
+                            payer_pubkey, mint
+                        )));
+                    }
+                    ensure_within_supply_cap(&RPC_CLIENT, mint, *amount)?;
+
+                    // Frozen recipient accounts must be thawed to receive tokens
+                    let frozen_by_default = state
+                        .get_extension::<DefaultAccountState>()
+                        .map(|ext| ext.state == spl_token_2022::state::AccountState::Frozen as u8)
+                        .unwrap_or(false);
+                    let lock = if frozen_by_default {
+                        RecipientLock::FrozenByDefault
+                    } else if info.token_program == TokenProgram::Legacy && is_registered(mint) {
+                        RecipientLock::Soulbound {
+                            account_exists: false,
+                        }
+                        .for_recipients(&RPC_CLIENT, mint, &[owner])?[0]
+                    } else {
+                        RecipientLock::Unlocked
+                    };
+                    let options = MintOptions {
+                        token_program: info.token_program,
+                        ..MintOptions::default()
+                    };
+                    let metadata = mint_metadata(&RPC_CLIENT, mint, &options)?;
+                    item_instructions.push(mint_instructions(
+                        &payer_pubkey,
+                        &payer_pubkey,
+                        mint,
+                        metadata,
+                        &owner,
+                        *amount,
+                        &options,
+                        lock,
+                    )?);
+                }
+                let tx_options = TxOptions::default();
+                let build = |items: &[Vec<Instruction>]| {
+                    let mut instructions: Vec<Instruction> = items.concat();
+                    instructions.extend(tx_options.platform_fee_instruction(&payer_pubkey));
+                    Ok(Message::new(&instructions, Some(&payer_pubkey)))
+                };
+                assert_items_fit(&item_instructions, 1, build)?;
+
+                let message = build(&item_instructions)?;
+                let payer = signing_payer()?;
+
+                let signature =
+                    journaled(JournalOperation::MintBasket, &params, &tx_options, || {
+                        sign_and_send(&RPC_CLIENT, message, &[&payer], &tx_options)
+                    })?;
+                for (mint, amount) in items {
+                    emit_mint_confirmed(&signature, *mint, owner, *amount);
+                }
+                Ok(signature)
+            })
+        },
+    )
+}
+
+/// State of a recipient's token account
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecipientStatus {
+    /// The token account of the recipient, its associated token account by default
+    pub token_account: Pubkey,
+    /// Whether the token account already exists
+    pub account_exists: bool,
+    /// Whether the token account is frozen, in which case minting to it fails
+    pub frozen: bool,
+    /// The current balance of the token account
+    pub balance: u64,
+    /// Lamports the payer is charged to create the account, 0 if it exists
+    pub creation_lamports: u64,
+    /// Lamports the payer is charged as platform fee for the mint, 0 if none applies
+    pub platform_fee_lamports: u64,
+    /// What the caller should double-check about the resolved account, e.g. an off-curve
+    /// recipient whose program may not use its associated token account
+    pub warnings: Vec<ResolutionWarning>,
+}
+
+/// Checks whether a wallet can receive tokens and what creating its account would cost
+///
+/// Makes at most two RPC calls: one fetching the token account (together with the mint
+/// unless it is cached) and one fetching the rent if the account has to be created.
+///
+/// # Arguments
+///
+/// * `mint` - The public key of the token's mint account
+/// * `owner` - The wallet that would receive tokens
+///
+/// # Returns
+///
+/// The state of the owner's associated token account
+pub fn check_recipient(mint: Pubkey, owner: Pubkey) -> SssResult<RecipientStatus> {
+    check_recipient_with_options(mint, owner, &TxOptions::default())
+}
+
+/// Checks a recipient like [`check_recipient`], including the platform fee of `options`
+///
+/// # Arguments
+///
+/// * `mint` - The public key of the token's mint account
+/// * `owner` - The wallet that would receive tokens
+/// * `options` - The transaction options the mint would be sent with
+///
+/// # Returns
+///
+/// The state of the owner's associated token account and the platform fee
+pub fn check_recipient_with_options(
+    mint: Pubkey,
+    owner: Pubkey,
+    options: &TxOptions,
+) -> SssResult<RecipientStatus> {
+    check_recipient_with_resolution(mint, owner, &TokenAccountResolution::Ata, options)
+}
+
+/// Checks a recipient like [`check_recipient_with_options`], finding its token account as
+/// requested
+///
+/// Recipients that are program-derived addresses often hold their tokens in accounts other
+/// than their associated token account; `ExplicitAccount` and `ScanOwner` find those.
+///
+/// # Arguments
+///
+/// * `mint` - The public key of the token's mint account
+/// * `owner` - The wallet or program-derived address that would receive tokens
+/// * `resolution` - How the owner's token account is found
+/// * `options` - The transaction options the mint would be sent with
+///
+/// # Returns
+///
+/// The state of the resolved token account and the platform fee, with a warning if the
+/// owner is off-curve and its associated account was used, or if it holds the mint in
+/// several accounts
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the mint does not exist, or an explicit account does not hold
+/// the mint or belong to the owner
+pub fn check_recipient_with_resolution(
+    mint: Pubkey,
+    owner: Pubkey,
+    resolution: &TokenAccountResolution,
+    options: &TxOptions,
+) -> SssResult<RecipientStatus> {
+    let platform_fee_lamports = options
+        .resolved_platform_fee()
+        .map(|(_, lamports)| lamports)
+        .unwrap_or(0);
+    if *resolution != TokenAccountResolution::Ata {
+        let info = mint_info(&mint)?;
+        let resolved = resolve_token_account(
+            &RPC_CLIENT,
+            &mint,
+            &owner,
+            &info.token_program.id(),
+            resolution,
+        )?;
+        let creation_lamports = match resolved.state {
+            Some(_) => 0,
+            None => RPC_CLIENT
+                .get_minimum_balance_for_rent_exemption(info.token_account_len)
+                .into_sss_error("Failed to get rent exemption from rpc")?,
+        };
+        return Ok(RecipientStatus {
+            token_account: resolved.address,
+            account_exists: resolved.state.is_some(),
+            frozen: resolved.state.is_some_and(|state| state.frozen),
+            balance: resolved.state.map_or(0, |state| state.amount),
+            creation_lamports,
+            platform_fee_lamports,
+            warnings: resolved.warnings,
+        });
+    }
+
+    let ata = |program: &Pubkey| {
+        spl_associated_token_account::get_associated_token_address_with_program_id(
+            &owner, &mint, program,
+        )
+    };
+
+    let (info, token_account, account) = match cached_mint(&mint) {
+        Some(info) => {
+            let token_account = ata(&info.token_program.id());
+            let account = RPC_CLIENT
+                .get_multiple_accounts(&[token_account])
+                .into_sss_error("Failed to get token account from rpc")?
+                .pop()
+                .flatten();
+            (info, token_account, account)
+        }
+        None => {
+            // The token program is unknown, so fetch both possible account addresses
+            let legacy = ata(&spl_token::id());
+            let token_2022 = ata(&spl_token_2022::id());
+            let mut accounts = RPC_CLIENT
+                .get_multiple_accounts(&[mint, legacy, token_2022])
+                .into_sss_error("Failed to get token accounts from rpc")?
+                .into_iter();
+            let mint_account = accounts.next().flatten().ok_or_else(|| {
+                SssError::TokenError(format!("Mint account {} does not exist", mint))
+            })?;
+            let info = MintInfo::from_account(&mint, &mint_account)?;
+            cache_mint(mint, info);
+
+            let (legacy_account, token_2022_account) =
+                (accounts.next().flatten(), accounts.next().flatten());
+            match info.token_program {
+                TokenProgram::Legacy => (info, legacy, legacy_account),
+                TokenProgram::Token2022 => (info, token_2022, token_2022_account),
+            }
+        }
+    };
+
+    let warnings: Vec<ResolutionWarning> = off_curve_warning(&owner, &token_account)
+        .into_iter()
+        .collect();
+    match account {
+        Some(account) => {
+            let state =
+                StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account.data)
+                    .into_sss_error("Failed to parse token account")?;
+            Ok(RecipientStatus {
+                token_account,
+                account_exists: true,
+                frozen: state.base.state == spl_token_2022::state::AccountState::Frozen,
+                balance: state.base.amount,
+                creation_lamports: 0,
+                platform_fee_lamports,
+                warnings,
+            })
+        }
+        None => {
+            let creation_lamports = RPC_CLIENT
+                .get_minimum_balance_for_rent_exemption(info.token_account_len)
+                .into_sss_error("Failed to get rent exemption from rpc")?;
+            Ok(RecipientStatus {
+                token_account,
+                account_exists: false,
+                frozen: false,
+                balance: 0,
+                creation_lamports,
+                platform_fee_lamports,
+                warnings,
+            })
+        }
+    }
+}
+
+/// Emits `MintConfirmed` for a confirmed mint transaction
+pub(crate) fn emit_mint_confirmed(signature: &str, mint: Pubkey, owner: Pubkey, amount: u64) {
+    emit(SssEvent::MintConfirmed {
+        signature: signature.to_string(),
+        mint,
+        owner,
+        amount,
+    });
+}
+
+/// Pre-flight check shared by every operation acting on an existing mint
+///
+/// In strict mode, refuses mints outside the trusted set so a spoofed token carrying a
+/// familiar name cannot be acted on.
+///
+/// # Errors
+///
+/// Returns a `TokenError` if strict mode is enabled and the mint is not trusted
+pub(crate) fn ensure_trusted_mint(mint: &Pubkey) -> SssResult<()> {
+    if strict_mode_enabled() && !is_trusted_mint(mint) {
+        return Err(SssError::TokenError(format!(
+            "mint {} is not in the trusted set",
+            mint
+        )));
+    }
+    Ok(())
+}
+
+/// Returns the metadata account minting goes through, or `None` for a mint without one
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the mint has no metadata account and the options require one
+pub(crate) fn mint_metadata(
+    client: &RpcClient,
+    mint: &Pubkey,
+    options: &MintOptions,
+) -> SssResult<Option<Pubkey>> {
+    if has_metadata(client, mint)? {
+        return Ok(Some(metadata_pda(mint)));
+    }
+    if options.require_metadata {
+        return Err(SssError::TokenError(format!(
+            "Mint {} has no Metaplex metadata account, e.g. because it was created with the \
+             plain spl-token program; add one with attach_metadata_to_existing_mint",
+            mint
+        )));
+    }
+    Ok(None)
+}
+
+/// Builds the instructions minting tokens to the associated account of a wallet without
+/// sending them
+///
+/// The payer is the mint authority. The mint is assumed to have Metaplex metadata and not
+/// to create its accounts frozen; for Token-2022 mints with a frozen default state use
+/// [`mint_token_with_options`].
+///
+/// # Arguments
+///
+/// * `payer` - The fee payer and mint authority
+/// * `mint` - The public key of the token's mint account
+/// * `owner` - The wallet receiving the tokens
+/// * `amount` - The amount of tokens to mint, in base units or as a [`UiAmount`](crate::UiAmount)
+/// * `options` - The token program and the state the recipient account is left in
+///
+/// # Returns
+///
+/// The instructions, in execution order
+pub fn build_mint_ix(
+    payer: &Pubkey,
+    mint: &Pubkey,
+    owner: &Pubkey,
+    amount: u64,
+    options: &MintOptions,
+) -> SssResult<Vec<Instruction>> {
+    mint_instructions(
+        payer,
+        payer,
+        mint,
+        Some(metadata_pda(mint)),
+        owner,
+        amount,
+        options,
+        RecipientLock::Unlocked,
+    )
+}
+
+/// Builds the instructions minting `amount` tokens to the associated account of `owner`
+///
+/// The recipient account is created by the same transaction, by the mint instruction or the
+/// explicit create for locked accounts, so a failed mint never leaves it funded but empty.
+///
+/// # Arguments
+///
+/// * `payer` - The account paying for the recipient token account
+/// * `authority` - The mint and freeze authority
+/// * `mint` - The public key of the token's mint account
+/// * `metadata` - The metadata account of the mint, `None` to mint with the plain token
+///   program, see [`mint_metadata`]
+/// * `owner` - The wallet receiving the tokens
+/// * `amount` - The amount of tokens to mint, in base units or as a [`UiAmount`](crate::UiAmount)
+/// * `options` - The token program and the state the recipient account is left in
+/// * `lock` - How the mint keeps the recipient account frozen, if it does
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn mint_instructions(
+    payer: &Pubkey,
+    authority: &Pubkey,
+    mint: &Pubkey,
+    metadata: Option<Pubkey>,
+    owner: &Pubkey,
+    amount: u64,
+    options: &MintOptions,
+    lock: RecipientLock,
+) -> SssResult<Vec<Instruction>> {
+    let token_program = options.token_program.id();
+    let token = spl_associated_token_account::get_associated_token_address_with_program_id(
+        owner,
+        mint,
+        &token_program,
+    );
+
+    // At most create, thaw, mint and freeze
+    let mut instructions = Vec::with_capacity(4);
+
+    // MintV1 creates a missing recipient account itself, a plain MintTo does not
+    if lock == RecipientLock::FrozenByDefault || metadata.is_none() {
+        instructions.push(
+            spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+                payer,
+                owner,
+                mint,
+                &token_program,
+            ),
+        );
+    }
+    if matches!(
+        lock,
+        RecipientLock::FrozenByDefault
+            | RecipientLock::Soulbound {
+                account_exists: true
+            }
+    ) {
+        instructions.push(
+            spl_token_2022::instruction::thaw_account(&token_program, &token, mint, authority, &[])
+                .into_sss_error("Failed to build token thaw instruction")?,
+        );
+    }
 
     // Create the mint instruction
-    let mint_ix = MintV1Builder::new()
-        .token(token)
-        .token_owner(Some(owner))
-        .metadata(metadata)
-        .mint(mint)
-        .authority(authority.pubkey())
-        .payer(payer.pubkey())
-        .amount(amount)
-        .instruction();
-
-    // Create the message
-    let message = Message::new(&[mint_ix], Some(&payer.pubkey()));
-
-    // Get the latest blockhash
-    let blockhash = RPC_CLIENT
-        .get_latest_blockhash()
-        .into_sss_error("Failed to get latest blockhash")?;
-
-    // Create and sign the transaction
-    let tx = Transaction::new(&[&authority, &payer], message, blockhash);
-
-    // Send and confirm the transaction
-    let signature = RPC_CLIENT
-        .send_and_confirm_transaction(&tx)
-        .into_sss_error("Failed to send and confirm transaction")?;
-
-    Ok(signature.to_string())
+    let mint_ix = match metadata {
+        Some(metadata) => instructions::mint_v1(MintV1Params {
+            token: Some(token),
+            token_owner: Some(*owner),
+            metadata: Some(metadata),
+            master_edition: None,
+            token_record: None,
+            mint: Some(*mint),
+            authority: Some(*authority),
+            payer: Some(*payer),
+            amount,
+            token_standard: TokenStandard::Fungible,
+            spl_token_program: token_program,
+        })?,
+        None => spl_token_2022::instruction::mint_to(
+            &token_program,
+            mint,
+            &token,
+            authority,
+            &[],
+            amount,
+        )
+        .into_sss_error("Failed to build token mint instruction")?,
+    };
+    instructions.push(mint_ix);
+
+    // Freeze the recipient account in the same transaction so the tokens are never spendable
+    if options.initial_state == AccountState::Frozen || lock != RecipientLock::Unlocked {
+        instructions.push(freeze_instruction(
+            options.token_program,
+            &token,
+            mint,
+            authority,
+        )?);
+    }
+
+    Ok(instructions)
+}
+
+/// Builds the instructions creating a Token-2022 mint with the extensions of the options
+///
+/// The extensions must be initialized before the mint, so the account is created up front
+/// instead of by the metadata program: `DefaultAccountState` for accounts starting frozen
+/// and `NonTransferable` for soulbound tokens.
+pub(crate) fn extended_mint_instructions(
+    client: &RpcClient,
+    payer: &Pubkey,
+    mint: &Pubkey,
+    decimals: u8,
+    options: &CreateOptions,
+) -> SssResult<Vec<Instruction>> {
+    let program_id = spl_token_2022::id();
+    let frozen = options.initial_state == AccountState::Frozen;
+    let mut extensions = Vec::with_capacity(2);
+    if frozen {
+        extensions.push(ExtensionType::DefaultAccountState);
+    }
+    if options.soulbound {
+        extensions.push(ExtensionType::NonTransferable);
+    }
+    let space =
+        ExtensionType::try_calculate_account_len::<spl_token_2022::state::Mint>(&extensions)
+            .into_sss_error("Failed to calculate token mint size")?;
+    let lamports = client
+        .get_minimum_balance_for_rent_exemption(space)
+        .into_sss_error("Failed to get rent exemption from rpc")?;
+
+    let mut instructions = vec![system_instruction::create_account(
+        payer,
+        mint,
+        lamports,
+        space as u64,
+        &program_id,
+    )];
+    if frozen {
+        instructions.push(
+            spl_token_2022::extension::default_account_state::instruction::initialize_default_account_state(
+                &program_id,
+                mint,
+                &spl_token_2022::state::AccountState::Frozen,
+            )
+            .into_sss_error("Failed to build token default state instruction")?,
+        );
+    }
+    if options.soulbound {
+        instructions.push(
+            spl_token_2022::instruction::initialize_non_transferable_mint(&program_id, mint)
+                .into_sss_error("Failed to build token non-transferable instruction")?,
+        );
+    }
+    instructions.push(
+        spl_token_2022::instruction::initialize_mint2(
+            &program_id,
+            mint,
+            payer,
+            Some(payer),
+            decimals,
+        )
+        .into_sss_error("Failed to build token mint initialization instruction")?,
+    );
+    Ok(instructions)
+}
+
+/// How a mint keeps the token accounts it mints to frozen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RecipientLock {
+    /// The mint does not freeze its token accounts
+    Unlocked,
+    /// Token-2022 accounts start frozen, so they are thawed to receive tokens and frozen again
+    FrozenByDefault,
+    /// Accounts of a legacy soulbound mint are frozen after every mint; an existing account
+    /// is frozen, so it is thawed first
+    Soulbound {
+        /// Whether the recipient account already exists
+        account_exists: bool,
+    },
+}
+
+impl RecipientLock {
+    /// Resolves the lock of each recipient, looking up which accounts of a legacy soulbound
+    /// mint exist
+    ///
+    /// # Returns
+    ///
+    /// One lock per owner, in the order given
+    pub(crate) fn for_recipients(
+        self,
+        client: &RpcClient,
+        mint: &Pubkey,
+        owners: &[Pubkey],
+    ) -> SssResult<Vec<Self>> {
+        if !matches!(self, Self::Soulbound { .. }) {
+            return Ok(vec![self; owners.len()]);
+        }
+        let accounts: Vec<Pubkey> = owners
+            .iter()
+            .map(|owner| {
+                spl_associated_token_account::get_associated_token_address_with_program_id(
+                    owner,
+                    mint,
+                    &spl_token::id(),
+                )
+            })
+            .collect();
+        let mut locks = Vec::with_capacity(owners.len());
+        for chunk in accounts.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            let found = client
+                .get_multiple_accounts(chunk)
+                .into_sss_error("Failed to get token accounts from rpc")?;
+            locks.extend(found.iter().map(|account| Self::Soulbound {
+                account_exists: account.is_some(),
+            }));
+        }
+        Ok(locks)
+    }
+}
+
+/// Returns how a mint keeps its token accounts frozen, before looking at any recipient
+///
+/// Legacy mints are looked up in the soulbound set without an RPC call.
+pub(crate) fn recipient_lock(
+    client: &RpcClient,
+    mint: &Pubkey,
+    token_program: TokenProgram,
+) -> SssResult<RecipientLock> {
+    Ok(match token_program {
+        TokenProgram::Legacy if is_registered(mint) => RecipientLock::Soulbound {
+            account_exists: false,
+        },
+        TokenProgram::Legacy => RecipientLock::Unlocked,
+        TokenProgram::Token2022 if is_frozen_by_default(client, mint)? => {
+            RecipientLock::FrozenByDefault
+        }
+        TokenProgram::Token2022 => RecipientLock::Unlocked,
+    })
+}
+
+/// Returns whether a Token-2022 mint creates its token accounts frozen
+pub(crate) fn is_frozen_by_default(client: &RpcClient, mint: &Pubkey) -> SssResult<bool> {
+    let data = client
+        .get_account_data(mint)
+        .into_sss_error("Failed to get mint account from rpc")?;
+    let state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)
+        .into_sss_error("Failed to parse token mint account")?;
+
+    Ok(state
+        .get_extension::<DefaultAccountState>()
+        .map(|ext| ext.state == spl_token_2022::state::AccountState::Frozen as u8)
+        .unwrap_or(false))
+}
+
+/// Builds a freeze instruction for a token account
+fn freeze_instruction(
+    token_program: TokenProgram,
+    account: &Pubkey,
+    mint: &Pubkey,
+    freeze_authority: &Pubkey,
+) -> SssResult<Instruction> {
+    match token_program {
+        TokenProgram::Legacy => spl_token::instruction::freeze_account(
+            &spl_token::id(),
+            account,
+            mint,
+            freeze_authority,
+            &[],
+        ),
+        TokenProgram::Token2022 => spl_token_2022::instruction::freeze_account(
+            &spl_token_2022::id(),
+            account,
+            mint,
+            freeze_authority,
+            &[],
+        ),
+    }
+    .into_sss_error("Failed to build token freeze instruction")
 }
@@ -0,0 +1,299 @@
+//! Finding the token account an owner actually holds a mint in
+//!
+//! Wallets hold their tokens in associated token accounts, but owners that are
+//! program-derived addresses, e.g. custodial vaults, often use accounts their program
+//! created at other addresses. Deriving the associated account for them silently points at
+//! an account nobody reads, so callers choose how the account is found.
+
+use crate::RPC_CLIENT;
+use crate::amount::{RawAmount, UiAmount};
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::mint_cache::mint_info;
+use serde_json::json;
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_rpc_client_api::request::RpcRequest;
+use solana_rpc_client_api::response::{Response, RpcKeyedAccount};
+use solana_sdk::{account::Account, pubkey::Pubkey};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_token_2022::extension::StateWithExtensions;
+use spl_token_2022::state::AccountState;
+use std::fmt;
+use std::str::FromStr;
+
+/// How the token account of an owner is found
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TokenAccountResolution {
+    /// The associated token account of the owner, as for ordinary wallets
+    #[default]
+    Ata,
+    /// A known token account, which must hold the mint and belong to the owner
+    ExplicitAccount(Pubkey),
+    /// Every token account of the owner holding the mint is listed and the one with the
+    /// largest balance is used, falling back to the associated account if there is none
+    ScanOwner,
+}
+
+/// Something about a resolved token account the caller should double-check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResolutionWarning {
+    /// The owner is off-curve, i.e. a program-derived address, but its associated token
+    /// account was used; the owning program may keep its tokens elsewhere
+    OffCurveOwner {
+        /// The off-curve owner
+        owner: Pubkey,
+        /// The associated token account that was used
+        token_account: Pubkey,
+    },
+    /// The owner holds the mint in several token accounts and the largest was used
+    MultipleAccounts {
+        /// The account that was used
+        chosen: Pubkey,
+        /// The other accounts holding the mint
+        others: Vec<Pubkey>,
+    },
+}
+
+impl fmt::Display for ResolutionWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OffCurveOwner {
+                owner,
+                token_account,
+            } => write!(
+                f,
+                "Owner {} is off-curve, so its program may not use the associated token account {}",
+                owner, token_account
+            ),
+            Self::MultipleAccounts { chosen, others } => write!(
+                f,
+                "The owner holds the mint in {} token accounts; used {}, the largest",
+                others.len() + 1,
+                chosen
+            ),
+        }
+    }
+}
+
+/// Balance of an owner's token account, see [`get_token_balance`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenBalance {
+    /// The token account that was resolved
+    pub token_account: Pubkey,
+    /// Whether the token account exists
+    pub account_exists: bool,
+    /// The balance in base units, 0 if the account does not exist
+    pub balance: u64,
+    /// The number of decimal places of the mint
+    pub decimals: u8,
+    /// Whether the token account is frozen
+    pub frozen: bool,
+    /// What the caller should double-check about the resolved account
+    pub warnings: Vec<ResolutionWarning>,
+}
+
+impl TokenBalance {
+    /// Returns the balance in whole tokens, e.g. `1.5`
+    pub fn ui_balance(&self) -> UiAmount {
+        RawAmount(self.balance).to_ui(self.decimals)
+    }
+}
+
+/// Returns an owner's balance of a mint
+///
+/// # Arguments
+///
+/// * `mint` - The public key of the token's mint account
+/// * `owner` - The wallet or program-derived address holding the tokens
+/// * `resolution` - How the owner's token account is found
+///
+/// # Returns
+///
+/// The resolved token account and its balance, with a warning if the owner is off-curve and
+/// its associated account was used, or if it holds the mint in several accounts
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the mint does not exist, or an explicit account does not hold
+/// the mint or belong to the owner, and an `RpcError` if the accounts cannot be read
+pub fn get_token_balance(
+    mint: Pubkey,
+    owner: Pubkey,
+    resolution: &TokenAccountResolution,
+) -> SssResult<TokenBalance> {
+    let info = mint_info(&mint)?;
+    let resolved = resolve_token_account(
+        &RPC_CLIENT,
+        &mint,
+        &owner,
+        &info.token_program.id(),
+        resolution,
+    )?;
+    Ok(TokenBalance {
+        token_account: resolved.address,
+        account_exists: resolved.state.is_some(),
+        balance: resolved.state.map_or(0, |state| state.amount),
+        decimals: info.decimals,
+        frozen: resolved.state.is_some_and(|state| state.frozen),
+        warnings: resolved.warnings,
+    })
+}
+
+/// The parts of a token account the resolution looks at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct HeldState {
+    pub amount: u64,
+    pub frozen: bool,
+}
+
+/// A token account found for an owner
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ResolvedTokenAccount {
+    pub address: Pubkey,
+    /// The account's state, `None` if it does not exist
+    pub state: Option<HeldState>,
+    pub warnings: Vec<ResolutionWarning>,
+}
+
+/// Finds the token account of an owner for a mint
+pub(crate) fn resolve_token_account(
+    client: &RpcClient,
+    mint: &Pubkey,
+    owner: &Pubkey,
+    token_program: &Pubkey,
+    resolution: &TokenAccountResolution,
+) -> SssResult<ResolvedTokenAccount> {
+    match resolution {
+        TokenAccountResolution::Ata => associated_account(client, mint, owner, token_program),
+        TokenAccountResolution::ExplicitAccount(address) => {
+            let account = client
+                .get_multiple_accounts(&[*address])
+                .into_sss_error("Failed to get token account from rpc")?
+                .pop()
+                .flatten();
+            let state = account
+                .map(|account| held_state(address, &account, mint, owner))
+                .transpose()?;
+            Ok(ResolvedTokenAccount {
+                address: *address,
+                state,
+                warnings: Vec::new(),
+            })
+        }
+        TokenAccountResolution::ScanOwner => {
+            let mut held = owner_token_accounts(client, mint, owner)?;
+            if held.is_empty() {
+                return associated_account(client, mint, owner, token_program);
+            }
+            held.sort_by_key(|(_, state)| std::cmp::Reverse(state.amount));
+            let (address, state) = held.remove(0);
+            let warnings = if held.is_empty() {
+                Vec::new()
+            } else {
+                vec![ResolutionWarning::MultipleAccounts {
+                    chosen: address,
+                    others: held.into_iter().map(|(address, _)| address).collect(),
+                }]
+            };
+            Ok(ResolvedTokenAccount {
+                address,
+                state: Some(state),
+                warnings,
+            })
+        }
+    }
+}
+
+/// Returns the warning for an off-curve owner of an associated token account, if it is one
+pub(crate) fn off_curve_warning(
+    owner: &Pubkey,
+    token_account: &Pubkey,
+) -> Option<ResolutionWarning> {
+    (!owner.is_on_curve()).then_some(ResolutionWarning::OffCurveOwner {
+        owner: *owner,
+        token_account: *token_account,
+    })
+}
+
+/// Resolves the associated token account, warning if the owner is off-curve
+fn associated_account(
+    client: &RpcClient,
+    mint: &Pubkey,
+    owner: &Pubkey,
+    token_program: &Pubkey,
+) -> SssResult<ResolvedTokenAccount> {
+    let address = get_associated_token_address_with_program_id(owner, mint, token_program);
+    let account = client
+        .get_multiple_accounts(&[address])
+        .into_sss_error("Failed to get token account from rpc")?
+        .pop()
+        .flatten();
+    let state = account
+        .map(|account| held_state(&address, &account, mint, owner))
+        .transpose()?;
+    Ok(ResolvedTokenAccount {
+        address,
+        state,
+        warnings: off_curve_warning(owner, &address).into_iter().collect(),
+    })
+}
+
+/// Lists the token accounts of an owner holding a mint, under the mint's token program
+fn owner_token_accounts(
+    client: &RpcClient,
+    mint: &Pubkey,
+    owner: &Pubkey,
+) -> SssResult<Vec<(Pubkey, HeldState)>> {
+    let response: Response<Vec<RpcKeyedAccount>> = client
+        .send(
+            RpcRequest::GetTokenAccountsByOwner,
+            json!([
+                owner.to_string(),
+                { "mint": mint.to_string() },
+                { "encoding": "base64", "commitment": client.commitment().commitment },
+            ]),
+        )
+        .into_sss_error("Failed to get owner token accounts from rpc")?;
+    let mut held = Vec::with_capacity(response.value.len());
+    for keyed in response.value {
+        let address = Pubkey::from_str(&keyed.pubkey)
+            .into_sss_error("Failed to parse token account address from rpc")?;
+        let Some(account) = keyed.account.decode::<Account>() else {
+            continue;
+        };
+        held.push((address, held_state(&address, &account, mint, owner)?));
+    }
+    Ok(held)
+}
+
+/// Parses a token account, checking it holds the mint and belongs to the owner
+fn held_state(
+    address: &Pubkey,
+    account: &Account,
+    mint: &Pubkey,
+    owner: &Pubkey,
+) -> SssResult<HeldState> {
+    if account.owner != spl_token::id() && account.owner != spl_token_2022::id() {
+        return Err(SssError::TokenError(format!(
+            "Account {} is not owned by a token program",
+            address
+        )));
+    }
+    let state = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account.data)
+        .into_sss_error("Failed to parse token account")?;
+    if state.base.mint != *mint {
+        return Err(SssError::TokenError(format!(
+            "Token account {} holds mint {}, not {}",
+            address, state.base.mint, mint
+        )));
+    }
+    if state.base.owner != *owner {
+        return Err(SssError::TokenError(format!(
+            "Token account {} belongs to {}, not {}",
+            address, state.base.owner, owner
+        )));
+    }
+    Ok(HeldState {
+        amount: state.base.amount,
+        frozen: state.base.state == AccountState::Frozen,
+    })
+}
@@ -0,0 +1,990 @@
+//! Shared transaction signing and submission
+
+use crate::blockhash::{fresh_blockhash, invalidate_blockhash, latest_blockhash};
+use crate::confirm::{
+    CancelToken, ConfirmationOutcome, ConfirmationStrategy, Confirmer,
+    DEFAULT_CONFIRMATION_TIMEOUT, TxStatus,
+};
+use crate::error::{SssError, SssResult};
+use crate::events::{SssEvent, emit, low_balance_threshold};
+use crate::in_flight::DuplicatePolicy;
+use crate::inspect::{TxInspection, describe_instruction_error, inspect_message};
+use crate::journal::{record_outcome, record_sent, replayed_signature};
+use crate::load_dotenv;
+use crate::metrics::{record_rate_limit_hit, record_rpc_retry};
+use crate::payer::ensure_signing_permitted;
+use crate::profiles::profile_setting;
+use crate::retry::{RetryPolicy, RetryTrail, classify_client_error, with_backoff};
+use base64::prelude::{BASE64_STANDARD, Engine};
+use lazy_static::lazy_static;
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_rpc_client_api::client_error::{Error as ClientError, ErrorKind as ClientErrorKind};
+use solana_rpc_client_api::config::RpcSendTransactionConfig;
+use solana_rpc_client_api::request::{RpcError, RpcResponseErrorData};
+use solana_sdk::{
+    instruction::Instruction,
+    message::Message,
+    packet::PACKET_DATA_SIZE,
+    pubkey::Pubkey,
+    signature::{Keypair, SIGNATURE_BYTES, Signature},
+    signer::Signer,
+    system_instruction,
+    transaction::{Transaction, TransactionError, VersionedTransaction},
+};
+use std::env;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Instant;
+
+lazy_static! {
+    /// Platform fee charged when none is set explicitly, from `SSS_PLATFORM_FEE_ACCOUNT`
+    /// and `SSS_PLATFORM_FEE_LAMPORTS`
+    static ref DEFAULT_PLATFORM_FEE: Option<(Pubkey, u64)> = {
+        load_dotenv();
+        let account = env::var("SSS_PLATFORM_FEE_ACCOUNT")
+            .ok()
+            .and_then(|v| Pubkey::from_str(&v).ok())?;
+        let lamports = env::var("SSS_PLATFORM_FEE_LAMPORTS")
+            .ok()
+            .and_then(|v| v.parse().ok())?;
+        Some((account, lamports))
+    };
+}
+
+/// Per-transaction options applied when signing and sending
+#[derive(Debug, Clone, Default)]
+pub struct TxOptions {
+    /// Called with the inspected message before signing; returning `false` aborts
+    pub inspection_hook: Option<fn(&TxInspection) -> bool>,
+    /// Caller-chosen key recorded with the transaction in the operation journal
+    pub idempotency_key: Option<String>,
+    /// Lamports transferred from the fee payer to a platform fee account in every
+    /// transaction that mints tokens
+    ///
+    /// When `None`, the fee of the profile selected on the current thread applies, or else
+    /// the fee configured by `SSS_PLATFORM_FEE_ACCOUNT` and `SSS_PLATFORM_FEE_LAMPORTS`, if
+    /// any. A fee of 0 lamports disables it.
+    pub platform_fee: Option<(Pubkey, u64)>,
+    /// What a mint does while an identical mint (same mint, owner, amount and
+    /// idempotency key) is still unconfirmed
+    pub on_duplicate: DuplicatePolicy,
+    /// How long transient RPC errors, such as a node lagging behind, are retried
+    pub retry: RetryPolicy,
+    /// Instructions placed before the library's own in token creation, minting and
+    /// transfers, so they succeed or fail together
+    pub pre_instructions: Vec<Instruction>,
+    /// Instructions placed after the library's own in token creation, minting and
+    /// transfers, so they succeed or fail together
+    pub post_instructions: Vec<Instruction>,
+    /// Additional keypairs signing the transaction, for accounts the pre and post
+    /// instructions require to sign
+    ///
+    /// Only the keypairs the transaction actually requires sign it.
+    pub extra_signers: Vec<Arc<Keypair>>,
+}
+
+impl TxOptions {
+    /// Returns the platform fee account and amount charged with these options
+    ///
+    /// # Returns
+    ///
+    /// The explicit fee, the fee of the active profile or the configured default, or
+    /// `None` if no fee or a fee of 0 lamports applies
+    pub fn resolved_platform_fee(&self) -> Option<(Pubkey, u64)> {
+        self.platform_fee
+            .or_else(|| {
+                profile_setting(|profile| profile.platform_fee)
+                    .ok()
+                    .flatten()
+                    .flatten()
+            })
+            .or(*DEFAULT_PLATFORM_FEE)
+            .filter(|(_, lamports)| *lamports > 0)
+    }
+
+    /// Builds the transfer paying the platform fee, if one applies
+    ///
+    /// # Arguments
+    ///
+    /// * `payer` - The fee payer of the transaction
+    pub(crate) fn platform_fee_instruction(&self, payer: &Pubkey) -> Option<Instruction> {
+        self.resolved_platform_fee()
+            .map(|(account, lamports)| system_instruction::transfer(payer, &account, lamports))
+    }
+
+    /// Places the pre and post instructions around the instructions of an operation
+    ///
+    /// # Arguments
+    ///
+    /// * `instructions` - The instructions built by the library
+    pub(crate) fn wrap_instructions(&self, instructions: Vec<Instruction>) -> Vec<Instruction> {
+        let mut wrapped = Vec::with_capacity(
+            self.pre_instructions.len() + instructions.len() + self.post_instructions.len(),
+        );
+        wrapped.extend(self.pre_instructions.iter().cloned());
+        wrapped.extend(instructions);
+        wrapped.extend(self.post_instructions.iter().cloned());
+        wrapped
+    }
+}
+
+/// Serializes a message to base64 in its wire format
+///
+/// The encoding is stable, so it can be stored and compared byte for byte, e.g. to
+/// detect changes in the instructions a builder emits.
+///
+/// # Arguments
+///
+/// * `message` - The message to serialize
+pub fn message_to_base64(message: &Message) -> String {
+    BASE64_STANDARD.encode(message.serialize())
+}
+
+/// Parses a message serialized by [`message_to_base64`]
+///
+/// # Arguments
+///
+/// * `encoded` - The base64 wire format of the message
+///
+/// # Errors
+///
+/// Returns an `FfiError` if the data is not a base64-encoded message
+pub fn message_from_base64(encoded: &str) -> SssResult<Message> {
+    let bytes = BASE64_STANDARD
+        .decode(encoded.trim())
+        .map_err(|e| SssError::FfiError(format!("Invalid base64 message: {}", e)))?;
+    bincode::deserialize(&bytes)
+        .map_err(|e| SssError::FfiError(format!("Invalid serialized message: {}", e)))
+}
+
+/// Size of a transaction checked against the packet limit, see [`assert_tx_size`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TxSizeInfo {
+    /// Size of the signed transaction in bytes
+    pub size: usize,
+    /// Bytes left before the transaction reaches `PACKET_DATA_SIZE`
+    pub remaining: usize,
+}
+
+/// Checks that the signed transaction carrying a message fits in a packet
+///
+/// The size counts the signatures, so it can be checked before a blockhash is requested;
+/// the blockhash does not change the size.
+///
+/// # Arguments
+///
+/// * `message` - The message to measure
+/// * `signer_count` - The number of signatures the transaction will carry
+///
+/// # Returns
+///
+/// The size of the transaction and the bytes left before the limit
+///
+/// # Errors
+///
+/// Returns `TransactionTooLarge` with the number of bytes over the limit if the transaction
+/// does not fit
+pub fn assert_tx_size(message: &Message, signer_count: usize) -> SssResult<TxSizeInfo> {
+    let size = transaction_size(message, signer_count)?;
+    match PACKET_DATA_SIZE.checked_sub(size) {
+        Some(remaining) => Ok(TxSizeInfo { size, remaining }),
+        None => Err(SssError::TransactionTooLarge {
+            size,
+            limit: PACKET_DATA_SIZE,
+            items_to_drop: None,
+        }),
+    }
+}
+
+/// Returns how many of the leading items fit in a single transaction
+///
+/// Batches use this to decide where a transaction ends instead of a fixed item count.
+///
+/// # Arguments
+///
+/// * `items` - The items still to send, in order
+/// * `signer_count` - The number of signatures the transaction will carry
+/// * `build` - Builds the message carrying a prefix of `items`
+///
+/// # Errors
+///
+/// Returns `TransactionTooLarge` if not even the first item fits, or the error of `build`
+pub(crate) fn fitting_items<T>(
+    items: &[T],
+    signer_count: usize,
+    build: impl Fn(&[T]) -> SssResult<Message>,
+) -> SssResult<usize> {
+    let mut count = 0;
+    while count < items.len() {
+        let size = transaction_size(&build(&items[..=count])?, signer_count)?;
+        if size > PACKET_DATA_SIZE {
+            if count == 0 {
+                return Err(SssError::TransactionTooLarge {
+                    size,
+                    limit: PACKET_DATA_SIZE,
+                    items_to_drop: None,
+                });
+            }
+            break;
+        }
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Checks that all items fit in a single transaction, like [`assert_tx_size`]
+///
+/// # Errors
+///
+/// Returns `TransactionTooLarge` naming how many trailing items to drop to fit, or the
+/// error of `build`
+pub(crate) fn assert_items_fit<T>(
+    items: &[T],
+    signer_count: usize,
+    build: impl Fn(&[T]) -> SssResult<Message>,
+) -> SssResult<TxSizeInfo> {
+    match assert_tx_size(&build(items)?, signer_count) {
+        Err(SssError::TransactionTooLarge { size, limit, .. }) => {
+            let fitting = fitting_items(items, signer_count, build)?;
+            Err(SssError::TransactionTooLarge {
+                size,
+                limit,
+                items_to_drop: Some(items.len() - fitting),
+            })
+        }
+        result => result,
+    }
+}
+
+/// Returns the number of signatures a message requires
+pub(crate) fn required_signatures(message: &Message) -> usize {
+    message.header.num_required_signatures as usize
+}
+
+/// Returns the size in bytes of the transaction carrying a message and its signatures
+fn transaction_size(message: &Message, signer_count: usize) -> SssResult<usize> {
+    let message_size = bincode::serialized_size(message)
+        .map_err(|e| SssError::FfiError(format!("Failed to serialize message: {}", e)))?;
+    // The signature count is a compact-u16: 7 bits per byte
+    let length_prefix = match signer_count {
+        0..0x80 => 1,
+        0x80..0x4000 => 2,
+        _ => 3,
+    };
+    Ok(length_prefix + signer_count * SIGNATURE_BYTES + message_size as usize)
+}
+
+/// Refuses signing in read-only mode or when the active role may not run the current
+/// operation, then runs the inspection hook, if any, on a message about to be signed
+///
+/// The role is checked here as well as on the send path, because signed transactions
+/// handed out unsent, such as claims, can be submitted by anyone.
+fn check_before_signing(message: &Message, options: &TxOptions) -> SssResult<()> {
+    ensure_signing_permitted()?;
+    if let Some(hook) = options.inspection_hook
+        && !hook(&inspect_message(message))
+    {
+        return Err(SssError::KeypairError(
+            "Inspection hook refused to sign the transaction".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the signers with duplicates removed, keeping the first keypair of each pubkey
+///
+/// The payer is often also the mint or update authority; passing it twice would make it
+/// sign twice, which some RPC versions reject as a duplicate signer.
+pub(crate) fn collect_signers<'a>(signers: &[&'a Keypair]) -> Vec<&'a Keypair> {
+    let mut unique: Vec<&Keypair> = Vec::with_capacity(signers.len());
+    for signer in signers {
+        if !unique.iter().any(|s| s.pubkey() == signer.pubkey()) {
+            unique.push(signer);
+        }
+    }
+    unique
+}
+
+/// Returns the signers of a message: the given keypairs, then the extra signers of the
+/// options that the message requires, without duplicates
+fn signers_for<'a>(
+    message: &Message,
+    signers: &[&'a Keypair],
+    options: &'a TxOptions,
+) -> Vec<&'a Keypair> {
+    let required = &message.account_keys[..required_signatures(message)];
+    let mut all = signers.to_vec();
+    all.extend(
+        options
+            .extra_signers
+            .iter()
+            .map(|signer| signer.as_ref())
+            .filter(|signer| required.contains(&signer.pubkey())),
+    );
+    collect_signers(&all)
+}
+
+/// Signs a message with a cached blockhash, sends it and waits for confirmation
+///
+/// If the cluster rejects the cached blockhash, the cache is invalidated and the
+/// transaction is re-signed and sent once more with a freshly fetched blockhash.
+///
+/// # Arguments
+///
+/// * `client` - The RPC client sending the transaction
+/// * `message` - The message to sign
+/// * `signers` - The keypairs required to sign the message
+/// * `options` - The inspection hook to consult before signing and the extra signers
+///
+/// # Returns
+///
+/// The transaction signature as a string
+pub(crate) fn sign_and_send(
+    client: &RpcClient,
+    message: Message,
+    signers: &[&Keypair],
+    options: &TxOptions,
+) -> SssResult<String> {
+    sign_and_send_reporting_attempt(client, message, signers, options).map_err(|(e, _)| e)
+}
+
+/// Signs and sends a message like [`sign_and_send`], reporting unconfirmed attempts
+///
+/// # Arguments
+///
+/// * `client` - The RPC client sending the transaction
+/// * `message` - The message to sign
+/// * `signers` - The keypairs required to sign the message
+/// * `options` - The inspection hook to consult before signing and the extra signers
+///
+/// # Returns
+///
+/// The transaction signature as a string
+///
+/// # Errors
+///
+/// Returns the error together with the signature of the last attempt if the transaction
+/// was sent but its outcome is unknown, e.g. because confirmation timed out. The attempt
+/// is `None` if the transaction was never sent or was rejected by the cluster.
+pub(crate) fn sign_and_send_reporting_attempt(
+    client: &RpcClient,
+    message: Message,
+    signers: &[&Keypair],
+    options: &TxOptions,
+) -> Result<String, (SssError, Option<String>)> {
+    check_before_signing(&message, options).map_err(|e| (e, None))?;
+    let signers = signers_for(&message, signers, options);
+    let blockhash = latest_blockhash(client).map_err(|e| (e, None))?;
+    let tx = Transaction::new(&signers, message.clone(), blockhash);
+
+    match send_journaled(client, &tx, &options.retry).map_err(|e| (e, None))? {
+        Ok(signature) => Ok(signature.to_string()),
+        Err(e) if e.error.get_transaction_error() == Some(TransactionError::BlockhashNotFound) => {
+            // The cached blockhash expired; retry once with a fresh one
+            record_rpc_retry();
+            let blockhash = fresh_blockhash(client).map_err(|e| (e, None))?;
+            let tx = Transaction::new(&signers, message, blockhash);
+            send_journaled(client, &tx, &options.retry)
+                .map_err(|e| (e, None))?
+                .map(|signature| signature.to_string())
+                .map_err(|e| failed_attempt(e, &tx))
+        }
+        Err(e) => Err(failed_attempt(e, &tx)),
+    }
+}
+
+/// Converts a send failure, keeping the signature unless the cluster rejected the transaction
+fn failed_attempt(e: SendFailure, tx: &Transaction) -> (SssError, Option<String>) {
+    let attempt = e
+        .error
+        .get_transaction_error()
+        .is_none()
+        .then(|| tx.signatures[0].to_string());
+    (send_error(e), attempt)
+}
+
+/// Signs a message with a cached blockhash without sending it
+///
+/// Callers that must record the signature before submission, such as resumable batch
+/// jobs, sign with this and submit with [`send_signed`].
+///
+/// # Arguments
+///
+/// * `client` - The RPC client providing the blockhash
+/// * `message` - The message to sign
+/// * `signers` - The keypairs required to sign the message
+/// * `options` - The inspection hook to consult before signing and the extra signers
+///
+/// # Returns
+///
+/// The signed transaction
+pub(crate) fn sign_with_cached_blockhash(
+    client: &RpcClient,
+    message: Message,
+    signers: &[&Keypair],
+    options: &TxOptions,
+) -> SssResult<Transaction> {
+    check_before_signing(&message, options)?;
+    let blockhash = latest_blockhash(client)?;
+    Ok(Transaction::new(
+        &signers_for(&message, signers, options),
+        message,
+        blockhash,
+    ))
+}
+
+/// Partially signs a message with a freshly fetched blockhash
+///
+/// Used for transactions handed to another party to countersign, which may hold them
+/// for a while, so the blockhash is not taken from the cache.
+///
+/// # Arguments
+///
+/// * `client` - The RPC client providing the blockhash
+/// * `message` - The message to sign
+/// * `signers` - The keypairs signing now; other required signatures are left empty
+/// * `options` - The inspection hook to consult before signing and the extra signers
+///
+/// # Returns
+///
+/// The partially signed transaction
+pub(crate) fn sign_partial(
+    client: &RpcClient,
+    message: Message,
+    signers: &[&Keypair],
+    options: &TxOptions,
+) -> SssResult<Transaction> {
+    check_before_signing(&message, options)?;
+    let blockhash = client
+        .get_latest_blockhash()
+        .map_err(|e| SssError::RpcError(format!("Failed to get latest blockhash: {}", e)))?;
+    let mut tx = Transaction::new_unsigned(message);
+    let signers = signers_for(&tx.message, signers, options);
+    tx.try_partial_sign(&signers, blockhash)
+        .map_err(|e| SssError::KeypairError(format!("Failed to sign transaction: {}", e)))?;
+    Ok(tx)
+}
+
+/// Sends a signed transaction and waits for confirmation
+///
+/// Unlike [`sign_and_send`] the transaction is never re-signed, so its signature stays
+/// valid as a record of the attempt. An expired blockhash only invalidates the cache.
+///
+/// # Arguments
+///
+/// * `client` - The RPC client sending the transaction
+/// * `tx` - The signed transaction
+///
+/// # Returns
+///
+/// The transaction signature as a string
+pub(crate) fn send_signed(client: &RpcClient, tx: &Transaction) -> SssResult<String> {
+    send_journaled(client, tx, &RetryPolicy::default())?
+        .map(|signature| signature.to_string())
+        .map_err(|e| {
+            if e.error.get_transaction_error() == Some(TransactionError::BlockhashNotFound) {
+                invalidate_blockhash(client);
+            }
+            send_error(e)
+        })
+}
+
+/// Sends a signed versioned transaction and waits for confirmation
+///
+/// Used for transactions built by another party, which may be v0 transactions and are
+/// therefore sent once, without the retries of [`send_signed`].
+///
+/// # Arguments
+///
+/// * `client` - The RPC client sending the transaction
+/// * `tx` - The signed transaction
+///
+/// # Returns
+///
+/// The transaction signature as a string
+pub(crate) fn send_versioned(client: &RpcClient, tx: &VersionedTransaction) -> SssResult<String> {
+    ensure_signing_permitted()?;
+    let blockhash = *tx.message.recent_blockhash();
+    record_sent(&tx.signatures[0], &blockhash)?;
+    let signature = tx.signatures[0].to_string();
+    emit(SssEvent::TransactionSent {
+        signature: signature.clone(),
+    });
+    let outcome = client.send_and_confirm_transaction(tx);
+    record_outcome(&tx.signatures[0], &blockhash, &outcome);
+    match outcome {
+        Ok(_) => {
+            emit(SssEvent::TransactionConfirmed {
+                signature: signature.clone(),
+            });
+            Ok(signature)
+        }
+        Err(e) => {
+            if e.get_transaction_error() == Some(TransactionError::BlockhashNotFound) {
+                invalidate_blockhash(client);
+            }
+            emit(SssEvent::TransactionFailed {
+                signature,
+                error: e.to_string(),
+            });
+            record_if_rate_limited(&e.to_string());
+            Err(SssError::RpcError(format!(
+                "Failed to send and confirm transaction: {}",
+                e
+            )))
+        }
+    }
+}
+
+/// Sends a signed transaction once, without preflight and without waiting for confirmation
+///
+/// The RPC node is asked not to retry, so the caller must confirm the transaction with
+/// [`bulk_statuses`](crate::confirm::bulk_statuses) and rebroadcast it with [`rebroadcast`]
+/// while it is not found.
+///
+/// # Errors
+///
+/// Returns an error without sending if the role policy refuses the transaction or the
+/// journal entry cannot be written, and an `RpcError` if the node refuses the
+/// transaction; it may still have been forwarded
+pub(crate) fn send_unconfirmed(client: &RpcClient, tx: &Transaction) -> SssResult<()> {
+    ensure_signing_permitted()?;
+    record_sent(&tx.signatures[0], &tx.message.recent_blockhash)?;
+    emit(SssEvent::TransactionSent {
+        signature: tx.signatures[0].to_string(),
+    });
+    rebroadcast(client, tx)
+}
+
+/// Sends an already recorded transaction again, without preflight or node retries
+pub(crate) fn rebroadcast(client: &RpcClient, tx: &Transaction) -> SssResult<()> {
+    let config = RpcSendTransactionConfig {
+        skip_preflight: true,
+        max_retries: Some(0),
+        ..RpcSendTransactionConfig::default()
+    };
+    client
+        .send_transaction_with_config(tx, config)
+        .map(|_| ())
+        .map_err(|e| {
+            record_if_rate_limited(&e.to_string());
+            SssError::RpcError(format!("Failed to send transaction: {}", e))
+        })
+}
+
+/// Records the final status of a transaction sent with [`send_unconfirmed`]
+///
+/// Emits the confirmation or failure event and, inside a journaled operation, updates the
+/// journal entry. Other statuses are left for [`journal_reconcile`](crate::journal_reconcile).
+pub(crate) fn record_bulk_outcome(tx: &Transaction, status: &TxStatus) {
+    let signature = tx.signatures[0].to_string();
+    match status {
+        TxStatus::Confirmed => {
+            record_outcome::<()>(&tx.signatures[0], &tx.message.recent_blockhash, &Ok(()));
+            emit(SssEvent::TransactionConfirmed { signature });
+        }
+        TxStatus::Failed(error) => {
+            record_outcome::<()>(
+                &tx.signatures[0],
+                &tx.message.recent_blockhash,
+                &Err(error.clone().into()),
+            );
+            emit(SssEvent::TransactionFailed {
+                signature,
+                error: error.to_string(),
+            });
+        }
+        TxStatus::Processed | TxStatus::NotFound => {}
+    }
+}
+
+/// A transaction that could not be confirmed
+struct SendFailure {
+    /// The error of the last attempt
+    error: ClientError,
+    /// The attempts that failed with a retryable error before it
+    trail: RetryTrail,
+    /// The decoded program error and the last program logs, if a program failed
+    program_error: Option<String>,
+}
+
+/// Sends a transaction and waits for confirmation, recording it in the journal
+///
+/// Transient errors such as a lagging node are retried with the same signed transaction,
+/// so a retry can never land twice. A transaction the operation already confirmed under
+/// its idempotency key, as recorded in the dedup store, is not sent again and its
+/// signature is returned instead.
+///
+/// # Errors
+///
+/// Returns an error without sending if the role policy refuses the transaction or the
+/// journal entry cannot be written
+fn send_journaled(
+    client: &RpcClient,
+    tx: &Transaction,
+    policy: &RetryPolicy,
+) -> SssResult<Result<Signature, SendFailure>> {
+    ensure_signing_permitted()?;
+    if let Some(signature) = replayed_signature(client)? {
+        return Ok(Ok(signature));
+    }
+    record_sent(&tx.signatures[0], &tx.message.recent_blockhash)?;
+    let signature = tx.signatures[0].to_string();
+    emit(SssEvent::TransactionSent {
+        signature: signature.clone(),
+    });
+    let mut attempts = 0;
+    let mut retries = 0;
+    let (outcome, trail) = with_backoff::<_, Box<ClientError>>(
+        policy,
+        |e| classify_client_error(e).map(|rule| rule.reason),
+        |e, failed| {
+            record_rpc_retry();
+            record_if_rate_limited(&e.to_string());
+            retries += 1;
+            emit(SssEvent::RetryScheduled {
+                signature: signature.clone(),
+                attempt: retries,
+                reason: failed.reason.to_string(),
+                error: failed.error.clone(),
+                delay_ms: failed.delay.as_millis() as u64,
+            });
+        },
+        || {
+            attempts += 1;
+            match client.send_transaction(tx).map_err(Box::new) {
+                Ok(_) => {}
+                // An earlier attempt landed even though its confirmation failed
+                Err(e)
+                    if attempts > 1
+                        && e.get_transaction_error()
+                            == Some(TransactionError::AlreadyProcessed) => {}
+                Err(e) => return Err(e),
+            }
+            await_confirmation(client, tx)
+        },
+    );
+    let outcome = outcome.map_err(|e| *e);
+    record_outcome(&tx.signatures[0], &tx.message.recent_blockhash, &outcome);
+    let outcome = outcome.map_err(|error| SendFailure {
+        program_error: describe_program_failure(tx, &error),
+        error,
+        trail,
+    });
+    match &outcome {
+        Ok(_) => {
+            emit(SssEvent::TransactionConfirmed { signature });
+            check_payer_balance(client, tx);
+        }
+        Err(e) => emit(SssEvent::TransactionFailed {
+            signature,
+            error: e.error.to_string(),
+        }),
+    }
+    Ok(outcome)
+}
+
+/// Waits for a sent transaction with the default strategy and timeout
+///
+/// A transaction that timed out is reported without a transaction error, so callers treat
+/// it as possibly landed.
+fn await_confirmation(client: &RpcClient, tx: &Transaction) -> Result<Signature, Box<ClientError>> {
+    let deadline = Instant::now() + DEFAULT_CONFIRMATION_TIMEOUT;
+    let outcome = Confirmer::new(client).with_transaction(tx).confirm(
+        &tx.signatures[0],
+        &ConfirmationStrategy::default(),
+        deadline,
+        &CancelToken::default(),
+    );
+    let error: ClientError = match outcome {
+        Ok(ConfirmationOutcome::Confirmed { signature }) => return Ok(signature),
+        Ok(ConfirmationOutcome::Failed { error, .. }) => error.into(),
+        Ok(ConfirmationOutcome::TimedOut { signature }) => ClientErrorKind::Custom(format!(
+            "Transaction {} was not confirmed within {:?} and may still land",
+            signature, DEFAULT_CONFIRMATION_TIMEOUT
+        ))
+        .into(),
+        Err(e) => ClientErrorKind::Custom(e.to_string()).into(),
+    };
+    Err(Box::new(error))
+}
+
+/// Emits `PayerLowOnFunds` if the fee payer balance fell below the configured threshold
+fn check_payer_balance(client: &RpcClient, tx: &Transaction) {
+    let Some(threshold) = low_balance_threshold() else {
+        return;
+    };
+    let payer = tx.message.account_keys[0];
+    if let Ok(lamports) = client.get_balance(&payer)
+        && lamports < threshold
+    {
+        emit(SssEvent::PayerLowOnFunds {
+            payer,
+            lamports,
+            threshold,
+        });
+    }
+}
+
+/// Converts a send failure into an `SssError`, recording rate-limit rejections
+///
+/// The message lists every retried attempt before the final one.
+fn send_error(e: SendFailure) -> SssError {
+    let mut message = e.error.to_string();
+    record_if_rate_limited(&message);
+    if let Some(program_error) = &e.program_error {
+        message = format!(
+            "{} ({})",
+            message.trim_end_matches(['\n', ' ', ';']),
+            program_error
+        );
+    }
+    if e.trail.0.is_empty() {
+        return SssError::RpcError(format!(
+            "Failed to send and confirm transaction: {}",
+            message
+        ));
+    }
+    SssError::RpcError(format!(
+        "Failed to send and confirm transaction after {} retries: {} (retry trail: {})",
+        e.trail.0.len(),
+        message,
+        e.trail
+    ))
+}
+
+/// Number of program log lines kept in the description of a failed transaction
+const PROGRAM_LOG_LINES: usize = 3;
+
+/// Describes a program failure from its decoded error code and its last log lines
+///
+/// Rule-set rejections of programmable NFTs, for example, only explain themselves in the
+/// logs of the authorization rules program.
+fn describe_program_failure(tx: &Transaction, e: &ClientError) -> Option<String> {
+    let decoded = e
+        .get_transaction_error()
+        .and_then(|error| describe_instruction_error(&tx.message, &error));
+    let logs: Vec<&str> = match e.kind() {
+        ClientErrorKind::RpcError(RpcError::RpcResponseError {
+            data: RpcResponseErrorData::SendTransactionPreflightFailure(result),
+            ..
+        }) => result
+            .logs
+            .iter()
+            .flatten()
+            .filter_map(|line| line.strip_prefix("Program log: "))
+            .collect(),
+        _ => Vec::new(),
+    };
+    let logs = &logs[logs.len().saturating_sub(PROGRAM_LOG_LINES)..];
+
+    match (decoded, logs.is_empty()) {
+        (None, true) => None,
+        (None, false) => Some(format!("program logs: {}", logs.join(" | "))),
+        (Some(decoded), true) => Some(decoded),
+        (Some(decoded), false) => Some(format!("{}; program logs: {}", decoded, logs.join(" | "))),
+    }
+}
+
+/// Records a rate-limit rejection if the error message reports one
+fn record_if_rate_limited(message: &str) {
+    if message.contains("429") || message.contains("Too Many Requests") {
+        record_rate_limit_hit();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::TokenBuilder;
+    use crate::inspect::MEMO_PROGRAM_ID;
+    use crate::token::{MintOptions, RecipientLock, build_mint_ix, mint_instructions};
+    use solana_sdk::signature::keypair_from_seed;
+
+    fn keypair(byte: u8) -> Keypair {
+        keypair_from_seed(&[byte; 32]).unwrap()
+    }
+
+    fn sign(message: Message, signers: &[&Keypair]) -> Transaction {
+        let client = RpcClient::new_mock("succeeds".to_string());
+        sign_with_cached_blockhash(&client, message, signers, &TxOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn collect_signers_keeps_the_first_of_each_pubkey() {
+        let payer = keypair(1);
+        let payer_copy = payer.insecure_clone();
+        let other = keypair(2);
+        let unique = collect_signers(&[&payer, &other, &payer_copy, &other]);
+        let pubkeys: Vec<Pubkey> = unique.iter().map(|s| s.pubkey()).collect();
+        assert_eq!(pubkeys, vec![payer.pubkey(), other.pubkey()]);
+        assert!(std::ptr::eq(unique[0], &payer));
+    }
+
+    #[test]
+    fn payer_authority_signs_once() {
+        let payer = keypair(1);
+        let authority = payer.insecure_clone();
+        let mint = keypair(2).pubkey();
+        let owner = keypair(3).pubkey();
+        let instructions =
+            build_mint_ix(&payer.pubkey(), &mint, &owner, 10, &MintOptions::default()).unwrap();
+        let message = Message::new(&instructions, Some(&payer.pubkey()));
+        assert_eq!(required_signatures(&message), 1);
+
+        let tx = sign(message, &[&payer, &authority]);
+        assert_eq!(tx.signatures.len(), 1);
+        assert!(tx.verify().is_ok());
+    }
+
+    #[test]
+    fn separate_authority_signs_alongside_payer() {
+        let payer = keypair(1);
+        let authority = keypair(4);
+        let mint = keypair(2).pubkey();
+        let owner = keypair(3).pubkey();
+        let instructions = mint_instructions(
+            &payer.pubkey(),
+            &authority.pubkey(),
+            &mint,
+            None,
+            &owner,
+            10,
+            &MintOptions::default(),
+            RecipientLock::Unlocked,
+        )
+        .unwrap();
+        let message = Message::new(&instructions, Some(&payer.pubkey()));
+        assert_eq!(required_signatures(&message), 2);
+
+        let tx = sign(message, &[&payer, &authority, &payer]);
+        assert_eq!(tx.signatures.len(), 2);
+        assert!(tx.verify().is_ok());
+    }
+
+    #[test]
+    fn mint_keypair_signs_alongside_payer() {
+        let payer = keypair(1);
+        let mint = keypair(2);
+        let instructions = TokenBuilder::new()
+            .name("Signers")
+            .symbol("SIG")
+            .uri("https://example.com/signers.json")
+            .build_create_token_ix(&payer.pubkey(), &mint.pubkey())
+            .unwrap();
+        let message = Message::new(&instructions, Some(&payer.pubkey()));
+        assert_eq!(required_signatures(&message), 2);
+
+        let payer_copy = payer.insecure_clone();
+        let tx = sign(message, &[&payer, &mint, &payer_copy]);
+        assert_eq!(tx.signatures.len(), 2);
+        assert!(tx.verify().is_ok());
+    }
+
+    #[test]
+    fn extra_signers_join_only_when_required() {
+        let payer = keypair(1);
+        let mint = keypair(2).pubkey();
+        let owner = keypair(3).pubkey();
+        let instructions =
+            build_mint_ix(&payer.pubkey(), &mint, &owner, 10, &MintOptions::default()).unwrap();
+        let message = Message::new(&instructions, Some(&payer.pubkey()));
+        let options = TxOptions {
+            extra_signers: vec![Arc::new(keypair(5)), Arc::new(payer.insecure_clone())],
+            ..TxOptions::default()
+        };
+        let signers = signers_for(&message, &[&payer], &options);
+        assert_eq!(signers.len(), 1);
+        assert_eq!(signers[0].pubkey(), payer.pubkey());
+    }
+
+    /// A message paid by `payer` carrying a memo of `len` bytes
+    fn memo_message(payer: &Pubkey, len: usize) -> Message {
+        let memo = Instruction::new_with_bytes(MEMO_PROGRAM_ID, &vec![b'x'; len], vec![]);
+        Message::new(&[memo], Some(payer))
+    }
+
+    /// The memo length making the signed transaction exactly `PACKET_DATA_SIZE` bytes
+    fn filling_memo_len(payer: &Pubkey) -> usize {
+        // Past 127 bytes the memo's length prefix stays at two bytes
+        let base = transaction_size(&memo_message(payer, 200), 1).unwrap();
+        200 + PACKET_DATA_SIZE - base
+    }
+
+    #[test]
+    fn transaction_size_matches_the_serialized_transaction() {
+        let payer = keypair(1);
+        let message = memo_message(&payer.pubkey(), 40);
+        // The signature count prefix grows a byte past 127 and past 16383 signatures
+        for signer_count in [0, 1, 2, 127, 128, 16_383, 16_384] {
+            let tx = Transaction {
+                signatures: vec![Signature::default(); signer_count],
+                message: message.clone(),
+            };
+            assert_eq!(
+                transaction_size(&message, signer_count).unwrap(),
+                bincode::serialized_size(&tx).unwrap() as usize,
+                "{} signatures",
+                signer_count
+            );
+        }
+    }
+
+    #[test]
+    fn a_transaction_of_exactly_the_packet_size_fits() {
+        let payer = keypair(1);
+        let message = memo_message(&payer.pubkey(), filling_memo_len(&payer.pubkey()));
+        let info = assert_tx_size(&message, 1).unwrap();
+        assert_eq!(info.size, PACKET_DATA_SIZE);
+        assert_eq!(info.size, 1232);
+        assert_eq!(info.remaining, 0);
+
+        let tx = sign(message, &[&payer]);
+        assert_eq!(bincode::serialized_size(&tx).unwrap(), 1232);
+    }
+
+    #[test]
+    fn a_transaction_one_byte_over_the_packet_size_is_refused() {
+        let payer = keypair(1);
+        let message = memo_message(&payer.pubkey(), filling_memo_len(&payer.pubkey()) + 1);
+        match assert_tx_size(&message, 1) {
+            Err(SssError::TransactionTooLarge {
+                size,
+                limit,
+                items_to_drop,
+            }) => {
+                assert_eq!((size, limit, items_to_drop), (1233, 1232, None));
+            }
+            other => panic!("accepted an oversized transaction: {:?}", other),
+        }
+        // The same message without its signature would fit
+        assert!(assert_tx_size(&message, 0).is_ok());
+    }
+
+    #[test]
+    fn items_are_cut_where_the_packet_is_full() {
+        let payer = keypair(1).pubkey();
+        // Each item adds a one-byte memo; the first item already fills the packet but one byte
+        let first = filling_memo_len(&payer) - 1;
+        let build = |items: &[usize]| -> SssResult<Message> {
+            let memos: Vec<Instruction> = items
+                .iter()
+                .map(|&len| Instruction::new_with_bytes(MEMO_PROGRAM_ID, &vec![b'x'; len], vec![]))
+                .collect();
+            Ok(Message::new(&memos, Some(&payer)))
+        };
+        let items = [first, 0, 0];
+        // A second, empty memo costs its program index and two length prefixes
+        assert_eq!(fitting_items(&items, 1, build).unwrap(), 1);
+        match assert_items_fit(&items, 1, build) {
+            Err(SssError::TransactionTooLarge { items_to_drop, .. }) => {
+                assert_eq!(items_to_drop, Some(2))
+            }
+            other => panic!("accepted an oversized batch: {:?}", other),
+        }
+        assert_eq!(
+            assert_items_fit(&items[..1], 1, build).unwrap().remaining,
+            1
+        );
+    }
+}
@@ -0,0 +1,472 @@
+//! Transfers of assets held by the payer, whatever their token standard
+
+use crate::RPC_CLIENT;
+use crate::amount::AmountSpec;
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::events::{SssEvent, emit};
+use crate::in_flight::exclusive;
+use crate::instructions::{self, TransferV1Params};
+use crate::journal::{JournalOperation, journaled};
+use crate::metrics::track;
+use crate::mint_cache::{MintInfo, cache_mint, metadata_pda};
+use crate::payer::{get_payer_pubkey, signing_payer};
+use crate::read_only::ensure_writable;
+use crate::soulbound::{is_soulbound_mint, soulbound_transfer_error};
+use crate::token::{TokenProgram, ensure_trusted_mint};
+use crate::token_accounts::{ResolvedTokenAccount, TokenAccountResolution, resolve_token_account};
+use crate::transaction::{TxOptions, assert_tx_size, required_signatures, sign_and_send};
+use mpl_token_metadata::accounts::{MasterEdition, Metadata, TokenRecord};
+use mpl_token_metadata::types::{ProgrammableConfig, TokenStandard};
+use serde_json::json;
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_sdk::{instruction::Instruction, message::Message, pubkey::Pubkey};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_associated_token_account::instruction::create_associated_token_account_idempotent;
+use spl_token_2022::extension::StateWithExtensions;
+use spl_token_2022::state::AccountState;
+
+/// Options applied when transferring an asset
+#[derive(Debug, Clone, Default)]
+pub struct TransferOptions {
+    /// The amount in base units or whole tokens, or `None` for the whole balance
+    ///
+    /// Non-fungible assets always transfer exactly 1; any other amount is rejected.
+    pub amount: Option<AmountSpec>,
+    /// How the recipient's token account is found, its associated token account by default
+    ///
+    /// Use `ExplicitAccount` or `ScanOwner` for recipients that are program-derived
+    /// addresses keeping their tokens elsewhere. Only an associated token account is
+    /// created when missing; programmable NFTs always move to the associated account.
+    pub destination: TokenAccountResolution,
+    /// Options for signing and sending the transaction
+    pub tx: TxOptions,
+}
+
+/// Transfers the payer's whole holding of an asset to another wallet
+///
+/// See [`transfer_asset_with_options`].
+///
+/// # Arguments
+///
+/// * `mint` - The public key of the asset's mint account
+/// * `to` - The wallet receiving the asset
+///
+/// # Returns
+///
+/// The transaction signature as a string
+pub fn transfer_asset(mint: Pubkey, to: Pubkey) -> SssResult<String> {
+    transfer_asset_with_options(mint, to, &TransferOptions::default())
+}
+
+/// Transfers an asset held by the payer to another wallet
+///
+/// The token standard is read from the asset's metadata. Programmable NFTs are moved with
+/// the metadata program's `TransferV1`, which updates their token records and enforces
+/// their rule set; every other standard is moved with a checked token transfer. The
+/// recipient's token account is found as `options.destination` requests; the associated
+/// token account is created if missing. Transferring to the associated account of an
+/// off-curve recipient emits [`SssEvent::TokenAccountWarning`](crate::SssEvent), since
+/// its program may not use that account. Mints without metadata are treated as NFTs if
+/// they have 0 decimals and a supply of 1, and as fungible otherwise.
+///
+/// # Arguments
+///
+/// * `mint` - The public key of the asset's mint account
+/// * `to` - The wallet receiving the asset
+/// * `options` - The amount and the transaction options
+///
+/// # Returns
+///
+/// The transaction signature as a string
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the mint does not exist, is soulbound, the payer holds none
+/// of it, its token account is frozen, the amount is invalid for the asset, or an explicit
+/// destination does not exist, hold the mint or belong to `to`. A transfer rejected
+/// on chain, e.g. by a pNFT rule set, is reported with the decoded program error.
+pub fn transfer_asset_with_options(
+    mint: Pubkey,
+    to: Pubkey,
+    options: &TransferOptions,
+) -> SssResult<String> {
+    transfer_asset_with_client(&RPC_CLIENT, mint, to, options)
+}
+
+/// Transfers an asset held by the payer to another wallet through the given RPC client
+///
+/// See [`transfer_asset_with_options`].
+///
+/// # Arguments
+///
+/// * `client` - The RPC client reading the asset and sending the transaction
+/// * `mint` - The public key of the asset's mint account
+/// * `to` - The wallet receiving the asset
+/// * `options` - The amount and the transaction options
+///
+/// # Returns
+///
+/// The transaction signature as a string
+pub fn transfer_asset_with_client(
+    client: &RpcClient,
+    mint: Pubkey,
+    to: Pubkey,
+    options: &TransferOptions,
+) -> SssResult<String> {
+    ensure_writable()?;
+    ensure_trusted_mint(&mint)?;
+    let params = json!({
+        "mint": mint.to_string(),
+        "to": to.to_string(),
+        "amount": options.amount,
+    });
+    exclusive(
+        JournalOperation::TransferAsset,
+        &params,
+        &options.tx,
+        || {
+            track(JournalOperation::TransferAsset, || {
+                let payer_pubkey = get_payer_pubkey()?;
+                if to == payer_pubkey {
+                    return Err(SssError::TokenError(format!(
+                        "Cannot transfer mint {} to the payer itself",
+                        mint
+                    )));
+                }
+
+                let asset = fetch_asset(client, &mint, &payer_pubkey)?;
+                let requested = options
+                    .amount
+                    .as_ref()
+                    .map(|amount| amount.to_raw(asset.info.decimals))
+                    .transpose()?;
+                let amount = transfer_amount(&mint, &asset, requested.map(u64::from))?;
+                let destination =
+                    transfer_destination(client, &mint, &to, &asset, &options.destination)?;
+                let mut instructions =
+                    transfer_instructions(&mint, &payer_pubkey, &to, &asset, amount, &destination)?;
+                instructions.extend(options.tx.platform_fee_instruction(&payer_pubkey));
+                let instructions = options.tx.wrap_instructions(instructions);
+
+                let message = Message::new(&instructions, Some(&payer_pubkey));
+                assert_tx_size(&message, required_signatures(&message))?;
+                let payer = signing_payer()?;
+                journaled(
+                    JournalOperation::TransferAsset,
+                    &params,
+                    &options.tx,
+                    || sign_and_send(client, message, &[&payer], &options.tx),
+                )
+            })
+        },
+    )
+}
+
+/// An asset and the payer's holding of it
+pub(crate) struct HeldAsset {
+    pub info: MintInfo,
+    pub standard: TokenStandard,
+    /// The rule set of a programmable NFT, if it has one
+    pub rule_set: Option<Pubkey>,
+    /// The payer's associated token account
+    pub source: Pubkey,
+    pub balance: u64,
+}
+
+/// Fetches the mint, its metadata and the payer's token account in one request
+pub(crate) fn fetch_asset(
+    client: &RpcClient,
+    mint: &Pubkey,
+    owner: &Pubkey,
+) -> SssResult<HeldAsset> {
+    let legacy = get_associated_token_address_with_program_id(owner, mint, &spl_token::id());
+    let token_2022 =
+        get_associated_token_address_with_program_id(owner, mint, &spl_token_2022::id());
+    let mut accounts = client
+        .get_multiple_accounts(&[*mint, metadata_pda(mint), legacy, token_2022])
+        .into_sss_error("Failed to get token accounts from rpc")?
+        .into_iter();
+
+    let mint_account = accounts
+        .next()
+        .flatten()
+        .ok_or_else(|| SssError::TokenError(format!("Mint account {} does not exist", mint)))?;
+    let info = MintInfo::from_account(mint, &mint_account)?;
+    cache_mint(*mint, info);
+    if is_soulbound_mint(mint, &mint_account)? {
+        return Err(soulbound_transfer_error(mint));
+    }
+    let metadata = accounts
+        .next()
+        .flatten()
+        .map(|account| Metadata::from_bytes(&account.data))
+        .transpose()
+        .into_sss_error("Failed to parse token metadata account")?;
+    let (legacy_account, token_2022_account) =
+        (accounts.next().flatten(), accounts.next().flatten());
+    let (source, source_account) = match info.token_program {
+        TokenProgram::Legacy => (legacy, legacy_account),
+        TokenProgram::Token2022 => (token_2022, token_2022_account),
+    };
+
+    let standard = match metadata.as_ref().and_then(|m| m.token_standard) {
+        Some(standard) => standard,
+        None => {
+            let state =
+                StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_account.data)
+                    .into_sss_error("Failed to parse token mint account")?;
+            if state.base.decimals == 0 && state.base.supply == 1 {
+                TokenStandard::NonFungible
+            } else {
+                TokenStandard::Fungible
+            }
+        }
+    };
+    let rule_set = match metadata.and_then(|m| m.programmable_config) {
+        Some(ProgrammableConfig::V1 { rule_set }) => rule_set,
+        None => None,
+    };
+
+    let source_account = source_account.ok_or_else(|| {
+        SssError::TokenError(format!("Payer holds no token account for mint {}", mint))
+    })?;
+    let state = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&source_account.data)
+        .into_sss_error("Failed to parse token account")?;
+    // The metadata program keeps every pNFT account frozen and thaws it during the transfer
+    if state.base.state == AccountState::Frozen && !is_programmable(standard) {
+        return Err(SssError::TokenError(format!(
+            "Token account {} of mint {} is frozen, so its tokens cannot be transferred",
+            source, mint
+        )));
+    }
+
+    Ok(HeldAsset {
+        info,
+        standard,
+        rule_set,
+        source,
+        balance: state.base.amount,
+    })
+}
+
+/// Resolves the amount to transfer: 1 for NFTs, the whole balance unless given otherwise
+pub(crate) fn transfer_amount(
+    mint: &Pubkey,
+    asset: &HeldAsset,
+    requested: Option<u64>,
+) -> SssResult<u64> {
+    if asset.balance == 0 {
+        return Err(SssError::TokenError(format!(
+            "Payer holds no tokens of mint {}",
+            mint
+        )));
+    }
+    let amount = if is_non_fungible(asset.standard) {
+        match requested {
+            None | Some(1) => 1,
+            Some(amount) => {
+                return Err(SssError::TokenError(format!(
+                    "Mint {} is a {:?} asset, so exactly 1 is transferred, not {}",
+                    mint, asset.standard, amount
+                )));
+            }
+        }
+    } else {
+        requested.unwrap_or(asset.balance)
+    };
+    if amount == 0 || amount > asset.balance {
+        return Err(SssError::TokenError(format!(
+            "Cannot transfer {} tokens of mint {}, the payer holds {}",
+            amount, mint, asset.balance
+        )));
+    }
+    Ok(amount)
+}
+
+/// Resolves the token account of `to` receiving the asset
+///
+/// Warnings about the resolved account are emitted as events, since a transfer only
+/// returns its signature.
+fn transfer_destination(
+    client: &RpcClient,
+    mint: &Pubkey,
+    to: &Pubkey,
+    asset: &HeldAsset,
+    resolution: &TokenAccountResolution,
+) -> SssResult<ResolvedTokenAccount> {
+    if is_programmable(asset.standard) && *resolution != TokenAccountResolution::Ata {
+        return Err(SssError::TokenError(format!(
+            "Mint {} is a programmable NFT, which can only move to an associated token account",
+            mint
+        )));
+    }
+    let destination =
+        resolve_token_account(client, mint, to, &asset.info.token_program.id(), resolution)?;
+    if let TokenAccountResolution::ExplicitAccount(account) = resolution
+        && destination.state.is_none()
+    {
+        return Err(SssError::TokenError(format!(
+            "Token account {} does not exist",
+            account
+        )));
+    }
+    for warning in &destination.warnings {
+        emit(SssEvent::TokenAccountWarning {
+            mint: *mint,
+            owner: *to,
+            token_account: destination.address,
+            warning: warning.to_string(),
+        });
+    }
+    Ok(destination)
+}
+
+/// Builds the instructions moving the asset into the resolved token account of `to`
+fn transfer_instructions(
+    mint: &Pubkey,
+    payer: &Pubkey,
+    to: &Pubkey,
+    asset: &HeldAsset,
+    amount: u64,
+    destination: &ResolvedTokenAccount,
+) -> SssResult<Vec<Instruction>> {
+    let token_program = asset.info.token_program.id();
+    let ata = get_associated_token_address_with_program_id(to, mint, &token_program);
+    let destination_account = destination.address;
+
+    if is_programmable(asset.standard) {
+        // TransferV1 creates the destination account and its token record itself
+        return Ok(vec![instructions::transfer_v1(TransferV1Params {
+            token: Some(asset.source),
+            token_owner: Some(*payer),
+            destination_token: Some(destination_account),
+            destination_owner: Some(*to),
+            metadata: Some(metadata_pda(mint)),
+            edition: Some(MasterEdition::find_pda(mint).0),
+            token_record: Some(TokenRecord::find_pda(mint, &asset.source).0),
+            destination_token_record: Some(TokenRecord::find_pda(mint, &destination_account).0),
+            mint: Some(*mint),
+            authority: Some(*payer),
+            payer: Some(*payer),
+            authorization_rules: asset.rule_set,
+            amount,
+            token_standard: asset.standard,
+            spl_token_program: token_program,
+        })?]);
+    }
+
+    let mut instructions = Vec::with_capacity(2);
+    if destination_account == ata {
+        instructions.push(create_associated_token_account_idempotent(
+            payer,
+            to,
+            mint,
+            &token_program,
+        ));
+    }
+    instructions.push(transfer_checked_instruction(
+        &asset.info,
+        &asset.source,
+        mint,
+        &destination_account,
+        payer,
+        amount,
+    )?);
+    Ok(instructions)
+}
+
+/// Builds the instructions transferring fungible tokens between associated token accounts
+///
+/// The recipient's associated token account is created if missing. Nothing is read from
+/// the cluster, so the caller provides the mint's decimals and token program;
+/// [`transfer_asset_with_options`] also handles NFTs and other destination accounts.
+///
+/// # Arguments
+///
+/// * `payer` - The fee payer, holding the tokens and signing the transfer
+/// * `mint` - The public key of the token's mint account
+/// * `to` - The wallet receiving the tokens
+/// * `amount` - The amount of tokens to transfer, in base units
+/// * `decimals` - The number of decimal places of the mint
+/// * `token_program` - The token program owning the mint
+///
+/// # Returns
+///
+/// The instructions, in execution order
+pub fn build_transfer_ix(
+    payer: &Pubkey,
+    mint: &Pubkey,
+    to: &Pubkey,
+    amount: u64,
+    decimals: u8,
+    token_program: TokenProgram,
+) -> SssResult<Vec<Instruction>> {
+    let program_id = token_program.id();
+    let source = get_associated_token_address_with_program_id(payer, mint, &program_id);
+    let destination = get_associated_token_address_with_program_id(to, mint, &program_id);
+    let transfer = spl_token_2022::instruction::transfer_checked(
+        &program_id,
+        &source,
+        mint,
+        &destination,
+        payer,
+        &[],
+        amount,
+        decimals,
+    )
+    .into_sss_error("Failed to build token transfer instruction")?;
+    Ok(vec![
+        create_associated_token_account_idempotent(payer, to, mint, &program_id),
+        transfer,
+    ])
+}
+
+/// Builds a checked token transfer between two token accounts of a mint
+pub(crate) fn transfer_checked_instruction(
+    info: &MintInfo,
+    source: &Pubkey,
+    mint: &Pubkey,
+    destination: &Pubkey,
+    authority: &Pubkey,
+    amount: u64,
+) -> SssResult<Instruction> {
+    match info.token_program {
+        TokenProgram::Legacy => spl_token::instruction::transfer_checked(
+            &spl_token::id(),
+            source,
+            mint,
+            destination,
+            authority,
+            &[],
+            amount,
+            info.decimals,
+        ),
+        TokenProgram::Token2022 => spl_token_2022::instruction::transfer_checked(
+            &spl_token_2022::id(),
+            source,
+            mint,
+            destination,
+            authority,
+            &[],
+            amount,
+            info.decimals,
+        ),
+    }
+    .into_sss_error("Failed to build token transfer instruction")
+}
+
+/// Returns whether a standard describes a non-fungible token
+fn is_non_fungible(standard: TokenStandard) -> bool {
+    !matches!(
+        standard,
+        TokenStandard::Fungible | TokenStandard::FungibleAsset
+    )
+}
+
+/// Returns whether a standard describes a programmable NFT
+pub(crate) fn is_programmable(standard: TokenStandard) -> bool {
+    matches!(
+        standard,
+        TokenStandard::ProgrammableNonFungible | TokenStandard::ProgrammableNonFungibleEdition
+    )
+}
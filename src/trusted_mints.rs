@@ -0,0 +1,202 @@
+//! Allowlist of trusted mints and the strict mode enforcing it
+//!
+//! The allowlist combines the mints trusted at runtime with those listed in the file named
+//! by `SSS_TRUSTED_MINTS_PATH`, which can be reloaded without a restart, see
+//! [`crate::reload_policy_files`].
+
+use crate::error::{IntoSssError, SssResult};
+use crate::load_dotenv;
+use crate::profiles::{profile_setting, update_profile};
+use lazy_static::lazy_static;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+lazy_static! {
+    /// Trusted mints, seeded from the comma-separated `SSS_TRUSTED_MINTS` variable
+    static ref TRUSTED_MINTS: RwLock<HashSet<Pubkey>> = {
+        load_dotenv();
+        let mints = env::var("SSS_TRUSTED_MINTS")
+            .map(|list| {
+                list.split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .filter_map(|s| Pubkey::from_str(s).ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        RwLock::new(mints)
+    };
+
+    /// File listing further trusted mints as a JSON array, from `SSS_TRUSTED_MINTS_PATH`
+    static ref TRUSTED_MINTS_PATH: Option<PathBuf> = {
+        load_dotenv();
+        env::var("SSS_TRUSTED_MINTS_PATH").ok().map(PathBuf::from)
+    };
+
+    /// Trusted mints listed in the trusted mints file, replaced as a whole on reload
+    static ref FILE_TRUSTED_MINTS: RwLock<HashSet<Pubkey>> =
+        RwLock::new(read_trusted_mints_file().ok().flatten().unwrap_or_default());
+
+    /// Whether operations on untrusted mints are refused, seeded from `SSS_STRICT_MODE`
+    static ref STRICT_MODE: AtomicBool = {
+        load_dotenv();
+        let enabled = env::var("SSS_STRICT_MODE")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+        AtomicBool::new(enabled)
+    };
+}
+
+/// Replaces the set of trusted mints
+///
+/// Mints listed in the trusted mints file stay trusted. While a profile is selected on the
+/// current thread, its set is replaced instead.
+///
+/// # Arguments
+///
+/// * `mints` - The mints to trust
+pub fn set_trusted_mints(mints: &[Pubkey]) {
+    if update_profile(|profile| profile.trusted_mints = mints.iter().copied().collect()) {
+        return;
+    }
+    if let Ok(mut trusted) = TRUSTED_MINTS.write() {
+        *trusted = mints.iter().copied().collect();
+    }
+}
+
+/// Adds a mint to the trusted set
+///
+/// While a profile is selected on the current thread, the mint is added to its set instead.
+///
+/// # Arguments
+///
+/// * `mint` - The mint to trust
+pub fn add_trusted_mint(mint: Pubkey) {
+    if update_profile(|profile| {
+        profile.trusted_mints.insert(mint);
+    }) {
+        return;
+    }
+    if let Ok(mut trusted) = TRUSTED_MINTS.write() {
+        trusted.insert(mint);
+    }
+}
+
+/// Returns whether a mint is in the trusted set
+///
+/// While a profile is selected on the current thread, only its set is consulted.
+///
+/// # Arguments
+///
+/// * `mint` - The mint to look up
+pub fn is_trusted_mint(mint: &Pubkey) -> bool {
+    match profile_setting(|profile| profile.trusted_mints.contains(mint)) {
+        Ok(Some(trusted)) => return trusted,
+        Ok(None) => {}
+        Err(_) => return false,
+    }
+    let listed = |set: &RwLock<HashSet<Pubkey>>| {
+        set.read()
+            .map(|trusted| trusted.contains(mint))
+            .unwrap_or(false)
+    };
+    listed(&TRUSTED_MINTS) || listed(&FILE_TRUSTED_MINTS)
+}
+
+/// Returns every trusted mint, in no particular order
+///
+/// While a profile is selected on the current thread, only its set is listed.
+pub(crate) fn trusted_mints() -> Vec<Pubkey> {
+    match profile_setting(|profile| profile.trusted_mints.iter().copied().collect()) {
+        Ok(Some(mints)) => return mints,
+        Ok(None) => {}
+        Err(_) => return Vec::new(),
+    }
+    let mut mints: HashSet<Pubkey> = HashSet::new();
+    for set in [&*TRUSTED_MINTS, &*FILE_TRUSTED_MINTS] {
+        if let Ok(trusted) = set.read() {
+            mints.extend(trusted.iter().copied());
+        }
+    }
+    mints.into_iter().collect()
+}
+
+/// Reads the trusted mints file
+///
+/// # Returns
+///
+/// The listed mints, or `None` if no file is configured or it does not exist
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if the file cannot be read or is not a JSON array of mint
+/// addresses
+pub(crate) fn read_trusted_mints_file() -> SssResult<Option<HashSet<Pubkey>>> {
+    let Some(path) = TRUSTED_MINTS_PATH.as_ref() else {
+        return Ok(None);
+    };
+    let json = match fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(e).into_sss_error(&format!(
+                "Failed to read trusted mints config {}",
+                path.display()
+            ));
+        }
+    };
+    let raw: Vec<String> = serde_json::from_str(&json).into_sss_error(&format!(
+        "Failed to parse trusted mints config {}",
+        path.display()
+    ))?;
+    raw.iter()
+        .map(|mint| {
+            Pubkey::from_str(mint).into_sss_error(&format!(
+                "Invalid mint '{}' in trusted mints config {}",
+                mint,
+                path.display()
+            ))
+        })
+        .collect::<SssResult<_>>()
+        .map(Some)
+}
+
+/// Replaces the mints trusted through the trusted mints file
+///
+/// # Returns
+///
+/// The mints added and removed, each sorted
+pub(crate) fn replace_file_trusted_mints(mints: HashSet<Pubkey>) -> (Vec<Pubkey>, Vec<Pubkey>) {
+    let Ok(mut listed) = FILE_TRUSTED_MINTS.write() else {
+        return (Vec::new(), Vec::new());
+    };
+    let mut added: Vec<_> = mints.difference(&listed).copied().collect();
+    let mut removed: Vec<_> = listed.difference(&mints).copied().collect();
+    added.sort_unstable();
+    removed.sort_unstable();
+    *listed = mints;
+    (added, removed)
+}
+
+/// Enables or disables strict mode
+///
+/// In strict mode, operations on existing mints fail unless the mint is trusted.
+///
+/// # Arguments
+///
+/// * `enabled` - Whether strict mode is on
+pub fn set_strict_mode(enabled: bool) {
+    STRICT_MODE.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns whether strict mode is enabled
+pub fn strict_mode_enabled() -> bool {
+    STRICT_MODE.load(Ordering::Relaxed)
+}
@@ -0,0 +1,464 @@
+//! Token vaults holding inventory on behalf of partners
+//!
+//! A vault is a token account created at an address derived with `create_with_seed` from
+//! the payer, so no keypair has to be kept for it. The payer is the vault's authority and
+//! signs every withdrawal.
+
+use crate::RPC_CLIENT;
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::in_flight::exclusive;
+use crate::journal::{JournalOperation, journaled};
+use crate::metrics::track;
+use crate::mint_cache::mint_info;
+use crate::payer::{get_payer_pubkey, signing_payer};
+use crate::read_only::ensure_writable;
+use crate::token::{TokenProgram, ensure_trusted_mint};
+use crate::transaction::{TxOptions, assert_tx_size, sign_and_send};
+use crate::transfer::{
+    fetch_asset, is_programmable, transfer_amount, transfer_checked_instruction,
+};
+use serde_json::json;
+use solana_sdk::{
+    hash::hashv, instruction::Instruction, message::Message, pubkey::Pubkey, system_instruction,
+};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_associated_token_account::instruction::create_associated_token_account_idempotent;
+use spl_token_2022::extension::StateWithExtensions;
+use spl_token_2022::state::AccountState;
+use std::fmt::Write;
+
+/// Namespace hashed into every vault seed
+///
+/// Vault addresses are shared with partners, so this and [`vault_seed`] must never change;
+/// a new derivation needs a new namespace.
+pub const VAULT_NAMESPACE: &str = "sss-vault-v1";
+
+/// Returns the `create_with_seed` seed of a vault
+///
+/// The seed is the first 16 bytes of the SHA-256 hash of the namespace, the partner id and
+/// the mint, hex encoded to the 32 characters a seed may hold.
+///
+/// # Arguments
+///
+/// * `partner_id` - The partner the vault holds inventory for
+/// * `mint` - The mint held in the vault
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the partner id is empty
+pub fn vault_seed(partner_id: &str, mint: &Pubkey) -> SssResult<String> {
+    if partner_id.is_empty() {
+        return Err(SssError::TokenError(
+            "Vault partner id must not be empty".to_string(),
+        ));
+    }
+    let hash = hashv(&[
+        VAULT_NAMESPACE.as_bytes(),
+        &[0],
+        partner_id.as_bytes(),
+        &[0],
+        mint.as_ref(),
+    ]);
+    let mut seed = String::with_capacity(32);
+    for byte in &hash.to_bytes()[..16] {
+        let _ = write!(seed, "{:02x}", byte);
+    }
+    Ok(seed)
+}
+
+/// Derives the address of a vault from an explicit base and token program
+///
+/// # Arguments
+///
+/// * `base` - The vault authority, normally the payer
+/// * `partner_id` - The partner the vault holds inventory for
+/// * `mint` - The mint held in the vault
+/// * `token_program` - The token program owning the mint
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the partner id is empty
+pub fn derive_vault_address_with_base(
+    base: &Pubkey,
+    partner_id: &str,
+    mint: &Pubkey,
+    token_program: TokenProgram,
+) -> SssResult<Pubkey> {
+    let seed = vault_seed(partner_id, mint)?;
+    Pubkey::create_with_seed(base, &seed, &token_program.id())
+        .into_sss_error("Failed to derive vault token address")
+}
+
+/// Derives the address of the payer's vault for a partner and a mint
+///
+/// The address depends only on the payer, the partner id and the mint, and stays the same
+/// across versions of this library.
+///
+/// # Arguments
+///
+/// * `partner_id` - The partner the vault holds inventory for
+/// * `mint` - The mint held in the vault
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the partner id is empty or the mint does not exist, and a
+/// `KeypairError` if the payer cannot be loaded
+pub fn derive_vault_address(partner_id: &str, mint: Pubkey) -> SssResult<Pubkey> {
+    let payer = get_payer_pubkey()?;
+    let info = mint_info(&mint)?;
+    derive_vault_address_with_base(&payer, partner_id, &mint, info.token_program)
+}
+
+/// Builds the instructions creating a vault without sending them
+///
+/// # Arguments
+///
+/// * `payer` - The fee payer and vault authority
+/// * `partner_id` - The partner the vault holds inventory for
+/// * `mint` - The mint held in the vault
+/// * `token_program` - The token program owning the mint
+/// * `space` - The size of a token account of the mint
+/// * `lamports` - The rent-exempt balance for `space`
+///
+/// # Returns
+///
+/// The instructions, in execution order
+pub fn build_create_vault_ix(
+    payer: &Pubkey,
+    partner_id: &str,
+    mint: &Pubkey,
+    token_program: TokenProgram,
+    space: usize,
+    lamports: u64,
+) -> SssResult<Vec<Instruction>> {
+    let seed = vault_seed(partner_id, mint)?;
+    let vault = derive_vault_address_with_base(payer, partner_id, mint, token_program)?;
+    let mut instructions = vec![system_instruction::create_account_with_seed(
+        payer,
+        &vault,
+        payer,
+        &seed,
+        lamports,
+        space as u64,
+        &token_program.id(),
+    )];
+    match token_program {
+        TokenProgram::Legacy => instructions.push(
+            spl_token::instruction::initialize_account3(&spl_token::id(), &vault, mint, payer)
+                .into_sss_error("Failed to build vault token account instruction")?,
+        ),
+        TokenProgram::Token2022 => {
+            instructions.push(
+                spl_token_2022::instruction::initialize_immutable_owner(
+                    &spl_token_2022::id(),
+                    &vault,
+                )
+                .into_sss_error("Failed to build vault token account instruction")?,
+            );
+            instructions.push(
+                spl_token_2022::instruction::initialize_account3(
+                    &spl_token_2022::id(),
+                    &vault,
+                    mint,
+                    payer,
+                )
+                .into_sss_error("Failed to build vault token account instruction")?,
+            );
+        }
+    }
+    Ok(instructions)
+}
+
+/// Moves tokens from the payer's associated account into a partner's vault
+///
+/// The vault is created in the same transaction if it does not exist yet.
+///
+/// # Arguments
+///
+/// * `partner_id` - The partner the vault holds inventory for
+/// * `mint` - The mint to deposit
+/// * `amount` - The amount in base units, exactly 1 for NFTs
+///
+/// # Returns
+///
+/// The transaction signature as a string
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the payer does not hold enough unfrozen tokens, the amount is
+/// invalid for the asset, or the asset is a programmable NFT, which can only be held in
+/// associated token accounts
+pub fn fund_vault(partner_id: &str, mint: Pubkey, amount: u64) -> SssResult<String> {
+    ensure_writable()?;
+    ensure_trusted_mint(&mint)?;
+    let options = TxOptions::default();
+    let params = json!({
+        "partner_id": partner_id,
+        "mint": mint.to_string(),
+        "amount": amount,
+    });
+    exclusive(JournalOperation::FundVault, &params, &options, || {
+        track(JournalOperation::FundVault, || {
+            let payer_pubkey = get_payer_pubkey()?;
+            let asset = fetch_asset(&RPC_CLIENT, &mint, &payer_pubkey)?;
+            if is_programmable(asset.standard) {
+                return Err(SssError::TokenError(format!(
+                    "Mint {} is a programmable NFT, which cannot be held in a vault",
+                    mint
+                )));
+            }
+            let amount = transfer_amount(&mint, &asset, Some(amount))?;
+
+            let vault = derive_vault_address_with_base(
+                &payer_pubkey,
+                partner_id,
+                &mint,
+                asset.info.token_program,
+            )?;
+            let mut instructions = Vec::new();
+            if fetch_vault(&vault)?.is_none() {
+                let lamports = RPC_CLIENT
+                    .get_minimum_balance_for_rent_exemption(asset.info.token_account_len)
+                    .into_sss_error("Failed to get rent exemption from rpc")?;
+                instructions.extend(build_create_vault_ix(
+                    &payer_pubkey,
+                    partner_id,
+                    &mint,
+                    asset.info.token_program,
+                    asset.info.token_account_len,
+                    lamports,
+                )?);
+            }
+            instructions.push(transfer_checked_instruction(
+                &asset.info,
+                &asset.source,
+                &mint,
+                &vault,
+                &payer_pubkey,
+                amount,
+            )?);
+
+            let message = Message::new(&instructions, Some(&payer_pubkey));
+            assert_tx_size(&message, 1)?;
+            let payer = signing_payer()?;
+            journaled(JournalOperation::FundVault, &params, &options, || {
+                sign_and_send(&RPC_CLIENT, message, &[&payer], &options)
+            })
+        })
+    })
+}
+
+/// Returns the balance of a partner's vault
+///
+/// # Arguments
+///
+/// * `partner_id` - The partner the vault holds inventory for
+/// * `mint` - The mint held in the vault
+///
+/// # Returns
+///
+/// The balance in base units, 0 if the vault was never funded
+pub fn vault_balance(partner_id: &str, mint: Pubkey) -> SssResult<u64> {
+    let vault = derive_vault_address(partner_id, mint)?;
+    Ok(fetch_vault(&vault)?.map_or(0, |state| state.amount))
+}
+
+/// Moves tokens out of a partner's vault to the associated account of a wallet
+///
+/// The recipient's associated token account is created if missing.
+///
+/// # Arguments
+///
+/// * `partner_id` - The partner the vault holds inventory for
+/// * `mint` - The mint to withdraw
+/// * `to` - The wallet receiving the tokens
+/// * `amount` - The amount in base units
+///
+/// # Returns
+///
+/// The transaction signature as a string
+///
+/// # Errors
+///
+/// Returns a `TokenError` if the vault does not exist, is frozen or holds less than
+/// `amount`
+pub fn withdraw_from_vault(
+    partner_id: &str,
+    mint: Pubkey,
+    to: Pubkey,
+    amount: u64,
+) -> SssResult<String> {
+    ensure_writable()?;
+    ensure_trusted_mint(&mint)?;
+    let options = TxOptions::default();
+    let params = json!({
+        "partner_id": partner_id,
+        "mint": mint.to_string(),
+        "to": to.to_string(),
+        "amount": amount,
+    });
+    exclusive(
+        JournalOperation::WithdrawFromVault,
+        &params,
+        &options,
+        || {
+            track(JournalOperation::WithdrawFromVault, || {
+                let payer_pubkey = get_payer_pubkey()?;
+                let info = mint_info(&mint)?;
+                let vault = derive_vault_address_with_base(
+                    &payer_pubkey,
+                    partner_id,
+                    &mint,
+                    info.token_program,
+                )?;
+                let state = fetch_vault(&vault)?.ok_or_else(|| {
+                    SssError::TokenError(format!(
+                        "Partner {} has no vault for mint {}",
+                        partner_id, mint
+                    ))
+                })?;
+                if state.state == AccountState::Frozen {
+                    return Err(SssError::TokenError(format!(
+                        "Vault {} of partner {} is frozen",
+                        vault, partner_id
+                    )));
+                }
+                if amount == 0 || amount > state.amount {
+                    return Err(SssError::TokenError(format!(
+                        "Cannot withdraw {} tokens of mint {}, the vault of partner {} holds {}",
+                        amount, mint, partner_id, state.amount
+                    )));
+                }
+
+                let token_program = info.token_program.id();
+                let destination =
+                    get_associated_token_address_with_program_id(&to, &mint, &token_program);
+                let instructions = vec![
+                    create_associated_token_account_idempotent(
+                        &payer_pubkey,
+                        &to,
+                        &mint,
+                        &token_program,
+                    ),
+                    transfer_checked_instruction(
+                        &info,
+                        &vault,
+                        &mint,
+                        &destination,
+                        &payer_pubkey,
+                        amount,
+                    )?,
+                ];
+
+                let message = Message::new(&instructions, Some(&payer_pubkey));
+                assert_tx_size(&message, 1)?;
+                let payer = signing_payer()?;
+                journaled(
+                    JournalOperation::WithdrawFromVault,
+                    &params,
+                    &options,
+                    || sign_and_send(&RPC_CLIENT, message, &[&payer], &options),
+                )
+            })
+        },
+    )
+}
+
+/// Fetches the token account state of a vault, `None` if it does not exist
+fn fetch_vault(vault: &Pubkey) -> SssResult<Option<spl_token_2022::state::Account>> {
+    let account = RPC_CLIENT
+        .get_multiple_accounts(&[*vault])
+        .into_sss_error("Failed to get vault token account from rpc")?
+        .pop()
+        .flatten();
+    account
+        .map(|account| {
+            StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account.data)
+                .map(|state| state.base)
+                .into_sss_error("Failed to parse vault token account")
+        })
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Computed independently as sha256(namespace 0 partner 0 mint)[..16] in hex, and the
+    // address as sha256(base || seed || token program); a change here moves partners' vaults
+    const BASE: Pubkey = Pubkey::new_from_array([1; 32]);
+    const MINT: Pubkey = Pubkey::new_from_array([2; 32]);
+
+    #[test]
+    fn seeds_match_the_pinned_vectors() {
+        assert_eq!(VAULT_NAMESPACE, "sss-vault-v1");
+        assert_eq!(
+            vault_seed("kiosk-eu-01", &MINT).unwrap(),
+            "0434f8e9173388788080f16d2cc0ba8e"
+        );
+        assert_eq!(
+            vault_seed("partenaire-é", &MINT).unwrap(),
+            "a233286b65708b42bc5feb2df8a725f2"
+        );
+    }
+
+    #[test]
+    fn addresses_match_the_pinned_vectors() {
+        for (partner_id, token_program, address) in [
+            (
+                "kiosk-eu-01",
+                TokenProgram::Legacy,
+                "7bCwEZvfqpRpuvY4WUvWG9iceXqd9TNrqo8R2uHeUGkk",
+            ),
+            (
+                "kiosk-eu-01",
+                TokenProgram::Token2022,
+                "Ai6jpGHf679cY7DSJZX67d1dHCpUMaSZaUghrzWS6Fja",
+            ),
+            (
+                "partenaire-é",
+                TokenProgram::Legacy,
+                "4J7SMqbGFKdakuEwzboHCCmC3C3f5gb2y1Vwu36fgUck",
+            ),
+            (
+                "partenaire-é",
+                TokenProgram::Token2022,
+                "9ECYYSEMxVRUJvBWkvpciK4XaMyH45EDXH1TbxzxA6eB",
+            ),
+        ] {
+            let derived =
+                derive_vault_address_with_base(&BASE, partner_id, &MINT, token_program).unwrap();
+            assert_eq!(
+                derived.to_string(),
+                address,
+                "{} {:?}",
+                partner_id,
+                token_program
+            );
+        }
+    }
+
+    #[test]
+    fn every_input_changes_the_address() {
+        let derive = |base: &Pubkey, partner_id: &str, mint: &Pubkey| {
+            derive_vault_address_with_base(base, partner_id, mint, TokenProgram::Legacy).unwrap()
+        };
+        let vault = derive(&BASE, "kiosk-eu-01", &MINT);
+        assert_ne!(derive(&MINT, "kiosk-eu-01", &MINT), vault);
+        assert_ne!(derive(&BASE, "kiosk-eu-02", &MINT), vault);
+        assert_ne!(derive(&BASE, "kiosk-eu-01", &BASE), vault);
+        // The separator keeps the partner id from running into the mint
+        assert_ne!(
+            vault_seed("a", &MINT).unwrap(),
+            vault_seed("a\0", &MINT).unwrap()
+        );
+    }
+
+    #[test]
+    fn empty_partner_id_is_refused() {
+        assert!(matches!(
+            derive_vault_address_with_base(&BASE, "", &MINT, TokenProgram::Legacy),
+            Err(SssError::TokenError(_))
+        ));
+    }
+}
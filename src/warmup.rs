@@ -0,0 +1,169 @@
+//! Warming up the RPC connection before the first operation after an idle period
+//!
+//! A cold connection costs a DNS lookup, a TCP and TLS handshake and the payer key
+//! derivation on the first operation. [`warm_up`] pays these costs up front, and the
+//! keep-alive stops the connection from going cold again.
+
+use crate::error::{IntoSssError, SssError, SssResult};
+use crate::payer::get_payer_pubkey;
+use crate::serde_utils::pubkey_string;
+use crate::{BLOCKHASH_CACHE, RPC_CLIENT};
+use serde::Serialize;
+use solana_sdk::pubkey::Pubkey;
+use std::net::ToSocketAddrs;
+use std::sync::Mutex;
+use std::sync::mpsc::{RecvTimeoutError, Sender, channel};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Longest wait between keep-alive pings while the endpoint is unreachable
+pub const KEEP_ALIVE_MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Stops the keep-alive thread when dropped, `None` while no keep-alive runs
+static KEEP_ALIVE: Mutex<Option<Sender<()>>> = Mutex::new(None);
+
+/// Timings of the steps of a [`warm_up`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WarmupReport {
+    /// The RPC endpoint that was warmed up
+    pub rpc_url: String,
+    /// The version of the node software reported by the endpoint
+    pub node_version: String,
+    /// The payer whose key was primed
+    #[serde(with = "pubkey_string")]
+    pub payer: Pubkey,
+    /// Time to resolve the endpoint's host name, in milliseconds
+    pub dns_ms: u64,
+    /// Time of the first request, including the connection handshake, in milliseconds
+    pub connect_ms: u64,
+    /// Time to fetch a blockhash into the cache, in milliseconds
+    pub blockhash_ms: u64,
+    /// Time to load the payer key, in milliseconds
+    pub payer_ms: u64,
+    /// Time of the whole warm-up, in milliseconds
+    pub total_ms: u64,
+}
+
+/// Prepares the library for a fast first operation
+///
+/// Resolves the RPC host name, opens the connection with a `getVersion` call, fetches a
+/// blockhash into the cache and loads the payer key. Call it when an idle device expects
+/// to send soon, e.g. when a kiosk shows its attract screen.
+///
+/// # Returns
+///
+/// The time each step took
+///
+/// # Errors
+///
+/// Returns an `RpcError` naming the step that failed if the endpoint cannot be resolved
+/// or reached, and a `KeypairError` if the payer failed to load
+pub fn warm_up() -> SssResult<WarmupReport> {
+    let started = Instant::now();
+    let rpc_url = RPC_CLIENT.url();
+
+    let step = Instant::now();
+    resolve_host(&rpc_url)?;
+    let dns_ms = elapsed_ms(step);
+
+    let step = Instant::now();
+    let version = RPC_CLIENT
+        .get_version()
+        .into_sss_error("Failed to reach rpc endpoint during warm-up")?;
+    let connect_ms = elapsed_ms(step);
+
+    let step = Instant::now();
+    BLOCKHASH_CACHE.refresh(&RPC_CLIENT)?;
+    let blockhash_ms = elapsed_ms(step);
+
+    let step = Instant::now();
+    let payer = get_payer_pubkey()?;
+    let payer_ms = elapsed_ms(step);
+
+    Ok(WarmupReport {
+        rpc_url,
+        node_version: version.solana_core,
+        payer,
+        dns_ms,
+        connect_ms,
+        blockhash_ms,
+        payer_ms,
+        total_ms: elapsed_ms(started),
+    })
+}
+
+/// Starts a background thread pinging the RPC endpoint to keep the connection warm
+///
+/// While the endpoint fails to answer, the wait between pings doubles up to
+/// [`KEEP_ALIVE_MAX_BACKOFF`] and returns to `interval` after the next answer. Calling
+/// this while a keep-alive is running has no effect. [`crate::shutdown`] stops it.
+///
+/// # Arguments
+///
+/// * `interval` - Time between pings while the endpoint answers
+pub fn start_keep_alive(interval: Duration) {
+    let Ok(mut keep_alive) = KEEP_ALIVE.lock() else {
+        return;
+    };
+    if keep_alive.is_some() {
+        return;
+    }
+    let (stop, stopped) = channel::<()>();
+    *keep_alive = Some(stop);
+
+    thread::spawn(move || {
+        let mut wait = interval;
+        // Dropping the sender disconnects the channel, which ends the thread at once
+        while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(wait) {
+            wait = match RPC_CLIENT.get_version() {
+                Ok(_) => interval,
+                Err(_) => wait
+                    .saturating_mul(2)
+                    .min(KEEP_ALIVE_MAX_BACKOFF.max(interval)),
+            };
+        }
+    });
+}
+
+/// Stops the keep-alive started by [`start_keep_alive`]
+pub fn stop_keep_alive() {
+    if let Ok(mut keep_alive) = KEEP_ALIVE.lock() {
+        keep_alive.take();
+    }
+}
+
+/// Resolves the host name of an RPC URL, so the first request finds it in the OS cache
+fn resolve_host(rpc_url: &str) -> SssResult<()> {
+    let (scheme, rest) = rpc_url.split_once("://").unwrap_or(("http", rpc_url));
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let authority = authority.rsplit('@').next().unwrap_or_default();
+    let default_port = if scheme.eq_ignore_ascii_case("https") {
+        443
+    } else {
+        80
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) if !port.contains(']') => (
+            host,
+            port.parse().map_err(|_| {
+                SssError::ConfigError(format!("Invalid port in rpc url {}", rpc_url))
+            })?,
+        ),
+        _ => (authority, default_port),
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    (host, port)
+        .to_socket_addrs()
+        .map_err(|e| {
+            SssError::RpcError(format!(
+                "Failed to resolve rpc host {} during warm-up: {}",
+                host, e
+            ))
+        })
+        .map(|_| ())
+}
+
+/// Returns the milliseconds since an instant
+fn elapsed_ms(since: Instant) -> u64 {
+    since.elapsed().as_millis() as u64
+}
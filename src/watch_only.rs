@@ -0,0 +1,101 @@
+//! Watch-only mode, in which no key material is loaded at all
+//!
+//! Stricter than read-only mode: the payer is only a public key, used for derivations,
+//! balances and reports, and every path that would load or sign with a keypair fails with
+//! `KeypairError("watch-only client")`. Once entered, the mode lasts for the process.
+
+use crate::error::{SssError, SssResult};
+use crate::load_dotenv;
+use lazy_static::lazy_static;
+use solana_sdk::pubkey::Pubkey;
+use std::env;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+/// Message of the `KeypairError` returned by operations refused in watch-only mode
+pub const WATCH_ONLY_MESSAGE: &str = "watch-only client";
+
+lazy_static! {
+    /// The public key standing in for the payer, seeded from `SSS_WATCH_ONLY_PUBKEY`
+    ///
+    /// An invalid key still enters watch-only mode, so a typo never falls back to loading
+    /// the payer keypair.
+    static ref WATCH_ONLY: RwLock<Option<Result<Pubkey, String>>> = {
+        load_dotenv();
+        let payer = env::var("SSS_WATCH_ONLY_PUBKEY").ok().map(|value| {
+            Pubkey::from_str(value.trim())
+                .map_err(|e| format!("Invalid SSS_WATCH_ONLY_PUBKEY {}: {}", value, e))
+        });
+        RwLock::new(payer)
+    };
+}
+
+/// Enters watch-only mode with the given wallet in place of the payer
+///
+/// The payer keypair, the keypairs of registered profiles and the journal audit key are
+/// dropped, and none can be loaded or set again. [`crate::get_payer_pubkey`] returns
+/// `payer`, so derivations, balances and fee reports work as for that wallet, while every
+/// operation that would sign fails with a `KeypairError`. Calling this again only replaces
+/// the watched wallet.
+///
+/// # Arguments
+///
+/// * `payer` - The wallet whose data is viewed
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if the mode cannot be recorded
+pub fn init_watch_only(payer: Pubkey) -> SssResult<()> {
+    {
+        let mut watch_only = WATCH_ONLY
+            .write()
+            .map_err(|e| SssError::ConfigError(format!("Watch-only state poisoned: {}", e)))?;
+        *watch_only = Some(Ok(payer));
+    }
+    // With the mode recorded first, none of these loads its keys if it was not used yet
+    crate::drop_payer(WATCH_ONLY_MESSAGE);
+    crate::profiles::clear_profiles();
+    crate::journal_audit::clear_audit_key();
+    Ok(())
+}
+
+/// Returns whether watch-only mode is on
+pub fn is_watch_only() -> bool {
+    WATCH_ONLY
+        .read()
+        .map_or(true, |watch_only| watch_only.is_some())
+}
+
+/// Returns the wallet standing in for the payer, `None` outside watch-only mode
+///
+/// # Errors
+///
+/// Returns a `ConfigError` if `SSS_WATCH_ONLY_PUBKEY` is not a valid public key
+pub(crate) fn watch_only_payer() -> Option<SssResult<Pubkey>> {
+    let watch_only = match WATCH_ONLY.read() {
+        Ok(watch_only) => watch_only,
+        Err(e) => {
+            return Some(Err(SssError::ConfigError(format!(
+                "Watch-only state poisoned: {}",
+                e
+            ))));
+        }
+    };
+    watch_only
+        .as_ref()
+        .map(|payer| payer.clone().map_err(SssError::ConfigError))
+}
+
+/// Fails with a `KeypairError` in watch-only mode
+pub(crate) fn ensure_not_watch_only() -> SssResult<()> {
+    if is_watch_only() {
+        return Err(SssError::KeypairError(WATCH_ONLY_MESSAGE.to_string()));
+    }
+    Ok(())
+}
+
+/// Returns whether an error is the refusal of [`ensure_not_watch_only`]
+#[cfg(feature = "ffi")]
+pub(crate) fn is_watch_only_error(error: &SssError) -> bool {
+    matches!(error, SssError::KeypairError(message) if message == WATCH_ONLY_MESSAGE)
+}
@@ -0,0 +1,134 @@
+//! Reading a million-row allocation file keeps memory within a bound
+//!
+//! The heap is measured by a counting global allocator, which sees every thread of the
+//! process, so this binary holds a single test.
+
+use solana_sdk::pubkey::Pubkey;
+use sss_shared::{CsvImportOptions, DuplicateWallets, load_allocations_csv_with_options};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const ROWS: u64 = 1_000_000;
+
+/// Counts the bytes allocated on the heap and their peak
+struct Counting;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            grow(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        LIVE.fetch_sub(layout.size(), Ordering::SeqCst);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = unsafe { System.realloc(ptr, layout, new_size) };
+        if !new.is_null() {
+            LIVE.fetch_sub(layout.size(), Ordering::SeqCst);
+            grow(new_size);
+        }
+        new
+    }
+}
+
+fn grow(size: usize) {
+    let live = LIVE.fetch_add(size, Ordering::SeqCst) + size;
+    PEAK.fetch_max(live, Ordering::SeqCst);
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// Runs `f`, returning its result and how far the heap grew above its size before
+fn peak_growth<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = LIVE.load(Ordering::SeqCst);
+    PEAK.store(before, Ordering::SeqCst);
+    let result = f();
+    (result, PEAK.load(Ordering::SeqCst) - before)
+}
+
+/// Writes a million rows paying `wallets` distinct wallets in turn, 1 to 1000 units each
+fn write_allocations(path: &Path, wallets: &[Pubkey]) -> u64 {
+    let mut out = BufWriter::new(File::create(path).unwrap());
+    writeln!(out, "wallet,amount,note").unwrap();
+    let mut total = 0;
+    for row in 0..ROWS {
+        let amount = row % 1000 + 1;
+        total += amount;
+        let wallet = wallets[(row % wallets.len() as u64) as usize];
+        writeln!(out, "{},{},row {}", wallet, amount, row).unwrap();
+    }
+    out.flush().unwrap();
+    total
+}
+
+fn temp_file(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("sss-{}-{}.csv", name, rand::random::<u64>()))
+}
+
+#[test]
+fn a_million_rows_are_read_within_a_memory_bound() {
+    // A thousand wallets paid a thousand times each: memory does not grow with the file
+    let wallets: Vec<Pubkey> = (0..1000).map(|_| Pubkey::new_unique()).collect();
+    let path = temp_file("allocations-repeated");
+    let expected = write_allocations(&path, &wallets);
+    let file_size = fs::metadata(&path).unwrap().len() as usize;
+    let ((rows, total), growth) = peak_growth(|| {
+        load_allocations_csv_with_options(&path, CsvImportOptions::default()).fold(
+            (0u64, 0u64),
+            |(rows, total), row| {
+                let (_, amount) = row.unwrap();
+                (rows + 1, total + amount)
+            },
+        )
+    });
+    assert_eq!((rows, total), (wallets.len() as u64, expected));
+    assert!(
+        growth < 512 * 1024,
+        "reading a {} byte file grew the heap by {} bytes",
+        file_size,
+        growth
+    );
+    fs::remove_file(&path).unwrap();
+
+    // A million distinct wallets: only a key and a line number are kept per wallet, 40
+    // bytes in the nodes of a B-tree holding up to 11 of them
+    let wallets: Vec<Pubkey> = (0..ROWS)
+        .map(|_| Pubkey::new_from_array(rand::random()))
+        .collect();
+    let path = temp_file("allocations-distinct");
+    write_allocations(&path, &wallets);
+    let file_size = fs::metadata(&path).unwrap().len() as usize;
+    let options = CsvImportOptions {
+        duplicates: DuplicateWallets::Error,
+        ..CsvImportOptions::default()
+    };
+    let (rows, growth) = peak_growth(|| {
+        load_allocations_csv_with_options(&path, options)
+            .inspect(|row| assert!(row.is_ok(), "{:?}", row))
+            .count()
+    });
+    assert_eq!(rows as u64, ROWS);
+    let per_row = growth / rows;
+    assert!(
+        per_row <= 72,
+        "reading a {} byte file grew the heap by {} bytes, {} per row",
+        file_size,
+        growth,
+        per_row
+    );
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,190 @@
+//! Claim links pay out their escrow once, to the wallet redeeming them, and nothing else
+//!
+//! Strict mode and the trusted mints are process-wide, so this test binary keeps them to
+//! itself. Every test escrows tokens of its own mint.
+
+mod common;
+
+use base64::prelude::{BASE64_URL_SAFE_NO_PAD, Engine};
+use common::{MockBackend, fixed_keypair, mint_account, token_account};
+use serde_json::json;
+use solana_sdk::signature::{Keypair, keypair_from_seed};
+use solana_sdk::{pubkey::Pubkey, signer::Signer, transaction::Transaction};
+use spl_associated_token_account::get_associated_token_address;
+use sss_shared::{
+    ClaimLink, DateTime, SssError, Utc, add_trusted_mint, create_claim_link_with_client,
+    redeem_claim_link_with_client, set_payer, set_strict_mode,
+};
+use std::sync::Arc;
+
+/// Instruction tag of a token transfer with decimals
+const TRANSFER_CHECKED: u8 = 12;
+/// Instruction tag of a token account close
+const CLOSE_ACCOUNT: u8 = 9;
+
+/// A trusted mint of which the payer holds 10 tokens
+fn ledger() -> (Arc<MockBackend>, Pubkey) {
+    set_payer(fixed_keypair(1));
+    set_strict_mode(true);
+    let payer = fixed_keypair(1).pubkey();
+    let backend = MockBackend::new();
+    let mint = Pubkey::new_unique();
+    backend.set_account(mint, mint_account(Some(payer), 10, 0));
+    let source = get_associated_token_address(&payer, &mint);
+    backend.set_account(source, token_account(&mint, &payer, 10));
+    add_trusted_mint(mint);
+    (backend, mint)
+}
+
+fn expiry() -> DateTime<Utc> {
+    DateTime::from_timestamp(1_000_000, 0).unwrap()
+}
+
+/// Creates a link escrowing 3 tokens, and the escrow its transaction funds
+fn funded_link(backend: &Arc<MockBackend>, mint: Pubkey) -> ClaimLink {
+    let link = create_claim_link_with_client(&backend.client(), mint, 3, expiry()).unwrap();
+    let claim = claim_key(&link.payload).pubkey();
+    backend.set_account(link.escrow, token_account(&mint, &claim, 3));
+    link
+}
+
+/// The claim key a payload carries
+fn claim_key(payload: &str) -> Keypair {
+    let bytes = BASE64_URL_SAFE_NO_PAD.decode(payload).unwrap();
+    keypair_from_seed(&bytes[1..33]).unwrap()
+}
+
+/// Overwrites the decoded bytes of a payload from `offset` on
+fn tampered(payload: &str, offset: usize, bytes: &[u8]) -> String {
+    let mut decoded = BASE64_URL_SAFE_NO_PAD.decode(payload).unwrap();
+    decoded[offset..offset + bytes.len()].copy_from_slice(bytes);
+    BASE64_URL_SAFE_NO_PAD.encode(decoded)
+}
+
+fn token_instruction(tx: &Transaction, tag: u8) -> Vec<Pubkey> {
+    let ix = tx
+        .message
+        .instructions
+        .iter()
+        .find(|ix| {
+            tx.message.account_keys[ix.program_id_index as usize] == spl_token::id()
+                && ix.data.first() == Some(&tag)
+        })
+        .expect("missing token instruction");
+    ix.accounts
+        .iter()
+        .map(|&index| tx.message.account_keys[index as usize])
+        .collect()
+}
+
+fn refusal(result: sss_shared::SssResult<String>) -> String {
+    match result {
+        Err(SssError::TokenError(message)) => message,
+        other => panic!("redeemed an altered link: {:?}", other),
+    }
+}
+
+#[test]
+fn a_link_pays_the_redeeming_wallet_once() {
+    let (backend, mint) = ledger();
+    let link = funded_link(&backend, mint);
+    let claim = claim_key(&link.payload).pubkey();
+    let recipient = Pubkey::new_unique();
+
+    redeem_claim_link_with_client(&backend.client(), &link.payload, recipient).unwrap();
+    let sent = backend.sent();
+    assert_eq!(sent.len(), 2);
+    let redeem = &sent[1];
+    assert!(redeem.verify().is_ok());
+    let transfer = token_instruction(redeem, TRANSFER_CHECKED);
+    assert_eq!(transfer[0], link.escrow);
+    assert_eq!(transfer[2], get_associated_token_address(&recipient, &mint));
+    assert_eq!(transfer[3], claim);
+    let close = token_instruction(redeem, CLOSE_ACCOUNT);
+    assert_eq!(close[..2], [link.escrow, fixed_keypair(1).pubkey()]);
+
+    // The escrow is closed once the redemption lands, so a replay finds it gone
+    backend.remove_account(&link.escrow);
+    match redeem_claim_link_with_client(&backend.client(), &link.payload, recipient) {
+        Err(SssError::ClaimLinkRedeemed { escrow, .. }) => assert_eq!(escrow, link.escrow),
+        other => panic!("redeemed a link twice: {:?}", other),
+    }
+    let thief = Pubkey::new_unique();
+    assert!(matches!(
+        redeem_claim_link_with_client(&backend.client(), &link.payload, thief),
+        Err(SssError::ClaimLinkRedeemed { .. })
+    ));
+    assert_eq!(backend.sent().len(), 2);
+}
+
+#[test]
+fn a_link_with_a_tampered_amount_is_refused() {
+    let (backend, mint) = ledger();
+    let link = funded_link(&backend, mint);
+    let payload = tampered(&link.payload, 65, &9u64.to_le_bytes());
+    let message = refusal(redeem_claim_link_with_client(
+        &backend.client(),
+        &payload,
+        Pubkey::new_unique(),
+    ));
+    assert!(
+        message.contains("amount 9 does not match the 3 tokens"),
+        "{}",
+        message
+    );
+    assert_eq!(backend.sent().len(), 1);
+}
+
+#[test]
+fn a_link_redeemed_to_its_own_claim_key_is_refused() {
+    let (backend, mint) = ledger();
+    let link = funded_link(&backend, mint);
+    let claim = claim_key(&link.payload).pubkey();
+    let message = refusal(redeem_claim_link_with_client(
+        &backend.client(),
+        &link.payload,
+        claim,
+    ));
+    assert!(message.contains("its own claim key"), "{}", message);
+    assert_eq!(backend.sent().len(), 1);
+}
+
+#[test]
+fn a_link_for_a_mint_off_the_allowlist_is_refused() {
+    let (backend, mint) = ledger();
+    let untrusted = Pubkey::new_unique();
+    backend.set_account(
+        untrusted,
+        mint_account(Some(fixed_keypair(1).pubkey()), 10, 0),
+    );
+    match create_claim_link_with_client(&backend.client(), untrusted, 1, expiry()) {
+        Err(SssError::TokenError(message)) => assert!(message.contains("not in the trusted set")),
+        other => panic!("escrowed an untrusted mint: {:?}", other),
+    }
+
+    // A link whose payload was pointed at another mint
+    let link = funded_link(&backend, mint);
+    let payload = tampered(&link.payload, 33, untrusted.as_ref());
+    let message = refusal(redeem_claim_link_with_client(
+        &backend.client(),
+        &payload,
+        Pubkey::new_unique(),
+    ));
+    assert_eq!(
+        message,
+        format!("mint {} is not in the trusted set", untrusted)
+    );
+    assert_eq!(backend.sent().len(), 1);
+}
+
+#[test]
+fn an_expired_link_is_refused() {
+    let (backend, mint) = ledger();
+    let link = funded_link(&backend, mint);
+    backend.on("getBlockTime", |_| json!(1_000_001));
+    match redeem_claim_link_with_client(&backend.client(), &link.payload, Pubkey::new_unique()) {
+        Err(SssError::ClaimLinkExpired { expiry: at, .. }) => assert_eq!(at, expiry()),
+        other => panic!("redeemed an expired link: {:?}", other),
+    }
+    assert_eq!(backend.sent().len(), 1);
+}
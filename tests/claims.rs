@@ -0,0 +1,192 @@
+//! Claims altered after the payer signed them, or submitted twice, are never sent
+//!
+//! Strict mode and the trusted mints are process-wide, so this test binary keeps them to
+//! itself. Every test mints its own mint, trusted unless the test says otherwise.
+
+mod common;
+
+use common::{MockBackend, fixed_keypair, mint_account};
+use solana_sdk::account::Account;
+use solana_sdk::{pubkey::Pubkey, signer::Signer, transaction::Transaction};
+use sss_shared::{
+    ClaimOptions, SssError, add_trusted_mint, build_claim_transaction_with_client, set_payer,
+    set_strict_mode, submit_claim_with_client,
+};
+use std::sync::Arc;
+
+/// Discriminator of the token metadata `MintV1` instruction
+const MINT_V1: [u8; 2] = [43, 0];
+
+/// A ledger holding one mint, trusted if asked
+fn ledger(trusted: bool) -> (Arc<MockBackend>, Pubkey) {
+    set_payer(fixed_keypair(1));
+    set_strict_mode(true);
+    let backend = MockBackend::new();
+    let mint = add_mint(&backend, trusted);
+    (backend, mint)
+}
+
+/// Adds a mint the payer may mint through its metadata account, trusted if asked
+fn add_mint(backend: &MockBackend, trusted: bool) -> Pubkey {
+    let mint = Pubkey::new_unique();
+    backend.set_account(mint, mint_account(Some(fixed_keypair(1).pubkey()), 0, 0));
+    let (metadata, _) = mpl_token_metadata::accounts::Metadata::find_pda(&mint);
+    backend.set_account(
+        metadata,
+        Account {
+            lamports: 1,
+            data: vec![4],
+            owner: mpl_token_metadata::ID,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+    if trusted {
+        add_trusted_mint(mint);
+    }
+    mint
+}
+
+fn build(backend: &Arc<MockBackend>, mint: Pubkey, claimer: Pubkey) -> Transaction {
+    let options = ClaimOptions::default();
+    let bytes =
+        build_claim_transaction_with_client(&backend.client(), mint, claimer, 5, &options).unwrap();
+    bincode::deserialize(&bytes).unwrap()
+}
+
+/// Position of the metadata mint instruction in the claim
+fn mint_v1(tx: &Transaction) -> usize {
+    tx.message
+        .instructions
+        .iter()
+        .position(|ix| {
+            tx.message.account_keys[ix.program_id_index as usize] == mpl_token_metadata::ID
+                && ix.data.starts_with(&MINT_V1)
+        })
+        .expect("claim without a mint instruction")
+}
+
+fn refusal(backend: &Arc<MockBackend>, tx: &Transaction) -> String {
+    let bytes = bincode::serialize(tx).unwrap();
+    match submit_claim_with_client(&backend.client(), &bytes) {
+        Err(SssError::TokenError(message)) => message,
+        other => panic!("submitted an altered claim: {:?}", other),
+    }
+}
+
+#[test]
+fn an_untouched_claim_is_sent() {
+    let (backend, mint) = ledger(true);
+    let tx = build(&backend, mint, Pubkey::new_unique());
+    let bytes = bincode::serialize(&tx).unwrap();
+    let signature = submit_claim_with_client(&backend.client(), &bytes).unwrap();
+    assert_eq!(signature, tx.signatures[0].to_string());
+    assert_eq!(backend.sent(), vec![tx]);
+}
+
+#[test]
+fn a_claim_with_a_tampered_amount_is_refused() {
+    let (backend, mint) = ledger(true);
+    let mut tx = build(&backend, mint, Pubkey::new_unique());
+    let index = mint_v1(&tx);
+    tx.message.instructions[index].data[2..10].copy_from_slice(&5_000u64.to_le_bytes());
+    assert!(refusal(&backend, &tx).contains("signatures are invalid"));
+    assert!(backend.sent().is_empty());
+}
+
+#[test]
+fn a_claim_redirected_to_another_wallet_is_refused() {
+    let (backend, mint) = ledger(true);
+    let claimer = Pubkey::new_unique();
+    let thief = Pubkey::new_unique();
+    let tx = build(&backend, mint, claimer);
+
+    let mut redirected = tx.clone();
+    for key in redirected.message.account_keys.iter_mut() {
+        if *key == claimer {
+            *key = thief;
+        }
+    }
+    assert!(refusal(&backend, &redirected).contains("signatures are invalid"));
+
+    // A claim the claimer has to countersign, countersigned by another wallet
+    let claimer = fixed_keypair(2);
+    let options = ClaimOptions {
+        require_claimer_signature: true,
+        ..ClaimOptions::default()
+    };
+    let bytes =
+        build_claim_transaction_with_client(&backend.client(), mint, claimer.pubkey(), 5, &options)
+            .unwrap();
+    let mut tx: Transaction = bincode::deserialize(&bytes).unwrap();
+    let slot = tx
+        .get_signing_keypair_positions(&[claimer.pubkey()])
+        .unwrap()[0]
+        .unwrap();
+    tx.signatures[slot] = fixed_keypair(3).sign_message(&tx.message_data());
+    assert!(refusal(&backend, &tx).contains("signatures are invalid"));
+    assert!(backend.sent().is_empty());
+
+    // Countersigned by the claimer, the same claim is sent
+    tx.partial_sign(&[&claimer], tx.message.recent_blockhash);
+    let bytes = bincode::serialize(&tx).unwrap();
+    submit_claim_with_client(&backend.client(), &bytes).unwrap();
+    assert_eq!(backend.sent().len(), 1);
+}
+
+#[test]
+fn a_claim_for_a_mint_off_the_allowlist_is_refused() {
+    let (backend, untrusted) = ledger(false);
+    let claimer = Pubkey::new_unique();
+    match build_claim_transaction_with_client(
+        &backend.client(),
+        untrusted,
+        claimer,
+        5,
+        &ClaimOptions::default(),
+    ) {
+        Err(SssError::TokenError(message)) => assert!(message.contains("not in the trusted set")),
+        other => panic!("built a claim for an untrusted mint: {:?}", other),
+    }
+
+    // Signed by the payer, e.g. before the mint left the allowlist
+    let trusted = add_mint(&backend, true);
+    let mut tx = build(&backend, trusted, claimer);
+    for key in tx.message.account_keys.iter_mut() {
+        if *key == trusted {
+            *key = untrusted;
+        }
+    }
+    tx.sign(&[&fixed_keypair(1)], tx.message.recent_blockhash);
+    let message = refusal(&backend, &tx);
+    assert!(message.contains(&format!("mint {} is not in the trusted set", untrusted)));
+    assert!(backend.sent().is_empty());
+}
+
+#[test]
+fn a_replayed_claim_is_refused() {
+    let (backend, mint) = ledger(true);
+    let tx = build(&backend, mint, Pubkey::new_unique());
+    let bytes = bincode::serialize(&tx).unwrap();
+
+    submit_claim_with_client(&backend.client(), &bytes).unwrap();
+    let message = refusal(&backend, &tx);
+    assert_eq!(
+        message,
+        format!("Claim {} was already submitted", tx.signatures[0])
+    );
+    assert_eq!(backend.call_count("sendTransaction"), 1);
+}
+
+#[test]
+fn a_claim_that_failed_to_send_may_be_submitted_again() {
+    let (backend, mint) = ledger(true);
+    let tx = build(&backend, mint, Pubkey::new_unique());
+    let bytes = bincode::serialize(&tx).unwrap();
+
+    let failing = MockBackend::new();
+    failing.reject("sendTransaction", |_| Some("node is behind".to_string()));
+    assert!(submit_claim_with_client(&failing.client(), &bytes).is_err());
+    submit_claim_with_client(&backend.client(), &bytes).unwrap();
+    assert_eq!(backend.sent(), vec![tx]);
+}
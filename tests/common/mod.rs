@@ -0,0 +1,372 @@
+//! Mock RPC backend for tests driving operations end to end
+//!
+//! Accounts are served from an in-memory ledger, sent transactions are recorded and
+//! reported as finalized, and any method can be overridden with a handler. Methods
+//! neither handled nor overridden get the canned answers of the stock `MockSender`.
+//! Operations without a `_with_client` variant reach the backend over HTTP, see
+//! [`MockBackend::serve`].
+
+#![allow(dead_code)]
+
+use async_trait::async_trait;
+use base64::prelude::{BASE64_STANDARD, Engine};
+use serde_json::{Value, json};
+use solana_program::program_pack::Pack;
+use solana_rpc_client::mock_sender::MockSender;
+use solana_rpc_client::rpc_client::{RpcClient, RpcClientConfig};
+use solana_rpc_client::rpc_sender::{RpcSender, RpcTransportStats};
+use solana_rpc_client_api::client_error::{ErrorKind, Result as ClientResult};
+use solana_rpc_client_api::request::{RpcError, RpcRequest, RpcResponseErrorData};
+use solana_sdk::{
+    account::Account,
+    commitment_config::CommitmentConfig,
+    pubkey::Pubkey,
+    signature::{Keypair, keypair_from_seed},
+    transaction::Transaction,
+};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::pin::pin;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+/// Answers one RPC method given its parameters
+pub type Handler = Box<dyn Fn(&Value) -> Value + Send + Sync>;
+
+/// Decides whether a request fails, returning the error message if it does
+pub type Rejecter = Box<dyn Fn(&Value) -> Option<String> + Send + Sync>;
+
+/// In-memory stand-in for an RPC node
+pub struct MockBackend {
+    accounts: Mutex<HashMap<Pubkey, Account>>,
+    handlers: Mutex<HashMap<String, Arc<Handler>>>,
+    rejecters: Mutex<HashMap<String, Arc<Rejecter>>>,
+    calls: Mutex<Vec<(String, Value)>>,
+    sent: Mutex<Vec<Transaction>>,
+    fallback: MockSender,
+}
+
+impl MockBackend {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            accounts: Mutex::new(HashMap::new()),
+            handlers: Mutex::new(HashMap::new()),
+            rejecters: Mutex::new(HashMap::new()),
+            calls: Mutex::new(Vec::new()),
+            sent: Mutex::new(Vec::new()),
+            fallback: MockSender::new("succeeds"),
+        })
+    }
+
+    /// Returns a client sending every request to this backend
+    pub fn client(self: &Arc<Self>) -> RpcClient {
+        RpcClient::new_sender(
+            Sender(Arc::clone(self)),
+            RpcClientConfig::with_commitment(CommitmentConfig::confirmed()),
+        )
+    }
+
+    /// Stores an account in the ledger, replacing any previous one
+    pub fn set_account(&self, address: Pubkey, account: Account) {
+        self.accounts.lock().unwrap().insert(address, account);
+    }
+
+    /// Removes an account from the ledger
+    pub fn remove_account(&self, address: &Pubkey) {
+        self.accounts.lock().unwrap().remove(address);
+    }
+
+    /// Returns an account of the ledger
+    pub fn account(&self, address: &Pubkey) -> Option<Account> {
+        self.accounts.lock().unwrap().get(address).cloned()
+    }
+
+    /// Answers `method` with `handler` instead of the default behaviour
+    pub fn on(&self, method: &str, handler: impl Fn(&Value) -> Value + Send + Sync + 'static) {
+        self.handlers
+            .lock()
+            .unwrap()
+            .insert(method.to_string(), Arc::new(Box::new(handler)));
+    }
+
+    /// Fails the requests for `method` that `reject` returns a message for, as a node
+    /// answering with a JSON-RPC error
+    pub fn reject(
+        &self,
+        method: &str,
+        reject: impl Fn(&Value) -> Option<String> + Send + Sync + 'static,
+    ) {
+        self.rejecters
+            .lock()
+            .unwrap()
+            .insert(method.to_string(), Arc::new(Box::new(reject)));
+    }
+
+    /// Returns the number of requests made for `method`
+    pub fn call_count(&self, method: &str) -> usize {
+        self.calls
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(m, _)| m == method)
+            .count()
+    }
+
+    /// Returns the transactions received through `sendTransaction`, in order
+    pub fn sent(&self) -> Vec<Transaction> {
+        self.sent.lock().unwrap().clone()
+    }
+
+    /// Serves this backend over HTTP on a local port, for the global client
+    ///
+    /// Returns the URL to set as `SOLANA_RPC_URL` before the global client is first used.
+    pub fn serve(self: &Arc<Self>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let backend = Arc::clone(self);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let backend = Arc::clone(&backend);
+                thread::spawn(move || backend.serve_connection(stream));
+            }
+        });
+        url
+    }
+
+    /// Answers the JSON-RPC requests of one keep-alive connection until it closes
+    fn serve_connection(self: Arc<Self>, stream: TcpStream) {
+        stream.set_nodelay(true).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        loop {
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                    return;
+                }
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':')
+                    && name.eq_ignore_ascii_case("content-length")
+                {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; length];
+            if reader.read_exact(&mut body).is_err() {
+                return;
+            }
+            let request: Value = serde_json::from_slice(&body).unwrap();
+            let method = interned(request["method"].as_str().unwrap());
+            let sender = Sender(Arc::clone(&self));
+            let outcome =
+                ready(sender.send(RpcRequest::Custom { method }, request["params"].clone()));
+            let reply = match outcome {
+                Ok(result) => json!({ "jsonrpc": "2.0", "result": result, "id": request["id"] }),
+                Err(e) => {
+                    let (code, message) = match e.kind() {
+                        ErrorKind::RpcError(RpcError::RpcResponseError {
+                            code, message, ..
+                        }) => (*code, message.clone()),
+                        kind => (-32603, kind.to_string()),
+                    };
+                    json!({
+                        "jsonrpc": "2.0",
+                        "error": { "code": code, "message": message },
+                        "id": request["id"],
+                    })
+                }
+            }
+            .to_string();
+            let http = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                reply.len(),
+                reply
+            );
+            if writer.write_all(http.as_bytes()).is_err() {
+                return;
+            }
+        }
+    }
+
+    fn handle(&self, method: &str, params: &Value) -> Option<Value> {
+        let handler = self.handlers.lock().unwrap().get(method).cloned();
+        if let Some(handler) = handler {
+            if method == "sendTransaction" {
+                self.sent.lock().unwrap().push(decode_transaction(params));
+            }
+            return Some(handler(params));
+        }
+        match method {
+            "getAccountInfo" => {
+                let address = pubkey_param(&params[0]);
+                Some(response(rpc_account(self.account(&address).as_ref())))
+            }
+            "getMultipleAccounts" => {
+                let accounts: Vec<Value> = params[0]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|address| rpc_account(self.account(&pubkey_param(address)).as_ref()))
+                    .collect();
+                Some(response(Value::Array(accounts)))
+            }
+            "getBalance" => {
+                let address = pubkey_param(&params[0]);
+                let lamports = self.account(&address).map_or(0, |a| a.lamports);
+                Some(response(json!(lamports)))
+            }
+            "sendTransaction" => {
+                let tx = decode_transaction(params);
+                let signature = tx.signatures[0].to_string();
+                self.sent.lock().unwrap().push(tx);
+                Some(Value::String(signature))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Sender handing the requests of one client to a shared backend
+struct Sender(Arc<MockBackend>);
+
+#[async_trait]
+impl RpcSender for Sender {
+    async fn send(&self, request: RpcRequest, params: Value) -> ClientResult<Value> {
+        let method = request.to_string();
+        self.0
+            .calls
+            .lock()
+            .unwrap()
+            .push((method.clone(), params.clone()));
+        let rejecter = self.0.rejecters.lock().unwrap().get(&method).cloned();
+        if let Some(message) = rejecter.and_then(|reject| reject(&params)) {
+            return Err(ErrorKind::RpcError(RpcError::RpcResponseError {
+                code: -32002,
+                message,
+                data: RpcResponseErrorData::Empty,
+            })
+            .into());
+        }
+        match self.0.handle(&method, &params) {
+            Some(value) => Ok(value),
+            None => self.0.fallback.send(request, params).await,
+        }
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        RpcTransportStats::default()
+    }
+
+    fn url(&self) -> String {
+        "mock".to_string()
+    }
+}
+
+/// Returns a method name that lives as long as the process, as `RpcRequest` requires
+fn interned(method: &str) -> &'static str {
+    static METHODS: Mutex<Option<HashSet<&'static str>>> = Mutex::new(None);
+    let mut methods = METHODS.lock().unwrap();
+    let methods = methods.get_or_insert_with(HashSet::new);
+    match methods.get(method) {
+        Some(interned) => interned,
+        None => {
+            let interned: &'static str = Box::leak(method.to_string().into_boxed_str());
+            methods.insert(interned);
+            interned
+        }
+    }
+}
+
+/// Runs a future that never waits, as every request of the backend
+fn ready<T>(future: impl Future<Output = T>) -> T {
+    let mut future = pin!(future);
+    match future
+        .as_mut()
+        .poll(&mut Context::from_waker(Waker::noop()))
+    {
+        Poll::Ready(value) => value,
+        Poll::Pending => panic!("a mock request waited"),
+    }
+}
+
+/// Wraps a value in an RPC response with a context
+pub fn response(value: Value) -> Value {
+    json!({ "context": { "slot": 1 }, "value": value })
+}
+
+/// Encodes an account as returned by `getAccountInfo`, `null` if missing
+pub fn rpc_account(account: Option<&Account>) -> Value {
+    match account {
+        Some(account) => json!({
+            "lamports": account.lamports,
+            "data": [BASE64_STANDARD.encode(&account.data), "base64"],
+            "owner": account.owner.to_string(),
+            "executable": account.executable,
+            "rentEpoch": 0,
+            "space": account.data.len(),
+        }),
+        None => Value::Null,
+    }
+}
+
+fn pubkey_param(value: &Value) -> Pubkey {
+    Pubkey::from_str(value.as_str().unwrap()).unwrap()
+}
+
+/// Decodes the transaction of a `sendTransaction` request
+pub fn decode_transaction(params: &Value) -> Transaction {
+    let data = BASE64_STANDARD.decode(params[0].as_str().unwrap()).unwrap();
+    bincode::deserialize(&data).unwrap()
+}
+
+/// Derives a keypair from a seed filled with `byte`
+pub fn fixed_keypair(byte: u8) -> Keypair {
+    keypair_from_seed(&[byte; 32]).unwrap()
+}
+
+/// Builds a legacy spl-token mint account
+pub fn mint_account(authority: Option<Pubkey>, supply: u64, decimals: u8) -> Account {
+    let mint = spl_token::state::Mint {
+        mint_authority: authority.into(),
+        supply,
+        decimals,
+        is_initialized: true,
+        freeze_authority: None.into(),
+    };
+    let mut data = vec![0; spl_token::state::Mint::LEN];
+    spl_token::state::Mint::pack(mint, &mut data).unwrap();
+    Account {
+        lamports: 1_461_600,
+        data,
+        owner: spl_token::id(),
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+/// Builds a legacy spl-token account holding `amount` tokens of `mint`
+pub fn token_account(mint: &Pubkey, owner: &Pubkey, amount: u64) -> Account {
+    let account = spl_token::state::Account {
+        mint: *mint,
+        owner: *owner,
+        amount,
+        state: spl_token::state::AccountState::Initialized,
+        ..Default::default()
+    };
+    let mut data = vec![0; spl_token::state::Account::LEN];
+    spl_token::state::Account::pack(account, &mut data).unwrap();
+    Account {
+        lamports: 2_039_280,
+        data,
+        owner: spl_token::id(),
+        executable: false,
+        rent_epoch: 0,
+    }
+}
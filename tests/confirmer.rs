@@ -0,0 +1,217 @@
+//! The confirmer waits out slow confirmations and tells expired and failed transactions
+//! apart from those that may still land
+
+mod common;
+
+use common::{MockBackend, fixed_keypair, response};
+use serde_json::{Value, json};
+use solana_sdk::instruction::InstructionError;
+use solana_sdk::transaction::{Transaction, TransactionError};
+use solana_sdk::{hash::Hash, pubkey::Pubkey, signature::Signature, signer::Signer};
+use sss_shared::{CancelToken, ConfirmationOutcome, ConfirmationStrategy, Confirmer};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+const FAST: ConfirmationStrategy = ConfirmationStrategy::Polling {
+    interval: Duration::from_millis(5),
+};
+
+/// A transaction status as `getSignatureStatuses` reports it
+fn status(confirmation: &str, err: Value) -> Value {
+    json!({
+        "slot": 1,
+        "confirmations": null,
+        "status": if err.is_null() { json!({ "Ok": null }) } else { json!({ "Err": err }) },
+        "err": err,
+        "confirmationStatus": confirmation,
+    })
+}
+
+/// Answers the n-th `getSignatureStatuses` request with `answer(n)`
+fn statuses(backend: &MockBackend, answer: impl Fn(usize) -> Value + Send + Sync + 'static) {
+    let requests = AtomicUsize::new(0);
+    backend.on("getSignatureStatuses", move |_| {
+        response(json!([answer(requests.fetch_add(1, Ordering::SeqCst))]))
+    });
+}
+
+fn blockhash_valid(backend: &MockBackend, valid: bool) {
+    backend.on("isBlockhashValid", move |_| response(json!(valid)));
+}
+
+/// A token transfer, signed but never sent
+fn transfer() -> Transaction {
+    let payer = fixed_keypair(1);
+    let ix = spl_token::instruction::transfer(
+        &spl_token::id(),
+        &Pubkey::new_unique(),
+        &Pubkey::new_unique(),
+        &payer.pubkey(),
+        &[],
+        5,
+    )
+    .unwrap();
+    Transaction::new_signed_with_payer(&[ix], Some(&payer.pubkey()), &[&payer], Hash::new_unique())
+}
+
+fn confirm(backend: &Arc<MockBackend>, tx: &Transaction, deadline: Instant) -> ConfirmationOutcome {
+    Confirmer::new(&backend.client())
+        .with_transaction(tx)
+        .confirm(&tx.signatures[0], &FAST, deadline, &CancelToken::default())
+        .unwrap()
+}
+
+#[test]
+fn a_slow_confirmation_is_waited_for() {
+    let backend = MockBackend::new();
+    // Unknown for three polls, then processed for two, then confirmed
+    statuses(&backend, |n| match n {
+        0..3 => Value::Null,
+        3..5 => status("processed", Value::Null),
+        _ => status("confirmed", Value::Null),
+    });
+    blockhash_valid(&backend, true);
+    let tx = transfer();
+
+    let outcome = confirm(&backend, &tx, Instant::now() + Duration::from_secs(10));
+    assert_eq!(
+        outcome,
+        ConfirmationOutcome::Confirmed {
+            signature: tx.signatures[0]
+        }
+    );
+    assert_eq!(backend.call_count("getSignatureStatuses"), 6);
+    assert_eq!(backend.call_count("isBlockhashValid"), 5);
+}
+
+#[test]
+fn a_transaction_pending_at_the_deadline_times_out() {
+    let backend = MockBackend::new();
+    statuses(&backend, |_| Value::Null);
+    blockhash_valid(&backend, true);
+    let tx = transfer();
+    let timed_out = ConfirmationOutcome::TimedOut {
+        signature: tx.signatures[0],
+    };
+
+    let start = Instant::now();
+    let outcome = confirm(&backend, &tx, start + Duration::from_millis(50));
+    assert_eq!(outcome, timed_out);
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    assert!(backend.call_count("getSignatureStatuses") > 1);
+
+    // A deadline already passed still checks once
+    let backend = MockBackend::new();
+    statuses(&backend, |_| Value::Null);
+    blockhash_valid(&backend, true);
+    assert_eq!(confirm(&backend, &tx, Instant::now()), timed_out);
+    assert_eq!(backend.call_count("getSignatureStatuses"), 1);
+
+    // So does a cancelled wait, whatever its deadline
+    let cancel = CancelToken::default();
+    cancel.clone().cancel();
+    let outcome = Confirmer::new(&backend.client())
+        .with_transaction(&tx)
+        .confirm(
+            &tx.signatures[0],
+            &FAST,
+            Instant::now() + Duration::from_secs(60),
+            &cancel,
+        )
+        .unwrap();
+    assert_eq!(outcome, timed_out);
+    assert_eq!(backend.call_count("getSignatureStatuses"), 2);
+}
+
+#[test]
+fn an_expired_transaction_fails_without_waiting_for_the_deadline() {
+    let backend = MockBackend::new();
+    statuses(&backend, |_| Value::Null);
+    blockhash_valid(&backend, false);
+    let tx = transfer();
+
+    let start = Instant::now();
+    let outcome = confirm(&backend, &tx, start + Duration::from_secs(60));
+    assert_eq!(
+        outcome,
+        ConfirmationOutcome::Failed {
+            signature: tx.signatures[0],
+            error: TransactionError::BlockhashNotFound,
+            program_error: None,
+        }
+    );
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    // Without the blockhash, expiry cannot be told apart from a slow confirmation
+    let outcome = Confirmer::new(&backend.client())
+        .confirm(
+            &tx.signatures[0],
+            &FAST,
+            Instant::now() + Duration::from_millis(20),
+            &CancelToken::default(),
+        )
+        .unwrap();
+    assert!(matches!(outcome, ConfirmationOutcome::TimedOut { .. }));
+}
+
+#[test]
+fn a_transaction_processed_before_its_blockhash_expired_is_still_confirmed() {
+    let backend = MockBackend::new();
+    statuses(&backend, |n| match n {
+        0..4 => status("processed", Value::Null),
+        _ => status("confirmed", Value::Null),
+    });
+    blockhash_valid(&backend, false);
+    let tx = transfer();
+
+    let outcome = confirm(&backend, &tx, Instant::now() + Duration::from_secs(10));
+    assert_eq!(
+        outcome,
+        ConfirmationOutcome::Confirmed {
+            signature: tx.signatures[0]
+        }
+    );
+}
+
+#[test]
+fn an_on_chain_failure_carries_the_decoded_program_error() {
+    let backend = MockBackend::new();
+    statuses(&backend, |_| {
+        status(
+            "confirmed",
+            json!({ "InstructionError": [0, { "Custom": 1 }] }),
+        )
+    });
+    let tx = transfer();
+    let error = TransactionError::InstructionError(0, InstructionError::Custom(1));
+
+    let outcome = confirm(&backend, &tx, Instant::now() + Duration::from_secs(10));
+    assert_eq!(
+        outcome,
+        ConfirmationOutcome::Failed {
+            signature: tx.signatures[0],
+            error: error.clone(),
+            program_error: Some(
+                "Token program error in instruction 0: InsufficientFunds: Insufficient funds"
+                    .to_string()
+            ),
+        }
+    );
+    assert_eq!(backend.call_count("getSignatureStatuses"), 1);
+    assert_eq!(backend.call_count("isBlockhashValid"), 0);
+
+    // Only the error is known without the transaction's message
+    let signature = Signature::new_unique();
+    let outcome = Confirmer::new(&backend.client())
+        .confirm(&signature, &FAST, Instant::now(), &CancelToken::default())
+        .unwrap();
+    assert_eq!(
+        outcome,
+        ConfirmationOutcome::Failed {
+            signature,
+            error,
+            program_error: None,
+        }
+    );
+}
@@ -0,0 +1,201 @@
+//! The consumable decimal policy, on creation and across existing fixture mints
+//!
+//! The policy and the payer are process-wide, so the tests of this binary run one at a
+//! time and each sets the policy it needs.
+
+mod common;
+
+use common::{MockBackend, fixed_keypair, mint_account, token_account};
+use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer};
+use sss_shared::{
+    ConsumablePolicy, ConsumableRule, MintOptions, SssError, TokenBuilder, UiAmount,
+    audit_existing_mints_with_client, create_consumable_token_with_client,
+    mint_token_detailed_with_client, set_consumable_policy, set_payer,
+};
+use std::sync::{Mutex, MutexGuard};
+
+static SERIAL: Mutex<()> = Mutex::new(());
+
+const OFF: ConsumablePolicy = ConsumablePolicy {
+    force_zero_decimals: false,
+    max_decimals: u8::MAX,
+};
+const WHOLE_ITEMS: ConsumablePolicy = ConsumablePolicy {
+    force_zero_decimals: true,
+    max_decimals: u8::MAX,
+};
+const TWO_DECIMALS: ConsumablePolicy = ConsumablePolicy {
+    force_zero_decimals: false,
+    max_decimals: 2,
+};
+
+/// Sets the policy for the rest of a test, holding the other tests off meanwhile
+fn policy(policy: ConsumablePolicy) -> MutexGuard<'static, ()> {
+    let serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    set_payer(fixed_keypair(1));
+    set_consumable_policy(policy);
+    serial
+}
+
+fn create(backend: &std::sync::Arc<MockBackend>, decimals: u8) -> Result<String, SssError> {
+    create_consumable_token_with_client(
+        &backend.client(),
+        &Keypair::new(),
+        "https://example.com/item.json",
+        "Item",
+        decimals,
+    )
+}
+
+/// Fixture mints of a backend with 0, 2, 6 and 9 decimals, in that order
+fn fixture_mints(backend: &MockBackend) -> Vec<Pubkey> {
+    [0, 2, 6, 9]
+        .into_iter()
+        .map(|decimals| {
+            let mint = Pubkey::new_unique();
+            backend.set_account(
+                mint,
+                mint_account(Some(fixed_keypair(1).pubkey()), 0, decimals),
+            );
+            mint
+        })
+        .collect()
+}
+
+#[test]
+fn without_a_policy_any_decimals_are_created() {
+    let _policy = policy(OFF);
+    let backend = MockBackend::new();
+    for decimals in [0, 6, 9] {
+        create(&backend, decimals).unwrap();
+    }
+    assert_eq!(backend.sent().len(), 3);
+    assert!(
+        audit_existing_mints_with_client(&backend.client(), &fixture_mints(&backend))
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[test]
+fn forced_zero_decimals_refuse_fractional_tokens() {
+    let _policy = policy(WHOLE_ITEMS);
+    let backend = MockBackend::new();
+    create(&backend, 0).unwrap();
+
+    let Err(SssError::ConfigError(message)) = create(&backend, 6) else {
+        panic!("a token with decimals was created");
+    };
+    assert!(message.contains("force_zero_decimals"), "{message}");
+    assert!(
+        message.contains("SSS_CONSUMABLE_FORCE_ZERO_DECIMALS"),
+        "{message}"
+    );
+    let builder = TokenBuilder::new()
+        .name("Item")
+        .uri("https://example.com/item.json")
+        .decimals(1)
+        .create_and_mint_with_client(&backend.client(), &Keypair::new(), 1, None);
+    assert!(matches!(builder, Err(SssError::ConfigError(_))));
+    assert_eq!(backend.sent().len(), 1);
+}
+
+#[test]
+fn max_decimals_allow_up_to_the_cap() {
+    let _policy = policy(TWO_DECIMALS);
+    let backend = MockBackend::new();
+    create(&backend, 2).unwrap();
+    let Err(SssError::ConfigError(message)) = create(&backend, 3) else {
+        panic!("a token past the cap was created");
+    };
+    assert!(message.contains("max_decimals"), "{message}");
+    assert!(message.contains("at most 2"), "{message}");
+    assert_eq!(backend.sent().len(), 1);
+}
+
+#[test]
+fn audit_reports_each_offending_fixture_mint_in_order() {
+    let backend = MockBackend::new();
+    let mints = fixture_mints(&backend);
+    let client = backend.client();
+
+    let _policy = policy(WHOLE_ITEMS);
+    let violations = audit_existing_mints_with_client(&client, &mints).unwrap();
+    assert_eq!(
+        violations
+            .iter()
+            .map(|v| (v.mint, v.decimals, v.rule, v.allowed_decimals))
+            .collect::<Vec<_>>(),
+        vec![
+            (mints[1], 2, ConsumableRule::ForceZeroDecimals, 0),
+            (mints[2], 6, ConsumableRule::ForceZeroDecimals, 0),
+            (mints[3], 9, ConsumableRule::ForceZeroDecimals, 0),
+        ]
+    );
+    assert!(violations[0].message.contains(&mints[1].to_string()));
+
+    // The mints are cached now, so auditing under another policy reads nothing
+    let fetches = backend.call_count("getMultipleAccounts");
+    set_consumable_policy(TWO_DECIMALS);
+    let violations = audit_existing_mints_with_client(&client, &mints).unwrap();
+    assert_eq!(
+        violations
+            .iter()
+            .map(|v| (v.mint, v.rule, v.allowed_decimals))
+            .collect::<Vec<_>>(),
+        vec![
+            (mints[2], ConsumableRule::MaxDecimals, 2),
+            (mints[3], ConsumableRule::MaxDecimals, 2),
+        ]
+    );
+    set_consumable_policy(OFF);
+    assert!(
+        audit_existing_mints_with_client(&client, &mints)
+            .unwrap()
+            .is_empty()
+    );
+    assert_eq!(backend.call_count("getMultipleAccounts"), fetches);
+}
+
+#[test]
+fn audit_fails_on_accounts_that_are_not_mints() {
+    let _policy = policy(WHOLE_ITEMS);
+    let backend = MockBackend::new();
+    let mut mints = fixture_mints(&backend);
+    let missing = Pubkey::new_unique();
+    mints.push(missing);
+    let Err(SssError::TokenError(message)) =
+        audit_existing_mints_with_client(&backend.client(), &mints)
+    else {
+        panic!("a missing mint was audited");
+    };
+    assert!(message.contains(&missing.to_string()), "{message}");
+
+    let account = Pubkey::new_unique();
+    backend.set_account(
+        account,
+        token_account(&mints[0], &fixed_keypair(1).pubkey(), 1),
+    );
+    assert!(audit_existing_mints_with_client(&backend.client(), &[account]).is_err());
+}
+
+#[test]
+fn fractional_quantity_of_a_whole_item_token_is_refused_before_sending() {
+    let _policy = policy(OFF);
+    let backend = MockBackend::new();
+    let mint = fixture_mints(&backend)[0];
+    let client = backend.client();
+    let options = MintOptions::default();
+
+    let amount: UiAmount = "1.5".parse().unwrap();
+    let result = mint_token_detailed_with_client(&client, mint, None, amount, &options);
+    let Err(SssError::TokenError(message)) = result else {
+        panic!("a fractional quantity was accepted: {:?}", result);
+    };
+    assert!(message.contains("whole"), "{message}");
+    assert_eq!(backend.call_count("sendTransaction"), 0);
+
+    let amount: UiAmount = "2.000".parse().unwrap();
+    mint_token_detailed_with_client(&client, mint, None, amount, &options).unwrap();
+    assert_eq!(backend.sent().len(), 1);
+}
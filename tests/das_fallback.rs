@@ -0,0 +1,175 @@
+//! A wallet's fungible tokens are still listed over plain RPC while the DAS provider is down
+//!
+//! Listing falls back to the global client, so the mock backend is served over HTTP and set
+//! as `SOLANA_RPC_URL` before anything reads it. The DAS provider is a local server answering
+//! every request with 503. Both are process-wide, so this binary holds a single test.
+
+mod common;
+
+use common::{MockBackend, fixed_keypair, mint_account, response, rpc_account, token_account};
+use mpl_token_metadata::ID as TOKEN_METADATA_ID;
+use mpl_token_metadata::accounts::Metadata;
+use serde_json::{Value, json};
+use solana_sdk::{account::Account, pubkey::Pubkey, signer::Signer};
+use sss_shared::{
+    AssetQueryOptions, AssetSource, DasProvider, SssError, fetch_digital_assets_by_owner,
+    fetch_digital_assets_by_owner_with_options, set_das_provider,
+};
+use std::env;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// `TokenStandard::Fungible` in a metadata account
+const FUNGIBLE: u8 = 2;
+
+const UNAVAILABLE: &[u8] =
+    b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+
+/// Starts a DAS provider answering every request with 503, counting the requests
+fn unavailable_provider() -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counted = Arc::clone(&requests);
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while reader.read_line(&mut line).is_ok_and(|read| read > 2) {
+                line.clear();
+            }
+            counted.fetch_add(1, Ordering::SeqCst);
+            let _ = stream.write_all(UNAVAILABLE);
+        }
+    });
+    (url, requests)
+}
+
+/// A metadata account of `mint` with the given token standard, if any
+fn metadata_account(mint: &Pubkey, name: &str, token_standard: Option<u8>) -> Account {
+    let mut data = vec![4u8]; // Key::MetadataV1
+    data.extend_from_slice(fixed_keypair(1).pubkey().as_ref());
+    data.extend_from_slice(mint.as_ref());
+    for field in [name, "BEAN", "https://meta.example.com/beans.json"] {
+        data.extend_from_slice(&(field.len() as u32).to_le_bytes());
+        data.extend_from_slice(field.as_bytes());
+    }
+    // Seller fee, no creators, primary sale, mutable, no edition nonce
+    data.extend_from_slice(&[0, 0, 0, 0, 1, 0]);
+    match token_standard {
+        Some(standard) => data.extend_from_slice(&[1, standard]),
+        None => data.push(0),
+    }
+    // No collection, uses, collection details or programmable config
+    data.extend_from_slice(&[0, 0, 0, 0]);
+    Account {
+        lamports: 5_616_720,
+        data,
+        owner: TOKEN_METADATA_ID,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+#[test]
+fn an_unavailable_das_provider_falls_back_to_rpc() {
+    let backend = MockBackend::new();
+    let (das_url, das_requests) = unavailable_provider();
+    unsafe { env::set_var("SOLANA_RPC_URL", backend.serve()) };
+    set_das_provider(DasProvider::standard(das_url)).unwrap();
+
+    let owner = Pubkey::new_unique();
+    // Beans have fungible metadata, stamps have none but decimals, and a pallet NFT neither
+    let beans = Pubkey::new_unique();
+    let stamps = Pubkey::new_unique();
+    let pallet = Pubkey::new_unique();
+    let emptied = Pubkey::new_unique();
+    backend.set_account(beans, mint_account(None, 1_000, 0));
+    backend.set_account(
+        Metadata::find_pda(&beans).0,
+        metadata_account(&beans, "Coffee Beans", Some(FUNGIBLE)),
+    );
+    backend.set_account(stamps, mint_account(None, 1_000, 2));
+    backend.set_account(pallet, mint_account(None, 1, 0));
+    backend.set_account(emptied, mint_account(None, 1, 6));
+    let holdings = [
+        (beans, 40),
+        (beans, 2),
+        (stamps, 150),
+        (pallet, 1),
+        (emptied, 0),
+    ];
+    let accounts: Vec<Value> = holdings
+        .iter()
+        .map(|(mint, amount)| {
+            json!({
+                "pubkey": Pubkey::new_unique().to_string(),
+                "account": rpc_account(Some(&token_account(mint, &owner, *amount))),
+            })
+        })
+        .collect();
+    backend.on("getTokenAccountsByOwner", move |params| {
+        // The accounts are all of the legacy token program
+        if params[1]["programId"] == json!(spl_token::id().to_string()) {
+            response(Value::Array(accounts.clone()))
+        } else {
+            response(json!([]))
+        }
+    });
+
+    let list = fetch_digital_assets_by_owner(&owner).unwrap();
+    assert_eq!(das_requests.load(Ordering::SeqCst), 1);
+    assert_eq!(list.warnings.len(), 1);
+    assert!(
+        list.warnings[0].starts_with("DAS unavailable"),
+        "{}",
+        list.warnings[0]
+    );
+    assert!(list.warnings[0].contains("503"), "{}", list.warnings[0]);
+
+    let mut assets = list.assets;
+    assets.sort_by_key(|asset| asset.balance);
+    let summary: Vec<_> = assets
+        .iter()
+        .map(|asset| {
+            (
+                asset.id,
+                asset.balance,
+                asset.decimals,
+                asset.name.as_deref(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (beans, Some(42), Some(0), Some("Coffee Beans")),
+            (stamps, Some(150), Some(2), None),
+        ]
+    );
+    for asset in &assets {
+        assert_eq!(asset.source, AssetSource::Fallback);
+        assert_eq!(asset.owner, Some(owner.to_string()));
+        assert!(!asset.compressed);
+        assert_eq!(asset.interface, None);
+    }
+    assert_eq!(
+        assets[0].uri.as_deref(),
+        Some("https://meta.example.com/beans.json")
+    );
+    assert_eq!(backend.call_count("getTokenAccountsByOwner"), 2);
+
+    // Without the fallback, the outage is the caller's to handle
+    let options = AssetQueryOptions {
+        fallback_to_rpc: false,
+    };
+    match fetch_digital_assets_by_owner_with_options(&owner, &options) {
+        Err(SssError::RpcError(message)) => assert!(message.contains("getAssetsByOwner")),
+        other => panic!("an outage was not reported: {:?}", other),
+    }
+    assert_eq!(das_requests.load(Ordering::SeqCst), 2);
+    assert_eq!(backend.call_count("getTokenAccountsByOwner"), 2);
+}
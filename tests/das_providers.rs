@@ -0,0 +1,209 @@
+//! Responses recorded from each DAS provider shape deserialize into the same assets
+//!
+//! Requests are answered from `fixtures/das_providers/<provider>` with `SSS_DAS_CACHE=replay`,
+//! so a request a provider would be sent differently finds no recording. The DAS provider is
+//! process-wide, so this test binary keeps it to itself and the tests take turns.
+
+use solana_sdk::pubkey::Pubkey;
+use sss_shared::{
+    AssetQueryOptions, AssetSource, DasProvider, DigitalAsset, SssError, fetch_digital_assets,
+    fetch_digital_assets_by_owner_with_options, get_asset, set_das_provider,
+};
+use std::env;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+const OWNER: &str = "3Wga6r7yBhshmEnNwTScK1GsAyE5ZkoV6TiheQAhEs6D";
+const BEANS: &str = "8T7QvSJBGmaSdWb5jqknmfVbkWSizLHSv3LnTtNCWjhQ";
+const PALLET_7: &str = "21XYiwLxC7EsYsGgKK6Z5nnihRwVqc6V6UeQ9yuqPuu9";
+const PALLET_8: &str = "8kJfejWGcKbny3XDapUiPdrWLTSPoH8zB1yRBuzATN1T";
+const CRATE: &str = "HZFhgaw21ojp7ZTb2F9bufopaQdAzLAi6EZTZtNRrcik";
+const CRATE_2: &str = "DzRvx4Mgh8bGB5RJGQeb6NbT6Psj588cnaj4KmDyBwBA";
+/// Unknown to every indexer
+const UNKNOWN: &str = "CzjEZ6Z6DjDqRFkHp9mpBmJvc2KH9bY3dkgAtrAKFscP";
+/// Rate limited by Triton
+const BUSY: &str = "EaVEw7gDs99nWQehtxpGvx5z4jBd44f9xRgtyQwDMkhG";
+
+static SERIAL: Mutex<()> = Mutex::new(());
+
+/// Makes `provider` the active one, answered from the recordings in `dir`
+fn replaying(provider: DasProvider, dir: &str) -> MutexGuard<'static, ()> {
+    let serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures/das_providers")
+        .join(dir);
+    unsafe {
+        env::set_var("SSS_DAS_CACHE", "replay");
+        env::set_var("SSS_DAS_CACHE_DIR", dir);
+        env::set_var("HELIUS_API_KEY", "recorded");
+        env::set_var("TRITON_TOKEN", "recorded");
+    }
+    set_das_provider(provider).unwrap();
+    serial
+}
+
+/// Lists the owner's assets, failing rather than falling back to RPC
+fn owned_assets() -> Vec<DigitalAsset> {
+    let options = AssetQueryOptions {
+        fallback_to_rpc: false,
+    };
+    let list =
+        fetch_digital_assets_by_owner_with_options(&OWNER.parse().unwrap(), &options).unwrap();
+    assert!(list.warnings.is_empty());
+    assert!(list.assets.iter().all(|a| a.source == AssetSource::Das));
+    list.assets
+}
+
+fn asset(id: &str) -> Option<DigitalAsset> {
+    get_asset(&id.parse().unwrap()).unwrap()
+}
+
+/// Fetches the assets with `getAssetBatch`, returning the names found or `None`
+fn batch(ids: &[&str]) -> Vec<Option<(String, bool)>> {
+    let ids: Vec<Pubkey> = ids.iter().map(|id| id.parse().unwrap()).collect();
+    let assets = fetch_digital_assets(&ids).unwrap();
+    assert_eq!(assets.len(), ids.len());
+    ids.iter()
+        .zip(assets)
+        .map(|(id, asset)| {
+            asset.map(|asset| {
+                assert_eq!(asset.id, *id);
+                (asset.name.unwrap_or_default(), asset.compressed)
+            })
+        })
+        .collect()
+}
+
+fn ids(assets: &[DigitalAsset]) -> Vec<String> {
+    assets.iter().map(|asset| asset.id.to_string()).collect()
+}
+
+#[test]
+fn helius_responses_include_fungible_tokens_and_compressed_assets() {
+    let _serial = replaying(
+        DasProvider::helius("https://mainnet.helius-rpc.com", "HELIUS_API_KEY"),
+        "helius",
+    );
+
+    let assets = owned_assets();
+    assert_eq!(ids(&assets), vec![BEANS, PALLET_7, PALLET_8]);
+    assert_eq!(
+        assets[0],
+        DigitalAsset {
+            id: BEANS.parse().unwrap(),
+            interface: Some("FungibleToken".to_string()),
+            name: Some("Coffee Beans".to_string()),
+            symbol: Some("BEAN".to_string()),
+            uri: Some("https://meta.example.com/beans.json".to_string()),
+            owner: Some(OWNER.to_string()),
+            balance: Some(4_200_000),
+            decimals: Some(6),
+            compressed: false,
+            source: AssetSource::Das,
+            trusted: false,
+        }
+    );
+    assert_eq!(assets[1].name.as_deref(), Some("Pallet 7"));
+    assert_eq!(assets[1].balance, None);
+    assert!(!assets[1].compressed);
+    assert!(assets[2].compressed);
+    assert_eq!(assets[2].owner.as_deref(), Some(OWNER));
+
+    // A fungible asset looked up on its own carries its decimals but no balance
+    let beans = asset(BEANS).unwrap();
+    assert_eq!((beans.balance, beans.decimals), (None, Some(6)));
+    assert_eq!(beans.owner.as_deref(), Some(""));
+}
+
+#[test]
+fn triton_responses_without_optional_sections_are_accepted() {
+    let _serial = replaying(
+        DasProvider::triton("https://example.rpcpool.com", "TRITON_TOKEN"),
+        "triton",
+    );
+
+    let assets = owned_assets();
+    assert_eq!(ids(&assets), vec![PALLET_7, CRATE]);
+    assert_eq!(assets[0].name.as_deref(), Some("Pallet 7"));
+    assert_eq!(assets[0].symbol, None);
+    // An empty json_uri is no URI
+    assert_eq!(assets[0].uri, None);
+    assert_eq!(assets[0].owner.as_deref(), Some(OWNER));
+    let bare = &assets[1];
+    assert_eq!(bare.interface.as_deref(), Some("Custom"));
+    assert_eq!(
+        (&bare.name, &bare.uri, &bare.owner, bare.decimals),
+        (&None, &None, &None, None)
+    );
+    assert!(!bare.compressed);
+
+    // Triton reports unknown assets as an error, and rate limits with its own code
+    assert_eq!(asset(UNKNOWN), None);
+    match get_asset(&BUSY.parse::<Pubkey>().unwrap()) {
+        Err(SssError::RpcError(message)) => {
+            assert!(
+                message.contains("rate limited getAsset, retry later"),
+                "{}",
+                message
+            )
+        }
+        other => panic!("rate limiting was not reported: {:?}", other),
+    }
+}
+
+#[test]
+fn standard_responses_with_null_sections_are_accepted() {
+    let _serial = replaying(
+        DasProvider::standard("http://das.internal:8899"),
+        "standard",
+    );
+
+    let assets = owned_assets();
+    assert_eq!(ids(&assets), vec![CRATE_2]);
+    assert_eq!(assets[0].interface.as_deref(), Some("ProgrammableNFT"));
+    assert_eq!(assets[0].name.as_deref(), Some("Crate 2"));
+    assert_eq!(assets[0].symbol, None);
+    assert_eq!(
+        assets[0].uri.as_deref(),
+        Some("https://meta.example.com/crate2.json")
+    );
+    assert_eq!((assets[0].balance, assets[0].decimals), (None, None));
+    assert!(!assets[0].compressed);
+
+    // A self-hosted node answers unknown assets with null
+    assert_eq!(asset(UNKNOWN), None);
+    let crate_2 = asset(CRATE_2).unwrap();
+    assert_eq!(crate_2.id.to_string(), CRATE_2);
+    assert_eq!((crate_2.name, crate_2.owner), (None, None));
+}
+
+#[test]
+fn batches_keep_the_request_order_with_unknown_ids_as_none() {
+    let found = |name: &str, compressed| Some((name.to_string(), compressed));
+    {
+        // Helius answers unknown ids with null
+        let _serial = replaying(
+            DasProvider::helius("https://mainnet.helius-rpc.com", "HELIUS_API_KEY"),
+            "helius",
+        );
+        assert_eq!(
+            batch(&[BEANS, UNKNOWN, PALLET_8, PALLET_7]),
+            vec![
+                found("Coffee Beans", false),
+                None,
+                found("Pallet 8", true),
+                found("Pallet 7", false),
+            ]
+        );
+    }
+
+    // Triton leaves them out and answers in its own order
+    let _serial = replaying(
+        DasProvider::triton("https://example.rpcpool.com", "TRITON_TOKEN"),
+        "triton",
+    );
+    assert_eq!(
+        batch(&[PALLET_7, UNKNOWN, PALLET_8]),
+        vec![found("Pallet 7", false), None, found("Pallet 8", true)]
+    );
+}
@@ -0,0 +1,199 @@
+//! A job replayed after the process was killed is not minted twice
+//!
+//! Each test runs the first attempt of a job in a child process that is aborted, then
+//! replays the job here with the dedup store reopened from the same file. The dedup store
+//! and the payer are process-wide, so the tests of this binary run one at a time.
+
+mod common;
+
+use common::{MockBackend, decode_transaction, fixed_keypair, mint_account, response};
+use serde_json::{Value, json};
+use solana_sdk::{pubkey::Pubkey, signer::Signer};
+use sss_shared::{
+    FileDedupStore, MintOptions, RawAmount, SssError, SssResult, TxOptions,
+    mint_token_detailed_with_client, set_dedup_store, set_payer,
+};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+const CHILD_MODE: &str = "SSS_DEDUP_TEST_CHILD";
+const CHILD_STORE: &str = "SSS_DEDUP_TEST_STORE";
+const JOB_KEY: &str = "order-4711";
+
+static SERIAL: Mutex<()> = Mutex::new(());
+
+fn mint() -> Pubkey {
+    fixed_keypair(2).pubkey()
+}
+
+/// Returns a backend knowing the mint, whose authority is the payer
+fn backend() -> Arc<MockBackend> {
+    set_payer(fixed_keypair(1));
+    let backend = MockBackend::new();
+    backend.set_account(mint(), mint_account(Some(fixed_keypair(1).pubkey()), 0, 0));
+    backend
+}
+
+/// Opens the dedup store at a path, as a process starting up does
+fn open_store(store: &Path) {
+    set_dedup_store(Box::new(FileDedupStore::open(store).unwrap()));
+}
+
+/// Mints the job's tokens through a backend
+fn run_job(backend: &Arc<MockBackend>) -> SssResult<String> {
+    let options = MintOptions {
+        tx: TxOptions {
+            idempotency_key: Some(JOB_KEY.to_string()),
+            ..TxOptions::default()
+        },
+        ..MintOptions::default()
+    };
+    mint_token_detailed_with_client(&backend.client(), mint(), None, RawAmount(5), &options)
+        .map(|result| result.signature)
+}
+
+/// The first attempt of the job, only run as a child process of [`kill_first_attempt`]
+#[test]
+fn first_attempt() {
+    let Ok(mode) = env::var(CHILD_MODE) else {
+        return;
+    };
+    let store = PathBuf::from(env::var(CHILD_STORE).unwrap());
+    let backend = backend();
+    open_store(&store);
+    match mode.as_str() {
+        // Killed after sending, before the confirmation was recorded
+        "while_sending" => {
+            backend.on("sendTransaction", |params| {
+                let signature = decode_transaction(params).signatures[0];
+                println!("signature {}", signature);
+                std::process::abort()
+            });
+            run_job(&backend).unwrap();
+        }
+        // Killed after the mint confirmed, before the queue acknowledged the job
+        "after_confirming" => {
+            println!("signature {}", run_job(&backend).unwrap());
+            std::process::abort()
+        }
+        other => panic!("unknown mode {}", other),
+    }
+    unreachable!("the first attempt must be killed");
+}
+
+/// Runs the first attempt in a child process that is killed, returning the signature of
+/// the transaction it sent
+fn kill_first_attempt(mode: &str, store: &Path) -> String {
+    let output = Command::new(env::current_exe().unwrap())
+        .args([
+            "--exact",
+            "first_attempt",
+            "--nocapture",
+            "--test-threads=1",
+        ])
+        .env(CHILD_MODE, mode)
+        .env(CHILD_STORE, store)
+        .output()
+        .unwrap();
+    assert!(!output.status.success(), "the first attempt was not killed");
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| {
+            line.split_once("signature ")
+                .map(|(_, signature)| signature)
+        })
+        .unwrap_or_else(|| panic!("the first attempt sent nothing: {:?}", output))
+        .to_string()
+}
+
+fn store_path() -> PathBuf {
+    env::temp_dir().join(format!("sss-dedup-restart-{}.jsonl", rand::random::<u64>()))
+}
+
+fn finalized() -> Value {
+    json!({
+        "slot": 1,
+        "confirmations": null,
+        "status": { "Ok": null },
+        "err": null,
+        "confirmationStatus": "finalized",
+    })
+}
+
+/// Answers the first `getSignatureStatuses` request with a status, and the later ones,
+/// confirming a new send, with a finalized status
+fn signature_status(first: Value) -> impl Fn(&Value) -> Value + Send + Sync {
+    let calls = AtomicUsize::new(0);
+    move |_| match calls.fetch_add(1, Ordering::SeqCst) {
+        0 => response(json!([first])),
+        _ => response(json!([finalized()])),
+    }
+}
+
+#[test]
+fn replay_after_a_confirmed_mint_returns_the_original_signature() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let store = store_path();
+    let original = kill_first_attempt("after_confirming", &store);
+
+    let backend = backend();
+    open_store(&store);
+    assert_eq!(run_job(&backend).unwrap(), original);
+    assert_eq!(backend.call_count("sendTransaction"), 0);
+    // The confirmed record is trusted without asking the chain
+    assert_eq!(backend.call_count("getSignatureStatuses"), 0);
+}
+
+#[test]
+fn replay_after_a_landed_send_returns_the_original_signature() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let store = store_path();
+    let original = kill_first_attempt("while_sending", &store);
+
+    let backend = backend();
+    open_store(&store);
+    backend.on("getSignatureStatuses", signature_status(finalized()));
+    assert_eq!(run_job(&backend).unwrap(), original);
+    assert_eq!(backend.call_count("sendTransaction"), 0);
+
+    // The outcome is now on record, so a second replay does not ask the chain again
+    let backend = self::backend();
+    assert_eq!(run_job(&backend).unwrap(), original);
+    assert_eq!(backend.call_count("getSignatureStatuses"), 0);
+}
+
+#[test]
+fn replay_after_a_send_that_may_still_land_is_refused() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let store = store_path();
+    let original = kill_first_attempt("while_sending", &store);
+
+    let backend = backend();
+    open_store(&store);
+    backend.on("getSignatureStatuses", signature_status(Value::Null));
+    backend.on("isBlockhashValid", |_| response(json!(true)));
+    match run_job(&backend) {
+        Err(SssError::DuplicateInFlight(message)) => {
+            assert!(message.contains(&original), "{message}")
+        }
+        other => panic!("expected DuplicateInFlight, got {:?}", other),
+    }
+    assert_eq!(backend.call_count("sendTransaction"), 0);
+}
+
+#[test]
+fn replay_after_a_send_that_expired_mints_again() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let store = store_path();
+    kill_first_attempt("while_sending", &store);
+
+    let backend = backend();
+    open_store(&store);
+    backend.on("getSignatureStatuses", signature_status(Value::Null));
+    backend.on("isBlockhashValid", |_| response(json!(false)));
+    run_job(&backend).unwrap();
+    assert_eq!(backend.sent().len(), 1);
+}
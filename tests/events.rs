@@ -0,0 +1,173 @@
+//! A mint emits exactly the documented event sequence, with or without retries
+//!
+//! The event sink and `SSS_LOW_BALANCE_LAMPORTS` are process-wide, so this test binary keeps
+//! them to itself and the tests take turns.
+
+mod common;
+
+use common::{MockBackend, fixed_keypair, mint_account};
+use serde_json::{Value, json};
+use solana_sdk::{account::Account, pubkey::Pubkey, signer::Signer};
+use sss_shared::{
+    AmountSpec, MintOptions, RawAmount, SssEvent, mint_token_detailed_with_client, set_event_sink,
+    set_payer,
+};
+use std::env;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const LOW_BALANCE: u64 = 1_000_000_000;
+
+static SERIAL: Mutex<()> = Mutex::new(());
+
+type Seen = Arc<Mutex<Vec<SssEvent>>>;
+
+/// A ledger holding a mint of the payer, and a sink recording every event
+fn ledger() -> (MutexGuard<'static, ()>, Arc<MockBackend>, Pubkey, Seen) {
+    let serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    unsafe { env::set_var("SSS_LOW_BALANCE_LAMPORTS", LOW_BALANCE.to_string()) };
+    set_payer(fixed_keypair(1));
+    let backend = MockBackend::new();
+    let mint = Pubkey::new_unique();
+    backend.set_account(mint, mint_account(Some(fixed_keypair(1).pubkey()), 0, 0));
+    let seen: Seen = Arc::default();
+    let sink = Arc::clone(&seen);
+    set_event_sink(move |event| sink.lock().unwrap().push(event));
+    (serial, backend, mint, seen)
+}
+
+fn fund_payer(backend: &MockBackend, lamports: u64) {
+    backend.set_account(
+        fixed_keypair(1).pubkey(),
+        Account {
+            lamports,
+            data: Vec::new(),
+            owner: solana_sdk::system_program::id(),
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+}
+
+/// Mints 5 tokens to `owner`, returning the signature
+fn mint_to(backend: &Arc<MockBackend>, mint: Pubkey, owner: Pubkey) -> String {
+    mint_token_detailed_with_client(
+        &backend.client(),
+        mint,
+        Some(owner),
+        AmountSpec::Raw(RawAmount(5)),
+        &MintOptions::default(),
+    )
+    .unwrap()
+    .signature
+}
+
+/// Waits for `count` events and returns them as JSON, without the jittered retry delays
+fn events(seen: &Seen, count: usize) -> Vec<Value> {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while seen.lock().unwrap().len() < count {
+        assert!(Instant::now() < deadline, "the sink never caught up");
+        thread::sleep(Duration::from_millis(5));
+    }
+    // Nothing may follow the expected events
+    thread::sleep(Duration::from_millis(50));
+    seen.lock()
+        .unwrap()
+        .drain(..)
+        .map(|event| {
+            let mut event = serde_json::to_value(event).unwrap();
+            if let Some(delay) = event.get_mut("delay_ms") {
+                assert!(delay.as_u64().unwrap() > 0);
+                *delay = Value::Null;
+            }
+            event
+        })
+        .collect()
+}
+
+#[test]
+fn a_successful_mint_emits_sent_confirmed_and_minted() {
+    let (_serial, backend, mint, seen) = ledger();
+    let owner = Pubkey::new_unique();
+    fund_payer(&backend, LOW_BALANCE);
+
+    let signature = mint_to(&backend, mint, owner);
+    let minted = json!({
+        "type": "mint_confirmed",
+        "signature": signature,
+        "mint": mint.to_string(),
+        "owner": owner.to_string(),
+        "amount": 5,
+    });
+    assert_eq!(
+        events(&seen, 3),
+        vec![
+            json!({ "type": "transaction_sent", "signature": signature }),
+            json!({ "type": "transaction_confirmed", "signature": signature }),
+            minted.clone(),
+        ]
+    );
+
+    // A payer left below the threshold is reported before the mint
+    fund_payer(&backend, LOW_BALANCE - 1);
+    let signature = mint_to(&backend, mint, owner);
+    let mut minted = minted;
+    minted["signature"] = json!(signature);
+    assert_eq!(
+        events(&seen, 4),
+        vec![
+            json!({ "type": "transaction_sent", "signature": signature }),
+            json!({ "type": "transaction_confirmed", "signature": signature }),
+            json!({
+                "type": "payer_low_on_funds",
+                "payer": fixed_keypair(1).pubkey().to_string(),
+                "lamports": LOW_BALANCE - 1,
+                "threshold": LOW_BALANCE,
+            }),
+            minted,
+        ]
+    );
+}
+
+#[test]
+fn a_retried_mint_emits_a_retry_per_failed_attempt() {
+    let (_serial, backend, mint, seen) = ledger();
+    let owner = Pubkey::new_unique();
+    fund_payer(&backend, LOW_BALANCE);
+    let rejected = Mutex::new(0);
+    backend.reject("sendTransaction", move |_| {
+        let mut rejected = rejected.lock().unwrap();
+        *rejected += 1;
+        (*rejected <= 2).then(|| "Node is behind by 153 slots".to_string())
+    });
+
+    let signature = mint_to(&backend, mint, owner);
+    assert_eq!(backend.call_count("sendTransaction"), 3);
+    let retry = |attempt: usize| {
+        json!({
+            "type": "retry_scheduled",
+            "signature": signature,
+            "attempt": attempt,
+            "reason": "node is behind",
+            "error": "RPC response error -32002: Node is behind by 153 slots; ",
+            "delay_ms": null,
+        })
+    };
+    assert_eq!(
+        events(&seen, 5),
+        vec![
+            json!({ "type": "transaction_sent", "signature": signature }),
+            retry(1),
+            retry(2),
+            json!({ "type": "transaction_confirmed", "signature": signature }),
+            json!({
+                "type": "mint_confirmed",
+                "signature": signature,
+                "mint": mint.to_string(),
+                "owner": owner.to_string(),
+                "amount": 5,
+            }),
+        ]
+    );
+}
@@ -0,0 +1,92 @@
+//! Partner transactions the payer cannot be asked to co-sign are refused before any review
+//!
+//! The payer is process-wide, so this test binary sets it once for all its tests. Every
+//! refusal happens before the review reads anything from the cluster.
+
+mod common;
+
+use common::fixed_keypair;
+use solana_sdk::hash::Hash;
+use solana_sdk::message::{Message, VersionedMessage};
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::VersionedTransaction;
+use solana_sdk::{pubkey::Pubkey, signer::Signer, system_instruction};
+use sss_shared::{ReviewPolicy, SssError, review_external_transaction, set_payer};
+
+/// A payer-funded transfer signed by the partner, left for the payer to co-sign
+fn partner_transaction() -> VersionedTransaction {
+    set_payer(fixed_keypair(1));
+    let payer = fixed_keypair(1).pubkey();
+    let partner = fixed_keypair(2);
+    let ix = system_instruction::transfer(&partner.pubkey(), &Pubkey::new_unique(), 1);
+    let mut message = Message::new(&[ix], Some(&payer));
+    message.recent_blockhash = Hash::new_unique();
+    let partner_signature = partner.sign_message(&message.serialize());
+    VersionedTransaction {
+        signatures: vec![Signature::default(), partner_signature],
+        message: VersionedMessage::Legacy(message),
+    }
+}
+
+fn legacy(tx: &mut VersionedTransaction) -> &mut Message {
+    match &mut tx.message {
+        VersionedMessage::Legacy(message) => message,
+        VersionedMessage::V0(_) => unreachable!(),
+    }
+}
+
+fn refusal(tx: &VersionedTransaction) -> String {
+    let bytes = bincode::serialize(tx).unwrap();
+    match review_external_transaction(&bytes, ReviewPolicy::default()) {
+        Err(SssError::TokenError(message)) => message,
+        other => panic!("reviewed a malformed transaction: {:?}", other),
+    }
+}
+
+#[test]
+fn a_transaction_without_signers_is_refused() {
+    let mut tx = partner_transaction();
+    legacy(&mut tx).header.num_required_signatures = 0;
+    legacy(&mut tx).header.num_readonly_signed_accounts = 0;
+    tx.signatures.clear();
+    assert!(refusal(&tx).contains("requires no signature"));
+}
+
+#[test]
+fn a_transaction_paid_by_someone_else_is_refused() {
+    let mut tx = partner_transaction();
+    let message = legacy(&mut tx);
+    message.account_keys.swap(0, 1);
+    let message = refusal(&tx);
+    assert!(message.contains("not by the payer"), "{}", message);
+    assert!(message.contains(&fixed_keypair(2).pubkey().to_string()));
+}
+
+#[test]
+fn malformed_transactions_are_refused() {
+    // An instruction invoking an account the message does not list
+    let mut tx = partner_transaction();
+    legacy(&mut tx).instructions[0].program_id_index = 9;
+    assert!(refusal(&tx).starts_with("Malformed transaction"));
+
+    // An instruction account out of range
+    let mut tx = partner_transaction();
+    legacy(&mut tx).instructions[0].accounts.push(200);
+    assert!(refusal(&tx).starts_with("Malformed transaction"));
+
+    // No signature slot for the partner
+    let mut tx = partner_transaction();
+    tx.signatures.truncate(1);
+    assert!(refusal(&tx).starts_with("Malformed transaction"));
+
+    // More required signers than accounts
+    let mut tx = partner_transaction();
+    legacy(&mut tx).header.num_required_signatures = 5;
+    tx.signatures.resize(5, Signature::default());
+    assert!(refusal(&tx).starts_with("Malformed transaction"));
+
+    // Every signer read-only, so nobody can pay the fee
+    let mut tx = partner_transaction();
+    legacy(&mut tx).header.num_readonly_signed_accounts = 2;
+    assert!(refusal(&tx).starts_with("Malformed transaction"));
+}
@@ -0,0 +1,176 @@
+//! Faucet grants accounted in the journal of a mock devnet
+//!
+//! The journal path and the payer are process-wide, so this test binary sets them once for
+//! all its tests, each of which grants its own mint.
+
+mod common;
+
+use common::{MockBackend, fixed_keypair, mint_account, response};
+use serde_json::json;
+use solana_sdk::{pubkey::Pubkey, signer::Signer};
+use sss_shared::{
+    FaucetLimit, FaucetOutcome, FaucetPolicy, SssError, faucet_grant_with_client, set_payer,
+};
+use std::env;
+use std::sync::{Arc, Barrier, Once};
+use std::thread;
+
+const DEVNET_GENESIS_HASH: &str = "EtWTRABZaYq6iMfeYKouRu166VU2xqa1wcaWoxPkrZBG";
+const MAINNET_GENESIS_HASH: &str = "5eykt4UsFv8P8NJdTREpY1vzqKqZKvdpKuc147dw2N9d";
+
+const POLICY: FaucetPolicy = FaucetPolicy {
+    per_recipient_daily: 100,
+    global_daily: 150,
+};
+
+/// Returns a devnet backend holding a fresh mint whose authority is the payer
+fn devnet() -> (Arc<MockBackend>, Pubkey) {
+    static SETUP: Once = Once::new();
+    SETUP.call_once(|| {
+        let journal = env::temp_dir().join(format!("sss-faucet-{}.jsonl", rand::random::<u64>()));
+        // Set before the journal is first used, while no other test thread reads it
+        unsafe { env::set_var("SSS_JOURNAL_PATH", journal) };
+        set_payer(fixed_keypair(1));
+    });
+    let backend = MockBackend::new();
+    backend.on("getGenesisHash", |_| json!(DEVNET_GENESIS_HASH));
+    let mint = Pubkey::new_unique();
+    backend.set_account(mint, mint_account(Some(fixed_keypair(1).pubkey()), 0, 0));
+    (backend, mint)
+}
+
+fn granted(outcome: &FaucetOutcome) -> Option<(u64, u64)> {
+    match outcome {
+        FaucetOutcome::Granted {
+            recipient_total,
+            global_total,
+            ..
+        } => Some((*recipient_total, *global_total)),
+        FaucetOutcome::Throttled { .. } => None,
+    }
+}
+
+#[test]
+fn grants_up_to_the_recipient_cap_and_throttles_past_it() {
+    let (backend, mint) = devnet();
+    let client = backend.client();
+    let recipient = Pubkey::new_unique();
+    let grant = |amount| faucet_grant_with_client(&client, mint, recipient, amount, POLICY);
+
+    assert_eq!(granted(&grant(60).unwrap()), Some((60, 60)));
+    assert_eq!(granted(&grant(40).unwrap()), Some((100, 100)));
+    let FaucetOutcome::Throttled {
+        limit,
+        granted_today,
+        cap,
+        resets_at,
+    } = grant(1).unwrap()
+    else {
+        panic!("expected the grant past the cap to be throttled");
+    };
+    assert_eq!(
+        (limit, granted_today, cap),
+        (FaucetLimit::Recipient, 100, 100)
+    );
+    assert_eq!(resets_at % 86_400, 0);
+    assert_eq!(backend.sent().len(), 2);
+}
+
+#[test]
+fn global_cap_throttles_a_new_recipient() {
+    let (backend, mint) = devnet();
+    let client = backend.client();
+    let grant =
+        |recipient, amount| faucet_grant_with_client(&client, mint, recipient, amount, POLICY);
+
+    assert!(granted(&grant(Pubkey::new_unique(), 100).unwrap()).is_some());
+    assert_eq!(
+        granted(&grant(Pubkey::new_unique(), 50).unwrap()),
+        Some((50, 150))
+    );
+    assert!(matches!(
+        grant(Pubkey::new_unique(), 1).unwrap(),
+        FaucetOutcome::Throttled {
+            limit: FaucetLimit::Global,
+            granted_today: 150,
+            cap: 150,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn concurrent_requests_for_one_recipient_resolve_to_one_grant() {
+    const REQUESTS: usize = 8;
+    let (backend, mint) = devnet();
+    let recipient = Pubkey::new_unique();
+    let barrier = Arc::new(Barrier::new(REQUESTS));
+
+    let requests: Vec<_> = (0..REQUESTS)
+        .map(|_| {
+            let backend = Arc::clone(&backend);
+            let barrier = Arc::clone(&barrier);
+            thread::spawn(move || {
+                let client = backend.client();
+                barrier.wait();
+                faucet_grant_with_client(&client, mint, recipient, 100, POLICY).unwrap()
+            })
+        })
+        .collect();
+    let outcomes: Vec<FaucetOutcome> = requests.into_iter().map(|r| r.join().unwrap()).collect();
+
+    let grants = outcomes.iter().filter(|o| granted(o).is_some()).count();
+    assert_eq!(grants, 1, "{:?}", outcomes);
+    assert!(outcomes.iter().all(|outcome| match outcome {
+        FaucetOutcome::Granted { amount, .. } => *amount == 100,
+        FaucetOutcome::Throttled {
+            limit,
+            granted_today,
+            ..
+        } => *limit == FaucetLimit::Recipient && *granted_today == 100,
+    }));
+    assert_eq!(backend.sent().len(), 1);
+}
+
+#[test]
+fn failed_mint_does_not_use_up_the_cap() {
+    let (backend, mint) = devnet();
+    let client = backend.client();
+    let recipient = Pubkey::new_unique();
+    // The mint lands but fails
+    let failed = json!({ "InstructionError": [0, { "Custom": 1 }] });
+    backend.on("getSignatureStatuses", move |_| {
+        response(json!([{
+            "slot": 1,
+            "confirmations": null,
+            "status": { "Err": failed },
+            "err": failed,
+            "confirmationStatus": "finalized",
+        }]))
+    });
+    assert!(faucet_grant_with_client(&client, mint, recipient, 100, POLICY).is_err());
+
+    let (backend, _) = devnet();
+    backend.set_account(mint, mint_account(Some(fixed_keypair(1).pubkey()), 0, 0));
+    let client = backend.client();
+    let outcome = faucet_grant_with_client(&client, mint, recipient, 100, POLICY).unwrap();
+    assert_eq!(granted(&outcome), Some((100, 100)));
+}
+
+#[test]
+fn faucet_refuses_mainnet_and_empty_grants() {
+    let (backend, mint) = devnet();
+    let client = backend.client();
+    let recipient = Pubkey::new_unique();
+    assert!(matches!(
+        faucet_grant_with_client(&client, mint, recipient, 0, POLICY),
+        Err(SssError::TokenError(_))
+    ));
+
+    backend.on("getGenesisHash", |_| json!(MAINNET_GENESIS_HASH));
+    assert!(matches!(
+        faucet_grant_with_client(&client, mint, recipient, 1, POLICY),
+        Err(SssError::ConfigError(message)) if message.contains("mainnet")
+    ));
+    assert_eq!(backend.call_count("sendTransaction"), 0);
+}
@@ -0,0 +1,218 @@
+//! Parameter indices reported by `sss_get_last_error` for rejected FFI inputs
+
+#![cfg(feature = "ffi")]
+
+use sss_shared::{
+    FFI_ERROR_MESSAGE_LEN, SssFfiError, create_token, mint_token_ffi, sss_get_last_error,
+    sss_mint_token_to_account,
+};
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::ptr;
+
+const MINT: &str = "So11111111111111111111111111111111111111112";
+
+fn last_error() -> SssFfiError {
+    let mut error = SssFfiError {
+        code: 0,
+        param_index: 0,
+        byte_offset: 0,
+        message: [0; FFI_ERROR_MESSAGE_LEN],
+    };
+    assert_eq!(unsafe { sss_get_last_error(&mut error) }, 0);
+    error
+}
+
+fn message(error: &SssFfiError) -> String {
+    unsafe { CStr::from_ptr(error.message.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn c(s: &str) -> CString {
+    CString::new(s).unwrap()
+}
+
+#[test]
+fn get_last_error_rejects_null_out_pointer() {
+    assert_eq!(unsafe { sss_get_last_error(ptr::null_mut()) }, -1);
+}
+
+#[test]
+fn create_token_reports_null_parameters() {
+    let uri = c("https://example.com/token.json");
+    let name = c("Token");
+    let mut signature = [0 as c_char; 128];
+    let mut mint = [0 as c_char; 64];
+
+    let cases: [(_, _, _, _, i32, &str); 4] = [
+        (
+            ptr::null(),
+            name.as_ptr(),
+            signature.as_mut_ptr(),
+            mint.as_mut_ptr(),
+            0,
+            "uri_ptr",
+        ),
+        (
+            uri.as_ptr(),
+            ptr::null(),
+            signature.as_mut_ptr(),
+            mint.as_mut_ptr(),
+            1,
+            "name_ptr",
+        ),
+        (
+            uri.as_ptr(),
+            name.as_ptr(),
+            ptr::null_mut(),
+            mint.as_mut_ptr(),
+            3,
+            "signature_out",
+        ),
+        (
+            uri.as_ptr(),
+            name.as_ptr(),
+            signature.as_mut_ptr(),
+            ptr::null_mut(),
+            4,
+            "mint_address_out",
+        ),
+    ];
+    for (uri, name, signature_out, mint_out, index, param) in cases {
+        let code = unsafe { create_token(uri, name, 6, signature_out, mint_out, 128, 64) };
+        assert_eq!(code, -1);
+        let error = last_error();
+        assert_eq!(error.code, -1);
+        assert_eq!(error.param_index, index, "{}", param);
+        assert_eq!(error.byte_offset, -1);
+        assert!(message(&error).contains(param), "{}", message(&error));
+    }
+}
+
+#[test]
+fn create_token_reports_invalid_utf8_with_offset() {
+    let uri = c("https://example.com/token.json");
+    let name = CString::new(vec![b'T', b'o', 0xff, b'k']).unwrap();
+    let mut signature = [0 as c_char; 128];
+    let mut mint = [0 as c_char; 64];
+
+    let code = unsafe {
+        create_token(
+            uri.as_ptr(),
+            name.as_ptr(),
+            6,
+            signature.as_mut_ptr(),
+            mint.as_mut_ptr(),
+            128,
+            64,
+        )
+    };
+    assert_eq!(code, -3);
+    let error = last_error();
+    assert_eq!(error.param_index, 1);
+    assert_eq!(error.byte_offset, 2);
+    assert!(message(&error).contains("name_ptr"));
+
+    let code = unsafe {
+        create_token(
+            name.as_ptr(),
+            uri.as_ptr(),
+            6,
+            signature.as_mut_ptr(),
+            mint.as_mut_ptr(),
+            128,
+            64,
+        )
+    };
+    assert_eq!(code, -2);
+    let error = last_error();
+    assert_eq!(error.param_index, 0);
+    assert_eq!(error.byte_offset, 2);
+}
+
+#[test]
+fn mint_token_reports_each_bad_parameter() {
+    let mint = c(MINT);
+    // '0' is not a base58 character
+    let bad_key = c("So1111111111110111111111111111111111111112");
+    let short_key = c("So111111");
+    let mut signature = [0 as c_char; 128];
+
+    let code = unsafe { mint_token_ffi(ptr::null(), ptr::null(), 1, signature.as_mut_ptr(), 128) };
+    assert_eq!(code, -1);
+    assert_eq!(last_error().param_index, 0);
+
+    let code = unsafe { mint_token_ffi(mint.as_ptr(), ptr::null(), 1, ptr::null_mut(), 128) };
+    assert_eq!(code, -1);
+    assert_eq!(last_error().param_index, 3);
+
+    let code = unsafe {
+        mint_token_ffi(
+            bad_key.as_ptr(),
+            ptr::null(),
+            1,
+            signature.as_mut_ptr(),
+            128,
+        )
+    };
+    assert_eq!(code, -2);
+    let error = last_error();
+    assert_eq!(error.param_index, 0);
+    assert_eq!(error.byte_offset, 14);
+    assert!(message(&error).contains("mint_str"));
+
+    let code = unsafe {
+        mint_token_ffi(
+            mint.as_ptr(),
+            short_key.as_ptr(),
+            1,
+            signature.as_mut_ptr(),
+            128,
+        )
+    };
+    assert_eq!(code, -3);
+    let error = last_error();
+    assert_eq!(error.param_index, 1);
+    assert_eq!(error.byte_offset, -1);
+    assert!(message(&error).contains("token_owner_str"));
+}
+
+#[test]
+fn mint_token_to_account_reports_each_bad_parameter() {
+    let mint = c(MINT);
+    let bad_key = c("not a key");
+    let mut signature = [0 as c_char; 128];
+
+    let code = unsafe {
+        sss_mint_token_to_account(mint.as_ptr(), ptr::null(), 1, signature.as_mut_ptr(), 128)
+    };
+    assert_eq!(code, -1);
+    assert_eq!(last_error().param_index, 1);
+
+    let code = unsafe {
+        sss_mint_token_to_account(
+            bad_key.as_ptr(),
+            mint.as_ptr(),
+            1,
+            signature.as_mut_ptr(),
+            128,
+        )
+    };
+    assert_eq!(code, -2);
+    let error = last_error();
+    assert_eq!(error.param_index, 0);
+    assert_eq!(error.byte_offset, 3);
+
+    let code = unsafe {
+        sss_mint_token_to_account(
+            mint.as_ptr(),
+            bad_key.as_ptr(),
+            1,
+            signature.as_mut_ptr(),
+            128,
+        )
+    };
+    assert_eq!(code, -3);
+    assert_eq!(last_error().param_index, 1);
+}
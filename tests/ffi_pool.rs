@@ -0,0 +1,127 @@
+//! Hundreds of asynchronous FFI mints all complete, once each, and shutdown waits for them
+//!
+//! Asynchronous calls use the global client, so the mock backend is served over HTTP and set
+//! as `SOLANA_RPC_URL` before anything reads it, as is the pool size. This binary holds a
+//! single test.
+
+mod common;
+
+use common::{MockBackend, decode_transaction, fixed_keypair, mint_account};
+use serde_json::Value;
+use solana_sdk::{pubkey::Pubkey, signer::Signer};
+use sss_shared::{set_payer, sss_mint_token_async, sss_pending_count, sss_shutdown};
+use std::env;
+use std::ffi::{CStr, CString, c_void};
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+
+const CALLS: u64 = 500;
+
+/// The host data of one call, handed back to its callback
+struct Call {
+    request_id: u64,
+}
+
+impl Drop for Call {
+    fn drop(&mut self) {
+        FREED.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+static FREED: AtomicUsize = AtomicUsize::new(0);
+
+/// Request id, code and signature of every completed call, and whether its data matched
+static COMPLETED: Mutex<Vec<(u64, c_int, String, bool)>> = Mutex::new(Vec::new());
+
+/// Holds every send until opened, so the calls pile up in the pool
+static GATE: (Mutex<bool>, Condvar) = (Mutex::new(false), Condvar::new());
+
+extern "C" fn completed(
+    request_id: u64,
+    code: c_int,
+    result_json: *const c_char,
+    data: *mut c_void,
+) {
+    let call = unsafe { Box::from_raw(data.cast::<Call>()) };
+    let json = unsafe { CStr::from_ptr(result_json) }.to_string_lossy();
+    let result: Value = serde_json::from_str(&json).unwrap_or_default();
+    let signature = result["data"]["result"]["signature"]
+        .as_str()
+        .unwrap_or_default();
+    COMPLETED.lock().unwrap().push((
+        request_id,
+        code,
+        signature.to_string(),
+        call.request_id == request_id,
+    ));
+}
+
+fn open_gate() {
+    *GATE.0.lock().unwrap() = true;
+    GATE.1.notify_all();
+}
+
+fn submit(mint: &CString, request_id: u64) {
+    let call = Box::into_raw(Box::new(Call { request_id }));
+    let code = unsafe {
+        sss_mint_token_async(
+            mint.as_ptr(),
+            ptr::null(),
+            request_id + 1,
+            request_id,
+            Some(completed),
+            call.cast(),
+        )
+    };
+    assert_eq!(code, 0, "call {} was not queued", request_id);
+}
+
+#[test]
+fn queued_mints_complete_once_and_shutdown_drains_them() {
+    let backend = MockBackend::new();
+    unsafe {
+        env::set_var("SOLANA_RPC_URL", backend.serve());
+        env::set_var("SSS_ASYNC_THREADS", "16");
+    }
+    set_payer(fixed_keypair(1));
+    let mint = Pubkey::new_unique();
+    backend.set_account(mint, mint_account(Some(fixed_keypair(1).pubkey()), 0, 0));
+    backend.on("sendTransaction", |params| {
+        let mut open = GATE.0.lock().unwrap();
+        while !*open {
+            open = GATE.1.wait(open).unwrap();
+        }
+        Value::String(decode_transaction(params).signatures[0].to_string())
+    });
+    let mint = CString::new(mint.to_string()).unwrap();
+
+    for request_id in 0..CALLS {
+        submit(&mint, request_id);
+    }
+    // No call can finish before its transaction is sent
+    assert_eq!(sss_pending_count(), CALLS as c_int);
+    assert!(COMPLETED.lock().unwrap().is_empty());
+
+    open_gate();
+    sss_shutdown();
+    assert_eq!(sss_pending_count(), 0);
+    let mut completed = COMPLETED.lock().unwrap().clone();
+    completed.sort();
+    assert_eq!(completed.len(), CALLS as usize);
+    for (expected, (request_id, code, signature, matched)) in (0..CALLS).zip(&completed) {
+        assert_eq!(*request_id, expected);
+        assert_eq!(*code, 0, "call {} failed", request_id);
+        assert!(!signature.is_empty());
+        assert!(matched, "call {} got another call's data", request_id);
+    }
+    assert_eq!(FREED.load(Ordering::SeqCst), CALLS as usize);
+    assert_eq!(backend.call_count("sendTransaction"), CALLS as usize);
+
+    // The drained pool starts again on the next call
+    submit(&mint, CALLS);
+    sss_shutdown();
+    assert_eq!(sss_pending_count(), 0);
+    assert_eq!(FREED.load(Ordering::SeqCst), CALLS as usize + 1);
+}
@@ -0,0 +1,236 @@
+//! Compares the messages emitted by the instruction builders with checked-in fixtures
+//!
+//! Run with `cargo test --test golden_messages`. After an intentional change to the
+//! emitted instructions, regenerate the fixtures with `SSS_UPDATE_GOLDEN=1` and review
+//! the diff. No network access is needed.
+//!
+//! The names of the capability flags are pinned the same way, since hosts branch on them;
+//! a diff of `capabilities.txt` may only add lines.
+
+use solana_sdk::{
+    hash::Hash,
+    message::Message,
+    pubkey::Pubkey,
+    signature::{Keypair, keypair_from_seed},
+    signer::Signer,
+};
+use sss_shared::{
+    AccountState, MintOptions, SssResult, TokenBuilder, TokenProgram, build_burn_ix,
+    build_create_vault_ix, build_mint_ix, build_transfer_ix, capabilities, message_to_base64,
+};
+use std::path::Path;
+use std::{env, fs};
+
+const FIXTURE_DIR: &str = "fixtures/golden";
+
+/// Derives a keypair from a seed filled with `byte`
+fn fixed_keypair(byte: u8) -> Keypair {
+    keypair_from_seed(&[byte; 32]).expect("a 32-byte seed is valid")
+}
+
+/// Builds every fixture message from fixed keys and a fixed blockhash
+fn golden_messages() -> SssResult<Vec<(&'static str, Message)>> {
+    let payer = fixed_keypair(1).pubkey();
+    let mint = fixed_keypair(2).pubkey();
+    let owner = fixed_keypair(3).pubkey();
+    let blockhash = Hash::new_from_array([4; 32]);
+    let message =
+        |instructions: Vec<_>| Message::new_with_blockhash(&instructions, Some(&payer), &blockhash);
+
+    let builder = TokenBuilder::new()
+        .name("Golden Token")
+        .symbol("GLD")
+        .uri("https://example.com/golden.json")
+        .decimals(6);
+    let token_2022 = MintOptions {
+        token_program: TokenProgram::Token2022,
+        ..MintOptions::default()
+    };
+    let frozen = MintOptions {
+        initial_state: AccountState::Frozen,
+        ..MintOptions::default()
+    };
+
+    Ok(vec![
+        (
+            "create",
+            message(builder.build_create_token_ix(&payer, &mint)?),
+        ),
+        (
+            "create_token_2022",
+            message(
+                builder
+                    .clone()
+                    .token_program(TokenProgram::Token2022)
+                    .build_create_token_ix(&payer, &mint)?,
+            ),
+        ),
+        (
+            "create_freeze_authority",
+            message(
+                builder
+                    .clone()
+                    .freeze_authority(Pubkey::new_from_array([5; 32]))
+                    .build_create_token_ix(&payer, &mint)?,
+            ),
+        ),
+        (
+            "mint",
+            message(build_mint_ix(
+                &payer,
+                &mint,
+                &owner,
+                1_000_000,
+                &MintOptions::default(),
+            )?),
+        ),
+        (
+            "mint_token_2022",
+            message(build_mint_ix(
+                &payer,
+                &mint,
+                &owner,
+                1_000_000,
+                &token_2022,
+            )?),
+        ),
+        (
+            "mint_frozen",
+            message(build_mint_ix(&payer, &mint, &owner, 1_000_000, &frozen)?),
+        ),
+        (
+            "transfer",
+            message(build_transfer_ix(
+                &payer,
+                &mint,
+                &owner,
+                250_000,
+                6,
+                TokenProgram::Legacy,
+            )?),
+        ),
+        (
+            "transfer_token_2022",
+            message(build_transfer_ix(
+                &payer,
+                &mint,
+                &owner,
+                250_000,
+                6,
+                TokenProgram::Token2022,
+            )?),
+        ),
+        (
+            "burn",
+            message(vec![build_burn_ix(
+                &payer,
+                &mint,
+                100_000,
+                6,
+                TokenProgram::Legacy,
+            )?]),
+        ),
+        (
+            "burn_token_2022",
+            message(vec![build_burn_ix(
+                &payer,
+                &mint,
+                100_000,
+                6,
+                TokenProgram::Token2022,
+            )?]),
+        ),
+        // Vault addresses are shared with partners, so these also pin their derivation
+        (
+            "vault_create",
+            message(build_create_vault_ix(
+                &payer,
+                "golden-partner",
+                &mint,
+                TokenProgram::Legacy,
+                165,
+                2_039_280,
+            )?),
+        ),
+        (
+            "vault_create_token_2022",
+            message(build_create_vault_ix(
+                &payer,
+                "golden-partner",
+                &mint,
+                TokenProgram::Token2022,
+                170,
+                2_074_080,
+            )?),
+        ),
+    ])
+}
+
+/// Lists the capability flag names, one per line; their values depend on the features
+fn golden_capabilities() -> String {
+    let names: Vec<&str> = capabilities().flags.into_keys().collect();
+    names.join("\n")
+}
+
+/// Compares each fixture with its file, or rewrites the files with `SSS_UPDATE_GOLDEN=1`
+fn check_fixtures(fixtures: Vec<(String, String)>) {
+    let update = env::var("SSS_UPDATE_GOLDEN").is_ok_and(|v| v == "1" || v == "true");
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(FIXTURE_DIR);
+    let mut mismatches = Vec::new();
+
+    for (file, actual) in fixtures {
+        let path = dir.join(&file);
+        if update {
+            fs::create_dir_all(&dir).expect("failed to create the fixture directory");
+            fs::write(&path, format!("{}\n", actual)).expect("failed to write the fixture");
+            continue;
+        }
+        match fs::read_to_string(&path) {
+            Ok(expected) if expected.trim() == actual => {}
+            Ok(_) => mismatches.push(format!("CHANGED {}", file)),
+            Err(_) => mismatches.push(format!("MISSING {}", file)),
+        }
+    }
+
+    assert!(
+        mismatches.is_empty(),
+        "{}\nIf the change is intended, rerun with SSS_UPDATE_GOLDEN=1 and review the diff",
+        mismatches.join("\n")
+    );
+}
+
+#[test]
+fn messages_match_fixtures() {
+    let fixtures = golden_messages()
+        .expect("the fixture messages build")
+        .into_iter()
+        .map(|(name, message)| (format!("{}.b64", name), message_to_base64(&message)))
+        .collect();
+    check_fixtures(fixtures);
+}
+
+#[test]
+fn capability_names_match_fixture() {
+    // Regenerating the fixture must not drop a name hosts may branch on
+    let pinned = fs::read_to_string(
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join(FIXTURE_DIR)
+            .join("capabilities.txt"),
+    )
+    .expect("the capability fixture exists");
+    let current = capabilities();
+    let removed: Vec<&str> = pinned
+        .lines()
+        .filter(|name| !current.flags.contains_key(name))
+        .collect();
+    assert!(
+        removed.is_empty(),
+        "capability flags removed: {:?}",
+        removed
+    );
+
+    check_fixtures(vec![(
+        "capabilities.txt".to_string(),
+        golden_capabilities(),
+    )]);
+}
@@ -0,0 +1,121 @@
+//! Identical calls racing each other submit a single transaction
+
+mod common;
+
+use common::{MockBackend, fixed_keypair, mint_account};
+use solana_sdk::signer::Signer;
+use sss_shared::{
+    DuplicatePolicy, MintOptions, RawAmount, SssError, TxOptions, mint_token_detailed_with_client,
+    set_payer,
+};
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::Duration;
+
+const THREADS: usize = 4;
+
+/// Runs the same mint on several threads at once, each with its own client
+fn race(
+    backend: &Arc<MockBackend>,
+    amount: u64,
+    policy: DuplicatePolicy,
+) -> Vec<Result<String, SssError>> {
+    let payer = fixed_keypair(1);
+    let mint = fixed_keypair(2).pubkey();
+    let owner = fixed_keypair(3).pubkey();
+    set_payer(payer.insecure_clone());
+    backend.set_account(mint, mint_account(Some(payer.pubkey()), 0, 0));
+
+    let barrier = Arc::new(Barrier::new(THREADS));
+    let handles: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let backend = Arc::clone(backend);
+            let barrier = Arc::clone(&barrier);
+            thread::spawn(move || {
+                let client = backend.client();
+                let options = MintOptions {
+                    tx: TxOptions {
+                        on_duplicate: policy,
+                        ..TxOptions::default()
+                    },
+                    ..MintOptions::default()
+                };
+                barrier.wait();
+                mint_token_detailed_with_client(
+                    &client,
+                    mint,
+                    Some(owner),
+                    RawAmount(amount),
+                    &options,
+                )
+                .map(|r| r.signature)
+            })
+        })
+        .collect();
+    handles.into_iter().map(|h| h.join().unwrap()).collect()
+}
+
+/// Holds every submitted transaction long enough for the duplicates to arrive
+fn slow_send(backend: &Arc<MockBackend>) {
+    backend.on("sendTransaction", |params| {
+        thread::sleep(Duration::from_millis(300));
+        let tx = common::decode_transaction(params);
+        serde_json::Value::String(tx.signatures[0].to_string())
+    });
+}
+
+#[test]
+fn waiting_duplicates_share_the_first_result() {
+    let backend = MockBackend::new();
+    slow_send(&backend);
+
+    let results = race(&backend, 11, DuplicatePolicy::Wait);
+
+    assert_eq!(backend.sent().len(), 1);
+    let signature = backend.sent()[0].signatures[0].to_string();
+    for result in results {
+        assert_eq!(result.unwrap(), signature);
+    }
+}
+
+#[test]
+fn rejected_duplicates_fail_fast() {
+    let backend = MockBackend::new();
+    slow_send(&backend);
+
+    let results = race(&backend, 12, DuplicatePolicy::Reject);
+
+    assert_eq!(backend.sent().len(), 1);
+    let succeeded = results.iter().filter(|r| r.is_ok()).count();
+    assert_eq!(succeeded, 1);
+    for result in results.iter().filter(|r| r.is_err()) {
+        assert!(
+            matches!(result, Err(SssError::DuplicateInFlight(_))),
+            "{:?}",
+            result
+        );
+    }
+}
+
+#[test]
+fn finished_calls_leave_the_registry() {
+    let backend = MockBackend::new();
+    let payer = fixed_keypair(1);
+    let mint = fixed_keypair(2).pubkey();
+    let owner = fixed_keypair(3).pubkey();
+    set_payer(payer.insecure_clone());
+    backend.set_account(mint, mint_account(Some(payer.pubkey()), 0, 0));
+    let client = backend.client();
+    let options = MintOptions {
+        tx: TxOptions {
+            on_duplicate: DuplicatePolicy::Reject,
+            ..TxOptions::default()
+        },
+        ..MintOptions::default()
+    };
+
+    // Once the first call returned, the same call is no longer a duplicate
+    mint_token_detailed_with_client(&client, mint, Some(owner), RawAmount(13), &options).unwrap();
+    mint_token_detailed_with_client(&client, mint, Some(owner), RawAmount(13), &options).unwrap();
+    assert_eq!(backend.sent().len(), 2);
+}
@@ -0,0 +1,269 @@
+//! Transactions left unresolved by a restart are resolved from the journal
+//!
+//! Each transaction is sent while the node fails to answer, which leaves it `Sent` in the
+//! journal as if the process had died before seeing it confirmed. The journal path, the
+//! payer and the event sink are process-wide, so this binary holds a single test.
+
+mod common;
+
+use base64::prelude::{BASE64_STANDARD, Engine};
+use common::{MockBackend, decode_transaction, fixed_keypair, mint_account, response};
+use serde_json::{Value, json};
+use solana_sdk::{hash::Hash, pubkey::Pubkey, signer::Signer, transaction::Transaction};
+use sss_shared::{
+    JournalFilter, JournalStatus, MintOptions, RawAmount, RecoveryOutcome, SssEvent, TxOptions,
+    journal_query, mint_token_detailed_with_client, recover_in_flight_with_client, set_event_sink,
+    set_payer,
+};
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// A transaction status as `getSignatureStatuses` reports it
+fn status(err: Value) -> Value {
+    json!({
+        "slot": 1,
+        "confirmations": null,
+        "status": if err.is_null() { json!({ "Ok": null }) } else { json!({ "Err": err }) },
+        "err": err,
+        "confirmationStatus": "finalized",
+    })
+}
+
+/// A transaction as `getTransaction` returns it, minting `amount` to a new token account
+fn landed(tx: &Transaction, mint: &Pubkey, amount: u64, err: Value) -> Value {
+    let keys = &tx.message.account_keys;
+    let balance = |amount: u64| {
+        json!([{
+            "accountIndex": 1,
+            "mint": mint.to_string(),
+            "owner": keys[0].to_string(),
+            "uiTokenAmount": { "amount": amount.to_string(), "decimals": 0 },
+        }])
+    };
+    json!({
+        "slot": 1,
+        "blockTime": 1_700_000_000,
+        "transaction": [BASE64_STANDARD.encode(bincode::serialize(tx).unwrap()), "base64"],
+        "meta": {
+            "err": err,
+            "fee": 5000,
+            "preBalances": vec![0; keys.len()],
+            "postBalances": vec![0; keys.len()],
+            "preTokenBalances": [],
+            "postTokenBalances": if err.is_null() { balance(amount) } else { json!([]) },
+        },
+    })
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+#[test]
+fn landed_failed_and_unknown_transactions_are_resolved_after_a_restart() {
+    let journal = env::temp_dir().join(format!("sss-recovery-{}.jsonl", rand::random::<u64>()));
+    // Set before the journal is first used, while no other thread reads it
+    unsafe { env::set_var("SSS_JOURNAL_PATH", &journal) };
+    set_payer(fixed_keypair(1));
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&events);
+    set_event_sink(move |event| {
+        if let SssEvent::InFlightRecovered {
+            idempotency_key,
+            status,
+            ..
+        } = event
+        {
+            sink.lock()
+                .unwrap()
+                .push((idempotency_key.unwrap(), status));
+        }
+    });
+
+    // The first run sends one mint per order; the node never answers
+    let backend = MockBackend::new();
+    let mint = Pubkey::new_unique();
+    backend.set_account(mint, mint_account(Some(fixed_keypair(1).pubkey()), 0, 0));
+    // A new blockhash per send, so only the last one can still be valid after the restart
+    backend.on("getLatestBlockhash", |_| {
+        response(json!({
+            "blockhash": Hash::new_unique().to_string(),
+            "lastValidBlockHeight": 100,
+        }))
+    });
+    let sent: Arc<Mutex<Vec<Transaction>>> = Arc::default();
+    let stash = Arc::clone(&sent);
+    backend.reject("sendTransaction", move |params| {
+        stash.lock().unwrap().push(decode_transaction(params));
+        Some("connection reset by peer".to_string())
+    });
+    let orders = ["landed", "failed", "unknown", "pending"];
+    for (amount, order) in (1..).zip(orders) {
+        let options = MintOptions {
+            tx: TxOptions {
+                idempotency_key: Some(order.to_string()),
+                ..TxOptions::default()
+            },
+            ..MintOptions::default()
+        };
+        let client = backend.client();
+        assert!(
+            mint_token_detailed_with_client(&client, mint, None, RawAmount(amount), &options)
+                .is_err()
+        );
+    }
+    let signatures: HashMap<&str, Transaction> = {
+        let sent = sent.lock().unwrap();
+        let mut by_order = HashMap::new();
+        for (order, tx) in orders.into_iter().zip(sent.iter()) {
+            by_order.insert(order, tx.clone());
+        }
+        assert_eq!(by_order.len(), orders.len(), "one send per order");
+        by_order
+    };
+    let signature = |order: &str| signatures[order].signatures[0].to_string();
+    let sent_entries = journal_query(&JournalFilter {
+        status: Some(JournalStatus::Sent),
+        ..JournalFilter::default()
+    })
+    .unwrap();
+    assert_eq!(sent_entries.len(), orders.len());
+
+    // After the restart, the node knows two of them; its history starts after the others
+    // were sent, and only the pending one still has a valid blockhash
+    let restarted = MockBackend::new();
+    let found: HashMap<String, Value> = HashMap::from([
+        (signature("landed"), Value::Null),
+        (
+            signature("failed"),
+            json!({ "InstructionError": [1, { "Custom": 1 }] }),
+        ),
+    ]);
+    let statuses = found.clone();
+    restarted.on("getSignatureStatuses", move |params| {
+        let statuses: Vec<Value> = params[0]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|sig| {
+                statuses
+                    .get(sig.as_str().unwrap())
+                    .map_or(Value::Null, |err| status(err.clone()))
+            })
+            .collect();
+        response(json!(statuses))
+    });
+    let transactions: HashMap<String, Value> = ["landed", "failed"]
+        .into_iter()
+        .map(|order| {
+            let err = found[&signature(order)].clone();
+            (signature(order), landed(&signatures[order], &mint, 1, err))
+        })
+        .collect();
+    restarted.on("getTransaction", move |params| {
+        transactions
+            .get(params[0].as_str().unwrap())
+            .cloned()
+            .unwrap_or(Value::Null)
+    });
+    let pending_blockhash = signatures["pending"].message.recent_blockhash.to_string();
+    restarted.on("isBlockhashValid", move |params| {
+        response(json!(
+            params[0].as_str() == Some(pending_blockhash.as_str())
+        ))
+    });
+    restarted.on("getFirstAvailableBlock", |_| json!(1_000));
+    let history_start = unix_now() + 3_600;
+    restarted.on("getBlockTime", move |_| json!(history_start));
+
+    let outcomes = recover_in_flight_with_client(&restarted.client()).unwrap();
+    let by_order = |outcomes: &[RecoveryOutcome], order: &str| {
+        outcomes
+            .iter()
+            .find(|outcome| outcome.entry.idempotency_key.as_deref() == Some(order))
+            .cloned()
+            .unwrap_or_else(|| panic!("no outcome for {}", order))
+    };
+    assert_eq!(outcomes.len(), orders.len());
+
+    let landed = by_order(&outcomes, "landed");
+    assert_eq!(landed.entry.status, JournalStatus::Confirmed);
+    assert_eq!(landed.entry.signature, signature("landed"));
+    let receipt = landed.receipt.expect("the landed mint is decoded");
+    assert!(receipt.success);
+    assert_eq!(receipt.token_balances[0].post, 1);
+
+    let failed = by_order(&outcomes, "failed");
+    assert_eq!(failed.entry.status, JournalStatus::Failed);
+    assert!(failed.entry.error.unwrap().contains("Transaction failed"));
+    assert!(!failed.receipt.expect("the failed mint is decoded").success);
+
+    let unknown = by_order(&outcomes, "unknown");
+    assert_eq!(unknown.entry.status, JournalStatus::Unknown);
+    assert!(unknown.entry.error.unwrap().contains("manual review"));
+    assert!(unknown.receipt.is_none());
+
+    let pending = by_order(&outcomes, "pending");
+    assert_eq!(pending.entry.status, JournalStatus::Sent);
+
+    // The journal holds the new states, and the host heard of every change but the pending one
+    let current = |status| {
+        let mut keys: Vec<String> = journal_query(&JournalFilter {
+            status: Some(status),
+            ..JournalFilter::default()
+        })
+        .unwrap()
+        .into_iter()
+        .filter_map(|entry| entry.idempotency_key)
+        .collect();
+        keys.sort();
+        keys
+    };
+    assert_eq!(current(JournalStatus::Confirmed), ["landed"]);
+    assert_eq!(current(JournalStatus::Failed), ["failed"]);
+    assert_eq!(current(JournalStatus::Unknown), ["unknown"]);
+    assert_eq!(current(JournalStatus::Sent), ["pending"]);
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while events.lock().unwrap().len() < 3 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+    let mut heard = events.lock().unwrap().clone();
+    heard.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        heard,
+        vec![
+            ("failed".to_string(), JournalStatus::Failed),
+            ("landed".to_string(), JournalStatus::Confirmed),
+            ("unknown".to_string(), JournalStatus::Unknown),
+        ]
+    );
+
+    // An archival node whose history reaches back settles the unknown one as expired, and
+    // resolved entries are not looked up again
+    let archive = MockBackend::new();
+    archive.on("getSignatureStatuses", |params| {
+        let nulls = vec![Value::Null; params[0].as_array().unwrap().len()];
+        response(json!(nulls))
+    });
+    archive.on("isBlockhashValid", |_| response(json!(false)));
+    archive.on("getFirstAvailableBlock", |_| json!(0));
+    archive.on("getBlockTime", |_| json!(0));
+    let outcomes = recover_in_flight_with_client(&archive.client()).unwrap();
+    assert_eq!(outcomes.len(), 2);
+    assert_eq!(
+        by_order(&outcomes, "unknown").entry.status,
+        JournalStatus::Expired
+    );
+    assert_eq!(
+        by_order(&outcomes, "pending").entry.status,
+        JournalStatus::Expired
+    );
+    assert_eq!(archive.call_count("getSignatureStatuses"), 2);
+    assert_eq!(current(JournalStatus::Expired), ["pending", "unknown"]);
+}
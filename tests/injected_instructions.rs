@@ -0,0 +1,212 @@
+//! Instructions injected through `TxOptions` land around the library's own, in one message
+//!
+//! The inspection hook is a plain function, so the inspections it sees are kept in a static
+//! shared by the tests of this binary. Every test tells its own apart by its memo.
+
+mod common;
+
+use common::{MockBackend, fixed_keypair, mint_account, token_account};
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::{pubkey::Pubkey, signer::Signer, system_instruction, transaction::Transaction};
+use spl_associated_token_account::get_associated_token_address;
+use sss_shared::{
+    AmountSpec, DecodedInstruction, MEMO_PROGRAM_ID, MintOptions, RawAmount, SssError,
+    TransferOptions, TxInspection, TxOptions, mint_token_detailed_with_client, set_payer,
+    transfer_asset_with_client,
+};
+use std::sync::{Arc, Mutex};
+
+/// Lamports the registry account receives with every operation
+const REGISTRY_FEE: u64 = 5_000;
+
+static INSPECTED: Mutex<Vec<TxInspection>> = Mutex::new(Vec::new());
+
+fn record(inspection: &TxInspection) -> bool {
+    INSPECTED.lock().unwrap().push(inspection.clone());
+    true
+}
+
+fn memo(text: &str, signer: &Pubkey) -> Instruction {
+    Instruction::new_with_bytes(
+        MEMO_PROGRAM_ID,
+        text.as_bytes(),
+        vec![AccountMeta::new_readonly(*signer, true)],
+    )
+}
+
+/// A memo before the operation, and a transfer out of a registry wallet after it
+fn injected(text: &str, registry: &Pubkey) -> TxOptions {
+    let funder = Arc::new(fixed_keypair(5));
+    TxOptions {
+        inspection_hook: Some(record),
+        pre_instructions: vec![memo(text, &fixed_keypair(1).pubkey())],
+        post_instructions: vec![system_instruction::transfer(
+            &funder.pubkey(),
+            registry,
+            REGISTRY_FEE,
+        )],
+        extra_signers: vec![funder],
+        ..TxOptions::default()
+    }
+}
+
+/// The inspection of the transaction starting with the memo `text`
+fn inspected(text: &str) -> TxInspection {
+    let matching: Vec<TxInspection> = INSPECTED
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|inspection| {
+            inspection.instructions[0].decoded == DecodedInstruction::Memo(text.to_string())
+        })
+        .cloned()
+        .collect();
+    assert_eq!(matching.len(), 1, "inspected {} times", matching.len());
+    matching.into_iter().next().unwrap()
+}
+
+/// The programs of a transaction's instructions, in order
+fn programs(tx: &Transaction) -> Vec<Pubkey> {
+    tx.message
+        .instructions
+        .iter()
+        .map(|ix| tx.message.account_keys[ix.program_id_index as usize])
+        .collect()
+}
+
+/// Checks the one transaction sent for `mint` carries the memo first and the registry
+/// transfer last around `core`, signed by the payer and the registry wallet
+fn assert_wrapped(backend: &MockBackend, mint: &Pubkey, text: &str, core: &[Pubkey]) {
+    let sent: Vec<Transaction> = backend
+        .sent()
+        .into_iter()
+        .filter(|tx| tx.message.account_keys.contains(mint))
+        .collect();
+    assert_eq!(sent.len(), 1, "the operation was split across transactions");
+    let tx = &sent[0];
+    let mut expected = vec![MEMO_PROGRAM_ID];
+    expected.extend_from_slice(core);
+    expected.push(solana_sdk::system_program::id());
+    assert_eq!(programs(tx), expected);
+    let last = tx.message.instructions.last().unwrap();
+    let (from, to) = (last.accounts[0] as usize, last.accounts[1] as usize);
+    assert_eq!(
+        (tx.message.account_keys[from], tx.message.account_keys[to]),
+        (fixed_keypair(5).pubkey(), Pubkey::new_from_array([9; 32]))
+    );
+    assert_eq!(
+        &tx.message.account_keys[..2],
+        &[fixed_keypair(1).pubkey(), fixed_keypair(5).pubkey()]
+    );
+    assert_eq!(tx.signatures.len(), 2);
+    assert!(tx.verify().is_ok());
+
+    // The hook saw the final instruction list, not only the library's part
+    let inspection = inspected(text);
+    assert_eq!(
+        inspection
+            .instructions
+            .iter()
+            .map(|ix| ix.program_id)
+            .collect::<Vec<_>>(),
+        expected
+    );
+    assert_eq!(
+        inspection.instructions.last().unwrap().decoded,
+        DecodedInstruction::SystemTransfer {
+            lamports: REGISTRY_FEE
+        }
+    );
+}
+
+#[test]
+fn a_mint_carries_the_memo_before_and_the_transfer_after() {
+    set_payer(fixed_keypair(1));
+    let backend = MockBackend::new();
+    let mint = Pubkey::new_unique();
+    backend.set_account(mint, mint_account(Some(fixed_keypair(1).pubkey()), 0, 0));
+    let registry = Pubkey::new_from_array([9; 32]);
+
+    mint_token_detailed_with_client(
+        &backend.client(),
+        mint,
+        Some(Pubkey::new_unique()),
+        AmountSpec::Raw(RawAmount(3)),
+        &MintOptions {
+            tx: injected("registry: mint", &registry),
+            ..MintOptions::default()
+        },
+    )
+    .unwrap();
+    assert_wrapped(
+        &backend,
+        &mint,
+        "registry: mint",
+        &[spl_associated_token_account::id(), spl_token::id()],
+    );
+}
+
+#[test]
+fn a_transfer_carries_the_memo_before_and_the_transfer_after() {
+    set_payer(fixed_keypair(1));
+    let backend = MockBackend::new();
+    let payer = fixed_keypair(1).pubkey();
+    let mint = Pubkey::new_unique();
+    backend.set_account(mint, mint_account(Some(payer), 10, 0));
+    backend.set_account(
+        get_associated_token_address(&payer, &mint),
+        token_account(&mint, &payer, 10),
+    );
+    let registry = Pubkey::new_from_array([9; 32]);
+
+    transfer_asset_with_client(
+        &backend.client(),
+        mint,
+        Pubkey::new_unique(),
+        &TransferOptions {
+            amount: Some(AmountSpec::Raw(RawAmount(4))),
+            tx: injected("registry: transfer", &registry),
+            ..TransferOptions::default()
+        },
+    )
+    .unwrap();
+    assert_wrapped(
+        &backend,
+        &mint,
+        "registry: transfer",
+        &[spl_associated_token_account::id(), spl_token::id()],
+    );
+}
+
+#[test]
+fn injected_instructions_that_do_not_fit_send_nothing() {
+    set_payer(fixed_keypair(1));
+    let backend = MockBackend::new();
+    let mint = Pubkey::new_unique();
+    backend.set_account(mint, mint_account(Some(fixed_keypair(1).pubkey()), 0, 0));
+    let mut options = injected("registry: oversized", &Pubkey::new_from_array([9; 32]));
+    options
+        .post_instructions
+        .push(memo(&"x".repeat(1_000), &fixed_keypair(1).pubkey()));
+
+    let result = mint_token_detailed_with_client(
+        &backend.client(),
+        mint,
+        None,
+        AmountSpec::Raw(RawAmount(3)),
+        &MintOptions {
+            tx: options,
+            ..MintOptions::default()
+        },
+    );
+    match result {
+        Err(SssError::TransactionTooLarge { size, limit, .. }) => assert!(size > limit),
+        other => panic!("an oversized mint was not refused: {:?}", other),
+    }
+    // Neither the mint nor the injected instructions went out, and nothing was signed
+    assert!(backend.sent().is_empty());
+    assert!(INSPECTED.lock().unwrap().iter().all(|inspection| {
+        inspection.instructions[0].decoded
+            != DecodedInstruction::Memo("registry: oversized".to_string())
+    }));
+}
@@ -0,0 +1,395 @@
+//! Migrating plain spl-token mints to tokens with Metaplex metadata, end to end
+//!
+//! The backend applies the instructions of every transaction it receives to its ledger,
+//! so each step reads what the previous one wrote. The payer is process-wide, so this
+//! test binary sets the same one from every test.
+
+mod common;
+
+use common::{MockBackend, decode_transaction, fixed_keypair, mint_account};
+use mpl_token_metadata::ID as TOKEN_METADATA_ID;
+use serde_json::Value;
+use solana_sdk::program_option::COption;
+use solana_sdk::program_pack::Pack;
+use solana_sdk::{account::Account, pubkey::Pubkey, signer::Signer, transaction::Transaction};
+use spl_token::instruction::{AuthorityType, TokenInstruction};
+use sss_shared::{
+    LegacyMigration, MigrationStatus, RotationOutcome, metadata_pda,
+    migrate_legacy_mints_with_client, set_payer,
+};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+
+/// `CreateV1` of the token metadata program
+const CREATE_V1: [u8; 2] = [42, 0];
+/// `UpdateMetadataAccountV2` of the token metadata program
+const UPDATE_METADATA_ACCOUNT_V2: u8 = 15;
+
+fn payer() -> Pubkey {
+    set_payer(fixed_keypair(1));
+    fixed_keypair(1).pubkey()
+}
+
+/// Reads a borsh string at `*offset`, moving past it
+fn read_string(data: &[u8], offset: &mut usize) -> String {
+    let len = u32::from_le_bytes(data[*offset..*offset + 4].try_into().unwrap()) as usize;
+    let s = String::from_utf8(data[*offset + 4..*offset + 4 + len].to_vec()).unwrap();
+    *offset += 4 + len;
+    s
+}
+
+/// Serializes a mutable metadata account
+fn metadata_account(update_authority: &Pubkey, mint: &Pubkey, fields: [&str; 3]) -> Account {
+    let mut data = vec![4u8]; // Key::MetadataV1
+    data.extend_from_slice(update_authority.as_ref());
+    data.extend_from_slice(mint.as_ref());
+    for field in fields {
+        data.extend_from_slice(&(field.len() as u32).to_le_bytes());
+        data.extend_from_slice(field.as_bytes());
+    }
+    // Seller fee, no creators, primary sale, mutable, then every option unset
+    data.extend_from_slice(&[0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+    Account {
+        lamports: 5_616_720,
+        data,
+        owner: TOKEN_METADATA_ID,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+fn mint_state(backend: &MockBackend, mint: &Pubkey) -> spl_token::state::Mint {
+    spl_token::state::Mint::unpack(&backend.account(mint).unwrap().data).unwrap()
+}
+
+/// Applies the metadata creations and authority moves of a transaction to the ledger
+fn apply(backend: &MockBackend, tx: &Transaction) {
+    let keys = &tx.message.account_keys;
+    for ix in &tx.message.instructions {
+        let program = keys[ix.program_id_index as usize];
+        let account = |i: usize| keys[ix.accounts[i] as usize];
+        if program == TOKEN_METADATA_ID && ix.data[..2] == CREATE_V1 {
+            // Metadata, master edition, mint, authority, payer, update authority
+            let mut offset = 2;
+            let name = read_string(&ix.data, &mut offset);
+            let symbol = read_string(&ix.data, &mut offset);
+            let uri = read_string(&ix.data, &mut offset);
+            let (mint, update_authority) = (account(2), account(5));
+            assert_eq!(
+                mint_state(backend, &mint).mint_authority,
+                COption::Some(account(3))
+            );
+            assert!(backend.account(&account(0)).is_none());
+            backend.set_account(
+                account(0),
+                metadata_account(&update_authority, &mint, [&name, &symbol, &uri]),
+            );
+        } else if program == TOKEN_METADATA_ID && ix.data[0] == UPDATE_METADATA_ACCOUNT_V2 {
+            // No new data, then the new update authority
+            assert_eq!(ix.data[1..3], [0, 1]);
+            let mut metadata = backend.account(&account(0)).unwrap();
+            assert_eq!(metadata.data[1..33], account(1).to_bytes());
+            metadata.data[1..33].copy_from_slice(&ix.data[3..35]);
+            backend.set_account(account(0), metadata);
+        } else if program == spl_token::id() {
+            let TokenInstruction::SetAuthority {
+                authority_type,
+                new_authority,
+            } = TokenInstruction::unpack(&ix.data).unwrap()
+            else {
+                panic!("unexpected token instruction");
+            };
+            let mint = account(0);
+            let mut state = mint_state(backend, &mint);
+            let current = match authority_type {
+                AuthorityType::MintTokens => &mut state.mint_authority,
+                AuthorityType::FreezeAccount => &mut state.freeze_authority,
+                other => panic!("unexpected authority {:?}", other),
+            };
+            assert_eq!(*current, COption::Some(account(1)));
+            *current = new_authority;
+            let mut data = backend.account(&mint).unwrap();
+            spl_token::state::Mint::pack(state, &mut data.data).unwrap();
+            backend.set_account(mint, data);
+        }
+    }
+}
+
+/// A backend whose ledger follows the transactions sent to it
+fn ledger() -> Arc<MockBackend> {
+    let backend = MockBackend::new();
+    let ledger: Weak<MockBackend> = Arc::downgrade(&backend);
+    backend.on("sendTransaction", move |params| {
+        let tx = decode_transaction(params);
+        apply(&ledger.upgrade().unwrap(), &tx);
+        Value::String(tx.signatures[0].to_string())
+    });
+    backend
+}
+
+/// A legacy mint whose mint and freeze authorities are `authority`
+fn legacy_mint(authority: &Pubkey, decimals: u8) -> Account {
+    let mut account = mint_account(Some(*authority), 1_000, decimals);
+    let mut state = spl_token::state::Mint::unpack(&account.data).unwrap();
+    state.freeze_authority = COption::Some(*authority);
+    spl_token::state::Mint::pack(state, &mut account.data).unwrap();
+    account
+}
+
+fn checkpoint_path() -> PathBuf {
+    env::temp_dir().join(format!("sss-migration-{}.json", rand::random::<u64>()))
+}
+
+/// Programs invoked by the transactions sent since `from`
+fn programs_sent(backend: &MockBackend, from: usize) -> Vec<Pubkey> {
+    backend.sent()[from..]
+        .iter()
+        .flat_map(|tx| {
+            tx.message
+                .instructions
+                .iter()
+                .map(|ix| tx.message.account_keys[ix.program_id_index as usize])
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+#[test]
+fn two_fixture_mints_are_migrated_end_to_end_and_a_foreign_one_is_reported() {
+    let payer = payer();
+    let backend = ledger();
+    let client = backend.client();
+    let (coffee, tea, foreign) = (
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+    let (treasury, cold) = (fixed_keypair(5).pubkey(), fixed_keypair(6).pubkey());
+    let owner = fixed_keypair(7).pubkey();
+    backend.set_account(coffee, legacy_mint(&payer, 6));
+    backend.set_account(tea, legacy_mint(&payer, 0));
+    backend.set_account(foreign, legacy_mint(&owner, 2));
+
+    let plan = [
+        LegacyMigration {
+            mint: coffee,
+            name: "Coffee".to_string(),
+            symbol: "COF".to_string(),
+            uri: "http://127.0.0.1:9/coffee.json".to_string(),
+            freeze_authority: Some(cold),
+            new_authority: Some(treasury),
+        },
+        LegacyMigration {
+            mint: foreign,
+            name: "Foreign".to_string(),
+            symbol: "FOR".to_string(),
+            uri: "http://127.0.0.1:9/foreign.json".to_string(),
+            ..LegacyMigration::default()
+        },
+        LegacyMigration {
+            mint: tea,
+            name: "Tea".to_string(),
+            symbol: "TEA".to_string(),
+            uri: "http://127.0.0.1:9/tea.json".to_string(),
+            ..LegacyMigration::default()
+        },
+    ];
+    let checkpoint = checkpoint_path();
+
+    let report = migrate_legacy_mints_with_client(&client, &plan, &checkpoint).unwrap();
+    let mints: Vec<Pubkey> = report.mints.iter().map(|m| m.mint).collect();
+    assert_eq!(mints, vec![coffee, foreign, tea]);
+
+    // Coffee got its metadata, then moved its freeze authority, then its mint and update
+    // authorities, in three transactions
+    let migrated = &report.mints[0];
+    assert_eq!(migrated.status, MigrationStatus::Migrated, "{:?}", migrated);
+    assert_eq!(
+        programs_sent(&backend, 0)[..3],
+        [TOKEN_METADATA_ID, spl_token::id(), spl_token::id()]
+    );
+    let state = mint_state(&backend, &coffee);
+    assert_eq!(state.mint_authority, COption::Some(treasury));
+    assert_eq!(state.freeze_authority, COption::Some(cold));
+    assert_eq!(state.decimals, 6);
+    let metadata = backend.account(&metadata_pda(&coffee)).unwrap();
+    assert_eq!(metadata.data[1..33], treasury.to_bytes());
+    assert_eq!(
+        migrated.metadata_signature.as_deref(),
+        Some(backend.sent()[0].signatures[0].to_string().as_str())
+    );
+    assert_eq!(migrated.rotations.len(), 2);
+    assert!(matches!(
+        migrated.rotations[0].freeze_authority,
+        Some(RotationOutcome::Rotated { .. })
+    ));
+    assert!(migrated.rotations[1].freeze_authority.is_none());
+    for outcome in [
+        &migrated.rotations[1].mint_authority,
+        &migrated.rotations[1].update_authority,
+    ] {
+        assert!(
+            matches!(outcome, Some(RotationOutcome::Rotated { .. })),
+            "{:?}",
+            outcome
+        );
+    }
+    let audit = migrated.audit.as_ref().unwrap();
+    assert!(audit.is_consistent(), "{:?}", audit);
+    assert_eq!(audit.decimals, 6);
+    assert_eq!(audit.supply, 1_000);
+    assert_eq!(audit.name.as_deref(), Some("Coffee"));
+    assert_eq!(audit.symbol.as_deref(), Some("COF"));
+    assert_eq!(audit.uri.as_deref(), Some("http://127.0.0.1:9/coffee.json"));
+
+    // The foreign mint was reported and left untouched
+    assert_eq!(
+        report.mints[1].status,
+        MigrationStatus::NotMintAuthority {
+            current: Some(owner.to_string())
+        }
+    );
+    assert!(report.mints[1].metadata_signature.is_none());
+    assert!(backend.account(&metadata_pda(&foreign)).is_none());
+    assert!(
+        backend
+            .sent()
+            .iter()
+            .all(|tx| !tx.message.account_keys.contains(&foreign))
+    );
+
+    // Tea got its metadata with its decimals kept, and kept its authorities
+    let migrated = &report.mints[2];
+    assert_eq!(migrated.status, MigrationStatus::Migrated, "{:?}", migrated);
+    assert!(migrated.rotations.is_empty());
+    let state = mint_state(&backend, &tea);
+    assert_eq!(state.mint_authority, COption::Some(payer));
+    assert_eq!(state.freeze_authority, COption::Some(payer));
+    let audit = migrated.audit.as_ref().unwrap();
+    assert_eq!(audit.decimals, 0);
+    assert_eq!(audit.name.as_deref(), Some("Tea"));
+    assert_eq!(audit.symbol.as_deref(), Some("TEA"));
+    assert_eq!(backend.sent().len(), 4);
+
+    let batch = report.batch_report();
+    assert_eq!(batch.succeeded.len(), 2);
+    assert_eq!(batch.skipped, vec![foreign]);
+    assert!(batch.failed.is_empty());
+    let json: Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+    assert_eq!(json["mints"][0]["status"], "migrated");
+    assert_eq!(json["mints"][1]["status"], "not_mint_authority");
+    assert_eq!(json["mints"][1]["current"], owner.to_string());
+
+    // Running the plan again with the checkpoint sends nothing
+    let again = migrate_legacy_mints_with_client(&client, &plan, &checkpoint).unwrap();
+    let statuses: Vec<&MigrationStatus> = again.mints.iter().map(|m| &m.status).collect();
+    assert_eq!(statuses[0], &MigrationStatus::AlreadyMigrated);
+    assert!(matches!(
+        statuses[1],
+        MigrationStatus::NotMintAuthority { .. }
+    ));
+    assert_eq!(statuses[2], &MigrationStatus::AlreadyMigrated);
+    assert_eq!(
+        again.mints[0].metadata_signature,
+        report.mints[0].metadata_signature
+    );
+    assert_eq!(backend.sent().len(), 4);
+
+    // Without the checkpoint, mints with metadata are recognized and left alone
+    let fresh = migrate_legacy_mints_with_client(&client, &plan, &checkpoint_path()).unwrap();
+    assert_eq!(fresh.mints[0].status, MigrationStatus::HasMetadata);
+    assert_eq!(fresh.mints[2].status, MigrationStatus::HasMetadata);
+    assert_eq!(backend.sent().len(), 4);
+    fs::remove_file(&checkpoint).unwrap();
+}
+
+#[test]
+fn an_interrupted_migration_resumes_after_its_last_completed_step() {
+    let payer = payer();
+    let backend = ledger();
+    let client = backend.client();
+    let (first, second) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let treasury = fixed_keypair(5).pubkey();
+    backend.set_account(first, legacy_mint(&payer, 6));
+    backend.set_account(second, legacy_mint(&payer, 9));
+    let plan: Vec<LegacyMigration> = [(first, "First"), (second, "Second")]
+        .into_iter()
+        .map(|(mint, name)| LegacyMigration {
+            mint,
+            name: name.to_string(),
+            symbol: name[..3].to_uppercase(),
+            uri: format!("http://127.0.0.1:9/{}.json", name),
+            new_authority: Some(treasury),
+            ..LegacyMigration::default()
+        })
+        .collect();
+    let checkpoint = checkpoint_path();
+
+    // The node fails the first read of the first mint once it has metadata, the read of
+    // the authority move
+    let interrupt = Arc::new(AtomicBool::new(true));
+    let ledger = Arc::downgrade(&backend);
+    let armed = Arc::clone(&interrupt);
+    backend.reject("getMultipleAccounts", move |params| {
+        let reads_first = params[0].as_array().unwrap()[0] == first.to_string();
+        let has_metadata = ledger
+            .upgrade()
+            .unwrap()
+            .account(&metadata_pda(&first))
+            .is_some();
+        (reads_first && has_metadata && armed.swap(false, Ordering::SeqCst))
+            .then(|| "node is behind".to_string())
+    });
+
+    let report = migrate_legacy_mints_with_client(&client, &plan, &checkpoint).unwrap();
+    let MigrationStatus::Failed { error } = &report.mints[0].status else {
+        panic!("{:?}", report.mints[0]);
+    };
+    assert!(error.contains("node is behind"), "{}", error);
+    let created = report.mints[0].metadata_signature.clone().unwrap();
+    assert_eq!(report.mints[1].status, MigrationStatus::Migrated);
+    assert_eq!(
+        mint_state(&backend, &first).mint_authority,
+        COption::Some(payer)
+    );
+    assert_eq!(report.batch_report().failed.len(), 1);
+
+    // The checkpoint holds the metadata of the first mint, not yet completed
+    let saved: Value = serde_json::from_str(&fs::read_to_string(&checkpoint).unwrap()).unwrap();
+    assert_eq!(
+        saved["mints"][first.to_string()]["metadata_signature"],
+        created
+    );
+    assert_eq!(saved["mints"][first.to_string()]["completed"], false);
+    assert_eq!(saved["mints"][second.to_string()]["completed"], true);
+
+    // Resuming moves the authorities without creating the metadata again
+    let sent = backend.sent().len();
+    let resumed = migrate_legacy_mints_with_client(&client, &plan, &checkpoint).unwrap();
+    assert_eq!(
+        resumed.mints[0].status,
+        MigrationStatus::Migrated,
+        "{:?}",
+        resumed
+    );
+    assert_eq!(resumed.mints[0].metadata_signature, Some(created));
+    assert_eq!(resumed.mints[1].status, MigrationStatus::AlreadyMigrated);
+    assert_eq!(
+        programs_sent(&backend, sent),
+        vec![spl_token::id(), TOKEN_METADATA_ID]
+    );
+    assert_eq!(
+        mint_state(&backend, &first).mint_authority,
+        COption::Some(treasury)
+    );
+    let metadata = backend.account(&metadata_pda(&first)).unwrap();
+    assert_eq!(metadata.data[1..33], treasury.to_bytes());
+    assert!(!interrupt.load(Ordering::SeqCst));
+
+    let saved: Value = serde_json::from_str(&fs::read_to_string(&checkpoint).unwrap()).unwrap();
+    assert_eq!(saved["mints"][first.to_string()]["completed"], true);
+    fs::remove_file(&checkpoint).unwrap();
+}
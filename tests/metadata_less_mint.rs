@@ -0,0 +1,160 @@
+//! Mints created without Metaplex metadata, e.g. by the plain spl-token CLI
+//!
+//! The payer is process-wide, so this test binary sets it once for all its tests.
+
+mod common;
+
+use common::{MockBackend, fixed_keypair, mint_account};
+use solana_sdk::{pubkey::Pubkey, signer::Signer, transaction::Transaction};
+use spl_token::instruction::TokenInstruction;
+use sss_shared::{
+    MintOptions, RawAmount, SssError, attach_metadata_to_existing_mint_with_client, metadata_pda,
+    mint_token_detailed_with_client, set_payer,
+};
+
+fn payer() -> Pubkey {
+    set_payer(fixed_keypair(1));
+    fixed_keypair(1).pubkey()
+}
+
+/// Returns the programs invoked by a transaction, in order
+fn programs(tx: &Transaction) -> Vec<Pubkey> {
+    tx.message
+        .instructions
+        .iter()
+        .map(|ix| tx.message.account_keys[ix.program_id_index as usize])
+        .collect()
+}
+
+#[test]
+fn mint_without_metadata_is_minted_with_the_token_program() {
+    let payer = payer();
+    let backend = MockBackend::new();
+    let mint = Pubkey::new_unique();
+    backend.set_account(mint, mint_account(Some(payer), 0, 6));
+
+    mint_token_detailed_with_client(
+        &backend.client(),
+        mint,
+        None,
+        RawAmount(25),
+        &MintOptions::default(),
+    )
+    .unwrap();
+
+    let sent = backend.sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(
+        programs(&sent[0]),
+        vec![spl_associated_token_account::id(), spl_token::id()]
+    );
+    let mint_to = sent[0].message.instructions.last().unwrap();
+    assert!(matches!(
+        TokenInstruction::unpack(&mint_to.data).unwrap(),
+        TokenInstruction::MintTo { amount: 25 }
+    ));
+    assert!(!sent[0].message.account_keys.contains(&metadata_pda(&mint)));
+}
+
+#[test]
+fn required_metadata_fails_fast_and_points_at_attaching_it() {
+    let payer = payer();
+    let backend = MockBackend::new();
+    let mint = Pubkey::new_unique();
+    backend.set_account(mint, mint_account(Some(payer), 0, 6));
+
+    let result = mint_token_detailed_with_client(
+        &backend.client(),
+        mint,
+        None,
+        RawAmount(25),
+        &MintOptions {
+            require_metadata: true,
+            ..MintOptions::default()
+        },
+    );
+
+    let Err(SssError::TokenError(message)) = result else {
+        panic!("expected a TokenError, got {:?}", result);
+    };
+    assert!(message.contains(&mint.to_string()), "{message}");
+    assert!(
+        message.contains("attach_metadata_to_existing_mint"),
+        "{message}"
+    );
+    assert_eq!(backend.call_count("sendTransaction"), 0);
+}
+
+#[test]
+fn attached_metadata_is_used_by_the_next_mint() {
+    let payer = payer();
+    let backend = MockBackend::new();
+    let mint = Pubkey::new_unique();
+    backend.set_account(mint, mint_account(Some(payer), 0, 6));
+
+    attach_metadata_to_existing_mint_with_client(
+        &backend.client(),
+        mint,
+        "https://example.com/legacy.json",
+        "Legacy",
+        "LGC",
+    )
+    .unwrap();
+    let sent = backend.sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(programs(&sent[0]), vec![mpl_token_metadata::ID]);
+    assert!(sent[0].message.account_keys.contains(&mint));
+    assert!(sent[0].message.account_keys.contains(&metadata_pda(&mint)));
+
+    // Metadata is now required and found, so the mint goes through the metadata program
+    mint_token_detailed_with_client(
+        &backend.client(),
+        mint,
+        None,
+        RawAmount(25),
+        &MintOptions {
+            require_metadata: true,
+            ..MintOptions::default()
+        },
+    )
+    .unwrap();
+    let sent = backend.sent();
+    assert_eq!(sent.len(), 2);
+    assert_eq!(programs(&sent[1]), vec![mpl_token_metadata::ID]);
+    assert!(sent[1].message.account_keys.contains(&metadata_pda(&mint)));
+}
+
+#[test]
+fn attaching_is_refused_for_mints_it_cannot_apply_to() {
+    let payer = payer();
+    let backend = MockBackend::new();
+    let attach = |mint| {
+        attach_metadata_to_existing_mint_with_client(
+            &backend.client(),
+            mint,
+            "https://example.com/legacy.json",
+            "Legacy",
+            "LGC",
+        )
+    };
+
+    let missing = Pubkey::new_unique();
+    assert!(
+        matches!(attach(missing), Err(SssError::TokenError(m)) if m.contains("does not exist"))
+    );
+
+    let foreign = Pubkey::new_unique();
+    backend.set_account(foreign, mint_account(Some(Pubkey::new_unique()), 0, 6));
+    assert!(
+        matches!(attach(foreign), Err(SssError::TokenError(m)) if m.contains("not the mint authority"))
+    );
+
+    let described = Pubkey::new_unique();
+    backend.set_account(described, mint_account(Some(payer), 0, 6));
+    backend.set_account(metadata_pda(&described), mint_account(None, 0, 0));
+    assert!(
+        matches!(attach(described), Err(SssError::TokenError(m)) if m.contains("already has a metadata account"))
+    );
+
+    assert_eq!(backend.call_count("sendTransaction"), 0);
+}
@@ -0,0 +1,450 @@
+//! A metadata swap undoes or keeps its upload correctly whichever step fails
+//!
+//! The chain is a mock backend holding the token's metadata account, and documents are
+//! uploaded to an in-memory target served over a local HTTP server, so every step can be
+//! made to fail. The payer and the upload target are process-wide, so the tests of this
+//! binary run one at a time.
+
+mod common;
+
+use common::{MockBackend, fixed_keypair, response};
+use mpl_token_metadata::ID as TOKEN_METADATA_ID;
+use mpl_token_metadata::accounts::Metadata;
+use serde_json::json;
+use solana_sdk::{account::Account, pubkey::Pubkey, signer::Signer};
+use sss_shared::{
+    MetadataUploadTarget, OffchainMetadata, SssError, SssResult, StagedUpload, SwapOutcome,
+    SwapStep, set_payer, set_upload_target, swap_metadata_with_client,
+};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+const PREVIOUS_KEY: &str = "previous.json";
+
+static SERIAL: Mutex<()> = Mutex::new(());
+
+/// Documents held in memory and served over HTTP, failing the step it is told to
+struct Storage {
+    base_url: String,
+    documents: Mutex<HashMap<String, Vec<u8>>>,
+    promoted: Mutex<Vec<String>>,
+    uploads: AtomicUsize,
+    /// Serves a different document than the one uploaded
+    tampered: AtomicBool,
+    failing: Mutex<Option<SwapStep>>,
+}
+
+impl Storage {
+    /// Starts serving an empty storage holding only the document the chain points at
+    fn start() -> Arc<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let storage = Arc::new(Self {
+            base_url: format!("http://{}", listener.local_addr().unwrap()),
+            documents: Mutex::new(HashMap::from([(
+                PREVIOUS_KEY.to_string(),
+                br#"{"name":"Coffee","symbol":"COF"}"#.to_vec(),
+            )])),
+            promoted: Mutex::new(Vec::new()),
+            uploads: AtomicUsize::new(0),
+            tampered: AtomicBool::new(false),
+            failing: Mutex::new(None),
+        });
+        let served = Arc::clone(&storage);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = Vec::new();
+                let mut byte = [0u8; 1];
+                while !request.ends_with(b"\r\n\r\n")
+                    && stream.read(&mut byte).is_ok_and(|read| read == 1)
+                {
+                    request.push(byte[0]);
+                }
+                let request = String::from_utf8_lossy(&request);
+                let key = request
+                    .split_whitespace()
+                    .nth(1)
+                    .unwrap_or("/")
+                    .trim_start_matches('/');
+                let document = served.documents.lock().unwrap().get(key).cloned();
+                let reply = match document {
+                    Some(_) if served.tampered.load(Ordering::SeqCst) => {
+                        ok(br#"{"name":"Tampered","symbol":"BAD"}"#)
+                    }
+                    Some(document) => ok(&document),
+                    None => b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_vec(),
+                };
+                let _ = stream.write_all(&reply);
+            }
+        });
+        storage
+    }
+
+    fn uri(&self, key: &str) -> String {
+        format!("{}/{}", self.base_url, key)
+    }
+
+    fn keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self.documents.lock().unwrap().keys().cloned().collect();
+        keys.sort();
+        keys
+    }
+
+    fn fail(&self, step: SwapStep) {
+        *self.failing.lock().unwrap() = Some(step);
+    }
+
+    fn failing(&self, step: SwapStep) -> SssResult<()> {
+        match *self.failing.lock().unwrap() {
+            Some(failing) if failing == step => {
+                Err(SssError::RpcError(format!("storage refused {:?}", step)))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn ok(body: &[u8]) -> Vec<u8> {
+    let mut reply = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n",
+        body.len()
+    )
+    .into_bytes();
+    reply.extend_from_slice(body);
+    reply
+}
+
+/// The upload target handed to the library, sharing the storage with the test
+struct Target(Arc<Storage>);
+
+impl MetadataUploadTarget for Target {
+    fn stage(&self, mint: &Pubkey, document: &[u8]) -> SssResult<StagedUpload> {
+        self.0.failing(SwapStep::Stage)?;
+        let version = self.0.uploads.fetch_add(1, Ordering::SeqCst);
+        let key = format!("{}-{}.json", mint, version);
+        self.0
+            .documents
+            .lock()
+            .unwrap()
+            .insert(key.clone(), document.to_vec());
+        Ok(StagedUpload {
+            uri: self.0.uri(&key),
+            key,
+        })
+    }
+
+    fn promote(&self, upload: &StagedUpload) -> SssResult<()> {
+        self.0.failing(SwapStep::Promote)?;
+        self.0.promoted.lock().unwrap().push(upload.key.clone());
+        Ok(())
+    }
+
+    fn remove(&self, uri: &str) -> SssResult<()> {
+        let Some(key) = uri.strip_prefix(&format!("{}/", self.0.base_url)) else {
+            return Ok(());
+        };
+        // The previous document is removed by the cleanup, a staged one by a rollback
+        self.0.failing(if key == PREVIOUS_KEY {
+            SwapStep::Cleanup
+        } else {
+            SwapStep::Rollback
+        })?;
+        self.0.documents.lock().unwrap().remove(key);
+        Ok(())
+    }
+}
+
+/// Serializes a mutable metadata account whose update authority is the payer
+fn metadata_account(mint: &Pubkey, uri: &str) -> Account {
+    let mut data = vec![4u8]; // Key::MetadataV1
+    data.extend_from_slice(fixed_keypair(1).pubkey().as_ref());
+    data.extend_from_slice(mint.as_ref());
+    for field in ["Coffee", "COF", uri] {
+        data.extend_from_slice(&(field.len() as u32).to_le_bytes());
+        data.extend_from_slice(field.as_bytes());
+    }
+    // Seller fee, no creators, primary sale, mutable, then every option unset
+    data.extend_from_slice(&[0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+    Account {
+        lamports: 5_616_720,
+        data,
+        owner: TOKEN_METADATA_ID,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+struct Swap {
+    backend: Arc<MockBackend>,
+    storage: Arc<Storage>,
+    mint: Pubkey,
+}
+
+impl Swap {
+    /// Sets up a token pointing at the storage's previous document
+    fn new() -> Self {
+        set_payer(fixed_keypair(1));
+        let storage = Storage::start();
+        set_upload_target(Box::new(Target(Arc::clone(&storage))));
+        let backend = MockBackend::new();
+        let mint = Pubkey::new_unique();
+        backend.set_account(
+            Metadata::find_pda(&mint).0,
+            metadata_account(&mint, &storage.uri(PREVIOUS_KEY)),
+        );
+        Self {
+            backend,
+            storage,
+            mint,
+        }
+    }
+
+    fn run(&self) -> SwapOutcome {
+        let metadata = OffchainMetadata {
+            name: "Coffee".to_string(),
+            symbol: "COF".to_string(),
+            description: Some("Single origin, 2026 harvest".to_string()),
+            ..OffchainMetadata::default()
+        };
+        swap_metadata_with_client(&self.backend.client(), self.mint, metadata).unwrap()
+    }
+
+    fn staged_key(&self) -> String {
+        format!("{}-0.json", self.mint)
+    }
+
+    /// The storage keys of the previous and the staged document, as [`Storage::keys`] lists them
+    fn both_documents(&self) -> Vec<String> {
+        let mut keys = vec![PREVIOUS_KEY.to_string(), self.staged_key()];
+        keys.sort();
+        keys
+    }
+}
+
+/// The steps of an outcome and whether each succeeded
+fn steps(outcome: &SwapOutcome) -> Vec<(SwapStep, bool)> {
+    outcome
+        .steps
+        .iter()
+        .map(|record| (record.step, record.ok))
+        .collect()
+}
+
+/// A status `getSignatureStatuses` reports for a transaction the cluster executed and failed
+fn failed_status() -> serde_json::Value {
+    let err = json!({ "InstructionError": [0, { "Custom": 7 }] });
+    response(json!([{
+        "slot": 1,
+        "confirmations": null,
+        "status": { "Err": err },
+        "err": err,
+        "confirmationStatus": "finalized",
+    }]))
+}
+
+#[test]
+fn a_swap_updates_the_chain_then_promotes_and_cleans_up() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let swap = Swap::new();
+    let outcome = swap.run();
+
+    assert_eq!(
+        steps(&outcome),
+        [
+            (SwapStep::Stage, true),
+            (SwapStep::Verify, true),
+            (SwapStep::Send, true),
+            (SwapStep::Confirm, true),
+            (SwapStep::Promote, true),
+            (SwapStep::Cleanup, true),
+        ]
+    );
+    assert!(outcome.swapped());
+    assert!(!outcome.outcome_unknown());
+    assert_eq!(outcome.previous_uri, swap.storage.uri(PREVIOUS_KEY));
+    let new_uri = swap.storage.uri(&swap.staged_key());
+    assert_eq!(outcome.new_uri.as_deref(), Some(new_uri.as_str()));
+
+    // One UpdateV1 carrying the new URI, signed by the payer
+    let sent = swap.backend.sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(
+        outcome.signature.as_deref(),
+        Some(sent[0].signatures[0].to_string().as_str())
+    );
+    let ix = &sent[0].message.instructions[0];
+    assert_eq!(
+        sent[0].message.account_keys[ix.program_id_index as usize],
+        TOKEN_METADATA_ID
+    );
+    assert!(
+        ix.data
+            .windows(new_uri.len())
+            .any(|w| w == new_uri.as_bytes())
+    );
+
+    assert_eq!(swap.storage.keys(), [swap.staged_key()]);
+    assert_eq!(*swap.storage.promoted.lock().unwrap(), [swap.staged_key()]);
+}
+
+#[test]
+fn a_failed_upload_sends_nothing() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let swap = Swap::new();
+    swap.storage.fail(SwapStep::Stage);
+    let outcome = swap.run();
+
+    assert_eq!(steps(&outcome), [(SwapStep::Stage, false)]);
+    assert!(
+        outcome.steps[0]
+            .detail
+            .as_ref()
+            .unwrap()
+            .contains("refused")
+    );
+    assert!(!outcome.swapped());
+    assert_eq!(outcome.new_uri, None);
+    assert!(swap.backend.sent().is_empty());
+    assert_eq!(swap.storage.keys(), [PREVIOUS_KEY]);
+}
+
+#[test]
+fn an_upload_served_differently_is_rolled_back_before_sending() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let swap = Swap::new();
+    swap.storage.tampered.store(true, Ordering::SeqCst);
+    let outcome = swap.run();
+
+    assert_eq!(
+        steps(&outcome),
+        [
+            (SwapStep::Stage, true),
+            (SwapStep::Verify, false),
+            (SwapStep::Rollback, true),
+        ]
+    );
+    assert!(
+        outcome.steps[1]
+            .detail
+            .as_ref()
+            .unwrap()
+            .contains("differs")
+    );
+    assert!(swap.backend.sent().is_empty());
+    assert_eq!(swap.storage.keys(), [PREVIOUS_KEY]);
+}
+
+#[test]
+fn an_update_rejected_by_the_cluster_is_rolled_back() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let swap = Swap::new();
+    swap.backend.on("getSignatureStatuses", |_| failed_status());
+    let outcome = swap.run();
+
+    assert_eq!(
+        steps(&outcome),
+        [
+            (SwapStep::Stage, true),
+            (SwapStep::Verify, true),
+            (SwapStep::Send, false),
+            (SwapStep::Rollback, true),
+        ]
+    );
+    assert!(!outcome.swapped());
+    assert!(!outcome.outcome_unknown());
+    assert_eq!(outcome.signature, None);
+    assert_eq!(swap.storage.keys(), [PREVIOUS_KEY]);
+    assert!(swap.storage.promoted.lock().unwrap().is_empty());
+}
+
+#[test]
+fn an_unconfirmed_update_keeps_the_staged_upload() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let swap = Swap::new();
+    swap.backend.reject("sendTransaction", |_| {
+        Some("connection reset by peer".to_string())
+    });
+    let outcome = swap.run();
+
+    assert_eq!(
+        steps(&outcome),
+        [
+            (SwapStep::Stage, true),
+            (SwapStep::Verify, true),
+            (SwapStep::Send, true),
+            (SwapStep::Confirm, false),
+        ]
+    );
+    assert!(!outcome.swapped());
+    assert!(outcome.outcome_unknown());
+    assert!(outcome.signature.is_some());
+    // The chain may point at either document, so both stay
+    assert_eq!(swap.storage.keys(), swap.both_documents());
+    assert!(swap.storage.promoted.lock().unwrap().is_empty());
+}
+
+#[test]
+fn a_failed_promotion_or_cleanup_does_not_undo_the_swap() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let swap = Swap::new();
+    swap.storage.fail(SwapStep::Promote);
+    let outcome = swap.run();
+    assert!(outcome.swapped());
+    assert_eq!(
+        steps(&outcome)[4..],
+        [(SwapStep::Promote, false), (SwapStep::Cleanup, true)]
+    );
+    assert_eq!(swap.storage.keys(), [swap.staged_key()]);
+
+    let swap = Swap::new();
+    swap.storage.fail(SwapStep::Cleanup);
+    let outcome = swap.run();
+    assert!(outcome.swapped());
+    assert_eq!(
+        steps(&outcome)[4..],
+        [(SwapStep::Promote, true), (SwapStep::Cleanup, false)]
+    );
+    // The previous document is left behind, and named in the outcome for a later cleanup
+    assert_eq!(outcome.previous_uri, swap.storage.uri(PREVIOUS_KEY));
+    assert_eq!(swap.storage.keys(), swap.both_documents());
+}
+
+#[test]
+fn a_failed_rollback_is_recorded() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let swap = Swap::new();
+    swap.storage.tampered.store(true, Ordering::SeqCst);
+    swap.storage.fail(SwapStep::Rollback);
+    let outcome = swap.run();
+
+    assert_eq!(
+        steps(&outcome),
+        [
+            (SwapStep::Stage, true),
+            (SwapStep::Verify, false),
+            (SwapStep::Rollback, false),
+        ]
+    );
+    assert!(swap.backend.sent().is_empty());
+    // The orphan is named in the outcome
+    let new_uri = swap.storage.uri(&swap.staged_key());
+    assert_eq!(outcome.new_uri.as_deref(), Some(new_uri.as_str()));
+}
+
+#[test]
+fn a_token_the_payer_cannot_update_is_refused_before_uploading() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let swap = Swap::new();
+    swap.backend
+        .remove_account(&Metadata::find_pda(&swap.mint).0);
+    let metadata = OffchainMetadata::default();
+    match swap_metadata_with_client(&swap.backend.client(), swap.mint, metadata) {
+        Err(SssError::TokenError(message)) => assert!(message.contains("no metadata")),
+        other => panic!("expected a TokenError, got {:?}", other),
+    }
+    assert_eq!(swap.storage.uploads.load(Ordering::SeqCst), 0);
+}
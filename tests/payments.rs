@@ -0,0 +1,456 @@
+//! Payment requests are encoded for wallets, and payments found for them are checked
+//!
+//! Payments are looked up with the global client, so the mock backend is served as
+//! `SOLANA_RPC_URL` and shared by the tests of this binary. Every test pays its own
+//! reference keys.
+
+mod common;
+
+use base64::prelude::{BASE64_STANDARD, Engine};
+use common::{MockBackend, fixed_keypair, mint_account};
+use serde_json::{Value, json};
+use solana_sdk::instruction::AccountMeta;
+use solana_sdk::{
+    pubkey::Pubkey, signature::Signature, signer::Signer, system_instruction,
+    transaction::Transaction,
+};
+use spl_associated_token_account::get_associated_token_address;
+use sss_shared::{
+    PaymentConfirmation, SssError, await_payment, create_payment_request,
+    generate_payment_reference, set_payer,
+};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+/// Paying transactions by reference key, oldest first, and their `getTransaction` results
+#[derive(Default)]
+struct Payments {
+    by_reference: HashMap<Pubkey, Vec<(Signature, bool)>>,
+    transactions: HashMap<String, Value>,
+}
+
+static BACKEND: OnceLock<(Arc<MockBackend>, Arc<Mutex<Payments>>)> = OnceLock::new();
+
+fn backend() -> (Arc<MockBackend>, Arc<Mutex<Payments>>) {
+    let (backend, payments) = BACKEND.get_or_init(|| {
+        let backend = MockBackend::new();
+        unsafe { env::set_var("SOLANA_RPC_URL", backend.serve()) };
+        set_payer(fixed_keypair(1));
+        let payments: Arc<Mutex<Payments>> = Arc::default();
+        let listed = Arc::clone(&payments);
+        backend.on("getSignaturesForAddress", move |params| {
+            let reference: Pubkey = params[0].as_str().unwrap().parse().unwrap();
+            let payments = listed.lock().unwrap();
+            let signatures = payments.by_reference.get(&reference).cloned();
+            // Newest first, as the cluster lists them
+            let statuses: Vec<Value> = signatures
+                .unwrap_or_default()
+                .iter()
+                .rev()
+                .map(|(signature, success)| {
+                    json!({
+                        "signature": signature.to_string(),
+                        "slot": 1000,
+                        "err": error(*success),
+                        "memo": null,
+                        "blockTime": null,
+                        "confirmationStatus": "confirmed",
+                    })
+                })
+                .collect();
+            Value::Array(statuses)
+        });
+        let fetched = Arc::clone(&payments);
+        backend.on("getTransaction", move |params| {
+            let signature = params[0].as_str().unwrap();
+            let payments = fetched.lock().unwrap();
+            payments
+                .transactions
+                .get(signature)
+                .cloned()
+                .unwrap_or(Value::Null)
+        });
+        (backend, payments)
+    });
+    (Arc::clone(backend), Arc::clone(payments))
+}
+
+/// The error of a transaction, as the cluster reports it
+fn error(success: bool) -> Value {
+    match success {
+        true => Value::Null,
+        false => json!({ "InstructionError": [0, { "Custom": 1 }] }),
+    }
+}
+
+fn shop() -> Pubkey {
+    fixed_keypair(1).pubkey()
+}
+
+/// What a paying transaction moves
+enum Paid {
+    /// Lamports credited to `to`
+    Sol { to: Pubkey, lamports: u64 },
+    /// Tokens of `mint` credited to the associated token account of `to`
+    Token {
+        to: Pubkey,
+        mint: Pubkey,
+        amount: u64,
+    },
+}
+
+/// Lands a transaction from a new customer carrying `reference`, failed or not
+fn pay(reference: Pubkey, paid: Paid, success: bool) -> Signature {
+    let (_, payments) = backend();
+    let customer = Pubkey::new_unique();
+    let mut transfer = match &paid {
+        Paid::Sol { to, lamports } => system_instruction::transfer(&customer, to, *lamports),
+        Paid::Token { to, mint, amount } => spl_token::instruction::transfer(
+            &spl_token::id(),
+            &get_associated_token_address(&customer, mint),
+            &get_associated_token_address(to, mint),
+            &customer,
+            &[],
+            *amount,
+        )
+        .unwrap(),
+    };
+    transfer
+        .accounts
+        .push(AccountMeta::new_readonly(reference, false));
+    let tx = Transaction::new_with_payer(&[transfer], Some(&customer));
+    let keys = &tx.message.account_keys;
+    let index = |key: &Pubkey| keys.iter().position(|k| k == key).unwrap();
+
+    let pre_balances = vec![10_000_000_000u64; keys.len()];
+    let mut post_balances = pre_balances.clone();
+    let (mut pre_tokens, mut post_tokens) = (Vec::new(), Vec::new());
+    match paid {
+        Paid::Sol { to, lamports } => {
+            post_balances[index(&customer)] -= lamports + 5000;
+            post_balances[index(&to)] += lamports;
+        }
+        Paid::Token { to, mint, amount } => {
+            post_balances[index(&customer)] -= 5000;
+            for (owner, pre, post) in [
+                (customer, 10_000_000, 10_000_000 - amount),
+                (to, 7, 7 + amount),
+            ] {
+                let balance = |value: u64| {
+                    json!({
+                        "accountIndex": index(&get_associated_token_address(&owner, &mint)),
+                        "mint": mint.to_string(),
+                        "owner": owner.to_string(),
+                        "uiTokenAmount": { "amount": value.to_string(), "decimals": 6 },
+                    })
+                };
+                pre_tokens.push(balance(pre));
+                post_tokens.push(balance(post));
+            }
+        }
+    }
+    if !success {
+        post_balances = pre_balances.clone();
+        post_balances[index(&customer)] -= 5000;
+        post_tokens = pre_tokens.clone();
+    }
+
+    let signature = Signature::new_unique();
+    let transaction = json!({
+        "slot": 1000,
+        "blockTime": null,
+        "transaction": [BASE64_STANDARD.encode(bincode::serialize(&tx).unwrap()), "base64"],
+        "meta": {
+            "err": error(success),
+            "fee": 5000,
+            "preBalances": pre_balances,
+            "postBalances": post_balances,
+            "preTokenBalances": pre_tokens,
+            "postTokenBalances": post_tokens,
+        },
+    });
+    let mut payments = payments.lock().unwrap();
+    payments
+        .transactions
+        .insert(signature.to_string(), transaction);
+    payments
+        .by_reference
+        .entry(reference)
+        .or_default()
+        .push((signature, success));
+    signature
+}
+
+/// A USDC-like mint with 6 decimals
+fn usdc() -> Pubkey {
+    let (backend, _) = backend();
+    let mint = Pubkey::new_unique();
+    backend.set_account(
+        mint,
+        mint_account(Some(Pubkey::new_unique()), 1_000_000_000, 6),
+    );
+    mint
+}
+
+fn wait(
+    reference: Pubkey,
+    spl_mint: Option<Pubkey>,
+    expected: u64,
+) -> Result<PaymentConfirmation, SssError> {
+    await_payment(reference, spl_mint, expected, Duration::ZERO)
+}
+
+#[test]
+fn request_urls_encode_spaces_and_unicode() {
+    backend();
+    let reference = generate_payment_reference();
+    let request = create_payment_request(
+        1_500_000_000,
+        None,
+        reference,
+        Some("Café Olé & Co"),
+        Some("2 × espresso = ☕ (to go)"),
+    )
+    .unwrap();
+    assert_eq!(
+        request.url,
+        format!(
+            "solana:{}?amount=1.5&reference={}&label=Caf%C3%A9%20Ol%C3%A9%20%26%20Co\
+             &message=2%20%C3%97%20espresso%20%3D%20%E2%98%95%20(to%20go)",
+            shop(),
+            reference
+        )
+    );
+    assert_eq!(
+        (
+            request.recipient,
+            request.amount,
+            request.spl_mint,
+            request.reference
+        ),
+        (shop(), 1_500_000_000, None, reference)
+    );
+
+    // Token amounts are in whole tokens, and empty texts are left out
+    let mint = usdc();
+    let request = create_payment_request(2_500_001, Some(mint), reference, Some(""), None).unwrap();
+    assert_eq!(
+        request.url,
+        format!(
+            "solana:{}?amount=2.500001&spl-token={}&reference={}",
+            shop(),
+            mint,
+            reference
+        )
+    );
+
+    // Reserved characters of URLs and query strings never pass through as they are
+    let request =
+        create_payment_request(1, None, reference, Some("a+b/c?d#e%f"), Some("ümlaut\n")).unwrap();
+    assert!(
+        request
+            .url
+            .ends_with("&label=a%2Bb%2Fc%3Fd%23e%25f&message=%C3%BCmlaut%0A"),
+        "{}",
+        request.url
+    );
+
+    match create_payment_request(0, None, reference, None, None) {
+        Err(SssError::TokenError(message)) => {
+            assert_eq!(message, "Payment amount must be greater than 0")
+        }
+        other => panic!("a free payment was requested: {:?}", other),
+    }
+}
+
+#[test]
+fn every_request_gets_a_new_reference() {
+    let references: HashSet<Pubkey> = (0..100).map(|_| generate_payment_reference()).collect();
+    assert_eq!(references.len(), 100);
+    assert!(!references.contains(&shop()));
+}
+
+#[test]
+fn an_exact_payment_is_confirmed() {
+    let reference = generate_payment_reference();
+    let signature = pay(
+        reference,
+        Paid::Sol {
+            to: shop(),
+            lamports: 250_000_000,
+        },
+        true,
+    );
+    let confirmation = wait(reference, None, 250_000_000).unwrap();
+    assert_eq!(confirmation.signature, signature.to_string());
+    assert_eq!(
+        (confirmation.received, confirmation.overpaid),
+        (250_000_000, 0)
+    );
+    assert_ne!(confirmation.payer, shop());
+
+    let reference = generate_payment_reference();
+    let mint = usdc();
+    let signature = pay(
+        reference,
+        Paid::Token {
+            to: shop(),
+            mint,
+            amount: 4_990_000,
+        },
+        true,
+    );
+    let confirmation = wait(reference, Some(mint), 4_990_000).unwrap();
+    assert_eq!(confirmation.signature, signature.to_string());
+    assert_eq!(
+        (confirmation.received, confirmation.overpaid),
+        (4_990_000, 0)
+    );
+}
+
+#[test]
+fn an_overpayment_is_confirmed_with_the_excess() {
+    let reference = generate_payment_reference();
+    pay(
+        reference,
+        Paid::Sol {
+            to: shop(),
+            lamports: 300_000_000,
+        },
+        true,
+    );
+    let confirmation = wait(reference, None, 250_000_000).unwrap();
+    assert_eq!(
+        (confirmation.received, confirmation.overpaid),
+        (300_000_000, 50_000_000)
+    );
+
+    let reference = generate_payment_reference();
+    let mint = usdc();
+    pay(
+        reference,
+        Paid::Token {
+            to: shop(),
+            mint,
+            amount: 5_000_000,
+        },
+        true,
+    );
+    let confirmation = wait(reference, Some(mint), 4_990_000).unwrap();
+    assert_eq!(
+        (confirmation.received, confirmation.overpaid),
+        (5_000_000, 10_000)
+    );
+}
+
+#[test]
+fn an_underpayment_is_refused() {
+    let reference = generate_payment_reference();
+    let signature = pay(
+        reference,
+        Paid::Sol {
+            to: shop(),
+            lamports: 249_999_999,
+        },
+        true,
+    );
+    match wait(reference, None, 250_000_000) {
+        Err(SssError::TokenError(message)) => assert_eq!(
+            message,
+            format!(
+                "Payment {} for reference {} is short: received 249999999, expected 250000000",
+                signature, reference
+            )
+        ),
+        other => panic!("an underpayment was accepted: {:?}", other),
+    }
+
+    let reference = generate_payment_reference();
+    let mint = usdc();
+    pay(
+        reference,
+        Paid::Token {
+            to: shop(),
+            mint,
+            amount: 4_989_999,
+        },
+        true,
+    );
+    assert!(matches!(
+        wait(reference, Some(mint), 4_990_000),
+        Err(SssError::TokenError(_))
+    ));
+}
+
+#[test]
+fn only_successful_payments_to_the_shop_in_the_requested_currency_count() {
+    let reference = generate_payment_reference();
+    let mint = usdc();
+    // A failed attempt, a payment to someone else and one in the wrong token come first
+    pay(
+        reference,
+        Paid::Token {
+            to: shop(),
+            mint,
+            amount: 4_990_000,
+        },
+        false,
+    );
+    pay(
+        reference,
+        Paid::Token {
+            to: Pubkey::new_unique(),
+            mint,
+            amount: 4_990_000,
+        },
+        true,
+    );
+    pay(
+        reference,
+        Paid::Token {
+            to: shop(),
+            mint: usdc(),
+            amount: 4_990_000,
+        },
+        true,
+    );
+    match wait(reference, Some(mint), 4_990_000) {
+        Err(SssError::RpcError(message)) => assert_eq!(
+            message,
+            format!("No payment for reference {} arrived within 0ns", reference)
+        ),
+        other => panic!("a payment that is not the shop's was accepted: {:?}", other),
+    }
+
+    // The first payment that does count decides, even when a later one is larger
+    let paid = pay(
+        reference,
+        Paid::Token {
+            to: shop(),
+            mint,
+            amount: 1_000_000,
+        },
+        true,
+    );
+    pay(
+        reference,
+        Paid::Token {
+            to: shop(),
+            mint,
+            amount: 4_990_000,
+        },
+        true,
+    );
+    match wait(reference, Some(mint), 4_990_000) {
+        Err(SssError::TokenError(message)) => {
+            assert!(
+                message.starts_with(&format!("Payment {} ", paid)),
+                "{}",
+                message
+            )
+        }
+        other => panic!("a later payment made up for a short one: {:?}", other),
+    }
+}
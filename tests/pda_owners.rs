@@ -0,0 +1,294 @@
+//! Owners that are program-derived addresses and hold a mint in two token accounts
+//!
+//! Balances and recipient checks use the global client, so the mock backend is served as
+//! `SOLANA_RPC_URL` and shared by the tests of this binary, together with the event sink
+//! collecting the warnings of transfers. Every test works on a vault of its own.
+
+mod common;
+
+use common::{MockBackend, fixed_keypair, mint_account, response, rpc_account, token_account};
+use serde_json::{Value, json};
+use solana_sdk::{pubkey::Pubkey, signer::Signer, transaction::Transaction};
+use spl_associated_token_account::get_associated_token_address;
+use sss_shared::{
+    AmountSpec, RawAmount, ResolutionWarning, SssError, SssEvent, TokenAccountResolution,
+    TransferOptions, TxOptions, check_recipient_with_resolution, get_token_balance, set_event_sink,
+    set_payer, transfer_asset_with_client,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use std::{env, thread};
+
+/// Instruction tag of `TransferChecked`
+const TRANSFER_CHECKED: u8 = 12;
+
+/// The token accounts each owner holds, as `getTokenAccountsByOwner` lists them
+type Holdings = Arc<Mutex<HashMap<Pubkey, Vec<Value>>>>;
+
+static BACKEND: OnceLock<(Arc<MockBackend>, Holdings)> = OnceLock::new();
+
+/// Warnings of transfers, as `(owner, token account, warning)`
+static WARNINGS: Mutex<Vec<(Pubkey, Pubkey, String)>> = Mutex::new(Vec::new());
+
+fn backend() -> (Arc<MockBackend>, Holdings) {
+    let (backend, holdings) = BACKEND.get_or_init(|| {
+        let backend = MockBackend::new();
+        unsafe { env::set_var("SOLANA_RPC_URL", backend.serve()) };
+        set_payer(fixed_keypair(1));
+        set_event_sink(|event| {
+            if let SssEvent::TokenAccountWarning {
+                owner,
+                token_account,
+                warning,
+                ..
+            } = event
+            {
+                WARNINGS
+                    .lock()
+                    .unwrap()
+                    .push((owner, token_account, warning));
+            }
+        });
+        let holdings: Holdings = Arc::default();
+        let listed = Arc::clone(&holdings);
+        backend.on("getTokenAccountsByOwner", move |params| {
+            let owner: Pubkey = params[0].as_str().unwrap().parse().unwrap();
+            let mint = params[1]["mint"].as_str().unwrap();
+            let accounts: Vec<Value> = listed
+                .lock()
+                .unwrap()
+                .get(&owner)
+                .into_iter()
+                .flatten()
+                .filter(|keyed| keyed["mint"] == mint)
+                .map(|keyed| json!({ "pubkey": keyed["pubkey"], "account": keyed["account"] }))
+                .collect();
+            response(Value::Array(accounts))
+        });
+        (backend, holdings)
+    });
+    (Arc::clone(backend), Arc::clone(holdings))
+}
+
+/// Waits for the warnings about `owner`'s accounts, as `(token account, warning)`
+fn warnings_for(owner: &Pubkey) -> Vec<(Pubkey, String)> {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        let warnings: Vec<(Pubkey, String)> = WARNINGS
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(warned, _, _)| warned == owner)
+            .map(|(_, account, warning)| (*account, warning.clone()))
+            .collect();
+        if !warnings.is_empty() {
+            return warnings;
+        }
+        assert!(Instant::now() < deadline, "the sink never caught up");
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+/// A custodial vault of a customer, holding a new mint in two accounts its program created
+struct Vault {
+    mint: Pubkey,
+    owner: Pubkey,
+    smaller: Pubkey,
+    larger: Pubkey,
+}
+
+impl Vault {
+    fn new(customer: u8) -> Self {
+        let (backend, holdings) = backend();
+        let program = Pubkey::new_unique();
+        let (owner, _) = Pubkey::find_program_address(&[b"vault", &[customer]], &program);
+        assert!(!owner.is_on_curve());
+        let mint = Pubkey::new_unique();
+        backend.set_account(mint, mint_account(Some(Pubkey::new_unique()), 1_000, 0));
+        let vault = Self {
+            mint,
+            owner,
+            smaller: Pubkey::new_unique(),
+            larger: Pubkey::new_unique(),
+        };
+        // A holding of another mint is never a candidate
+        let other_mint = Pubkey::new_unique();
+        let mut listed = Vec::new();
+        for (address, mint, amount) in [
+            (vault.smaller, mint, 40),
+            (Pubkey::new_unique(), other_mint, 500),
+            (vault.larger, mint, 60),
+        ] {
+            let account = token_account(&mint, &owner, amount);
+            listed.push(json!({
+                "pubkey": address.to_string(),
+                "mint": mint.to_string(),
+                "account": rpc_account(Some(&account)),
+            }));
+            backend.set_account(address, account);
+        }
+        holdings.lock().unwrap().insert(owner, listed);
+        vault
+    }
+
+    fn ata(&self) -> Pubkey {
+        get_associated_token_address(&self.owner, &self.mint)
+    }
+}
+
+#[test]
+fn scanning_the_owner_finds_the_larger_of_two_accounts() {
+    let vault = Vault::new(1);
+    let balance =
+        get_token_balance(vault.mint, vault.owner, &TokenAccountResolution::ScanOwner).unwrap();
+    assert_eq!(balance.token_account, vault.larger);
+    assert!(balance.account_exists);
+    assert_eq!(balance.balance, 60);
+    assert_eq!(
+        balance.warnings,
+        vec![ResolutionWarning::MultipleAccounts {
+            chosen: vault.larger,
+            others: vec![vault.smaller],
+        }]
+    );
+    assert_eq!(
+        balance.warnings[0].to_string(),
+        format!(
+            "The owner holds the mint in 2 token accounts; used {}, the largest",
+            vault.larger
+        )
+    );
+
+    let status = check_recipient_with_resolution(
+        vault.mint,
+        vault.owner,
+        &TokenAccountResolution::ScanOwner,
+        &TxOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        (
+            status.token_account,
+            status.balance,
+            status.creation_lamports
+        ),
+        (vault.larger, 60, 0)
+    );
+    assert_eq!(status.warnings, balance.warnings);
+}
+
+#[test]
+fn the_associated_account_of_a_pda_is_flagged() {
+    let vault = Vault::new(2);
+    let expected = vec![ResolutionWarning::OffCurveOwner {
+        owner: vault.owner,
+        token_account: vault.ata(),
+    }];
+
+    // Nothing is held there, and the caller is told why that may be misleading
+    let balance = get_token_balance(vault.mint, vault.owner, &TokenAccountResolution::Ata).unwrap();
+    assert_eq!(balance.token_account, vault.ata());
+    assert!(!balance.account_exists);
+    assert_eq!(balance.balance, 0);
+    assert_eq!(balance.warnings, expected);
+
+    let status = check_recipient_with_resolution(
+        vault.mint,
+        vault.owner,
+        &TokenAccountResolution::Ata,
+        &TxOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(status.token_account, vault.ata());
+    assert!(!status.account_exists);
+    assert_eq!(status.warnings, expected);
+}
+
+#[test]
+fn an_explicit_account_is_used_as_given_once_checked() {
+    let vault = Vault::new(3);
+    let balance = get_token_balance(
+        vault.mint,
+        vault.owner,
+        &TokenAccountResolution::ExplicitAccount(vault.smaller),
+    )
+    .unwrap();
+    assert_eq!(
+        (balance.token_account, balance.balance),
+        (vault.smaller, 40)
+    );
+    assert!(balance.warnings.is_empty());
+
+    // An account of another vault is refused rather than read as this one's
+    let other = Vault::new(4);
+    match get_token_balance(
+        vault.mint,
+        vault.owner,
+        &TokenAccountResolution::ExplicitAccount(other.larger),
+    ) {
+        Err(SssError::TokenError(message)) => assert_eq!(
+            message,
+            format!(
+                "Token account {} holds mint {}, not {}",
+                other.larger, other.mint, vault.mint
+            )
+        ),
+        other => panic!("read another vault's account: {:?}", other),
+    }
+}
+
+#[test]
+fn a_transfer_to_a_pda_lands_in_the_account_its_program_uses() {
+    let (backend, _) = backend();
+    let vault = Vault::new(5);
+    let payer = fixed_keypair(1).pubkey();
+    backend.set_account(
+        get_associated_token_address(&payer, &vault.mint),
+        token_account(&vault.mint, &payer, 10),
+    );
+
+    transfer_asset_with_client(
+        &backend.client(),
+        vault.mint,
+        vault.owner,
+        &TransferOptions {
+            amount: Some(AmountSpec::Raw(RawAmount(3))),
+            destination: TokenAccountResolution::ScanOwner,
+            ..TransferOptions::default()
+        },
+    )
+    .unwrap();
+    let sent: Vec<Transaction> = backend
+        .sent()
+        .into_iter()
+        .filter(|tx| tx.message.account_keys.contains(&vault.mint))
+        .collect();
+    assert_eq!(sent.len(), 1);
+    let tx = &sent[0];
+    // No associated account is created, and the tokens go to the larger holding
+    assert!(!tx.message.account_keys.contains(&vault.ata()));
+    let transfers: Vec<Pubkey> = tx
+        .message
+        .instructions
+        .iter()
+        .filter(|ix| ix.data.first() == Some(&TRANSFER_CHECKED))
+        .map(|ix| tx.message.account_keys[ix.accounts[2] as usize])
+        .collect();
+    assert_eq!(transfers, vec![vault.larger]);
+
+    // Sending only returns a signature, so the ambiguity is reported as an event, delivered
+    // on the sink's own thread
+    let warnings = warnings_for(&vault.owner);
+    assert_eq!(
+        warnings,
+        vec![(
+            vault.larger,
+            ResolutionWarning::MultipleAccounts {
+                chosen: vault.larger,
+                others: vec![vault.smaller],
+            }
+            .to_string()
+        )]
+    );
+}
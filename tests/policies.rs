@@ -0,0 +1,239 @@
+//! The policy files are swapped while a mint is in flight, without dropping it
+//!
+//! The policy file paths, strict mode and the event sink are process-wide, so this test
+//! binary keeps them to itself and the tests take turns, each starting from its own files.
+
+mod common;
+
+use common::{MockBackend, fixed_keypair, mint_account};
+use serde_json::{Value, json};
+use solana_sdk::{pubkey::Pubkey, signer::Signer};
+use sss_shared::{
+    AmountSpec, MintOptions, RawAmount, SssError, SupplyCapChange, is_trusted_mint,
+    mint_token_detailed_with_client, reload_policy_files, set_event_sink, set_payer,
+    set_strict_mode, supply_cap,
+};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant};
+use std::{env, fs, thread};
+
+static SERIAL: Mutex<()> = Mutex::new(());
+
+/// The trusted mints and supply caps files
+static FILES: OnceLock<(PathBuf, PathBuf)> = OnceLock::new();
+
+type Seen = Arc<Mutex<Vec<Value>>>;
+
+/// Points the policy paths at fresh files listing `trusted` and `caps`, and applies them
+fn policies(trusted: &[Pubkey], caps: &[(Pubkey, u64)]) -> (MutexGuard<'static, ()>, Seen) {
+    let serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    FILES.get_or_init(|| {
+        let dir = env::temp_dir().join(format!("sss-policies-{}", rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        let files = (dir.join("trusted.json"), dir.join("caps.json"));
+        unsafe {
+            env::set_var("SSS_TRUSTED_MINTS_PATH", &files.0);
+            env::set_var("SSS_SUPPLY_CAPS_PATH", &files.1);
+        }
+        files
+    });
+    set_payer(fixed_keypair(1));
+    set_strict_mode(true);
+    write_files(trusted, caps);
+    reload_policy_files().unwrap();
+    let seen: Seen = Arc::default();
+    let sink = Arc::clone(&seen);
+    set_event_sink(move |event| {
+        let event = serde_json::to_value(event).unwrap();
+        if event["type"].as_str().unwrap().starts_with("polic") {
+            sink.lock().unwrap().push(event);
+        }
+    });
+    (serial, seen)
+}
+
+fn write_files(trusted: &[Pubkey], caps: &[(Pubkey, u64)]) {
+    let (trusted_path, caps_path) = FILES.get().unwrap();
+    let trusted: Vec<String> = trusted.iter().map(Pubkey::to_string).collect();
+    fs::write(trusted_path, json!(trusted).to_string()).unwrap();
+    let caps: serde_json::Map<String, Value> = caps
+        .iter()
+        .map(|(mint, cap)| (mint.to_string(), json!(cap)))
+        .collect();
+    fs::write(caps_path, Value::Object(caps).to_string()).unwrap();
+}
+
+/// A ledger holding mints of the payer with the given supplies
+fn ledger(mints: &[(Pubkey, u64)]) -> Arc<MockBackend> {
+    let backend = MockBackend::new();
+    for (mint, supply) in mints {
+        backend.set_account(
+            *mint,
+            mint_account(Some(fixed_keypair(1).pubkey()), *supply, 0),
+        );
+    }
+    backend
+}
+
+fn mint(backend: &Arc<MockBackend>, mint: Pubkey, amount: u64) -> Result<String, SssError> {
+    mint_token_detailed_with_client(
+        &backend.client(),
+        mint,
+        None,
+        AmountSpec::Raw(RawAmount(amount)),
+        &MintOptions::default(),
+    )
+    .map(|result| result.signature)
+}
+
+/// Holds the first transaction sent until the returned sender is used, telling `sending`
+/// once it arrived
+fn hold_first_send(backend: &MockBackend, sending: Sender<()>) -> Sender<()> {
+    let (release, released) = mpsc::channel::<()>();
+    let held = Mutex::new(Some((sending, released)));
+    backend.on("sendTransaction", move |params| {
+        let first = held.lock().unwrap().take();
+        if let Some((sending, released)) = first {
+            sending.send(()).unwrap();
+            released.recv_timeout(Duration::from_secs(10)).unwrap();
+        }
+        let tx = common::decode_transaction(params);
+        Value::String(tx.signatures[0].to_string())
+    });
+    release
+}
+
+/// Waits for `count` policy events
+fn events(seen: &Seen, count: usize) -> Vec<Value> {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while seen.lock().unwrap().len() < count {
+        assert!(Instant::now() < deadline, "the sink never caught up");
+        thread::sleep(Duration::from_millis(5));
+    }
+    seen.lock().unwrap().drain(..).collect()
+}
+
+fn assert_untrusted(result: Result<String, SssError>, mint: &Pubkey) {
+    match result {
+        Err(SssError::TokenError(message)) => {
+            assert_eq!(message, format!("mint {} is not in the trusted set", mint))
+        }
+        other => panic!("minted an untrusted mint: {:?}", other),
+    }
+}
+
+#[test]
+fn a_new_mint_becomes_trusted_while_a_mint_is_in_flight() {
+    let beans = Pubkey::new_unique();
+    let stamps = Pubkey::new_unique();
+    let (_serial, seen) = policies(&[beans], &[(beans, 100)]);
+    events(&seen, 0);
+    let backend = ledger(&[(beans, 90), (stamps, 0)]);
+    assert_untrusted(mint(&backend, stamps, 1), &stamps);
+
+    // A mint of beans is held on its way to the cluster
+    let (sending, sent) = mpsc::channel();
+    let release = hold_first_send(&backend, sending);
+    let in_flight = {
+        let backend = Arc::clone(&backend);
+        thread::spawn(move || mint(&backend, beans, 5))
+    };
+    sent.recv_timeout(Duration::from_secs(10)).unwrap();
+
+    // Meanwhile stamps are launched with a cap, and the beans cap is raised
+    write_files(&[beans, stamps], &[(beans, 200), (stamps, 50)]);
+    let reload = reload_policy_files().unwrap();
+    assert_eq!(reload.trusted_added, vec![stamps]);
+    assert!(reload.trusted_removed.is_empty());
+    let mut expected_caps = vec![
+        SupplyCapChange {
+            mint: beans,
+            previous: Some(100),
+            cap: Some(200),
+        },
+        SupplyCapChange {
+            mint: stamps,
+            previous: None,
+            cap: Some(50),
+        },
+    ];
+    expected_caps.sort_by_key(|change| change.mint);
+    assert_eq!(reload.caps_changed, expected_caps);
+    assert!(is_trusted_mint(&stamps));
+    assert_eq!(supply_cap(&stamps), Some(50));
+
+    // The new mint can be minted before the held one even lands
+    mint(&backend, stamps, 50).unwrap();
+    release.send(()).unwrap();
+    let signature = in_flight.join().unwrap().unwrap();
+    assert_eq!(backend.sent().len(), 2);
+    assert!(
+        backend
+            .sent()
+            .iter()
+            .any(|tx| tx.signatures[0].to_string() == signature)
+    );
+    // The raised cap lets beans pass the old one
+    backend.set_account(beans, mint_account(Some(fixed_keypair(1).pubkey()), 95, 0));
+    mint(&backend, beans, 10).unwrap();
+
+    let reloaded = events(&seen, 1);
+    assert_eq!(
+        reloaded,
+        vec![json!({
+            "type": "policies_reloaded",
+            "trusted_added": [stamps.to_string()],
+            "trusted_removed": [],
+            "caps_changed": serde_json::to_value(&expected_caps).unwrap(),
+            "roles_changed": [],
+        })]
+    );
+}
+
+#[test]
+fn a_malformed_file_keeps_the_previous_policies() {
+    let beans = Pubkey::new_unique();
+    let stamps = Pubkey::new_unique();
+    let (_serial, seen) = policies(&[beans], &[(beans, 100)]);
+    events(&seen, 0);
+    let backend = ledger(&[(beans, 0), (stamps, 0)]);
+
+    // A truncated trusted mints file leaves both lists as they were
+    let (trusted_path, caps_path) = FILES.get().unwrap();
+    fs::write(trusted_path, format!("[\"{}\", \"{}", beans, stamps)).unwrap();
+    fs::write(caps_path, json!({ stamps.to_string(): 10 }).to_string()).unwrap();
+    match reload_policy_files() {
+        Err(SssError::ConfigError(message)) => assert!(message.contains("trusted"), "{}", message),
+        other => panic!("a malformed file was applied: {:?}", other),
+    }
+    assert!(is_trusted_mint(&beans));
+    assert!(!is_trusted_mint(&stamps));
+    assert_eq!((supply_cap(&beans), supply_cap(&stamps)), (Some(100), None));
+    mint(&backend, beans, 1).unwrap();
+    assert_untrusted(mint(&backend, stamps, 1), &stamps);
+
+    // So does a malformed caps file next to a valid trusted mints file
+    write_files(&[beans, stamps], &[]);
+    fs::write(caps_path, "{ \"not a mint\": -1 }").unwrap();
+    assert!(matches!(
+        reload_policy_files(),
+        Err(SssError::ConfigError(_))
+    ));
+    assert!(!is_trusted_mint(&stamps));
+    assert_eq!(supply_cap(&beans), Some(100));
+
+    let failed = events(&seen, 2);
+    assert_eq!(failed.len(), 2);
+    for event in failed {
+        assert_eq!(event["type"], "policy_reload_failed");
+        assert!(!event["error"].as_str().unwrap().is_empty());
+    }
+
+    // Once fixed, the files apply
+    write_files(&[beans, stamps], &[(beans, 100)]);
+    let reload = reload_policy_files().unwrap();
+    assert_eq!(reload.trusted_added, vec![stamps]);
+    mint(&backend, stamps, 1).unwrap();
+}
@@ -0,0 +1,127 @@
+//! Two brands minting side by side each sign with their own profile's payer
+//!
+//! Profiles and the global payer are process-wide, so this test binary keeps them to itself.
+
+mod common;
+
+use common::{MockBackend, fixed_keypair, mint_account};
+use solana_sdk::{pubkey::Pubkey, signer::Signer, transaction::Transaction};
+use sss_shared::{
+    AmountSpec, MintOptions, ProfileConfig, RawAmount, SssError, active_profile,
+    mint_token_detailed_with_client, register_profile, select_profile, set_payer, with_profile,
+};
+use std::sync::{Arc, Barrier};
+use std::thread;
+
+const MINTS_PER_BRAND: u64 = 10;
+
+fn mint(backend: &Arc<MockBackend>, mint: Pubkey, amount: u64) -> Result<String, SssError> {
+    mint_token_detailed_with_client(
+        &backend.client(),
+        mint,
+        None,
+        AmountSpec::Raw(RawAmount(amount)),
+        &MintOptions::default(),
+    )
+    .map(|result| result.signature)
+}
+
+/// The amount a mint transaction mints, from its `MintTo` instruction
+fn minted_amount(tx: &Transaction) -> u64 {
+    tx.message
+        .instructions
+        .iter()
+        .find(|ix| {
+            tx.message.account_keys[ix.program_id_index as usize] == spl_token::id()
+                && ix.data.first() == Some(&7)
+        })
+        .map(|ix| u64::from_le_bytes(ix.data[1..9].try_into().unwrap()))
+        .expect("no MintTo instruction")
+}
+
+#[test]
+fn each_profile_signs_with_its_own_payer() {
+    set_payer(fixed_keypair(1));
+    let brands = [
+        ("brand-a", fixed_keypair(2), 0),
+        ("brand-b", fixed_keypair(3), 100),
+    ];
+    let backend = MockBackend::new();
+    let mut mints = Vec::new();
+    for (name, payer, _) in &brands {
+        let mint = Pubkey::new_unique();
+        backend.set_account(mint, mint_account(Some(payer.pubkey()), 0, 0));
+        register_profile(*name, ProfileConfig::new(payer.insecure_clone()));
+        mints.push(mint);
+    }
+
+    // Both brands mint at the same time, each on its own thread
+    let start = Arc::new(Barrier::new(brands.len()));
+    let workers: Vec<_> = brands
+        .iter()
+        .zip(&mints)
+        .map(|(&(name, _, offset), &mint)| {
+            let backend = Arc::clone(&backend);
+            let start = Arc::clone(&start);
+            thread::spawn(move || {
+                with_profile(name, || {
+                    start.wait();
+                    for amount in 1..=MINTS_PER_BRAND {
+                        self::mint(&backend, mint, offset + amount)?;
+                    }
+                    Ok(())
+                })
+                .unwrap();
+                // The profile is not left selected once the work is done
+                assert_eq!(active_profile(), None);
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    let sent = backend.sent();
+    assert_eq!(sent.len(), brands.len() * MINTS_PER_BRAND as usize);
+    for tx in &sent {
+        let amount = minted_amount(tx);
+        let (name, payer, _) = &brands[usize::from(amount > 100)];
+        assert_eq!(
+            tx.message.account_keys[0],
+            payer.pubkey(),
+            "{} paid with another payer",
+            name
+        );
+        assert_eq!(tx.signatures.len(), 1);
+        assert!(tx.verify().is_ok());
+        assert!(!tx.message.account_keys.contains(&fixed_keypair(1).pubkey()));
+    }
+
+    // Without a profile, the global payer signs
+    let global_mint = Pubkey::new_unique();
+    backend.set_account(
+        global_mint,
+        mint_account(Some(fixed_keypair(1).pubkey()), 0, 0),
+    );
+    mint(&backend, global_mint, 1).unwrap();
+    let tx = backend.sent().pop().unwrap();
+    assert_eq!(tx.message.account_keys[0], fixed_keypair(1).pubkey());
+
+    // A missing profile is refused and keeps the current selection
+    select_profile(Some("brand-b")).unwrap();
+    match select_profile(Some("brand-c")) {
+        Err(SssError::ConfigError(message)) => {
+            assert_eq!(message, "Profile 'brand-c' is not registered")
+        }
+        other => panic!("selected a missing profile: {:?}", other),
+    }
+    assert_eq!(active_profile().as_deref(), Some("brand-b"));
+    mint(&backend, mints[1], 150).unwrap();
+    let tx = backend.sent().pop().unwrap();
+    assert_eq!(tx.message.account_keys[0], fixed_keypair(3).pubkey());
+    assert!(matches!(
+        with_profile("brand-c", || Ok(())),
+        Err(SssError::ConfigError(_))
+    ));
+    select_profile(None).unwrap();
+}
@@ -0,0 +1,317 @@
+//! Every mutation entry point refuses to run in read-only mode
+//!
+//! Read-only mode is process-wide, so this test binary only holds tests run with it on.
+
+mod common;
+
+use common::{MockBackend, fixed_keypair};
+use solana_sdk::{message::Message, signer::Signer, transaction::Transaction};
+use sss_shared::{
+    AuthorityKinds, ClaimOptions, CreatedAccount, CreatedAccounts, DateTime, DustDestination,
+    LegacyMigration, MintOptions, READ_ONLY_MESSAGE, RawAmount, ReviewPolicy, SssError, SssResult,
+    TokenBuilder, TokenProgram, TransferOptions, Utc, attach_metadata_to_existing_mint,
+    attach_metadata_to_existing_mint_with_client, build_claim_transaction,
+    build_claim_transaction_with_client, build_mint_ix, burn_tokens, consolidate_balances,
+    create_claim_link, create_claim_link_with_client, create_new_token, fund_vault,
+    get_payer_pubkey, migrate_legacy_mints, migrate_legacy_mints_with_client, mint_basket,
+    mint_token, mint_token_detailed_with_client, mint_token_idempotent, mint_token_to_account,
+    reclaim_claim_link, reclaim_claim_link_with_client, redeem_claim_link,
+    redeem_claim_link_with_client, resume_create_with_client, review_external_transaction,
+    rollback_created_accounts_with_client, rotate_authorities, rotate_authorities_with_client,
+    set_payer, set_read_only, submit_claim, submit_claim_with_client, sweep_dust, transfer_asset,
+    transfer_asset_with_client, transfer_asset_with_options, verify_creator, withdraw_from_vault,
+};
+use std::env;
+
+fn read_only() {
+    set_payer(fixed_keypair(1));
+    set_read_only(true);
+}
+
+fn assert_refused<T: std::fmt::Debug>(entry_point: &str, result: SssResult<T>) {
+    match result {
+        Err(SssError::ConfigError(message)) if message == READ_ONLY_MESSAGE => {}
+        other => panic!("{} was not refused: {:?}", entry_point, other),
+    }
+}
+
+#[test]
+fn every_mutation_entry_point_is_refused() {
+    read_only();
+    let payer = fixed_keypair(1).pubkey();
+    let mint = fixed_keypair(2).pubkey();
+    let owner = fixed_keypair(3).pubkey();
+    let expiry: DateTime<Utc> = DateTime::from_timestamp(4_102_444_800, 0).unwrap();
+    let checkpoint = env::temp_dir().join(format!("sss-read-only-{}", rand::random::<u64>()));
+    let external = Transaction::new_unsigned(Message::new(
+        &build_mint_ix(&owner, &mint, &payer, 1, &MintOptions::default()).unwrap(),
+        Some(&payer),
+    ));
+    let external = bincode::serialize(&external).unwrap();
+
+    assert_refused(
+        "create_new_token",
+        create_new_token("https://example.com/t.json", "T", 0),
+    );
+    assert_refused(
+        "TokenBuilder::create_and_mint",
+        TokenBuilder::new()
+            .name("T")
+            .uri("https://example.com/t.json")
+            .create_and_mint(10, None),
+    );
+    assert_refused(
+        "attach_metadata_to_existing_mint",
+        attach_metadata_to_existing_mint(mint, "https://example.com/t.json", "T", "T"),
+    );
+    assert_refused("mint_token", mint_token(mint, Some(owner), RawAmount(1)));
+    assert_refused(
+        "mint_token_idempotent",
+        mint_token_idempotent(mint, None, RawAmount(1), "key"),
+    );
+    assert_refused(
+        "mint_token_to_account",
+        mint_token_to_account(mint, owner, RawAmount(1)),
+    );
+    assert_refused("mint_basket", mint_basket(&[(mint, 1)], owner));
+    assert_refused("transfer_asset", transfer_asset(mint, owner));
+    assert_refused(
+        "transfer_asset_with_options",
+        transfer_asset_with_options(mint, owner, &TransferOptions::default()),
+    );
+    assert_refused("burn_tokens", burn_tokens(mint, RawAmount(1)));
+    assert_refused("fund_vault", fund_vault("partner", mint, 1));
+    assert_refused(
+        "withdraw_from_vault",
+        withdraw_from_vault("partner", mint, owner, 1),
+    );
+    assert_refused("create_claim_link", create_claim_link(mint, 1, expiry));
+    assert_refused("redeem_claim_link", redeem_claim_link("payload", owner));
+    assert_refused("reclaim_claim_link", reclaim_claim_link(owner));
+    assert_refused(
+        "build_claim_transaction",
+        build_claim_transaction(mint, owner, 1),
+    );
+    assert_refused("submit_claim", submit_claim(&[]));
+    assert_refused(
+        "execute_airdrop",
+        sss_shared::execute_airdrop(mint, &[(owner, 1)], &checkpoint),
+    );
+    assert_refused(
+        "rotate_authorities",
+        rotate_authorities(&[mint], owner, AuthorityKinds::default()),
+    );
+    assert_refused("sweep_dust", sweep_dust(mint, 1, DustDestination::Burn));
+    assert_refused(
+        "consolidate_balances",
+        consolidate_balances(payer, owner, None, 0, false),
+    );
+    #[cfg(feature = "http-metadata")]
+    assert_refused(
+        "swap_metadata",
+        sss_shared::swap_metadata(mint, sss_shared::OffchainMetadata::default()),
+    );
+    assert_refused("verify_creator", verify_creator(mint));
+    assert_refused(
+        "review_external_transaction",
+        review_external_transaction(&external, ReviewPolicy::default()),
+    );
+    assert_refused(
+        "migrate_legacy_mints",
+        migrate_legacy_mints(
+            &[LegacyMigration {
+                mint,
+                ..LegacyMigration::default()
+            }],
+            &checkpoint,
+        ),
+    );
+    #[cfg(feature = "dev-tools")]
+    {
+        assert_refused(
+            "burn_all_assets",
+            sss_shared::burn_all_assets(&sss_shared::AssetFilter::default(), false),
+        );
+        assert_refused(
+            "faucet_grant",
+            sss_shared::faucet_grant(
+                mint,
+                owner,
+                1,
+                sss_shared::FaucetPolicy {
+                    per_recipient_daily: 10,
+                    global_daily: 10,
+                },
+            ),
+        );
+    }
+    assert!(!checkpoint.exists());
+}
+
+#[test]
+fn refused_operations_make_no_rpc_call() {
+    read_only();
+    let backend = MockBackend::new();
+    let client = backend.client();
+    let mint = fixed_keypair(2).pubkey();
+
+    assert_refused(
+        "mint_token_detailed_with_client",
+        mint_token_detailed_with_client(&client, mint, None, RawAmount(1), &MintOptions::default()),
+    );
+    assert_refused(
+        "transfer_asset_with_client",
+        transfer_asset_with_client(&client, mint, mint, &TransferOptions::default()),
+    );
+    assert_refused(
+        "attach_metadata_to_existing_mint_with_client",
+        attach_metadata_to_existing_mint_with_client(
+            &client,
+            mint,
+            "https://example.com/t.json",
+            "T",
+            "T",
+        ),
+    );
+    assert_refused(
+        "TokenBuilder::create_with_client",
+        TokenBuilder::new()
+            .name("T")
+            .uri("https://example.com/t.json")
+            .create_with_client(&client, &fixed_keypair(4)),
+    );
+    assert_refused(
+        "TokenBuilder::create_and_mint_with_client",
+        TokenBuilder::new()
+            .name("T")
+            .uri("https://example.com/t.json")
+            .create_and_mint_with_client(&client, &fixed_keypair(4), 1, None),
+    );
+    assert_refused(
+        "build_claim_transaction_with_client",
+        build_claim_transaction_with_client(&client, mint, mint, 1, &ClaimOptions::default()),
+    );
+    assert_refused(
+        "submit_claim_with_client",
+        submit_claim_with_client(&client, &[]),
+    );
+    assert_refused(
+        "create_claim_link_with_client",
+        create_claim_link_with_client(&client, mint, 1, DateTime::<Utc>::MAX_UTC),
+    );
+    assert_refused(
+        "redeem_claim_link_with_client",
+        redeem_claim_link_with_client(&client, "payload", mint),
+    );
+    assert_refused(
+        "reclaim_claim_link_with_client",
+        reclaim_claim_link_with_client(&client, mint),
+    );
+    assert_refused(
+        "rollback_created_accounts_with_client",
+        rollback_created_accounts_with_client(
+            &client,
+            &CreatedAccounts {
+                funder: fixed_keypair(1).pubkey(),
+                accounts: vec![CreatedAccount {
+                    address: fixed_keypair(5).pubkey(),
+                    mint,
+                    owner: fixed_keypair(1).pubkey(),
+                    token_program: TokenProgram::Legacy,
+                }],
+            },
+        ),
+    );
+    #[cfg(feature = "dev-tools")]
+    assert_refused(
+        "faucet_grant_with_client",
+        sss_shared::faucet_grant_with_client(
+            &client,
+            mint,
+            fixed_keypair(3).pubkey(),
+            1,
+            sss_shared::FaucetPolicy {
+                per_recipient_daily: 10,
+                global_daily: 10,
+            },
+        ),
+    );
+    assert_refused(
+        "rotate_authorities_with_client",
+        rotate_authorities_with_client(
+            &client,
+            &[mint],
+            fixed_keypair(3).pubkey(),
+            AuthorityKinds::default(),
+        ),
+    );
+    assert_refused(
+        "migrate_legacy_mints_with_client",
+        migrate_legacy_mints_with_client(
+            &client,
+            &[LegacyMigration {
+                mint,
+                ..LegacyMigration::default()
+            }],
+            &env::temp_dir().join(format!("sss-read-only-{}", rand::random::<u64>())),
+        ),
+    );
+    #[cfg(feature = "http-metadata")]
+    assert_refused(
+        "swap_metadata_with_client",
+        sss_shared::swap_metadata_with_client(
+            &client,
+            mint,
+            sss_shared::OffchainMetadata::default(),
+        ),
+    );
+    assert!(backend.sent().is_empty());
+    assert_eq!(backend.call_count("getMultipleAccounts"), 0);
+    assert_eq!(backend.call_count("getAccountInfo"), 0);
+    assert_eq!(backend.call_count("getLatestBlockhash"), 0);
+}
+
+#[test]
+fn reads_keep_working() {
+    read_only();
+    let backend = MockBackend::new();
+    let client = backend.client();
+    let mint = fixed_keypair(2).pubkey();
+
+    assert_eq!(get_payer_pubkey().unwrap(), fixed_keypair(1).pubkey());
+    let signature = solana_sdk::signature::Signature::from([7; 64]).to_string();
+    assert!(resume_create_with_client(&client, mint, &signature).is_ok());
+    assert!(
+        build_mint_ix(
+            &fixed_keypair(1).pubkey(),
+            &mint,
+            &mint,
+            1,
+            &MintOptions::default()
+        )
+        .is_ok()
+    );
+}
+
+#[cfg(feature = "ffi")]
+#[test]
+fn ffi_calls_return_the_read_only_code() {
+    use sss_shared::{SSS_ERR_READ_ONLY, mint_token_ffi, sss_set_read_only};
+    use std::ffi::CString;
+    use std::os::raw::c_char;
+
+    read_only();
+    assert_eq!(sss_set_read_only(1), 0);
+    let mint = CString::new(fixed_keypair(2).pubkey().to_string()).unwrap();
+    let mut signature = [0 as c_char; 128];
+    let code = unsafe {
+        mint_token_ffi(
+            mint.as_ptr(),
+            std::ptr::null(),
+            1,
+            signature.as_mut_ptr(),
+            128,
+        )
+    };
+    assert_eq!(code, SSS_ERR_READ_ONLY);
+}
@@ -0,0 +1,167 @@
+//! Every payer signature is checked against the policy of the role making it
+//!
+//! Role policies are process-wide, so this test binary keeps them to itself. Each role signs
+//! with its own key, and the default payer is never restricted.
+
+mod common;
+
+use common::{MockBackend, fixed_keypair, mint_account, token_account};
+use serde_json::json;
+use solana_sdk::{pubkey::Pubkey, signature::Signature, signer::Signer};
+use spl_associated_token_account::get_associated_token_address;
+use sss_shared::{
+    JournalOperation, MintOptions, Permission, ProfileConfig, RawAmount, RolePolicy, SigningDomain,
+    SssError, SssResult, TransferOptions, mint_token_detailed_with_client, register_profile,
+    set_payer, set_role_policy, sign_message, sign_structured, transfer_asset_with_client,
+    verify_message, verify_structured, with_profile,
+};
+use std::str::FromStr;
+
+/// May only burn, so it can sign none of the operations below
+const DENIED: &str = "burner";
+/// May mint, transfer and sign messages and structured data
+const ALLOWED: &str = "cashier";
+
+fn roles() {
+    set_payer(fixed_keypair(1));
+    register_profile(DENIED, ProfileConfig::new(fixed_keypair(2)));
+    register_profile(ALLOWED, ProfileConfig::new(fixed_keypair(3)));
+    set_role_policy(
+        DENIED,
+        RolePolicy {
+            permissions: vec![Permission::operation(JournalOperation::BurnTokens)],
+        },
+    )
+    .unwrap();
+    set_role_policy(
+        ALLOWED,
+        RolePolicy {
+            permissions: [
+                JournalOperation::MintToken,
+                JournalOperation::TransferAsset,
+                JournalOperation::SignMessage,
+                JournalOperation::SignStructured,
+            ]
+            .into_iter()
+            .map(Permission::operation)
+            .collect(),
+        },
+    )
+    .unwrap();
+}
+
+fn domain() -> SigningDomain {
+    SigningDomain {
+        name: "LD coupons".to_string(),
+        version: "1".to_string(),
+        cluster: None,
+        salt: None,
+    }
+}
+
+/// A mint the role's payer may mint, and an account of it holding 10 tokens
+fn ledger(role: u8) -> (std::sync::Arc<MockBackend>, Pubkey) {
+    let payer = fixed_keypair(role).pubkey();
+    let backend = MockBackend::new();
+    let mint = Pubkey::new_unique();
+    backend.set_account(mint, mint_account(Some(payer), 10, 0));
+    let source = get_associated_token_address(&payer, &mint);
+    backend.set_account(source, token_account(&mint, &payer, 10));
+    (backend, mint)
+}
+
+fn assert_denied<T: std::fmt::Debug>(capability: &str, result: SssResult<T>) {
+    match result {
+        Err(SssError::ConfigError(message)) => assert!(
+            message.contains(&format!("'{}' capability", capability)),
+            "{}",
+            message
+        ),
+        other => panic!("{} was not refused: {:?}", capability, other),
+    }
+}
+
+#[test]
+fn a_denied_role_signs_nothing() {
+    roles();
+    let (backend, mint) = ledger(2);
+    let client = backend.client();
+    let recipient = Pubkey::new_unique();
+
+    with_profile(DENIED, || {
+        let minted = mint_token_detailed_with_client(
+            &client,
+            mint,
+            None,
+            RawAmount(1),
+            &MintOptions::default(),
+        );
+        assert_denied("mint_token", minted);
+        let options = TransferOptions::default();
+        assert_denied(
+            "transfer_asset",
+            transfer_asset_with_client(&client, mint, recipient, &options),
+        );
+        assert_denied("sign_message", sign_message(b"redeem coupon SPRING-10"));
+        let coupon = json!({"coupon": "SPRING-10"});
+        assert_denied("sign_structured", sign_structured(&domain(), &coupon));
+        Ok(())
+    })
+    .unwrap();
+    assert!(backend.sent().is_empty());
+    assert_eq!(backend.call_count("sendTransaction"), 0);
+}
+
+#[test]
+fn an_allowed_role_signs_with_its_own_key() {
+    roles();
+    let payer = fixed_keypair(3).pubkey();
+    let (backend, mint) = ledger(3);
+    let client = backend.client();
+    let recipient = Pubkey::new_unique();
+
+    with_profile(ALLOWED, || {
+        mint_token_detailed_with_client(&client, mint, None, RawAmount(1), &MintOptions::default())
+            .unwrap();
+        transfer_asset_with_client(&client, mint, recipient, &TransferOptions::default()).unwrap();
+
+        let signature = sign_message(b"redeem coupon SPRING-10").unwrap();
+        assert!(verify_message(
+            &payer,
+            b"redeem coupon SPRING-10",
+            &signature
+        ));
+
+        let coupon = json!({"coupon": "SPRING-10"});
+        let signed = sign_structured(&domain(), &coupon).unwrap();
+        assert_eq!(signed.signer, payer);
+        let signature = Signature::from_str(&signed.signature).unwrap();
+        let payload = coupon.to_string();
+        assert!(verify_structured(&domain(), &payload, &signature, &payer).unwrap());
+        Ok(())
+    })
+    .unwrap();
+
+    let sent = backend.sent();
+    assert_eq!(sent.len(), 2);
+    for tx in &sent {
+        assert_eq!(tx.message.account_keys[0], payer);
+        assert!(tx.verify().is_ok());
+    }
+}
+
+#[test]
+fn the_default_payer_is_not_restricted() {
+    roles();
+    let (backend, mint) = ledger(1);
+    mint_token_detailed_with_client(
+        &backend.client(),
+        mint,
+        None,
+        RawAmount(1),
+        &MintOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(backend.sent().len(), 1);
+    assert!(sign_message(b"hello").is_ok());
+}
@@ -0,0 +1,481 @@
+//! Accounts created by a flow that failed later on are closed only while empty
+//!
+//! The payer is process-wide, so this test binary sets it once for all its tests.
+
+mod common;
+
+use common::{MockBackend, fixed_keypair};
+use solana_program::program_pack::Pack;
+use solana_sdk::transaction::Transaction;
+use solana_sdk::{account::Account, instruction::Instruction, pubkey::Pubkey, signer::Signer};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_token::instruction::TokenInstruction;
+use spl_token::state::AccountState;
+use sss_shared::{
+    AccountRollback, CreatedAccount, CreatedAccounts, CreatorShare, RollbackOutcome, RollbackSkip,
+    SssError, TokenBuilder, TokenProgram, TxOptions, rollback_created_accounts_with_client,
+    set_payer,
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+fn payer() -> Pubkey {
+    set_payer(fixed_keypair(1));
+    fixed_keypair(1).pubkey()
+}
+
+/// Builds a legacy token account in the given state, closable by `close_authority` if set
+fn token_account(
+    mint: &Pubkey,
+    owner: &Pubkey,
+    amount: u64,
+    state: AccountState,
+    close_authority: Option<Pubkey>,
+) -> Account {
+    let account = spl_token::state::Account {
+        mint: *mint,
+        owner: *owner,
+        amount,
+        state,
+        close_authority: close_authority.into(),
+        ..Default::default()
+    };
+    let mut data = vec![0; spl_token::state::Account::LEN];
+    spl_token::state::Account::pack(account, &mut data).unwrap();
+    Account {
+        lamports: 2_039_280,
+        data,
+        owner: spl_token::id(),
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+/// The memo program, whose instructions carry arbitrary bytes
+fn spl_memo_id() -> Pubkey {
+    "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr"
+        .parse()
+        .unwrap()
+}
+
+fn created(address: Pubkey, mint: Pubkey, owner: Pubkey) -> CreatedAccount {
+    CreatedAccount {
+        address,
+        mint,
+        owner,
+        token_program: TokenProgram::Legacy,
+    }
+}
+
+/// Returns the reason an account was skipped, or `None` if it was closed
+fn skipped(rollback: &AccountRollback) -> Option<&RollbackSkip> {
+    match &rollback.outcome {
+        RollbackOutcome::Closed(_) => None,
+        RollbackOutcome::Skipped(reason) => Some(reason),
+    }
+}
+
+/// Returns the token instructions of every sent transaction, in order
+fn sent_token_instructions(backend: &MockBackend) -> Vec<TokenInstruction<'static>> {
+    backend
+        .sent()
+        .iter()
+        .flat_map(|tx| {
+            tx.message
+                .instructions
+                .iter()
+                .filter(|ix| {
+                    tx.message.account_keys[ix.program_id_index as usize] == spl_token::id()
+                })
+                .map(|ix| TokenInstruction::unpack(&ix.data).unwrap())
+                .map(|ix| match ix {
+                    TokenInstruction::CloseAccount => TokenInstruction::CloseAccount,
+                    TokenInstruction::ThawAccount => TokenInstruction::ThawAccount,
+                    other => panic!("unexpected token instruction {:?}", other),
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+#[test]
+fn empty_account_is_closed_to_the_funder() {
+    let payer = payer();
+    let backend = MockBackend::new();
+    let mint = Pubkey::new_unique();
+    let address = Pubkey::new_unique();
+    backend.set_account(
+        address,
+        token_account(&mint, &payer, 0, AccountState::Initialized, None),
+    );
+
+    let rollbacks = rollback_created_accounts_with_client(
+        &backend.client(),
+        &CreatedAccounts {
+            funder: payer,
+            accounts: vec![created(address, mint, payer)],
+        },
+    )
+    .unwrap();
+
+    let sent = backend.sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(
+        rollbacks,
+        vec![AccountRollback {
+            address,
+            outcome: RollbackOutcome::Closed(sent[0].signatures[0].to_string()),
+        }]
+    );
+    let close = &sent[0].message.instructions[0];
+    let keys = &sent[0].message.account_keys;
+    assert_eq!(keys[close.accounts[0] as usize], address);
+    assert_eq!(keys[close.accounts[1] as usize], payer);
+    assert!(matches!(
+        sent_token_instructions(&backend)[..],
+        [TokenInstruction::CloseAccount]
+    ));
+}
+
+#[test]
+fn account_that_received_tokens_is_never_closed() {
+    let payer = payer();
+    let backend = MockBackend::new();
+    let mint = Pubkey::new_unique();
+    let funded = Pubkey::new_unique();
+    let empty = Pubkey::new_unique();
+    backend.set_account(
+        funded,
+        token_account(&mint, &payer, 1, AccountState::Initialized, None),
+    );
+    backend.set_account(
+        empty,
+        token_account(&mint, &payer, 0, AccountState::Initialized, None),
+    );
+
+    let rollbacks = rollback_created_accounts_with_client(
+        &backend.client(),
+        &CreatedAccounts {
+            funder: payer,
+            accounts: vec![created(funded, mint, payer), created(empty, mint, payer)],
+        },
+    )
+    .unwrap();
+
+    assert_eq!(rollbacks.len(), 2);
+    assert_eq!(rollbacks[0].address, funded);
+    assert_eq!(
+        skipped(&rollbacks[0]),
+        Some(&RollbackSkip::HoldsTokens { amount: 1 })
+    );
+    assert_eq!(rollbacks[1].address, empty);
+    assert_eq!(skipped(&rollbacks[1]), None);
+    assert_eq!(backend.sent().len(), 1);
+    for tx in backend.sent() {
+        assert!(!tx.message.account_keys.contains(&funded));
+    }
+}
+
+#[test]
+fn accounts_the_payer_cannot_close_are_left_alone() {
+    let payer = payer();
+    let backend = MockBackend::new();
+    let mint = Pubkey::new_unique();
+    let other = Pubkey::new_unique();
+    let foreign = Pubkey::new_unique();
+    let wrong_mint = Pubkey::new_unique();
+    let missing = Pubkey::new_unique();
+    let not_token = Pubkey::new_unique();
+    let garbled = Pubkey::new_unique();
+    let other_mint = Pubkey::new_unique();
+    backend.set_account(
+        foreign,
+        token_account(&mint, &other, 0, AccountState::Initialized, None),
+    );
+    backend.set_account(
+        wrong_mint,
+        token_account(&other_mint, &payer, 0, AccountState::Initialized, None),
+    );
+    // A wallet at the address, and a token program account too short to be a token account
+    backend.set_account(
+        not_token,
+        Account {
+            lamports: 1_000_000,
+            ..Account::default()
+        },
+    );
+    let mut short = token_account(&mint, &payer, 0, AccountState::Initialized, None);
+    short.data.truncate(64);
+    backend.set_account(garbled, short);
+
+    let rollbacks = rollback_created_accounts_with_client(
+        &backend.client(),
+        &CreatedAccounts {
+            funder: payer,
+            accounts: vec![
+                created(foreign, mint, other),
+                created(wrong_mint, mint, payer),
+                created(missing, mint, payer),
+                created(not_token, mint, payer),
+                created(garbled, mint, payer),
+            ],
+        },
+    )
+    .unwrap();
+
+    let reasons: Vec<_> = rollbacks.iter().map(|r| (r.address, skipped(r))).collect();
+    assert_eq!(
+        reasons,
+        vec![
+            (
+                foreign,
+                Some(&RollbackSkip::NotClosable {
+                    close_authority: other
+                })
+            ),
+            (
+                wrong_mint,
+                Some(&RollbackSkip::OtherMint { mint: other_mint })
+            ),
+            (missing, Some(&RollbackSkip::Missing)),
+            (
+                not_token,
+                Some(&RollbackSkip::NotTokenAccount {
+                    owner: Pubkey::default()
+                })
+            ),
+            (
+                garbled,
+                Some(&RollbackSkip::NotTokenAccount {
+                    owner: spl_token::id()
+                })
+            ),
+        ]
+    );
+    assert_eq!(backend.call_count("sendTransaction"), 0);
+}
+
+#[test]
+fn close_authority_of_another_owner_lets_the_payer_close() {
+    let payer = payer();
+    let backend = MockBackend::new();
+    let mint = Pubkey::new_unique();
+    let other = Pubkey::new_unique();
+    let address = Pubkey::new_unique();
+    backend.set_account(
+        address,
+        token_account(&mint, &other, 0, AccountState::Initialized, Some(payer)),
+    );
+
+    let rollbacks = rollback_created_accounts_with_client(
+        &backend.client(),
+        &CreatedAccounts {
+            funder: payer,
+            accounts: vec![created(address, mint, other)],
+        },
+    )
+    .unwrap();
+    assert_eq!(skipped(&rollbacks[0]), None);
+}
+
+#[test]
+fn frozen_account_is_thawed_before_closing() {
+    let payer = payer();
+    let backend = MockBackend::new();
+    let mint = Pubkey::new_unique();
+    let address = Pubkey::new_unique();
+    backend.set_account(
+        address,
+        token_account(&mint, &payer, 0, AccountState::Frozen, None),
+    );
+
+    rollback_created_accounts_with_client(
+        &backend.client(),
+        &CreatedAccounts {
+            funder: payer,
+            accounts: vec![created(address, mint, payer)],
+        },
+    )
+    .unwrap();
+    assert!(matches!(
+        sent_token_instructions(&backend)[..],
+        [
+            TokenInstruction::ThawAccount,
+            TokenInstruction::CloseAccount
+        ]
+    ));
+}
+
+#[test]
+fn accounts_of_another_funder_are_refused() {
+    let payer = payer();
+    let backend = MockBackend::new();
+    let result = rollback_created_accounts_with_client(
+        &backend.client(),
+        &CreatedAccounts {
+            funder: Pubkey::new_unique(),
+            accounts: vec![created(Pubkey::new_unique(), Pubkey::new_unique(), payer)],
+        },
+    );
+    assert!(matches!(result, Err(SssError::TokenError(_))));
+    assert_eq!(backend.call_count("getMultipleAccounts"), 0);
+}
+
+/// A token whose creation and initial mint do not fit in one transaction
+fn oversized_token() -> TokenBuilder {
+    let creators = (1..=5)
+        .map(|byte| CreatorShare::new(fixed_keypair(byte + 10).pubkey(), 20))
+        .collect();
+    TokenBuilder::new()
+        .name("N".repeat(32))
+        .symbol("S".repeat(10))
+        .uri(format!("https://example.com/{}", "u".repeat(180)))
+        .creators(creators)
+        .token_program(TokenProgram::Token2022)
+        .initial_state(sss_shared::AccountState::Frozen)
+        .soulbound(true)
+        .freeze_authority(Pubkey::new_unique())
+        .tx_options(TxOptions {
+            // Pushes the creation and the mint over the size of one transaction
+            post_instructions: vec![Instruction::new_with_bytes(
+                spl_memo_id(),
+                &[b'm'; 80],
+                Vec::new(),
+            )],
+            ..TxOptions::default()
+        })
+}
+
+/// Returns the data of the instructions a transaction sends to a program, in order
+fn instructions_for(tx: &Transaction, program: Pubkey) -> Vec<Vec<u8>> {
+    tx.message
+        .instructions
+        .iter()
+        .filter(|ix| tx.message.account_keys[ix.program_id_index as usize] == program)
+        .map(|ix| ix.data.clone())
+        .collect()
+}
+
+#[test]
+fn split_creation_creates_the_recipient_account_then_mints_into_it() {
+    payer();
+    let backend = MockBackend::new();
+    let mint = fixed_keypair(8);
+    let owner = Pubkey::new_unique();
+    let result = oversized_token()
+        .create_and_mint_with_client(&backend.client(), &mint, 10, Some(owner))
+        .unwrap();
+
+    let sent = backend.sent();
+    assert_eq!(sent.len(), 2);
+    let (create, mint_tx) = (&sent[0], &sent[1]);
+    let address =
+        get_associated_token_address_with_program_id(&owner, &mint.pubkey(), &spl_token_2022::id());
+    // The creation is signed by the mint, creates its metadata and the owner's account
+    let (create_v1, mint_v1) = (vec![42, 0], vec![43, 0]);
+    let metadata_discriminators = |tx| {
+        instructions_for(tx, mpl_token_metadata::ID)
+            .into_iter()
+            .map(|data| data[..2].to_vec())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(create.signatures.len(), 2);
+    assert!(create.message.account_keys.contains(&mint.pubkey()));
+    assert!(create.message.account_keys.contains(&address));
+    assert_eq!(metadata_discriminators(create), vec![create_v1]);
+    assert_eq!(
+        instructions_for(create, spl_associated_token_account::id()),
+        vec![vec![1]]
+    );
+    // The mint only needs the payer, and mints into that account
+    assert_eq!(mint_tx.signatures.len(), 1);
+    assert_eq!(metadata_discriminators(mint_tx), vec![mint_v1]);
+    assert!(mint_tx.message.account_keys.contains(&address));
+    assert_eq!(result.signature, mint_tx.signatures[0].to_string());
+}
+
+#[test]
+fn failed_split_creation_is_a_partial_create_without_created_accounts() {
+    payer();
+    let backend = MockBackend::new();
+    backend.reject("sendTransaction", |_| {
+        Some("Transaction simulation failed: custom program error: 0x0".to_string())
+    });
+    let mint = fixed_keypair(7);
+    let result = oversized_token().create_and_mint_with_client(&backend.client(), &mint, 10, None);
+
+    // The creation may have landed, but no account was created for a mint that never came
+    let Err(SssError::PartialCreate {
+        mint: reported,
+        source,
+        ..
+    }) = result
+    else {
+        panic!("expected PartialCreate, got {:?}", result);
+    };
+    assert_eq!(reported, mint.pubkey());
+    assert!(matches!(*source, SssError::RpcError(_)), "{:?}", source);
+    assert_eq!(backend.call_count("sendTransaction"), 1);
+    assert!(backend.sent().is_empty());
+}
+
+#[test]
+fn failed_mint_after_a_split_creation_can_be_rolled_back() {
+    let payer = payer();
+    let backend = MockBackend::new();
+    let sends = AtomicUsize::new(0);
+    // The creation lands, the mint that follows it fails
+    backend.reject("sendTransaction", move |_| {
+        (sends.fetch_add(1, Ordering::SeqCst) > 0)
+            .then(|| "Transaction simulation failed: custom program error: 0x1".to_string())
+    });
+
+    let mint = fixed_keypair(9);
+    let result = oversized_token().create_and_mint_with_client(&backend.client(), &mint, 10, None);
+
+    let Err(SssError::AccountsCreated { created, source }) = result else {
+        panic!("expected AccountsCreated, got {:?}", result);
+    };
+    assert!(matches!(*source, SssError::RpcError(_)), "{:?}", source);
+    let address =
+        get_associated_token_address_with_program_id(&payer, &mint.pubkey(), &spl_token_2022::id());
+    assert_eq!(
+        created,
+        CreatedAccounts {
+            funder: payer,
+            accounts: vec![CreatedAccount {
+                address,
+                mint: mint.pubkey(),
+                owner: payer,
+                token_program: TokenProgram::Token2022,
+            }],
+        }
+    );
+    // The recipient account was created by the first transaction, before the mint
+    assert_eq!(backend.sent().len(), 1);
+    assert!(backend.sent()[0].message.account_keys.contains(&address));
+
+    // Tokens that arrived in the meantime keep the account open
+    let mut account = token_account(&mint.pubkey(), &payer, 10, AccountState::Frozen, None);
+    account.owner = spl_token_2022::id();
+    backend.set_account(address, account);
+    backend.reject("sendTransaction", |_| None);
+    let rollbacks = rollback_created_accounts_with_client(&backend.client(), &created).unwrap();
+    assert_eq!(
+        skipped(&rollbacks[0]),
+        Some(&RollbackSkip::HoldsTokens { amount: 10 })
+    );
+    assert_eq!(backend.sent().len(), 1);
+
+    // The still empty, frozen account is thawed and closed
+    let mut account = token_account(&mint.pubkey(), &payer, 0, AccountState::Frozen, None);
+    account.owner = spl_token_2022::id();
+    backend.set_account(address, account);
+    let rollbacks = rollback_created_accounts_with_client(&backend.client(), &created).unwrap();
+    let close = &backend.sent()[1];
+    assert_eq!(
+        rollbacks[0].outcome,
+        RollbackOutcome::Closed(close.signatures[0].to_string())
+    );
+    assert_eq!(close.message.instructions.len(), 2);
+    assert!(close.message.account_keys.contains(&address));
+}
@@ -0,0 +1,133 @@
+//! Off-chain message signing never signs a transaction
+//!
+//! Every test signs with the same default payer, so setting it from each is harmless. The
+//! role policy set here only restricts its own profile.
+
+mod common;
+
+use common::fixed_keypair;
+use solana_sdk::hash::Hash;
+use solana_sdk::message::{Message, VersionedMessage, v0};
+use solana_sdk::{pubkey::Pubkey, signer::Signer, system_instruction};
+use sss_shared::{
+    JournalOperation, Permission, ProfileConfig, RolePolicy, SssError, register_profile, set_payer,
+    set_role_policy, sign_message, verify_message, with_profile,
+};
+
+/// A transfer of all the payer's lamports, as an attacker would hand it in
+fn drain_payer() -> Message {
+    let payer = fixed_keypair(1).pubkey();
+    let ix = system_instruction::transfer(&payer, &Pubkey::new_unique(), 1_000_000_000);
+    Message::new_with_blockhash(&[ix], Some(&payer), &Hash::new_unique())
+}
+
+fn assert_refused(bytes: &[u8]) {
+    match sign_message(bytes) {
+        Err(SssError::KeypairError(message)) => {
+            assert!(message.contains("transaction message"), "{}", message)
+        }
+        other => panic!("signed a transaction message: {:?}", other),
+    }
+}
+
+#[test]
+fn text_and_arbitrary_bytes_are_signed() {
+    set_payer(fixed_keypair(1));
+    let payer = fixed_keypair(1).pubkey();
+    for message in [
+        b"redeem coupon SPRING-10".to_vec(),
+        Vec::new(),
+        vec![0; 32],
+        vec![0xff; 200],
+    ] {
+        let signature = sign_message(&message).unwrap();
+        assert!(verify_message(&payer, &message, &signature));
+    }
+}
+
+#[test]
+fn transaction_messages_are_not_signed() {
+    set_payer(fixed_keypair(1));
+    let legacy = drain_payer();
+    assert_refused(&legacy.serialize());
+
+    let payer = fixed_keypair(1).pubkey();
+    let ix = system_instruction::transfer(&payer, &Pubkey::new_unique(), 1);
+    let v0 = v0::Message::try_compile(&payer, &[ix], &[], Hash::new_unique()).unwrap();
+    let versioned = VersionedMessage::V0(v0);
+    assert_refused(&versioned.serialize());
+    assert_refused(&VersionedMessage::Legacy(legacy.clone()).serialize());
+
+    // Padding after the message does not hide it
+    let mut padded = legacy.serialize();
+    padded.extend_from_slice(b"trailing");
+    assert_refused(&padded);
+}
+
+#[test]
+fn a_role_signs_messages_only_if_its_policy_allows_it() {
+    set_payer(fixed_keypair(1));
+    register_profile("message-kiosk", ProfileConfig::new(fixed_keypair(2)));
+    register_profile("message-notary", ProfileConfig::new(fixed_keypair(3)));
+    set_role_policy(
+        "message-kiosk",
+        RolePolicy {
+            permissions: vec![Permission::operation(JournalOperation::MintToken)],
+        },
+    )
+    .unwrap();
+    set_role_policy(
+        "message-notary",
+        RolePolicy {
+            permissions: vec![Permission::operation(JournalOperation::SignMessage)],
+        },
+    )
+    .unwrap();
+
+    let refused = with_profile("message-kiosk", || sign_message(b"hello")).unwrap_err();
+    assert!(
+        matches!(&refused, SssError::ConfigError(m) if m.contains("'sign_message' capability")),
+        "{:?}",
+        refused
+    );
+
+    let signature = with_profile("message-notary", || sign_message(b"hello")).unwrap();
+    assert!(verify_message(
+        &fixed_keypair(3).pubkey(),
+        b"hello",
+        &signature
+    ));
+    // Allowed to sign messages is not allowed to sign transactions
+    let tx = drain_payer().serialize();
+    let refused = with_profile("message-notary", || sign_message(&tx)).unwrap_err();
+    assert!(matches!(refused, SssError::KeypairError(_)));
+}
+
+#[cfg(feature = "ffi")]
+#[test]
+fn the_c_api_refuses_transaction_messages() {
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+    use sss_shared::sss_sign_message;
+    use std::ffi::{CStr, CString};
+    use std::os::raw::c_char;
+
+    set_payer(fixed_keypair(1));
+    let sign = |bytes: &[u8]| {
+        let message = CString::new(STANDARD.encode(bytes)).unwrap();
+        let mut out = [0 as c_char; 128];
+        let code = unsafe { sss_sign_message(message.as_ptr(), out.as_mut_ptr(), 128) };
+        let signature = unsafe { CStr::from_ptr(out.as_ptr()) };
+        (code, signature.to_string_lossy().into_owned())
+    };
+
+    let (code, signature) = sign(b"hello");
+    assert_eq!(code, 0);
+    let signature = signature.parse().unwrap();
+    assert!(verify_message(
+        &fixed_keypair(1).pubkey(),
+        b"hello",
+        &signature
+    ));
+    assert_eq!(sign(&drain_payer().serialize()), (-4, String::new()));
+}
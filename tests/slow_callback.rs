@@ -0,0 +1,213 @@
+//! A slow or deadlocked event sink does not hold up minting
+//!
+//! Every mint emits `transaction_sent`, `transaction_confirmed` and `mint_confirmed`. The
+//! sinks here take far longer to handle those than the mints take to send them, or never
+//! return. The event queue, the sink and the watchdog timeout are process-wide, so this
+//! binary holds a single test that runs through the overflow policies in turn and ends by
+//! shutting the library down.
+
+mod common;
+
+use common::{MockBackend, fixed_keypair, mint_account};
+use solana_sdk::{pubkey::Pubkey, signer::Signer};
+use sss_shared::{
+    CallbackQueue, MintOptions, OverflowPolicy, QueueMetrics, QueueOptions, RawAmount, SssEvent,
+    metrics_snapshot, mint_token_detailed_with_client, set_callback_queue_options, set_event_sink,
+    set_payer,
+};
+use std::env;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const MINTS: u64 = 40;
+const SINK_DELAY: Duration = Duration::from_millis(50);
+const WATCHDOG: Duration = Duration::from_millis(300);
+
+type Seen = Arc<Mutex<Vec<SssEvent>>>;
+
+/// Installs a sink recording every event after sleeping `delay`
+fn slow_sink(delay: Duration) -> Seen {
+    let seen: Seen = Arc::default();
+    let sink = Arc::clone(&seen);
+    set_event_sink(move |event| {
+        thread::sleep(delay);
+        sink.lock().unwrap().push(event);
+    });
+    seen
+}
+
+fn configure(capacity: usize, overflow: OverflowPolicy) {
+    set_callback_queue_options(CallbackQueue::Events, QueueOptions { capacity, overflow }).unwrap();
+}
+
+fn event_queue() -> QueueMetrics {
+    metrics_snapshot()
+        .callback_queues
+        .into_iter()
+        .find(|queue| queue.queue == CallbackQueue::Events)
+        .unwrap()
+}
+
+/// Mints `MINTS` times, checking the queue never outgrows its capacity, and returns how
+/// long it took and the signatures in order
+fn mint_all(backend: &Arc<MockBackend>, mint: Pubkey) -> (Duration, Vec<String>) {
+    let client = backend.client();
+    let started = Instant::now();
+    let signatures = (1..=MINTS)
+        .map(|amount| {
+            let signature = mint_token_detailed_with_client(
+                &client,
+                mint,
+                None,
+                RawAmount(amount),
+                &MintOptions::default(),
+            )
+            .unwrap()
+            .signature;
+            let queue = event_queue();
+            assert!(queue.depth <= queue.capacity, "{:?}", queue);
+            signature
+        })
+        .collect();
+    (started.elapsed(), signatures)
+}
+
+/// Waits until the sink has seen an event matching `done`
+fn wait_for(seen: &Seen, done: impl Fn(&[SssEvent]) -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while !done(&seen.lock().unwrap()) {
+        assert!(Instant::now() < deadline, "the sink never caught up");
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn dropped_reported(seen: &[SssEvent]) -> u64 {
+    seen.iter()
+        .map(|event| match event {
+            SssEvent::EventsDropped {
+                queue: CallbackQueue::Events,
+                count,
+            } => *count,
+            _ => 0,
+        })
+        .sum()
+}
+
+fn mint_confirmed(seen: &[SssEvent], signature: &str) -> bool {
+    seen.iter().any(
+        |event| matches!(event, SssEvent::MintConfirmed { signature: s, .. } if s == signature),
+    )
+}
+
+#[test]
+fn operations_are_not_held_up_by_a_slow_or_deadlocked_sink() {
+    // Set before the watchdog is first read, while no other thread runs
+    unsafe { env::set_var("SSS_CALLBACK_WATCHDOG_MS", WATCHDOG.as_millis().to_string()) };
+    set_payer(fixed_keypair(1));
+    let backend = MockBackend::new();
+    let mint = fixed_keypair(2).pubkey();
+    backend.set_account(mint, mint_account(Some(fixed_keypair(1).pubkey()), 0, 0));
+
+    // What the sink would need to handle every event of the mints in turn
+    let events = 3 * MINTS as u32;
+    let sink_time = SINK_DELAY * events;
+
+    // Dropping the newest events: the mints finish long before the sink could, the first
+    // events are delivered and the drops are announced
+    configure(8, OverflowPolicy::DropNewest);
+    let seen = slow_sink(SINK_DELAY);
+    let (elapsed, signatures) = mint_all(&backend, mint);
+    assert!(
+        elapsed < sink_time / 4,
+        "{} mints took {:?} with a sink needing {:?}",
+        MINTS,
+        elapsed,
+        sink_time
+    );
+    let dropped = event_queue().dropped;
+    assert!(dropped > 0);
+    wait_for(&seen, |seen| dropped_reported(seen) == dropped);
+    wait_for(&seen, |seen| mint_confirmed(seen, &signatures[0]));
+    assert_eq!(event_queue().stalls, 0);
+
+    // Dropping the oldest events: the last mint is heard of
+    let before = event_queue().dropped;
+    configure(8, OverflowPolicy::DropOldest);
+    let seen = slow_sink(SINK_DELAY);
+    let (elapsed, signatures) = mint_all(&backend, mint);
+    assert!(elapsed < sink_time / 4, "{:?}", elapsed);
+    let last = signatures.last().unwrap().clone();
+    wait_for(&seen, |seen| mint_confirmed(seen, &last));
+    let dropped = event_queue().dropped - before;
+    assert!(dropped > 0);
+    wait_for(&seen, |seen| dropped_reported(seen) == dropped);
+
+    // Blocking for a sink that keeps up within the timeout: nothing is dropped and every
+    // event arrives in order
+    let before = event_queue().dropped;
+    configure(2, OverflowPolicy::Block(Duration::from_secs(5)));
+    let seen = slow_sink(Duration::from_millis(2));
+    let (_, signatures) = mint_all(&backend, mint);
+    let last = signatures.last().unwrap().clone();
+    wait_for(&seen, |seen| mint_confirmed(seen, &last));
+    assert_eq!(event_queue().dropped, before);
+    let confirmed: Vec<String> = seen
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(|event| match event {
+            SssEvent::MintConfirmed { signature, .. } => Some(signature.clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(confirmed, signatures);
+
+    // Blocking for a deadlocked sink: producers wait until the watchdog declares it
+    // stalled, then no longer, and the stall is reported
+    configure(2, OverflowPolicy::Block(Duration::from_secs(5)));
+    let (release, deadlock) = mpsc::channel::<()>();
+    let deadlock = Mutex::new(deadlock);
+    set_event_sink(move |_| {
+        let _ = deadlock.lock().unwrap().recv();
+    });
+    let (elapsed, _) = mint_all(&backend, mint);
+    assert!(
+        elapsed < WATCHDOG + Duration::from_secs(2),
+        "{} mints took {:?} with a deadlocked sink",
+        MINTS,
+        elapsed
+    );
+    let queue = event_queue();
+    assert_eq!(queue.stalls, 1);
+    assert!(queue.stalled_ms.unwrap() >= WATCHDOG.as_millis() as u64);
+    assert_eq!(queue.depth, 2);
+
+    // A new sink gets a new delivery thread and the queued events
+    let seen = slow_sink(Duration::ZERO);
+    wait_for(&seen, |seen| seen.len() >= 2);
+    let (_, signatures) = mint_all(&backend, mint);
+    let last = signatures.last().unwrap().clone();
+    wait_for(&seen, |seen| mint_confirmed(seen, &last));
+    assert_eq!(event_queue().stalled_ms, None);
+    drop(release);
+
+    // Shutting down right after a burst discards the queued events, but the drops not
+    // reported yet reach the sink before it goes quiet
+    configure(1, OverflowPolicy::DropNewest);
+    let seen = slow_sink(SINK_DELAY);
+    let before = event_queue().dropped;
+    mint_all(&backend, mint);
+    let dropped = event_queue().dropped - before;
+    assert!(dropped > 0);
+    sss_shared::shutdown();
+    let delivered = seen.lock().unwrap().len();
+    assert_eq!(dropped_reported(&seen.lock().unwrap()), dropped);
+    assert!(matches!(
+        seen.lock().unwrap().last(),
+        Some(SssEvent::EventsDropped { .. })
+    ));
+    thread::sleep(SINK_DELAY * 2);
+    assert_eq!(seen.lock().unwrap().len(), delivered);
+}
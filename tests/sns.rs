@@ -0,0 +1,179 @@
+//! `.sol` domains resolve to their owners and wallets back to their primary domains
+//!
+//! The name accounts are answered from `fixtures/sns`, which holds them in the shape
+//! `getMultipleAccounts` returns, at the addresses the name service derives for `bonfida.sol`
+//! and `dex.bonfida.sol`. Lookups use the global client and process-wide caches, so the mock
+//! backend is served as `SOLANA_RPC_URL` and the tests take turns.
+
+mod common;
+
+use base64::prelude::{BASE64_STANDARD, Engine};
+use common::MockBackend;
+use serde_json::Value;
+use solana_sdk::{account::Account, pubkey::Pubkey};
+use sss_shared::{SssError, primary_domain_for, resolve_recipient};
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::{env, fs};
+
+/// Name account of `bonfida.sol`, as the name service documents it
+const BONFIDA: &str = "Crf8hzfthWGbGbLTVCiqRqV5MVnbpHB1L9KQMd6gsinb";
+/// Name account of `dex.bonfida.sol`
+const DEX_BONFIDA: &str = "HoFfFXqFHAC8RP3duuQNzag1ieUwJRBv1HtRNiWFq4Qu";
+/// Owner of `bonfida.sol`, whose favourite it is
+const BONFIDA_OWNER: &str = "4yiyrMmukw76aSBDwnGBd1JFyUnZNMfx1Whfv5ovgpWK";
+/// Owner of `dex.bonfida.sol`, whose favourite it is
+const DEX_OWNER: &str = "6UHPLnHvj5qc25TVQ4YrL7QP9W6iJ9YsANrBV7Zjj1M7";
+/// A wallet whose favourite is `bonfida.sol`, which it no longer owns
+const FORMER_OWNER: &str = "6EAtVEurU6cH3psxw5Av7kGrtdMNf1BYRWFo84BEtoUS";
+/// The program account holding `wrapped.sol` in escrow
+const ESCROW: &str = "EKdqmsbBbZFb22Mg4B8MPr5nTZX2xXAQ4hB3RopZUS43";
+
+static SERIAL: Mutex<()> = Mutex::new(());
+
+static BACKEND: OnceLock<Arc<MockBackend>> = OnceLock::new();
+
+/// The backend holding the recorded name accounts
+fn ledger() -> (MutexGuard<'static, ()>, Arc<MockBackend>) {
+    let serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let backend = BACKEND.get_or_init(|| {
+        let backend = MockBackend::new();
+        unsafe { env::set_var("SOLANA_RPC_URL", backend.serve()) };
+        for fixture in ["forward.json", "reverse.json"] {
+            for (address, account) in recorded_accounts(fixture) {
+                backend.set_account(address, account);
+            }
+        }
+        backend
+    });
+    (serial, Arc::clone(backend))
+}
+
+/// Reads the accounts of a fixture
+fn recorded_accounts(fixture: &str) -> Vec<(Pubkey, Account)> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures/sns")
+        .join(fixture);
+    let recorded: Vec<Value> = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+    recorded
+        .iter()
+        .map(|keyed| {
+            let account = &keyed["account"];
+            let data = account["data"][0].as_str().unwrap();
+            (
+                key(keyed["pubkey"].as_str().unwrap()),
+                Account {
+                    lamports: account["lamports"].as_u64().unwrap(),
+                    data: BASE64_STANDARD.decode(data).unwrap(),
+                    owner: key(account["owner"].as_str().unwrap()),
+                    executable: account["executable"].as_bool().unwrap(),
+                    rent_epoch: account["rentEpoch"].as_u64().unwrap(),
+                },
+            )
+        })
+        .collect()
+}
+
+fn key(address: &str) -> Pubkey {
+    address.parse().unwrap()
+}
+
+fn assert_refused(input: &str, expected: &str) {
+    match resolve_recipient(input) {
+        Err(SssError::TokenError(message)) => assert_eq!(message, expected),
+        other => panic!("resolved {}: {:?}", input, other),
+    }
+}
+
+#[test]
+fn domains_resolve_to_the_owner_of_their_name_account() {
+    let (_serial, backend) = ledger();
+    assert!(backend.account(&key(BONFIDA)).is_some());
+    assert_eq!(
+        resolve_recipient("bonfida.sol").unwrap(),
+        key(BONFIDA_OWNER)
+    );
+    // As pasted, in any case
+    assert_eq!(
+        resolve_recipient("  Bonfida.SOL\n").unwrap(),
+        key(BONFIDA_OWNER)
+    );
+    // A subdomain is a child of its parent's name account
+    assert!(backend.account(&key(DEX_BONFIDA)).is_some());
+    assert_eq!(
+        resolve_recipient("dex.bonfida.sol").unwrap(),
+        key(DEX_OWNER)
+    );
+    // A public key is taken as it is, without a lookup
+    let calls = backend.call_count("getMultipleAccounts");
+    assert_eq!(resolve_recipient(ESCROW).unwrap(), key(ESCROW));
+    assert_eq!(backend.call_count("getMultipleAccounts"), calls);
+}
+
+#[test]
+fn resolved_domains_are_cached() {
+    let (_serial, backend) = ledger();
+    resolve_recipient("bonfida.sol").unwrap();
+    let calls = backend.call_count("getMultipleAccounts");
+    for input in ["bonfida.sol", "BONFIDA.sol"] {
+        assert_eq!(resolve_recipient(input).unwrap(), key(BONFIDA_OWNER));
+    }
+    assert_eq!(backend.call_count("getMultipleAccounts"), calls);
+}
+
+#[test]
+fn domains_without_a_usable_owner_are_refused() {
+    let (_serial, _backend) = ledger();
+    assert_refused("nobody.sol", "Domain nobody.sol is not registered");
+    assert_refused(
+        "expired.sol",
+        "Domain expired.sol has no owner; it expired or was released",
+    );
+    assert_refused(
+        "wrapped.sol",
+        &format!(
+            "Domain wrapped.sol is owned by the program account {}, e.g. because it is \
+             wrapped as an NFT; resolve it to the holder's wallet instead",
+            ESCROW
+        ),
+    );
+    // A domain that failed to resolve is not cached as anything
+    assert_refused("nobody.sol", "Domain nobody.sol is not registered");
+
+    assert_refused(
+        "a.b.bonfida.sol",
+        "Domain a.b.bonfida.sol is not of the form name.sol or sub.name.sol",
+    );
+    assert_refused(
+        "bonfida..sol",
+        "Domain bonfida..sol is not of the form name.sol or sub.name.sol",
+    );
+    assert_refused(
+        "alice.eth",
+        "Recipient 'alice.eth' is neither a public key nor a .sol domain",
+    );
+}
+
+#[test]
+fn wallets_resolve_back_to_their_primary_domain() {
+    let (_serial, _backend) = ledger();
+    assert_eq!(
+        primary_domain_for(key(BONFIDA_OWNER)).unwrap().as_deref(),
+        Some("bonfida.sol")
+    );
+    // A subdomain's name is put together from its own reverse record and its parent's
+    assert_eq!(
+        primary_domain_for(key(DEX_OWNER)).unwrap().as_deref(),
+        Some("dex.bonfida.sol")
+    );
+    // A favourite the wallet no longer owns is not shown as its domain
+    assert_eq!(primary_domain_for(key(FORMER_OWNER)).unwrap(), None);
+    // Nor is anything shown for a wallet without a favourite
+    assert_eq!(primary_domain_for(key(ESCROW)).unwrap(), None);
+
+    // Both directions agree
+    for wallet in [BONFIDA_OWNER, DEX_OWNER] {
+        let domain = primary_domain_for(key(wallet)).unwrap().unwrap();
+        assert_eq!(resolve_recipient(&domain).unwrap(), key(wallet));
+    }
+}
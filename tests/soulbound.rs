@@ -0,0 +1,345 @@
+//! Soulbound tokens are minted locked, refused for transfer and still burnable
+//!
+//! Burning uses the global client, so the mock backend is served as `SOLANA_RPC_URL` and
+//! shared by the tests of this binary. Every test works on mints of its own.
+
+mod common;
+
+use common::{MockBackend, fixed_keypair, token_account};
+use solana_program::program_pack::Pack;
+use solana_sdk::system_instruction::SystemInstruction;
+use solana_sdk::{account::Account, pubkey::Pubkey, signer::Signer, transaction::Transaction};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_token_2022::extension::non_transferable::NonTransferable;
+use spl_token_2022::extension::{
+    BaseStateWithExtensionsMut, ExtensionType, StateWithExtensionsMut,
+};
+use sss_shared::{
+    AmountSpec, MintOptions, RawAmount, SssError, TokenBuilder, TokenProgram, TransferOptions,
+    add_soulbound_mint, burn_tokens, is_soulbound_mint, mint_token_detailed_with_client, set_payer,
+    transfer_asset_with_client,
+};
+use std::env;
+use std::sync::{Arc, OnceLock};
+
+/// Instruction tags of the token programs
+const MINT_TO: u8 = 7;
+const FREEZE_ACCOUNT: u8 = 10;
+const THAW_ACCOUNT: u8 = 11;
+const BURN_CHECKED: u8 = 15;
+const INITIALIZE_MINT2: u8 = 20;
+const INITIALIZE_NON_TRANSFERABLE_MINT: u8 = 32;
+
+static BACKEND: OnceLock<Arc<MockBackend>> = OnceLock::new();
+
+fn backend() -> Arc<MockBackend> {
+    Arc::clone(BACKEND.get_or_init(|| {
+        let backend = MockBackend::new();
+        unsafe { env::set_var("SOLANA_RPC_URL", backend.serve()) };
+        set_payer(fixed_keypair(1));
+        backend
+    }))
+}
+
+fn payer() -> Pubkey {
+    fixed_keypair(1).pubkey()
+}
+
+/// A legacy mint of the payer, which keeps the freeze authority
+fn legacy_mint(supply: u64) -> Account {
+    let mint = spl_token::state::Mint {
+        mint_authority: Some(payer()).into(),
+        supply,
+        decimals: 0,
+        is_initialized: true,
+        freeze_authority: Some(payer()).into(),
+    };
+    let mut data = vec![0; spl_token::state::Mint::LEN];
+    spl_token::state::Mint::pack(mint, &mut data).unwrap();
+    Account {
+        lamports: 1_461_600,
+        data,
+        owner: spl_token::id(),
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+/// A Token-2022 mint of the payer with the NonTransferable extension
+fn non_transferable_mint(supply: u64) -> Account {
+    let space = ExtensionType::try_calculate_account_len::<spl_token_2022::state::Mint>(&[
+        ExtensionType::NonTransferable,
+    ])
+    .unwrap();
+    let mut data = vec![0; space];
+    let mut state =
+        StateWithExtensionsMut::<spl_token_2022::state::Mint>::unpack_uninitialized(&mut data)
+            .unwrap();
+    state.init_extension::<NonTransferable>(true).unwrap();
+    state.base = spl_token_2022::state::Mint {
+        mint_authority: Some(payer()).into(),
+        supply,
+        decimals: 0,
+        is_initialized: true,
+        freeze_authority: Some(payer()).into(),
+    };
+    state.pack_base();
+    state.init_account_type().unwrap();
+    Account {
+        lamports: 1_500_000,
+        data,
+        owner: spl_token_2022::id(),
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+/// A token account of `owner`, frozen or not, owned by `program`
+fn holding(mint: &Pubkey, owner: &Pubkey, amount: u64, frozen: bool, program: &Pubkey) -> Account {
+    let mut account = token_account(mint, owner, amount);
+    if frozen {
+        let mut state = spl_token::state::Account::unpack(&account.data).unwrap();
+        state.state = spl_token::state::AccountState::Frozen;
+        spl_token::state::Account::pack(state, &mut account.data).unwrap();
+    }
+    account.owner = *program;
+    account
+}
+
+/// The token instructions of a transaction, in order, with the account each applies to:
+/// the destination of a mint, and otherwise the first account
+fn token_steps(tx: &Transaction) -> Vec<(u8, Pubkey)> {
+    let keys = &tx.message.account_keys;
+    tx.message
+        .instructions
+        .iter()
+        .filter(|ix| {
+            let program = keys[ix.program_id_index as usize];
+            program == spl_token::id() || program == spl_token_2022::id()
+        })
+        .map(|ix| {
+            let target = if ix.data[0] == MINT_TO { 1 } else { 0 };
+            (ix.data[0], keys[ix.accounts[target] as usize])
+        })
+        .collect()
+}
+
+/// The last transaction sent for a mint; the tests share the backend
+fn last_sent(backend: &MockBackend, mint: &Pubkey) -> Transaction {
+    backend
+        .sent()
+        .into_iter()
+        .rev()
+        .find(|tx| tx.message.account_keys.contains(mint))
+        .expect("nothing was sent")
+}
+
+fn ata(owner: &Pubkey, mint: &Pubkey, program: &Pubkey) -> Pubkey {
+    get_associated_token_address_with_program_id(owner, mint, program)
+}
+
+fn assert_soulbound_transfer_refused(mint: Pubkey) {
+    let backend = backend();
+    let result = transfer_asset_with_client(
+        &backend.client(),
+        mint,
+        Pubkey::new_unique(),
+        &TransferOptions::default(),
+    );
+    match result {
+        Err(SssError::TokenError(message)) => assert_eq!(
+            message,
+            format!(
+                "Mint {} is soulbound, so its tokens cannot be transferred",
+                mint
+            )
+        ),
+        other => panic!("a soulbound transfer was not refused: {:?}", other),
+    }
+    assert!(
+        backend
+            .sent()
+            .iter()
+            .all(|tx| !tx.message.account_keys.contains(&mint))
+    );
+}
+
+#[test]
+fn a_token_2022_soulbound_token_is_created_non_transferable() {
+    let backend = backend();
+    let mint = fixed_keypair(20);
+    let holder = Pubkey::new_unique();
+    TokenBuilder::new()
+        .name("Forklift certificate")
+        .uri("https://example.com/cert.json")
+        .token_program(TokenProgram::Token2022)
+        .soulbound(true)
+        .create_and_mint_with_client(&backend.client(), &mint, 1, Some(holder))
+        .unwrap();
+
+    // The extension is initialized before the mint itself
+    let tx = last_sent(&backend, &mint.pubkey());
+    assert_eq!(
+        token_steps(&tx),
+        vec![
+            (INITIALIZE_NON_TRANSFERABLE_MINT, mint.pubkey()),
+            (INITIALIZE_MINT2, mint.pubkey()),
+        ]
+    );
+    // The mint account is created with room for the extension
+    let create = &tx.message.instructions[0];
+    assert_eq!(
+        tx.message.account_keys[create.program_id_index as usize],
+        solana_sdk::system_program::id()
+    );
+    match bincode::deserialize(&create.data).unwrap() {
+        SystemInstruction::CreateAccount { space, owner, .. } => {
+            assert_eq!(space as usize, non_transferable_mint(0).data.len());
+            assert_eq!(owner, spl_token_2022::id());
+        }
+        other => panic!("the mint account was not created first: {:?}", other),
+    }
+    assert!(is_soulbound_mint(&mint.pubkey(), &non_transferable_mint(1)).unwrap());
+}
+
+#[test]
+fn a_legacy_soulbound_token_freezes_every_recipient_account() {
+    let backend = backend();
+    let mint = fixed_keypair(21);
+    let holder = Pubkey::new_unique();
+    TokenBuilder::new()
+        .name("Forklift certificate")
+        .uri("https://example.com/cert.json")
+        .soulbound(true)
+        .create_and_mint_with_client(&backend.client(), &mint, 1, Some(holder))
+        .unwrap();
+    let mint = mint.pubkey();
+    let account = ata(&holder, &mint, &spl_token::id());
+    assert_eq!(
+        token_steps(&last_sent(&backend, &mint)),
+        vec![(FREEZE_ACCOUNT, account)]
+    );
+    assert!(is_soulbound_mint(&mint, &legacy_mint(1)).unwrap());
+
+    // A new recipient's account is frozen once minted to
+    backend.set_account(mint, legacy_mint(1));
+    let other = Pubkey::new_unique();
+    let other_account = ata(&other, &mint, &spl_token::id());
+    mint_token_detailed_with_client(
+        &backend.client(),
+        mint,
+        Some(other),
+        AmountSpec::Raw(RawAmount(1)),
+        &MintOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        token_steps(&last_sent(&backend, &mint)),
+        vec![(MINT_TO, other_account), (FREEZE_ACCOUNT, other_account)]
+    );
+
+    // An account already frozen is thawed to receive more, then frozen again
+    backend.set_account(account, holding(&mint, &holder, 1, true, &spl_token::id()));
+    mint_token_detailed_with_client(
+        &backend.client(),
+        mint,
+        Some(holder),
+        AmountSpec::Raw(RawAmount(1)),
+        &MintOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        token_steps(&last_sent(&backend, &mint)),
+        vec![
+            (THAW_ACCOUNT, account),
+            (MINT_TO, account),
+            (FREEZE_ACCOUNT, account)
+        ]
+    );
+}
+
+#[test]
+fn transfers_of_soulbound_tokens_are_refused_before_sending() {
+    let backend = backend();
+    let payer = payer();
+
+    let legacy = Pubkey::new_unique();
+    backend.set_account(legacy, legacy_mint(5));
+    backend.set_account(
+        ata(&payer, &legacy, &spl_token::id()),
+        holding(&legacy, &payer, 5, true, &spl_token::id()),
+    );
+    add_soulbound_mint(legacy);
+    assert_soulbound_transfer_refused(legacy);
+
+    let token_2022 = Pubkey::new_unique();
+    backend.set_account(token_2022, non_transferable_mint(5));
+    backend.set_account(
+        ata(&payer, &token_2022, &spl_token_2022::id()),
+        holding(&token_2022, &payer, 5, false, &spl_token_2022::id()),
+    );
+    assert_soulbound_transfer_refused(token_2022);
+
+    // The same legacy mint transfers when it is not marked soulbound
+    let transferable = Pubkey::new_unique();
+    backend.set_account(transferable, legacy_mint(5));
+    backend.set_account(
+        ata(&payer, &transferable, &spl_token::id()),
+        holding(&transferable, &payer, 5, false, &spl_token::id()),
+    );
+    transfer_asset_with_client(
+        &backend.client(),
+        transferable,
+        Pubkey::new_unique(),
+        &TransferOptions::default(),
+    )
+    .unwrap();
+}
+
+#[test]
+fn soulbound_tokens_stay_burnable() {
+    let backend = backend();
+    let payer = payer();
+
+    // The frozen legacy account is thawed for the burn and frozen again
+    let legacy = Pubkey::new_unique();
+    let account = ata(&payer, &legacy, &spl_token::id());
+    backend.set_account(legacy, legacy_mint(5));
+    backend.set_account(account, holding(&legacy, &payer, 5, true, &spl_token::id()));
+    add_soulbound_mint(legacy);
+    burn_tokens(legacy, AmountSpec::Raw(RawAmount(2))).unwrap();
+    assert_eq!(
+        token_steps(&last_sent(&backend, &legacy)),
+        vec![
+            (THAW_ACCOUNT, account),
+            (BURN_CHECKED, account),
+            (FREEZE_ACCOUNT, account)
+        ]
+    );
+
+    // Token-2022 burns NonTransferable tokens as any other
+    let token_2022 = Pubkey::new_unique();
+    let account = ata(&payer, &token_2022, &spl_token_2022::id());
+    backend.set_account(token_2022, non_transferable_mint(5));
+    backend.set_account(
+        account,
+        holding(&token_2022, &payer, 5, false, &spl_token_2022::id()),
+    );
+    burn_tokens(token_2022, AmountSpec::Raw(RawAmount(2))).unwrap();
+    assert_eq!(
+        token_steps(&last_sent(&backend, &token_2022)),
+        vec![(BURN_CHECKED, account)]
+    );
+
+    // A frozen account of a mint that is not soulbound is not thawed
+    let frozen = Pubkey::new_unique();
+    backend.set_account(frozen, legacy_mint(5));
+    backend.set_account(
+        ata(&payer, &frozen, &spl_token::id()),
+        holding(&frozen, &payer, 5, true, &spl_token::id()),
+    );
+    match burn_tokens(frozen, AmountSpec::Raw(RawAmount(2))) {
+        Err(SssError::TokenError(message)) => assert!(message.contains("is frozen"), "{}", message),
+        other => panic!("burned from a frozen account: {:?}", other),
+    }
+}
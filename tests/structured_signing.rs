@@ -0,0 +1,326 @@
+//! Signing structured data and verifying it, in Rust and over the C API
+//!
+//! Every test signs with the same default payer, so setting it from each is harmless.
+
+mod common;
+
+use common::fixed_keypair;
+use serde::Serialize;
+use serde_json::json;
+use solana_sdk::{signature::Signature, signer::Signer};
+use sss_shared::{
+    DEFAULT_ROLE, ProfileConfig, SigningDomain, SssError, register_profile, set_payer,
+    sign_structured, verify_structured, with_profile,
+};
+use std::str::FromStr;
+
+#[derive(Serialize)]
+struct Coupon {
+    coupon: &'static str,
+    discount_bps: u16,
+    expires_at: i64,
+    kiosk: Kiosk,
+}
+
+#[derive(Serialize)]
+struct Kiosk {
+    id: u32,
+    tags: Vec<Option<&'static str>>,
+}
+
+fn coupon() -> Coupon {
+    Coupon {
+        coupon: "SPRING-10",
+        discount_bps: 1000,
+        expires_at: 1_767_225_600,
+        kiosk: Kiosk {
+            id: 7,
+            tags: vec![Some("café"), None],
+        },
+    }
+}
+
+/// The coupon as another platform would send it: reordered, reformatted and decomposed
+const COUPON_FROM_KIOSK: &str = r#"
+    {
+        "kiosk": { "tags": ["café", null], "id": 7 },
+        "expires_at": 1767225600,
+        "discount_bps": 1000,
+        "coupon": "SPRING-10"
+    }
+"#;
+
+fn domain() -> SigningDomain {
+    SigningDomain {
+        name: "LD coupons".to_string(),
+        version: "1".to_string(),
+        cluster: Some("devnet".to_string()),
+        salt: None,
+    }
+}
+
+#[test]
+fn a_signed_coupon_verifies_however_it_is_formatted() {
+    set_payer(fixed_keypair(1));
+    let signed = sign_structured(&domain(), &coupon()).unwrap();
+    assert_eq!(signed.signer, fixed_keypair(1).pubkey());
+    assert_eq!(signed.role, DEFAULT_ROLE);
+    assert_eq!(signed.digest.len(), 64);
+    let signature = Signature::from_str(&signed.signature).unwrap();
+    let signer = signed.signer;
+
+    assert!(verify_structured(&domain(), COUPON_FROM_KIOSK, &signature, &signer).unwrap());
+    let compact = serde_json::to_string(&coupon()).unwrap();
+    assert!(verify_structured(&domain(), &compact, &signature, &signer).unwrap());
+
+    // Ed25519 signatures are deterministic, so signing again gives the same signature
+    assert_eq!(sign_structured(&domain(), &coupon()).unwrap(), signed);
+}
+
+#[test]
+fn a_changed_coupon_domain_or_signer_does_not_verify() {
+    set_payer(fixed_keypair(1));
+    let signed = sign_structured(&domain(), &coupon()).unwrap();
+    let signature = Signature::from_str(&signed.signature).unwrap();
+    let signer = signed.signer;
+
+    let mut changed = serde_json::to_value(coupon()).unwrap();
+    for (path, value) in [
+        ("/discount_bps", json!(9000)),
+        ("/coupon", json!("SPRING-100")),
+        ("/kiosk/id", json!(8)),
+        ("/kiosk/tags/1", json!("vip")),
+        ("/kiosk/tags/0", json!("cafe")),
+    ] {
+        let original = changed.pointer(path).unwrap().clone();
+        *changed.pointer_mut(path).unwrap() = value;
+        let payload = changed.to_string();
+        assert!(
+            !verify_structured(&domain(), &payload, &signature, &signer).unwrap(),
+            "{}",
+            payload
+        );
+        *changed.pointer_mut(path).unwrap() = original;
+    }
+    let mut extra = changed.clone();
+    extra["kiosk"]["region"] = json!("eu");
+    assert!(!verify_structured(&domain(), &extra.to_string(), &signature, &signer).unwrap());
+    changed.as_object_mut().unwrap().remove("expires_at");
+    assert!(!verify_structured(&domain(), &changed.to_string(), &signature, &signer).unwrap());
+
+    let refund = SigningDomain {
+        name: "LD refunds".to_string(),
+        ..domain()
+    };
+    let mainnet = SigningDomain {
+        cluster: Some("mainnet-beta".to_string()),
+        ..domain()
+    };
+    for other in [refund, mainnet] {
+        assert!(!verify_structured(&other, COUPON_FROM_KIOSK, &signature, &signer).unwrap());
+    }
+
+    let stranger = fixed_keypair(2).pubkey();
+    assert!(!verify_structured(&domain(), COUPON_FROM_KIOSK, &signature, &stranger).unwrap());
+}
+
+#[test]
+fn each_role_signs_with_its_own_key() {
+    set_payer(fixed_keypair(1));
+    register_profile("coupon-issuer", ProfileConfig::new(fixed_keypair(3)));
+    let signed = with_profile("coupon-issuer", || sign_structured(&domain(), &coupon())).unwrap();
+    assert_eq!(signed.role, "coupon-issuer");
+    assert_eq!(signed.signer, fixed_keypair(3).pubkey());
+    let signature = Signature::from_str(&signed.signature).unwrap();
+    assert!(verify_structured(&domain(), COUPON_FROM_KIOSK, &signature, &signed.signer).unwrap());
+    let default_payer = fixed_keypair(1).pubkey();
+    assert!(!verify_structured(&domain(), COUPON_FROM_KIOSK, &signature, &default_payer).unwrap());
+}
+
+#[test]
+fn payloads_that_cannot_be_encoded_are_not_signed() {
+    set_payer(fixed_keypair(1));
+    #[derive(Serialize)]
+    struct Priced {
+        price: f64,
+    }
+    let error = sign_structured(&domain(), &Priced { price: 9.99 }).unwrap_err();
+    assert!(matches!(&error, SssError::KeypairError(m) if m.contains("encode it as a string")));
+    #[derive(Serialize)]
+    struct Totalled {
+        total: u128,
+    }
+    let total = u128::from(u64::MAX) + 1;
+    let error = sign_structured(&domain(), &Totalled { total }).unwrap_err();
+    assert!(matches!(error, SssError::KeypairError(_)));
+
+    let signature = Signature::default();
+    let signer = fixed_keypair(1).pubkey();
+    for payload in [r#"{"price": 9.99}"#, r#"{"a": 1, "a": 1}"#, "{"] {
+        let error = verify_structured(&domain(), payload, &signature, &signer).unwrap_err();
+        assert!(matches!(error, SssError::KeypairError(_)), "{}", payload);
+    }
+}
+
+#[cfg(feature = "ffi")]
+mod ffi {
+    use super::*;
+    use serde_json::Value;
+    use sss_shared::{sss_sign_structured, sss_verify_structured};
+    use std::ffi::{CStr, CString};
+    use std::os::raw::c_char;
+    use std::ptr;
+
+    fn c(s: &str) -> CString {
+        CString::new(s).unwrap()
+    }
+
+    fn domain_json() -> CString {
+        c(&serde_json::to_string(&domain()).unwrap())
+    }
+
+    fn verify(payload: &str, signature: &str, signer: &str) -> i32 {
+        let (domain, payload) = (domain_json(), c(payload));
+        let (signature, signer) = (c(signature), c(signer));
+        unsafe {
+            sss_verify_structured(
+                domain.as_ptr(),
+                payload.as_ptr(),
+                signature.as_ptr(),
+                signer.as_ptr(),
+            )
+        }
+    }
+
+    #[test]
+    fn a_coupon_signed_over_the_c_api_verifies_in_rust_and_over_the_c_api() {
+        set_payer(fixed_keypair(1));
+        let (domain, payload) = (domain_json(), c(COUPON_FROM_KIOSK));
+        let mut out = [0 as c_char; 512];
+        let code = unsafe {
+            sss_sign_structured(
+                domain.as_ptr(),
+                payload.as_ptr(),
+                ptr::null(),
+                out.as_mut_ptr(),
+                out.len() as i32,
+            )
+        };
+        assert_eq!(code, 0);
+        let json: Value =
+            serde_json::from_str(unsafe { CStr::from_ptr(out.as_ptr()) }.to_str().unwrap())
+                .unwrap();
+        let signed = &json["data"];
+
+        // The same signature as signing the struct in Rust
+        let in_rust = sign_structured(&super::domain(), &coupon()).unwrap();
+        assert_eq!(signed["signature"], in_rust.signature.as_str());
+        assert_eq!(signed["digest"], in_rust.digest.as_str());
+        assert_eq!(signed["role"], DEFAULT_ROLE);
+
+        let signature = signed["signature"].as_str().unwrap();
+        let signer = signed["signer"].as_str().unwrap();
+        assert_eq!(signer, fixed_keypair(1).pubkey().to_string());
+        let compact = serde_json::to_string(&coupon()).unwrap();
+        assert_eq!(verify(&compact, signature, signer), 1);
+        assert_eq!(verify(COUPON_FROM_KIOSK, signature, signer), 1);
+        let changed = compact.replace("1000", "9000");
+        assert_eq!(verify(&changed, signature, signer), 0);
+        let stranger = fixed_keypair(2).pubkey().to_string();
+        assert_eq!(verify(&compact, signature, &stranger), 0);
+    }
+
+    #[test]
+    fn a_role_signs_over_the_c_api() {
+        set_payer(fixed_keypair(1));
+        register_profile("ffi-issuer", ProfileConfig::new(fixed_keypair(4)));
+        let (domain, payload, role) = (domain_json(), c(COUPON_FROM_KIOSK), c("ffi-issuer"));
+        let mut out = [0 as c_char; 512];
+        let code = unsafe {
+            sss_sign_structured(
+                domain.as_ptr(),
+                payload.as_ptr(),
+                role.as_ptr(),
+                out.as_mut_ptr(),
+                out.len() as i32,
+            )
+        };
+        assert_eq!(code, 0);
+        let json: Value =
+            serde_json::from_str(unsafe { CStr::from_ptr(out.as_ptr()) }.to_str().unwrap())
+                .unwrap();
+        assert_eq!(json["data"]["role"], "ffi-issuer");
+        assert_eq!(
+            json["data"]["signer"],
+            fixed_keypair(4).pubkey().to_string()
+        );
+        let signature = json["data"]["signature"].as_str().unwrap();
+        let signer = fixed_keypair(4).pubkey().to_string();
+        assert_eq!(verify(COUPON_FROM_KIOSK, signature, &signer), 1);
+    }
+
+    #[test]
+    fn the_c_api_rejects_what_it_cannot_sign_or_verify() {
+        set_payer(fixed_keypair(1));
+        let (domain, payload) = (domain_json(), c(COUPON_FROM_KIOSK));
+        let mut out = [0 as c_char; 512];
+        type Text = *const c_char;
+        let sign = |domain: Text, payload: Text, role: Text, out: &mut [c_char]| unsafe {
+            sss_sign_structured(domain, payload, role, out.as_mut_ptr(), out.len() as i32)
+        };
+
+        assert_eq!(
+            sign(ptr::null(), payload.as_ptr(), ptr::null(), &mut out),
+            -1
+        );
+        assert_eq!(
+            sign(domain.as_ptr(), ptr::null(), ptr::null(), &mut out),
+            -1
+        );
+        let unknown_field = c(r#"{"name":"LD coupons","version":"1","chain":"devnet"}"#);
+        assert_eq!(
+            sign(
+                unknown_field.as_ptr(),
+                payload.as_ptr(),
+                ptr::null(),
+                &mut out
+            ),
+            -2
+        );
+        let float = c(r#"{"price": 9.99}"#);
+        assert_eq!(
+            sign(domain.as_ptr(), float.as_ptr(), ptr::null(), &mut out),
+            -6
+        );
+        let unregistered = c("nobody");
+        assert_eq!(
+            sign(
+                domain.as_ptr(),
+                payload.as_ptr(),
+                unregistered.as_ptr(),
+                &mut out
+            ),
+            -6
+        );
+        assert_eq!(
+            sign(
+                domain.as_ptr(),
+                payload.as_ptr(),
+                ptr::null(),
+                &mut out[..16]
+            ),
+            -5
+        );
+
+        let signer = fixed_keypair(1).pubkey().to_string();
+        let signature = Signature::default().to_string();
+        assert_eq!(verify(r#"{"price": 9.99}"#, &signature, &signer), -3);
+        assert_eq!(verify(COUPON_FROM_KIOSK, "not base58!", &signer), -4);
+        assert_eq!(verify(COUPON_FROM_KIOSK, &signature, "not a key"), -5);
+        let payload = c(COUPON_FROM_KIOSK);
+        let null_signer = unsafe {
+            sss_verify_structured(domain.as_ptr(), payload.as_ptr(), ptr::null(), ptr::null())
+        };
+        assert_eq!(null_signer, -1);
+    }
+}
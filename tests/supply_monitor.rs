@@ -0,0 +1,391 @@
+//! Supply monitoring against a mock backend whose supply jumps
+//!
+//! Anomalies are collected from the process-wide event sink, each test only looking at
+//! those of its own mint.
+
+mod common;
+
+use base64::prelude::{BASE64_STANDARD, Engine};
+use common::{MockBackend, mint_account, response, token_account};
+use serde_json::{Value, json};
+use solana_sdk::{pubkey::Pubkey, signature::Signature, transaction::Transaction};
+use sss_shared::{
+    DateTime, SssEvent, SupplyAnomaly, SupplySchedule, Utc, monitor_supply_with_client,
+    set_event_sink,
+};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Once};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Long enough for a test to change the cluster between two polls
+const POLL: Duration = Duration::from_millis(100);
+
+/// Block time of the mock cluster, a day after the schedule's only point
+const NOW: i64 = 1_700_086_400;
+
+static ANOMALIES: Mutex<Vec<SupplyAnomaly>> = Mutex::new(Vec::new());
+
+/// Returns the anomalies reported for a mint so far
+fn anomalies(mint: &Pubkey) -> Vec<SupplyAnomaly> {
+    static SINK: Once = Once::new();
+    SINK.call_once(|| {
+        set_event_sink(|event| {
+            if let SssEvent::SupplyAnomaly { anomaly } = event {
+                ANOMALIES.lock().unwrap().push(anomaly);
+            }
+        })
+    });
+    ANOMALIES
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|anomaly| anomaly.mint == *mint)
+        .cloned()
+        .collect()
+}
+
+/// Waits until a mint has `count` anomalies, returning them
+fn wait_for_anomalies(mint: &Pubkey, count: usize) -> Vec<SupplyAnomaly> {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        let anomalies = anomalies(mint);
+        if anomalies.len() >= count || Instant::now() > deadline {
+            return anomalies;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Waits until `count` more polls listed the mint's transactions
+///
+/// Listing the transactions ends a poll that finds no anomaly, so a change made right after
+/// is seen as a whole by the next poll.
+fn wait_for_polls(backend: &MockBackend, count: usize) {
+    let method = "getSignaturesForAddress";
+    let target = backend.call_count(method) + count;
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while backend.call_count(method) < target && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(1));
+    }
+    assert!(
+        backend.call_count(method) >= target,
+        "the monitor stopped polling"
+    );
+}
+
+/// A cluster holding a mint whose supply and transactions the test controls
+struct Cluster {
+    backend: Arc<MockBackend>,
+    mint: Pubkey,
+    supply: Arc<AtomicU64>,
+    /// Signatures of the mint, newest first
+    signatures: Arc<Mutex<Vec<Signature>>>,
+    transactions: Arc<Mutex<HashMap<String, Value>>>,
+}
+
+impl Cluster {
+    fn new(supply: u64) -> Self {
+        let backend = MockBackend::new();
+        let mint = Pubkey::new_unique();
+        backend.set_account(mint, mint_account(Some(Pubkey::new_unique()), supply, 0));
+        let cluster = Self {
+            backend,
+            mint,
+            supply: Arc::new(AtomicU64::new(supply)),
+            signatures: Arc::new(Mutex::new(Vec::new())),
+            transactions: Arc::new(Mutex::new(HashMap::new())),
+        };
+
+        let supply = Arc::clone(&cluster.supply);
+        cluster.backend.on("getTokenSupply", move |_| {
+            let amount = supply.load(Ordering::SeqCst);
+            response(json!({
+                "amount": amount.to_string(),
+                "decimals": 0,
+                "uiAmount": amount as f64,
+                "uiAmountString": amount.to_string(),
+            }))
+        });
+        cluster.backend.on("getSlot", |_| json!(1000));
+        cluster.backend.on("getBlockTime", |_| json!(NOW));
+        let signatures = Arc::clone(&cluster.signatures);
+        cluster
+            .backend
+            .on("getSignaturesForAddress", move |params| {
+                let config = &params[1];
+                let until = config["until"].as_str();
+                let limit = config["limit"].as_u64().unwrap_or(1000) as usize;
+                let page: Vec<Value> = signatures
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .map(|signature| signature.to_string())
+                    .take_while(|signature| Some(signature.as_str()) != until)
+                    .take(limit)
+                    .map(|signature| {
+                        json!({
+                            "signature": signature,
+                            "slot": 1000,
+                            "err": null,
+                            "memo": null,
+                            "blockTime": NOW,
+                            "confirmationStatus": "finalized",
+                        })
+                    })
+                    .collect();
+                Value::Array(page)
+            });
+        let transactions = Arc::clone(&cluster.transactions);
+        cluster.backend.on("getTransaction", move |params| {
+            let signature = params[0].as_str().unwrap();
+            transactions
+                .lock()
+                .unwrap()
+                .get(signature)
+                .cloned()
+                .unwrap_or(Value::Null)
+        });
+        cluster
+    }
+
+    /// Lands a transaction moving the mint's tokens from `pre` to `post` balances, per
+    /// token account, returning its signature
+    fn land(&self, balances: &[(u64, u64)]) -> Signature {
+        let payer = Pubkey::new_unique();
+        let accounts: Vec<Pubkey> = balances.iter().map(|_| Pubkey::new_unique()).collect();
+        let instructions: Vec<_> = accounts
+            .iter()
+            .map(|account| {
+                spl_token::instruction::mint_to(
+                    &spl_token::id(),
+                    &self.mint,
+                    account,
+                    &payer,
+                    &[],
+                    1,
+                )
+                .unwrap()
+            })
+            .collect();
+        let tx = Transaction::new_with_payer(&instructions, Some(&payer));
+        let keys = &tx.message.account_keys;
+        let token_balances = |pick: fn(&(u64, u64)) -> u64| -> Vec<Value> {
+            accounts
+                .iter()
+                .zip(balances)
+                .map(|(account, balance)| {
+                    json!({
+                        "accountIndex": keys.iter().position(|key| key == account).unwrap(),
+                        "mint": self.mint.to_string(),
+                        "owner": Pubkey::new_unique().to_string(),
+                        "uiTokenAmount": { "amount": pick(balance).to_string(), "decimals": 0 },
+                    })
+                })
+                .collect()
+        };
+        let signature = Signature::new_unique();
+        let transaction = json!({
+            "slot": 1000,
+            "blockTime": NOW,
+            "transaction": [BASE64_STANDARD.encode(bincode::serialize(&tx).unwrap()), "base64"],
+            "meta": {
+                "err": null,
+                "fee": 5000,
+                "preBalances": vec![0; keys.len()],
+                "postBalances": vec![0; keys.len()],
+                "preTokenBalances": token_balances(|(pre, _)| *pre),
+                "postTokenBalances": token_balances(|(_, post)| *post),
+            },
+        });
+        self.transactions
+            .lock()
+            .unwrap()
+            .insert(signature.to_string(), transaction);
+        self.signatures.lock().unwrap().insert(0, signature);
+        signature
+    }
+
+    fn set_supply(&self, supply: u64) {
+        self.supply.store(supply, Ordering::SeqCst);
+    }
+}
+
+fn schedule(supply: u64, tolerance: u64) -> SupplySchedule {
+    let start = DateTime::<Utc>::from_timestamp(NOW - 86_400, 0).unwrap();
+    SupplySchedule::step([(start, supply)], tolerance)
+}
+
+#[test]
+fn unexpected_mint_is_reported_with_the_transaction_behind_it() {
+    let cluster = Cluster::new(1_000);
+    let handle = monitor_supply_with_client(
+        Arc::new(cluster.backend.client()),
+        cluster.mint,
+        schedule(1_000, 10),
+        POLL,
+    )
+    .unwrap();
+
+    // On schedule, and within the tolerance of it
+    wait_for_polls(&cluster.backend, 2);
+    cluster.set_supply(1_010);
+    wait_for_polls(&cluster.backend, 2);
+    assert!(anomalies(&cluster.mint).is_empty());
+
+    // A transfer leaves the supply alone, the extra mint does not
+    wait_for_polls(&cluster.backend, 1);
+    let transfer = cluster.land(&[(500, 400), (0, 100)]);
+    let extra_mint = cluster.land(&[(0, 1_000_000)]);
+    cluster.set_supply(1_001_010);
+
+    let anomalies = wait_for_anomalies(&cluster.mint, 1);
+    assert_eq!(
+        anomalies,
+        vec![SupplyAnomaly {
+            mint: cluster.mint,
+            expected: 1_000,
+            actual: 1_001_010,
+            delta: 1_000_010,
+            tolerance: 10,
+            signatures: vec![extra_mint.to_string()],
+        }]
+    );
+    assert!(!anomalies[0].signatures.contains(&transfer.to_string()));
+
+    // An unchanged supply is not reported again, a further jump is
+    wait_for_polls(&cluster.backend, 3);
+    assert_eq!(self::anomalies(&cluster.mint).len(), 1);
+    wait_for_polls(&cluster.backend, 1);
+    let second_mint = cluster.land(&[(1_000_000, 1_500_000)]);
+    cluster.set_supply(1_501_010);
+    let anomalies = wait_for_anomalies(&cluster.mint, 2);
+    assert_eq!(anomalies[1].delta, 1_500_010);
+    assert_eq!(anomalies[1].signatures, vec![second_mint.to_string()]);
+    handle.stop();
+}
+
+#[test]
+fn burn_below_the_schedule_is_reported_with_a_negative_delta() {
+    let cluster = Cluster::new(5_000);
+    let handle = monitor_supply_with_client(
+        Arc::new(cluster.backend.client()),
+        cluster.mint,
+        schedule(5_000, 0),
+        POLL,
+    )
+    .unwrap();
+    wait_for_polls(&cluster.backend, 2);
+    let burn = cluster.land(&[(3_000, 0)]);
+    cluster.set_supply(2_000);
+    let anomalies = wait_for_anomalies(&cluster.mint, 1);
+    assert_eq!(anomalies[0].delta, -3_000);
+    assert_eq!(anomalies[0].signatures, vec![burn.to_string()]);
+    handle.stop();
+}
+
+#[test]
+fn history_before_the_first_poll_is_not_blamed() {
+    let cluster = Cluster::new(1_000);
+    cluster.land(&[(0, 9_000)]);
+    cluster.set_supply(10_000);
+
+    let handle = monitor_supply_with_client(
+        Arc::new(cluster.backend.client()),
+        cluster.mint,
+        schedule(1_000, 0),
+        POLL,
+    )
+    .unwrap();
+    let anomalies = wait_for_anomalies(&cluster.mint, 1);
+    assert_eq!(anomalies[0].actual, 10_000);
+    assert!(anomalies[0].signatures.is_empty());
+    handle.stop();
+}
+
+#[test]
+fn failing_node_is_retried_until_the_jump_is_seen() {
+    let cluster = Cluster::new(1_000);
+    let failures = AtomicUsize::new(0);
+    cluster.backend.reject("getTokenSupply", move |_| {
+        (failures.fetch_add(1, Ordering::SeqCst) < 3).then(|| "Node is behind".to_string())
+    });
+    cluster.set_supply(2_000);
+
+    let handle = monitor_supply_with_client(
+        Arc::new(cluster.backend.client()),
+        cluster.mint,
+        schedule(1_000, 0),
+        POLL,
+    )
+    .unwrap();
+    let anomalies = wait_for_anomalies(&cluster.mint, 1);
+    assert_eq!(anomalies.len(), 1);
+    assert_eq!(anomalies[0].delta, 1_000);
+    assert!(cluster.backend.call_count("getTokenSupply") >= 4);
+    handle.stop();
+}
+
+#[test]
+fn stopped_monitor_stops_polling() {
+    let cluster = Cluster::new(1_000);
+    let handle = monitor_supply_with_client(
+        Arc::new(cluster.backend.client()),
+        cluster.mint,
+        schedule(1_000, 0),
+        POLL,
+    )
+    .unwrap();
+    wait_for_polls(&cluster.backend, 2);
+    assert!(handle.is_running());
+    assert!(handle.stop());
+    assert!(!handle.is_running());
+    assert!(!handle.stop());
+
+    let polls = cluster.backend.call_count("getTokenSupply");
+    cluster.set_supply(1_000_000);
+    thread::sleep(POLL * 3);
+    assert_eq!(cluster.backend.call_count("getTokenSupply"), polls);
+    assert!(anomalies(&cluster.mint).is_empty());
+}
+
+#[test]
+fn monitor_needs_an_existing_mint() {
+    let backend = MockBackend::new();
+    let client = Arc::new(backend.client());
+    let missing = Pubkey::new_unique();
+    assert!(
+        monitor_supply_with_client(Arc::clone(&client), missing, schedule(1, 0), POLL).is_err()
+    );
+
+    let not_a_mint = Pubkey::new_unique();
+    backend.set_account(
+        not_a_mint,
+        token_account(&Pubkey::new_unique(), &Pubkey::new_unique(), 0),
+    );
+    assert!(monitor_supply_with_client(client, not_a_mint, schedule(1, 0), POLL).is_err());
+    assert_eq!(backend.call_count("getTokenSupply"), 0);
+}
+
+#[test]
+fn every_mint_since_the_previous_poll_is_listed_oldest_first() {
+    let cluster = Cluster::new(0);
+    let handle = monitor_supply_with_client(
+        Arc::new(cluster.backend.client()),
+        cluster.mint,
+        schedule(0, 0),
+        POLL,
+    )
+    .unwrap();
+    wait_for_polls(&cluster.backend, 1);
+    let signatures: Vec<String> = (0..3)
+        .map(|_| cluster.land(&[(0, 1)]).to_string())
+        .collect();
+    cluster.set_supply(3);
+    let anomalies = wait_for_anomalies(&cluster.mint, 1);
+    assert_eq!(anomalies[0].signatures, signatures);
+    // Monitoring only reads, whatever it finds
+    assert!(cluster.backend.sent().is_empty());
+    handle.stop();
+}
@@ -0,0 +1,252 @@
+//! A throughput-mode airdrop of a few hundred transactions is settled by the bulk sweep
+//!
+//! Airdrops use the global client, so the mock backend is served over HTTP and set as
+//! `SOLANA_RPC_URL` before anything reads it. This binary holds a single test.
+
+mod common;
+
+use common::{MockBackend, decode_transaction, fixed_keypair, mint_account, response};
+use serde_json::{Value, json};
+use solana_sdk::{pubkey::Pubkey, signature::Signature, signer::Signer, transaction::Transaction};
+use sss_shared::{BatchOptions, SssError, execute_airdrop_with_options, set_payer};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{env, fs};
+
+const RECIPIENTS: usize = 3_000;
+
+/// What the cluster does with a transaction, by the order it was first sent in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fate {
+    Confirmed,
+    /// Only seen once it was rebroadcast
+    Late,
+    /// Never seen, so it expires
+    Dropped,
+    /// Lands and fails
+    Failed,
+}
+
+impl Fate {
+    fn of(order: usize) -> Self {
+        match order % 10 {
+            3 => Self::Dropped,
+            6 => Self::Failed,
+            8 => Self::Late,
+            _ => Self::Confirmed,
+        }
+    }
+}
+
+/// The transactions the cluster received, with their fate and how often they were sent
+#[derive(Default)]
+struct Cluster {
+    sent: HashMap<Signature, (Fate, usize, Transaction)>,
+    /// Confirms every transaction from now on
+    healthy: bool,
+}
+
+impl Cluster {
+    fn status(&self, signature: &str) -> Value {
+        let Some((fate, sends, _)) = signature.parse().ok().and_then(|s| self.sent.get(&s)) else {
+            return Value::Null;
+        };
+        let err = match (fate, sends) {
+            (Fate::Dropped, _) | (Fate::Late, 1) => return Value::Null,
+            (Fate::Failed, _) => json!({ "InstructionError": [1, { "Custom": 0 }] }),
+            _ => Value::Null,
+        };
+        json!({
+            "slot": 1,
+            "confirmations": null,
+            "status": if err.is_null() { json!({ "Ok": null }) } else { json!({ "Err": err }) },
+            "err": err,
+            "confirmationStatus": "confirmed",
+        })
+    }
+
+    fn signatures(&self, fate: Fate) -> BTreeSet<Signature> {
+        self.sent
+            .iter()
+            .filter(|(_, (f, _, _))| *f == fate)
+            .map(|(signature, _)| *signature)
+            .collect()
+    }
+
+    /// The wallets the transactions of a fate create token accounts for
+    fn recipients(&self, fate: Fate) -> BTreeSet<Pubkey> {
+        self.sent
+            .values()
+            .filter(|(f, _, _)| *f == fate)
+            .flat_map(|(_, _, tx)| {
+                let keys = &tx.message.account_keys;
+                tx.message
+                    .instructions
+                    .iter()
+                    .filter(|ix| {
+                        keys[ix.program_id_index as usize] == spl_associated_token_account::id()
+                    })
+                    .map(|ix| keys[ix.accounts[2] as usize])
+            })
+            .collect()
+    }
+}
+
+fn keys(entries: &[(Pubkey, impl Sized)]) -> BTreeSet<Pubkey> {
+    entries.iter().map(|(key, _)| *key).collect()
+}
+
+#[test]
+fn a_few_hundred_transactions_are_swept_into_landed_failed_and_never_landed() {
+    let backend = MockBackend::new();
+    unsafe { env::set_var("SOLANA_RPC_URL", backend.serve()) };
+    set_payer(fixed_keypair(1));
+    let mint = Pubkey::new_unique();
+    backend.set_account(mint, mint_account(Some(fixed_keypair(1).pubkey()), 0, 0));
+
+    let cluster = Arc::new(Mutex::new(Cluster::default()));
+    let sent = Arc::clone(&cluster);
+    backend.on("sendTransaction", move |params| {
+        let tx = decode_transaction(params);
+        let signature = tx.signatures[0];
+        let mut cluster = sent.lock().unwrap();
+        let fate = if cluster.healthy {
+            Fate::Confirmed
+        } else {
+            Fate::of(cluster.sent.len())
+        };
+        let (_, sends, _) = cluster.sent.entry(signature).or_insert((fate, 0, tx));
+        *sends += 1;
+        Value::String(signature.to_string())
+    });
+    let polled = Arc::clone(&cluster);
+    backend.on("getSignatureStatuses", move |params| {
+        let cluster = polled.lock().unwrap();
+        let statuses: Vec<Value> = params[0]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|signature| cluster.status(signature.as_str().unwrap()))
+            .collect();
+        response(json!(statuses))
+    });
+    // The blockhash expires once the late transactions were rebroadcast
+    let expired = Arc::new(AtomicBool::new(false));
+    let expiring = Arc::clone(&expired);
+    let rebroadcast = Arc::clone(&cluster);
+    backend.on("isBlockhashValid", move |_| {
+        let cluster = rebroadcast.lock().unwrap();
+        let late_resent = cluster
+            .sent
+            .values()
+            .filter(|(fate, _, _)| *fate == Fate::Late)
+            .all(|(_, sends, _)| *sends > 1);
+        if late_resent {
+            expiring.store(true, Ordering::SeqCst);
+        }
+        response(json!(!late_resent))
+    });
+
+    let allocations: Vec<(Pubkey, u64)> = (0..RECIPIENTS)
+        .map(|i| (Pubkey::new_unique(), i as u64 + 1))
+        .collect();
+    let checkpoint = env::temp_dir().join(format!("sss-throughput-{}.json", rand::random::<u64>()));
+    let options = BatchOptions {
+        throughput_mode: true,
+        wave_size: 1_000,
+        confirmation_timeout: Duration::from_secs(60),
+        ..BatchOptions::default()
+    };
+
+    let start = Instant::now();
+    let report = execute_airdrop_with_options(mint, &allocations, &checkpoint, &options)
+        .unwrap()
+        .batch;
+    assert!(start.elapsed() < Duration::from_secs(30));
+    assert!(expired.load(Ordering::SeqCst));
+
+    let cluster_now = cluster.lock().unwrap();
+    let transactions = cluster_now.sent.len();
+    assert!(transactions >= 256, "only {} transactions", transactions);
+    // Every transaction was sent once, and those not seen yet a second time
+    for (fate, sends, _) in cluster_now.sent.values() {
+        let expected = match fate {
+            Fate::Confirmed | Fate::Failed => 1,
+            Fate::Late | Fate::Dropped => 2,
+        };
+        assert_eq!(*sends, expected, "{:?}", fate);
+    }
+
+    let delivered: BTreeSet<Pubkey> = cluster_now
+        .recipients(Fate::Confirmed)
+        .union(&cluster_now.recipients(Fate::Late))
+        .copied()
+        .collect();
+    assert_eq!(keys(&report.succeeded), delivered);
+    let signatures: BTreeSet<Signature> = report
+        .succeeded
+        .iter()
+        .map(|(_, signature)| signature.parse().unwrap())
+        .collect();
+    let landed: BTreeSet<Signature> = cluster_now
+        .signatures(Fate::Confirmed)
+        .union(&cluster_now.signatures(Fate::Late))
+        .copied()
+        .collect();
+    assert_eq!(signatures, landed);
+
+    let never_landed = cluster_now.recipients(Fate::Dropped);
+    let landed_failed = cluster_now.recipients(Fate::Failed);
+    assert!(!never_landed.is_empty() && !landed_failed.is_empty());
+    assert_eq!(
+        report.never_landed.iter().copied().collect::<BTreeSet<_>>(),
+        never_landed
+    );
+    assert_eq!(
+        report
+            .landed_failed
+            .iter()
+            .copied()
+            .collect::<BTreeSet<_>>(),
+        landed_failed
+    );
+    assert_eq!(
+        report.failed.len(),
+        never_landed.len() + landed_failed.len()
+    );
+    for (recipient, error) in &report.failed {
+        match error {
+            SssError::RpcError(message) => {
+                assert!(never_landed.contains(recipient));
+                assert_eq!(message, "Transaction expired before landing");
+            }
+            SssError::TokenError(message) => {
+                assert!(landed_failed.contains(recipient));
+                assert!(message.starts_with("Transaction failed"), "{}", message);
+            }
+            other => panic!("unexpected failure: {:?}", other),
+        }
+    }
+    assert_eq!(report.succeeded.len() + report.failed.len(), RECIPIENTS);
+    drop(cluster_now);
+
+    // Running again pays the failed recipients only
+    let retry = report.failed_keys();
+    {
+        let mut cluster = cluster.lock().unwrap();
+        cluster.sent.clear();
+        cluster.healthy = true;
+    }
+    let again = execute_airdrop_with_options(mint, &allocations, &checkpoint, &options)
+        .unwrap()
+        .batch;
+    assert!(again.failed.is_empty());
+    assert_eq!(keys(&again.succeeded), retry.into_iter().collect());
+    assert_eq!(
+        again.skipped.iter().copied().collect::<BTreeSet<_>>(),
+        delivered
+    );
+    let _ = fs::remove_file(&checkpoint);
+}
@@ -0,0 +1,516 @@
+//! Every signing entry point refuses to run in watch-only mode, while reads keep working
+//!
+//! Watch-only mode is process-wide and cannot be left, so this test binary only holds tests
+//! run with it on. The mock backend is served as `SOLANA_RPC_URL` for the global client.
+
+mod common;
+
+use base64::prelude::{BASE64_URL_SAFE_NO_PAD, Engine};
+use common::{MockBackend, fixed_keypair, mint_account, response, rpc_account, token_account};
+use mpl_token_metadata::ID as TOKEN_METADATA_ID;
+use mpl_token_metadata::accounts::Metadata;
+use serde_json::json;
+use solana_program::program_pack::Pack;
+use solana_sdk::account::Account;
+use solana_sdk::{message::Message, pubkey::Pubkey, signer::Signer, transaction::Transaction};
+use spl_associated_token_account::get_associated_token_address;
+use sss_shared::{
+    AuthorityKinds, ClaimOptions, CreatedAccount, CreatedAccounts, DateTime, DustDestination,
+    LegacyMigration, MintOptions, ProfileConfig, RawAmount, ReviewPolicy, SigningDomain, SssError,
+    SssResult, TokenBuilder, TokenProgram, TransferOptions, Utc, WATCH_ONLY_MESSAGE,
+    attach_metadata_to_existing_mint, attach_metadata_to_existing_mint_with_client,
+    build_claim_transaction, build_claim_transaction_with_client, build_mint_ix, burn_tokens,
+    consolidate_balances, create_claim_link, create_claim_link_with_client, create_new_token,
+    derive_vault_address, fee_report, fund_vault, get_payer, get_payer_pubkey, init_watch_only,
+    is_watch_only, migrate_legacy_mints, migrate_legacy_mints_with_client, mint_basket, mint_token,
+    mint_token_detailed_with_client, mint_token_idempotent, mint_token_to_account, payer_info,
+    reclaim_claim_link, reclaim_claim_link_with_client, redeem_claim_link,
+    redeem_claim_link_with_client, register_profile, review_external_transaction,
+    rollback_created_accounts_with_client, rotate_authorities, rotate_authorities_with_client,
+    set_journal_audit_key, set_payer, sign_message, sign_structured, sweep_dust, transfer_asset,
+    transfer_asset_with_client, transfer_asset_with_options, verify_creator, with_profile,
+    withdraw_from_vault,
+};
+use std::env;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+/// Balance of the watched wallet on the mock ledger
+const WATCHED_LAMPORTS: u64 = 2_500_000_000;
+
+#[cfg(feature = "dev-tools")]
+const DEVNET_GENESIS_HASH: &str = "EtWTRABZaYq6iMfeYKouRu166VU2xqa1wcaWoxPkrZBG";
+
+static BACKEND: OnceLock<Arc<MockBackend>> = OnceLock::new();
+
+/// The watched wallet, which is not any of the keys loaded before watching started
+fn watched() -> Pubkey {
+    fixed_keypair(9).pubkey()
+}
+
+fn temp_path(name: &str) -> std::path::PathBuf {
+    env::temp_dir().join(format!("sss-watch-only-{}-{}", name, rand::random::<u64>()))
+}
+
+/// A mint of the watched wallet
+fn mint() -> Pubkey {
+    fixed_keypair(2).pubkey()
+}
+
+/// A mint of the watched wallet without metadata
+fn bare_mint() -> Pubkey {
+    fixed_keypair(12).pubkey()
+}
+
+fn customer() -> Pubkey {
+    fixed_keypair(3).pubkey()
+}
+
+/// A token account of the customer, at an address of its own
+fn customer_account() -> Pubkey {
+    fixed_keypair(8).pubkey()
+}
+
+/// Enters watch-only mode once a payer, a profile and an audit key were loaded
+fn watching() -> Arc<MockBackend> {
+    Arc::clone(BACKEND.get_or_init(|| {
+        let backend = MockBackend::new();
+        unsafe { env::set_var("SOLANA_RPC_URL", backend.serve()) };
+        // The faucet and metadata swaps need somewhere to record grants and upload to
+        #[cfg(feature = "dev-tools")]
+        {
+            unsafe { env::set_var("SSS_JOURNAL_PATH", temp_path("journal")) };
+            // Devnet, where dev tools are allowed
+            backend.on("getGenesisHash", |_| json!(DEVNET_GENESIS_HASH));
+        }
+        #[cfg(feature = "http-metadata")]
+        sss_shared::set_upload_target(Box::new(sss_shared::DirectoryUploadTarget::new(
+            temp_path("uploads"),
+            "https://meta.example.com",
+        )));
+        backend.set_account(
+            watched(),
+            Account {
+                lamports: WATCHED_LAMPORTS,
+                data: Vec::new(),
+                owner: solana_sdk::system_program::id(),
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+        backend.set_account(mint(), mint_account(Some(watched()), 10, 0));
+        // The watched wallet holds 10 tokens, and a customer an empty account
+        let holding = get_associated_token_address(&watched(), &mint());
+        backend.set_account(holding, token_account(&mint(), &watched(), 10));
+        backend.set_account(customer_account(), token_account(&mint(), &customer(), 0));
+        backend.set_account(escrow(), escrow_account());
+        // An empty account the watched wallet created, and one holding a single token of dust
+        backend.set_account(created_account(), token_account(&mint(), &watched(), 0));
+        let dust = json!({
+            "pubkey": dust_account().to_string(),
+            "account": rpc_account(Some(&token_account(&mint(), &watched(), 1))),
+        });
+        backend.on("getProgramAccounts", move |_| response(json!([dust])));
+        backend.set_account(Metadata::find_pda(&mint()).0, metadata_account());
+        backend.set_account(bare_mint(), mint_account(Some(watched()), 0, 0));
+        backend.on("getSignaturesForAddress", |_| json!([]));
+
+        set_payer(fixed_keypair(1));
+        register_profile("brand", ProfileConfig::new(fixed_keypair(4)));
+        set_journal_audit_key(fixed_keypair(5)).unwrap();
+        init_watch_only(watched()).unwrap();
+        // The vault is derived from the watched wallet
+        let vault = derive_vault_address("partner", mint()).unwrap();
+        backend.set_account(vault, token_account(&mint(), &watched(), 10));
+        backend
+    }))
+}
+
+fn expiry() -> DateTime<Utc> {
+    DateTime::from_timestamp(4_102_444_800, 0).unwrap()
+}
+
+fn created_account() -> Pubkey {
+    fixed_keypair(7).pubkey()
+}
+
+fn dust_account() -> Pubkey {
+    fixed_keypair(11).pubkey()
+}
+
+/// Metadata of the mint listing the watched wallet as an unverified creator
+fn metadata_account() -> Account {
+    let mut data = vec![4u8]; // Key::MetadataV1
+    data.extend_from_slice(watched().as_ref());
+    data.extend_from_slice(mint().as_ref());
+    for field in ["Crate", "CRT", "https://meta.example.com/crate.json"] {
+        data.extend_from_slice(&(field.len() as u32).to_le_bytes());
+        data.extend_from_slice(field.as_bytes());
+    }
+    // Seller fee, then a single unverified creator with the whole share
+    data.extend_from_slice(&[0, 0, 1, 1, 0, 0, 0]);
+    data.extend_from_slice(watched().as_ref());
+    data.extend_from_slice(&[0, 100]);
+    // Primary sale, mutable, no edition nonce, token standard, collection, uses, collection
+    // details or programmable config
+    data.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+    Account {
+        lamports: 5_616_720,
+        data,
+        owner: TOKEN_METADATA_ID,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+/// The escrow of the claim link of [`link_payload`]
+fn escrow() -> Pubkey {
+    get_associated_token_address(&fixed_keypair(10).pubkey(), &mint())
+}
+
+/// The escrow holding 3 tokens for the claim key, closable by the watched wallet
+fn escrow_account() -> Account {
+    let mut account = token_account(&mint(), &fixed_keypair(10).pubkey(), 3);
+    let mut state = spl_token::state::Account::unpack(&account.data).unwrap();
+    state.close_authority = Some(watched()).into();
+    spl_token::state::Account::pack(state, &mut account.data).unwrap();
+    account
+}
+
+/// The payload of a claim link escrowing 3 tokens, as `create_claim_link` encodes it
+fn link_payload() -> String {
+    let mut bytes = vec![1];
+    bytes.extend_from_slice(fixed_keypair(10).secret().as_bytes());
+    bytes.extend_from_slice(mint().as_ref());
+    bytes.extend_from_slice(&3u64.to_le_bytes());
+    bytes.extend_from_slice(&expiry().timestamp().to_le_bytes());
+    BASE64_URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn assert_refused<T: std::fmt::Debug>(entry_point: &str, result: SssResult<T>) {
+    match result {
+        Err(SssError::KeypairError(message)) if message == WATCH_ONLY_MESSAGE => {}
+        other => panic!("{} was not refused: {:?}", entry_point, other),
+    }
+}
+
+fn domain() -> SigningDomain {
+    SigningDomain {
+        name: "LD audit".to_string(),
+        version: "1".to_string(),
+        cluster: None,
+        salt: None,
+    }
+}
+
+#[test]
+fn every_signing_entry_point_is_refused() {
+    let backend = watching();
+    let payer = watched();
+    let mint = mint();
+    let owner = customer();
+    let expiry = expiry();
+    let checkpoint = temp_path("checkpoint");
+    let external = Transaction::new_unsigned(Message::new(
+        &build_mint_ix(&owner, &mint, &payer, 1, &MintOptions::default()).unwrap(),
+        Some(&payer),
+    ));
+    let external = bincode::serialize(&external).unwrap();
+
+    assert_refused(
+        "create_new_token",
+        create_new_token("https://example.com/t.json", "T", 0),
+    );
+    assert_refused(
+        "TokenBuilder::create_and_mint",
+        TokenBuilder::new()
+            .name("T")
+            .uri("https://example.com/t.json")
+            .create_and_mint(10, None),
+    );
+    assert_refused(
+        "attach_metadata_to_existing_mint",
+        attach_metadata_to_existing_mint(bare_mint(), "https://example.com/t.json", "T", "T"),
+    );
+    assert_refused("mint_token", mint_token(mint, Some(owner), RawAmount(1)));
+    assert_refused(
+        "mint_token_idempotent",
+        mint_token_idempotent(mint, None, RawAmount(1), "key"),
+    );
+    assert_refused(
+        "mint_token_to_account",
+        mint_token_to_account(mint, customer_account(), RawAmount(1)),
+    );
+    assert_refused("mint_basket", mint_basket(&[(mint, 1)], owner));
+    assert_refused("transfer_asset", transfer_asset(mint, owner));
+    assert_refused(
+        "transfer_asset_with_options",
+        transfer_asset_with_options(mint, owner, &TransferOptions::default()),
+    );
+    assert_refused("burn_tokens", burn_tokens(mint, RawAmount(1)));
+    assert_refused("fund_vault", fund_vault("partner", mint, 1));
+    assert_refused(
+        "withdraw_from_vault",
+        withdraw_from_vault("partner", mint, owner, 1),
+    );
+    assert_refused("create_claim_link", create_claim_link(mint, 1, expiry));
+    assert_refused(
+        "redeem_claim_link",
+        redeem_claim_link(&link_payload(), owner),
+    );
+    assert_refused("reclaim_claim_link", reclaim_claim_link(escrow()));
+    assert_refused(
+        "build_claim_transaction",
+        build_claim_transaction(mint, owner, 1),
+    );
+    assert_refused(
+        "execute_airdrop",
+        sss_shared::execute_airdrop(mint, &[(owner, 1)], &checkpoint),
+    );
+    assert_refused(
+        "rotate_authorities",
+        rotate_authorities(&[mint], owner, AuthorityKinds::default()),
+    );
+    assert_refused("sweep_dust", sweep_dust(mint, 5, DustDestination::Burn));
+    assert_refused(
+        "consolidate_balances",
+        consolidate_balances(payer, owner, None, 0, false),
+    );
+    #[cfg(feature = "http-metadata")]
+    assert_refused(
+        "swap_metadata",
+        sss_shared::swap_metadata(mint, sss_shared::OffchainMetadata::default()),
+    );
+    assert_refused("verify_creator", verify_creator(mint));
+    assert_refused(
+        "review_external_transaction",
+        review_external_transaction(&external, ReviewPolicy::default()),
+    );
+    assert_refused(
+        "migrate_legacy_mints",
+        migrate_legacy_mints(
+            &[LegacyMigration {
+                mint,
+                ..LegacyMigration::default()
+            }],
+            &checkpoint,
+        ),
+    );
+    #[cfg(feature = "dev-tools")]
+    {
+        assert_refused(
+            "burn_all_assets",
+            sss_shared::burn_all_assets(&sss_shared::AssetFilter::default(), false),
+        );
+        assert_refused(
+            "faucet_grant",
+            sss_shared::faucet_grant(
+                mint,
+                owner,
+                1,
+                sss_shared::FaucetPolicy {
+                    per_recipient_daily: 10,
+                    global_daily: 10,
+                },
+            ),
+        );
+    }
+    assert_refused("sign_message", sign_message(b"redeem coupon SPRING-10"));
+    assert_refused(
+        "sign_structured",
+        sign_structured(&domain(), &json!({"coupon": "SPRING-10"})),
+    );
+    assert!(!checkpoint.exists());
+    assert!(backend.sent().is_empty());
+}
+
+#[test]
+fn entry_points_taking_a_client_are_refused() {
+    let backend = watching();
+    let client = backend.client();
+    let mint = mint();
+    let owner = customer();
+
+    assert_refused(
+        "mint_token_detailed_with_client",
+        mint_token_detailed_with_client(&client, mint, None, RawAmount(1), &MintOptions::default()),
+    );
+    assert_refused(
+        "transfer_asset_with_client",
+        transfer_asset_with_client(&client, mint, owner, &TransferOptions::default()),
+    );
+    assert_refused(
+        "attach_metadata_to_existing_mint_with_client",
+        attach_metadata_to_existing_mint_with_client(
+            &client,
+            bare_mint(),
+            "https://example.com/t.json",
+            "T",
+            "T",
+        ),
+    );
+    assert_refused(
+        "TokenBuilder::create_and_mint_with_client",
+        TokenBuilder::new()
+            .name("T")
+            .uri("https://example.com/t.json")
+            .create_and_mint_with_client(&client, &fixed_keypair(6), 1, None),
+    );
+    assert_refused(
+        "build_claim_transaction_with_client",
+        build_claim_transaction_with_client(&client, mint, owner, 1, &ClaimOptions::default()),
+    );
+    assert_refused(
+        "create_claim_link_with_client",
+        create_claim_link_with_client(&client, mint, 1, DateTime::<Utc>::MAX_UTC),
+    );
+    assert_refused(
+        "redeem_claim_link_with_client",
+        redeem_claim_link_with_client(&client, &link_payload(), owner),
+    );
+    assert_refused(
+        "reclaim_claim_link_with_client",
+        reclaim_claim_link_with_client(&client, escrow()),
+    );
+    assert_refused(
+        "rollback_created_accounts_with_client",
+        rollback_created_accounts_with_client(
+            &client,
+            &CreatedAccounts {
+                funder: watched(),
+                accounts: vec![CreatedAccount {
+                    address: created_account(),
+                    mint,
+                    owner: watched(),
+                    token_program: TokenProgram::Legacy,
+                }],
+            },
+        ),
+    );
+    assert_refused(
+        "rotate_authorities_with_client",
+        rotate_authorities_with_client(&client, &[mint], owner, AuthorityKinds::default()),
+    );
+    assert_refused(
+        "migrate_legacy_mints_with_client",
+        migrate_legacy_mints_with_client(
+            &client,
+            &[LegacyMigration {
+                mint,
+                ..LegacyMigration::default()
+            }],
+            &temp_path("checkpoint"),
+        ),
+    );
+    #[cfg(feature = "dev-tools")]
+    assert_refused(
+        "faucet_grant_with_client",
+        sss_shared::faucet_grant_with_client(
+            &client,
+            mint,
+            owner,
+            1,
+            sss_shared::FaucetPolicy {
+                per_recipient_daily: 10,
+                global_daily: 10,
+            },
+        ),
+    );
+    #[cfg(feature = "http-metadata")]
+    assert_refused(
+        "swap_metadata_with_client",
+        sss_shared::swap_metadata_with_client(
+            &client,
+            mint,
+            sss_shared::OffchainMetadata::default(),
+        ),
+    );
+    assert!(backend.sent().is_empty());
+    assert_eq!(backend.call_count("sendTransaction"), 0);
+}
+
+#[test]
+fn no_key_material_can_be_loaded() {
+    watching();
+    assert!(is_watch_only());
+
+    // Keys loaded before watching started were dropped
+    match get_payer() {
+        Err(e) => assert!(e.to_string().ends_with(WATCH_ONLY_MESSAGE), "{}", e),
+        Ok(payer) => panic!("loaded payer {}", payer.pubkey()),
+    }
+    match with_profile("brand", || Ok(())) {
+        Err(SssError::ConfigError(message)) => assert!(message.contains("brand"), "{}", message),
+        other => panic!("the profile was kept: {:?}", other),
+    }
+    assert!(sss_shared::export_journal_digest("journal", expiry().date_naive(), "out").is_err());
+
+    // And none can be set again
+    set_payer(fixed_keypair(1));
+    register_profile("brand", ProfileConfig::new(fixed_keypair(4)));
+    assert_eq!(get_payer_pubkey().unwrap(), watched());
+    assert!(get_payer().is_err());
+    assert!(with_profile("brand", || Ok(())).is_err());
+    assert_refused(
+        "set_journal_audit_key",
+        set_journal_audit_key(fixed_keypair(5)),
+    );
+    #[cfg(feature = "keystore")]
+    {
+        let path = temp_path("keystore");
+        assert_refused(
+            "load_encrypted_keypair",
+            sss_shared::load_encrypted_keypair(&path, "passphrase"),
+        );
+        assert_refused(
+            "unlock_payer_keystore",
+            sss_shared::unlock_payer_keystore(&path, "passphrase"),
+        );
+    }
+}
+
+#[test]
+fn balances_and_fee_reports_are_those_of_the_watched_wallet() {
+    watching();
+    assert_eq!(get_payer_pubkey().unwrap(), watched());
+
+    let info = payer_info().unwrap();
+    assert!(info.watch_only);
+    assert!(!info.loaded);
+    assert_eq!(info.pubkey, Some(watched()));
+    assert_eq!(info.lamports, Some(WATCHED_LAMPORTS));
+    assert_eq!(info.load_error, None);
+
+    let until = expiry();
+    let report = fee_report(until - Duration::from_secs(7 * 86_400), until).unwrap();
+    assert_eq!(report.payer, watched());
+    assert_eq!(report.fetched, 0);
+    assert!(report.daily.is_empty());
+}
+
+#[cfg(feature = "ffi")]
+#[test]
+fn ffi_calls_return_the_watch_only_code() {
+    use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+    use sss_shared::{SSS_ERR_WATCH_ONLY, mint_token_ffi, sss_sign_message};
+    use std::ffi::CString;
+    use std::os::raw::c_char;
+
+    watching();
+    let mint = CString::new(mint().to_string()).unwrap();
+    let mut signature = [0 as c_char; 128];
+    let code = unsafe {
+        mint_token_ffi(
+            mint.as_ptr(),
+            std::ptr::null(),
+            1,
+            signature.as_mut_ptr(),
+            128,
+        )
+    };
+    assert_eq!(code, SSS_ERR_WATCH_ONLY);
+
+    let message = CString::new(BASE64.encode(b"redeem coupon SPRING-10")).unwrap();
+    let code = unsafe { sss_sign_message(message.as_ptr(), signature.as_mut_ptr(), 128) };
+    assert_eq!(code, SSS_ERR_WATCH_ONLY);
+}